@@ -0,0 +1,127 @@
+//! rhine 命令行：交互式对话与配置校验
+//! rhine command line: interactive chat and configuration validation
+//!
+//! ```text
+//! rhine chat --config config.toml --capability long_context
+//! rhine validate --config config.toml --probe
+//! ```
+
+mod repl;
+
+// 标准库
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+// 命令行
+use clap::{Parser, Subcommand, ValueEnum};
+
+// 项目内部模块
+use rhine::config::validate::Severity;
+use rhine::config::{Config, ModelCapability};
+
+#[derive(Debug, Parser)]
+#[command(name = "rhine", version, about = "Rhine Lab command line")]
+struct Cli {
+    /// 配置文件路径 / Config file path
+    #[arg(long, short, global = true, default_value = "config.toml")]
+    config: PathBuf,
+
+    /// 配置环境，默认读取 RHINE_PROFILE / Config profile, RHINE_PROFILE by default
+    #[arg(long, global = true)]
+    profile: Option<String>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// 交互式对话 / Interactive chat
+    Chat(repl::ChatArgs),
+
+    /// 校验配置文件 / Validate the config file
+    Validate {
+        /// 同时探测每个API是否可用 / Also probe whether every API is reachable
+        #[arg(long)]
+        probe: bool,
+    },
+}
+
+/// 命令行中的模型能力
+/// Model capability on the command line
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum CapabilityArg {
+    Think,
+    ToolUse,
+    LongContext,
+    Cheap,
+}
+
+impl From<CapabilityArg> for ModelCapability {
+    fn from(capability: CapabilityArg) -> Self {
+        match capability {
+            CapabilityArg::Think => ModelCapability::Think,
+            CapabilityArg::ToolUse => ModelCapability::ToolUse,
+            CapabilityArg::LongContext => ModelCapability::LongContext,
+            CapabilityArg::Cheap => ModelCapability::Cheap,
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    let path = cli.config.to_string_lossy();
+    let profile = cli.profile.or_else(|| std::env::var("RHINE_PROFILE").ok().filter(|profile| !profile.is_empty()));
+    if let Err(report) = Config::load_with_profile(&path, profile.as_deref()) {
+        eprintln!("Failed to load {}: {:?}", path, report);
+        return ExitCode::FAILURE;
+    }
+
+    match cli.command {
+        Command::Chat(args) => match repl::run(args).await {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(report) => {
+                eprintln!("{:?}", report);
+                ExitCode::FAILURE
+            }
+        },
+        Command::Validate { probe } => validate(probe).await,
+    }
+}
+
+/// 打印配置问题与探测结果，存在错误或不可用的API时返回失败
+/// Print configuration issues and probe results, failing on errors or unreachable APIs
+async fn validate(probe: bool) -> ExitCode {
+    let issues = Config::validate();
+    for issue in &issues {
+        println!("{}", issue);
+    }
+    let mut failed = issues.iter().any(|issue| issue.severity == Severity::Error);
+
+    if probe {
+        for report in Config::probe().await {
+            let status = if report.healthy { "ok" } else { "unreachable" };
+            let tools = match report.supports_tools {
+                Some(true) => ", native tools",
+                Some(false) => ", prompt tools",
+                None => "",
+            };
+            println!("{} ({}): {} in {:?}{}", report.api_name, report.model, status, report.latency, tools);
+            if let Some(error) = &report.error {
+                println!("    {}", error);
+            }
+            failed |= !report.healthy;
+        }
+    }
+
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        if issues.is_empty() {
+            println!("Config is valid");
+        }
+        ExitCode::SUCCESS
+    }
+}
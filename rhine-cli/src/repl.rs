@@ -0,0 +1,249 @@
+// 标准库
+use std::io::Write;
+use std::sync::Arc;
+
+// 错误处理
+use error_stack::{Result, ResultExt};
+
+// 异步
+use futures::StreamExt;
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+// 命令行
+use clap::Args;
+
+// 项目内部模块
+use crate::CapabilityArg;
+use rhine::chat::chat_base::ChatError;
+use rhine::chat::chat_single::SingleChat;
+use rhine::chat::mcp::McpTools;
+use rhine::chat::message::{Messages, Session};
+use rhine::chat::tool_source::ToolSource;
+use rhine::error::sanitize_url;
+
+const HELP: &str = "\
+/help               显示帮助 / Show this help
+/model              当前API与模型 / Current API and model
+/tools              已加载的工具 / Loaded tools
+/branch             列出当前路径上的消息 / List messages on the current path
+/branch <id>        从该消息处分支，可用ID前缀 / Branch off the message, an ID prefix is enough
+/save <path>        保存会话 / Save the session
+/load <path>        加载会话 / Load a session
+/usage              累计 token 用量 / Tokens used so far
+/exit               退出 / Quit";
+
+#[derive(Debug, Args)]
+pub struct ChatArgs {
+    /// 按名称（或别名）选择API / Select the API by name (or alias)
+    #[arg(long)]
+    api: Option<String>,
+
+    /// 按能力选择API，未指定 --api 时默认为 long-context / Select the API by capability, long-context unless --api is given
+    #[arg(long, value_enum)]
+    capability: Option<CapabilityArg>,
+
+    /// 系统提示词 / System prompt
+    #[arg(long)]
+    system: Option<String>,
+
+    /// 等待完整回答而不是流式输出 / Wait for the whole answer instead of streaming it
+    #[arg(long)]
+    no_stream: bool,
+
+    /// MCP 服务的工具，格式为 <命名空间>=<地址>，可以重复 / Tools of an MCP server as <namespace>=<url>, may be repeated
+    #[arg(long, value_name = "NAMESPACE=URL", value_parser = parse_mcp)]
+    mcp: Vec<(String, String)>,
+}
+
+/// 解析 `--mcp` 的 `<命名空间>=<地址>`
+/// Parse the `<namespace>=<url>` of `--mcp`
+fn parse_mcp(value: &str) -> std::result::Result<(String, String), String> {
+    match value.split_once('=') {
+        Some((namespace, url)) if !namespace.is_empty() && !url.is_empty() => {
+            Ok((namespace.to_string(), url.to_string()))
+        }
+        _ => Err(format!("expected <namespace>=<url>, got {}", value)),
+    }
+}
+
+/// 命令执行后是否继续
+/// Whether to go on after a command
+enum Flow {
+    Continue,
+    Exit,
+}
+
+/// 运行交互式对话，直到输入 `/exit` 或标准输入结束
+/// Run the interactive chat until `/exit` or the end of standard input
+pub async fn run(args: ChatArgs) -> Result<(), ChatError> {
+    let mut chat = build_chat(&args).await?;
+    let stream = !args.no_stream;
+    println!("{} ({}), /help for commands", chat.base.api_name, chat.base.model);
+
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    loop {
+        print!("> ");
+        let _ = std::io::stdout().flush();
+        let Ok(Some(line)) = lines.next_line().await else {
+            break;
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(command) = line.strip_prefix('/') {
+            match run_command(&mut chat, command) {
+                Flow::Continue => continue,
+                Flow::Exit => break,
+            }
+        }
+
+        if let Err(report) = ask(&mut chat, line, stream).await {
+            eprintln!("{:?}", report);
+        }
+    }
+    Ok(())
+}
+
+/// 按命令行参数创建对话；工具只来自 MCP 服务，命令行进程中没有注册工具实现，单独的工具定义无法执行
+/// Create the chat from the command line arguments; tools only come from MCP servers, since no tool implementation
+/// is registered in the command line process and bare tool definitions could not run
+async fn build_chat(args: &ChatArgs) -> Result<SingleChat, ChatError> {
+    let mut builder = SingleChat::builder().stream(!args.no_stream);
+    if let Some(api) = &args.api {
+        builder = builder.api(api);
+    }
+    if let Some(capability) = args.capability.or(args.api.is_none().then_some(CapabilityArg::LongContext)) {
+        builder = builder.capability(capability.into());
+    }
+    if let Some(system) = &args.system {
+        builder = builder.system(system);
+    }
+    let mut chat = builder.build()?;
+    if !args.mcp.is_empty() {
+        let sources = args
+            .mcp
+            .iter()
+            .map(|(namespace, url)| Arc::new(McpTools::new(namespace, url)) as Arc<dyn ToolSource>)
+            .collect();
+        chat.set_tool_sources(sources)
+            .await
+            .attach_printable("Cannot list the tools of the MCP servers")?;
+    }
+    Ok(chat)
+}
+
+/// 提问并打印回答；设置了工具时执行工具调用并打印结果
+/// Ask and print the answer; with tools set, run the tool calls and print their results
+async fn ask(chat: &mut SingleChat, input: &str, stream: bool) -> Result<(), ChatError> {
+    if !chat.tools().is_empty() {
+        let (answer, results) = chat
+            .get_tool_answer(input)
+            .await
+            .change_context(ChatError::GetFunctionError)?;
+        println!("{}", answer.trim());
+        for result in results {
+            println!("[tool] {}", result);
+        }
+    } else if stream {
+        let mut deltas = std::pin::pin!(chat.stream_answer(input).await?);
+        while let Some(delta) = deltas.next().await {
+            print!("{}", delta?);
+            let _ = std::io::stdout().flush();
+        }
+        println!();
+    } else {
+        println!("{}", chat.get_answer(input).await?);
+    }
+    Ok(())
+}
+
+fn run_command(chat: &mut SingleChat, command: &str) -> Flow {
+    let (name, argument) = command.split_once(' ').map_or((command, ""), |(name, argument)| (name, argument.trim()));
+    match (name, argument) {
+        ("exit" | "quit", _) => return Flow::Exit,
+        ("help", _) => println!("{}", HELP),
+        ("model", _) => println!("{} ({}) at {}", chat.base.api_name, chat.base.model, sanitize_url(&chat.base.base_url)),
+        ("usage", _) => println!("{} tokens", chat.base.usage),
+        ("tools", _) if chat.tools().is_empty() => println!("No tools loaded, start with --mcp <namespace>=<url>"),
+        ("tools", _) => {
+            for tool in chat.tools() {
+                let function = &tool["function"];
+                println!("{}: {}", function["name"].as_str().unwrap_or("?"), function["description"].as_str().unwrap_or(""));
+            }
+        }
+        ("branch", "") => list_path(&chat.base.session),
+        ("branch", prefix) => match find_by_prefix(&chat.base.session, prefix) {
+            Ok(id) => match chat.base.session.checkout(&id) {
+                Ok(()) => println!("Next question branches off {}", short_id(&id)),
+                Err(error) => eprintln!("{}", error),
+            },
+            Err(message) => eprintln!("{}", message),
+        },
+        ("save", "") | ("load", "") => eprintln!("Usage: /{} <path>", name),
+        ("save", path) => match serde_json::to_string_pretty(&chat.base.session) {
+            Ok(json) => match std::fs::write(path, json) {
+                Ok(()) => println!("Saved to {}", path),
+                Err(error) => eprintln!("Cannot write {}: {}", path, error),
+            },
+            Err(error) => eprintln!("Cannot serialize the session: {}", error),
+        },
+        ("load", path) => match std::fs::read_to_string(path).map_err(|e| e.to_string()).and_then(|text| {
+            serde_json::from_str::<Session>(&text).map_err(|e| e.to_string())
+        }) {
+            Ok(session) => {
+                chat.base.session = session;
+                println!("Loaded {}", path);
+                list_path(&chat.base.session);
+            }
+            Err(error) => eprintln!("Cannot load {}: {}", path, error),
+        },
+        _ => eprintln!("Unknown command /{}, /help for commands", name),
+    }
+    Flow::Continue
+}
+
+fn short_id(id: &str) -> &str {
+    &id[..id.len().min(8)]
+}
+
+/// 打印默认路径上的消息
+/// Print the messages on the default path
+fn list_path(session: &Session) {
+    let Some((&root, rest)) = session.default_path.split_first() else {
+        println!("The session is empty");
+        return;
+    };
+    let mut node = session.message_roots.get(root);
+    let mut rest = rest.iter();
+    while let Some(message) = node {
+        let text: String = message.content.to_text().chars().take(60).collect();
+        let branches = if message.child.len() > 1 { format!(" ({} branches)", message.child.len()) } else { String::new() };
+        println!("{} {}: {}{}", short_id(&message.id), message.role, text.replace('\n', " "), branches);
+        node = rest.next().and_then(|&index| message.child.get(index));
+    }
+}
+
+/// 按ID前缀查找消息，前缀不唯一或找不到时返回错误描述
+/// Find a message by ID prefix, describing the problem if the prefix is ambiguous or matches nothing
+fn find_by_prefix(session: &Session, prefix: &str) -> std::result::Result<String, String> {
+    fn collect<'a>(message: &'a Messages, prefix: &str, found: &mut Vec<&'a str>) {
+        if message.id.starts_with(prefix) {
+            found.push(&message.id);
+        }
+        for child in &message.child {
+            collect(child, prefix, found);
+        }
+    }
+
+    let mut found = Vec::new();
+    for root in &session.message_roots {
+        collect(root, prefix, &mut found);
+    }
+    match found.as_slice() {
+        [id] => Ok(id.to_string()),
+        [] => Err(format!("No message with ID {}", prefix)),
+        _ => Err(format!("{} messages match {}, use a longer prefix", found.len(), prefix)),
+    }
+}
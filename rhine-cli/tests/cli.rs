@@ -0,0 +1,194 @@
+use std::path::PathBuf;
+use std::process::{Output, Stdio};
+
+use serde_json::json;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::process::Command;
+
+use rhine::testing::MockProvider;
+
+/// 写入指向模拟提供商的配置文件，API `<name>-chat` 用于对话，`<name>-tools` 用于解析工具调用
+/// Write a config file pointing at the mock provider, with API `<name>-chat` for chatting and `<name>-tools` for
+/// parsing tool calls
+fn write_config(mock: &MockProvider, name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("rhine_cli_{}.toml", name));
+    let config = format!(
+        r#"
+            [[api_source]]
+            name = "{name}-source"
+            base_url = "{url}"
+            parallelism = 2
+
+            [[api_info]]
+            name = "{name}-chat"
+            model = "mock-model"
+            capability = "long_context"
+            source = "{name}-source"
+            api_key = "sk-mock"
+
+            [[api_info]]
+            name = "{name}-tools"
+            model = "mock-model"
+            capability = "tool_use"
+            source = "{name}-source"
+            api_key = "sk-mock"
+        "#,
+        name = name,
+        url = mock.url(),
+    );
+    std::fs::write(&path, config).expect("Failed to write the config file");
+    path
+}
+
+/// 运行命令行，`input` 作为标准输入
+/// Run the command line with `input` as standard input
+async fn rhine(args: &[&str], input: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rhine"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start rhine");
+    let mut stdin = child.stdin.take().unwrap();
+    stdin.write_all(input.as_bytes()).await.unwrap();
+    drop(stdin);
+    child.wait_with_output().await.expect("rhine did not finish")
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+/// 读取一个 HTTP 请求的 JSON 请求体
+/// Read the JSON body of one HTTP request
+async fn read_body(socket: &mut TcpStream) -> Option<serde_json::Value> {
+    let mut request = Vec::new();
+    let mut buffer = [0; 4096];
+    loop {
+        let read = socket.read(&mut buffer).await.ok().filter(|read| *read > 0)?;
+        request.extend_from_slice(&buffer[..read]);
+        let text = String::from_utf8_lossy(&request);
+        let Some((head, body)) = text.split_once("\r\n\r\n") else {
+            continue;
+        };
+        let length = head
+            .lines()
+            .filter_map(|line| line.split_once(':'))
+            .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+            .and_then(|(_, value)| value.trim().parse().ok())
+            .unwrap_or(0);
+        if body.len() >= length {
+            return serde_json::from_str(body).ok();
+        }
+    }
+}
+
+/// MCP 请求的结果，通知没有结果
+/// Result of an MCP request, notifications have none
+fn mcp_result(body: &serde_json::Value) -> Option<serde_json::Value> {
+    match body["method"].as_str()? {
+        "initialize" => Some(json!({"protocolVersion": "2025-03-26", "capabilities": {"tools": {}}})),
+        "tools/list" => Some(json!({"tools": [{
+            "name": "lookup",
+            "description": "Look up a customer",
+            "inputSchema": {"type": "object", "properties": {"q": {"type": "string"}}, "required": ["q"]}
+        }]})),
+        "tools/call" => {
+            let customer = body["params"]["arguments"]["q"].as_str().unwrap_or("?");
+            Some(json!({"content": [{"type": "text", "text": format!("customer {} is active", customer)}]}))
+        }
+        _ => None,
+    }
+}
+
+/// 模拟的 MCP 服务，提供一个 `lookup` 工具
+/// Mock MCP server offering one `lookup` tool
+async fn mcp_server() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("Failed to bind the MCP server");
+    let url = format!("http://{}/mcp", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        while let Ok((mut socket, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let Some(body) = read_body(&mut socket).await else {
+                    return;
+                };
+                let (status, content) = match mcp_result(&body) {
+                    Some(result) => {
+                        let content = json!({"jsonrpc": "2.0", "id": body["id"], "result": result});
+                        ("200 OK", content.to_string())
+                    }
+                    None => ("202 Accepted", String::new()),
+                };
+                let response = format!(
+                    "HTTP/1.1 {}\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+                    status,
+                    content.len()
+                ) + &content;
+                let _ = socket.write_all(response.as_bytes()).await;
+            });
+        }
+    });
+    url
+}
+
+#[tokio::test]
+async fn chat_answers_and_runs_commands() {
+    let mock = MockProvider::start("cli-chat").await;
+    let config = write_config(&mock, "chat");
+    mock.reply("hello there").reply("one two three");
+
+    let output = rhine(
+        &["chat", "--config", config.to_str().unwrap(), "--api", "chat-chat", "--system", "be brief", "--no-stream"],
+        "hi\n/model\n/tools\n/usage\n/exit\nnever asked\n",
+    )
+    .await;
+    assert!(output.status.success());
+    let printed = stdout(&output);
+    assert!(printed.contains("chat-chat (mock-model), /help for commands"));
+    assert!(printed.contains("hello there"));
+    assert!(printed.contains("chat-chat (mock-model) at http://127.0.0.1"));
+    assert!(printed.contains("No tools loaded, start with --mcp <namespace>=<url>"));
+    assert!(printed.contains("2 tokens"));
+    assert_eq!(mock.requests().len(), 1);
+    mock.last_request().contains("be brief").contains("hi");
+
+    // 流式输出到标准输入结束为止
+    // Streamed output until standard input ends
+    let output = rhine(&["chat", "--config", config.to_str().unwrap(), "--api", "chat-chat"], "count\n").await;
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("one two three"));
+}
+
+#[tokio::test]
+async fn chat_uses_the_tools_of_mcp_servers() {
+    let mock = MockProvider::start("cli-tools").await;
+    let config = write_config(&mock, "tools");
+    let crm = format!("crm={}", mcp_server().await);
+    mock.reply("<ToolUse>check acme</ToolUse>").reply_tool_call("crm__lookup", json!({"q": "acme"}));
+
+    let args = ["chat", "--config", config.to_str().unwrap(), "--api", "tools-chat", "--mcp", crm.as_str()];
+    let output = rhine(&args, "/tools\ncheck acme\n").await;
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let printed = stdout(&output);
+    assert!(printed.contains("crm__lookup: Look up a customer"));
+    assert!(printed.contains("[tool] ") && printed.contains("customer acme is active"));
+    mock.request(0).contains("crm__lookup");
+
+    let output = rhine(&["chat", "--config", config.to_str().unwrap(), "--mcp", "no-url"], "").await;
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("expected <namespace>=<url>, got no-url"));
+}
+
+#[tokio::test]
+async fn validate_reports_the_config() {
+    let mock = MockProvider::start("cli-validate").await;
+    let config = write_config(&mock, "validate");
+    let output = rhine(&["validate", "--config", config.to_str().unwrap()], "").await;
+    assert!(output.status.success(), "{}", stdout(&output));
+
+    let output = rhine(&["validate", "--config", "/nonexistent/rhine.toml"], "").await;
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Failed to load /nonexistent/rhine.toml"));
+}
@@ -0,0 +1,79 @@
+/*
+ * rhine 的 C ABI
+ * C ABI of rhine
+ *
+ * 所有字符串均为 UTF-8 且以 NUL 结尾；返回的字符串用 rhine_string_free 释放。
+ * 失败时返回 NULL 或非零状态码，rhine_last_error 给出本线程最近一次错误。
+ * 同一个对话句柄不能被多个线程同时使用。
+ *
+ * All strings are NUL-terminated UTF-8; returned strings are freed with rhine_string_free.
+ * Failures return NULL or a non-zero status, rhine_last_error gives the latest error of the calling thread.
+ * One chat handle must not be used by several threads at once.
+ */
+
+#ifndef RHINE_H
+#define RHINE_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define RHINE_OK 0
+#define RHINE_INVALID_ARGUMENT 1
+#define RHINE_ERROR 2
+#define RHINE_PANIC 3
+#define RHINE_CANCELLED 4
+
+/* 不透明的对话句柄 / Opaque chat handle */
+typedef struct RhineChat RhineChat;
+
+/* 收到一段回答时调用，返回非零值停止接收；delta 只在回调期间有效
+ * Called for each piece of the answer, return non-zero to stop; delta is only valid during the callback */
+typedef int (*RhineStreamCallback)(const char *delta, void *user_data);
+
+/* 本线程最近一次错误，没有错误时为 NULL；无需释放
+ * Latest error of the calling thread, NULL if none; must not be freed */
+const char *rhine_last_error(void);
+
+/* 从 TOML 文件加载配置，profile 可为 NULL
+ * Load the configuration from a TOML file, profile may be NULL */
+int rhine_config_load(const char *path, const char *profile);
+
+/* 添加API来源 / Add an API source */
+int rhine_config_add_source(const char *name, const char *base_url, size_t parallelism);
+
+/* 添加API，capability 为 "think"、"tool_use" 或 "long_context"
+ * Add an API, capability is "think", "tool_use" or "long_context" */
+int rhine_config_add_api(const char *name, const char *model, const char *capability, const char *source,
+                         const char *api_key);
+
+/* 创建对话，system 可为 NULL；失败时返回 NULL
+ * Create a chat, system may be NULL; returns NULL on failure */
+RhineChat *rhine_chat_create(const char *api_name, const char *system, bool stream);
+RhineChat *rhine_chat_create_with_capability(const char *capability, const char *system, bool stream);
+
+/* 提问并阻塞等待回答，失败时返回 NULL
+ * Ask and block until the answer arrives, NULL on failure */
+char *rhine_chat_ask(RhineChat *chat, const char *input);
+
+/* 提问并以回调逐段接收回答，阻塞到回答结束；callback 为 NULL 时返回 RHINE_INVALID_ARGUMENT
+ * Ask and receive the answer piece by piece through the callback, blocking until it ends; returns
+ * RHINE_INVALID_ARGUMENT if callback is NULL */
+int rhine_chat_stream(RhineChat *chat, const char *input, RhineStreamCallback callback, void *user_data);
+
+/* 对话累计消耗的 token 数 / Tokens used by the chat so far */
+int32_t rhine_chat_usage(const RhineChat *chat);
+
+/* 释放句柄与字符串，均可传入 NULL / Free handles and strings, NULL is allowed for both */
+void rhine_chat_free(RhineChat *chat);
+void rhine_string_free(char *value);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* RHINE_H */
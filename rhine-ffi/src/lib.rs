@@ -0,0 +1,404 @@
+//! rhine 的 C ABI，供 Unity、Swift、C++ 等应用嵌入，头文件见 `include/rhine.h`
+//! C ABI of rhine for embedding in Unity, Swift, C++ and other applications, see `include/rhine.h` for the header
+//!
+//! 约定：
+//! Conventions:
+//! - 所有字符串均为 UTF-8 且以 NUL 结尾 / All strings are NUL-terminated UTF-8
+//! - 返回的字符串由调用方用 `rhine_string_free` 释放 / Returned strings are freed by the caller with `rhine_string_free`
+//! - 失败时返回 NULL 或非零状态码，`rhine_last_error` 给出本线程最近一次错误
+//!   Failures return NULL or a non-zero status, `rhine_last_error` gives the latest error of the calling thread
+//! - 同一个对话句柄不能被多个线程同时使用 / One chat handle must not be used by several threads at once
+
+// 标准库
+use std::cell::RefCell;
+use std::ffi::{CStr, CString, c_char, c_int, c_void};
+use std::panic::{AssertUnwindSafe, catch_unwind};
+use std::ptr;
+
+// 错误处理
+use error_stack::Report;
+
+// 异步
+use futures::StreamExt;
+
+// 项目内部模块
+use rhine::blocking::block_on;
+use rhine::chat::chat_single::SingleChat;
+use rhine::config::{Config, ModelCapability};
+use rhine::error::{ReportExt, RhineError};
+
+/// 成功
+/// Success
+pub const RHINE_OK: c_int = 0;
+
+/// 参数为空或不是有效的 UTF-8
+/// An argument is NULL or not valid UTF-8
+pub const RHINE_INVALID_ARGUMENT: c_int = 1;
+
+/// 调用失败，详见 `rhine_last_error`
+/// The call failed, see `rhine_last_error`
+pub const RHINE_ERROR: c_int = 2;
+
+/// 内部发生 panic，未跨越 FFI 边界传播
+/// A panic happened inside and was not propagated across the FFI boundary
+pub const RHINE_PANIC: c_int = 3;
+
+/// 流式回调返回非零值后停止
+/// Stopped after the stream callback returned non-zero
+pub const RHINE_CANCELLED: c_int = 4;
+
+/// 流式回调：收到一段回答时调用，返回非零值停止接收；C 侧传入 NULL 时为 None
+/// Stream callback: called for each piece of the answer, return non-zero to stop receiving; None when C passes NULL
+pub type RhineStreamCallback = Option<extern "C" fn(delta: *const c_char, user_data: *mut c_void) -> c_int>;
+
+/// 不透明的对话句柄
+/// Opaque chat handle
+pub struct RhineChat {
+    chat: SingleChat,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: String) {
+    let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|error| *error.borrow_mut() = Some(message));
+}
+
+/// 记录错误报告，返回对应的状态码
+/// Record an error report, returning the matching status
+fn report_error<C: RhineError>(report: Report<C>) -> c_int {
+    set_last_error(format!("{}: {:?}", report.code(), report));
+    RHINE_ERROR
+}
+
+/// 读取 C 字符串参数，空指针或无效 UTF-8 时记录错误
+/// Read a C string argument, recording an error for NULL or invalid UTF-8
+///
+/// # Safety
+/// `value` 为空或指向有效的 NUL 结尾字符串
+/// `value` is NULL or points to a valid NUL-terminated string
+unsafe fn read_str<'a>(value: *const c_char, name: &str) -> Option<&'a str> {
+    if value.is_null() {
+        set_last_error(format!("Argument '{}' is NULL", name));
+        return None;
+    }
+    match unsafe { CStr::from_ptr(value) }.to_str() {
+        Ok(value) => Some(value),
+        Err(_) => {
+            set_last_error(format!("Argument '{}' is not valid UTF-8", name));
+            None
+        }
+    }
+}
+
+/// 可选的 C 字符串参数，空指针视为未提供
+/// Optional C string argument, NULL counts as not given
+unsafe fn read_optional_str<'a>(value: *const c_char, name: &str) -> Result<Option<&'a str>, ()> {
+    if value.is_null() {
+        return Ok(None);
+    }
+    unsafe { read_str(value, name) }.map(Some).ok_or(())
+}
+
+/// 执行调用并拦截 panic
+/// Run a call, catching panics
+fn guard<T>(fallback: T, call: impl FnOnce() -> T) -> T {
+    catch_unwind(AssertUnwindSafe(call)).unwrap_or_else(|panic| {
+        let message = panic
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .or_else(|| panic.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic".to_string());
+        set_last_error(format!("panic: {}", message));
+        fallback
+    })
+}
+
+fn into_c_string(value: String) -> *mut c_char {
+    CString::new(value.replace('\0', " ")).map_or(ptr::null_mut(), CString::into_raw)
+}
+
+fn parse_capability(capability: &str) -> Option<ModelCapability> {
+    match capability {
+        "think" => Some(ModelCapability::Think),
+        "tool_use" => Some(ModelCapability::ToolUse),
+        "long_context" => Some(ModelCapability::LongContext),
+        "cheap" => Some(ModelCapability::Cheap),
+        other => {
+            set_last_error(format!("Unknown capability: {}", other));
+            None
+        }
+    }
+}
+
+fn into_handle(chat: SingleChat, system: Option<&str>) -> *mut RhineChat {
+    let mut chat = chat;
+    if let Some(system) = system
+        && let Err(report) = chat.base.add_message(rhine::chat::message::Role::System, system)
+    {
+        report_error(report);
+        return ptr::null_mut();
+    }
+    Box::into_raw(Box::new(RhineChat { chat }))
+}
+
+/// 本线程最近一次错误的描述，没有错误时为 NULL；指针在本线程下一次调用前有效，无需释放
+/// Description of the latest error on the calling thread, NULL if none; valid until the next call on this thread
+/// and must not be freed
+#[unsafe(no_mangle)]
+pub extern "C" fn rhine_last_error() -> *const c_char {
+    LAST_ERROR.with(|error| error.borrow().as_ref().map_or(ptr::null(), |message| message.as_ptr()))
+}
+
+/// 从 TOML 文件加载配置，`profile` 可为 NULL
+/// Load the configuration from a TOML file, `profile` may be NULL
+///
+/// # Safety
+/// 参数为空或指向有效的 NUL 结尾字符串
+/// Arguments are NULL or point to valid NUL-terminated strings
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rhine_config_load(path: *const c_char, profile: *const c_char) -> c_int {
+    guard(RHINE_PANIC, || {
+        let Some(path) = (unsafe { read_str(path, "path") }) else {
+            return RHINE_INVALID_ARGUMENT;
+        };
+        let Ok(profile) = (unsafe { read_optional_str(profile, "profile") }) else {
+            return RHINE_INVALID_ARGUMENT;
+        };
+        match Config::load_with_profile(path, profile) {
+            Ok(()) => RHINE_OK,
+            Err(report) => report_error(report),
+        }
+    })
+}
+
+/// 添加API来源
+/// Add an API source
+///
+/// # Safety
+/// 参数指向有效的 NUL 结尾字符串
+/// Arguments point to valid NUL-terminated strings
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rhine_config_add_source(name: *const c_char, base_url: *const c_char, parallelism: usize) -> c_int {
+    guard(RHINE_PANIC, || {
+        let (Some(name), Some(base_url)) = (unsafe { read_str(name, "name") }, unsafe { read_str(base_url, "base_url") }) else {
+            return RHINE_INVALID_ARGUMENT;
+        };
+        Config::add_api_source(name, base_url, parallelism);
+        RHINE_OK
+    })
+}
+
+/// 添加API，`capability` 为 `think`、`tool_use` 或 `long_context`
+/// Add an API, `capability` is `think`, `tool_use` or `long_context`
+///
+/// # Safety
+/// 参数指向有效的 NUL 结尾字符串
+/// Arguments point to valid NUL-terminated strings
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rhine_config_add_api(
+    name: *const c_char,
+    model: *const c_char,
+    capability: *const c_char,
+    source: *const c_char,
+    api_key: *const c_char,
+) -> c_int {
+    guard(RHINE_PANIC, || {
+        let arguments = unsafe {
+            (
+                read_str(name, "name"),
+                read_str(model, "model"),
+                read_str(capability, "capability"),
+                read_str(source, "source"),
+                read_str(api_key, "api_key"),
+            )
+        };
+        let (Some(name), Some(model), Some(capability), Some(source), Some(api_key)) = arguments else {
+            return RHINE_INVALID_ARGUMENT;
+        };
+        let Some(capability) = parse_capability(capability) else {
+            return RHINE_INVALID_ARGUMENT;
+        };
+        match Config::add_api_info(name, model, capability, source, api_key) {
+            Ok(()) => RHINE_OK,
+            Err(report) => report_error(report),
+        }
+    })
+}
+
+/// 按API名称创建对话，`system` 可为 NULL；失败时返回 NULL
+/// Create a chat by API name, `system` may be NULL; returns NULL on failure
+///
+/// # Safety
+/// 参数为空或指向有效的 NUL 结尾字符串
+/// Arguments are NULL or point to valid NUL-terminated strings
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rhine_chat_create(api_name: *const c_char, system: *const c_char, stream: bool) -> *mut RhineChat {
+    guard(ptr::null_mut(), || {
+        let Some(api_name) = (unsafe { read_str(api_name, "api_name") }) else {
+            return ptr::null_mut();
+        };
+        let Ok(system) = (unsafe { read_optional_str(system, "system") }) else {
+            return ptr::null_mut();
+        };
+        match SingleChat::try_new_with_api_name(api_name, "", stream) {
+            Ok(chat) => into_handle(chat, system),
+            Err(report) => {
+                report_error(report);
+                ptr::null_mut()
+            }
+        }
+    })
+}
+
+/// 按能力创建对话，`capability` 为 `think`、`tool_use` 或 `long_context`，`system` 可为 NULL；失败时返回 NULL
+/// Create a chat by capability, `capability` is `think`, `tool_use` or `long_context` and `system` may be NULL;
+/// returns NULL on failure
+///
+/// # Safety
+/// 参数为空或指向有效的 NUL 结尾字符串
+/// Arguments are NULL or point to valid NUL-terminated strings
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rhine_chat_create_with_capability(
+    capability: *const c_char,
+    system: *const c_char,
+    stream: bool,
+) -> *mut RhineChat {
+    guard(ptr::null_mut(), || {
+        let Some(capability) = (unsafe { read_str(capability, "capability") }).and_then(parse_capability) else {
+            return ptr::null_mut();
+        };
+        let Ok(system) = (unsafe { read_optional_str(system, "system") }) else {
+            return ptr::null_mut();
+        };
+        match SingleChat::try_new_with_model_capability(capability, "", stream) {
+            Ok(chat) => into_handle(chat, system),
+            Err(report) => {
+                report_error(report);
+                ptr::null_mut()
+            }
+        }
+    })
+}
+
+/// 提问并阻塞等待回答，失败时返回 NULL；回答用 `rhine_string_free` 释放
+/// Ask and block until the answer arrives, NULL on failure; free the answer with `rhine_string_free`
+///
+/// # Safety
+/// `chat` 为 `rhine_chat_create*` 返回且未释放的句柄，`input` 指向有效的 NUL 结尾字符串
+/// `chat` is a live handle from `rhine_chat_create*`, `input` points to a valid NUL-terminated string
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rhine_chat_ask(chat: *mut RhineChat, input: *const c_char) -> *mut c_char {
+    guard(ptr::null_mut(), || {
+        let Some(handle) = (unsafe { chat.as_mut() }) else {
+            set_last_error("Argument 'chat' is NULL".to_string());
+            return ptr::null_mut();
+        };
+        let Some(input) = (unsafe { read_str(input, "input") }) else {
+            return ptr::null_mut();
+        };
+        match block_on(handle.chat.get_answer(input)) {
+            Ok(answer) => into_c_string(answer),
+            Err(report) => {
+                report_error(report);
+                ptr::null_mut()
+            }
+        }
+    })
+}
+
+/// 提问并以回调逐段接收回答，阻塞到回答结束；回调返回非零值时停止，此时回答不写入对话历史
+/// Ask and receive the answer piece by piece through the callback, blocking until it ends; stops when the callback
+/// returns non-zero, in which case the answer is not written to the history
+///
+/// 回调在调用线程上执行，`delta` 只在回调期间有效。
+/// The callback runs on the calling thread, `delta` is only valid during the callback.
+///
+/// 回调为 NULL 时返回 `RHINE_INVALID_ARGUMENT`，不发出请求。
+/// Returns `RHINE_INVALID_ARGUMENT` without sending a request if the callback is NULL.
+///
+/// # Safety
+/// `chat` 为有效句柄，`input` 指向有效的 NUL 结尾字符串，`user_data` 原样传给回调
+/// `chat` is a live handle, `input` points to a valid NUL-terminated string, `user_data` is passed to the callback
+/// as is
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rhine_chat_stream(
+    chat: *mut RhineChat,
+    input: *const c_char,
+    callback: RhineStreamCallback,
+    user_data: *mut c_void,
+) -> c_int {
+    guard(RHINE_PANIC, || {
+        let Some(handle) = (unsafe { chat.as_mut() }) else {
+            set_last_error("Argument 'chat' is NULL".to_string());
+            return RHINE_INVALID_ARGUMENT;
+        };
+        let Some(input) = (unsafe { read_str(input, "input") }) else {
+            return RHINE_INVALID_ARGUMENT;
+        };
+        let Some(callback) = callback else {
+            set_last_error("Argument 'callback' is NULL".to_string());
+            return RHINE_INVALID_ARGUMENT;
+        };
+        block_on(async {
+            let deltas = match handle.chat.stream_answer(input).await {
+                Ok(deltas) => deltas,
+                Err(report) => return report_error(report),
+            };
+            let mut deltas = std::pin::pin!(deltas);
+            while let Some(delta) = deltas.next().await {
+                let delta = match delta {
+                    Ok(delta) => delta,
+                    Err(report) => return report_error(report),
+                };
+                let delta = CString::new(delta.replace('\0', " ")).unwrap_or_default();
+                if callback(delta.as_ptr(), user_data) != 0 {
+                    return RHINE_CANCELLED;
+                }
+            }
+            RHINE_OK
+        })
+    })
+}
+
+/// 对话累计消耗的 token 数
+/// Tokens used by the chat so far
+///
+/// # Safety
+/// `chat` 为空或有效句柄
+/// `chat` is NULL or a live handle
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rhine_chat_usage(chat: *const RhineChat) -> i32 {
+    unsafe { chat.as_ref() }.map_or(0, |handle| handle.chat.base.usage)
+}
+
+/// 释放对话句柄，可传入 NULL
+/// Free a chat handle, NULL is allowed
+///
+/// # Safety
+/// `chat` 为空或尚未释放的有效句柄
+/// `chat` is NULL or a live handle not yet freed
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rhine_chat_free(chat: *mut RhineChat) {
+    if !chat.is_null() {
+        drop(unsafe { Box::from_raw(chat) });
+    }
+}
+
+/// 释放 rhine 返回的字符串，可传入 NULL
+/// Free a string returned by rhine, NULL is allowed
+///
+/// # Safety
+/// `value` 为空或由 rhine 返回且尚未释放
+/// `value` is NULL or was returned by rhine and not yet freed
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn rhine_string_free(value: *mut c_char) {
+    if !value.is_null() {
+        drop(unsafe { CString::from_raw(value) });
+    }
+}
+
+#[cfg(test)]
+mod tests;
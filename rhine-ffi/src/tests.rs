@@ -0,0 +1,80 @@
+use std::ffi::{CStr, CString, c_char, c_int, c_void};
+use std::ptr;
+
+use rhine::blocking::block_on;
+use rhine::testing::MockProvider;
+
+use super::*;
+
+fn c(value: &str) -> CString {
+    CString::new(value).unwrap()
+}
+
+fn last_error() -> String {
+    let error = rhine_last_error();
+    assert!(!error.is_null(), "No error was recorded");
+    unsafe { CStr::from_ptr(error) }.to_string_lossy().into_owned()
+}
+
+/// 将每段回答追加到 `user_data` 指向的字符串，收到 `stop` 时停止
+/// Append every piece of the answer to the string behind `user_data`, stopping at `stop`
+extern "C" fn collect(delta: *const c_char, user_data: *mut c_void) -> c_int {
+    let received = unsafe { &mut *(user_data as *mut String) };
+    let delta = unsafe { CStr::from_ptr(delta) }.to_str().unwrap();
+    received.push_str(delta);
+    (delta.trim() == "stop") as c_int
+}
+
+#[test]
+fn chat_round_trip_through_the_c_abi() {
+    let mock = block_on(MockProvider::start("ffi-mock"));
+    mock.reply("hello from rhine").reply("streamed reply").reply("please stop now");
+
+    let chat = unsafe { rhine_chat_create(c("ffi-mock").as_ptr(), c("be brief").as_ptr(), false) };
+    assert!(!chat.is_null(), "{}", last_error());
+
+    let answer = unsafe { rhine_chat_ask(chat, c("hi").as_ptr()) };
+    assert!(!answer.is_null(), "{}", last_error());
+    assert_eq!(unsafe { CStr::from_ptr(answer) }.to_str().unwrap(), "hello from rhine");
+    unsafe { rhine_string_free(answer) };
+    mock.last_request().contains("be brief").contains("hi");
+
+    let mut received = String::new();
+    let user_data = &mut received as *mut String as *mut c_void;
+    let status = unsafe { rhine_chat_stream(chat, c("again").as_ptr(), Some(collect), user_data) };
+    assert_eq!(status, RHINE_OK, "{}", last_error());
+    assert_eq!(received, "streamed reply");
+
+    // 回调返回非零值时停止接收
+    // Receiving stops once the callback returns non-zero
+    received.clear();
+    let user_data = &mut received as *mut String as *mut c_void;
+    let status = unsafe { rhine_chat_stream(chat, c("once more").as_ptr(), Some(collect), user_data) };
+    assert_eq!(status, RHINE_CANCELLED);
+    assert_eq!(received, "please stop ");
+
+    unsafe { rhine_chat_free(chat) };
+}
+
+#[test]
+fn invalid_arguments_are_rejected() {
+    let status = unsafe { rhine_chat_stream(ptr::null_mut(), c("hi").as_ptr(), Some(collect), ptr::null_mut()) };
+    assert_eq!(status, RHINE_INVALID_ARGUMENT);
+    assert_eq!(last_error(), "Argument 'chat' is NULL");
+
+    let mock = block_on(MockProvider::start("ffi-mock-null-callback"));
+    let chat = unsafe { rhine_chat_create(c(mock.api_name()).as_ptr(), ptr::null(), true) };
+    assert!(!chat.is_null(), "{}", last_error());
+    let status = unsafe { rhine_chat_stream(chat, c("hi").as_ptr(), None, ptr::null_mut()) };
+    assert_eq!(status, RHINE_INVALID_ARGUMENT);
+    assert_eq!(last_error(), "Argument 'callback' is NULL");
+    assert_eq!(mock.requests().len(), 0);
+    unsafe { rhine_chat_free(chat) };
+
+    let chat = unsafe { rhine_chat_create(c("no-such-api").as_ptr(), ptr::null(), false) };
+    assert!(chat.is_null());
+    assert!(last_error().starts_with("chat.invalid_config"), "{}", last_error());
+
+    let status = unsafe { rhine_config_add_api(ptr::null(), ptr::null(), ptr::null(), ptr::null(), ptr::null()) };
+    assert_eq!(status, RHINE_INVALID_ARGUMENT);
+}
@@ -0,0 +1,342 @@
+//! rhine 的 gRPC 服务：以类型化接口托管对话，接口定义见 `proto/rhine.proto`
+//! gRPC service of rhine: hosts chats behind a typed API, see `proto/rhine.proto` for the interface
+//!
+//! 每个对话由 `CreateChat` 创建并以ID引用，同一对话上的请求依次执行。
+//! Every chat is created by `CreateChat` and referenced by its ID, requests on one chat run one after another.
+//!
+//! ```no_run
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! rhine::config::Config::load("config.toml")?;
+//! rhine_grpc::serve("127.0.0.1:50051".parse()?).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+// 标准库
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+
+// 核心基础库
+use dashmap::DashMap;
+
+// 错误处理
+use error_stack::Report;
+
+// 异步
+use futures::{Stream, StreamExt};
+use tokio::sync::{Mutex as AsyncMutex, broadcast, mpsc};
+use tokio_stream::wrappers::{BroadcastStream, ReceiverStream};
+
+// gRPC
+use tonic::{Request, Response, Status};
+
+// 项目内部模块
+use rhine::chat::chat_single::SingleChat;
+use rhine::chat::event::ChatEvent;
+use rhine::chat::message::{Messages, Session};
+use rhine::config::ModelCapability;
+use rhine::error::{ReportExt, RhineError};
+
+/// 由 `proto/rhine.proto` 生成的消息与服务
+/// Messages and services generated from `proto/rhine.proto`
+pub mod proto {
+    tonic::include_proto!("rhine.v1");
+}
+
+use proto::rhine_server::{Rhine, RhineServer};
+
+/// 每个订阅者可缓冲的工具事件数，落后更多时跳过最旧的事件
+/// Tool events buffered per subscriber, the oldest are skipped when a subscriber falls further behind
+const TOOL_EVENT_BUFFER: usize = 256;
+
+/// 服务中的一个对话
+/// A chat hosted by the service
+struct HostedChat {
+    chat: Arc<AsyncMutex<SingleChat>>,
+    tool_events: broadcast::Sender<proto::ToolEvent>,
+}
+
+/// gRPC 服务实现，持有所有已创建的对话
+/// gRPC service implementation, holding every created chat
+#[derive(Clone, Default)]
+pub struct RhineService {
+    chats: Arc<DashMap<String, Arc<HostedChat>>>,
+}
+
+impl RhineService {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 包装为可注册到 tonic 服务器的服务
+    /// Wrap into a service that can be added to a tonic server
+    pub fn into_server(self) -> RhineServer<Self> {
+        RhineServer::new(self)
+    }
+
+    fn hosted(&self, chat_id: &str) -> Result<Arc<HostedChat>, Status> {
+        self.chats
+            .get(chat_id)
+            .map(|hosted| hosted.value().clone())
+            .ok_or_else(|| Status::not_found(format!("Unknown chat: {}", chat_id)))
+    }
+}
+
+/// 在指定地址上运行服务，直到出错
+/// Run the service on the address until it fails
+pub async fn serve(addr: SocketAddr) -> Result<(), tonic::transport::Error> {
+    tonic::transport::Server::builder()
+        .add_service(RhineService::new().into_server())
+        .serve(addr)
+        .await
+}
+
+/// 将错误报告转换为 gRPC 状态，消息以错误码开头；完整报告只写入日志，状态随响应头发出，不能带上调用栈
+/// Convert an error report into a gRPC status, the message starts with the error code; the full report only goes to
+/// the log, the status travels in response headers and cannot carry backtraces
+fn to_status<C: RhineError>(report: Report<C>) -> Status {
+    tracing::warn!("Request failed: {:?}", report);
+    let message = format!("{}: {}", report.code(), report.current_context());
+    match report.status() {
+        Some(429) => Status::resource_exhausted(message),
+        Some(400 | 422) => Status::invalid_argument(message),
+        Some(401 | 403) => Status::permission_denied(message),
+        _ if report.is_retryable() => Status::unavailable(message),
+        _ => Status::internal(message),
+    }
+}
+
+fn parse_capability(capability: &str) -> Result<ModelCapability, Status> {
+    match capability {
+        "think" => Ok(ModelCapability::Think),
+        "tool_use" => Ok(ModelCapability::ToolUse),
+        "long_context" => Ok(ModelCapability::LongContext),
+        "cheap" => Ok(ModelCapability::Cheap),
+        other => Err(Status::invalid_argument(format!("Unknown capability: {}", other))),
+    }
+}
+
+/// 转发工具调用事件，其他事件忽略
+/// Forward tool call events, other events are ignored
+fn to_tool_event(event: &ChatEvent) -> Option<proto::ToolEvent> {
+    use proto::tool_event::Kind;
+    match event {
+        ChatEvent::ToolCallStarted { call_id, name, arguments } => Some(proto::ToolEvent {
+            call_id: call_id.clone(),
+            name: name.clone(),
+            kind: Some(Kind::Started(proto::ToolCallStarted {
+                arguments: arguments.to_string(),
+            })),
+        }),
+        ChatEvent::ToolCallFinished {
+            call_id,
+            name,
+            output,
+            error,
+            duration,
+        } => Some(proto::ToolEvent {
+            call_id: call_id.clone(),
+            name: name.clone(),
+            kind: Some(Kind::Finished(proto::ToolCallFinished {
+                output: output.clone(),
+                error: error.clone(),
+                duration_ms: duration.as_millis() as u64,
+            })),
+        }),
+        _ => None,
+    }
+}
+
+fn to_proto_message(message: &Messages) -> proto::Message {
+    proto::Message {
+        id: message.id.clone(),
+        role: message.role.to_string(),
+        content: message.content.to_text().into_owned(),
+        children: message.child.iter().map(to_proto_message).collect(),
+    }
+}
+
+fn to_proto_session(session: &Session) -> proto::Session {
+    proto::Session {
+        roots: session.message_roots.iter().map(to_proto_message).collect(),
+        default_path: session.default_path.iter().map(|&index| index as u32).collect(),
+    }
+}
+
+fn done(chat: &SingleChat, answer: String, tool_results: Vec<String>) -> proto::ChatResponse {
+    proto::ChatResponse {
+        answer,
+        tool_results,
+        message_id: chat.last_message_id().unwrap_or_default().to_string(),
+        usage: chat.base.usage,
+    }
+}
+
+/// 定位到请求指定的父消息
+/// Move to the parent message named by the request
+fn checkout(chat: &mut SingleChat, parent_id: Option<&str>) -> Result<(), Status> {
+    match parent_id {
+        Some(parent_id) => chat
+            .base
+            .session
+            .checkout(parent_id)
+            .map_err(|error| Status::not_found(error.to_string())),
+        None => Ok(()),
+    }
+}
+
+/// 提问并等待完整回答；对话设置了工具时执行工具调用
+/// Ask and wait for the whole answer; runs tool calls if the chat has tools
+async fn answer(chat: &mut SingleChat, input: &str) -> Result<proto::ChatResponse, Status> {
+    if chat.tools().is_empty() {
+        let answer = chat.get_answer(input).await.map_err(to_status)?;
+        Ok(done(chat, answer, Vec::new()))
+    } else {
+        let (answer, tool_results) = chat.get_tool_answer(input).await.map_err(to_status)?;
+        Ok(done(chat, answer, tool_results))
+    }
+}
+
+type ServiceStream<T> = Pin<Box<dyn Stream<Item = Result<T, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl Rhine for RhineService {
+    async fn create_chat(
+        &self,
+        request: Request<proto::CreateChatRequest>,
+    ) -> Result<Response<proto::CreateChatResponse>, Status> {
+        let request = request.into_inner();
+        let mut builder = SingleChat::builder();
+        if let Some(api) = &request.api {
+            builder = builder.api(api);
+        }
+        match (&request.capability, &request.api) {
+            (Some(capability), _) => builder = builder.capability(parse_capability(capability)?),
+            (None, None) => builder = builder.capability(ModelCapability::LongContext),
+            (None, Some(_)) => {}
+        }
+        if let Some(system) = &request.system {
+            builder = builder.system(system);
+        }
+        if !request.tools.is_empty() {
+            let tools = request
+                .tools
+                .iter()
+                .map(|tool| serde_json::from_str(tool))
+                .collect::<Result<Vec<serde_json::Value>, _>>()
+                .map_err(|e| Status::invalid_argument(format!("Invalid tool definition: {}", e)))?;
+            builder = builder.tools(tools);
+        }
+        let mut chat = builder.build().map_err(to_status)?;
+
+        let (tool_events, _) = broadcast::channel(TOOL_EVENT_BUFFER);
+        let sender = tool_events.clone();
+        chat.on_event(move |event| {
+            if let Some(event) = to_tool_event(event) {
+                let _ = sender.send(event);
+            }
+        });
+
+        let chat_id = uuid::Uuid::new_v4().to_string();
+        let hosted = HostedChat {
+            chat: Arc::new(AsyncMutex::new(chat)),
+            tool_events,
+        };
+        self.chats.insert(chat_id.clone(), Arc::new(hosted));
+        Ok(Response::new(proto::CreateChatResponse { chat_id }))
+    }
+
+    async fn chat(&self, request: Request<proto::ChatRequest>) -> Result<Response<proto::ChatResponse>, Status> {
+        let request = request.into_inner();
+        let hosted = self.hosted(&request.chat_id)?;
+        let mut chat = hosted.chat.lock().await;
+        checkout(&mut chat, request.parent_id.as_deref())?;
+        answer(&mut chat, &request.input).await.map(Response::new)
+    }
+
+    type StreamChatStream = ServiceStream<proto::ChatChunk>;
+
+    async fn stream_chat(
+        &self,
+        request: Request<proto::ChatRequest>,
+    ) -> Result<Response<Self::StreamChatStream>, Status> {
+        use proto::chat_chunk::Kind;
+
+        let request = request.into_inner();
+        let hosted = self.hosted(&request.chat_id)?;
+        let (sender, receiver) = mpsc::channel(64);
+        tokio::spawn(async move {
+            let mut chat = hosted.chat.lock().await;
+            if let Err(status) = checkout(&mut chat, request.parent_id.as_deref()) {
+                let _ = sender.send(Err(status)).await;
+                return;
+            }
+
+            // 工具调用需要完整回答，只发送最后一段
+            // Tool calls need the whole answer, only the last chunk is sent
+            if !chat.tools().is_empty() {
+                let response = answer(&mut chat, &request.input).await;
+                let _ = sender.send(response.map(|done| proto::ChatChunk { kind: Some(Kind::Done(done)) })).await;
+                return;
+            }
+
+            let mut content = String::new();
+            {
+                let deltas = match chat.stream_answer(&request.input).await {
+                    Ok(deltas) => deltas,
+                    Err(report) => {
+                        let _ = sender.send(Err(to_status(report))).await;
+                        return;
+                    }
+                };
+                let mut deltas = std::pin::pin!(deltas);
+                while let Some(delta) = deltas.next().await {
+                    let chunk = delta.map_err(to_status).map(|delta| {
+                        content.push_str(&delta);
+                        proto::ChatChunk { kind: Some(Kind::Delta(delta)) }
+                    });
+                    let failed = chunk.is_err();
+                    // 客户端断开时停止接收，回答不写入对话历史
+                    // Stop receiving once the client is gone, the answer is then not written to the history
+                    if sender.send(chunk).await.is_err() || failed {
+                        return;
+                    }
+                }
+            }
+            let done = done(&chat, content, Vec::new());
+            let _ = sender.send(Ok(proto::ChatChunk { kind: Some(Kind::Done(done)) })).await;
+        });
+        Ok(Response::new(Box::pin(ReceiverStream::new(receiver))))
+    }
+
+    type ToolEventsStream = ServiceStream<proto::ToolEvent>;
+
+    async fn tool_events(
+        &self,
+        request: Request<proto::ToolEventsRequest>,
+    ) -> Result<Response<Self::ToolEventsStream>, Status> {
+        let hosted = self.hosted(&request.into_inner().chat_id)?;
+        // 落后过多的订阅者跳过丢失的事件继续接收
+        // Subscribers that fall too far behind skip the lost events and keep receiving
+        let events = BroadcastStream::new(hosted.tool_events.subscribe()).filter_map(|event| async move { event.ok().map(Ok) });
+        Ok(Response::new(Box::pin(events)))
+    }
+
+    async fn get_session(&self, request: Request<proto::GetSessionRequest>) -> Result<Response<proto::Session>, Status> {
+        let hosted = self.hosted(&request.into_inner().chat_id)?;
+        let chat = hosted.chat.lock().await;
+        Ok(Response::new(to_proto_session(&chat.base.session)))
+    }
+
+    async fn close_chat(
+        &self,
+        request: Request<proto::CloseChatRequest>,
+    ) -> Result<Response<proto::CloseChatResponse>, Status> {
+        let chat_id = request.into_inner().chat_id;
+        match self.chats.remove(&chat_id) {
+            Some(_) => Ok(Response::new(proto::CloseChatResponse {})),
+            None => Err(Status::not_found(format!("Unknown chat: {}", chat_id))),
+        }
+    }
+}
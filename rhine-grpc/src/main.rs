@@ -0,0 +1,54 @@
+//! rhine gRPC 服务端
+//! rhine gRPC server
+//!
+//! ```text
+//! rhine-grpc --config config.toml --addr 127.0.0.1:50051
+//! ```
+
+// 标准库
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+// 命令行
+use clap::Parser;
+
+// 项目内部模块
+use rhine::config::Config;
+
+#[derive(Debug, Parser)]
+#[command(name = "rhine-grpc", version, about = "Rhine Lab gRPC server")]
+struct Cli {
+    /// 配置文件路径 / Config file path
+    #[arg(long, short, default_value = "config.toml")]
+    config: PathBuf,
+
+    /// 配置环境，默认读取 RHINE_PROFILE / Config profile, RHINE_PROFILE by default
+    #[arg(long)]
+    profile: Option<String>,
+
+    /// 监听地址 / Listen address
+    #[arg(long, default_value = "127.0.0.1:50051")]
+    addr: SocketAddr,
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    let path = cli.config.to_string_lossy();
+    let profile = cli.profile.or_else(|| std::env::var("RHINE_PROFILE").ok().filter(|profile| !profile.is_empty()));
+    if let Err(report) = Config::load_with_profile(&path, profile.as_deref()) {
+        eprintln!("Failed to load {}: {:?}", path, report);
+        return ExitCode::FAILURE;
+    }
+
+    println!("Serving rhine.v1.Rhine on {}", cli.addr);
+    match rhine_grpc::serve(cli.addr).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("Server failed: {}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
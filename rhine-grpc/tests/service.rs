@@ -0,0 +1,99 @@
+use futures::StreamExt;
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::Code;
+use tonic::transport::{Channel, Server};
+
+use rhine::testing::MockProvider;
+use rhine_grpc::RhineService;
+use rhine_grpc::proto::chat_chunk::Kind;
+use rhine_grpc::proto::rhine_client::RhineClient;
+use rhine_grpc::proto::{ChatRequest, CloseChatRequest, CreateChatRequest, GetSessionRequest};
+
+/// 在随机端口上启动服务并连接客户端
+/// Start the service on a random port and connect a client
+async fn connect() -> RhineClient<Channel> {
+    let listener = TcpListener::bind("127.0.0.1:0").await.expect("Failed to bind the service");
+    let addr = listener.local_addr().expect("Listener has no address");
+    tokio::spawn(
+        Server::builder()
+            .add_service(RhineService::new().into_server())
+            .serve_with_incoming(TcpListenerStream::new(listener)),
+    );
+    RhineClient::connect(format!("http://{}", addr)).await.expect("Failed to connect to the service")
+}
+
+fn ask(chat_id: &str, input: &str) -> ChatRequest {
+    ChatRequest {
+        chat_id: chat_id.to_string(),
+        input: input.to_string(),
+        parent_id: None,
+    }
+}
+
+#[tokio::test]
+async fn chat_stream_and_session_round_trip() {
+    let mock = MockProvider::start("grpc-mock").await;
+    mock.reply("hello there").reply("streamed answer");
+    let mut client = connect().await;
+
+    let create = CreateChatRequest {
+        api: Some(mock.api_name().to_string()),
+        system: Some("be brief".to_string()),
+        ..Default::default()
+    };
+    let chat_id = client.create_chat(create).await.expect("CreateChat failed").into_inner().chat_id;
+
+    let response = client.chat(ask(&chat_id, "hi")).await.expect("Chat failed").into_inner();
+    assert_eq!(response.answer, "hello there");
+    assert!(!response.message_id.is_empty());
+    mock.last_request().contains("be brief").contains("hi");
+
+    let mut chunks = client.stream_chat(ask(&chat_id, "again")).await.expect("StreamChat failed").into_inner();
+    let mut deltas = String::new();
+    let mut done = None;
+    while let Some(chunk) = chunks.next().await {
+        match chunk.expect("Stream chunk failed").kind {
+            Some(Kind::Delta(delta)) => deltas.push_str(&delta),
+            Some(Kind::Done(response)) => done = Some(response),
+            None => {}
+        }
+    }
+    assert_eq!(deltas, "streamed answer");
+    assert_eq!(done.expect("Stream ended without a final chunk").answer, "streamed answer");
+
+    let session = client
+        .get_session(GetSessionRequest { chat_id: chat_id.clone() })
+        .await
+        .expect("GetSession failed")
+        .into_inner();
+    assert_eq!(session.default_path.len(), 5);
+    assert_eq!(session.roots[0].role, "system");
+
+    client.close_chat(CloseChatRequest { chat_id: chat_id.clone() }).await.expect("CloseChat failed");
+    let status = client.chat(ask(&chat_id, "still there?")).await.expect_err("Closed chat answered");
+    assert_eq!(status.code(), Code::NotFound);
+}
+
+#[tokio::test]
+async fn provider_errors_map_to_status_codes() {
+    let mock = MockProvider::start("grpc-mock-errors").await;
+    mock.reply_error(429, r#"{"error":"rate limited"}"#);
+    let mut client = connect().await;
+
+    let create = CreateChatRequest {
+        api: Some(mock.api_name().to_string()),
+        ..Default::default()
+    };
+    let chat_id = client.create_chat(create).await.expect("CreateChat failed").into_inner().chat_id;
+    let status = client.chat(ask(&chat_id, "hi")).await.expect_err("Rate limited request succeeded");
+    assert_eq!(status.code(), Code::ResourceExhausted, "{}", status.message());
+    assert!(status.message().starts_with("chat.http_error"), "{}", status.message());
+
+    let create = CreateChatRequest {
+        capability: Some("psychic".to_string()),
+        ..Default::default()
+    };
+    let status = client.create_chat(create).await.expect_err("Unknown capability accepted");
+    assert_eq!(status.code(), Code::InvalidArgument);
+}
@@ -0,0 +1,70 @@
+// rhine 的 TypeScript 类型声明
+// TypeScript declarations of rhine
+
+export type Capability = "think" | "tool_use" | "long_context";
+
+export interface ChatOptions {
+  /** 按名称（或别名）选择API / Select the API by name (or alias) */
+  api?: string;
+  /** 二者都未给出时为 long_context / long_context if neither this nor `api` is given */
+  capability?: Capability;
+  system?: string;
+  stream?: boolean;
+  temperature?: number;
+  maxTokens?: number;
+  topP?: number;
+}
+
+export interface GroupChatOptions {
+  api?: string;
+  capability?: Capability;
+  stream?: boolean;
+}
+
+export interface ToolAnswer {
+  answer: string;
+  toolResults: string[];
+}
+
+export interface ToolDefinition {
+  type: "function";
+  function: { name: string; description: string; parameters: object };
+}
+
+export function loadConfig(path: string, profile?: string | null): void;
+export function addApiSource(name: string, baseUrl: string, parallelism: number): void;
+export function addApi(name: string, model: string, capability: Capability, source: string, apiKey: string): void;
+
+/** 将函数注册为工具，函数可以是 async 的 / Register a function as a tool, the function may be async */
+export function tool<A = any, R = unknown>(
+  name: string,
+  description: string,
+  parameters: object,
+  fn: (args: A) => R | Promise<R>,
+): ToolDefinition;
+
+export class AnswerStream implements AsyncIterable<string> {
+  /** 下一段回答，结束时为 null / The next piece of the answer, null once it ends */
+  nextDelta(): Promise<string | null>;
+  [Symbol.asyncIterator](): AsyncIterator<string>;
+}
+
+export class SingleChat {
+  constructor(options?: ChatOptions);
+  getAnswer(userInput: string): Promise<string>;
+  getJsonAnswer<T = unknown>(userInput: string, schema: object): Promise<T>;
+  streamAnswer(userInput: string): AnswerStream;
+  setTools(tools: ToolDefinition[]): Promise<void>;
+  getToolAnswer(userInput: string): Promise<ToolAnswer>;
+  usage(): Promise<number>;
+  lastMessageId(): Promise<string | null>;
+}
+
+export class GroupChat {
+  constructor(characters: Record<string, string>, options: GroupChatOptions);
+  setCharacter(character: string): Promise<void>;
+  getAnswer(userInput: string): Promise<string>;
+  dialogue(character: string, userInput: string): Promise<string>;
+  getJsonAnswer<T = unknown>(userInput: string, schema: object): Promise<T>;
+  usage(): Promise<number>;
+}
@@ -0,0 +1,61 @@
+// rhine 的 Node.js 入口：加载原生模块并补充异步迭代与工具包装
+// Node.js entry of rhine: loads the native module and adds async iteration and tool wrapping
+"use strict";
+
+const fs = require("fs");
+const path = require("path");
+
+// `napi build --platform` 生成 rhine.<平台>.node，手动复制的构建产物命名为 rhine.node
+// `napi build --platform` produces rhine.<platform>.node, a build output copied by hand is named rhine.node
+function loadNative() {
+  const candidates = fs
+    .readdirSync(__dirname)
+    .filter((file) => file.startsWith("rhine.") && file.endsWith(".node"))
+    .sort((a, b) => (a === "rhine.node") - (b === "rhine.node"));
+  for (const file of candidates) {
+    try {
+      return require(path.join(__dirname, file));
+    } catch (error) {
+      if (file === candidates[candidates.length - 1]) throw error;
+    }
+  }
+  throw new Error("rhine native module not found, run `npm run build` first");
+}
+
+const native = loadNative();
+
+// 流式回答作为异步迭代器 / Streamed answers as async iterators
+native.AnswerStream.prototype[Symbol.asyncIterator] = function () {
+  return {
+    next: async () => {
+      const delta = await this.nextDelta();
+      return delta === null ? { done: true, value: undefined } : { done: false, value: delta };
+    },
+  };
+};
+
+/**
+ * 将 JavaScript 函数注册为工具，返回传给 `setTools` 的工具定义；函数可以是 async 的
+ * Register a JavaScript function as a tool, returning the definition to pass to `setTools`; the function may be async
+ */
+function tool(name, description, parameters, fn) {
+  native.registerTool(name, (args, done) => {
+    Promise.resolve()
+      .then(() => fn(args))
+      .then(
+        (result) => done(null, result === undefined ? null : result),
+        (error) => done(String(error && error.message ? error.message : error)),
+      );
+  });
+  return { type: "function", function: { name, description, parameters } };
+}
+
+module.exports = {
+  loadConfig: native.loadConfig,
+  addApiSource: native.addApiSource,
+  addApi: native.addApi,
+  tool,
+  SingleChat: native.SingleChat,
+  GroupChat: native.GroupChat,
+  AnswerStream: native.AnswerStream,
+};
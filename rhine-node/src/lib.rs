@@ -0,0 +1,344 @@
+//! rhine 的 Node.js 绑定：以 async/await 调用对话、以异步迭代器接收流式回答、以 JavaScript 函数作为工具
+//! Node.js bindings of rhine: chats with async/await, streamed answers as async iterators, and JavaScript functions
+//! as tools
+//!
+//! 原生模块由 `index.js` 包装，JavaScript 一侧应使用该文件导出的接口。
+//! The native module is wrapped by `index.js`, JavaScript code should use the interface exported there.
+//!
+//! ```js
+//! const rhine = require("rhine");
+//!
+//! rhine.loadConfig("config.toml");
+//! const chat = new rhine.SingleChat({ capability: "long_context", system: "be brief" });
+//! for await (const delta of chat.streamAnswer("Tell me a story")) {
+//!   process.stdout.write(delta);
+//! }
+//! ```
+
+// 标准库
+use std::collections::HashMap;
+use std::sync::Arc;
+
+// 错误处理
+use error_stack::{Report, ResultExt};
+
+// 异步
+use futures::StreamExt;
+use tokio::sync::{Mutex as AsyncMutex, mpsc, oneshot};
+
+// Node.js 绑定
+use napi::bindgen_prelude::spawn;
+use napi::threadsafe_function::{ErrorStrategy, ThreadSafeCallContext, ThreadsafeFunction, ThreadsafeFunctionCallMode};
+use napi::{Env, JsFunction, JsUnknown};
+use napi_derive::napi;
+
+// 项目内部模块
+use rhine::chat::builder::SingleChatBuilder;
+use rhine::chat::chat_multi::MultiChat;
+use rhine::chat::chat_single::SingleChat as RustSingleChat;
+use rhine::chat::params::ChatParams;
+use rhine::config::{Config, ModelCapability};
+use rhine::error::{ReportExt, RhineError};
+use rhine::schema::json_schema::response_format;
+use rhine::schema::tool_schema::{ChatToolSchemaError, get_tool_registry};
+
+/// 将错误报告转换为 JavaScript 错误，消息以错误码开头
+/// Convert an error report into a JavaScript error, the message starts with the error code
+fn to_napi_err<C: RhineError>(report: Report<C>) -> napi::Error {
+    napi::Error::from_reason(format!("{}: {:?}", report.code(), report))
+}
+
+fn parse_capability(capability: &str) -> napi::Result<ModelCapability> {
+    serde_json::from_value(serde_json::Value::String(capability.to_string()))
+        .map_err(|_| napi::Error::from_reason(format!("Unknown capability: {}", capability)))
+}
+
+#[napi]
+pub fn load_config(path: String, profile: Option<String>) -> napi::Result<()> {
+    Config::load_with_profile(&path, profile.as_deref()).map_err(to_napi_err)
+}
+
+#[napi]
+pub fn add_api_source(name: String, base_url: String, parallelism: u32) {
+    Config::add_api_source(&name, &base_url, parallelism as usize);
+}
+
+#[napi]
+pub fn add_api(name: String, model: String, capability: String, source: String, api_key: String) -> napi::Result<()> {
+    Config::add_api_info(&name, &model, parse_capability(&capability)?, &source, &api_key).map_err(to_napi_err)
+}
+
+/// 一次工具调用：模型给出的参数与回传结果的通道
+/// One tool call: the arguments chosen by the model and the channel carrying the result back
+struct ToolCall {
+    arguments: serde_json::Value,
+    reply: oneshot::Sender<Result<serde_json::Value, String>>,
+}
+
+/// 在 JavaScript 线程上组装调用参数：`(arguments, done)`，`done(error, result)` 回传结果
+/// Build the call arguments on the JavaScript thread: `(arguments, done)`, where `done(error, result)` reports back
+fn tool_call_arguments(context: ThreadSafeCallContext<ToolCall>) -> napi::Result<Vec<JsUnknown>> {
+    let ToolCall { arguments, reply } = context.value;
+    // `done` 可能被多次调用，只有第一次的结果回传
+    // `done` may be called more than once, only the first result is reported back
+    let reply = std::sync::Mutex::new(Some(reply));
+    let done = context.env.create_function_from_closure("done", move |call| {
+        let error: Option<String> = call.get(0)?;
+        let result = match error {
+            Some(error) => Err(error),
+            None => call.get::<serde_json::Value>(1).map_err(|e| e.to_string()),
+        };
+        if let Some(reply) = reply.lock().unwrap().take() {
+            let _ = reply.send(result);
+        }
+        Ok(())
+    })?;
+    Ok(vec![context.env.to_js_value(&arguments)?, done.into_unknown()])
+}
+
+/// 将 JavaScript 函数注册为工具，`function(arguments, done)` 由 `index.js` 中的 `tool` 包装生成
+/// Register a JavaScript function as a tool, `function(arguments, done)` is produced by the `tool` wrapper in
+/// `index.js`
+///
+/// 工具等待 JavaScript 回传结果时让出所在的异步工作线程，不会阻塞运行时上的其他任务；JavaScript 线程须保持空闲
+/// （即以 await 等待回答）。
+/// While waiting for JavaScript to report back the tool hands its async worker thread over, so other tasks on the
+/// runtime are not blocked; the JavaScript thread must stay free (that is, awaiting the answer).
+#[napi(js_name = "registerTool")]
+pub fn register_tool(env: Env, name: String, function: JsFunction) -> napi::Result<()> {
+    let mut function: ThreadsafeFunction<ToolCall, ErrorStrategy::Fatal> =
+        function.create_threadsafe_function(0, tool_call_arguments)?;
+    // 已注册的工具不阻止 Node.js 进程退出
+    // Registered tools do not keep the Node.js process alive
+    function.unref(&env)?;
+
+    let tool_name = name.clone();
+    let call = move |arguments: serde_json::Value| -> error_stack::Result<serde_json::Value, ChatToolSchemaError> {
+        let (reply, result) = oneshot::channel();
+        function.call(ToolCall { arguments, reply }, ThreadsafeFunctionCallMode::Blocking);
+        tokio::task::block_in_place(|| result.blocking_recv())
+            .map_err(|_| "The JavaScript tool never reported back".to_string())
+            .and_then(|result| result)
+            .map_err(|e| Report::new(ChatToolSchemaError::FunctionCallError).attach_printable(e))
+            .attach_printable_lazy(|| format!("JavaScript tool: {}", tool_name))
+    };
+    get_tool_registry().insert(name, Arc::new(call));
+    Ok(())
+}
+
+#[napi(object)]
+pub struct ChatOptions {
+    /// 按名称（或别名）选择API / Select the API by name (or alias)
+    pub api: Option<String>,
+    /// think、tool_use、long_context 或 cheap / think, tool_use, long_context or cheap
+    pub capability: Option<String>,
+    pub system: Option<String>,
+    pub stream: Option<bool>,
+    pub temperature: Option<f64>,
+    pub max_tokens: Option<u32>,
+    pub top_p: Option<f64>,
+}
+
+#[napi(object)]
+pub struct ToolAnswer {
+    pub answer: String,
+    pub tool_results: Vec<String>,
+}
+
+/// 流式回答，由 `index.js` 包装为异步迭代器
+/// Streamed answer, wrapped into an async iterator by `index.js`
+#[napi]
+pub struct AnswerStream {
+    deltas: Arc<AsyncMutex<mpsc::Receiver<Result<String, String>>>>,
+}
+
+#[napi]
+impl AnswerStream {
+    /// 下一段回答，结束时为 null
+    /// The next piece of the answer, null once it ends
+    #[napi]
+    pub async fn next_delta(&self) -> napi::Result<Option<String>> {
+        match self.deltas.lock().await.recv().await {
+            Some(Ok(delta)) => Ok(Some(delta)),
+            Some(Err(error)) => Err(napi::Error::from_reason(error)),
+            None => Ok(None),
+        }
+    }
+}
+
+/// 单人对话，同一对话上的调用依次执行
+/// Single chat, calls on one chat run one after another
+#[napi]
+pub struct SingleChat {
+    inner: Arc<AsyncMutex<RustSingleChat>>,
+}
+
+#[napi]
+impl SingleChat {
+    /// 按API名称或能力创建对话，二者都给出时API名称优先，都未给出时为 long_context
+    /// Create a chat by API name or capability, the API name wins when both are given, long_context if neither is
+    #[napi(constructor)]
+    pub fn new(options: Option<ChatOptions>) -> napi::Result<Self> {
+        let options = options.unwrap_or(ChatOptions {
+            api: None,
+            capability: None,
+            system: None,
+            stream: None,
+            temperature: None,
+            max_tokens: None,
+            top_p: None,
+        });
+        let mut builder = SingleChatBuilder::new().stream(options.stream.unwrap_or(false)).params(ChatParams {
+            temperature: options.temperature,
+            max_tokens: options.max_tokens,
+            top_p: options.top_p,
+            stop: None,
+            logit_bias: None,
+        });
+        if let Some(api) = &options.api {
+            builder = builder.api(api);
+        }
+        match (&options.capability, &options.api) {
+            (Some(capability), _) => builder = builder.capability(parse_capability(capability)?),
+            (None, None) => builder = builder.capability(ModelCapability::LongContext),
+            (None, Some(_)) => {}
+        }
+        if let Some(system) = &options.system {
+            builder = builder.system(system);
+        }
+        Ok(Self {
+            inner: Arc::new(AsyncMutex::new(builder.build().map_err(to_napi_err)?)),
+        })
+    }
+
+    #[napi]
+    pub async fn get_answer(&self, user_input: String) -> napi::Result<String> {
+        self.inner.lock().await.get_answer(&user_input).await.map_err(to_napi_err)
+    }
+
+    /// 取回符合 JSON Schema 的结构化回答
+    /// Get a structured answer matching the JSON Schema
+    #[napi]
+    pub async fn get_json_answer(&self, user_input: String, schema: serde_json::Value) -> napi::Result<serde_json::Value> {
+        let mut chat = self.inner.lock().await;
+        chat.get_json_answer_with_schema(&user_input, response_format(schema))
+            .await
+            .map_err(to_napi_err)
+    }
+
+    /// 提问并以流的形式接收回答，回答在流结束后加入对话历史
+    /// Ask and receive the answer as a stream, the answer joins the history once the stream ends
+    #[napi]
+    pub fn stream_answer(&self, user_input: String) -> AnswerStream {
+        let (sender, receiver) = mpsc::channel(64);
+        let chat = self.inner.clone();
+        spawn(async move {
+            let mut chat = chat.lock().await;
+            let deltas = match chat.stream_answer(&user_input).await {
+                Ok(deltas) => deltas,
+                Err(report) => {
+                    let _ = sender.send(Err(to_napi_err(report).reason)).await;
+                    return;
+                }
+            };
+            let mut deltas = std::pin::pin!(deltas);
+            while let Some(delta) = deltas.next().await {
+                let delta = delta.map_err(|report| to_napi_err(report).reason);
+                let failed = delta.is_err();
+                if sender.send(delta).await.is_err() || failed {
+                    return;
+                }
+            }
+        });
+        AnswerStream {
+            deltas: Arc::new(AsyncMutex::new(receiver)),
+        }
+    }
+
+    /// 设置工具，工具定义由 `tool` 返回
+    /// Set the tools, whose definitions are returned by `tool`
+    #[napi]
+    pub async fn set_tools(&self, tools: Vec<serde_json::Value>) -> napi::Result<()> {
+        self.inner.lock().await.set_tools(tools).map_err(to_napi_err)
+    }
+
+    /// 提问并执行模型调用的工具，返回去掉调用标记的回答与各工具的结果
+    /// Ask and run the tools called by the model, returning the answer without call markers and the tool results
+    #[napi]
+    pub async fn get_tool_answer(&self, user_input: String) -> napi::Result<ToolAnswer> {
+        let (answer, tool_results) = self.inner.lock().await.get_tool_answer(&user_input).await.map_err(to_napi_err)?;
+        Ok(ToolAnswer { answer, tool_results })
+    }
+
+    /// 对话累计消耗的 token 数
+    /// Tokens used by the chat so far
+    #[napi]
+    pub async fn usage(&self) -> i32 {
+        self.inner.lock().await.base.usage
+    }
+
+    #[napi]
+    pub async fn last_message_id(&self) -> Option<String> {
+        self.inner.lock().await.last_message_id().map(str::to_string)
+    }
+}
+
+#[napi(object)]
+pub struct GroupChatOptions {
+    pub api: Option<String>,
+    pub capability: Option<String>,
+    pub stream: Option<bool>,
+}
+
+/// 群组对话，每个角色有自己的提示词
+/// Group chat, each character has its own prompt
+#[napi]
+pub struct GroupChat {
+    inner: Arc<AsyncMutex<MultiChat>>,
+}
+
+#[napi]
+impl GroupChat {
+    #[napi(constructor)]
+    pub fn new(characters: HashMap<String, String>, options: GroupChatOptions) -> napi::Result<Self> {
+        let stream = options.stream.unwrap_or(false);
+        let inner = match (&options.api, &options.capability) {
+            (Some(api), _) => MultiChat::new_with_api_name(api, characters, stream),
+            (None, Some(capability)) => MultiChat::new_with_model_capability(parse_capability(capability)?, characters, stream),
+            (None, None) => return Err(napi::Error::from_reason("Either an API name or a capability is required")),
+        };
+        Ok(Self {
+            inner: Arc::new(AsyncMutex::new(inner.map_err(to_napi_err)?)),
+        })
+    }
+
+    #[napi]
+    pub async fn set_character(&self, character: String) -> napi::Result<()> {
+        self.inner.lock().await.set_character(&character).map_err(to_napi_err)
+    }
+
+    #[napi]
+    pub async fn get_answer(&self, user_input: String) -> napi::Result<String> {
+        self.inner.lock().await.get_answer(&user_input).await.map_err(to_napi_err)
+    }
+
+    /// 切换到指定角色并回答
+    /// Switch to the character and answer
+    #[napi]
+    pub async fn dialogue(&self, character: String, user_input: String) -> napi::Result<String> {
+        self.inner.lock().await.dialogue(&character, &user_input).await.map_err(to_napi_err)
+    }
+
+    #[napi]
+    pub async fn get_json_answer(&self, user_input: String, schema: serde_json::Value) -> napi::Result<serde_json::Value> {
+        let mut chat = self.inner.lock().await;
+        chat.get_json_answer_with_schema(&user_input, response_format(schema))
+            .await
+            .map_err(to_napi_err)
+    }
+
+    #[napi]
+    pub async fn usage(&self) -> i32 {
+        self.inner.lock().await.base.usage
+    }
+}
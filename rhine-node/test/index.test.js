@@ -0,0 +1,126 @@
+// rhine Node.js 绑定的测试，模型由本地的模拟提供商扮演
+// Tests of the rhine Node.js bindings, with a local mock provider standing in for the model
+"use strict";
+
+const assert = require("node:assert");
+const http = require("node:http");
+const { after, before, test } = require("node:test");
+
+const rhine = require("..");
+
+// 按顺序回放的回复：字符串为文本回答，`{ tool, arguments }` 为原生工具调用
+// Replies played back in order: strings are text answers, `{ tool, arguments }` native tool calls
+const replies = [];
+const requests = [];
+
+function respond(body) {
+  const reply = replies.shift();
+  if (reply === undefined) {
+    return [500, "application/json", JSON.stringify({ error: { message: "Mock provider script exhausted" } })];
+  }
+  const usage = { prompt_tokens: 1, completion_tokens: 1, total_tokens: 2 };
+  if (typeof reply !== "string") {
+    const fn = { name: reply.tool, arguments: JSON.stringify(reply.arguments) };
+    const call = { id: "call_mock", type: "function", function: fn };
+    const message = { role: "assistant", content: null, tool_calls: [call] };
+    return [200, "application/json", JSON.stringify({ choices: [{ message, finish_reason: "tool_calls" }], usage })];
+  }
+  if (!body.stream) {
+    const message = { role: "assistant", content: reply };
+    return [200, "application/json", JSON.stringify({ choices: [{ message, finish_reason: "stop" }], usage })];
+  }
+  const events = reply.split(/(?<= )/).map((word) => ({ choices: [{ delta: { content: word } }] }));
+  events.push({ choices: [{ delta: {}, finish_reason: "stop" }] }, { choices: [], usage });
+  const stream = events.map((event) => `data: ${JSON.stringify(event)}\n\n`).join("") + "data: [DONE]\n\n";
+  return [200, "text/event-stream", stream];
+}
+
+const server = http.createServer((request, response) => {
+  let body = "";
+  request.on("data", (chunk) => (body += chunk));
+  request.on("end", () => {
+    const parsed = body ? JSON.parse(body) : {};
+    requests.push(parsed);
+    const [status, contentType, content] = respond(parsed);
+    response.writeHead(status, { "content-type": contentType });
+    response.end(content);
+  });
+});
+
+before(async () => {
+  await new Promise((resolve) => server.listen(0, "127.0.0.1", resolve));
+  const url = `http://127.0.0.1:${server.address().port}/v1/chat/completions`;
+  rhine.addApiSource("node-mock", url, 2);
+  rhine.addApi("node-chat", "mock-model", "long_context", "node-mock", "sk-mock");
+  rhine.addApi("node-tool", "mock-model", "tool_use", "node-mock", "sk-mock");
+});
+
+after(() => {
+  server.closeAllConnections();
+  server.close();
+});
+
+test("getAnswer returns the answer and keeps the history", async () => {
+  const chat = new rhine.SingleChat({ api: "node-chat", system: "Be brief." });
+  replies.push("Hello from the mock.", "Still here.");
+  assert.strictEqual(await chat.getAnswer("Hi"), "Hello from the mock.");
+  assert.strictEqual(await chat.getAnswer("Are you there?"), "Still here.");
+  const contents = requests[requests.length - 1].messages.map((message) => message.content);
+  assert.ok(contents.includes("Be brief."));
+  assert.ok(contents.includes("Hello from the mock."));
+  assert.ok((await chat.usage()) > 0);
+});
+
+test("getJsonAnswer accepts a bare schema", async () => {
+  const chat = new rhine.SingleChat({ api: "node-chat" });
+  // 先是模型的回答，再是按格式整理后的 JSON
+  // First the model's answer, then the JSON arranged in the format
+  replies.push("Paris, about 2.1 million people.", '{"city": "Paris", "population": 2100000}');
+  const schema = {
+    title: "City",
+    type: "object",
+    properties: { city: { type: "string" }, population: { type: "integer" } },
+    required: ["city", "population"],
+  };
+  assert.deepStrictEqual(await chat.getJsonAnswer("Largest city of France?", schema), {
+    city: "Paris",
+    population: 2100000,
+  });
+  assert.strictEqual(requests[requests.length - 1].response_format.json_schema.name, "City");
+});
+
+test("streamAnswer yields the answer piece by piece", async () => {
+  const chat = new rhine.SingleChat({ api: "node-chat", stream: true });
+  replies.push("one two three");
+  const pieces = [];
+  for await (const piece of chat.streamAnswer("Count")) pieces.push(piece);
+  assert.ok(pieces.length > 1);
+  assert.strictEqual(pieces.join(""), "one two three");
+});
+
+test("tools run in JavaScript and report their results", async () => {
+  const seen = [];
+  const add = rhine.tool(
+    "node_add",
+    "Add two numbers",
+    { type: "object", properties: { a: { type: "number" }, b: { type: "number" } }, required: ["a", "b"] },
+    async ({ a, b }) => {
+      seen.push([a, b]);
+      return { sum: a + b };
+    },
+  );
+  const fail = rhine.tool("node_fail", "Always fails", { type: "object", properties: {} }, () => {
+    throw new Error("out of order");
+  });
+  const chat = new rhine.SingleChat({ api: "node-tool" });
+  await chat.setTools([add, fail]);
+
+  replies.push("<ToolUse>add 1 and 2</ToolUse>", { tool: "node_add", arguments: { a: 1, b: 2 } });
+  const answer = await chat.getToolAnswer("What is 1 + 2?");
+  assert.deepStrictEqual(seen, [[1, 2]]);
+  assert.deepStrictEqual(answer.toolResults.map((result) => JSON.parse(result)), [{ sum: 3 }]);
+
+  replies.push("<ToolUse>break it</ToolUse>", { tool: "node_fail", arguments: {} });
+  const failed = await chat.getToolAnswer("Break it");
+  assert.strictEqual(JSON.parse(failed.toolResults[0]).error.error_type, "execution_failed");
+});
@@ -0,0 +1,320 @@
+//! rhine 的 Python 绑定：单人与群组对话、以 Python 函数作为工具、以及基于 pydantic 模型的结构化输出
+//! Python bindings of rhine: single and group chats, Python callables as tools, and structured outputs driven by
+//! pydantic models
+//!
+//! 请求在 rhine 的共享运行时上执行，等待期间释放 GIL，其他 Python 线程可以继续运行。
+//! Requests run on the shared rhine runtime and release the GIL while waiting, so other Python threads keep running.
+//!
+//! ```python
+//! import rhine
+//! from pydantic import BaseModel
+//!
+//! class City(BaseModel):
+//!     name: str
+//!     population: int
+//!
+//! rhine.load_config("config.toml")
+//! chat = rhine.SingleChat(capability="long_context", system="be brief")
+//! city = chat.get_json_answer("Largest city in Japan?", City)
+//! ```
+
+// 标准库
+use std::collections::HashMap;
+use std::sync::Arc;
+
+// 错误处理
+use error_stack::{Report, ResultExt};
+
+// Python 绑定
+use pyo3::create_exception;
+use pyo3::exceptions::PyException;
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyType};
+
+// 项目内部模块
+use rhine::blocking::block_on;
+use rhine::chat::builder::SingleChatBuilder;
+use rhine::chat::chat_multi::MultiChat;
+use rhine::chat::chat_single::SingleChat as RustSingleChat;
+use rhine::chat::params::ChatParams;
+use rhine::config::{Config, ModelCapability};
+use rhine::error::{ReportExt, RhineError as RustRhineError};
+use rhine::schema::json_schema::response_format;
+use rhine::schema::tool_schema::{ChatToolSchemaError, get_tool_registry};
+
+create_exception!(rhine, RhineError, PyException, "rhine 调用失败，消息以错误码开头 / A rhine call failed, the message starts with the error code");
+
+/// 将错误报告转换为 Python 异常
+/// Convert an error report into a Python exception
+fn to_py_err<C: RustRhineError>(report: Report<C>) -> PyErr {
+    RhineError::new_err(format!("{}: {:?}", report.code(), report))
+}
+
+/// JSON 值转换为 Python 对象
+/// Convert a JSON value into a Python object
+fn json_to_py(py: Python<'_>, value: &serde_json::Value) -> PyResult<PyObject> {
+    Ok(py.import("json")?.call_method1("loads", (value.to_string(),))?.unbind())
+}
+
+/// Python 对象转换为 JSON 值，对象需能被 `json.dumps` 序列化
+/// Convert a Python object into a JSON value, the object must be serializable by `json.dumps`
+fn py_to_json(object: &Bound<'_, PyAny>) -> PyResult<serde_json::Value> {
+    let text: String = object.py().import("json")?.call_method1("dumps", (object,))?.extract()?;
+    serde_json::from_str(&text).map_err(|e| RhineError::new_err(format!("Invalid JSON from Python: {}", e)))
+}
+
+/// 取得 JSON Schema：pydantic 模型类调用 `model_json_schema()`，字典直接使用
+/// Get a JSON Schema: pydantic model classes via `model_json_schema()`, dicts are used as is
+fn schema_of(schema: &Bound<'_, PyAny>) -> PyResult<serde_json::Value> {
+    if schema.hasattr("model_json_schema")? {
+        py_to_json(&schema.call_method0("model_json_schema")?)
+    } else {
+        py_to_json(schema)
+    }
+}
+
+fn parse_capability(capability: &str) -> PyResult<ModelCapability> {
+    serde_json::from_value(serde_json::Value::String(capability.to_string()))
+        .map_err(|_| RhineError::new_err(format!("Unknown capability: {}", capability)))
+}
+
+fn chat_params(temperature: Option<f64>, max_tokens: Option<u32>, top_p: Option<f64>) -> ChatParams {
+    ChatParams {
+        temperature,
+        max_tokens,
+        top_p,
+        stop: None,
+        logit_bias: None,
+    }
+}
+
+/// 从 TOML 文件加载配置
+/// Load the configuration from a TOML file
+#[pyfunction]
+#[pyo3(signature = (path, profile=None))]
+fn load_config(path: &str, profile: Option<&str>) -> PyResult<()> {
+    Config::load_with_profile(path, profile).map_err(to_py_err)
+}
+
+#[pyfunction]
+fn add_api_source(name: &str, base_url: &str, parallelism: usize) {
+    Config::add_api_source(name, base_url, parallelism);
+}
+
+#[pyfunction]
+fn add_api(name: &str, model: &str, capability: &str, source: &str, api_key: &str) -> PyResult<()> {
+    Config::add_api_info(name, model, parse_capability(capability)?, source, api_key).map_err(to_py_err)
+}
+
+/// 将 Python 函数注册为工具，返回传给 `set_tools` 的工具定义
+/// Register a Python callable as a tool, returning the tool definition to pass to `set_tools`
+///
+/// 模型给出的参数以关键字参数传入，返回值需能被 `json.dumps` 序列化；函数抛出的异常作为调用失败告知模型。
+/// The arguments chosen by the model are passed as keyword arguments and the return value must be serializable by
+/// `json.dumps`; exceptions raised by the callable are reported to the model as a failed call.
+///
+/// # 参数 (Parameters)
+/// * `function` - 工具函数 / The tool callable
+/// * `parameters` - 参数的 JSON Schema 或 pydantic 模型类 / JSON Schema or pydantic model class of the arguments
+/// * `name` - 工具名称，默认为函数名 / Tool name, the function name by default
+/// * `description` - 工具说明，默认为函数的文档字符串 / Tool description, the docstring by default
+#[pyfunction]
+#[pyo3(signature = (function, parameters, name=None, description=None))]
+fn tool(
+    py: Python<'_>,
+    function: PyObject,
+    parameters: &Bound<'_, PyAny>,
+    name: Option<String>,
+    description: Option<String>,
+) -> PyResult<PyObject> {
+    let callable = function.bind(py);
+    let name = match name {
+        Some(name) => name,
+        None => callable.getattr("__name__")?.extract()?,
+    };
+    let description = match description {
+        Some(description) => description,
+        None => callable.getattr("__doc__")?.extract::<Option<String>>()?.unwrap_or_default().trim().to_string(),
+    };
+    let parameters = schema_of(parameters)?;
+
+    let tool_name = name.clone();
+    let call = move |arguments: serde_json::Value| -> error_stack::Result<serde_json::Value, ChatToolSchemaError> {
+        Python::with_gil(|py| {
+            let kwargs = json_to_py(py, &arguments)?;
+            let result = function.bind(py).call((), Some(kwargs.bind(py).downcast::<PyDict>()?))?;
+            py_to_json(&result)
+        })
+        .map_err(|e| Report::new(ChatToolSchemaError::FunctionCallError).attach_printable(e.to_string()))
+        .attach_printable_lazy(|| format!("Python tool: {}", tool_name))
+    };
+    get_tool_registry().insert(name.clone(), Arc::new(call));
+
+    json_to_py(py, &serde_json::json!({
+        "type": "function",
+        "function": {"name": name, "description": description, "parameters": parameters},
+    }))
+}
+
+/// 单人对话
+/// Single chat
+#[pyclass]
+struct SingleChat {
+    inner: RustSingleChat,
+}
+
+#[pymethods]
+impl SingleChat {
+    /// 按API名称或能力创建对话，二者都给出时API名称优先
+    /// Create a chat by API name or capability, the API name wins when both are given
+    #[new]
+    #[pyo3(signature = (api=None, capability=None, system=None, stream=false, temperature=None, max_tokens=None, top_p=None))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        api: Option<&str>,
+        capability: Option<&str>,
+        system: Option<&str>,
+        stream: bool,
+        temperature: Option<f64>,
+        max_tokens: Option<u32>,
+        top_p: Option<f64>,
+    ) -> PyResult<Self> {
+        let mut builder = SingleChatBuilder::new()
+            .stream(stream)
+            .params(chat_params(temperature, max_tokens, top_p));
+        if let Some(api) = api {
+            builder = builder.api(api);
+        }
+        if let Some(capability) = capability {
+            builder = builder.capability(parse_capability(capability)?);
+        }
+        if let Some(system) = system {
+            builder = builder.system(system);
+        }
+        Ok(Self {
+            inner: builder.build().map_err(to_py_err)?,
+        })
+    }
+
+    fn get_answer(&mut self, py: Python<'_>, user_input: &str) -> PyResult<String> {
+        let chat = &mut self.inner;
+        py.allow_threads(|| block_on(chat.get_answer(user_input))).map_err(to_py_err)
+    }
+
+    /// 取回结构化回答；`schema` 为 pydantic 模型类时返回模型实例，为字典时返回解析后的对象
+    /// Get a structured answer; returns a model instance when `schema` is a pydantic model class, the parsed object
+    /// when it is a dict
+    fn get_json_answer(&mut self, py: Python<'_>, user_input: &str, schema: &Bound<'_, PyAny>) -> PyResult<PyObject> {
+        let json_schema = response_format(schema_of(schema)?);
+        let chat = &mut self.inner;
+        let value = py
+            .allow_threads(|| block_on(chat.get_json_answer_with_schema(user_input, json_schema)))
+            .map_err(to_py_err)?;
+        validate(py, schema, &value)
+    }
+
+    /// 设置工具，工具定义由 `rhine.tool` 返回
+    /// Set the tools, whose definitions are returned by `rhine.tool`
+    fn set_tools(&mut self, tools: Vec<Bound<'_, PyAny>>) -> PyResult<()> {
+        let tools = tools.iter().map(py_to_json).collect::<PyResult<Vec<_>>>()?;
+        self.inner.set_tools(tools).map_err(to_py_err)
+    }
+
+    /// 提问并执行模型调用的工具，返回去掉调用标记的回答与各工具的结果
+    /// Ask and run the tools called by the model, returning the answer without call markers and the tool results
+    fn get_tool_answer(&mut self, py: Python<'_>, user_input: &str) -> PyResult<(String, Vec<String>)> {
+        let chat = &mut self.inner;
+        py.allow_threads(|| block_on(chat.get_tool_answer(user_input))).map_err(to_py_err)
+    }
+
+    #[getter]
+    fn usage(&self) -> i32 {
+        self.inner.base.usage
+    }
+
+    fn last_message_id(&self) -> Option<String> {
+        self.inner.last_message_id().map(str::to_string)
+    }
+}
+
+/// 群组对话，每个角色有自己的提示词
+/// Group chat, each character has its own prompt
+#[pyclass]
+struct GroupChat {
+    inner: MultiChat,
+}
+
+#[pymethods]
+impl GroupChat {
+    #[new]
+    #[pyo3(signature = (characters, api=None, capability=None, stream=false))]
+    fn new(
+        characters: HashMap<String, String>,
+        api: Option<&str>,
+        capability: Option<&str>,
+        stream: bool,
+    ) -> PyResult<Self> {
+        let inner = match (api, capability) {
+            (Some(api), _) => MultiChat::new_with_api_name(api, characters, stream),
+            (None, Some(capability)) => MultiChat::new_with_model_capability(parse_capability(capability)?, characters, stream),
+            (None, None) => return Err(RhineError::new_err("Either an API name or a capability is required")),
+        };
+        Ok(Self {
+            inner: inner.map_err(to_py_err)?,
+        })
+    }
+
+    fn set_character(&mut self, character: &str) -> PyResult<()> {
+        self.inner.set_character(character).map_err(to_py_err)
+    }
+
+    fn get_answer(&mut self, py: Python<'_>, user_input: &str) -> PyResult<String> {
+        let chat = &mut self.inner;
+        py.allow_threads(|| block_on(chat.get_answer(user_input))).map_err(to_py_err)
+    }
+
+    /// 切换到指定角色并回答
+    /// Switch to the character and answer
+    fn dialogue(&mut self, py: Python<'_>, character: &str, user_input: &str) -> PyResult<String> {
+        let chat = &mut self.inner;
+        py.allow_threads(|| block_on(chat.dialogue(character, user_input))).map_err(to_py_err)
+    }
+
+    fn get_json_answer(&mut self, py: Python<'_>, user_input: &str, schema: &Bound<'_, PyAny>) -> PyResult<PyObject> {
+        let json_schema = response_format(schema_of(schema)?);
+        let chat = &mut self.inner;
+        let value = py
+            .allow_threads(|| block_on(chat.get_json_answer_with_schema(user_input, json_schema)))
+            .map_err(to_py_err)?;
+        validate(py, schema, &value)
+    }
+
+    #[getter]
+    fn usage(&self) -> i32 {
+        self.inner.base.usage
+    }
+}
+
+/// pydantic 模型类用 `model_validate` 构造实例，其他情况直接返回 Python 对象
+/// Build an instance with `model_validate` for pydantic model classes, otherwise return the Python object as is
+fn validate(py: Python<'_>, schema: &Bound<'_, PyAny>, value: &serde_json::Value) -> PyResult<PyObject> {
+    let object = json_to_py(py, value)?;
+    if schema.is_instance_of::<PyType>() && schema.hasattr("model_validate")? {
+        Ok(schema.call_method1("model_validate", (object,))?.unbind())
+    } else {
+        Ok(object)
+    }
+}
+
+#[pymodule]
+#[pyo3(name = "rhine")]
+fn rhine_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add("RhineError", m.py().get_type::<RhineError>())?;
+    m.add_function(wrap_pyfunction!(load_config, m)?)?;
+    m.add_function(wrap_pyfunction!(add_api_source, m)?)?;
+    m.add_function(wrap_pyfunction!(add_api, m)?)?;
+    m.add_function(wrap_pyfunction!(tool, m)?)?;
+    m.add_class::<SingleChat>()?;
+    m.add_class::<GroupChat>()?;
+    Ok(())
+}
@@ -0,0 +1,148 @@
+# rhine Python 绑定的测试，模型由本地的模拟提供商扮演
+# Tests of the rhine Python bindings, with a local mock provider standing in for the model
+#
+# 以 `maturin develop` 安装后运行：python -m unittest discover -s tests
+# Run after installing with `maturin develop`: python -m unittest discover -s tests
+
+import json
+import threading
+import unittest
+from http.server import BaseHTTPRequestHandler, ThreadingHTTPServer
+
+import rhine
+
+# 按顺序回放的回复：字符串为文本回答，`(name, arguments)` 为原生工具调用
+# Replies played back in order: strings are text answers, `(name, arguments)` native tool calls
+REPLIES = []
+REQUESTS = []
+USAGE = {"prompt_tokens": 1, "completion_tokens": 1, "total_tokens": 2}
+
+
+def respond(body):
+    if not REPLIES:
+        return 500, {"error": {"message": "Mock provider script exhausted"}}
+    reply = REPLIES.pop(0)
+    if isinstance(reply, tuple):
+        name, arguments = reply
+        call = {"id": "call_mock", "type": "function", "function": {"name": name, "arguments": json.dumps(arguments)}}
+        message = {"role": "assistant", "content": None, "tool_calls": [call]}
+        return 200, {"choices": [{"message": message, "finish_reason": "tool_calls"}], "usage": USAGE}
+    message = {"role": "assistant", "content": reply}
+    return 200, {"choices": [{"message": message, "finish_reason": "stop"}], "usage": USAGE}
+
+
+class MockProvider(BaseHTTPRequestHandler):
+    def do_POST(self):
+        body = json.loads(self.rfile.read(int(self.headers["content-length"])))
+        REQUESTS.append(body)
+        status, response = respond(body)
+        content = json.dumps(response).encode()
+        self.send_response(status)
+        self.send_header("content-type", "application/json")
+        self.send_header("content-length", str(len(content)))
+        self.end_headers()
+        self.wfile.write(content)
+
+    def log_message(self, *args):
+        pass
+
+
+def setUpModule():
+    global SERVER
+    SERVER = ThreadingHTTPServer(("127.0.0.1", 0), MockProvider)
+    threading.Thread(target=SERVER.serve_forever, daemon=True).start()
+    url = "http://127.0.0.1:%d/v1/chat/completions" % SERVER.server_address[1]
+    rhine.add_api_source("py-mock", url, 2)
+    rhine.add_api("py-chat", "mock-model", "long_context", "py-mock", "sk-mock")
+    rhine.add_api("py-tool", "mock-model", "tool_use", "py-mock", "sk-mock")
+
+
+def tearDownModule():
+    SERVER.shutdown()
+    SERVER.server_close()
+
+
+class SingleChatTest(unittest.TestCase):
+    def setUp(self):
+        REPLIES.clear()
+        REQUESTS.clear()
+
+    def test_get_answer_keeps_the_history(self):
+        chat = rhine.SingleChat(api="py-chat", system="Be brief.")
+        REPLIES.extend(["Hello from the mock.", "Still here."])
+        self.assertEqual(chat.get_answer("Hi"), "Hello from the mock.")
+        self.assertEqual(chat.get_answer("Are you there?"), "Still here.")
+        contents = [message["content"] for message in REQUESTS[-1]["messages"]]
+        self.assertIn("Be brief.", contents)
+        self.assertIn("Hello from the mock.", contents)
+        self.assertGreater(chat.usage, 0)
+
+    def test_get_json_answer_with_a_dict_schema(self):
+        chat = rhine.SingleChat(api="py-chat")
+        schema = {
+            "title": "City",
+            "type": "object",
+            "properties": {"city": {"type": "string"}, "population": {"type": "integer"}},
+            "required": ["city", "population"],
+        }
+        # 先是模型的回答，再是按格式整理后的 JSON
+        # First the model's answer, then the JSON arranged in the format
+        REPLIES.extend(["Paris, about 2.1 million people.", '{"city": "Paris", "population": 2100000}'])
+        city = chat.get_json_answer("Largest city of France?", schema)
+        self.assertEqual(city, {"city": "Paris", "population": 2100000})
+        self.assertEqual(REQUESTS[-1]["response_format"]["json_schema"]["name"], "City")
+
+    def test_get_json_answer_with_a_pydantic_model(self):
+        try:
+            from pydantic import BaseModel
+        except ImportError:
+            self.skipTest("pydantic is not installed")
+
+        class City(BaseModel):
+            city: str
+            population: int
+
+        chat = rhine.SingleChat(api="py-chat")
+        REPLIES.extend(["Tokyo, about 14 million people.", '{"city": "Tokyo", "population": 14000000}'])
+        city = chat.get_json_answer("Largest city of Japan?", City)
+        self.assertEqual(city, City(city="Tokyo", population=14000000))
+        self.assertEqual(REQUESTS[-1]["response_format"]["json_schema"]["name"], "City")
+
+    def test_errors_carry_the_error_code(self):
+        chat = rhine.SingleChat(api="py-chat")
+        with self.assertRaises(rhine.RhineError):
+            chat.get_answer("Nobody answers")
+        with self.assertRaises(rhine.RhineError):
+            rhine.SingleChat(capability="telepathy")
+
+    def test_tools_run_in_python(self):
+        seen = []
+
+        def py_add(a, b):
+            """Add two numbers"""
+            seen.append((a, b))
+            return {"sum": a + b}
+
+        def py_fail():
+            raise ValueError("out of order")
+
+        parameters = {"type": "object", "properties": {"a": {"type": "number"}, "b": {"type": "number"}}}
+        add = rhine.tool(py_add, parameters)
+        self.assertEqual(add["function"]["name"], "py_add")
+        self.assertEqual(add["function"]["description"], "Add two numbers")
+        fail = rhine.tool(py_fail, {"type": "object", "properties": {}}, description="Always fails")
+        chat = rhine.SingleChat(api="py-tool")
+        chat.set_tools([add, fail])
+
+        REPLIES.extend(["<ToolUse>add 1 and 2</ToolUse>", ("py_add", {"a": 1, "b": 2})])
+        answer, results = chat.get_tool_answer("What is 1 + 2?")
+        self.assertEqual(seen, [(1, 2)])
+        self.assertEqual([json.loads(result) for result in results], [{"sum": 3}])
+
+        REPLIES.extend(["<ToolUse>break it</ToolUse>", ("py_fail", {})])
+        _, results = chat.get_tool_answer("Break it")
+        self.assertEqual(json.loads(results[0])["error"]["error_type"], "execution_failed")
+
+
+if __name__ == "__main__":
+    unittest.main()
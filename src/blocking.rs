@@ -0,0 +1,284 @@
+//! 同步（阻塞）接口，供不使用异步运行时的命令行工具和插件调用
+//! Blocking (sync) facade for CLI tools and plugins that do not run an async runtime
+//!
+//! 与 `reqwest::blocking` 类似，内部使用一个共享的异步运行时执行请求。不能在异步上下文中调用，
+//! 否则会因嵌套运行时而 panic；异步代码请直接使用 `SingleChat`。
+//! Like `reqwest::blocking`, requests run on a shared internal runtime. Must not be called from an async context,
+//! which panics on the nested runtime; async code should use `SingleChat` directly.
+
+// 标准库
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+// 并发和同步原语
+use once_cell::sync::Lazy;
+use tokio::runtime::Runtime;
+
+// 序列化
+use serde::de::DeserializeOwned;
+
+// 错误处理
+use error_stack::Result;
+
+// 异步
+use futures::StreamExt;
+
+// 项目内部模块
+use crate::chat::agent::{AgentRun, Deadline};
+use crate::chat::chat_base::{ChatError, ModelOverride};
+use crate::chat::chat_single::{SingleChat, ToolCallError};
+use crate::chat::chat_tool::ToolChoice;
+use crate::chat::event::ChatEvent;
+use crate::chat::guardrail::Guardrails;
+use crate::chat::history::HistoryPolicy;
+use crate::chat::injection::InjectionScreen;
+use crate::chat::interceptor::RequestInterceptor;
+use crate::chat::language::LanguagePolicy;
+use crate::chat::moderation::Moderation;
+use crate::chat::output_cap::OutputCap;
+use crate::chat::pii::PiiScrubber;
+use crate::chat::queue::QueuePriority;
+use crate::chat::preview::{Estimate, RequestPreview};
+use crate::chat::params::ChatParams;
+use crate::chat::tenant::Tenant;
+use crate::chat::tool_source::ToolSource;
+use crate::config::ModelCapability;
+use crate::schema::json_schema::JsonSchema;
+
+/// 阻塞接口共享的运行时
+/// Runtime shared by the blocking facade
+static RUNTIME: Lazy<Runtime> = Lazy::new(|| {
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .thread_name("rhine-blocking")
+        .build()
+        .expect("Failed to start the blocking runtime")
+});
+
+/// 在共享运行时上阻塞执行，也供语言绑定等同步调用方驱动其他异步接口
+/// Run a future to completion on the shared runtime, also used by sync callers such as language bindings to drive
+/// other async APIs
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    RUNTIME.block_on(future)
+}
+
+/// `SingleChat` 的阻塞版本，方法与异步版本一致，流式回答改为回调
+/// Blocking version of `SingleChat`, with the same methods except that streamed answers are delivered through a
+/// callback
+#[derive(Debug, Clone)]
+pub struct SingleChatBlocking {
+    inner: SingleChat,
+}
+
+impl From<SingleChat> for SingleChatBlocking {
+    fn from(inner: SingleChat) -> Self {
+        Self { inner }
+    }
+}
+
+impl SingleChatBlocking {
+    pub fn new_with_api_name(api_name: &str, character_prompt: &str, need_stream: bool) -> Self {
+        SingleChat::new_with_api_name(api_name, character_prompt, need_stream).into()
+    }
+
+    pub fn try_new_with_api_name(api_name: &str, character_prompt: &str, need_stream: bool) -> Result<Self, ChatError> {
+        Ok(SingleChat::try_new_with_api_name(api_name, character_prompt, need_stream)?.into())
+    }
+
+    pub fn try_new_with_model_capability(
+        model_capability: ModelCapability,
+        character_prompt: &str,
+        need_stream: bool,
+    ) -> Result<Self, ChatError> {
+        Ok(SingleChat::try_new_with_model_capability(model_capability, character_prompt, need_stream)?.into())
+    }
+
+    pub fn new_with_model_capability(
+        model_capability: ModelCapability,
+        character_prompt: &str,
+        need_stream: bool,
+    ) -> Self {
+        SingleChat::new_with_model_capability(model_capability, character_prompt, need_stream).into()
+    }
+
+    /// 底层的异步对话
+    /// The underlying async chat
+    pub fn inner(&self) -> &SingleChat {
+        &self.inner
+    }
+
+    pub fn inner_mut(&mut self) -> &mut SingleChat {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> SingleChat {
+        self.inner
+    }
+
+    pub fn on_event(&mut self, handler: impl Fn(&ChatEvent) + Send + Sync + 'static) -> &mut Self {
+        self.inner.on_event(handler);
+        self
+    }
+
+    pub fn add_tag(&mut self, tag: &str) -> &mut Self {
+        self.inner.add_tag(tag);
+        self
+    }
+
+    pub fn set_params(&mut self, overrides: &ChatParams) {
+        self.inner.set_params(overrides);
+    }
+
+    pub fn set_history_policy(&mut self, policy: impl HistoryPolicy + 'static) -> &mut Self {
+        self.inner.set_history_policy(policy);
+        self
+    }
+
+    pub fn set_moderation(&mut self, moderation: Moderation) -> &mut Self {
+        self.inner.set_moderation(moderation);
+        self
+    }
+
+    pub fn set_pii_scrubber(&mut self, scrubber: PiiScrubber) -> &mut Self {
+        self.inner.set_pii_scrubber(scrubber);
+        self
+    }
+
+    pub fn set_guardrails(&mut self, guardrails: Guardrails) -> &mut Self {
+        self.inner.set_guardrails(guardrails);
+        self
+    }
+
+    pub fn set_injection_screen(&mut self, screen: InjectionScreen) -> &mut Self {
+        self.inner.set_injection_screen(screen);
+        self
+    }
+
+    pub fn set_output_cap(&mut self, cap: OutputCap) -> &mut Self {
+        self.inner.set_output_cap(cap);
+        self
+    }
+
+    pub fn set_stream_stall_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.inner.set_stream_stall_timeout(timeout);
+        self
+    }
+
+    pub fn set_max_continuations(&mut self, max: u32) -> &mut Self {
+        self.inner.set_max_continuations(max);
+        self
+    }
+
+    pub fn set_language_policy(&mut self, policy: LanguagePolicy) -> &mut Self {
+        self.inner.set_language_policy(policy);
+        self
+    }
+
+    pub fn set_queue_key(&mut self, key: &str) -> &mut Self {
+        self.inner.set_queue_key(key);
+        self
+    }
+
+    pub fn set_queue_priority(&mut self, priority: QueuePriority) -> &mut Self {
+        self.inner.set_queue_priority(priority);
+        self
+    }
+
+    pub fn set_tenant(&mut self, tenant: Tenant) -> &mut Self {
+        self.inner.set_tenant(tenant);
+        self
+    }
+
+    pub fn add_request_interceptor(&mut self, interceptor: impl RequestInterceptor + 'static) -> &mut Self {
+        self.inner.add_request_interceptor(interceptor);
+        self
+    }
+
+    pub fn set_tools(&mut self, tools_schema: Vec<serde_json::Value>) -> Result<(), ChatError> {
+        self.inner.set_tools(tools_schema)
+    }
+
+    pub fn set_tool_sources(&mut self, sources: Vec<Arc<dyn ToolSource>>) -> Result<(), ChatError> {
+        block_on(self.inner.set_tool_sources(sources))
+    }
+
+    pub fn set_tool_choice(&mut self, choice: ToolChoice) -> &mut Self {
+        self.inner.set_tool_choice(choice);
+        self
+    }
+
+    pub fn set_parallel_tool_calls(&mut self, parallel: bool) -> &mut Self {
+        self.inner.set_parallel_tool_calls(parallel);
+        self
+    }
+
+    /// 对话累计消耗的token数
+    /// Tokens consumed by the chat so far
+    pub fn usage(&self) -> i32 {
+        self.inner.base.usage
+    }
+
+    pub fn get_answer(&mut self, user_input: &str) -> Result<String, ChatError> {
+        block_on(self.inner.get_answer(user_input))
+    }
+
+    pub fn get_answer_with_model(&mut self, user_input: &str, model: ModelOverride) -> Result<String, ChatError> {
+        block_on(self.inner.get_answer_with_model(user_input, model))
+    }
+
+    pub fn dry_run(&self, user_input: &str) -> Result<RequestPreview, ChatError> {
+        block_on(self.inner.dry_run(user_input))
+    }
+
+    pub fn estimate(&self, user_input: &str) -> Result<Estimate, ChatError> {
+        block_on(self.inner.estimate(user_input))
+    }
+
+    pub fn get_json_answer<T: DeserializeOwned + 'static + JsonSchema>(&mut self, user_input: &str) -> Result<T, ChatError> {
+        block_on(self.inner.get_json_answer(user_input))
+    }
+
+    pub fn get_json_items<T: DeserializeOwned + 'static + JsonSchema>(
+        &mut self,
+        user_input: &str,
+        on_item: impl FnMut(&T) + Send,
+    ) -> Result<Vec<T>, ChatError> {
+        block_on(self.inner.get_json_items(user_input, on_item))
+    }
+
+    pub fn get_tool_answer(&mut self, user_input: &str) -> Result<(String, Vec<String>), ToolCallError> {
+        block_on(self.inner.get_tool_answer(user_input))
+    }
+
+    pub fn run_agent(&mut self, user_input: &str, deadline: Deadline) -> Result<AgentRun, ToolCallError> {
+        block_on(self.inner.run_agent(user_input, deadline))
+    }
+
+    pub fn map_answers<I, S>(&self, inputs: I, concurrency: usize) -> Vec<Result<String, ChatError>>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        block_on(self.inner.map_answers(inputs, concurrency))
+    }
+
+    /// 流式提问，每收到一段内容调用一次 `on_delta`，返回完整回答
+    /// Ask with streaming, calling `on_delta` for every piece of content received, and return the whole answer
+    ///
+    /// # 参数 (Parameters)
+    /// * `user_input` - 用户输入 / User input
+    /// * `on_delta` - 内容回调，在调用线程上执行 / Content callback, run on the calling thread
+    pub fn stream_answer(&mut self, user_input: &str, mut on_delta: impl FnMut(&str)) -> Result<String, ChatError> {
+        block_on(async {
+            let mut answer = String::new();
+            let mut deltas = std::pin::pin!(self.inner.stream_answer(user_input).await?);
+            while let Some(delta) = deltas.next().await {
+                let delta = delta?;
+                on_delta(&delta);
+                answer.push_str(&delta);
+            }
+            Ok(answer)
+        })
+    }
+}
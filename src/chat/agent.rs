@@ -0,0 +1,318 @@
+// 标准库
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+// 异步
+use tokio::time;
+
+// 数据序列化
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+// 项目内部模块
+use crate::chat::transaction::{ToolIntent, ToolJournal};
+use crate::eval::embedding_cache::content_hash;
+
+/// 一次智能体运行默认最多的步数，每步是一次模型调用及其发起的工具调用，见 `SingleChat::set_max_agent_steps`
+/// Default maximum steps of one agent run, each step being one model call and the tool calls it makes, see
+/// `SingleChat::set_max_agent_steps`
+pub const MAX_AGENT_STEPS: usize = 8;
+
+/// 整个智能体运行的截止时间，内部的每次模型调用与工具执行以剩余时间为超时；按 tokio 的时钟计时，测试中可暂停时钟
+/// Deadline of a whole agent run, every model call and tool execution inside it times out after the remaining time;
+/// timed on tokio's clock, which tests can pause
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Deadline {
+    at: time::Instant,
+}
+
+impl Deadline {
+    pub fn at(at: Instant) -> Self {
+        Self {
+            at: time::Instant::from_std(at),
+        }
+    }
+
+    /// 从现在起经过 `budget` 后到期
+    /// Expires `budget` from now
+    pub fn after(budget: Duration) -> Self {
+        Self {
+            at: time::Instant::now() + budget,
+        }
+    }
+
+    /// 剩余时间，已到期时为零
+    /// Remaining time, zero once expired
+    pub fn remaining(&self) -> Duration {
+        self.at.saturating_duration_since(time::Instant::now())
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.remaining().is_zero()
+    }
+
+    /// 在剩余时间内运行，到期时放弃并返回 None；已完成的结果即使在到期后也会返回
+    /// Run within the remaining time, giving up with None once it expires; a result that is already complete is
+    /// returned even after expiry
+    pub async fn run<F: Future>(&self, future: F) -> Option<F::Output> {
+        time::timeout_at(self.at, future).await.ok()
+    }
+}
+
+tokio::task_local! {
+    /// 当前任务中正在进行的智能体运行，见 `AgentScope`
+    /// Agent run in progress in the current task, see `AgentScope`
+    static AGENT_RUN: AgentScope;
+}
+
+/// 正在进行的智能体运行中由作为工具的子智能体继承的部分：截止时间与事务的意图日志
+/// What sub-agents called as tools inherit from the agent run in progress: the deadline and the transaction's intent
+/// journal
+#[derive(Clone, Debug)]
+pub(crate) struct AgentScope {
+    pub(crate) deadline: Deadline,
+
+    pub(crate) journal: Option<ToolJournal>,
+}
+
+impl AgentScope {
+    /// 当前任务中正在进行的智能体运行，不在运行中时为 None
+    /// Agent run in progress in the current task, None outside of a run
+    pub(crate) fn current() -> Option<Self> {
+        AGENT_RUN.try_with(Clone::clone).ok()
+    }
+
+    /// 在这次运行的作用域内执行；智能体循环的 future 很大，放在堆上以免嵌套的子智能体撑满栈
+    /// Run within the scope of this run; the agent loop's future is large and goes on the heap so nested
+    /// sub-agents do not overflow the stack
+    pub(crate) async fn run<F: Future>(self, future: F) -> F::Output {
+        AGENT_RUN.scope(self, Box::pin(future)).await
+    }
+
+    /// 让 `future` 在当前的智能体运行中执行，用于把运行带入新启动的任务，如并发的工具调用
+    /// Run `future` within the current agent run, carrying the run into newly spawned tasks such as concurrent tool
+    /// calls
+    pub(crate) fn in_current_run<F: Future>(future: F) -> impl Future<Output = F::Output> {
+        let current = Self::current();
+        async move {
+            match current {
+                Some(current) => current.run(future).await,
+                None => future.await,
+            }
+        }
+    }
+}
+
+/// 智能体运行结束的原因
+/// Why an agent run ended
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AgentStop {
+    /// 模型给出了不再调用工具的回答
+    /// The model gave an answer without further tool calls
+    #[default]
+    Answered,
+
+    /// 截止时间已到，结果为到期前的部分结果
+    /// The deadline passed, the result is what was done before it
+    DeadlineExpired,
+
+    /// 达到最多步数
+    /// The maximum number of steps was reached
+    StepLimit,
+}
+
+/// 智能体运行的结果，到期或达到步数上限时为已完成部分的最佳结果
+/// Result of an agent run, the best partial result when the deadline passed or the step limit was reached
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AgentRun {
+    /// 最近一次回答，已去除工具调用标记；首次模型调用前到期时为空
+    /// The latest answer with the tool call markup removed; empty if the deadline passed before the first model
+    /// call finished
+    pub answer: String,
+
+    /// 各步按调用顺序排列的工具结果，到期时未完成的调用不计入
+    /// Tool results of every step in call order, calls unfinished at the deadline are left out
+    pub tool_results: Vec<String>,
+
+    /// 已开始的步数
+    /// Number of steps started
+    pub steps: usize,
+
+    pub stop: AgentStop,
+
+    /// 逐步的运行记录
+    /// Step-by-step record of the run
+    pub trace: RunTrace,
+
+    /// 设置事务作用域时，有副作用的工具调用的意图及其最终状态，见 `TransactionScope`
+    /// With a transaction scope, the intents of tool calls with side effects and their final status, see
+    /// `TransactionScope`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub journal: Vec<ToolIntent>,
+}
+
+impl AgentRun {
+    pub fn is_complete(&self) -> bool {
+        self.stop == AgentStop::Answered
+    }
+
+    /// 记录结束的一步
+    /// Record a finished step
+    pub(crate) fn end_step(&mut self, mut step: TraceStep, started: Instant) {
+        step.elapsed_ms = started.elapsed().as_millis() as u64;
+        self.trace.steps.push(step);
+    }
+
+    /// 以给定原因结束运行
+    /// End the run for the given reason
+    pub(crate) fn finish(mut self, stop: AgentStop, started: Instant) -> Self {
+        self.stop = stop;
+        self.trace.stop = stop;
+        self.trace.elapsed_ms = started.elapsed().as_millis() as u64;
+        self
+    }
+}
+
+/// 一步结束时的决定
+/// Decision taken at the end of a step
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StepDecision {
+    /// 回答中有工具调用，执行后进入下一步
+    /// The answer calls tools, which run before the next step
+    CallTools,
+
+    /// 回答中没有工具调用，运行结束
+    /// The answer calls no tools, the run ends
+    #[default]
+    Answer,
+
+    /// 这一步中截止时间已到
+    /// The deadline passed during this step
+    DeadlineExpired,
+}
+
+/// 一步中的模型调用
+/// The model call of a step
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TraceLlmCall {
+    pub request_id: String,
+
+    pub model: String,
+
+    /// 请求体的哈希，见 `request_hash`；重放时相同的哈希说明请求与原运行一致
+    /// Hash of the request body, see `request_hash`; an equal hash on replay means the request matches the
+    /// original run
+    pub request_hash: String,
+
+    /// 模型的原始回答，包括工具调用标记
+    /// Raw answer of the model, including the tool call markup
+    pub output: String,
+
+    pub latency_ms: u64,
+
+    pub prompt_tokens: Option<u64>,
+
+    pub completion_tokens: Option<u64>,
+}
+
+/// 一步中的工具调用
+/// A tool call of a step
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TraceToolCall {
+    pub name: String,
+
+    /// JSON 字符串形式的参数
+    /// Arguments as a JSON string
+    pub arguments: String,
+
+    pub output: String,
+
+    pub duration_ms: u64,
+
+    pub is_error: bool,
+}
+
+/// 智能体运行中的一步
+/// One step of an agent run
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TraceStep {
+    pub step: usize,
+
+    /// 模型调用，截止时间在调用完成前到达时为 None
+    /// The model call, None if the deadline passed before it finished
+    pub llm_call: Option<TraceLlmCall>,
+
+    /// 按调用顺序排列的工具调用，截止时间到达时未完成的调用不计入
+    /// Tool calls in call order, calls unfinished at the deadline are left out
+    pub tool_calls: Vec<TraceToolCall>,
+
+    pub decision: StepDecision,
+
+    /// 这一步的耗时（毫秒）
+    /// Time taken by this step in milliseconds
+    pub elapsed_ms: u64,
+}
+
+/// 智能体运行的结构化记录：每一步的模型调用、工具调用、决定、耗时与用量，可以序列化后附在问题报告中，并用
+/// `MockProvider::replay` 在模拟提供商上重放以便调试
+/// Structured record of an agent run: every step's model call, tool calls, decision, timings and usage; it can be
+/// serialized and attached to bug reports, and replayed on the mock provider with `MockProvider::replay` for
+/// debugging
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RunTrace {
+    pub user_input: String,
+
+    pub steps: Vec<TraceStep>,
+
+    pub stop: AgentStop,
+
+    /// 整个运行的耗时（毫秒）
+    /// Time taken by the whole run in milliseconds
+    pub elapsed_ms: u64,
+}
+
+impl RunTrace {
+    /// 各次模型调用的提示词与回答 token 合计
+    /// Prompt and completion tokens summed over the model calls
+    pub fn usage(&self) -> (u64, u64) {
+        self.steps.iter().filter_map(|step| step.llm_call.as_ref()).fold((0, 0), |(prompt, completion), call| {
+            (prompt + call.prompt_tokens.unwrap_or(0), completion + call.completion_tokens.unwrap_or(0))
+        })
+    }
+
+    /// 与另一次运行（如重放）比较，返回第一个请求哈希不同的步骤序号，一致时返回 None
+    /// Compare with another run (such as a replay), returning the number of the first step whose request hash
+    /// differs, None if they agree
+    pub fn divergence(&self, other: &RunTrace) -> Option<usize> {
+        let hash = |trace: &RunTrace, index: usize| {
+            trace.steps.get(index).and_then(|step| step.llm_call.as_ref()).map(|call| call.request_hash.clone())
+        };
+        (0..self.steps.len().max(other.steps.len()))
+            .find(|&index| hash(self, index) != hash(other, index))
+            .map(|index| index + 1)
+    }
+}
+
+/// 请求体的哈希，去除消息中每次运行都不同的工具调用ID后计算，同样的对话在不同运行中哈希相同
+/// Hash of a request body, computed without the tool call IDs in the messages that differ on every run, so the
+/// same conversation hashes the same across runs
+pub fn request_hash(body: &Value) -> String {
+    fn strip_ids(value: &mut Value) {
+        match value {
+            Value::Object(map) => {
+                map.remove("id");
+                map.remove("tool_call_id");
+                map.values_mut().for_each(strip_ids);
+            }
+            Value::Array(items) => items.iter_mut().for_each(strip_ids),
+            _ => {}
+        }
+    }
+    let mut body = body.clone();
+    if let Some(messages) = body.get_mut("messages") {
+        strip_ids(messages);
+    }
+    content_hash(&body.to_string())
+}
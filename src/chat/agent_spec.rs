@@ -0,0 +1,329 @@
+// 标准库
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+// 错误处理
+use error_stack::{Report, Result, ResultExt};
+
+// 数据序列化
+use serde::Deserialize;
+use serde_json::Value;
+
+// 项目内部模块
+use crate::chat::agent::{AgentRun, Deadline};
+use crate::chat::builder::SingleChatBuilder;
+use crate::chat::chat_base::ChatError;
+use crate::chat::chat_single::{SingleChat, ToolCallError};
+use crate::chat::guardrail::{DenyList, GuardrailAction, Guardrails, MaxLength, RegexRule, UrlAllowlist};
+use crate::chat::history::{SlidingWindow, SummarizeOld};
+use crate::chat::params::ChatParams;
+use crate::chat::tenant::Tenant;
+use crate::config::ModelCapability;
+
+/// 智能体一次运行的默认截止时间，定义文件未设置 `budget.timeout_secs` 时使用
+/// Default deadline of one agent run, used when the definition file sets no `budget.timeout_secs`
+pub const DEFAULT_AGENT_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// 智能体的记忆设置
+/// Memory settings of an agent
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MemorySpec {
+    /// 只发送最近的若干条消息，见 `SlidingWindow`
+    /// Only send the latest messages, see `SlidingWindow`
+    #[serde(default)]
+    pub window: Option<usize>,
+
+    /// 保留最近的若干条消息，更早的消息替换为摘要，见 `SummarizeOld`；不能与 `window` 同时设置
+    /// Keep the latest messages and replace older ones with a summary, see `SummarizeOld`; cannot be set together
+    /// with `window`
+    #[serde(default)]
+    pub summarize_after: Option<usize>,
+
+    /// 是否提供草稿本工具，见 `SingleChat::enable_scratchpad`
+    /// Whether the scratchpad tools are offered, see `SingleChat::enable_scratchpad`
+    #[serde(default)]
+    pub scratchpad: bool,
+}
+
+/// 智能体的回答检查规则，按禁用词、正则、长度、链接的顺序执行，命中时都按 `action` 处理
+/// Guardrails of an agent, run as deny-list, patterns, length and links in this order, all handled by `action` on
+/// a match
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GuardrailSpec {
+    #[serde(default = "default_guardrail_action")]
+    pub action: GuardrailAction,
+
+    #[serde(default)]
+    pub deny: Vec<String>,
+
+    /// 正则表达式，改写时删除匹配内容
+    /// Regular expressions, matches are removed when rewriting
+    #[serde(default)]
+    pub patterns: Vec<String>,
+
+    #[serde(default)]
+    pub max_length: Option<usize>,
+
+    /// 允许出现在回答中的链接域名，为 None 时不检查链接
+    /// Link domains allowed in answers, links are not checked if None
+    #[serde(default)]
+    pub allowed_domains: Option<Vec<String>>,
+
+    #[serde(default)]
+    pub max_regenerations: Option<usize>,
+}
+
+fn default_guardrail_action() -> GuardrailAction {
+    GuardrailAction::Block
+}
+
+/// 智能体的预算：每次运行的截止时间，以及整个智能体的令牌与费用上限
+/// Budget of an agent: the deadline of every run, and the token and cost limits of the agent as a whole
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BudgetSpec {
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+
+    /// 令牌上限，用完后请求返回 `ChatError::TenantBudgetExceeded`
+    /// Token limit, requests fail with `ChatError::TenantBudgetExceeded` once used up
+    #[serde(default)]
+    pub max_tokens: Option<u64>,
+
+    /// 费用上限（美元），按模型价格计算
+    /// Cost limit in USD, computed from the model prices
+    #[serde(default)]
+    pub max_cost: Option<f64>,
+}
+
+/// 声明式的智能体定义，可从 TOML、JSON 或 YAML 文件加载，使智能体作为配置编写、版本化与审阅，而不是写在代码里
+/// Declarative agent definition, loadable from TOML, JSON or YAML files, so agents are written, versioned and
+/// reviewed as configuration rather than code
+///
+/// ```toml
+/// name = "researcher"
+/// version = "3"
+/// capability = "tool_use"
+/// system = "You research topics and cite your sources."
+/// tools = [{ type = "function", function = { name = "search", description = "Search the web", parameters = {...} } }]
+///
+/// [memory]
+/// window = 20
+/// scratchpad = true
+///
+/// [guardrails]
+/// action = "regenerate"
+/// allowed_domains = ["wikipedia.org"]
+///
+/// [budget]
+/// timeout_secs = 120
+/// max_tokens = 200000
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AgentSpec {
+    pub name: String,
+
+    /// 定义的版本，随用量记录以 `version:<version>` 标签上报
+    /// Version of the definition, reported with usage records as the `version:<version>` tag
+    #[serde(default)]
+    pub version: Option<String>,
+
+    #[serde(default)]
+    pub description: Option<String>,
+
+    /// 智能体使用的API，未设置时按 `capability` 选择
+    /// API the agent uses, selected by `capability` if unset
+    #[serde(default)]
+    pub api: Option<String>,
+
+    /// 选择API的能力，API与能力都未设置时为 tool_use
+    /// Capability selecting the API, tool_use if neither is set
+    #[serde(default)]
+    pub capability: Option<ModelCapability>,
+
+    pub system: String,
+
+    /// 可用的工具，OpenAI 格式的定义，函数需已在本地注册
+    /// Available tools, as OpenAI definitions whose functions are registered locally
+    #[serde(default)]
+    pub tools: Vec<Value>,
+
+    #[serde(default)]
+    pub params: Option<ChatParams>,
+
+    #[serde(default)]
+    pub memory: MemorySpec,
+
+    #[serde(default)]
+    pub guardrails: Option<GuardrailSpec>,
+
+    #[serde(default)]
+    pub budget: BudgetSpec,
+}
+
+impl AgentSpec {
+    /// 从文件加载定义，按扩展名（`toml`、`json`、`yaml` 或 `yml`）解析，其他扩展名返回错误
+    /// Load a definition from a file, parsed by extension (`toml`, `json`, `yaml` or `yml`), other extensions fail
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ChatError> {
+        let path = path.as_ref();
+        let spec_error = || ChatError::AgentSpecError(path.display().to_string());
+        let extension = path.extension().and_then(|extension| extension.to_str());
+        if !matches!(extension, Some("toml" | "json" | "yaml" | "yml")) {
+            return Err(Report::new(spec_error()).attach_printable(format!(
+                "Unsupported extension {:?}, expected toml, json, yaml or yml",
+                extension.unwrap_or_default()
+            )));
+        }
+        let content = fs::read_to_string(path).change_context_lazy(spec_error)?;
+        let spec: Self = match extension {
+            Some("json") => serde_json::from_str(&content).change_context_lazy(spec_error)?,
+            Some("yaml" | "yml") => serde_yaml::from_str(&content).change_context_lazy(spec_error)?,
+            _ => toml::from_str(&content).change_context_lazy(spec_error)?,
+        };
+        Ok(spec)
+    }
+
+    /// 按定义配置的对话构建器；定义自相矛盾或正则无效时返回错误
+    /// Chat builder configured by the definition; fails if the definition contradicts itself or has an invalid
+    /// pattern
+    pub fn builder(&self) -> Result<SingleChatBuilder, ChatError> {
+        let spec_error = || ChatError::AgentSpecError(self.name.clone());
+        if self.name.trim().is_empty() {
+            return Err(Report::new(spec_error()).attach_printable("Agent without a name"));
+        }
+
+        let mut builder = SingleChat::builder().system(&self.system).tag(&format!("agent:{}", self.name));
+        if let Some(version) = &self.version {
+            builder = builder.tag(&format!("version:{}", version));
+        }
+        if let Some(api) = &self.api {
+            builder = builder.api(api);
+        }
+        let default_capability = self.api.is_none().then_some(ModelCapability::ToolUse);
+        if let Some(capability) = self.capability.clone().or(default_capability) {
+            builder = builder.capability(capability);
+        }
+        if !self.tools.is_empty() {
+            builder = builder.tools(self.tools.clone());
+        }
+        if let Some(params) = &self.params {
+            builder = builder.params(params.clone());
+        }
+
+        match (self.memory.window, self.memory.summarize_after) {
+            (Some(_), Some(_)) => {
+                return Err(Report::new(spec_error())
+                    .attach_printable("memory.window and memory.summarize_after cannot both be set"));
+            }
+            (Some(window), None) => builder = builder.history_policy(SlidingWindow::new(window)),
+            (None, Some(keep_recent)) => builder = builder.history_policy(SummarizeOld::new(keep_recent)),
+            (None, None) => {}
+        }
+        if self.memory.scratchpad {
+            builder = builder.scratchpad();
+        }
+
+        if let Some(spec) = &self.guardrails {
+            let action = spec.action;
+            let mut guardrails = Guardrails::new();
+            if !spec.deny.is_empty() {
+                guardrails = guardrails.with(DenyList::new(&spec.deny, action));
+            }
+            for pattern in &spec.patterns {
+                let rule = RegexRule::new(pattern, action)
+                    .change_context_lazy(spec_error)
+                    .attach_printable_lazy(|| format!("Invalid pattern: {}", pattern))?;
+                guardrails = guardrails.with(rule);
+            }
+            if let Some(max_length) = spec.max_length {
+                guardrails = guardrails.with(MaxLength::new(max_length, action));
+            }
+            if let Some(domains) = &spec.allowed_domains {
+                guardrails = guardrails.with(UrlAllowlist::new(domains.clone(), action));
+            }
+            if let Some(max_regenerations) = spec.max_regenerations {
+                guardrails = guardrails.max_regenerations(max_regenerations);
+            }
+            builder = builder.guardrails(guardrails);
+        }
+
+        // 令牌与费用上限借助以智能体命名的租户执行
+        // Token and cost limits are enforced through a tenant named after the agent
+        if self.budget.max_tokens.is_some() || self.budget.max_cost.is_some() {
+            let mut tenant = Tenant::new(&format!("agent:{}", self.name));
+            tenant.token_budget = self.budget.max_tokens;
+            tenant.budget = self.budget.max_cost;
+            builder = builder.tenant(tenant);
+        }
+        Ok(builder)
+    }
+
+    /// 每次运行的截止时间
+    /// Deadline of every run
+    pub fn timeout(&self) -> Duration {
+        self.budget.timeout_secs.map(Duration::from_secs).unwrap_or(DEFAULT_AGENT_TIMEOUT)
+    }
+}
+
+/// 由定义创建的智能体：一个按定义配置的对话，每次运行在预算的截止时间内执行智能体循环
+/// Agent created from a definition: a chat configured by it, every run executing the agent loop within the budget's
+/// deadline
+///
+/// ```ignore
+/// let mut agent = Agent::from_file("agents/researcher.toml")?;
+/// let run = agent.run("Summarize the history of the Rhine").await?;
+/// ```
+#[derive(Debug)]
+pub struct Agent {
+    spec: AgentSpec,
+    chat: SingleChat,
+}
+
+impl Agent {
+    /// 从 TOML、JSON 或 YAML 定义文件创建智能体，见 `AgentSpec`
+    /// Create an agent from a TOML, JSON or YAML definition file, see `AgentSpec`
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ChatError> {
+        let path = path.as_ref();
+        Self::from_spec(AgentSpec::load(path)?).attach_printable_lazy(|| format!("Defined in {}", path.display()))
+    }
+
+    pub fn from_spec(spec: AgentSpec) -> Result<Self, ChatError> {
+        let chat = spec
+            .builder()?
+            .build()
+            .attach_printable_lazy(|| format!("Failed to create agent {}", spec.name))?;
+        Ok(Self { spec, chat })
+    }
+
+    pub fn spec(&self) -> &AgentSpec {
+        &self.spec
+    }
+
+    pub fn name(&self) -> &str {
+        &self.spec.name
+    }
+
+    /// 智能体的对话，可用于设置事件处理函数、工具来源等定义文件无法表达的设置
+    /// The agent's chat, for settings a definition file cannot express such as event handlers and tool sources
+    pub fn chat_mut(&mut self) -> &mut SingleChat {
+        &mut self.chat
+    }
+
+    pub fn chat(&self) -> &SingleChat {
+        &self.chat
+    }
+
+    pub fn into_chat(self) -> SingleChat {
+        self.chat
+    }
+
+    /// 在定义的截止时间内运行智能体循环，见 `SingleChat::run_agent`
+    /// Run the agent loop within the defined deadline, see `SingleChat::run_agent`
+    pub async fn run(&mut self, task: &str) -> Result<AgentRun, ToolCallError> {
+        self.chat.run_agent(task, Deadline::after(self.spec.timeout())).await
+    }
+}
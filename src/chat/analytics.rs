@@ -0,0 +1,350 @@
+// 标准库
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// 错误处理
+use error_stack::{Report, Result, ResultExt};
+use thiserror::Error;
+
+// 时间
+use chrono::{DateTime, NaiveDate, Utc};
+
+// 数据序列化
+use serde::Serialize;
+use serde_json::Value;
+
+// 项目内部模块
+use crate::chat::content::Content;
+use crate::chat::message::{Role, Session};
+use crate::chat::refusal::RefusalReason;
+use crate::chat::store::{SessionEntry, SessionStore};
+use crate::telemetry::ledger::{UsageGroup, UsageLedger, usage_ledger};
+
+/// 对话分析错误枚举
+/// Conversation analytics error enum
+#[derive(Debug, Error)]
+pub enum AnalyticsError {
+    /// 读取对话存储失败
+    /// Reading the conversation store failed
+    #[error("Failed to read the conversation store")]
+    Store,
+
+    /// 读取用量账本失败
+    /// Reading the usage ledger failed
+    #[error("Failed to read the usage ledger")]
+    Ledger,
+
+    /// 没有设置用量账本，也没有启用全局账本
+    /// No usage ledger was set and no global ledger is enabled
+    #[error("No usage ledger is available")]
+    NoLedger,
+}
+
+/// 一天的消息数，按 UTC 日期统计
+/// Message counts of one day, by UTC date
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct DailyMessages {
+    pub date: NaiveDate,
+    pub messages: u64,
+    pub user_messages: u64,
+    pub assistant_messages: u64,
+}
+
+/// 每个对话的平均轮数，一轮为一条用户消息
+/// Average turns per conversation, one turn being one user message
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct TurnStats {
+    pub conversations: u64,
+    pub turns: u64,
+
+    /// 没有对话时为 0
+    /// 0 if there are no conversations
+    pub average: f64,
+}
+
+/// 一个工具的调用情况
+/// Usage of one tool
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ToolUsage {
+    pub name: String,
+    pub calls: u64,
+    pub errors: u64,
+
+    /// 记录了耗时的调用的平均耗时（毫秒）
+    /// Average duration in milliseconds of the calls with a recorded duration
+    pub average_duration_ms: Option<f64>,
+}
+
+/// 一类错误出现的次数
+/// How often one kind of error occurred
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ErrorCount {
+    /// 错误类型，工具错误形如 `tool.not_found`，拒绝回答形如 `refusal.content_filter`
+    /// Kind of error, such as `tool.not_found` for tool errors and `refusal.content_filter` for refusals
+    pub kind: String,
+    pub count: u64,
+}
+
+/// 一个标签的用量与费用
+/// Usage and cost of one tag
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct TagCost {
+    pub tag: String,
+    pub requests: u64,
+    pub total_tokens: u64,
+
+    /// 费用（美元），未配置价格的模型不计费
+    /// Cost in USD, models without prices cost nothing
+    pub cost: f64,
+}
+
+/// 对话分析：在对话存储与用量账本之上统计产品指标，返回带类型的结果，无需编写 SQL
+/// Conversation analytics: product metrics computed on top of the conversation store and the usage ledger, returned
+/// as typed results without writing SQL
+///
+/// 对话按默认路径统计，即用户看到的那条分支；存储需支持 `SessionStore::list`。
+/// Conversations are counted along their default path, the branch the user sees; the store must support
+/// `SessionStore::list`.
+///
+/// ```no_run
+/// # use std::sync::Arc;
+/// # use rhine::chat::analytics::ConversationAnalytics;
+/// # use rhine::chat::store::DirectorySessionStore;
+/// # async fn run() {
+/// let store = Arc::new(DirectorySessionStore::new("sessions").unwrap());
+/// let analytics = ConversationAnalytics::new(store);
+/// for tool in analytics.tool_usage().await.unwrap() {
+///     println!("{}: {} calls, {} errors", tool.name, tool.calls, tool.errors);
+/// }
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct ConversationAnalytics {
+    store: Arc<dyn SessionStore>,
+    ledger: Option<Arc<dyn UsageLedger>>,
+    since: Option<SystemTime>,
+}
+
+impl std::fmt::Debug for ConversationAnalytics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ConversationAnalytics").field("since", &self.since).finish_non_exhaustive()
+    }
+}
+
+impl ConversationAnalytics {
+    pub fn new(store: Arc<dyn SessionStore>) -> Self {
+        Self {
+            store,
+            ledger: None,
+            since: None,
+        }
+    }
+
+    /// 统计费用时使用的用量账本，未设置时使用全局账本
+    /// Usage ledger used for costs, the global ledger if not set
+    pub fn ledger(mut self, ledger: Arc<dyn UsageLedger>) -> Self {
+        self.ledger = Some(ledger);
+        self
+    }
+
+    /// 只统计该时间之后保存过的对话
+    /// Only count conversations saved after this time
+    pub fn since(mut self, time: SystemTime) -> Self {
+        self.since = Some(time);
+        self
+    }
+
+    /// 时间范围内每天的消息数，按日期排列，没有消息的日期不列出；旧版会话中没有创建时间的消息按对话的保存时间计
+    /// Message counts per day within the time range, by date, days without messages are left out; messages of older
+    /// sessions without a creation time count at the time the conversation was saved
+    pub async fn messages_per_day(
+        &self,
+        from: SystemTime,
+        to: SystemTime,
+    ) -> Result<Vec<DailyMessages>, AnalyticsError> {
+        let (from, to) = (unix_millis(from), unix_millis(to));
+        let mut days: BTreeMap<NaiveDate, DailyMessages> = BTreeMap::new();
+        self.for_each_conversation(|entry, session| {
+            let saved_at = unix_millis(entry.saved_at);
+            for message in session.default_path_messages() {
+                let created_at = message.created_at_ms.unwrap_or(saved_at);
+                if !(from..to).contains(&created_at) {
+                    continue;
+                }
+                let date = DateTime::<Utc>::from(UNIX_EPOCH + Duration::from_millis(created_at)).date_naive();
+                let day = days.entry(date).or_insert_with(|| DailyMessages {
+                    date,
+                    messages: 0,
+                    user_messages: 0,
+                    assistant_messages: 0,
+                });
+                day.messages += 1;
+                match message.role {
+                    Role::User => day.user_messages += 1,
+                    Role::Assistant | Role::Character(_) => day.assistant_messages += 1,
+                    _ => {}
+                }
+            }
+        })
+        .await?;
+        Ok(days.into_values().collect())
+    }
+
+    /// 每个对话的平均轮数
+    /// Average turns per conversation
+    pub async fn average_turns(&self) -> Result<TurnStats, AnalyticsError> {
+        let (mut count, mut turns) = (0, 0);
+        self.for_each_conversation(|_, session| {
+            count += 1;
+            turns += session.default_path_messages().iter().filter(|message| message.role == Role::User).count() as u64;
+        })
+        .await?;
+        Ok(TurnStats {
+            conversations: count,
+            turns,
+            average: if count == 0 { 0.0 } else { turns as f64 / count as f64 },
+        })
+    }
+
+    /// 各工具的调用次数、失败次数与平均耗时，按调用次数从多到少排列，次数相同时按名称
+    /// Calls, failures and average duration of each tool, from most to least called, ties broken by name
+    pub async fn tool_usage(&self) -> Result<Vec<ToolUsage>, AnalyticsError> {
+        let mut tools: HashMap<String, (ToolUsage, u64, u64)> = HashMap::new();
+        self.for_each_conversation(|_, session| {
+            let mut names: HashMap<String, String> = HashMap::new();
+            for message in session.default_path_messages() {
+                for part in message.content.parts() {
+                    match part {
+                        Content::ToolCall { id, name, .. } => {
+                            names.insert(id.clone(), name.clone());
+                        }
+                        Content::ToolResult {
+                            call_id,
+                            name,
+                            duration_ms,
+                            is_error,
+                            ..
+                        } => {
+                            let Some(name) = name.clone().or_else(|| names.get(call_id).cloned()) else {
+                                continue;
+                            };
+                            let (usage, total_ms, timed) = tools.entry(name.clone()).or_insert_with(|| {
+                                let usage = ToolUsage {
+                                    name,
+                                    calls: 0,
+                                    errors: 0,
+                                    average_duration_ms: None,
+                                };
+                                (usage, 0, 0)
+                            });
+                            usage.calls += 1;
+                            usage.errors += *is_error as u64;
+                            if let Some(duration_ms) = duration_ms {
+                                *total_ms += duration_ms;
+                                *timed += 1;
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        })
+        .await?;
+
+        let mut tools: Vec<ToolUsage> = tools
+            .into_values()
+            .map(|(usage, total_ms, timed)| ToolUsage {
+                average_duration_ms: (timed > 0).then(|| total_ms as f64 / timed as f64),
+                ..usage
+            })
+            .collect();
+        tools.sort_by(|a, b| b.calls.cmp(&a.calls).then_with(|| a.name.cmp(&b.name)));
+        Ok(tools)
+    }
+
+    /// 出现最多的 `limit` 类错误：失败的工具调用按错误类型，拒绝回答按拒绝原因
+    /// The `limit` most frequent kinds of error: failed tool calls by error type, refusals by reason
+    pub async fn top_errors(&self, limit: usize) -> Result<Vec<ErrorCount>, AnalyticsError> {
+        let mut errors: HashMap<String, u64> = HashMap::new();
+        self.for_each_conversation(|_, session| {
+            for message in session.default_path_messages() {
+                for part in message.content.parts() {
+                    if let Content::ToolResult {
+                        content, is_error: true, ..
+                    } = part
+                    {
+                        *errors.entry(tool_error_kind(content)).or_default() += 1;
+                    }
+                }
+                if let Some(reason) = message.metadata.as_ref().and_then(|metadata| metadata.refusal.as_ref()) {
+                    let kind = match reason {
+                        RefusalReason::Declined { .. } => "refusal.declined",
+                        RefusalReason::ContentFilter => "refusal.content_filter",
+                        RefusalReason::Safety { .. } => "refusal.safety",
+                    };
+                    *errors.entry(kind.to_string()).or_default() += 1;
+                }
+            }
+        })
+        .await?;
+
+        let mut errors: Vec<ErrorCount> = errors.into_iter().map(|(kind, count)| ErrorCount { kind, count }).collect();
+        errors.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.kind.cmp(&b.kind)));
+        errors.truncate(limit);
+        Ok(errors)
+    }
+
+    /// 时间范围内各标签的用量与费用，按费用从高到低排列，费用相同时按标签
+    /// Usage and cost of each tag within the time range, from most to least expensive, ties broken by tag
+    pub fn cost_per_tag(&self, from: SystemTime, to: SystemTime) -> Result<Vec<TagCost>, AnalyticsError> {
+        let ledger = self
+            .ledger
+            .clone()
+            .or_else(usage_ledger)
+            .ok_or_else(|| Report::new(AnalyticsError::NoLedger))?;
+        let totals = ledger.totals_by(from, to, UsageGroup::Tag).change_context(AnalyticsError::Ledger)?;
+        let mut costs: Vec<TagCost> = totals
+            .into_iter()
+            .map(|(tag, totals)| TagCost {
+                tag,
+                requests: totals.requests,
+                total_tokens: totals.total_tokens,
+                cost: totals.cost,
+            })
+            .collect();
+        costs.sort_by(|a, b| b.cost.total_cmp(&a.cost).then_with(|| a.tag.cmp(&b.tag)));
+        Ok(costs)
+    }
+
+    /// 逐个读取存储中的对话并交给 `visit`，按 `since` 筛选；同一时间只有一个对话在内存中，统计期间被删除的对话跳过
+    /// Load the conversations in the store one at a time and hand each to `visit`, filtered by `since`; only one
+    /// conversation is in memory at a time, conversations removed meanwhile are skipped
+    async fn for_each_conversation(
+        &self,
+        mut visit: impl FnMut(&SessionEntry, &Session),
+    ) -> Result<(), AnalyticsError> {
+        for entry in self.store.list().await.change_context(AnalyticsError::Store)? {
+            if self.since.is_some_and(|since| entry.saved_at < since) {
+                continue;
+            }
+            if let Some(stored) = self.store.load(&entry.id).await.change_context(AnalyticsError::Store)? {
+                visit(&entry, &stored.session);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 失败的工具结果的错误类型，见 `ToolError::to_output`；其他格式计为 `tool.unknown`
+/// Error type of a failed tool result, see `ToolError::to_output`; other formats count as `tool.unknown`
+fn tool_error_kind(output: &str) -> String {
+    let kind = serde_json::from_str::<Value>(output)
+        .ok()
+        .and_then(|output| output["error"]["error_type"].as_str().map(str::to_string));
+    format!("tool.{}", kind.as_deref().unwrap_or("unknown"))
+}
+
+fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64
+}
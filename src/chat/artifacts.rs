@@ -0,0 +1,369 @@
+// 文本处理
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+// 数据序列化
+use serde::{Deserialize, Serialize};
+
+/// 代码围栏的开头：至少三个反引号或波浪号，之后为信息串
+/// Opening of a code fence: at least three backticks or tildes, followed by the info string
+static FENCE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^\s*(`{3,}|~{3,})\s*(.*?)\s*$").unwrap());
+
+/// 路径的形式：不含空白，带扩展名或目录
+/// Shape of a path: no whitespace, with an extension or a directory
+const PATH: &str = r"[`'\x22*]*([\w@~+-][\w./@~+\\-]*)[`'\x22*]*";
+
+/// 写入文件的说明，如 "Create file `src/main.rs` with:" 或 "创建文件 src/main.rs："
+/// Directive to write a file, such as "Create file `src/main.rs` with:" or "创建文件 src/main.rs："
+static WRITE_DIRECTIVE: Lazy<Regex> = Lazy::new(|| {
+    let english = r"\b(?:create|write|save|add|update|overwrite|replace|modify|edit)\b.*?\bfile\b\s*[:：]?";
+    let chinese = r"(?:创建|新建|写入|更新|修改|覆盖)(?:文件)?\s*[:：]?";
+    Regex::new(&format!(r"(?i)(?:{}|{})\s*{}", english, chinese, PATH)).unwrap()
+});
+
+/// 删除文件的说明，如 "Delete the file `old.rs`"
+/// Directive to delete a file, such as "Delete the file `old.rs`"
+static DELETE_DIRECTIVE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(&format!(r"(?i)(?:\b(?:delete|remove)\s+(?:the\s+)?file\b\s*[:：]?|删除文件\s*[:：]?)\s*{}", PATH)).unwrap()
+});
+
+/// 文件名标签，如 "File: src/main.rs"、"### src/main.rs" 或单独一行的 "**src/main.rs**"
+/// File name label, such as "File: src/main.rs", "### src/main.rs" or "**src/main.rs**" on its own line
+static PATH_LABEL: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(&format!(r"(?i)^\s*(?:#+\s*)?(?:\**(?:file(?:name)?|path|文件)\**\s*[:：]\**\s*)?{}\s*[:：]?\s*$", PATH))
+        .unwrap()
+});
+
+/// 代码块首行的文件名注释，如 "// file: src/main.rs"
+/// File name comment on the first line of a code block, such as "// file: src/main.rs"
+static PATH_COMMENT: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(&format!(r"(?i)^\s*(?://|#|--|<!--|/\*)\s*(?:file(?:name)?|path)\s*[:：]\s*{}", PATH)).unwrap()
+});
+
+/// 信息串中的文件名属性，如 `title="src/main.rs"`
+/// File name attribute of the info string, such as `title="src/main.rs"`
+static INFO_PATH: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"(?i)\b(?:title|file|filename|path)\s*=\s*["']?([^"'\s]+)"#).unwrap());
+
+/// 差异块的头：`@@ -1,3 +1,4 @@`，行数可省略
+/// Hunk header of a diff: `@@ -1,3 +1,4 @@`, line counts may be omitted
+static HUNK_HEADER: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^@@\s*(?:-(\d+)(?:,(\d+))?\s+\+(\d+)(?:,(\d+))?)?").unwrap());
+
+/// 回答中的一个代码块
+/// One code block of an answer
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CodeBlock {
+    /// 信息串中的语言，如 `rust`
+    /// Language of the info string, such as `rust`
+    pub language: Option<String>,
+
+    /// 代码块对应的文件，来自信息串、首行注释或紧邻的上一行说明
+    /// File the block belongs to, from the info string, a first-line comment or the line right above
+    pub path: Option<String>,
+
+    pub code: String,
+
+    /// 围栏是否闭合；回答被截断时最后一个代码块可能不完整
+    /// Whether the fence was closed; the last block may be incomplete when the answer was cut off
+    pub complete: bool,
+}
+
+impl CodeBlock {
+    /// 是否为统一差异格式
+    /// Whether the block is in unified diff format
+    pub fn is_diff(&self) -> bool {
+        matches!(self.language.as_deref(), Some("diff" | "patch"))
+            || self.code.starts_with("diff --git ")
+            || (self.code.starts_with("--- ") && self.code.lines().nth(1).is_some_and(|line| line.starts_with("+++ ")))
+    }
+}
+
+/// 对文件的操作
+/// Operation on a file
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileAction {
+    /// 以给定内容创建或覆盖
+    /// Create or overwrite with the given content
+    Write,
+
+    Delete,
+}
+
+/// 回答中的文件说明，如 "create file X with contents"
+/// File directive of an answer, such as "create file X with contents"
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileDirective {
+    pub path: String,
+
+    pub action: FileAction,
+
+    /// 写入的内容，删除时为 None
+    /// Content to write, None for deletions
+    pub content: Option<String>,
+
+    pub language: Option<String>,
+}
+
+/// 差异块中的一行
+/// One line of a hunk
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "text", rename_all = "snake_case")]
+pub enum DiffLine {
+    Context(String),
+    Added(String),
+    Removed(String),
+}
+
+/// 差异中的一块改动
+/// One hunk of a diff
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Hunk {
+    /// 原文件的起始行号，头中省略时为 None
+    /// Starting line in the original file, None if the header omits it
+    pub old_start: Option<usize>,
+
+    /// 新文件的起始行号，头中省略时为 None
+    /// Starting line in the new file, None if the header omits it
+    pub new_start: Option<usize>,
+
+    pub lines: Vec<DiffLine>,
+}
+
+/// 一个文件的统一差异
+/// Unified diff of one file
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FilePatch {
+    /// 原文件路径，去掉 `a/` 前缀；新建文件为 None
+    /// Original path without the `a/` prefix; None for new files
+    pub old_path: Option<String>,
+
+    /// 新文件路径，去掉 `b/` 前缀；删除文件为 None
+    /// New path without the `b/` prefix; None for deleted files
+    pub new_path: Option<String>,
+
+    pub hunks: Vec<Hunk>,
+}
+
+impl FilePatch {
+    /// 改动的文件，优先取新路径
+    /// File changed, preferring the new path
+    pub fn path(&self) -> Option<&str> {
+        self.new_path.as_deref().or(self.old_path.as_deref())
+    }
+
+    /// 增加与删除的行数
+    /// Numbers of added and removed lines
+    pub fn stats(&self) -> (usize, usize) {
+        self.hunks.iter().flat_map(|hunk| &hunk.lines).fold((0, 0), |(added, removed), line| match line {
+            DiffLine::Added(_) => (added + 1, removed),
+            DiffLine::Removed(_) => (added, removed + 1),
+            DiffLine::Context(_) => (added, removed),
+        })
+    }
+
+    /// 解析统一差异文本，可包含多个文件；容忍模型常见的行数错误与省略的行号
+    /// Parse unified diff text, possibly of several files; tolerates the wrong line counts and omitted line numbers
+    /// models commonly produce
+    pub fn parse(diff: &str) -> Vec<Self> {
+        let mut patches: Vec<Self> = Vec::new();
+        let lines: Vec<&str> = diff.lines().collect();
+        let mut index = 0;
+        while index < lines.len() {
+            let line = lines[index];
+            let next = lines.get(index + 1).copied().unwrap_or_default();
+            if let Some(paths) = line.strip_prefix("diff --git ") {
+                let (old, new) = paths.split_once(' ').unwrap_or((paths, paths));
+                patches.push(Self {
+                    old_path: diff_path(old),
+                    new_path: diff_path(new),
+                    hunks: Vec::new(),
+                });
+            } else if line.starts_with("--- ") && next.starts_with("+++ ") {
+                // `diff --git` 之后紧跟的文件头属于同一个文件
+                // File headers right after `diff --git` belong to the same file
+                if !patches.last().is_some_and(|patch| patch.hunks.is_empty()) {
+                    patches.push(Self::default());
+                }
+                let patch = patches.last_mut().unwrap();
+                patch.old_path = diff_path(&line[4..]);
+                patch.new_path = diff_path(&next[4..]);
+                index += 1;
+            } else if let Some(captures) = HUNK_HEADER.captures(line) {
+                if patches.is_empty() {
+                    patches.push(Self::default());
+                }
+                let number = |group: usize| captures.get(group).and_then(|number| number.as_str().parse().ok());
+                patches.last_mut().unwrap().hunks.push(Hunk {
+                    old_start: number(1),
+                    new_start: number(3),
+                    lines: Vec::new(),
+                });
+            } else if let Some(hunk) = patches.last_mut().and_then(|patch| patch.hunks.last_mut()) {
+                match line.chars().next() {
+                    Some('+') => hunk.lines.push(DiffLine::Added(line[1..].to_string())),
+                    Some('-') => hunk.lines.push(DiffLine::Removed(line[1..].to_string())),
+                    Some(' ') => hunk.lines.push(DiffLine::Context(line[1..].to_string())),
+                    None => hunk.lines.push(DiffLine::Context(String::new())),
+                    _ => {}
+                }
+            }
+            index += 1;
+        }
+        patches
+    }
+}
+
+/// 差异头中的路径：去掉时间戳与 `a/`、`b/` 前缀，`/dev/null` 为 None
+/// Path of a diff header: the timestamp and the `a/` and `b/` prefixes are removed, `/dev/null` is None
+fn diff_path(header: &str) -> Option<String> {
+    let path = header.split('\t').next().unwrap_or_default().trim();
+    if path.is_empty() || path == "/dev/null" {
+        return None;
+    }
+    let path = path.strip_prefix("a/").or_else(|| path.strip_prefix("b/")).unwrap_or(path);
+    Some(path.to_string())
+}
+
+/// 解析后的回答：正文、代码块、文件说明与差异，供编码智能体直接使用而不必各自编写脆弱的正则
+/// Parsed answer: prose, code blocks, file directives and diffs, ready for coding agents so each does not have to
+/// write its own fragile regular expressions
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ParsedAnswer {
+    /// 去掉代码块后的正文
+    /// Prose with the code blocks removed
+    pub prose: String,
+
+    /// 全部代码块，按出现顺序
+    /// Every code block, in order of appearance
+    pub code_blocks: Vec<CodeBlock>,
+
+    /// 带有文件名的非差异代码块（写入）与删除说明，按出现顺序
+    /// Non-diff code blocks with a file name (writes) and delete directives, in order of appearance
+    pub files: Vec<FileDirective>,
+
+    /// 差异代码块中解析出的文件改动
+    /// File changes parsed from diff code blocks
+    pub patches: Vec<FilePatch>,
+}
+
+impl ParsedAnswer {
+    /// 解析回答
+    /// Parse an answer
+    pub fn parse(answer: &str) -> Self {
+        let mut parsed = Self::default();
+        let mut prose: Vec<&str> = Vec::new();
+        let mut lines = answer.lines();
+
+        while let Some(line) = lines.next() {
+            let Some(captures) = FENCE.captures(line) else {
+                if let Some(path) = DELETE_DIRECTIVE.captures(line).and_then(|captures| valid_path(&captures[1])) {
+                    parsed.files.push(FileDirective {
+                        path,
+                        action: FileAction::Delete,
+                        content: None,
+                        language: None,
+                    });
+                }
+                prose.push(line);
+                continue;
+            };
+
+            // 闭合围栏与开头的字符相同、长度不短于开头且没有信息串
+            // The closing fence uses the opening character, is at least as long and has no info string
+            let fence = &captures[1];
+            let info = &captures[2];
+            let mut body: Vec<&str> = Vec::new();
+            let mut complete = false;
+            for line in lines.by_ref() {
+                let trimmed = line.trim();
+                if trimmed.len() >= fence.len() && trimmed.chars().all(|c| fence.starts_with(c)) {
+                    complete = true;
+                    break;
+                }
+                body.push(line);
+            }
+
+            let mut block = CodeBlock {
+                complete,
+                ..Default::default()
+            };
+            let mut words = info.split_whitespace();
+            if let Some(first) = words.next().filter(|word| !word.contains('=')) {
+                match first.split_once(':') {
+                    Some((language, path)) => {
+                        block.language = Some(language.to_string()).filter(|language| !language.is_empty());
+                        block.path = valid_path(path);
+                    }
+                    None if first.contains('/') || first.contains('.') => block.path = valid_path(first),
+                    None => block.language = Some(first.to_string()),
+                }
+            }
+            block.path = block
+                .path
+                .or_else(|| INFO_PATH.captures(info).and_then(|captures| valid_path(&captures[1])))
+                .or_else(|| words.next().filter(|word| !word.contains('=')).and_then(valid_path));
+            let comment = body.first().and_then(|line| PATH_COMMENT.captures(line));
+            if let Some(path) = comment.and_then(|captures| valid_path(&captures[1])) {
+                block.path = block.path.or(Some(path));
+                body.remove(0);
+            }
+            if block.path.is_none() {
+                block.path = prose.iter().rev().find(|line| !line.trim().is_empty()).and_then(|line| {
+                    let captures = WRITE_DIRECTIVE.captures(line).or_else(|| PATH_LABEL.captures(line))?;
+                    valid_path(&captures[1])
+                });
+            }
+            block.code = body.join("\n");
+
+            if block.is_diff() {
+                // 改动绝对路径或工作目录之外的文件的差异不接受
+                // Diffs changing absolute paths or files outside the working directory are rejected
+                let patches = FilePatch::parse(&block.code).into_iter().filter(|patch| {
+                    [&patch.old_path, &patch.new_path].into_iter().flatten().all(|path| is_relative_path(path))
+                });
+                parsed.patches.extend(patches);
+            } else if let Some(path) = &block.path {
+                parsed.files.push(FileDirective {
+                    path: path.clone(),
+                    action: FileAction::Write,
+                    content: Some(block.code.clone()),
+                    language: block.language.clone(),
+                });
+            }
+            parsed.code_blocks.push(block);
+        }
+
+        parsed.prose = prose.join("\n").trim().to_string();
+        parsed
+    }
+
+    /// 指定语言的代码块，语言不区分大小写
+    /// Code blocks of a language, compared case-insensitively
+    pub fn blocks_in(&self, language: &str) -> Vec<&CodeBlock> {
+        self.code_blocks
+            .iter()
+            .filter(|block| block.language.as_deref().is_some_and(|lang| lang.eq_ignore_ascii_case(language)))
+            .collect()
+    }
+}
+
+/// 只接受像路径的文本：带扩展名或目录，且不是网址；绝对路径与跳出工作目录的路径不接受
+/// Only accept text that looks like a path: with an extension or a directory, and not a URL; absolute paths and
+/// paths leaving the working directory are rejected
+fn valid_path(path: &str) -> Option<String> {
+    let path = path
+        .trim_matches(['`', '\'', '"', '*'])
+        .trim_end_matches([':', '：', ',', '.']);
+    let looks_like_path = (path.contains('/') || path.contains('.')) && !path.contains("://");
+    (looks_like_path && is_relative_path(path)).then(|| path.to_string())
+}
+
+/// 相对于工作目录且不含 `..` 的路径，不接受 `/`、`\`、`~` 开头或带盘符的路径
+/// Path relative to the working directory without `..`, paths starting with `/`, `\` or `~` or with a drive letter
+/// are not accepted
+fn is_relative_path(path: &str) -> bool {
+    let absolute = path.starts_with(['/', '\\', '~']) || path.chars().nth(1) == Some(':');
+    !absolute && path.split(['/', '\\']).all(|component| component != "..")
+}
@@ -0,0 +1,253 @@
+// 标准库
+use std::fmt::Debug;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+
+// 错误处理
+use error_stack::{Report, Result};
+
+// 并发和同步原语
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+
+// 数据序列化
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+
+// 唯一标识
+use uuid::Uuid;
+
+// 项目内部模块
+use crate::chat::chat_base::ChatError;
+use crate::config::Config;
+use crate::config::prompts::PromptKey;
+use crate::utils::common::file_name::encode_file_name;
+
+static STORE: Lazy<RwLock<Arc<dyn AttachmentStore>>> =
+    Lazy::new(|| RwLock::new(Arc::new(MemoryAttachmentStore::default())));
+
+fn attachment_error(message: impl Into<String>) -> Report<ChatError> {
+    Report::new(ChatError::AttachmentError(message.into()))
+}
+
+/// 附件内容的存储后端，进程内所有对话共用，工具也从这里读取附件
+/// Storage backend of attachment content, shared by every chat in the process; tools read attachments from here too
+pub trait AttachmentStore: Send + Sync {
+    fn put(&self, id: &str, bytes: Bytes) -> Result<(), ChatError>;
+
+    /// 读取附件内容，不存在时返回 None
+    /// Read the attachment content, None if it does not exist
+    fn get(&self, id: &str) -> Result<Option<Bytes>, ChatError>;
+
+    fn remove(&self, id: &str) -> Result<(), ChatError>;
+}
+
+/// 进程内存储，默认使用，进程退出后内容丢失
+/// In-process storage, used by default, its content is lost when the process exits
+#[derive(Debug, Default)]
+pub struct MemoryAttachmentStore {
+    items: DashMap<String, Bytes>,
+}
+
+impl AttachmentStore for MemoryAttachmentStore {
+    fn put(&self, id: &str, bytes: Bytes) -> Result<(), ChatError> {
+        self.items.insert(id.to_string(), bytes);
+        Ok(())
+    }
+
+    fn get(&self, id: &str) -> Result<Option<Bytes>, ChatError> {
+        Ok(self.items.get(id).map(|item| item.value().clone()))
+    }
+
+    fn remove(&self, id: &str) -> Result<(), ChatError> {
+        self.items.remove(id);
+        Ok(())
+    }
+}
+
+/// 目录存储，每个附件一个以ID命名的文件，保存的会话重新加载后附件仍可读取
+/// Directory storage, one file named by ID per attachment, so attachments stay readable when a saved session is
+/// loaded again
+#[derive(Clone, Debug)]
+pub struct DirectoryAttachmentStore {
+    directory: PathBuf,
+}
+
+impl DirectoryAttachmentStore {
+    pub fn new(directory: impl Into<PathBuf>) -> Result<Self, ChatError> {
+        let directory = directory.into();
+        std::fs::create_dir_all(&directory)
+            .map_err(|e| attachment_error(format!("{}: {}", directory.display(), e)))?;
+        Ok(Self { directory })
+    }
+
+    /// 附件文件路径，不同的ID总是对应不同的文件，且不会写出目录之外
+    /// Path of an attachment file, distinct IDs always map to distinct files and nothing is written outside the
+    /// directory
+    fn path(&self, id: &str) -> PathBuf {
+        self.directory.join(encode_file_name(id))
+    }
+}
+
+impl AttachmentStore for DirectoryAttachmentStore {
+    fn put(&self, id: &str, bytes: Bytes) -> Result<(), ChatError> {
+        let path = self.path(id);
+        std::fs::write(&path, &bytes).map_err(|e| attachment_error(format!("{}: {}", path.display(), e)))
+    }
+
+    fn get(&self, id: &str) -> Result<Option<Bytes>, ChatError> {
+        let path = self.path(id);
+        match std::fs::read(&path) {
+            Ok(bytes) => Ok(Some(Bytes::from(bytes))),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(attachment_error(format!("{}: {}", path.display(), e))),
+        }
+    }
+
+    fn remove(&self, id: &str) -> Result<(), ChatError> {
+        match std::fs::remove_file(self.path(id)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(attachment_error(e.to_string())),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// 设置进程内使用的附件存储，已有附件不会迁移
+/// Set the attachment storage used in the process, existing attachments are not migrated
+pub fn set_attachment_store(store: impl AttachmentStore + 'static) {
+    *STORE.write().unwrap() = Arc::new(store);
+}
+
+pub fn attachment_store() -> Arc<dyn AttachmentStore> {
+    STORE.read().unwrap().clone()
+}
+
+/// 按ID读取附件内容，供工具使用
+/// Read attachment content by ID, for tools
+pub fn load_attachment(id: &str) -> Result<Bytes, ChatError> {
+    attachment_store()
+        .get(id)?
+        .ok_or_else(|| attachment_error(format!("attachment {} is not in the store", id)))
+}
+
+/// 附件的大小与数量上限
+/// Size and count limits of attachments
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AttachmentLimits {
+    /// 单个附件的最大字节数
+    /// Maximum bytes of one attachment
+    pub max_bytes: usize,
+
+    /// 一条消息最多携带的附件数
+    /// Maximum attachments carried by one message
+    pub max_per_message: usize,
+}
+
+impl Default for AttachmentLimits {
+    fn default() -> Self {
+        Self {
+            max_bytes: 20 * 1024 * 1024,
+            max_per_message: 10,
+        }
+    }
+}
+
+/// 消息携带的附件，只保存元数据，内容在存储后端中按需读取
+/// Attachment carried by a message, only the metadata is kept, the content is read from the storage on demand
+///
+/// 附件内容默认不发给模型，请求中只列出编号、名称与ID，工具可按ID读取内容。
+/// The content is not sent to the model by default, requests only list the numbers, names and IDs, and tools read
+/// the content by ID.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Attachment {
+    /// 存储后端中的ID
+    /// ID in the storage backend
+    pub id: String,
+
+    pub name: String,
+
+    pub mime_type: String,
+
+    /// 来源，如上传的文件路径或网址
+    /// Origin, such as the uploaded file path or URL
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub origin: Option<String>,
+
+    pub size: usize,
+}
+
+impl Attachment {
+    /// 存入存储后端并创建附件，超过大小上限时报错；指定命名空间时ID以 `<命名空间>/` 开头
+    /// Put the content into the storage backend and create the attachment, failing if it exceeds the size limit;
+    /// with a namespace the ID starts with `<namespace>/`
+    pub(crate) fn store(
+        name: &str,
+        mime_type: &str,
+        bytes: Bytes,
+        origin: Option<&str>,
+        namespace: Option<&str>,
+        limits: &AttachmentLimits,
+    ) -> Result<Self, ChatError> {
+        if bytes.len() > limits.max_bytes {
+            return Err(attachment_error(format!(
+                "{} is {} bytes, over the limit of {} bytes",
+                name,
+                bytes.len(),
+                limits.max_bytes
+            )));
+        }
+        let attachment = Self {
+            id: match namespace {
+                Some(namespace) => format!("{}/{}", namespace, Uuid::new_v4()),
+                None => Uuid::new_v4().to_string(),
+            },
+            name: name.to_string(),
+            mime_type: mime_type.to_string(),
+            origin: origin.map(str::to_string),
+            size: bytes.len(),
+        };
+        attachment_store().put(&attachment.id, bytes)?;
+        Ok(attachment)
+    }
+
+    /// 从存储后端读取内容
+    /// Read the content from the storage backend
+    pub fn load(&self) -> Result<Bytes, ChatError> {
+        load_attachment(&self.id)
+    }
+
+    /// 以 UTF-8 文本读取内容，无效字节被替换
+    /// Read the content as UTF-8 text, invalid bytes are replaced
+    pub fn load_text(&self) -> Result<String, ChatError> {
+        Ok(String::from_utf8_lossy(&self.load()?).into_owned())
+    }
+}
+
+/// 将附件清单作为系统消息插入请求体，位于最后一条消息之前；清单只进入本次请求
+/// Insert the attachment list into a request body as a system message, right before the last message; the list only
+/// goes into this request
+pub(crate) fn insert_attachment_list(request_body: &mut serde_json::Value, attachments: &[&Attachment]) {
+    if attachments.is_empty() {
+        return;
+    }
+
+    let list = attachments
+        .iter()
+        .enumerate()
+        .map(|(index, attachment)| {
+            let (number, size) = ((index + 1).to_string(), attachment.size.to_string());
+            let entry = [number.as_str(), &attachment.name, &attachment.mime_type, &size, &attachment.id];
+            Config::render_prompt(&PromptKey::AttachmentEntry, &entry)
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    let message = serde_json::json!({
+        "role": "system",
+        "content": Config::render_prompt(&PromptKey::AttachmentList, &[&list]),
+    });
+
+    if let Some(messages) = request_body.get_mut("messages").and_then(|messages| messages.as_array_mut()) {
+        let position = messages.len().saturating_sub(1);
+        messages.insert(position, message);
+    }
+}
@@ -0,0 +1,216 @@
+// 标准库
+use std::pin::pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+// 错误处理
+use error_stack::{Report, Result};
+
+// 异步
+use async_stream::stream;
+use futures::{Stream, StreamExt};
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TrySendError;
+
+// 项目内部模块
+use crate::chat::chat_base::ChatError;
+
+/// 订阅者跟不上时的处理方式
+/// What to do when a subscriber cannot keep up
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Backpressure {
+    /// 等待订阅者腾出空间，生成随之放慢；用于不能漏掉内容的订阅者，如安全检查
+    /// Wait for the subscriber to make room, slowing the generation down; for subscribers that must not miss
+    /// anything, such as guardrails
+    Wait,
+
+    /// 缓冲已满时把后续内容合并为一段，分块变少但内容完整；用于界面
+    /// Merge further content into one piece while the buffer is full, fewer chunks but nothing lost; for UIs
+    #[default]
+    Coalesce,
+
+    /// 缓冲已满时断开该订阅者，其流以 `ChatError::SubscriberLagged` 结束；用于不能拖慢生成的订阅者，如日志
+    /// Disconnect the subscriber once its buffer is full, its stream ends with `ChatError::SubscriberLagged`; for
+    /// subscribers that must not slow the generation down, such as loggers
+    Disconnect,
+}
+
+/// 一个订阅者的发送端
+/// Sending side of one subscriber
+struct Sink {
+    sender: mpsc::Sender<Result<String, ChatError>>,
+    backpressure: Backpressure,
+
+    /// 合并中尚未送出的内容
+    /// Content being coalesced that has not been sent yet
+    pending: String,
+
+    lagged: Arc<AtomicBool>,
+}
+
+impl Sink {
+    /// 送出一段内容，返回订阅者是否仍然连接
+    /// Send one piece of content, returning whether the subscriber is still connected
+    async fn push(&mut self, delta: &str) -> bool {
+        match self.backpressure {
+            Backpressure::Wait => self.sender.send(Ok(delta.to_string())).await.is_ok(),
+            Backpressure::Coalesce => {
+                self.pending.push_str(delta);
+                match self.sender.try_send(Ok(std::mem::take(&mut self.pending))) {
+                    Ok(()) => true,
+                    Err(TrySendError::Full(pending)) => {
+                        self.pending = pending.unwrap_or_default();
+                        true
+                    }
+                    Err(TrySendError::Closed(_)) => false,
+                }
+            }
+            Backpressure::Disconnect => match self.sender.try_send(Ok(delta.to_string())) {
+                Ok(()) => true,
+                Err(TrySendError::Full(_)) => {
+                    self.lagged.store(true, Ordering::Relaxed);
+                    false
+                }
+                Err(TrySendError::Closed(_)) => false,
+            },
+        }
+    }
+
+    /// 生成结束：在后台送出合并中的内容与错误，不让慢的订阅者拖住生成方
+    /// The generation ended: the coalesced content and the error are sent in the background, so slow subscribers
+    /// do not hold up the generating side
+    fn close(self, error: Option<&str>) {
+        let mut items = Vec::new();
+        if !self.pending.is_empty() {
+            items.push(Ok(self.pending));
+        }
+        if let Some(error) = error {
+            items.push(Err(Report::new(ChatError::StreamFailed(error.to_string()))));
+        }
+        if items.is_empty() {
+            return;
+        }
+        let sender = self.sender;
+        tokio::spawn(async move {
+            for item in items {
+                if sender.send(item).await.is_err() {
+                    break;
+                }
+            }
+        });
+    }
+}
+
+#[derive(Default)]
+struct Shared {
+    sinks: Vec<Sink>,
+
+    /// 目前为止的回答，供中途加入的订阅者补齐
+    /// The answer so far, for subscribers joining midway to catch up
+    text: String,
+
+    finished: bool,
+}
+
+/// 流式回答的广播：一次生成的内容同时送给多个订阅者（如界面、日志、安全检查），每个订阅者各自缓冲并按自己的方式处理背压
+/// Broadcast of a streamed answer: the content of one generation goes to several subscribers at once (such as a
+/// UI, a logger and a guardrail), each with its own buffer and backpressure handling
+///
+/// 句柄可克隆，生成进行中也可以订阅，新订阅者先收到目前为止的内容。订阅者丢弃其流即取消订阅，不影响生成。
+/// The handle is cloneable and subscribing works while the generation is running, a new subscriber first receives
+/// the content so far. A subscriber unsubscribes by dropping its stream, without affecting the generation.
+#[derive(Clone, Default)]
+pub struct StreamBroadcast {
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl StreamBroadcast {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 订阅回答，`capacity` 为缓冲的分块数；生成失败时流以 `ChatError::StreamFailed` 结束
+    /// Subscribe to the answer, `capacity` being the number of buffered chunks; the stream ends with
+    /// `ChatError::StreamFailed` if the generation fails
+    pub fn subscribe(
+        &self,
+        backpressure: Backpressure,
+        capacity: usize,
+    ) -> impl Stream<Item = Result<String, ChatError>> + Send + 'static {
+        let (sender, mut receiver) = mpsc::channel(capacity.max(1));
+        let lagged = Arc::new(AtomicBool::new(false));
+        let mut shared = self.shared.lock().unwrap();
+        if !shared.text.is_empty() {
+            let _ = sender.try_send(Ok(shared.text.clone()));
+        }
+        if !shared.finished {
+            shared.sinks.push(Sink {
+                sender,
+                backpressure,
+                pending: String::new(),
+                lagged: lagged.clone(),
+            });
+        }
+        drop(shared);
+
+        stream! {
+            while let Some(item) = receiver.recv().await {
+                yield item;
+            }
+            if lagged.load(Ordering::Relaxed) {
+                yield Err(Report::new(ChatError::SubscriberLagged));
+            }
+        }
+    }
+
+    /// 驱动回答流并转发给所有订阅者，返回完整的回答；出错时原样返回错误
+    /// Drive the answer stream and forward it to every subscriber, returning the full answer; errors are returned
+    /// as they are
+    pub async fn run(
+        &self,
+        deltas: impl Stream<Item = Result<String, ChatError>>,
+    ) -> Result<String, ChatError> {
+        let mut deltas = pin!(deltas);
+        while let Some(delta) = deltas.next().await {
+            match delta {
+                Ok(delta) => self.forward(&delta).await,
+                Err(report) => {
+                    self.finish(Some(&report.current_context().to_string()));
+                    return Err(report);
+                }
+            }
+        }
+        Ok(self.finish(None))
+    }
+
+    /// 转发一段内容；转发期间加入的订阅者已从目前为止的内容中补齐这一段
+    /// Forward one piece of content; subscribers joining meanwhile already caught up with it from the content so far
+    async fn forward(&self, delta: &str) {
+        let mut sinks = {
+            let mut shared = self.shared.lock().unwrap();
+            shared.text.push_str(delta);
+            std::mem::take(&mut shared.sinks)
+        };
+        let mut connected = Vec::with_capacity(sinks.len());
+        for mut sink in sinks.drain(..) {
+            if sink.push(delta).await {
+                connected.push(sink);
+            }
+        }
+        let mut shared = self.shared.lock().unwrap();
+        connected.append(&mut shared.sinks);
+        shared.sinks = connected;
+    }
+
+    fn finish(&self, error: Option<&str>) -> String {
+        let (sinks, text) = {
+            let mut shared = self.shared.lock().unwrap();
+            shared.finished = true;
+            (std::mem::take(&mut shared.sinks), shared.text.clone())
+        };
+        for sink in sinks {
+            sink.close(error);
+        }
+        text
+    }
+}
@@ -0,0 +1,473 @@
+// 标准库
+use std::sync::Arc;
+use std::time::Duration;
+
+// 错误处理
+use error_stack::{Report, Result, ResultExt};
+
+// 项目内部模块
+use crate::chat::attachment::AttachmentLimits;
+use crate::chat::chat_base::{BaseChat, ChatError, ModelOverride};
+use crate::chat::chat_single::SingleChat;
+use crate::chat::chat_tool::ToolChoice;
+use crate::chat::event::{ChatEvent, EventHandler};
+use crate::chat::confidence::ConfidenceScorer;
+use crate::chat::context_provider::ContextProvider;
+use crate::chat::faithfulness::FaithfulnessChecker;
+use crate::chat::hot_reload::HotReload;
+use crate::chat::guardrail::Guardrails;
+use crate::chat::history::HistoryPolicy;
+use crate::chat::injection::InjectionScreen;
+use crate::chat::interceptor::RequestInterceptor;
+use crate::chat::message::Role;
+use crate::chat::moderation::Moderation;
+use crate::chat::language::LanguagePolicy;
+use crate::chat::output_cap::OutputCap;
+use crate::chat::pii::PiiScrubber;
+use crate::chat::postprocess::PostProcessors;
+use crate::chat::queue::QueuePriority;
+use crate::chat::params::ChatParams;
+use crate::chat::retriever::Retriever;
+use crate::chat::shadow::Shadow;
+use crate::chat::transaction::TransactionScope;
+use crate::chat::trigger::ToolTrigger;
+use crate::chat::middleware::{AnswerCache, AnswerMiddleware};
+use crate::chat::tenant::Tenant;
+use crate::chat::transform::StreamTransformers;
+use crate::config::{Config, ModelCapability};
+
+/// 单人对话构建器
+/// Single chat builder
+///
+/// ```ignore
+/// let mut chat = SingleChat::builder()
+///     .capability(ModelCapability::ToolUse)
+///     .system("你是一个助手")
+///     .tools(tools)
+///     .params(ChatParams::new().temperature(0.2))
+///     .build()?;
+/// ```
+#[derive(Clone, Default)]
+pub struct SingleChatBuilder {
+    api_name: Option<String>,
+    capability: Option<ModelCapability>,
+    system: String,
+    stream: bool,
+    tools: Vec<serde_json::Value>,
+    tool_choice: Option<ToolChoice>,
+    parallel_tool_calls: Option<bool>,
+    params: Option<ChatParams>,
+    retriever: Option<Arc<dyn Retriever>>,
+    triggers: Vec<ToolTrigger>,
+    middlewares: Vec<Arc<dyn AnswerMiddleware>>,
+    faithfulness: Option<FaithfulnessChecker>,
+    shadow: Option<Shadow>,
+    confidence: Option<ConfidenceScorer>,
+    hot_reload: Option<HotReload>,
+    transaction: Option<TransactionScope>,
+    max_agent_steps: Option<usize>,
+    context_providers: Vec<Arc<dyn ContextProvider>>,
+    scratchpad: bool,
+    attachment_limits: Option<AttachmentLimits>,
+    history_policy: Option<Arc<dyn HistoryPolicy>>,
+    moderation: Option<Moderation>,
+    pii: Option<PiiScrubber>,
+    guardrails: Option<Guardrails>,
+    post_processors: Option<PostProcessors>,
+    injection: Option<InjectionScreen>,
+    output_cap: Option<OutputCap>,
+    language: Option<LanguagePolicy>,
+    stream_transformers: Option<StreamTransformers>,
+    request_id_header: Option<Option<String>>,
+    stream_stall_timeout: Option<Duration>,
+    max_continuations: u32,
+    stream_fallbacks: Vec<ModelOverride>,
+    queue_key: Option<String>,
+    queue_priority: QueuePriority,
+    request_interceptors: Vec<Arc<dyn RequestInterceptor>>,
+    tenant: Option<Tenant>,
+    answer_cache: Option<AnswerCache>,
+    tags: Vec<String>,
+    handlers: Vec<EventHandler>,
+}
+
+impl SingleChatBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 按API名称（或别名）选择API，优先于 `capability`
+    /// Select the API by name (or alias), takes precedence over `capability`
+    pub fn api(mut self, api_name: &str) -> Self {
+        self.api_name = Some(api_name.to_string());
+        self
+    }
+
+    /// 按能力选择API；同时指定 `api` 时只用于能力并发额度和用量统计
+    /// Select the API by capability; when `api` is also set, only used for capability permits and usage totals
+    pub fn capability(mut self, capability: ModelCapability) -> Self {
+        self.capability = Some(capability);
+        self
+    }
+
+    /// 系统提示词，作为会话的第一条消息
+    /// System prompt, the first message of the session
+    pub fn system(mut self, prompt: &str) -> Self {
+        self.system = prompt.to_string();
+        self
+    }
+
+    pub fn stream(mut self, stream: bool) -> Self {
+        self.stream = stream;
+        self
+    }
+
+    pub fn tools(mut self, tools_schema: Vec<serde_json::Value>) -> Self {
+        self.tools = tools_schema;
+        self
+    }
+
+    /// 工具选择方式，默认由提供商决定
+    /// How tools are picked, left to the provider by default
+    pub fn tool_choice(mut self, choice: ToolChoice) -> Self {
+        self.tool_choice = Some(choice);
+        self
+    }
+
+    /// 是否允许一轮回答中并行调用多个工具，默认由提供商决定，见 `SingleChat::set_parallel_tool_calls`
+    /// Whether one answer may call several tools in parallel, left to the provider by default, see
+    /// `SingleChat::set_parallel_tool_calls`
+    pub fn parallel_tool_calls(mut self, parallel: bool) -> Self {
+        self.parallel_tool_calls = Some(parallel);
+        self
+    }
+
+    /// 生成参数，覆盖API配置中的默认参数
+    /// Generation parameters, overriding the defaults of the API configuration
+    pub fn params(mut self, params: ChatParams) -> Self {
+        self.params = Some(params);
+        self
+    }
+
+    pub fn retriever(mut self, retriever: impl Retriever + 'static) -> Self {
+        self.retriever = Some(Arc::new(retriever));
+        self
+    }
+
+    /// 由用户输入触发的工具调用，可多次调用添加多个，见 `SingleChat::add_tool_trigger`
+    /// Tool call triggered by the user input, call repeatedly to add several, see `SingleChat::add_tool_trigger`
+    pub fn tool_trigger(mut self, trigger: ToolTrigger) -> Self {
+        self.triggers.push(trigger);
+        self
+    }
+
+    /// 包裹 `get_answer` 的中间件，可多次调用添加多个，先添加的在外层，见 `SingleChat::add_middleware`
+    /// Middleware wrapping `get_answer`, call repeatedly to add several with the first added outermost, see
+    /// `SingleChat::add_middleware`
+    pub fn middleware(mut self, middleware: impl AnswerMiddleware + 'static) -> Self {
+        self.middlewares.push(Arc::new(middleware));
+        self
+    }
+
+    /// 回答缓存，见 `AnswerCache`
+    /// Answer cache, see `AnswerCache`
+    pub fn answer_cache(mut self, cache: AnswerCache) -> Self {
+        self.answer_cache = Some(cache);
+        self
+    }
+
+    /// 回答的忠实度检查，见 `SingleChat::get_verified_answer`
+    /// Faithfulness check of answers, see `SingleChat::get_verified_answer`
+    pub fn faithfulness(mut self, checker: FaithfulnessChecker) -> Self {
+        self.faithfulness = Some(checker);
+        self
+    }
+
+    /// 复制给候选模型的影子流量，见 `SingleChat::set_shadow`
+    /// Shadow traffic copied to candidate models, see `SingleChat::set_shadow`
+    pub fn shadow(mut self, shadow: Shadow) -> Self {
+        self.shadow = Some(shadow);
+        self
+    }
+
+    /// 回答的置信度评估，见 `SingleChat::get_scored_answer`
+    /// Confidence estimation of answers, see `SingleChat::get_scored_answer`
+    pub fn confidence(mut self, scorer: ConfidenceScorer) -> Self {
+        self.confidence = Some(scorer);
+        self
+    }
+
+    /// 开发模式的热重载，见 `SingleChat::set_hot_reload`
+    /// Hot reload for development, see `SingleChat::set_hot_reload`
+    pub fn hot_reload(mut self, reload: HotReload) -> Self {
+        self.hot_reload = Some(reload);
+        self
+    }
+
+    /// 智能体运行的事务作用域，见 `SingleChat::set_transaction`
+    /// Transaction scope of agent runs, see `SingleChat::set_transaction`
+    pub fn transaction(mut self, scope: TransactionScope) -> Self {
+        self.transaction = Some(scope);
+        self
+    }
+
+    /// 智能体运行最多的步数，见 `SingleChat::set_max_agent_steps`
+    /// Maximum steps of an agent run, see `SingleChat::set_max_agent_steps`
+    pub fn max_agent_steps(mut self, steps: usize) -> Self {
+        self.max_agent_steps = Some(steps);
+        self
+    }
+
+    /// 添加应用状态的提供者，见 `SingleChat::add_context_provider`
+    /// Add a provider of application state, see `SingleChat::add_context_provider`
+    pub fn context_provider(mut self, provider: Arc<dyn ContextProvider>) -> Self {
+        self.context_providers.push(provider);
+        self
+    }
+
+    /// 提供草稿本工具，见 `SingleChat::enable_scratchpad`
+    /// Offer the scratchpad tools, see `SingleChat::enable_scratchpad`
+    pub fn scratchpad(mut self) -> Self {
+        self.scratchpad = true;
+        self
+    }
+
+    /// 附件的大小与数量上限
+    /// Size and count limits of attachments
+    pub fn attachment_limits(mut self, limits: AttachmentLimits) -> Self {
+        self.attachment_limits = Some(limits);
+        self
+    }
+
+    /// 历史策略，默认发送全部历史
+    /// History policy, the whole history is sent by default
+    pub fn history_policy(mut self, policy: impl HistoryPolicy + 'static) -> Self {
+        self.history_policy = Some(Arc::new(policy));
+        self
+    }
+
+    /// 输入与回答的审核，默认不审核
+    /// Moderation of inputs and answers, nothing is screened by default
+    pub fn moderation(mut self, moderation: Moderation) -> Self {
+        self.moderation = Some(moderation);
+        self
+    }
+
+    /// 发送前的敏感信息清洗，默认不清洗
+    /// Pre-send scrubbing of sensitive data, nothing is scrubbed by default
+    pub fn pii_scrubber(mut self, scrubber: PiiScrubber) -> Self {
+        self.pii = Some(scrubber);
+        self
+    }
+
+    /// 回答的检查规则，默认不检查
+    /// Guardrails checking answers, nothing is checked by default
+    pub fn guardrails(mut self, guardrails: Guardrails) -> Self {
+        self.guardrails = Some(guardrails);
+        self
+    }
+
+    /// 回答的后处理，默认不处理
+    /// Post-processing of answers, nothing is processed by default
+    pub fn post_processors(mut self, post_processors: PostProcessors) -> Self {
+        self.post_processors = Some(post_processors);
+        self
+    }
+
+    /// 工具结果与检索片段的注入筛查，默认不筛查
+    /// Injection screening of tool results and retrieved chunks, nothing is screened by default
+    pub fn injection_screen(mut self, screen: InjectionScreen) -> Self {
+        self.injection = Some(screen);
+        self
+    }
+
+    /// 回答大小的硬上限，默认不限制
+    /// Hard cap on the answer size, unlimited by default
+    pub fn output_cap(mut self, cap: OutputCap) -> Self {
+        self.output_cap = Some(cap);
+        self
+    }
+
+    /// 回答语种的检查，默认不检查
+    /// Answer language check, nothing is checked by default
+    pub fn language_policy(mut self, policy: LanguagePolicy) -> Self {
+        self.language = Some(policy);
+        self
+    }
+
+    /// 流式增量的变换链，默认原样产出
+    /// Transformer chain of streamed deltas, yielded as is by default
+    pub fn stream_transformers(mut self, transformers: StreamTransformers) -> Self {
+        self.stream_transformers = Some(transformers);
+        self
+    }
+
+    /// 携带请求ID的请求头，默认为 `X-Request-Id`，为 None 时不发送
+    /// Header carrying the request ID, `X-Request-Id` by default, not sent if None
+    pub fn request_id_header(mut self, header: Option<&str>) -> Self {
+        self.request_id_header = Some(header.map(str::to_string));
+        self
+    }
+
+    /// 流式响应停滞的判定时间，超过该时间没有收到事件块时中止并续传，默认不检测
+    /// Stall timeout of streaming responses, a stream receiving no chunk for longer is aborted and resumed; not
+    /// detected by default
+    pub fn stream_stall_timeout(mut self, timeout: Duration) -> Self {
+        self.stream_stall_timeout = Some(timeout);
+        self
+    }
+
+    /// 回答因长度限制中断时的最大续写次数，默认不续写
+    /// Maximum number of continuations when an answer is cut off by the length limit, not continued by default
+    pub fn max_continuations(mut self, max: u32) -> Self {
+        self.max_continuations = max;
+        self
+    }
+
+    /// 添加流式请求的备用API或能力，原API失败且无法续传时按添加顺序换用；已收到部分回答时以其为前缀继续
+    /// Add a fallback API or capability for streaming requests, switched to in the order added when the current
+    /// API fails and cannot resume; an answer that was partly received continues with it as the prefix
+    pub fn stream_fallback(mut self, fallback: ModelOverride) -> Self {
+        self.stream_fallbacks.push(fallback);
+        self
+    }
+
+    /// 额度不足时的排队键，如租户标签，默认每个对话单独排队
+    /// Queue key used when permits run short, such as a tenant tag; each chat queues on its own by default
+    pub fn queue_key(mut self, key: &str) -> Self {
+        self.queue_key = Some(key.to_string());
+        self
+    }
+
+    /// 额度不足时的排队优先级，默认为 `Normal`
+    /// Queueing priority when permits run short, `Normal` by default
+    pub fn queue_priority(mut self, priority: QueuePriority) -> Self {
+        self.queue_priority = priority;
+        self
+    }
+
+    /// 添加对话自身的请求拦截器
+    /// Add a request interceptor of the chat
+    pub fn request_interceptor(mut self, interceptor: impl RequestInterceptor + 'static) -> Self {
+        self.request_interceptors.push(Arc::new(interceptor));
+        self
+    }
+
+    /// 对话所属的租户，工具定义按租户允许的工具筛选
+    /// Tenant owning the chat, the tool definitions are filtered to the tools it allows
+    pub fn tenant(mut self, tenant: Tenant) -> Self {
+        self.tenant = Some(tenant);
+        self
+    }
+
+    pub fn tag(mut self, tag: &str) -> Self {
+        self.tags.push(tag.to_string());
+        self
+    }
+
+    pub fn on_event(mut self, handler: impl Fn(&ChatEvent) + Send + Sync + 'static) -> Self {
+        self.handlers.push(Arc::new(handler));
+        self
+    }
+
+    /// 解析API并创建对话，未指定API或能力、API不存在或工具定义无效时返回错误
+    /// Resolve the API and create the chat, failing if neither API nor capability is set, the API does not exist
+    /// or the tool definitions are invalid
+    pub fn build(self) -> Result<SingleChat, ChatError> {
+        let api_info = match (&self.api_name, &self.capability) {
+            (Some(api_name), _) => Config::get_api_info_with_name(api_name.clone())
+                .change_context(ChatError::InvalidConfig)
+                .attach_printable_lazy(|| format!("Unknown API: {}", api_name))?,
+            (None, Some(capability)) => Config::get_api_info_with_capability(capability.clone())
+                .change_context(ChatError::InvalidConfig)
+                .attach_printable_lazy(|| format!("No API bound to capability: {:?}", capability))?,
+            (None, None) => {
+                return Err(Report::new(ChatError::InvalidConfig)
+                    .attach_printable("Either an API name or a capability is required"));
+            }
+        };
+
+        let mut base = BaseChat::from_api_info(api_info, self.capability, "", self.stream);
+        if let Some(params) = &self.params {
+            base.set_params(params);
+        }
+        base.tags = self.tags;
+        if let Some(policy) = self.history_policy {
+            base.history_policy = policy;
+        }
+        base.moderation = self.moderation;
+        base.pii = self.pii;
+        base.guardrails = self.guardrails;
+        base.post_processors = self.post_processors;
+        base.injection = self.injection;
+        base.output_cap = self.output_cap;
+        base.language = self.language;
+        base.stream_transformers = self.stream_transformers;
+        if let Some(header) = self.request_id_header {
+            base.request_id_header = header;
+        }
+        base.stream_stall_timeout = self.stream_stall_timeout;
+        base.max_continuations = self.max_continuations;
+        base.stream_fallbacks = self.stream_fallbacks;
+        base.queue_key = self.queue_key;
+        base.queue_priority = self.queue_priority;
+        base.request_interceptors = self.request_interceptors;
+        if let Some(tenant) = self.tenant {
+            base.set_tenant(tenant);
+        }
+        base.answer_cache = self.answer_cache;
+        for handler in self.handlers {
+            base.events.add(handler);
+        }
+
+        if !self.system.is_empty() {
+            base.add_message(Role::System, &self.system)?;
+        }
+
+        let mut chat = SingleChat::from_base(base);
+        if !self.tools.is_empty() {
+            chat.set_tools(self.tools)?;
+        }
+        if self.scratchpad {
+            chat.enable_scratchpad()?;
+        }
+        if let Some(choice) = self.tool_choice {
+            chat.set_tool_choice(choice);
+        }
+        if let Some(parallel) = self.parallel_tool_calls {
+            chat.set_parallel_tool_calls(parallel);
+        }
+        if let Some(retriever) = self.retriever {
+            chat.set_retriever(retriever);
+        }
+        for trigger in self.triggers {
+            chat.add_tool_trigger(trigger);
+        }
+        chat.middlewares = self.middlewares;
+        if let Some(checker) = self.faithfulness {
+            chat.set_faithfulness(checker);
+        }
+        if let Some(shadow) = self.shadow {
+            chat.set_shadow(shadow);
+        }
+        if let Some(scorer) = self.confidence {
+            chat.set_confidence(scorer);
+        }
+        if let Some(reload) = self.hot_reload {
+            chat.set_hot_reload(reload);
+        }
+        if let Some(scope) = self.transaction {
+            chat.set_transaction(scope);
+        }
+        if let Some(steps) = self.max_agent_steps {
+            chat.set_max_agent_steps(steps);
+        }
+        for provider in self.context_providers {
+            chat.add_context_provider(provider);
+        }
+        if let Some(limits) = self.attachment_limits {
+            chat.set_attachment_limits(limits);
+        }
+        Ok(chat)
+    }
+}
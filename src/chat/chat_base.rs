@@ -1,17 +1,75 @@
+use std::borrow::Cow;
+use std::fmt::Debug;
+use std::pin::pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
 use bytes::Bytes;
 use serde_json::json;
 
 use error_stack::{Report, Result, ResultExt};
 use thiserror::Error;
 
-use futures::{Stream, TryStreamExt};
+use async_stream::stream;
+use futures::{Stream, StreamExt, TryStreamExt};
 use tokio::sync::OwnedSemaphorePermit;
+use reqwest::header::{AUTHORIZATION, CONTENT_TYPE, HeaderMap, HeaderName, HeaderValue};
 use reqwest::{Client, Error, Response};
-use tracing::info;
-use crate::chat::message::{Role, Session};
+use tracing::{Instrument, Span, field, info_span, warn};
+use uuid::Uuid;
+use crate::chat::event::{ChatEvent, EventHandlers, EventSource};
+use crate::chat::guardrail::{GuardrailVerdict, Guardrails};
+use crate::chat::history::{HistoryPolicy, KeepAll};
+use crate::chat::injection::InjectionScreen;
+use crate::chat::interceptor::{OutgoingRequest, RequestInterceptor, intercept};
+use crate::chat::language::{LanguagePolicy, language_instruction};
+use crate::chat::middleware::AnswerCache;
+use crate::chat::content::Content;
+use crate::chat::context_provider::{ContextProvider, context_variables, render_system_prompt, variable_texts};
+use crate::chat::message::{MessageMetadata, Probability, ProviderSwitch, Role, Session};
+use crate::chat::moderation::{Moderation, ModerationStage, Screened};
+use crate::chat::output_cap::{CapCounter, OutputCap, Truncated};
+use crate::chat::pii::PiiScrubber;
+use crate::chat::postprocess::PostProcessors;
+use crate::chat::queue::{FairQueue, QueuePriority};
+use crate::chat::refusal::RefusalReason;
+use crate::chat::preview::RequestPreview;
+use crate::chat::params::ChatParams;
+use crate::chat::stream::{StreamChunk, StreamStalled, chunk_stream, stalling_chunk_stream};
+use crate::chat::summary::ChatMetadata;
+use crate::chat::tenant::Tenant;
+use crate::chat::transform::{RestorePii, StreamTransformer, StreamTransformers, TransformerChain};
+
+use crate::config::balance::{DEFAULT_CREDENTIAL_COOLDOWN, cool_down, get_health};
+use crate::config::metadata::ModelMetadata;
+use crate::config::prompts::PromptKey;
+use crate::config::{ApiInfo, CFG, Config, ModelCapability};
+use crate::schema::gbnf::json_schema_to_gbnf;
+use crate::shutdown::{InFlight, track_generation};
+use crate::error::{ErrorBody, ProviderInfo, ReportExt, RhineError, sanitize_url};
+use crate::telemetry::meter::record_permit_wait;
+use crate::telemetry::{LlmCall, TokenUsage, export_llm_call, provider_system};
+use crate::utils::common::redact::{REDACTED, redact};
+use crate::utils::common::tokens::tokenizer_for;
+
+
+/// 流式连接中断后默认的续传次数
+/// Default number of resumes after a streaming connection drops
+pub const DEFAULT_STREAM_RESUME_ATTEMPTS: u32 = 2;
 
-use crate::config::{Config, ModelCapability, THREAD_POOL};
+/// 回答因长度限制中断时，续写请求中附加的指令
+/// Instruction appended to the continuation request when an answer was cut off by the length limit
+pub const CONTINUATION_PROMPT: &str =
+    "回答因长度限制中断，请从中断处原样继续，不要重复已写的内容 / Your answer was cut off by the length limit, \
+     continue exactly where you left off without repeating anything";
 
+/// 拼接续写内容时识别重叠的最短与最长字符数
+/// Shortest and longest overlap, in characters, recognized when stitching a continuation
+const CONTINUATION_OVERLAP: (usize, usize) = (4, 500);
+
+/// 默认携带请求ID的请求头
+/// Default header carrying the request ID
+pub const DEFAULT_REQUEST_ID_HEADER: &str = "X-Request-Id";
 
 #[derive(Debug, Error)]
 pub enum ChatError {
@@ -48,16 +106,204 @@ pub enum ChatError {
     #[error("No character selected")]
     NoCharacterSelected,
 
+    #[error("Failed to acquire request permit")]
+    PermitError,
+
+    #[error("Invalid chat configuration")]
+    InvalidConfig,
+
+    #[error("Operation cancelled")]
+    Cancelled,
+
+    #[error("Content flagged by moderation at {0}")]
+    Moderated(ModerationStage),
+
+    #[error("Answer rejected by guardrail: {0}")]
+    GuardrailBlocked(String),
+
+    #[error("Failed to read document: {0}")]
+    DocumentError(String),
+
+    #[error("Invalid image: {0}")]
+    ImageError(String),
+
+    #[error("Unknown checkpoint: {0}")]
+    UnknownCheckpoint(String),
+
+    #[error("Attachment error: {0}")]
+    AttachmentError(String),
+
+    /// 广播的流式回答生成失败，订阅者收到原错误的描述
+    /// The broadcast streamed answer failed, subscribers receive the description of the original error
+    #[error("Streamed answer failed: {0}")]
+    StreamFailed(String),
+
+    /// 订阅者跟不上广播而被断开
+    /// The subscriber could not keep up with the broadcast and was disconnected
+    #[error("Subscriber lagged behind the stream and was disconnected")]
+    SubscriberLagged,
+
+    /// 租户的预算已用完
+    /// The tenant has used up its budget
+    #[error("Budget of tenant {0} exceeded")]
+    TenantBudgetExceeded(String),
+
+    /// 租户的请求频率超出上限
+    /// The tenant exceeded its request rate limit
+    #[error("Tenant {0} is rate limited")]
+    TenantRateLimited(String),
+
+    /// 访问了租户命名空间之外的数据
+    /// Data outside the tenant's namespace was accessed
+    #[error("Access outside the namespace of tenant {0}")]
+    TenantForbidden(String),
+
+    /// 从工具来源（MCP 服务、OpenAPI 接口等）列出工具失败
+    /// Listing the tools of a tool source (MCP server, OpenAPI service and the like) failed
+    #[error("Tool source {0} failed")]
+    ToolSourceError(String),
+
+    /// 人设文件无法读取或解析
+    /// A persona file could not be read or parsed
+    #[error("Invalid persona: {0}")]
+    PersonaError(String),
+
+    /// 智能体定义文件无法读取、解析，或定义自相矛盾
+    /// An agent definition file could not be read or parsed, or the definition contradicts itself
+    #[error("Invalid agent definition: {0}")]
+    AgentSpecError(String),
+
+    /// 进程正在关闭，不再接受新的提问
+    /// The process is shutting down and accepts no new questions
+    #[error("Shutting down")]
+    ShuttingDown,
+
+    /// 回答的后处理失败，如要求只输出 JSON 而回答中没有 JSON
+    /// Post-processing of the answer failed, such as JSON only being required while the answer has none
+    #[error("Post-processing failed: {0}")]
+    PostProcessError(String),
+
+    /// 热重载的文件无法读取或解析
+    /// A hot-reloaded file could not be read or parsed
+    #[error("Failed to reload {0}")]
+    ReloadError(String),
+
+    /// 应用状态无法获取，或无法渲染进系统提示词模板
+    /// Application state could not be fetched or rendered into the system prompt template
+    #[error("Failed to render the prompt context: {0}")]
+    ContextError(String),
+
     #[error("Unknown error")]
     UnknownError,
 }
 
-#[derive(Debug, Clone)]
+/// 单次调用使用的模型，覆盖对话默认的API；对话历史仍然共用
+/// Model used by a single call, overriding the chat's default API; the history stays shared
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ModelOverride {
+    /// 按API名称选择
+    /// Selected by API name
+    Api(String),
+
+    /// 按模型能力选择，凭据失效时在该能力内切换
+    /// Selected by model capability, failing over within the capability when a credential is rejected
+    Capability(ModelCapability),
+}
+
+/// 对话使用的API连接，临时换用其他模型时保存以便恢复
+/// The API connection a chat uses, kept to be restored after temporarily switching models
+#[derive(Clone, Debug)]
+pub struct ApiBinding {
+    pub api_name: String,
+    pub model: String,
+    pub base_url: String,
+    pub source: String,
+    pub api_key: String,
+    pub client: Client,
+    pub capability: Option<ModelCapability>,
+}
+
+impl ApiBinding {
+    fn from_api_info(api_info: ApiInfo, capability: Option<ModelCapability>) -> Self {
+        Self {
+            api_name: api_info.name,
+            model: api_info.model,
+            base_url: api_info.base_url,
+            source: api_info.source,
+            api_key: api_info.api_key,
+            client: api_info.client,
+            capability,
+        }
+    }
+
+    /// 解析模型覆盖对应的API连接
+    /// Resolve the API connection of a model override
+    pub fn resolve(model: &ModelOverride) -> Result<Self, ChatError> {
+        match model {
+            ModelOverride::Api(api_name) => Config::get_api_info_with_name(api_name.clone())
+                .change_context(ChatError::InvalidConfig)
+                .attach_printable_lazy(|| format!("Unknown API: {}", api_name))
+                .map(|api_info| Self::from_api_info(api_info, None)),
+            ModelOverride::Capability(capability) => Config::get_api_info_with_capability(capability.clone())
+                .change_context(ChatError::InvalidConfig)
+                .attach_printable_lazy(|| format!("No API bound to capability: {:?}", capability))
+                .map(|api_info| Self::from_api_info(api_info, Some(capability.clone()))),
+        }
+    }
+}
+
+/// 一次请求中故障转移的进度
+/// Progress of failover during one request
+#[derive(Default)]
+struct FailOver {
+    /// 本次请求中凭据被拒而冷却的API
+    /// APIs cooled down during this request because their credentials were rejected
+    rejected: Vec<String>,
+
+    /// 下一个要尝试的备用API下标
+    /// Index of the next fallback API to try
+    next_fallback: usize,
+
+    /// 已发生的切换
+    /// Switches made so far
+    switches: Vec<ProviderSwitch>,
+}
+
+/// 请求并发额度，持有期间占用API来源的额度以及（若设置了上限）能力的额度
+/// Request permit, holding the API source permit and, if a limit is set, the capability permit while alive
+#[derive(Debug)]
+pub struct RequestPermit {
+    _source: OwnedSemaphorePermit,
+
+    _capability: Option<OwnedSemaphorePermit>,
+
+    _in_flight: InFlight,
+}
+
+impl From<OwnedSemaphorePermit> for RequestPermit {
+    fn from(permit: OwnedSemaphorePermit) -> Self {
+        Self {
+            _source: permit,
+            _capability: None,
+            _in_flight: track_generation(),
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct BaseChat {
+    /// 配置中的API名称
+    /// API name in the configuration
+    pub api_name: String,
+
     pub model: String,
 
     pub base_url: String,
 
+    /// 所属API来源的名称
+    /// Name of the API source in use
+    pub source: String,
+
     pub api_key: String,
 
     pub client: Client,
@@ -69,41 +315,328 @@ pub struct BaseChat {
     pub usage: i32,
 
     pub need_stream: bool,
+
+    pub params: ChatParams,
+
+    pub capability: Option<ModelCapability>,
+
+    pub session_id: String,
+
+    pub events: EventHandlers,
+
+    pub tags: Vec<String>,
+
+    pub last_call: Option<MessageMetadata>,
+
+    /// 流式连接在回答中途中断时的最大续传次数，为 0 时不续传
+    /// Maximum number of resumes when a streaming connection drops mid-answer, 0 disables resuming
+    pub stream_resume_attempts: u32,
+
+    /// 回答因 `max_tokens` 中断（`finish_reason` 为 `length`）时的最大续写次数，为 0 时不续写
+    /// Maximum number of continuations when an answer is cut off by `max_tokens` (`finish_reason` is `length`), 0
+    /// disables continuing
+    pub max_continuations: u32,
+
+    /// 流式响应停滞的判定时间，超过该时间没有收到事件块时中止并续传；为 None 时不检测
+    /// Stall timeout of streaming responses, a stream receiving no chunk for longer is aborted and resumed; not
+    /// detected if None
+    pub stream_stall_timeout: Option<Duration>,
+
+    /// 凭据失效或额度用尽时失败API的冷却时间；按能力创建的对话会换用同一能力下的其他API重试，为 None 时不转移
+    /// Cool-down of the failing API when a credential fails or runs out of quota; chats created by capability retry
+    /// with another API of the same capability, no failover if None
+    pub credential_cooldown: Option<Duration>,
+
+    /// 流式请求失败且无法在原API上续传时依次换用的备用API或能力；换用只在本次请求内有效，下一次请求仍先用原API
+    /// Fallback APIs or capabilities switched to in order when a streaming request fails and cannot be resumed on
+    /// the current API; the switch only lasts for that request, the next request starts on the original API
+    pub stream_fallbacks: Vec<ModelOverride>,
+
+    /// 携带请求ID的请求头，提供商日志可据此与追踪对应；为 None 时不发送
+    /// Header carrying the request ID, so provider logs can be matched with traces; not sent if None
+    pub request_id_header: Option<String>,
+
+    /// 调用方为下一次请求指定的ID，为 None 时自动生成
+    /// ID the caller set for the next request, generated if None
+    pub next_request_id: Option<String>,
+
+    /// 历史策略，决定哪些消息随请求发送
+    /// History policy, decides which messages are sent with a request
+    pub history_policy: Arc<dyn HistoryPolicy>,
+
+    /// 输入与回答的审核设置，为 None 时不审核
+    /// Moderation of inputs and answers, nothing is screened if None
+    pub moderation: Option<Moderation>,
+
+    /// 发送前的敏感信息清洗，为 None 时原样发送
+    /// Pre-send scrubbing of sensitive data, text is sent as is if None
+    pub pii: Option<PiiScrubber>,
+
+    /// 应用状态的提供者，变量在每次请求前渲染进系统提示词模板，见 `ContextProvider`
+    /// Providers of application state, their variables rendered into the system prompt template before every
+    /// request, see `ContextProvider`
+    pub(crate) context_providers: Vec<Arc<dyn ContextProvider>>,
+
+    /// 回答返回与写入会话前的检查规则，为 None 时不检查
+    /// Rules checking answers before they are returned and stored, nothing is checked if None
+    pub guardrails: Option<Guardrails>,
+
+    /// 回答返回与写入会话前的后处理，在检查规则之前执行，为 None 时不处理
+    /// Post-processing of answers before they are returned and stored, run before the guardrails, nothing is
+    /// processed if None
+    pub post_processors: Option<PostProcessors>,
+
+    /// 工具结果与检索片段的注入筛查，为 None 时不筛查
+    /// Injection screening of tool results and retrieved chunks, nothing is screened if None
+    pub injection: Option<InjectionScreen>,
+
+    /// 回答大小的硬上限，为 None 时不限制
+    /// Hard cap on the answer size, unlimited if None
+    pub output_cap: Option<OutputCap>,
+
+    /// 回答语种的检查，为 None 时不检查
+    /// Answer language check, nothing is checked if None
+    pub language: Option<LanguagePolicy>,
+
+    /// 流式增量的变换链，为 None 时原样产出（设置敏感信息清洗时仍会还原占位符）
+    /// Transformer chain of streamed deltas, yielded as is if None (placeholders are still restored when PII
+    /// scrubbing is set)
+    pub stream_transformers: Option<StreamTransformers>,
+
+    /// 额度不足时的排队键，同一键下的请求按到达顺序排队，不同键之间轮流获得额度；为 None 时按对话排队
+    /// Queue key used when permits run short, requests under one key queue in arrival order and keys take turns;
+    /// each chat queues on its own if None
+    pub queue_key: Option<String>,
+
+    /// 额度不足时的排队优先级
+    /// Queueing priority when permits run short
+    pub queue_priority: QueuePriority,
+
+    /// 对话自身的请求拦截器，在全局拦截器之后执行
+    /// The chat's own request interceptors, run after the global ones
+    pub request_interceptors: Vec<Arc<dyn RequestInterceptor>>,
+
+    /// 生成的标题与摘要
+    /// Generated title and summary
+    pub metadata: ChatMetadata,
+
+    /// 对话所属的租户，为 None 时不做租户隔离
+    /// Tenant owning the chat, no tenant isolation if None
+    pub tenant: Option<Tenant>,
+
+    /// 非流式请求的响应缓存，见 `AnswerCache`
+    /// Response cache of non-streaming requests, see `AnswerCache`
+    pub answer_cache: Option<AnswerCache>,
+
+    /// 为 Some 时调用记录先暂存于此，不计入租户与遥测，由暂存方决定何时记录（如预生成被采用时）
+    /// When Some, call records are held here instead of being charged to the tenant and exported, the holder decides
+    /// when to record them (such as once a speculation is used)
+    pub(crate) deferred_calls: Option<Arc<Mutex<Vec<LlmCall>>>>,
+
+    /// 本次请求因故障转移换用其他API前的连接，请求结束时恢复
+    /// Connection from before failover switched APIs during this request, restored once the request ends
+    request_binding: Option<ApiBinding>,
+}
+
+// API密钥不出现在调试输出中
+// The API key never shows up in debug output
+impl Debug for BaseChat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BaseChat")
+            .field("api_name", &self.api_name)
+            .field("model", &self.model)
+            .field("base_url", &self.base_url)
+            .field("source", &self.source)
+            .field("api_key", &REDACTED)
+            .field("client", &self.client)
+            .field("character_prompt", &self.character_prompt)
+            .field("session", &self.session)
+            .field("usage", &self.usage)
+            .field("need_stream", &self.need_stream)
+            .field("params", &self.params)
+            .field("capability", &self.capability)
+            .field("session_id", &self.session_id)
+            .field("events", &self.events)
+            .field("tags", &self.tags)
+            .field("last_call", &self.last_call)
+            .field("stream_resume_attempts", &self.stream_resume_attempts)
+            .field("max_continuations", &self.max_continuations)
+            .field("stream_stall_timeout", &self.stream_stall_timeout)
+            .field("credential_cooldown", &self.credential_cooldown)
+            .field("stream_fallbacks", &self.stream_fallbacks)
+            .field("request_id_header", &self.request_id_header)
+            .field("next_request_id", &self.next_request_id)
+            .field("history_policy", &self.history_policy)
+            .field("moderation", &self.moderation)
+            .field("pii", &self.pii)
+            .field("context_providers", &self.context_providers.len())
+            .field("guardrails", &self.guardrails)
+            .field("injection", &self.injection)
+            .field("output_cap", &self.output_cap)
+            .field("language", &self.language)
+            .field("stream_transformers", &self.stream_transformers)
+            .field("queue_key", &self.queue_key)
+            .field("queue_priority", &self.queue_priority)
+            .field("request_interceptors", &self.request_interceptors.len())
+            .field("metadata", &self.metadata)
+            .field("tenant", &self.tenant)
+            .field("answer_cache", &self.answer_cache)
+            .field("deferred_calls", &self.deferred_calls.is_some())
+            .field("request_binding", &self.request_binding)
+            .finish()
+    }
 }
 
 impl BaseChat {
+    /// 按API名称创建对话
+    /// Create a chat by API name
+    ///
+    /// # Panics
+    /// API不存在时 panic，需要处理错误时使用 `try_new_with_api_name`
+    /// Panics if the API does not exist, use `try_new_with_api_name` to handle the error
     pub fn new_with_api_name(api_name: &str, character_prompt: &str, need_stream: bool) -> Self {
-        let api_info = Config::get_api_info_with_name(api_name.to_string()).unwrap();
-
-        Self {
-            model: api_info.model,
-            base_url: api_info.base_url,
-            api_key: api_info.api_key,
-            client: api_info.client,
-            character_prompt: character_prompt.to_string(),
-            session: Session::new(),
-            usage: 0,
-            need_stream,
-        }
+        Self::try_new_with_api_name(api_name, character_prompt, need_stream).unwrap()
     }
 
+    /// 按模型能力创建对话
+    /// Create a chat by model capability
+    ///
+    /// # Panics
+    /// 没有API绑定该能力时 panic，需要处理错误时使用 `try_new_with_model_capability`
+    /// Panics if no API is bound to the capability, use `try_new_with_model_capability` to handle the error
     pub fn new_with_model_capability(
         model_capability: ModelCapability,
         character_prompt: &str,
         need_stream: bool,
     ) -> Self {
-        let api_info = Config::get_api_info_with_capability(model_capability.clone()).unwrap();
+        Self::try_new_with_model_capability(model_capability, character_prompt, need_stream).unwrap()
+    }
 
+    /// 按API名称创建对话，API不存在时返回错误
+    /// Create a chat by API name, failing if the API does not exist
+    pub fn try_new_with_api_name(api_name: &str, character_prompt: &str, need_stream: bool) -> Result<Self, ChatError> {
+        let api_info = Config::get_api_info_with_name(api_name.to_string())
+            .change_context(ChatError::InvalidConfig)
+            .attach_printable_lazy(|| format!("Unknown API: {}", api_name))?;
+        Ok(Self::from_api_info(api_info, None, character_prompt, need_stream))
+    }
+
+    /// 按模型能力创建对话，没有API绑定该能力时返回错误
+    /// Create a chat by model capability, failing if no API is bound to the capability
+    pub fn try_new_with_model_capability(
+        model_capability: ModelCapability,
+        character_prompt: &str,
+        need_stream: bool,
+    ) -> Result<Self, ChatError> {
+        let api_info = Config::get_api_info_with_capability(model_capability.clone())
+            .change_context(ChatError::InvalidConfig)
+            .attach_printable_lazy(|| format!("No API bound to capability: {:?}", model_capability))?;
+        Ok(Self::from_api_info(api_info, Some(model_capability), character_prompt, need_stream))
+    }
+
+    /// 基于已解析的API信息创建对话
+    /// Create a chat from resolved API information
+    ///
+    /// # 参数 (Parameters)
+    /// * `api_info` - API信息 / API information
+    /// * `capability` - 选择该API时使用的能力，用于能力并发额度和用量统计
+    ///   - Capability the API was selected for, used for capability permits and usage totals
+    pub fn from_api_info(
+        api_info: ApiInfo,
+        capability: Option<ModelCapability>,
+        character_prompt: &str,
+        need_stream: bool,
+    ) -> Self {
+        let session_id = Uuid::new_v4().to_string();
         Self {
+            api_name: api_info.name,
             model: api_info.model,
             base_url: api_info.base_url,
+            source: api_info.source,
             api_key: api_info.api_key,
             client: api_info.client,
             character_prompt: character_prompt.to_string(),
             session: Session::new(),
             usage: 0,
             need_stream,
+            params: api_info.params,
+            capability,
+            events: EventHandlers::new(EventSource::Chat {
+                session_id: session_id.clone(),
+            }),
+            session_id,
+            tags: Vec::new(),
+            last_call: None,
+            stream_resume_attempts: DEFAULT_STREAM_RESUME_ATTEMPTS,
+            max_continuations: 0,
+            stream_stall_timeout: None,
+            credential_cooldown: Some(DEFAULT_CREDENTIAL_COOLDOWN),
+            stream_fallbacks: Vec::new(),
+            request_id_header: Some(DEFAULT_REQUEST_ID_HEADER.to_string()),
+            next_request_id: None,
+            history_policy: Arc::new(KeepAll),
+            moderation: None,
+            pii: None,
+            context_providers: Vec::new(),
+            guardrails: None,
+            post_processors: None,
+            injection: None,
+            output_cap: None,
+            language: None,
+            stream_transformers: None,
+            queue_key: None,
+            queue_priority: QueuePriority::default(),
+            request_interceptors: Vec::new(),
+            metadata: ChatMetadata::default(),
+            tenant: None,
+            answer_cache: None,
+            deferred_calls: None,
+            request_binding: None,
+        }
+    }
+
+    /// 当前使用的API连接
+    /// The API connection currently in use
+    pub fn api_binding(&self) -> ApiBinding {
+        ApiBinding {
+            api_name: self.api_name.clone(),
+            model: self.model.clone(),
+            base_url: self.base_url.clone(),
+            source: self.source.clone(),
+            api_key: self.api_key.clone(),
+            client: self.client.clone(),
+            capability: self.capability.clone(),
+        }
+    }
+
+    /// 换用另一个API连接，生成参数与对话历史不变，返回原来的连接
+    /// Switch to another API connection, keeping the generation parameters and history, returning the previous one
+    pub fn bind_api(&mut self, binding: ApiBinding) -> ApiBinding {
+        let previous = self.api_binding();
+        self.api_name = binding.api_name;
+        self.model = binding.model;
+        self.base_url = binding.base_url;
+        self.source = binding.source;
+        self.api_key = binding.api_key;
+        self.client = binding.client;
+        self.capability = binding.capability;
+        previous
+    }
+
+    pub fn model_metadata(&self) -> Option<ModelMetadata> {
+        Config::get_model_metadata(&self.model)
+    }
+
+    /// 模型支持 GBNF 语法时将模式转换为语法，不支持或模式无法转换时返回 None
+    /// Convert the schema into a grammar if the model supports GBNF, None if it does not or the schema cannot be
+    /// converted
+    pub fn schema_grammar(&self, schema: &serde_json::Value) -> Option<String> {
+        if !self.model_metadata().is_some_and(|metadata| metadata.supports_grammar) {
+            return None;
         }
+        json_schema_to_gbnf(schema)
+            .inspect_err(|report| warn!("Schema not convertible to a grammar, falling back: {:?}", report))
+            .ok()
     }
 
     pub fn add_message_with_parent_path(
@@ -113,145 +646,788 @@ impl BaseChat {
         content: &str,
     ) -> Result<(), ChatError> {
         self.session
-            .add_with_parent_path(path, role, content.to_string())
+            .add_with_parent_path(path, role, content)
             .change_context(ChatError::SessionError)
     }
 
     pub fn add_message(&mut self, role: Role, content: &str) -> Result<(), ChatError> {
         self.session
-            .add_with_default_path(role, content.to_string())
+            .add_with_default_path(role, content)
+            .change_context(ChatError::SessionError)
+    }
+
+    /// 添加非纯文本内容的消息，如图片或工具调用
+    /// Add a message whose content is not plain text, such as an image or a tool call
+    pub fn add_content(&mut self, role: Role, content: Content) -> Result<(), ChatError> {
+        self.session
+            .add_with_default_path(role, content)
             .change_context(ChatError::SessionError)
     }
 
+    /// 添加回答消息，并附上最近一次LLM调用的性能数据
+    /// Add an answer message, attaching the performance data of the latest LLM call
+    pub fn add_answer(&mut self, role: Role, content: &str) -> Result<(), ChatError> {
+        self.add_message(role, content)?;
+        if let Some(metadata) = self.last_call.take() {
+            self.session
+                .last_message_mut()
+                .change_context(ChatError::SessionError)?
+                .metadata = Some(metadata);
+        }
+        Ok(())
+    }
+
     pub fn build_request_body(
-        &mut self,
+        &self,
         end_path: &[usize],
         current_speaker: &Role,
     ) -> Result<serde_json::Value, ChatError> {
         let messages_json = self
             .session
-            .assemble_context(end_path, current_speaker)
+            .assemble_context_for(end_path, current_speaker, provider_system(&self.base_url))
             .change_context(ChatError::SessionError)?;
+        let messages_json = self.history_policy.select(messages_json);
 
-        Ok(json!({
+        let mut request_body = json!({
             "model": self.model,
             "messages": messages_json,
             "stream": self.need_stream,
-        }))
+        });
+        self.params.apply_to(&mut request_body);
+
+        Ok(request_body)
+    }
+
+    pub fn set_params(&mut self, overrides: &ChatParams) {
+        self.params = self.params.merge(overrides);
+    }
+
+    pub fn set_history_policy(&mut self, policy: impl HistoryPolicy + 'static) {
+        self.history_policy = Arc::new(policy);
+    }
+
+    pub fn set_moderation(&mut self, moderation: Moderation) {
+        self.moderation = Some(moderation);
+    }
+
+    pub fn set_pii_scrubber(&mut self, scrubber: PiiScrubber) {
+        self.pii = Some(scrubber);
+    }
+
+    pub fn set_guardrails(&mut self, guardrails: Guardrails) {
+        self.guardrails = Some(guardrails);
+    }
+
+    pub fn set_post_processors(&mut self, post_processors: PostProcessors) {
+        self.post_processors = Some(post_processors);
+    }
+
+    /// 按后处理链改写回答，没有设置时原样返回
+    /// Rewrite the answer with the post-processors, returned as is if none are set
+    fn post_process(&self, content: String) -> Result<String, ChatError> {
+        match &self.post_processors {
+            Some(post_processors) => post_processors.process(&content),
+            None => Ok(content),
+        }
+    }
+
+    pub fn set_injection_screen(&mut self, screen: InjectionScreen) {
+        self.injection = Some(screen);
+    }
+
+    pub fn set_output_cap(&mut self, cap: OutputCap) {
+        self.output_cap = Some(cap);
+    }
+
+    pub fn set_language_policy(&mut self, policy: LanguagePolicy) {
+        self.language = Some(policy);
+    }
+
+    pub fn set_stream_transformers(&mut self, transformers: StreamTransformers) {
+        self.stream_transformers = Some(transformers);
+    }
+
+    pub fn set_stream_stall_timeout(&mut self, timeout: Duration) {
+        self.stream_stall_timeout = Some(timeout);
+    }
+
+    pub fn set_max_continuations(&mut self, max: u32) {
+        self.max_continuations = max;
+    }
+
+    pub fn set_stream_fallbacks(&mut self, fallbacks: Vec<ModelOverride>) {
+        self.stream_fallbacks = fallbacks;
+    }
+
+    pub fn set_queue_key(&mut self, key: &str) {
+        self.queue_key = Some(key.to_string());
+    }
+
+    pub fn set_queue_priority(&mut self, priority: QueuePriority) {
+        self.queue_priority = priority;
+    }
+
+    /// 设置对话所属的租户：用量记录带上租户标签，未设置排队键时按租户排队
+    /// Set the tenant owning the chat: usage records get the tenant tag, and requests queue per tenant unless a
+    /// queue key is set
+    pub fn set_tenant(&mut self, tenant: Tenant) {
+        let tag = tenant.tag();
+        if !self.tags.contains(&tag) {
+            self.tags.push(tag.clone());
+        }
+        self.queue_key.get_or_insert(tag);
+        self.tenant = Some(tenant);
+    }
+
+    pub fn add_request_interceptor(&mut self, interceptor: impl RequestInterceptor + 'static) {
+        self.request_interceptors.push(Arc::new(interceptor));
+    }
+
+    pub fn set_request_id_header(&mut self, header: Option<&str>) {
+        self.request_id_header = header.map(str::to_string);
+    }
+
+    /// 指定下一次请求的ID，如上游服务传入的追踪ID；只用于一次请求
+    /// Set the ID of the next request, such as a trace ID passed in by an upstream service; used for one request only
+    pub fn set_request_id(&mut self, request_id: &str) {
+        self.next_request_id = Some(request_id.to_string());
+    }
+
+    /// 一个流的变换链：设置敏感信息清洗时先还原占位符，再依次执行设置的变换
+    /// Transformer chain for one stream: placeholders are restored first when PII scrubbing is set, then the
+    /// configured transformers run in order
+    fn start_transformers(&self) -> TransformerChain {
+        let mut chain = self.stream_transformers.as_ref().map(StreamTransformers::start).unwrap_or_default();
+        if let Some(scrubber) = &self.pii {
+            chain.prepend(RestorePii::new(scrubber.clone()));
+        }
+        chain
+    }
+
+    /// 汇总流式回答的初始状态，带有输出上限的计数
+    /// Initial state of a collected streaming answer, with the output cap accounting
+    fn new_stream_output(&self) -> StreamOutput {
+        let cap = self.output_cap.unwrap_or_default();
+        let counter = match cap.max_tokens {
+            Some(_) => CapCounter::new(cap).tokenizer(tokenizer_for(&self.model)),
+            None => CapCounter::new(cap),
+        };
+        StreamOutput {
+            cap: counter,
+            ..StreamOutput::default()
+        }
+    }
+
+    /// 在最近一次LLM调用的性能数据中记录截断
+    /// Record a truncation in the performance data of the latest LLM call
+    fn mark_truncated(&mut self, truncated: Truncated) {
+        warn!("Answer truncated at the output cap: {:?}", truncated);
+        if let Some(metadata) = &mut self.last_call {
+            metadata.truncated = Some(truncated);
+        }
+    }
+
+    /// 在最近一次LLM调用的性能数据中记录拒绝
+    /// Record a refusal in the performance data of the latest LLM call
+    fn mark_refused(&mut self, refusal: RefusalReason) {
+        warn!("Answer refused: {}", redact(&refusal.to_string()));
+        if let Some(metadata) = &mut self.last_call {
+            metadata.refusal = Some(refusal);
+        }
+    }
+
+    /// 筛查将进入提示词的外部内容，未设置筛查时原样返回
+    /// Screen external content about to enter the prompt, returned as is if screening is not set
+    pub async fn screen_external(&self, text: &str) -> Result<String, ChatError> {
+        let Some(screen) = &self.injection else {
+            return Ok(text.to_string());
+        };
+        let screened = screen.screen(text).await?;
+        if screened.flagged() {
+            warn!("Suspected prompt injection in external content: {}", redact(&screened.findings.join(" | ")));
+        }
+        Ok(screened.text)
+    }
+
+    /// 筛查工具结果后加入会话
+    /// Screen a tool result and add it to the session
+    pub async fn add_tool_result(&mut self, call_id: &str, output: &str) -> Result<(), ChatError> {
+        let output = self.screen_external(output).await?;
+        self.add_content(Role::tool(call_id), Content::tool_result(call_id, &output))
+    }
+
+    /// 查询应用状态的提供者，变量中的文本经过敏感信息清洗后渲染进请求的系统提示词模板；没有提供者时不改动请求
+    /// Query the providers of application state and render their variables, with the texts scrubbed of sensitive
+    /// data, into the system prompt template of the request; the request is left alone without providers
+    ///
+    /// # 参数 (Parameters)
+    /// * `recognize` - 是否调用实体识别清洗，为 false 时只按模式清洗，不发出请求，用于预览请求体
+    ///   Whether to scrub with entity recognition, only patterns are applied without any request if false, for
+    ///   previewing request bodies
+    pub(crate) async fn render_context(
+        &self,
+        request_body: &mut serde_json::Value,
+        recognize: bool,
+    ) -> Result<(), ChatError> {
+        if self.context_providers.is_empty() {
+            return Ok(());
+        }
+        let mut variables = context_variables(&self.context_providers).await?;
+        if let Some(scrubber) = &self.pii {
+            for text in variable_texts(&mut variables) {
+                *text = match recognize {
+                    true => scrubber.scrub(text).await?,
+                    false => scrubber.scrub_patterns(text),
+                };
+            }
+        }
+        render_system_prompt(request_body, variables)
+    }
+
+    /// 以占位符替换用户输入中的敏感信息，未设置清洗时原样返回
+    /// Replace the sensitive data in a user input with placeholders, returned as is if scrubbing is not set
+    pub async fn scrub_pii(&self, text: &str) -> Result<String, ChatError> {
+        match &self.pii {
+            Some(scrubber) => scrubber.scrub(text).await,
+            None => Ok(text.to_string()),
+        }
+    }
+
+    /// 将回答中的占位符还原为原文，未设置清洗时原样返回
+    /// Restore the placeholders in an answer to the originals, returned as is if scrubbing is not set
+    pub fn restore_pii(&self, text: String) -> String {
+        match &self.pii {
+            Some(scrubber) => scrubber.restore(&text),
+            None => text,
+        }
+    }
+
+    /// 按审核设置处理文本，未设置审核时原样放行
+    /// Screen a text per the moderation settings, letting it through unchanged if moderation is not set
+    pub async fn moderate(&self, stage: ModerationStage, text: &str) -> Result<Screened, ChatError> {
+        match &self.moderation {
+            Some(moderation) => moderation.screen(stage, text).await,
+            None => Ok(Screened::default()),
+        }
+    }
+
+    /// 在最新一条消息的元数据中记录审核标记的类别
+    /// Record the categories flagged by moderation in the metadata of the latest message
+    pub fn annotate_last_message(&mut self, categories: Vec<String>) -> Result<(), ChatError> {
+        if categories.is_empty() {
+            return Ok(());
+        }
+        self.session
+            .last_message_mut()
+            .change_context(ChatError::SessionError)?
+            .metadata
+            .get_or_insert_with(MessageMetadata::default)
+            .moderation = categories;
+        Ok(())
+    }
+
+    /// 流式回答未能写入会话时撤回末尾尚未回答的提问，历史不以没有回答的提问结尾
+    /// Withdraw the unanswered question at the end when a streamed answer could not be written to the session, so the
+    /// history does not end with a question without an answer
+    fn withdraw_question(&mut self) {
+        if !self.session.last_message_mut().is_ok_and(|message| message.role == Role::User) {
+            return;
+        }
+        if let Err(report) = self.session.remove_last_message() {
+            warn!("Failed to withdraw the unanswered question: {:?}", report);
+        }
+    }
+
+    /// 审核回答后加入会话，返回实际写入的回答
+    /// Screen an answer and add it to the session, returning the answer actually written
+    pub async fn add_screened_answer(&mut self, role: Role, content: String) -> Result<String, ChatError> {
+        let screened = self.moderate(ModerationStage::Output, &content).await?;
+        let content = screened.replacement.unwrap_or(content);
+        self.add_answer(role, &content)?;
+        self.annotate_last_message(screened.categories)?;
+        Ok(content)
+    }
+
+    /// 发往提供商的请求体：模型设置了对话模板时转换为补全请求体，否则原样发送
+    /// Request body sent to the provider: turned into a completion request body when the model has a chat
+    /// template, sent as is otherwise
+    pub fn provider_body<'a>(&self, request_body: &'a serde_json::Value) -> Result<Cow<'a, serde_json::Value>, ChatError> {
+        match self.model_metadata().and_then(|metadata| metadata.chat_template) {
+            Some(template) => Ok(Cow::Owned(
+                template.completion_body(request_body).attach_printable_lazy(|| format!("Model: {}", self.model))?,
+            )),
+            None => Ok(Cow::Borrowed(request_body)),
+        }
+    }
+
+    /// 即将发往提供商的请求，已按API来源的兼容性设置改写，并经过全局与对话自身的拦截器
+    /// The request about to be sent to the provider, rewritten by the compatibility settings of the API source and
+    /// passed through the global and the chat's own interceptors
+    pub fn outgoing_request(&self, provider_body: &serde_json::Value, request_id: &str) -> OutgoingRequest {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        match HeaderValue::from_str(&format!("Bearer {}", self.api_key)) {
+            Ok(mut value) => {
+                value.set_sensitive(true);
+                headers.insert(AUTHORIZATION, value);
+            }
+            Err(_) => warn!("API key of {} is not a valid header value", self.api_name),
+        }
+        if let Some(header) = &self.request_id_header {
+            match (HeaderName::from_bytes(header.as_bytes()), HeaderValue::from_str(request_id)) {
+                (Ok(name), Ok(value)) => {
+                    headers.insert(name, value);
+                }
+                _ => warn!("Request ID header not sent: {}: {}", header, request_id),
+            }
+        }
+
+        let mut request = OutgoingRequest {
+            url: self.base_url.clone(),
+            model: self.model.clone(),
+            request_id: request_id.to_string(),
+            headers,
+            body: provider_body.clone(),
+        };
+        if let Some(compat) = Config::get_compat(&self.base_url) {
+            compat.apply(&mut request.body);
+        }
+        intercept(&mut request, &self.request_interceptors);
+        request
+    }
+
+    /// 组装发往提供商的请求但不发送
+    /// Assemble the request to the provider without sending it
+    pub fn preview_request(&self, request_body: &serde_json::Value) -> Result<RequestPreview, ChatError> {
+        let request = self.outgoing_request(self.provider_body(request_body)?.as_ref(), "<request id>");
+        let headers = request
+            .headers
+            .iter()
+            .map(|(name, value)| {
+                let value = match value.is_sensitive() {
+                    true => REDACTED.to_string(),
+                    false => redact(&String::from_utf8_lossy(value.as_bytes())),
+                };
+                (name.to_string(), value)
+            })
+            .collect();
+        Ok(RequestPreview::new(request.url, request.model, headers, request.body, self.model_metadata()))
     }
 
     pub async fn send_request(
-        &mut self,
-        request_body: serde_json::Value,
+        &self,
+        request_body: &serde_json::Value,
+        request_id: &str,
     ) -> core::result::Result<Response, Error> {
-        self.client
-            .post(&self.base_url)
-            .header("Content-Type", "application/json")
-            .bearer_auth(&self.api_key)
-            .json(&request_body)
-            .send()
-            .await
+        let request = self.outgoing_request(request_body, request_id);
+        self.events.emit(|| ChatEvent::RequestDispatched {
+            request_id: request_id.to_string(),
+            at: SystemTime::now(),
+        });
+        let builder = self.client.post(&request.url).headers(request.headers);
+        Config::get_compression(&self.source).apply(builder, &request.body).send().await
     }
 
     pub async fn get_response(
         &mut self,
         request_body: serde_json::Value,
     ) -> Result<serde_json::Value, ChatError> {
-        let semaphore_permit = THREAD_POOL
-            .get(&self.base_url)
-            .unwrap()
-            .clone()
-            .acquire_owned()
-            .await
-            .unwrap();
+        let request_body = Arc::new(request_body);
+        let (span, mut call) = self.begin_llm_call(&request_body);
+        let started = Instant::now();
+
+        let result = async {
+            self.admit_tenant()?;
+            let provider_body = self.provider_body(&request_body)?;
+            let cache_key = self.answer_cache.as_ref().map(|_| AnswerCache::key(self.tenant.as_ref(), &provider_body));
+            if let Some(parsed) = cache_key.as_deref().and_then(|key| self.answer_cache.as_ref()?.get(key)) {
+                return Ok((parsed, true));
+            }
+            let semaphore_permit = self.acquire_permit().await?;
 
-        let response = self.send_request(request_body.clone()).await;
+            let response = self.send_request(&provider_body, &call.request_id).await;
 
-        drop(semaphore_permit);
+            drop(semaphore_permit);
 
-        match response {
-            Ok(res) => {
-                let res = res.error_for_status().map_err(|e| {
-                    Report::new(ChatError::HttpError(e.status().unwrap().as_u16()))
-                        .attach_printable(format!("HTTP error with request body: {}", request_body))
-                })?;
+            match response {
+                Ok(res) => {
+                    let res = Self::check_status(res, &request_body).await?;
 
-                let parsed: serde_json::Value = res
-                    .json()
-                    .await
-                    .change_context(ChatError::ParseResponseError)
-                    .attach_printable("Failed to parse response JSON")?;
+                    let parsed: serde_json::Value = res
+                        .json()
+                        .await
+                        .change_context(ChatError::ParseResponseError)
+                        .attach_printable("Failed to parse response JSON")?;
 
-                self.usage += parsed["usage"]["total_tokens"]
-                    .as_i64()
-                    .ok_or_else(|| Report::new(ChatError::MissingUsageData))
-                    .attach_printable("Missing usage data in response")?
-                    as i32;
+                    self.usage += parsed["usage"]["total_tokens"]
+                        .as_i64()
+                        .ok_or_else(|| Report::new(ChatError::MissingUsageData))
+                        .attach_printable("Missing usage data in response")?
+                        as i32;
 
-                Ok(parsed)
+                    if let (Some(cache), Some(key)) = (&self.answer_cache, cache_key) {
+                        cache.insert(key, parsed.clone());
+                    }
+                    Ok((parsed, false))
+                }
+                Err(e) => {
+                    if e.is_timeout() {
+                        Err(Report::new(ChatError::TimeoutError)
+                            .attach_printable(redact(&format!("Request timeout: {}", request_body))))
+                    } else {
+                        Err(Report::new(ChatError::UnknownError)
+                            .attach_printable(redact(&format!("Network error: {} - {}", e, request_body))))
+                    }
+                }
             }
-            Err(e) => {
-                if e.is_timeout() {
-                    Err(Report::new(ChatError::TimeoutError)
-                        .attach_printable(format!("Request timeout: {}", request_body)))
-                } else {
-                    Err(Report::new(ChatError::UnknownError)
-                        .attach_printable(format!("Network error: {} - {}", e, request_body)))
+        }
+        .instrument(span.clone())
+        .await;
+
+        // 命中缓存的响应没有产生用量
+        // A cached response incurred no usage
+        let result = result.map(|(parsed, cached)| {
+            if !cached {
+                call.usage = parsed.get("usage").and_then(TokenUsage::from_json);
+            }
+            parsed
+        });
+        match &result {
+            Ok(parsed) => {
+                call.finish_reason = parsed["choices"][0]["finish_reason"].as_str().map(str::to_string);
+                let choice = &parsed["choices"][0];
+                call.output = choice["message"]["content"].as_str().or(choice["text"].as_str()).map(str::to_string);
+                call.response = Some(parsed.clone());
+                self.finish_llm_call(&span, call, started, None);
+            }
+            Err(report) => self.finish_llm_call(&span, call, started, Some(report.current_context())),
+        }
+
+        result.map_err(|report| report.attach_printable(self.provider_info(1)))
+    }
+
+    /// 取回回答并按规则链检查，要求重新生成时把原因作为系统消息附在重试请求中；设置语种检查时，
+    /// 回答语种不符则附上该回答与明确的语种要求重新提问
+    /// Get the answer and check it against the guardrails, a regeneration attaches the reason to the retry request
+    /// as a system message; with a language policy, an answer in the wrong language is sent back together with an
+    /// explicit language instruction
+    pub async fn get_content(&mut self, mut request_body: serde_json::Value) -> Result<String, ChatError> {
+        let Some((policy, expected)) = self
+            .language
+            .and_then(|policy| policy.expected(&request_body).map(|expected| (policy, expected)))
+        else {
+            return self.get_guarded_content(request_body).await;
+        };
+
+        let mut retries = 0;
+        loop {
+            let content = self.get_guarded_content(request_body.clone()).await?;
+            let Some(detected) = policy.mismatch(&content, expected) else {
+                return Ok(content);
+            };
+            if retries >= policy.max_retries {
+                warn!("Answer still in {} instead of {} after {} retries", detected, expected, retries);
+                return Ok(content);
+            }
+            retries += 1;
+            warn!("Answer in {} instead of {}, asking again ({})", detected, expected, retries);
+            if let Some(messages) = request_body["messages"].as_array_mut() {
+                messages.push(json!({"role": "assistant", "content": content}));
+                messages.push(json!({"role": "system", "content": language_instruction(expected)}));
+            }
+        }
+    }
+
+    async fn get_guarded_content(&mut self, mut request_body: serde_json::Value) -> Result<String, ChatError> {
+        let mut regenerations = 0;
+        loop {
+            let Some(guardrails) = self.guardrails.clone() else {
+                let content = self.get_unchecked_content(request_body).await?;
+                return self.post_process(content);
+            };
+            let content = self.get_unchecked_content(request_body.clone()).await?;
+            let content = self.post_process(content)?;
+            match guardrails.check(&content) {
+                GuardrailVerdict::Pass => return Ok(content),
+                GuardrailVerdict::Rewrite(rewritten) => return Ok(rewritten),
+                GuardrailVerdict::Block(reason) => return Err(Report::new(ChatError::GuardrailBlocked(reason))),
+                GuardrailVerdict::Regenerate(reason) if regenerations < guardrails.max_regenerations => {
+                    regenerations += 1;
+                    warn!("Guardrail asked for regeneration ({}): {}", regenerations, reason);
+                    if let Some(messages) = request_body["messages"].as_array_mut() {
+                        let feedback = Config::render_prompt(&PromptKey::GuardrailRegeneration, &[&reason]);
+                        messages.push(json!({"role": "system", "content": feedback}));
+                    }
                 }
+                GuardrailVerdict::Regenerate(reason) => {
+                    return Err(Report::new(ChatError::GuardrailBlocked(reason)))
+                        .attach_printable(format!("Still rejected after {} regenerations", regenerations));
+                }
+            }
+        }
+    }
+
+    /// 按规则链检查由文本回答转换出的JSON：转换请求不经过 `get_content`，在此以JSON文本检查；无法重新生成，
+    /// 要求重新生成时与拒绝一样返回错误，改写的结果须仍为合法JSON
+    /// Check the JSON converted from a text answer against the guardrails: the conversion request does not go
+    /// through `get_content`, so the JSON text is checked here; it cannot be regenerated, so a regeneration fails like
+    /// a rejection, and a rewrite must still be valid JSON
+    pub(crate) fn check_converted_json(&self, value: serde_json::Value) -> Result<serde_json::Value, ChatError> {
+        let Some(guardrails) = &self.guardrails else {
+            return Ok(value);
+        };
+        match guardrails.check(&value.to_string()) {
+            GuardrailVerdict::Pass => Ok(value),
+            GuardrailVerdict::Rewrite(rewritten) => serde_json::from_str(&rewritten)
+                .change_context(ChatError::GetJsonError)
+                .attach_printable("Guardrail rewrote the JSON answer into invalid JSON"),
+            GuardrailVerdict::Block(reason) | GuardrailVerdict::Regenerate(reason) => {
+                Err(Report::new(ChatError::GuardrailBlocked(reason)))
             }
         }
     }
 
+    /// 发送请求并取回回答内容，凭据失效或额度用尽时在本次请求内转移到同一能力下的其他API
+    /// Send a request and return the answer content, failing over to another API of the same capability for this
+    /// request when the credential fails or runs out of quota
+    async fn get_unchecked_content(&mut self, mut request_body: serde_json::Value) -> Result<String, ChatError> {
+        if self.begin_request() {
+            request_body["model"] = json!(self.model);
+        }
+        let mut failover = FailOver::default();
+        let result = loop {
+            match self.get_content_once(request_body.clone()).await {
+                Err(report) if self.fail_over(&report, &mut failover, None) => {
+                    request_body["model"] = json!(self.model);
+                }
+                result => break result,
+            }
+        };
+        if result.is_ok() {
+            self.record_provider_switches(failover.switches);
+        }
+        self.restore_binding();
+        result
+    }
+
+    /// 发送请求并取回回答内容；回答因长度限制中断时按 `max_continuations` 续写，拼接各段并去除重叠，
+    /// 最后一次调用的元数据合计各段的用量与延迟并记录续写次数
+    /// Send a request and return the answer content; an answer cut off by the length limit is continued up to
+    /// `max_continuations` times, stitching the parts without their overlap, and the metadata of the last call sums
+    /// up the usage and latency of every part and records the number of continuations
+    async fn get_content_once(&mut self, request_body: serde_json::Value) -> Result<String, ChatError> {
+        let (mut content, mut finish_reason) = self.get_content_part(request_body.clone()).await?;
+        let mut merged = self.last_call.clone();
+        let mut continuations = 0;
+        while continuations < self.max_continuations
+            && finish_reason.as_deref() == Some("length")
+            && merged.as_ref().is_some_and(|metadata| metadata.truncated.is_none())
+        {
+            continuations += 1;
+            warn!("Answer cut off by the length limit after {} chars, continuing ({})", content.len(), continuations);
+            let (part, reason) = self.get_content_part(self.continuation_body(&request_body, &content)).await?;
+            content = stitch_continuation(&content, &part);
+            finish_reason = reason;
+            if let (Some(metadata), Some(part)) = (&mut merged, &self.last_call) {
+                metadata.latency_ms += part.latency_ms;
+                metadata.prompt_tokens = add_tokens(metadata.prompt_tokens, part.prompt_tokens);
+                metadata.completion_tokens = add_tokens(metadata.completion_tokens, part.completion_tokens);
+                metadata.truncated = part.truncated;
+                metadata.continuations = continuations;
+            }
+        }
+        if continuations > 0 {
+            self.last_call = merged;
+        }
+        Ok(content)
+    }
+
+    /// 发送一次请求，返回回答内容与结束原因，根据 `need_stream` 选择流式或非流式接口
+    /// Send one request and return the answer content with the finish reason, using the streaming or
+    /// non-streaming API per `need_stream`
+    async fn get_content_part(&mut self, request_body: serde_json::Value) -> Result<(String, Option<String>), ChatError> {
+        if self.need_stream {
+            let output = self
+                .get_stream_output(request_body)
+                .await
+                .attach_printable("Failed to get stream response")?;
+            Ok((output.content, output.finish_reason))
+        } else {
+            let response = self
+                .get_response(request_body)
+                .await
+                .attach_printable("Failed to get response")?;
+
+            let refusal = RefusalReason::from_response(&response);
+            let mut content = match &refusal {
+                // 拒绝时回答内容通常为空，以拒绝说明代替
+                // A refused answer usually has no content, the refusal explanation stands in for it
+                Some(refusal) if !response["choices"][0]["message"]["content"].is_string() => match refusal {
+                    RefusalReason::Declined { message } => message.clone(),
+                    _ => String::new(),
+                },
+                _ => Self::get_content_from_resp(&response)
+                    .attach_printable("Failed to extract content from response")?,
+            };
+            let completion_tokens = self.last_call.as_ref().and_then(|metadata| metadata.completion_tokens);
+            if let Some(truncated) = self.output_cap.and_then(|cap| cap.truncate(&mut content, completion_tokens)) {
+                self.mark_truncated(truncated);
+            }
+            if let Some(refusal) = refusal {
+                self.mark_refused(refusal);
+            }
+            if let (Some(metadata), Some(probability)) = (&mut self.last_call, token_probability(&response)) {
+                metadata.token_probability = Some(probability);
+            }
+            let finish_reason = response["choices"][0]["finish_reason"].as_str().map(str::to_string);
+            Ok((content, finish_reason))
+        }
+    }
+
+    /// 取出回答内容，补全接口的回答在 `text` 中
+    /// Extract the answer content, answers of the completion endpoint are in `text`
     pub fn get_content_from_resp(resp: &serde_json::Value) -> Result<String, ChatError> {
-        let content = resp
-            .get("choices")
-            .and_then(|c| c.get(0))
+        let choice = resp.get("choices").and_then(|c| c.get(0));
+        let content = choice
             .and_then(|c| c.get("message"))
-            .and_then(|m| m.get("content"));
+            .and_then(|m| m.get("content"))
+            .or_else(|| choice.and_then(|c| c.get("text")));
 
         match content {
+            Some(serde_json::Value::String(content)) => Ok(content.clone()),
             Some(content) => Ok(content.to_string()),
             None => Err(Report::new(ChatError::ParseResponseError))
                 .attach_printable("Failed to parse response content"),
         }
     }
 
-    pub async fn get_stream_response(
+    /// 发送流式请求并汇总全部分块，附带用量与结束原因；换用的备用API在下一次请求开始时恢复
+    /// Send a streaming request and collect every chunk, together with usage and finish reason; a fallback API
+    /// switched to is restored when the next request begins
+    pub async fn get_stream_output(
         &mut self,
         request_body: serde_json::Value,
+    ) -> Result<StreamOutput, ChatError> {
+        let mut request_body = Arc::new(request_body);
+        let (span, mut call) = self.begin_llm_call(&request_body);
+        let started = Instant::now();
+
+        let events = self.events.clone();
+        let request_id = call.request_id.clone();
+        let stall_timeout = self.stream_stall_timeout;
+        let mut resumes = 0;
+        let mut failover = FailOver::default();
+        let result = async {
+            self.admit_tenant()?;
+            let mut output = self.new_stream_output();
+            loop {
+                // 换用备用API后以已收到的内容为前缀重新开始
+                // After switching to a fallback, restart with the content received so far as the prefix
+                let body = if resumes == 0 && output.content.is_empty() {
+                    request_body.clone()
+                } else {
+                    Arc::new(self.resume_body(&request_body, &output.content))
+                };
+                let attempt = async {
+                    let (stream, semaphore_permit) = self.get_stream_response(&body, &request_id).await?;
+                    let mut chunks = pin!(stalling_chunk_stream(stream, semaphore_permit, stall_timeout));
+                    while let Some(chunk) = chunks.try_next().await? {
+                        let first = output.first_token_at.is_none();
+                        let delta = output.absorb(chunk, true);
+                        if first && output.first_token_at.is_some() {
+                            emit_first_token(&events, &request_id, started);
+                        }
+                        if !delta.is_empty() {
+                            events.emit(|| ChatEvent::TokenReceived {
+                                request_id: request_id.clone(),
+                                delta,
+                            });
+                        }
+                        // 到达上限后丢弃连接，中止生成
+                        // Drop the connection once the cap is reached, aborting generation
+                        if output.cap.truncated.is_some() {
+                            break;
+                        }
+                    }
+                    Ok::<_, Report<ChatError>>(())
+                }
+                .await;
+
+                match attempt {
+                    Ok(()) => return Ok(output),
+                    Err(report) if self.should_resume(&report, &output, resumes) => {
+                        resumes += 1;
+                        warn!("Stream dropped after {} bytes, resuming (attempt {}): {:?}", output.content.len(), resumes, report);
+                    }
+                    Err(report) => {
+                        if !self.fail_over(&report, &mut failover, Some(&output)) {
+                            return Err(report);
+                        }
+                        let mut body = (*request_body).clone();
+                        body["model"] = json!(self.model);
+                        request_body = Arc::new(body);
+                        span.record("model", self.model.as_str());
+                        resumes = 0;
+                    }
+                }
+            }
+        }
+        .instrument(span.clone())
+        .await;
+
+        call.model = self.model.clone();
+        call.base_url = self.base_url.clone();
+        match &result {
+            Ok(output) => {
+                self.finish_stream_call(&span, call, started, output, true);
+                self.record_provider_switches(failover.switches);
+            }
+            Err(report) => self.finish_llm_call(&span, call, started, Some(report.current_context())),
+        }
+
+        result.map_err(|report| report.attach_printable(self.provider_info(resumes + 1)))
+    }
+
+    pub async fn get_stream_response(
+        &mut self,
+        request_body: &serde_json::Value,
+        request_id: &str,
     ) -> Result<
         (
             impl Stream<Item = reqwest::Result<Bytes>> + Send + Unpin,
-            OwnedSemaphorePermit,
+            RequestPermit,
         ),
         ChatError,
     > {
-        let semaphore_permit = THREAD_POOL
-            .get(&self.base_url)
-            .unwrap()
-            .clone()
-            .acquire_owned()
-            .await
-            .unwrap();
+        let (response, semaphore_permit) = self.open_stream(request_body, request_id).await?;
+        Ok((response.bytes_stream(), semaphore_permit))
+    }
 
-        let response = self.send_request(request_body.clone()).await;
+    /// 发出流式请求，返回状态正常的响应与并发额度
+    /// Send a streaming request, returning the successful response together with the request permit
+    async fn open_stream(
+        &self,
+        request_body: &serde_json::Value,
+        request_id: &str,
+    ) -> Result<(Response, RequestPermit), ChatError> {
+        let provider_body = self.provider_body(request_body)?;
+        let semaphore_permit = self.acquire_permit().await?;
+
+        let response = self.send_request(&provider_body, request_id).await;
 
         match response {
             Ok(res) => {
-                let res = res.error_for_status().map_err(|e| {
-                    Report::new(ChatError::HttpError(e.status().unwrap().as_u16()))
-                        .attach_printable(format!("HTTP error with request body: {}", request_body))
-                })?;
+                let res = Self::check_status(res, request_body).await?;
 
-                Ok((res.bytes_stream(), semaphore_permit))
+                Ok((res, semaphore_permit))
             }
             Err(e) => {
                 if e.is_timeout() {
                     Err(Report::new(ChatError::TimeoutError)
-                        .attach_printable(format!("Request timeout: {}", request_body)))
+                        .attach_printable(redact(&format!("Request timeout: {}", request_body))))
                 } else {
                     Err(Report::new(ChatError::UnknownError)
-                        .attach_printable(format!("Network error: {} - {}", e, request_body)))
+                        .attach_printable(redact(&format!("Network error: {} - {}", e, request_body))))
                 }
             }
         }
@@ -259,55 +1435,766 @@ impl BaseChat {
 
     pub async fn get_content_from_stream_resp(
         stream: impl Stream<Item = reqwest::Result<Bytes>> + Send + Unpin,
-        semaphore_permit: OwnedSemaphorePermit,
+        semaphore_permit: impl Into<RequestPermit>,
     ) -> Result<String, ChatError> {
-        #[derive(Default)]
-        struct StreamResult {
-            content: String,
-            usage: Option<serde_json::Value>,
+        Ok(Self::collect_stream(stream, semaphore_permit).await?.content)
+    }
+
+    pub async fn collect_stream(
+        stream: impl Stream<Item = reqwest::Result<Bytes>> + Send + Unpin,
+        semaphore_permit: impl Into<RequestPermit>,
+    ) -> Result<StreamOutput, ChatError> {
+        Self::collect_stream_with(stream, semaphore_permit, |_| {}).await
+    }
+
+    /// 汇总流式回答，每收到一段内容调用一次 `on_delta`
+    /// Collect a streaming answer, calling `on_delta` for every piece of content received
+    pub async fn collect_stream_with(
+        stream: impl Stream<Item = reqwest::Result<Bytes>> + Send + Unpin,
+        semaphore_permit: impl Into<RequestPermit>,
+        mut on_delta: impl FnMut(&str),
+    ) -> Result<StreamOutput, ChatError> {
+        let mut result = StreamOutput::default();
+        let mut chunks = pin!(chunk_stream(stream, semaphore_permit));
+        while let Some(chunk) = chunks.try_next().await? {
+            let delta = result.absorb(chunk, true);
+            if !delta.is_empty() {
+                on_delta(&delta);
+            }
         }
+        Ok(result)
+    }
 
-        let result = stream
-            .map_err(|err| {
-                Report::new(ChatError::HttpError(0))
-                    .attach_printable(format!("Failed to get response: {}", err))
-            })
-            .try_fold(StreamResult::default(), |mut result, chunk| async move {
-                String::from_utf8_lossy(&chunk)
-                    .split('\n')
-                    .filter(|line| !line.is_empty() && *line != "data: [DONE]")
-                    .try_for_each(|line| {
-                        let json_str = line.strip_prefix("data: ").unwrap_or(line);
-
-                        serde_json::from_str::<serde_json::Value>(json_str)
-                            .map_err(|err| {
-                                Report::new(ChatError::ParseResponseError)
-                                    .attach_printable(format!("Failed to parse JSON: {}", err))
-                            })
-                            .map(|json| {
-                                json.get("choices")
-                                    .and_then(|c| c.as_array())
-                                    .map(|choices| {
-                                        choices
-                                            .iter()
-                                            .filter_map(|choice| choice.get("delta"))
-                                            .filter_map(|delta| {
-                                                delta.get("content").and_then(|c| c.as_str())
-                                            })
-                                            .for_each(|content| result.content.push_str(content));
-                                    });
-
-                                json.get("usage")
-                                    .filter(|u| !u.is_null())
-                                    .map(|usage| result.usage = Some(usage.clone()));
-                            })
-                    })?;
-
-                Ok(result)
-            })
-            .await?;
+    /// 发送流式请求，回答内容在到达时逐段产出，不等待整个回答
+    /// Send a streaming request and yield the answer content piece by piece as it arrives, without waiting for the
+    /// whole answer
+    ///
+    /// `answer_role` 为 Some 时汇总回答，并在流结束后以该角色加入会话；为 None 时只产出增量。
+    /// 连接在回答中途中断时，以已收到的内容为前缀续传（见 `stream_resume_attempts`），产出的增量自然衔接。
+    /// 流在结束前被丢弃时，本次调用不会被记录。
+    /// When `answer_role` is Some, the answer is accumulated and added to the session with that role once the
+    /// stream ends; when None, only the deltas are yielded. If the connection drops mid-answer, the request is
+    /// resumed with the content received so far as the prefix (see `stream_resume_attempts`), so the yielded
+    /// deltas continue seamlessly. If the stream is dropped before it ends, the call is not recorded.
+    ///
+    /// 对话设置了后处理器或回答审核时，写入会话的回答先经处理与审核，此前不产出增量，通过后一次产出写入的回答；
+    /// 处理失败或审核拒绝时以错误结束流，被拦截的内容不会产出，并撤回会话末尾尚未回答的提问。
+    /// With post-processors or answer moderation set, the answer written to the session is processed and screened
+    /// first and no deltas are yielded before; once it passes, the answer written is yielded at once. If processing
+    /// fails or moderation blocks it, the stream ends with the error without yielding the blocked content, and the
+    /// unanswered question at the end of the session is withdrawn.
+    ///
+    /// # 参数 (Parameters)
+    /// * `request_body` - 请求体，需开启 `stream` / Request body, with `stream` enabled
+    /// * `answer_role` - 回答加入会话时使用的角色，为 None 时不加入会话
+    ///   - Role of the answer added to the session, the answer is not added if None
+    pub fn stream_content(
+        &mut self,
+        request_body: serde_json::Value,
+        answer_role: Option<Role>,
+    ) -> impl Stream<Item = Result<String, ChatError>> + Send + '_ {
+        stream! {
+            let mut request_body = request_body;
+            if self.begin_request() {
+                request_body["model"] = json!(self.model);
+            }
+            let mut request_body = Arc::new(request_body);
+            let (span, mut call) = self.begin_llm_call(&request_body);
+            let started = Instant::now();
+
+            // 内容有一部分未保留时不记录回答内容
+            // The answer content is not recorded once part of it was not kept
+            let mut complete = true;
+            // 写入会话的回答需经后处理或回答审核时先不发出增量，通过后一次发出写入的回答
+            // When the answer written to the session is post-processed or moderated, no deltas are yielded before;
+            // once it passes, the answer written is yielded at once
+            let screens_output = self.moderation.as_ref().is_some_and(|moderation| moderation.output.is_some());
+            let withheld = answer_role.is_some() && (self.post_processors.is_some() || screens_output);
+            let mut output = self.new_stream_output();
+            let mut transformers = self.start_transformers();
+            let mut resumes = 0;
+            let mut failover = FailOver::default();
+            // 续写的次数，正在续写时 `held` 为这一段在内容中的起点，重叠部分确定前暂不发出增量
+            // Number of continuations; while continuing, `held` is where the part starts in the content, its deltas
+            // are held back until the overlap is known
+            let mut continuations = 0;
+            let mut held: Option<usize> = None;
+            let mut earlier_usage = None;
+            if let Err(report) = self.admit_tenant() {
+                self.finish_llm_call(&span, call, started, Some(report.current_context()));
+                self.restore_binding();
+                yield Err(report);
+                return;
+            }
+            'attempts: loop {
+                let body = if resumes == 0 && output.content.is_empty() {
+                    request_body.clone()
+                } else if held == Some(output.content.len()) {
+                    Arc::new(self.continuation_body(&request_body, &output.content))
+                } else {
+                    Arc::new(self.resume_body(&request_body, &output.content))
+                };
+                let failure = match self.open_stream(&body, &call.request_id).instrument(span.clone()).await {
+                    Ok((response, permit)) => {
+                        let mut chunks =
+                            pin!(stalling_chunk_stream(response.bytes_stream(), permit, self.stream_stall_timeout));
+                        loop {
+                            match chunks.next().await {
+                                Some(Ok(chunk)) => {
+                                    // 回答加入会话时需要完整内容；否则只在还能续传、续写或换用备用API时保留，作为其前缀
+                                    // The session needs the whole answer; otherwise the content is only kept while
+                                    // a resume, continuation or fallback can still use it as the prefix
+                                    let keep_content = answer_role.is_some()
+                                        || resumes < self.stream_resume_attempts
+                                        || continuations < self.max_continuations
+                                        || failover.next_fallback < self.stream_fallbacks.len();
+                                    let first = output.first_token_at.is_none();
+                                    let mut content = output.absorb(chunk, keep_content);
+                                    complete &= keep_content || content.is_empty();
+                                    if let Some(start) = held {
+                                        let part = &output.content[start..];
+                                        content = if output.cap.truncated.is_some()
+                                            || part.chars().count() > CONTINUATION_OVERLAP.1
+                                        {
+                                            held = None;
+                                            release_continuation(&mut output.content, start)
+                                        } else {
+                                            String::new()
+                                        };
+                                    }
+                                    let delta = transformers.push(&content);
+                                    if first && output.first_token_at.is_some() {
+                                        emit_first_token(&self.events, &call.request_id, started);
+                                    }
+                                    if !delta.is_empty() && !withheld {
+                                        self.events.emit(|| ChatEvent::TokenReceived {
+                                            request_id: call.request_id.clone(),
+                                            delta: delta.clone(),
+                                        });
+                                        yield Ok(delta);
+                                    }
+                                    if output.cap.truncated.is_some() || transformers.stopped() {
+                                        break 'attempts;
+                                    }
+                                }
+                                Some(Err(report)) => break report,
+                                None => {
+                                    if let Some(start) = held.take() {
+                                        let released = release_continuation(&mut output.content, start);
+                                        let delta = transformers.push(&released);
+                                        if !delta.is_empty() && !withheld {
+                                            yield Ok(delta);
+                                        }
+                                    }
+                                    if continuations < self.max_continuations
+                                        && output.finish_reason.as_deref() == Some("length")
+                                        && !transformers.stopped()
+                                    {
+                                        continuations += 1;
+                                        warn!(
+                                            "Answer cut off by the length limit after {} chars, continuing ({})",
+                                            output.content.len(),
+                                            continuations
+                                        );
+                                        held = Some(output.content.len());
+                                        output.finish_reason = None;
+                                        earlier_usage = add_usage(earlier_usage, output.usage.take());
+                                        resumes = 0;
+                                        continue 'attempts;
+                                    }
+                                    break 'attempts;
+                                }
+                            }
+                        }
+                    }
+                    Err(report) => report.attach_printable("Failed to get stream response"),
+                };
+
+                if self.should_resume(&failure, &output, resumes) {
+                    resumes += 1;
+                    warn!("Stream dropped after {} bytes, resuming (attempt {}): {:?}", output.content.len(), resumes, failure);
+                    continue;
+                }
+                if self.fail_over(&failure, &mut failover, Some(&output)) {
+                    let mut body = (*request_body).clone();
+                    body["model"] = json!(self.model);
+                    request_body = Arc::new(body);
+                    call.model = self.model.clone();
+                    call.base_url = self.base_url.clone();
+                    span.record("model", self.model.as_str());
+                    resumes = 0;
+                    continue;
+                }
+                self.finish_llm_call(&span, call, started, Some(failure.current_context()));
+                let failure = failure.attach_printable(self.provider_info(resumes + 1));
+                self.restore_binding();
+                yield Err(failure);
+                return;
+            }
+
+            output.usage = add_usage(earlier_usage, output.usage.take());
+            self.finish_stream_call(&span, call, started, &output, complete);
+            if let Some(metadata) = self.last_call.as_mut().filter(|_| continuations > 0) {
+                metadata.continuations = continuations;
+            }
+            self.record_provider_switches(failover.switches);
+            self.restore_binding();
+            let rest = transformers.finish();
+            if !rest.is_empty() && !withheld {
+                yield Ok(rest);
+            }
+            // 未暂缓时增量已经发出，规则链只决定写入会话的回答；检查拒绝时以错误结束流并撤回提问，流式回答无法重新生成
+            // Unless withheld, the deltas are already out and guardrails only decide the answer written to the
+            // session; a rejection ends the stream with an error and withdraws the question, a streamed answer cannot
+            // be regenerated
+            let Some(role) = answer_role else {
+                return;
+            };
+            let content = match self.post_process(output.content) {
+                Ok(content) => content,
+                Err(report) => {
+                    self.withdraw_question();
+                    yield Err(report);
+                    return;
+                }
+            };
+            let content = match self.guardrails.as_ref().map(|guardrails| guardrails.check(&content)) {
+                None | Some(GuardrailVerdict::Pass) => content,
+                Some(GuardrailVerdict::Rewrite(rewritten)) => rewritten,
+                Some(GuardrailVerdict::Block(reason) | GuardrailVerdict::Regenerate(reason)) => {
+                    self.withdraw_question();
+                    yield Err(Report::new(ChatError::GuardrailBlocked(reason)));
+                    return;
+                }
+            };
+            match self.add_screened_answer(role, content).await {
+                Ok(answer) if withheld && !answer.is_empty() => yield Ok(answer),
+                Ok(_) => {}
+                Err(report) => {
+                    self.withdraw_question();
+                    yield Err(report);
+                }
+            }
+        }
+    }
+
+    /// 检查租户的预算与频率上限，每次调用在第一次尝试前检查一次，续传与重试不再计数
+    /// Check the tenant's budget and rate limit, once per call before the first attempt; resumes and retries are not
+    /// counted again
+    fn admit_tenant(&self) -> Result<(), ChatError> {
+        match &self.tenant {
+            Some(tenant) => tenant.admit_with_priority(self.queue_priority),
+            None => Ok(()),
+        }
+    }
+
+    /// 获取请求并发额度：先取能力额度（若该能力设置了上限），再取API来源额度，并记录等待时间
+    /// Acquire the request permits: the capability permit is taken first (if the capability has a limit), then the
+    /// API source permit, recording the wait time
+    pub async fn acquire_permit(&self) -> Result<RequestPermit, ChatError> {
+        let started = Instant::now();
+        let pools = CFG.current();
+        let source = pools
+            .source_pool()
+            .get(&self.base_url)
+            .map(|semaphore| semaphore.clone())
+            .ok_or_else(|| Report::new(ChatError::PermitError))
+            .attach_printable_lazy(|| format!("No semaphore registered for: {}", self.base_url))?;
+        let capability = self
+            .capability
+            .as_ref()
+            .and_then(|capability| pools.capability_pool().get(capability).map(|semaphore| semaphore.clone()));
+
+        let key = self.queue_key.as_deref().unwrap_or(&self.session_id);
+        let capability = match (capability, &self.capability) {
+            (Some(semaphore), Some(name)) => Some(
+                FairQueue::of(&format!("capability:{:?}", name), &semaphore)
+                    .acquire(key, self.queue_priority)
+                    .await
+                    .change_context(ChatError::PermitError)?,
+            ),
+            _ => None,
+        };
+        let source = FairQueue::of(&format!("source:{}", self.base_url), &source)
+            .acquire(key, self.queue_priority)
+            .await
+            .change_context(ChatError::PermitError)?;
+
+        record_permit_wait(&self.model, self.capability.as_ref(), started.elapsed());
+        Ok(RequestPermit {
+            _source: source,
+            _capability: capability,
+            _in_flight: track_generation(),
+        })
+    }
+
+    /// 检查响应状态，失败时读取提供商返回的错误内容并附加在错误报告上
+    /// Check the response status, on failure the error body returned by the provider is attached to the report
+    async fn check_status(response: Response, request_body: &serde_json::Value) -> Result<Response, ChatError> {
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+
+        let body = response.text().await.unwrap_or_default();
+        Err(Report::new(ChatError::HttpError(status.as_u16()))
+            .attach_printable(ErrorBody(redact(&body)))
+            .attach_printable(redact(&format!("HTTP error with request body: {}", request_body))))
+    }
+
+    /// 请求失败时在本次请求内换用其他API，换用成功时返回 true 并记录这次切换
+    /// On a failed request, switch to another API for the rest of this request, returning true and recording the
+    /// switch if switched
+    ///
+    /// 凭据失效或额度用尽且尚未收到回答时，当前API进入冷却，先换用同一能力下尚未失败的健康API；
+    /// 流式请求其次在错误可重试或凭据被拒时换用下一个备用API，跳过的与已用的备用项不再尝试。
+    /// 请求结束后恢复原来的连接，见 `restore_binding`。
+    /// When the credential fails or runs out of quota before any answer arrived, the current API cools down and a
+    /// healthy API of the same capability that has not failed yet is tried first; streaming requests then switch to
+    /// the next fallback API if the error is retryable or the credential was rejected, skipped and used fallbacks are
+    /// not tried again. The original connection is restored once the request ends, see `restore_binding`.
+    ///
+    /// # 参数 (Parameters)
+    /// * `output` - 流式请求已收到的输出，非流式请求为 None，此时不换用备用API
+    ///   Output received so far by a streaming request, None for non-streaming requests, which use no fallbacks
+    fn fail_over(
+        &mut self,
+        report: &Report<ChatError>,
+        failover: &mut FailOver,
+        output: Option<&StreamOutput>,
+    ) -> bool {
+        let rejected = report.rejects_credentials();
+        let answered = output.is_some_and(|output| output.first_token_at.is_some());
+        let binding = match (self.credential_cooldown, self.capability.clone()) {
+            (Some(cooldown), Some(capability)) if rejected && !answered => {
+                cool_down(&self.api_name, cooldown);
+                failover.rejected.push(self.api_name.clone());
+                Config::get_failover_api_info(capability, &failover.rejected)
+                    .map(|api_info| ApiBinding::from_api_info(api_info, self.capability.clone()))
+            }
+            _ => None,
+        };
+        let binding = match binding {
+            Some(binding) => binding,
+            None if output.is_some() && (rejected || report.is_retryable()) => match self.next_fallback(failover) {
+                Some(binding) => binding,
+                None => return false,
+            },
+            None => return false,
+        };
+
+        let received_bytes = output.map_or(0, |output| output.content.len());
+        warn!("Request on {} failed after {} bytes, switching to {}", self.api_name, received_bytes, binding.api_name);
+        failover.switches.push(ProviderSwitch {
+            from: self.api_name.clone(),
+            to: binding.api_name.clone(),
+            error: report.code().to_string(),
+            received_bytes,
+        });
+        self.bind_for_request(binding);
+        true
+    }
+
+    /// 解析下一个可用的备用API，跳过无法解析的与当前正在使用的
+    /// Resolve the next available fallback API, skipping unresolvable ones and the one in use
+    fn next_fallback(&self, failover: &mut FailOver) -> Option<ApiBinding> {
+        while let Some(fallback) = self.stream_fallbacks.get(failover.next_fallback) {
+            failover.next_fallback += 1;
+            match ApiBinding::resolve(fallback) {
+                Ok(binding) if binding.api_name != self.api_name => return Some(binding),
+                Ok(_) => {}
+                Err(report) => warn!("Fallback {:?} unavailable: {:?}", fallback, report),
+            }
+        }
+        None
+    }
+
+    /// 开始一次请求：恢复上一次请求中换用前的连接（流被中途丢弃时可能未恢复）；
+    /// 按能力选择的对话当前API正在冷却时，本次请求换用同一能力下的健康API
+    /// Begin a request: restore the connection from before any switch in the previous request (a stream dropped
+    /// midway may have left it switched); a chat selected by capability whose API is cooling down uses a healthy API
+    /// of the same capability for this request
+    ///
+    /// # 返回 (Returns)
+    /// 是否换用了其他API，此时请求体中的模型需要更新
+    /// Whether another API is used, the model in the request body then needs updating
+    fn begin_request(&mut self) -> bool {
+        self.restore_binding();
+        let Some(capability) = self.capability.clone() else {
+            return false;
+        };
+        if !get_health(&self.api_name).is_some_and(|health| health.cooling_down()) {
+            return false;
+        }
+        let Some(api_info) = Config::get_failover_api_info(capability, std::slice::from_ref(&self.api_name)) else {
+            return false;
+        };
+        self.bind_for_request(ApiBinding::from_api_info(api_info, self.capability.clone()));
+        true
+    }
+
+    /// 在本次请求内换用另一个API连接，请求结束时由 `restore_binding` 恢复
+    /// Switch to another API connection for this request, `restore_binding` restores the original once it ends
+    fn bind_for_request(&mut self, binding: ApiBinding) {
+        let original = self.bind_api(binding);
+        self.request_binding.get_or_insert(original);
+    }
+
+    /// 恢复本次请求中换用前的API连接
+    /// Restore the API connection from before the switches of this request
+    fn restore_binding(&mut self) {
+        if let Some(original) = self.request_binding.take() {
+            self.bind_api(original);
+        }
+    }
+
+    /// 将换用其他API的记录写入最近一次调用的元数据，排在该调用内部已记录的切换之前
+    /// Record the API switches in the metadata of the latest call, ahead of the switches the call recorded itself
+    fn record_provider_switches(&mut self, switches: Vec<ProviderSwitch>) {
+        if let Some(metadata) = self.last_call.as_mut() {
+            metadata.provider_switches.splice(0..0, switches);
+        }
+    }
+
+    /// 判断中断的流式请求是否续传：已收到部分回答（停滞的流除外）、回答未结束、错误可重试且未超过续传次数
+    /// Decide whether to resume a dropped stream: part of the answer arrived (unless the stream stalled), the answer
+    /// has not finished, the error is retryable and the resume limit is not reached
+    fn should_resume(&self, report: &Report<ChatError>, output: &StreamOutput, resumes: u32) -> bool {
+        resumes < self.stream_resume_attempts
+            && (!output.content.is_empty() || report.contains::<StreamStalled>())
+            && output.finish_reason.is_none()
+            && report.current_context().is_retryable()
+    }
+
+    /// 构造续传请求体：将已收到的内容作为助手消息前缀追加到消息列表末尾
+    /// Build the resume request body: the content received so far is appended to the messages as an assistant
+    /// prefix
+    ///
+    /// 支持前缀续写的提供商（如 DeepSeek）会额外标记 `prefix`，其余提供商依赖末尾的助手消息继续生成。
+    /// Providers supporting prefix completion (such as DeepSeek) get the `prefix` flag as well, the others continue
+    /// from the trailing assistant message.
+    pub fn resume_body(&self, request_body: &serde_json::Value, received: &str) -> serde_json::Value {
+        let mut prefix = json!({"role": "assistant", "content": received});
+        if provider_system(&self.base_url) == "deepseek" {
+            prefix["prefix"] = json!(true);
+        }
+
+        let mut body = request_body.clone();
+        if let Some(messages) = body.get_mut("messages").and_then(|messages| messages.as_array_mut()) {
+            messages.push(prefix);
+        }
+        body
+    }
+
+    /// 构造续写请求体：已生成的内容作为助手消息，随后是从中断处继续的指令
+    /// Build the continuation request body: the content generated so far as an assistant message, followed by the
+    /// instruction to continue where it left off
+    pub fn continuation_body(&self, request_body: &serde_json::Value, received: &str) -> serde_json::Value {
+        let mut body = request_body.clone();
+        if let Some(messages) = body.get_mut("messages").and_then(|messages| messages.as_array_mut()) {
+            messages.push(json!({"role": "assistant", "content": received}));
+            messages.push(json!({"role": "user", "content": CONTINUATION_PROMPT}));
+        }
+        body
+    }
+
+    /// 请求失败时附加在错误报告上的来源信息
+    /// Provenance attached to the report when a request fails
+    ///
+    /// # 参数 (Parameters)
+    /// * `attempt` - 失败的尝试序号，从 1 开始，流式续传时递增
+    ///   - Number of the failed attempt, starting at 1 and increasing with stream resumes
+    pub fn provider_info(&self, attempt: u32) -> ProviderInfo {
+        ProviderInfo {
+            provider: provider_system(&self.base_url).to_string(),
+            api_name: self.api_name.clone(),
+            model: self.model.clone(),
+            capability: self.capability.clone(),
+            base_url: sanitize_url(&self.base_url),
+            attempt,
+        }
+    }
+
+    /// 为一次LLM调用创建追踪 span 与遥测记录，用量、延迟与结束原因在调用结束后填写
+    /// Create the tracing span and telemetry record of one LLM call, usage, latency and finish reason are filled in
+    /// when it ends
+    fn begin_llm_call(&mut self, request_body: &Arc<serde_json::Value>) -> (Span, LlmCall) {
+        let call = LlmCall {
+            request_id: self.next_request_id.take().unwrap_or_else(|| Uuid::new_v4().to_string()),
+            session_id: self.session_id.clone(),
+            model: self.model.clone(),
+            base_url: self.base_url.clone(),
+            capability: self.capability.clone(),
+            message_count: request_body["messages"].as_array().map_or(0, |messages| messages.len()),
+            stream: request_body["stream"].as_bool().unwrap_or(false),
+            tags: self.tags.clone(),
+            request_body: Arc::clone(request_body),
+            output: None,
+            response: None,
+            started_at: SystemTime::now(),
+            latency: Duration::ZERO,
+            time_to_first_token: None,
+            usage: None,
+            finish_reason: None,
+            error: None,
+            http_status: None,
+        };
+
+        let capability = call
+            .capability
+            .as_ref()
+            .map_or_else(|| "none".to_string(), |capability| format!("{:?}", capability));
+
+        let span = info_span!(
+            "llm_call",
+            request_id = %call.request_id,
+            model = %call.model,
+            capability = %capability,
+            message_count = call.message_count,
+            stream = call.stream,
+            prompt_tokens = field::Empty,
+            completion_tokens = field::Empty,
+            total_tokens = field::Empty,
+            latency_ms = field::Empty,
+            ttft_ms = field::Empty,
+            tokens_per_second = field::Empty,
+            finish_reason = field::Empty,
+            error = field::Empty,
+        );
+
+        self.events.emit(|| ChatEvent::RequestStarted {
+            request_id: call.request_id.clone(),
+            model: call.model.clone(),
+            message_count: call.message_count,
+            stream: call.stream,
+        });
+
+        (span, call)
+    }
+
+    /// 用流式回答的汇总结果结束一次LLM调用，`keep_content` 为 false 时不记录回答内容
+    /// End one LLM call with the collected streaming result, the answer content is not recorded if `keep_content`
+    /// is false
+    fn finish_stream_call(&mut self, span: &Span, mut call: LlmCall, started: Instant, output: &StreamOutput, keep_content: bool) {
+        if let Some(total_tokens) = output.usage.as_ref().and_then(|u| u["total_tokens"].as_i64()) {
+            self.usage += total_tokens as i32;
+        }
+        call.usage = output.usage.as_ref().and_then(TokenUsage::from_json);
+        call.finish_reason = output.finish_reason.clone();
+        call.output = keep_content.then(|| output.content.clone());
+        call.response = Some(json!({
+            "content": call.output,
+            "usage": output.usage,
+            "finish_reason": output.finish_reason,
+        }));
+        call.time_to_first_token = output.first_token_at.map(|at| at.duration_since(started));
+        self.finish_llm_call(span, call, started, None);
+        if let Some(truncated) = output.cap.truncated {
+            self.mark_truncated(truncated);
+        }
+        if let Some(refusal) = RefusalReason::from_finish(output.finish_reason.as_deref(), &output.refusal) {
+            self.mark_refused(refusal);
+        }
+    }
+
+    /// 结束一次LLM调用：写入追踪 span 字段并导出遥测记录
+    /// Finish one LLM call: record the tracing span fields and export the telemetry record
+    fn finish_llm_call(&mut self, span: &Span, mut call: LlmCall, started: Instant, error: Option<&ChatError>) {
+        call.latency = started.elapsed();
+        call.error = error.map(|error| error.to_string());
+        if let Some(ChatError::HttpError(status)) = error {
+            call.http_status = Some(*status);
+        }
+
+        span.record("latency_ms", call.latency.as_millis() as u64);
+        if let Some(ttft) = call.time_to_first_token {
+            span.record("ttft_ms", ttft.as_millis() as u64);
+        }
+        if let Some(tokens_per_second) = call.tokens_per_second() {
+            span.record("tokens_per_second", tokens_per_second);
+        }
+        if let Some(usage) = &call.usage {
+            span.record("prompt_tokens", usage.prompt_tokens);
+            span.record("completion_tokens", usage.completion_tokens);
+            span.record("total_tokens", usage.total_tokens);
+        }
+        if let Some(finish_reason) = &call.finish_reason {
+            span.record("finish_reason", finish_reason.as_str());
+        }
+        if let Some(error) = &call.error {
+            span.record("error", error.as_str());
+        }
+
+        self.last_call = call.error.is_none().then(|| MessageMetadata {
+            request_id: call.request_id.clone(),
+            model: call.model.clone(),
+            ttft_ms: call.time_to_first_token.map(|ttft| ttft.as_millis() as u64),
+            latency_ms: call.latency.as_millis() as u64,
+            prompt_tokens: call.usage.map(|usage| usage.prompt_tokens),
+            completion_tokens: call.usage.map(|usage| usage.completion_tokens),
+            downgrade: None,
+            moderation: Vec::new(),
+            truncated: None,
+            continuations: 0,
+            refusal: None,
+            token_probability: None,
+            provider_switches: Vec::new(),
+        });
+
+        match (&call.error, &call.output) {
+            (Some(error), _) => self.events.emit(|| ChatEvent::Error {
+                request_id: call.request_id.clone(),
+                message: error.clone(),
+            }),
+            (None, Some(content)) => self.events.emit(|| ChatEvent::AnswerReady {
+                request_id: call.request_id.clone(),
+                content: content.clone(),
+            }),
+            (None, None) => {}
+        }
+        self.events.emit(|| ChatEvent::GenerationFinished {
+            request_id: call.request_id.clone(),
+            at: SystemTime::now(),
+            duration: call.latency,
+            finish_reason: call.finish_reason.clone(),
+            error: call.error.clone(),
+        });
+
+        match &self.deferred_calls {
+            Some(deferred) => deferred.lock().unwrap().push(call),
+            None => self.record_call(call),
+        }
+    }
+
+    /// 将一次LLM调用计入租户用量并导出到遥测后端
+    /// Charge one LLM call to the tenant and export it to the telemetry backends
+    pub(crate) fn record_call(&self, call: LlmCall) {
+        if let Some(tenant) = &self.tenant {
+            tenant.record(&call);
+        }
+        export_llm_call(call);
+    }
+}
+
+/// 发出第一个 token 的事件，时长从请求开始算起
+/// Emit the first token event, its latency counted from the start of the request
+fn emit_first_token(events: &EventHandlers, request_id: &str, started: Instant) {
+    events.emit(|| ChatEvent::FirstToken {
+        request_id: request_id.to_string(),
+        at: SystemTime::now(),
+        latency: started.elapsed(),
+    });
+}
+
+/// 流式回答的汇总结果
+/// Collected result of a streaming answer
+#[derive(Debug, Clone, Default)]
+pub struct StreamOutput {
+    pub content: String,
+
+    pub usage: Option<serde_json::Value>,
+
+    pub finish_reason: Option<String>,
+
+    /// 拒绝说明，同时计入回答内容
+    /// Refusal explanation, also counted as answer content
+    pub refusal: String,
+
+    /// 收到第一个内容分块的时间
+    /// When the first content chunk arrived
+    pub first_token_at: Option<Instant>,
+
+    /// 输出上限的计数，到达上限后不再接收内容
+    /// Output cap accounting, no more content is taken once the cap is reached
+    pub cap: CapCounter,
+}
+
+impl StreamOutput {
+    /// 并入一个事件块，返回其中新增的回答内容；`keep_content` 为 false 时不保留内容
+    /// Merge one chunk, returning the answer content it adds; the content is not kept if `keep_content` is false
+    pub fn absorb(&mut self, chunk: StreamChunk, keep_content: bool) -> String {
+        self.refusal.push_str(&chunk.refusal);
+        let content = self.cap.admit(chunk.content + &chunk.refusal);
+        if !content.is_empty() {
+            self.first_token_at.get_or_insert_with(Instant::now);
+            if keep_content {
+                self.content.push_str(&content);
+            }
+        }
+        if chunk.finish_reason.is_some() {
+            self.finish_reason = chunk.finish_reason;
+        }
+        if chunk.usage.is_some() {
+            self.usage = chunk.usage;
+        }
+        content
+    }
+}
+
+/// 由响应中的 logprobs 计算回答的 token 几何平均概率，没有 logprobs 时返回 None
+/// Geometric mean token probability of the answer from the logprobs of the response, None without logprobs
+fn token_probability(response: &serde_json::Value) -> Option<Probability> {
+    let logprobs: Vec<f64> = response["choices"][0]["logprobs"]["content"]
+        .as_array()?
+        .iter()
+        .filter_map(|token| token["logprob"].as_f64())
+        .collect();
+    if logprobs.is_empty() {
+        return None;
+    }
+    Some(Probability::new((logprobs.iter().sum::<f64>() / logprobs.len() as f64).exp()))
+}
+
+/// 拼接续写的内容：续写开头重复了已有内容结尾的整行时去除重叠部分；重叠只从行首算起，
+/// 行内恰好相同的文字（如 "ha ha"）视为正常的重复而保留
+/// Stitch a continuation on: when it starts by repeating the end of the existing content from a line start, the
+/// overlap is removed; an overlap only counts from a line start, text that merely happens to match within a line
+/// (such as "ha ha") is kept as a legitimate repeat
+fn stitch_continuation(received: &str, part: &str) -> String {
+    let (shortest, longest) = CONTINUATION_OVERLAP;
+    let overlap = part
+        .char_indices()
+        .map(|(index, _)| index)
+        .chain([part.len()])
+        .skip(shortest)
+        .take_while(|&end| part[..end].chars().count() <= longest)
+        .filter(|&end| {
+            received
+                .strip_suffix(&part[..end])
+                .is_some_and(|before| before.is_empty() || before.ends_with('\n'))
+        })
+        .last()
+        .unwrap_or(0);
+    format!("{}{}", received, &part[overlap..])
+}
+
+/// 放出流式续写中暂存的一段：从 `start` 起的内容与之前的内容拼接并去除重叠，返回剩下的新增内容
+/// Release the held part of a streamed continuation: the content from `start` on is stitched onto the content before
+/// it without the overlap, returning what remains as new content
+fn release_continuation(content: &mut String, start: usize) -> String {
+    let part = content.split_off(start);
+    *content = stitch_continuation(content, &part);
+    content[start..].to_string()
+}
+
+/// 合计两段流式回答的用量，只在两者之一存在时返回
+/// Sum up the usage of two parts of a streamed answer, returned only if either exists
+fn add_usage(total: Option<serde_json::Value>, part: Option<serde_json::Value>) -> Option<serde_json::Value> {
+    let (mut total, part) = match (total, part) {
+        (Some(total), Some(part)) => (total, part),
+        (total, part) => return total.or(part),
+    };
+    for key in ["prompt_tokens", "completion_tokens", "total_tokens"] {
+        if let Some(tokens) = add_tokens(total[key].as_u64(), part[key].as_u64()) {
+            total[key] = json!(tokens);
+        }
+    }
+    Some(total)
+}
 
-        drop(semaphore_permit);
-        Ok(result.content)
+fn add_tokens(total: Option<u64>, part: Option<u64>) -> Option<u64> {
+    match (total, part) {
+        (None, None) => None,
+        (total, part) => Some(total.unwrap_or(0) + part.unwrap_or(0)),
     }
 }
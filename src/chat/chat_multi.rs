@@ -1,19 +1,40 @@
 use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
 
 use serde::de::DeserializeOwned;
 use serde_json::json;
 
 use error_stack::{Report, Result, ResultExt};
-use thiserror::Error;
 
+use futures::Stream;
 use tracing::info;
 
-use crate::chat::chat_base::{BaseChat, ChatError};
+use crate::chat::chat_base::{ApiBinding, BaseChat, ChatError, ModelOverride};
 use crate::chat::chat_tool::ChatTool;
+use crate::chat::context_provider::ContextProvider;
+use crate::chat::event::ChatEvent;
+use crate::chat::guardrail::Guardrails;
+use crate::chat::history::HistoryPolicy;
+use crate::chat::injection::InjectionScreen;
+use crate::chat::interceptor::RequestInterceptor;
+use crate::chat::language::LanguagePolicy;
 use crate::chat::message::Role;
+use crate::chat::middleware::AnswerCache;
+use crate::chat::moderation::{Moderation, ModerationStage};
+use crate::chat::output_cap::OutputCap;
+use crate::chat::pii::PiiScrubber;
+use crate::chat::queue::QueuePriority;
+use crate::chat::params::ChatParams;
+use crate::chat::persona::{Persona, load_personas};
+use crate::chat::tenant::Tenant;
+use crate::chat::transcript::{Perspective, observer_markdown, render_transcript};
 use crate::config::ModelCapability;
 use crate::prompt::assembler::assemble_output_description;
 use crate::schema::json_schema::JsonSchema;
+use crate::shutdown::admit_question;
+use crate::utils::common::redact::redact;
 
 #[derive(Debug, Clone)]
 pub struct MultiChat {
@@ -21,9 +42,12 @@ pub struct MultiChat {
 
     character_prompts: HashMap<String, String>,
 
+    /// 由人设创建的角色，轮到角色发言时换用其模型
+    /// Characters created from personas, switching to their model on their turn
+    personas: HashMap<String, Persona>,
+
     pub current_character: String,
 
-    need_stream: bool,
 }
 
 impl MultiChat {
@@ -37,10 +61,10 @@ impl MultiChat {
         }
 
         Ok(Self {
-            base: BaseChat::new_with_api_name(api_name, "", need_stream),
+            base: BaseChat::try_new_with_api_name(api_name, "", need_stream)?,
             character_prompts,
+            personas: HashMap::new(),
             current_character: String::new(),
-            need_stream,
         })
     }
 
@@ -54,24 +78,276 @@ impl MultiChat {
         }
 
         Ok(Self {
-            base: BaseChat::new_with_model_capability(model_capability, "", need_stream),
+            base: BaseChat::try_new_with_model_capability(model_capability, "", need_stream)?,
             character_prompts,
+            personas: HashMap::new(),
             current_character: String::new(),
-            need_stream,
         })
     }
 
+    /// 按人设创建角色阵容，角色的系统提示词包含语气与示例对话，轮到角色发言时使用其模型（见 `Persona::model`）
+    /// Create a cast from personas, the system prompt of each character carrying its voice and example dialogues,
+    /// and each character speaking on its own model (see `Persona::model`)
+    pub fn new_with_personas(personas: Vec<Persona>, need_stream: bool) -> Result<Self, ChatError> {
+        let Some(first) = personas.first() else {
+            return Err(Report::new(ChatError::NoCharacterPrompts));
+        };
+        let base = match first.model() {
+            ModelOverride::Api(api_name) => BaseChat::try_new_with_api_name(&api_name, "", need_stream)?,
+            ModelOverride::Capability(capability) => {
+                BaseChat::try_new_with_model_capability(capability, "", need_stream)?
+            }
+        };
+
+        let mut character_prompts = HashMap::with_capacity(personas.len());
+        let mut by_name = HashMap::with_capacity(personas.len());
+        for persona in personas {
+            if character_prompts.insert(persona.name.clone(), persona.prompt()).is_some() {
+                return Err(Report::new(ChatError::PersonaError(persona.name.clone()))
+                    .attach_printable(format!("Character {} is defined more than once", persona.name)));
+            }
+            by_name.insert(persona.name.clone(), persona);
+        }
+        Ok(Self {
+            base,
+            character_prompts,
+            personas: by_name,
+            current_character: String::new(),
+        })
+    }
+
+    /// 加载目录中的人设文件（见 `load_personas`），一次创建整个角色阵容
+    /// Load the persona files of a directory (see `load_personas`) and create the whole cast in one call
+    pub fn from_personas(dir: impl AsRef<Path>, need_stream: bool) -> Result<Self, ChatError> {
+        Self::new_with_personas(load_personas(dir)?, need_stream)
+    }
+
+    /// 由人设创建的角色的人设
+    /// Persona of a character created from one
+    pub fn persona(&self, character: &str) -> Option<&Persona> {
+        self.personas.get(character)
+    }
+
     pub fn set_character(&mut self, character: &str) -> Result<(), ChatError> {
         if !self.character_prompts.contains_key(character) {
             return Err(Report::new(ChatError::UndefinedCharacter(
                 character.to_owned(),
             )));
         }
+        if let Some(persona) = self.personas.get(character) {
+            self.base.bind_api(ApiBinding::resolve(&persona.model())?);
+        }
         self.current_character = character.to_owned();
         self.base.character_prompt = self.character_prompts[&self.current_character].clone();
         Ok(())
     }
 
+    /// 定义的角色名称，按名称排序
+    /// Names of the defined characters, sorted by name
+    pub fn characters(&self) -> Vec<&str> {
+        let mut characters: Vec<&str> = self.character_prompts.keys().map(String::as_str).collect();
+        characters.sort();
+        characters
+    }
+
+    /// 按视角渲染对话记录，见 [`render_transcript`]；角色未定义时返回错误
+    /// Render the transcript from a point of view, see [`render_transcript`]; fails if the character is undefined
+    pub fn transcript(&self, perspective: &Perspective) -> Result<serde_json::Value, ChatError> {
+        if let Perspective::Character(name) = perspective
+            && !self.character_prompts.contains_key(name)
+        {
+            return Err(Report::new(ChatError::UndefinedCharacter(name.clone())));
+        }
+        Ok(render_transcript(&self.base.session, perspective))
+    }
+
+    /// 导出时使用的全部视角：每个角色各一份，最后是旁观视角
+    /// Every point of view for exporting: one per character, then the observer view
+    pub fn transcripts(&self) -> Vec<serde_json::Value> {
+        self.characters()
+            .into_iter()
+            .map(|name| Perspective::Character(name.to_string()))
+            .chain([Perspective::Observer])
+            .map(|perspective| render_transcript(&self.base.session, &perspective))
+            .collect()
+    }
+
+    /// 旁观视角的 Markdown 文本，便于人工查看
+    /// Markdown text of the observer view, for human review
+    pub fn observer_markdown(&self) -> String {
+        observer_markdown(&self.base.session)
+    }
+
+    /// 注册事件处理函数，观察请求、流式内容、回答与错误
+    /// Register an event handler observing requests, streamed content, answers and errors
+    pub fn on_event(&mut self, handler: impl Fn(&ChatEvent) + Send + Sync + 'static) -> &mut Self {
+        self.base.events.add(Arc::new(handler));
+        self
+    }
+
+    /// 添加用户标签，用量按标签汇总到 `UsageAggregator`
+    /// Add a user tag, usage is totalled per tag in `UsageAggregator`
+    pub fn add_tag(&mut self, tag: &str) -> &mut Self {
+        self.base.tags.push(tag.to_string());
+        self
+    }
+
+    pub fn set_params(&mut self, overrides: &ChatParams) {
+        self.base.set_params(overrides);
+    }
+
+    /// 设置历史策略，控制哪些历史消息随请求发送
+    /// Set the history policy, controlling which history messages are sent with a request
+    pub fn set_history_policy(&mut self, policy: impl HistoryPolicy + 'static) -> &mut Self {
+        self.base.set_history_policy(policy);
+        self
+    }
+
+    /// 设置输入与回答的审核
+    /// Set the moderation of inputs and answers
+    pub fn set_moderation(&mut self, moderation: Moderation) -> &mut Self {
+        self.base.set_moderation(moderation);
+        self
+    }
+
+    /// 设置发送前的敏感信息清洗
+    /// Set the pre-send scrubbing of sensitive data
+    pub fn set_pii_scrubber(&mut self, scrubber: PiiScrubber) -> &mut Self {
+        self.base.set_pii_scrubber(scrubber);
+        self
+    }
+
+    /// 设置回答的检查规则
+    /// Set the guardrails checking answers
+    pub fn set_guardrails(&mut self, guardrails: Guardrails) -> &mut Self {
+        self.base.set_guardrails(guardrails);
+        self
+    }
+
+    /// 设置工具结果与检索片段的注入筛查
+    /// Set the injection screening of tool results and retrieved chunks
+    pub fn set_injection_screen(&mut self, screen: InjectionScreen) -> &mut Self {
+        self.base.set_injection_screen(screen);
+        self
+    }
+
+    /// 设置回答大小的硬上限
+    /// Set the hard cap on the answer size
+    pub fn set_output_cap(&mut self, cap: OutputCap) -> &mut Self {
+        self.base.set_output_cap(cap);
+        self
+    }
+
+    /// 设置回答语种的检查
+    /// Set the answer language check
+    pub fn set_language_policy(&mut self, policy: LanguagePolicy) -> &mut Self {
+        self.base.set_language_policy(policy);
+        self
+    }
+
+    /// 设置流式响应停滞的判定时间，超过该时间没有收到事件块时中止并续传
+    /// Set the stall timeout of streaming responses, a stream receiving no chunk for longer is aborted and resumed
+    pub fn set_stream_stall_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.base.set_stream_stall_timeout(timeout);
+        self
+    }
+
+    /// 设置回答因长度限制中断时的最大续写次数，续写的各段拼接为一个回答，默认不续写
+    /// Set the maximum number of continuations when an answer is cut off by the length limit, the parts are
+    /// stitched into one answer; answers are not continued by default
+    pub fn set_max_continuations(&mut self, max: u32) -> &mut Self {
+        self.base.set_max_continuations(max);
+        self
+    }
+
+    /// 添加应用状态的提供者，每次请求前查询，变量渲染进系统提示词模板，见 `ContextProvider`
+    /// Add a provider of application state, queried before every request with its variables rendered into the
+    /// system prompt template, see `ContextProvider`
+    pub fn add_context_provider(&mut self, provider: Arc<dyn ContextProvider>) -> &mut Self {
+        self.base.context_providers.push(provider);
+        self
+    }
+
+    /// 设置流式请求的备用API或能力，原API失败且无法续传时在本次请求内依次换用，换用记录在回答的元数据中
+    /// Set the fallback APIs or capabilities of streaming requests, switched to in order for the rest of the request
+    /// when the current API fails and cannot resume; switches are recorded in the answer metadata
+    pub fn set_stream_fallbacks(&mut self, fallbacks: Vec<ModelOverride>) -> &mut Self {
+        self.base.set_stream_fallbacks(fallbacks);
+        self
+    }
+
+    /// 设置额度不足时的排队键，如租户标签；同一键下的对话共同排队，默认每个对话单独排队
+    /// Set the queue key used when permits run short, such as a tenant tag; chats sharing a key queue together,
+    /// each chat queues on its own by default
+    pub fn set_queue_key(&mut self, key: &str) -> &mut Self {
+        self.base.set_queue_key(key);
+        self
+    }
+
+    /// 设置额度不足时的排队优先级，批量任务可设为 `Background`，交互对话可设为 `Interactive`
+    /// Set the queueing priority when permits run short, such as `Background` for bulk work and `Interactive` for
+    /// chats a user is waiting on
+    pub fn set_queue_priority(&mut self, priority: QueuePriority) -> &mut Self {
+        self.base.set_queue_priority(priority);
+        self
+    }
+
+    /// 设置对话所属的租户，预算与频率上限随之生效
+    /// Set the tenant owning the chat, enforcing its budget and rate limit
+    pub fn set_tenant(&mut self, tenant: Tenant) -> &mut Self {
+        self.base.set_tenant(tenant);
+        self
+    }
+
+    /// 设置回答缓存，完全相同的请求直接使用缓存的响应，见 `AnswerCache`
+    /// Set the answer cache, identical requests use the cached response directly, see `AnswerCache`
+    pub fn set_answer_cache(&mut self, cache: AnswerCache) -> &mut Self {
+        self.base.answer_cache = Some(cache);
+        self
+    }
+
+    /// 添加请求拦截器，在请求发出前检查或修改请求体与请求头
+    /// Add a request interceptor, inspecting or changing the body and headers just before a request is sent
+    pub fn add_request_interceptor(&mut self, interceptor: impl RequestInterceptor + 'static) -> &mut Self {
+        self.base.add_request_interceptor(interceptor);
+        self
+    }
+
+    /// 设置携带请求ID的请求头，为 None 时不发送
+    /// Set the header carrying the request ID, not sent if None
+    pub fn set_request_id_header(&mut self, header: Option<&str>) -> &mut Self {
+        self.base.set_request_id_header(header);
+        self
+    }
+
+    /// 指定下一次请求的ID，如上游服务传入的追踪ID
+    /// Set the ID of the next request, such as a trace ID passed in by an upstream service
+    pub fn set_request_id(&mut self, request_id: &str) -> &mut Self {
+        self.base.set_request_id(request_id);
+        self
+    }
+
+    /// 最新一条消息的ID，可供外部系统长期引用
+    /// ID of the latest message, a durable reference for external systems
+    pub fn last_message_id(&self) -> Option<&str> {
+        self.base.session.last_message_id()
+    }
+
+    /// 从ID对应的消息处分支提问，新的问答成为当前路径
+    /// Ask a question branching off the message with the ID, the new exchange becomes the current path
+    pub async fn get_answer_at(&mut self, message_id: &str, user_input: &str) -> Result<String, ChatError> {
+        self.base.session.checkout(message_id).change_context(ChatError::SessionError)?;
+        self.get_answer(user_input).await
+    }
+
+    /// 复制对话并定位到ID对应的消息，副本中的后续提问不影响原对话
+    /// Copy the chat positioned at the message with the ID, later questions on the copy do not affect the original
+    pub fn fork_at_id(&self, message_id: &str) -> Result<Self, ChatError> {
+        let mut fork = self.clone();
+        fork.base.session.checkout(message_id).change_context(ChatError::SessionError)?;
+        Ok(fork)
+    }
+
     pub fn add_user_message(&mut self, content: &str) -> Result<(), ChatError> {
         self.base.add_message(Role::User, content)
     }
@@ -94,6 +370,7 @@ impl MultiChat {
         parent_path: &[usize],
         user_input: &str,
     ) -> Result<serde_json::Value, ChatError> {
+        admit_question()?;
         if self.current_character.is_empty() {
             return Err(Report::new(ChatError::NoCharacterSelected));
         }
@@ -101,11 +378,9 @@ impl MultiChat {
         self.base
             .add_message_with_parent_path(parent_path, Role::User, user_input)?;
 
-        let character_role = Role::Character(self.current_character.clone());
-
-        Ok(self
-            .base
-            .build_request_body(&self.base.session.default_path.clone(), &character_role)?)
+        let mut request_body = self.build_character_request_body(&self.base.session.default_path.clone())?;
+        self.base.render_context(&mut request_body, true).await?;
+        Ok(request_body)
     }
 
     pub async fn get_req_body_again(
@@ -116,51 +391,53 @@ impl MultiChat {
             return Err(Report::new(ChatError::NoCharacterSelected));
         }
 
-        let character_role = Role::Character(self.current_character.clone());
+        let mut request_body = self.build_character_request_body(end_path)?;
+        self.base.render_context(&mut request_body, true).await?;
+        Ok(request_body)
+    }
 
-        Ok(self.base.build_request_body(end_path, &character_role)?)
+    /// 以当前角色为发言者组装请求，角色的提示词作为系统消息放在对话共用的系统消息之后
+    /// Assemble the request with the current character as the speaker, its prompt going in as a system message after
+    /// the system messages shared by the conversation
+    fn build_character_request_body(&self, end_path: &[usize]) -> Result<serde_json::Value, ChatError> {
+        let character_role = Role::Character(self.current_character.clone());
+        let mut request_body = self.base.build_request_body(end_path, &character_role)?;
+        if !self.base.character_prompt.is_empty()
+            && let Some(messages) = request_body["messages"].as_array_mut()
+        {
+            let position = messages.iter().take_while(|message| message["role"] == "system").count();
+            messages.insert(position, json!({"role": "system", "content": self.base.character_prompt}));
+        }
+        Ok(request_body)
     }
 
     pub async fn get_req_body(&mut self, user_input: &str) -> Result<serde_json::Value, ChatError> {
-        info!("path: {:?}", self.base.session.default_path.clone());
-        self.get_req_body_with_new_question(&self.base.session.default_path.clone(), user_input)
-            .await
+        admit_question()?;
+        info!(path = ?self.base.session.default_path, "Adding question");
+        let user_input = &self.base.scrub_pii(user_input).await?;
+        let screened = self.base.moderate(ModerationStage::Input, user_input).await?;
+        let user_input = screened.replacement.as_deref().unwrap_or(user_input);
+        let request_body = self
+            .get_req_body_with_new_question(&self.base.session.default_path.clone(), user_input)
+            .await?;
+        self.base.annotate_last_message(screened.categories)?;
+        Ok(request_body)
     }
 
     async fn get_content_from_req_body(
         &mut self,
         request_body: serde_json::Value,
     ) -> Result<String, ChatError> {
-        let content = if self.need_stream {
-            let (stream, semaphore_permit) = self
-                .base
-                .get_stream_response(request_body.clone())
-                .await
-                .attach_printable("Failed to get stream response")?;
-
-            BaseChat::get_content_from_stream_resp(stream, semaphore_permit)
-                .await
-                .attach_printable("Failed to extract content from stream response")?
-        } else {
-            let response = self
-                .base
-                .get_response(request_body.clone())
-                .await
-                .attach_printable("Failed to get response")?;
-
-            BaseChat::get_content_from_resp(&response)
-                .attach_printable("Failed to extract content from response")?
-        };
+        let content = self
+            .base
+            .get_content(request_body)
+            .await
+            .attach_printable("Failed to get LLM answer")?;
 
-        info!(
-            "GetLLMAPIAnswer from {}: {}",
-            self.current_character, content
-        );
+        info!(character = %self.current_character, answer = %redact(&content), "Received LLM answer");
 
         let character_role = Role::Character(self.current_character.clone());
-        self.base.add_message(character_role, &content)?;
-
-        Ok(content)
+        self.base.add_screened_answer(character_role, content).await
     }
 
     pub async fn get_answer(&mut self, user_input: &str) -> Result<String, ChatError> {
@@ -170,14 +447,44 @@ impl MultiChat {
 
         let request_body = self.get_req_body(user_input).await?;
 
-        self.get_content_from_req_body(request_body).await
+        let answer = self.get_content_from_req_body(request_body).await?;
+        Ok(self.base.restore_pii(answer))
+    }
+
+    /// 以当前角色提问并以流的形式逐段取回回答，回答在流结束后加入对话历史
+    /// Ask the current character and receive the answer piece by piece as a stream, the answer joins the history
+    /// once the stream ends
+    pub async fn stream_answer(
+        &mut self,
+        user_input: &str,
+    ) -> Result<impl Stream<Item = Result<String, ChatError>> + Send + '_, ChatError> {
+        if self.current_character.is_empty() {
+            return Err(Report::new(ChatError::NoCharacterSelected));
+        }
+
+        let mut request_body = self.get_req_body(user_input).await?;
+        request_body["stream"] = json!(true);
+        let character_role = Role::Character(self.current_character.clone());
+        Ok(self.base.stream_content(request_body, Some(character_role)))
     }
 
     pub async fn get_json_answer<T: DeserializeOwned + 'static + JsonSchema>(
         &mut self,
         user_input: &str,
     ) -> Result<T, ChatError> {
-        let schema = T::json_schema();
+        let value = self.get_json_answer_with_schema(user_input, T::json_schema()).await?;
+        serde_json::from_value(value)
+            .change_context(ChatError::GetJsonError)
+            .attach_printable("Answer does not match the requested type")
+    }
+
+    /// 按运行时提供的JSON Schema取回结构化回答
+    /// Get a structured answer for a JSON Schema given at runtime
+    pub async fn get_json_answer_with_schema(
+        &mut self,
+        user_input: &str,
+        schema: serde_json::Value,
+    ) -> Result<serde_json::Value, ChatError> {
 
         let output_description = assemble_output_description(schema.clone())
             .change_context(ChatError::AssembleOutputDescriptionError)
@@ -190,11 +497,52 @@ impl MultiChat {
         self.base
             .add_message(Role::System, output_description.as_str())?;
 
-        let answer = self.get_answer(user_input).await?;
+        let Some(grammar) = self.base.schema_grammar(&schema) else {
+            let answer = self.get_answer(user_input).await?;
+            return ChatTool::get_json::<serde_json::Value>(&answer, schema)
+                .await
+                .and_then(|value| self.base.check_converted_json(value))
+                .attach_printable(redact(&format!("Failed to parse answer as JSON: {}", answer)));
+        };
 
-        ChatTool::get_json::<T>(&answer, schema)
-            .await
-            .attach_printable(format!("Failed to parse answer as JSON: {}", answer))
+        if self.current_character.is_empty() {
+            return Err(Report::new(ChatError::NoCharacterSelected));
+        }
+        let mut request_body = self.get_req_body(user_input).await?;
+        request_body["grammar"] = json!(grammar);
+        let answer = self.get_content_from_req_body(request_body).await?;
+
+        // 受语法约束的回答已是合法JSON，无需再转换
+        // An answer constrained by the grammar is valid JSON already and needs no conversion
+        let mut value: serde_json::Value = serde_json::from_str(&answer)
+            .change_context(ChatError::GetJsonError)
+            .attach_printable(redact(&format!("Failed to parse answer as JSON: {}", answer)))?;
+        if let Some(scrubber) = &self.base.pii {
+            scrubber.restore_json(&mut value);
+        }
+        Ok(value)
+    }
+
+    /// 指定角色接着对话发言，不加入新的用户消息；其他角色的发言以 `<name> said: ` 的形式交给它
+    /// The named character speaks next without a new user message; lines of other characters reach it as
+    /// `<name> said: `
+    pub async fn turn(&mut self, character: &str) -> Result<String, ChatError> {
+        admit_question()?;
+        self.set_character(character)?;
+        let request_body = self.get_req_body_again(&self.base.session.default_path.clone()).await?;
+        let answer = self.get_content_from_req_body(request_body).await?;
+        Ok(self.base.restore_pii(answer))
+    }
+
+    /// 所有角色按名称顺序各发言一次，返回各自的回答
+    /// Every character speaks once in order of name, returning their answers
+    pub async fn round(&mut self) -> Result<Vec<String>, ChatError> {
+        let characters: Vec<String> = self.characters().into_iter().map(str::to_string).collect();
+        let mut answers = Vec::with_capacity(characters.len());
+        for character in characters {
+            answers.push(self.turn(&character).await?);
+        }
+        Ok(answers)
     }
 
     pub async fn dialogue(
@@ -0,0 +1,92 @@
+// 标准库
+use std::future::Future;
+
+// 序列化
+use serde::de::DeserializeOwned;
+
+// 错误处理
+use error_stack::Result;
+
+// 异步
+use futures::Stream;
+
+// 项目内部模块
+use crate::chat::chat_base::ChatError;
+use crate::chat::chat_multi::MultiChat;
+use crate::chat::chat_single::SingleChat;
+use crate::schema::json_schema::JsonSchema;
+
+/// 各类对话的共同接口，应用代码和测试可以对具体的对话类型保持泛型
+/// Common interface of every chat type, so application code and tests can stay generic over the chat
+/// implementation
+pub trait ChatSession: Send {
+    /// 提问并取回回答，问答加入对话历史
+    /// Ask a question and return the answer, both join the chat history
+    fn get_answer(&mut self, user_input: &str) -> impl Future<Output = Result<String, ChatError>> + Send;
+
+    /// 提问并将回答解析为结构化数据
+    /// Ask a question and parse the answer into structured data
+    fn get_json_answer<T: DeserializeOwned + JsonSchema + Send + 'static>(
+        &mut self,
+        user_input: &str,
+    ) -> impl Future<Output = Result<T, ChatError>> + Send;
+
+    /// 提问并以流的形式逐段取回回答
+    /// Ask a question and receive the answer piece by piece as a stream
+    fn stream_answer(
+        &mut self,
+        user_input: &str,
+    ) -> impl Future<Output = Result<impl Stream<Item = Result<String, ChatError>> + Send + '_, ChatError>> + Send;
+
+    /// 对话累计消耗的token数
+    /// Tokens consumed by the chat so far
+    fn usage(&self) -> i32;
+}
+
+impl ChatSession for SingleChat {
+    fn get_answer(&mut self, user_input: &str) -> impl Future<Output = Result<String, ChatError>> + Send {
+        SingleChat::get_answer(self, user_input)
+    }
+
+    fn get_json_answer<T: DeserializeOwned + JsonSchema + Send + 'static>(
+        &mut self,
+        user_input: &str,
+    ) -> impl Future<Output = Result<T, ChatError>> + Send {
+        SingleChat::get_json_answer(self, user_input)
+    }
+
+    fn stream_answer(
+        &mut self,
+        user_input: &str,
+    ) -> impl Future<Output = Result<impl Stream<Item = Result<String, ChatError>> + Send + '_, ChatError>> + Send {
+        SingleChat::stream_answer(self, user_input)
+    }
+
+    fn usage(&self) -> i32 {
+        self.base.usage
+    }
+}
+
+impl ChatSession for MultiChat {
+    fn get_answer(&mut self, user_input: &str) -> impl Future<Output = Result<String, ChatError>> + Send {
+        MultiChat::get_answer(self, user_input)
+    }
+
+    fn get_json_answer<T: DeserializeOwned + JsonSchema + Send + 'static>(
+        &mut self,
+        user_input: &str,
+    ) -> impl Future<Output = Result<T, ChatError>> + Send {
+        MultiChat::get_json_answer(self, user_input)
+    }
+
+    fn stream_answer(
+        &mut self,
+        user_input: &str,
+    ) -> impl Future<Output = Result<impl Stream<Item = Result<String, ChatError>> + Send + '_, ChatError>> + Send {
+        MultiChat::stream_answer(self, user_input)
+    }
+
+    fn usage(&self) -> i32 {
+        self.base.usage
+    }
+}
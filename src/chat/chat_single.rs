@@ -1,20 +1,84 @@
+use std::fmt::Debug;
+use std::pin::pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use serde::Serialize;
 use serde::de::DeserializeOwned;
 use serde_json::json;
 
 use error_stack::{Report, Result, ResultExt};
 use thiserror::Error;
 
+use bytes::Bytes;
+use futures::{Stream, StreamExt, TryStreamExt, stream};
 use tokio::task;
+use uuid::Uuid;
 
-use tracing::log::info;
+use tracing::{info, warn};
 
-use crate::chat::chat_base::{BaseChat, ChatError};
-use crate::chat::chat_tool::ChatTool;
-use crate::chat::message::Role;
-use crate::config::ModelCapability;
+use crate::chat::agent::{
+    AgentRun, AgentScope, AgentStop, Deadline, MAX_AGENT_STEPS, StepDecision, TraceLlmCall, TraceStep, TraceToolCall,
+    request_hash,
+};
+use crate::chat::attachment::{Attachment, AttachmentLimits, insert_attachment_list};
+use crate::chat::broadcast::StreamBroadcast;
+use crate::chat::builder::SingleChatBuilder;
+use crate::chat::chat_base::{ApiBinding, BaseChat, ChatError, ModelOverride};
+use crate::chat::chat_tool::{ChatTool, ToolChoice};
+use crate::chat::checkpoint::Checkpoint;
+use crate::chat::compact::{CompactReport, compact_session};
+use crate::chat::content::Content;
+use crate::chat::image::take_tool_images;
+use crate::chat::event::{ChatEvent, EventHandlers, EventSource};
+use crate::chat::confidence::{ConfidenceReport, ConfidenceScorer, ScoredAnswer};
+use crate::chat::context_provider::ContextProvider;
+use crate::chat::extraction::{DocumentExtraction, reduce};
+use crate::chat::faithfulness::{FaithfulnessChecker, VerifiedAnswer, regeneration_instruction};
+use crate::chat::guardrail::Guardrails;
+use crate::chat::history::HistoryPolicy;
+use crate::chat::hot_reload::HotReload;
+use crate::chat::injection::InjectionScreen;
+use crate::chat::interceptor::RequestInterceptor;
+use crate::chat::json_lines::{JSON_LINES_INSTRUCTION, JsonLines};
+use crate::chat::language::LanguagePolicy;
+use crate::chat::middleware::{AnswerCache, AnswerMiddleware, Next};
+use crate::chat::message::{Probability, Role, Session};
+use crate::chat::moderation::{Moderation, ModerationStage, Screened};
+use crate::chat::output_cap::OutputCap;
+use crate::chat::pii::PiiScrubber;
+use crate::chat::postprocess::PostProcessors;
+use crate::chat::queue::QueuePriority;
+use crate::chat::preview::{Estimate, RequestPreview};
+use crate::chat::params::ChatParams;
+use crate::chat::artifacts::ParsedAnswer;
+use crate::chat::citation::CitedAnswer;
+use crate::chat::refusal::AnswerOutcome;
+use crate::chat::retriever::{Retriever, Source, insert_cited_context, insert_context};
+use crate::chat::scratchpad::{Scratchpad, scratchpad_tools};
+use crate::chat::shadow::Shadow;
+use crate::chat::speculation::{SpeculativeAnswer, Speculation};
+use crate::chat::summary::{
+    ChatMetadata, HandoverBrief, TITLE_MAX_CHARS, ask_cheap_model, clean_title, handover_brief, rate_salience,
+};
+use crate::chat::tenant::Tenant;
+use crate::chat::tool_source::{ResolvedTool, ToolRoutes, ToolSource, collect_tools};
+use crate::chat::transform::StreamTransformers;
+use crate::chat::transaction::{IntentStatus, ToolIntent, ToolJournal, TransactionScope};
+use crate::chat::trigger::{ToolTrigger, insert_tool_results};
+use crate::config::prompts::PromptKey;
+use crate::config::{Config, ModelCapability};
+use crate::documents::{Chunker, Document};
 use crate::prompt::assembler::{assemble_output_description, assemble_tools_prompt};
 use crate::schema::json_schema::JsonSchema;
+use crate::schema::tool_error::{ToolError, ToolErrorType};
+use crate::schema::tool_docs::register_tool_definition;
 use crate::schema::tool_schema::extract_tool_uses;
+use crate::shutdown::{admit_question, track_tool, within_started_answer};
+use crate::telemetry::export_tool_call;
+use crate::telemetry::exporter::ToolCallRecord;
+use crate::utils::common::redact::redact;
+use crate::utils::common::tokens::{Tokenizer, tokenizer_for};
 
 #[derive(Debug, Error)]
 pub enum ToolCallError {
@@ -41,39 +105,711 @@ pub enum ToolCallError {
 
     #[error("Missing field: {0}")]
     MissingField(String),
+
+    #[error("Failed to record tool calls in the session")]
+    RecordToolCalls,
+
+    #[error("Transaction '{0}' aborted while committing")]
+    TransactionAborted(String),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct SingleChat {
     pub base: BaseChat,
 
-    need_stream: bool,
+    tools_schema: Arc<Vec<serde_json::Value>>,
+
+    /// 来自工具来源的工具的路由，见 `set_tool_sources`
+    /// Routes of the tools coming from tool sources, see `set_tool_sources`
+    tool_routes: ToolRoutes,
+
+    /// 工具选择方式，为 None 时由提供商决定（通常为自动）
+    /// How tools are picked, left to the provider (usually automatic) if None
+    tool_choice: Option<ToolChoice>,
+
+    /// 是否允许一轮回答中调用多个工具，为 None 时由提供商决定
+    /// Whether one answer may call several tools, left to the provider if None
+    parallel_tool_calls: Option<bool>,
+
+    speculation: Option<Speculation>,
+
+    retriever: Option<Arc<dyn Retriever>>,
+
+    /// 带资料回答的忠实度检查，见 `get_verified_answer`
+    /// Faithfulness check of answers grounded on material, see `get_verified_answer`
+    faithfulness: Option<FaithfulnessChecker>,
+
+    /// 最近一次检索到的资料，按随请求发送的编号排列
+    /// Material of the latest retrieval, in the numbering sent with the request
+    sources: Vec<Source>,
+
+    /// 等待随下一次提问附上的附件
+    /// Attachments waiting to go with the next question
+    pending_attachments: Vec<Attachment>,
+
+    attachment_limits: AttachmentLimits,
+
+    checkpoints: Vec<Checkpoint>,
+
+    /// 由用户输入触发的工具调用，见 `add_tool_trigger`
+    /// Tool calls triggered by the user input, see `add_tool_trigger`
+    triggers: Vec<ToolTrigger>,
 
-    tools_schema: Vec<serde_json::Value>,
+    /// 包裹 `get_answer` 的中间件，见 `add_middleware`
+    /// Middlewares wrapping `get_answer`, see `add_middleware`
+    pub(crate) middlewares: Vec<Arc<dyn AnswerMiddleware>>,
+
+    /// 复制给候选模型的影子流量，见 `set_shadow`
+    /// Shadow traffic copied to candidate models, see `set_shadow`
+    shadow: Option<Shadow>,
+
+    /// 回答的置信度评估，见 `get_scored_answer`
+    /// Confidence estimation of answers, see `get_scored_answer`
+    confidence: Option<ConfidenceScorer>,
+
+    /// `get_scored_answer` 进行中时为 Some，最内层在其中记下发给模型的请求体
+    /// Some while `get_scored_answer` runs, the innermost layer records the request body sent to the model in it
+    scored_request: Option<Option<serde_json::Value>>,
+
+    /// 开发模式的热重载，见 `set_hot_reload`
+    /// Hot reload of the development mode, see `set_hot_reload`
+    hot_reload: Option<HotReload>,
+
+    /// 已应用的热重载版本
+    /// Generation of the hot reload already applied
+    reload_generation: u64,
+
+    /// 智能体运行的事务作用域，见 `set_transaction`
+    /// Transaction scope of agent runs, see `set_transaction`
+    transaction: Option<TransactionScope>,
+
+    /// 智能体运行最多的步数，见 `set_max_agent_steps`
+    /// Maximum steps of an agent run, see `set_max_agent_steps`
+    max_agent_steps: usize,
+
+    /// 是否提供草稿本工具，见 `enable_scratchpad`
+    /// Whether the scratchpad tools are offered, see `enable_scratchpad`
+    scratchpad: bool,
+}
+
+impl Debug for SingleChat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SingleChat")
+            .field("base", &self.base)
+            .field("tools_schema", &self.tools_schema)
+            .field("tool_routes", &self.tool_routes)
+            .field("tool_choice", &self.tool_choice)
+            .field("parallel_tool_calls", &self.parallel_tool_calls)
+            .field("speculation", &self.speculation)
+            .field("retriever", &self.retriever.is_some())
+            .field("faithfulness", &self.faithfulness)
+            .field("sources", &self.sources.len())
+            .field("pending_attachments", &self.pending_attachments)
+            .field("attachment_limits", &self.attachment_limits)
+            .field("checkpoints", &self.checkpoints.len())
+            .field("triggers", &self.triggers)
+            .field("middlewares", &self.middlewares.len())
+            .field("shadow", &self.shadow)
+            .field("confidence", &self.confidence)
+            .field("hot_reload", &self.hot_reload)
+            .field("transaction", &self.transaction)
+            .field("max_agent_steps", &self.max_agent_steps)
+            .field("scratchpad", &self.scratchpad)
+            .finish()
+    }
 }
 
 impl SingleChat {
-    pub fn new_with_api_name(api_name: &str, character_prompt: &str, need_stream: bool) -> Self {
-        let base = BaseChat::new_with_api_name(api_name, character_prompt, need_stream);
+    /// 创建对话构建器
+    /// Create a chat builder
+    pub fn builder() -> SingleChatBuilder {
+        SingleChatBuilder::new()
+    }
+
+    pub fn from_base(base: BaseChat) -> Self {
         Self {
             base,
-            need_stream,
-            tools_schema: Vec::new(),
+            tools_schema: Arc::default(),
+            tool_routes: ToolRoutes::default(),
+            tool_choice: None,
+            parallel_tool_calls: None,
+            speculation: None,
+            retriever: None,
+            faithfulness: None,
+            sources: Vec::new(),
+            pending_attachments: Vec::new(),
+            attachment_limits: AttachmentLimits::default(),
+            checkpoints: Vec::new(),
+            triggers: Vec::new(),
+            middlewares: Vec::new(),
+            shadow: None,
+            confidence: None,
+            scored_request: None,
+            hot_reload: None,
+            reload_generation: 0,
+            transaction: None,
+            max_agent_steps: MAX_AGENT_STEPS,
+            scratchpad: false,
         }
     }
 
+    /// 按API名称创建对话
+    /// Create a chat by API name
+    ///
+    /// # Panics
+    /// API不存在时 panic，需要处理错误时使用 `try_new_with_api_name`
+    /// Panics if the API does not exist, use `try_new_with_api_name` to handle the error
+    pub fn new_with_api_name(api_name: &str, character_prompt: &str, need_stream: bool) -> Self {
+        Self::from_base(BaseChat::new_with_api_name(api_name, character_prompt, need_stream))
+    }
+
+    /// 按API名称创建对话，API不存在时返回错误
+    /// Create a chat by API name, failing if the API does not exist
+    pub fn try_new_with_api_name(api_name: &str, character_prompt: &str, need_stream: bool) -> Result<Self, ChatError> {
+        Ok(Self::from_base(BaseChat::try_new_with_api_name(api_name, character_prompt, need_stream)?))
+    }
+
+    /// 按模型能力创建对话，没有API绑定该能力时返回错误
+    /// Create a chat by model capability, failing if no API is bound to the capability
+    pub fn try_new_with_model_capability(
+        model_capability: ModelCapability,
+        character_prompt: &str,
+        need_stream: bool,
+    ) -> Result<Self, ChatError> {
+        Ok(Self::from_base(BaseChat::try_new_with_model_capability(
+            model_capability,
+            character_prompt,
+            need_stream,
+        )?))
+    }
+
+    /// 按模型能力创建对话
+    /// Create a chat by model capability
+    ///
+    /// # Panics
+    /// 没有API绑定该能力时 panic，需要处理错误时使用 `try_new_with_model_capability`
+    /// Panics if no API is bound to the capability, use `try_new_with_model_capability` to handle the error
     pub fn new_with_model_capability(
         model_capability: ModelCapability,
         character_prompt: &str,
         need_stream: bool,
     ) -> Self {
-        let base =
-            BaseChat::new_with_model_capability(model_capability, character_prompt, need_stream);
-        Self {
-            base,
-            need_stream,
-            tools_schema: Vec::new(),
+        Self::from_base(BaseChat::new_with_model_capability(model_capability, character_prompt, need_stream))
+    }
+
+    /// 设置检索器，每次提问时检索相关资料并随请求发送
+    /// Set the retriever, relevant material is retrieved for every question and sent with the request
+    pub fn set_retriever(&mut self, retriever: Arc<dyn Retriever>) -> &mut Self {
+        self.retriever = Some(retriever);
+        self
+    }
+
+    /// 添加由用户输入触发的工具调用：提问时输入匹配的触发器直接调用工具，结果随请求发给模型，不经模型选择
+    /// Add a tool call triggered by the user input: on a question, matching triggers call their tool directly and
+    /// the results go to the model with the request, without the model choosing the tool
+    pub fn add_tool_trigger(&mut self, trigger: ToolTrigger) -> &mut Self {
+        self.triggers.push(trigger);
+        self
+    }
+
+    /// 添加包裹 `get_answer` 的中间件，先添加的在外层
+    /// Add a middleware wrapping `get_answer`, the ones added first are the outer layers
+    pub fn add_middleware(&mut self, middleware: impl AnswerMiddleware + 'static) -> &mut Self {
+        self.middlewares.push(Arc::new(middleware));
+        self
+    }
+
+    /// 设置回答缓存，完全相同的请求直接使用缓存的响应，见 `AnswerCache`
+    /// Set the answer cache, identical requests use the cached response directly, see `AnswerCache`
+    pub fn set_answer_cache(&mut self, cache: AnswerCache) -> &mut Self {
+        self.base.answer_cache = Some(cache);
+        self
+    }
+
+    /// 设置回答的忠实度检查，对 `get_verified_answer` 生效
+    /// Set the faithfulness check of answers, used by `get_verified_answer`
+    pub fn set_faithfulness(&mut self, checker: FaithfulnessChecker) -> &mut Self {
+        self.faithfulness = Some(checker);
+        self
+    }
+
+    /// 设置影子流量，按比例把 `get_answer` 的请求在后台复制给候选模型并记录两边的回答
+    /// Set shadow traffic, copying a share of the `get_answer` requests to candidate models in the background and
+    /// logging both answers
+    pub fn set_shadow(&mut self, shadow: Shadow) -> &mut Self {
+        self.shadow = Some(shadow);
+        self
+    }
+
+    /// 设置回答的置信度评估，对 `get_scored_answer` 生效
+    /// Set the confidence estimation of answers, used by `get_scored_answer`
+    pub fn set_confidence(&mut self, scorer: ConfidenceScorer) -> &mut Self {
+        self.confidence = Some(scorer);
+        self
+    }
+
+    /// 设置开发模式的热重载，每次提问前应用监视的文件的最新版本，见 `HotReload`
+    /// Set hot reload for development, the latest version of the watched files is applied before every question,
+    /// see `HotReload`
+    pub fn set_hot_reload(&mut self, reload: HotReload) -> &mut Self {
+        self.hot_reload = Some(reload);
+        self
+    }
+
+    /// 设置智能体运行的事务作用域，`run_agent` 中对有副作用的工具的调用在运行成功结束后才执行，见 `TransactionScope`
+    /// Set the transaction scope of agent runs, calls to tools with side effects in `run_agent` only run once the
+    /// run completes successfully, see `TransactionScope`
+    pub fn set_transaction(&mut self, scope: TransactionScope) -> &mut Self {
+        self.transaction = Some(scope);
+        self
+    }
+
+    /// 设置智能体运行最多的步数，每步是一次模型调用及其发起的工具调用，默认 `MAX_AGENT_STEPS` 步
+    /// Set the maximum steps of an agent run, each step being one model call and the tool calls it makes,
+    /// `MAX_AGENT_STEPS` steps by default
+    pub fn set_max_agent_steps(&mut self, steps: usize) -> &mut Self {
+        self.max_agent_steps = steps;
+        self
+    }
+
+    /// 添加应用状态的提供者，每次请求前查询，变量渲染进系统提示词模板，见 `ContextProvider`
+    /// Add a provider of application state, queried before every request with its variables rendered into the
+    /// system prompt template, see `ContextProvider`
+    pub fn add_context_provider(&mut self, provider: Arc<dyn ContextProvider>) -> &mut Self {
+        self.base.context_providers.push(provider);
+        self
+    }
+
+    /// 提供 `scratchpad_write` 与 `scratchpad_read` 工具，模型可在多轮之间把中间笔记写入会话的草稿本，
+    /// 笔记不进入提示词，需要时再读取，见 `Scratchpad`；之后设置的工具也会带上这两个工具
+    /// Offer the `scratchpad_write` and `scratchpad_read` tools, letting the model keep intermediate notes in the
+    /// session's scratchpad across turns, out of the prompt until read back, see `Scratchpad`; tools set later keep
+    /// these two as well
+    pub fn enable_scratchpad(&mut self) -> Result<(), ChatError> {
+        self.scratchpad = true;
+        self.replace_tools(self.tools_schema.to_vec())
+    }
+
+    /// 会话的草稿本，应用也可以直接读写
+    /// The session's scratchpad, which the app may read and write directly too
+    pub fn scratchpad(&self) -> &Scratchpad {
+        &self.base.session.scratchpad
+    }
+
+    /// 设置附件的大小与数量上限
+    /// Set the size and count limits of attachments
+    pub fn set_attachment_limits(&mut self, limits: AttachmentLimits) -> &mut Self {
+        self.attachment_limits = limits;
+        self
+    }
+
+    /// 注册事件处理函数，观察请求、流式内容、工具调用、回答与错误
+    /// Register an event handler observing requests, streamed content, tool calls, answers and errors
+    pub fn on_event(&mut self, handler: impl Fn(&ChatEvent) + Send + Sync + 'static) -> &mut Self {
+        self.base.events.add(Arc::new(handler));
+        self
+    }
+
+    /// 添加用户标签，用量按标签汇总到 `UsageAggregator`
+    /// Add a user tag, usage is totalled per tag in `UsageAggregator`
+    pub fn add_tag(&mut self, tag: &str) -> &mut Self {
+        self.base.tags.push(tag.to_string());
+        self
+    }
+
+    pub fn set_params(&mut self, overrides: &ChatParams) {
+        self.base.set_params(overrides);
+    }
+
+    /// 设置历史策略，控制哪些历史消息随请求发送
+    /// Set the history policy, controlling which history messages are sent with a request
+    pub fn set_history_policy(&mut self, policy: impl HistoryPolicy + 'static) -> &mut Self {
+        self.base.set_history_policy(policy);
+        self
+    }
+
+    /// 设置输入与回答的审核
+    /// Set the moderation of inputs and answers
+    pub fn set_moderation(&mut self, moderation: Moderation) -> &mut Self {
+        self.base.set_moderation(moderation);
+        self
+    }
+
+    /// 设置发送前的敏感信息清洗
+    /// Set the pre-send scrubbing of sensitive data
+    pub fn set_pii_scrubber(&mut self, scrubber: PiiScrubber) -> &mut Self {
+        self.base.set_pii_scrubber(scrubber);
+        self
+    }
+
+    /// 设置回答的检查规则
+    /// Set the guardrails checking answers
+    pub fn set_guardrails(&mut self, guardrails: Guardrails) -> &mut Self {
+        self.base.set_guardrails(guardrails);
+        self
+    }
+
+    /// 设置回答的后处理，如 `PostProcessors::plain_text()`
+    /// Set the post-processing of answers, such as `PostProcessors::plain_text()`
+    pub fn set_post_processors(&mut self, post_processors: PostProcessors) -> &mut Self {
+        self.base.set_post_processors(post_processors);
+        self
+    }
+
+    /// 设置工具结果与检索片段的注入筛查
+    /// Set the injection screening of tool results and retrieved chunks
+    pub fn set_injection_screen(&mut self, screen: InjectionScreen) -> &mut Self {
+        self.base.set_injection_screen(screen);
+        self
+    }
+
+    /// 设置回答大小的硬上限
+    /// Set the hard cap on the answer size
+    pub fn set_output_cap(&mut self, cap: OutputCap) -> &mut Self {
+        self.base.set_output_cap(cap);
+        self
+    }
+
+    /// 设置回答语种的检查
+    /// Set the answer language check
+    pub fn set_language_policy(&mut self, policy: LanguagePolicy) -> &mut Self {
+        self.base.set_language_policy(policy);
+        self
+    }
+
+    /// 设置流式增量到达应用前的变换链
+    /// Set the transformer chain applied to streamed deltas before they reach the application
+    pub fn set_stream_transformers(&mut self, transformers: StreamTransformers) -> &mut Self {
+        self.base.set_stream_transformers(transformers);
+        self
+    }
+
+    /// 设置流式响应停滞的判定时间，超过该时间没有收到事件块时中止并续传
+    /// Set the stall timeout of streaming responses, a stream receiving no chunk for longer is aborted and resumed
+    pub fn set_stream_stall_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.base.set_stream_stall_timeout(timeout);
+        self
+    }
+
+    /// 设置回答因长度限制中断时的最大续写次数，续写的各段拼接为一个回答，默认不续写
+    /// Set the maximum number of continuations when an answer is cut off by the length limit, the parts are
+    /// stitched into one answer; answers are not continued by default
+    pub fn set_max_continuations(&mut self, max: u32) -> &mut Self {
+        self.base.set_max_continuations(max);
+        self
+    }
+
+    /// 设置流式请求的备用API或能力，原API失败且无法续传时在本次请求内依次换用，换用记录在回答的元数据中
+    /// Set the fallback APIs or capabilities of streaming requests, switched to in order for the rest of the request
+    /// when the current API fails and cannot resume; switches are recorded in the answer metadata
+    pub fn set_stream_fallbacks(&mut self, fallbacks: Vec<ModelOverride>) -> &mut Self {
+        self.base.set_stream_fallbacks(fallbacks);
+        self
+    }
+
+    /// 设置额度不足时的排队键，如租户标签；同一键下的对话共同排队，默认每个对话单独排队
+    /// Set the queue key used when permits run short, such as a tenant tag; chats sharing a key queue together,
+    /// each chat queues on its own by default
+    pub fn set_queue_key(&mut self, key: &str) -> &mut Self {
+        self.base.set_queue_key(key);
+        self
+    }
+
+    /// 设置额度不足时的排队优先级，批量任务可设为 `Background`，交互对话可设为 `Interactive`
+    /// Set the queueing priority when permits run short, such as `Background` for bulk work and `Interactive` for
+    /// chats a user is waiting on
+    pub fn set_queue_priority(&mut self, priority: QueuePriority) -> &mut Self {
+        self.base.set_queue_priority(priority);
+        self
+    }
+
+    /// 设置对话所属的租户，预算、频率上限、可用工具与附件的命名空间随之生效；已设置的工具按租户重新筛选，
+    /// 与设置工具的先后顺序无关
+    /// Set the tenant owning the chat, enforcing its budget, rate limit, allowed tools and attachment namespace;
+    /// tools already set are filtered again for the tenant, so it does not matter whether the tools are set first
+    pub fn set_tenant(&mut self, tenant: Tenant) -> &mut Self {
+        self.base.set_tenant(tenant);
+        // 筛选已校验过的工具定义的子集不会失败
+        // Filtering a subset of tool definitions that were already validated cannot fail
+        if !self.tools_schema.is_empty()
+            && let Err(report) = self.replace_tools(self.tools_schema.to_vec())
+        {
+            warn!("Failed to filter the tools for the tenant: {:?}", report);
         }
+        self
+    }
+
+    /// 添加请求拦截器，在请求发出前检查或修改请求体与请求头
+    /// Add a request interceptor, inspecting or changing the body and headers just before a request is sent
+    pub fn add_request_interceptor(&mut self, interceptor: impl RequestInterceptor + 'static) -> &mut Self {
+        self.base.add_request_interceptor(interceptor);
+        self
+    }
+
+    /// 设置携带请求ID的请求头，为 None 时不发送
+    /// Set the header carrying the request ID, not sent if None
+    pub fn set_request_id_header(&mut self, header: Option<&str>) -> &mut Self {
+        self.base.set_request_id_header(header);
+        self
+    }
+
+    /// 指定下一次请求的ID，如上游服务传入的追踪ID
+    /// Set the ID of the next request, such as a trace ID passed in by an upstream service
+    pub fn set_request_id(&mut self, request_id: &str) -> &mut Self {
+        self.base.set_request_id(request_id);
+        self
+    }
+
+    /// 将文档切分后逐段作为系统消息加入会话，每段带有来源与页码，返回片段数
+    /// Chunk a document and add each chunk to the session as a system message with its source and page, returning
+    /// the number of chunks
+    ///
+    /// 片段与检索结果一样经过注入筛查。
+    /// Chunks go through injection screening like retrieved documents.
+    pub async fn add_document(&mut self, document: &Document, chunker: &Chunker) -> Result<usize, ChatError> {
+        let chunks = chunker.chunk(document);
+        for chunk in &chunks {
+            let context = self.base.screen_external(&chunk.to_context()).await?;
+            self.base.add_message(Role::System, &context)?;
+        }
+        Ok(chunks.len())
+    }
+
+    /// 用廉价模型为对话生成简短标题，存入 `base.metadata.title` 并返回
+    /// Generate a short title for the chat with a cheap model, stored in `base.metadata.title` and returned
+    ///
+    /// 优先使用绑定 `ModelCapability::Cheap` 的API，没有时使用本对话的API。
+    /// Uses an API bound to `ModelCapability::Cheap`, or this chat's API if there is none.
+    pub async fn generate_title(&mut self) -> Result<String, ChatError> {
+        let instruction = Config::render_prompt(&PromptKey::TitleGeneration, &[&TITLE_MAX_CHARS.to_string()]);
+        let title = clean_title(&ask_cheap_model(&self.base, &instruction).await?);
+        self.base.metadata.title = Some(title.clone());
+        Ok(title)
+    }
+
+    /// 用廉价模型为对话生成摘要，存入 `base.metadata.summary` 并返回
+    /// Generate a summary of the chat with a cheap model, stored in `base.metadata.summary` and returned
+    pub async fn summary(&mut self) -> Result<String, ChatError> {
+        let instruction = Config::capability_prompt(&PromptKey::ChatSummary);
+        let summary = ask_cheap_model(&self.base, &instruction).await?;
+        self.base.metadata.summary = Some(summary.clone());
+        Ok(summary)
+    }
+
+    /// 用廉价模型生成结构化的交接简报（目标、决定、待解决的问题、关键事实与待完成的操作），用于把对话移交给
+    /// 另一个智能体或人工坐席；不写入对话历史
+    /// Generate a structured handover brief (goal, decisions, open questions, key facts and pending actions) with a
+    /// cheap model, for transferring the conversation to another agent or a human operator; the history is untouched
+    pub async fn handover_brief(&self) -> Result<HandoverBrief, ChatError> {
+        handover_brief(&self.base).await
+    }
+
+    /// 标记消息为重要或取消标记，`ImportanceWeighted` 裁剪历史时总是保留标记的消息
+    /// Flag a message as important or clear the flag, `ImportanceWeighted` always keeps flagged messages when trimming
+    /// the history
+    pub fn pin_message(&mut self, message_id: &str, pinned: bool) -> Result<(), ChatError> {
+        let node = self.base.session.get_node_by_id(message_id).change_context(ChatError::SessionError)?;
+        node.importance.pinned = pinned;
+        Ok(())
+    }
+
+    /// 用廉价模型为默认路径上尚未评分的消息评定显著性（0 至 100），供 `ImportanceWeighted` 的默认评分使用；返回本次
+    /// 评分的消息数
+    /// Rate the salience (0 to 100) of the messages on the default path not rated yet with a cheap model, for the
+    /// default score of `ImportanceWeighted`; returns how many messages were rated
+    pub async fn score_salience(&mut self) -> Result<usize, ChatError> {
+        rate_salience(&mut self.base).await
+    }
+
+    /// 保存当前的消息、默认路径、用量与工具定义，返回检查点ID
+    /// Save the current messages, default path, usage and tool definitions, returning the checkpoint ID
+    pub fn checkpoint(&mut self) -> String {
+        let checkpoint = Checkpoint::new(
+            self.base.session.clone(),
+            self.base.usage,
+            self.tools_schema.clone(),
+            self.base.last_call.clone(),
+            self.base.metadata.clone(),
+        );
+        let id = checkpoint.id.clone();
+        self.checkpoints.push(checkpoint);
+        id
+    }
+
+    /// 恢复到检查点保存的状态；检查点保留，可多次回滚，之后创建的检查点同样保留
+    /// Restore the state saved by a checkpoint; the checkpoint is kept for further rollbacks, and so are the
+    /// checkpoints created after it
+    pub fn rollback(&mut self, checkpoint_id: &str) -> Result<(), ChatError> {
+        let checkpoint = self
+            .checkpoints
+            .iter()
+            .find(|checkpoint| checkpoint.id == checkpoint_id)
+            .ok_or_else(|| Report::new(ChatError::UnknownCheckpoint(checkpoint_id.to_string())))?;
+        self.base.session = checkpoint.session.clone();
+        self.base.usage = checkpoint.usage;
+        self.tools_schema = checkpoint.tools_schema.clone();
+        self.base.last_call = checkpoint.last_call.clone();
+        self.base.metadata = checkpoint.metadata.clone();
+        Ok(())
+    }
+
+    /// 删除检查点，不存在时返回 false
+    /// Remove a checkpoint, false if it does not exist
+    pub fn release_checkpoint(&mut self, checkpoint_id: &str) -> bool {
+        let count = self.checkpoints.len();
+        self.checkpoints.retain(|checkpoint| checkpoint.id != checkpoint_id);
+        self.checkpoints.len() < count
+    }
+
+    /// 保存的检查点，按创建顺序排列
+    /// Saved checkpoints, in creation order
+    pub fn checkpoints(&self) -> &[Checkpoint] {
+        &self.checkpoints
+    }
+
+    /// 从 `path` 所指的消息分出独立的子对话：复制从根到该消息的上下文，附上额外的系统提示词，用于隔离地探索子任务
+    /// Fork an independent child chat at the message at `path`: the context from the root to that message is
+    /// copied and the extra system prompt appended, to explore a sub-task in isolation
+    ///
+    /// 子对话沿用本对话的设置（API、工具、检索器等），有自己的会话ID、用量与检查点；结论可用 `merge_child` 并回。
+    /// The child keeps this chat's settings (API, tools, retriever and so on) with its own session ID, usage and
+    /// checkpoints; its conclusion can be merged back with `merge_child`.
+    pub fn spawn_child(&self, path: &[usize], extra_system_prompt: &str) -> Result<SingleChat, ChatError> {
+        let session = self.base.session.branch_to(path).change_context(ChatError::SessionError)?;
+        self.child_with_session(session, extra_system_prompt)
+    }
+
+    /// 同 `spawn_child`，但复制整个会话而不只是一条路径，用于以同一设定反复分出全新的子对话，如作为工具的子智能体；
+    /// 子对话没有自己的事务作用域，有副作用的工具调用随调用它的运行一起提交，见 `run_agent`
+    /// Like `spawn_child`, but the whole session is copied rather than one path, for forking fresh children off the
+    /// same setup again and again, such as sub-agents offered as tools; the child has no transaction scope of its
+    /// own, its calls to tools with side effects commit together with the run calling it, see `run_agent`
+    pub(crate) fn fork(&self, extra_system_prompt: &str) -> Result<SingleChat, ChatError> {
+        let mut child = self.child_with_session(self.base.session.clone(), extra_system_prompt)?;
+        child.transaction = None;
+        Ok(child)
+    }
+
+    /// 加入另一次运行的事务：之后有副作用的工具调用记录到它的意图日志中，由那次运行提交
+    /// Join the transaction of another run: calls to tools with side effects are recorded into its journal from now
+    /// on and committed by that run
+    pub(crate) fn join_transaction(&mut self, journal: ToolJournal) {
+        self.tool_routes = self.tool_routes.with_journal(journal);
+    }
+
+    /// 同 `spawn_child`，但上下文由廉价模型概括为摘要，只保留路径上的系统消息，适合上下文很长的对话
+    /// Like `spawn_child`, but the context is condensed into a summary by a cheap model and only the system
+    /// messages on the path are kept, suited to chats with long contexts
+    pub async fn spawn_child_summarized(
+        &self,
+        path: &[usize],
+        extra_system_prompt: &str,
+    ) -> Result<SingleChat, ChatError> {
+        let branch = self.base.session.branch_to(path).change_context(ChatError::SessionError)?;
+        let mut helper = self.base.clone();
+        helper.session = branch.clone();
+        let instruction = "概括下面的对话，保留后续工作需要的事实、决定与未解决的问题，使用对话的语言。只输出摘要本身。";
+        let summary = ask_cheap_model(&helper, instruction).await?;
+
+        let mut session = Session::new();
+        let mut node = branch.message_roots.first();
+        while let Some(message) = node {
+            if message.role == Role::System {
+                session
+                    .add_with_default_path(Role::System, message.content.clone())
+                    .change_context(ChatError::SessionError)?;
+            }
+            node = message.child.first();
+        }
+        session
+            .add_with_default_path(Role::System, format!("此前对话的摘要：\n{}", summary))
+            .change_context(ChatError::SessionError)?;
+        self.child_with_session(session, extra_system_prompt)
+    }
+
+    fn child_with_session(&self, session: Session, extra_system_prompt: &str) -> Result<SingleChat, ChatError> {
+        let mut child = SingleChat::from_base(self.base.clone());
+        child.tools_schema = self.tools_schema.clone();
+        child.tool_routes = self.tool_routes.clone();
+        child.tool_choice = self.tool_choice.clone();
+        child.parallel_tool_calls = self.parallel_tool_calls;
+        child.retriever = self.retriever.clone();
+        child.faithfulness = self.faithfulness.clone();
+        child.shadow = self.shadow.clone();
+        child.confidence = self.confidence.clone();
+        child.hot_reload = self.hot_reload.clone();
+        child.reload_generation = self.reload_generation;
+        child.transaction = self.transaction.clone();
+        child.scratchpad = self.scratchpad;
+        child.attachment_limits = self.attachment_limits;
+
+        child.base.session = session;
+        child.base.session_id = Uuid::new_v4().to_string();
+        child.base.events.set_source(EventSource::Chat {
+            session_id: child.base.session_id.clone(),
+        });
+        child.base.usage = 0;
+        child.base.last_call = None;
+        child.base.metadata = ChatMetadata::default();
+        if !extra_system_prompt.is_empty() {
+            child.base.add_message(Role::System, extra_system_prompt)?;
+        }
+        Ok(child)
+    }
+
+    /// 把子对话的结论（其最后一条回答）作为系统消息并回本对话，返回该结论
+    /// Merge the conclusion of a child chat (its latest answer) back into this chat as a system message, returning
+    /// the conclusion
+    pub fn merge_child(&mut self, child: &SingleChat) -> Result<String, ChatError> {
+        let mut last = None;
+        let mut siblings = &child.base.session.message_roots;
+        for &index in &child.base.session.default_path {
+            last = siblings.get(index);
+            siblings = last.map_or(siblings, |message| &message.child);
+        }
+        let Some(last) = last.filter(|message| message.role == Role::Assistant) else {
+            return Err(Report::new(ChatError::SessionError).attach_printable("The child chat has not answered yet"));
+        };
+        let conclusion = child.base.restore_pii(last.content.to_text().into_owned());
+        self.base.add_message(Role::System, &format!("子任务的结论：\n{}", conclusion))?;
+        Ok(conclusion)
+    }
+
+    /// 压缩会话：不在默认路径上的分支与已解决的工具调用（连同其结果）各折叠为简短摘要，返回节省的 token 数等结果，
+    /// token 数按模型的分词器计算
+    /// Compact the session: branches off the default path and resolved tool calls (together with their results) are
+    /// each collapsed into short summaries, returning the tokens saved and other figures, counted by the model's
+    /// tokenizer
+    ///
+    /// 压缩不可撤销，需要保留原状态时先创建检查点。
+    /// Compaction cannot be undone, create a checkpoint first to keep the original state.
+    pub fn compact(&mut self) -> CompactReport {
+        let (session, report) = compact_session(&self.base.session, tokenizer_for(&self.base.model).as_ref());
+        self.base.session = session;
+        info!("Compacted session: {:?}", report);
+        report
+    }
+
+    /// 最新一条消息的ID，可供外部系统长期引用
+    /// ID of the latest message, a durable reference for external systems
+    pub fn last_message_id(&self) -> Option<&str> {
+        self.base.session.last_message_id()
+    }
+
+    /// 从ID对应的消息处分支提问，新的问答成为当前路径
+    /// Ask a question branching off the message with the ID, the new exchange becomes the current path
+    pub async fn get_answer_at(&mut self, message_id: &str, user_input: &str) -> Result<String, ChatError> {
+        self.base.session.checkout(message_id).change_context(ChatError::SessionError)?;
+        self.get_answer(user_input).await
+    }
+
+    /// 复制对话并定位到ID对应的消息，副本中的后续提问不影响原对话
+    /// Copy the chat positioned at the message with the ID, later questions on the copy do not affect the original
+    pub fn fork_at_id(&self, message_id: &str) -> Result<Self, ChatError> {
+        let mut fork = self.clone();
+        fork.speculation = None;
+        fork.base.session.checkout(message_id).change_context(ChatError::SessionError)?;
+        Ok(fork)
     }
 
     pub async fn get_req_body_with_new_question(
@@ -81,62 +817,674 @@ impl SingleChat {
         parent_path: &[usize],
         user_input: &str,
     ) -> Result<serde_json::Value, ChatError> {
-        self.base
-            .add_message_with_parent_path(parent_path, Role::User, user_input)?;
-        Ok(self
-            .base
-            .build_request_body(&self.base.session.default_path.clone(), &Role::User)?)
+        admit_question()?;
+        self.new_question_body(parent_path, user_input, true).await
+    }
+
+    /// 添加问题并构建请求体，系统提示词按应用状态渲染，见 `BaseChat::render_context`
+    /// Add the question and build the request body, with the system prompt rendered from the application state, see
+    /// `BaseChat::render_context`
+    async fn new_question_body(
+        &mut self,
+        parent_path: &[usize],
+        user_input: &str,
+        recognize: bool,
+    ) -> Result<serde_json::Value, ChatError> {
+        self.base.add_message_with_parent_path(parent_path, Role::User, user_input)?;
+        let mut request_body = self.base.build_request_body(&self.base.session.default_path.clone(), &Role::User)?;
+        self.base.render_context(&mut request_body, recognize).await?;
+        Ok(request_body)
     }
 
     pub async fn get_req_body_again(
         &mut self,
         end_path: &[usize],
     ) -> Result<serde_json::Value, ChatError> {
-        Ok(self.base.build_request_body(end_path, &Role::User)?)
+        let mut request_body = self.base.build_request_body(end_path, &Role::User)?;
+        self.base.render_context(&mut request_body, true).await?;
+        Ok(request_body)
     }
 
     pub async fn get_req_body(&mut self, user_input: &str) -> Result<serde_json::Value, ChatError> {
-        info!("path: {:?}", self.base.session.default_path.clone());
-        self.get_req_body_with_new_question(&self.base.session.default_path.clone(), user_input)
-            .await
+        self.question_body(user_input, BodyPurpose::Answer).await
+    }
+
+    /// 添加问题并构建请求体，设置了检索器时附上检索结果，见 `BodyPurpose`
+    /// Add the question and build the request body, with the retrieval results if a retriever is set, see
+    /// `BodyPurpose`
+    async fn question_body(&mut self, user_input: &str, purpose: BodyPurpose) -> Result<serde_json::Value, ChatError> {
+        admit_question()?;
+        self.apply_hot_reload().await?;
+        info!(path = ?self.base.session.default_path, "Adding question");
+        let (scrubbed, screened) = match purpose {
+            BodyPurpose::Preview => {
+                let scrubbed = self.base.pii.as_ref().map(|scrubber| scrubber.scrub_patterns(user_input));
+                (scrubbed.unwrap_or_else(|| user_input.to_string()), Screened::default())
+            }
+            _ => {
+                let scrubbed = self.base.scrub_pii(user_input).await?;
+                let screened = self.base.moderate(ModerationStage::Input, &scrubbed).await?;
+                (scrubbed, screened)
+            }
+        };
+        let user_input = screened.replacement.as_deref().unwrap_or(&scrubbed);
+        let results = match purpose {
+            BodyPurpose::Preview => Vec::new(),
+            _ => self.run_triggers(user_input).await?,
+        };
+        // 应用状态在插入检索结果与工具结果之前渲染，外部文本不会被当作模板
+        // Application state is rendered before retrieval and tool results are inserted, so external text is never
+        // treated as a template
+        let parent_path = self.base.session.default_path.clone();
+        let recognize = purpose != BodyPurpose::Preview;
+        let mut request_body = self.new_question_body(&parent_path, user_input, recognize).await?;
+        self.base.annotate_last_message(screened.categories)?;
+        if !self.pending_attachments.is_empty() {
+            let message = self.base.session.last_message_mut().change_context(ChatError::SessionError)?;
+            message.attachments.append(&mut self.pending_attachments);
+        }
+        self.sources.clear();
+        if let Some(retriever) = self.retriever.as_ref().filter(|_| purpose != BodyPurpose::Preview) {
+            let mut sources = retriever.retrieve_sources(user_input).await;
+            for source in &mut sources {
+                source.text = self.base.screen_external(&source.text).await?;
+            }
+            let documents: Vec<String> = sources.iter().map(Source::to_context).collect();
+            if purpose == BodyPurpose::Cite {
+                insert_cited_context(&mut request_body, &documents);
+            } else {
+                insert_context(&mut request_body, &documents);
+            }
+            self.sources = sources;
+        }
+        insert_tool_results(&mut request_body, &results);
+        insert_attachment_list(&mut request_body, &self.base.session.attachments());
+        Ok(request_body)
+    }
+
+    /// 应用热重载的最新版本：重新加载的提示词替换第一条系统消息，重新加载的工具替换对话的工具；没有新版本时不做任何事
+    /// Apply the latest hot reload: a reloaded prompt replaces the first system message and reloaded tools replace
+    /// the chat's tools; nothing happens without a newer version
+    async fn apply_hot_reload(&mut self) -> Result<(), ChatError> {
+        let generation = self.reload_generation;
+        let Some(snapshot) = self.hot_reload.as_ref().and_then(|reload| reload.newer_than(generation)) else {
+            return Ok(());
+        };
+        self.reload_generation = snapshot.generation;
+        if let Some(prompt) = snapshot.prompt {
+            match self.base.session.message_roots.first_mut().filter(|message| message.role == Role::System) {
+                Some(message) => message.content = prompt.into(),
+                None => warn!("Chat has no system message, the reloaded prompt is not applied"),
+            }
+        }
+        if let Some(sources) = snapshot.tools {
+            let (tools, routes) = collect_tools(&sources).await?;
+            self.tool_routes = routes;
+            self.replace_tools(tools)?;
+        }
+        Ok(())
+    }
+
+    /// 调用输入匹配的触发器的工具，返回工具名称、参数与经过外部内容筛查的输出
+    /// Call the tools of the triggers the input matches, returning the tool name, arguments and output screened as
+    /// external content
+    async fn run_triggers(&self, user_input: &str) -> Result<Vec<(String, String, String)>, ChatError> {
+        let mut results = Vec::new();
+        for trigger in &self.triggers {
+            for mut arguments in trigger.calls(user_input) {
+                // 输入已清洗敏感信息，工具在进程内运行，参数中的占位符还原为原文
+                // The input is scrubbed of PII, tools run in-process so placeholders in the arguments are restored
+                if let Some(scrubber) = &self.base.pii {
+                    scrubber.restore_json(&mut arguments);
+                }
+                let tenant = &self.base.tenant;
+                let Some(tool) = self
+                    .tool_routes
+                    .resolve(trigger.tool())
+                    .filter(|_| tenant.as_ref().is_none_or(|tenant| tenant.allows_tool(trigger.tool())))
+                else {
+                    return Err(Report::new(ChatError::GetFunctionError)
+                        .attach_printable(format!("Tool {} of a trigger is not available", trigger.tool())));
+                };
+                let call_id = Uuid::new_v4().to_string();
+                let session_id = self.base.session_id.clone();
+                let outcome =
+                    Self::call_resolved_tool(tool, call_id, trigger.tool(), arguments, session_id, &self.base.events)
+                        .await
+                        .change_context(ChatError::GetFunctionError)?;
+                let output = self.base.screen_external(&outcome.output).await?;
+                results.push((outcome.name, outcome.arguments, output));
+            }
+        }
+        Ok(results)
+    }
+
+    /// 添加附件，随下一次提问附在用户消息上；内容存入附件存储，不发给模型，请求中只列出附件清单
+    /// Add an attachment that goes with the next question's user message; the content is put into the attachment
+    /// storage and not sent to the model, requests only carry the attachment list
+    ///
+    /// 超出大小上限或单条消息的附件数上限时报错。
+    /// Fails when the size limit or the per-message attachment limit is exceeded.
+    pub fn attach(
+        &mut self,
+        name: &str,
+        mime_type: &str,
+        bytes: impl Into<Bytes>,
+        origin: Option<&str>,
+    ) -> Result<Attachment, ChatError> {
+        if self.pending_attachments.len() >= self.attachment_limits.max_per_message {
+            return Err(Report::new(ChatError::AttachmentError(format!(
+                "a message carries at most {} attachments",
+                self.attachment_limits.max_per_message
+            ))));
+        }
+        let namespace = self.base.tenant.as_ref().map(|tenant| tenant.namespace.as_str());
+        let attachment =
+            Attachment::store(name, mime_type, bytes.into(), origin, namespace, &self.attachment_limits)?;
+        self.pending_attachments.push(attachment.clone());
+        Ok(attachment)
+    }
+
+    /// 默认路径上的附件，编号 `#n` 对应第 n 个
+    /// Attachments on the default path, number `#n` being the n-th
+    pub fn attachments(&self) -> Vec<&Attachment> {
+        self.base.session.attachments()
+    }
+
+    /// 编号对应的附件，编号从 1 开始
+    /// The attachment with the number, numbers starting at 1
+    pub fn attachment(&self, number: usize) -> Option<&Attachment> {
+        number.checked_sub(1).and_then(|index| self.attachments().get(index).copied())
+    }
+
+    /// 最近一次检索到的资料，编号从 1 开始对应此列表的顺序
+    /// Material of the latest retrieval, numbers starting at 1 follow the order of this list
+    pub fn sources(&self) -> &[Source] {
+        &self.sources
     }
 
     pub async fn get_content_from_req_body(
         &mut self,
         request_body: serde_json::Value,
     ) -> Result<String, ChatError> {
-        let content = if self.need_stream {
-            let (stream, semaphore_permit) = self
-                .base
-                .get_stream_response(request_body.clone())
-                .await
-                .attach_printable("Failed to get stream response")?;
+        let content = self
+            .base
+            .get_content(request_body)
+            .await
+            .attach_printable("Failed to get LLM answer")?;
 
-            BaseChat::get_content_from_stream_resp(stream, semaphore_permit)
-                .await
-                .attach_printable("Failed to extract content from stream response")?
-        } else {
-            let response = self
-                .base
-                .get_response(request_body.clone())
-                .await
-                .attach_printable("Failed to get response")?;
+        info!(answer = %redact(&content), "Received LLM answer");
+
+        self.base.add_screened_answer(Role::Assistant, content).await
+    }
+
+    /// 提问并取回回答；若已为该输入预生成了回答且会话未变化，直接使用预生成的结果
+    /// Ask a question and return the answer; if an answer was speculated for this input and the session has not
+    /// moved since, the speculative answer is used directly
+    pub async fn get_answer(&mut self, user_input: &str) -> Result<String, ChatError> {
+        if self.middlewares.is_empty() {
+            return self.answer(user_input).await;
+        }
+        let middlewares = self.middlewares.clone();
+        Next::new(&middlewares, self).run(user_input.to_string()).await
+    }
 
-            BaseChat::get_content_from_resp(&response)
-                .attach_printable("Failed to extract content from response")?
+    /// 提问并区分正常回答与拒绝：模型的 `refusal` 说明、内容过滤与安全拦截都归为 `AnswerOutcome::Refused`；
+    /// 拒绝的回答同样写入会话，拒绝原因记录在其元数据中
+    /// Ask a question and tell a regular answer from a refusal: the model's `refusal` explanation, content filters
+    /// and safety blocks all become `AnswerOutcome::Refused`; a refused answer is stored in the session as well, with
+    /// the reason recorded in its metadata
+    pub async fn get_answer_outcome(&mut self, user_input: &str) -> Result<AnswerOutcome, ChatError> {
+        let answer = self.get_answer(user_input).await?;
+        let refusal = self
+            .base
+            .session
+            .last_message_mut()
+            .ok()
+            .and_then(|message| message.metadata.as_ref())
+            .and_then(|metadata| metadata.refusal.clone());
+        Ok(match refusal {
+            Some(reason) => AnswerOutcome::Refused { reason },
+            None => AnswerOutcome::Answered { answer },
+        })
+    }
+
+    /// 不经中间件提问，中间件链的最内层
+    /// Ask without the middlewares, the innermost layer of the middleware chain
+    pub(crate) async fn answer(&mut self, user_input: &str) -> Result<String, ChatError> {
+        if let Some(answer) = self.take_speculation(user_input).await? {
+            return Ok(answer);
+        }
+
+        let mut request_body = self.get_req_body(user_input).await?;
+        if let Some(scored_request) = &mut self.scored_request {
+            self.confidence.clone().unwrap_or_default().prepare(&mut request_body);
+            *scored_request = Some(request_body.clone());
+        }
+        let candidates = self.shadow.as_ref().map(Shadow::sample).unwrap_or_default();
+        if candidates.is_empty() {
+            let answer = self.get_content_from_req_body(request_body).await?;
+            return Ok(self.base.restore_pii(answer));
+        }
+
+        let started = Instant::now();
+        let answer = self.get_content_from_req_body(request_body.clone()).await?;
+        if let Some(shadow) = &self.shadow {
+            shadow.mirror(&self.base, candidates, &request_body, &answer, started.elapsed());
+        }
+        Ok(self.base.restore_pii(answer))
+    }
+
+    /// 以指定的模型提问并取回回答，之后恢复对话默认的API；历史仍然共用，回答消息的元数据记录实际使用的模型
+    /// Ask a question with the given model and return the answer, restoring the chat's default API afterwards; the
+    /// history stays shared and the metadata of the answer records the model actually used
+    ///
+    /// 适合混合模型的对话，如闲聊用廉价模型、分析用强模型。生成参数沿用对话的设置。预生成的回答不适用于此方法。
+    /// Suited to mixed-model conversations, such as a cheap model for small talk and a strong one for analysis.
+    /// Generation parameters follow the chat's settings. Speculative answers do not apply.
+    pub async fn get_answer_with_model(&mut self, user_input: &str, model: ModelOverride) -> Result<String, ChatError> {
+        self.cancel_speculation();
+        let previous = self.base.bind_api(ApiBinding::resolve(&model)?);
+        let answer = async {
+            let request_body = self.get_req_body(user_input).await?;
+            self.get_content_from_req_body(request_body).await
+        }
+        .await;
+        self.base.bind_api(previous);
+        Ok(self.base.restore_pii(answer?))
+    }
+
+    /// 组装提问的请求但不发送，返回请求体、脱敏的请求头、估算的 token 数与费用；对话历史不变
+    /// Assemble the request for a question without sending it, returning the body, redacted headers and estimated
+    /// tokens and cost; the chat history is left unchanged
+    ///
+    /// 预览不产生副作用，也不发出任何请求：个人信息只按正则模式替换，不做审核与检索，也不调用触发器的工具，
+    /// 因此请求体中没有检索结果与工具结果，其余组装过程与 `get_answer` 一致。
+    /// Previews have no side effects and send no requests: PII is only replaced by the regex patterns, there is no
+    /// moderation or retrieval and the tools of the triggers are not called, so the body lacks retrieved material and
+    /// tool results; the rest of the assembly matches `get_answer`.
+    pub async fn dry_run(&self, user_input: &str) -> Result<RequestPreview, ChatError> {
+        let mut chat = self.clone();
+        let request_body = chat.question_body(user_input, BodyPurpose::Preview).await?;
+        chat.base.preview_request(&request_body)
+    }
+
+    /// 预估提问的提示词 token 数、回答最多的 token 数与费用范围，不发送请求；提示词按模型的分词器计算，见 `tokenizer_for`
+    /// Estimate the prompt tokens, the most answer tokens and the cost range of a question without sending it; the
+    /// prompt is counted by the model's tokenizer, see `tokenizer_for`
+    pub async fn estimate(&self, user_input: &str) -> Result<Estimate, ChatError> {
+        self.estimate_by(user_input, tokenizer_for(&self.base.model).as_ref()).await
+    }
+
+    /// 同 `estimate`，但用给定的分词器计算提示词
+    /// Same as `estimate`, but counts the prompt with the given tokenizer
+    pub async fn estimate_with(&self, user_input: &str, tokenizer: &impl Tokenizer) -> Result<Estimate, ChatError> {
+        self.estimate_by(user_input, tokenizer).await
+    }
+
+    async fn estimate_by(&self, user_input: &str, tokenizer: &dyn Tokenizer) -> Result<Estimate, ChatError> {
+        let preview = self.dry_run(user_input).await?;
+        Ok(Estimate::new(preview.model, &preview.body, tokenizer, self.base.model_metadata().as_ref()))
+    }
+
+    /// 提问并取回带引用的回答：要求模型以资料编号标注引用，解析后返回实际引用的来源
+    /// Ask a question and return the answer with citations: the model is asked to mark citations with the material
+    /// numbers, which are parsed into the sources actually cited
+    ///
+    /// 没有设置检索器或没有检索到资料时，引用列表为空。预生成的回答不适用于此方法。
+    /// The citations are empty if no retriever is set or nothing was retrieved. Speculative answers do not apply.
+    pub async fn get_cited_answer(&mut self, user_input: &str) -> Result<CitedAnswer, ChatError> {
+        self.cancel_speculation();
+        let request_body = self.question_body(user_input, BodyPurpose::Cite).await?;
+        let answer = self.get_content_from_req_body(request_body).await?;
+        Ok(CitedAnswer::parse(&self.base.restore_pii(answer), &self.sources))
+    }
+
+    /// 提问并把回答解析为正文、代码块、文件说明与差异，见 `ParsedAnswer`
+    /// Ask a question and parse the answer into prose, code blocks, file directives and diffs, see `ParsedAnswer`
+    pub async fn get_parsed_answer(&mut self, user_input: &str) -> Result<ParsedAnswer, ChatError> {
+        let answer = self.get_answer(user_input).await?;
+        Ok(ParsedAnswer::parse(&answer))
+    }
+
+    /// 提问并检查回答的忠实度：评判模型逐条判断回答中的陈述能否由检索到的资料支持，不可信的回答按设置重新生成
+    /// Ask a question and check the answer's faithfulness: a judge model decides claim by claim whether the
+    /// retrieved material supports the answer, and unfaithful answers are regenerated as configured
+    ///
+    /// 未设置检查时使用 `FaithfulnessChecker::default()`，只标记不重答；没有检索到资料时不做检查。只有最终回答
+    /// 写入会话。预生成的回答不适用于此方法。
+    /// Without a checker set, `FaithfulnessChecker::default()` is used, which only flags; nothing is checked when
+    /// no material was retrieved. Only the final answer is written to the session. Speculative answers do not apply.
+    pub async fn get_verified_answer(&mut self, user_input: &str) -> Result<VerifiedAnswer, ChatError> {
+        self.cancel_speculation();
+        let checker = self.faithfulness.clone().unwrap_or_default();
+        let mut request_body = self.question_body(user_input, BodyPurpose::Answer).await?;
+        let mut regenerations = 0;
+        loop {
+            let content = self.base.get_content(request_body.clone()).await?;
+            let mut report = checker.check(&self.base, &content, &self.sources).await?;
+            report.regenerations = regenerations;
+            if report.is_faithful() || regenerations >= checker.max_regenerations {
+                if !report.is_faithful() {
+                    warn!("Answer has {} unsupported claims", report.unsupported().len());
+                }
+                let answer = self.base.add_screened_answer(Role::Assistant, content).await?;
+                return Ok(VerifiedAnswer {
+                    text: self.base.restore_pii(answer),
+                    report,
+                });
+            }
+
+            regenerations += 1;
+            warn!("Answer has unsupported claims, regenerating ({})", regenerations);
+            if let Some(messages) = request_body["messages"].as_array_mut() {
+                messages.push(json!({"role": "assistant", "content": content}));
+                messages.push(json!({"role": "system", "content": regeneration_instruction(&report)}));
+            }
+        }
+    }
+
+    /// 提问并评估回答的置信度，低置信度的回答可转交人工处理
+    /// Ask a question and estimate the answer's confidence, so low-confidence answers can be routed to a human
+    ///
+    /// 未设置评估时使用 `ConfidenceScorer::default()`，即 token 概率与评判模型。回答与 `get_answer` 一样经过中间件与
+    /// 影子流量，只有原回答写入会话；中间件未请求模型就给出回答时没有可用信号，分数为 0。预生成的回答不适用于此方法。
+    /// Without a scorer set, `ConfidenceScorer::default()` is used, which is token probabilities and the judge model.
+    /// The answer goes through the middlewares and shadow traffic like `get_answer` and only the answer itself is
+    /// written to the session; when a middleware answers without asking the model there is no signal and the score is
+    /// 0. Speculative answers do not apply.
+    pub async fn get_scored_answer(&mut self, user_input: &str) -> Result<ScoredAnswer, ChatError> {
+        self.cancel_speculation();
+        let scorer = self.confidence.clone().unwrap_or_default();
+        self.scored_request = Some(None);
+        let answer = self.get_answer(user_input).await;
+        let request_body = self.scored_request.take().flatten();
+        let answer = answer?;
+        let Some(request_body) = request_body else {
+            warn!("Answer did not come from the model, its confidence is not estimated");
+            return Ok(ScoredAnswer {
+                text: answer,
+                confidence: ConfidenceReport {
+                    threshold: scorer.threshold,
+                    ..Default::default()
+                },
+            });
         };
+        let message = self.base.session.last_message_mut().change_context(ChatError::SessionError)?;
+        let content = message.content.to_string();
+        let token_probability = message
+            .metadata
+            .as_ref()
+            .and_then(|metadata| metadata.token_probability)
+            .map(Probability::get);
+        let confidence = scorer.score(&mut self.base, &request_body, &content, token_probability).await?;
+        if confidence.is_low() {
+            warn!("Answer confidence {:.2} is below {:.2}", confidence.score, confidence.threshold);
+        }
+        Ok(ScoredAnswer {
+            text: answer,
+            confidence,
+        })
+    }
 
-        info!("GetLLMAPIAnswer: {}", content);
+    /// 在用户空闲时，为应用预测的下一轮输入提前生成回答（可选功能）
+    /// While the user is idle, generate the answer to the next input predicted by the application ahead of time
+    /// (opt-in)
+    ///
+    /// 预生成在对话副本上进行，带有 `speculative` 标签；下一次 `get_answer` 的输入一致时立即返回该回答，
+    /// 否则放弃预生成。再次调用会替换之前的预生成。适用于向导式等下一步可预测的流程。
+    /// The speculation runs on a copy of the chat tagged `speculative`; the next `get_answer` with the same input
+    /// returns it instantly, any other input discards it. Calling again replaces the previous speculation. Suited to
+    /// wizard-style flows whose next step is predictable.
+    ///
+    /// 预生成的调用在被采用时才计入租户与遥测中对话的用量，放弃的预生成导出时不带会话。
+    /// 对话被丢弃时预生成随之取消。
+    /// The calls of a speculation are charged to the tenant and to the chat's usage in telemetry only once it is
+    /// used, discarded speculations are exported without the session. Dropping the chat cancels the speculation.
+    ///
+    /// # 参数 (Parameters)
+    /// * `predicted_input` - 预测的下一轮用户输入
+    ///   Predicted next user input
+    ///
+    /// # 返回 (Returns)
+    /// * `bool` - 是否开始了预生成，不在 tokio 运行时中时为 false
+    ///   Whether a speculation started, false outside a tokio runtime
+    pub fn speculate(&mut self, predicted_input: &str) -> bool {
+        self.cancel_speculation();
 
-        self.base.add_message(Role::Assistant, &content)?;
-        Ok(content)
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let mut chat = self.clone();
+        chat.add_tag("speculative");
+        chat.base.deferred_calls = Some(calls.clone());
+        let input = predicted_input.to_string();
+        let generate = async move {
+            let request_body = chat.get_req_body(&input).await.ok()?;
+            let usage = chat.base.usage;
+            let answer = chat.get_content_from_req_body(request_body).await.ok()?;
+            let metadata = chat.base.session.last_message_mut().ok()?.metadata.clone();
+            Some(SpeculativeAnswer {
+                answer,
+                metadata,
+                usage: chat.base.usage - usage,
+            })
+        };
+
+        self.speculation = Speculation::spawn(predicted_input, self.base.session.default_path.clone(), calls, generate);
+        if self.speculation.is_none() {
+            warn!("Speculation skipped outside a tokio runtime");
+        }
+        self.speculation.is_some()
+    }
+
+    /// 放弃当前的预生成
+    /// Discard the current speculation
+    pub fn cancel_speculation(&mut self) {
+        if let Some(speculation) = self.speculation.take() {
+            speculation.discard();
+        }
+    }
+
+    /// 取出与输入一致的预生成回答并写入会话，不一致或预生成失败时返回 None
+    /// Take the speculative answer matching the input and write it to the session, None on mismatch or failure
+    async fn take_speculation(&mut self, user_input: &str) -> Result<Option<String>, ChatError> {
+        let Some(speculation) = self.speculation.take() else {
+            return Ok(None);
+        };
+        if !speculation.matches(user_input, &self.base.session.default_path) {
+            speculation.discard();
+            return Ok(None);
+        }
+        let Some(answer) = speculation.answer().await else {
+            return Ok(None);
+        };
+        for call in speculation.take_calls() {
+            self.base.record_call(call);
+        }
+
+        // 预生成的副本审核过同一输入，这里重新审核以得到写入会话的文本与类别
+        // The speculative copy screened the same input, it is screened again here for the text and categories to store
+        let user_input = &self.base.scrub_pii(user_input).await?;
+        let screened = self.base.moderate(ModerationStage::Input, user_input).await?;
+        self.base.add_message(Role::User, screened.replacement.as_deref().unwrap_or(user_input))?;
+        self.base.annotate_last_message(screened.categories)?;
+        self.base.last_call = answer.metadata;
+        self.base.add_answer(Role::Assistant, &answer.answer)?;
+        self.base.usage += answer.usage;
+        Ok(Some(self.base.restore_pii(answer.answer)))
+    }
+
+    /// 提问并以流的形式逐段取回回答，回答在流结束后加入对话历史
+    /// Ask a question and receive the answer piece by piece as a stream, the answer joins the history once the
+    /// stream ends
+    pub async fn stream_answer(
+        &mut self,
+        user_input: &str,
+    ) -> Result<impl Stream<Item = Result<String, ChatError>> + Send + '_, ChatError> {
+        let mut request_body = self.get_req_body(user_input).await?;
+        request_body["stream"] = json!(true);
+        Ok(self.base.stream_content(request_body, Some(Role::Assistant)))
+    }
+
+    /// 提问并把流式回答广播给所有订阅者，返回完整的回答；订阅者可在调用前或进行中通过 `broadcast.subscribe` 加入
+    /// Ask a question and broadcast the streamed answer to every subscriber, returning the full answer; subscribers
+    /// join through `broadcast.subscribe` before or during the call
+    pub async fn broadcast_answer(
+        &mut self,
+        user_input: &str,
+        broadcast: &StreamBroadcast,
+    ) -> Result<String, ChatError> {
+        let deltas = self.stream_answer(user_input).await?;
+        broadcast.run(deltas).await
+    }
+
+    /// 以当前对话为共同上下文，并发回答多个问题，结果按输入顺序返回
+    /// Answer many questions concurrently on top of the current chat context, results come back in input order
+    ///
+    /// 每个问题在对话的独立副本上回答，当前对话不会被修改；单个问题失败只影响对应的结果。
+    /// 并发数同时受端点的请求信号量限制。
+    /// Each question is answered on its own copy of the chat, the current chat is left untouched; a failing question
+    /// only affects its own result. Concurrency is also bounded by the endpoint's request semaphore.
+    ///
+    /// # 参数 (Parameters)
+    /// * `inputs` - 要回答的问题 / Questions to answer
+    /// * `concurrency` - 同时进行的最大请求数，为 0 时按 1 处理 / Maximum number of requests in flight, 0 is treated as 1
+    pub async fn map_answers<I, S>(&self, inputs: I, concurrency: usize) -> Vec<Result<String, ChatError>>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        stream::iter(inputs)
+            .map(|input| {
+                let mut chat = self.clone();
+                async move {
+                    let body = chat.get_req_body(input.as_ref()).await?;
+                    let answer = chat.get_content_from_req_body(body).await?;
+                    Ok(chat.base.restore_pii(answer))
+                }
+            })
+            .buffered(concurrency.max(1))
+            .collect()
+            .await
     }
 
     pub async fn get_json_answer<T: DeserializeOwned + 'static + JsonSchema>(
         &mut self,
         user_input: &str,
     ) -> Result<T, ChatError> {
-        let schema = T::json_schema();
+        let value = self.get_json_answer_with_schema(user_input, T::json_schema()).await?;
+        serde_json::from_value(value)
+            .change_context(ChatError::GetJsonError)
+            .attach_printable("Answer does not match the requested type")
+    }
+
+    /// 提问并以 JSON Lines 流式取回多条结构化结果，每解析出一条即调用 `on_item`，结束后返回全部结果
+    /// Ask a question and stream back many structured results as JSON Lines, calling `on_item` as soon as each one
+    /// is parsed and returning all of them at the end
+    ///
+    /// `T` 为单条结果的类型。适合数百条记录的长提取任务，结果逐条到达而不必等待整个回答；某一行无法解析为 `T` 时返回错误，
+    /// 此前的条目已交给回调。
+    /// `T` is the type of one result. Suited to long extraction jobs of hundreds of records, which arrive one by one
+    /// instead of with the whole answer; a line that does not parse as `T` is an error, with the items before it
+    /// already handed to the callback.
+    pub async fn get_json_items<T: DeserializeOwned + 'static + JsonSchema>(
+        &mut self,
+        user_input: &str,
+        mut on_item: impl FnMut(&T) + Send,
+    ) -> Result<Vec<T>, ChatError> {
+        let output_description = assemble_output_description(T::json_schema())
+            .change_context(ChatError::AssembleOutputDescriptionError)?;
+        // 格式说明只随本次请求发送，不写入会话，多次提取不会在历史中累积
+        // The format instruction is only sent with this request and not written to the session, so repeated
+        // extractions do not pile up in the history
+        let mut request_body = self.get_req_body(user_input).await?;
+        request_body["stream"] = json!(true);
+        if let Some(messages) = request_body["messages"].as_array_mut() {
+            let instruction = format!("{}\n{}", output_description, JSON_LINES_INSTRUCTION);
+            messages.push(json!({"role": "system", "content": instruction}));
+        }
+        let pii = self.base.pii.clone();
+        let parse = |line: String| -> Result<T, ChatError> {
+            let mut value: serde_json::Value = serde_json::from_str(&line)
+                .change_context(ChatError::GetJsonError)
+                .attach_printable_lazy(|| redact(&format!("Failed to parse line as JSON: {}", line)))?;
+            if let Some(scrubber) = &pii {
+                scrubber.restore_json(&mut value);
+            }
+            serde_json::from_value(value)
+                .change_context(ChatError::GetJsonError)
+                .attach_printable_lazy(|| redact(&format!("Line does not match the requested type: {}", line)))
+        };
+
+        let mut lines = JsonLines::default();
+        let mut items = Vec::new();
+        let mut deltas = pin!(self.base.stream_content(request_body, Some(Role::Assistant)));
+        while let Some(delta) = deltas.next().await {
+            for line in lines.push(&delta?) {
+                items.push(parse(line)?);
+                on_item(&items[items.len() - 1]);
+            }
+        }
+        if let Some(line) = lines.finish() {
+            items.push(parse(line)?);
+            on_item(&items[items.len() - 1]);
+        }
+        Ok(items)
+    }
+
+    /// 从长文档中提取结构化结果：文档切分为片段后并发地逐片提取，再合并各片段的结果并去重，可处理远超上下文窗口的输入
+    /// Extract structured results from a long document: the document is chunked, the chunks are extracted
+    /// concurrently and the results of all chunks are merged and deduplicated, handling inputs far beyond the
+    /// context window
+    ///
+    /// 使用 `DocumentExtraction::default()` 的设置，见 `extract_from_document_with`。
+    /// Uses the settings of `DocumentExtraction::default()`, see `extract_from_document_with`.
+    pub async fn extract_from_document<T>(&self, text: &str) -> Result<Vec<T>, ChatError>
+    where
+        T: DeserializeOwned + Serialize + JsonSchema + Send + 'static,
+    {
+        self.extract_from_document_with(text, &DocumentExtraction::default()).await
+    }
+
+    /// 按给定设置从长文档中提取结构化结果，结果按在文档中首次出现的顺序排列
+    /// Extract structured results from a long document with the given settings, in order of first appearance in
+    /// the document
+    ///
+    /// 每个片段在对话的独立副本上以 `get_json_items` 提取，当前对话不会被修改；任一片段失败时返回错误。
+    /// 合并时去掉重复的条目与被另一条目完整包含的不完整条目（如被片段边界截断的记录）。
+    /// Every chunk is extracted with `get_json_items` on its own copy of the chat, the current chat is left
+    /// untouched; an error is returned if any chunk fails. Merging drops repeated entries and incomplete ones fully
+    /// contained in another entry (such as records cut by a chunk boundary).
+    pub async fn extract_from_document_with<T>(
+        &self,
+        text: &str,
+        extraction: &DocumentExtraction,
+    ) -> Result<Vec<T>, ChatError>
+    where
+        T: DeserializeOwned + Serialize + JsonSchema + Send + 'static,
+    {
+        let chunks = extraction.chunker.chunk(&Document::from_text("document", text));
+        let count = chunks.len();
+        let items: Vec<Vec<T>> = stream::iter(chunks)
+            .map(|chunk| {
+                let mut chat = self.clone();
+                let question = extraction.question(&chunk.text);
+                let position = format!("Extraction failed at chunk {} of {}", chunk.index + 1, count);
+                async move { chat.get_json_items(&question, |_: &T| {}).await.attach_printable(position) }
+            })
+            .buffered(extraction.concurrency.max(1))
+            .try_collect()
+            .await?;
+        Ok(reduce(items.into_iter().flatten().collect()))
+    }
+
+    /// 按运行时提供的JSON Schema取回结构化回答，用于无法在编译期确定类型的调用方（如语言绑定）
+    /// Get a structured answer for a JSON Schema given at runtime, for callers whose types are not known at compile
+    /// time (such as language bindings)
+    pub async fn get_json_answer_with_schema(
+        &mut self,
+        user_input: &str,
+        schema: serde_json::Value,
+    ) -> Result<serde_json::Value, ChatError> {
 
         let output_description = assemble_output_description(schema.clone())
             .change_context(ChatError::AssembleOutputDescriptionError)
@@ -149,32 +1497,160 @@ impl SingleChat {
         self.base
             .add_message(Role::System, output_description.as_str())?;
 
-        let resp = self
+        let grammar = self.base.schema_grammar(&schema);
+        let mut resp = self
             .get_req_body(user_input)
             .await
             .attach_printable("Failed to get answer for JSON request")?;
+        if let Some(grammar) = &grammar {
+            resp["grammar"] = json!(grammar);
+        }
 
         let answer = self.get_content_from_req_body(resp).await?;
 
-        ChatTool::get_json::<T>(&answer, schema)
-            .await
-            .attach_printable(format!("Failed to parse answer as JSON: {}", answer))
+        // 受语法约束的回答已是合法JSON，无需再转换
+        // An answer constrained by the grammar is valid JSON already and needs no conversion
+        let mut value = match grammar {
+            Some(_) => serde_json::from_str(&answer).change_context(ChatError::GetJsonError),
+            None => ChatTool::get_json::<serde_json::Value>(&answer, schema)
+                .await
+                .and_then(|value| self.base.check_converted_json(value)),
+        }
+        .attach_printable(redact(&format!("Failed to parse answer as JSON: {}", answer)))?;
+        if let Some(scrubber) = &self.base.pii {
+            scrubber.restore_json(&mut value);
+        }
+        Ok(value)
     }
 
-    pub fn set_tools(&mut self, tools_schema: Vec<serde_json::Value>) -> Result<(), ChatError> {
-        self.tools_schema = tools_schema.clone();
+    /// 当前设置的工具定义
+    /// The tool definitions currently set
+    pub fn tools(&self) -> &[serde_json::Value] {
+        &self.tools_schema
+    }
 
-        let tools_prompt = assemble_tools_prompt(tools_schema).unwrap();
+    /// 设置工具定义；对话设置了租户时只保留租户允许的工具
+    /// Set the tool definitions; with a tenant set on the chat only the tools it allows are kept
+    pub fn set_tools(&mut self, tools_schema: Vec<serde_json::Value>) -> Result<(), ChatError> {
+        let tools_prompt = self.store_tools(tools_schema)?;
+        self.base.add_message(Role::System, &tools_prompt)
+    }
 
+    /// 替换工具定义，会话中已有的工具提示消息原地更新，没有时与 `set_tools` 一样添加
+    /// Replace the tool definitions, updating the tools prompt message of the session in place, or adding one like
+    /// `set_tools` if there is none
+    fn replace_tools(&mut self, tools_schema: Vec<serde_json::Value>) -> Result<(), ChatError> {
+        let old_prompt = match self.tools_schema.is_empty() {
+            true => None,
+            false => assemble_tools_prompt(self.tools_schema.to_vec()).ok(),
+        };
+        let tools_prompt = self.store_tools(tools_schema)?;
+        let path = self.base.session.default_path.clone();
+        for depth in 1..=path.len() {
+            let message = self.base.session.get_node_by_path(&path[..depth]).change_context(ChatError::SessionError)?;
+            if message.role == Role::System && old_prompt.as_deref() == Some(&*message.content.to_string()) {
+                message.content = tools_prompt.into();
+                return Ok(());
+            }
+        }
         self.base.add_message(Role::System, &tools_prompt)
     }
 
+    /// 按租户过滤后保存工具定义，返回组装好的工具提示
+    /// Store the tool definitions filtered by the tenant, returning the assembled tools prompt
+    fn store_tools(&mut self, mut tools_schema: Vec<serde_json::Value>) -> Result<String, ChatError> {
+        if self.scratchpad {
+            for tool in scratchpad_tools() {
+                if !tools_schema.iter().any(|existing| existing["function"]["name"] == tool["function"]["name"]) {
+                    tools_schema.push(tool);
+                }
+            }
+        }
+        if let Some(tenant) = &self.base.tenant {
+            tools_schema.retain(|tool| tool["function"]["name"].as_str().is_some_and(|name| tenant.allows_tool(name)));
+        }
+        self.tools_schema = Arc::new(tools_schema.clone());
+
+        let tools_prompt = assemble_tools_prompt(tools_schema.clone())
+            .change_context(ChatError::InvalidConfig)
+            .attach_printable("Invalid tool definitions")?;
+        // 交给对话的工具同时登记到工具文档中，文档与模型看到的定义一致
+        // Tools handed to the chat are registered in the tool documentation as well, so the docs match what the
+        // model sees
+        for tool in &tools_schema {
+            if let Err(report) = register_tool_definition(tool) {
+                warn!("Tool definition not documented: {:?}", report);
+            }
+        }
+        Ok(tools_prompt)
+    }
+
+    /// 从多个来源（本地注册表、MCP 服务、OpenAPI 接口等）汇集工具，加上各来源的命名空间后作为一份工具定义设置，
+    /// 工具提示跨来源统一组装；模型调用时按名称转给对应的来源
+    /// Gather tools from several sources (the local registry, MCP servers, OpenAPI services and the like) and set
+    /// them, namespaced per source, as one set of definitions with a tools prompt assembled across sources; calls by
+    /// the model are routed to the matching source by name
+    pub async fn set_tool_sources(&mut self, sources: Vec<Arc<dyn ToolSource>>) -> Result<(), ChatError> {
+        let (tools, routes) = collect_tools(&sources).await?;
+        self.tool_routes = routes;
+        self.set_tools(tools)
+    }
+
+    /// 设置工具选择方式，`Required` 与 `Function` 在回答中没有工具调用时仍以用户输入调用工具
+    /// Set how tools are picked, `Required` and `Function` still call a tool with the user input when the answer
+    /// contains no tool call
+    pub fn set_tool_choice(&mut self, choice: ToolChoice) -> &mut Self {
+        self.tool_choice = Some(choice);
+        self
+    }
+
+    /// 设置是否允许一轮回答中并行调用多个工具；为 false 时请求提供商每轮只调用一个工具，
+    /// 回答中仍有多个调用时依次逐个执行，不会丢弃
+    /// Set whether one answer may call several tools in parallel; if false the provider is asked for one tool call
+    /// per answer, and when an answer still holds several calls they run one after another rather than being dropped
+    pub fn set_parallel_tool_calls(&mut self, parallel: bool) -> &mut Self {
+        self.parallel_tool_calls = Some(parallel);
+        self
+    }
+
+    /// 解析工具调用时的工具定义与选择设置
+    /// Tool definitions and choice settings used when parsing tool calls
+    fn tool_request(&self) -> serde_json::Value {
+        let mut request = json!({"tools": self.tools_schema.as_ref()});
+        if let Some(choice) = &self.tool_choice {
+            request["tool_choice"] = choice.to_json();
+        }
+        if let Some(parallel) = self.parallel_tool_calls {
+            request["parallel_tool_calls"] = json!(parallel);
+        }
+        request
+    }
+
+    /// 按工具选择设置调整回答中的工具调用：强制调用且没有调用时以用户输入作为调用（`user_input` 为 None 时
+    /// 不强制）
+    /// Adjust the tool calls of an answer to the choice settings: with a forced call and no call in the answer the
+    /// user input becomes the call (not forced when `user_input` is None)
+    fn select_tool_calls(&self, mut text_calls: Vec<String>, user_input: Option<&str>) -> Vec<String> {
+        if let Some(user_input) = user_input.filter(|_| text_calls.is_empty())
+            && self.tool_choice.as_ref().is_some_and(ToolChoice::forces_call)
+        {
+            info!("Tool call forced by tool_choice, calling with the user input");
+            text_calls.push(user_input.to_string());
+        }
+        text_calls
+    }
+
     async fn process_tool_call(
         text_call: String,
-        tools_schema: Vec<serde_json::Value>,
-    ) -> error_stack::Result<String, ToolCallError> {
+        tool_request: Arc<serde_json::Value>,
+        session_id: String,
+        events: EventHandlers,
+        pii: Option<PiiScrubber>,
+        tenant: Option<Tenant>,
+        routes: ToolRoutes,
+    ) -> error_stack::Result<ToolOutcome, ToolCallError> {
         let function_call: serde_json::Value =
-            ChatTool::get_function(&text_call, json!({"tools": tools_schema}))
+            ChatTool::get_function(&text_call, tool_request.as_ref().clone())
                 .await
                 .change_context(ToolCallError::ParseFunctionCall)
                 .attach_printable(format!(
@@ -184,7 +1660,7 @@ impl SingleChat {
 
         info!(
             "function_call: {}",
-            serde_json::to_string_pretty(&function_call).unwrap_or_default()
+            redact(&serde_json::to_string_pretty(&function_call).unwrap_or_default())
         );
 
         let function_name = function_call["name"].as_str().ok_or_else(|| {
@@ -203,7 +1679,7 @@ impl SingleChat {
             )
         })?;
 
-        let arg_json: serde_json::Value = serde_json::from_str(arg_str).map_err(|e| {
+        let mut arg_json: serde_json::Value = serde_json::from_str(arg_str).map_err(|e| {
             Report::new(ToolCallError::DeserializeArguments(e.to_string())).attach_printable(
                 format!(
                     "Failed to deserialize arguments for function '{}': {}",
@@ -212,39 +1688,163 @@ impl SingleChat {
             )
         })?;
 
-        use crate::schema::tool_schema::get_tool_registry;
-        let registry = get_tool_registry();
+        // 工具在进程内运行，参数中的占位符还原为原文
+        // Tools run in-process, placeholders in the arguments are restored to the originals
+        if let Some(scrubber) = &pii {
+            scrubber.restore_json(&mut arg_json);
+        }
 
-        match registry.get(function_name) {
-            Some(tool_fn) => {
+        let call_id = Uuid::new_v4().to_string();
+        let arguments = serde_json::to_string(&arg_json).unwrap_or_default();
+        // 租户不允许的工具与不存在的工具同样处理，不透露其存在
+        // Tools the tenant does not allow are handled like missing ones, without revealing they exist
+        let tool = routes
+            .resolve(function_name)
+            .filter(|_| tenant.as_ref().is_none_or(|tenant| tenant.allows_tool(function_name)));
+        // 事务中有副作用的工具只记录意图，运行成功结束后才执行
+        // Tools with side effects in a transaction only record an intent, run once the run completes successfully
+        let journal = routes.journal().filter(|journal| journal.defers(function_name));
+        match (tool, journal) {
+            (Some(tool), Some(journal)) => {
+                Ok(ToolOutcome {
+                    output: journal.record(&call_id, function_name, arg_json, tool),
+                    call_id,
+                    name: function_name.to_string(),
+                    arguments,
+                    duration: Duration::ZERO,
+                    is_error: false,
+                    images: Vec::new(),
+                })
+            }
+            (Some(tool), None) => {
                 info!("Calling function named: {}", function_name);
-                match tool_fn(arg_json.clone()) {
-                    Ok(result) => {
-                        let serialized = serde_json::to_string_pretty(&result).map_err(|e| {
-                            Report::new(ToolCallError::SerializeResult).attach_printable(format!(
-                                "Failed to serialize result for function '{}': {:?}",
-                                function_name, e
-                            ))
-                        })?;
-
-                        info!("Calling function succeeded: {}", serialized);
-                        Ok(serialized)
-                    }
-                    Err(e) => {
-                        let err_msg = format!("Calling function '{}' failed: {}", function_name, e);
-                        info!("{}", err_msg);
-                        Ok(err_msg)
-                    }
-                }
+                Self::call_resolved_tool(tool, call_id, function_name, arg_json, session_id, &events).await
             }
-            None => {
+            (None, _) => {
                 let err_msg = format!("Cannot find function named '{}'", function_name);
-                info!("{}", err_msg);
-                Ok(err_msg)
+                info!("{}", redact(&err_msg));
+                let available = tool_request["tools"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|tool| tool["function"]["name"].as_str())
+                    .filter(|name| tenant.as_ref().is_none_or(|tenant| tenant.allows_tool(name)))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                let error = ToolError::new(ToolErrorType::NotFound, &err_msg, true)
+                    .suggestion(&format!("只调用以下工具之一：{}", available));
+                Ok(ToolOutcome {
+                    call_id,
+                    name: function_name.to_string(),
+                    arguments,
+                    output: error.to_output(),
+                    duration: Duration::ZERO,
+                    is_error: true,
+                    images: Vec::new(),
+                })
             }
         }
     }
 
+    /// 调用已找到的工具，发出调用事件并导出调用记录；调用失败时以错误输出返回
+    /// Call a resolved tool, emitting the call events and exporting the call record; a failed call is returned as
+    /// an error output
+    async fn call_resolved_tool(
+        tool: ResolvedTool,
+        call_id: String,
+        name: &str,
+        arg_json: serde_json::Value,
+        session_id: String,
+        events: &EventHandlers,
+    ) -> error_stack::Result<ToolOutcome, ToolCallError> {
+        let in_flight = track_tool();
+        let arguments = serde_json::to_string(&arg_json).unwrap_or_default();
+        events.emit(|| ChatEvent::ToolCallStarted {
+            call_id: call_id.clone(),
+            name: name.to_string(),
+            arguments: arg_json.clone(),
+        });
+
+        // 本地工具是同步函数，在阻塞线程上运行，截止时间到达时不必等待其返回，但在线程结束前仍计为进行中；
+        // 工具来源（如子智能体）的提问属于已开始的回答，关闭开始后不被拒绝
+        // Local tools are sync functions, run on a blocking thread so a deadline does not have to wait for them, yet
+        // they count as in flight until the thread finishes; questions asked by tool sources (such as sub-agents)
+        // belong to an answer already started and are not refused once shutdown began
+        let started_at = SystemTime::now();
+        let started = Instant::now();
+        let tool_arguments = arg_json.clone();
+        let result = match tool {
+            ResolvedTool::Source(source, source_name) => {
+                within_started_answer(source.call_tool(&source_name, tool_arguments)).await
+            }
+            ResolvedTool::Local(tool_fn) => {
+                task::spawn_blocking(move || {
+                    let _in_flight = in_flight;
+                    tool_fn(tool_arguments)
+                })
+                .await
+                .map_err(|e| {
+                    Report::new(ToolCallError::FunctionExecution(name.to_string()))
+                        .attach_printable(e.to_string())
+                })?
+            }
+        };
+        // 图片另行发送给模型，结果文本、事件与调用记录中只留占位
+        // Images go to the model separately, the result text, events and call record only keep placeholders
+        let (result, images) = match result {
+            Ok(mut result) => {
+                let images = take_tool_images(&mut result);
+                (Ok(result), images)
+            }
+            Err(e) => (Err(e), Vec::new()),
+        };
+        let record = ToolCallRecord {
+            id: call_id.clone(),
+            session_id,
+            name: name.to_string(),
+            arguments: arg_json.clone(),
+            output: result.as_ref().ok().map(|output| output.to_string()),
+            error: result.as_ref().err().map(|e| e.to_string()),
+            started_at,
+            duration: started.elapsed(),
+        };
+        let duration = record.duration;
+        events.emit(|| ChatEvent::ToolCallFinished {
+            call_id: record.id.clone(),
+            name: record.name.clone(),
+            output: record.output.clone(),
+            error: record.error.clone(),
+            duration: record.duration,
+        });
+        export_tool_call(record);
+        let (output, is_error) = match result {
+            Ok(result) => {
+                let serialized = serde_json::to_string_pretty(&result).map_err(|e| {
+                    Report::new(ToolCallError::SerializeResult).attach_printable(format!(
+                        "Failed to serialize result for function '{}': {:?}",
+                        name, e
+                    ))
+                })?;
+
+                info!("Calling function succeeded: {}", redact(&serialized));
+                (serialized, false)
+            }
+            Err(e) => {
+                info!("{}", redact(&format!("Calling function '{}' failed: {}", name, e)));
+                (ToolError::from_report(&e).to_output(), true)
+            }
+        };
+        Ok(ToolOutcome {
+            call_id,
+            name: name.to_string(),
+            arguments,
+            output,
+            duration,
+            is_error,
+            images,
+        })
+    }
+
     pub async fn get_tool_answer(
         &mut self,
         user_input: &str,
@@ -268,57 +1868,300 @@ impl SingleChat {
             })?;
 
         let text_calls = extract_tool_uses(&answer_with_text_calls);
-        info!("text_calls: {:?}", text_calls);
-
-        let mut results = Vec::with_capacity(text_calls.len());
-
-        if text_calls.is_empty() {
-            info!("No function calls found, returning original answer");
-            return Ok((answer_with_text_calls, results));
-        }
-
+        info!("text_calls: {}", redact(&format!("{:?}", text_calls)));
         let clean_answer = text_calls
             .iter()
             .fold(answer_with_text_calls.clone(), |acc, call| {
                 acc.replace(&format!("<ToolUse>{}</ToolUse>", call), "")
             });
-        info!("clean_answer: {}", clean_answer);
 
-        let tools_schema = self.tools_schema.clone();
+        let text_calls = self.select_tool_calls(text_calls, Some(user_input));
+        if text_calls.is_empty() {
+            info!("No function calls found, returning original answer");
+            return Ok((self.base.restore_pii(answer_with_text_calls), Vec::new()));
+        }
+
+        info!("clean_answer: {}", redact(&clean_answer));
+        let clean_answer = self.base.restore_pii(clean_answer);
+
+        let (outcomes, _) = self.run_tool_calls(text_calls, None).await;
+        self.record_tool_calls(&outcomes).await?;
+        Ok((clean_answer, outcomes.into_iter().map(|outcome| outcome.output).collect()))
+    }
+
+    /// 在截止时间内运行智能体循环：提问，执行回答中的工具调用，把结果交回模型，直到模型不再调用工具
+    /// Run the agent loop within a deadline: ask, run the tool calls in the answer and hand the results back to
+    /// the model, until it answers without calling tools
+    ///
+    /// 每次模型调用与工具执行都以剩余时间为超时；到期或达到最多步数（见 `set_max_agent_steps`）时返回已完成部分的
+    /// 结果，见 `AgentRun::stop`。到期时仍在运行的工具在后台继续运行，其结果被丢弃；第一次模型调用就已到期时，
+    /// 提问从会话中撤回，不会留下没有回答的用户消息。
+    /// Every model call and tool execution times out after the remaining time; once the deadline passes or the
+    /// maximum steps (see `set_max_agent_steps`) are reached, the result of what was done so far is returned, see
+    /// `AgentRun::stop`. Tools still running at the deadline keep running in the background and their results are
+    /// dropped; when the deadline passes during the first model call, the question is withdrawn from the session
+    /// rather than left there unanswered.
+    ///
+    /// 设置事务作用域时，有副作用的工具调用只记录为意图：运行以回答结束时依次执行，否则全部丢弃，
+    /// 意图的最终状态见 `AgentRun::journal`；提交中途失败时补偿已执行的意图并返回 `TransactionAborted`。
+    /// With a transaction scope, calls to tools with side effects are only recorded as intents: they run in order
+    /// when the run ends with an answer and are all discarded otherwise, see `AgentRun::journal` for their final
+    /// status; a commit failing halfway compensates the executed intents and returns `TransactionAborted`.
+    ///
+    /// 作为工具的子智能体（见 `ToolRegistry::register_agent`）在调用它的运行的截止时间内运行，
+    /// 其有副作用的工具调用记录到调用方的意图日志中，与调用方一同提交或丢弃。
+    /// Sub-agents called as tools (see `ToolRegistry::register_agent`) run within the deadline of the run calling
+    /// them, and their calls to tools with side effects are recorded into the caller's journal, committed or
+    /// discarded together with the caller.
+    pub async fn run_agent(&mut self, user_input: &str, deadline: Deadline) -> Result<AgentRun, ToolCallError> {
+        let Some(scope) = self.transaction.clone() else {
+            let journal = self.tool_routes.journal().cloned();
+            return AgentScope { deadline, journal }.run(self.run_agent_steps(user_input, deadline)).await;
+        };
+        let journal = ToolJournal::new(scope);
+        let routes = self.tool_routes.clone();
+        self.tool_routes = routes.with_journal(journal.clone());
+        let agent = AgentScope {
+            deadline,
+            journal: Some(journal.clone()),
+        };
+        let run = agent.run(self.run_agent_steps(user_input, deadline)).await;
+        self.tool_routes = routes;
+
+        match run {
+            Ok(mut run) if run.is_complete() => {
+                run.journal = self.commit_journal(&journal).await?;
+                Ok(run)
+            }
+            Ok(mut run) => {
+                run.journal = journal.roll_back();
+                Ok(run)
+            }
+            Err(e) => {
+                journal.roll_back();
+                Err(e)
+            }
+        }
+    }
+
+    /// 依次执行事务中记录的意图；某个意图失败时补偿已执行的意图并返回错误
+    /// Run the intents recorded in a transaction in order; if one fails, the executed ones are compensated and an
+    /// error is returned
+    async fn commit_journal(&self, journal: &ToolJournal) -> Result<Vec<ToolIntent>, ToolCallError> {
+        let (mut intents, tools) = journal.take();
+        for (index, tool) in tools.into_iter().enumerate() {
+            let intent = &intents[index];
+            let outcome = Self::call_resolved_tool(
+                tool,
+                intent.call_id.clone(),
+                &intent.name,
+                intent.arguments.clone(),
+                self.base.session_id.clone(),
+                &self.base.events,
+            )
+            .await
+            .map(|outcome| (outcome.output, outcome.is_error))
+            .unwrap_or_else(|e| (format!("{:?}", e), true));
+            let intent = &mut intents[index];
+            let (output, failed) = outcome;
+            intent.output = Some(output);
+            if !failed {
+                intent.status = IntentStatus::Committed;
+                continue;
+            }
+
+            intent.status = IntentStatus::Failed;
+            let name = intent.name.clone();
+            journal.compensate(&mut intents).await;
+            return Err(Report::new(ToolCallError::TransactionAborted(journal.scope().name().to_string()))
+                .attach_printable(format!("Intent of {} failed", name))
+                .attach_printable(redact(&serde_json::to_string(&intents).unwrap_or_default())));
+        }
+        if !intents.is_empty() {
+            info!("Committed {} intents of transaction {}", intents.len(), journal.scope().name());
+        }
+        Ok(intents)
+    }
+
+    /// 智能体循环本身，见 `run_agent`
+    /// The agent loop itself, see `run_agent`
+    async fn run_agent_steps(&mut self, user_input: &str, deadline: Deadline) -> Result<AgentRun, ToolCallError> {
+        let started = Instant::now();
+        let mut run = AgentRun::default();
+        run.trace.user_input = user_input.to_string();
+        let session = self.base.session.clone();
+        while run.steps < self.max_agent_steps {
+            run.steps += 1;
+            let step_started = Instant::now();
+            let mut trace = TraceStep {
+                step: run.steps,
+                ..TraceStep::default()
+            };
+            let step = async {
+                // 之后的步骤中工具结果已在会话里，直接再次请求
+                // In later steps the tool results are already in the session, so just ask again
+                let request_body = match run.steps {
+                    1 => self.get_req_body(user_input).await?,
+                    _ => self.get_req_body_again(&self.base.session.default_path.clone()).await?,
+                };
+                let hash = request_hash(&request_body);
+                self.get_content_from_req_body(request_body).await.map(|answer| (answer, hash))
+            };
+            let Some(answer) = deadline.run(step).await else {
+                info!("Agent deadline expired during model call of step {}", run.steps);
+                // 还没有任何回答时撤回提问，之后的步骤中会话以工具结果结尾，下一次提问可以直接接上
+                // Withdraw the question while nothing was answered yet, in later steps the session ends with tool
+                // results the next question can follow
+                if run.steps == 1 {
+                    self.base.session = session;
+                }
+                trace.decision = StepDecision::DeadlineExpired;
+                run.end_step(trace, step_started);
+                return Ok(run.finish(AgentStop::DeadlineExpired, started));
+            };
+            let (answer, hash) = answer.map_err(|e| {
+                Report::new(ToolCallError::ExtractFunctionCall(format!("Failed to get answer for tool call: {:?}", e)))
+                    .attach_printable(format!("User input: {}", user_input))
+            })?;
+            trace.llm_call = Some(self.trace_llm_call(&answer, hash));
+
+            let text_calls = extract_tool_uses(&answer);
+            let clean_answer = text_calls
+                .iter()
+                .fold(answer, |acc, call| acc.replace(&format!("<ToolUse>{}</ToolUse>", call), ""));
+            run.answer = self.base.restore_pii(clean_answer);
+            // 强制调用只作用于第一步，之后模型才能给出最终回答
+            // A forced call only applies to the first step, so the model can give a final answer afterwards
+            let text_calls = self.select_tool_calls(text_calls, (run.steps == 1).then_some(user_input));
+            if text_calls.is_empty() {
+                run.end_step(trace, step_started);
+                return Ok(run.finish(AgentStop::Answered, started));
+            }
 
+            let (outcomes, finished) = self.run_tool_calls(text_calls, Some(&deadline)).await;
+            self.record_tool_calls(&outcomes).await?;
+            trace.tool_calls = outcomes.iter().map(ToolOutcome::trace).collect();
+            run.tool_results.extend(outcomes.into_iter().map(|outcome| outcome.output));
+            if !finished {
+                info!("Agent deadline expired during tool calls of step {}", run.steps);
+                trace.decision = StepDecision::DeadlineExpired;
+                run.end_step(trace, step_started);
+                return Ok(run.finish(AgentStop::DeadlineExpired, started));
+            }
+            trace.decision = StepDecision::CallTools;
+            run.end_step(trace, step_started);
+        }
+        Ok(run.finish(AgentStop::StepLimit, started))
+    }
+
+    /// 运行记录中刚完成的模型调用，性能数据取自会话中回答消息的元数据
+    /// Run trace entry of the model call just finished, with the performance data taken from the metadata of the
+    /// answer message in the session
+    fn trace_llm_call(&mut self, output: &str, request_hash: String) -> TraceLlmCall {
+        let metadata = self.base.session.last_message_mut().ok().and_then(|message| message.metadata.clone());
+        let metadata = metadata.unwrap_or_default();
+        TraceLlmCall {
+            request_id: metadata.request_id,
+            model: metadata.model,
+            request_hash,
+            output: output.to_string(),
+            latency_ms: metadata.latency_ms,
+            prompt_tokens: metadata.prompt_tokens,
+            completion_tokens: metadata.completion_tokens,
+        }
+    }
+
+    /// 并发执行工具调用（不允许并行调用时依次执行），按调用顺序返回结果；设置截止时间时只等待到期为止，
+    /// 未完成或未开始的调用不计入结果，此时第二个返回值为 false
+    /// Run the tool calls concurrently (one after another without parallel tool calls), returning the results in
+    /// call order; with a deadline, calls unfinished or not started when it passes are left out of the results and
+    /// the second return value is false
+    async fn run_tool_calls(&self, text_calls: Vec<String>, deadline: Option<&Deadline>) -> (Vec<ToolOutcome>, bool) {
+        let tool_request = Arc::new(self.tool_request());
+        let session_id = self.base.session_id.clone();
+        let events = self.base.events.clone();
+        let pii = self.base.pii.clone();
+        let tenant = self.base.tenant.clone();
+        let routes = match self.scratchpad {
+            true => self.tool_routes.with_scratchpad(self.base.session.scratchpad.share()),
+            false => self.tool_routes.clone(),
+        };
+
+        let spawn = |text_call: String| {
+            let tool_request = Arc::clone(&tool_request);
+            let session_id = session_id.clone();
+            let events = events.clone();
+            let pii = pii.clone();
+            let tenant = tenant.clone();
+            let routes = routes.clone();
+            task::spawn(Config::in_current_scope(AgentScope::in_current_run(async move {
+                Self::process_tool_call(text_call, tool_request, session_id, events, pii, tenant, routes).await
+            })))
+        };
+        // 允许并行时全部立即开始，否则轮到时才开始
+        // With parallel calls all of them start at once, otherwise each starts when its turn comes
+        let sequential = self.parallel_tool_calls == Some(false);
         let tasks = text_calls
             .into_iter()
             .map(|text_call| {
-                let tools_schema_clone = tools_schema.clone();
-                task::spawn(
-                    async move { Self::process_tool_call(text_call, tools_schema_clone).await },
-                )
+                let task = (!sequential).then(|| spawn(text_call.clone()));
+                (text_call, task)
             })
             .collect::<Vec<_>>();
 
+        let mut results = Vec::with_capacity(tasks.len());
         let mut errors = Vec::new();
+        let mut finished = true;
 
-        for (i, task) in tasks.into_iter().enumerate() {
-            match task.await {
+        for (i, (text_call, task)) in tasks.into_iter().enumerate() {
+            let task = match task {
+                Some(task) => task,
+                None if deadline.is_some_and(Deadline::is_expired) => {
+                    finished = false;
+                    continue;
+                }
+                None => spawn(text_call.clone()),
+            };
+            let joined = match deadline {
+                Some(deadline) => {
+                    let abort = task.abort_handle();
+                    match deadline.run(task).await {
+                        Some(joined) => joined,
+                        None => {
+                            abort.abort();
+                            finished = false;
+                            continue;
+                        }
+                    }
+                }
+                None => task.await,
+            };
+            match joined {
                 Ok(result) => match result {
-                    Ok(success_result) => results.push(success_result),
+                    Ok(outcome) => results.push(outcome),
                     Err(err) => {
                         errors.push(format!("Tool call #{} failed: {}", i, err));
 
-                        results.push(format!(
-                            "{{\"error\": \"Tool call failed with error: {}\"}}",
-                            err
-                        ));
+                        let error = match err.current_context() {
+                            ToolCallError::DeserializeArguments(_) => {
+                                ToolError::new(ToolErrorType::InvalidArguments, &err.to_string(), true)
+                                    .suggestion("参数必须是符合工具参数定义的JSON对象")
+                            }
+                            ToolCallError::FunctionExecution(_) => {
+                                ToolError::new(ToolErrorType::ExecutionFailed, &err.to_string(), false)
+                            }
+                            _ => ToolError::new(ToolErrorType::InvalidCall, &err.to_string(), true)
+                                .suggestion("按工具提示的要求，在 <ToolUse></ToolUse> 中写出工具名称与完整的参数"),
+                        };
+                        results.push(ToolOutcome::unparsed(&text_call, error.to_output()));
                     }
                 },
                 Err(e) => {
                     let error_msg = format!("Task join error for call #{}: {:?}", i, e);
                     errors.push(error_msg.clone());
 
-                    results.push(format!(
-                        "{{\"error\": \"Task execution failed: {}\"}}",
-                        error_msg
-                    ));
+                    let error = ToolError::new(ToolErrorType::ExecutionFailed, &error_msg, false);
+                    results.push(ToolOutcome::unparsed(&text_call, error.to_output()));
                 }
             }
         }
@@ -327,6 +2170,110 @@ impl SingleChat {
             info!("Tool call errors occurred: {:?}", errors);
         }
 
-        Ok((clean_answer, results))
+        (results, finished)
+    }
+
+    /// 把一轮工具调用写入会话：最新的回答改为正文加工具调用，每个结果（经过外部内容筛查）作为一条工具结果消息
+    /// Write one round of tool calls into the session: the latest answer becomes its text plus the tool calls,
+    /// and every result (after external content screening) becomes a tool result message
+    ///
+    /// 只记录已完成的调用，未完成的调用不留下没有结果的调用记录。
+    /// Only finished calls are recorded, so unfinished ones leave no call without a result behind.
+    async fn record_tool_calls(&mut self, outcomes: &[ToolOutcome]) -> Result<(), ToolCallError> {
+        if outcomes.is_empty() {
+            return Ok(());
+        }
+        let answer = self.base.session.last_message_mut().change_context(ToolCallError::RecordToolCalls)?;
+        let text = answer.content.to_text().into_owned();
+        let text = extract_tool_uses(&text)
+            .iter()
+            .fold(text.clone(), |acc, call| acc.replace(&format!("<ToolUse>{}</ToolUse>", call), ""));
+        let mut parts: Vec<Content> = Vec::new();
+        if !text.trim().is_empty() {
+            parts.push(Content::from(text));
+        }
+        parts.extend(
+            outcomes
+                .iter()
+                .map(|outcome| Content::tool_call(&outcome.call_id, &outcome.name, &outcome.arguments)),
+        );
+        answer.content = Content::Parts(parts);
+
+        for outcome in outcomes {
+            let output =
+                self.base.screen_external(&outcome.output).await.change_context(ToolCallError::RecordToolCalls)?;
+            let result =
+                Content::tool_outcome(&outcome.call_id, &outcome.name, &output, outcome.duration, outcome.is_error)
+                    .with_images(outcome.images.clone());
+            self.base.add_content(Role::tool(&outcome.call_id), result).change_context(ToolCallError::RecordToolCalls)?;
+        }
+        Ok(())
+    }
+}
+
+/// 未能解析为函数调用的工具调用记录的名称
+/// Name recorded for tool calls that could not be parsed into a function call
+pub const UNPARSED_TOOL_CALL: &str = "unparsed_tool_call";
+
+/// 提问请求体的用途
+/// What the request body of a question is built for
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum BodyPurpose {
+    /// 发送给模型
+    /// Sent to the model
+    Answer,
+
+    /// 发送给模型，并要求模型标注引用
+    /// Sent to the model, which is asked to mark its citations
+    Cite,
+
+    /// 只供 `dry_run` 预览，不调用实体识别、审核、检索与触发器的工具
+    /// Only previewed by `dry_run`, without calling the entity recognizer, moderation, retrieval or trigger tools
+    Preview,
+}
+
+/// 一次工具调用的执行结果
+/// Outcome of one tool call
+#[derive(Debug)]
+struct ToolOutcome {
+    call_id: String,
+    name: String,
+
+    /// JSON 字符串形式的参数
+    /// Arguments as a JSON string
+    arguments: String,
+
+    output: String,
+    duration: Duration,
+    is_error: bool,
+
+    /// 从结果中取出的图片
+    /// Images taken out of the result
+    images: Vec<Arc<str>>,
+}
+
+impl ToolOutcome {
+    fn trace(&self) -> TraceToolCall {
+        TraceToolCall {
+            name: self.name.clone(),
+            arguments: self.arguments.clone(),
+            output: self.output.clone(),
+            duration_ms: self.duration.as_millis() as u64,
+            is_error: self.is_error,
+        }
+    }
+
+    /// 未能解析的调用，参数中保留原始文本
+    /// A call that could not be parsed, keeping the original text in the arguments
+    fn unparsed(text_call: &str, output: String) -> Self {
+        Self {
+            call_id: Uuid::new_v4().to_string(),
+            name: UNPARSED_TOOL_CALL.to_string(),
+            arguments: json!({"text": text_call}).to_string(),
+            output,
+            duration: Duration::ZERO,
+            is_error: true,
+            images: Vec::new(),
+        }
     }
 }
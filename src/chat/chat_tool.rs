@@ -3,13 +3,56 @@ use error_stack::{Report, Result, ResultExt};
 // 序列化相关
 use serde::de::DeserializeOwned;
 // 日志功能
-use tracing::log::info;
+use tracing::{info, warn};
 
 // 项目内部模块
 use crate::chat::chat_base::{BaseChat, ChatError};
 use crate::chat::message::Role;
+use crate::config::Config;
 use crate::config::ModelCapability::ToolUse;
-use crate::schema::json_schema::JsonSchema;
+use crate::config::prompts::PromptKey;
+use crate::error::ReportExt;
+use crate::prompt::assembler::assemble_output_description;
+use crate::utils::common::redact::redact;
+
+/// 工具选择方式，对应请求中的 `tool_choice`
+/// How the model picks tools, the `tool_choice` of requests
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ToolChoice {
+    /// 由模型决定是否调用工具
+    /// The model decides whether to call a tool
+    Auto,
+
+    /// 必须调用某个工具
+    /// Some tool must be called
+    Required,
+
+    /// 必须调用指定名称的工具
+    /// The tool with the given name must be called
+    Function(String),
+}
+
+impl ToolChoice {
+    pub fn function(name: &str) -> Self {
+        Self::Function(name.to_string())
+    }
+
+    /// 是否强制调用工具
+    /// Whether a tool call is forced
+    pub fn forces_call(&self) -> bool {
+        !matches!(self, Self::Auto)
+    }
+
+    /// 请求体中的 `tool_choice` 值
+    /// The `tool_choice` value of a request body
+    pub fn to_json(&self) -> serde_json::Value {
+        match self {
+            Self::Auto => serde_json::json!("auto"),
+            Self::Required => serde_json::json!("required"),
+            Self::Function(name) => serde_json::json!({"type": "function", "function": {"name": name}}),
+        }
+    }
+}
 
 /// ChatTool结构体：提供与语言模型交互的工具功能
 /// ChatTool struct: Provides utility functions for interacting with language models
@@ -19,6 +62,11 @@ impl ChatTool {
     /// 从文本获取JSON格式的结果
     /// Get JSON formatted result from text input
     ///
+    /// 提供商以参数错误拒绝 `response_format` 时，在同一次调用内改为在提示词中描述输出格式，
+    /// 并在助手消息的元数据中记录降级。
+    /// If the provider rejects `response_format` as an invalid parameter, the output format is described in the
+    /// prompt instead within the same call, and the downgrade is recorded in the assistant message metadata.
+    ///
     /// # 参数 (Parameters)
     /// * `text_answer` - 需要转换为JSON的文本输入
     ///                 - Text input to be converted to JSON
@@ -28,17 +76,15 @@ impl ChatTool {
     /// # 返回 (Returns)
     /// * `Result<T, ChatError>` - 成功时返回反序列化的T类型数据，失败时返回ChatError
     ///                          - Returns deserialized data of type T on success, ChatError on failure
-    pub async fn get_json<T: DeserializeOwned + 'static + JsonSchema>(
+    pub async fn get_json<T: DeserializeOwned + 'static>(
         text_answer: &str,
         json_schema: serde_json::Value,
     ) -> Result<T, ChatError> {
         // 创建支持工具使用能力的基础聊天实例
         // Create a base chat instance with tool use capability
-        let mut base = BaseChat::new_with_model_capability(
-            ToolUse,
-            "将输入内容整理为指定的json形式输出", // Format input content into specified JSON output
-            false,
-        );
+        let prompt = Config::capability_prompt(&PromptKey::ToolUseJson);
+        let mut base = BaseChat::try_new_with_model_capability(ToolUse, &prompt, false)
+            .change_context(ChatError::GetJsonError)?;
 
         // 添加用户消息
         // Add user message
@@ -46,48 +92,62 @@ impl ChatTool {
 
         // 构建包含响应格式的请求体
         // Build request body with response format
-        let request_body = add_response_format(
-            base.build_request_body(&base.session.default_path.clone(), &Role::User)?,
-            json_schema
-        );
-
-        // 发送请求并处理可能的错误
-        // Send request and handle potential errors
-        let response = base.get_response(request_body)
-            .await
+        let plain_body = base.build_request_body(&base.session.default_path.clone(), &Role::User)?;
+        let request_body = add_response_format(plain_body.clone(), json_schema.clone());
+
+        // 发送请求，提供商不支持响应格式时改用提示词描述格式
+        // Send request, describing the format in the prompt if the provider does not support response format
+        let (response, downgrade) = match base.get_response(request_body).await {
+            Err(report) if report.rejects_parameter("response_format") => {
+                warn!("Provider rejected response_format, falling back to prompt-described output: {:?}", report);
+                let output_description = assemble_output_description(json_schema)
+                    .change_context(ChatError::AssembleOutputDescriptionError)?;
+                let response = base.get_response(add_system_message(plain_body, &output_description)).await;
+                (response, Some("response_format"))
+            }
+            response => (response, None),
+        };
+        let response = response
             .change_context(ChatError::GetJsonError)
             .attach_printable("Failed to send request")?;
 
-        // 从响应中提取内容
-        // Extract content from response
+        // 从响应中提取内容，提示词方式的回答可能包在代码块中
+        // Extract content from response, prompt-described answers may be wrapped in a code block
         let json_answer = response["choices"][0]["message"]["content"]
             .as_str()
+            .map(strip_code_fence)
             .ok_or(Report::new(ChatError::GetJsonError))
             .attach_printable("Failed to get content from response")?;
 
         // 记录LLM返回的答案
         // Log the answer from LLM
-        info!("Get LLM API Answer: {}", json_answer);
+        info!(answer = %redact(json_answer), "Received LLM answer");
 
         // 添加助手回复
         // Add assistant reply
-        base.add_message(Role::Assistant, json_answer)?;
+        record_downgrade(&mut base, downgrade);
+        base.add_answer(Role::Assistant, json_answer)?;
 
         // 将JSON字符串反序列化为目标类型
         // Deserialize JSON string to target type
         serde_json::from_str(json_answer)
             .change_context(ChatError::GetJsonError)
-            .attach_printable_lazy(|| format!("Failed to deserialize JSON: {}", json_answer))
+            .attach_printable_lazy(|| redact(&format!("Failed to deserialize JSON: {}", json_answer)))
     }
 
     /// 基于输入文本调用函数
     /// Call a function based on text input
     ///
+    /// 提供商以参数错误拒绝原生 `tools` 时，在同一次调用内改为在提示词中列出工具并要求输出调用JSON。
+    /// If the provider rejects native `tools` as an invalid parameter, the tools are listed in the prompt instead
+    /// within the same call and the call JSON is requested as the answer.
+    ///
     /// # 参数 (Parameters)
     /// * `text_answer` - 用户输入的文本
     ///                 - Text input from user
-    /// * `tools_schema` - 可用工具的模式定义
-    ///                  - Schema defining available tools
+    /// * `tools_schema` - 可用工具的模式定义，可同时带有 `tool_choice` 与 `parallel_tool_calls`，一并写入请求体
+    ///   - Schema defining available tools, may also carry `tool_choice` and `parallel_tool_calls`,
+    ///     which are written into the request body as well
     ///
     /// # 返回 (Returns)
     /// * `Result<serde_json::Value, ChatError>` - 成功时返回函数调用的JSON结果，失败时返回ChatError
@@ -98,11 +158,9 @@ impl ChatTool {
     ) -> Result<serde_json::Value, ChatError> {
         // 创建支持工具使用能力的基础聊天实例
         // Create a base chat instance with tool use capability
-        let mut base = BaseChat::new_with_model_capability(
-            ToolUse,
-            "根据输入的内容调用指定的函数", // Call specified function based on input content
-            false,
-        );
+        let prompt = Config::capability_prompt(&PromptKey::ToolUseFunction);
+        let mut base = BaseChat::try_new_with_model_capability(ToolUse, &prompt, false)
+            .change_context(ChatError::GetFunctionError)?;
 
         // 添加用户消息
         // Add user message
@@ -110,23 +168,87 @@ impl ChatTool {
 
         // 构建包含工具的请求体
         // Build request body with tools
-        let request_body = add_tools(base.build_request_body(
-            &base.session.default_path.clone(),
-            &Role::User,
-        )?, tools_schema);
-
-        // 发送请求并处理可能的错误
-        // Send request and handle potential errors
-        let response = base.get_response(request_body)
-            .await
-            .change_context(ChatError::GetFunctionError)
-            .attach_printable("Failed to send request")?;
+        let plain_body = base.build_request_body(&base.session.default_path.clone(), &Role::User)?;
+        let request_body = add_tools(plain_body.clone(), tools_schema.clone());
 
-        // 从响应中提取函数调用结果
-        // Extract function call result from response
-        let json_answer = response["choices"][0]["message"]["tool_calls"][0]["function"].clone();
+        // 发送请求，提供商不支持原生工具时改用提示词列出工具
+        // Send request, listing the tools in the prompt if the provider does not support native tools
+        match base.get_response(request_body).await {
+            Err(report) if report.rejects_parameter("tools") => {
+                warn!("Provider rejected tools, falling back to prompt-described function call: {:?}", report);
+                let response = base
+                    .get_response(add_system_message(plain_body, &function_call_prompt(&tools_schema)))
+                    .await
+                    .change_context(ChatError::GetFunctionError)
+                    .attach_printable("Failed to send request")?;
+                record_downgrade(&mut base, Some("tools"));
+
+                let content = BaseChat::get_content_from_resp(&response)?;
+                let call: serde_json::Value = serde_json::from_str(strip_code_fence(&content))
+                    .change_context(ChatError::GetFunctionError)
+                    .attach_printable_lazy(|| redact(&format!("Failed to parse function call: {}", content)))?;
+
+                // 与原生工具调用保持一致：参数为JSON字符串
+                // Match native tool calls: the arguments are a JSON string
+                let arguments = match &call["arguments"] {
+                    serde_json::Value::String(arguments) => arguments.clone(),
+                    arguments => arguments.to_string(),
+                };
+                Ok(serde_json::json!({"name": call["name"], "arguments": arguments}))
+            }
+            response => {
+                let response = response
+                    .change_context(ChatError::GetFunctionError)
+                    .attach_printable("Failed to send request")?;
+
+                // 从响应中提取函数调用结果
+                // Extract function call result from response
+                Ok(response["choices"][0]["message"]["tool_calls"][0]["function"].clone())
+            }
+        }
+    }
+}
+
+/// 在最近一次调用的元数据中记录结构化输出降级
+/// Record a structured output downgrade in the metadata of the latest call
+fn record_downgrade(base: &mut BaseChat, downgrade: Option<&str>) {
+    if let (Some(metadata), Some(downgrade)) = (base.last_call.as_mut(), downgrade) {
+        metadata.downgrade = Some(downgrade.to_string());
+    }
+}
+
+/// 在请求体的消息列表末尾追加一条系统消息
+/// Append a system message to the messages of a request body
+fn add_system_message(mut request_body: serde_json::Value, content: &str) -> serde_json::Value {
+    if let Some(messages) = request_body.get_mut("messages").and_then(|messages| messages.as_array_mut()) {
+        messages.push(serde_json::json!({"role": "system", "content": content}));
+    }
+    request_body
+}
+
+/// 提示词方式调用函数时的系统提示，指定了工具时只列出该工具
+/// System prompt for calling a function through the prompt, only the chosen tool is listed when one is specified
+fn function_call_prompt(tools_schema: &serde_json::Value) -> String {
+    let chosen = tools_schema["tool_choice"]["function"]["name"].as_str();
+    let tools: Vec<&serde_json::Value> = tools_schema["tools"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|tool| chosen.is_none_or(|name| tool["function"]["name"] == name))
+        .collect();
+    format!(
+        "从以下工具中选择一个进行调用，只输出JSON对象 {{\"name\": 工具名称, \"arguments\": 参数对象}}，不要输出其他内容。\n{}",
+        serde_json::json!(tools)
+    )
+}
 
-        Ok(json_answer)
+/// 去除包裹回答的 Markdown 代码块标记
+/// Strip the Markdown code fence wrapping an answer
+pub(crate) fn strip_code_fence(answer: &str) -> &str {
+    let trimmed = answer.trim();
+    match trimmed.strip_prefix("```").and_then(|rest| rest.strip_suffix("```")) {
+        Some(inner) => inner.split_once('\n').map_or(inner, |(_, body)| body).trim(),
+        None => trimmed,
     }
 }
 
@@ -0,0 +1,50 @@
+// 标准库
+use std::sync::Arc;
+
+// 唯一标识
+use uuid::Uuid;
+
+// 项目内部模块
+use crate::chat::message::{MessageMetadata, Session};
+use crate::chat::summary::ChatMetadata;
+
+/// 对话状态的快照，由 `SingleChat::checkpoint` 创建，`SingleChat::rollback` 恢复
+/// Snapshot of the chat state, created by `SingleChat::checkpoint` and restored by `SingleChat::rollback`
+///
+/// 会话中的文本共享存储，快照不复制消息内容。
+/// Session text is shared, so a snapshot does not copy the message contents.
+#[derive(Clone, Debug)]
+pub struct Checkpoint {
+    pub id: String,
+
+    /// 消息树与默认路径
+    /// Message tree and default path
+    pub session: Session,
+
+    pub usage: i32,
+
+    pub tools_schema: Arc<Vec<serde_json::Value>>,
+
+    pub last_call: Option<MessageMetadata>,
+
+    pub metadata: ChatMetadata,
+}
+
+impl Checkpoint {
+    pub(crate) fn new(
+        session: Session,
+        usage: i32,
+        tools_schema: Arc<Vec<serde_json::Value>>,
+        last_call: Option<MessageMetadata>,
+        metadata: ChatMetadata,
+    ) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            session,
+            usage,
+            tools_schema,
+            last_call,
+            metadata,
+        }
+    }
+}
@@ -0,0 +1,63 @@
+// 文本处理
+use once_cell::sync::Lazy;
+use regex::{Captures, Regex};
+
+// 数据序列化
+use serde::{Deserialize, Serialize};
+
+// 项目内部模块
+use crate::chat::retriever::Source;
+
+/// 连续的引用标记，如 `[2]`、`[1, 3]` 或 `[1][3]`
+/// A run of citation markers, such as `[2]`, `[1, 3]` or `[1][3]`
+static MARKERS: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?:\[\d+(?:\s*[,，]\s*\d+)*\])+").unwrap());
+
+static NUMBER: Lazy<Regex> = Lazy::new(|| Regex::new(r"\d+").unwrap());
+
+/// 带有引用的回答
+/// An answer with its citations
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CitedAnswer {
+    /// 回答文本，标记 `[n]` 已重新编号，指向 `citations` 中的第 n 个来源
+    /// Answer text, its `[n]` markers renumbered to point at the n-th source in `citations`
+    pub text: String,
+
+    /// 回答实际引用的来源，按首次出现的顺序
+    /// Sources the answer actually cites, in order of first appearance
+    pub citations: Vec<Source>,
+}
+
+impl CitedAnswer {
+    /// 解析回答中的引用标记，`sources` 是随请求发送、按 1 开始编号的资料
+    /// Parse the citation markers of an answer, `sources` being the material sent with the request, numbered from 1
+    ///
+    /// 指向不存在编号的标记视为模型编造，从文本中移除。
+    /// Markers pointing at numbers that do not exist are taken as made up by the model and removed from the text.
+    pub fn parse(answer: &str, sources: &[Source]) -> Self {
+        let mut cited: Vec<usize> = Vec::new();
+        let text = MARKERS.replace_all(answer, |captures: &Captures| {
+            let mut numbers: Vec<usize> = Vec::new();
+            for number in NUMBER.find_iter(&captures[0]) {
+                let Some(index) = number.as_str().parse::<usize>().ok().filter(|n| (1..=sources.len()).contains(n)) else {
+                    continue;
+                };
+                let position = match cited.iter().position(|&c| c == index) {
+                    Some(position) => position,
+                    None => {
+                        cited.push(index);
+                        cited.len() - 1
+                    }
+                };
+                if !numbers.contains(&(position + 1)) {
+                    numbers.push(position + 1);
+                }
+            }
+            numbers.iter().map(|number| format!("[{}]", number)).collect::<String>()
+        });
+
+        Self {
+            text: text.into_owned(),
+            citations: cited.into_iter().map(|index| sources[index - 1].clone()).collect(),
+        }
+    }
+}
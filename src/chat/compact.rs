@@ -0,0 +1,209 @@
+// 标准库
+use std::collections::{HashMap, HashSet};
+
+// 项目内部模块
+use crate::chat::content::Content;
+use crate::chat::message::{Messages, Session};
+use crate::utils::common::tokens::Tokenizer;
+
+/// 摘要中保留的工具结果字符数
+/// Characters of a tool result kept in the summary
+pub const RESULT_EXCERPT_CHARS: usize = 200;
+
+/// 一次压缩的结果
+/// Outcome of one compaction
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CompactReport {
+    /// 压缩前整个会话的 token 数，含所有分支，按模型的分词器计算
+    /// Tokens of the whole session before compaction, every branch included, counted by the model's tokenizer
+    pub tokens_before: usize,
+
+    pub tokens_after: usize,
+
+    /// 折叠为摘要的工具调用消息数
+    /// Tool call messages collapsed into summaries
+    pub exchanges_collapsed: usize,
+
+    /// 折叠为摘要消息的不在默认路径上的分支数
+    /// Branches off the default path collapsed into summary messages
+    pub branches_summarized: usize,
+}
+
+impl CompactReport {
+    pub fn tokens_saved(&self) -> usize {
+        self.tokens_before.saturating_sub(self.tokens_after)
+    }
+}
+
+/// 压缩会话：不在默认路径上的分支各折叠为一条摘要消息，已解决的工具调用连同其结果折叠为一条摘要消息
+/// Compact a session: every branch off the default path is collapsed into one summary message, and resolved tool
+/// calls are collapsed together with their results into one summary message
+///
+/// 调用的结果紧随其后、全部已返回且之后对话仍在继续时，调用视为已解决；其他调用连同结果保持原样，不会留下找不到
+/// 调用的结果。分支摘要沿用分支第一条消息的ID与角色，保留的消息沿用原来的ID与性能数据。
+/// A call counts as resolved once all its results are back right after it and the conversation has moved on after
+/// them; other calls are left as is together with their results, so no result is left without its call. Branch
+/// summaries keep the ID and role of the branch's first message, kept messages keep their IDs and performance data.
+pub(crate) fn compact_session(session: &Session, tokenizer: &dyn Tokenizer) -> (Session, CompactReport) {
+    let mut report = CompactReport {
+        tokens_before: session.message_roots.iter().map(|root| tree_tokens(root, tokenizer)).sum(),
+        ..Default::default()
+    };
+
+    // 默认路径上的消息（不带子节点）及其所在的兄弟节点
+    // Messages on the default path, without their children, and the siblings they sit among
+    let mut path = Vec::with_capacity(session.default_path.len());
+    let mut levels: Vec<(&[Messages], usize)> = Vec::with_capacity(session.default_path.len());
+    let mut siblings = &session.message_roots;
+    for &index in &session.default_path {
+        let Some(node) = siblings.get(index) else {
+            break;
+        };
+        report.branches_summarized += siblings.len() - 1;
+        path.push(without_children(node));
+        levels.push((siblings, index));
+        siblings = &node.child;
+    }
+    report.branches_summarized += siblings.len();
+
+    let mut collapsed: HashMap<usize, Content> = HashMap::new();
+    let mut removed: HashSet<usize> = HashSet::new();
+    for (position, message) in path.iter().enumerate() {
+        let calls: Vec<(&str, &str, &str)> = message
+            .content
+            .parts()
+            .iter()
+            .filter_map(|part| match part {
+                Content::ToolCall { id, name, arguments } => Some((id.as_str(), name.as_str(), &**arguments)),
+                _ => None,
+            })
+            .collect();
+        if calls.is_empty() {
+            continue;
+        }
+        // 紧随调用的结果消息，其后还有消息时对话才算继续
+        // Result messages right after the call, the conversation has only moved on if a message follows them
+        let end = position + 1 + path[position + 1..].iter().take_while(|later| is_tool_result(&later.content)).count();
+        if end == path.len() {
+            continue;
+        }
+        let results: HashMap<&str, &str> = path[position + 1..end]
+            .iter()
+            .flat_map(|later| later.content.parts())
+            .filter_map(|part| match part {
+                Content::ToolResult { call_id, content, .. } => Some((call_id.as_str(), &**content)),
+                _ => None,
+            })
+            .collect();
+        let answered = calls.iter().all(|(id, ..)| results.contains_key(id));
+        let own = results.keys().all(|call_id| calls.iter().any(|(id, ..)| id == call_id));
+        if !answered || !own {
+            continue;
+        }
+
+        let mut lines: Vec<String> = message
+            .content
+            .parts()
+            .iter()
+            .filter(|part| !matches!(part, Content::ToolCall { .. }))
+            .map(|part| part.to_text().into_owned())
+            .filter(|text| !text.trim().is_empty())
+            .collect();
+        for (id, name, arguments) in &calls {
+            lines.push(format!("[已调用工具 {}({})，结果：{}]", name, arguments, excerpt(results[id])));
+        }
+        collapsed.insert(position, Content::from(lines.join("\n")));
+        removed.extend(position + 1..end);
+        report.exchanges_collapsed += 1;
+    }
+
+    // 由后向前重新组装：保留的消息挂上下一层，折叠掉的结果消息的兄弟分支与子节点上移到调用消息下
+    // Assemble again from the end: kept messages get the level below as children, while the sibling branches and
+    // children of collapsed result messages move up under the call message
+    let mut level: Vec<Messages> = siblings.iter().map(summarize_branch).collect();
+    let mut index: Option<usize> = None;
+    let mut default_path = Vec::with_capacity(path.len());
+    for (position, mut message) in path.into_iter().enumerate().rev() {
+        let (siblings, at) = levels[position];
+        let before: Vec<Messages> = siblings[..at].iter().map(summarize_branch).collect();
+        let after = siblings[at + 1..].iter().map(summarize_branch);
+        if removed.contains(&position) {
+            index = index.map(|index| index + before.len());
+            level = before.into_iter().chain(level).chain(after).collect();
+            continue;
+        }
+        if let Some(content) = collapsed.remove(&position) {
+            message.content = content;
+        }
+        message.child = level;
+        default_path.extend(index);
+        index = Some(before.len());
+        level = before.into_iter().chain(std::iter::once(message)).chain(after).collect();
+    }
+    default_path.extend(index);
+    default_path.reverse();
+
+    let compacted = Session {
+        message_roots: level,
+        default_path,
+        scratchpad: session.scratchpad.clone(),
+    };
+    report.tokens_after = compacted.message_roots.iter().map(|root| tree_tokens(root, tokenizer)).sum();
+    (compacted, report)
+}
+
+/// 消息是否只由工具结果组成
+/// Whether a message consists of tool results only
+fn is_tool_result(content: &Content) -> bool {
+    content.parts().iter().all(|part| matches!(part, Content::ToolResult { .. }))
+}
+
+/// 截取摘要中保留的部分
+/// Cut the part kept in a summary
+fn excerpt(text: &str) -> String {
+    let mut excerpt: String = text.chars().take(RESULT_EXCERPT_CHARS).collect();
+    if text.chars().count() > RESULT_EXCERPT_CHARS {
+        excerpt.push('…');
+    }
+    excerpt
+}
+
+/// 把一个分支折叠为一条摘要消息，按顺序列出分支中每条消息的角色与开头部分，沿用分支第一条消息的ID与角色
+/// Collapse a branch into one summary message listing the role and opening of every message in it in order, keeping
+/// the ID and role of the branch's first message
+fn summarize_branch(branch: &Messages) -> Messages {
+    fn walk(message: &Messages, lines: &mut Vec<String>) {
+        lines.push(format!("{}: {}", message.role, excerpt(&message.content.to_text())));
+        for child in &message.child {
+            walk(child, lines);
+        }
+    }
+    let mut summary = vec!["[未选用的分支]".to_string()];
+    walk(branch, &mut summary);
+    Messages {
+        content: Content::from(summary.join("\n")),
+        ..without_children(branch)
+    }
+}
+
+/// 不带子节点的消息副本
+/// Copy of a message without its children
+fn without_children(message: &Messages) -> Messages {
+    Messages {
+        id: message.id.clone(),
+        role: message.role.clone(),
+        content: message.content.clone(),
+        child: Vec::new(),
+        metadata: message.metadata.clone(),
+        attachments: message.attachments.clone(),
+        importance: message.importance,
+        created_at_ms: message.created_at_ms,
+    }
+}
+
+/// 一棵消息树的 token 数
+/// Tokens of a message tree
+fn tree_tokens(message: &Messages, tokenizer: &dyn Tokenizer) -> usize {
+    let children: usize = message.child.iter().map(|child| tree_tokens(child, tokenizer)).sum();
+    tokenizer.count(&message.content.to_text()) + children
+}
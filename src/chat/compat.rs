@@ -0,0 +1,175 @@
+// 数据序列化
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+// 观测诊断
+use tracing::{debug, warn};
+
+// 项目内部模块
+use crate::prompt::assembler::assemble_output_description;
+
+/// 提供商的兼容性设置：请求体在发出前按设置改写，同一套代码因此可以不加修改地在行为特殊的提供商之间切换
+/// Compatibility settings of a provider: request bodies are rewritten to conform before they are sent, so the same
+/// code runs unchanged against providers with quirks
+///
+/// 在配置文件中按API来源设置，如 `[api_source.compat]`；默认值表示完全兼容 OpenAI 格式，不做任何改写。
+/// Set per API source in the config file, such as `[api_source.compat]`; the defaults mean full OpenAI format
+/// compatibility and rewrite nothing.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct ProviderCompat {
+    /// 是否支持 system 角色，不支持时系统消息合并到第一条用户消息的开头
+    /// Whether the system role is supported, system messages are merged into the start of the first user message
+    /// if not
+    pub system_role: bool,
+
+    /// 允许的系统消息数量，超出时全部合并为第一条系统消息所在位置的一条
+    /// Number of system messages allowed, once exceeded they are all merged into one at the place of the first
+    pub max_system_messages: Option<usize>,
+
+    /// 是否支持 `response_format`，不支持时移除该字段并在系统消息中描述输出格式
+    /// Whether `response_format` is supported, the field is removed and the output format described in a system
+    /// message if not
+    pub response_format: bool,
+
+    /// 不支持的顶层请求参数，如 `parallel_tool_calls`、`seed`，发出前移除
+    /// Unsupported top-level request parameters, such as `parallel_tool_calls` or `seed`, removed before sending
+    pub unsupported_params: Vec<String>,
+}
+
+impl Default for ProviderCompat {
+    fn default() -> Self {
+        Self {
+            system_role: true,
+            max_system_messages: None,
+            response_format: true,
+            unsupported_params: Vec::new(),
+        }
+    }
+}
+
+impl ProviderCompat {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn system_role(mut self, supported: bool) -> Self {
+        self.system_role = supported;
+        self
+    }
+
+    pub fn max_system_messages(mut self, max: usize) -> Self {
+        self.max_system_messages = Some(max);
+        self
+    }
+
+    pub fn response_format(mut self, supported: bool) -> Self {
+        self.response_format = supported;
+        self
+    }
+
+    pub fn unsupported_param(mut self, param: &str) -> Self {
+        self.unsupported_params.push(param.to_string());
+        self
+    }
+
+    /// 是否不需要任何改写
+    /// Whether nothing needs rewriting
+    pub fn is_noop(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// 按兼容性设置改写请求体，返回所做的改写，便于记录
+    /// Rewrite a request body to conform to the settings, returning the rewrites made for logging
+    pub fn apply(&self, body: &mut Value) -> Vec<String> {
+        let mut rewrites = Vec::new();
+        let Some(object) = body.as_object_mut() else {
+            return rewrites;
+        };
+
+        if !self.response_format
+            && let Some(format) = object.remove("response_format")
+        {
+            rewrites.push("response_format".to_string());
+            match assemble_output_description(format) {
+                Ok(description) => {
+                    if let Some(messages) = object.get_mut("messages").and_then(Value::as_array_mut) {
+                        messages.push(json!({"role": "system", "content": description}));
+                    }
+                }
+                Err(report) => warn!("Removed response_format could not be described in the prompt: {:?}", report),
+            }
+        }
+
+        for param in &self.unsupported_params {
+            if object.remove(param).is_some() {
+                rewrites.push(param.clone());
+            }
+        }
+
+        if let Some(messages) = object.get_mut("messages").and_then(Value::as_array_mut) {
+            let max_system = match self.system_role {
+                true => self.max_system_messages,
+                false => Some(0),
+            };
+            if let Some(max_system) = max_system
+                && merge_system_messages(messages, max_system)
+            {
+                rewrites.push("system".to_string());
+            }
+        }
+
+        if !rewrites.is_empty() {
+            debug!("Request rewritten for provider compatibility: {:?}", rewrites);
+        }
+        rewrites
+    }
+}
+
+/// 系统消息超出允许的数量时合并：允许 0 条时并入第一条用户消息，否则合并为一条；返回是否有改动
+/// Merge system messages once there are more than allowed: into the first user message if none are allowed,
+/// into a single one otherwise; returns whether anything changed
+fn merge_system_messages(messages: &mut Vec<Value>, max_system: usize) -> bool {
+    let positions: Vec<usize> =
+        messages.iter().enumerate().filter(|(_, message)| message["role"] == "system").map(|(i, _)| i).collect();
+    if positions.len() <= max_system {
+        return false;
+    }
+
+    let merged = positions.iter().map(|&i| content_text(&messages[i]["content"])).collect::<Vec<_>>().join("\n\n");
+    let first = positions[0];
+    for &i in positions.iter().skip(1).rev() {
+        messages.remove(i);
+    }
+    if max_system > 0 {
+        messages[first]["content"] = Value::String(merged);
+        return true;
+    }
+
+    messages.remove(first);
+    match messages.iter_mut().find(|message| message["role"] == "user") {
+        Some(user) => {
+            user["content"] = match user["content"].take() {
+                Value::Array(mut parts) => {
+                    parts.insert(0, json!({"type": "text", "text": merged}));
+                    Value::Array(parts)
+                }
+                content => Value::String(format!("{}\n\n{}", merged, content_text(&content))),
+            }
+        }
+        None => messages.insert(0, json!({"role": "user", "content": merged})),
+    }
+    true
+}
+
+/// 消息内容中的文本，多段内容只取文本段
+/// Text of a message content, only the text parts of multi-part content
+fn content_text(content: &Value) -> String {
+    match content {
+        Value::String(text) => text.clone(),
+        Value::Array(parts) => {
+            parts.iter().filter_map(|part| part["text"].as_str()).collect::<Vec<_>>().join("\n")
+        }
+        _ => String::new(),
+    }
+}
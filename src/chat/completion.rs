@@ -0,0 +1,171 @@
+// 模板引擎
+use minijinja::{Environment, Error, ErrorKind, context};
+
+// 序列化
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+// 错误处理
+use error_stack::{Report, Result};
+
+// 项目内部模块
+use crate::chat::chat_base::ChatError;
+
+/// ChatML 模板，Qwen、Yi 等模型使用
+/// ChatML template, used by models such as Qwen and Yi
+pub const CHATML_TEMPLATE: &str = "\
+{% for message in messages %}<|im_start|>{{ message.role }}
+{{ message.content }}<|im_end|>
+{% endfor %}{% if add_generation_prompt %}<|im_start|>assistant
+{% endif %}";
+
+/// 补全模式的对话模板，把消息列表渲染为一段提示词，用于只提供 `/completions` 接口的后端
+/// Chat template of the completion mode, rendering the message list into one prompt for backends that only expose
+/// `/completions`
+///
+/// 在模型元数据中设置后，该模型的请求改为补全请求：`messages` 渲染为 `prompt`，`stop` 附上模板的终止符，
+/// 不支持的 `tools` 等字段被移除；API的 `base_url` 应指向 `/completions` 接口。模板为 Jinja 风格，可用变量
+/// 与 Hugging Face 的对话模板一致：`messages`、`add_generation_prompt`、`bos_token`、`eos_token`。
+/// Once set in the model metadata, requests of that model become completion requests: `messages` is rendered into
+/// `prompt`, the template's stop tokens are added to `stop`, and unsupported fields such as `tools` are removed; the
+/// API's `base_url` should point at the `/completions` endpoint. Templates are Jinja-style with the same variables
+/// as Hugging Face chat templates: `messages`, `add_generation_prompt`, `bos_token` and `eos_token`.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+pub struct ChatTemplate {
+    pub template: String,
+
+    /// 分隔轮次的终止符，生成到此处即停止
+    /// Stop tokens delimiting the turns, generation stops there
+    #[serde(default)]
+    pub stop: Vec<String>,
+
+    #[serde(default)]
+    pub bos_token: String,
+
+    #[serde(default)]
+    pub eos_token: String,
+}
+
+impl ChatTemplate {
+    pub fn new(template: &str) -> Self {
+        Self {
+            template: template.to_string(),
+            ..Self::default()
+        }
+    }
+
+    /// ChatML 模板，以 `<|im_end|>` 终止
+    /// ChatML template, stopping at `<|im_end|>`
+    pub fn chatml() -> Self {
+        Self::new(CHATML_TEMPLATE).stop("<|im_end|>")
+    }
+
+    pub fn stop(mut self, stop: &str) -> Self {
+        self.stop.push(stop.to_string());
+        self
+    }
+
+    pub fn bos_token(mut self, token: &str) -> Self {
+        self.bos_token = token.to_string();
+        self
+    }
+
+    pub fn eos_token(mut self, token: &str) -> Self {
+        self.eos_token = token.to_string();
+        self
+    }
+
+    /// 检查模板能否编译
+    /// Check that the template compiles
+    pub fn check(&self) -> Result<(), ChatError> {
+        self.environment()?;
+        Ok(())
+    }
+
+    /// 渲染消息列表，消息内容中的分段只保留文字
+    /// Render a message list, only the text of multi-part message content is kept
+    pub fn render(&self, messages: &[Value], add_generation_prompt: bool) -> Result<String, ChatError> {
+        let messages: Vec<Value> = messages.iter().map(plain_message).collect();
+        self.environment()?
+            .get_template("chat")
+            .and_then(|template| {
+                template.render(context! {
+                    messages => messages,
+                    add_generation_prompt => add_generation_prompt,
+                    bos_token => self.bos_token,
+                    eos_token => self.eos_token,
+                })
+            })
+            .map_err(template_error)
+    }
+
+    /// 把对话补全请求体转换为补全请求体
+    /// Turn a chat completion request body into a completion request body
+    ///
+    /// 末尾的助手消息（如流式续传的前缀）不作为完整轮次渲染，而是接在生成提示之后继续生成。
+    /// A trailing assistant message (such as the prefix of a resumed stream) is not rendered as a finished turn, it
+    /// follows the generation prompt and is continued.
+    pub fn completion_body(&self, request_body: &Value) -> Result<Value, ChatError> {
+        let mut messages = request_body["messages"].as_array().cloned().unwrap_or_default();
+        let prefix = match messages.last() {
+            Some(last) if last["role"] == "assistant" && last.get("tool_calls").is_none() => {
+                let prefix = plain_message(last)["content"].as_str().unwrap_or_default().to_string();
+                messages.pop();
+                prefix
+            }
+            _ => String::new(),
+        };
+        let prompt = self.render(&messages, true)? + &prefix;
+
+        let mut body = request_body.clone();
+        if let Some(fields) = body.as_object_mut() {
+            for field in ["messages", "tools", "tool_choice", "parallel_tool_calls", "response_format"] {
+                fields.remove(field);
+            }
+        }
+        let mut stop: Vec<Value> = match body.get("stop") {
+            Some(Value::String(stop)) => vec![json!(stop)],
+            Some(Value::Array(stop)) => stop.clone(),
+            _ => Vec::new(),
+        };
+        for token in &self.stop {
+            if !stop.iter().any(|existing| existing == token) {
+                stop.push(json!(token));
+            }
+        }
+        body["prompt"] = json!(prompt);
+        if !stop.is_empty() {
+            body["stop"] = json!(stop);
+        }
+        Ok(body)
+    }
+
+    fn environment(&self) -> Result<Environment<'_>, ChatError> {
+        let mut environment = Environment::new();
+        // 与 Hugging Face 的模板渲染设置一致
+        // Same rendering settings as Hugging Face templates
+        environment.set_trim_blocks(true);
+        environment.set_lstrip_blocks(true);
+        environment.add_function("raise_exception", |message: String| -> std::result::Result<String, Error> {
+            Err(Error::new(ErrorKind::InvalidOperation, message))
+        });
+        environment.add_template("chat", &self.template).map_err(template_error)?;
+        Ok(environment)
+    }
+}
+
+fn template_error(error: Error) -> Report<ChatError> {
+    Report::new(ChatError::InvalidConfig).attach_printable(format!("Chat template error: {:#}", error))
+}
+
+/// 消息内容为分段时合并其文字，模板通常只处理字符串内容
+/// Merge the text of multi-part message content, templates usually only handle string content
+fn plain_message(message: &Value) -> Value {
+    let Some(parts) = message["content"].as_array() else {
+        return message.clone();
+    };
+    let text = parts.iter().filter_map(|part| part["text"].as_str()).collect::<Vec<_>>().join("\n");
+    let mut message = message.clone();
+    message["content"] = json!(text);
+    message
+}
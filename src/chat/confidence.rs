@@ -0,0 +1,318 @@
+// 标准库
+use std::collections::HashSet;
+
+// 错误处理
+use error_stack::{Result, ResultExt};
+
+// 异步
+use futures::future::join_all;
+
+// 数据序列化
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+// 观测诊断
+use tracing::warn;
+
+// 项目内部模块
+use crate::chat::chat_base::{BaseChat, ChatError};
+use crate::chat::chat_tool::strip_code_fence;
+use crate::chat::summary::{cheap_helper, helper_chat};
+
+/// 评判模型的指令
+/// Instruction of the judge model
+const JUDGE_INSTRUCTION: &str = "你是严格的答案审核员。根据问题判断回答正确且完整的可能性，\
+     给出0到1的分数（1为确定正确，0为确定错误或答非所问），不确定时给出中间值。\
+     只输出JSON：{\"score\": 分数}";
+
+/// 回答的置信度评估：综合 token 概率、多次采样的一致性与评判模型的判断，给出 0 到 1 的分数，
+/// 低置信度的回答可转交人工处理而不是直接返回给用户
+/// Confidence estimation of answers: combines token probabilities, agreement across several samples and a judge
+/// model's verdict into a score from 0 to 1, so low-confidence answers can be routed to a human instead of being
+/// returned to the user
+///
+/// 三种信号各自可选，分数是可用信号的加权平均。token 概率只在提供商返回 logprobs 时可用；一致性为额外采样的回答
+/// 与原回答的平均相似度，每次采样都是一次完整请求，带有 `confidence` 标签并计入用量。
+/// Each of the three signals is optional and the score is the weighted mean of those available. Token probabilities
+/// are only available when the provider returns logprobs; agreement is the mean similarity of the extra samples to
+/// the answer, every sample being a full request tagged `confidence` that counts towards usage.
+///
+/// ```ignore
+/// chat.set_confidence(ConfidenceScorer::new().samples(3).threshold(0.6));
+/// let answer = chat.get_scored_answer("退款需要几天到账？").await?;
+/// if answer.confidence.is_low() {
+///     escalate_to_human(&answer.text);
+/// }
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConfidenceScorer {
+    /// 是否请求 logprobs 并使用 token 概率
+    /// Whether to request logprobs and use token probabilities
+    pub logprobs: bool,
+
+    /// 一致性检查的额外采样次数，为 0 时不检查一致性
+    /// Extra samples of the agreement check, 0 skips it
+    pub samples: usize,
+
+    /// 额外采样的温度
+    /// Temperature of the extra samples
+    pub sample_temperature: f64,
+
+    /// 是否由评判模型判断回答
+    /// Whether a judge model rates the answer
+    pub judge: bool,
+
+    /// 评判模型的API，为 None 时使用廉价模型，没有廉价模型时使用对话自身的API
+    /// API of the judge model; the cheap model if None, or the chat's own API if there is no cheap model
+    pub judge_api: Option<String>,
+
+    /// token 概率、一致性与评判的权重
+    /// Weights of the token probability, the agreement and the judge
+    pub weights: [f64; 3],
+
+    pub threshold: f64,
+}
+
+impl Default for ConfidenceScorer {
+    fn default() -> Self {
+        Self {
+            logprobs: true,
+            samples: 0,
+            sample_temperature: 1.0,
+            judge: true,
+            judge_api: None,
+            weights: [1.0; 3],
+            threshold: 0.5,
+        }
+    }
+}
+
+impl ConfidenceScorer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 不支持 logprobs 参数的提供商可能拒绝请求，此时关闭
+    /// Turn off for providers that reject the logprobs parameter
+    pub fn logprobs(mut self, logprobs: bool) -> Self {
+        self.logprobs = logprobs;
+        self
+    }
+
+    pub fn samples(mut self, samples: usize) -> Self {
+        self.samples = samples;
+        self
+    }
+
+    pub fn sample_temperature(mut self, temperature: f64) -> Self {
+        self.sample_temperature = temperature;
+        self
+    }
+
+    pub fn judge(mut self, judge: bool) -> Self {
+        self.judge = judge;
+        self
+    }
+
+    pub fn judge_api(mut self, api_name: &str) -> Self {
+        self.judge_api = Some(api_name.to_string());
+        self
+    }
+
+    /// 按 token 概率、一致性、评判的顺序设置权重，权重为 0 的信号不参与计算
+    /// Set the weights in the order token probability, agreement, judge; signals weighted 0 are left out
+    pub fn weights(mut self, logprob: f64, consistency: f64, judge: f64) -> Self {
+        self.weights = [logprob, consistency, judge];
+        self
+    }
+
+    pub fn threshold(mut self, threshold: f64) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// 按设置在请求中要求 logprobs
+    /// Ask for logprobs in the request as configured
+    pub(crate) fn prepare(&self, request_body: &mut Value) {
+        if self.logprobs && self.weights[0] > 0.0 {
+            request_body["logprobs"] = json!(true);
+        }
+    }
+
+    /// 评估回答的置信度；`request_body` 为得到回答的请求，`token_probability` 为其 token 概率，额外采样的用量计入 `chat`
+    /// Estimate the confidence of an answer; `request_body` is the request that produced it and `token_probability`
+    /// its token probability, the usage of the extra samples is added to `chat`
+    pub async fn score(
+        &self,
+        chat: &mut BaseChat,
+        request_body: &Value,
+        answer: &str,
+        token_probability: Option<f64>,
+    ) -> Result<ConfidenceReport, ChatError> {
+        let mut report = ConfidenceReport {
+            threshold: self.threshold,
+            logprob: token_probability.filter(|_| self.logprobs && self.weights[0] > 0.0),
+            ..Default::default()
+        };
+        if self.samples > 0 && self.weights[1] > 0.0 {
+            report.consistency = self.consistency(chat, request_body, answer).await;
+        }
+        if self.judge && self.weights[2] > 0.0 {
+            report.judge = self.ask_judge(chat, &last_message(request_body), answer).await;
+        }
+        report.score = report.weighted_score(&self.weights);
+        Ok(report)
+    }
+
+    /// 并发地额外采样，返回采样回答与原回答的平均相似度；全部采样失败时为 None
+    /// Draw the extra samples concurrently, returning their mean similarity to the answer; None if every sample failed
+    async fn consistency(&self, chat: &mut BaseChat, request_body: &Value, answer: &str) -> Option<f64> {
+        let mut request_body = request_body.clone();
+        request_body["temperature"] = json!(self.sample_temperature);
+        if let Some(body) = request_body.as_object_mut() {
+            body.remove("logprobs");
+        }
+        let usage = chat.usage;
+        let samples = (0..self.samples).map(|_| {
+            let mut sampler = chat.clone();
+            sampler.tags.push("confidence".to_string());
+            let request_body = request_body.clone();
+            async move {
+                let sample = sampler.get_content(request_body).await;
+                (sample, sampler.usage)
+            }
+        });
+        let mut similarities = Vec::new();
+        for (sample, sample_usage) in join_all(samples).await {
+            chat.usage += sample_usage - usage;
+            match sample {
+                Ok(sample) => similarities.push(similarity(answer, &sample)),
+                Err(e) => warn!("Confidence sample failed: {:?}", e),
+            }
+        }
+        if similarities.is_empty() {
+            return None;
+        }
+        Some(similarities.iter().sum::<f64>() / similarities.len() as f64)
+    }
+
+    /// 由评判模型判断回答正确的可能性；请求失败或判断无法解析时为 None
+    /// Have the judge model rate how likely the answer is correct; None if the request fails or the verdict does not
+    /// parse
+    async fn ask_judge(&self, chat: &BaseChat, question: &str, answer: &str) -> Option<f64> {
+        match self.judge_verdict(chat, question, answer).await {
+            Ok(score) => Some(score),
+            Err(e) => {
+                warn!("Confidence judge failed: {:?}", e);
+                None
+            }
+        }
+    }
+
+    async fn judge_verdict(&self, chat: &BaseChat, question: &str, answer: &str) -> Result<f64, ChatError> {
+        let mut judge = match &self.judge_api {
+            Some(api_name) => {
+                let judge = BaseChat::try_new_with_api_name(api_name, "", false)?;
+                helper_chat(chat, judge, JUDGE_INSTRUCTION)?
+            }
+            None => cheap_helper(chat, JUDGE_INSTRUCTION)?,
+        };
+
+        let verdict = judge
+            .get_answer(&format!("问题：\n{}\n\n回答：\n{}", question, answer))
+            .await?;
+
+        #[derive(Deserialize)]
+        struct Verdict {
+            score: f64,
+        }
+        let verdict: Verdict = serde_json::from_str(strip_code_fence(&verdict))
+            .change_context(ChatError::GetJsonError)
+            .attach_printable_lazy(|| format!("Failed to parse the confidence verdict: {}", verdict))?;
+        Ok(verdict.score.clamp(0.0, 1.0))
+    }
+}
+
+/// 请求中的最后一条消息
+/// The last message of the request
+fn last_message(request_body: &Value) -> String {
+    request_body["messages"]
+        .as_array()
+        .and_then(|messages| messages.last())
+        .map(|message| match &message["content"] {
+            Value::String(text) => text.clone(),
+            content => content.to_string(),
+        })
+        .unwrap_or_default()
+}
+
+/// 两段回答的相似度：忽略大小写、空白与标点后字符二元组的 Jaccard 系数，0 到 1
+/// Similarity of two answers: Jaccard index of their character bigrams ignoring case, whitespace and punctuation,
+/// 0 to 1
+pub(crate) fn similarity(a: &str, b: &str) -> f64 {
+    fn bigrams(text: &str) -> HashSet<(char, char)> {
+        let chars: Vec<char> = text.chars().filter(|c| c.is_alphanumeric()).flat_map(char::to_lowercase).collect();
+        match chars.len() {
+            0 => HashSet::new(),
+            1 => HashSet::from([(chars[0], chars[0])]),
+            _ => chars.windows(2).map(|pair| (pair[0], pair[1])).collect(),
+        }
+    }
+    let (a, b) = (bigrams(a), bigrams(b));
+    if a.is_empty() && b.is_empty() {
+        return 1.0;
+    }
+    a.intersection(&b).count() as f64 / a.union(&b).count() as f64
+}
+
+/// 置信度评估结果，各信号为 None 表示未使用或不可用
+/// Result of a confidence estimation, a signal is None when it was not used or not available
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ConfidenceReport {
+    /// 综合分数，0 到 1；没有任何可用信号时为 0，按低置信度处理
+    /// Combined score, 0 to 1; 0 without any available signal, so it is handled as low confidence
+    pub score: f64,
+
+    /// 回答的 token 几何平均概率
+    /// Geometric mean token probability of the answer
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logprob: Option<f64>,
+
+    /// 额外采样与回答的平均相似度
+    /// Mean similarity of the extra samples to the answer
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub consistency: Option<f64>,
+
+    /// 评判模型给出的分数
+    /// Score given by the judge model
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub judge: Option<f64>,
+
+    pub threshold: f64,
+}
+
+impl ConfidenceReport {
+    /// 综合分数是否低于阈值
+    /// Whether the combined score is below the threshold
+    pub fn is_low(&self) -> bool {
+        self.score < self.threshold
+    }
+
+    fn weighted_score(&self, weights: &[f64; 3]) -> f64 {
+        let (total, weight) = [self.logprob, self.consistency, self.judge]
+            .iter()
+            .zip(weights)
+            .filter_map(|(signal, weight)| signal.map(|signal| (signal * weight, *weight)))
+            .fold((0.0, 0.0), |(total, sum), (value, weight)| (total + value, sum + weight));
+        if weight > 0.0 { (total / weight).clamp(0.0, 1.0) } else { 0.0 }
+    }
+}
+
+/// 带置信度的回答
+/// An answer with its confidence
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ScoredAnswer {
+    pub text: String,
+
+    pub confidence: ConfidenceReport,
+}
@@ -0,0 +1,345 @@
+// 标准库
+use std::borrow::Cow;
+use std::fmt::Display;
+use std::sync::Arc;
+use std::time::Duration;
+
+// 序列化
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+/// 消息内容
+/// Message content
+///
+/// 纯文本序列化为字符串，与旧版会话兼容；其余类型序列化为带字段的对象，多段内容序列化为数组。
+/// Plain text serializes to a string, compatible with older sessions; other kinds serialize to objects with their
+/// own fields, and multi-part content serializes to an array.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Content {
+    Text(Arc<str>),
+
+    /// 图片，`url` 为 http(s) 地址或 data URL
+    /// Image, `url` is an http(s) address or a data URL
+    Image { url: Arc<str> },
+
+    /// 音频，`data` 为 base64 编码，`format` 如 `wav`、`mp3`
+    /// Audio, `data` is base64 encoded, `format` such as `wav` or `mp3`
+    Audio { data: Arc<str>, format: String },
+
+    /// 模型发起的工具调用，`arguments` 为 JSON 字符串
+    /// Tool call made by the model, `arguments` is a JSON string
+    ToolCall { id: String, name: String, arguments: Arc<str> },
+
+    /// 工具调用的结果，由本库执行的工具另记录工具名称、耗时与是否失败
+    /// Result of a tool call; tools run by this crate also record the tool name, duration and whether it failed
+    ToolResult {
+        call_id: String,
+        content: Arc<str>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        name: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        duration_ms: Option<u64>,
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        is_error: bool,
+        /// 工具返回的图片（http(s) 地址或 data URL），结果文本中以 `[image N]` 占位
+        /// Images returned by the tool (http(s) addresses or data URLs), marked `[image N]` in the result text
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        images: Vec<Arc<str>>,
+    },
+
+    /// 文件，`data` 为 base64 编码
+    /// File, `data` is base64 encoded
+    File { name: String, mime_type: String, data: Arc<str> },
+
+    /// 多段内容，如文字加图片
+    /// Multi-part content, such as text with images
+    Parts(Vec<Content>),
+}
+
+impl Default for Content {
+    fn default() -> Self {
+        Self::Text(Arc::from(""))
+    }
+}
+
+impl From<&str> for Content {
+    fn from(text: &str) -> Self {
+        Self::Text(Arc::from(text))
+    }
+}
+
+impl From<String> for Content {
+    fn from(text: String) -> Self {
+        Self::Text(Arc::from(text))
+    }
+}
+
+impl From<Arc<str>> for Content {
+    fn from(text: Arc<str>) -> Self {
+        Self::Text(text)
+    }
+}
+
+impl From<Vec<Content>> for Content {
+    fn from(parts: Vec<Content>) -> Self {
+        Self::Parts(parts)
+    }
+}
+
+impl Display for Content {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.to_text())
+    }
+}
+
+impl Content {
+    pub fn image(url: &str) -> Self {
+        Self::Image { url: Arc::from(url) }
+    }
+
+    pub fn audio(data: &str, format: &str) -> Self {
+        Self::Audio {
+            data: Arc::from(data),
+            format: format.to_string(),
+        }
+    }
+
+    pub fn tool_call(id: &str, name: &str, arguments: &str) -> Self {
+        Self::ToolCall {
+            id: id.to_string(),
+            name: name.to_string(),
+            arguments: Arc::from(arguments),
+        }
+    }
+
+    pub fn tool_result(call_id: &str, content: &str) -> Self {
+        Self::ToolResult {
+            call_id: call_id.to_string(),
+            content: Arc::from(content),
+            name: None,
+            duration_ms: None,
+            is_error: false,
+            images: Vec::new(),
+        }
+    }
+
+    /// 本库执行的工具的结果，带工具名称、耗时与是否失败
+    /// Result of a tool run by this crate, with the tool name, duration and whether it failed
+    pub fn tool_outcome(call_id: &str, name: &str, output: &str, duration: Duration, is_error: bool) -> Self {
+        Self::ToolResult {
+            call_id: call_id.to_string(),
+            content: Arc::from(output),
+            name: Some(name.to_string()),
+            duration_ms: Some(duration.as_millis() as u64),
+            is_error,
+            images: Vec::new(),
+        }
+    }
+
+    /// 为工具结果附上工具返回的图片，其他类型的内容不变
+    /// Attach the images returned by the tool to a tool result, other kinds of content are unchanged
+    pub fn with_images(mut self, urls: Vec<Arc<str>>) -> Self {
+        if let Self::ToolResult { images, .. } = &mut self {
+            *images = urls;
+        }
+        self
+    }
+
+    pub fn file(name: &str, mime_type: &str, data: &str) -> Self {
+        Self::File {
+            name: name.to_string(),
+            mime_type: mime_type.to_string(),
+            data: Arc::from(data),
+        }
+    }
+
+    /// 纯文本内容，其他类型返回 None
+    /// The text of plain text content, None for other kinds
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            Self::Text(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    /// 内容的文本形式，非文本部分以简短的占位描述代替，用于日志与不支持该类型的提供商
+    /// Text form of the content, non-text parts are replaced by short placeholders; used for logs and for
+    /// providers that do not support the kind
+    pub fn to_text(&self) -> Cow<'_, str> {
+        match self {
+            Self::Text(text) => Cow::Borrowed(text),
+            Self::Image { url } if url.starts_with("data:") => Cow::Borrowed("[image]"),
+            Self::Image { url } => Cow::Owned(format!("[image: {}]", url)),
+            Self::Audio { format, .. } => Cow::Owned(format!("[audio: {}]", format)),
+            Self::ToolCall { name, arguments, .. } => Cow::Owned(format!("[tool call: {}({})]", name, arguments)),
+            Self::ToolResult { call_id, content, .. } => Cow::Owned(format!("[tool result {}]: {}", call_id, content)),
+            Self::File { name, .. } => Cow::Owned(format!("[file: {}]", name)),
+            Self::Parts(parts) => Cow::Owned(parts.iter().map(Content::to_text).collect::<Vec<_>>().join("\n")),
+        }
+    }
+
+    /// 各部分，单一内容视为只有一部分
+    /// The parts, a single piece of content counts as one part
+    pub fn parts(&self) -> &[Content] {
+        match self {
+            Self::Parts(parts) => parts,
+            single => std::slice::from_ref(single),
+        }
+    }
+
+    /// 按提供商渲染为请求体中的消息字段
+    /// Render into the message fields of a request body for a provider
+    pub(crate) fn render(&self, provider: &str) -> RenderedContent<'_> {
+        if let Self::Text(text) = self {
+            return RenderedContent {
+                content: ApiContent::Text(Cow::Borrowed(text)),
+                ..Default::default()
+            };
+        }
+        if let Self::ToolResult { call_id, content, images, .. } = self {
+            // 接受工具消息中图片段的提供商直接内嵌图片，其余提供商的图片由 `deferred_tool_images` 另行发送
+            // Providers accepting image parts in tool messages get the images inline, the others get them sent
+            // separately by `deferred_tool_images`
+            let content = match images.is_empty() || !supports(provider, Modality::ToolImage) {
+                true => ApiContent::Text(Cow::Borrowed(content)),
+                false => ApiContent::Parts(
+                    std::iter::once(json!({"type": "text", "text": content}))
+                        .chain(images.iter().map(|url| json!({"type": "image_url", "image_url": {"url": url}})))
+                        .collect(),
+                ),
+            };
+            return RenderedContent {
+                content,
+                tool_call_id: Some(call_id),
+                ..Default::default()
+            };
+        }
+
+        let mut parts = Vec::new();
+        let mut tool_calls = Vec::new();
+        for part in self.parts() {
+            match part {
+                Self::ToolCall { id, name, arguments } => tool_calls.push(json!({
+                    "id": id,
+                    "type": "function",
+                    "function": {"name": name, "arguments": arguments},
+                })),
+                Self::Image { url } if supports(provider, Modality::Image) => {
+                    parts.push(json!({"type": "image_url", "image_url": {"url": url}}))
+                }
+                Self::Audio { data, format } if supports(provider, Modality::Audio) => {
+                    parts.push(json!({"type": "input_audio", "input_audio": {"data": data, "format": format}}))
+                }
+                Self::File { name, mime_type, data } if supports(provider, Modality::File) => parts.push(json!({
+                    "type": "file",
+                    "file": {"filename": name, "file_data": format!("data:{};base64,{}", mime_type, data)},
+                })),
+                other => parts.push(json!({"type": "text", "text": other.to_text()})),
+            }
+        }
+
+        // 只有文字时合并为字符串，兼容不接受分段内容的提供商
+        // Merge text-only content into a string, for providers that do not accept content parts
+        let content = if parts.is_empty() {
+            ApiContent::Null
+        } else if parts.iter().all(|part| part["type"] == "text") {
+            let text = parts.iter().filter_map(|part| part["text"].as_str()).collect::<Vec<_>>().join("\n");
+            ApiContent::Text(Cow::Owned(text))
+        } else {
+            ApiContent::Parts(parts)
+        };
+
+        RenderedContent {
+            content,
+            tool_calls: (!tool_calls.is_empty()).then_some(tool_calls),
+            tool_call_id: None,
+        }
+    }
+
+    /// 工具消息只接受文本的提供商（如 OpenAI）需要另行发送的工具结果图片：每个带图片的结果一段说明加其图片段，
+    /// 在这一轮工具结果之后作为一条用户消息发送；不支持图片的提供商返回空列表，图片只留下文本中的占位
+    /// Tool result images to send separately for providers whose tool messages only take text (such as OpenAI): a
+    /// label plus the image parts for each result with images, sent as one user message after the round of tool
+    /// results; empty for providers without image support, leaving only the placeholders in the text
+    pub(crate) fn deferred_tool_images(&self, provider: &str) -> Vec<serde_json::Value> {
+        if !supports(provider, Modality::Image) || supports(provider, Modality::ToolImage) {
+            return Vec::new();
+        }
+        let mut parts = Vec::new();
+        for part in self.parts() {
+            let Self::ToolResult { call_id, name, images, .. } = part else {
+                continue;
+            };
+            if images.is_empty() {
+                continue;
+            }
+            let label = match name {
+                Some(name) => format!("Images returned by tool call {} ({}):", call_id, name),
+                None => format!("Images returned by tool call {}:", call_id),
+            };
+            parts.push(json!({"type": "text", "text": label}));
+            parts.extend(images.iter().map(|url| json!({"type": "image_url", "image_url": {"url": url}})));
+        }
+        parts
+    }
+}
+
+/// 请求体中的消息内容
+/// Message content in a request body
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(untagged)]
+pub enum ApiContent<'a> {
+    Text(Cow<'a, str>),
+
+    Parts(Vec<serde_json::Value>),
+
+    #[default]
+    Null,
+}
+
+impl ApiContent<'_> {
+    /// 文本内容，分段或空内容返回 None
+    /// The text content, None for parts or empty content
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            Self::Text(text) => Some(text),
+            _ => None,
+        }
+    }
+}
+
+/// 渲染后的消息字段
+/// Rendered message fields
+#[derive(Debug, Default)]
+pub(crate) struct RenderedContent<'a> {
+    pub content: ApiContent<'a>,
+
+    pub tool_calls: Option<Vec<serde_json::Value>>,
+
+    pub tool_call_id: Option<&'a str>,
+}
+
+enum Modality {
+    Image,
+    Audio,
+    File,
+
+    /// 工具结果消息中的图片
+    /// Images inside tool result messages
+    ToolImage,
+}
+
+/// 提供商的 OpenAI 兼容接口是否接受该类型的内容
+/// Whether the OpenAI-compatible API of a provider accepts the kind of content
+fn supports(provider: &str, modality: Modality) -> bool {
+    match modality {
+        Modality::Image => matches!(
+            provider,
+            "openai" | "azure.ai.openai" | "anthropic" | "gcp.gemini" | "xai" | "mistral_ai" | "groq"
+        ),
+        Modality::Audio => matches!(provider, "openai" | "azure.ai.openai" | "gcp.gemini"),
+        Modality::File => matches!(provider, "openai" | "azure.ai.openai"),
+        Modality::ToolImage => provider == "anthropic",
+    }
+}
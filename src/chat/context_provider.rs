@@ -0,0 +1,139 @@
+// 标准库
+use std::sync::Arc;
+
+// 异步
+use futures::future::BoxFuture;
+
+// 模板引擎
+use minijinja::{Environment, UndefinedBehavior};
+
+// 数据序列化
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+// 错误处理
+use error_stack::{Report, Result, ResultExt};
+
+// 项目内部模块
+use crate::chat::chat_base::ChatError;
+
+/// 应用状态的提供者，对话在每次请求前查询，返回的变量渲染进系统提示词模板，如当前用户、时间与功能开关，
+/// 使动态上下文保持最新而无需手动替换消息
+/// Provider of application state, queried by chats before every request; the variables it returns are rendered
+/// into the system prompt template, such as the current user, the time and feature flags, so dynamic context stays
+/// current without juggling messages by hand
+///
+/// 系统提示词按 Jinja 语法渲染，如 `当前用户：{{ user.name }}`；会话中保存的仍是模板，渲染结果只进入本次请求。
+/// 多个提供者的变量合并，同名变量以后添加的为准；变量中的文本在渲染前经过对话的PII脱敏。
+/// 引用了提供的变量的模板又引用不存在的变量时请求失败，而不是发出缺字的提示词；没有引用任何提供的变量的提示词原样发送。
+/// The system prompt is rendered with Jinja syntax, such as `Current user: {{ user.name }}`; the session keeps the
+/// template and the rendered prompt only goes into the request. Variables of several providers are merged, later
+/// ones winning on equal names; texts in the variables go through the chat's PII scrubbing before rendering. A
+/// template referring to a provided variable and to a missing one fails the request instead of sending a prompt with
+/// gaps; prompts referring to no provided variable are sent as they are.
+///
+/// ```ignore
+/// #[derive(Serialize)]
+/// struct AppState { user: String, beta: bool }
+///
+/// chat.add_context_provider(Arc::new(TypedContext::new(|| AppState { user: current_user(), beta: beta_on() })));
+/// ```
+pub trait ContextProvider: Send + Sync {
+    /// 本次请求的变量
+    /// Variables of this request
+    fn variables(&self) -> BoxFuture<'_, Result<Map<String, Value>, ChatError>>;
+}
+
+/// 由函数返回的可序列化结构体提供变量，结构体的字段即变量名
+/// Variables provided by a serializable struct returned from a function, the struct's fields being the variable
+/// names
+pub struct TypedContext<F> {
+    state: F,
+}
+
+impl<F, T> TypedContext<F>
+where
+    F: Fn() -> T + Send + Sync,
+    T: Serialize,
+{
+    pub fn new(state: F) -> Self {
+        Self { state }
+    }
+}
+
+impl<F, T> ContextProvider for TypedContext<F>
+where
+    F: Fn() -> T + Send + Sync,
+    T: Serialize,
+{
+    fn variables(&self) -> BoxFuture<'_, Result<Map<String, Value>, ChatError>> {
+        let variables = serde_json::to_value((self.state)())
+            .change_context_lazy(|| ChatError::ContextError("state is not serializable".to_string()))
+            .and_then(|value| match value {
+                Value::Object(variables) => Ok(variables),
+                value => Err(Report::new(ChatError::ContextError(format!("state is not a struct or map: {}", value)))),
+            });
+        Box::pin(async move { variables })
+    }
+}
+
+/// 查询所有提供者并合并它们的变量，同名变量以后添加的为准
+/// Query every provider and merge their variables, later ones winning on equal names
+pub(crate) async fn context_variables(providers: &[Arc<dyn ContextProvider>]) -> Result<Map<String, Value>, ChatError> {
+    let mut variables = Map::new();
+    for provider in providers {
+        variables.extend(provider.variables().await?);
+    }
+    Ok(variables)
+}
+
+/// 变量中的全部字符串，供渲染前脱敏
+/// Every string in the variables, for scrubbing before rendering
+pub(crate) fn variable_texts(variables: &mut Map<String, Value>) -> Vec<&mut String> {
+    let mut texts = Vec::new();
+    let mut pending: Vec<&mut Value> = variables.values_mut().collect();
+    while let Some(value) = pending.pop() {
+        match value {
+            Value::String(text) => texts.push(text),
+            Value::Array(values) => pending.extend(values.iter_mut()),
+            Value::Object(values) => pending.extend(values.values_mut()),
+            _ => {}
+        }
+    }
+    texts
+}
+
+/// 按变量渲染请求中第一条系统消息的模板
+/// Render the template of the first system message in the request with the variables
+///
+/// 只有能解析且引用了提供的变量的提示词才按模板渲染，其余提示词原样保留，因此提示词中字面的 `{{` 或 `{%` 不会导致请求失败；
+/// 渲染的提示词中需要字面的 `{{` 时用 `{% raw %}` 包裹。
+/// Only prompts that parse and refer to a provided variable are rendered as templates, others are kept as they are,
+/// so a literal `{{` or `{%` in a prompt does not fail the request; wrap literal `{{` in `{% raw %}` within prompts
+/// that are rendered.
+pub(crate) fn render_system_prompt(request_body: &mut Value, variables: Map<String, Value>) -> Result<(), ChatError> {
+    let Some(content) = request_body["messages"]
+        .as_array_mut()
+        .and_then(|messages| messages.iter_mut().find(|message| message["role"] == "system"))
+        .map(|message| &mut message["content"])
+    else {
+        return Ok(());
+    };
+    let Some(source) = content.as_str() else {
+        return Ok(());
+    };
+
+    let mut environment = Environment::new();
+    environment.set_undefined_behavior(UndefinedBehavior::Strict);
+    let Ok(template) = environment.template_from_str(source) else {
+        return Ok(());
+    };
+    if !template.undeclared_variables(false).iter().any(|name| variables.contains_key(name)) {
+        return Ok(());
+    }
+    let rendered = template
+        .render(Value::Object(variables))
+        .map_err(|e| Report::new(ChatError::ContextError(format!("{:#}", e))))?;
+    *content = Value::String(rendered);
+    Ok(())
+}
@@ -0,0 +1,138 @@
+// 错误处理
+use error_stack::{Result, ResultExt};
+
+// 数据序列化
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::{Value, json};
+
+// 项目内部模块
+use crate::chat::chat_base::{BaseChat, ChatError};
+use crate::chat::chat_single::SingleChat;
+use crate::chat::summary::{cheap_helper, helper_chat};
+use crate::config::Config;
+use crate::config::prompts::PromptKey;
+use crate::schema::json_schema::JsonSchema;
+
+/// 多轮对话中按类型维护的状态，如表单或预订中已收集的字段
+/// Typed state kept across the turns of a dialogue, such as the fields collected so far by a form or a booking
+///
+/// 每轮先用廉价模型从用户的发言中结构化提取，更新状态，再把状态与尚未填写的字段附在本次请求中，使回答基于最新的
+/// 状态并追问缺少的信息。状态只进入请求，不写入会话历史；应用可随时读取或修改状态。
+/// Every turn first updates the state by structured extraction from the user's words with a cheap model, then
+/// attaches the state and the fields still missing to the request, so the answer builds on the latest state and asks
+/// for what is missing. The state only goes into requests and is never written to the session history; the app can
+/// read or change it at any time.
+#[derive(Clone, Debug)]
+pub struct DialogueState<T> {
+    chat: SingleChat,
+    state: T,
+    extract_api: Option<String>,
+    last_reply: Option<String>,
+}
+
+impl<T> DialogueState<T>
+where
+    T: JsonSchema + Serialize + DeserializeOwned + 'static,
+{
+    /// `state` 为初始状态，通常所有字段为空
+    /// `state` is the initial state, usually with every field empty
+    pub fn new(chat: SingleChat, state: T) -> Self {
+        Self {
+            chat,
+            state,
+            extract_api: None,
+            last_reply: None,
+        }
+    }
+
+    /// 用指定的API提取状态，默认使用绑定 `ModelCapability::Cheap` 的API，没有时使用对话自身的API
+    /// Extract the state with the given API, by default an API bound to `ModelCapability::Cheap`, or the chat's own
+    /// API if there is none
+    pub fn extract_with(mut self, api: &str) -> Self {
+        self.extract_api = Some(api.to_string());
+        self
+    }
+
+    pub fn state(&self) -> &T {
+        &self.state
+    }
+
+    /// 由应用修改状态，如用户在界面上直接填写了字段
+    /// Change the state from the app, such as when the user filled in a field in the UI directly
+    pub fn set_state(&mut self, state: T) {
+        self.state = state;
+    }
+
+    pub fn chat(&self) -> &SingleChat {
+        &self.chat
+    }
+
+    pub fn chat_mut(&mut self) -> &mut SingleChat {
+        &mut self.chat
+    }
+
+    /// 尚未填写的顶层字段，即状态中为 null 的字段，按字段名排序
+    /// Top-level fields not filled in yet, those that are null in the state, sorted by name
+    pub fn missing(&self) -> Vec<String> {
+        match serde_json::to_value(&self.state) {
+            Ok(Value::Object(fields)) => {
+                fields.into_iter().filter(|(_, value)| value.is_null()).map(|(name, _)| name).collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.missing().is_empty()
+    }
+
+    /// 从用户的发言中提取字段并更新状态，不向对话提问
+    /// Extract fields from the user's words and update the state, without asking the chat
+    pub async fn update(&mut self, user_input: &str) -> Result<&T, ChatError> {
+        let current = serde_json::to_string(&self.state).change_context(ChatError::GetJsonError)?;
+        let last_reply = match &self.last_reply {
+            Some(reply) => Config::render_prompt(&PromptKey::DialogueStateLastReply, &[reply]),
+            None => String::new(),
+        };
+        let material = Config::render_prompt(&PromptKey::DialogueStateMaterial, &[&current, &last_reply, user_input]);
+
+        let instruction = Config::capability_prompt(&PromptKey::DialogueStateExtraction);
+        let mut helper = match &self.extract_api {
+            Some(api) => {
+                let base = BaseChat::try_new_with_api_name(api, "", false)?;
+                helper_chat(&self.chat.base, base, &instruction)?
+            }
+            None => cheap_helper(&self.chat.base, &instruction)?,
+        };
+        let state = helper.get_json_answer::<T>(&material).await;
+        self.state = state.attach_printable("Failed to update the dialogue state")?;
+        Ok(&self.state)
+    }
+
+    /// 进行一轮对话：更新状态，再带着状态提问并返回回答
+    /// Take one turn: update the state, then ask with the state attached and return the answer
+    pub async fn turn(&mut self, user_input: &str) -> Result<String, ChatError> {
+        self.update(user_input).await?;
+        let state = serde_json::to_string_pretty(&self.state).change_context(ChatError::GetJsonError)?;
+        let mut request_body = self.chat.get_req_body(user_input).await?;
+        insert_state(&mut request_body, &state, &self.missing());
+        let answer = self.chat.get_content_from_req_body(request_body).await?;
+        let answer = self.chat.base.restore_pii(answer);
+        self.last_reply = Some(answer.clone());
+        Ok(answer)
+    }
+}
+
+/// 在请求的最后一条消息之前插入状态说明
+/// Insert the state description before the last message of the request
+fn insert_state(request_body: &mut Value, state: &str, missing: &[String]) {
+    let mut content = Config::render_prompt(&PromptKey::DialogueStateNotice, &[state]);
+    if !missing.is_empty() {
+        content.push_str(&Config::render_prompt(&PromptKey::DialogueStateMissing, &[&missing.join(", ")]));
+    }
+    if let Some(messages) = request_body.get_mut("messages").and_then(|messages| messages.as_array_mut()) {
+        let position = messages.len().saturating_sub(1);
+        messages.insert(position, json!({"role": "system", "content": content}));
+    }
+}
@@ -0,0 +1,268 @@
+// 标准库
+use std::path::Path;
+use std::sync::Arc;
+
+// 加密
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+
+// 数据序列化
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use bytes::Bytes;
+
+// 错误处理
+use error_stack::{Report, Result, ResultExt};
+use thiserror::Error;
+
+// 项目内部模块
+use crate::chat::attachment::AttachmentStore;
+use crate::chat::chat_base::ChatError;
+use crate::chat::message::Session;
+use crate::chat::store::{SessionCodec, StoreError};
+use crate::config::secrets::resolve_secret;
+
+/// 加密数据的开头标记，没有该标记的数据视为加密启用前写入的明文
+/// Leading marker of encrypted data, data without it is treated as plaintext written before encryption was enabled
+const MAGIC: &[u8] = b"RHENC1";
+
+const NONCE_LEN: usize = 12;
+
+/// 静态加密错误枚举
+/// At-rest encryption error enum
+#[derive(Debug, Error)]
+pub enum EncryptionError {
+    /// 密钥不存在或格式错误
+    /// Key missing or malformed
+    #[error("Encryption key unavailable: {0}")]
+    KeyUnavailable(String),
+
+    /// 加密失败
+    /// Encryption failed
+    #[error("Encryption failed")]
+    EncryptFailed,
+
+    /// 解密失败，数据损坏或密钥不匹配
+    /// Decryption failed, the data is corrupted or the key does not match
+    #[error("Decryption failed: {0}")]
+    DecryptFailed(String),
+
+    /// 读写文件失败
+    /// Reading or writing a file failed
+    #[error("Encrypted file IO error: {0}")]
+    IoError(String),
+}
+
+/// 密钥提供者，按ID给出 256 位密钥；密文记录加密时的密钥ID，轮换后旧数据仍可解密
+/// Key provider, yields 256-bit keys by ID; ciphertext records the ID it was encrypted with, so old data still
+/// decrypts after a rotation
+pub trait KeyProvider: Send + Sync {
+    /// 新数据使用的密钥ID
+    /// ID of the key used for new data
+    fn current_key_id(&self) -> String;
+
+    fn key(&self, key_id: &str) -> Result<[u8; 32], EncryptionError>;
+}
+
+/// 固定密钥提供者，密钥直接在代码中给出
+/// Fixed key provider, the keys are given in code
+#[derive(Clone)]
+pub struct StaticKeyProvider {
+    current: String,
+    keys: Vec<(String, [u8; 32])>,
+}
+
+impl StaticKeyProvider {
+    pub fn new(key_id: &str, key: [u8; 32]) -> Self {
+        Self {
+            current: key_id.to_string(),
+            keys: vec![(key_id.to_string(), key)],
+        }
+    }
+
+    /// 保留一个旧密钥，仅用于解密
+    /// Keep an older key, used for decryption only
+    pub fn with_retired(mut self, key_id: &str, key: [u8; 32]) -> Self {
+        self.keys.push((key_id.to_string(), key));
+        self
+    }
+}
+
+impl KeyProvider for StaticKeyProvider {
+    fn current_key_id(&self) -> String {
+        self.current.clone()
+    }
+
+    fn key(&self, key_id: &str) -> Result<[u8; 32], EncryptionError> {
+        self.keys
+            .iter()
+            .find(|(id, _)| id == key_id)
+            .map(|(_, key)| *key)
+            .ok_or_else(|| Report::new(EncryptionError::KeyUnavailable(key_id.to_string())))
+    }
+}
+
+/// 从密钥提供者读取 Base64 编码的密钥，密钥ID即 `secret:<provider>:<key>` 引用，密钥不进入配置文件
+/// Reads Base64-encoded keys from the secret providers, the key ID being a `secret:<provider>:<key>` reference, so
+/// keys never sit in config files
+#[derive(Clone, Debug)]
+pub struct SecretKeyProvider {
+    current: String,
+}
+
+impl SecretKeyProvider {
+    pub fn new(reference: &str) -> Self {
+        Self {
+            current: reference.to_string(),
+        }
+    }
+}
+
+impl KeyProvider for SecretKeyProvider {
+    fn current_key_id(&self) -> String {
+        self.current.clone()
+    }
+
+    fn key(&self, key_id: &str) -> Result<[u8; 32], EncryptionError> {
+        let unavailable = || EncryptionError::KeyUnavailable(key_id.to_string());
+        let encoded = resolve_secret(key_id).change_context_lazy(unavailable)?;
+        let bytes = STANDARD.decode(encoded.trim()).change_context_lazy(unavailable)?;
+        <[u8; 32]>::try_from(bytes.as_slice())
+            .map_err(|_| Report::new(unavailable()).attach_printable("Expected a 32-byte key"))
+    }
+}
+
+/// AES-256-GCM 加解密器
+/// AES-256-GCM cipher
+///
+/// 密文格式为标记、密钥ID长度（1 字节）、密钥ID、12 字节随机数与密文。
+/// The ciphertext is the marker, the key ID length (1 byte), the key ID, a 12-byte nonce and the sealed data.
+#[derive(Clone)]
+pub struct Cipher {
+    keys: Arc<dyn KeyProvider>,
+}
+
+impl Cipher {
+    pub fn new(keys: impl KeyProvider + 'static) -> Self {
+        Self { keys: Arc::new(keys) }
+    }
+
+    pub fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+        let key_id = self.keys.current_key_id();
+        let key_len = u8::try_from(key_id.len())
+            .map_err(|_| Report::new(EncryptionError::KeyUnavailable(key_id.clone())))
+            .attach_printable("Key IDs are limited to 255 bytes")?;
+        let key = self.keys.key(&key_id)?;
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key));
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let sealed = cipher
+            .encrypt(&nonce, plaintext)
+            .map_err(|_| Report::new(EncryptionError::EncryptFailed))?;
+
+        let mut output = Vec::with_capacity(MAGIC.len() + 1 + key_id.len() + NONCE_LEN + sealed.len());
+        output.extend_from_slice(MAGIC);
+        output.push(key_len);
+        output.extend_from_slice(key_id.as_bytes());
+        output.extend_from_slice(&nonce);
+        output.extend_from_slice(&sealed);
+        Ok(output)
+    }
+
+    /// 解密数据，没有加密标记的明文原样返回，便于迁移已有的明文记录
+    /// Decrypt data, plaintext without the marker is returned as is so existing plaintext records keep loading
+    pub fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+        let Some(rest) = data.strip_prefix(MAGIC) else {
+            return Ok(data.to_vec());
+        };
+        let truncated = || Report::new(EncryptionError::DecryptFailed("truncated data".to_string()));
+        let (&key_len, rest) = rest.split_first().ok_or_else(truncated)?;
+        if rest.len() < key_len as usize + NONCE_LEN {
+            return Err(truncated());
+        }
+        let (key_id, rest) = rest.split_at(key_len as usize);
+        let (nonce, sealed) = rest.split_at(NONCE_LEN);
+        let key_id = String::from_utf8_lossy(key_id);
+
+        let key = self.keys.key(&key_id)?;
+        Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key))
+            .decrypt(Nonce::from_slice(nonce), sealed)
+            .map_err(|_| Report::new(EncryptionError::DecryptFailed(format!("key {}", key_id))))
+    }
+
+    /// 数据是否已加密
+    /// Whether the data is encrypted
+    pub fn is_encrypted(data: &[u8]) -> bool {
+        data.starts_with(MAGIC)
+    }
+}
+
+/// 将会话加密保存到文件
+/// Save a session to a file, encrypted
+pub fn save_session(path: impl AsRef<Path>, session: &Session, cipher: &Cipher) -> Result<(), EncryptionError> {
+    let path = path.as_ref();
+    let io_error = || EncryptionError::IoError(path.display().to_string());
+    let json = serde_json::to_vec(session).change_context_lazy(io_error)?;
+    std::fs::write(path, cipher.encrypt(&json)?).change_context_lazy(io_error)
+}
+
+/// 从文件加载会话，加密与明文保存的会话均可加载
+/// Load a session from a file, both encrypted and plaintext sessions load
+pub fn load_session(path: impl AsRef<Path>, cipher: &Cipher) -> Result<Session, EncryptionError> {
+    let path = path.as_ref();
+    let io_error = || EncryptionError::IoError(path.display().to_string());
+    let data = std::fs::read(path).change_context_lazy(io_error)?;
+    serde_json::from_slice(&cipher.decrypt(&data)?).change_context_lazy(io_error)
+}
+
+/// 作为对话存储的编码器时，对话在写入前加密、读取时透明解密，如 `DirectorySessionStore::new(dir)?.codec(cipher)`；
+/// 启用加密前保存的明文对话仍可读出，下次保存时即被加密
+/// As the codec of a conversation store, conversations are encrypted before writing and transparently decrypted on
+/// read, such as `DirectorySessionStore::new(dir)?.codec(cipher)`; plaintext conversations saved before encryption
+/// was enabled still load and are encrypted on their next save
+impl SessionCodec for Cipher {
+    fn encode(&self, data: Vec<u8>) -> Result<Vec<u8>, StoreError> {
+        self.encrypt(&data).change_context(StoreError::CodecError("encryption".to_string()))
+    }
+
+    fn decode(&self, data: Vec<u8>) -> Result<Vec<u8>, StoreError> {
+        self.decrypt(&data).change_context(StoreError::CodecError("decryption".to_string()))
+    }
+}
+
+/// 加密附件内容的存储包装，写入时加密，读取时透明解密
+/// Storage wrapper encrypting attachment content, encrypted on write and transparently decrypted on read
+pub struct EncryptedAttachmentStore<S> {
+    inner: S,
+    cipher: Cipher,
+}
+
+impl<S: AttachmentStore> EncryptedAttachmentStore<S> {
+    pub fn new(inner: S, cipher: Cipher) -> Self {
+        Self { inner, cipher }
+    }
+}
+
+impl<S: AttachmentStore> AttachmentStore for EncryptedAttachmentStore<S> {
+    fn put(&self, id: &str, bytes: Bytes) -> Result<(), ChatError> {
+        let sealed = self
+            .cipher
+            .encrypt(&bytes)
+            .change_context_lazy(|| ChatError::AttachmentError(id.to_string()))?;
+        self.inner.put(id, Bytes::from(sealed))
+    }
+
+    fn get(&self, id: &str) -> Result<Option<Bytes>, ChatError> {
+        let Some(data) = self.inner.get(id)? else {
+            return Ok(None);
+        };
+        let plain = self
+            .cipher
+            .decrypt(&data)
+            .change_context_lazy(|| ChatError::AttachmentError(id.to_string()))?;
+        Ok(Some(Bytes::from(plain)))
+    }
+
+    fn remove(&self, id: &str) -> Result<(), ChatError> {
+        self.inner.remove(id)
+    }
+}
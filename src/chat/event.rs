@@ -0,0 +1,241 @@
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use once_cell::sync::Lazy;
+use tokio::sync::broadcast;
+
+/// 对话与定时任务的进度事件
+/// Progress event of chats and scheduled tasks
+#[derive(Clone, Debug)]
+pub enum ChatEvent {
+    /// 请求已发出
+    /// A request was sent
+    RequestStarted {
+        request_id: String,
+        model: String,
+        message_count: usize,
+        stream: bool,
+    },
+
+    /// 请求在取得并发额度后发往提供商，续传与重试的每次发送各有一个；从此时到第一个 token 之间界面可显示“思考中”
+    /// The request went out to the provider after getting its permit, once per send including resumes and retries;
+    /// until the first token arrives a UI may show "thinking…"
+    RequestDispatched { request_id: String, at: SystemTime },
+
+    /// 流式请求收到第一个 token，`latency` 从请求开始（含排队）算起；非流式请求没有该事件
+    /// A streaming request received its first token, `latency` counted from the start of the request (queueing
+    /// included); non-streaming requests have no such event
+    FirstToken {
+        request_id: String,
+        at: SystemTime,
+        latency: Duration,
+    },
+
+    /// 流式请求收到一段内容
+    /// A streaming request received a piece of content
+    TokenReceived { request_id: String, delta: String },
+
+    /// 生成结束，成功与失败都会发出，界面据此收起输入指示
+    /// Generation ended, emitted on success and failure alike so a UI can clear its typing indicator
+    GenerationFinished {
+        request_id: String,
+        at: SystemTime,
+        duration: Duration,
+        finish_reason: Option<String>,
+        error: Option<String>,
+    },
+
+    /// 工具调用开始
+    /// A tool call started
+    ToolCallStarted {
+        call_id: String,
+        name: String,
+        arguments: serde_json::Value,
+    },
+
+    /// 工具调用结束
+    /// A tool call finished
+    ToolCallFinished {
+        call_id: String,
+        name: String,
+        output: Option<String>,
+        error: Option<String>,
+        duration: Duration,
+    },
+
+    /// 回答已完整收到
+    /// The answer was fully received
+    AnswerReady { request_id: String, content: String },
+
+    /// 请求失败
+    /// A request failed
+    Error { request_id: String, message: String },
+
+    /// 定时任务开始运行
+    /// A scheduled task started running
+    TaskStarted { task: String, run_id: String },
+
+    /// 定时任务完成，`attempts` 含重试次数
+    /// A scheduled task finished, `attempts` includes the retries
+    TaskFinished {
+        task: String,
+        run_id: String,
+        output: String,
+        attempts: u32,
+        duration: Duration,
+    },
+
+    /// 定时任务在用尽重试后仍失败
+    /// A scheduled task failed after using up its retries
+    TaskFailed {
+        task: String,
+        run_id: String,
+        error: String,
+        attempts: u32,
+        duration: Duration,
+    },
+
+    /// 上一次运行尚未结束，本次触发被跳过
+    /// The previous run had not finished, so this trigger was skipped
+    TaskSkipped { task: String },
+
+    /// 开发模式下监视的文件已重新加载，加载失败时附上错误并保留上一个版本
+    /// A file watched in development mode was reloaded, with the error if loading failed and the previous version
+    /// was kept
+    Reloaded { path: String, error: Option<String> },
+}
+
+/// 事件处理函数
+/// Event handler
+pub type EventHandler = Arc<dyn Fn(&ChatEvent) + Send + Sync>;
+
+/// 事件总线的缓冲容量，落后超过该数量的订阅者丢失最旧的事件
+/// Buffer capacity of the event bus, subscribers lagging further behind lose the oldest events
+pub const EVENT_BUS_CAPACITY: usize = 1024;
+
+static EVENT_BUS: Lazy<broadcast::Sender<BusEvent>> = Lazy::new(|| broadcast::channel(EVENT_BUS_CAPACITY).0);
+
+/// 事件的来源
+/// Where an event comes from
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum EventSource {
+    /// 对话，工具调用事件也来自执行它们的对话
+    /// A chat, tool call events also come from the chat running them
+    Chat { session_id: String },
+
+    /// 定时任务调度器
+    /// The task scheduler
+    Scheduler,
+
+    /// 开发模式的热重载，见 `HotReload`
+    /// Hot reload of the development mode, see `HotReload`
+    HotReload,
+
+    /// 应用经 `EventBus::publish` 发布的事件
+    /// Events published by the application through `EventBus::publish`
+    Custom(String),
+
+    #[default]
+    Unknown,
+}
+
+/// 总线上的事件：来源、发生时间与事件本身
+/// Event on the bus: its source, time and the event itself
+#[derive(Clone, Debug)]
+pub struct BusEvent {
+    pub source: EventSource,
+
+    pub timestamp: SystemTime,
+
+    pub event: ChatEvent,
+}
+
+/// 进程级事件总线：所有对话与调度器的事件都发布到这里，仪表盘、持久化与监控可集中订阅，无需逐个对话注册处理函数
+/// Process-wide event bus: events of every chat and the scheduler are published here, so dashboards, persistence
+/// and guards can subscribe centrally instead of registering handlers on each chat
+///
+/// 没有订阅者时事件不会被构造。订阅者需要及时接收，落后超过 `EVENT_BUS_CAPACITY` 的订阅者收到
+/// `RecvError::Lagged` 并跳过最旧的事件。
+/// Events are not built while nobody subscribes. Subscribers need to keep up, one lagging more than
+/// `EVENT_BUS_CAPACITY` behind gets `RecvError::Lagged` and skips the oldest events.
+pub struct EventBus;
+
+impl EventBus {
+    /// 订阅之后发布的事件
+    /// Subscribe to the events published from now on
+    pub fn subscribe() -> broadcast::Receiver<BusEvent> {
+        EVENT_BUS.subscribe()
+    }
+
+    /// 发布应用自身的事件，没有订阅者时忽略
+    /// Publish an event of the application, ignored while nobody subscribes
+    pub fn publish(source: EventSource, event: ChatEvent) {
+        let _ = EVENT_BUS.send(BusEvent {
+            source,
+            timestamp: SystemTime::now(),
+            event,
+        });
+    }
+
+    pub fn subscriber_count() -> usize {
+        EVENT_BUS.receiver_count()
+    }
+}
+
+/// 对话上注册的事件处理函数列表，事件同时发布到 `EventBus`
+/// Event handlers registered on a chat, events are also published to the `EventBus`
+#[derive(Clone, Default)]
+pub struct EventHandlers {
+    handlers: Vec<EventHandler>,
+
+    source: EventSource,
+}
+
+impl EventHandlers {
+    pub fn new(source: EventSource) -> Self {
+        Self {
+            handlers: Vec::new(),
+            source,
+        }
+    }
+
+    pub fn add(&mut self, handler: EventHandler) {
+        self.handlers.push(handler);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.handlers.is_empty()
+    }
+
+    pub fn source(&self) -> &EventSource {
+        &self.source
+    }
+
+    pub fn set_source(&mut self, source: EventSource) {
+        self.source = source;
+    }
+
+    /// 将事件交给所有处理函数并发布到总线，既没有处理函数也没有订阅者时不构造事件
+    /// Hand the event to every handler and publish it to the bus, the event is not built if there are neither
+    /// handlers nor subscribers
+    pub fn emit(&self, event: impl FnOnce() -> ChatEvent) {
+        let publish = EVENT_BUS.receiver_count() > 0;
+        if self.handlers.is_empty() && !publish {
+            return;
+        }
+        let event = event();
+        for handler in &self.handlers {
+            handler(&event);
+        }
+        if publish {
+            EventBus::publish(self.source.clone(), event);
+        }
+    }
+}
+
+impl Debug for EventHandlers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "EventHandlers({})", self.handlers.len())
+    }
+}
@@ -0,0 +1,244 @@
+// 标准库
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::path::Path;
+use std::sync::Arc;
+
+// 错误处理
+use error_stack::{Result, ResultExt};
+
+// 数据序列化
+use serde_json::{Value, json};
+
+// 项目内部模块
+use crate::chat::chat_base::ChatError;
+use crate::chat::content::ApiContent;
+use crate::chat::message::{ApiMessage, Messages, Role, Session};
+
+/// 训练数据的格式
+/// Format of the training data
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// OpenAI 微调格式，每行 `{"messages": [...]}`，工具调用沿用请求中的 `tool_calls` 与 `tool` 消息
+    /// OpenAI fine-tuning format, one `{"messages": [...]}` per line, tool calls kept as the `tool_calls` and
+    /// `tool` messages of requests
+    #[default]
+    OpenAi,
+
+    /// ShareGPT 格式，每行 `{"conversations": [{"from": ..., "value": ...}]}`，工具调用为 `function_call`
+    /// 轮次，工具结果为 `observation` 轮次
+    /// ShareGPT format, one `{"conversations": [{"from": ..., "value": ...}]}` per line, tool calls become
+    /// `function_call` turns and tool results `observation` turns
+    ShareGpt,
+}
+
+impl ExportFormat {
+    /// 格式默认的角色名称
+    /// Role names used by the format by default
+    fn role_name(self, role: &str) -> &str {
+        match (self, role) {
+            (Self::ShareGpt, "user") => "human",
+            (Self::ShareGpt, "assistant") => "gpt",
+            (Self::ShareGpt, "tool") => "observation",
+            (_, role) => role,
+        }
+    }
+}
+
+/// 对话过滤函数，参数为默认路径上的消息，返回 false 的对话不导出
+/// Conversation filter, given the messages of the default path; conversations it returns false for are not
+/// exported
+pub type ConversationFilter = Arc<dyn Fn(&[&Messages]) -> bool + Send + Sync>;
+
+/// 将保存的会话导出为训练数据，每个会话沿默认路径导出为一行
+/// Export stored sessions as training data, each session becoming one line along its default path
+///
+/// 多角色会话以 `speaker` 的消息作为 assistant 轮次，其他角色的消息按请求中的方式带上发言者前缀作为 user
+/// 轮次。附件内容不导出。
+/// In multi-character sessions the messages of `speaker` become assistant turns, and other characters' messages
+/// become user turns prefixed with their name the same way requests do. Attachment contents are not exported.
+#[derive(Clone, Default)]
+pub struct DatasetExporter {
+    format: ExportFormat,
+
+    /// 发言者，默认为助手
+    /// The speaker, the assistant by default
+    speaker: Option<Role>,
+
+    /// 导出时的角色改名，键为格式默认的角色名称
+    /// Role renames applied on export, keyed by the default role name of the format
+    roles: HashMap<String, String>,
+
+    /// 每行附带的工具定义
+    /// Tool definitions attached to every line
+    tools: Vec<Value>,
+
+    filters: Vec<ConversationFilter>,
+}
+
+impl Debug for DatasetExporter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DatasetExporter")
+            .field("format", &self.format)
+            .field("speaker", &self.speaker)
+            .field("roles", &self.roles)
+            .field("tools", &self.tools.len())
+            .field("filters", &self.filters.len())
+            .finish()
+    }
+}
+
+impl DatasetExporter {
+    pub fn new(format: ExportFormat) -> Self {
+        Self {
+            format,
+            ..Self::default()
+        }
+    }
+
+    pub fn openai() -> Self {
+        Self::new(ExportFormat::OpenAi)
+    }
+
+    pub fn sharegpt() -> Self {
+        Self::new(ExportFormat::ShareGpt)
+    }
+
+    /// 以某一角色的消息作为 assistant 轮次，用于多角色会话
+    /// Use the messages of a character as assistant turns, for multi-character sessions
+    pub fn speaker(mut self, speaker: Role) -> Self {
+        self.speaker = Some(speaker);
+        self
+    }
+
+    /// 将导出的角色名称 `from` 改为 `to`，如 ShareGPT 中的 `human` 改为 `user`
+    /// Rename the exported role `from` to `to`, such as `human` to `user` in ShareGPT
+    pub fn map_role(mut self, from: &str, to: &str) -> Self {
+        self.roles.insert(from.to_string(), to.to_string());
+        self
+    }
+
+    /// 每行附带的工具定义（OpenAI 格式），ShareGPT 中序列化为字符串
+    /// Tool definitions attached to every line, in OpenAI format; serialized to a string in ShareGPT
+    pub fn tools(mut self, tools_schema: Vec<Value>) -> Self {
+        self.tools = tools_schema;
+        self
+    }
+
+    /// 追加一个对话过滤函数，所有过滤函数都通过的对话才会导出
+    /// Append a conversation filter, only conversations passing every filter are exported
+    pub fn filter(mut self, filter: impl Fn(&[&Messages]) -> bool + Send + Sync + 'static) -> Self {
+        self.filters.push(Arc::new(filter));
+        self
+    }
+
+    /// 导出一个会话，空会话或被过滤掉的会话返回 None
+    /// Export one session, None for empty sessions or sessions filtered out
+    pub fn export(&self, session: &Session) -> Option<Value> {
+        let conversation = session.default_path_messages();
+        if conversation.is_empty() || !self.filters.iter().all(|filter| filter(&conversation)) {
+            return None;
+        }
+
+        let speaker = self.speaker.clone().unwrap_or(Role::Assistant);
+        let messages: Vec<ApiMessage<'_>> =
+            conversation.iter().map(|message| message.to_api_format(&speaker)).collect();
+        Some(match self.format {
+            ExportFormat::OpenAi => self.openai_line(messages),
+            ExportFormat::ShareGpt => self.sharegpt_line(messages),
+        })
+    }
+
+    /// 导出为 JSONL 文本，每个导出的会话一行
+    /// Export as JSONL text, one line per exported session
+    pub fn to_jsonl<'a>(&self, sessions: impl IntoIterator<Item = &'a Session>) -> String {
+        sessions
+            .into_iter()
+            .filter_map(|session| self.export(session))
+            .map(|line| format!("{}\n", line))
+            .collect()
+    }
+
+    /// 导出到 JSONL 文件，返回导出的会话数
+    /// Export to a JSONL file, returning the number of sessions exported
+    pub fn write_jsonl<'a>(
+        &self,
+        path: impl AsRef<Path>,
+        sessions: impl IntoIterator<Item = &'a Session>,
+    ) -> Result<usize, ChatError> {
+        let path = path.as_ref();
+        let content = self.to_jsonl(sessions);
+        std::fs::write(path, &content)
+            .change_context(ChatError::InvalidConfig)
+            .attach_printable_lazy(|| format!("Failed to write dataset {}", path.display()))?;
+        Ok(content.lines().count())
+    }
+
+    fn role(&self, role: &str) -> String {
+        let role = self.format.role_name(role);
+        self.roles.get(role).cloned().unwrap_or_else(|| role.to_string())
+    }
+
+    fn openai_line(&self, messages: Vec<ApiMessage<'_>>) -> Value {
+        let messages: Vec<Value> = messages
+            .into_iter()
+            .map(|message| {
+                let mut value = json!(message);
+                value["role"] = json!(self.role(message.role));
+                value
+            })
+            .collect();
+
+        let mut line = json!({"messages": messages});
+        if !self.tools.is_empty() {
+            line["tools"] = json!(self.tools);
+        }
+        line
+    }
+
+    fn sharegpt_line(&self, messages: Vec<ApiMessage<'_>>) -> Value {
+        let mut turns = Vec::new();
+        for message in messages {
+            let text = content_text(&message.content);
+            let Some(tool_calls) = message.tool_calls else {
+                turns.push(json!({"from": self.role(message.role), "value": text}));
+                continue;
+            };
+
+            if !text.is_empty() {
+                turns.push(json!({"from": self.role(message.role), "value": text}));
+            }
+            for call in tool_calls {
+                // 参数不是合法JSON时保留原字符串
+                // Arguments that are not valid JSON are kept as the raw string
+                let arguments = call["function"]["arguments"].as_str().unwrap_or_default();
+                let arguments = serde_json::from_str(arguments).unwrap_or_else(|_| json!(arguments));
+                let value = json!({"name": call["function"]["name"], "arguments": arguments});
+                turns.push(json!({"from": self.role("function_call"), "value": value.to_string()}));
+            }
+        }
+
+        let mut line = json!({"conversations": turns});
+        if !self.tools.is_empty() {
+            line["tools"] = json!(json!(self.tools).to_string());
+        }
+        line
+    }
+}
+
+/// 请求消息内容的文字，非文字部分以类型占位
+/// Text of request message content, non-text parts replaced by their type
+fn content_text(content: &ApiContent<'_>) -> String {
+    match content {
+        ApiContent::Text(text) => text.to_string(),
+        ApiContent::Parts(parts) => parts
+            .iter()
+            .map(|part| match part["text"].as_str() {
+                Some(text) => text.to_string(),
+                None => format!("[{}]", part["type"].as_str().unwrap_or("content")),
+            })
+            .collect::<Vec<_>>()
+            .join("\n"),
+        ApiContent::Null => String::new(),
+    }
+}
@@ -0,0 +1,115 @@
+// 数据序列化
+use serde::Serialize;
+use serde_json::Value;
+
+// 项目内部模块
+use crate::config::Config;
+use crate::config::prompts::PromptKey;
+use crate::documents::Chunker;
+
+/// 长文档的结构化提取设置：切分方式、并发数与每个片段的提取指令，见 `SingleChat::extract_from_document_with`
+/// Settings of structured extraction over long documents: how to chunk, concurrency and the extraction instruction
+/// of every chunk, see `SingleChat::extract_from_document_with`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DocumentExtraction {
+    /// 片段的大小应留出提示词与输出的空间，相邻片段的重叠使跨边界的条目至少完整出现一次
+    /// Chunks should leave room for the prompt and the output, the overlap of adjacent chunks lets entries
+    /// crossing a boundary appear whole at least once
+    pub chunker: Chunker,
+
+    /// 同时提取的最大片段数，为 0 时按 1 处理
+    /// Maximum number of chunks extracted at once, 0 is treated as 1
+    pub concurrency: usize,
+
+    /// 附在每个片段之前的指令，为 None 时使用提示词表中的 `document_extraction`
+    /// Instruction put before every chunk, `document_extraction` of the prompt table if None
+    pub instruction: Option<String>,
+}
+
+impl Default for DocumentExtraction {
+    fn default() -> Self {
+        Self {
+            chunker: Chunker::new(8000).overlap(400),
+            concurrency: 4,
+            instruction: None,
+        }
+    }
+}
+
+impl DocumentExtraction {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn chunker(mut self, chunker: Chunker) -> Self {
+        self.chunker = chunker;
+        self
+    }
+
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency;
+        self
+    }
+
+    /// 替换默认的提取指令，如说明要提取的内容或领域术语
+    /// Replace the default extraction instruction, such as to explain what to extract or the domain's terms
+    pub fn instruction(mut self, instruction: &str) -> Self {
+        self.instruction = Some(instruction.to_string());
+        self
+    }
+
+    /// 一个片段的提问
+    /// The question of one chunk
+    pub(crate) fn question(&self, chunk: &str) -> String {
+        let instruction = match &self.instruction {
+            Some(instruction) => instruction.clone(),
+            None => Config::capability_prompt(&PromptKey::DocumentExtraction),
+        };
+        format!("{}\n\n{}", instruction, chunk)
+    }
+}
+
+/// 合并各片段的结果：去掉重复的条目，以及所有字段都包含在另一条目中的不完整条目（如被片段边界截断的记录），
+/// 保留的条目按首次出现的顺序排列；比较时忽略字符串的大小写与多余空白以及为 null 的字段
+/// Merge the results of the chunks: drop repeated entries and incomplete ones whose fields are all contained in
+/// another entry (such as records cut by a chunk boundary), keeping the rest in order of first appearance; the
+/// comparison ignores the case and extra whitespace of strings and fields that are null
+pub(crate) fn reduce<T: Serialize>(items: Vec<T>) -> Vec<T> {
+    let values: Vec<Value> =
+        items.iter().map(|item| serde_json::to_value(item).map(normalize).unwrap_or(Value::Null)).collect();
+    let redundant = |index: usize| {
+        values.iter().enumerate().any(|(other, value)| {
+            other != index && contains(value, &values[index]) && (other < index || values[index] != *value)
+        })
+    };
+    let keep: Vec<bool> = (0..values.len()).map(|index| !redundant(index)).collect();
+    items.into_iter().zip(keep).filter_map(|(item, keep)| keep.then_some(item)).collect()
+}
+
+/// 用于比较的形式：字符串小写并合并空白，对象去掉为 null 的字段
+/// Form used for comparison: strings lowercased with their whitespace collapsed, objects without null fields
+fn normalize(value: Value) -> Value {
+    match value {
+        Value::String(text) => Value::String(text.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()),
+        Value::Array(items) => Value::Array(items.into_iter().map(normalize).collect()),
+        Value::Object(fields) => Value::Object(
+            fields
+                .into_iter()
+                .filter(|(_, value)| !value.is_null())
+                .map(|(key, value)| (key, normalize(value)))
+                .collect(),
+        ),
+        value => value,
+    }
+}
+
+/// `whole` 是否包含 `part`：对象逐字段递归比较，其他值要求相等
+/// Whether `whole` contains `part`: objects are compared field by field recursively, other values must be equal
+fn contains(whole: &Value, part: &Value) -> bool {
+    match (whole, part) {
+        (Value::Object(whole), Value::Object(part)) => {
+            part.iter().all(|(key, value)| whole.get(key).is_some_and(|field| contains(field, value)))
+        }
+        (whole, part) => whole == part,
+    }
+}
@@ -0,0 +1,185 @@
+// 错误处理
+use error_stack::{Result, ResultExt};
+
+// 数据序列化
+use serde::{Deserialize, Serialize};
+
+// 项目内部模块
+use crate::chat::chat_base::{BaseChat, ChatError};
+use crate::chat::chat_tool::strip_code_fence;
+use crate::chat::retriever::Source;
+use crate::chat::summary::{cheap_helper, helper_chat};
+use crate::config::prompts::PromptKey;
+use crate::config::Config;
+
+/// 回答的忠实度检查：回答后由评判模型逐条判断其中的陈述能否由检索到的资料支持
+/// Faithfulness check of answers: after answering, a judge model decides claim by claim whether the retrieved
+/// material supports the answer
+///
+/// 支持度低于阈值的陈述视为没有依据。设置了重新生成次数时，不可信的回答连同没有依据的陈述交回模型重答，
+/// 次数用尽后返回最后一次回答及其检查结果。
+/// Claims scoring below the threshold count as unsupported. With regenerations set, an unfaithful answer is sent
+/// back to the model together with its unsupported claims to answer again; once they are used up the last answer
+/// is returned with its report.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FaithfulnessChecker {
+    /// 评判模型的API，为 None 时使用廉价模型，没有廉价模型时使用对话自身的API
+    /// API of the judge model; the cheap model if None, or the chat's own API if there is no cheap model
+    pub api_name: Option<String>,
+
+    pub threshold: f64,
+
+    /// 回答不可信时最多重新生成的次数，为 0 时只标记不重答
+    /// Maximum regenerations of an unfaithful answer, 0 only flags it
+    pub max_regenerations: u32,
+}
+
+impl Default for FaithfulnessChecker {
+    fn default() -> Self {
+        Self {
+            api_name: None,
+            threshold: 0.5,
+            max_regenerations: 0,
+        }
+    }
+}
+
+impl FaithfulnessChecker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn api(mut self, api_name: &str) -> Self {
+        self.api_name = Some(api_name.to_string());
+        self
+    }
+
+    pub fn threshold(mut self, threshold: f64) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    pub fn max_regenerations(mut self, max_regenerations: u32) -> Self {
+        self.max_regenerations = max_regenerations;
+        self
+    }
+
+    /// 检查回答能否由资料支持，`sources` 按 1 开始编号；没有资料时不调用评判模型，返回空的检查结果
+    /// Check whether the sources support an answer, `sources` numbered from 1; without sources the judge is not
+    /// called and an empty report is returned
+    pub async fn check(
+        &self,
+        chat: &BaseChat,
+        answer: &str,
+        sources: &[Source],
+    ) -> Result<FaithfulnessReport, ChatError> {
+        let mut report = FaithfulnessReport {
+            threshold: self.threshold,
+            ..Default::default()
+        };
+        if sources.is_empty() || answer.trim().is_empty() {
+            return Ok(report);
+        }
+
+        let instruction = Config::capability_prompt(&PromptKey::FaithfulnessJudge);
+        let mut judge = match &self.api_name {
+            Some(api_name) => {
+                let judge = BaseChat::try_new_with_api_name(api_name, "", false)?;
+                helper_chat(chat, judge, &instruction)?
+            }
+            None => cheap_helper(chat, &instruction)?,
+        };
+
+        let material = sources
+            .iter()
+            .enumerate()
+            .map(|(index, source)| format!("[{}] {}", index + 1, source.to_context()))
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        let verdict = judge
+            .get_answer(&Config::render_prompt(&PromptKey::FaithfulnessMaterial, &[&material, answer]))
+            .await?;
+
+        #[derive(Deserialize)]
+        struct Verdict {
+            claims: Vec<ClaimSupport>,
+        }
+        let verdict: Verdict = serde_json::from_str(strip_code_fence(&verdict))
+            .change_context(ChatError::GetJsonError)
+            .attach_printable_lazy(|| format!("Failed to parse the faithfulness verdict: {}", verdict))?;
+        report.claims = verdict
+            .claims
+            .into_iter()
+            .map(|claim| ClaimSupport {
+                score: claim.score.clamp(0.0, 1.0),
+                ..claim
+            })
+            .collect();
+        Ok(report)
+    }
+}
+
+/// 一条陈述的支持情况
+/// Support of one claim
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ClaimSupport {
+    pub claim: String,
+
+    /// 支持度，0 到 1
+    /// Support score, 0 to 1
+    pub score: f64,
+
+    /// 支持该陈述的资料编号，从 1 开始
+    /// Numbers of the sources supporting the claim, starting at 1
+    #[serde(default)]
+    pub sources: Vec<usize>,
+}
+
+/// 忠实度检查结果
+/// Result of a faithfulness check
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct FaithfulnessReport {
+    pub claims: Vec<ClaimSupport>,
+
+    pub threshold: f64,
+
+    /// 得到最终回答前重新生成的次数
+    /// Regenerations made before the final answer
+    pub regenerations: u32,
+}
+
+impl FaithfulnessReport {
+    /// 没有依据的陈述
+    /// The unsupported claims
+    pub fn unsupported(&self) -> Vec<&ClaimSupport> {
+        self.claims.iter().filter(|claim| claim.score < self.threshold).collect()
+    }
+
+    /// 是否所有陈述都有依据；没有检查任何陈述时为 true
+    /// Whether every claim is supported; true if no claim was checked
+    pub fn is_faithful(&self) -> bool {
+        self.unsupported().is_empty()
+    }
+
+    /// 最低的支持度，没有陈述时为 None
+    /// The lowest support score, None without claims
+    pub fn min_score(&self) -> Option<f64> {
+        self.claims.iter().map(|claim| claim.score).reduce(f64::min)
+    }
+}
+
+/// 经过忠实度检查的回答
+/// An answer that went through the faithfulness check
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct VerifiedAnswer {
+    pub text: String,
+
+    pub report: FaithfulnessReport,
+}
+
+/// 要求模型只依据资料重答的指令，列出没有依据的陈述
+/// Instruction asking the model to answer again from the material only, listing the unsupported claims
+pub(crate) fn regeneration_instruction(report: &FaithfulnessReport) -> String {
+    let claims = report.unsupported().iter().map(|claim| format!("- {}", claim.claim)).collect::<Vec<_>>().join("\n");
+    Config::render_prompt(&PromptKey::FaithfulnessRegeneration, &[&claims])
+}
@@ -0,0 +1,270 @@
+// 标准库
+use std::fmt::Debug;
+use std::sync::Arc;
+
+// 文本处理
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+// 数据序列化
+use serde::Deserialize;
+
+// 项目内部模块
+use crate::config::Config;
+use crate::config::prompts::PromptKey;
+
+/// 回答中的链接，不含末尾的标点
+/// Links in an answer, without trailing punctuation
+static URL: Lazy<Regex> = Lazy::new(|| Regex::new(r#"https?://([^/\s)"'>\]]+)(?:[^\s)"'>\]]*[^\s)"'>\].,;:!?])?"#).unwrap());
+
+/// 规则对回答的判定
+/// Verdict of a rule on an answer
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum GuardrailVerdict {
+    /// 放行
+    /// Let the answer through
+    Pass,
+
+    /// 拒绝回答，附带原因
+    /// Reject the answer, with the reason
+    Block(String),
+
+    /// 以改写后的回答继续检查
+    /// Continue checking with the rewritten answer
+    Rewrite(String),
+
+    /// 要求模型重新生成，原因作为反馈附在重试请求中
+    /// Ask the model to generate again, the reason is attached to the retry request as feedback
+    Regenerate(String),
+}
+
+/// 规则命中时的处理方式，改写的方式由各规则决定
+/// What to do when a rule matches, each rule decides how to rewrite
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GuardrailAction {
+    Block,
+    Rewrite,
+    Regenerate,
+}
+
+impl GuardrailAction {
+    fn verdict(self, reason: String, rewrite: impl FnOnce() -> String) -> GuardrailVerdict {
+        match self {
+            Self::Block => GuardrailVerdict::Block(reason),
+            Self::Rewrite => GuardrailVerdict::Rewrite(rewrite()),
+            Self::Regenerate => GuardrailVerdict::Regenerate(reason),
+        }
+    }
+}
+
+/// 回答生成后、返回与写入会话前的检查规则
+/// Rule checking an answer after generation, before it is returned and stored in history
+pub trait Guardrail: Send + Sync {
+    fn check(&self, answer: &str) -> GuardrailVerdict;
+}
+
+/// 函数可直接作为自定义校验规则
+/// Functions can be used directly as custom validators
+impl<F> Guardrail for F
+where
+    F: Fn(&str) -> GuardrailVerdict + Send + Sync,
+{
+    fn check(&self, answer: &str) -> GuardrailVerdict {
+        self(answer)
+    }
+}
+
+/// 禁用词列表（不区分大小写），改写时以星号遮盖
+/// Deny-list of words (case-insensitive), masked with asterisks when rewriting
+#[derive(Clone, Debug)]
+pub struct DenyList {
+    pattern: Option<Regex>,
+    action: GuardrailAction,
+}
+
+impl DenyList {
+    pub fn new(words: impl IntoIterator<Item = impl AsRef<str>>, action: GuardrailAction) -> Self {
+        let words: Vec<String> = words.into_iter().map(|word| regex::escape(word.as_ref())).collect();
+        let pattern = (!words.is_empty()).then(|| Regex::new(&format!("(?i){}", words.join("|"))).unwrap());
+        Self { pattern, action }
+    }
+}
+
+impl Guardrail for DenyList {
+    fn check(&self, answer: &str) -> GuardrailVerdict {
+        let Some(found) = self.pattern.as_ref().and_then(|pattern| pattern.find(answer)) else {
+            return GuardrailVerdict::Pass;
+        };
+        self.action.verdict(format!("Answer contains denied word \"{}\"", found.as_str()), || {
+            self.pattern
+                .as_ref()
+                .unwrap()
+                .replace_all(answer, |captures: &regex::Captures| "*".repeat(captures[0].chars().count()))
+                .into_owned()
+        })
+    }
+}
+
+/// 正则规则，改写时以 `replacement` 替换匹配内容（支持 `$1` 等捕获组引用）
+/// Regex rule, matches are replaced with `replacement` when rewriting (capture references such as `$1` work)
+#[derive(Clone, Debug)]
+pub struct RegexRule {
+    pattern: Regex,
+    replacement: String,
+    action: GuardrailAction,
+}
+
+impl RegexRule {
+    pub fn new(pattern: &str, action: GuardrailAction) -> Result<Self, regex::Error> {
+        Ok(Self {
+            pattern: Regex::new(pattern)?,
+            replacement: String::new(),
+            action,
+        })
+    }
+
+    pub fn replacement(mut self, replacement: &str) -> Self {
+        self.replacement = replacement.to_string();
+        self
+    }
+}
+
+impl Guardrail for RegexRule {
+    fn check(&self, answer: &str) -> GuardrailVerdict {
+        if !self.pattern.is_match(answer) {
+            return GuardrailVerdict::Pass;
+        }
+        self.action.verdict(format!("Answer matches forbidden pattern {}", self.pattern), || {
+            self.pattern.replace_all(answer, self.replacement.as_str()).into_owned()
+        })
+    }
+}
+
+/// 回答的最大字符数，改写时截断
+/// Maximum number of characters in an answer, truncated when rewriting
+#[derive(Clone, Copy, Debug)]
+pub struct MaxLength {
+    max_chars: usize,
+    action: GuardrailAction,
+}
+
+impl MaxLength {
+    pub fn new(max_chars: usize, action: GuardrailAction) -> Self {
+        Self { max_chars, action }
+    }
+}
+
+impl Guardrail for MaxLength {
+    fn check(&self, answer: &str) -> GuardrailVerdict {
+        let length = answer.chars().count();
+        if length <= self.max_chars {
+            return GuardrailVerdict::Pass;
+        }
+        self.action.verdict(
+            format!("Answer has {} characters, at most {} are allowed", length, self.max_chars),
+            || answer.chars().take(self.max_chars).collect(),
+        )
+    }
+}
+
+/// 链接的域名允许列表，子域名同样允许，改写时移除其他链接
+/// Allowlist of link domains, subdomains are allowed too, other links are removed when rewriting
+#[derive(Clone, Debug)]
+pub struct UrlAllowlist {
+    domains: Vec<String>,
+    action: GuardrailAction,
+}
+
+impl UrlAllowlist {
+    pub fn new(domains: impl IntoIterator<Item = impl Into<String>>, action: GuardrailAction) -> Self {
+        Self {
+            domains: domains.into_iter().map(|domain| domain.into().to_ascii_lowercase()).collect(),
+            action,
+        }
+    }
+
+    fn allows(&self, host: &str) -> bool {
+        let host = host.split(':').next().unwrap_or(host).to_ascii_lowercase();
+        self.domains
+            .iter()
+            .any(|domain| host == *domain || host.ends_with(&format!(".{}", domain)))
+    }
+}
+
+impl Guardrail for UrlAllowlist {
+    fn check(&self, answer: &str) -> GuardrailVerdict {
+        let Some(denied) = URL.captures_iter(answer).find(|captures| !self.allows(&captures[1])) else {
+            return GuardrailVerdict::Pass;
+        };
+        self.action.verdict(format!("Answer links to a domain outside the allowlist: {}", &denied[1]), || {
+            let removed = Config::capability_prompt(&PromptKey::GuardrailLinkRemoved);
+            URL.replace_all(answer, |captures: &regex::Captures| match self.allows(&captures[1]) {
+                true => captures[0].to_string(),
+                false => removed.clone(),
+            })
+            .into_owned()
+        })
+    }
+}
+
+/// 按顺序执行的规则链
+/// Chain of rules run in order
+#[derive(Clone)]
+pub struct Guardrails {
+    rules: Vec<Arc<dyn Guardrail>>,
+    /// 要求重新生成的最多次数，用尽后按拒绝处理
+    /// Maximum number of regenerations, the answer is rejected once they are used up
+    pub max_regenerations: usize,
+}
+
+impl Debug for Guardrails {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Guardrails")
+            .field("rules", &self.rules.len())
+            .field("max_regenerations", &self.max_regenerations)
+            .finish()
+    }
+}
+
+impl Default for Guardrails {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Guardrails {
+    /// 空规则链，最多重新生成一次
+    /// Empty chain, regenerating at most once
+    pub fn new() -> Self {
+        Self {
+            rules: Vec::new(),
+            max_regenerations: 1,
+        }
+    }
+
+    pub fn with(mut self, rule: impl Guardrail + 'static) -> Self {
+        self.rules.push(Arc::new(rule));
+        self
+    }
+
+    pub fn max_regenerations(mut self, max_regenerations: usize) -> Self {
+        self.max_regenerations = max_regenerations;
+        self
+    }
+
+    /// 依次执行规则，改写后的回答交给后续规则；返回 `Pass`、最终的 `Rewrite`，或第一个 `Block`/`Regenerate`
+    /// Run the rules in order, a rewritten answer is passed on to the following rules; returns `Pass`, the final
+    /// `Rewrite`, or the first `Block`/`Regenerate`
+    pub fn check(&self, answer: &str) -> GuardrailVerdict {
+        let mut rewritten: Option<String> = None;
+        for rule in &self.rules {
+            match rule.check(rewritten.as_deref().unwrap_or(answer)) {
+                GuardrailVerdict::Pass => {}
+                GuardrailVerdict::Rewrite(text) => rewritten = Some(text),
+                verdict => return verdict,
+            }
+        }
+        rewritten.map_or(GuardrailVerdict::Pass, GuardrailVerdict::Rewrite)
+    }
+}
@@ -0,0 +1,169 @@
+// 标准库
+use std::pin::pin;
+use std::sync::Arc;
+
+// 错误处理
+use error_stack::{Report, Result};
+
+// 异步
+use futures::{Stream, StreamExt};
+use tokio::sync::{Notify, mpsc, oneshot};
+use tokio_stream::wrappers::ReceiverStream;
+
+// 项目内部模块
+use crate::chat::chat_base::ChatError;
+use crate::chat::chat_single::SingleChat;
+use crate::config::Config;
+
+/// 命令队列长度
+/// Command queue length
+const COMMAND_BUFFER: usize = 32;
+
+/// 流式回答转发缓冲的分块数
+/// Number of chunks buffered when forwarding a streamed answer
+const STREAM_BUFFER: usize = 64;
+
+/// 访问对话的闭包
+/// Closure accessing the chat
+type Access = Box<dyn FnOnce(&mut SingleChat) + Send>;
+
+/// 发送给对话任务的命令
+/// Command sent to the chat task
+enum Command {
+    Answer {
+        input: String,
+        reply: oneshot::Sender<Result<String, ChatError>>,
+    },
+
+    Stream {
+        input: String,
+        deltas: mpsc::Sender<Result<String, ChatError>>,
+    },
+
+    Access(Access),
+}
+
+/// 对话句柄，以独立任务持有一个 `SingleChat`，可克隆并在多个任务间共享
+/// Chat handle, a separate task owns one `SingleChat`; the handle is cloneable and can be shared across tasks
+///
+/// 命令按到达顺序依次执行，因此多个任务（如界面与后台工具）可以同时驱动同一段对话而不需要外部加锁。
+/// 调用方放弃等待（丢弃 future 或流）时，对应的命令随之取消；`cancel` 取消正在执行的命令。
+/// 被取消的提问可能已写入用户消息而没有回答。所有句柄都被丢弃后，对话任务结束。
+/// Commands run one after another in arrival order, so several tasks (such as a UI and background tools) can
+/// drive one conversation concurrently without external locking. When a caller stops waiting (drops the future or
+/// stream) its command is cancelled; `cancel` cancels the command in progress. A cancelled question may have
+/// written the user message without an answer. The chat task ends once every handle is dropped.
+#[derive(Clone, Debug)]
+pub struct ChatHandle {
+    commands: mpsc::Sender<Command>,
+
+    cancel: Arc<Notify>,
+}
+
+impl ChatHandle {
+    /// 在当前运行时上启动对话任务并返回句柄
+    /// Spawn the chat task on the current runtime and return its handle
+    pub fn spawn(chat: SingleChat) -> Self {
+        let (commands, receiver) = mpsc::channel(COMMAND_BUFFER);
+        let cancel = Arc::new(Notify::new());
+        tokio::spawn(Config::in_current_scope(run(chat, receiver, cancel.clone())));
+        Self { commands, cancel }
+    }
+
+    /// 提问并取回回答
+    /// Ask a question and return the answer
+    pub async fn get_answer(&self, user_input: &str) -> Result<String, ChatError> {
+        let (reply, answer) = oneshot::channel();
+        self.send(Command::Answer {
+            input: user_input.to_string(),
+            reply,
+        })
+        .await?;
+        answer.await.map_err(|_| Report::new(ChatError::Cancelled))?
+    }
+
+    /// 提问并以流的形式逐段取回回答
+    /// Ask a question and receive the answer piece by piece as a stream
+    pub async fn stream_answer(
+        &self,
+        user_input: &str,
+    ) -> Result<impl Stream<Item = Result<String, ChatError>> + Send + 'static, ChatError> {
+        let (deltas, receiver) = mpsc::channel(STREAM_BUFFER);
+        self.send(Command::Stream {
+            input: user_input.to_string(),
+            deltas,
+        })
+        .await?;
+        Ok(ReceiverStream::new(receiver))
+    }
+
+    /// 在对话任务上执行闭包，可读取或修改对话，如查询用量、添加消息
+    /// Run a closure on the chat task to read or modify the chat, such as querying usage or adding messages
+    pub async fn with<R: Send + 'static>(
+        &self,
+        access: impl FnOnce(&mut SingleChat) -> R + Send + 'static,
+    ) -> Result<R, ChatError> {
+        let (reply, result) = oneshot::channel();
+        self.send(Command::Access(Box::new(move |chat| {
+            let _ = reply.send(access(chat));
+        })))
+        .await?;
+        result.await.map_err(|_| Report::new(ChatError::Cancelled))
+    }
+
+    /// 取消正在执行的命令，排队中的命令不受影响
+    /// Cancel the command in progress, queued commands are not affected
+    pub fn cancel(&self) {
+        self.cancel.notify_waiters();
+    }
+
+    async fn send(&self, command: Command) -> Result<(), ChatError> {
+        self.commands
+            .send(command)
+            .await
+            .map_err(|_| Report::new(ChatError::Cancelled).attach_printable("Chat task has stopped"))
+    }
+}
+
+/// 对话任务：依次执行命令，直到所有句柄都被丢弃
+/// Chat task: run commands one by one until every handle is dropped
+async fn run(mut chat: SingleChat, mut commands: mpsc::Receiver<Command>, cancel: Arc<Notify>) {
+    while let Some(command) = commands.recv().await {
+        match command {
+            Command::Answer { input, mut reply } => {
+                let result = tokio::select! {
+                    result = chat.get_answer(&input) => result,
+                    _ = cancel.notified() => Err(Report::new(ChatError::Cancelled)),
+                    _ = reply.closed() => continue,
+                };
+                let _ = reply.send(result);
+            }
+            Command::Stream { input, deltas } => {
+                let forward = async {
+                    match chat.stream_answer(&input).await {
+                        Ok(stream) => {
+                            let mut stream = pin!(stream);
+                            while let Some(delta) = stream.next().await {
+                                if deltas.send(delta).await.is_err() {
+                                    break;
+                                }
+                            }
+                        }
+                        Err(report) => {
+                            let _ = deltas.send(Err(report)).await;
+                        }
+                    }
+                };
+                let cancelled = tokio::select! {
+                    _ = forward => false,
+                    _ = cancel.notified() => true,
+                    _ = deltas.closed() => false,
+                };
+                if cancelled {
+                    let _ = deltas.send(Err(Report::new(ChatError::Cancelled))).await;
+                }
+            }
+            Command::Access(access) => access(&mut chat),
+        }
+    }
+}
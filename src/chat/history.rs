@@ -0,0 +1,263 @@
+// 标准库
+use std::collections::HashSet;
+use std::fmt::Debug;
+use std::sync::Arc;
+
+// 项目内部模块
+use crate::chat::content::ApiContent;
+use crate::chat::message::ApiMessage;
+use crate::config::Config;
+use crate::config::prompts::PromptKey;
+
+/// 历史策略，决定会话中的哪些消息随请求发送给模型
+/// History policy, decides which messages of the session are sent to the model with a request
+///
+/// 在 `BaseChat::build_request_body` 中对组装好的消息调用，只影响请求，不修改会话历史。
+/// Called by `BaseChat::build_request_body` on the assembled messages; it only shapes the request and never
+/// modifies the session history.
+pub trait HistoryPolicy: Debug + Send + Sync {
+    /// 从按时间排序的消息中选出本次请求要发送的消息
+    /// Select the messages to send with this request from the chronologically ordered messages
+    fn select<'a>(&self, messages: Vec<ApiMessage<'a>>) -> Vec<ApiMessage<'a>>;
+}
+
+/// 发送全部历史，默认策略
+/// Send the whole history, the default policy
+#[derive(Debug, Clone, Copy, Default)]
+pub struct KeepAll;
+
+impl HistoryPolicy for KeepAll {
+    fn select<'a>(&self, messages: Vec<ApiMessage<'a>>) -> Vec<ApiMessage<'a>> {
+        messages
+    }
+}
+
+/// 滑动窗口，保留开头的系统消息和最近的 `max_messages` 条消息
+/// Sliding window, keeps the leading system messages and the latest `max_messages` messages
+#[derive(Debug, Clone, Copy)]
+pub struct SlidingWindow {
+    pub max_messages: usize,
+}
+
+impl SlidingWindow {
+    pub fn new(max_messages: usize) -> Self {
+        Self { max_messages }
+    }
+}
+
+impl HistoryPolicy for SlidingWindow {
+    fn select<'a>(&self, mut messages: Vec<ApiMessage<'a>>) -> Vec<ApiMessage<'a>> {
+        let pinned = leading_system_count(&messages);
+        let excess = (messages.len() - pinned).saturating_sub(self.max_messages);
+        messages.drain(pinned..pinned + excess);
+        retain_tool_pairs(messages)
+    }
+}
+
+/// 摘要函数，将较早的消息压缩为一段文字
+/// Summarizer, condenses older messages into one piece of text
+pub type Summarizer = Arc<dyn Fn(&[ApiMessage<'_>]) -> String + Send + Sync>;
+
+/// 保留最近的 `keep_recent` 条消息，更早的消息替换为一条摘要系统消息
+/// Keep the latest `keep_recent` messages and replace older ones with one summary system message
+///
+/// 请求构建是同步的，摘要函数不能调用模型；需要模型生成的摘要时，可预先异步生成并在摘要函数中返回缓存结果。
+/// Request building is synchronous, so the summarizer cannot call a model; for model-written summaries,
+/// generate them ahead of time asynchronously and return the cached text from the summarizer.
+#[derive(Clone)]
+pub struct SummarizeOld {
+    pub keep_recent: usize,
+    summarizer: Summarizer,
+}
+
+impl Debug for SummarizeOld {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SummarizeOld").field("keep_recent", &self.keep_recent).finish_non_exhaustive()
+    }
+}
+
+impl SummarizeOld {
+    /// 使用默认摘要函数，每条消息截取开头 `EXCERPT_CHARS` 个字符
+    /// Use the default summarizer, which keeps the first `EXCERPT_CHARS` characters of each message
+    pub fn new(keep_recent: usize) -> Self {
+        Self {
+            keep_recent,
+            summarizer: Arc::new(excerpt_summary),
+        }
+    }
+
+    pub fn with_summarizer(
+        keep_recent: usize,
+        summarizer: impl Fn(&[ApiMessage<'_>]) -> String + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            keep_recent,
+            summarizer: Arc::new(summarizer),
+        }
+    }
+}
+
+/// 默认摘要中每条消息保留的字符数
+/// Characters kept per message in the default summary
+pub const EXCERPT_CHARS: usize = 80;
+
+fn excerpt_summary(messages: &[ApiMessage<'_>]) -> String {
+    messages
+        .iter()
+        .map(|message| {
+            let text = message.content.as_text().unwrap_or("[non-text content]");
+            let excerpt: String = text.chars().take(EXCERPT_CHARS).collect();
+            let ellipsis = if text.chars().count() > EXCERPT_CHARS { "…" } else { "" };
+            format!("{}: {}{}", message.role, excerpt, ellipsis)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+impl HistoryPolicy for SummarizeOld {
+    fn select<'a>(&self, mut messages: Vec<ApiMessage<'a>>) -> Vec<ApiMessage<'a>> {
+        let pinned = leading_system_count(&messages);
+        let excess = (messages.len() - pinned).saturating_sub(self.keep_recent);
+        if excess == 0 {
+            return messages;
+        }
+
+        let older: Vec<ApiMessage<'a>> = messages.drain(pinned..pinned + excess).collect();
+        let text = (self.summarizer)(&older);
+        let summary = ApiMessage {
+            role: "system",
+            content: ApiContent::Text(Config::render_prompt(&PromptKey::OlderMessagesSummary, &[&text]).into()),
+            tool_calls: None,
+            tool_call_id: None,
+            importance: Default::default(),
+        };
+        messages.insert(pinned, summary);
+        retain_tool_pairs(messages)
+    }
+}
+
+/// 重要性评分函数，参数为消息、在历史中的位置与消息总数
+/// Importance scorer, given the message, its position in the history and the message count
+pub type ImportanceScorer = Arc<dyn Fn(&ApiMessage<'_>, usize, usize) -> f32 + Send + Sync>;
+
+/// 按重要性保留消息：开头的系统消息、用户标记的消息与最后一条消息总是保留，其余消息按评分选出至多 `max_messages`
+/// 条，保持原有顺序；裁剪时先舍弃评分最低的消息，而不是最早的消息
+/// Keep messages by importance: the leading system messages, messages flagged by the user and the last message are
+/// always kept, and at most `max_messages` of the rest are chosen by score, in their original order; trimming drops
+/// the lowest scoring messages first rather than the oldest
+#[derive(Clone)]
+pub struct ImportanceWeighted {
+    pub max_messages: usize,
+    scorer: ImportanceScorer,
+}
+
+impl Debug for ImportanceWeighted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ImportanceWeighted").field("max_messages", &self.max_messages).finish_non_exhaustive()
+    }
+}
+
+impl ImportanceWeighted {
+    /// 使用默认评分：越新越重要，用户消息与工具结果、对话中途的系统消息、较长的消息与工具调用适当加分，再加上
+    /// `SingleChat::score_salience` 评定的显著性，未评定的按中等计
+    /// Use the default score: newer is more important, with bonuses for user messages and tool results, system
+    /// messages mid-conversation, longer messages and tool calls, plus the salience rated by
+    /// `SingleChat::score_salience`, unrated messages counting as middling
+    pub fn new(max_messages: usize) -> Self {
+        Self {
+            max_messages,
+            scorer: Arc::new(default_importance),
+        }
+    }
+
+    pub fn with_scorer(
+        max_messages: usize,
+        scorer: impl Fn(&ApiMessage<'_>, usize, usize) -> f32 + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            max_messages,
+            scorer: Arc::new(scorer),
+        }
+    }
+}
+
+fn default_importance(message: &ApiMessage<'_>, position: usize, total: usize) -> f32 {
+    let recency = (position + 1) as f32 / total.max(1) as f32;
+    let length = message.content.as_text().map_or(1.0, |text| (text.chars().count() as f32 / 1000.0).min(1.0));
+    let tools = if message.tool_calls.is_some() || message.tool_call_id.is_some() { 0.5 } else { 0.0 };
+    let role = match message.role {
+        "system" => 0.5,
+        "user" | "tool" => 0.25,
+        _ => 0.0,
+    };
+    let salience = message.importance.salience.map_or(0.5, |salience| salience as f32 / 100.0);
+    recency + 0.5 * length + tools + role + salience
+}
+
+impl HistoryPolicy for ImportanceWeighted {
+    fn select<'a>(&self, messages: Vec<ApiMessage<'a>>) -> Vec<ApiMessage<'a>> {
+        let pinned = leading_system_count(&messages);
+        let total = messages.len();
+        let flagged = messages.iter().take(total.saturating_sub(1)).skip(pinned);
+        let flagged = flagged.filter(|message| message.importance.pinned).count();
+        if total <= pinned + flagged + 1 + self.max_messages {
+            return messages;
+        }
+
+        // 在开头的系统消息与最后一条消息之间，从未标记的消息中按评分挑选
+        // Pick by score among the unflagged messages between the leading system messages and the last message
+        let mut candidates: Vec<(usize, f32)> = (pinned..total - 1)
+            .filter(|&position| !messages[position].importance.pinned)
+            .map(|position| (position, (self.scorer)(&messages[position], position, total)))
+            .collect();
+        candidates.sort_by(|a, b| b.1.total_cmp(&a.1));
+        let chosen: HashSet<usize> = candidates.into_iter().take(self.max_messages).map(|(position, _)| position).collect();
+
+        let selected = messages
+            .into_iter()
+            .enumerate()
+            .filter(|(position, message)| {
+                *position < pinned || *position == total - 1 || message.importance.pinned || chosen.contains(position)
+            })
+            .map(|(_, message)| message)
+            .collect();
+        retain_tool_pairs(selected)
+    }
+}
+
+/// 开头连续的系统消息数量
+/// Number of consecutive system messages at the start
+fn leading_system_count(messages: &[ApiMessage<'_>]) -> usize {
+    messages.iter().take_while(|message| message.role == "system").count()
+}
+
+/// 移除不成对的工具调用与工具结果，避免提供商因调用与结果不匹配而拒绝请求
+/// Remove tool calls and tool results that lost their counterpart, so providers do not reject the request for
+/// unmatched calls
+pub fn retain_tool_pairs(messages: Vec<ApiMessage<'_>>) -> Vec<ApiMessage<'_>> {
+    let call_ids: HashSet<String> = messages
+        .iter()
+        .flat_map(|message| message.tool_calls.iter().flatten())
+        .filter_map(|call| call["id"].as_str().map(str::to_string))
+        .collect();
+    let result_ids: HashSet<String> =
+        messages.iter().filter_map(|message| message.tool_call_id.map(str::to_string)).collect();
+
+    messages
+        .into_iter()
+        .filter(|message| message.tool_call_id.is_none_or(|id| call_ids.contains(id)))
+        .filter_map(|mut message| {
+            if let Some(calls) = message.tool_calls.take() {
+                let calls: Vec<_> = calls
+                    .into_iter()
+                    .filter(|call| call["id"].as_str().is_some_and(|id| result_ids.contains(id)))
+                    .collect();
+                if calls.is_empty() && message.content == ApiContent::Null {
+                    return None;
+                }
+                message.tool_calls = (!calls.is_empty()).then_some(calls);
+            }
+            Some(message)
+        })
+        .collect()
+}
@@ -0,0 +1,298 @@
+// 标准库
+use std::fmt::Debug;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+// 错误处理
+use error_stack::{Result, ResultExt};
+
+// 异步
+use tokio::task::AbortHandle;
+
+// 观测诊断
+use tracing::{info, warn};
+
+// 项目内部模块
+use crate::chat::chat_base::ChatError;
+use crate::chat::event::{ChatEvent, EventHandlers, EventSource};
+use crate::chat::openapi::OpenApiTools;
+use crate::chat::persona::Persona;
+use crate::chat::tool_source::{LocalTools, ToolSource};
+
+/// 监视文件的默认检查间隔
+/// Default check interval of the watched files
+pub const DEFAULT_RELOAD_INTERVAL: Duration = Duration::from_millis(500);
+
+/// 监视的文件种类
+/// Kind of a watched file
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum WatchKind {
+    /// 文本文件，全文作为系统提示词
+    /// Text file whose whole content is the system prompt
+    Prompt,
+
+    /// 人设文件，见 `Persona::load`
+    /// Persona file, see `Persona::load`
+    Persona,
+
+    /// JSON 格式的 OpenAPI 文档，见 `OpenApiTools::from_spec`
+    /// OpenAPI document in JSON, see `OpenApiTools::from_spec`
+    OpenApi { namespace: String },
+}
+
+/// 监视的文件、上次加载时的修改时间与大小，以及加载出的内容
+/// A watched file, its modification time and size at the last load, and what was loaded from it
+struct Watched {
+    kind: WatchKind,
+    path: PathBuf,
+    stamp: Option<(SystemTime, u64)>,
+    prompt: Option<String>,
+    tools: Option<Arc<dyn ToolSource>>,
+}
+
+/// 从一个文件加载出的提示词与工具
+/// Prompt and tools loaded from one file
+struct Loaded {
+    prompt: Option<String>,
+    tools: Option<Arc<dyn ToolSource>>,
+}
+
+/// 加载文件，不持有监视状态的锁
+/// Load a file, without holding the lock of the watch state
+fn load(kind: &WatchKind, path: &Path) -> Result<Loaded, ChatError> {
+    let reload_error = || ChatError::ReloadError(path.display().to_string());
+    let loaded = match kind {
+        WatchKind::Prompt => {
+            let prompt = fs::read_to_string(path).change_context_lazy(reload_error)?;
+            Loaded {
+                prompt: Some(prompt.trim().to_string()),
+                tools: None,
+            }
+        }
+        WatchKind::Persona => {
+            let persona = Persona::load(path).change_context_lazy(reload_error)?;
+            Loaded {
+                prompt: Some(persona.prompt()),
+                tools: (!persona.tools.is_empty())
+                    .then(|| Arc::new(LocalTools::new(persona.tools)) as Arc<dyn ToolSource>),
+            }
+        }
+        WatchKind::OpenApi { namespace } => {
+            let spec = fs::read_to_string(path).change_context_lazy(reload_error)?;
+            let spec = serde_json::from_str(&spec).change_context_lazy(reload_error)?;
+            let tools = OpenApiTools::from_spec(namespace, &spec).change_context_lazy(reload_error)?;
+            Loaded {
+                prompt: None,
+                tools: Some(Arc::new(tools)),
+            }
+        }
+    };
+    Ok(loaded)
+}
+
+/// 最新加载的系统提示词与工具来源及其版本，由对话在下一次提问前应用
+/// The latest loaded system prompt and tool sources with their generation, applied by chats before their next
+/// question
+#[derive(Clone)]
+pub(crate) struct ReloadSnapshot {
+    pub generation: u64,
+
+    pub prompt: Option<String>,
+
+    /// 既没有监视 OpenAPI 文档，人设也没有工具时为 None，此时不改动对话的工具
+    /// None if no OpenAPI document is watched and the personas have no tools, the chat's tools are left alone then
+    pub tools: Option<Vec<Arc<dyn ToolSource>>>,
+}
+
+#[derive(Default)]
+struct ReloadState {
+    watched: Vec<Watched>,
+    generation: u64,
+}
+
+/// 开发模式的热重载：监视提示词文件、人设与 OpenAPI 工具文档，文件改动后无需重启即重新加载到运行中的对话，
+/// 并发出 `ChatEvent::Reloaded` 事件，缩短智能体开发的迭代周期
+/// Hot reload for development: watches prompt files, personas and OpenAPI tool specs and reloads them into running
+/// chats without a restart when the files change, emitting `ChatEvent::Reloaded`, to tighten the iteration loop of
+/// agent development
+///
+/// 以轮询文件的修改时间与大小检测改动，在 Windows 与 Unix 上行为一致，编辑器先删除再写入的保存方式也能识别。
+/// 文件加载失败时保留上一个版本并在事件中附上错误。对话在下一次提问前应用最新版本：提示词或人设替换对话的第一条
+/// 系统消息，人设的工具与 OpenAPI 文档一起替换对话的工具；克隆共享同一份监视状态，一个监视器可以服务多个对话。
+/// Changes are detected by polling the modification time and size of the files, which behaves the same on Windows
+/// and Unix and also catches editors that save by deleting and rewriting. A file that fails to load keeps its
+/// previous version and the event carries the error. Chats apply the latest version before their next question: a
+/// prompt or persona replaces the first system message of the chat, and the persona's tools together with the
+/// OpenAPI documents replace the chat's tools; clones share the watch state, so one watcher can serve many chats.
+///
+/// ```ignore
+/// let reload = HotReload::new().prompt("prompts/support.md").openapi("shop", "specs/shop.json");
+/// let watcher = reload.clone().spawn(DEFAULT_RELOAD_INTERVAL);
+/// chat.set_hot_reload(reload);
+/// ```
+#[derive(Clone)]
+pub struct HotReload {
+    state: Arc<Mutex<ReloadState>>,
+    events: EventHandlers,
+}
+
+impl Debug for HotReload {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let state = self.state.lock().unwrap();
+        let paths: Vec<&Path> = state.watched.iter().map(|watched| watched.path.as_path()).collect();
+        f.debug_struct("HotReload")
+            .field("watched", &paths)
+            .field("generation", &state.generation)
+            .finish()
+    }
+}
+
+impl Default for HotReload {
+    fn default() -> Self {
+        Self {
+            state: Arc::default(),
+            events: EventHandlers::new(EventSource::HotReload),
+        }
+    }
+}
+
+impl HotReload {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn watch(self, kind: WatchKind, path: impl Into<PathBuf>) -> Self {
+        self.state.lock().unwrap().watched.push(Watched {
+            kind,
+            path: path.into(),
+            stamp: None,
+            prompt: None,
+            tools: None,
+        });
+        self
+    }
+
+    /// 监视系统提示词文件，全文作为对话的系统提示词
+    /// Watch a system prompt file, whose whole content becomes the chat's system prompt
+    pub fn prompt(self, path: impl Into<PathBuf>) -> Self {
+        self.watch(WatchKind::Prompt, path)
+    }
+
+    /// 监视人设文件，其提示词作为对话的系统提示词，其工具加入对话的工具
+    /// Watch a persona file, its prompt becomes the chat's system prompt and its tools join the chat's tools
+    pub fn persona(self, path: impl Into<PathBuf>) -> Self {
+        self.watch(WatchKind::Persona, path)
+    }
+
+    /// 监视 JSON 格式的 OpenAPI 文档，以 `namespace` 为命名空间导入为工具
+    /// Watch an OpenAPI document in JSON, imported as tools under `namespace`
+    pub fn openapi(self, namespace: &str, path: impl Into<PathBuf>) -> Self {
+        let namespace = namespace.to_string();
+        self.watch(WatchKind::OpenApi { namespace }, path)
+    }
+
+    /// 注册重新加载事件的处理函数，事件同时发布到 `EventBus`
+    /// Register a handler of reload events, which are also published to the `EventBus`
+    pub fn on_reload(mut self, handler: impl Fn(&ChatEvent) + Send + Sync + 'static) -> Self {
+        self.events.add(Arc::new(handler));
+        self
+    }
+
+    /// 检查一次所有文件，重新加载有改动的文件，返回成功重新加载的文件数；首次检查加载全部文件。
+    /// 暂时无法读取的文件（如正在保存）跳过，下次检查时重试
+    /// Check every file once and reload the changed ones, returning the number reloaded successfully; the first
+    /// check loads every file. Files that cannot be read for now (such as while being saved) are skipped and retried
+    /// on the next check
+    ///
+    /// 读取文件与发出事件都在锁外进行，提问与事件处理函数不会等待文件IO
+    /// Files are read and events emitted outside the lock, so questions and event handlers never wait on file IO
+    pub fn check(&self) -> usize {
+        let watched: Vec<_> = {
+            let state = self.state.lock().unwrap();
+            state.watched.iter().map(|watched| (watched.kind.clone(), watched.path.clone(), watched.stamp)).collect()
+        };
+        let mut loads = Vec::new();
+        for (index, (kind, path, stamp)) in watched.into_iter().enumerate() {
+            let Ok(metadata) = fs::metadata(&path) else {
+                continue;
+            };
+            let current = (metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH), metadata.len());
+            if stamp != Some(current) {
+                loads.push((index, current, load(&kind, &path)));
+            }
+        }
+
+        let mut events = Vec::new();
+        let mut reloaded = 0;
+        {
+            let mut state = self.state.lock().unwrap();
+            for (index, stamp, loaded) in loads {
+                let watched = &mut state.watched[index];
+                // 并发的另一次检查已经应用了同一版本
+                // Another concurrent check already applied this version
+                if watched.stamp == Some(stamp) {
+                    continue;
+                }
+                watched.stamp = Some(stamp);
+                let path = watched.path.display().to_string();
+                match loaded {
+                    Ok(loaded) => {
+                        info!("Reloaded {}", path);
+                        watched.prompt = loaded.prompt;
+                        watched.tools = loaded.tools;
+                        reloaded += 1;
+                        events.push((path, None));
+                    }
+                    Err(e) => {
+                        warn!("Failed to reload {}, keeping the previous version: {:?}", path, e);
+                        events.push((path, Some(format!("{:?}", e))));
+                    }
+                }
+            }
+            state.generation += reloaded as u64;
+        }
+        for (path, error) in events {
+            self.events.emit(|| ChatEvent::Reloaded { path, error });
+        }
+        reloaded
+    }
+
+    /// 在后台每隔 `every` 检查一次文件，首次检查立即进行；检查在阻塞线程池中执行。返回的句柄用于停止监视
+    /// Check the files every `every` in the background, the first check right away; checks run on the blocking
+    /// thread pool. The returned handle stops watching
+    pub fn spawn(self, every: Duration) -> AbortHandle {
+        let handle = tokio::spawn(async move {
+            let mut interval = tokio::time::interval(every);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                interval.tick().await;
+                let reload = self.clone();
+                if let Err(e) = tokio::task::spawn_blocking(move || reload.check()).await {
+                    warn!("Hot reload check panicked: {}", e);
+                }
+            }
+        });
+        handle.abort_handle()
+    }
+
+    /// 版本新于 `generation` 时返回最新加载的内容，尚未加载任何文件时为 None
+    /// The latest loaded content if its generation is newer than `generation`, None before any file was loaded
+    pub(crate) fn newer_than(&self, generation: u64) -> Option<ReloadSnapshot> {
+        let state = self.state.lock().unwrap();
+        if state.generation <= generation {
+            return None;
+        }
+        let prompt = state.watched.iter().rev().find_map(|watched| watched.prompt.clone());
+        let tools: Vec<Arc<dyn ToolSource>> =
+            state.watched.iter().filter_map(|watched| watched.tools.clone()).collect();
+        let watches_tools =
+            !tools.is_empty() || state.watched.iter().any(|watched| matches!(watched.kind, WatchKind::OpenApi { .. }));
+        Some(ReloadSnapshot {
+            generation: state.generation,
+            prompt,
+            tools: watches_tools.then_some(tools),
+        })
+    }
+}
@@ -0,0 +1,302 @@
+// 标准库
+use std::path::Path;
+use std::sync::Arc;
+
+// 错误处理
+use error_stack::{Report, Result};
+
+// 编码
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+
+// 序列化
+use serde_json::{Map, Value, json};
+
+// 项目内部模块
+use crate::chat::chat_base::ChatError;
+use crate::chat::content::Content;
+
+/// 标记工具结果中要发给模型的图片的键，只有带此标记的对象会被当作图片取出
+/// Key marking an image in a tool result to be sent to the model, only objects carrying it are taken out as images
+pub(crate) const TOOL_IMAGE_MARKER: &str = "rhine_image";
+
+fn image_error(message: impl Into<String>) -> Report<ChatError> {
+    Report::new(ChatError::ImageError(message.into()))
+}
+
+/// 图片消息部分，来自文件、内存或网址，转换为 [`Content::Image`] 后加入多段内容
+/// Image message part, from a file, memory or a URL, converted into [`Content::Image`] to join multi-part content
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImagePart {
+    /// http(s) 地址，由提供商自行下载
+    /// http(s) address, downloaded by the provider
+    Url(String),
+
+    /// 内嵌的图片内容，发送时编码为 base64 data URL
+    /// Inline image content, encoded as a base64 data URL when sent
+    Data { mime_type: String, bytes: Vec<u8> },
+}
+
+impl ImagePart {
+    /// 读取图片文件，按文件头识别类型，无法识别时按扩展名判断
+    /// Read an image file, detecting its type from the file header and falling back to the extension
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self, ChatError> {
+        let path = path.as_ref();
+        let bytes = std::fs::read(path).map_err(|e| image_error(format!("{}: {}", path.display(), e)))?;
+        let mime_type = detect_mime(&bytes)
+            .or_else(|| path.extension().and_then(|extension| mime_from_extension(&extension.to_string_lossy())))
+            .ok_or_else(|| image_error(format!("{} is not a supported image", path.display())))?;
+        Ok(Self::Data {
+            mime_type: mime_type.to_string(),
+            bytes,
+        })
+    }
+
+    /// 内存中的图片，按文件头识别类型
+    /// Image in memory, its type detected from the header
+    pub fn from_bytes(bytes: impl Into<Vec<u8>>) -> Result<Self, ChatError> {
+        let bytes = bytes.into();
+        let mime_type = detect_mime(&bytes).ok_or_else(|| image_error("unrecognized image format"))?;
+        Ok(Self::Data {
+            mime_type: mime_type.to_string(),
+            bytes,
+        })
+    }
+
+    /// http(s) 地址或 base64 data URL；data URL 会被解码，以便缩放
+    /// An http(s) address or a base64 data URL; data URLs are decoded so they can be resized
+    pub fn from_url(url: &str) -> Result<Self, ChatError> {
+        if url.starts_with("http://") || url.starts_with("https://") {
+            return Ok(Self::Url(url.to_string()));
+        }
+
+        let (header, data) = url
+            .strip_prefix("data:")
+            .and_then(|rest| rest.split_once(','))
+            .ok_or_else(|| image_error(format!("unsupported image URL: {}", url)))?;
+        let mime_type = header
+            .strip_suffix(";base64")
+            .ok_or_else(|| image_error("data URL is not base64 encoded"))?;
+        let bytes = STANDARD
+            .decode(data)
+            .map_err(|e| image_error(format!("invalid base64 in data URL: {}", e)))?;
+        let mime_type = detect_mime(&bytes).unwrap_or(mime_type);
+        if !mime_type.starts_with("image/") {
+            return Err(image_error(format!("data URL is not an image: {}", mime_type)));
+        }
+        Ok(Self::Data {
+            mime_type: mime_type.to_string(),
+            bytes,
+        })
+    }
+
+    /// 内嵌图片的 MIME 类型，网址返回 None
+    /// MIME type of an inline image, None for URLs
+    pub fn mime_type(&self) -> Option<&str> {
+        match self {
+            Self::Url(_) => None,
+            Self::Data { mime_type, .. } => Some(mime_type),
+        }
+    }
+
+    /// 发送给提供商的地址：网址原样返回，内嵌图片编码为 data URL
+    /// Address sent to the provider: URLs as is, inline images encoded as a data URL
+    pub fn to_url(&self) -> String {
+        match self {
+            Self::Url(url) => url.clone(),
+            Self::Data { mime_type, bytes } => format!("data:{};base64,{}", mime_type, STANDARD.encode(bytes)),
+        }
+    }
+
+    /// 工具结果中的图片，工具函数可将其放在返回值的任意位置，如 `json!({"chart": image.to_tool_output()})`；
+    /// 对话把图片从结果中取出，按提供商发给模型
+    /// An image in a tool result, tool functions can put it anywhere in their return value, such as
+    /// `json!({"chart": image.to_tool_output()})`; the chat takes it out of the result and sends it to the model in the
+    /// way of the provider
+    ///
+    /// 输出带有显式标记，结果中其他形似图片的对象（如搜索结果中的图片元数据）保持原样。
+    /// The output carries an explicit marker, other image-like objects in the result (such as image metadata in search
+    /// results) are left as they are.
+    pub fn to_tool_output(&self) -> Value {
+        match self {
+            Self::Url(url) => json!({"type": "image", "url": url, TOOL_IMAGE_MARKER: true}),
+            Self::Data { mime_type, bytes } => json!({
+                "type": "image",
+                "mime_type": mime_type,
+                "data": STANDARD.encode(bytes),
+                TOOL_IMAGE_MARKER: true,
+            }),
+        }
+    }
+
+    /// 缩小内嵌图片，使其满足尺寸与大小限制；已满足限制的图片与网址保持不变
+    /// Downscale an inline image to meet the dimension and size limits; images within the limits and URLs are left
+    /// unchanged
+    ///
+    /// 超出大小的图片会重新编码，不含透明通道的格式优先编码为 JPEG。
+    /// Oversized images are re-encoded, as JPEG unless they need transparency.
+    #[cfg(feature = "images")]
+    pub fn resize(self, limits: &ImageLimits) -> Result<Self, ChatError> {
+        match self {
+            Self::Url(_) => Ok(self),
+            Self::Data { mime_type, bytes } => {
+                let (mime_type, bytes) = resize::fit(&mime_type, bytes, limits)?;
+                Ok(Self::Data { mime_type, bytes })
+            }
+        }
+    }
+}
+
+impl From<ImagePart> for Content {
+    fn from(image: ImagePart) -> Self {
+        Content::image(&image.to_url())
+    }
+}
+
+/// 取出工具结果中的图片，原位置替换为 `[image N]` 占位，返回图片的地址（http(s) 地址或 data URL）
+/// Take the images out of a tool result, replacing each with an `[image N]` placeholder, and return their addresses
+/// (http(s) addresses or data URLs)
+///
+/// 只识别 `ImagePart::to_tool_output` 输出的带标记的图片，MCP 工具的图片内容在返回时已转换为这一格式。
+/// Only recognizes the marked images output by `ImagePart::to_tool_output`, image content of MCP tools is converted
+/// to this format when returned.
+pub(crate) fn take_tool_images(value: &mut Value) -> Vec<Arc<str>> {
+    let mut images = Vec::new();
+    collect_tool_images(value, &mut images);
+    images
+}
+
+fn collect_tool_images(value: &mut Value, images: &mut Vec<Arc<str>>) {
+    match value {
+        Value::Object(object) => match tool_image_url(object) {
+            Some(url) => {
+                images.push(Arc::from(url));
+                *value = json!(format!("[image {}]", images.len()));
+            }
+            None => object.values_mut().for_each(|value| collect_tool_images(value, images)),
+        },
+        Value::Array(items) => items.iter_mut().for_each(|value| collect_tool_images(value, images)),
+        _ => {}
+    }
+}
+
+fn tool_image_url(object: &Map<String, Value>) -> Option<String> {
+    if object.get(TOOL_IMAGE_MARKER) != Some(&Value::Bool(true)) || object.get("type")? != "image" {
+        return None;
+    }
+    if let Some(url) = object.get("url").and_then(Value::as_str) {
+        return Some(url.to_string());
+    }
+    let data = object.get("data")?.as_str()?;
+    let mime_type = object.get("mime_type")?.as_str()?;
+    mime_type.starts_with("image/").then(|| format!("data:{};base64,{}", mime_type, data))
+}
+
+/// 内嵌图片的尺寸与大小上限
+/// Dimension and size limits of inline images
+#[cfg(feature = "images")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ImageLimits {
+    /// 长边的最大像素数
+    /// Maximum pixels of the longer side
+    pub max_dimension: u32,
+
+    /// 编码后的最大字节数，不含 base64 膨胀
+    /// Maximum encoded bytes, before base64 expansion
+    pub max_bytes: usize,
+}
+
+#[cfg(feature = "images")]
+impl Default for ImageLimits {
+    /// 满足主流提供商限制的保守取值
+    /// Conservative values meeting the limits of mainstream providers
+    fn default() -> Self {
+        Self {
+            max_dimension: 2048,
+            max_bytes: 5 * 1024 * 1024,
+        }
+    }
+}
+
+/// 按文件头识别常见图片格式
+/// Detect common image formats from the file header
+fn detect_mime(bytes: &[u8]) -> Option<&'static str> {
+    match bytes {
+        [0x89, b'P', b'N', b'G', ..] => Some("image/png"),
+        [0xFF, 0xD8, 0xFF, ..] => Some("image/jpeg"),
+        [b'G', b'I', b'F', b'8', ..] => Some("image/gif"),
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Some("image/webp"),
+        [b'B', b'M', ..] => Some("image/bmp"),
+        _ => None,
+    }
+}
+
+fn mime_from_extension(extension: &str) -> Option<&'static str> {
+    match extension.to_lowercase().as_str() {
+        "png" => Some("image/png"),
+        "jpg" | "jpeg" => Some("image/jpeg"),
+        "gif" => Some("image/gif"),
+        "webp" => Some("image/webp"),
+        "bmp" => Some("image/bmp"),
+        _ => None,
+    }
+}
+
+#[cfg(feature = "images")]
+mod resize {
+    // 标准库
+    use std::io::Cursor;
+
+    // 错误处理
+    use error_stack::Result;
+
+    // 图片处理
+    use image::codecs::jpeg::JpegEncoder;
+    use image::imageops::FilterType;
+    use image::{DynamicImage, GenericImageView, ImageFormat};
+
+    // 项目内部模块
+    use super::{ImageLimits, image_error};
+    use crate::chat::chat_base::ChatError;
+
+    /// 重新编码后仍超出大小时，每次缩小的比例
+    /// Ratio applied on each step while the re-encoded image is still oversized
+    const SHRINK_STEP: f32 = 0.75;
+
+    pub(super) fn fit(mime_type: &str, bytes: Vec<u8>, limits: &ImageLimits) -> Result<(String, Vec<u8>), ChatError> {
+        let image = image::load_from_memory(&bytes).map_err(|e| image_error(format!("failed to decode image: {}", e)))?;
+        let (width, height) = image.dimensions();
+        if width.max(height) <= limits.max_dimension && bytes.len() <= limits.max_bytes {
+            return Ok((mime_type.to_string(), bytes));
+        }
+
+        let mut image = if width.max(height) > limits.max_dimension {
+            image.resize(limits.max_dimension, limits.max_dimension, FilterType::Lanczos3)
+        } else {
+            image
+        };
+        loop {
+            let encoded = encode(&image)?;
+            let (width, height) = image.dimensions();
+            if encoded.1.len() <= limits.max_bytes || width.max(height) <= 1 {
+                return Ok(encoded);
+            }
+            let side = ((width.max(height) as f32 * SHRINK_STEP) as u32).max(1);
+            image = image.resize(side, side, FilterType::Triangle);
+        }
+    }
+
+    fn encode(image: &DynamicImage) -> Result<(String, Vec<u8>), ChatError> {
+        let mut bytes = Cursor::new(Vec::new());
+        if image.color().has_alpha() {
+            image
+                .write_to(&mut bytes, ImageFormat::Png)
+                .map_err(|e| image_error(format!("failed to encode PNG: {}", e)))?;
+            return Ok(("image/png".to_string(), bytes.into_inner()));
+        }
+        JpegEncoder::new_with_quality(&mut bytes, 85)
+            .encode_image(&image.to_rgb8())
+            .map_err(|e| image_error(format!("failed to encode JPEG: {}", e)))?;
+        Ok(("image/jpeg".to_string(), bytes.into_inner()))
+    }
+}
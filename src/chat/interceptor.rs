@@ -0,0 +1,75 @@
+// 标准库
+use std::sync::{Arc, RwLock};
+
+// 并发和同步原语
+use once_cell::sync::Lazy;
+
+// 网络通信
+use reqwest::header::HeaderMap;
+
+/// 全局请求拦截器，对所有对话生效，先于对话自身的拦截器执行
+/// Global request interceptors, applied to every chat before the chat's own interceptors
+static INTERCEPTORS: Lazy<RwLock<Vec<Arc<dyn RequestInterceptor>>>> = Lazy::new(|| RwLock::new(Vec::new()));
+
+/// 即将发往提供商的请求
+/// A request about to be sent to the provider
+#[derive(Clone, Debug)]
+pub struct OutgoingRequest {
+    /// 请求地址，可改写以经由网关转发
+    /// Request URL, may be rewritten to route through a gateway
+    pub url: String,
+
+    pub model: String,
+
+    pub request_id: String,
+
+    /// 请求头，包含 `Authorization` 与请求ID头
+    /// Request headers, including `Authorization` and the request ID header
+    pub headers: HeaderMap,
+
+    /// 发往提供商的请求体，已转换为提供商的格式
+    /// Request body sent to the provider, already in the provider's format
+    pub body: serde_json::Value,
+}
+
+/// 请求拦截器，在请求发出前检查或修改请求体与请求头，用于网关适配、实验开关和提供商的特殊要求
+/// Request interceptor, inspecting or changing the body and headers just before a request is sent, for gateway
+/// adaptations, experiment flags and provider quirks
+///
+/// 续传与重试的每次请求都会经过拦截器。
+/// Every request of resumes and retries passes through the interceptors.
+pub trait RequestInterceptor: Send + Sync {
+    fn intercept(&self, request: &mut OutgoingRequest);
+}
+
+/// 同步函数可直接作为拦截器
+/// Synchronous functions can be used directly as interceptors
+impl<F> RequestInterceptor for F
+where
+    F: Fn(&mut OutgoingRequest) + Send + Sync,
+{
+    fn intercept(&self, request: &mut OutgoingRequest) {
+        self(request)
+    }
+}
+
+/// 注册全局请求拦截器，按注册顺序执行
+/// Register a global request interceptor, interceptors run in registration order
+pub fn register_request_interceptor(interceptor: impl RequestInterceptor + 'static) {
+    INTERCEPTORS.write().unwrap().push(Arc::new(interceptor));
+}
+
+/// 移除全部全局请求拦截器
+/// Remove every global request interceptor
+pub fn clear_request_interceptors() {
+    INTERCEPTORS.write().unwrap().clear();
+}
+
+/// 依次执行全局拦截器与对话自身的拦截器
+/// Run the global interceptors, then the chat's own ones
+pub(crate) fn intercept(request: &mut OutgoingRequest, chat_interceptors: &[Arc<dyn RequestInterceptor>]) {
+    let global = INTERCEPTORS.read().unwrap().clone();
+    for interceptor in global.iter().chain(chat_interceptors) {
+        interceptor.intercept(request);
+    }
+}
@@ -0,0 +1,40 @@
+/// JSON Lines 模式下附加在输出要求之后的指令
+/// Instruction appended to the output description in JSON Lines mode
+pub const JSON_LINES_INSTRUCTION: &str =
+    "逐条输出所有结果，每行一个完整的JSON对象（JSON Lines），不要用数组包裹，不要输出其他内容。";
+
+/// 流式回答的 JSON Lines 切分器，把逐段到达的文本切分为完整的条目行
+/// JSON Lines splitter of a streamed answer, cutting text arriving piece by piece into complete item lines
+///
+/// 空行、代码块标记等不以 `{` 或 `[` 开头的行被忽略，行尾多余的逗号被去除。
+/// Empty lines, code fences and other lines not starting with `{` or `[` are ignored, and stray trailing commas
+/// removed.
+#[derive(Clone, Debug, Default)]
+pub struct JsonLines {
+    buffer: String,
+}
+
+impl JsonLines {
+    /// 加入一段文本，返回因此完整的条目行
+    /// Add a piece of text, returning the item lines it completes
+    pub fn push(&mut self, delta: &str) -> Vec<String> {
+        self.buffer.push_str(delta);
+        let Some(end) = self.buffer.rfind('\n') else {
+            return Vec::new();
+        };
+        let rest = self.buffer.split_off(end + 1);
+        let complete = std::mem::replace(&mut self.buffer, rest);
+        complete.lines().filter_map(item_line).collect()
+    }
+
+    /// 回答结束时取出最后一行（没有换行结尾时）
+    /// Take the last line when the answer ends (if it has no trailing newline)
+    pub fn finish(&mut self) -> Option<String> {
+        item_line(&std::mem::take(&mut self.buffer))
+    }
+}
+
+fn item_line(line: &str) -> Option<String> {
+    let line = line.trim().trim_end_matches(',');
+    line.starts_with(['{', '[']).then(|| line.to_string())
+}
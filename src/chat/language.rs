@@ -0,0 +1,139 @@
+// 文本处理
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+pub use whatlang::Lang;
+
+/// 代码块，识别语种时忽略
+/// Code blocks, ignored when detecting the language
+static CODE_BLOCK: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?s)```.*?```").unwrap());
+
+/// 识别文本的语种，代码块不计入；文本过短或结果不可靠时返回 None
+/// Detect the language of a text, code blocks left out; None if the text is too short or the result unreliable
+pub fn detect_language(text: &str, min_chars: usize) -> Option<Lang> {
+    let text = CODE_BLOCK.replace_all(text, " ");
+    if text.trim().chars().count() < min_chars {
+        return None;
+    }
+    whatlang::detect(&text).filter(|info| info.is_reliable()).map(|info| info.lang())
+}
+
+/// 回答应使用的语种
+/// Language the answers should be in
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LanguageTarget {
+    /// 与用户最近一条消息的语种一致
+    /// The language of the latest user message
+    #[default]
+    MatchUser,
+
+    /// 固定语种
+    /// A fixed language
+    Fixed(Lang),
+}
+
+/// 回答语种的检查：识别回答的语种，与目标不符时附上明确要求重新提问
+/// Answer language check: the language of the answer is detected, and the model is asked again with an explicit
+/// instruction when it does not match the target
+///
+/// 内部的提示词多为中文，模型有时会随之改用中文回答。只检查完整收到的回答，`stream_answer` 逐段产出的回答
+/// 不检查。重问次数用尽后返回最后一次回答。
+/// Internal prompts are mostly Chinese, which sometimes pulls the model into answering in Chinese. Only answers
+/// received in full are checked, answers yielded piece by piece by `stream_answer` are not. Once the retries are
+/// used up the last answer is returned.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LanguagePolicy {
+    pub target: LanguageTarget,
+
+    /// 语种不符时最多重问的次数
+    /// Maximum times to ask again when the language does not match
+    pub max_retries: u32,
+
+    /// 参与识别的最少字符数，更短的文本不检查
+    /// Minimum characters for detection, shorter texts are not checked
+    pub min_chars: usize,
+}
+
+impl Default for LanguagePolicy {
+    fn default() -> Self {
+        Self {
+            target: LanguageTarget::MatchUser,
+            max_retries: 1,
+            min_chars: 10,
+        }
+    }
+}
+
+impl LanguagePolicy {
+    /// 回答与用户使用同一语种
+    /// Answers in the language of the user
+    pub fn match_user() -> Self {
+        Self::default()
+    }
+
+    /// 回答固定使用某一语种
+    /// Answers always in one language
+    pub fn fixed(language: Lang) -> Self {
+        Self {
+            target: LanguageTarget::Fixed(language),
+            ..Self::default()
+        }
+    }
+
+    pub fn max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    pub fn min_chars(mut self, min_chars: usize) -> Self {
+        self.min_chars = min_chars;
+        self
+    }
+
+    /// 请求期望的回答语种，跟随用户时识别请求体中最后一条用户消息
+    /// Answer language expected for a request, detected from the last user message of the body when following the
+    /// user
+    pub fn expected(&self, request_body: &serde_json::Value) -> Option<Lang> {
+        match self.target {
+            LanguageTarget::Fixed(language) => Some(language),
+            LanguageTarget::MatchUser => {
+                let message = request_body["messages"]
+                    .as_array()?
+                    .iter()
+                    .rev()
+                    .find(|message| message["role"] == "user")?;
+                detect_language(&message_text(&message["content"]), self.min_chars)
+            }
+        }
+    }
+
+    /// 回答的语种与期望不符时返回识别出的语种；无法识别时视为相符
+    /// The detected language if the answer does not match the expected one; undetectable answers count as matching
+    pub fn mismatch(&self, answer: &str, expected: Lang) -> Option<Lang> {
+        detect_language(answer, self.min_chars).filter(|&detected| detected != expected)
+    }
+}
+
+/// 要求以目标语种重新回答的消息
+/// Message asking to answer again in the target language
+pub(crate) fn language_instruction(expected: Lang) -> String {
+    format!(
+        "上一个回答使用了错误的语言，请完全使用{}重新回答 / The previous answer was in the wrong language, answer again entirely in {}",
+        expected.name(),
+        expected.eng_name()
+    )
+}
+
+/// 消息内容中的文字，分段内容只取文字部分
+/// Text of a message content, only the text parts of multi-part content
+fn message_text(content: &serde_json::Value) -> String {
+    match content {
+        serde_json::Value::String(text) => text.clone(),
+        serde_json::Value::Array(parts) => parts
+            .iter()
+            .filter_map(|part| part["text"].as_str())
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => String::new(),
+    }
+}
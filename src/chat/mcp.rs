@@ -0,0 +1,215 @@
+// 标准库
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// 错误处理
+use error_stack::{Report, Result};
+
+// 异步
+use futures::future::BoxFuture;
+use tokio::sync::OnceCell;
+
+// 数据序列化
+use serde_json::{Value, json};
+
+// 项目内部模块
+use crate::chat::chat_base::ChatError;
+use crate::chat::image::ImagePart;
+use crate::chat::tool_source::{ToolSource, function_tool};
+use crate::schema::tool_schema::ChatToolSchemaError;
+
+/// 初始化时声明的 MCP 协议版本
+/// MCP protocol version announced on initialization
+pub const MCP_PROTOCOL_VERSION: &str = "2025-03-26";
+
+/// 会话ID的响应头与请求头
+/// Response and request header carrying the session ID
+const SESSION_HEADER: &str = "mcp-session-id";
+
+/// 通过 HTTP（Streamable HTTP 传输）连接的 MCP 服务的工具
+/// Tools of an MCP server reached over HTTP (the Streamable HTTP transport)
+///
+/// 第一次使用时完成初始化握手，服务返回的会话ID随之后的请求发送；响应可以是 JSON，也可以是 SSE 流。
+/// The initialization handshake happens on first use and the session ID returned by the server is sent with later
+/// requests; responses may be JSON or an SSE stream.
+#[derive(Debug)]
+pub struct McpTools {
+    namespace: String,
+    url: String,
+    headers: Vec<(String, String)>,
+    client: reqwest::Client,
+    session_id: OnceCell<Option<String>>,
+    next_id: AtomicU64,
+}
+
+impl McpTools {
+    pub fn new(namespace: &str, url: &str) -> Self {
+        Self {
+            namespace: namespace.to_string(),
+            url: url.to_string(),
+            headers: Vec::new(),
+            client: reqwest::Client::new(),
+            session_id: OnceCell::new(),
+            next_id: AtomicU64::new(1),
+        }
+    }
+
+    /// 附加在每个请求上的请求头，如鉴权
+    /// Header added to every request, such as authorization
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// 发送一条 JSON-RPC 消息，`id` 为 None 时为通知，不等待结果
+    /// Send one JSON-RPC message, a notification without waiting for a result if `id` is None
+    async fn send(
+        &self,
+        id: Option<u64>,
+        method: &str,
+        params: Value,
+        session_id: Option<&str>,
+    ) -> std::result::Result<(Option<Value>, Option<String>), String> {
+        let mut message = json!({"jsonrpc": "2.0", "method": method, "params": params});
+        if let Some(id) = id {
+            message["id"] = json!(id);
+        }
+        let mut request = self
+            .client
+            .post(&self.url)
+            .header("accept", "application/json, text/event-stream")
+            .json(&message);
+        for (name, value) in &self.headers {
+            request = request.header(name, value);
+        }
+        if let Some(session_id) = session_id {
+            request = request.header(SESSION_HEADER, session_id);
+        }
+
+        let response = request.send().await.map_err(|e| e.to_string())?;
+        let status = response.status();
+        let session = response
+            .headers()
+            .get(SESSION_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        let text = response.text().await.map_err(|e| e.to_string())?;
+        if !status.is_success() {
+            return Err(format!("{} {}: {}", status, method, text));
+        }
+        let Some(id) = id else {
+            return Ok((None, session));
+        };
+
+        // SSE 响应中找到与请求ID对应的消息
+        // In SSE responses, find the message answering the request ID
+        let reply = match text.trim_start().starts_with('{') {
+            true => serde_json::from_str::<Value>(&text).ok(),
+            false => text
+                .lines()
+                .filter_map(|line| line.strip_prefix("data:"))
+                .filter_map(|data| serde_json::from_str::<Value>(data.trim()).ok())
+                .find(|message| message["id"] == json!(id)),
+        }
+        .ok_or_else(|| format!("No reply to {} in: {}", method, text))?;
+        if let Some(error) = reply.get("error") {
+            return Err(format!("{} failed: {}", method, error));
+        }
+        Ok((Some(reply["result"].clone()), session))
+    }
+
+    /// 完成初始化握手后调用方法，返回结果
+    /// Call a method once the initialization handshake is done, returning its result
+    async fn request(&self, method: &str, params: Value) -> std::result::Result<Value, String> {
+        let session_id = self
+            .session_id
+            .get_or_try_init(|| async {
+                let params = json!({
+                    "protocolVersion": MCP_PROTOCOL_VERSION,
+                    "capabilities": {},
+                    "clientInfo": {"name": "rhine", "version": env!("CARGO_PKG_VERSION")}
+                });
+                let (_, session_id) = self.send(Some(0), "initialize", params, None).await?;
+                self.send(None, "notifications/initialized", json!({}), session_id.as_deref()).await?;
+                Ok::<_, String>(session_id)
+            })
+            .await?;
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (result, _) = self.send(Some(id), method, params, session_id.as_deref()).await?;
+        Ok(result.unwrap_or_default())
+    }
+}
+
+impl ToolSource for McpTools {
+    fn namespace(&self) -> Option<&str> {
+        Some(&self.namespace)
+    }
+
+    fn list_tools(&self) -> BoxFuture<'_, Result<Vec<Value>, ChatError>> {
+        Box::pin(async move {
+            let source_error = || ChatError::ToolSourceError(self.namespace.clone());
+            let mut tools = Vec::new();
+            let mut cursor = None;
+            loop {
+                let params = match &cursor {
+                    Some(cursor) => json!({"cursor": cursor}),
+                    None => json!({}),
+                };
+                let result = self
+                    .request("tools/list", params)
+                    .await
+                    .map_err(|e| Report::new(source_error()).attach_printable(e))?;
+                for tool in result["tools"].as_array().into_iter().flatten() {
+                    let name = tool["name"].as_str().ok_or_else(|| {
+                        Report::new(source_error()).attach_printable(format!("Tool without a name: {}", tool))
+                    })?;
+                    tools.push(function_tool(name, tool["description"].as_str(), tool.get("inputSchema")));
+                }
+                cursor = result["nextCursor"].as_str().map(str::to_string);
+                if cursor.is_none() {
+                    return Ok(tools);
+                }
+            }
+        })
+    }
+
+    fn call_tool<'a>(&'a self, name: &'a str, arguments: Value) -> BoxFuture<'a, Result<Value, ChatToolSchemaError>> {
+        Box::pin(async move {
+            let result = self
+                .request("tools/call", json!({"name": name, "arguments": arguments}))
+                .await
+                .map_err(|e| Report::new(ChatToolSchemaError::FunctionCallError).attach_printable(e))?;
+            let text = result["content"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|content| content["text"].as_str())
+                .collect::<Vec<_>>()
+                .join("\n");
+            if result["isError"].as_bool() == Some(true) {
+                return Err(Report::new(ChatToolSchemaError::FunctionCallError).attach_printable(text));
+            }
+            let output = match result.get("structuredContent") {
+                Some(structured) => structured.clone(),
+                None => serde_json::from_str(&text).unwrap_or(Value::String(text)),
+            };
+            // 图片内容转换为带标记的图片随结果返回，由对话取出后发给模型
+            // Image content is returned with the result as marked images, for the chat to take out and send to the
+            // model
+            let images: Vec<Value> = result["content"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter(|content| content["type"] == "image")
+                .filter_map(|content| {
+                    let url = format!("data:{};base64,{}", content["mimeType"].as_str()?, content["data"].as_str()?);
+                    ImagePart::from_url(&url).ok()
+                })
+                .map(|image| image.to_tool_output())
+                .collect();
+            match images.is_empty() {
+                true => Ok(output),
+                false => Ok(json!({"output": output, "images": images})),
+            }
+        })
+    }
+}
@@ -1,8 +1,17 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::borrow::Cow;
 use std::fmt::Display;
+use std::time::{SystemTime, UNIX_EPOCH};
 use thiserror::Error;
 use tracing::info;
+use uuid::Uuid;
+
+use crate::chat::attachment::Attachment;
+use crate::chat::content::{ApiContent, Content};
+use crate::chat::output_cap::Truncated;
+use crate::chat::refusal::RefusalReason;
+use crate::chat::scratchpad::Scratchpad;
+use crate::utils::common::redact::redact;
 
 #[derive(Debug, Error)]
 pub enum MessageError {
@@ -14,6 +23,12 @@ pub enum MessageError {
 
     #[error("Unsupported operation: {0}")]
     UnsupportedOperation(String),
+
+    #[error("Unknown message ID: {0}")]
+    UnknownId(String),
+
+    #[error("Invalid API message at index {0}: {1}")]
+    InvalidApiMessage(usize, String),
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -22,16 +37,33 @@ pub enum Role {
     System,
     User,
     Assistant,
+
+    /// 工具结果，`tool_call_id` 为它回答的工具调用，请求中以 tool 角色发送
+    /// Tool result, `tool_call_id` being the tool call it answers; sent with the tool role in requests
+    Tool { tool_call_id: String },
+
     #[serde(untagged)]
     Character(String),
 }
 
+impl Role {
+    pub fn tool(tool_call_id: &str) -> Self {
+        Self::Tool {
+            tool_call_id: tool_call_id.to_string(),
+        }
+    }
+}
+
+/// 由角色名转换；名称中没有调用ID，"tool" 得到调用ID为空的工具角色，需要ID时使用 `Role::tool`
+/// Conversion from a role name; the name carries no call ID, so "tool" gives a tool role with an empty call ID, use
+/// `Role::tool` when the ID is needed
 impl From<&str> for Role {
     fn from(s: &str) -> Self {
         match s {
             "system" => Self::System,
             "user" => Self::User,
             "assistant" => Self::Assistant,
+            "tool" => Self::tool(""),
             other => Self::Character(other.to_string()), // 自定义角色转换 / Custom role conversion
         }
     }
@@ -43,25 +75,200 @@ impl Display for Role {
             Self::System => "system".to_string(),
             Self::User => "user".to_string(),
             Self::Assistant => "assistant".to_string(),
+            Self::Tool { .. } => "tool".to_string(),
             Self::Character(name) => name.clone(),
         };
         write!(f, "{}", str)
     }
 }
 
+/// 生成该消息的LLM调用的性能数据
+/// Performance data of the LLM call that produced the message
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MessageMetadata {
+    pub request_id: String,
+    pub model: String,
+    /// 首token延迟（毫秒），仅流式请求
+    /// Time to first token in milliseconds, streaming requests only
+    pub ttft_ms: Option<u64>,
+    /// 总延迟（毫秒）
+    /// Total latency in milliseconds
+    pub latency_ms: u64,
+    pub prompt_tokens: Option<u64>,
+    pub completion_tokens: Option<u64>,
+    /// 提供商拒绝原生结构化输出参数（如 `response_format`、`tools`）后改用提示词方式时，记录被拒绝的参数
+    /// The rejected parameter (such as `response_format` or `tools`) when the provider refused native structured
+    /// output and the prompt-engineered path was used instead
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub downgrade: Option<String>,
+    /// 审核标记的类别（处理方式为屏蔽或标注时）
+    /// Categories flagged by moderation (when the action is redact or annotate)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub moderation: Vec<String>,
+    /// 回答达到输出上限被截断时的标记
+    /// Marker set when the answer was truncated at the output cap
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub truncated: Option<Truncated>,
+    /// 回答因长度限制中断后的续写次数，各段的用量与延迟已合计
+    /// Number of continuations after the answer was cut off by the length limit, the usage and latency of the parts
+    /// are summed up
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub continuations: u32,
+    /// 模型或提供商拒绝回答时的原因
+    /// Reason when the model or the provider refused to answer
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refusal: Option<RefusalReason>,
+    /// 提供商返回 logprobs 时回答的 token 几何平均概率，0 到 1
+    /// Geometric mean token probability of the answer when the provider returned logprobs, 0 to 1
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_probability: Option<Probability>,
+    /// 请求失败后换用其他API的记录，包括凭据被拒后的故障转移与流式请求的备用API，按发生顺序
+    /// Switches to other APIs after requests failed, including failovers on rejected credentials and fallbacks of
+    /// streaming requests, in the order they happened
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub provider_switches: Vec<ProviderSwitch>,
+}
+
+/// 请求失败后换用其他API的记录
+/// Record of switching to another API after a request failed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProviderSwitch {
+    /// 失败的API名称
+    /// Name of the failing API
+    pub from: String,
+    /// 换用的API名称
+    /// Name of the API switched to
+    pub to: String,
+    /// 失败的错误码，如 `chat.http_error`
+    /// Code of the failure, such as `chat.http_error`
+    pub error: String,
+    /// 换用前已收到的回答字节数，备用API以这部分回答为前缀继续；为 0 时从头生成
+    /// Bytes of the answer received before the switch, the fallback continues with them as the prefix; generated
+    /// from scratch if 0
+    pub received_bytes: usize,
+}
+
+fn is_zero(count: &u32) -> bool {
+    *count == 0
+}
+
+impl MessageMetadata {
+    /// 输出速度（token/秒），流式请求从首token开始计时
+    /// Output speed in tokens per second, timed from the first token for streaming requests
+    pub fn tokens_per_second(&self) -> Option<f64> {
+        let generation_ms = self.latency_ms.saturating_sub(self.ttft_ms.unwrap_or(0));
+        match self.completion_tokens {
+            Some(tokens) if generation_ms > 0 => Some(tokens as f64 * 1000.0 / generation_ms as f64),
+            _ => None,
+        }
+    }
+}
+
+/// 消息的重要性标记，供 `ImportanceWeighted` 在裁剪历史时参考
+/// Importance marks of a message, consulted by `ImportanceWeighted` when trimming the history
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Importance {
+    /// 用户标记为重要，裁剪历史时总是保留
+    /// Flagged as important by the user, always kept when the history is trimmed
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub pinned: bool,
+
+    /// 模型评定的显著性，0 至 100，见 `SingleChat::score_salience`
+    /// Salience rated by a model from 0 to 100, see `SingleChat::score_salience`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub salience: Option<u8>,
+}
+
+impl Importance {
+    pub fn is_unset(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// 0 到 1 之间的概率，构造时排除 NaN，因此可以比较相等
+/// Probability between 0 and 1, NaN is ruled out on construction so it can be compared for equality
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Probability(f64);
+
+impl Eq for Probability {}
+
+impl Probability {
+    /// 超出 0 到 1 的值被截断，NaN 视为 0
+    /// Values outside 0 to 1 are clamped, NaN counts as 0
+    pub fn new(value: f64) -> Self {
+        Self(if value.is_nan() { 0.0 } else { value.clamp(0.0, 1.0) })
+    }
+
+    pub fn get(self) -> f64 {
+        self.0
+    }
+}
+
+/// 请求体中的一条消息，借用会话中的内容，避免为每次请求复制整段对话
+/// One message of a request body, borrowing content from the session so the conversation is not copied per request
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ApiMessage<'a> {
+    pub role: &'static str,
+    pub content: ApiContent<'a>,
+    /// 助手消息中的工具调用（OpenAI 格式）
+    /// Tool calls of an assistant message, in OpenAI format
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_calls: Option<Vec<serde_json::Value>>,
+    /// 工具结果消息对应的调用ID
+    /// Call ID that a tool result message answers
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<&'a str>,
+    /// 会话消息的重要性标记，不随请求发送
+    /// Importance marks of the session message, not sent with the request
+    #[serde(skip)]
+    pub importance: Importance,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Messages {
+    /// 消息的唯一ID，分支被裁剪或重排后仍然有效；旧版会话加载时自动生成
+    /// Unique ID of the message, stays valid when branches are pruned or reordered; generated when loading older
+    /// sessions
+    #[serde(default = "new_message_id")]
+    pub id: String,
     pub role: Role,
-    pub content: String,
+    /// 消息内容，文本部分共享存储，克隆消息或会话时不复制文本
+    /// Message content, text is shared so cloning a message or session does not copy it
+    pub content: Content,
     pub child: Vec<Messages>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<MessageMetadata>,
+    /// 附件，内容不随请求发送
+    /// Attachments, their content is not sent with requests
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attachments: Vec<Attachment>,
+    #[serde(default, skip_serializing_if = "Importance::is_unset")]
+    pub importance: Importance,
+    /// 消息创建时间（Unix 毫秒），旧版会话中为 None
+    /// Creation time of the message (Unix milliseconds), None in older sessions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at_ms: Option<u64>,
+}
+
+fn new_message_id() -> String {
+    Uuid::new_v4().to_string()
 }
 
 impl Messages {
-    pub fn new(role: Role, content: String) -> Self {
+    pub fn new(role: Role, content: impl Into<Content>) -> Self {
         Self {
+            id: new_message_id(),
             role,
-            content,
+            content: content.into(),
             child: Vec::new(),
+            metadata: None,
+            attachments: Vec::new(),
+            importance: Importance::default(),
+            created_at_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .ok()
+                .map(|elapsed| elapsed.as_millis() as u64),
         }
     }
 
@@ -77,11 +284,25 @@ impl Messages {
         self.child[path[0]].get_node_by_path(&path[1..])
     }
 
+    /// 在子树中查找ID对应的消息，返回相对于本消息的索引路径
+    /// Find the message with the ID in the subtree, returning its index path relative to this message
+    pub fn path_of(&self, id: &str) -> Option<Vec<usize>> {
+        if self.id == id {
+            return Some(Vec::new());
+        }
+        self.child.iter().enumerate().find_map(|(index, child)| {
+            child.path_of(id).map(|mut path| {
+                path.insert(0, index);
+                path
+            })
+        })
+    }
+
     pub fn add_with_parent_path(
         &mut self,
         parent_path: &[usize],
         role: Role,
-        content: String,
+        content: impl Into<Content>,
     ) -> Result<Vec<usize>, MessageError> {
         let parent = self.get_node_by_path(parent_path)?;
         let new_message = Self::new(role, content);
@@ -91,35 +312,96 @@ impl Messages {
         Ok(new_default_path)
     }
 
-    pub fn to_api_format(&self, current_speaker: &Role) -> HashMap<String, String> {
+    /// 按 OpenAI 格式转换为请求消息
+    /// Convert into a request message in OpenAI format
+    pub fn to_api_format(&self, current_speaker: &Role) -> ApiMessage<'_> {
+        self.to_api_format_for(current_speaker, "openai")
+    }
+
+    /// 按提供商转换为请求消息，提供商不支持的内容类型以文本描述代替
+    /// Convert into a request message for a provider, content kinds the provider does not support are described as text
+    ///
+    /// # 参数 (Parameters)
+    /// * `current_speaker` - 当前发言者，其消息作为 assistant 输出 / The current speaker, whose messages are output as assistant
+    /// * `provider` - 提供商名称，见 [`crate::telemetry::provider_system`] / Provider name, see [`crate::telemetry::provider_system`]
+    pub fn to_api_format_for(&self, current_speaker: &Role, provider: &str) -> ApiMessage<'_> {
+        let rendered = self.content.render(provider);
+
+        // 工具结果无论由谁添加都以 tool 角色发送
+        // Tool results are sent with the tool role whoever added them
+        if rendered.tool_call_id.is_some() {
+            return ApiMessage {
+                role: "tool",
+                content: rendered.content,
+                tool_calls: None,
+                tool_call_id: rendered.tool_call_id,
+                importance: self.importance,
+            };
+        }
+
         // 根据角色和当前发言者确定 API 格式
         // Determine API format based on role and current speaker
-        let (role_str, content) = match &self.role {
-            Role::System => ("system", self.content.clone()),
-            Role::User => ("user", self.content.clone()),
-            Role::Assistant => ("assistant", self.content.clone()),
+        let (role, content) = match &self.role {
+            Role::System => ("system", rendered.content),
+            // 以文本等形式保存的工具结果同样带上调用ID
+            // Tool results stored as text or other content carry their call ID as well
+            Role::Tool { tool_call_id } => {
+                let content = match rendered.content {
+                    ApiContent::Null => ApiContent::Text(self.content.to_text()),
+                    content => content,
+                };
+                return ApiMessage {
+                    role: "tool",
+                    content,
+                    tool_calls: None,
+                    tool_call_id: Some(tool_call_id),
+                    importance: self.importance,
+                };
+            }
+            Role::User => ("user", rendered.content),
+            Role::Assistant => ("assistant", rendered.content),
             Role::Character(c) => {
                 // 判断是否是当前发言者
                 // Check if it's the current speaker
                 if self.role == *current_speaker {
                     // 是发言者：作为 assistant 输出
                     // Is the speaker: output as assistant
-                    ("assistant", self.content.clone())
+                    ("assistant", rendered.content)
                 } else {
                     // 非发言者：添加前缀并作为 user 输出
                     // Not the speaker: add prefix and output as user
-                    let prefixed_content = format!("{} said: {}", c, self.content);
-                    ("user", prefixed_content)
+                    let content = match rendered.content {
+                        ApiContent::Parts(mut parts) => {
+                            parts.insert(0, serde_json::json!({"type": "text", "text": format!("{} said:", c)}));
+                            ApiContent::Parts(parts)
+                        }
+                        ApiContent::Text(text) => ApiContent::Text(Cow::Owned(format!("{} said: {}", c, text))),
+                        ApiContent::Null => {
+                            ApiContent::Text(Cow::Owned(format!("{} said: {}", c, self.content.to_text())))
+                        }
+                    };
+                    ("user", content)
                 }
             }
         };
 
+        // 工具调用只属于发言者自己的消息
+        // Tool calls only belong to the speaker's own messages
+        let tool_calls = rendered.tool_calls.filter(|_| role == "assistant");
+        let content = match (content, &tool_calls) {
+            (ApiContent::Null, None) => ApiContent::Text(self.content.to_text()),
+            (content, _) => content,
+        };
+
         // 创建并返回 API 格式的消息
         // Create and return message in API format
-        HashMap::from([
-            ("role".to_string(), role_str.to_string()),
-            ("content".to_string(), content),
-        ])
+        ApiMessage {
+            role,
+            content,
+            tool_calls,
+            tool_call_id: None,
+            importance: self.importance,
+        }
     }
 }
 
@@ -127,6 +409,17 @@ impl Messages {
 pub struct Session {
     pub message_roots: Vec<Messages>,
     pub default_path: Vec<usize>,
+
+    /// 模型的草稿本笔记，不进入提示词，见 `SingleChat::enable_scratchpad`
+    /// Scratchpad notes of the model, kept out of the prompt, see `SingleChat::enable_scratchpad`
+    #[serde(default, skip_serializing_if = "Scratchpad::is_empty")]
+    pub scratchpad: Scratchpad,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Session {
@@ -134,6 +427,7 @@ impl Session {
         Self {
             message_roots: Vec::new(),
             default_path: Vec::new(),
+            scratchpad: Scratchpad::default(),
         }
     }
 
@@ -152,7 +446,7 @@ impl Session {
         &mut self,
         path: &[usize],
         role: Role,
-        content: String,
+        content: impl Into<Content>,
     ) -> Result<(), MessageError> {
         if path.is_empty() {
             self.message_roots.push(Messages::new(role, content));
@@ -165,29 +459,258 @@ impl Session {
         Ok(())
     }
 
+    /// ID对应消息的索引路径
+    /// Index path of the message with the ID
+    pub fn path_of(&self, id: &str) -> Result<Vec<usize>, MessageError> {
+        self.message_roots
+            .iter()
+            .enumerate()
+            .find_map(|(index, root)| {
+                root.path_of(id).map(|mut path| {
+                    path.insert(0, index);
+                    path
+                })
+            })
+            .ok_or_else(|| MessageError::UnknownId(id.to_string()))
+    }
+
+    pub fn get_node_by_id(&mut self, id: &str) -> Result<&mut Messages, MessageError> {
+        let path = self.path_of(id)?;
+        self.get_node_by_path(&path)
+    }
+
+    /// 以ID对应的消息为父消息添加消息，新消息成为默认路径的末端
+    /// Add a message under the message with the ID, the new message becomes the end of the default path
+    pub fn add_with_parent_id(
+        &mut self,
+        parent_id: &str,
+        role: Role,
+        content: impl Into<Content>,
+    ) -> Result<(), MessageError> {
+        let path = self.path_of(parent_id)?;
+        self.add_with_parent_path(&path, role, content)
+    }
+
+    /// 将默认路径移到ID对应的消息，之后的消息接在它后面
+    /// Move the default path to the message with the ID, later messages follow it
+    pub fn checkout(&mut self, id: &str) -> Result<(), MessageError> {
+        self.default_path = self.path_of(id)?;
+        Ok(())
+    }
+
+    /// 默认路径末端消息的ID，会话为空时返回 None
+    /// ID of the message at the end of the default path, None for an empty session
+    pub fn last_message_id(&self) -> Option<&str> {
+        let (&root, rest) = self.default_path.split_first()?;
+        let mut node = self.message_roots.get(root)?;
+        for &index in rest {
+            node = node.child.get(index)?;
+        }
+        Some(&node.id)
+    }
+
+    /// 默认路径上的消息，从根开始按时间顺序排列；路径失效处之后的部分被忽略
+    /// Messages on the default path in chronological order from the root; anything past an invalid index is ignored
+    pub fn default_path_messages(&self) -> Vec<&Messages> {
+        let mut messages = Vec::with_capacity(self.default_path.len());
+        let mut siblings = &self.message_roots;
+        for &index in &self.default_path {
+            let Some(node) = siblings.get(index) else {
+                break;
+            };
+            messages.push(node);
+            siblings = &node.child;
+        }
+        messages
+    }
+
+    /// 默认路径上所有消息的附件，按消息顺序排列，编号 `#n` 对应第 n 个
+    /// Attachments of every message on the default path in message order, number `#n` being the n-th
+    pub fn attachments(&self) -> Vec<&Attachment> {
+        self.default_path_messages()
+            .into_iter()
+            .flat_map(|message| &message.attachments)
+            .collect()
+    }
+
+    /// 从根到 `path` 所指消息的一条分支的副本，不含其他分支，默认路径指向该消息；消息ID保持不变
+    /// Copy of the branch from the root to the message at `path`, without other branches, its default path ending at
+    /// that message; message IDs are kept
+    pub fn branch_to(&self, path: &[usize]) -> Result<Session, MessageError> {
+        let mut nodes = Vec::with_capacity(path.len());
+        let mut siblings = &self.message_roots;
+        for (depth, &index) in path.iter().enumerate() {
+            let node = siblings.get(index).ok_or_else(|| MessageError::InvalidIndex(index, path[..depth].to_vec()))?;
+            nodes.push(node);
+            siblings = &node.child;
+        }
+        if nodes.is_empty() {
+            return Err(MessageError::InvalidPath);
+        }
+
+        let mut chain: Option<Messages> = None;
+        for node in nodes.into_iter().rev() {
+            chain = Some(Messages {
+                id: node.id.clone(),
+                role: node.role.clone(),
+                content: node.content.clone(),
+                child: chain.into_iter().collect(),
+                metadata: node.metadata.clone(),
+                attachments: node.attachments.clone(),
+                importance: node.importance,
+                created_at_ms: node.created_at_ms,
+            });
+        }
+        Ok(Session {
+            message_roots: chain.into_iter().collect(),
+            default_path: vec![0; path.len()],
+            scratchpad: self.scratchpad.clone(),
+        })
+    }
+
+    pub fn last_message_mut(&mut self) -> Result<&mut Messages, MessageError> {
+        let path = self.default_path.clone();
+        self.get_node_by_path(&path)
+    }
+
+    /// 移除默认路径末端刚加入的消息，默认路径退回其父消息；只能移除没有后续消息、且是最后一个兄弟的消息
+    /// Remove the message just added at the end of the default path, the default path moving back to its parent;
+    /// only a message without follow-ups that is the last of its siblings can be removed
+    pub fn remove_last_message(&mut self) -> Result<Messages, MessageError> {
+        let (&index, parent) = self.default_path.split_last().ok_or(MessageError::InvalidPath)?;
+        let parent = parent.to_vec();
+        let siblings = match parent.is_empty() {
+            true => &mut self.message_roots,
+            false => &mut self.get_node_by_path(&parent)?.child,
+        };
+        match siblings.get(index) {
+            None => return Err(MessageError::InvalidIndex(index, parent)),
+            Some(message) if !message.child.is_empty() || index + 1 != siblings.len() => {
+                return Err(MessageError::UnsupportedOperation(format!("Message {} is not the latest", message.id)));
+            }
+            Some(_) => {}
+        }
+        let message = siblings.remove(index);
+        self.default_path = parent;
+        Ok(message)
+    }
+
     pub fn add_with_default_path(
         &mut self,
         role: Role,
-        content: String,
+        content: impl Into<Content>,
     ) -> Result<(), MessageError> {
         self.add_with_parent_path(&self.default_path.clone(), role, content)
     }
 
-    pub fn assemble_context(
-        &mut self,
+    /// 由 OpenAI 格式的消息列表创建线性会话；工具调用与工具结果保留为结构化内容，再次组装请求时按原格式发出
+    /// Build a linear session from a list of messages in OpenAI format; tool calls and tool results are kept as
+    /// structured content and sent in the same format when requests are assembled again
+    pub fn from_api_messages(messages: &[serde_json::Value]) -> Result<Self, MessageError> {
+        let mut session = Self::new();
+        for (index, message) in messages.iter().enumerate() {
+            let invalid = |reason: &str| MessageError::InvalidApiMessage(index, reason.to_string());
+            let role = message["role"].as_str().ok_or_else(|| invalid("missing role"))?;
+            let content = api_content(&message["content"]);
+            let (role, content) = match (role, message["tool_calls"].as_array()) {
+                ("tool", _) => {
+                    let call_id = message["tool_call_id"].as_str().ok_or_else(|| invalid("missing tool_call_id"))?;
+                    (Role::tool(call_id), Content::tool_result(call_id, &content.to_text()))
+                }
+                ("assistant", Some(calls)) if !calls.is_empty() => {
+                    let mut parts: Vec<Content> = content
+                        .parts()
+                        .iter()
+                        .filter(|part| !matches!(part, Content::Text(text) if text.is_empty()))
+                        .cloned()
+                        .collect();
+                    for call in calls {
+                        let id = call["id"].as_str().ok_or_else(|| invalid("tool call without id"))?;
+                        let name = call["function"]["name"].as_str().ok_or_else(|| invalid("tool call without name"))?;
+                        let arguments = match &call["function"]["arguments"] {
+                            serde_json::Value::String(arguments) => arguments.clone(),
+                            arguments => arguments.to_string(),
+                        };
+                        parts.push(Content::tool_call(id, name, &arguments));
+                    }
+                    (Role::Assistant, Content::Parts(parts))
+                }
+                (role, _) => (Role::from(role), content),
+            };
+            session.add_with_default_path(role, content)?;
+        }
+        Ok(session)
+    }
+
+    pub fn assemble_context<'a>(
+        &'a self,
         end_path: &[usize],
         current_speaker: &Role,
-    ) -> Result<Vec<HashMap<String, String>>, MessageError> {
-        let mut node = self.get_node_by_path([end_path[0]].as_ref())?;
-        let mut messages_vec = vec![node.to_api_format(current_speaker)];
-        info!("node: {:?}", node);
+    ) -> Result<Vec<ApiMessage<'a>>, MessageError> {
+        self.assemble_context_for(end_path, current_speaker, "openai")
+    }
 
-        // 将for_each改为传统for循环
-        for &idx in end_path[1..].iter() {
-            node = &mut node.child[idx];
-            messages_vec.push(node.to_api_format(current_speaker));
+    /// 按提供商组装从根到 `end_path` 的请求消息
+    /// Assemble the request messages from the root to `end_path` for a provider
+    pub fn assemble_context_for<'a>(
+        &'a self,
+        end_path: &[usize],
+        current_speaker: &Role,
+        provider: &str,
+    ) -> Result<Vec<ApiMessage<'a>>, MessageError> {
+        let (&root, rest) = end_path.split_first().ok_or(MessageError::InvalidPath)?;
+        let mut node = self.message_roots.get(root).ok_or(MessageError::InvalidPath)?;
+        let mut messages_vec = Vec::with_capacity(end_path.len());
+        messages_vec.push(node.to_api_format_for(current_speaker, provider));
+        info!("node: {}", redact(&format!("{:?}", node)));
+
+        // 工具结果中需要另行发送的图片，在这一轮工具结果之后作为一条用户消息发送
+        // Tool result images that must be sent separately, as one user message after the round of tool results
+        let mut images = node.content.deferred_tool_images(provider);
+        for &idx in rest {
+            node = node.child.get(idx).ok_or(MessageError::InvalidIndex(idx, end_path.to_vec()))?;
+            let message = node.to_api_format_for(current_speaker, provider);
+            if message.role != "tool" && !images.is_empty() {
+                messages_vec.push(tool_images_message(std::mem::take(&mut images)));
+            }
+            images.extend(node.content.deferred_tool_images(provider));
+            messages_vec.push(message);
+        }
+        if !images.is_empty() {
+            messages_vec.push(tool_images_message(images));
         }
 
         Ok(messages_vec)
     }
 }
+
+/// 携带工具结果图片的用户消息
+/// User message carrying tool result images
+fn tool_images_message<'a>(parts: Vec<serde_json::Value>) -> ApiMessage<'a> {
+    ApiMessage {
+        role: "user",
+        content: ApiContent::Parts(parts),
+        tool_calls: None,
+        tool_call_id: None,
+        importance: Importance::default(),
+    }
+}
+
+/// OpenAI 格式的消息内容：文本、图片段组成的列表或空
+/// Message content in OpenAI format: text, a list of text and image parts, or null
+fn api_content(content: &serde_json::Value) -> Content {
+    match content {
+        serde_json::Value::String(text) => Content::from(text.as_str()),
+        serde_json::Value::Array(parts) => Content::Parts(
+            parts
+                .iter()
+                .filter_map(|part| match part["type"].as_str() {
+                    Some("text") => part["text"].as_str().map(Content::from),
+                    Some("image_url") => part["image_url"]["url"].as_str().map(Content::image),
+                    _ => None,
+                })
+                .collect(),
+        ),
+        _ => Content::from(""),
+    }
+}
@@ -0,0 +1,137 @@
+// 标准库
+use std::fmt::Debug;
+use std::sync::Arc;
+
+// 并发和同步原语
+use dashmap::DashMap;
+
+// 错误处理
+use error_stack::Result;
+
+// 异步
+use futures::future::BoxFuture;
+
+// 哈希
+use sha2::{Digest, Sha256};
+
+// 项目内部模块
+use crate::chat::chat_base::ChatError;
+use crate::chat::chat_single::SingleChat;
+use crate::chat::tenant::Tenant;
+
+/// 包裹 `SingleChat::get_answer` 的中间件，可改写输入、直接返回回答、检查或改写回答，用于缓存、日志、预算检查等
+/// 横切关注点，无需修改对话本身
+/// Middleware wrapping `SingleChat::get_answer`, which may rewrite the input, answer directly, or inspect and rewrite
+/// the answer, for cross-cutting concerns such as caching, logging and budget checks without changing the chat
+/// itself
+///
+/// 中间件按添加顺序由外向内执行，调用 `next.run` 交给下一层，最内层向模型提问。不调用 `next` 直接返回的回答不经过
+/// 模型，也不写入会话历史，除非中间件自己写入。
+/// Middlewares run from the outside in, in the order they were added, handing over to the next layer with
+/// `next.run`; the innermost layer asks the model. Answers returned without calling `next` skip the model and are
+/// not written to the session history unless the middleware writes them.
+pub trait AnswerMiddleware: Send + Sync {
+    fn call<'a>(&'a self, input: String, next: Next<'a>) -> BoxFuture<'a, Result<String, ChatError>>;
+}
+
+/// 调用链中余下的部分：之后的中间件，最后是模型
+/// The rest of the chain: the middlewares after this one, then the model
+pub struct Next<'a> {
+    middlewares: &'a [Arc<dyn AnswerMiddleware>],
+    chat: &'a mut SingleChat,
+}
+
+impl<'a> Next<'a> {
+    pub(crate) fn new(middlewares: &'a [Arc<dyn AnswerMiddleware>], chat: &'a mut SingleChat) -> Self {
+        Self { middlewares, chat }
+    }
+
+    pub fn chat(&self) -> &SingleChat {
+        self.chat
+    }
+
+    pub fn chat_mut(&mut self) -> &mut SingleChat {
+        self.chat
+    }
+
+    /// 把输入交给下一层，返回其回答
+    /// Hand the input to the next layer and return its answer
+    pub fn run(self, input: String) -> BoxFuture<'a, Result<String, ChatError>> {
+        match self.middlewares.split_first() {
+            Some((middleware, rest)) => middleware.call(input, Next::new(rest, self.chat)),
+            None => Box::pin(async move { self.chat.answer(&input).await }),
+        }
+    }
+}
+
+/// 改写输入的中间件，如补全缩写、统一术语
+/// Middleware rewriting the input, such as expanding abbreviations or unifying terms
+pub struct RewriteInput<F>(pub F);
+
+impl<F> AnswerMiddleware for RewriteInput<F>
+where
+    F: Fn(&str) -> String + Send + Sync,
+{
+    fn call<'a>(&'a self, input: String, next: Next<'a>) -> BoxFuture<'a, Result<String, ChatError>> {
+        next.run((self.0)(&input))
+    }
+}
+
+/// 回答缓存：以发给提供商的完整请求体与租户为键缓存模型的响应，命中时不请求模型；克隆共享同一份缓存
+/// Answer cache: caches model responses keyed on the full request body sent to the provider plus the tenant, a hit
+/// skips the model; clones share one cache
+///
+/// 通过 `SingleChat::set_answer_cache` 设置，对所有非流式的提问方法生效。键包含渲染后的全部消息、系统提示词、
+/// 工具与生成参数，只有上下文完全相同时才会命中，不同租户之间从不共用；命中的响应与新响应一样经过后处理、
+/// 规则链与输出审核，并按会话中保存的脱敏形式写入历史。租户的预算与频率上限照常检查，命中不计用量。
+/// Set through `SingleChat::set_answer_cache`, it applies to every non-streaming way of asking. The key covers
+/// all the rendered messages, the system prompt, the tools and the generation parameters, so only identical
+/// contexts hit and tenants never share entries; a cached response goes through post-processing, guardrails and
+/// output moderation like a fresh one and enters the history in the scrubbed form the session keeps. The tenant's
+/// budget and rate limit are still checked, and hits cost no usage.
+#[derive(Clone, Default)]
+pub struct AnswerCache {
+    responses: Arc<DashMap<String, serde_json::Value>>,
+}
+
+impl Debug for AnswerCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AnswerCache").field("responses", &self.responses.len()).finish()
+    }
+}
+
+impl AnswerCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.responses.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.responses.is_empty()
+    }
+
+    pub fn clear(&self) {
+        self.responses.clear();
+    }
+
+    /// 租户与请求体的缓存键
+    /// Cache key of the tenant and the request body
+    pub(crate) fn key(tenant: Option<&Tenant>, request_body: &serde_json::Value) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(tenant.map_or("", |tenant| tenant.id.as_str()).as_bytes());
+        hasher.update([0]);
+        hasher.update(request_body.to_string().as_bytes());
+        format!("{:x}", hasher.finalize())
+    }
+
+    pub(crate) fn get(&self, key: &str) -> Option<serde_json::Value> {
+        self.responses.get(key).map(|response| response.clone())
+    }
+
+    pub(crate) fn insert(&self, key: String, response: serde_json::Value) {
+        self.responses.insert(key, response);
+    }
+}
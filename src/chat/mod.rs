@@ -1,5 +1,68 @@
 pub mod message;
+pub mod agent;
+pub mod agent_spec;
+pub mod attachment;
+pub mod content;
+#[cfg(feature = "encryption")]
+pub mod encryption;
+pub mod image;
 pub mod chat_base;
 pub mod chat_single;
 pub mod chat_multi;
 pub mod chat_tool;
+pub mod params;
+pub mod event;
+pub mod stream;
+pub mod speculation;
+pub mod subagent;
+pub mod store;
+#[cfg(feature = "sqlite")]
+pub mod sqlite_store;
+pub mod retriever;
+pub mod shadow;
+pub mod artifacts;
+pub mod broadcast;
+pub mod builder;
+pub mod chat_session;
+pub mod checkpoint;
+pub mod citation;
+pub mod compact;
+pub mod confidence;
+pub mod context_provider;
+pub mod dialogue_state;
+pub mod compat;
+pub mod completion;
+pub mod export;
+pub mod extraction;
+pub mod faithfulness;
+pub mod handle;
+pub mod history;
+pub mod hot_reload;
+pub mod moderation;
+pub mod pii;
+pub mod preview;
+pub mod guardrail;
+pub mod injection;
+pub mod interceptor;
+pub mod json_lines;
+pub mod language;
+pub mod mcp;
+pub mod ocr;
+pub mod middleware;
+pub mod openapi;
+pub mod output_cap;
+pub mod persona;
+pub mod postprocess;
+pub mod queue;
+pub mod refusal;
+pub mod retention;
+pub mod analytics;
+pub mod scratchpad;
+pub mod summary;
+pub mod tenant;
+pub mod tool_source;
+pub mod transaction;
+pub mod transcript;
+pub mod transform;
+pub mod trigger;
+pub mod vision;
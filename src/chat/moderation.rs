@@ -0,0 +1,284 @@
+// 标准库
+use std::fmt::{Debug, Display};
+use std::sync::Arc;
+
+// 错误处理
+use error_stack::{Report, Result, ResultExt};
+
+// 异步
+use futures::future::BoxFuture;
+
+// 网络通信
+use reqwest::Client;
+
+// 项目内部模块
+use crate::chat::chat_base::ChatError;
+use crate::config::Config;
+use crate::error::ErrorBody;
+use crate::utils::common::redact::redact;
+
+/// 审核发生的阶段
+/// Stage at which moderation happens
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ModerationStage {
+    /// 用户输入发送之前
+    /// Before the user input is sent
+    Input,
+
+    /// 模型回答返回之后
+    /// After the model answer is received
+    Output,
+}
+
+impl Display for ModerationStage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Input => write!(f, "input"),
+            Self::Output => write!(f, "output"),
+        }
+    }
+}
+
+/// 内容被标记后的处理方式
+/// What to do once content is flagged
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ModerationAction {
+    /// 以 `ChatError::Moderated` 拒绝，输入不写入会话，回答不返回
+    /// Reject with `ChatError::Moderated`, the input is not written to the session and the answer is not returned
+    Block,
+
+    /// 以占位文本替换被标记的内容，并在消息元数据中记录类别
+    /// Replace the flagged content with a placeholder and record the categories in the message metadata
+    Redact,
+
+    /// 原样放行，只在消息元数据中记录类别
+    /// Let the content through unchanged, only recording the categories in the message metadata
+    Annotate,
+}
+
+/// 审核结果
+/// Moderation result
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ModerationResult {
+    pub flagged: bool,
+    /// 被标记的类别，如 `violence`、`harassment`
+    /// Flagged categories, such as `violence` or `harassment`
+    pub categories: Vec<String>,
+}
+
+impl ModerationResult {
+    pub fn clean() -> Self {
+        Self::default()
+    }
+
+    pub fn flagged(categories: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self {
+            flagged: true,
+            categories: categories.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+/// 内容分类器，判断文本是否需要审核处理
+/// Content classifier, deciding whether a text needs moderation
+pub trait Moderator: Send + Sync {
+    fn classify<'a>(&'a self, text: &'a str) -> BoxFuture<'a, Result<ModerationResult, ChatError>>;
+}
+
+/// 同步函数可直接作为本地分类器
+/// Synchronous functions can be used directly as local classifiers
+impl<F> Moderator for F
+where
+    F: Fn(&str) -> ModerationResult + Send + Sync,
+{
+    fn classify<'a>(&'a self, text: &'a str) -> BoxFuture<'a, Result<ModerationResult, ChatError>> {
+        let result = self(text);
+        Box::pin(async move { Ok(result) })
+    }
+}
+
+/// 调用 OpenAI 兼容的 `/moderations` 接口的分类器
+/// Classifier calling an OpenAI-compatible `/moderations` endpoint
+#[derive(Clone)]
+pub struct OpenAiModerator {
+    url: String,
+    api_key: String,
+    client: Client,
+    model: String,
+}
+
+impl Debug for OpenAiModerator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OpenAiModerator")
+            .field("url", &self.url)
+            .field("model", &self.model)
+            .finish_non_exhaustive()
+    }
+}
+
+impl OpenAiModerator {
+    /// 使用配置中API的地址与密钥，接口地址由对话补全地址推出
+    /// Use the address and key of a configured API, the endpoint is derived from the chat completion address
+    pub fn from_api(api_name: &str) -> Result<Self, ChatError> {
+        let api_info = Config::get_api_info_with_name(api_name.to_string())
+            .change_context(ChatError::InvalidConfig)
+            .attach_printable_lazy(|| format!("Unknown API: {}", api_name))?;
+        Ok(Self {
+            url: moderations_url(&api_info.base_url),
+            api_key: api_info.api_key,
+            client: api_info.client,
+            model: "omni-moderation-latest".to_string(),
+        })
+    }
+
+    /// 审核模型，默认为 `omni-moderation-latest`
+    /// Moderation model, `omni-moderation-latest` by default
+    pub fn model(mut self, model: &str) -> Self {
+        self.model = model.to_string();
+        self
+    }
+}
+
+/// 将对话补全地址转换为同一服务的审核地址
+/// Turn a chat completion address into the moderation address of the same service
+fn moderations_url(base_url: &str) -> String {
+    match base_url.strip_suffix("/chat/completions") {
+        Some(prefix) => format!("{}/moderations", prefix),
+        None => format!("{}/moderations", base_url.trim_end_matches('/')),
+    }
+}
+
+impl Moderator for OpenAiModerator {
+    fn classify<'a>(&'a self, text: &'a str) -> BoxFuture<'a, Result<ModerationResult, ChatError>> {
+        Box::pin(async move {
+            let response = self
+                .client
+                .post(&self.url)
+                .bearer_auth(&self.api_key)
+                .json(&serde_json::json!({"model": self.model, "input": text}))
+                .send()
+                .await
+                .change_context(ChatError::UnknownError)
+                .attach_printable("Moderation request failed")?;
+
+            let status = response.status();
+            if !status.is_success() {
+                let body = response.text().await.unwrap_or_default();
+                return Err(Report::new(ChatError::HttpError(status.as_u16()))
+                    .attach(ErrorBody(redact(&body)))
+                    .attach_printable("Moderation endpoint returned an error"));
+            }
+
+            let parsed: serde_json::Value = response
+                .json()
+                .await
+                .change_context(ChatError::ParseResponseError)
+                .attach_printable("Failed to parse moderation response")?;
+            let result = &parsed["results"][0];
+            let categories = result["categories"]
+                .as_object()
+                .map(|categories| {
+                    categories
+                        .iter()
+                        .filter(|(_, flagged)| flagged.as_bool() == Some(true))
+                        .map(|(category, _)| category.clone())
+                        .collect()
+                })
+                .unwrap_or_default();
+            Ok(ModerationResult {
+                flagged: result["flagged"].as_bool().unwrap_or(false),
+                categories,
+            })
+        })
+    }
+}
+
+/// 对话的审核设置：分类器与各阶段的处理方式
+/// Moderation settings of a chat: the classifier and the action of each stage
+#[derive(Clone)]
+pub struct Moderation {
+    moderator: Arc<dyn Moderator>,
+    /// 输入的处理方式，为 None 时不审核输入
+    /// Action for inputs, inputs are not screened if None
+    pub input: Option<ModerationAction>,
+    /// 回答的处理方式，为 None 时不审核回答
+    /// Action for answers, answers are not screened if None
+    pub output: Option<ModerationAction>,
+}
+
+impl Debug for Moderation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Moderation")
+            .field("input", &self.input)
+            .field("output", &self.output)
+            .finish_non_exhaustive()
+    }
+}
+
+/// 审核后的文本
+/// Text after moderation
+#[derive(Debug, Default)]
+pub struct Screened {
+    /// 替换原文的文本，为 None 时使用原文
+    /// Text replacing the original, the original is used if None
+    pub replacement: Option<String>,
+    /// 记入消息元数据的类别，未被标记时为空
+    /// Categories recorded in the message metadata, empty if nothing was flagged
+    pub categories: Vec<String>,
+}
+
+impl Moderation {
+    /// 输入与回答都审核，被标记时拒绝
+    /// Screen both inputs and answers, rejecting flagged content
+    pub fn new(moderator: impl Moderator + 'static) -> Self {
+        Self {
+            moderator: Arc::new(moderator),
+            input: Some(ModerationAction::Block),
+            output: Some(ModerationAction::Block),
+        }
+    }
+
+    pub fn on_input(mut self, action: Option<ModerationAction>) -> Self {
+        self.input = action;
+        self
+    }
+
+    pub fn on_output(mut self, action: Option<ModerationAction>) -> Self {
+        self.output = action;
+        self
+    }
+
+    /// 按阶段的处理方式审核文本
+    /// Screen a text with the action of the stage
+    pub async fn screen(&self, stage: ModerationStage, text: &str) -> Result<Screened, ChatError> {
+        let action = match stage {
+            ModerationStage::Input => self.input,
+            ModerationStage::Output => self.output,
+        };
+        let Some(action) = action else {
+            return Ok(Screened::default());
+        };
+
+        let result = self
+            .moderator
+            .classify(text)
+            .await
+            .attach_printable_lazy(|| format!("Failed to moderate {}", stage))?;
+        if !result.flagged {
+            return Ok(Screened::default());
+        }
+
+        match action {
+            ModerationAction::Block => Err(Report::new(ChatError::Moderated(stage))
+                .attach_printable(format!("Flagged categories: {}", result.categories.join(", ")))),
+            ModerationAction::Redact => Ok(Screened {
+                replacement: Some(format!("[内容已屏蔽 / Content removed by moderation: {}]", result.categories.join(", "))),
+                categories: result.categories,
+            }),
+            ModerationAction::Annotate => Ok(Screened {
+                replacement: None,
+                categories: result.categories,
+            }),
+        }
+    }
+}
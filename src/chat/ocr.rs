@@ -0,0 +1,413 @@
+// 标准库
+use std::path::{Path, PathBuf};
+
+// 错误处理
+use error_stack::{Report, Result, ResultExt};
+
+// 异步
+use futures::future::BoxFuture;
+
+// 数据序列化
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+// 项目内部模块
+use crate::chat::chat_base::{BaseChat, ChatError};
+use crate::chat::content::Content;
+use crate::chat::image::ImagePart;
+use crate::chat::message::Role;
+use crate::chat::tool_source::{ToolSource, function_tool};
+use crate::config::prompts::PromptKey;
+use crate::config::{CFG, Config};
+use crate::schema::tool_schema::ChatToolSchemaError;
+
+/// 文字识别工具的名称
+/// Name of the text recognition tool
+pub const OCR_TOOL: &str = "ocr_image";
+
+/// 文字块的版面类型
+/// Layout kind of a text block
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlockKind {
+    Heading,
+    #[default]
+    Paragraph,
+    List,
+
+    /// 表格，文字为 Markdown 表格
+    /// Table, its text is a Markdown table
+    Table,
+    Caption,
+    #[serde(other)]
+    Other,
+}
+
+/// 按阅读顺序识别出的一块文字
+/// One block of recognized text, in reading order
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OcrBlock {
+    #[serde(default)]
+    pub kind: BlockKind,
+
+    pub text: String,
+
+    /// 块在图片中的位置 `[left, top, width, height]`，单位为像素；视觉模型不提供位置
+    /// Position of the block in the image as `[left, top, width, height]` in pixels; vision models give no position
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bbox: Option<[u32; 4]>,
+}
+
+/// 文字识别的结果：全文与带版面提示的文字块
+/// Result of text recognition: the full text and the blocks with their layout hints
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OcrText {
+    /// 各块文字按阅读顺序以空行连接
+    /// Text of every block in reading order, joined by blank lines
+    pub text: String,
+
+    pub blocks: Vec<OcrBlock>,
+
+    /// 识别所用的引擎：视觉模型的API名称，或 `tesseract`
+    /// Engine that did the recognition: the API name of the vision model, or `tesseract`
+    pub engine: String,
+}
+
+impl OcrText {
+    fn new(blocks: Vec<OcrBlock>, engine: &str) -> Self {
+        let text = blocks.iter().map(|block| block.text.as_str()).collect::<Vec<_>>().join("\n\n");
+        Self {
+            text,
+            blocks,
+            engine: engine.to_string(),
+        }
+    }
+}
+
+/// 识别引擎
+/// Recognition engine
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Engine {
+    /// 视觉模型，为 None 时使用第一个支持视觉的已配置模型
+    /// Vision model, the first configured model supporting vision if None
+    Model(Option<String>),
+
+    /// 本地的 tesseract 命令
+    /// The local tesseract command
+    #[cfg(feature = "tesseract")]
+    Tesseract,
+}
+
+/// 内置的文字识别工具 `ocr_image`，把图片交给支持视觉的模型（或开启 `tesseract` 特性后的本地 tesseract）识别，
+/// 返回全文与带版面提示的文字块，使处理文档的智能体开箱即用
+/// Built-in text recognition tool `ocr_image`, which hands an image to a vision-capable model (or to a local
+/// tesseract with the `tesseract` feature) and returns the full text with layout-hinted blocks, so document-processing
+/// agents work out of the box
+///
+/// 图片参数可以是 http(s) 网址或 base64 data URL；设置了 `files` 目录时，也可以是该目录中的文件路径。
+/// The image argument may be an http(s) URL or a base64 data URL; with a `files` directory set, it may also be the
+/// path of a file in that directory.
+///
+/// ```ignore
+/// chat.set_tool_sources(vec![Arc::new(OcrTools::new().files("./scans"))]).await?;
+/// ```
+#[derive(Clone, Debug)]
+pub struct OcrTools {
+    namespace: Option<String>,
+    engine: Engine,
+    language: Option<String>,
+    files: Option<PathBuf>,
+}
+
+impl Default for OcrTools {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OcrTools {
+    /// 使用第一个支持视觉的已配置模型，见 `ModelMetadata::supports_vision`
+    /// Use the first configured model supporting vision, see `ModelMetadata::supports_vision`
+    pub fn new() -> Self {
+        Self {
+            namespace: None,
+            engine: Engine::Model(None),
+            language: None,
+            files: None,
+        }
+    }
+
+    /// 使用指定API的模型识别
+    /// Recognize with the model of the given API
+    pub fn with_api(api: &str) -> Self {
+        Self {
+            engine: Engine::Model(Some(api.to_string())),
+            ..Self::new()
+        }
+    }
+
+    /// 使用本地的 tesseract 命令识别，不请求模型；只支持内嵌图片
+    /// Recognize with the local tesseract command without asking a model; only inline images are supported
+    #[cfg(feature = "tesseract")]
+    pub fn tesseract() -> Self {
+        Self {
+            engine: Engine::Tesseract,
+            ..Self::new()
+        }
+    }
+
+    pub fn namespace(mut self, namespace: &str) -> Self {
+        self.namespace = Some(namespace.to_string());
+        self
+    }
+
+    /// 图片文字的语言，视觉模型作为提示使用，tesseract 作为 `-l` 参数使用（如 `chi_sim+eng`）
+    /// Language of the text in images, a hint for vision models and the `-l` argument of tesseract (such as
+    /// `chi_sim+eng`)
+    pub fn language(mut self, language: &str) -> Self {
+        self.language = Some(language.to_string());
+        self
+    }
+
+    /// 允许以文件路径传入图片，路径相对于该目录解析，且不能指向目录之外
+    /// Allow images given as file paths, resolved relative to this directory and not pointing outside it
+    pub fn files(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.files = Some(dir.into());
+        self
+    }
+
+    /// 识别图片中的文字
+    /// Recognize the text in an image
+    pub async fn recognize(&self, image: ImagePart) -> Result<OcrText, ChatError> {
+        match &self.engine {
+            Engine::Model(api) => {
+                let api = match api {
+                    Some(api) => api.clone(),
+                    None => vision_api().ok_or_else(|| {
+                        Report::new(ChatError::InvalidConfig).attach_printable("No configured model supports vision")
+                    })?,
+                };
+                let blocks = self.recognize_with_model(&api, image).await?;
+                Ok(OcrText::new(blocks, &api))
+            }
+            #[cfg(feature = "tesseract")]
+            Engine::Tesseract => {
+                let language = self.language.clone();
+                let blocks = tokio::task::spawn_blocking(move || tesseract::recognize(image, language.as_deref()))
+                    .await
+                    .map_err(|e| Report::new(ChatError::ImageError(e.to_string())))??;
+                Ok(OcrText::new(blocks, "tesseract"))
+            }
+        }
+    }
+
+    async fn recognize_with_model(&self, api: &str, image: ImagePart) -> Result<Vec<OcrBlock>, ChatError> {
+        let mut chat = BaseChat::try_new_with_api_name(api, "", false)?;
+        chat.add_message(Role::System, &Config::capability_prompt(&PromptKey::OcrInstruction))?;
+        let request = match &self.language {
+            Some(language) => Config::render_prompt(&PromptKey::OcrRequestWithLanguage, &[language]),
+            None => Config::capability_prompt(&PromptKey::OcrRequest),
+        };
+        chat.add_content(Role::User, Content::Parts(vec![Content::from(request), image.into()]))?;
+        let request_body = chat.build_request_body(&chat.session.default_path.clone(), &Role::User)?;
+        let answer = chat.get_content(request_body).await?;
+        Ok(parse_blocks(&answer))
+    }
+
+    /// 按工具参数读取图片
+    /// Read the image given by the tool argument
+    fn image(&self, image: &str) -> Result<ImagePart, ChatError> {
+        if image.starts_with("http://") || image.starts_with("https://") || image.starts_with("data:") {
+            return ImagePart::from_url(image);
+        }
+        let Some(dir) = &self.files else {
+            return Err(Report::new(ChatError::ImageError(image.to_string()))
+                .attach_printable("File paths are not allowed, set a files directory first"));
+        };
+        let path = inside(dir, Path::new(image))?;
+        ImagePart::from_path(path)
+    }
+}
+
+impl ToolSource for OcrTools {
+    fn namespace(&self) -> Option<&str> {
+        self.namespace.as_deref()
+    }
+
+    fn list_tools(&self) -> BoxFuture<'_, Result<Vec<Value>, ChatError>> {
+        Box::pin(async move {
+            let image = match self.files {
+                Some(_) => Config::capability_prompt(&PromptKey::OcrImageArgumentWithFiles),
+                None => Config::capability_prompt(&PromptKey::OcrImageArgument),
+            };
+            let parameters = json!({
+                "type": "object",
+                "properties": {"image": {"type": "string", "description": image}},
+                "required": ["image"],
+            });
+            let description = Config::capability_prompt(&PromptKey::OcrToolDescription);
+            Ok(vec![function_tool(OCR_TOOL, Some(&description), Some(&parameters))])
+        })
+    }
+
+    fn call_tool<'a>(&'a self, name: &'a str, arguments: Value) -> BoxFuture<'a, Result<Value, ChatToolSchemaError>> {
+        Box::pin(async move {
+            let call_error = || ChatToolSchemaError::FunctionCallError;
+            if name != OCR_TOOL {
+                return Err(Report::new(call_error()).attach_printable(format!("No tool named {}", name)));
+            }
+            let image = arguments["image"].as_str().ok_or_else(|| {
+                Report::new(ChatToolSchemaError::ParamsParseError(name.to_string(), arguments.to_string()))
+            })?;
+            let image = self.image(image).change_context_lazy(call_error)?;
+            let text = self.recognize(image).await.change_context_lazy(call_error)?;
+            serde_json::to_value(text).change_context_lazy(|| ChatToolSchemaError::ResultParseError(name.to_string()))
+        })
+    }
+}
+
+/// 第一个模型支持视觉的已配置API，按名称排序
+/// The first configured API whose model supports vision, sorted by name
+pub(crate) fn vision_api() -> Option<String> {
+    let mut apis: Vec<(String, String)> =
+        CFG.current().api_info.iter().map(|entry| (entry.key().0.clone(), entry.value().model.clone())).collect();
+    apis.sort();
+    apis.into_iter()
+        .find(|(_, model)| CFG.current().model_metadata.get(model).is_some_and(|metadata| metadata.supports_vision))
+        .map(|(api, _)| api)
+}
+
+/// 相对于目录解析路径，解析后位于目录之外时返回错误
+/// Resolve a path relative to the directory, failing if it ends up outside the directory
+fn inside(dir: &Path, path: &Path) -> Result<PathBuf, ChatError> {
+    let outside = || ChatError::ImageError(path.display().to_string());
+    let dir = dir.canonicalize().change_context_lazy(outside)?;
+    let resolved = dir.join(path).canonicalize().change_context_lazy(outside)?;
+    if !resolved.starts_with(&dir) {
+        return Err(Report::new(outside()).attach_printable("Path points outside the files directory"));
+    }
+    Ok(resolved)
+}
+
+/// 解析模型输出的文字块，输出不是预期的 JSON 时整体作为一个段落
+/// Parse the blocks output by the model, the whole output becomes one paragraph if it is not the expected JSON
+fn parse_blocks(answer: &str) -> Vec<OcrBlock> {
+    #[derive(Deserialize)]
+    struct Blocks {
+        blocks: Vec<OcrBlock>,
+    }
+
+    let trimmed = answer.trim();
+    let json = trimmed
+        .strip_prefix("```json")
+        .or_else(|| trimmed.strip_prefix("```"))
+        .and_then(|rest| rest.strip_suffix("```"))
+        .unwrap_or(trimmed);
+    match serde_json::from_str::<Blocks>(json) {
+        Ok(Blocks { blocks }) => blocks.into_iter().filter(|block| !block.text.trim().is_empty()).collect(),
+        Err(_) if trimmed.is_empty() => Vec::new(),
+        Err(_) => vec![OcrBlock {
+            kind: BlockKind::Paragraph,
+            text: trimmed.to_string(),
+            bbox: None,
+        }],
+    }
+}
+
+#[cfg(feature = "tesseract")]
+mod tesseract {
+    // 标准库
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    // 错误处理
+    use error_stack::{Report, Result};
+
+    // 项目内部模块
+    use super::{BlockKind, OcrBlock};
+    use crate::chat::chat_base::ChatError;
+    use crate::chat::image::ImagePart;
+
+    fn tesseract_error(message: impl Into<String>) -> Report<ChatError> {
+        Report::new(ChatError::ImageError(message.into()))
+    }
+
+    /// 以 TSV 格式运行 tesseract，图片从标准输入传入
+    /// Run tesseract with TSV output, passing the image on standard input
+    pub(super) fn recognize(image: ImagePart, language: Option<&str>) -> Result<Vec<OcrBlock>, ChatError> {
+        let ImagePart::Data { bytes, .. } = image else {
+            return Err(tesseract_error("tesseract only reads inline images, not URLs"));
+        };
+        let mut command = Command::new("tesseract");
+        command.args(["stdin", "stdout"]);
+        if let Some(language) = language {
+            command.args(["-l", language]);
+        }
+        let mut child = command
+            .arg("tsv")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| tesseract_error(format!("failed to run tesseract: {}", e)))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(&bytes).map_err(|e| tesseract_error(format!("failed to pass the image: {}", e)))?;
+        }
+        let output = child.wait_with_output().map_err(|e| tesseract_error(e.to_string()))?;
+        if !output.status.success() {
+            return Err(tesseract_error(format!(
+                "tesseract failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        Ok(parse_tsv(&String::from_utf8_lossy(&output.stdout)))
+    }
+
+    /// 把 TSV 中的单词按段落合并为文字块，块内按行换行，位置为所含单词的外接矩形
+    /// Merge the words of the TSV into one block per paragraph, with a line break per line and the bounding box of
+    /// its words as position
+    pub(super) fn parse_tsv(tsv: &str) -> Vec<OcrBlock> {
+        let mut blocks: Vec<((u32, u32, u32), u32, OcrBlock)> = Vec::new();
+        for line in tsv.lines().skip(1) {
+            let fields: Vec<&str> = line.splitn(12, '\t').collect();
+            let [level, page, block, paragraph, line_number, _, left, top, width, height, _, text] = fields[..]
+            else {
+                continue;
+            };
+            let text = text.trim();
+            if level != "5" || text.is_empty() {
+                continue;
+            }
+            let number = |field: &str| field.parse::<u32>().unwrap_or(0);
+            let key = (number(page), number(block), number(paragraph));
+            let (left, top) = (number(left), number(top));
+            let (right, bottom) = (left + number(width), top + number(height));
+
+            match blocks.last_mut() {
+                Some((last, last_line, current)) if *last == key => {
+                    let separator = if *last_line == number(line_number) { " " } else { "\n" };
+                    current.text.push_str(separator);
+                    current.text.push_str(text);
+                    *last_line = number(line_number);
+                    if let Some([x, y, w, h]) = current.bbox.as_mut() {
+                        let (x2, y2) = ((*x + *w).max(right), (*y + *h).max(bottom));
+                        *x = (*x).min(left);
+                        *y = (*y).min(top);
+                        *w = x2 - *x;
+                        *h = y2 - *y;
+                    }
+                }
+                _ => blocks.push((
+                    key,
+                    number(line_number),
+                    OcrBlock {
+                        kind: BlockKind::Paragraph,
+                        text: text.to_string(),
+                        bbox: Some([left, top, right - left, bottom - top]),
+                    },
+                )),
+            }
+        }
+        blocks.into_iter().map(|(_, _, block)| block).collect()
+    }
+}
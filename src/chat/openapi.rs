@@ -0,0 +1,229 @@
+// 错误处理
+use error_stack::{Report, Result};
+
+// 异步
+use futures::future::BoxFuture;
+
+// 数据序列化
+use serde_json::{Map, Value, json};
+
+// 项目内部模块
+use crate::chat::chat_base::ChatError;
+use crate::chat::tool_source::{ToolSource, function_tool};
+use crate::schema::tool_schema::ChatToolSchemaError;
+
+/// 导入的 HTTP 方法
+/// HTTP methods imported
+const METHODS: [&str; 5] = ["get", "post", "put", "patch", "delete"];
+
+/// OpenAPI 文档中的一个操作，对应一个工具
+/// One operation of an OpenAPI document, mapped to one tool
+#[derive(Clone, Debug)]
+struct Operation {
+    name: String,
+    method: reqwest::Method,
+    path: String,
+    path_params: Vec<String>,
+    query_params: Vec<String>,
+    tool: Value,
+}
+
+/// 由 OpenAPI 3 文档导入的接口，每个带 `operationId` 的操作成为一个工具
+/// A service imported from an OpenAPI 3 document, every operation with an `operationId` becomes a tool
+///
+/// 路径与查询参数成为同名的工具参数，JSON 请求体成为 `body` 参数；响应为 JSON 时按 JSON 返回，否则按文本返回。
+/// Path and query parameters become tool parameters of the same name and a JSON request body the `body`
+/// parameter; JSON responses are returned as JSON, anything else as text.
+#[derive(Clone, Debug)]
+pub struct OpenApiTools {
+    namespace: String,
+    base_url: String,
+    headers: Vec<(String, String)>,
+    operations: Vec<Operation>,
+    client: reqwest::Client,
+}
+
+impl OpenApiTools {
+    /// 解析 OpenAPI 文档，接口地址取自第一个 `servers` 条目
+    /// Parse an OpenAPI document, the service address is taken from the first `servers` entry
+    pub fn from_spec(namespace: &str, spec: &Value) -> Result<Self, ChatError> {
+        let source_error = || Report::new(ChatError::ToolSourceError(namespace.to_string()));
+        let paths = spec["paths"]
+            .as_object()
+            .ok_or_else(|| source_error().attach_printable("OpenAPI document without paths"))?;
+
+        let mut operations = Vec::new();
+        for (path, item) in paths {
+            let item = resolve(spec, item);
+            for method in METHODS {
+                let Some(operation) = item.get(method).map(|operation| resolve(spec, operation)) else {
+                    continue;
+                };
+                let Some(name) = operation["operationId"].as_str() else {
+                    continue;
+                };
+                operations.push(parse_operation(spec, name, method, path, item, operation));
+            }
+        }
+        Ok(Self {
+            namespace: namespace.to_string(),
+            base_url: spec["servers"][0]["url"].as_str().unwrap_or_default().trim_end_matches('/').to_string(),
+            headers: Vec::new(),
+            operations,
+            client: reqwest::Client::new(),
+        })
+    }
+
+    /// 覆盖文档中的接口地址
+    /// Override the service address of the document
+    pub fn base_url(mut self, url: &str) -> Self {
+        self.base_url = url.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// 附加在每个请求上的请求头，如鉴权
+    /// Header added to every request, such as authorization
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// 以给定参数调用工具时的请求地址，工具不存在时返回 None
+    /// Request URL a call of the tool with the given arguments goes to, None if there is no such tool
+    pub fn url_for(&self, name: &str, arguments: &Value) -> Option<String> {
+        let operation = self.operations.iter().find(|operation| operation.name == name)?;
+        Some(self.request_url(operation, arguments))
+    }
+
+    /// 按参数填入路径与查询参数后的请求地址
+    /// Request URL with the path and query parameters filled in from the arguments
+    fn request_url(&self, operation: &Operation, arguments: &Value) -> String {
+        let mut path = operation.path.clone();
+        for param in &operation.path_params {
+            path = path.replace(&format!("{{{}}}", param), &encode(&argument_text(&arguments[param])));
+        }
+        let query: Vec<_> = operation
+            .query_params
+            .iter()
+            .filter(|param| !arguments[param.as_str()].is_null())
+            .map(|param| format!("{}={}", encode(param), encode(&argument_text(&arguments[param.as_str()]))))
+            .collect();
+        match query.is_empty() {
+            true => format!("{}{}", self.base_url, path),
+            false => format!("{}{}?{}", self.base_url, path, query.join("&")),
+        }
+    }
+}
+
+/// 跟随文档内的 `$ref` 引用
+/// Follow an in-document `$ref` reference
+fn resolve<'a>(spec: &'a Value, value: &'a Value) -> &'a Value {
+    match value["$ref"].as_str().and_then(|reference| reference.strip_prefix('#')) {
+        Some(pointer) => spec.pointer(pointer).map_or(value, |target| resolve(spec, target)),
+        None => value,
+    }
+}
+
+fn parse_operation(spec: &Value, name: &str, method: &str, path: &str, item: &Value, operation: &Value) -> Operation {
+    let mut properties = Map::new();
+    let mut required = Vec::new();
+    let (mut path_params, mut query_params) = (Vec::new(), Vec::new());
+
+    // 路径级与操作级的参数，后者可覆盖前者
+    // Path-level and operation-level parameters, the latter may override the former
+    let params = item["parameters"].as_array().into_iter().chain(operation["parameters"].as_array()).flatten();
+    for param in params.map(|param| resolve(spec, param)) {
+        let Some(param_name) = param["name"].as_str() else {
+            continue;
+        };
+        let list = match param["in"].as_str() {
+            Some("path") => &mut path_params,
+            Some("query") => &mut query_params,
+            _ => continue,
+        };
+        if !list.iter().any(|existing| existing == param_name) {
+            list.push(param_name.to_string());
+        }
+        let mut schema = resolve(spec, &param["schema"]).clone();
+        if let Some(description) = param["description"].as_str() {
+            schema["description"] = json!(description);
+        }
+        properties.insert(param_name.to_string(), schema);
+        if param["required"].as_bool() == Some(true) || param["in"] == "path" {
+            required.push(json!(param_name));
+        }
+    }
+
+    let body = resolve(spec, &operation["requestBody"]);
+    if let Some(schema) = body["content"]["application/json"].get("schema") {
+        properties.insert("body".to_string(), resolve(spec, schema).clone());
+        if body["required"].as_bool() == Some(true) {
+            required.push(json!("body"));
+        }
+    }
+
+    let description = operation["description"].as_str().or(operation["summary"].as_str());
+    let parameters = json!({"type": "object", "properties": properties, "required": required});
+    Operation {
+        name: name.to_string(),
+        method: method.to_uppercase().parse().unwrap_or(reqwest::Method::GET),
+        path: path.to_string(),
+        path_params,
+        query_params,
+        tool: function_tool(name, description, Some(&parameters)),
+    }
+}
+
+fn argument_text(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// 百分号编码，只保留非保留字符
+/// Percent-encode, keeping only unreserved characters
+fn encode(text: &str) -> String {
+    text.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (byte as char).to_string(),
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+impl ToolSource for OpenApiTools {
+    fn namespace(&self) -> Option<&str> {
+        Some(&self.namespace)
+    }
+
+    fn list_tools(&self) -> BoxFuture<'_, Result<Vec<Value>, ChatError>> {
+        Box::pin(async move { Ok(self.operations.iter().map(|operation| operation.tool.clone()).collect()) })
+    }
+
+    fn call_tool<'a>(&'a self, name: &'a str, arguments: Value) -> BoxFuture<'a, Result<Value, ChatToolSchemaError>> {
+        Box::pin(async move {
+            let call_error = || Report::new(ChatToolSchemaError::FunctionCallError);
+            let operation = self
+                .operations
+                .iter()
+                .find(|operation| operation.name == name)
+                .ok_or_else(|| call_error().attach_printable(format!("No operation named {}", name)))?;
+
+            let mut request = self.client.request(operation.method.clone(), self.request_url(operation, &arguments));
+            for (header, value) in &self.headers {
+                request = request.header(header, value);
+            }
+            if let Some(body) = arguments.get("body") {
+                request = request.json(body);
+            }
+            let response = request.send().await.map_err(|e| call_error().attach_printable(e.to_string()))?;
+            let status = response.status();
+            let text = response.text().await.map_err(|e| call_error().attach_printable(e.to_string()))?;
+            if !status.is_success() {
+                return Err(call_error().attach_printable(format!("{} {}: {}", status, name, text)));
+            }
+            Ok(serde_json::from_str(&text).unwrap_or(Value::String(text)))
+        })
+    }
+}
@@ -0,0 +1,166 @@
+// 标准库
+use std::fmt;
+use std::sync::Arc;
+
+// 数据序列化
+use serde::{Deserialize, Serialize};
+
+// 项目内部模块
+use crate::utils::common::tokens::{Heuristic, Tokenizer};
+
+/// 回答大小的硬上限，超出时中止生成并截断回答，保护下游系统不被超大输出拖垮
+/// Hard cap on the answer size, generation is aborted and the answer truncated once it is exceeded, protecting
+/// downstream systems from oversized outputs
+///
+/// 流式回答在到达上限时立即断开连接；非流式回答在收到后按上限截断。流式回答的 token 数由模型的分词器
+/// （见 `tokenizer_for`）逐段计数，非流式回答使用提供商返回的 `completion_tokens`，两者都按比例截断。
+/// Streamed answers drop the connection as soon as the cap is reached; non-streamed answers are truncated once
+/// received. Tokens of a streamed answer are counted per delta with the model's tokenizer (see `tokenizer_for`),
+/// non-streamed answers use the provider's `completion_tokens`; both are cut proportionally.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct OutputCap {
+    pub max_tokens: Option<usize>,
+    pub max_bytes: Option<usize>,
+}
+
+impl OutputCap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn max_tokens(mut self, max_tokens: usize) -> Self {
+        self.max_tokens = Some(max_tokens);
+        self
+    }
+
+    pub fn max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+
+    /// 截断已完整收到的回答
+    /// Truncate an answer that was received in full
+    ///
+    /// # 参数 (Parameters)
+    /// * `content` - 回答内容 / Answer content
+    /// * `completion_tokens` - 提供商报告的回答 token 数 / Answer tokens reported by the provider
+    pub fn truncate(&self, content: &mut String, completion_tokens: Option<u64>) -> Option<Truncated> {
+        let mut truncated = None;
+        if let (Some(max_tokens), Some(tokens)) = (self.max_tokens, completion_tokens)
+            && tokens as usize > max_tokens
+        {
+            let keep = content.chars().count() * max_tokens / tokens as usize;
+            let end = content.char_indices().nth(keep).map_or(content.len(), |(index, _)| index);
+            content.truncate(end);
+            truncated = Some(Truncated::Tokens(max_tokens));
+        }
+        if let Some(max_bytes) = self.max_bytes
+            && content.len() > max_bytes
+        {
+            content.truncate(floor_char_boundary(content, max_bytes));
+            truncated = Some(Truncated::Bytes(max_bytes));
+        }
+        truncated
+    }
+}
+
+/// 回答因达到上限被截断的标记，记录在助手消息的元数据中
+/// Marker of an answer truncated at the cap, recorded in the assistant message metadata
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Truncated {
+    /// 达到 token 上限
+    /// The token cap was reached
+    Tokens(usize),
+
+    /// 达到字节上限
+    /// The byte cap was reached
+    Bytes(usize),
+}
+
+/// 流式回答的上限计数
+/// Cap accounting of a streamed answer
+#[derive(Clone)]
+pub struct CapCounter {
+    cap: OutputCap,
+    tokenizer: Arc<dyn Tokenizer>,
+    tokens: usize,
+    bytes: usize,
+    pub truncated: Option<Truncated>,
+}
+
+impl Default for CapCounter {
+    fn default() -> Self {
+        Self {
+            cap: OutputCap::default(),
+            tokenizer: Arc::new(Heuristic),
+            tokens: 0,
+            bytes: 0,
+            truncated: None,
+        }
+    }
+}
+
+impl fmt::Debug for CapCounter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CapCounter")
+            .field("cap", &self.cap)
+            .field("tokens", &self.tokens)
+            .field("bytes", &self.bytes)
+            .field("truncated", &self.truncated)
+            .finish()
+    }
+}
+
+impl CapCounter {
+    /// 创建计数，token 默认按 `Heuristic` 估算
+    /// Create the accounting, tokens are estimated by `Heuristic` by default
+    pub fn new(cap: OutputCap) -> Self {
+        Self {
+            cap,
+            ..Self::default()
+        }
+    }
+
+    /// 设置计算 token 数的分词器，应与生成回答的模型一致
+    /// Set the tokenizer counting tokens, which should match the model generating the answer
+    pub fn tokenizer(mut self, tokenizer: Arc<dyn Tokenizer>) -> Self {
+        self.tokenizer = tokenizer;
+        self
+    }
+
+    /// 计入一段增量，返回上限之内的部分；到达上限后返回空字符串
+    /// Count one delta, returning the part within the cap; an empty string once the cap is reached
+    pub fn admit(&mut self, mut delta: String) -> String {
+        if self.truncated.is_some() || delta.is_empty() {
+            return String::new();
+        }
+        if let Some(max_tokens) = self.cap.max_tokens {
+            let tokens = self.tokenizer.count(&delta);
+            if self.tokens + tokens > max_tokens {
+                // 越过上限的一段按字符比例保留剩余的 token 数
+                // A delta crossing the cap keeps the remaining tokens by character proportion
+                let keep = delta.chars().count() * (max_tokens - self.tokens) / tokens;
+                delta.truncate(delta.char_indices().nth(keep).map_or(delta.len(), |(index, _)| index));
+                self.tokens = max_tokens;
+                self.truncated = Some(Truncated::Tokens(max_tokens));
+            } else {
+                self.tokens += tokens;
+            }
+        }
+        if let Some(max_bytes) = self.cap.max_bytes
+            && self.bytes + delta.len() > max_bytes
+        {
+            delta.truncate(floor_char_boundary(&delta, max_bytes - self.bytes));
+            self.truncated = Some(Truncated::Bytes(max_bytes));
+        }
+        self.bytes += delta.len();
+        delta
+    }
+}
+
+/// 不超过 `index` 的最近字符边界
+/// Nearest character boundary not past `index`
+fn floor_char_boundary(text: &str, index: usize) -> usize {
+    (0..=index.min(text.len())).rev().find(|&index| text.is_char_boundary(index)).unwrap_or(0)
+}
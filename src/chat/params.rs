@@ -0,0 +1,119 @@
+// 标准库
+use std::collections::BTreeMap;
+
+// 序列化
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize};
+
+// 项目内部模块
+use crate::utils::common::tokens::Tokenizer;
+
+/// 禁止 token 出现的偏置
+/// Bias banning a token
+pub const BAN_BIAS: i32 = -100;
+
+/// 生成参数，未设置的字段不会出现在请求体中
+/// Generation parameters, unset fields are left out of the request body
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ChatParams {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f64>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f64>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop: Option<Vec<String>>,
+
+    /// token ID 到偏置（-100 到 100）的映射，-100 禁止该 token，100 几乎必然选中
+    /// Map from token ID to bias (-100 to 100), -100 bans the token and 100 all but forces it
+    #[serde(default, skip_serializing_if = "Option::is_none", deserialize_with = "token_keys")]
+    pub logit_bias: Option<BTreeMap<u32, i32>>,
+}
+
+impl ChatParams {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn temperature(mut self, temperature: f64) -> Self {
+        self.temperature = Some(temperature);
+        self
+    }
+
+    pub fn max_tokens(mut self, max_tokens: u32) -> Self {
+        self.max_tokens = Some(max_tokens);
+        self
+    }
+
+    pub fn top_p(mut self, top_p: f64) -> Self {
+        self.top_p = Some(top_p);
+        self
+    }
+
+    pub fn stop(mut self, stop: Vec<String>) -> Self {
+        self.stop = Some(stop);
+        self
+    }
+
+    /// 设置一个 token 的偏置，超出 -100 到 100 的值被截断
+    /// Set the bias of one token, values outside -100 to 100 are clamped
+    pub fn logit_bias(mut self, token: u32, bias: i32) -> Self {
+        self.logit_bias.get_or_insert_with(BTreeMap::new).insert(token, bias.clamp(-100, 100));
+        self
+    }
+
+    /// 经分词器把文本映射为 token，为其中每个 token 设置偏置
+    /// Map text to tokens through the tokenizer and set the bias of each of them
+    ///
+    /// 偏置作用于单个 token 而非整段文本，较短的 token 也会在其他词中受到影响。
+    /// The bias applies to single tokens rather than the whole text, so short tokens are affected inside other
+    /// words as well.
+    pub fn bias_text(self, tokenizer: &impl Tokenizer, text: &str, bias: i32) -> Self {
+        tokenizer.encode(text).into_iter().fold(self, |params, token| params.logit_bias(token, bias))
+    }
+
+    /// 禁止文本的 token 出现，如禁止结构化输出中的 Markdown 代码围栏
+    /// Ban the tokens of a text, such as Markdown code fences in structured output
+    pub fn ban_text(self, tokenizer: &impl Tokenizer, text: &str) -> Self {
+        self.bias_text(tokenizer, text, BAN_BIAS)
+    }
+
+    /// 用 `overrides` 中已设置的字段覆盖当前参数
+    /// Override current parameters with the fields set in `overrides`
+    pub fn merge(&self, overrides: &ChatParams) -> ChatParams {
+        ChatParams {
+            temperature: overrides.temperature.or(self.temperature),
+            max_tokens: overrides.max_tokens.or(self.max_tokens),
+            top_p: overrides.top_p.or(self.top_p),
+            stop: overrides.stop.clone().or_else(|| self.stop.clone()),
+            logit_bias: overrides.logit_bias.clone().or_else(|| self.logit_bias.clone()),
+        }
+    }
+
+    /// 将已设置的参数写入请求体，覆盖请求体中的同名字段
+    /// Write set parameters into a request body, replacing fields of the same name
+    pub fn apply_to(&self, request_body: &mut serde_json::Value) {
+        if let (serde_json::Value::Object(body), Ok(serde_json::Value::Object(params))) =
+            (request_body, serde_json::to_value(self))
+        {
+            body.extend(params);
+        }
+    }
+}
+
+/// 读取以字符串为键的 token ID，TOML 的键总是字符串
+/// Read token IDs keyed by strings, TOML keys are always strings
+fn token_keys<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<BTreeMap<u32, i32>>, D::Error> {
+    let Some(biases) = Option::<BTreeMap<String, i32>>::deserialize(deserializer)? else {
+        return Ok(None);
+    };
+    biases
+        .into_iter()
+        .map(|(token, bias)| token.trim().parse().map(|token| (token, bias)).map_err(D::Error::custom))
+        .collect::<Result<_, _>>()
+        .map(Some)
+}
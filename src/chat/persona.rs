@@ -0,0 +1,158 @@
+// 标准库
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+// 错误处理
+use error_stack::{Report, Result, ResultExt};
+
+// 数据序列化
+use serde::Deserialize;
+use serde_json::Value;
+
+// 项目内部模块
+use crate::chat::chat_base::{ChatError, ModelOverride};
+use crate::chat::chat_single::SingleChat;
+use crate::config::ModelCapability;
+
+/// 示例对话中的一轮：别人说的话与角色的回答
+/// One exchange of an example dialogue: what someone said and the character's reply
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+pub struct ExampleDialogue {
+    pub user: String,
+    pub reply: String,
+}
+
+/// 角色扮演的人设，可从 TOML 或 JSON 文件加载，见 `MultiChat::from_personas`
+/// Role-play persona, loadable from TOML or JSON files, see `MultiChat::from_personas`
+///
+/// ```toml
+/// name = "alice"
+/// system = "You are Alice, a tea merchant."
+/// voice = "Warm, short sentences, fond of proverbs."
+/// capability = "long_context"
+///
+/// [[examples]]
+/// user = "What do you sell?"
+/// reply = "Tea, friend. A good cup mends a bad day."
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+pub struct Persona {
+    /// 角色名称，在对话中标明发言者
+    /// Character name, labelling the speaker in the conversation
+    pub name: String,
+
+    pub system: String,
+
+    /// 说话的语气与风格
+    /// Voice and style of speech
+    #[serde(default)]
+    pub voice: Option<String>,
+
+    /// 角色使用的API，未设置时按 `capability` 选择
+    /// API the character uses, selected by `capability` if unset
+    #[serde(default)]
+    pub api: Option<String>,
+
+    /// 选择API的能力，API与能力都未设置时为 long_context
+    /// Capability selecting the API, long_context if neither is set
+    #[serde(default)]
+    pub capability: Option<ModelCapability>,
+
+    /// 角色单独对话时可用的工具，OpenAI 格式的定义，函数需已在本地注册，见 `Persona::chat`
+    /// Tools the character can use in a chat of its own, as OpenAI definitions whose functions are registered
+    /// locally, see `Persona::chat`
+    #[serde(default)]
+    pub tools: Vec<Value>,
+
+    /// 示例对话，附在系统提示词之后示范角色的说话方式
+    /// Example dialogues, appended to the system prompt to show how the character talks
+    #[serde(default)]
+    pub examples: Vec<ExampleDialogue>,
+}
+
+impl Persona {
+    /// 从文件加载人设，按扩展名解析为 TOML 或 JSON
+    /// Load a persona from a file, parsed as TOML or JSON by extension
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ChatError> {
+        let path = path.as_ref();
+        let persona_error = || ChatError::PersonaError(path.display().to_string());
+        let content = fs::read_to_string(path).change_context_lazy(persona_error)?;
+        let persona: Self = match path.extension().and_then(|extension| extension.to_str()) {
+            Some("json") => serde_json::from_str(&content).change_context_lazy(persona_error)?,
+            _ => toml::from_str(&content).change_context_lazy(persona_error)?,
+        };
+        if persona.name.trim().is_empty() {
+            return Err(Report::new(persona_error()).attach_printable("Persona without a name"));
+        }
+        Ok(persona)
+    }
+
+    /// 角色使用的模型：指定的API，否则按能力选择，都未设置时为 long_context
+    /// Model the character uses: the given API, otherwise selected by capability, long_context if neither is set
+    pub fn model(&self) -> ModelOverride {
+        match (&self.api, &self.capability) {
+            (Some(api), _) => ModelOverride::Api(api.clone()),
+            (None, Some(capability)) => ModelOverride::Capability(capability.clone()),
+            (None, None) => ModelOverride::Capability(ModelCapability::LongContext),
+        }
+    }
+
+    /// 以人设为系统提示词的单独对话，使用角色的模型与工具
+    /// Chat of its own with the persona as the system prompt, on the character's model and with its tools
+    pub fn chat(&self) -> Result<SingleChat, ChatError> {
+        let builder = SingleChat::builder().stream(false).system(&self.prompt());
+        let mut builder = match self.model() {
+            ModelOverride::Api(api) => builder.api(&api),
+            ModelOverride::Capability(capability) => builder.capability(capability),
+        };
+        if !self.tools.is_empty() {
+            builder = builder.tools(self.tools.clone());
+        }
+        builder.build().attach_printable_lazy(|| format!("Failed to create character {}", self.name))
+    }
+
+    /// 完整的系统提示词：人设、语气与示例对话
+    /// Full system prompt: the persona, its voice and the example dialogues
+    pub fn prompt(&self) -> String {
+        let mut prompt = self.system.trim().to_string();
+        if let Some(voice) = self.voice.as_deref().filter(|voice| !voice.trim().is_empty()) {
+            prompt.push_str(&format!("\n\n说话风格：{}", voice.trim()));
+        }
+        if !self.examples.is_empty() {
+            prompt.push_str("\n\n示例对话：");
+            for example in &self.examples {
+                prompt.push_str(&format!("\nuser: {}\n{}: {}", example.user, self.name, example.reply));
+            }
+        }
+        prompt
+    }
+}
+
+/// 加载目录中所有 `.toml` 与 `.json` 人设文件，按文件名排序；角色名称重复时返回错误
+/// Load every `.toml` and `.json` persona file of a directory, sorted by file name; repeated character names are
+/// an error
+pub fn load_personas(dir: impl AsRef<Path>) -> Result<Vec<Persona>, ChatError> {
+    let dir = dir.as_ref();
+    let dir_error = || ChatError::PersonaError(dir.display().to_string());
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir).change_context_lazy(dir_error)? {
+        let path = entry.change_context_lazy(dir_error)?.path();
+        if matches!(path.extension().and_then(|extension| extension.to_str()), Some("toml" | "json")) {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+
+    let mut names = HashSet::new();
+    let mut personas = Vec::with_capacity(paths.len());
+    for path in paths {
+        let persona = Persona::load(&path)?;
+        if !names.insert(persona.name.clone()) {
+            return Err(Report::new(ChatError::PersonaError(path.display().to_string()))
+                .attach_printable(format!("Character {} is defined more than once", persona.name)));
+        }
+        personas.push(persona);
+    }
+    Ok(personas)
+}
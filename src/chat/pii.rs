@@ -0,0 +1,285 @@
+// 标准库
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+
+// 错误处理
+use error_stack::{Report, Result, ResultExt};
+
+// 异步
+use futures::future::BoxFuture;
+
+// 文本处理
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+// 项目内部模块
+use crate::chat::chat_base::ChatError;
+
+/// 占位符的形式：`<EMAIL_1>`
+/// Shape of a placeholder: `<EMAIL_1>`
+static PLACEHOLDER: Lazy<Regex> = Lazy::new(|| Regex::new(r"<([A-Z][A-Z0-9_]*_\d+)>").unwrap());
+
+/// 默认的敏感信息模式：类别与正则
+/// Default sensitive data patterns: label and regular expression
+const DEFAULT_PATTERNS: &[(&str, &str)] = &[
+    ("EMAIL", r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}"),
+    ("CARD", r"\b\d(?:[ -]?\d){12,18}\b"),
+    ("PHONE", r"(?:\+\d{1,3}[ .-]?)?(?:\(\d{2,4}\)|\b\d{2,4})[ .-]?\d{3,4}[ .-]?\d{4}\b"),
+];
+
+/// 编译后的默认模式
+/// Compiled default patterns
+static DEFAULT_REGEXES: Lazy<Vec<(&str, Regex)>> = Lazy::new(|| {
+    DEFAULT_PATTERNS.iter().map(|(label, pattern)| (*label, Regex::new(pattern).unwrap())).collect()
+});
+
+/// 流式还原时最多暂存的未闭合占位符长度
+/// Longest unclosed placeholder held back while restoring a stream
+const MAX_PLACEHOLDER_LEN: usize = 48;
+
+/// 命名实体识别器找到的敏感片段，以字节位置表示
+/// Sensitive span found by a named entity recognizer, in byte offsets
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PiiEntity {
+    pub start: usize,
+    pub end: usize,
+    /// 占位符使用的类别，如 `PERSON`
+    /// Label used in the placeholder, such as `PERSON`
+    pub label: String,
+}
+
+/// 命名实体识别模型，补充正则无法覆盖的姓名、地址等
+/// Named entity recognition model, covering names, addresses and the like that patterns cannot
+pub trait EntityRecognizer: Send + Sync {
+    fn recognize<'a>(&'a self, text: &'a str) -> BoxFuture<'a, Result<Vec<PiiEntity>, ChatError>>;
+}
+
+/// 同步函数可直接作为本地识别器
+/// Synchronous functions can be used directly as local recognizers
+impl<F> EntityRecognizer for F
+where
+    F: Fn(&str) -> Vec<PiiEntity> + Send + Sync,
+{
+    fn recognize<'a>(&'a self, text: &'a str) -> BoxFuture<'a, Result<Vec<PiiEntity>, ChatError>> {
+        let entities = self(text);
+        Box::pin(async move { Ok(entities) })
+    }
+}
+
+/// 占位符与原文的对应表，同一原文总是得到同一占位符
+/// Table between placeholders and original text, the same original always gets the same placeholder
+#[derive(Debug, Default)]
+struct PiiVault {
+    originals: HashMap<String, String>,
+    placeholders: HashMap<String, String>,
+    counters: HashMap<String, usize>,
+}
+
+impl PiiVault {
+    fn placeholder(&mut self, label: &str, original: &str) -> String {
+        if let Some(placeholder) = self.placeholders.get(original) {
+            return placeholder.clone();
+        }
+        let counter = self.counters.entry(label.to_string()).or_default();
+        *counter += 1;
+        let placeholder = format!("<{}_{}>", label, counter);
+        self.originals.insert(placeholder.clone(), original.to_string());
+        self.placeholders.insert(original.to_string(), placeholder.clone());
+        placeholder
+    }
+}
+
+/// 发送前的敏感信息清洗：以可还原的占位符替换邮箱、电话、银行卡号及自定义模式，回答中的占位符在返回前还原
+/// Pre-send scrubber for sensitive data: emails, phone numbers, card numbers and custom patterns are replaced with
+/// reversible placeholders, and placeholders in the answer are restored before it is returned
+///
+/// 会话中只保存占位符形式的文本，原文只在进程内的对应表中；对话的副本共享同一张表。
+/// 系统提示词与工具结果不经过清洗。
+/// The session only stores the placeholder form, the originals only live in the in-process table; copies of a
+/// chat share the same table. System prompts and tool results are not scrubbed.
+#[derive(Clone)]
+pub struct PiiScrubber {
+    patterns: Vec<(String, Regex)>,
+    recognizer: Option<Arc<dyn EntityRecognizer>>,
+    vault: Arc<Mutex<PiiVault>>,
+}
+
+impl Debug for PiiScrubber {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PiiScrubber")
+            .field("patterns", &self.patterns.iter().map(|(label, _)| label).collect::<Vec<_>>())
+            .field("recognizer", &self.recognizer.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for PiiScrubber {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PiiScrubber {
+    /// 带有邮箱、银行卡号（通过 Luhn 校验）与电话号码的默认模式
+    /// With the default patterns for emails, card numbers (passing the Luhn check) and phone numbers
+    pub fn new() -> Self {
+        Self {
+            patterns: DEFAULT_REGEXES.iter().map(|(label, regex)| (label.to_string(), regex.clone())).collect(),
+            recognizer: None,
+            vault: Arc::default(),
+        }
+    }
+
+    /// 添加自定义模式，匹配内容以 `<LABEL_n>` 替换，`label` 需为大写字母、数字与下划线
+    /// Add a custom pattern, matches are replaced with `<LABEL_n>`, `label` must be uppercase letters, digits and
+    /// underscores
+    pub fn with_pattern(mut self, label: &str, pattern: &str) -> Result<Self, ChatError> {
+        if !PLACEHOLDER.is_match(&format!("<{}_1>", label)) {
+            return Err(Report::new(ChatError::InvalidConfig))
+                .attach_printable(format!("Invalid PII label: {}", label));
+        }
+        let regex = Regex::new(pattern)
+            .change_context(ChatError::InvalidConfig)
+            .attach_printable_lazy(|| format!("Invalid PII pattern: {}", pattern))?;
+        self.patterns.push((label.to_string(), regex));
+        Ok(self)
+    }
+
+    /// 在正则之后运行的命名实体识别器
+    /// Named entity recognizer run after the patterns
+    pub fn with_recognizer(mut self, recognizer: impl EntityRecognizer + 'static) -> Self {
+        self.recognizer = Some(Arc::new(recognizer));
+        self
+    }
+
+    /// 以占位符替换文本中的敏感信息
+    /// Replace the sensitive data in a text with placeholders
+    pub async fn scrub(&self, text: &str) -> Result<String, ChatError> {
+        let mut scrubbed = self.scrub_patterns(text);
+        if let Some(recognizer) = &self.recognizer {
+            let mut entities = recognizer.recognize(&scrubbed).await.attach_printable("Failed to recognize entities")?;
+            entities.sort_by_key(|entity| std::cmp::Reverse(entity.start));
+            let mut vault = self.vault.lock().unwrap();
+            let mut limit = scrubbed.len();
+            for entity in entities {
+                // 跳过越界、重叠或不在字符边界上的片段
+                // Skip spans out of range, overlapping, or not on character boundaries
+                if entity.start >= entity.end
+                    || entity.end > limit
+                    || !scrubbed.is_char_boundary(entity.start)
+                    || !scrubbed.is_char_boundary(entity.end)
+                {
+                    continue;
+                }
+                let placeholder = vault.placeholder(&entity.label, &scrubbed[entity.start..entity.end]);
+                scrubbed.replace_range(entity.start..entity.end, &placeholder);
+                limit = entity.start;
+            }
+        }
+        Ok(scrubbed)
+    }
+
+    /// 只按正则模式替换，不调用实体识别
+    /// Replace by the regex patterns only, without calling the entity recognizer
+    pub(crate) fn scrub_patterns(&self, text: &str) -> String {
+        let mut scrubbed = text.to_string();
+        for (label, pattern) in &self.patterns {
+            let mut vault = self.vault.lock().unwrap();
+            scrubbed = pattern
+                .replace_all(&scrubbed, |captures: &regex::Captures| {
+                    let matched = &captures[0];
+                    match label.as_str() == "CARD" && !luhn_valid(matched) {
+                        true => matched.to_string(),
+                        false => vault.placeholder(label, matched),
+                    }
+                })
+                .into_owned();
+        }
+        scrubbed
+    }
+
+    /// 将文本中的占位符还原为原文，未知的占位符保持不变
+    /// Restore the placeholders in a text to the originals, unknown placeholders are left as is
+    pub fn restore(&self, text: &str) -> String {
+        let vault = self.vault.lock().unwrap();
+        PLACEHOLDER
+            .replace_all(text, |captures: &regex::Captures| {
+                vault.originals.get(&captures[0]).cloned().unwrap_or_else(|| captures[0].to_string())
+            })
+            .into_owned()
+    }
+
+    /// 还原JSON值中所有字符串里的占位符
+    /// Restore the placeholders in every string of a JSON value
+    pub fn restore_json(&self, value: &mut serde_json::Value) {
+        match value {
+            serde_json::Value::String(text) => *text = self.restore(text),
+            serde_json::Value::Array(items) => items.iter_mut().for_each(|item| self.restore_json(item)),
+            serde_json::Value::Object(fields) => fields.values_mut().for_each(|field| self.restore_json(field)),
+            _ => {}
+        }
+    }
+}
+
+/// 流式回答的占位符还原，暂存可能被切断的占位符直到其闭合
+/// Placeholder restoration for streamed answers, holding back a possibly split placeholder until it closes
+#[derive(Clone, Debug, Default)]
+pub struct StreamRestorer {
+    pending: String,
+}
+
+impl StreamRestorer {
+    /// 加入一段增量，返回可以发出的还原后文本
+    /// Add a delta, returning the restored text that can be sent
+    pub fn push(&mut self, scrubber: &PiiScrubber, delta: &str) -> String {
+        self.pending.push_str(delta);
+        let held = match self.pending.rfind('<') {
+            Some(start)
+                if self.pending.len() - start < MAX_PLACEHOLDER_LEN
+                    && self.pending[start + 1..].chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_') =>
+            {
+                self.pending.split_off(start)
+            }
+            _ => String::new(),
+        };
+        let ready = std::mem::replace(&mut self.pending, held);
+        scrubber.restore(&ready)
+    }
+
+    /// 流结束时发出剩余的文本
+    /// Send the remaining text when the stream ends
+    pub fn finish(&mut self, scrubber: &PiiScrubber) -> String {
+        scrubber.restore(&std::mem::take(&mut self.pending))
+    }
+}
+
+/// 文本中出现的默认敏感信息类别（`EMAIL`、`CARD`、`PHONE`），不做替换
+/// Labels of the default sensitive data (`EMAIL`, `CARD`, `PHONE`) present in a text, without replacing anything
+pub(crate) fn detect_pii(text: &str) -> Vec<&'static str> {
+    DEFAULT_REGEXES
+        .iter()
+        .filter(|(label, regex)| match *label == "CARD" {
+            true => regex.find_iter(text).any(|card| luhn_valid(card.as_str())),
+            false => regex.is_match(text),
+        })
+        .map(|(label, _)| *label)
+        .collect()
+}
+
+/// 银行卡号的 Luhn 校验，用于排除普通的长数字
+/// Luhn check of card numbers, ruling out ordinary long numbers
+fn luhn_valid(number: &str) -> bool {
+    let digits: Vec<u32> = number.chars().filter_map(|c| c.to_digit(10)).collect();
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, &digit)| match i % 2 {
+            1 if digit * 2 > 9 => digit * 2 - 9,
+            1 => digit * 2,
+            _ => digit,
+        })
+        .sum();
+    sum.is_multiple_of(10)
+}
@@ -0,0 +1,203 @@
+// 标准库
+use std::fmt::Debug;
+use std::sync::Arc;
+
+// 错误处理
+use error_stack::{Report, Result};
+
+// 文本处理
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+// 项目内部模块
+use crate::chat::chat_base::ChatError;
+use crate::chat::transform::strip_line;
+
+/// 成对的思考过程标记及其内容
+/// Paired reasoning markers with their content
+static REASONING: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(concat!(
+        r"(?s)<think>.*?</think>|<thinking>.*?</thinking>",
+        r"|<reasoning>.*?</reasoning>|<reflection>.*?</reflection>"
+    ))
+    .unwrap()
+});
+
+/// 缺少开始标记时思考过程的结束标记，部分提供商只输出结束标记
+/// Closing reasoning marker without its opening one, some providers only output the closing marker
+static REASONING_END: Lazy<Regex> = Lazy::new(|| Regex::new(r"</(?:think|thinking|reasoning|reflection)>").unwrap());
+
+/// 以 `*` 或 `+` 开头的列表项
+/// List items starting with `*` or `+`
+static BULLET: Lazy<Regex> = Lazy::new(|| Regex::new(r"^(\s*)[*+]\s+").unwrap());
+
+/// 回答的后处理，在回答返回与写入会话前改写它，如去掉思考过程或转换为纯文本
+/// Post-processing of an answer, rewriting it before it is returned and stored, such as removing the reasoning or
+/// converting it to plain text
+pub trait PostProcessor: Send + Sync {
+    /// 返回改写后的回答，无法处理时返回错误
+    /// Return the rewritten answer, or an error if it cannot be processed
+    fn process(&self, answer: &str) -> Result<String, ChatError>;
+}
+
+/// 函数可直接作为自定义的后处理
+/// Functions can be used directly as custom post-processors
+impl<F> PostProcessor for F
+where
+    F: Fn(&str) -> String + Send + Sync,
+{
+    fn process(&self, answer: &str) -> Result<String, ChatError> {
+        Ok(self(answer))
+    }
+}
+
+/// 去掉 `<think>`、`<thinking>`、`<reasoning>` 与 `<reflection>` 标记的思考过程；只有结束标记时去掉其之前的全部内容
+/// Remove reasoning marked with `<think>`, `<thinking>`, `<reasoning>` and `<reflection>`; with only a closing
+/// marker, everything before it is removed
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StripReasoning;
+
+impl PostProcessor for StripReasoning {
+    fn process(&self, answer: &str) -> Result<String, ChatError> {
+        let answer = REASONING.replace_all(answer, "");
+        match REASONING_END.find_iter(&answer).last() {
+            Some(end) => Ok(answer[end.end()..].to_string()),
+            None => Ok(answer.into_owned()),
+        }
+    }
+}
+
+/// 统一 Markdown 格式：换行符统一为 `\n`，去掉行尾空白，列表标记统一为 `-`，连续空行合并为一行，补上未闭合的代码块围栏；
+/// 代码块内的内容保持不变
+/// Normalize Markdown: line breaks become `\n`, trailing whitespace is removed, list markers become `-`, runs of
+/// blank lines collapse into one and an unclosed code fence is closed; content inside code blocks is left as is
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NormalizeMarkdown;
+
+impl PostProcessor for NormalizeMarkdown {
+    fn process(&self, answer: &str) -> Result<String, ChatError> {
+        let mut lines: Vec<String> = Vec::new();
+        let mut in_code = false;
+        for line in answer.replace("\r\n", "\n").split('\n') {
+            let fence = line.trim_start().starts_with("```") || line.trim_start().starts_with("~~~");
+            if in_code && !fence {
+                lines.push(line.to_string());
+                continue;
+            }
+            if fence {
+                in_code = !in_code;
+            }
+            let line = line.trim_end();
+            if line.is_empty() && lines.last().is_some_and(|last| last.is_empty()) {
+                continue;
+            }
+            lines.push(BULLET.replace(line, "$1- ").into_owned());
+        }
+        if in_code {
+            lines.push("```".to_string());
+        }
+        Ok(lines.join("\n"))
+    }
+}
+
+/// 去掉 Markdown 标记，只保留文字，与流式变换 `StripMarkdown` 的处理相同
+/// Strip Markdown markup and keep only the text, the same processing as the stream transformer `StripMarkdown`
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PlainText;
+
+impl PostProcessor for PlainText {
+    fn process(&self, answer: &str) -> Result<String, ChatError> {
+        Ok(answer.lines().filter_map(strip_line).collect::<Vec<_>>().join("\n"))
+    }
+}
+
+/// 去掉开头与结尾的空白以及每行行尾的空白
+/// Remove the leading and trailing whitespace and the trailing whitespace of every line
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TrimWhitespace;
+
+impl PostProcessor for TrimWhitespace {
+    fn process(&self, answer: &str) -> Result<String, ChatError> {
+        Ok(answer.lines().map(str::trim_end).collect::<Vec<_>>().join("\n").trim().to_string())
+    }
+}
+
+/// 只保留回答中的第一个 JSON 对象或数组，去掉代码块围栏与前后的说明文字；没有合法的 JSON 时返回错误
+/// Keep only the first JSON object or array of the answer, dropping code fences and the surrounding prose; an error
+/// if there is no valid JSON
+#[derive(Clone, Copy, Debug, Default)]
+pub struct JsonOnly;
+
+impl PostProcessor for JsonOnly {
+    fn process(&self, answer: &str) -> Result<String, ChatError> {
+        for (start, _) in answer.match_indices(['{', '[']) {
+            let mut values = serde_json::Deserializer::from_str(&answer[start..]).into_iter::<serde_json::Value>();
+            if let Some(Ok(_)) = values.next() {
+                return Ok(answer[start..start + values.byte_offset()].to_string());
+            }
+        }
+        Err(Report::new(ChatError::PostProcessError("json_only".to_string()))
+            .attach_printable("The answer contains no valid JSON"))
+    }
+}
+
+/// 按顺序执行的后处理链，前一个后处理的输出作为后一个的输入
+/// Chain of post-processors run in order, each fed with the output of the previous one
+///
+/// 后处理作用于对话的每个最终回答，包括结构化输出与工具调用后的回答；流式回答的增量已经发出，后处理只影响写入会话的回答。
+/// Post-processors apply to every final answer of the chat, including structured outputs and answers after tool
+/// calls; the deltas of a streamed answer are already out, so only the answer stored in the session is processed.
+#[derive(Clone, Default)]
+pub struct PostProcessors {
+    processors: Vec<Arc<dyn PostProcessor>>,
+}
+
+impl Debug for PostProcessors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PostProcessors").field("processors", &self.processors.len()).finish()
+    }
+}
+
+impl PostProcessors {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 面向阅读的 Markdown：去掉思考过程、统一格式并去掉多余空白
+    /// Markdown for reading: remove the reasoning, normalize the formatting and trim the whitespace
+    pub fn markdown() -> Self {
+        Self::new().with(StripReasoning).with(NormalizeMarkdown).with(TrimWhitespace)
+    }
+
+    /// 纯文本，如短信与语音合成：去掉思考过程与 Markdown 标记并去掉多余空白
+    /// Plain text, such as for SMS or speech synthesis: remove the reasoning and the Markdown markup and trim the
+    /// whitespace
+    pub fn plain_text() -> Self {
+        Self::new().with(StripReasoning).with(PlainText).with(TrimWhitespace)
+    }
+
+    /// 只有 JSON，供程序读取：去掉思考过程并只保留第一个 JSON 值
+    /// JSON only, for programs to read: remove the reasoning and keep only the first JSON value
+    pub fn json_only() -> Self {
+        Self::new().with(StripReasoning).with(JsonOnly)
+    }
+
+    pub fn with(mut self, processor: impl PostProcessor + 'static) -> Self {
+        self.processors.push(Arc::new(processor));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.processors.is_empty()
+    }
+
+    /// 依次执行后处理
+    /// Run the post-processors in order
+    pub fn process(&self, answer: &str) -> Result<String, ChatError> {
+        let mut processed = answer.to_string();
+        for processor in &self.processors {
+            processed = processor.process(&processed)?;
+        }
+        Ok(processed)
+    }
+}
@@ -0,0 +1,158 @@
+// 数据序列化
+use serde::Serialize;
+
+// 项目内部模块
+use crate::config::metadata::ModelMetadata;
+use crate::telemetry::TokenUsage;
+use crate::utils::common::tokens::{Tokenizer, tokenizer_for};
+
+/// 组装完成但未发送的请求，由 `SingleChat::dry_run` 返回，用于调试提示词组装与预估高成本请求
+/// An assembled request that was not sent, returned by `SingleChat::dry_run` to debug prompt assembly and to
+/// pre-flight expensive requests
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct RequestPreview {
+    pub url: String,
+
+    pub model: String,
+
+    /// 请求头（已经过拦截器），API密钥等敏感值已脱敏
+    /// Request headers (after the interceptors), with sensitive values such as the API key redacted
+    pub headers: Vec<(String, String)>,
+
+    /// 发往提供商的请求体（已经过拦截器），除了没有检索结果与触发器的工具结果，与实际发送的内容一致
+    /// Request body sent to the provider (after the interceptors), exactly as it would be sent except for the
+    /// retrieved material and the results of trigger tools
+    pub body: serde_json::Value,
+
+    /// 提示词 token 数，按模型的分词器计算，见 `tokenizer_for`
+    /// Prompt tokens, counted by the model's tokenizer, see `tokenizer_for`
+    pub estimated_prompt_tokens: u64,
+
+    /// 估算的费用（美元）：提示词费用，设置了 `max_tokens` 时加上回答的最大费用；未配置价格时为 None
+    /// Estimated cost in USD: the prompt cost, plus the maximum answer cost if `max_tokens` is set; None if no
+    /// price is configured
+    pub estimated_cost: Option<f64>,
+}
+
+impl RequestPreview {
+    pub(crate) fn new(
+        url: String,
+        model: String,
+        headers: Vec<(String, String)>,
+        body: serde_json::Value,
+        metadata: Option<ModelMetadata>,
+    ) -> Self {
+        let estimated_prompt_tokens = estimate_prompt_tokens(&body, tokenizer_for(&model).as_ref());
+        let max_tokens = requested_max_tokens(&body).unwrap_or(0);
+        let estimated_cost = metadata.and_then(|metadata| {
+            metadata.cost(&TokenUsage {
+                prompt_tokens: estimated_prompt_tokens,
+                completion_tokens: max_tokens,
+                total_tokens: estimated_prompt_tokens + max_tokens,
+            })
+        });
+        Self {
+            url,
+            model,
+            headers,
+            body,
+            estimated_prompt_tokens,
+            estimated_cost,
+        }
+    }
+}
+
+/// 提问的预估用量与费用，由 `SingleChat::estimate` 返回，供应用在发出高成本请求前提醒用户或换用更便宜的模型
+/// Estimated usage and cost of a question, returned by `SingleChat::estimate` so applications can warn users or
+/// choose a cheaper model before sending an expensive request
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Estimate {
+    pub model: String,
+
+    /// 提示词 token 数，按给定的分词器计算，未给定时按模型的分词器，见 `tokenizer_for`
+    /// Prompt tokens, counted by the given tokenizer or else the model's, see `tokenizer_for`
+    pub prompt_tokens: u64,
+
+    /// 回答最多的 token 数：请求设置的 `max_tokens`，未设置时为上下文窗口余下的部分；都未知时为 None
+    /// Most tokens the answer can take: the `max_tokens` of the request, or what is left of the context window if
+    /// unset; None if neither is known
+    pub max_completion_tokens: Option<u64>,
+
+    /// 费用范围（美元）：下限只计提示词，上限加上最长回答的费用；未配置价格或回答长度未知时为 None
+    /// Cost range in USD: the lower bound counts the prompt only, the upper bound adds the longest answer; None if
+    /// no price is configured or the answer length is unknown
+    pub cost_range: Option<(f64, f64)>,
+}
+
+impl Estimate {
+    pub(crate) fn new(
+        model: String,
+        body: &serde_json::Value,
+        tokenizer: &dyn Tokenizer,
+        metadata: Option<&ModelMetadata>,
+    ) -> Self {
+        let prompt_tokens = estimate_prompt_tokens(body, tokenizer);
+        let context_left = metadata
+            .and_then(|metadata| metadata.context_window)
+            .map(|window| (window as u64).saturating_sub(prompt_tokens));
+        let mut estimate = Self {
+            model,
+            prompt_tokens,
+            max_completion_tokens: requested_max_tokens(body).or(context_left),
+            cost_range: None,
+        };
+        estimate.cost_range = metadata.and_then(|metadata| estimate.cost_range_for(metadata));
+        estimate
+    }
+
+    /// 按另一模型的价格计算同样用量的费用范围，用于比较模型；回答长度未知时使用该模型上下文窗口余下的部分
+    /// Cost range of the same usage at another model's prices, to compare models; if the answer length is unknown
+    /// what is left of that model's context window is used
+    pub fn cost_range_for(&self, metadata: &ModelMetadata) -> Option<(f64, f64)> {
+        let max_completion = self.max_completion_tokens.or_else(|| {
+            metadata.context_window.map(|window| (window as u64).saturating_sub(self.prompt_tokens))
+        })?;
+        let cost = |completion_tokens| {
+            metadata.cost(&TokenUsage {
+                prompt_tokens: self.prompt_tokens,
+                completion_tokens,
+                total_tokens: self.prompt_tokens + completion_tokens,
+            })
+        };
+        Some((cost(0)?, cost(max_completion)?))
+    }
+
+    /// 提示词是否超出模型的上下文窗口，窗口未知时返回 false
+    /// Whether the prompt exceeds the model's context window, false if the window is unknown
+    pub fn exceeds_context(&self, metadata: &ModelMetadata) -> bool {
+        metadata.context_window.is_some_and(|window| self.prompt_tokens > window as u64)
+    }
+}
+
+/// 请求体中设置的回答 token 上限
+/// Answer token limit set in a request body
+fn requested_max_tokens(body: &serde_json::Value) -> Option<u64> {
+    body["max_tokens"].as_u64().or(body["max_completion_tokens"].as_u64())
+}
+
+/// 估算请求体的提示词 token 数：消息文本、工具调用与工具定义，补全请求体按 `prompt` 计算
+/// Estimate the prompt tokens of a request body: message text, tool calls and tool definitions; completion
+/// request bodies count their `prompt`
+fn estimate_prompt_tokens(body: &serde_json::Value, tokenizer: &dyn Tokenizer) -> u64 {
+    let count = |text: &str| tokenizer.count(text);
+    let mut tokens = body["prompt"].as_str().map_or(0, &count);
+    for message in body["messages"].as_array().into_iter().flatten() {
+        tokens += match &message["content"] {
+            serde_json::Value::String(text) => count(text),
+            serde_json::Value::Array(parts) => parts.iter().filter_map(|part| part["text"].as_str()).map(&count).sum(),
+            _ => 0,
+        };
+        if let Some(calls) = message.get("tool_calls") {
+            tokens += count(&calls.to_string());
+        }
+    }
+    if let Some(tools) = body.get("tools") {
+        tokens += count(&tools.to_string());
+    }
+    tokens as u64
+}
@@ -0,0 +1,242 @@
+// 标准库
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+// 并发和同步原语
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use tokio::sync::{AcquireError, OwnedSemaphorePermit, Semaphore, oneshot};
+
+// 数据序列化
+use serde::{Deserialize, Serialize};
+
+/// 每个并发额度池的公平队列，额度池重新创建（如重新加载配置）时随之替换
+/// Fair queue of each permit pool, replaced together with the pool when it is recreated (such as on a config reload)
+static QUEUES: Lazy<DashMap<String, PoolQueue>> = Lazy::new(DashMap::new);
+
+/// 额度池与它的公平队列
+/// A permit pool and its fair queue
+type PoolQueue = (Arc<Semaphore>, Arc<FairQueue>);
+
+/// 一轮调度的步长，权重越大每次前进越少，被选中的次数越多
+/// Stride of one scheduling round, lanes with larger weights advance less per turn and are picked more often
+const STRIDE: u64 = 1 << 20;
+
+/// 后台请求等待期间前台最多连续获得的轮次，之后让后台通道获得一次额度，前台请求持续到来时后台也不会无限期等待
+/// Turns the foreground may take in a row while background requests wait, after which the background lane gets one
+/// permit, so a steady stream of foreground requests cannot hold background work back indefinitely
+const BACKGROUND_AGING: u32 = 16;
+
+/// 排队优先级，额度不足时按权重在各队列间分配额度
+/// Queueing priority, permits are shared between queues by weight when they run short
+///
+/// 权重低于 `Normal` 的请求属于后台通道：有前台请求在排队时，前台每连续获得 [`BACKGROUND_AGING`] 轮才轮到后台一次，
+/// 租户的频率上限与服务的准入控制也为前台请求保留余量，同一进程中的批量评估任务因此不会拖慢用户等待中的对话。
+/// Requests weighted below `Normal` go to the background lane: while foreground requests are queued it only gets
+/// one turn after every [`BACKGROUND_AGING`] foreground turns, and the tenant rate limit and the server admission
+/// control keep headroom for foreground requests, so bulk evaluation jobs in the same process barely delay chats a
+/// user is waiting on.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QueuePriority {
+    /// 批量任务，如代理的后台工作，权重 1
+    /// Bulk work such as background agent runs, weight 1
+    Background,
+
+    /// 权重 4
+    /// Weight 4
+    #[default]
+    Normal,
+
+    /// 用户等待中的对话，权重 16
+    /// Chats a user is waiting on, weight 16
+    Interactive,
+
+    /// 自定义权重，至少为 1
+    /// Custom weight, at least 1
+    Weight(u32),
+}
+
+impl QueuePriority {
+    pub fn weight(&self) -> u64 {
+        match self {
+            Self::Background => 1,
+            Self::Normal => 4,
+            Self::Interactive => 16,
+            Self::Weight(weight) => (*weight).max(1) as u64,
+        }
+    }
+
+    /// 是否属于后台通道
+    /// Whether it belongs to the background lane
+    pub fn is_background(&self) -> bool {
+        is_background(self.weight())
+    }
+}
+
+fn is_background(weight: u64) -> bool {
+    weight < QueuePriority::Normal.weight()
+}
+
+/// 同一队列键下的等待者，按到达顺序排列
+/// Waiters under one queue key, in arrival order
+#[derive(Default)]
+struct Lane {
+    waiters: VecDeque<(u64, oneshot::Sender<()>)>,
+
+    /// 调度进度，最小的非空队列先获得额度
+    /// Scheduling progress, the non-empty lane with the smallest pass gets the next permit
+    pass: u64,
+
+    weight: u64,
+}
+
+#[derive(Default)]
+struct Dispatch {
+    lanes: HashMap<String, Lane>,
+
+    /// 最近一次分配时的进度，重新开始排队的队列从这里起算，不能凭空闲期间积累额度
+    /// Pass of the latest turn, lanes that start queueing again begin here and cannot bank turns while idle
+    clock: u64,
+
+    /// 正在等待额度的排队者，同一时刻只有它向额度池申请
+    /// The queued request currently waiting for a permit, the only one asking the pool at a time
+    head: Option<u64>,
+
+    /// 后台请求等待期间前台已连续获得的轮次
+    /// Turns the foreground has taken in a row while background requests wait
+    passed_over: u32,
+
+    next_ticket: u64,
+}
+
+impl Dispatch {
+    /// 轮到下一个等待者：有前台队列在等待时只在前台队列中选，后台已让过 [`BACKGROUND_AGING`] 轮时只在后台队列中选，
+    /// 取进度最小的非空队列，进度相同时按队列键排序
+    /// Give the turn to the next waiter: only foreground lanes are considered while any of them is waiting, unless
+    /// the background has been passed over [`BACKGROUND_AGING`] times, then only background lanes; the non-empty lane
+    /// with the smallest pass is taken, ties broken by key
+    fn promote(&mut self) {
+        self.head = None;
+        let clock = self.clock;
+        self.lanes.retain(|_, lane| !lane.waiters.is_empty() || lane.pass > clock);
+        loop {
+            let waiting = |background: bool| {
+                self.lanes
+                    .values()
+                    .any(|lane| !lane.waiters.is_empty() && is_background(lane.weight) == background)
+            };
+            let (foreground, background) = (waiting(false), waiting(true));
+            let pick_background = !foreground || (background && self.passed_over >= BACKGROUND_AGING);
+            let Some((_, lane)) = self
+                .lanes
+                .iter_mut()
+                .filter(|(_, lane)| !lane.waiters.is_empty() && is_background(lane.weight) == pick_background)
+                .min_by(|(a_key, a), (b_key, b)| a.pass.cmp(&b.pass).then_with(|| a_key.cmp(b_key)))
+            else {
+                return;
+            };
+            // 后台队列的进度可能落后于前台，时钟不回退
+            // Background lanes may lag behind the foreground, the clock never goes back
+            self.clock = self.clock.max(lane.pass);
+            lane.pass += STRIDE / lane.weight;
+            let (ticket, turn) = lane.waiters.pop_front().expect("lane is not empty");
+            if turn.send(()).is_ok() {
+                self.passed_over = match background && !pick_background {
+                    true => self.passed_over + 1,
+                    false => 0,
+                };
+                self.head = Some(ticket);
+                return;
+            }
+        }
+    }
+
+    fn is_idle(&self) -> bool {
+        self.head.is_none() && self.lanes.values().all(|lane| lane.waiters.is_empty())
+    }
+}
+
+/// 并发额度池前的公平队列：额度充足时直接获取，不足时按队列键（对话或租户）轮流分配，避免批量任务挤占交互对话
+/// Fair queue in front of a permit pool: permits are taken directly while available; when they run short, queue
+/// keys (chats or tenants) take turns by weight, so bulk work cannot starve interactive chats
+pub struct FairQueue {
+    semaphore: Arc<Semaphore>,
+    dispatch: Mutex<Dispatch>,
+}
+
+impl FairQueue {
+    pub fn new(semaphore: Arc<Semaphore>) -> Arc<Self> {
+        Arc::new(Self {
+            semaphore,
+            dispatch: Mutex::new(Dispatch::default()),
+        })
+    }
+
+    /// 额度池对应的公平队列，`pool` 为额度池名称
+    /// The fair queue of a permit pool, `pool` naming the pool
+    pub fn of(pool: &str, semaphore: &Arc<Semaphore>) -> Arc<Self> {
+        let mut entry = QUEUES
+            .entry(pool.to_string())
+            .or_insert_with(|| (semaphore.clone(), FairQueue::new(semaphore.clone())));
+        if !Arc::ptr_eq(&entry.0, semaphore) {
+            *entry = (semaphore.clone(), FairQueue::new(semaphore.clone()));
+        }
+        entry.1.clone()
+    }
+
+    /// 获取一个额度；没有人排队且额度充足时立即返回
+    /// Acquire one permit; returns at once if nobody is queued and a permit is free
+    pub async fn acquire(&self, key: &str, priority: QueuePriority) -> Result<OwnedSemaphorePermit, AcquireError> {
+        let (ticket, turn) = {
+            let mut dispatch = self.dispatch.lock().unwrap();
+            if dispatch.is_idle()
+                && let Ok(permit) = self.semaphore.clone().try_acquire_owned()
+            {
+                return Ok(permit);
+            }
+
+            let ticket = dispatch.next_ticket;
+            dispatch.next_ticket += 1;
+            let (sender, turn) = oneshot::channel();
+            let clock = dispatch.clock;
+            let lane = dispatch.lanes.entry(key.to_string()).or_default();
+            if lane.waiters.is_empty() {
+                lane.pass = lane.pass.max(clock);
+            }
+            lane.weight = priority.weight();
+            lane.waiters.push_back((ticket, sender));
+            if dispatch.head.is_none() {
+                dispatch.promote();
+            }
+            (ticket, turn)
+        };
+
+        // 取消排队（如请求超时）时从队列中移除，轮到自己时把机会让给下一个
+        // Leave the queue when cancelled (such as on a timeout), passing the turn on if it was ours
+        let guard = Leave { queue: self, key, ticket };
+        let _ = turn.await;
+        let permit = self.semaphore.clone().acquire_owned().await;
+        drop(guard);
+        permit
+    }
+}
+
+/// 排队者离开队列时的清理
+/// Cleanup when a queued request leaves the queue
+struct Leave<'a> {
+    queue: &'a FairQueue,
+    key: &'a str,
+    ticket: u64,
+}
+
+impl Drop for Leave<'_> {
+    fn drop(&mut self) {
+        let mut dispatch = self.queue.dispatch.lock().unwrap();
+        if dispatch.head == Some(self.ticket) {
+            dispatch.promote();
+        } else if let Some(lane) = dispatch.lanes.get_mut(self.key) {
+            lane.waiters.retain(|(ticket, _)| *ticket != self.ticket);
+        }
+    }
+}
@@ -0,0 +1,104 @@
+// 数据序列化
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// 提供商表示安全拦截的结束原因，如 Gemini 的 `SAFETY` 与 `PROHIBITED_CONTENT`
+/// Finish reasons by which providers signal a safety block, such as Gemini's `SAFETY` and `PROHIBITED_CONTENT`
+const SAFETY_REASONS: [&str; 7] =
+    ["safety", "prohibited_content", "blocklist", "spii", "recitation", "image_safety", "language"];
+
+/// 模型拒绝回答的原因，记录在助手消息的元数据中
+/// Why the model refused to answer, recorded in the metadata of the assistant message
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum RefusalReason {
+    /// 模型自己拒绝，附其说明，如 OpenAI 的 `refusal` 字段与 Anthropic 的 `refusal` 结束原因
+    /// The model itself declined, with its explanation, such as OpenAI's `refusal` field and Anthropic's `refusal`
+    /// stop reason
+    Declined { message: String },
+
+    /// 提供商的内容过滤器中止了输出（`finish_reason` 为 `content_filter`）
+    /// The provider's content filter stopped the output (`finish_reason` is `content_filter`)
+    ContentFilter,
+
+    /// 提供商的安全拦截，附其类别，如 Gemini 的 `safety` 或拦截提示词的 `block_reason`
+    /// Safety block of the provider with its category, such as Gemini's `safety` or the `block_reason` of a blocked
+    /// prompt
+    Safety { category: String },
+}
+
+impl std::fmt::Display for RefusalReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Declined { message } => write!(f, "declined: {}", message),
+            Self::ContentFilter => write!(f, "content filter"),
+            Self::Safety { category } => write!(f, "safety block: {}", category),
+        }
+    }
+}
+
+impl RefusalReason {
+    /// 由结束原因与流式输出中的 `refusal` 文字判断是否拒绝
+    /// Tell a refusal from the finish reason and the `refusal` text of a streamed output
+    pub(crate) fn from_finish(finish_reason: Option<&str>, refusal: &str) -> Option<Self> {
+        if !refusal.is_empty() {
+            return Some(Self::Declined {
+                message: refusal.to_string(),
+            });
+        }
+        let finish_reason = finish_reason?.to_ascii_lowercase();
+        match finish_reason.as_str() {
+            "content_filter" => Some(Self::ContentFilter),
+            "refusal" => Some(Self::Declined { message: String::new() }),
+            reason if SAFETY_REASONS.contains(&reason) => Some(Self::Safety { category: finish_reason }),
+            _ => None,
+        }
+    }
+
+    /// 由非流式响应判断是否拒绝，兼容转发 Anthropic 与 Gemini 原生字段的网关
+    /// Tell a refusal from a non-streamed response, also covering gateways that pass Anthropic and Gemini native
+    /// fields through
+    pub(crate) fn from_response(response: &Value) -> Option<Self> {
+        if let Some(category) = response["promptFeedback"]["blockReason"].as_str() {
+            return Some(Self::Safety {
+                category: category.to_ascii_lowercase(),
+            });
+        }
+        let choice = &response["choices"][0];
+        let refusal = choice["message"]["refusal"].as_str().unwrap_or_default();
+        let finish_reason = choice["finish_reason"]
+            .as_str()
+            .or(response["stop_reason"].as_str())
+            .or(response["candidates"][0]["finishReason"].as_str());
+        match Self::from_finish(finish_reason, refusal)? {
+            Self::Declined { message } if message.is_empty() => Some(Self::Declined {
+                message: choice["message"]["content"].as_str().unwrap_or_default().to_string(),
+            }),
+            reason => Some(reason),
+        }
+    }
+}
+
+/// 回答的结果：正常回答，或模型与提供商拒绝回答
+/// Outcome of an answer: a regular answer, or a refusal by the model or the provider
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "outcome", rename_all = "snake_case")]
+pub enum AnswerOutcome {
+    Answered { answer: String },
+    Refused { reason: RefusalReason },
+}
+
+impl AnswerOutcome {
+    pub fn is_refused(&self) -> bool {
+        matches!(self, Self::Refused { .. })
+    }
+
+    /// 正常回答的内容，拒绝时为 None
+    /// Content of a regular answer, None for a refusal
+    pub fn answer(&self) -> Option<&str> {
+        match self {
+            Self::Answered { answer } => Some(answer),
+            Self::Refused { .. } => None,
+        }
+    }
+}
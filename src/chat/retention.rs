@@ -0,0 +1,290 @@
+// 标准库
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+// 错误处理
+use error_stack::{Result, ResultExt};
+
+// 异步
+use tokio::task::AbortHandle;
+
+// 观测诊断
+use tracing::{info, warn};
+
+// 项目内部模块
+use crate::chat::store::{SessionEntry, SessionStore, StoreError, StoredSession};
+use crate::chat::tenant::Tenant;
+use crate::utils::common::file_name::encode_file_name;
+
+/// 删除前接收对话的冷存储，归档失败的对话不会被删除
+/// Cold storage receiving conversations before they are deleted, conversations that fail to archive are not deleted
+pub trait Archiver: Send + Sync {
+    fn archive(&self, id: &str, stored: &StoredSession) -> Result<(), StoreError>;
+}
+
+/// 函数可直接作为归档
+/// Functions can be used directly as archivers
+impl<F> Archiver for F
+where
+    F: Fn(&str, &StoredSession) -> Result<(), StoreError> + Send + Sync,
+{
+    fn archive(&self, id: &str, stored: &StoredSession) -> Result<(), StoreError> {
+        self(id, stored)
+    }
+}
+
+/// 归档到目录，每个对话一个 JSON 文件，文件名是ID经 `encode_file_name` 编码的结果，同一对话再次归档时覆盖
+/// Archive into a directory, one JSON file per conversation named by its ID encoded with `encode_file_name`,
+/// overwritten when the same conversation is archived again
+#[derive(Clone, Debug)]
+pub struct DirectoryArchiver {
+    directory: PathBuf,
+}
+
+impl DirectoryArchiver {
+    pub fn new(directory: impl Into<PathBuf>) -> Result<Self, StoreError> {
+        let directory = directory.into();
+        fs::create_dir_all(&directory).change_context_lazy(|| StoreError::IoError(directory.display().to_string()))?;
+        Ok(Self { directory })
+    }
+}
+
+impl Archiver for DirectoryArchiver {
+    fn archive(&self, id: &str, stored: &StoredSession) -> Result<(), StoreError> {
+        let path = self.directory.join(format!("{}.json", encode_file_name(id)));
+        let io_error = || StoreError::IoError(path.display().to_string());
+        fs::write(&path, serde_json::to_vec(stored).change_context_lazy(io_error)?).change_context_lazy(io_error)
+    }
+}
+
+/// 对话的保留策略：最长保留时间、对话总数上限与每个租户的对话数上限，超出的对话从最早保存的开始删除
+/// Retention policy of conversations: maximum age, a cap on the total number of conversations and per-tenant caps,
+/// conversations over them are deleted starting from the least recently saved
+///
+/// 对话按 `Tenant::storage_key` 的命名空间前缀（第一个 `/` 之前的部分）归属租户，没有前缀的对话不计入任何租户的上限。
+/// 时间以最后保存的时间计算，仍在进行的对话每次保存都会刷新。
+/// Conversations belong to a tenant by the namespace prefix of `Tenant::storage_key` (the part before the first
+/// `/`), conversations without a prefix count towards no tenant's cap. Ages count from the last save, which every
+/// save of an ongoing conversation refreshes.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RetentionPolicy {
+    pub max_age: Option<Duration>,
+
+    pub max_conversations: Option<usize>,
+
+    /// 每个租户的默认对话数上限
+    /// Default conversation cap of every tenant
+    pub per_tenant: Option<usize>,
+
+    /// 按命名空间覆盖的对话数上限
+    /// Conversation caps overridden per namespace
+    pub tenant_quotas: HashMap<String, usize>,
+}
+
+impl RetentionPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    pub fn max_conversations(mut self, max: usize) -> Self {
+        self.max_conversations = Some(max);
+        self
+    }
+
+    pub fn per_tenant(mut self, max: usize) -> Self {
+        self.per_tenant = Some(max);
+        self
+    }
+
+    /// 为租户单独设置对话数上限，覆盖 `per_tenant`
+    /// Set a conversation cap for one tenant, overriding `per_tenant`
+    pub fn tenant_quota(mut self, tenant: &Tenant, max: usize) -> Self {
+        self.tenant_quotas.insert(tenant.namespace.clone(), max);
+        self
+    }
+
+    fn quota(&self, namespace: &str) -> Option<usize> {
+        self.tenant_quotas.get(namespace).copied().or(self.per_tenant)
+    }
+
+    /// 按策略选出要删除的对话，结果按保存时间从早到晚排列
+    /// Pick the conversations to delete by the policy, ordered from the least recently saved
+    fn victims(&self, mut entries: Vec<SessionEntry>, now: SystemTime) -> Vec<SessionEntry> {
+        entries.sort_by(|a, b| b.saved_at.cmp(&a.saved_at).then_with(|| a.id.cmp(&b.id)));
+        let mut kept = 0;
+        let mut per_namespace: HashMap<String, usize> = HashMap::new();
+        let mut victims = Vec::new();
+        for entry in entries {
+            let age = now.duration_since(entry.saved_at).unwrap_or_default();
+            let expired = self.max_age.is_some_and(|max_age| age > max_age);
+            let namespace = entry.id.split_once('/').map(|(namespace, _)| namespace.to_string());
+            let over_quota = namespace.as_ref().is_some_and(|namespace| {
+                let count = per_namespace.get(namespace).copied().unwrap_or(0);
+                self.quota(namespace).is_some_and(|quota| count >= quota)
+            });
+            let over_total = self.max_conversations.is_some_and(|max| kept >= max);
+            if expired || over_quota || over_total {
+                victims.push(entry);
+                continue;
+            }
+            kept += 1;
+            if let Some(namespace) = namespace {
+                *per_namespace.entry(namespace).or_default() += 1;
+            }
+        }
+        victims.reverse();
+        victims
+    }
+}
+
+/// 一次清理的结果
+/// Result of one sweep
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RetentionReport {
+    /// 检查的对话数
+    /// Number of conversations examined
+    pub examined: usize,
+
+    /// 已删除的对话ID
+    /// IDs of the deleted conversations
+    pub removed: Vec<String>,
+
+    /// 删除前已归档的对话数
+    /// Number of conversations archived before deletion
+    pub archived: usize,
+
+    /// 归档或删除失败而保留的对话ID，下次清理时重试
+    /// IDs of conversations kept because archiving or deleting failed, retried on the next sweep
+    pub failed: Vec<String>,
+
+    /// 清理期间又被保存而保留的对话ID
+    /// IDs of conversations kept because they were saved again during the sweep
+    pub refreshed: Vec<String>,
+}
+
+/// 单个对话的清理结果
+/// Outcome of retiring one conversation
+enum Retired {
+    Removed { archived: bool },
+    Refreshed,
+}
+
+/// 在存储后端上执行保留策略，可手动清理，也可在后台定期清理，使长期运行的部署不会无限增长
+/// Enforces a retention policy over a storage backend, swept by hand or periodically in the background, so
+/// long-running deployments do not grow unbounded
+///
+/// ```ignore
+/// let retention = Retention::new(store.clone(), RetentionPolicy::new().max_age(Duration::from_secs(90 * 86400)))
+///     .archiver(DirectoryArchiver::new("archive")?);
+/// let task = retention.spawn(Duration::from_secs(3600));
+/// ```
+#[derive(Clone)]
+pub struct Retention {
+    store: Arc<dyn SessionStore>,
+    policy: RetentionPolicy,
+    archiver: Option<Arc<dyn Archiver>>,
+}
+
+impl Debug for Retention {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Retention")
+            .field("policy", &self.policy)
+            .field("archiver", &self.archiver.is_some())
+            .finish()
+    }
+}
+
+impl Retention {
+    pub fn new(store: Arc<dyn SessionStore>, policy: RetentionPolicy) -> Self {
+        Self {
+            store,
+            policy,
+            archiver: None,
+        }
+    }
+
+    /// 删除前先归档到冷存储
+    /// Archive to cold storage before deleting
+    pub fn archiver(mut self, archiver: impl Archiver + 'static) -> Self {
+        self.archiver = Some(Arc::new(archiver));
+        self
+    }
+
+    /// 清理一次；单个对话归档或删除失败时记录在报告中并继续，列出对话失败时返回错误
+    /// Sweep once; a conversation that fails to archive or delete is recorded in the report and the sweep goes on,
+    /// an error is returned if the conversations cannot be listed
+    pub async fn sweep(&self) -> Result<RetentionReport, StoreError> {
+        let entries = self.store.list().await?;
+        let mut report = RetentionReport {
+            examined: entries.len(),
+            ..RetentionReport::default()
+        };
+        for entry in self.policy.victims(entries, SystemTime::now()) {
+            match self.retire(&entry.id).await {
+                Ok(Retired::Removed { archived }) => {
+                    report.archived += archived as usize;
+                    report.removed.push(entry.id);
+                }
+                Ok(Retired::Refreshed) => report.refreshed.push(entry.id),
+                Err(e) => {
+                    warn!("Failed to retire conversation {}: {:?}", entry.id, e);
+                    report.failed.push(entry.id);
+                }
+            }
+        }
+        if !report.removed.is_empty() || !report.failed.is_empty() {
+            info!(
+                "Retention sweep removed {} of {} conversations, {} failed",
+                report.removed.len(),
+                report.examined,
+                report.failed.len()
+            );
+        }
+        Ok(report)
+    }
+
+    /// 归档并删除对话；删除以读取时的版本号比较并交换，归档期间又被保存的对话保留
+    /// Archive and delete a conversation; deletion compares and swaps on the revision read, so a conversation saved
+    /// again while it was being archived is kept
+    async fn retire(&self, id: &str) -> Result<Retired, StoreError> {
+        let Some(stored) = self.store.load(id).await? else {
+            return Ok(Retired::Removed { archived: false });
+        };
+        if let Some(archiver) = &self.archiver {
+            archiver.archive(id, &stored)?;
+        }
+        match self.store.remove_revision(id, stored.revision).await {
+            Ok(()) => Ok(Retired::Removed {
+                archived: self.archiver.is_some(),
+            }),
+            Err(e) if matches!(e.current_context(), StoreError::Conflict { .. }) => Ok(Retired::Refreshed),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// 在后台每隔 `every` 清理一次，首次在一个间隔之后。返回的句柄用于停止后台任务
+    /// Sweep every `every` in the background, the first time one interval from now. The returned handle stops the
+    /// background task
+    pub fn spawn(self, every: Duration) -> AbortHandle {
+        let handle = tokio::spawn(async move {
+            let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + every, every);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                interval.tick().await;
+                if let Err(e) = self.sweep().await {
+                    warn!("Retention sweep failed: {:?}", e);
+                }
+            }
+        });
+        handle.abort_handle()
+    }
+}
@@ -0,0 +1,114 @@
+// 异步
+use futures::future::BoxFuture;
+
+// 数据序列化
+use serde::{Deserialize, Serialize};
+
+/// 回答中引用资料时的标注要求，随引用模式的检索结果发送
+/// Instruction on marking cited material, sent with the retrieval results in citation mode
+pub const CITATION_INSTRUCTION: &str =
+    "回答中用到参考资料时，在相应句子末尾标注资料编号，如 [1] 或 [1][3]；不要标注未用到或不存在的编号。";
+
+/// 检索到的资料片段，带有ID与出处
+/// A retrieved piece of material, with its ID and origin
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Source {
+    /// 片段ID，如向量库中的ID，用于回溯原文
+    /// Chunk ID, such as its ID in a vector store, for tracing back to the original
+    pub id: String,
+
+    /// 出处，如文件名与页码
+    /// Origin, such as a file name and page number
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub origin: Option<String>,
+
+    pub text: String,
+}
+
+impl Source {
+    pub fn new(id: &str, text: &str) -> Self {
+        Self {
+            id: id.to_string(),
+            origin: None,
+            text: text.to_string(),
+        }
+    }
+
+    pub fn origin(mut self, origin: &str) -> Self {
+        self.origin = Some(origin.to_string());
+        self
+    }
+
+    /// 作为上下文发送的文本，有出处时以出处开头
+    /// Text sent as context, led by the origin if there is one
+    pub fn to_context(&self) -> String {
+        match &self.origin {
+            Some(origin) => format!("[{}]\n{}", origin, self.text),
+            None => self.text.clone(),
+        }
+    }
+}
+
+/// 检索器，为用户输入查找相关资料，检索结果作为上下文随请求发送
+/// Retriever, looks up material relevant to the user input; the results are sent with the request as context
+pub trait Retriever: Send + Sync {
+    /// 检索与查询相关的文档片段，没有结果时返回空列表
+    /// Retrieve document snippets relevant to the query, an empty list if nothing matches
+    fn retrieve<'a>(&'a self, query: &'a str) -> BoxFuture<'a, Vec<String>>;
+
+    /// 检索带有ID与出处的片段，用于引用追踪；默认以 `retrieve` 的结果按序号编号，没有出处
+    /// Retrieve snippets with their IDs and origins, for citation tracking; by default the results of `retrieve`
+    /// are numbered in order, without origins
+    fn retrieve_sources<'a>(&'a self, query: &'a str) -> BoxFuture<'a, Vec<Source>> {
+        Box::pin(async move {
+            self.retrieve(query)
+                .await
+                .into_iter()
+                .enumerate()
+                .map(|(index, text)| Source::new(&(index + 1).to_string(), &text))
+                .collect()
+        })
+    }
+}
+
+/// 将检索结果作为系统消息插入请求体，位于最后一条消息之前
+/// Insert retrieval results into a request body as a system message, right before the last message
+///
+/// 检索结果只进入本次请求，不写入会话历史。
+/// The results only go into this request and are not written to the session history.
+pub fn insert_context(request_body: &mut serde_json::Value, documents: &[String]) {
+    insert_context_with(request_body, documents, None);
+}
+
+/// 同 `insert_context`，并要求模型以资料编号标注引用
+/// Like `insert_context`, also asking the model to mark citations with the material numbers
+pub fn insert_cited_context(request_body: &mut serde_json::Value, documents: &[String]) {
+    insert_context_with(request_body, documents, Some(CITATION_INSTRUCTION));
+}
+
+fn insert_context_with(request_body: &mut serde_json::Value, documents: &[String], instruction: Option<&str>) {
+    if documents.is_empty() {
+        return;
+    }
+
+    let context = documents
+        .iter()
+        .enumerate()
+        .map(|(index, document)| format!("[{}] {}", index + 1, document))
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    let mut content = format!("以下是与问题相关的参考资料：\n{}", context);
+    if let Some(instruction) = instruction {
+        content.push_str("\n\n");
+        content.push_str(instruction);
+    }
+    let message = serde_json::json!({
+        "role": "system",
+        "content": content,
+    });
+
+    if let Some(messages) = request_body.get_mut("messages").and_then(|messages| messages.as_array_mut()) {
+        let position = messages.len().saturating_sub(1);
+        messages.insert(position, message);
+    }
+}
@@ -0,0 +1,193 @@
+// 标准库
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+
+// 错误处理
+use error_stack::{Report, Result};
+
+// 异步
+use futures::future::BoxFuture;
+
+// 数据序列化
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{Value, json};
+
+// 项目内部模块
+use crate::chat::chat_base::ChatError;
+use crate::chat::tool_source::{ToolSource, function_tool};
+use crate::config::Config;
+use crate::config::prompts::PromptKey;
+use crate::schema::tool_schema::ChatToolSchemaError;
+
+/// 写入草稿本的工具名称
+/// Name of the tool writing to the scratchpad
+pub const SCRATCHPAD_WRITE: &str = "scratchpad_write";
+
+/// 读取草稿本的工具名称
+/// Name of the tool reading the scratchpad
+pub const SCRATCHPAD_READ: &str = "scratchpad_read";
+
+/// 对话的草稿本：模型在多轮之间记录中间结果的笔记，按键保存，随会话一起保存与加载
+/// Scratchpad of a chat: notes the model keeps across turns for intermediate results, stored by key and saved and
+/// loaded together with the session
+///
+/// 笔记不进入提示词，只有模型调用 `scratchpad_read` 时才作为工具结果读出，长时间的推理因此不会让上下文膨胀。
+/// 克隆得到一份独立的副本，如检查点中的会话不会随之后的写入改变。
+/// Notes stay out of the prompt and only come back as a tool result when the model calls `scratchpad_read`, so
+/// long reasoning sessions do not bloat the context. Clones are independent copies, so a session in a checkpoint
+/// does not change with later writes.
+#[derive(Default)]
+pub struct Scratchpad {
+    notes: Arc<Mutex<BTreeMap<String, String>>>,
+}
+
+impl Clone for Scratchpad {
+    fn clone(&self) -> Self {
+        Self {
+            notes: Arc::new(Mutex::new(self.notes())),
+        }
+    }
+}
+
+impl PartialEq for Scratchpad {
+    fn eq(&self, other: &Self) -> bool {
+        self.notes() == other.notes()
+    }
+}
+
+impl Eq for Scratchpad {}
+
+impl Debug for Scratchpad {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Scratchpad").field(&self.notes()).finish()
+    }
+}
+
+impl Serialize for Scratchpad {
+    fn serialize<S: Serializer>(&self, serializer: S) -> core::result::Result<S::Ok, S::Error> {
+        self.notes().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Scratchpad {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> core::result::Result<Self, D::Error> {
+        let notes = BTreeMap::deserialize(deserializer)?;
+        Ok(Self {
+            notes: Arc::new(Mutex::new(notes)),
+        })
+    }
+}
+
+impl Scratchpad {
+    pub fn get(&self, key: &str) -> Option<String> {
+        self.notes.lock().unwrap().get(key).cloned()
+    }
+
+    pub fn set(&self, key: &str, note: &str) {
+        self.notes.lock().unwrap().insert(key.to_string(), note.to_string());
+    }
+
+    pub fn remove(&self, key: &str) -> Option<String> {
+        self.notes.lock().unwrap().remove(key)
+    }
+
+    pub fn clear(&self) {
+        self.notes.lock().unwrap().clear();
+    }
+
+    /// 全部笔记，按键排序
+    /// Every note, sorted by key
+    pub fn notes(&self) -> BTreeMap<String, String> {
+        self.notes.lock().unwrap().clone()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.notes.lock().unwrap().is_empty()
+    }
+
+    /// 共享同一份笔记的句柄，工具调用通过它写入会话中的草稿本
+    /// Handle sharing the same notes, through which tool calls write to the scratchpad in the session
+    pub(crate) fn share(&self) -> Self {
+        Self {
+            notes: self.notes.clone(),
+        }
+    }
+}
+
+/// 草稿本工具的定义
+/// Definitions of the scratchpad tools
+pub(crate) fn scratchpad_tools() -> Vec<Value> {
+    let prompt = |key| Config::capability_prompt(&key);
+    let write = json!({
+        "type": "object",
+        "properties": {
+            "key": {"type": "string", "description": prompt(PromptKey::ScratchpadKeyArgument)},
+            "content": {"type": "string", "description": prompt(PromptKey::ScratchpadContentArgument)},
+            "append": {"type": "boolean", "description": prompt(PromptKey::ScratchpadAppendArgument)},
+        },
+        "required": ["key", "content"],
+    });
+    let read = json!({
+        "type": "object",
+        "properties": {"key": {"type": "string", "description": prompt(PromptKey::ScratchpadReadKeyArgument)}},
+    });
+    vec![
+        function_tool(SCRATCHPAD_WRITE, Some(&prompt(PromptKey::ScratchpadWriteDescription)), Some(&write)),
+        function_tool(SCRATCHPAD_READ, Some(&prompt(PromptKey::ScratchpadReadDescription)), Some(&read)),
+    ]
+}
+
+/// 绑定到某个会话草稿本的工具来源
+/// Tool source bound to the scratchpad of one session
+pub(crate) struct ScratchpadTools(pub(crate) Scratchpad);
+
+impl ToolSource for ScratchpadTools {
+    fn namespace(&self) -> Option<&str> {
+        None
+    }
+
+    fn list_tools(&self) -> BoxFuture<'_, Result<Vec<Value>, ChatError>> {
+        Box::pin(async move { Ok(scratchpad_tools()) })
+    }
+
+    fn call_tool<'a>(&'a self, name: &'a str, arguments: Value) -> BoxFuture<'a, Result<Value, ChatToolSchemaError>> {
+        Box::pin(async move {
+            let params_error = || ChatToolSchemaError::ParamsParseError(name.to_string(), arguments.to_string());
+            let key = arguments["key"].as_str();
+            match name {
+                SCRATCHPAD_WRITE => {
+                    let (Some(key), Some(content)) = (key, arguments["content"].as_str()) else {
+                        return Err(Report::new(params_error()));
+                    };
+                    let mut notes = self.0.notes.lock().unwrap();
+                    if content.is_empty() {
+                        notes.remove(key);
+                        return Ok(json!({"status": "removed", "key": key}));
+                    }
+                    let note = notes.entry(key.to_string()).or_default();
+                    match arguments["append"].as_bool() == Some(true) && !note.is_empty() {
+                        true => {
+                            note.push('\n');
+                            note.push_str(content);
+                        }
+                        false => *note = content.to_string(),
+                    }
+                    Ok(json!({"status": "saved", "key": key, "length": note.chars().count()}))
+                }
+                // 读取不存在的笔记时列出已有的名称
+                // Reading a missing note lists the existing keys
+                SCRATCHPAD_READ => match key.map(|key| (key, self.0.get(key))) {
+                    Some((key, Some(content))) => Ok(json!({"key": key, "content": content})),
+                    Some((key, None)) => {
+                        let keys: Vec<String> = self.0.notes().into_keys().collect();
+                        Ok(json!({"key": key, "content": null, "keys": keys}))
+                    }
+                    None => Ok(json!({"notes": self.0.notes()})),
+                },
+                _ => Err(Report::new(ChatToolSchemaError::FunctionCallError)
+                    .attach_printable(format!("No tool named {}", name))),
+            }
+        })
+    }
+}
@@ -0,0 +1,307 @@
+// 标准库
+use std::fmt::Debug;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+// 错误处理
+use error_stack::Result;
+
+// 随机数
+use rand::Rng;
+
+// 数据序列化
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+// 观测诊断
+use tracing::warn;
+
+// 项目内部模块
+use crate::chat::chat_base::{BaseChat, ChatError};
+use crate::chat::tenant::Tenant;
+use crate::config::Config;
+use crate::telemetry::exporter::rfc3339;
+
+/// 接收影子流量的候选：API，可选的替换系统提示词，以及复制的请求比例
+/// Candidate receiving shadow traffic: an API, an optional replacement system prompt and the share of requests
+/// copied to it
+#[derive(Clone, Debug, PartialEq)]
+pub struct ShadowCandidate {
+    label: String,
+    api: String,
+    prompt: Option<String>,
+    percent: f64,
+}
+
+impl ShadowCandidate {
+    /// `percent` 为复制到该候选的请求百分比，限制在 0 到 100 之间，非有限值（如 NaN）视为 0
+    /// `percent` is the percentage of requests copied to this candidate, clamped to 0 through 100, non-finite values
+    /// (such as NaN) count as 0
+    pub fn new(api: &str, percent: f64) -> Self {
+        Self {
+            label: api.to_string(),
+            api: api.to_string(),
+            prompt: None,
+            percent: if percent.is_finite() { percent.clamp(0.0, 100.0) } else { 0.0 },
+        }
+    }
+
+    /// 用此系统提示词替换请求开头的系统消息，用于比较提示词的改动
+    /// Replace the system messages at the head of the request with this prompt, for comparing prompt changes
+    pub fn prompt(mut self, prompt: &str) -> Self {
+        self.prompt = Some(prompt.to_string());
+        self
+    }
+
+    /// 记录中的候选名称，默认为API名称；同一API配不同提示词时用以区分
+    /// Candidate name in the records, the API name by default; tells apart one API with different prompts
+    pub fn label(mut self, label: &str) -> Self {
+        self.label = label.to_string();
+        self
+    }
+
+    /// 按候选的提示词与模型改写请求体，并关闭流式输出
+    /// Rewrite the request body for the candidate's prompt and model, turning streaming off
+    fn request_body(&self, mut request_body: Value, model: &str) -> Value {
+        request_body["model"] = json!(model);
+        request_body["stream"] = json!(false);
+        if let Some(body) = request_body.as_object_mut() {
+            body.remove("stream_options");
+        }
+        if let (Some(prompt), Some(messages)) = (&self.prompt, request_body["messages"].as_array_mut()) {
+            let leading = messages.iter().take_while(|message| message["role"] == "system").count();
+            messages.splice(..leading, [json!({"role": "system", "content": prompt})]);
+        }
+        request_body
+    }
+}
+
+/// 一次影子请求的记录：同一请求的线上回答与候选回答，供离线比较
+/// Record of one shadow request: the live answer and the candidate's answer to the same request, for offline
+/// comparison
+///
+/// 输入与回答都是发给提供商的形式，设置了敏感信息清洗时其中是占位符。
+/// The input and answers are as sent to the provider, holding placeholders when PII scrubbing is set.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ShadowRecord {
+    /// RFC 3339 时间
+    /// RFC 3339 time
+    pub timestamp: String,
+
+    pub session_id: String,
+
+    /// 候选名称，见 `ShadowCandidate::label`
+    /// Candidate name, see `ShadowCandidate::label`
+    pub candidate: String,
+
+    /// 请求中的最后一条消息
+    /// The last message of the request
+    pub input: String,
+
+    pub primary_model: String,
+
+    pub primary_answer: String,
+
+    pub primary_latency_ms: u64,
+
+    pub candidate_model: String,
+
+    /// 候选的回答，请求失败时为 None
+    /// The candidate's answer, None if its request failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub candidate_answer: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub candidate_error: Option<String>,
+
+    pub candidate_latency_ms: u64,
+}
+
+/// 影子记录的去处
+/// Destination of shadow records
+pub trait ShadowLog: Send + Sync {
+    fn record(&self, record: &ShadowRecord);
+}
+
+/// 保存在内存中的影子记录，克隆共享同一份记录
+/// Shadow records kept in memory, clones share the records
+#[derive(Clone, Debug, Default)]
+pub struct MemoryShadowLog {
+    records: Arc<Mutex<Vec<ShadowRecord>>>,
+}
+
+impl MemoryShadowLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn records(&self) -> Vec<ShadowRecord> {
+        self.records.lock().unwrap().clone()
+    }
+}
+
+impl ShadowLog for MemoryShadowLog {
+    fn record(&self, record: &ShadowRecord) {
+        self.records.lock().unwrap().push(record.clone());
+    }
+}
+
+/// 每条记录一行 JSON，追加写入文件；写入失败时记录警告并丢弃该条记录
+/// One JSON line per record, appended to a file; a failed write logs a warning and drops the record
+#[derive(Debug)]
+pub struct JsonlShadowLog {
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl JsonlShadowLog {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            lock: Mutex::new(()),
+        }
+    }
+}
+
+impl ShadowLog for JsonlShadowLog {
+    fn record(&self, record: &ShadowRecord) {
+        let _guard = self.lock.lock().unwrap();
+        let written = serde_json::to_string(record).map_err(|e| e.to_string()).and_then(|line| {
+            let mut file =
+                OpenOptions::new().create(true).append(true).open(&self.path).map_err(|e| e.to_string())?;
+            writeln!(file, "{}", line).map_err(|e| e.to_string())
+        });
+        if let Err(e) = written {
+            warn!("Failed to write shadow record to {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+/// 影子流量：按比例把线上请求复制给候选模型或提示词，异步请求且不影响返回给用户的回答，两边的输出写入记录以便离线比较；
+/// 是评估更换提供商最安全的方式
+/// Shadow traffic: copies a share of live requests to candidate models or prompts, asynchronously and without
+/// affecting the answer returned to the user, logging both outputs for offline comparison; the safest way to
+/// evaluate a provider switch
+///
+/// 作用于 `get_answer` 及基于它的方法，复制的是实际发出的请求（包括检索与触发工具的结果）。每个候选各自按比例抽样，
+/// 影子请求带有 `shadow` 标签，计入用量，并受原对话租户的预算与频率上限约束。
+/// Applies to `get_answer` and the methods built on it, copying the request actually sent (retrieval and triggered
+/// tool results included). Each candidate samples on its own, and shadow requests carry the `shadow` tag, count
+/// towards usage and are bound by the budget and rate limit of the chat's tenant.
+///
+/// ```ignore
+/// let shadow = Shadow::new(JsonlShadowLog::new("shadow.jsonl"))
+///     .candidate(ShadowCandidate::new("claude-next", 10.0))
+///     .candidate(ShadowCandidate::new("gpt-4o", 5.0).prompt(NEW_PROMPT).label("gpt-4o-new-prompt"));
+/// chat.set_shadow(shadow);
+/// ```
+#[derive(Clone)]
+pub struct Shadow {
+    candidates: Vec<ShadowCandidate>,
+    log: Arc<dyn ShadowLog>,
+}
+
+impl Debug for Shadow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Shadow").field("candidates", &self.candidates).finish()
+    }
+}
+
+impl Shadow {
+    pub fn new(log: impl ShadowLog + 'static) -> Self {
+        Self {
+            candidates: Vec::new(),
+            log: Arc::new(log),
+        }
+    }
+
+    pub fn candidate(mut self, candidate: ShadowCandidate) -> Self {
+        self.candidates.push(candidate);
+        self
+    }
+
+    /// 为本次请求抽样的候选
+    /// Candidates sampled for this request
+    pub(crate) fn sample(&self) -> Vec<ShadowCandidate> {
+        let mut rng = rand::rng();
+        self.candidates
+            .iter()
+            .filter(|candidate| rng.random_bool(candidate.percent / 100.0))
+            .cloned()
+            .collect()
+    }
+
+    /// 在后台把请求发给抽中的候选，完成后写入记录；`answer` 为线上回答，`latency` 为其耗时
+    /// Send the request to the sampled candidates in the background and log the records once done; `answer` is the
+    /// live answer and `latency` the time it took
+    pub(crate) fn mirror(
+        &self,
+        chat: &BaseChat,
+        candidates: Vec<ShadowCandidate>,
+        request_body: &Value,
+        answer: &str,
+        latency: Duration,
+    ) {
+        let input = request_body["messages"]
+            .as_array()
+            .and_then(|messages| messages.last())
+            .map(|message| match &message["content"] {
+                Value::String(text) => text.clone(),
+                content => content.to_string(),
+            })
+            .unwrap_or_default();
+        for candidate in candidates {
+            let record = ShadowRecord {
+                timestamp: rfc3339(SystemTime::now()),
+                session_id: chat.session_id.clone(),
+                candidate: candidate.label.clone(),
+                input: input.clone(),
+                primary_model: chat.model.clone(),
+                primary_answer: answer.to_string(),
+                primary_latency_ms: latency.as_millis() as u64,
+                ..ShadowRecord::default()
+            };
+            let log = self.log.clone();
+            let tags = chat.tags.clone();
+            let tenant = chat.tenant.clone();
+            let request_body = request_body.clone();
+            tokio::spawn(Config::in_current_scope(async move {
+                let started = Instant::now();
+                let result = ask_candidate(&candidate, tags, tenant, request_body).await;
+                let (candidate_model, answer) = match result {
+                    Ok((model, answer)) => (model, answer),
+                    Err(report) => (String::new(), Err(report)),
+                };
+                let record = ShadowRecord {
+                    candidate_model,
+                    candidate_latency_ms: started.elapsed().as_millis() as u64,
+                    candidate_error: answer.as_ref().err().map(|report| report.current_context().to_string()),
+                    candidate_answer: answer.ok(),
+                    ..record
+                };
+                log.record(&record);
+            }));
+        }
+    }
+}
+
+/// 以原对话的租户向候选发送请求，返回候选的模型与回答；候选的API不存在时返回错误
+/// Send the request to the candidate on behalf of the chat's tenant, returning its model and answer; an error if
+/// the candidate's API does not exist
+async fn ask_candidate(
+    candidate: &ShadowCandidate,
+    tags: Vec<String>,
+    tenant: Option<Tenant>,
+    request_body: Value,
+) -> Result<(String, Result<String, ChatError>), ChatError> {
+    let mut chat = BaseChat::try_new_with_api_name(&candidate.api, "", false)?;
+    chat.tags = tags;
+    chat.tenant = tenant;
+    chat.tags.push("shadow".to_string());
+    let request_body = candidate.request_body(request_body, &chat.model);
+    let answer = chat.get_content(request_body).await;
+    Ok((chat.model, answer))
+}
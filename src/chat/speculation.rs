@@ -0,0 +1,126 @@
+// 标准库
+use std::fmt::Debug;
+use std::mem;
+use std::sync::{Arc, Mutex};
+
+// 异步
+use futures::FutureExt;
+use futures::future::{BoxFuture, Shared};
+use tokio::runtime::Handle;
+use tokio::task::AbortHandle;
+
+// 项目内部模块
+use crate::chat::message::MessageMetadata;
+use crate::config::Config;
+use crate::telemetry::{LlmCall, export_llm_call};
+
+/// 预先生成的回答
+/// A pre-generated answer
+#[derive(Debug, Clone)]
+pub struct SpeculativeAnswer {
+    pub answer: String,
+
+    pub metadata: Option<MessageMetadata>,
+
+    /// 预生成消耗的令牌数
+    /// Tokens spent by the pre-generation
+    pub usage: i32,
+}
+
+/// 预生成的后台任务与它暂存的调用记录；最后一个副本被丢弃时取消任务，未被采用的调用不计入对话
+/// The background task of a speculation and the call records it holds; dropping the last copy cancels the task,
+/// calls that were never used are not charged to the chat
+struct Task {
+    abort: AbortHandle,
+    calls: Arc<Mutex<Vec<LlmCall>>>,
+}
+
+impl Drop for Task {
+    fn drop(&mut self) {
+        self.abort.abort();
+        // 被放弃的预生成仍是真实的花费，导出时不带会话，因而不计入对话的用量
+        // A discarded speculation is still real spend, it is exported without the session so the chat is not
+        // billed for it
+        for mut call in mem::take(&mut *self.calls.lock().unwrap()) {
+            call.session_id.clear();
+            export_llm_call(call);
+        }
+    }
+}
+
+/// 进行中的预生成：为预测的下一轮输入提前生成回答
+/// A running speculation: an answer generated ahead of time for the predicted next input
+#[derive(Clone)]
+pub struct Speculation {
+    /// 预测的输入
+    /// Predicted input
+    pub input: String,
+
+    /// 预生成开始时的会话路径，会话在此之后变化则预生成作废
+    /// Session path when the speculation started, the speculation is void if the session moved since
+    pub path: Vec<usize>,
+
+    answer: Shared<BoxFuture<'static, Option<SpeculativeAnswer>>>,
+
+    task: Arc<Task>,
+}
+
+impl Speculation {
+    /// 在后台开始预生成，失败的预生成视为没有结果；不在 tokio 运行时中时返回 None
+    /// Start a speculation in the background, a failed one counts as having no result; None outside a tokio runtime
+    ///
+    /// # 参数 (Parameters)
+    /// * `calls` - 预生成的调用记录暂存处，采用预生成时由 `take_calls` 取出
+    ///   Where the calls of the speculation are held, taken out by `take_calls` when the speculation is used
+    pub fn spawn<F>(input: &str, path: Vec<usize>, calls: Arc<Mutex<Vec<LlmCall>>>, generate: F) -> Option<Self>
+    where
+        F: Future<Output = Option<SpeculativeAnswer>> + Send + 'static,
+    {
+        let handle = Handle::try_current().ok()?.spawn(Config::in_current_scope(generate));
+        let task = Arc::new(Task {
+            abort: handle.abort_handle(),
+            calls,
+        });
+        Some(Self {
+            input: input.to_string(),
+            path,
+            answer: handle.map(|result| result.ok().flatten()).boxed().shared(),
+            task,
+        })
+    }
+
+    /// 预生成是否适用于给定的输入与会话路径
+    /// Whether the speculation applies to the given input and session path
+    pub fn matches(&self, input: &str, path: &[usize]) -> bool {
+        self.input.trim() == input.trim() && self.path == path
+    }
+
+    /// 等待预生成结果
+    /// Wait for the speculative answer
+    pub async fn answer(&self) -> Option<SpeculativeAnswer> {
+        self.answer.clone().await
+    }
+
+    /// 取出预生成发出的调用记录，由采用预生成的对话记录；之后被丢弃时不再导出它们
+    /// Take the calls made by the speculation for the chat using it to record; they are no longer exported when the
+    /// speculation is dropped afterwards
+    pub(crate) fn take_calls(&self) -> Vec<LlmCall> {
+        mem::take(&mut *self.task.calls.lock().unwrap())
+    }
+
+    /// 放弃预生成，仍在进行的请求会被取消
+    /// Discard the speculation, a request still in flight is cancelled
+    pub fn discard(&self) {
+        self.task.abort.abort();
+    }
+}
+
+impl Debug for Speculation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Speculation")
+            .field("input", &self.input)
+            .field("path", &self.path)
+            .field("finished", &self.task.abort.is_finished())
+            .finish()
+    }
+}
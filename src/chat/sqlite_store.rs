@@ -0,0 +1,223 @@
+// 标准库
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// 错误处理
+use error_stack::{Report, Result, ResultExt};
+
+// 数据库
+use futures::future::BoxFuture;
+use rusqlite::types::Value;
+use rusqlite::{Connection, OptionalExtension, params};
+
+// 项目内部模块
+use crate::chat::message::Session;
+use crate::chat::store::{SessionCodec, SessionEntry, SessionStore, StoreError, StoredSession};
+
+/// SQLite 存储，所有对话保存在一个数据库文件中，可由共用该文件的多个进程同时使用
+/// SQLite storage, every conversation lives in one database file, usable by several processes sharing the file
+///
+/// 比较并交换由带条件的单条 `UPDATE` 完成，不需要额外的锁；数据库以 WAL 模式打开，读取不会阻塞写入。
+/// 数据库调用在阻塞线程池中执行，不占用异步运行时的线程。
+/// Compare-and-swap is a single conditional `UPDATE` and needs no extra lock; the database is opened in WAL mode so
+/// reads do not block writes. Database calls run on the blocking thread pool, off the threads of the async runtime.
+#[derive(Clone)]
+pub struct SqliteSessionStore {
+    path: String,
+    connection: Arc<Mutex<Connection>>,
+    codec: Option<Arc<dyn SessionCodec>>,
+}
+
+impl std::fmt::Debug for SqliteSessionStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SqliteSessionStore")
+            .field("path", &self.path)
+            .field("encoded", &self.codec.is_some())
+            .finish()
+    }
+}
+
+impl SqliteSessionStore {
+    /// 打开或创建数据库文件；其他进程持有写锁时最多等待 `busy_timeout`
+    /// Open or create the database file; waits up to `busy_timeout` while another process holds the write lock
+    pub fn open(path: impl AsRef<Path>, busy_timeout: Duration) -> Result<Self, StoreError> {
+        let path = path.as_ref().display().to_string();
+        let io_error = || StoreError::IoError(path.clone());
+        let connection = Connection::open(&path).change_context_lazy(io_error)?;
+        connection.busy_timeout(busy_timeout).change_context_lazy(io_error)?;
+        connection
+            .pragma_update(None, "journal_mode", "WAL")
+            .change_context_lazy(io_error)?;
+        connection
+            .execute_batch(
+                "CREATE TABLE IF NOT EXISTS sessions (
+                    id TEXT PRIMARY KEY,
+                    revision INTEGER NOT NULL,
+                    session TEXT NOT NULL,
+                    saved_at INTEGER NOT NULL
+                )",
+            )
+            .change_context_lazy(io_error)?;
+        Ok(Self {
+            path,
+            connection: Arc::new(Mutex::new(connection)),
+            codec: None,
+        })
+    }
+
+    /// 对话写入前编码、读出后解码，如静态加密；编码后的对话以二进制保存，启用前写入的文本对话仍可读出
+    /// Encode conversations before writing and decode them after reading, such as for at-rest encryption; encoded
+    /// conversations are stored as blobs and text conversations written before still load
+    pub fn codec(mut self, codec: impl SessionCodec + 'static) -> Self {
+        self.codec = Some(Arc::new(codec));
+        self
+    }
+
+    /// 在阻塞线程池中以数据库连接执行 `task`
+    /// Run `task` with the database connection on the blocking thread pool
+    async fn run<T: Send + 'static>(
+        &self,
+        task: impl FnOnce(&Connection) -> rusqlite::Result<T> + Send + 'static,
+    ) -> Result<T, StoreError> {
+        let connection = self.connection.clone();
+        let io_error = || StoreError::IoError(self.path.clone());
+        tokio::task::spawn_blocking(move || task(&connection.lock().unwrap_or_else(|e| e.into_inner())))
+            .await
+            .change_context_lazy(io_error)?
+            .change_context_lazy(io_error)
+    }
+}
+
+fn revision_of(connection: &Connection, id: &str) -> rusqlite::Result<u64> {
+    connection
+        .query_row("SELECT revision FROM sessions WHERE id = ?1", [id], |row| row.get::<_, i64>(0))
+        .optional()
+        .map(|revision| revision.unwrap_or(0) as u64)
+}
+
+fn unix_millis(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_millis() as i64)
+}
+
+impl SessionStore for SqliteSessionStore {
+    fn load<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<Option<StoredSession>, StoreError>> {
+        Box::pin(async move {
+            let key = id.to_string();
+            let row = self
+                .run(move |connection| {
+                    connection
+                        .query_row("SELECT revision, session FROM sessions WHERE id = ?1", [key], |row| {
+                            Ok((row.get::<_, i64>(0)? as u64, row.get_ref(1)?.as_bytes()?.to_vec()))
+                        })
+                        .optional()
+                })
+                .await?;
+            let Some((revision, session)) = row else {
+                return Ok(None);
+            };
+            let session = match &self.codec {
+                Some(codec) => codec.decode(session).attach_printable_lazy(|| id.to_string())?,
+                None => session,
+            };
+            let session = serde_json::from_slice(&session).change_context_lazy(|| StoreError::IoError(id.to_string()))?;
+            Ok(Some(StoredSession { revision, session }))
+        })
+    }
+
+    fn save<'a>(
+        &'a self,
+        id: &'a str,
+        session: &'a Session,
+        expected_revision: u64,
+    ) -> BoxFuture<'a, Result<u64, StoreError>> {
+        Box::pin(async move {
+            let data = serde_json::to_string(session).change_context_lazy(|| StoreError::IoError(id.to_string()))?;
+            let data = match &self.codec {
+                Some(codec) => Value::Blob(codec.encode(data.into_bytes()).attach_printable_lazy(|| id.to_string())?),
+                None => Value::Text(data),
+            };
+            let key = id.to_string();
+            let saved_at = unix_millis(SystemTime::now());
+            let (written, actual) = self
+                .run(move |connection| {
+                    let written = if expected_revision == 0 {
+                        connection.execute(
+                            "INSERT INTO sessions (id, revision, session, saved_at) VALUES (?1, 1, ?2, ?3)
+                             ON CONFLICT (id) DO NOTHING",
+                            params![key, data, saved_at],
+                        )?
+                    } else {
+                        connection.execute(
+                            "UPDATE sessions SET revision = revision + 1, session = ?2, saved_at = ?3
+                             WHERE id = ?1 AND revision = ?4",
+                            params![key, data, saved_at, expected_revision as i64],
+                        )?
+                    };
+                    // 未写入时读出当前版本号用于报告冲突
+                    // Read the current revision for the conflict report when nothing was written
+                    let actual = if written == 0 { revision_of(connection, &key)? } else { expected_revision + 1 };
+                    Ok((written, actual))
+                })
+                .await?;
+            if written == 0 {
+                return Err(Report::new(StoreError::Conflict {
+                    id: id.to_string(),
+                    expected: expected_revision,
+                    actual,
+                }));
+            }
+            Ok(actual)
+        })
+    }
+
+    fn remove<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<(), StoreError>> {
+        let key = id.to_string();
+        Box::pin(async move {
+            self.run(move |connection| connection.execute("DELETE FROM sessions WHERE id = ?1", [key])).await?;
+            Ok(())
+        })
+    }
+
+    fn remove_revision<'a>(&'a self, id: &'a str, expected_revision: u64) -> BoxFuture<'a, Result<(), StoreError>> {
+        let key = id.to_string();
+        Box::pin(async move {
+            let actual = self
+                .run(move |connection| {
+                    let deleted = connection.execute(
+                        "DELETE FROM sessions WHERE id = ?1 AND revision = ?2",
+                        params![key, expected_revision as i64],
+                    )?;
+                    // 未删除时读出当前版本号，对话已不存在则为 0
+                    // Read the current revision when nothing was deleted, 0 if the conversation no longer exists
+                    if deleted == 0 { revision_of(connection, &key) } else { Ok(0) }
+                })
+                .await?;
+            if actual != 0 {
+                return Err(Report::new(StoreError::Conflict {
+                    id: id.to_string(),
+                    expected: expected_revision,
+                    actual,
+                }));
+            }
+            Ok(())
+        })
+    }
+
+    fn list(&self) -> BoxFuture<'_, Result<Vec<SessionEntry>, StoreError>> {
+        Box::pin(async move {
+            self.run(|connection| {
+                let mut statement = connection.prepare("SELECT id, saved_at FROM sessions")?;
+                let rows = statement.query_map([], |row| {
+                    let saved_at = Duration::from_millis(row.get::<_, i64>(1)?.max(0) as u64);
+                    Ok(SessionEntry {
+                        id: row.get(0)?,
+                        saved_at: UNIX_EPOCH + saved_at,
+                    })
+                })?;
+                rows.collect()
+            })
+            .await
+        })
+    }
+}
@@ -0,0 +1,468 @@
+// 标准库
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+
+// 错误处理
+use error_stack::{Report, Result, ResultExt};
+use thiserror::Error;
+
+// 并发和同步原语
+use dashmap::DashMap;
+use dashmap::mapref::entry::Entry;
+use futures::future::BoxFuture;
+use rand::Rng;
+
+// 数据序列化
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+// 项目内部模块
+use crate::chat::message::Session;
+use crate::utils::common::file_name::{decode_file_name, encode_file_name};
+
+/// 并发冲突时 `update_session` 的最多尝试次数
+/// Most attempts of `update_session` on concurrent conflicts
+pub const MAX_UPDATE_ATTEMPTS: u32 = 5;
+
+/// `update_session` 第一次重试前的基础等待时间，之后每次加倍并加上随机抖动
+/// Base wait of `update_session` before the first retry, doubled on every further retry with random jitter added
+const UPDATE_BACKOFF: Duration = Duration::from_millis(10);
+
+/// 目录存储的锁文件数量，对话按ID的哈希分到其中一个
+/// Number of lock files of the directory storage, conversations are spread over them by the hash of their ID
+const LOCK_STRIPES: usize = 64;
+
+/// 等待目录存储的锁时两次尝试之间的最长间隔
+/// Longest interval between two attempts while waiting for a lock of the directory storage
+const MAX_LOCK_DELAY: Duration = Duration::from_millis(200);
+
+/// 对话存储错误枚举
+/// Conversation store error enum
+#[derive(Debug, Error)]
+pub enum StoreError {
+    /// 保存时存储中的版本号与读取时不同，对话已被其他写入者修改
+    /// The revision in the store differs from the one read, the conversation was modified by another writer
+    #[error("Conversation {id} was modified concurrently: expected revision {expected}, found {actual}")]
+    Conflict { id: String, expected: u64, actual: u64 },
+
+    /// 等待对话锁超时
+    /// Timed out waiting for the conversation lock
+    #[error("Conversation {0} is locked by another writer")]
+    Locked(String),
+
+    /// 读写存储失败
+    /// Reading or writing the store failed
+    #[error("Conversation store IO error: {0}")]
+    IoError(String),
+
+    /// 存储后端不支持该操作
+    /// The storage backend does not support the operation
+    #[error("Conversation store does not support {0}")]
+    Unsupported(String),
+
+    /// 对话内容编码或解码失败，如解密失败
+    /// Encoding or decoding conversation content failed, such as a failed decryption
+    #[error("Conversation codec failed: {0}")]
+    CodecError(String),
+}
+
+/// 存储中的对话及其版本号，每次保存版本号加一，尚未保存过的对话版本号为 0
+/// A stored conversation with its revision, which goes up by one on every save; never saved conversations are at
+/// revision 0
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoredSession {
+    pub revision: u64,
+    pub session: Session,
+}
+
+/// 存储中的一个对话及其最后保存的时间，由 `SessionStore::list` 返回
+/// One conversation in the store with the time it was last saved, returned by `SessionStore::list`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SessionEntry {
+    pub id: String,
+    pub saved_at: SystemTime,
+}
+
+/// 对话存储后端，保存采用比较并交换：只有存储中的版本号仍为读取时的版本号才写入，否则返回 `StoreError::Conflict`，
+/// 多个进程共用同一存储时，并发的写入者不会互相覆盖对方的分支
+/// Storage backend of conversations, saving is compare-and-swap: it only writes if the revision in the store is
+/// still the one read and returns `StoreError::Conflict` otherwise, so concurrent writers sharing one store across
+/// processes do not clobber each other's branches
+///
+/// 数据库后端可以用带条件的更新实现 `save`，如 `UPDATE ... SET revision = revision + 1 WHERE id = ? AND revision = ?`，
+/// 没有行被更新时返回冲突。
+/// Database backends can implement `save` with a conditional update such as `UPDATE ... SET revision = revision +
+/// 1 WHERE id = ? AND revision = ?`, returning a conflict when no row was updated.
+pub trait SessionStore: Send + Sync {
+    /// 读取对话，不存在时返回 None
+    /// Read a conversation, None if it does not exist
+    fn load<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<Option<StoredSession>, StoreError>>;
+
+    /// 在存储中的版本号等于 `expected_revision` 时保存对话，返回新的版本号；新对话的 `expected_revision` 为 0
+    /// Save a conversation if the revision in the store equals `expected_revision`, returning the new revision;
+    /// `expected_revision` is 0 for new conversations
+    fn save<'a>(
+        &'a self,
+        id: &'a str,
+        session: &'a Session,
+        expected_revision: u64,
+    ) -> BoxFuture<'a, Result<u64, StoreError>>;
+
+    fn remove<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<(), StoreError>>;
+
+    /// 在存储中的版本号仍等于 `expected_revision` 时删除对话，否则返回 `StoreError::Conflict`，
+    /// 读取后又被保存的对话不会被删除；对话已不存在时视为成功
+    /// Delete a conversation if its revision in the store still equals `expected_revision` and return
+    /// `StoreError::Conflict` otherwise, so a conversation saved again after it was read is not deleted; succeeds if
+    /// the conversation no longer exists
+    fn remove_revision<'a>(&'a self, id: &'a str, expected_revision: u64) -> BoxFuture<'a, Result<(), StoreError>>;
+
+    /// 列出全部对话及其最后保存的时间，供保留策略清理；默认不支持
+    /// List every conversation with the time it was last saved, for retention policies to clean up; unsupported by
+    /// default
+    fn list(&self) -> BoxFuture<'_, Result<Vec<SessionEntry>, StoreError>> {
+        Box::pin(async { Err(Report::new(StoreError::Unsupported("listing conversations".to_string()))) })
+    }
+}
+
+/// 对话内容写入存储前与读出后的字节变换，如 `encryption` 特性中 `Cipher` 提供的静态加密；
+/// 通过 `DirectorySessionStore::codec` 与 `SqliteSessionStore::codec` 启用，对调用方透明
+/// Byte transformation of conversation content before it is written to and after it is read from a store, such as
+/// the at-rest encryption `Cipher` provides under the `encryption` feature; enabled through
+/// `DirectorySessionStore::codec` and `SqliteSessionStore::codec`, transparently to callers
+pub trait SessionCodec: Send + Sync {
+    fn encode(&self, data: Vec<u8>) -> Result<Vec<u8>, StoreError>;
+
+    /// 还原编码后的数据；启用编码前写入的数据应原样返回，已有的存储因此可以直接启用编码
+    /// Restore encoded data; data written before the codec was enabled should come back as is, so existing stores
+    /// can enable a codec in place
+    fn decode(&self, data: Vec<u8>) -> Result<Vec<u8>, StoreError>;
+}
+
+fn conflict(id: &str, expected: u64, actual: u64) -> Report<StoreError> {
+    Report::new(StoreError::Conflict {
+        id: id.to_string(),
+        expected,
+        actual,
+    })
+}
+
+/// 读取、修改并保存对话，遇到冲突时退避后重新读取最新版本再修改，最多尝试 `MAX_UPDATE_ATTEMPTS` 次；
+/// 对话不存在时从空对话开始
+/// Read, modify and save a conversation, backing off and then reading the latest revision again and reapplying the
+/// change on conflicts, at most `MAX_UPDATE_ATTEMPTS` times; starts from an empty session if the conversation does
+/// not exist
+pub async fn update_session(
+    store: &dyn SessionStore,
+    id: &str,
+    mut change: impl FnMut(&mut Session),
+) -> Result<StoredSession, StoreError> {
+    let mut attempt = 1;
+    loop {
+        let StoredSession { revision, mut session } = store.load(id).await?.unwrap_or(StoredSession {
+            revision: 0,
+            session: Session::new(),
+        });
+        change(&mut session);
+        match store.save(id, &session, revision).await {
+            Ok(revision) => return Ok(StoredSession { revision, session }),
+            Err(e) if attempt < MAX_UPDATE_ATTEMPTS && matches!(e.current_context(), StoreError::Conflict { .. }) => {
+                // 随机抖动让同时冲突的写入者错开重试
+                // Random jitter spreads out the retries of writers that conflicted at the same time
+                let backoff = UPDATE_BACKOFF * 2u32.pow(attempt - 1);
+                let jitter = rand::rng().random_range(Duration::ZERO..=backoff);
+                tokio::time::sleep(backoff + jitter).await;
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// 进程内存储，比较并交换在单个条目上原子完成，进程退出后内容丢失
+/// In-process storage, compare-and-swap is atomic on each entry; its content is lost when the process exits
+#[derive(Debug, Default)]
+pub struct MemorySessionStore {
+    items: DashMap<String, (StoredSession, SystemTime)>,
+}
+
+impl MemorySessionStore {
+    fn save_now(&self, id: &str, session: &Session, expected_revision: u64) -> Result<u64, StoreError> {
+        match self.items.entry(id.to_string()) {
+            Entry::Occupied(mut entry) => {
+                let actual = entry.get().0.revision;
+                if actual != expected_revision {
+                    return Err(conflict(id, expected_revision, actual));
+                }
+                let stored = StoredSession {
+                    revision: actual + 1,
+                    session: session.clone(),
+                };
+                entry.insert((stored, SystemTime::now()));
+                Ok(actual + 1)
+            }
+            Entry::Vacant(_) if expected_revision != 0 => Err(conflict(id, expected_revision, 0)),
+            Entry::Vacant(entry) => {
+                let stored = StoredSession {
+                    revision: 1,
+                    session: session.clone(),
+                };
+                entry.insert((stored, SystemTime::now()));
+                Ok(1)
+            }
+        }
+    }
+
+}
+
+impl SessionStore for MemorySessionStore {
+    fn load<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<Option<StoredSession>, StoreError>> {
+        Box::pin(async move { Ok(self.items.get(id).map(|item| item.value().0.clone())) })
+    }
+
+    fn save<'a>(
+        &'a self,
+        id: &'a str,
+        session: &'a Session,
+        expected_revision: u64,
+    ) -> BoxFuture<'a, Result<u64, StoreError>> {
+        Box::pin(async move { self.save_now(id, session, expected_revision) })
+    }
+
+    fn remove<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<(), StoreError>> {
+        Box::pin(async move {
+            self.items.remove(id);
+            Ok(())
+        })
+    }
+
+    fn remove_revision<'a>(&'a self, id: &'a str, expected_revision: u64) -> BoxFuture<'a, Result<(), StoreError>> {
+        Box::pin(async move {
+            if let Entry::Occupied(entry) = self.items.entry(id.to_string()) {
+                let actual = entry.get().0.revision;
+                if actual != expected_revision {
+                    return Err(conflict(id, expected_revision, actual));
+                }
+                entry.remove();
+            }
+            Ok(())
+        })
+    }
+
+    fn list(&self) -> BoxFuture<'_, Result<Vec<SessionEntry>, StoreError>> {
+        Box::pin(async move {
+            Ok(self
+                .items
+                .iter()
+                .map(|item| SessionEntry {
+                    id: item.key().clone(),
+                    saved_at: item.value().1,
+                })
+                .collect())
+        })
+    }
+}
+
+/// 目录存储，每个对话一个 JSON 文件，文件名是ID经 `encode_file_name` 编码的结果，可由共用目录的多个进程同时使用
+/// Directory storage, one JSON file per conversation named by its ID encoded with `encode_file_name`, usable by
+/// several processes sharing the directory
+///
+/// 保存时先取得对话所在锁文件的咨询锁，在锁内比较版本号并以改名原子地替换文件。锁由操作系统随文件关闭释放，
+/// 崩溃的进程不会留下需要清理的锁；锁文件固定为 `LOCK_STRIPES` 个，从不删除，等待锁时异步退避而不阻塞线程。
+/// Saving first takes the advisory lock of the conversation's lock file, then compares the revision and atomically
+/// replaces the file by renaming while holding it. The operating system releases the lock when the file is closed,
+/// so a crashed process leaves no lock behind to clean up; there is a fixed set of `LOCK_STRIPES` lock files that
+/// are never deleted, and waiting for a lock backs off asynchronously instead of blocking the thread.
+#[derive(Clone)]
+pub struct DirectorySessionStore {
+    directory: PathBuf,
+
+    /// 等待对话锁的最长时间
+    /// Longest time to wait for a conversation lock
+    pub lock_timeout: Duration,
+
+    codec: Option<Arc<dyn SessionCodec>>,
+}
+
+impl std::fmt::Debug for DirectorySessionStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DirectorySessionStore")
+            .field("directory", &self.directory)
+            .field("lock_timeout", &self.lock_timeout)
+            .field("encoded", &self.codec.is_some())
+            .finish()
+    }
+}
+
+impl DirectorySessionStore {
+    pub fn new(directory: impl Into<PathBuf>) -> Result<Self, StoreError> {
+        let directory = directory.into();
+        let locks = directory.join("locks");
+        fs::create_dir_all(&locks).change_context_lazy(|| StoreError::IoError(locks.display().to_string()))?;
+        Ok(Self {
+            directory,
+            lock_timeout: Duration::from_secs(5),
+            codec: None,
+        })
+    }
+
+    /// 对话文件写入前编码、读出后解码，如静态加密
+    /// Encode conversation files before writing and decode them after reading, such as for at-rest encryption
+    pub fn codec(mut self, codec: impl SessionCodec + 'static) -> Self {
+        self.codec = Some(Arc::new(codec));
+        self
+    }
+
+    /// 对话文件路径，不同的ID总是对应不同的文件，且不会写出目录之外
+    /// Path of a conversation file, distinct IDs always map to distinct files and nothing is written outside the
+    /// directory
+    fn path(&self, id: &str, extension: &str) -> PathBuf {
+        self.directory.join(format!("{}.{}", encode_file_name(id), extension))
+    }
+
+    /// 取得对话锁，返回的文件关闭时释放锁；锁被占用时以指数退避重试，直到 `lock_timeout`
+    /// Take the conversation lock, released when the returned file is closed; while the lock is held elsewhere it
+    /// retries with exponential backoff until `lock_timeout`
+    pub(crate) async fn lock(&self, id: &str) -> Result<File, StoreError> {
+        let stripe = Sha256::digest(id.as_bytes())[0] as usize % LOCK_STRIPES;
+        let path = self.directory.join("locks").join(format!("{:02}.lock", stripe));
+        let io_error = || StoreError::IoError(path.display().to_string());
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)
+            .change_context_lazy(io_error)?;
+        let started = Instant::now();
+        let mut delay = Duration::from_millis(5);
+        loop {
+            match file.try_lock() {
+                Ok(()) => return Ok(file),
+                Err(TryLockError::WouldBlock) => {
+                    let Some(remaining) = self.lock_timeout.checked_sub(started.elapsed()).filter(|r| !r.is_zero())
+                    else {
+                        return Err(Report::new(StoreError::Locked(id.to_string())));
+                    };
+                    tokio::time::sleep(delay.min(remaining)).await;
+                    delay = (delay * 2).min(MAX_LOCK_DELAY);
+                }
+                Err(TryLockError::Error(e)) => return Err(Report::new(io_error()).attach_printable(e)),
+            }
+        }
+    }
+
+    /// 删除对话文件，调用方需持有对话锁
+    /// Delete the conversation file, the caller must hold the conversation lock
+    fn delete(&self, id: &str) -> Result<(), StoreError> {
+        match fs::remove_file(self.path(id, "json")) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(Report::new(StoreError::IoError(id.to_string())).attach_printable(e))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn read(&self, id: &str) -> Result<Option<StoredSession>, StoreError> {
+        let path = self.path(id, "json");
+        let io_error = || StoreError::IoError(path.display().to_string());
+        match fs::read(&path) {
+            Ok(data) => {
+                let data = match &self.codec {
+                    Some(codec) => codec.decode(data).attach_printable_lazy(|| path.display().to_string())?,
+                    None => data,
+                };
+                serde_json::from_slice(&data).change_context_lazy(io_error).map(Some)
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(Report::new(io_error()).attach_printable(e)),
+        }
+    }
+}
+
+impl SessionStore for DirectorySessionStore {
+    fn load<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<Option<StoredSession>, StoreError>> {
+        Box::pin(async move { self.read(id) })
+    }
+
+    fn save<'a>(
+        &'a self,
+        id: &'a str,
+        session: &'a Session,
+        expected_revision: u64,
+    ) -> BoxFuture<'a, Result<u64, StoreError>> {
+        Box::pin(async move {
+            let _lock = self.lock(id).await?;
+            let actual = self.read(id)?.map_or(0, |stored| stored.revision);
+            if actual != expected_revision {
+                return Err(conflict(id, expected_revision, actual));
+            }
+
+            let stored = StoredSession {
+                revision: actual + 1,
+                session: session.clone(),
+            };
+            let (path, temp) = (self.path(id, "json"), self.path(id, "tmp"));
+            let io_error = || StoreError::IoError(path.display().to_string());
+            let data = serde_json::to_vec(&stored).change_context_lazy(io_error)?;
+            let data = match &self.codec {
+                Some(codec) => codec.encode(data).attach_printable_lazy(|| path.display().to_string())?,
+                None => data,
+            };
+            fs::write(&temp, data).change_context_lazy(io_error)?;
+            fs::rename(&temp, &path).change_context_lazy(io_error)?;
+            Ok(stored.revision)
+        })
+    }
+
+    fn remove<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<(), StoreError>> {
+        Box::pin(async move {
+            let _lock = self.lock(id).await?;
+            self.delete(id)
+        })
+    }
+
+    fn remove_revision<'a>(&'a self, id: &'a str, expected_revision: u64) -> BoxFuture<'a, Result<(), StoreError>> {
+        Box::pin(async move {
+            let _lock = self.lock(id).await?;
+            match self.read(id)? {
+                Some(stored) if stored.revision != expected_revision => {
+                    Err(conflict(id, expected_revision, stored.revision))
+                }
+                Some(_) => self.delete(id),
+                None => Ok(()),
+            }
+        })
+    }
+
+    /// ID从文件名还原，保存时间取文件的修改时间，不读取文件内容
+    /// IDs are restored from the file names and the save time is the modification time of the file, without
+    /// reading any file content
+    fn list(&self) -> BoxFuture<'_, Result<Vec<SessionEntry>, StoreError>> {
+        Box::pin(async move {
+            let io_error = || StoreError::IoError(self.directory.display().to_string());
+            let mut entries = Vec::new();
+            for file in fs::read_dir(&self.directory).change_context_lazy(io_error)? {
+                let file = file.change_context_lazy(io_error)?;
+                let path = file.path();
+                if path.extension().is_none_or(|extension| extension != "json") {
+                    continue;
+                }
+                let Some(id) = path.file_stem().and_then(|stem| decode_file_name(&stem.to_string_lossy())) else {
+                    continue;
+                };
+                // 列出与读取元数据之间被删除的文件跳过
+                // Files removed between listing and reading their metadata are skipped
+                let Ok(metadata) = file.metadata() else {
+                    continue;
+                };
+                entries.push(SessionEntry {
+                    id,
+                    saved_at: metadata.modified().change_context_lazy(io_error)?,
+                });
+            }
+            Ok(entries)
+        })
+    }
+}
@@ -0,0 +1,186 @@
+// 标准库
+use std::time::Duration;
+
+// 异步
+use async_stream::stream;
+use bytes::Bytes;
+use futures::{Stream, TryStreamExt};
+use tokio::time::{Instant, timeout_at};
+
+// 错误处理
+use error_stack::{Report, Result};
+
+// 项目内部模块
+use crate::chat::chat_base::{ChatError, RequestPermit};
+
+/// 流式响应中的一个事件块
+/// One event chunk of a streaming response
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StreamChunk {
+    /// 本块新增的回答内容，没有内容时为空
+    /// Answer content added by this chunk, empty if none
+    pub content: String,
+
+    pub finish_reason: Option<String>,
+
+    /// 本块新增的拒绝说明（OpenAI 的 `refusal` 增量）
+    /// Refusal explanation added by this chunk (OpenAI's `refusal` delta)
+    pub refusal: String,
+
+    pub usage: Option<serde_json::Value>,
+}
+
+impl StreamChunk {
+    /// 解析一条 SSE `data` 负载
+    /// Parse one SSE `data` payload
+    pub fn parse(payload: &str) -> Result<Self, ChatError> {
+        let json = serde_json::from_str::<serde_json::Value>(payload).map_err(|err| {
+            Report::new(ChatError::ParseResponseError).attach_printable(format!("Failed to parse JSON: {}", err))
+        })?;
+
+        let mut chunk = Self::default();
+        for choice in json["choices"].as_array().into_iter().flatten() {
+            // 补全接口的增量在 `text` 中
+            // Deltas of the completion endpoint are in `text`
+            if let Some(content) = choice["delta"]["content"].as_str().or(choice["text"].as_str()) {
+                chunk.content.push_str(content);
+            }
+            if let Some(refusal) = choice["delta"]["refusal"].as_str() {
+                chunk.refusal.push_str(refusal);
+            }
+            if let Some(finish_reason) = choice["finish_reason"].as_str() {
+                chunk.finish_reason = Some(finish_reason.to_string());
+            }
+        }
+        chunk.usage = json.get("usage").filter(|usage| !usage.is_null()).cloned();
+        Ok(chunk)
+    }
+}
+
+/// 增量 SSE 解码器，缓存跨网络分块的不完整行
+/// Incremental SSE decoder, buffering lines split across network chunks
+#[derive(Debug, Default)]
+pub struct SseDecoder {
+    buffer: Vec<u8>,
+}
+
+impl SseDecoder {
+    /// 送入一个网络分块，返回其中已完整的 `data` 负载
+    /// Feed one network chunk, returning the `data` payloads it completes
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<String> {
+        self.buffer.extend_from_slice(bytes);
+
+        let mut payloads = Vec::new();
+        while let Some(end) = self.buffer.iter().position(|byte| *byte == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=end).collect();
+            payloads.extend(Self::payload(&line));
+        }
+        payloads
+    }
+
+    /// 流结束时取出缓存中剩余的负载
+    /// Take the payload left in the buffer when the stream ends
+    pub fn finish(&mut self) -> Option<String> {
+        let line = std::mem::take(&mut self.buffer);
+        Self::payload(&line)
+    }
+
+    fn payload(line: &[u8]) -> Option<String> {
+        let line = String::from_utf8_lossy(line);
+        let line = line.trim_end_matches(['\r', '\n']);
+        // 注释行（如 `: keep-alive`）与 `data` 以外的字段行不含负载
+        // Comment lines (such as `: keep-alive`) and field lines other than `data` carry no payload
+        if line.starts_with(':') || ["event:", "id:", "retry:"].iter().any(|field| line.starts_with(field)) {
+            return None;
+        }
+        let payload = line.strip_prefix("data:").map_or(line, |data| data.trim_start());
+        (!payload.is_empty() && payload != "[DONE]" && !is_keep_alive(payload)).then(|| payload.to_string())
+    }
+}
+
+/// 是否为提供商的保活事件，如 Anthropic 的 `{"type": "ping"}`
+/// Whether the payload is a provider keep-alive event, such as Anthropic's `{"type": "ping"}`
+pub fn is_keep_alive(payload: &str) -> bool {
+    payload.contains("\"ping\"")
+        && serde_json::from_str::<serde_json::Value>(payload).is_ok_and(|json| json["type"] == "ping")
+}
+
+/// 流式响应停滞时附加在错误报告上的标记，停滞的流即使尚未收到内容也会续传
+/// Marker attached to the report when a streaming response stalls, a stalled stream is resumed even before any
+/// content arrived
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamStalled(pub Duration);
+
+impl std::fmt::Display for StreamStalled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Stream stalled: no chunk for {:?}", self.0)
+    }
+}
+
+/// 将字节流转换为事件块流，块在到达时立即产出，不做汇总
+/// Turn a byte stream into a stream of chunks, yielded as they arrive without being accumulated
+///
+/// 并发额度在事件块流结束或被丢弃时释放。
+/// The request permit is released when the chunk stream ends or is dropped.
+pub fn chunk_stream(
+    bytes: impl Stream<Item = reqwest::Result<Bytes>> + Send + Unpin,
+    permit: impl Into<RequestPermit>,
+) -> impl Stream<Item = Result<StreamChunk, ChatError>> + Send {
+    stalling_chunk_stream(bytes, permit, None)
+}
+
+/// 同 `chunk_stream`，`stall_timeout` 内没有收到事件块时以超时错误结束，错误报告带有 `StreamStalled` 标记
+/// Like `chunk_stream`, ending with a timeout error when no chunk arrives within `stall_timeout`, the report
+/// carrying the `StreamStalled` marker
+///
+/// 保活注释与 ping 事件让代理保持连接，但不算作进展。
+/// Keep-alive comments and ping events keep proxies from closing the connection, but do not count as progress.
+pub fn stalling_chunk_stream(
+    mut bytes: impl Stream<Item = reqwest::Result<Bytes>> + Send + Unpin,
+    permit: impl Into<RequestPermit>,
+    stall_timeout: Option<Duration>,
+) -> impl Stream<Item = Result<StreamChunk, ChatError>> + Send {
+    let permit = permit.into();
+    stream! {
+        let _permit = permit;
+        let mut decoder = SseDecoder::default();
+        let mut deadline = stall_timeout.map(|timeout| Instant::now() + timeout);
+
+        loop {
+            let next = match deadline {
+                Some(deadline) => timeout_at(deadline, bytes.try_next()).await,
+                None => Ok(bytes.try_next().await),
+            };
+            let Ok(next) = next else {
+                let stalled = StreamStalled(stall_timeout.unwrap_or_default());
+                yield Err(Report::new(ChatError::TimeoutError).attach_printable(stalled));
+                return;
+            };
+            let (payloads, finished) = match next {
+                Ok(Some(bytes)) => (decoder.push(&bytes), false),
+                Ok(None) => (decoder.finish().into_iter().collect(), true),
+                Err(err) => {
+                    yield Err(Report::new(ChatError::HttpError(0))
+                        .attach_printable(format!("Failed to get response: {}", err)));
+                    return;
+                }
+            };
+
+            if !payloads.is_empty() {
+                deadline = stall_timeout.map(|timeout| Instant::now() + timeout);
+            }
+            for payload in payloads {
+                let chunk = StreamChunk::parse(&payload);
+                let failed = chunk.is_err();
+                yield chunk;
+                if failed {
+                    return;
+                }
+            }
+
+            if finished {
+                return;
+            }
+        }
+    }
+}
@@ -0,0 +1,146 @@
+// 标准库
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::time::Duration;
+
+// 错误处理
+use error_stack::{Report, Result, ResultExt};
+
+// 异步
+use futures::future::BoxFuture;
+
+// 数据结构
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+
+// 数据序列化
+use serde_json::{Value, json};
+
+// 项目内部模块
+use crate::chat::agent::{AgentScope, Deadline};
+use crate::chat::chat_base::ChatError;
+use crate::chat::chat_single::SingleChat;
+use crate::chat::message::Role;
+use crate::chat::tool_source::{ToolSource, function_tool};
+use crate::schema::tool_schema::ChatToolSchemaError;
+
+/// 不在智能体运行中调用时，子智能体一次运行的截止时间；在运行中调用时沿用调用方的截止时间
+/// Deadline of one sub-agent run when called outside an agent run; called within a run, it keeps the caller's
+/// deadline
+pub const DEFAULT_SUBAGENT_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// 注册为工具的子智能体，见 `ToolRegistry::register_agent`
+/// Sub-agents registered as tools, see `ToolRegistry::register_agent`
+static AGENTS: Lazy<DashMap<String, Arc<SubAgent>>> = Lazy::new(DashMap::new);
+
+/// 作为工具的子智能体：工具的参数是任务描述，结果是子智能体的最终回答
+/// A sub-agent offered as a tool: the tool's argument is a task description and its result is the sub-agent's final
+/// answer
+///
+/// 每次调用从注册的对话分出新的子对话（见 `SingleChat::spawn_child`），沿用其系统提示词、API与工具，彼此之间不共享历史，
+/// 并以 `run_agent` 运行。
+/// Every call forks a fresh child of the registered chat (see `SingleChat::spawn_child`), keeping its system prompt,
+/// API and tools without sharing history between calls, and runs it with `run_agent`.
+struct SubAgent {
+    name: String,
+    description: String,
+    chat: SingleChat,
+}
+
+impl Debug for SubAgent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SubAgent").field("name", &self.name).field("description", &self.description).finish()
+    }
+}
+
+impl SubAgent {
+    /// 让子智能体完成任务，返回其运行结果的工具输出；在智能体运行中调用时继承其截止时间与事务
+    /// Have the sub-agent carry out a task, returning the tool output of its run; called within an agent run, it
+    /// inherits the run's deadline and transaction
+    async fn delegate(&self, task: &str) -> Result<Value, ChatError> {
+        let caller = AgentScope::current();
+        let deadline = caller.as_ref().map_or_else(|| Deadline::after(DEFAULT_SUBAGENT_TIMEOUT), |run| run.deadline);
+        let mut child = self.chat.fork("")?;
+        if let Some(journal) = caller.and_then(|run| run.journal) {
+            child.join_transaction(journal);
+        }
+        let run = child
+            .run_agent(task, deadline)
+            .await
+            .change_context(ChatError::ToolSourceError(self.name.clone()))?;
+        Ok(json!({"answer": run.answer, "stop": run.stop}))
+    }
+
+    /// 子智能体的工具定义
+    /// Tool definition of the sub-agent
+    fn definition(&self) -> Value {
+        let task = "要完成的任务，写明所需的全部背景，子智能体看不到当前对话";
+        let parameters = json!({
+            "type": "object",
+            "properties": {"task": {"type": "string", "description": task}},
+            "required": ["task"],
+        });
+        function_tool(&self.name, Some(&self.description), Some(&parameters))
+    }
+}
+
+impl ToolSource for SubAgent {
+    fn namespace(&self) -> Option<&str> {
+        None
+    }
+
+    fn list_tools(&self) -> BoxFuture<'_, Result<Vec<Value>, ChatError>> {
+        Box::pin(async move { Ok(vec![self.definition()]) })
+    }
+
+    fn call_tool<'a>(&'a self, name: &'a str, arguments: Value) -> BoxFuture<'a, Result<Value, ChatToolSchemaError>> {
+        Box::pin(async move {
+            let task = arguments["task"].as_str().ok_or_else(|| {
+                Report::new(ChatToolSchemaError::ParamsParseError(name.to_string(), arguments.to_string()))
+            })?;
+            self.delegate(task).await.change_context(ChatToolSchemaError::FunctionCallError)
+        })
+    }
+}
+
+/// 对话的第一条系统消息
+/// The first system message of the chat
+fn system_prompt(chat: &SingleChat) -> Option<String> {
+    chat.base
+        .session
+        .message_roots
+        .first()
+        .filter(|message| message.role == Role::System)
+        .map(|message| message.content.to_string())
+}
+
+/// 注册子智能体并返回其工具定义，未给出描述时取自对话的系统提示词；同名的子智能体被替换
+/// Register a sub-agent and return its tool definition, the description is taken from the chat's system prompt when
+/// not given; a sub-agent of the same name is replaced
+pub(crate) fn register_agent(name: &str, description: Option<&str>, chat: SingleChat) -> Value {
+    let description = match (description, system_prompt(&chat)) {
+        (Some(description), _) => description.to_string(),
+        (None, Some(prompt)) => format!("把任务交给子智能体 {} 完成，返回其最终回答。子智能体的设定：{}", name, prompt),
+        (None, None) => format!("把任务交给子智能体 {} 完成，返回其最终回答", name),
+    };
+    let agent = SubAgent {
+        name: name.to_string(),
+        description,
+        chat,
+    };
+    let definition = agent.definition();
+    AGENTS.insert(name.to_string(), Arc::new(agent));
+    definition
+}
+
+/// 移除注册的子智能体，返回是否存在
+/// Remove a registered sub-agent, returning whether it existed
+pub(crate) fn remove_agent(name: &str) -> bool {
+    AGENTS.remove(name).is_some()
+}
+
+/// 按名称找到注册的子智能体
+/// Find a registered sub-agent by name
+pub(crate) fn registered_agent(name: &str) -> Option<Arc<dyn ToolSource>> {
+    AGENTS.get(name).map(|agent| agent.value().clone() as Arc<dyn ToolSource>)
+}
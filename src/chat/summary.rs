@@ -0,0 +1,205 @@
+// 错误处理
+use error_stack::{Report, Result, ResultExt};
+
+// 数据序列化
+use rhine_schema_derive::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+// 项目内部模块
+use crate::chat::chat_base::{BaseChat, ChatError};
+use crate::chat::chat_single::SingleChat;
+use crate::chat::message::{Messages, Role, Session};
+use crate::config::prompts::PromptKey;
+use crate::config::{Config, ModelCapability};
+use crate::schema::json_schema::JsonSchema;
+
+/// 标题的最大字符数，超出部分截断
+/// Maximum characters of a title, the rest is cut off
+pub const TITLE_MAX_CHARS: usize = 30;
+
+/// 对话记录中每条消息保留的字符数，避免长对话撑满廉价模型的上下文
+/// Characters kept per message in the transcript, so long chats do not overflow the cheap model's context
+const TRANSCRIPT_MESSAGE_CHARS: usize = 2000;
+
+/// 对话的元数据，由 `SingleChat::generate_title` 与 `SingleChat::summary` 填写，供界面展示会话列表
+/// Metadata of a chat, filled in by `SingleChat::generate_title` and `SingleChat::summary`, for UIs listing chats
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChatMetadata {
+    pub title: Option<String>,
+
+    pub summary: Option<String>,
+}
+
+/// 交接简报，把对话移交给另一个智能体或人工坐席时说明目前的进展，见 `SingleChat::handover_brief`
+/// Handover brief, telling another agent or a human operator where a conversation stands when it is transferred,
+/// see `SingleChat::handover_brief`
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[schema(name = "handover_brief", description = "Brief for handing a conversation over to another agent or a human")]
+pub struct HandoverBrief {
+    #[schema(desc = "用户想达成的目标，一句话")]
+    pub goal: String,
+
+    #[schema(desc = "已经做出的决定与达成的结论")]
+    pub decisions: Vec<String>,
+
+    #[schema(desc = "尚未解决、需要接手者跟进的问题")]
+    pub open_questions: Vec<String>,
+
+    #[schema(desc = "接手者需要知道的关键事实，如用户信息、约束、偏好与编号")]
+    pub key_facts: Vec<String>,
+
+    #[schema(desc = "已承诺或已发起但尚未完成的操作，包括未返回结果的工具调用")]
+    pub pending_actions: Vec<String>,
+}
+
+impl HandoverBrief {
+    /// 以 Markdown 呈现，可直接交给人工坐席或作为接手智能体的系统提示词的一部分
+    /// Render as Markdown, ready for a human operator or as part of the system prompt of the agent taking over
+    pub fn to_markdown(&self) -> String {
+        let heading = |key| Config::capability_prompt(&key);
+        let mut markdown = format!("## {}\n{}\n", heading(PromptKey::HandoverGoalHeading), self.goal);
+        let sections = [
+            (PromptKey::HandoverDecisionsHeading, &self.decisions),
+            (PromptKey::HandoverOpenQuestionsHeading, &self.open_questions),
+            (PromptKey::HandoverKeyFactsHeading, &self.key_facts),
+            (PromptKey::HandoverPendingActionsHeading, &self.pending_actions),
+        ];
+        for (key, items) in sections.into_iter().filter(|(_, items)| !items.is_empty()) {
+            markdown.push_str(&format!("\n## {}\n", heading(key)));
+            for item in items {
+                markdown.push_str(&format!("- {}\n", item));
+            }
+        }
+        markdown
+    }
+}
+
+/// 用廉价模型处理当前对话记录：优先使用绑定 `Cheap` 能力的API，没有时使用对话自身的API
+/// Run an instruction over the chat transcript with a cheap model: an API bound to the `Cheap` capability if any,
+/// otherwise the chat's own API
+///
+/// 辅助对话沿用原对话的标签与敏感信息清洗，不写入原对话历史。
+/// The helper chat keeps the tags and PII scrubbing of the chat, and never writes to its history.
+pub(crate) async fn ask_cheap_model(chat: &BaseChat, instruction: &str) -> Result<String, ChatError> {
+    let transcript = transcript(&chat.session);
+    if transcript.is_empty() {
+        return Err(Report::new(ChatError::SessionError).attach_printable("The chat has no messages to summarize"));
+    }
+    let material = Config::render_prompt(&PromptKey::TranscriptMaterial, &[&transcript]);
+    ask_cheap_model_about(chat, instruction, &material).await
+}
+
+/// 用廉价模型按 `HandoverBrief` 的结构整理当前对话记录
+/// Condense the chat transcript into the structure of `HandoverBrief` with a cheap model
+pub(crate) async fn handover_brief(chat: &BaseChat) -> Result<HandoverBrief, ChatError> {
+    let transcript = transcript(&chat.session);
+    if transcript.is_empty() {
+        return Err(Report::new(ChatError::SessionError).attach_printable("The chat has no messages to hand over"));
+    }
+    let instruction = Config::capability_prompt(&PromptKey::HandoverInstruction);
+    let material = Config::render_prompt(&PromptKey::TranscriptMaterial, &[&transcript]);
+    cheap_helper(chat, &instruction)?.get_json_answer(&material).await
+}
+
+/// 同 `ask_cheap_model`，但处理给定的材料而不是整段对话记录
+/// Like `ask_cheap_model`, but over the given material instead of the whole transcript
+async fn ask_cheap_model_about(chat: &BaseChat, instruction: &str, material: &str) -> Result<String, ChatError> {
+    let answer = cheap_helper(chat, instruction)?.get_answer(material).await?;
+    Ok(answer.trim().to_string())
+}
+
+/// 以给定指令为系统提示词的辅助对话，使用廉价模型，沿用原对话的标签、敏感信息清洗与租户
+/// Helper chat with the instruction as its system prompt, on a cheap model, keeping the tags, PII scrubbing and
+/// tenant of the chat
+pub(crate) fn cheap_helper(chat: &BaseChat, instruction: &str) -> Result<SingleChat, ChatError> {
+    let helper = match BaseChat::try_new_with_model_capability(ModelCapability::Cheap, "", false) {
+        Ok(helper) => helper,
+        Err(_) => BaseChat::try_new_with_api_name(&chat.api_name, "", false)?,
+    };
+    helper_chat(chat, helper, instruction)
+}
+
+/// 同 `cheap_helper`，使用给定的对话作为辅助对话；辅助请求同样受租户的预算与频率上限约束并计入其用量
+/// Like `cheap_helper`, with the given chat as the helper; helper requests are bound by the tenant's budget and
+/// rate limit and charged to it as well
+pub(crate) fn helper_chat(chat: &BaseChat, mut helper: BaseChat, instruction: &str) -> Result<SingleChat, ChatError> {
+    helper.tags = chat.tags.clone();
+    helper.pii = chat.pii.clone();
+    helper.tenant = chat.tenant.clone();
+    helper.add_message(Role::System, instruction)?;
+    Ok(SingleChat::from_base(helper))
+}
+
+/// 用廉价模型为默认路径上尚未评分的非系统消息评定显著性，返回评分的消息数
+/// Rate the salience of the unscored non-system messages on the default path with a cheap model, returning how
+/// many were rated
+pub(crate) async fn rate_salience(chat: &mut BaseChat) -> Result<usize, ChatError> {
+    let unscored: Vec<(String, String)> = chat.session.default_path_messages()
+        .into_iter()
+        .filter(|node| node.role != Role::System && node.importance.salience.is_none())
+        .map(|node| (node.id.clone(), transcript_line(node)))
+        .collect();
+    if unscored.is_empty() {
+        return Ok(0);
+    }
+
+    let material: Vec<String> =
+        unscored.iter().enumerate().map(|(number, (_, line))| format!("[{}] {}", number + 1, line)).collect();
+    let instruction = Config::capability_prompt(&PromptKey::SalienceRating);
+    let material = Config::render_prompt(&PromptKey::SalienceMaterial, &[&material.join("\n")]);
+    let answer = ask_cheap_model_about(chat, &instruction, &material).await?;
+
+    let scores: Vec<f64> = answer
+        .find('[')
+        .zip(answer.rfind(']'))
+        .and_then(|(start, end)| serde_json::from_str(answer.get(start..=end)?).ok())
+        .filter(|scores: &Vec<f64>| scores.len() == unscored.len())
+        .ok_or_else(|| {
+            Report::new(ChatError::ParseResponseError)
+                .attach_printable(format!("Expected {} salience scores, got: {}", unscored.len(), answer))
+        })?;
+    for ((id, _), score) in unscored.iter().zip(scores) {
+        let node = chat.session.get_node_by_id(id).change_context(ChatError::SessionError)?;
+        node.importance.salience = Some(score.clamp(0.0, 100.0).round() as u8);
+    }
+    Ok(unscored.len())
+}
+
+/// 对话记录中的一行，过长的消息截断
+/// One line of the transcript, with long messages cut off
+fn transcript_line(node: &Messages) -> String {
+    let text = node.content.to_text();
+    let mut excerpt: String = text.chars().take(TRANSCRIPT_MESSAGE_CHARS).collect();
+    if text.chars().count() > TRANSCRIPT_MESSAGE_CHARS {
+        excerpt.push('…');
+    }
+    format!("{}: {}", node.role, excerpt)
+}
+
+/// 默认路径上的对话记录，系统消息不计入，过长的消息截断
+/// Transcript of the default path, without system messages and with long messages cut off
+fn transcript(session: &Session) -> String {
+    let lines: Vec<String> = session.default_path_messages()
+        .into_iter()
+        .filter(|node| node.role != Role::System)
+        .map(transcript_line)
+        .collect();
+    lines.join("\n")
+}
+
+/// 去掉模型常加的引号、书名号、“标题：”前缀与结尾标点，并限制长度
+/// Strip the quotes, title marks, "Title:" prefixes and trailing punctuation models tend to add, and cap the length
+pub(crate) fn clean_title(answer: &str) -> String {
+    let line = answer.lines().find(|line| !line.trim().is_empty()).unwrap_or_default().trim();
+    let line = ["标题：", "标题:", "Title:", "title:"]
+        .iter()
+        .find_map(|prefix| line.strip_prefix(prefix))
+        .unwrap_or(line)
+        .trim();
+    let title = line
+        .trim_matches(|c: char| {
+            matches!(c, '"' | '\'' | '“' | '”' | '《' | '》' | '「' | '」' | '*' | '#' | '。' | '.' | '！' | '!' | '？' | '?')
+        })
+        .trim();
+    title.chars().take(TITLE_MAX_CHARS).collect()
+}
@@ -0,0 +1,239 @@
+// 标准库
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// 错误处理
+use error_stack::{Report, Result};
+
+// 数据序列化
+use bytes::Bytes;
+
+// 观测诊断
+use tracing::warn;
+
+// 项目内部模块
+use crate::chat::attachment::load_attachment;
+use crate::chat::chat_base::ChatError;
+use crate::chat::queue::QueuePriority;
+use crate::telemetry::LlmCall;
+use crate::telemetry::ledger::UsageRecord;
+use crate::telemetry::usage::UsageTotals;
+use crate::utils::common::file_name::encode_file_name;
+
+/// 请求频率上限：每 `per` 时间内最多 `requests` 次请求
+/// Request rate limit: at most `requests` requests per `per`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RateLimit {
+    pub requests: u32,
+    pub per: Duration,
+}
+
+/// 租户的累计用量与近期请求时间
+/// Accumulated usage and recent request times of a tenant
+#[derive(Debug, Default)]
+struct TenantState {
+    usage: UsageTotals,
+    requests: VecDeque<Instant>,
+}
+
+/// 租户上下文：一个进程服务多个客户时，为每个客户的对话设置预算、频率上限、可用工具与存储命名空间，并互相隔离
+/// Tenant context: when one process serves many customers, each customer's chats get their own budget, rate limit,
+/// allowed tools and storage namespace, isolated from each other
+///
+/// 句柄可克隆，克隆共享同一份用量与频率计数，同一租户的所有对话应使用同一个句柄的克隆。设置到对话上后（见
+/// `BaseChat::set_tenant`），每次请求前检查预算与频率，请求结束后计入用量，用量记录带上 `tenant:<id>` 标签，
+/// 回答中调用不在允许列表中的工具视为工具不存在，附件存入租户的命名空间。
+/// The handle is cloneable and clones share the same usage and rate counters, so all chats of one tenant should use
+/// clones of one handle. Once set on a chat (see `BaseChat::set_tenant`), the budget and rate are checked before
+/// every request and the usage is counted when it ends, usage records are tagged `tenant:<id>`, tools outside the
+/// allowed list are treated as non-existent and attachments are stored in the tenant's namespace.
+#[derive(Clone, Debug)]
+pub struct Tenant {
+    pub id: String,
+
+    /// 费用预算（美元），按模型价格计算，未配置价格的模型不计费；为 None 时不限制
+    /// Cost budget in USD, computed from the model prices, models without prices cost nothing; unlimited if None
+    pub budget: Option<f64>,
+
+    /// 令牌预算，为 None 时不限制
+    /// Token budget, unlimited if None
+    pub token_budget: Option<u64>,
+
+    pub rate_limit: Option<RateLimit>,
+
+    /// 频率上限的每个窗口中为前台请求保留的请求数，后台请求在只剩这些余量时被拒绝
+    /// Requests of each rate limit window kept for foreground requests, background requests are refused once only
+    /// this many are left
+    pub interactive_reserve: u32,
+
+    /// 允许调用的工具名称，为 None 时不限制
+    /// Names of the tools allowed to be called, unrestricted if None
+    pub allowed_tools: Option<Vec<String>>,
+
+    /// 存储命名空间，默认与租户ID相同
+    /// Storage namespace, the tenant ID by default
+    pub namespace: String,
+
+    state: Arc<Mutex<TenantState>>,
+}
+
+impl Tenant {
+    pub fn new(id: &str) -> Self {
+        Self {
+            id: id.to_string(),
+            budget: None,
+            token_budget: None,
+            rate_limit: None,
+            interactive_reserve: 0,
+            allowed_tools: None,
+            namespace: id.to_string(),
+            state: Arc::default(),
+        }
+    }
+
+    pub fn budget(mut self, usd: f64) -> Self {
+        self.budget = Some(usd);
+        self
+    }
+
+    pub fn token_budget(mut self, tokens: u64) -> Self {
+        self.token_budget = Some(tokens);
+        self
+    }
+
+    pub fn rate_limit(mut self, requests: u32, per: Duration) -> Self {
+        self.rate_limit = Some(RateLimit { requests, per });
+        self
+    }
+
+    /// 为前台请求保留频率上限中的 `requests` 次请求，见 `QueuePriority`
+    /// Keep `requests` of the rate limit for foreground requests, see `QueuePriority`
+    pub fn interactive_reserve(mut self, requests: u32) -> Self {
+        self.interactive_reserve = requests;
+        self
+    }
+
+    pub fn allowed_tools(mut self, tools: &[&str]) -> Self {
+        self.allowed_tools = Some(tools.iter().map(|tool| tool.to_string()).collect());
+        self
+    }
+
+    pub fn namespace(mut self, namespace: &str) -> Self {
+        self.namespace = namespace.to_string();
+        self
+    }
+
+    /// 用量记录上的标签
+    /// Tag on the usage records
+    pub fn tag(&self) -> String {
+        format!("tenant:{}", self.id)
+    }
+
+    /// 目前为止的用量
+    /// Usage so far
+    pub fn usage(&self) -> UsageTotals {
+        self.state.lock().unwrap().usage.clone()
+    }
+
+    /// 恢复之前的用量，如进程重启后从用量账本中按标签汇总的结果
+    /// Restore earlier usage, such as the totals of the tag summed from the usage ledger after a restart
+    pub fn restore_usage(&self, usage: UsageTotals) {
+        self.state.lock().unwrap().usage = usage;
+    }
+
+    pub fn allows_tool(&self, name: &str) -> bool {
+        self.allowed_tools.as_ref().is_none_or(|tools| tools.iter().any(|tool| tool == name))
+    }
+
+    /// 命名空间内的存储键
+    /// Storage key inside the namespace
+    pub fn storage_key(&self, key: &str) -> String {
+        format!("{}/{}", self.namespace, key)
+    }
+
+    /// 命名空间在 `root` 下的目录，不同的命名空间总是对应不同的目录，且不会写出 `root` 之外
+    /// Directory of the namespace under `root`, distinct namespaces always map to distinct directories and nothing
+    /// is written outside `root`
+    pub fn storage_dir(&self, root: impl AsRef<Path>) -> PathBuf {
+        root.as_ref().join(encode_file_name(&self.namespace))
+    }
+
+    /// 按ID读取附件内容，只能读取租户自己命名空间中的附件，供工具使用；命名空间可以含有 `/`，
+    /// 因此嵌套命名空间（如 `acme/eu`）中的附件不属于外层命名空间（如 `acme`）
+    /// Read attachment content by ID, only attachments in the tenant's own namespace can be read; for tools.
+    /// Namespaces may contain `/`, so attachments of a nested namespace (such as `acme/eu`) do not belong to the
+    /// outer one (such as `acme`)
+    pub fn load_attachment(&self, id: &str) -> Result<Bytes, ChatError> {
+        let own = id.strip_prefix(&self.storage_key("")).is_some_and(|rest| !rest.is_empty() && !rest.contains('/'));
+        if !own {
+            return Err(Report::new(ChatError::TenantForbidden(self.id.clone()))
+                .attach_printable(format!("Attachment {} is outside the tenant's namespace", id)));
+        }
+        load_attachment(id)
+    }
+
+    /// 请求前检查预算与频率上限，通过时计入这次请求；按默认优先级计，见 [`Tenant::admit_with_priority`]
+    /// Check the budget and the rate limit before a request, counting the request if admitted; counted at the
+    /// default priority, see [`Tenant::admit_with_priority`]
+    pub fn admit(&self) -> Result<(), ChatError> {
+        self.admit_with_priority(QueuePriority::default())
+    }
+
+    /// 按给定优先级检查预算与频率上限，后台请求不能使用为前台保留的余量。每次调用只检查一次，
+    /// 流的续传、换用备用API与故障转移不算新的请求
+    /// Check the budget and the rate limit at the given priority, background requests cannot use the headroom kept
+    /// for the foreground. Checked once per call, stream resumes, fallbacks and failovers do not count as new
+    /// requests
+    pub fn admit_with_priority(&self, priority: QueuePriority) -> Result<(), ChatError> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(budget) = self.budget.filter(|budget| state.usage.cost >= *budget) {
+            return Err(Report::new(ChatError::TenantBudgetExceeded(self.id.clone()))
+                .attach_printable(format!("Spent {:.4} USD of a {:.4} USD budget", state.usage.cost, budget)));
+        }
+        if let Some(budget) = self.token_budget.filter(|budget| state.usage.total_tokens >= *budget) {
+            return Err(Report::new(ChatError::TenantBudgetExceeded(self.id.clone()))
+                .attach_printable(format!("Used {} tokens of a {} token budget", state.usage.total_tokens, budget)));
+        }
+
+        let now = Instant::now();
+        if let Some(limit) = &self.rate_limit {
+            while state.requests.front().is_some_and(|at| now.duration_since(*at) >= limit.per) {
+                state.requests.pop_front();
+            }
+            let available = match priority.is_background() {
+                true => limit.requests.saturating_sub(self.interactive_reserve),
+                false => limit.requests,
+            };
+            if state.requests.len() >= available as usize {
+                let retry_after = state.requests.front().map(|at| limit.per - now.duration_since(*at));
+                return Err(Report::new(ChatError::TenantRateLimited(self.id.clone())).attach_printable(format!(
+                    "At most {} {}requests per {:?}, retry after {:?}",
+                    available,
+                    if priority.is_background() { "background " } else { "" },
+                    limit.per,
+                    retry_after.unwrap_or_default()
+                )));
+            }
+            state.requests.push_back(now);
+        }
+        Ok(())
+    }
+
+    /// 计入一次已结束的调用的用量
+    /// Count the usage of one finished call
+    pub fn record(&self, call: &LlmCall) {
+        let totals = UsageTotals::from(&UsageRecord::from_call(call));
+        let mut state = self.state.lock().unwrap();
+        let exhausted = |usage: &UsageTotals| {
+            self.budget.is_some_and(|budget| usage.cost >= budget)
+                || self.token_budget.is_some_and(|budget| usage.total_tokens >= budget)
+        };
+        let was_exhausted = exhausted(&state.usage);
+        state.usage += &totals;
+        if !was_exhausted && exhausted(&state.usage) {
+            warn!("Tenant {} has used up its budget", self.id);
+        }
+    }
+}
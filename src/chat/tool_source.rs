@@ -0,0 +1,235 @@
+// 标准库
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::Arc;
+
+// 错误处理
+use error_stack::{Report, Result};
+
+// 异步
+use futures::future::{BoxFuture, try_join_all};
+use tokio::task;
+
+// 数据序列化
+use serde_json::{Value, json};
+
+// 项目内部模块
+use crate::chat::chat_base::ChatError;
+use crate::chat::scratchpad::{SCRATCHPAD_READ, SCRATCHPAD_WRITE, Scratchpad, ScratchpadTools};
+use crate::chat::subagent::registered_agent;
+use crate::chat::transaction::ToolJournal;
+use crate::schema::tool_schema::{ChatToolSchemaError, ToolFunction, get_tool_function};
+
+/// 命名空间与工具名称之间的分隔符，OpenAI 的工具名称不允许使用 `.` 或 `/`
+/// Separator between the namespace and the tool name, OpenAI tool names do not allow `.` or `/`
+pub const NAMESPACE_SEPARATOR: &str = "__";
+
+/// 工具来源，如本地注册表、MCP 服务或由 OpenAPI 文档导入的接口；一个对话可以同时使用多个来源的工具，见
+/// `SingleChat::set_tool_sources`
+/// Source of tools, such as the local registry, an MCP server or a service imported from an OpenAPI document; one
+/// chat can use the tools of several sources at once, see `SingleChat::set_tool_sources`
+pub trait ToolSource: Send + Sync {
+    /// 命名空间，来源中的工具以 `<namespace>__<name>` 的名称提供给模型，避免不同来源的工具重名；为 None 时保留原名
+    /// Namespace, tools of the source are offered to the model as `<namespace>__<name>` so tools of different
+    /// sources do not collide; their own names are kept if None
+    fn namespace(&self) -> Option<&str>;
+
+    /// 来源中的工具定义，OpenAI 格式，使用来源自己的名称
+    /// Tool definitions of the source in OpenAI format, under the source's own names
+    fn list_tools(&self) -> BoxFuture<'_, Result<Vec<Value>, ChatError>>;
+
+    /// 以来源自己的名称调用工具
+    /// Call a tool by the source's own name
+    fn call_tool<'a>(&'a self, name: &'a str, arguments: Value) -> BoxFuture<'a, Result<Value, ChatToolSchemaError>>;
+}
+
+/// 模型看到的工具名称
+/// Tool name as seen by the model
+pub fn namespaced(namespace: Option<&str>, name: &str) -> String {
+    match namespace {
+        Some(namespace) => format!("{}{}{}", namespace, NAMESPACE_SEPARATOR, name),
+        None => name.to_string(),
+    }
+}
+
+/// OpenAI 格式的工具定义，缺少的描述与参数属性补为空值，以便组装工具提示
+/// Tool definition in OpenAI format, a missing description or parameter properties are filled with empty values so
+/// the tools prompt can be assembled
+pub fn function_tool(name: &str, description: Option<&str>, parameters: Option<&Value>) -> Value {
+    let mut parameters = parameters.cloned().unwrap_or_else(|| json!({"type": "object"}));
+    if parameters.get("properties").is_none() {
+        parameters["properties"] = json!({});
+    }
+    json!({
+        "type": "function",
+        "function": {"name": name, "description": description.unwrap_or_default(), "parameters": parameters}
+    })
+}
+
+/// 本地注册表中的工具，调用时在阻塞线程上运行注册的函数
+/// Tools of the local registry, the registered functions run on a blocking thread when called
+#[derive(Clone, Debug, Default)]
+pub struct LocalTools {
+    namespace: Option<String>,
+    tools: Vec<Value>,
+}
+
+impl LocalTools {
+    /// `tools` 为本地注册的工具的定义，见 `get_tool_registry`
+    /// `tools` are the definitions of locally registered tools, see `get_tool_registry`
+    pub fn new(tools: Vec<Value>) -> Self {
+        Self { namespace: None, tools }
+    }
+
+    pub fn namespace(mut self, namespace: &str) -> Self {
+        self.namespace = Some(namespace.to_string());
+        self
+    }
+}
+
+impl ToolSource for LocalTools {
+    fn namespace(&self) -> Option<&str> {
+        self.namespace.as_deref()
+    }
+
+    fn list_tools(&self) -> BoxFuture<'_, Result<Vec<Value>, ChatError>> {
+        Box::pin(async move { Ok(self.tools.clone()) })
+    }
+
+    fn call_tool<'a>(&'a self, name: &'a str, arguments: Value) -> BoxFuture<'a, Result<Value, ChatToolSchemaError>> {
+        Box::pin(async move {
+            if let Some(agent) = registered_agent(name) {
+                return agent.call_tool(name, arguments).await;
+            }
+            let tool_fn = get_tool_function(name).ok_or_else(|| {
+                Report::new(ChatToolSchemaError::FunctionCallError)
+                    .attach_printable(format!("Tool {} is not registered", name))
+            })?;
+            task::spawn_blocking(move || tool_fn(arguments)).await.map_err(|e| {
+                Report::new(ChatToolSchemaError::FunctionCallError).attach_printable(e.to_string())
+            })?
+        })
+    }
+}
+
+/// 按名称找到的工具：某一来源中的工具，或本地注册表中的函数
+/// A tool found by name: a tool of some source, or a function of the local registry
+#[derive(Clone)]
+pub(crate) enum ResolvedTool {
+    Source(Arc<dyn ToolSource>, String),
+    Local(ToolFunction),
+}
+
+impl Debug for ResolvedTool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Source(source, name) => write!(f, "Source({:?}, {})", source.namespace(), name),
+            Self::Local(_) => f.write_str("Local"),
+        }
+    }
+}
+
+/// 工具所在的来源与来源中的名称
+/// Source of a tool and its name there
+type Route = (Arc<dyn ToolSource>, String);
+
+/// 模型看到的工具名称到来源及来源中名称的映射，克隆共享同一份映射
+/// Mapping from the tool names seen by the model to their source and name in it, clones share one mapping
+#[derive(Clone, Default)]
+pub struct ToolRoutes {
+    routes: Arc<HashMap<String, Route>>,
+
+    /// 事务中的意图日志，有副作用的工具调用记录在这里而不是立即执行
+    /// Intent journal of a transaction, calls to tools with side effects are recorded there instead of running
+    journal: Option<ToolJournal>,
+
+    /// 对话的草稿本，设置后 `scratchpad_write` 与 `scratchpad_read` 写入和读取它
+    /// The chat's scratchpad, `scratchpad_write` and `scratchpad_read` write and read it when set
+    scratchpad: Option<Arc<ScratchpadTools>>,
+}
+
+impl Debug for ToolRoutes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut names: Vec<_> = self.routes.keys().collect();
+        names.sort();
+        f.debug_tuple("ToolRoutes").field(&names).finish()
+    }
+}
+
+impl ToolRoutes {
+    pub fn is_empty(&self) -> bool {
+        self.routes.is_empty()
+    }
+
+    /// 按模型看到的名称找到工具，不在任何来源中时查找本地注册表中的函数与子智能体
+    /// Find a tool by the name seen by the model, falling back to the functions and sub-agents of the local registry
+    /// if no source has it
+    pub(crate) fn resolve(&self, name: &str) -> Option<ResolvedTool> {
+        if let Some(scratchpad) = &self.scratchpad
+            && matches!(name, SCRATCHPAD_WRITE | SCRATCHPAD_READ)
+        {
+            return Some(ResolvedTool::Source(scratchpad.clone(), name.to_string()));
+        }
+        match self.routes.get(name) {
+            Some((source, name)) => Some(ResolvedTool::Source(source.clone(), name.clone())),
+            None => get_tool_function(name).map(ResolvedTool::Local).or_else(|| {
+                registered_agent(name).map(|agent| ResolvedTool::Source(agent, name.to_string()))
+            }),
+        }
+    }
+
+    /// 同样的路由，有副作用的工具调用记录到 `journal`
+    /// The same routes, with calls to tools with side effects recorded into `journal`
+    pub(crate) fn with_journal(&self, journal: ToolJournal) -> Self {
+        Self {
+            journal: Some(journal),
+            ..self.clone()
+        }
+    }
+
+    /// 同样的路由，草稿本工具写入和读取 `scratchpad`
+    /// The same routes, with the scratchpad tools writing and reading `scratchpad`
+    pub(crate) fn with_scratchpad(&self, scratchpad: Scratchpad) -> Self {
+        Self {
+            scratchpad: Some(Arc::new(ScratchpadTools(scratchpad))),
+            ..self.clone()
+        }
+    }
+
+    pub(crate) fn journal(&self) -> Option<&ToolJournal> {
+        self.journal.as_ref()
+    }
+}
+
+/// 并发列出各来源的工具，加上命名空间后合并为一份工具定义，并返回对应的路由；加上命名空间后仍然重名时返回错误
+/// List the tools of every source concurrently and merge them, namespaced, into one set of definitions, returned
+/// with the matching routes; names still colliding after namespacing are an error
+pub async fn collect_tools(sources: &[Arc<dyn ToolSource>]) -> Result<(Vec<Value>, ToolRoutes), ChatError> {
+    let listed = try_join_all(sources.iter().map(|source| source.list_tools())).await?;
+
+    let mut tools = Vec::new();
+    let mut routes = HashMap::new();
+    for (source, source_tools) in sources.iter().zip(listed) {
+        for mut tool in source_tools {
+            let Some(name) = tool["function"]["name"].as_str().map(str::to_string) else {
+                return Err(Report::new(ChatError::ToolSourceError(
+                    source.namespace().unwrap_or("local").to_string(),
+                ))
+                .attach_printable(format!("Tool definition without a name: {}", tool)));
+            };
+            let visible = namespaced(source.namespace(), &name);
+            if routes.insert(visible.clone(), (source.clone(), name)).is_some() {
+                return Err(Report::new(ChatError::InvalidConfig)
+                    .attach_printable(format!("Tool name {} is provided by more than one source", visible)));
+            }
+            tool["function"]["name"] = json!(visible);
+            tools.push(tool);
+        }
+    }
+    let routes = ToolRoutes {
+        routes: Arc::new(routes),
+        journal: None,
+        scratchpad: None,
+    };
+    Ok((tools, routes))
+}
@@ -0,0 +1,236 @@
+// 标准库
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+
+// 错误处理
+use error_stack::Result;
+
+// 数据序列化
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+// 异步
+use tokio::task;
+
+// 观测诊断
+use tracing::{info, warn};
+
+// 项目内部模块
+use crate::chat::tool_source::ResolvedTool;
+use crate::config::Config;
+use crate::config::prompts::PromptKey;
+use crate::schema::tool_schema::ChatToolSchemaError;
+
+/// 撤销已提交操作的补偿函数，参数为工具调用的参数与输出
+/// Compensation undoing a committed action, given the arguments and the output of the tool call
+pub type Compensation = Arc<dyn Fn(&Value, &Value) -> Result<(), ChatToolSchemaError> + Send + Sync>;
+
+/// 命名的事务作用域：声明哪些工具有副作用，`run_agent` 运行期间对这些工具的调用只记录为意图，
+/// 运行成功结束后才依次执行，运行失败、到期或达到步数上限时全部丢弃，避免中断的循环留下执行了一半的操作
+/// Named transaction scope: declares which tools have side effects; during `run_agent` calls to them are only
+/// recorded as intents, executed in order once the run completes successfully and discarded when it fails, times
+/// out or hits the step limit, so aborted loops do not leave half-applied actions behind
+///
+/// 提交时某个意图执行失败，已执行的意图按相反顺序调用其补偿函数（如有），其余意图不再执行。
+/// 没有副作用的工具照常立即执行。
+/// If an intent fails on commit, the ones already executed are compensated in reverse order (when they have a
+/// compensation) and the rest are not executed. Tools without side effects run right away as usual.
+///
+/// ```ignore
+/// let scope = TransactionScope::new("checkout")
+///     .side_effect("charge_card")
+///     .compensate("reserve_stock", |arguments, _| release_stock(arguments));
+/// chat.set_transaction(scope);
+/// let run = chat.run_agent("买两件并付款", Deadline::after(Duration::from_secs(60))).await?;
+/// ```
+#[derive(Clone, Default)]
+pub struct TransactionScope {
+    name: String,
+    side_effects: HashMap<String, Option<Compensation>>,
+}
+
+impl Debug for TransactionScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut tools: Vec<_> = self.side_effects.keys().collect();
+        tools.sort();
+        f.debug_struct("TransactionScope").field("name", &self.name).field("side_effects", &tools).finish()
+    }
+}
+
+impl TransactionScope {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            side_effects: HashMap::new(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// 声明有副作用但无法撤销的工具，以模型看到的名称
+    /// Declare a tool with side effects that cannot be undone, by the name seen by the model
+    pub fn side_effect(mut self, tool: &str) -> Self {
+        self.side_effects.entry(tool.to_string()).or_insert(None);
+        self
+    }
+
+    /// 声明有副作用的工具及其补偿函数，提交中途失败时用于撤销已执行的调用
+    /// Declare a tool with side effects and its compensation, used to undo executed calls when a commit fails
+    /// halfway
+    pub fn compensate(
+        mut self,
+        tool: &str,
+        compensation: impl Fn(&Value, &Value) -> Result<(), ChatToolSchemaError> + Send + Sync + 'static,
+    ) -> Self {
+        self.side_effects.insert(tool.to_string(), Some(Arc::new(compensation)));
+        self
+    }
+
+    pub fn has_side_effect(&self, tool: &str) -> bool {
+        self.side_effects.contains_key(tool)
+    }
+}
+
+/// 意图的状态
+/// Status of an intent
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IntentStatus {
+    /// 已记录，等待运行结束
+    /// Recorded, waiting for the run to end
+    #[default]
+    Pending,
+
+    /// 已执行
+    /// Executed
+    Committed,
+
+    /// 执行失败，导致提交中止
+    /// Failed to execute, aborting the commit
+    Failed,
+
+    /// 未执行即被丢弃
+    /// Discarded without being executed
+    RolledBack,
+
+    /// 已执行，随后被补偿函数撤销
+    /// Executed, then undone by its compensation
+    Compensated,
+}
+
+/// 事务中记录的一次有副作用的工具调用
+/// A tool call with side effects recorded in a transaction
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ToolIntent {
+    pub call_id: String,
+
+    /// 模型看到的工具名称
+    /// Tool name as seen by the model
+    pub name: String,
+
+    pub arguments: Value,
+
+    pub status: IntentStatus,
+
+    /// 执行后的输出或错误输出，尚未执行时为 None
+    /// Output or error output once executed, None before
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+}
+
+/// 一次运行的意图日志，克隆共享同一份日志，供并发的工具调用与子智能体记录意图
+/// Intent journal of one run, clones share the journal so concurrent tool calls and sub-agents can record into it
+///
+/// 意图与记录它的工具一同保存，提交时执行的正是当时找到的工具，子智能体自己的工具也不例外。
+/// Intents are kept together with the tool that recorded them, so the commit runs the very tool found back then,
+/// including tools only a sub-agent has.
+#[derive(Clone, Debug)]
+pub struct ToolJournal {
+    scope: TransactionScope,
+    intents: Arc<Mutex<Vec<(ToolIntent, ResolvedTool)>>>,
+}
+
+impl ToolJournal {
+    pub(crate) fn new(scope: TransactionScope) -> Self {
+        Self {
+            scope,
+            intents: Arc::default(),
+        }
+    }
+
+    pub fn scope(&self) -> &TransactionScope {
+        &self.scope
+    }
+
+    /// 工具的调用是否只记录为意图
+    /// Whether calls to the tool are only recorded as intents
+    pub(crate) fn defers(&self, tool: &str) -> bool {
+        self.scope.has_side_effect(tool)
+    }
+
+    /// 记录一次调用的意图及要调用的工具，返回交给模型的工具输出
+    /// Record the intent of a call with the tool to call, returning the tool output handed to the model
+    pub(crate) fn record(&self, call_id: &str, name: &str, arguments: Value, tool: ResolvedTool) -> String {
+        info!("Recorded intent of {} in transaction {}", name, self.scope.name);
+        let intent = ToolIntent {
+            call_id: call_id.to_string(),
+            name: name.to_string(),
+            arguments,
+            ..ToolIntent::default()
+        };
+        self.intents.lock().unwrap().push((intent, tool));
+        let output = json!({
+            "status": "pending",
+            "message": Config::capability_prompt(&PromptKey::TransactionPending),
+        });
+        serde_json::to_string_pretty(&output).unwrap_or_default()
+    }
+
+    /// 取出记录的全部意图及各自的工具
+    /// Take every recorded intent with its tool
+    pub(crate) fn take(&self) -> (Vec<ToolIntent>, Vec<ResolvedTool>) {
+        std::mem::take(&mut *self.intents.lock().unwrap()).into_iter().unzip()
+    }
+
+    /// 丢弃全部尚未执行的意图
+    /// Discard every intent not executed yet
+    pub(crate) fn roll_back(&self) -> Vec<ToolIntent> {
+        let (mut intents, _) = self.take();
+        for intent in intents.iter_mut().filter(|intent| intent.status == IntentStatus::Pending) {
+            intent.status = IntentStatus::RolledBack;
+        }
+        if !intents.is_empty() {
+            info!("Rolled back {} intents of transaction {}", intents.len(), self.scope.name);
+        }
+        intents
+    }
+
+    /// 提交失败后的处理：尚未执行的意图标记为丢弃，已执行的按相反顺序调用补偿函数
+    /// Clean up after a failed commit: intents not executed yet are marked discarded and the executed ones are
+    /// compensated in reverse order
+    pub(crate) async fn compensate(&self, intents: &mut [ToolIntent]) {
+        for intent in intents.iter_mut().rev() {
+            match intent.status {
+                IntentStatus::Pending => intent.status = IntentStatus::RolledBack,
+                IntentStatus::Committed => {
+                    let Some(Some(compensation)) = self.scope.side_effects.get(&intent.name).cloned() else {
+                        warn!("Intent {} of {} has no compensation and stays applied", intent.call_id, intent.name);
+                        continue;
+                    };
+                    let arguments = intent.arguments.clone();
+                    let output = intent.output.as_deref().and_then(|output| serde_json::from_str(output).ok());
+                    let output = output.unwrap_or(Value::Null);
+                    match task::spawn_blocking(move || compensation(&arguments, &output)).await {
+                        Ok(Ok(())) => intent.status = IntentStatus::Compensated,
+                        Ok(Err(e)) => warn!("Failed to compensate {} of {}: {:?}", intent.call_id, intent.name, e),
+                        Err(e) => warn!("Compensation of {} of {} panicked: {}", intent.call_id, intent.name, e),
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
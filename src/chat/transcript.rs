@@ -0,0 +1,80 @@
+// 数据序列化
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+// 项目内部模块
+use crate::chat::message::{Role, Session};
+
+/// 查看多角色对话记录的视角
+/// Point of view a multi-character transcript is viewed from
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Perspective {
+    /// 某一角色的视角，与该角色发言时请求中看到的一致：自己的消息为 assistant，其他角色的消息带上发言者前缀作为 user
+    /// A character's point of view, the same as its requests see it: its own messages are assistant messages and
+    /// the other characters' messages user messages prefixed with the speaker
+    Character(String),
+
+    /// 中立的旁观视角，每条消息标明发言者，不区分 assistant 与 user
+    /// Neutral observer view, every message labelled with its speaker without telling assistant from user
+    Observer,
+}
+
+impl Perspective {
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Character(name) => name,
+            Self::Observer => "observer",
+        }
+    }
+}
+
+/// 旁观视角中的一条消息
+/// One message of the observer view
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TranscriptEntry {
+    /// 发言者：角色名称，或 `system`、`user`、`assistant`、`tool`
+    /// The speaker: a character name, or `system`, `user`, `assistant` or `tool`
+    pub speaker: String,
+
+    pub content: String,
+}
+
+/// 旁观视角的对话记录，沿默认路径
+/// Observer view of a conversation, along its default path
+pub fn observer_transcript(session: &Session) -> Vec<TranscriptEntry> {
+    session.default_path_messages()
+        .into_iter()
+        .map(|message| TranscriptEntry {
+            speaker: message.role.to_string(),
+            content: message.content.to_text().into_owned(),
+        })
+        .collect()
+}
+
+/// 按视角渲染沿默认路径的对话记录，格式为 `{"perspective": ..., "messages": [...]}`；角色视角的消息为请求格式，
+/// 旁观视角的消息为 `{"speaker": ..., "content": ...}`
+/// Render the conversation along its default path from a point of view, as `{"perspective": ..., "messages":
+/// [...]}`; the messages of a character's view are in request format, those of the observer view
+/// `{"speaker": ..., "content": ...}`
+pub fn render_transcript(session: &Session, perspective: &Perspective) -> Value {
+    let messages = match perspective {
+        Perspective::Character(name) => {
+            let speaker = Role::Character(name.clone());
+            let messages: Vec<_> =
+                session.default_path_messages().into_iter().map(|message| message.to_api_format(&speaker)).collect();
+            json!(messages)
+        }
+        Perspective::Observer => json!(observer_transcript(session)),
+    };
+    json!({"perspective": perspective.name(), "messages": messages})
+}
+
+/// 旁观视角的 Markdown 文本，每条消息一段，以发言者开头
+/// Markdown text of the observer view, one paragraph per message starting with the speaker
+pub fn observer_markdown(session: &Session) -> String {
+    observer_transcript(session)
+        .iter()
+        .map(|entry| format!("**{}**: {}", entry.speaker, entry.content))
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
@@ -0,0 +1,341 @@
+// 标准库
+use std::fmt::Debug;
+use std::sync::Arc;
+
+// 文本处理
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+// 项目内部模块
+use crate::chat::pii::{PiiScrubber, StreamRestorer};
+
+/// 行首的标题与引用标记
+/// Heading and quote markers at the start of a line
+static LINE_MARKER: Lazy<Regex> = Lazy::new(|| Regex::new(r"^(\s*)(?:#{1,6}\s+|(?:>\s?)+)").unwrap());
+
+/// 分隔线
+/// Horizontal rules
+static RULE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^\s*(?:[-*_]\s*){3,}$").unwrap());
+
+/// 链接与图片，保留文字
+/// Links and images, keeping their text
+static LINK: Lazy<Regex> = Lazy::new(|| Regex::new(r"!?\[([^\]]*)\]\([^)]*\)").unwrap());
+
+/// 单个星号包围的强调
+/// Emphasis wrapped in single asterisks
+static EMPHASIS: Lazy<Regex> = Lazy::new(|| Regex::new(r"\*([^*\s][^*]*?)\*").unwrap());
+
+/// 流式回答的变换，在增量到达应用之前改写它们
+/// Transformation of a streamed answer, rewriting the deltas before they reach the application
+///
+/// 变换可以暂存尚不能确定的文本（如可能被切断的词），在后续增量或 `finish` 中发出。
+/// 变换只影响应用收到的增量，写入会话的回答仍是模型的原始输出。
+/// A transformer may hold back text it cannot decide on yet (such as a possibly split word) and send it with a
+/// later delta or from `finish`. Transformers only shape the deltas the application receives, the answer stored in
+/// the session is still the model's raw output.
+pub trait StreamTransformer: Send {
+    /// 处理一段增量，返回可以发出的文本
+    /// Process a delta, returning the text that can be sent
+    fn push(&mut self, delta: &str) -> String;
+
+    /// 流结束时发出暂存的文本
+    /// Send the held-back text when the stream ends
+    fn finish(&mut self) -> String {
+        String::new()
+    }
+
+    /// 为 true 时不再接收模型输出，流随即结束
+    /// When true no more model output is taken and the stream ends
+    fn stopped(&self) -> bool {
+        false
+    }
+}
+
+type TransformerFactory = Arc<dyn Fn() -> Box<dyn StreamTransformer> + Send + Sync>;
+
+/// 按顺序执行的流式变换链，每个流使用各变换的一份新副本
+/// Chain of stream transformers run in order, every stream uses fresh copies of the transformers
+#[derive(Clone, Default)]
+pub struct StreamTransformers {
+    factories: Vec<TransformerFactory>,
+}
+
+impl Debug for StreamTransformers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StreamTransformers").field("transformers", &self.factories.len()).finish()
+    }
+}
+
+impl StreamTransformers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 追加一个变换，前一个变换的输出作为它的输入
+    /// Append a transformer, fed with the output of the previous one
+    pub fn with(mut self, transformer: impl StreamTransformer + Clone + Sync + 'static) -> Self {
+        self.factories.push(Arc::new(move || Box::new(transformer.clone())));
+        self
+    }
+
+    /// 为一个流创建变换链的状态
+    /// Create the chain state for one stream
+    pub fn start(&self) -> TransformerChain {
+        TransformerChain {
+            transformers: self.factories.iter().map(|factory| factory()).collect(),
+        }
+    }
+}
+
+/// 一个流的变换链状态
+/// State of the transformer chain for one stream
+#[derive(Default)]
+pub struct TransformerChain {
+    transformers: Vec<Box<dyn StreamTransformer>>,
+}
+
+impl TransformerChain {
+    /// 在链首插入一个变换
+    /// Insert a transformer at the head of the chain
+    pub fn prepend(&mut self, transformer: impl StreamTransformer + 'static) {
+        self.transformers.insert(0, Box::new(transformer));
+    }
+}
+
+impl StreamTransformer for TransformerChain {
+    fn push(&mut self, delta: &str) -> String {
+        let mut text = delta.to_string();
+        for transformer in &mut self.transformers {
+            if text.is_empty() {
+                break;
+            }
+            text = transformer.push(&text);
+        }
+        text
+    }
+
+    /// 依次结束各变换，前面变换的剩余文本先经过后面的变换
+    /// Finish the transformers in order, the rest of an earlier transformer goes through the later ones first
+    fn finish(&mut self) -> String {
+        let mut text = String::new();
+        for transformer in &mut self.transformers {
+            if !text.is_empty() {
+                text = transformer.push(&text);
+            }
+            text.push_str(&transformer.finish());
+        }
+        text
+    }
+
+    fn stopped(&self) -> bool {
+        self.transformers.iter().any(|transformer| transformer.stopped())
+    }
+}
+
+/// 以星号遮盖词语（不区分大小写），与 `DenyList` 的改写一致；可能被切断的词暂存到下一段增量
+/// Mask words with asterisks (case-insensitive), matching the rewrite of `DenyList`; a possibly split word is held
+/// back until the next delta
+#[derive(Clone, Debug)]
+pub struct MaskWords {
+    pattern: Option<Regex>,
+    max_chars: usize,
+    pending: String,
+}
+
+impl MaskWords {
+    pub fn new(words: impl IntoIterator<Item = impl AsRef<str>>) -> Self {
+        let mut words: Vec<String> =
+            words.into_iter().map(|word| word.as_ref().to_string()).filter(|word| !word.is_empty()).collect();
+        // 长词优先，避免只遮盖长词的前缀
+        // Longer words first, so a long word is not masked only up to a shorter prefix
+        words.sort_by_key(|word| std::cmp::Reverse(word.chars().count()));
+        let max_chars = words.first().map_or(0, |word| word.chars().count());
+        let alternatives: Vec<String> = words.iter().map(|word| regex::escape(word)).collect();
+        let pattern = (!alternatives.is_empty()).then(|| Regex::new(&format!("(?i){}", alternatives.join("|"))).unwrap());
+        Self {
+            pattern,
+            max_chars,
+            pending: String::new(),
+        }
+    }
+
+    fn mask(&self, text: &str) -> String {
+        match &self.pattern {
+            Some(pattern) => pattern
+                .replace_all(text, |captures: &regex::Captures| "*".repeat(captures[0].chars().count()))
+                .into_owned(),
+            None => text.to_string(),
+        }
+    }
+}
+
+impl StreamTransformer for MaskWords {
+    fn push(&mut self, delta: &str) -> String {
+        let Some(pattern) = &self.pattern else {
+            return delta.to_string();
+        };
+        self.pending.push_str(delta);
+
+        // 末尾不足一个最长词的部分可能是词的开头，连同跨越分界的匹配一起暂存
+        // The tail shorter than the longest word may start a word, it is held back along with any match crossing
+        // the split
+        let mut split = match self.max_chars - 1 {
+            0 => self.pending.len(),
+            held => self.pending.char_indices().rev().nth(held - 1).map_or(0, |(index, _)| index),
+        };
+        if let Some(found) = pattern.find_iter(&self.pending).find(|found| found.start() < split && found.end() > split) {
+            split = found.start();
+        }
+
+        let rest = self.pending.split_off(split);
+        let ready = std::mem::replace(&mut self.pending, rest);
+        self.mask(&ready)
+    }
+
+    fn finish(&mut self) -> String {
+        let rest = std::mem::take(&mut self.pending);
+        self.mask(&rest)
+    }
+}
+
+/// 去掉 Markdown 标记，保留文字，适合语音合成等纯文本输出；按行处理，每行在换行到达后发出
+/// Strip Markdown markup and keep the text, for plain text outputs such as speech synthesis; works line by line,
+/// each line is sent once its line break arrives
+///
+/// 处理标题、引用、分隔线、代码块围栏、链接与图片、加粗、斜体、删除线与行内代码；列表标记保持不变。
+/// Handles headings, quotes, horizontal rules, code fences, links and images, bold, italics, strikethrough and
+/// inline code; list markers are left as is.
+#[derive(Clone, Debug, Default)]
+pub struct StripMarkdown {
+    pending: String,
+}
+
+impl StripMarkdown {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+/// 去掉一行中的标记，代码块围栏返回 None
+/// Strip the markup of one line, None for code fences
+pub(crate) fn strip_line(line: &str) -> Option<String> {
+    let trimmed = line.trim_start();
+    if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+        return None;
+    }
+    if RULE.is_match(line) {
+        return Some(String::new());
+    }
+    let line = LINE_MARKER.replace(line, "$1");
+    let line = LINK.replace_all(&line, "$1");
+    let line = line.replace("**", "").replace("__", "").replace("~~", "").replace('`', "");
+    Some(EMPHASIS.replace_all(&line, "$1").into_owned())
+}
+
+impl StreamTransformer for StripMarkdown {
+    fn push(&mut self, delta: &str) -> String {
+        self.pending.push_str(delta);
+        let Some(end) = self.pending.rfind('\n') else {
+            return String::new();
+        };
+        let rest = self.pending.split_off(end + 1);
+        let lines = std::mem::replace(&mut self.pending, rest);
+        lines
+            .lines()
+            .filter_map(strip_line)
+            .map(|line| line + "\n")
+            .collect()
+    }
+
+    fn finish(&mut self) -> String {
+        strip_line(&std::mem::take(&mut self.pending)).unwrap_or_default()
+    }
+}
+
+/// 将占位符还原为原文，设置敏感信息清洗后自动位于链首
+/// Restore placeholders to the originals, placed at the head of the chain automatically when PII scrubbing is set
+#[derive(Clone, Debug)]
+pub struct RestorePii {
+    scrubber: PiiScrubber,
+    restorer: StreamRestorer,
+}
+
+impl RestorePii {
+    pub fn new(scrubber: PiiScrubber) -> Self {
+        Self {
+            scrubber,
+            restorer: StreamRestorer::default(),
+        }
+    }
+}
+
+impl StreamTransformer for RestorePii {
+    fn push(&mut self, delta: &str) -> String {
+        self.restorer.push(&self.scrubber, delta)
+    }
+
+    fn finish(&mut self) -> String {
+        self.restorer.finish(&self.scrubber)
+    }
+}
+
+/// 在客户端执行停止序列：遇到任一序列时截断并结束流，序列本身不发出
+/// Enforce stop sequences on the client: the stream is cut and ended at the first sequence, which is not sent
+///
+/// 用于不支持 `stop` 参数或限制其数量的提供商；可能是序列开头的末尾文本暂存到下一段增量。
+/// For providers that ignore the `stop` parameter or limit its size; a tail that may start a sequence is held back
+/// until the next delta.
+#[derive(Clone, Debug)]
+pub struct StopSequences {
+    sequences: Vec<String>,
+    pending: String,
+    stopped: bool,
+}
+
+impl StopSequences {
+    pub fn new(sequences: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self {
+            sequences: sequences.into_iter().map(Into::into).filter(|sequence: &String| !sequence.is_empty()).collect(),
+            pending: String::new(),
+            stopped: false,
+        }
+    }
+
+    /// 末尾可能是某个序列开头的最长部分的起点
+    /// Start of the longest tail that may begin a sequence
+    fn held_from(&self) -> usize {
+        self.pending
+            .char_indices()
+            .map(|(index, _)| index)
+            .find(|&index| {
+                let tail = &self.pending[index..];
+                self.sequences.iter().any(|sequence| sequence.starts_with(tail))
+            })
+            .unwrap_or(self.pending.len())
+    }
+}
+
+impl StreamTransformer for StopSequences {
+    fn push(&mut self, delta: &str) -> String {
+        if self.stopped {
+            return String::new();
+        }
+        self.pending.push_str(delta);
+
+        if let Some(position) = self.sequences.iter().filter_map(|sequence| self.pending.find(sequence.as_str())).min() {
+            self.stopped = true;
+            self.pending.truncate(position);
+            return std::mem::take(&mut self.pending);
+        }
+        let rest = self.pending.split_off(self.held_from());
+        std::mem::replace(&mut self.pending, rest)
+    }
+
+    fn finish(&mut self) -> String {
+        std::mem::take(&mut self.pending)
+    }
+
+    fn stopped(&self) -> bool {
+        self.stopped
+    }
+}
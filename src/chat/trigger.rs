@@ -0,0 +1,115 @@
+// 正则表达式
+use regex::{Captures, Regex};
+
+// 数据序列化
+use serde_json::{Map, Value, json};
+
+/// 由用户输入触发的工具调用：输入匹配正则时直接调用工具，不经模型选择，结果随本次请求发给模型
+/// Tool call triggered by the user input: when the input matches the pattern the tool is called directly, without
+/// the model choosing it, and the result goes to the model with the request
+///
+/// 适合总是需要的查询，如输入中出现工单号时先取回工单，省去一次模型往返。
+/// Suited to lookups that are always needed, such as fetching the ticket whenever a ticket key shows up in the
+/// input, saving a model round trip.
+///
+/// ```ignore
+/// let trigger = ToolTrigger::new(r"(?P<key>JIRA-\d+)", "get_ticket")?;
+/// chat.add_tool_trigger(trigger);
+/// ```
+#[derive(Clone, Debug)]
+pub struct ToolTrigger {
+    pattern: Regex,
+    tool: String,
+    arguments: Option<Value>,
+}
+
+impl ToolTrigger {
+    /// 默认以命名捕获组作为调用参数，如 `(?P<key>JIRA-\d+)` 匹配 `JIRA-123` 时参数为 `{"key": "JIRA-123"}`
+    /// The named capture groups are the call arguments by default, such as `{"key": "JIRA-123"}` when
+    /// `(?P<key>JIRA-\d+)` matches `JIRA-123`
+    pub fn new(pattern: &str, tool: &str) -> Result<Self, regex::Error> {
+        Ok(Self {
+            pattern: Regex::new(pattern)?,
+            tool: tool.to_string(),
+            arguments: None,
+        })
+    }
+
+    /// 参数模板，其中字符串里的 `$0`、`$1`、`$name` 等替换为捕获的内容
+    /// Arguments template, `$0`, `$1`, `$name` and the like in its strings are replaced with the captured text
+    pub fn arguments(mut self, template: Value) -> Self {
+        self.arguments = Some(template);
+        self
+    }
+
+    pub fn tool(&self) -> &str {
+        &self.tool
+    }
+
+    /// 输入中每处匹配的调用参数，相同的参数只调用一次
+    /// Call arguments for every match in the input, identical arguments are called once
+    pub fn calls(&self, input: &str) -> Vec<Value> {
+        let mut calls = Vec::new();
+        for captures in self.pattern.captures_iter(input) {
+            let arguments = match &self.arguments {
+                Some(template) => expand(template, &captures),
+                None => self.named_groups(&captures),
+            };
+            if !calls.contains(&arguments) {
+                calls.push(arguments);
+            }
+        }
+        calls
+    }
+
+    fn named_groups(&self, captures: &Captures) -> Value {
+        let arguments: Map<String, Value> = self
+            .pattern
+            .capture_names()
+            .flatten()
+            .filter_map(|name| captures.name(name).map(|found| (name.to_string(), json!(found.as_str()))))
+            .collect();
+        Value::Object(arguments)
+    }
+}
+
+fn expand(template: &Value, captures: &Captures) -> Value {
+    match template {
+        Value::String(text) => {
+            let mut expanded = String::new();
+            captures.expand(text, &mut expanded);
+            Value::String(expanded)
+        }
+        Value::Array(items) => Value::Array(items.iter().map(|item| expand(item, captures)).collect()),
+        Value::Object(fields) => {
+            Value::Object(fields.iter().map(|(key, value)| (key.clone(), expand(value, captures))).collect())
+        }
+        other => other.clone(),
+    }
+}
+
+/// 在请求的最后一条消息之前插入触发的工具调用结果，每项为工具名称、参数与输出
+/// Insert the results of the triggered tool calls before the last message of the request, each as the tool name,
+/// its arguments and output
+///
+/// 结果只进入本次请求，不写入会话历史。
+/// The results only go into this request and are not written to the session history.
+pub fn insert_tool_results(request_body: &mut Value, results: &[(String, String, String)]) {
+    if results.is_empty() {
+        return;
+    }
+
+    let results = results
+        .iter()
+        .map(|(name, arguments, output)| format!("[{} {}]\n{}", name, arguments, output))
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    let message = json!({
+        "role": "system",
+        "content": format!("以下是根据用户输入自动调用工具得到的结果：\n{}", results),
+    });
+    if let Some(messages) = request_body.get_mut("messages").and_then(|messages| messages.as_array_mut()) {
+        let position = messages.len().saturating_sub(1);
+        messages.insert(position, message);
+    }
+}
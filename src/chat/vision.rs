@@ -0,0 +1,169 @@
+// 错误处理
+use error_stack::{Report, Result, ResultExt};
+
+// 异步
+use futures::future::BoxFuture;
+
+// 数据序列化
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+// 项目内部模块
+use crate::chat::attachment::load_attachment;
+use crate::chat::chat_base::{BaseChat, ChatError};
+use crate::chat::content::Content;
+use crate::chat::image::ImagePart;
+use crate::chat::message::Role;
+use crate::chat::ocr::vision_api;
+use crate::chat::tool_source::{ToolSource, function_tool};
+use crate::config::Config;
+use crate::config::prompts::PromptKey;
+use crate::schema::tool_schema::ChatToolSchemaError;
+
+/// 图片问答工具的名称
+/// Name of the image question answering tool
+pub const VISION_TOOL: &str = "ask_image";
+
+/// 视觉模型对图片问题的回答
+/// Answer of the vision model to a question about an image
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VisionAnswer {
+    pub answer: String,
+
+    /// 回答所用视觉模型的API名称
+    /// API name of the vision model that answered
+    pub engine: String,
+}
+
+/// 内置的图片问答工具 `ask_image`，接收图片附件的引用与问题，交给支持视觉的模型回答，
+/// 使不支持视觉的主模型也能在工具循环中处理截图、图表等视觉问题
+/// Built-in image question answering tool `ask_image`, which takes a reference to an image attachment and a
+/// question and hands them to a vision-capable model, so primary models without vision can delegate visual
+/// questions about screenshots, diagrams and the like through the tool loop
+///
+/// 图片参数可以是附件ID（见 `SingleChat::attach`，请求中的附件清单列出了ID），也可以是 http(s) 网址或 base64 data URL。
+/// The image argument may be an attachment ID (see `SingleChat::attach`, the attachment list in requests shows the
+/// IDs), an http(s) URL or a base64 data URL.
+///
+/// ```ignore
+/// chat.set_tool_sources(vec![Arc::new(VisionTools::with_api("vision"))]).await?;
+/// chat.attach("screenshot.png", "image/png", png, None)?;
+/// let answer = chat.get_answer("截图里的报错是什么意思？").await?;
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct VisionTools {
+    namespace: Option<String>,
+
+    /// 为 None 时使用第一个支持视觉的已配置模型
+    /// The first configured model supporting vision if None
+    api: Option<String>,
+
+    /// 为 None 时使用提示词表中的 `vision_instruction`
+    /// `vision_instruction` of the prompt table if None
+    instruction: Option<String>,
+}
+
+impl VisionTools {
+    /// 使用第一个支持视觉的已配置模型，见 `ModelMetadata::supports_vision`
+    /// Use the first configured model supporting vision, see `ModelMetadata::supports_vision`
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 使用指定API的模型回答
+    /// Answer with the model of the given API
+    pub fn with_api(api: &str) -> Self {
+        Self {
+            api: Some(api.to_string()),
+            ..Self::new()
+        }
+    }
+
+    pub fn namespace(mut self, namespace: &str) -> Self {
+        self.namespace = Some(namespace.to_string());
+        self
+    }
+
+    /// 替换给视觉模型的默认指令，如要求按领域惯例描述图表
+    /// Replace the default instruction of the vision model, such as to describe charts by the domain's conventions
+    pub fn instruction(mut self, instruction: &str) -> Self {
+        self.instruction = Some(instruction.to_string());
+        self
+    }
+
+    /// 就图片提问
+    /// Ask a question about an image
+    pub async fn ask(&self, image: ImagePart, question: &str) -> Result<VisionAnswer, ChatError> {
+        let api = match &self.api {
+            Some(api) => api.clone(),
+            None => vision_api().ok_or_else(|| {
+                Report::new(ChatError::InvalidConfig).attach_printable("No configured model supports vision")
+            })?,
+        };
+        let mut chat = BaseChat::try_new_with_api_name(&api, "", false)?;
+        let instruction = match &self.instruction {
+            Some(instruction) => instruction.clone(),
+            None => Config::capability_prompt(&PromptKey::VisionInstruction),
+        };
+        chat.add_message(Role::System, &instruction)?;
+        chat.add_content(Role::User, Content::Parts(vec![Content::from(question.to_string()), image.into()]))?;
+        let request_body = chat.build_request_body(&chat.session.default_path.clone(), &Role::User)?;
+        let answer = chat.get_content(request_body).await?;
+        Ok(VisionAnswer {
+            answer: answer.trim().to_string(),
+            engine: api,
+        })
+    }
+
+    /// 按工具参数读取图片：网址直接使用，其他视为附件ID
+    /// Read the image given by the tool argument: URLs are used as is, anything else is taken as an attachment ID
+    fn image(&self, image: &str) -> Result<ImagePart, ChatError> {
+        if image.starts_with("http://") || image.starts_with("https://") || image.starts_with("data:") {
+            return ImagePart::from_url(image);
+        }
+        let bytes = load_attachment(image.trim_start_matches('#'))?;
+        ImagePart::from_bytes(bytes.to_vec())
+    }
+}
+
+impl ToolSource for VisionTools {
+    fn namespace(&self) -> Option<&str> {
+        self.namespace.as_deref()
+    }
+
+    fn list_tools(&self) -> BoxFuture<'_, Result<Vec<Value>, ChatError>> {
+        Box::pin(async move {
+            let parameters = json!({
+                "type": "object",
+                "properties": {
+                    "image": {
+                        "type": "string",
+                        "description": Config::capability_prompt(&PromptKey::VisionImageArgument),
+                    },
+                    "question": {
+                        "type": "string",
+                        "description": Config::capability_prompt(&PromptKey::VisionQuestionArgument),
+                    },
+                },
+                "required": ["image", "question"],
+            });
+            let description = Config::capability_prompt(&PromptKey::VisionToolDescription);
+            Ok(vec![function_tool(VISION_TOOL, Some(&description), Some(&parameters))])
+        })
+    }
+
+    fn call_tool<'a>(&'a self, name: &'a str, arguments: Value) -> BoxFuture<'a, Result<Value, ChatToolSchemaError>> {
+        Box::pin(async move {
+            let call_error = || ChatToolSchemaError::FunctionCallError;
+            if name != VISION_TOOL {
+                return Err(Report::new(call_error()).attach_printable(format!("No tool named {}", name)));
+            }
+            let params_error = || ChatToolSchemaError::ParamsParseError(name.to_string(), arguments.to_string());
+            let image = arguments["image"].as_str().ok_or_else(|| Report::new(params_error()))?;
+            let question = arguments["question"].as_str().ok_or_else(|| Report::new(params_error()))?;
+            let image = self.image(image).change_context_lazy(call_error)?;
+            let answer = self.ask(image, question).await.change_context_lazy(call_error)?;
+            serde_json::to_value(answer).change_context_lazy(|| ChatToolSchemaError::ResultParseError(name.to_string()))
+        })
+    }
+}
@@ -0,0 +1,150 @@
+// 标准库
+use std::time::{Duration, Instant};
+
+// 并发和同步原语
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+
+// 随机数
+use rand::Rng;
+
+// 错误处理
+use error_stack::{Report, Result};
+
+// 项目内部模块
+use crate::config::{ApiInfo, CFG, ConfigError};
+
+/// API端点健康状态
+/// API endpoint health status
+#[derive(Clone, Debug)]
+pub struct EndpointHealth {
+    /// 是否健康，不健康的端点不会被按能力选中
+    /// Whether healthy, unhealthy endpoints are skipped by capability selection
+    pub healthy: bool,
+
+    /// 最近一次探测的延迟
+    /// Latency of the last probe
+    pub latency: Option<Duration>,
+
+    /// 最近一次探测的时间
+    /// Time of the last probe
+    pub last_checked: Option<Instant>,
+
+    /// 探测到的原生工具调用支持情况，未探测时为 None
+    /// Detected native tool calling support, None if not probed
+    pub supports_tools: Option<bool>,
+
+    /// 凭据失效或额度用尽后的冷却截止时间，截止前端点视为不健康
+    /// End of the cool-down after a credential failed or ran out of quota, the endpoint is considered unhealthy
+    /// until then
+    pub cooldown_until: Option<Instant>,
+}
+
+impl Default for EndpointHealth {
+    fn default() -> Self {
+        Self {
+            healthy: true,
+            latency: None,
+            last_checked: None,
+            supports_tools: None,
+            cooldown_until: None,
+        }
+    }
+}
+
+impl EndpointHealth {
+    /// 是否处于冷却中
+    /// Whether the endpoint is cooling down
+    pub fn cooling_down(&self) -> bool {
+        self.cooldown_until.is_some_and(|until| Instant::now() < until)
+    }
+}
+
+/// 凭据失效或额度用尽后默认的冷却时间
+/// Default cool-down after a credential failed or ran out of quota
+pub const DEFAULT_CREDENTIAL_COOLDOWN: Duration = Duration::from_secs(300);
+
+/// 全局端点健康表 - 存储API名称到健康状态的映射
+/// Global endpoint health table - stores mappings from API name to health status
+pub static ENDPOINT_HEALTH: Lazy<DashMap<String, EndpointHealth>> = Lazy::new(DashMap::new);
+
+/// 标记API是否健康，标记为健康时同时结束冷却
+/// Mark whether an API is healthy, marking it healthy also ends its cool-down
+pub fn set_healthy(api_name: &str, healthy: bool) {
+    let mut health = ENDPOINT_HEALTH.entry(api_name.to_string()).or_default();
+    health.healthy = healthy;
+    if healthy {
+        health.cooldown_until = None;
+    }
+}
+
+/// 让API冷却一段时间，期间不会被按能力选中
+/// Put an API on cool-down for a while, it is not selected by capability meanwhile
+pub fn cool_down(api_name: &str, cooldown: Duration) {
+    ENDPOINT_HEALTH.entry(api_name.to_string()).or_default().cooldown_until = Some(Instant::now() + cooldown);
+}
+
+/// 获取API的健康状态
+/// Get health status of an API
+pub fn get_health(api_name: &str) -> Option<EndpointHealth> {
+    ENDPOINT_HEALTH.get(api_name).map(|entry| entry.value().clone())
+}
+
+/// 判断API是否健康，未记录的API视为健康，冷却中的API视为不健康
+/// Whether an API is healthy, APIs without a record are considered healthy and APIs cooling down unhealthy
+pub fn is_healthy(api_name: &str) -> bool {
+    ENDPOINT_HEALTH.get(api_name).is_none_or(|health| health.healthy && !health.cooling_down())
+}
+
+/// 判断API所属来源是否还有空闲的并发额度
+/// Whether the source of an API still has free concurrency permits
+fn has_free_permit(api_info: &ApiInfo) -> bool {
+    CFG.current()
+        .source_pool()
+        .get(&api_info.base_url)
+        .is_none_or(|semaphore| semaphore.available_permits() > 0)
+}
+
+/// 从候选API中选择一个
+/// Select one API among candidates
+///
+/// 权重为0的API已停用，不会被选中；其余候选依次收窄：健康 → 有空闲并发额度，某一步筛选后为空时保留上一步的结果，
+/// 最后在剩余候选中按权重随机选择。没有候选时返回 `ApiInfoNotFound`，候选全部停用时返回 `NoEnabledApi`。
+/// APIs with weight 0 are disabled and never picked; the rest are narrowed in turn: healthy → free permits, a step
+/// that would leave nothing keeps the previous set. The final pick is weighted random among what remains. Fails
+/// with `ApiInfoNotFound` without candidates and with `NoEnabledApi` when every candidate is disabled.
+pub fn select_endpoint(candidates: Vec<ApiInfo>) -> Result<ApiInfo, ConfigError> {
+    if candidates.is_empty() {
+        return Err(Report::new(ConfigError::ApiInfoNotFound));
+    }
+    let (candidates, disabled): (Vec<ApiInfo>, Vec<ApiInfo>) = candidates.into_iter().partition(|api| api.weight > 0);
+    if candidates.is_empty() {
+        let names: Vec<&str> = disabled.iter().map(|api| api.name.as_str()).collect();
+        return Err(Report::new(ConfigError::NoEnabledApi).attach_printable(format!("Disabled APIs: {:?}", names)));
+    }
+    let candidates = narrow(candidates, |api| is_healthy(&api.name));
+    let candidates = narrow(candidates, has_free_permit);
+
+    let total: u64 = candidates.iter().map(|api| api.weight as u64).sum();
+    let mut point = rand::rng().random_range(0..total);
+    candidates.into_iter().find(|api| {
+        let weight = api.weight as u64;
+        if point < weight {
+            true
+        } else {
+            point -= weight;
+            false
+        }
+    })
+    .ok_or_else(|| Report::new(ConfigError::ApiInfoNotFound))
+}
+
+/// 按条件筛选候选，筛选结果为空时返回原候选
+/// Filter candidates by a predicate, returning the original candidates if nothing passes
+fn narrow(candidates: Vec<ApiInfo>, predicate: impl Fn(&ApiInfo) -> bool) -> Vec<ApiInfo> {
+    if candidates.iter().any(&predicate) {
+        candidates.into_iter().filter(|api| predicate(api)).collect()
+    } else {
+        candidates
+    }
+}
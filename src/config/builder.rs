@@ -0,0 +1,249 @@
+// 标准库
+use std::collections::HashMap;
+
+// 并发和同步原语
+use dashmap::DashMap;
+
+// 错误处理
+use error_stack::{Report, Result, ResultExt};
+
+// 项目内部模块
+use crate::chat::compat::ProviderCompat;
+use crate::chat::params::ChatParams;
+use crate::config::compression::CompressionConfig;
+use crate::config::metadata::ModelMetadata;
+use crate::config::prompts::PromptKey;
+use crate::config::tls::{TlsConfig, build_client};
+use crate::config::transport::TransportConfig;
+use crate::config::{ApiInfo, ApiSource, Config, ConfigError, ModelCapability};
+
+/// 默认并行度
+/// Default parallelism
+const DEFAULT_PARALLELISM: usize = 10;
+
+/// 构建器中的API条目
+/// API entry held by the builder
+#[derive(Clone, Debug)]
+struct ApiEntry {
+    name: String,
+    base_url: String,
+    api_key: String,
+    model: String,
+    parallelism: usize,
+    weight: u32,
+    params: ChatParams,
+    tls: Option<TlsConfig>,
+    compat: ProviderCompat,
+    compression: CompressionConfig,
+    transport: TransportConfig,
+}
+
+/// 配置构建器，用于在代码中构建 `Config`
+/// Configuration builder, used to construct a `Config` in code
+///
+/// ```ignore
+/// let config = Config::builder()
+///     .api("gpt-4o", "https://api.openai.com/v1/chat/completions", "sk-...", "gpt-4o")
+///     .capability(ModelCapability::ToolUse, "gpt-4o")
+///     .build()?;
+/// Config::set_global(config);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct ConfigBuilder {
+    apis: Vec<ApiEntry>,
+    capabilities: Vec<(ModelCapability, String)>,
+    aliases: HashMap<String, String>,
+    capability_limits: HashMap<ModelCapability, usize>,
+    capability_prompts: HashMap<PromptKey, String>,
+    model_metadata: HashMap<String, ModelMetadata>,
+}
+
+impl ConfigBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 添加一个API
+    /// Add an API
+    ///
+    /// # 参数 (Parameters)
+    /// * `name` - API名称 / API name
+    /// * `base_url` - API基础URL / API base URL
+    /// * `api_key` - API密钥 / API key
+    /// * `model` - 模型名称 / Model name
+    pub fn api(mut self, name: &str, base_url: &str, api_key: &str, model: &str) -> Self {
+        self.apis.push(ApiEntry {
+            name: name.to_string(),
+            base_url: base_url.to_string(),
+            api_key: api_key.to_string(),
+            model: model.to_string(),
+            parallelism: DEFAULT_PARALLELISM,
+            weight: 1,
+            params: ChatParams::default(),
+            tls: None,
+            compat: ProviderCompat::default(),
+            compression: CompressionConfig::default(),
+            transport: TransportConfig::default(),
+        });
+        self
+    }
+
+    /// 设置最近添加的API的并行度
+    /// Set parallelism of the most recently added API
+    pub fn parallelism(mut self, parallelism: usize) -> Self {
+        if let Some(api) = self.apis.last_mut() {
+            api.parallelism = parallelism;
+        }
+        self
+    }
+
+    /// 设置最近添加的API的负载均衡权重
+    /// Set load balancing weight of the most recently added API
+    pub fn weight(mut self, weight: u32) -> Self {
+        if let Some(api) = self.apis.last_mut() {
+            api.weight = weight;
+        }
+        self
+    }
+
+    /// 设置最近添加的API的默认生成参数
+    /// Set default generation parameters of the most recently added API
+    pub fn params(mut self, params: ChatParams) -> Self {
+        if let Some(api) = self.apis.last_mut() {
+            api.params = params;
+        }
+        self
+    }
+
+    /// 设置最近添加的API的TLS配置
+    /// Set TLS configuration of the most recently added API
+    pub fn tls(mut self, tls: TlsConfig) -> Self {
+        if let Some(api) = self.apis.last_mut() {
+            api.tls = Some(tls);
+        }
+        self
+    }
+
+    /// 设置最近添加的API的提供商兼容性
+    /// Set provider compatibility of the most recently added API
+    pub fn compat(mut self, compat: ProviderCompat) -> Self {
+        if let Some(api) = self.apis.last_mut() {
+            api.compat = compat;
+        }
+        self
+    }
+
+    /// 设置最近添加的API的HTTP压缩
+    /// Set HTTP compression of the most recently added API
+    pub fn compression(mut self, compression: CompressionConfig) -> Self {
+        if let Some(api) = self.apis.last_mut() {
+            api.compression = compression;
+        }
+        self
+    }
+
+    /// 设置最近添加的API的传输层调优
+    /// Set transport tuning of the most recently added API
+    pub fn transport(mut self, transport: TransportConfig) -> Self {
+        if let Some(api) = self.apis.last_mut() {
+            api.transport = transport;
+        }
+        self
+    }
+
+    /// 将模型能力绑定到指定名称的API
+    /// Bind a model capability to the API with the given name
+    pub fn capability(mut self, capability: ModelCapability, api_name: &str) -> Self {
+        self.capabilities.push((capability, api_name.to_string()));
+        self
+    }
+
+    /// 设置某个能力的并发请求上限
+    /// Set the concurrent request limit of a capability
+    pub fn capability_limit(mut self, capability: ModelCapability, limit: usize) -> Self {
+        self.capability_limits.insert(capability, limit);
+        self
+    }
+
+    /// 设置某个键的内部提示词，替换内置的默认提示词
+    /// Set the internal prompt of a key, replacing the built-in default
+    pub fn capability_prompt(mut self, key: PromptKey, prompt: &str) -> Self {
+        self.capability_prompts.insert(key, prompt.to_string());
+        self
+    }
+
+    /// 添加别名
+    /// Add an alias
+    pub fn alias(mut self, alias: &str, api_name: &str) -> Self {
+        self.aliases.insert(alias.to_string(), api_name.to_string());
+        self
+    }
+
+    /// 设置模型元数据
+    /// Set model metadata
+    pub fn model_metadata(mut self, model: &str, metadata: ModelMetadata) -> Self {
+        self.model_metadata.insert(model.to_string(), metadata);
+        self
+    }
+
+    /// 构建配置
+    /// Build the configuration
+    ///
+    /// # 返回 (Returns)
+    /// * `Result<Config, ConfigError>` - 能力引用了未定义的API时返回错误 / Fails if a capability references an undefined API
+    pub fn build(self) -> Result<Config, ConfigError> {
+        let api_source = DashMap::new();
+        let api_info = DashMap::new();
+        let apis: HashMap<&str, &ApiEntry> = self.apis.iter().map(|api| (api.name.as_str(), api)).collect();
+
+        for (capability, api_name) in &self.capabilities {
+            let api = apis
+                .get(api_name.as_str())
+                .ok_or_else(|| Report::new(ConfigError::UnknownApi(api_name.clone())))?;
+
+            api_info.insert(
+                (api.name.clone(), capability.clone()),
+                ApiInfo {
+                    name: api.name.clone(),
+                    model: api.model.clone(),
+                    base_url: api.base_url.clone(),
+                    source: api.name.clone(),
+                    api_key: api.api_key.clone(),
+                    client: build_client(api.tls.as_ref(), &api.transport)
+                        .attach_printable_lazy(|| format!("For API '{}'", api.name))?,
+                    weight: api.weight,
+                    params: api.params.clone(),
+                },
+            );
+        }
+
+        for api in &self.apis {
+            api_source.insert(
+                api.name.clone(),
+                ApiSource {
+                    base_url: api.base_url.clone(),
+                    parallelism: api.parallelism,
+                    tls: api.tls.clone(),
+                    compat: api.compat.clone(),
+                    compression: api.compression.clone(),
+                    transport: api.transport.clone(),
+                },
+            );
+        }
+
+        for api_name in self.aliases.values() {
+            if !apis.contains_key(api_name.as_str()) {
+                return Err(Report::new(ConfigError::UnknownApi(api_name.clone())));
+            }
+        }
+
+        Ok(Config {
+            api_source,
+            api_info,
+            aliases: self.aliases.into_iter().collect(),
+            capability_limits: self.capability_limits.into_iter().collect(),
+            capability_prompts: self.capability_prompts.into_iter().collect(),
+            model_metadata: self.model_metadata.into_iter().collect(),
+        })
+    }
+}
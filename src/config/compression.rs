@@ -0,0 +1,91 @@
+// 标准库
+use std::io::{self, Write};
+
+// 压缩
+use flate2::Compression;
+use flate2::write::GzEncoder;
+
+// HTTP客户端
+use reqwest::RequestBuilder;
+use reqwest::header::{ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_TYPE, HeaderValue};
+
+// 序列化
+use serde::Deserialize;
+
+/// 关闭响应压缩时声明的编码
+/// Encoding declared when response compression is off
+pub const IDENTITY_ENCODING: &str = "identity";
+
+/// 默认的请求压缩阈值，小于该字节数的请求体不压缩
+/// Default request compression threshold, request bodies smaller than this many bytes are sent as is
+pub const DEFAULT_REQUEST_MIN_BYTES: usize = 32 * 1024;
+
+/// API来源的HTTP压缩设置，减少代理大量RAG上下文或工具定义的部署的带宽
+/// HTTP compression settings of an API source, reducing bandwidth for deployments proxying huge RAG contexts or
+/// tool schemas
+///
+/// 响应由HTTP客户端按 gzip、deflate 与 brotli 透明解压，流式响应边接收边解压。请求压缩默认关闭，
+/// 只有确认提供商或网关接受 gzip 请求体时才应开启。
+/// Responses are decompressed transparently by the HTTP client for gzip, deflate and brotli, streamed responses as
+/// they arrive. Request compression is off by default and should only be turned on once the provider or gateway is
+/// known to accept gzip request bodies.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct CompressionConfig {
+    /// 是否声明接受压缩的响应
+    /// Whether to accept compressed responses
+    pub response: bool,
+
+    /// 是否以 gzip 压缩较大的请求体
+    /// Whether to gzip large request bodies
+    pub request: bool,
+
+    /// 压缩请求体的最小字节数
+    /// Minimum size in bytes of request bodies to compress
+    pub request_min_bytes: usize,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            response: true,
+            request: false,
+            request_min_bytes: DEFAULT_REQUEST_MIN_BYTES,
+        }
+    }
+}
+
+impl CompressionConfig {
+    /// 按设置写入JSON请求体与编码相关的请求头
+    /// Write the JSON request body and the encoding headers as configured
+    pub fn apply(&self, builder: RequestBuilder, body: &serde_json::Value) -> RequestBuilder {
+        // 开启时由客户端声明它能解压的编码
+        // When on, the client declares the encodings it can decompress
+        let builder = match self.response {
+            true => builder,
+            false => builder.header(ACCEPT_ENCODING, IDENTITY_ENCODING),
+        };
+        if !self.request {
+            return builder.json(body);
+        }
+        let Ok(bytes) = serde_json::to_vec(body) else {
+            return builder.json(body);
+        };
+        let builder = builder.header(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        if bytes.len() < self.request_min_bytes {
+            return builder.body(bytes);
+        }
+        match gzip(&bytes) {
+            Ok(compressed) => builder.header(CONTENT_ENCODING, "gzip").body(compressed),
+            Err(_) => builder.body(bytes),
+        }
+    }
+}
+
+/// 以 gzip 压缩数据
+/// Compress data with gzip
+pub fn gzip(data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data)?;
+    encoder.finish()
+}
@@ -0,0 +1,159 @@
+// 标准库
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+// 并发和同步原语
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+
+// HTTP客户端
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::{Client, ClientBuilder};
+use tower::util::MapResponseLayer;
+
+// 指标门面
+use metrics::counter;
+
+// 项目内部模块
+use crate::telemetry::meter::{HTTP_CONNECTIONS_OPENED_TOTAL, HTTP_DNS_CACHE_HITS_TOTAL, HTTP_DNS_LOOKUPS_TOTAL};
+
+/// 空闲连接在连接池中的保留时间
+/// How long idle connections are kept in the pool
+pub const POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+
+/// 每个主机保留的最大空闲连接数
+/// Maximum idle connections kept per host
+pub const POOL_MAX_IDLE_PER_HOST: usize = 32;
+
+/// TCP keep-alive 间隔
+/// TCP keep-alive interval
+pub const TCP_KEEPALIVE: Duration = Duration::from_secs(60);
+
+/// HTTP/2 PING 保活间隔
+/// HTTP/2 PING keep-alive interval
+pub const HTTP2_KEEPALIVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// DNS解析结果的缓存时间
+/// How long DNS resolutions are cached
+pub const DNS_CACHE_TTL: Duration = Duration::from_secs(60);
+
+/// 连接池统计
+/// Connection pool statistics
+///
+/// `connections_opened` 远小于请求数说明连接被复用；两者接近时说明每个请求都在新建连接。
+/// A `connections_opened` far below the request count means connections are reused; when the two are close,
+/// every request is opening a new connection.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PoolStats {
+    /// 新建的连接数
+    /// Connections opened
+    pub connections_opened: u64,
+
+    /// 实际发出的DNS查询数
+    /// DNS lookups actually performed
+    pub dns_lookups: u64,
+
+    /// 命中DNS缓存的解析数
+    /// Resolutions answered from the DNS cache
+    pub dns_cache_hits: u64,
+}
+
+static CONNECTIONS_OPENED: AtomicU64 = AtomicU64::new(0);
+static DNS_LOOKUPS: AtomicU64 = AtomicU64::new(0);
+static DNS_CACHE_HITS: AtomicU64 = AtomicU64::new(0);
+
+/// 获取进程内所有共享配置的客户端的连接池统计
+/// Get the connection pool statistics of every client built from the shared configuration in this process
+pub fn pool_stats() -> PoolStats {
+    PoolStats {
+        connections_opened: CONNECTIONS_OPENED.load(Ordering::Relaxed),
+        dns_lookups: DNS_LOOKUPS.load(Ordering::Relaxed),
+        dns_cache_hits: DNS_CACHE_HITS.load(Ordering::Relaxed),
+    }
+}
+
+/// 带缓存的DNS解析器，在 `DNS_CACHE_TTL` 内复用解析结果
+/// Caching DNS resolver, resolutions are reused within `DNS_CACHE_TTL`
+#[derive(Clone, Debug, Default)]
+pub struct CachingResolver {
+    cache: Arc<DashMap<String, (Instant, Vec<SocketAddr>)>>,
+}
+
+impl CachingResolver {
+    /// 清空DNS缓存
+    /// Clear the DNS cache
+    pub fn clear(&self) {
+        self.cache.clear();
+    }
+}
+
+impl Resolve for CachingResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let resolver = self.clone();
+        Box::pin(async move {
+            let host = name.as_str().to_string();
+            if let Some(entry) = resolver.cache.get(&host)
+                && entry.0.elapsed() < DNS_CACHE_TTL
+            {
+                DNS_CACHE_HITS.fetch_add(1, Ordering::Relaxed);
+                counter!(HTTP_DNS_CACHE_HITS_TOTAL).increment(1);
+                let addrs: Addrs = Box::new(entry.1.clone().into_iter());
+                return Ok(addrs);
+            }
+
+            DNS_LOOKUPS.fetch_add(1, Ordering::Relaxed);
+            counter!(HTTP_DNS_LOOKUPS_TOTAL).increment(1);
+            let resolved: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), 0)).await?.collect();
+            resolver.cache.insert(host, (Instant::now(), resolved.clone()));
+            let addrs: Addrs = Box::new(resolved.into_iter());
+            Ok(addrs)
+        })
+    }
+}
+
+/// 全局DNS解析器
+/// Global DNS resolver
+static RESOLVER: Lazy<Arc<CachingResolver>> = Lazy::new(Arc::default);
+
+/// 全局共享HTTP客户端，所有未配置TLS的请求路径（普通、流式、工具调用、探测、追踪导出）共用它的连接池
+/// Global shared HTTP client, every request path without TLS configuration (normal, streaming, tool calls,
+/// probes, trace exports) shares its connection pool
+static SHARED_CLIENT: Lazy<Client> = Lazy::new(|| {
+    client_builder().build().expect("the shared HTTP client configuration is valid")
+});
+
+/// 获取全局DNS解析器
+/// Get the global DNS resolver
+pub fn dns_resolver() -> Arc<CachingResolver> {
+    RESOLVER.clone()
+}
+
+/// 获取全局共享HTTP客户端，克隆开销很小且共享同一连接池
+/// Get the global shared HTTP client, clones are cheap and share one connection pool
+pub fn shared_client() -> Client {
+    SHARED_CLIENT.clone()
+}
+
+/// 带连接复用配置的客户端构建器：keep-alive、HTTP/2（经ALPN协商）、DNS缓存与连接计数
+/// Client builder with connection reuse settings: keep-alive, HTTP/2 (negotiated through ALPN), DNS caching and
+/// connection counting
+///
+/// 需要自定义TLS的客户端也从这里开始构建，以便保持相同的连接池行为。
+/// Clients needing custom TLS start from here too, so they keep the same pool behavior.
+pub fn client_builder() -> ClientBuilder {
+    Client::builder()
+        .pool_idle_timeout(POOL_IDLE_TIMEOUT)
+        .pool_max_idle_per_host(POOL_MAX_IDLE_PER_HOST)
+        .tcp_keepalive(TCP_KEEPALIVE)
+        .http2_keep_alive_interval(HTTP2_KEEPALIVE_INTERVAL)
+        .http2_keep_alive_while_idle(true)
+        .http2_adaptive_window(true)
+        .dns_resolver(dns_resolver())
+        .connector_layer(MapResponseLayer::new(|conn| {
+            CONNECTIONS_OPENED.fetch_add(1, Ordering::Relaxed);
+            counter!(HTTP_CONNECTIONS_OPENED_TOTAL).increment(1);
+            conn
+        }))
+}
@@ -0,0 +1,88 @@
+use serde::Deserialize;
+
+use crate::chat::completion::ChatTemplate;
+use crate::telemetry::TokenUsage;
+
+/// 价格档位
+/// Price tier
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PriceTier {
+    /// 低价
+    /// Low cost
+    Economy,
+
+    /// 标准
+    /// Standard cost
+    Standard,
+
+    /// 高价
+    /// High cost
+    Premium,
+}
+
+/// 模型元数据，供上下文管理、原生工具调用等功能在运行时自适应
+/// Model metadata, lets context management, native tool calling and similar features adapt at runtime
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+pub struct ModelMetadata {
+    /// 上下文窗口大小（token数）
+    /// Context window size (in tokens)
+    #[serde(default)]
+    pub context_window: Option<usize>,
+
+    /// 是否支持原生工具调用
+    /// Whether native tool calling is supported
+    #[serde(default)]
+    pub supports_tools: bool,
+
+    /// 是否支持图像输入
+    /// Whether image input is supported
+    #[serde(default)]
+    pub supports_vision: bool,
+
+    /// 是否支持请求中的 GBNF `grammar` 字段（如 llama.cpp 服务），支持时结构化输出在解码时即受语法约束
+    /// Whether the `grammar` GBNF field of requests is supported (such as by the llama.cpp server), structured
+    /// outputs are constrained by the grammar while decoding if so
+    #[serde(default)]
+    pub supports_grammar: bool,
+
+    /// 价格档位
+    /// Price tier
+    #[serde(default)]
+    pub price_tier: Option<PriceTier>,
+
+    /// 输入价格（美元/百万token）
+    /// Input price (USD per million tokens)
+    #[serde(default)]
+    pub input_price: Option<f64>,
+
+    /// 输出价格（美元/百万token）
+    /// Output price (USD per million tokens)
+    #[serde(default)]
+    pub output_price: Option<f64>,
+
+    /// 补全模式的对话模板，设置后该模型的请求发往 `/completions` 接口
+    /// Chat template of the completion mode, requests of the model go to a `/completions` endpoint once set
+    #[serde(default)]
+    pub chat_template: Option<ChatTemplate>,
+
+    /// 计算 token 数所用的分词器：tiktoken 编码名称（如 `o200k_base`，需启用 `tiktoken` 特性）或 `heuristic`；
+    /// 未设置时按模型名称选择，见 `tokenizer_for`
+    /// Tokenizer used to count tokens: a tiktoken encoding name (such as `o200k_base`, requires the `tiktoken`
+    /// feature) or `heuristic`; chosen by model name if unset, see `tokenizer_for`
+    #[serde(default)]
+    pub tokenizer: Option<String>,
+}
+
+impl ModelMetadata {
+    /// 按价格计算一次调用的费用（美元），未配置价格时返回 None
+    /// Compute the cost of one call in USD from the prices, None if no price is configured
+    pub fn cost(&self, usage: &TokenUsage) -> Option<f64> {
+        if self.input_price.is_none() && self.output_price.is_none() {
+            return None;
+        }
+        let input = self.input_price.unwrap_or(0.0) * usage.prompt_tokens as f64;
+        let output = self.output_price.unwrap_or(0.0) * usage.completion_tokens as f64;
+        Some((input + output) / 1_000_000.0)
+    }
+}
@@ -0,0 +1,1262 @@
+// 标准库
+use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::future::Future;
+use std::ops::Deref;
+use std::sync::{Arc, RwLock};
+
+// 并发和同步原语
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use tokio::sync::Semaphore;
+
+// HTTP客户端
+use reqwest::Client;
+
+// 序列化
+use serde::Deserialize;
+
+// 错误处理
+use error_stack::{Report, Result, ResultExt};
+use thiserror::Error;
+
+// 项目内部模块
+use crate::chat::compat::ProviderCompat;
+use crate::chat::params::ChatParams;
+use crate::config::balance::{is_healthy, select_endpoint};
+use crate::config::builder::ConfigBuilder;
+use crate::config::compression::CompressionConfig;
+use crate::config::metadata::ModelMetadata;
+use crate::config::probe::{DEFAULT_PROBE_TIMEOUT, ProbeReport};
+use crate::config::profile::{PROFILE_ENV_VAR, resolve_profile};
+use crate::config::prompts::PromptKey;
+use crate::config::secrets::resolve_secret;
+use crate::config::tls::{TlsConfig, build_client};
+use crate::config::transport::TransportConfig;
+use crate::config::validate::ConfigIssue;
+use crate::shutdown::ShutdownState;
+use crate::telemetry::anonymize::{AnonymizeConfig, Anonymizer};
+use crate::telemetry::audit::{AuditLog, AuditLogConfig};
+use crate::telemetry::exporter::{TraceExporterEntry, register_trace_exporter};
+use crate::telemetry::ledger::{JsonlUsageLedger, set_usage_ledger};
+use crate::utils::common::expand_env::expand_env;
+use crate::utils::common::load_toml::load_toml;
+use crate::utils::common::redact::REDACTED;
+use crate::utils::common::tokens::{Tokenizer, register_tokenizer, tokenizer_for};
+
+pub mod balance;
+pub mod builder;
+pub mod compression;
+pub mod http;
+pub mod metadata;
+pub mod probe;
+pub mod profile;
+pub mod prompts;
+pub mod secrets;
+pub mod tls;
+pub mod transport;
+pub mod validate;
+
+/// 配置相关错误枚举
+/// Configuration related error enum
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    /// 获取配置锁失败
+    /// Failed to acquire configuration lock
+    #[error("Failed to acquire config lock")]
+    ConfigLockFailure,
+    
+    /// 配置未初始化
+    /// Configuration not initialized
+    #[error("Configuration not initialized")]
+    ConfigNotInitialized,
+    
+    /// API信息未找到
+    /// API information not found
+    #[error("API info not found")]
+    ApiInfoNotFound,
+
+    /// 候选API的权重全部为0（已停用）
+    /// Every candidate API has weight 0 (disabled)
+    #[error("No enabled API, every candidate has weight 0")]
+    NoEnabledApi,
+
+    /// 配置文件加载失败
+    /// Failed to load configuration file
+    #[error("Failed to load config file: {0}")]
+    LoadError(String),
+
+    /// 配置值中的环境变量展开失败
+    /// Failed to expand environment variables in a config value
+    #[error("Failed to expand environment variables in '{0}'")]
+    EnvExpansionError(String),
+
+    /// 从密钥提供者获取密钥失败
+    /// Failed to fetch a secret from a secret provider
+    #[error("Failed to resolve secret for '{0}'")]
+    SecretError(String),
+
+    /// 能力绑定引用了未定义的API
+    /// Capability binding references an undefined API
+    #[error("Unknown API referenced: {0}")]
+    UnknownApi(String),
+
+    /// 引用了未定义的API来源
+    /// Undefined API source referenced
+    #[error("Unknown API source referenced: {0}")]
+    UnknownSource(String),
+
+    /// TLS配置无效
+    /// Invalid TLS configuration
+    #[error("Invalid TLS configuration: {0}")]
+    TlsError(String),
+
+    /// 配置中存在重复名称
+    /// Duplicate name in configuration
+    #[error("Duplicate name in config: {0}")]
+    DuplicateName(String),
+
+    /// 环境不存在
+    /// Profile does not exist
+    #[error("Unknown profile: {0}")]
+    UnknownProfile(String),
+
+    /// 环境继承存在循环
+    /// Profile inheritance contains a cycle
+    #[error("Profile inheritance cycle at: {0}")]
+    ProfileCycle(String),
+
+    /// 加盐哈希的盐为空
+    /// The salt of salted hashes is empty
+    #[error("Salt '{0}' is empty while identifiers are hashed")]
+    MissingSalt(String),
+}
+
+/// 模型能力枚举
+/// Model capability enum
+#[derive(Clone, Debug, Eq, Hash, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ModelCapability {
+    /// 思考能力
+    /// Thinking capability
+    Think,
+    
+    /// 工具使用能力
+    /// Tool usage capability
+    ToolUse,
+    
+    /// 长上下文处理能力
+    /// Long context processing capability
+    LongContext,
+
+    /// 廉价快速的模型，用于标题、摘要等辅助任务
+    /// Cheap and fast model, used for auxiliary tasks such as titles and summaries
+    Cheap,
+}
+
+/// API来源结构体
+/// API source structure
+#[derive(Clone, Debug)]
+pub struct ApiSource {
+    /// API基础URL
+    /// API base URL
+    pub base_url: String,
+    
+    /// 并行请求数量限制
+    /// Parallel request limit
+    pub parallelism: usize,
+
+    /// TLS配置，为 None 时使用默认设置
+    /// TLS configuration, defaults are used if None
+    pub tls: Option<TlsConfig>,
+
+    /// 提供商兼容性设置，请求体在发出前按此改写
+    /// Provider compatibility settings, request bodies are rewritten by them before sending
+    pub compat: ProviderCompat,
+
+    /// HTTP压缩设置
+    /// HTTP compression settings
+    pub compression: CompressionConfig,
+
+    /// 传输层调优（HTTP/2、进行中的请求上限、TCP keep-alive、连接超时）
+    /// Transport tuning (HTTP/2, in-flight cap, TCP keep-alive, connect timeout)
+    pub transport: TransportConfig,
+}
+
+impl ApiSource {
+    /// 来源的并发额度，即并行度与进行中的请求上限中的较小者
+    /// Concurrency permits of the source, the smaller of the parallelism and the in-flight cap
+    pub fn permits(&self) -> usize {
+        self.transport.permits(self.parallelism)
+    }
+}
+
+/// API信息结构体
+/// API information structure
+#[derive(Clone)]
+pub struct ApiInfo {
+    /// API名称
+    /// API name
+    pub name: String,
+
+    /// 模型名称
+    /// Model name
+    pub model: String,
+    
+    /// API基础URL
+    /// API base URL
+    pub base_url: String,
+
+    /// 所属API来源的名称
+    /// Name of the API source it belongs to
+    pub source: String,
+    
+    /// API密钥
+    /// API key
+    pub api_key: String,
+    
+    /// HTTP客户端实例
+    /// HTTP client instance
+    pub client: Client,
+
+    /// 负载均衡权重，同一能力下按权重随机选择
+    /// Load balancing weight, endpoints of one capability are picked at random by weight
+    pub weight: u32,
+
+    /// 默认生成参数，构建请求体时应用
+    /// Default generation parameters, applied when building request bodies
+    pub params: ChatParams,
+}
+
+// API密钥不出现在调试输出中
+// The API key never shows up in debug output
+impl Debug for ApiInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ApiInfo")
+            .field("name", &self.name)
+            .field("model", &self.model)
+            .field("base_url", &self.base_url)
+            .field("source", &self.source)
+            .field("api_key", &REDACTED)
+            .field("client", &self.client)
+            .field("weight", &self.weight)
+            .field("params", &self.params)
+            .finish()
+    }
+}
+
+/// 配置文件结构体，对应 TOML 配置文件的顶层
+/// Configuration file struct, the top level of the TOML config file
+#[derive(Debug, Default, Deserialize)]
+pub struct ConfigFile {
+    /// 继承的环境名称，仅在环境配置中有效
+    /// Name of the inherited profile, only meaningful inside a profile
+    #[serde(default)]
+    pub inherits: Option<String>,
+
+    /// API来源列表
+    /// List of API sources
+    #[serde(default)]
+    pub api_source: Vec<ApiSourceEntry>,
+
+    /// API信息列表
+    /// List of API information
+    #[serde(default)]
+    pub api_info: Vec<ApiInfoEntry>,
+
+    /// 别名表，别名到API名称
+    /// Alias table, from alias to API name
+    #[serde(default)]
+    pub alias: HashMap<String, String>,
+
+    /// 模型元数据表，模型名称到元数据
+    /// Model metadata table, from model name to metadata
+    #[serde(default)]
+    pub model_metadata: HashMap<String, ModelMetadata>,
+
+    /// 按能力的并发请求上限，能力名称到上限，与API来源的并行度同时生效
+    /// Concurrent request limits per capability, from capability name to limit, applied on top of source parallelism
+    #[serde(default)]
+    pub capability_limit: HashMap<ModelCapability, usize>,
+
+    /// 内部提示词，提示词键到提示词，替换内置的默认提示词（如工具调用的JSON整理人设 `tool_use_json`）
+    /// Internal prompts, from prompt key to prompt, replacing the built-in defaults (such as the JSON conversion
+    /// persona of tool use, `tool_use_json`)
+    #[serde(default)]
+    pub capability_prompt: HashMap<PromptKey, String>,
+
+    /// 追踪导出器列表（Langfuse、LangSmith）
+    /// List of trace exporters (Langfuse, LangSmith)
+    #[serde(default)]
+    pub trace_exporter: Vec<TraceExporterEntry>,
+
+    /// 审计日志配置，缺省时不记录
+    /// Audit log configuration, nothing is recorded if absent
+    #[serde(default)]
+    pub audit_log: Option<AuditLogConfig>,
+
+    /// 用量账本的 JSONL 文件路径，缺省时不记录
+    /// Path of the JSONL usage ledger file, nothing is recorded if absent
+    #[serde(default)]
+    pub usage_ledger: Option<String>,
+
+    /// 审计日志与追踪导出器的匿名化配置，缺省时按原样记录
+    /// Anonymization configuration of the audit log and trace exporters, records are kept as is if absent
+    #[serde(default)]
+    pub anonymize: Option<AnonymizeConfig>,
+
+    /// 环境配置表（如 dev/staging/prod），环境名称到该环境的覆盖配置
+    /// Profile table (such as dev/staging/prod), from profile name to the overrides of that profile
+    #[serde(default)]
+    pub profile: HashMap<String, ConfigFile>,
+}
+
+/// 配置文件中的API来源条目
+/// API source entry in the config file
+#[derive(Debug, Deserialize)]
+pub struct ApiSourceEntry {
+    pub name: String,
+    pub base_url: String,
+    pub parallelism: usize,
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
+    #[serde(default)]
+    pub compat: ProviderCompat,
+    #[serde(default)]
+    pub compression: CompressionConfig,
+    #[serde(default)]
+    pub transport: TransportConfig,
+}
+
+/// 配置文件中的API信息条目
+/// API information entry in the config file
+#[derive(Deserialize)]
+pub struct ApiInfoEntry {
+    pub name: String,
+    pub model: String,
+    pub capability: ModelCapability,
+    pub source: String,
+    pub api_key: String,
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+    #[serde(default)]
+    pub params: ChatParams,
+}
+
+impl Debug for ApiInfoEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ApiInfoEntry")
+            .field("name", &self.name)
+            .field("model", &self.model)
+            .field("capability", &self.capability)
+            .field("source", &self.source)
+            .field("api_key", &REDACTED)
+            .field("weight", &self.weight)
+            .field("params", &self.params)
+            .finish()
+    }
+}
+
+impl ConfigFile {
+    /// 检查重复的API来源名称和重复的(API名称, 能力)条目
+    /// Check for duplicate API source names and duplicate (API name, capability) entries
+    pub(crate) fn check_duplicates(&self) -> Result<(), ConfigError> {
+        let mut sources = HashSet::new();
+        if let Some(source) = self.api_source.iter().find(|source| !sources.insert(&source.name)) {
+            return Err(Report::new(ConfigError::DuplicateName(format!("api_source.{}", source.name))));
+        }
+
+        let mut exporters = HashSet::new();
+        if let Some(exporter) = self.trace_exporter.iter().find(|exporter| !exporters.insert(&exporter.name)) {
+            return Err(Report::new(ConfigError::DuplicateName(format!("trace_exporter.{}", exporter.name))));
+        }
+
+        let mut infos = HashSet::new();
+        if let Some(info) = self.api_info.iter().find(|info| !infos.insert((&info.name, &info.capability))) {
+            return Err(Report::new(ConfigError::DuplicateName(format!("api_info.{} ({:?})", info.name, info.capability))));
+        }
+
+        Ok(())
+    }
+}
+
+/// 返回默认权重
+/// Returns default weight
+fn default_weight() -> u32 {
+    1
+}
+
+/// 配置管理结构体
+/// Configuration management structure
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// API来源映射表 - 存储名称到API来源的映射
+    /// API source map - stores mappings from name to API source
+    pub api_source: DashMap<String, ApiSource>,
+    
+    /// API信息映射表 - 存储(名称,能力)到API信息的映射
+    /// API info map - stores mappings from (name, capability) to API info
+    pub api_info: DashMap<(String, ModelCapability), ApiInfo>,
+
+    /// 别名映射表 - 存储别名到API名称的映射（如 "fast" → "pumpkin-gpt-4o-mini"）
+    /// Alias map - stores mappings from alias to API name (e.g. "fast" → "pumpkin-gpt-4o-mini")
+    pub aliases: DashMap<String, String>,
+
+    /// 能力并发上限映射表 - 存储能力到并发请求上限的映射
+    /// Capability limit map - stores mappings from capability to concurrent request limit
+    pub capability_limits: DashMap<ModelCapability, usize>,
+
+    /// 内部提示词映射表 - 存储提示词键到替换提示词的映射
+    /// Internal prompt map - stores mappings from prompt key to replacement prompt
+    pub capability_prompts: DashMap<PromptKey, String>,
+
+    /// 模型元数据映射表 - 存储模型名称到元数据的映射
+    /// Model metadata map - stores mappings from model name to metadata
+    pub model_metadata: DashMap<String, ModelMetadata>,
+}
+
+impl Config {
+    /// 创建配置构建器，无需配置文件即可在代码中完成全部配置
+    /// Create a configuration builder, allowing everything to be configured in code without a config file
+    pub fn builder() -> ConfigBuilder {
+        ConfigBuilder::new()
+    }
+
+    /// 用给定配置替换全局配置
+    /// Replace the global configuration with the given one
+    ///
+    /// # 参数 (Parameters)
+    /// * `config` - 新的配置，通常由 `Config::builder()` 构建 / New configuration, usually built by `Config::builder()`
+    pub fn set_global(config: Config) {
+        let cfg = CFG.current();
+
+        // 先换上新配置的并发额度再移除多余的，整份配置最后一次性替换，读取方不会看到清空了一半的配置；
+        // 已有的额度原地调整，进行中的请求继续计入上限
+        // New permits go in before stale ones are removed and the whole configuration is swapped in last, so readers
+        // never see a half-cleared configuration; existing permits are resized in place, so requests in flight still
+        // count against the limit
+        for source in config.api_source.iter() {
+            let (base_url, limit) = (source.base_url.clone(), source.permits());
+            match cfg.api_source.iter().find(|old| old.base_url == base_url).map(|old| old.permits()) {
+                Some(previous) => resize_pool_entry(cfg.source_pool(), base_url, previous, limit),
+                None => {
+                    cfg.source_pool().insert(base_url, Arc::new(Semaphore::new(limit)));
+                }
+            }
+        }
+        cfg.source_pool().retain(|base_url, _| config.api_source.iter().any(|source| source.base_url == *base_url));
+        for entry in config.capability_limits.iter() {
+            let (capability, limit) = (entry.key().clone(), *entry.value());
+            match cfg.capability_limits.get(&capability).map(|previous| *previous) {
+                Some(previous) => resize_pool_entry(cfg.capability_pool(), capability, previous, limit),
+                None => {
+                    cfg.capability_pool().insert(capability, Arc::new(Semaphore::new(limit)));
+                }
+            }
+        }
+        cfg.capability_pool().retain(|capability, _| config.capability_limits.contains_key(capability));
+
+        cfg.replace(config);
+    }
+
+    /// 在当前任务的作用域内以给定配置替代全局配置运行 `future`，作用域之外（包括并行运行的其他测试）仍使用全局配置，
+    /// 并行的测试因此可以使用各自的配置而不会在全局配置上竞争
+    /// Run `future` with the given configuration standing in for the global one within the current task's scope;
+    /// outside the scope, including other tests running in parallel, the global configuration is still used, so
+    /// parallel tests can use their own configurations without racing on the global one
+    ///
+    /// 作用域内对配置的修改（包括 `Config::set_global`、`Config::add_api_source` 等）只影响该配置。作用域有自己的
+    /// 并发额度，按其API来源与能力上限建立，不与全局配置或其他作用域共享；关闭状态（见 `Shutdown`）同样各自独立。
+    /// 作用域只跟随 `future` 本身，对话内部启动的任务（推测生成、影子流量等）会继承作用域，自行用 `tokio::spawn`
+    /// 启动的任务需以 `Config::in_current_scope` 包装。作用域结束且其中的任务都完成后配置即被释放。
+    /// Changes to the configuration within the scope (`Config::set_global`, `Config::add_api_source` and the like
+    /// included) only affect it. The scope has concurrency permits of its own, built from its API sources and
+    /// capability limits and shared neither with the global configuration nor with other scopes; the same goes for
+    /// the shutdown state (see `Shutdown`). The scope follows `future` itself only: tasks started inside chats
+    /// (speculation, shadow traffic and so on) inherit it, tasks started with `tokio::spawn` directly need wrapping in
+    /// `Config::in_current_scope`. The configuration is freed once the scope ends and every task within it finishes.
+    ///
+    /// # 参数 (Parameters)
+    /// * `config` - 作用域内使用的配置，通常由 `Config::builder()` 构建
+    ///   Configuration used within the scope, usually built by `Config::builder()`
+    /// * `future` - 在作用域内运行的异步代码
+    ///   Async code run within the scope
+    pub async fn with_scoped<F: Future>(config: Config, future: F) -> F::Output {
+        let source_pool = config
+            .api_source
+            .iter()
+            .map(|source| (source.base_url.clone(), Arc::new(Semaphore::new(source.permits()))))
+            .collect();
+        let capability_pool = config
+            .capability_limits
+            .iter()
+            .map(|entry| (entry.key().clone(), Arc::new(Semaphore::new(*entry.value()))))
+            .collect();
+        let scope = Scope {
+            config: RwLock::new(Arc::new(config)),
+            source_pool,
+            capability_pool,
+            shutdown: Arc::default(),
+        };
+        SCOPED_CFG.scope(Arc::new(scope), future).await
+    }
+
+    /// 让 `future` 在当前的配置作用域中运行，用于把作用域带入新启动的任务；不在作用域内时原样返回其结果
+    /// Run `future` in the current configuration scope, carrying the scope into newly spawned tasks; outside any
+    /// scope it just runs `future`
+    pub fn in_current_scope<F: Future>(future: F) -> impl Future<Output = F::Output> {
+        let scope = SCOPED_CFG.try_with(Arc::clone).ok();
+        async move {
+            match scope {
+                Some(scope) => SCOPED_CFG.scope(scope, future).await,
+                None => future.await,
+            }
+        }
+    }
+
+    /// 探测所有已配置的API，记录延迟与能力，并将失败的API标记为不健康
+    /// Probe every configured API, record latency and capabilities, and mark failing APIs unhealthy
+    ///
+    /// # 返回 (Returns)
+    /// * `Vec<ProbeReport>` - 每个API的探测报告 / Probe report of each API
+    pub async fn probe() -> Vec<ProbeReport> {
+        probe::probe(DEFAULT_PROBE_TIMEOUT).await
+    }
+
+    /// 校验全局配置，返回发现的所有问题
+    /// Validate the global configuration, returning every problem found
+    ///
+    /// # 返回 (Returns)
+    /// * `Vec<ConfigIssue>` - 问题列表，为空表示配置有效 / List of problems, empty if the configuration is valid
+    pub fn validate() -> Vec<ConfigIssue> {
+        validate::validate(&CFG.current())
+    }
+
+    /// 从 TOML 文件加载配置
+    /// Load configuration from a TOML file
+    ///
+    /// `base_url` 与 `api_key` 支持 `${ENV_VAR}` 插值和 `env:ENV_VAR` 写法，
+    /// `api_key` 还支持 `secret:<provider>:<key>` 从已注册的密钥提供者获取，密钥因此无需写入配置文件。
+    /// `base_url` and `api_key` support `${ENV_VAR}` interpolation and the `env:ENV_VAR` form,
+    /// and `api_key` may also be `secret:<provider>:<key>` to fetch it from a registered secret provider,
+    /// so secrets never need to be committed.
+    ///
+    /// 环境由 `RHINE_PROFILE` 环境变量选择，未设置时只使用顶层配置。
+    /// The profile is selected by the `RHINE_PROFILE` environment variable, only the top level is used if unset.
+    ///
+    /// # 参数 (Parameters)
+    /// * `path` - 配置文件路径 / Config file path
+    pub fn load(path: &str) -> Result<(), ConfigError> {
+        let profile = std::env::var(PROFILE_ENV_VAR).ok().filter(|profile| !profile.is_empty());
+        Self::load_with_profile(path, profile.as_deref())
+    }
+
+    /// 从 TOML 文件加载指定环境的配置
+    /// Load configuration of the given profile from a TOML file
+    ///
+    /// # 参数 (Parameters)
+    /// * `path` - 配置文件路径 / Config file path
+    /// * `profile` - 环境名称，为 None 时只使用顶层配置 / Profile name, only the top level is used if None
+    pub fn load_with_profile(path: &str, profile: Option<&str>) -> Result<(), ConfigError> {
+        let file: ConfigFile = load_toml(path)
+            .change_context_lazy(|| ConfigError::LoadError(path.to_string()))?;
+        let file = resolve_profile(file, profile)
+            .attach_printable_lazy(|| format!("In config file: {}", path))?;
+
+        for source in &file.api_source {
+            let base_url = expand_env(&source.base_url)
+                .change_context_lazy(|| ConfigError::EnvExpansionError(format!("api_source.{}.base_url", source.name)))?;
+            Self::add_api_source(&source.name, &base_url, source.parallelism);
+            if let Some(tls) = &source.tls {
+                Self::set_source_tls(&source.name, tls.clone())?;
+            }
+            if !source.transport.is_default() {
+                Self::set_source_transport(&source.name, source.transport.clone())?;
+            }
+            Self::set_source_compat(&source.name, source.compat.clone())?;
+            Self::set_source_compression(&source.name, source.compression.clone())?;
+        }
+
+        for info in &file.api_info {
+            let api_key = expand_env(&info.api_key)
+                .change_context_lazy(|| ConfigError::EnvExpansionError(format!("api_info.{}.api_key", info.name)))?;
+            let api_key = resolve_secret(&api_key)
+                .change_context_lazy(|| ConfigError::SecretError(format!("api_info.{}.api_key", info.name)))?;
+            Self::add_api_info(&info.name, &info.model, info.capability.clone(), &info.source, &api_key)?;
+            Self::set_api_weight(&info.name, info.weight);
+            Self::set_api_params(&info.name, info.params.clone());
+        }
+
+        for (alias, name) in &file.alias {
+            Self::add_alias(alias, name);
+        }
+
+        for (model, metadata) in file.model_metadata {
+            Self::set_model_metadata(&model, metadata);
+        }
+
+        for (capability, limit) in file.capability_limit {
+            Self::set_capability_limit(capability, limit);
+        }
+
+        for (key, prompt) in file.capability_prompt {
+            Self::set_capability_prompt(key, &prompt);
+        }
+
+        for entry in file.trace_exporter {
+            let kind = entry.kind
+                .try_map_values(|field, value| {
+                    let value = expand_env(&value)
+                        .change_context_lazy(|| ConfigError::EnvExpansionError(format!("trace_exporter.{}.{}", entry.name, field)))?;
+                    resolve_secret(&value)
+                        .change_context_lazy(|| ConfigError::SecretError(format!("trace_exporter.{}.{}", entry.name, field)))
+                })?;
+            register_trace_exporter(&entry.name, kind.build());
+        }
+
+        if let Some(audit_log) = file.audit_log {
+            AuditLog::enable(audit_log)
+                .change_context(ConfigError::LoadError("audit_log".to_string()))?;
+        }
+
+        if let Some(path) = file.usage_ledger {
+            let ledger = JsonlUsageLedger::new(path)
+                .change_context(ConfigError::LoadError("usage_ledger".to_string()))?;
+            set_usage_ledger(Arc::new(ledger));
+        }
+
+        if let Some(mut anonymize) = file.anonymize {
+            let salt = expand_env(&anonymize.salt)
+                .change_context_lazy(|| ConfigError::EnvExpansionError("anonymize.salt".to_string()))?;
+            anonymize.salt = resolve_secret(&salt)
+                .change_context_lazy(|| ConfigError::SecretError("anonymize.salt".to_string()))?;
+            if anonymize.missing_salt() {
+                return Err(Report::new(ConfigError::MissingSalt("anonymize.salt".to_string())));
+            }
+            Anonymizer::enable(anonymize);
+        }
+
+        Ok(())
+    }
+
+    /// 添加API来源
+    /// Add API source
+    ///
+    /// # 参数 (Parameters)
+    /// * `name` - API来源名称
+    ///          - API source name
+    /// * `base_url` - API基础URL
+    ///              - API base URL
+    /// * `parallelism` - 并行度（允许的并发请求数）
+    ///                 - Parallelism (allowed concurrent requests)
+    pub fn add_api_source(name: &str, base_url: &str, parallelism: usize) {
+        let cfg = CFG.current();
+        // 向配置中添加API来源
+        // Add API source to configuration
+        cfg.api_source.insert(
+            name.to_string(),
+            ApiSource {
+                base_url: base_url.to_string(),
+                parallelism,
+                tls: None,
+                compat: ProviderCompat::default(),
+                compression: CompressionConfig::default(),
+                transport: TransportConfig::default(),
+            },
+        );
+
+        // 为该API来源创建信号量用于控制并发
+        // Create semaphore for this API source to control concurrency
+        cfg.source_pool().insert(base_url.to_string(), Arc::new(Semaphore::new(parallelism)));
+    }
+
+    /// 调整API来源的并行度；已发出的请求继续持有额度直到完成，之后的请求按新的上限放行，并发数不会超过新的上限
+    /// Change the parallelism of an API source; requests already in flight keep their permits until they finish,
+    /// later requests are admitted against the new limit, so concurrency never exceeds it
+    ///
+    /// # 参数 (Parameters)
+    /// * `name` - API来源名称 / API source name
+    /// * `parallelism` - 新的并行度 / New parallelism
+    pub fn set_source_parallelism(name: &str, parallelism: usize) -> Result<(), ConfigError> {
+        let cfg = CFG.current();
+        let mut source = cfg.api_source
+            .get_mut(name)
+            .ok_or_else(|| Report::new(ConfigError::UnknownSource(name.to_string())))?;
+        let previous = source.permits();
+        source.parallelism = parallelism;
+        resize_pool_entry(cfg.source_pool(), source.base_url.clone(), previous, source.permits());
+        Ok(())
+    }
+
+    /// 获取API来源的并发额度使用情况
+    /// Get the concurrency permit usage of an API source
+    pub fn get_source_permits(name: &str) -> Option<PermitStats> {
+        let cfg = CFG.current();
+        let source = cfg.api_source.get(name)?;
+        let semaphore = cfg.source_pool().get(&source.base_url)?;
+        Some(PermitStats {
+            limit: source.permits(),
+            available: semaphore.available_permits(),
+        })
+    }
+
+    /// 设置某个能力的并发请求上限，该能力下所有API的请求共享这一额度
+    /// Set the concurrent request limit of a capability, requests to every API of the capability share it
+    ///
+    /// # 参数 (Parameters)
+    /// * `capability` - 模型能力 / Model capability
+    /// * `limit` - 并发请求上限 / Concurrent request limit
+    pub fn set_capability_limit(capability: ModelCapability, limit: usize) {
+        let cfg = CFG.current();
+        let previous = cfg.capability_limits.insert(capability.clone(), limit);
+        match previous {
+            Some(previous) => resize_pool_entry(cfg.capability_pool(), capability, previous, limit),
+            None => {
+                cfg.capability_pool().insert(capability, Arc::new(Semaphore::new(limit)));
+            }
+        }
+    }
+
+    /// 移除某个能力的并发请求上限
+    /// Remove the concurrent request limit of a capability
+    pub fn remove_capability_limit(capability: &ModelCapability) {
+        let cfg = CFG.current();
+        cfg.capability_limits.remove(capability);
+        cfg.capability_pool().remove(capability);
+    }
+
+    /// 获取某个能力的并发额度使用情况，未设置上限时返回 None
+    /// Get the concurrency permit usage of a capability, None if no limit is set
+    pub fn get_capability_permits(capability: &ModelCapability) -> Option<PermitStats> {
+        let cfg = CFG.current();
+        let limit = *cfg.capability_limits.get(capability)?;
+        let semaphore = cfg.capability_pool().get(capability)?;
+        Some(PermitStats {
+            limit,
+            available: semaphore.available_permits(),
+        })
+    }
+
+    /// 设置某个键的内部提示词，替换内置的默认提示词，便于按部署调整或本地化
+    /// Set the internal prompt of a key, replacing its built-in default so deployments can tune or localize it
+    ///
+    /// # 参数 (Parameters)
+    /// * `key` - 提示词键 / Prompt key
+    /// * `prompt` - 提示词 / Prompt
+    pub fn set_capability_prompt(key: PromptKey, prompt: &str) {
+        CFG.current().capability_prompts.insert(key, prompt.to_string());
+    }
+
+    /// 移除某个键的内部提示词，恢复内置的默认提示词
+    /// Remove the internal prompt of a key, restoring the built-in default
+    pub fn remove_capability_prompt(key: &PromptKey) {
+        CFG.current().capability_prompts.remove(key);
+    }
+
+    /// 获取某个键的内部提示词，未配置时返回内置的默认提示词
+    /// Get the internal prompt of a key, the built-in default if none is configured
+    pub fn capability_prompt(key: &PromptKey) -> String {
+        CFG.current().capability_prompts
+            .get(key)
+            .map(|prompt| prompt.clone())
+            .unwrap_or_else(|| key.default_prompt().to_string())
+    }
+
+    /// 获取某个键的内部提示词，并依次以 `values` 填入其中的 `{}` 占位符；多余的占位符留空，多余的值丢弃
+    /// Get the internal prompt of a key with its `{}` placeholders filled by `values` in order; extra placeholders
+    /// are left empty and extra values dropped
+    pub fn render_prompt(key: &PromptKey, values: &[&str]) -> String {
+        let prompt = Self::capability_prompt(key);
+        let mut values = values.iter();
+        let mut pieces = prompt.split("{}");
+        let mut rendered = pieces.next().unwrap_or_default().to_string();
+        for piece in pieces {
+            rendered.push_str(values.next().copied().unwrap_or_default());
+            rendered.push_str(piece);
+        }
+        rendered
+    }
+
+    /// 设置API来源的TLS配置，并重建使用该来源的API的HTTP客户端
+    /// Set TLS configuration of an API source and rebuild the HTTP clients of APIs using it
+    ///
+    /// # 参数 (Parameters)
+    /// * `name` - API来源名称 / API source name
+    /// * `tls` - TLS配置（自定义根证书、客户端证书、跳过校验） / TLS configuration (custom root CA, client certificate, skip verification)
+    pub fn set_source_tls(name: &str, tls: TlsConfig) -> Result<(), ConfigError> {
+        let cfg = CFG.current();
+        let transport = cfg.api_source
+            .get(name)
+            .map(|source| source.transport.clone())
+            .ok_or_else(|| Report::new(ConfigError::UnknownSource(name.to_string())))?;
+        let client = build_client(Some(&tls), &transport)
+            .attach_printable_lazy(|| format!("For API source '{}'", name))?;
+
+        let base_url = {
+            let mut source = cfg.api_source
+                .get_mut(name)
+                .ok_or_else(|| Report::new(ConfigError::UnknownSource(name.to_string())))?;
+            source.tls = Some(tls);
+            source.base_url.clone()
+        };
+
+        cfg.api_info
+            .iter_mut()
+            .filter(|entry| entry.value().base_url == base_url)
+            .for_each(|mut entry| entry.value_mut().client = client.clone());
+
+        Ok(())
+    }
+
+    /// 设置API来源的传输层调优，重建使用该来源的API的HTTP客户端，并按进行中的请求上限原地调整并发额度，
+    /// 同 `set_source_parallelism`
+    /// Set the transport tuning of an API source, rebuilding the HTTP clients of APIs using it and resizing the
+    /// concurrency permits in place by the in-flight cap, as `set_source_parallelism` does
+    ///
+    /// # 参数 (Parameters)
+    /// * `name` - API来源名称 / API source name
+    /// * `transport` - 传输层调优（HTTP/2开关、进行中的请求上限、TCP keep-alive、连接超时）
+    ///   - Transport tuning (HTTP/2 switch, in-flight cap, TCP keep-alive, connect timeout)
+    pub fn set_source_transport(name: &str, transport: TransportConfig) -> Result<(), ConfigError> {
+        let cfg = CFG.current();
+        let tls = cfg.api_source
+            .get(name)
+            .map(|source| source.tls.clone())
+            .ok_or_else(|| Report::new(ConfigError::UnknownSource(name.to_string())))?;
+        let client = build_client(tls.as_ref(), &transport)
+            .attach_printable_lazy(|| format!("For API source '{}'", name))?;
+
+        let base_url = {
+            let mut source = cfg.api_source
+                .get_mut(name)
+                .ok_or_else(|| Report::new(ConfigError::UnknownSource(name.to_string())))?;
+            let previous = source.permits();
+            source.transport = transport;
+            resize_pool_entry(cfg.source_pool(), source.base_url.clone(), previous, source.permits());
+            source.base_url.clone()
+        };
+
+        cfg.api_info
+            .iter_mut()
+            .filter(|entry| entry.value().base_url == base_url)
+            .for_each(|mut entry| entry.value_mut().client = client.clone());
+
+        Ok(())
+    }
+
+    /// 设置API来源的提供商兼容性，如不支持 system 角色或 `response_format`，发往该来源的请求体随之改写
+    /// Set the provider compatibility of an API source, such as no system role or no `response_format`; request
+    /// bodies sent to the source are rewritten accordingly
+    pub fn set_source_compat(name: &str, compat: ProviderCompat) -> Result<(), ConfigError> {
+        let cfg = CFG.current();
+        let mut source = cfg.api_source
+            .get_mut(name)
+            .ok_or_else(|| Report::new(ConfigError::UnknownSource(name.to_string())))?;
+        source.compat = compat;
+        Ok(())
+    }
+
+    /// 获取基础URL所属API来源的兼容性设置，没有需要的改写时返回 None
+    /// Get the compatibility settings of the API source owning a base URL, None if nothing needs rewriting
+    pub fn get_compat(base_url: &str) -> Option<ProviderCompat> {
+        CFG.current().api_source
+            .iter()
+            .find(|source| source.base_url == base_url && !source.compat.is_noop())
+            .map(|source| source.compat.clone())
+    }
+
+    /// 设置API来源的HTTP压缩，如开启较大请求体的 gzip 压缩
+    /// Set the HTTP compression of an API source, such as turning on gzip for large request bodies
+    pub fn set_source_compression(name: &str, compression: CompressionConfig) -> Result<(), ConfigError> {
+        let cfg = CFG.current();
+        let mut source = cfg.api_source
+            .get_mut(name)
+            .ok_or_else(|| Report::new(ConfigError::UnknownSource(name.to_string())))?;
+        source.compression = compression;
+        Ok(())
+    }
+
+    /// 获取API来源的HTTP压缩设置，来源不存在时返回默认设置
+    /// Get the HTTP compression settings of an API source, the defaults if there is no such source
+    pub fn get_compression(source_name: &str) -> CompressionConfig {
+        CFG.current().api_source
+            .get(source_name)
+            .map(|source| source.compression.clone())
+            .unwrap_or_default()
+    }
+
+    /// 添加API信息
+    /// Add API information
+    ///
+    /// # 参数 (Parameters)
+    /// * `name` - API名称
+    ///          - API name
+    /// * `model` - 模型名称
+    ///           - Model name
+    /// * `capability` - 模型能力
+    ///                - Model capability
+    /// * `source_name` - API来源名称
+    ///                 - API source name
+    /// * `api_key` - API密钥
+    ///             - API key
+    ///
+    /// # 返回 (Returns)
+    /// * `Result<(), ConfigError>` - API来源不存在时返回错误 / Fails if the API source does not exist
+    pub fn add_api_info(
+        name: &str,
+        model: &str,
+        capability: ModelCapability,
+        source_name: &str,
+        api_key: &str,
+    ) -> Result<(), ConfigError> {
+        let cfg = CFG.current();
+        // 获取API来源的基础URL、TLS配置与传输层调优
+        // Get the base URL, TLS configuration and transport tuning of API source
+        let (base_url, tls, transport) = cfg
+            .api_source
+            .get(source_name)
+            .map(|source| (source.base_url.clone(), source.tls.clone(), source.transport.clone()))
+            .ok_or_else(|| Report::new(ConfigError::UnknownSource(source_name.to_string())))
+            .attach_printable_lazy(|| format!("Referenced by API '{}'", name))?;
+        let client = build_client(tls.as_ref(), &transport)
+            .attach_printable_lazy(|| format!("For API source '{}'", source_name))?;
+
+        // 向配置中添加API信息
+        // Add API information to configuration
+        cfg.api_info.insert(
+            (name.to_string(), capability),
+            ApiInfo {
+                name: name.to_string(),
+                model: model.to_string(),
+                base_url,
+                source: source_name.to_string(),
+                api_key: api_key.to_string(),
+                client,
+                weight: 1,
+                params: ChatParams::default(),
+            },
+        );
+
+        Ok(())
+    }
+
+    /// 设置API的默认生成参数
+    /// Set default generation parameters of an API
+    ///
+    /// # 参数 (Parameters)
+    /// * `name` - API名称 / API name
+    /// * `params` - 默认生成参数 / Default generation parameters
+    pub fn set_api_params(name: &str, params: ChatParams) {
+        CFG.current().api_info
+            .iter_mut()
+            .filter(|entry| entry.key().0 == name)
+            .for_each(|mut entry| entry.value_mut().params = params.clone());
+    }
+
+    /// 设置API的负载均衡权重
+    /// Set load balancing weight of an API
+    ///
+    /// # 参数 (Parameters)
+    /// * `name` - API名称 / API name
+    /// * `weight` - 权重，为0时该API不会被按能力选中 / Weight, an API with weight 0 is never picked by capability
+    pub fn set_api_weight(name: &str, weight: u32) {
+        CFG.current().api_info
+            .iter_mut()
+            .filter(|entry| entry.key().0 == name)
+            .for_each(|mut entry| entry.value_mut().weight = weight);
+    }
+
+    /// 添加别名
+    /// Add an alias
+    ///
+    /// # 参数 (Parameters)
+    /// * `alias` - 别名 / Alias
+    /// * `api_name` - 别名指向的API名称 / API name the alias points to
+    pub fn add_alias(alias: &str, api_name: &str) {
+        CFG.current().aliases.insert(alias.to_string(), api_name.to_string());
+    }
+
+    /// 解析别名，非别名的名称原样返回
+    /// Resolve an alias, names that are not aliases are returned unchanged
+    pub fn resolve_alias(name: &str) -> String {
+        CFG.current().aliases
+            .get(name)
+            .map(|entry| entry.value().clone())
+            .unwrap_or_else(|| name.to_string())
+    }
+
+    /// 设置模型元数据
+    /// Set model metadata
+    ///
+    /// # 参数 (Parameters)
+    /// * `model` - 模型名称 / Model name
+    /// * `metadata` - 模型元数据 / Model metadata
+    pub fn set_model_metadata(model: &str, metadata: ModelMetadata) {
+        CFG.current().model_metadata.insert(model.to_string(), metadata);
+    }
+
+    /// 获取模型元数据
+    /// Get model metadata
+    pub fn get_model_metadata(model: &str) -> Option<ModelMetadata> {
+        CFG.current().model_metadata.get(model).map(|entry| entry.value().clone())
+    }
+
+    /// 为模型注册分词器（如包装 HuggingFace `tokenizers` 的实现），优先于模型元数据中的 `tokenizer`
+    /// Register a tokenizer for a model (such as one wrapping HuggingFace `tokenizers`), taking precedence over the
+    /// `tokenizer` of the model metadata
+    pub fn set_tokenizer(model: &str, tokenizer: impl Tokenizer + 'static) {
+        register_tokenizer(model, Some(Arc::new(tokenizer)));
+    }
+
+    /// 移除为模型注册的分词器
+    /// Remove the tokenizer registered for a model
+    pub fn remove_tokenizer(model: &str) {
+        register_tokenizer(model, None);
+    }
+
+    /// 模型使用的分词器，见 `tokenizer_for`
+    /// Tokenizer of a model, see `tokenizer_for`
+    pub fn tokenizer(model: &str) -> Arc<dyn Tokenizer> {
+        tokenizer_for(model)
+    }
+
+    /// 根据名称获取API信息，名称可以是别名
+    /// Get API information by name, the name may be an alias
+    ///
+    /// # 参数 (Parameters)
+    /// * `name` - API名称
+    ///          - API name
+    ///
+    /// # 返回 (Returns)
+    /// * `Result<ApiInfo, ConfigError>` - 成功返回API信息，失败返回配置错误
+    ///                                  - Returns API info on success, config error on failure
+    pub fn get_api_info_with_name(name: String) -> Result<ApiInfo, ConfigError> {
+        let name = Self::resolve_alias(&name);
+
+
+        // 在API信息映射表中查找匹配的条目
+        // Find matching entry in API info map
+        CFG.current().api_info
+            .iter()
+            .find_map(|entry| {
+                (entry.key().0 == name).then(|| entry.value().clone())
+            })
+            .ok_or(ConfigError::ApiInfoNotFound.into())
+    }
+
+    /// 根据模型能力获取API信息
+    /// Get API information by model capability
+    ///
+    /// 同一能力绑定多个API时，在健康的API中按权重随机选择，并优先选择仍有空闲并发额度的API。
+    /// When several APIs are bound to one capability, one is picked at random by weight among healthy APIs,
+    /// preferring those that still have free concurrency permits.
+    ///
+    /// # 参数 (Parameters)
+    /// * `capability` - 模型能力
+    ///                - Model capability
+    ///
+    /// # 返回 (Returns)
+    /// * `Result<ApiInfo, ConfigError>` - 成功返回API信息，失败返回配置错误
+    ///                                  - Returns API info on success, config error on failure
+    pub fn get_api_info_with_capability(
+        capability: ModelCapability,
+    ) -> Result<ApiInfo, ConfigError> {
+        // 收集匹配该能力的所有条目
+        // Collect all entries matching the capability
+        let candidates: Vec<ApiInfo> = CFG.current().api_info
+            .iter()
+            .filter(|entry| entry.key().1 == capability)
+            .map(|entry| entry.value().clone())
+            .collect();
+
+        select_endpoint(candidates)
+    }
+
+    /// 为故障转移选择同一能力下的另一个API，只在健康且不在 `exclude` 中的API中选择
+    /// Select another API of the capability for failover, only among healthy APIs not in `exclude`
+    pub fn get_failover_api_info(capability: ModelCapability, exclude: &[String]) -> Option<ApiInfo> {
+        let candidates: Vec<ApiInfo> = CFG.current().api_info
+            .iter()
+            .filter(|entry| entry.key().1 == capability)
+            .map(|entry| entry.value().clone())
+            .filter(|api| !exclude.contains(&api.name) && is_healthy(&api.name))
+            .collect();
+
+        select_endpoint(candidates).ok()
+    }
+}
+
+/// 作用域中的配置及其独立的并发额度，见 `Config::with_scoped`
+/// A scoped configuration with its own concurrency permits, see `Config::with_scoped`
+struct Scope {
+    config: RwLock<Arc<Config>>,
+    source_pool: DashMap<String, Arc<Semaphore>>,
+    capability_pool: DashMap<ModelCapability, Arc<Semaphore>>,
+    shutdown: Arc<ShutdownState>,
+}
+
+tokio::task_local! {
+    /// 当前任务作用域内替代全局配置的配置，见 `Config::with_scoped`
+    /// Configuration standing in for the global one within the current task's scope, see `Config::with_scoped`
+    static SCOPED_CFG: Arc<Scope>;
+}
+
+/// 全局配置，通过 `current` 取得当前生效的配置
+/// Global configuration, `current` gives the configuration in effect
+pub struct GlobalConfig {
+    global: RwLock<Arc<Config>>,
+}
+
+impl GlobalConfig {
+    /// 当前生效的配置：在 `Config::with_scoped` 的作用域内为作用域中的配置，否则为全局配置
+    /// The configuration in effect: the scoped configuration within a `Config::with_scoped` scope, the global one
+    /// otherwise
+    pub fn current(&self) -> CurrentConfig {
+        let scope = SCOPED_CFG.try_with(Arc::clone).ok();
+        let config = match &scope {
+            Some(scope) => scope.config.read().unwrap().clone(),
+            None => self.global.read().unwrap().clone(),
+        };
+        CurrentConfig { scope, config }
+    }
+}
+
+/// 当前生效的配置，持有取得时的配置直到释放，期间被 `Config::set_global` 替换也不受影响
+/// The configuration in effect, holding on to the configuration as it was when taken until dropped, unaffected by a
+/// `Config::set_global` in between
+pub struct CurrentConfig {
+    scope: Option<Arc<Scope>>,
+    config: Arc<Config>,
+}
+
+impl CurrentConfig {
+    /// 替换作用域中的配置，不在作用域内时替换全局配置
+    /// Replace the scoped configuration, or the global one outside any scope
+    fn replace(&self, config: Config) {
+        let slot = match &self.scope {
+            Some(scope) => &scope.config,
+            None => &CFG.global,
+        };
+        *slot.write().unwrap() = Arc::new(config);
+    }
+
+    /// 按API地址的并发额度
+    /// Concurrency permits by API address
+    pub(crate) fn source_pool(&self) -> &DashMap<String, Arc<Semaphore>> {
+        match &self.scope {
+            Some(scope) => &scope.source_pool,
+            None => &THREAD_POOL,
+        }
+    }
+
+    /// 按能力的并发额度
+    /// Concurrency permits by capability
+    pub(crate) fn capability_pool(&self) -> &DashMap<ModelCapability, Arc<Semaphore>> {
+        match &self.scope {
+            Some(scope) => &scope.capability_pool,
+            None => &CAPABILITY_POOL,
+        }
+    }
+
+    /// 关闭状态与进行中的工作计数
+    /// Shutdown state and the counts of work in flight
+    pub(crate) fn shutdown_state(&self) -> Arc<ShutdownState> {
+        match &self.scope {
+            Some(scope) => Arc::clone(&scope.shutdown),
+            None => Arc::clone(&SHUTDOWN_STATE),
+        }
+    }
+}
+
+impl Deref for CurrentConfig {
+    type Target = Config;
+
+    fn deref(&self) -> &Config {
+        &self.config
+    }
+}
+
+/// 全局配置实例
+/// Global configuration instance
+pub static CFG: Lazy<GlobalConfig> = Lazy::new(|| GlobalConfig {
+    global: RwLock::new(Arc::new(Config {
+        api_source: DashMap::new(),
+        api_info: DashMap::new(),
+        aliases: DashMap::new(),
+        capability_limits: DashMap::new(),
+        capability_prompts: DashMap::new(),
+        model_metadata: DashMap::new(),
+    })),
+});
+
+/// 全局线程池（信号量池）- 用于控制对不同API来源的并发请求，作用域中的配置另有自己的额度
+/// Global thread pool (semaphore pool) - used to control concurrent requests to different API sources, scoped
+/// configurations have permits of their own
+pub static THREAD_POOL: Lazy<DashMap<String, Arc<Semaphore>>> = Lazy::new(|| DashMap::new());
+
+/// 能力信号量池 - 用于限制同一能力下的并发请求，作用域中的配置另有自己的额度
+/// Capability semaphore pool - used to limit concurrent requests of one capability, scoped configurations have
+/// permits of their own
+pub static CAPABILITY_POOL: Lazy<DashMap<ModelCapability, Arc<Semaphore>>> = Lazy::new(DashMap::new);
+
+/// 原地调整额度池中某个信号量的额度，池中没有时按新的额度创建
+/// Resize one semaphore of a permit pool in place, creating it with the new limit if the pool has none
+///
+/// 替换信号量会让进行中的请求持有的旧额度不再计入，并发数可能超过上限，因此只增减现有信号量的额度：
+/// 增加时立即放出；减少时先收回空闲额度，其余额度在进行中的请求归还时收回，收回之前新的请求排队等待。
+/// Swapping the semaphore would stop counting the permits held by requests in flight and let concurrency exceed
+/// the limit, so the existing semaphore is resized instead: added permits are released at once; removed ones are
+/// taken from the free permits first and the rest reclaimed as requests in flight return theirs, new requests
+/// queueing until then.
+fn resize_pool_entry<K: Eq + Hash>(pool: &DashMap<K, Arc<Semaphore>>, key: K, previous: usize, limit: usize) {
+    let Some(semaphore) = pool.get(&key).map(|semaphore| Arc::clone(semaphore.value())) else {
+        pool.insert(key, Arc::new(Semaphore::new(limit)));
+        return;
+    };
+    if limit >= previous {
+        semaphore.add_permits(limit - previous);
+        return;
+    }
+    let deficit = previous - limit;
+    let pending = deficit - semaphore.forget_permits(deficit);
+    if pending == 0 {
+        return;
+    }
+    // 信号量是公平的，等待收回的额度排在之后的请求前面
+    // The semaphore is fair, the reclaim waits ahead of later requests
+    let reclaim = async move {
+        if let Ok(permits) = semaphore.acquire_many_owned(pending as u32).await {
+            permits.forget();
+        }
+    };
+    match tokio::runtime::Handle::try_current() {
+        Ok(handle) => drop(handle.spawn(reclaim)),
+        Err(_) => drop(std::thread::spawn(move || futures::executor::block_on(reclaim))),
+    }
+}
+
+/// 全局的关闭状态，作用域中的配置另有自己的一份
+/// Global shutdown state, scoped configurations have one of their own
+static SHUTDOWN_STATE: Lazy<Arc<ShutdownState>> = Lazy::new(Arc::default);
+
+/// 并发额度使用情况
+/// Concurrency permit usage
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PermitStats {
+    /// 并发上限
+    /// Concurrency limit
+    pub limit: usize,
+
+    /// 空闲额度
+    /// Free permits
+    pub available: usize,
+}
+
+impl PermitStats {
+    /// 正在使用的额度
+    /// Permits in use
+    pub fn in_use(&self) -> usize {
+        self.limit.saturating_sub(self.available)
+    }
+}
\ No newline at end of file
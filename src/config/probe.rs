@@ -0,0 +1,140 @@
+// 标准库
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+// 异步
+use futures::future::join_all;
+use tokio::time::timeout;
+
+// 序列化
+use serde_json::json;
+
+// 项目内部模块
+use crate::config::balance::ENDPOINT_HEALTH;
+use crate::config::{ApiInfo, CFG};
+
+/// 默认探测超时时间
+/// Default probe timeout
+pub const DEFAULT_PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 单个API的探测报告
+/// Probe report of a single API
+#[derive(Clone, Debug)]
+pub struct ProbeReport {
+    /// API名称
+    /// API name
+    pub api_name: String,
+
+    /// 模型名称
+    /// Model name
+    pub model: String,
+
+    /// 是否健康
+    /// Whether healthy
+    pub healthy: bool,
+
+    /// 1-token 补全请求的延迟
+    /// Latency of the 1-token completion
+    pub latency: Duration,
+
+    /// 是否接受原生工具调用参数，健康检查失败时为 None
+    /// Whether native tool parameters are accepted, None when the health check failed
+    pub supports_tools: Option<bool>,
+
+    /// 失败原因
+    /// Failure reason
+    pub error: Option<String>,
+}
+
+/// 探测所有已配置的API
+/// Probe every configured API
+///
+/// 对每个API发送一次 1-token 补全请求，成功后再带上一个空工具发送一次以检测原生工具调用支持，
+/// 结果写入全局端点健康表，不健康的API会被负载均衡跳过。
+/// Sends a 1-token completion to each API and, on success, a second one carrying an empty tool to detect
+/// native tool calling. Results are written to the global endpoint health table, so unhealthy APIs are
+/// skipped by load balancing.
+///
+/// # 参数 (Parameters)
+/// * `probe_timeout` - 单次请求的超时时间 / Timeout of a single request
+pub async fn probe(probe_timeout: Duration) -> Vec<ProbeReport> {
+    // 同名API在不同能力下共享端点，只探测一次
+    // APIs with the same name share an endpoint across capabilities, probe once
+    let apis: HashMap<String, ApiInfo> = CFG.current().api_info
+        .iter()
+        .map(|entry| (entry.value().name.clone(), entry.value().clone()))
+        .collect();
+
+    let reports = join_all(apis.into_values().map(|api| probe_api(api, probe_timeout))).await;
+
+    for report in &reports {
+        let mut health = ENDPOINT_HEALTH.entry(report.api_name.clone()).or_default();
+        health.healthy = report.healthy;
+        health.latency = Some(report.latency);
+        health.last_checked = Some(Instant::now());
+        health.supports_tools = report.supports_tools;
+    }
+
+    reports
+}
+
+/// 探测单个API
+/// Probe a single API
+async fn probe_api(api: ApiInfo, probe_timeout: Duration) -> ProbeReport {
+    let body = json!({
+        "model": api.model,
+        "messages": [{"role": "user", "content": "ping"}],
+        "max_tokens": 1,
+        "stream": false,
+    });
+
+    let started = Instant::now();
+    let result = send_probe(&api, &body, probe_timeout).await;
+    let latency = started.elapsed();
+
+    let mut report = ProbeReport {
+        api_name: api.name.clone(),
+        model: api.model.clone(),
+        healthy: result.is_ok(),
+        latency,
+        supports_tools: None,
+        error: result.err(),
+    };
+
+    if report.healthy {
+        let mut tools_body = body;
+        tools_body["tools"] = json!([{
+            "type": "function",
+            "function": {
+                "name": "ping",
+                "description": "Health check, never needs to be called.",
+                "parameters": {"type": "object", "properties": {}},
+            },
+        }]);
+        report.supports_tools = Some(send_probe(&api, &tools_body, probe_timeout).await.is_ok());
+    }
+
+    report
+}
+
+/// 发送探测请求，非2xx状态、网络错误与超时均视为失败
+/// Send a probe request, non-2xx status, network errors and timeouts all count as failures
+async fn send_probe(
+    api: &ApiInfo,
+    body: &serde_json::Value,
+    probe_timeout: Duration,
+) -> core::result::Result<(), String> {
+    let request = api.client
+        .post(&api.base_url)
+        .header("Content-Type", "application/json")
+        .bearer_auth(&api.api_key)
+        .json(body)
+        .send();
+
+    match timeout(probe_timeout, request).await {
+        Ok(Ok(res)) if res.status().is_success() => Ok(()),
+        Ok(Ok(res)) => Err(format!("HTTP error with status code: {}", res.status().as_u16())),
+        Ok(Err(e)) => Err(format!("Network error: {}", e)),
+        Err(_) => Err("Timeout error".to_string()),
+    }
+}
@@ -0,0 +1,102 @@
+// 标准库
+use std::collections::HashSet;
+
+// 错误处理
+use error_stack::{Report, Result, ResultExt};
+
+// 项目内部模块
+use crate::config::{ConfigError, ConfigFile};
+
+/// 选择配置环境的环境变量
+/// Environment variable selecting the configuration profile
+pub const PROFILE_ENV_VAR: &str = "RHINE_PROFILE";
+
+/// 将选中的环境配置叠加到顶层配置上
+/// Overlay the selected profile onto the top-level configuration
+///
+/// 顶层配置是所有环境的基础。环境可以通过 `inherits` 继承另一个环境，叠加顺序为从最远的祖先到选中的环境，
+/// 同名的API来源、同名同能力的API信息、同名的追踪导出器、别名、能力并发上限、能力提示词和模型元数据
+/// 会被后叠加的覆盖。
+/// The top level is the base of every profile. A profile may `inherits` another one; layers are applied from
+/// the farthest ancestor down to the selected profile, and API sources with the same name, API info with the
+/// same name and capability, trace exporters with the same name, aliases, capability limits, capability prompts
+/// and model metadata are overridden by later layers.
+///
+/// # 参数 (Parameters)
+/// * `file` - 完整的配置文件 / The whole config file
+/// * `profile` - 选中的环境名称，为 None 时只使用顶层配置 / Selected profile name, only the top level is used if None
+pub fn resolve_profile(mut file: ConfigFile, profile: Option<&str>) -> Result<ConfigFile, ConfigError> {
+    let mut profiles = std::mem::take(&mut file.profile);
+    file.check_duplicates()?;
+
+    let Some(profile) = profile else {
+        return Ok(file);
+    };
+
+    // 从选中的环境沿继承链向上收集
+    // Collect from the selected profile up the inheritance chain
+    let mut chain = Vec::new();
+    let mut visited = HashSet::new();
+    let mut current = Some(profile.to_string());
+    while let Some(name) = current {
+        if !visited.insert(name.clone()) {
+            return Err(Report::new(ConfigError::ProfileCycle(name)));
+        }
+        let layer = profiles
+            .remove(&name)
+            .ok_or_else(|| Report::new(ConfigError::UnknownProfile(name.clone())))?;
+        current = layer.inherits.clone();
+        chain.push((name, layer));
+    }
+
+    for (name, layer) in chain.into_iter().rev() {
+        layer.check_duplicates()
+            .attach_printable_lazy(|| format!("In profile: {}", name))?;
+        overlay(&mut file, layer);
+    }
+
+    Ok(file)
+}
+
+/// 将一层配置叠加到基础配置上
+/// Overlay one layer onto the base configuration
+fn overlay(base: &mut ConfigFile, layer: ConfigFile) {
+    for source in layer.api_source {
+        match base.api_source.iter_mut().find(|existing| existing.name == source.name) {
+            Some(existing) => *existing = source,
+            None => base.api_source.push(source),
+        }
+    }
+
+    for info in layer.api_info {
+        match base.api_info
+            .iter_mut()
+            .find(|existing| existing.name == info.name && existing.capability == info.capability)
+        {
+            Some(existing) => *existing = info,
+            None => base.api_info.push(info),
+        }
+    }
+
+    for exporter in layer.trace_exporter {
+        match base.trace_exporter.iter_mut().find(|existing| existing.name == exporter.name) {
+            Some(existing) => *existing = exporter,
+            None => base.trace_exporter.push(exporter),
+        }
+    }
+
+    if layer.audit_log.is_some() {
+        base.audit_log = layer.audit_log;
+    }
+    if layer.usage_ledger.is_some() {
+        base.usage_ledger = layer.usage_ledger;
+    }
+    if layer.anonymize.is_some() {
+        base.anonymize = layer.anonymize;
+    }
+
+    base.alias.extend(layer.alias);
+    base.capability_limit.extend(layer.capability_limit);
+    base.capability_prompt.extend(layer.capability_prompt);
+    base.model_metadata.extend(layer.model_metadata);
+}
@@ -0,0 +1,261 @@
+use serde::Deserialize;
+
+/// 内部提示词的键，每个键对应一段发给模型的内置文本，可在配置的 `capability_prompt` 表中按键替换
+/// Key of an internal prompt, each key names one piece of built-in text sent to models and can be replaced by key
+/// in the `capability_prompt` table of the configuration
+///
+/// 提示词中的 `{}` 是占位符，由 `Config::render_prompt` 依次填入运行时的内容，替换的提示词须保留同样数量的占位符。
+/// `{}` in a prompt is a placeholder filled in order with runtime content by `Config::render_prompt`, so a
+/// replacement prompt must keep the same number of placeholders.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PromptKey {
+    /// 把文本整理为指定JSON的人设
+    /// Persona converting text into the requested JSON
+    ToolUseJson,
+
+    /// 按文本调用指定函数的人设
+    /// Persona calling the given function from text
+    ToolUseFunction,
+
+    /// `SummarizeOld` 替换较早消息的摘要系统消息，占位符为摘要
+    /// Summary system message `SummarizeOld` puts in place of older messages, the placeholder is the summary
+    OlderMessagesSummary,
+
+    /// 请求中的附件清单，占位符为各附件的条目
+    /// Attachment list in requests, the placeholder is the entries of the attachments
+    AttachmentList,
+
+    /// 附件清单中的一个条目，占位符依次为序号、名称、MIME 类型、字节数与附件ID
+    /// One entry of the attachment list, the placeholders are the number, name, MIME type, size in bytes and ID
+    AttachmentEntry,
+
+    /// 忠实度检查中评判模型的指令
+    /// Instruction of the judge model in faithfulness checks
+    FaithfulnessJudge,
+
+    /// 交给忠实度评判模型的材料，占位符依次为编号的参考资料与回答
+    /// Material handed to the faithfulness judge, the placeholders are the numbered sources and the answer
+    FaithfulnessMaterial,
+
+    /// 要求模型只依据资料重答的指令，占位符为没有依据的陈述
+    /// Instruction asking the model to answer again from the sources only, the placeholder is the unsupported claims
+    FaithfulnessRegeneration,
+
+    /// 提取对话状态的指令
+    /// Instruction extracting the dialogue state
+    DialogueStateExtraction,
+
+    /// 交给状态提取的材料，占位符依次为当前状态、助手上一句（`DialogueStateLastReply`，可能为空）与用户的发言
+    /// Material handed to state extraction, the placeholders are the current state, the assistant's last reply
+    /// (`DialogueStateLastReply`, possibly empty) and the user's words
+    DialogueStateMaterial,
+
+    /// 状态提取材料中助手的上一句，占位符为回答
+    /// The assistant's last reply in the state extraction material, the placeholder is the answer
+    DialogueStateLastReply,
+
+    /// 附在请求中的状态说明，占位符为状态
+    /// State notice attached to requests, the placeholder is the state
+    DialogueStateNotice,
+
+    /// 状态说明后尚未填写的字段，占位符为字段名
+    /// Fields still missing after the state notice, the placeholder is the field names
+    DialogueStateMissing,
+
+    /// 要求视觉模型转写图片文字的指令
+    /// Instruction asking the vision model to transcribe the text of the image
+    OcrInstruction,
+
+    /// 文字识别的请求
+    /// Text recognition request
+    OcrRequest,
+
+    /// 指定了语言的文字识别请求，占位符为语言
+    /// Text recognition request with a given language, the placeholder is the language
+    OcrRequestWithLanguage,
+
+    /// 文字识别工具的说明
+    /// Description of the text recognition tool
+    OcrToolDescription,
+
+    /// 文字识别工具的图片参数的说明
+    /// Description of the image argument of the text recognition tool
+    OcrImageArgument,
+
+    /// 设置了文件目录时，文字识别工具的图片参数的说明
+    /// Description of the image argument of the text recognition tool when a files directory is set
+    OcrImageArgumentWithFiles,
+
+    /// 长文档结构化提取中附在每个片段之前的默认指令
+    /// Default instruction put before every chunk in structured extraction over long documents
+    DocumentExtraction,
+
+    /// 事务中只记录了意图的工具调用交给模型的说明
+    /// Note handed to the model for a tool call whose intent was only recorded in a transaction
+    TransactionPending,
+
+    /// 要求视觉模型只依据图片回答的默认指令
+    /// Default instruction asking the vision model to answer from the image alone
+    VisionInstruction,
+
+    /// 图片问答工具的说明
+    /// Description of the image question answering tool
+    VisionToolDescription,
+
+    /// 图片问答工具的图片参数的说明
+    /// Description of the image argument of the image question answering tool
+    VisionImageArgument,
+
+    /// 图片问答工具的问题参数的说明
+    /// Description of the question argument of the image question answering tool
+    VisionQuestionArgument,
+
+    /// 写入草稿本的工具的说明
+    /// Description of the tool writing to the scratchpad
+    ScratchpadWriteDescription,
+
+    /// 读取草稿本的工具的说明
+    /// Description of the tool reading the scratchpad
+    ScratchpadReadDescription,
+
+    /// 写入草稿本时笔记名称参数的说明
+    /// Description of the note key argument when writing to the scratchpad
+    ScratchpadKeyArgument,
+
+    /// 写入草稿本时笔记内容参数的说明
+    /// Description of the note content argument when writing to the scratchpad
+    ScratchpadContentArgument,
+
+    /// 写入草稿本时追加参数的说明
+    /// Description of the append argument when writing to the scratchpad
+    ScratchpadAppendArgument,
+
+    /// 读取草稿本时笔记名称参数的说明
+    /// Description of the note key argument when reading the scratchpad
+    ScratchpadReadKeyArgument,
+
+    /// 生成对话标题的指令，占位符为标题的最大字符数
+    /// Instruction for generating a chat title, the placeholder is the maximum characters of the title
+    TitleGeneration,
+
+    /// 生成对话摘要的指令
+    /// Instruction for summarizing a chat
+    ChatSummary,
+
+    /// 交给廉价模型的对话记录，占位符为对话记录
+    /// Chat transcript handed to a cheap model, the placeholder is the transcript
+    TranscriptMaterial,
+
+    /// 整理交接简报的指令
+    /// Instruction for condensing a chat into a handover brief
+    HandoverInstruction,
+
+    /// 交接简报中目标一节的标题
+    /// Heading of the goal section of a handover brief
+    HandoverGoalHeading,
+
+    /// 交接简报中决定一节的标题
+    /// Heading of the decisions section of a handover brief
+    HandoverDecisionsHeading,
+
+    /// 交接简报中待解决的问题一节的标题
+    /// Heading of the open questions section of a handover brief
+    HandoverOpenQuestionsHeading,
+
+    /// 交接简报中关键事实一节的标题
+    /// Heading of the key facts section of a handover brief
+    HandoverKeyFactsHeading,
+
+    /// 交接简报中待完成的操作一节的标题
+    /// Heading of the pending actions section of a handover brief
+    HandoverPendingActionsHeading,
+
+    /// 为消息的显著性打分的指令
+    /// Instruction for rating the salience of messages
+    SalienceRating,
+
+    /// 待评分的消息，占位符为编号的消息列表
+    /// Messages to rate, the placeholder is the numbered list of messages
+    SalienceMaterial,
+
+    /// 回答未通过规则链检查、要求重新生成时附上的反馈，占位符为原因
+    /// Feedback attached when an answer fails the guardrails and is regenerated, the placeholder is the reason
+    GuardrailRegeneration,
+
+    /// 替换不在允许列表中的链接的文本
+    /// Text replacing links outside the allowlist
+    GuardrailLinkRemoved,
+}
+
+impl PromptKey {
+    /// 未配置时使用的内置提示词
+    /// Built-in prompt used when none is configured
+    pub fn default_prompt(&self) -> &'static str {
+        match self {
+            Self::ToolUseJson => "将输入内容整理为指定的json形式输出",
+            Self::ToolUseFunction => "根据输入的内容调用指定的函数",
+            Self::OlderMessagesSummary => "以下是较早对话的摘要：\n{}",
+            Self::AttachmentList => "对话中有以下附件，内容未直接提供，需要时可将附件ID交给工具读取：\n{}",
+            Self::AttachmentEntry => "#{} {}（{}，{} 字节，ID：{}）",
+            Self::FaithfulnessJudge => "你是严格的事实核查员。把回答拆分为独立的事实陈述，逐条判断能否由参考资料支持，\
+                给出0到1的支持度（1为资料明确支持，0为资料未提及或相矛盾），并列出支持它的资料编号。\
+                只输出JSON：{\"claims\": [{\"claim\": 陈述, \"score\": 支持度, \"sources\": [编号]}]}",
+            Self::FaithfulnessMaterial => "参考资料：\n{}\n\n回答：\n{}",
+            Self::DialogueStateExtraction => "你负责维护一段对话的状态。根据用户最新的发言更新当前状态：只修改用户明确给出或更正的\
+                字段，其余字段保持原值，用户没有提供的字段保持为 null，不要猜测。输出更新后的完整状态。",
+            Self::DialogueStateMaterial => "当前状态：{}\n{}用户：{}",
+            Self::DialogueStateLastReply => "助手上一句：{}\n",
+            Self::DialogueStateNotice => "当前对话状态（由系统维护，回答时以此为准）：\n{}",
+            Self::DialogueStateMissing => "\n尚未填写：{}。需要时请向用户询问。",
+            Self::OcrInstruction => "你是文字识别引擎。逐字转写图片中的全部文字，不翻译、不总结，看不清的内容不要猜测。\
+                按阅读顺序把文字分成块，每块标注类型：heading（标题）、paragraph（段落）、list（列表，每项一行）、\
+                table（表格，用 Markdown 表格）、caption（图注）或 other。只输出 JSON，形如 \
+                {\"blocks\": [{\"kind\": \"paragraph\", \"text\": \"...\"}]}，不加其他内容。",
+            Self::OcrRequest => "识别这张图片中的文字。",
+            Self::OcrRequestWithLanguage => "识别这张图片中的文字，文字的语言：{}。",
+            Self::OcrToolDescription => "识别图片中的文字，按阅读顺序返回全文与文字块，每块带有类型（标题、段落、列表、表格等）",
+            Self::OcrImageArgument => "图片：http(s) 网址或 base64 data URL",
+            Self::OcrImageArgumentWithFiles => "图片：http(s) 网址、base64 data URL，或文件目录中的相对路径",
+            Self::DocumentExtraction => "以下是一份长文档中的一个片段。提取片段中所有符合要求的条目，\
+                不要编造片段中没有的内容；片段中没有符合要求的条目时不输出任何内容。",
+            Self::TransactionPending => "该操作已记录，将在本次任务成功完成后执行",
+            Self::VisionInstruction => "你是视觉助手，替一个看不到图片的模型回答关于图片的问题。只依据图片中可见的内容回答，\
+                引用图中的文字、数字与标注时保持原样；图片中看不出答案时直接说明，不要猜测。回答简洁，不复述问题。",
+            Self::VisionToolDescription => "把图片交给能看图的模型回答问题，用于截图、图表、示意图等自己无法查看的图片",
+            Self::VisionImageArgument => "图片：附件ID、http(s) 网址或 base64 data URL",
+            Self::VisionQuestionArgument => "关于图片的问题，写清需要从图中得到的信息",
+            Self::ScratchpadWriteDescription => {
+                "把中间结果、计划或待办写入草稿本，之后的轮次可以读取；草稿本不会自动出现在上下文中"
+            }
+            Self::ScratchpadReadDescription => "读取之前写入草稿本的笔记",
+            Self::ScratchpadKeyArgument => "笔记的名称，如 plan、findings",
+            Self::ScratchpadContentArgument => "笔记内容，为空字符串时删除该笔记",
+            Self::ScratchpadAppendArgument => "为 true 时追加到已有内容之后，默认替换",
+            Self::ScratchpadReadKeyArgument => "要读取的笔记名称，省略时读取全部笔记",
+            Self::TitleGeneration => {
+                "为下面的对话拟一个标题，概括对话主题，不超过{}个字，使用对话的语言。只输出标题本身，不加引号和标点。"
+            }
+            Self::ChatSummary => {
+                "用两到三句话概括下面的对话：用户想做什么、得到了什么结论、还有什么未解决，使用对话的语言。只输出摘要本身。"
+            }
+            Self::TranscriptMaterial => "对话记录：\n{}",
+            Self::HandoverInstruction => "这段对话将移交给另一个智能体或人工坐席继续处理。整理一份交接简报，让接手者无需\
+                阅读对话记录即可继续：目标、已做出的决定、尚未解决的问题、关键事实，以及已承诺或已发起但尚未完成的操作。\
+                只写对话中明确出现的内容，不要推测；没有的项留空；使用对话的语言。",
+            Self::HandoverGoalHeading => "目标 / Goal",
+            Self::HandoverDecisionsHeading => "决定 / Decisions",
+            Self::HandoverOpenQuestionsHeading => "待解决的问题 / Open questions",
+            Self::HandoverKeyFactsHeading => "关键事实 / Key facts",
+            Self::HandoverPendingActionsHeading => "待完成的操作 / Pending actions",
+            Self::SalienceRating => "为下面每条消息的重要性打分，0 到 100：包含之后仍需记住的事实、决定、约束或用户偏好的\
+                消息得分高，寒暄、客套与重复的内容得分低。按消息顺序只输出一个 JSON 整数数组，不加其他内容。",
+            Self::SalienceMaterial => "消息：\n{}",
+            Self::GuardrailRegeneration => "上一个回答未通过检查，请重新回答：{}",
+            Self::GuardrailLinkRemoved => "[链接已移除]",
+            Self::FaithfulnessRegeneration => {
+                "上一个回答中的以下陈述没有参考资料支持，请只依据参考资料重新回答，资料未提及的内容如实说明：\n{}"
+            }
+        }
+    }
+}
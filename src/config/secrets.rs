@@ -0,0 +1,116 @@
+// 标准库
+use std::sync::Arc;
+
+// 并发和同步原语
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+
+// 错误处理
+use error_stack::{Report, Result};
+use thiserror::Error;
+
+/// 密钥提供者错误枚举
+/// Secret provider error enum
+#[derive(Debug, Error)]
+pub enum SecretError {
+    /// 未注册的密钥提供者
+    /// Secret provider not registered
+    #[error("Secret provider not registered: {0}")]
+    ProviderNotFound(String),
+
+    /// 密钥不存在
+    /// Secret not found
+    #[error("Secret not found: {0}")]
+    SecretNotFound(String),
+
+    /// 密钥引用格式错误，应为 `secret:<provider>:<key>`
+    /// Malformed secret reference, expected `secret:<provider>:<key>`
+    #[error("Malformed secret reference: {0}")]
+    MalformedReference(String),
+
+    /// 后端访问失败
+    /// Backend access failed
+    #[error("Secret backend error: {0}")]
+    BackendError(String),
+}
+
+/// 密钥提供者，在运行时获取API密钥，使其无需明文写入配置文件
+/// Secret provider, fetches API keys at runtime so they never sit in plaintext config files
+///
+/// Vault、SSM 等外部密钥管理服务通过实现该 trait 接入。
+/// External secret managers such as Vault or SSM plug in by implementing this trait.
+pub trait SecretProvider: Send + Sync {
+    /// 获取指定键对应的密钥
+    /// Get the secret stored under the given key
+    fn get_secret(&self, key: &str) -> Result<String, SecretError>;
+}
+
+/// 全局密钥提供者表 - 存储提供者名称到提供者的映射
+/// Global secret provider table - stores mappings from provider name to provider
+pub static SECRET_PROVIDERS: Lazy<DashMap<String, Arc<dyn SecretProvider>>> = Lazy::new(|| {
+    let providers: DashMap<String, Arc<dyn SecretProvider>> = DashMap::new();
+    #[cfg(feature = "keyring")]
+    providers.insert("keyring".to_string(), Arc::new(KeyringProvider::new("rhine")));
+    providers
+});
+
+/// 注册密钥提供者，同名提供者会被替换
+/// Register a secret provider, replacing any provider with the same name
+pub fn register_secret_provider(name: &str, provider: Arc<dyn SecretProvider>) {
+    SECRET_PROVIDERS.insert(name.to_string(), provider);
+}
+
+/// 解析配置值中的密钥引用
+/// Resolve a secret reference in a config value
+///
+/// 形如 `secret:<provider>:<key>` 的值会交给对应提供者解析，其他值原样返回。
+/// Values of the form `secret:<provider>:<key>` are resolved by the named provider, others are returned unchanged.
+pub fn resolve_secret(value: &str) -> Result<String, SecretError> {
+    let Some(reference) = value.strip_prefix("secret:") else {
+        return Ok(value.to_string());
+    };
+
+    let (provider_name, key) = reference
+        .split_once(':')
+        .filter(|(provider, key)| !provider.is_empty() && !key.is_empty())
+        .ok_or_else(|| Report::new(SecretError::MalformedReference(value.to_string())))?;
+
+    let provider = SECRET_PROVIDERS
+        .get(provider_name)
+        .map(|entry| entry.value().clone())
+        .ok_or_else(|| Report::new(SecretError::ProviderNotFound(provider_name.to_string())))?;
+
+    provider.get_secret(key)
+}
+
+/// 系统密钥环提供者（macOS Keychain、Windows Credential Manager、Linux keyutils）
+/// OS keyring provider (macOS Keychain, Windows Credential Manager, Linux keyutils)
+///
+/// 键即密钥环中的用户名，服务名在创建时指定。
+/// The key is the keyring user name, the service name is given on creation.
+#[cfg(feature = "keyring")]
+pub struct KeyringProvider {
+    service: String,
+}
+
+#[cfg(feature = "keyring")]
+impl KeyringProvider {
+    pub fn new(service: &str) -> Self {
+        Self {
+            service: service.to_string(),
+        }
+    }
+}
+
+#[cfg(feature = "keyring")]
+impl SecretProvider for KeyringProvider {
+    fn get_secret(&self, key: &str) -> Result<String, SecretError> {
+        let entry = keyring::Entry::new(&self.service, key)
+            .map_err(|e| Report::new(SecretError::BackendError(e.to_string())))?;
+
+        entry.get_password().map_err(|e| match e {
+            keyring::Error::NoEntry => Report::new(SecretError::SecretNotFound(key.to_string())),
+            other => Report::new(SecretError::BackendError(other.to_string())),
+        })
+    }
+}
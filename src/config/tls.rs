@@ -0,0 +1,99 @@
+// 标准库
+use std::fs;
+
+// HTTP客户端
+use reqwest::{Certificate, Client, ClientBuilder, Identity};
+
+// 序列化
+use serde::Deserialize;
+
+// 错误处理
+use error_stack::{Report, Result, ResultExt};
+
+// 项目内部模块
+use crate::config::ConfigError;
+use crate::config::http::{client_builder, shared_client};
+use crate::config::transport::TransportConfig;
+
+/// 端点TLS配置，用于访问企业内部PKI后的自托管网关
+/// Endpoint TLS configuration, for self-hosted gateways behind corporate PKI
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct TlsConfig {
+    /// 额外信任的根证书路径（PEM，可包含多个证书）
+    /// Path of additional trusted root certificates (PEM, may hold several certificates)
+    #[serde(default)]
+    pub ca_cert: Option<String>,
+
+    /// 客户端证书路径（PEM），用于双向TLS
+    /// Client certificate path (PEM), for mutual TLS
+    #[serde(default)]
+    pub client_cert: Option<String>,
+
+    /// 客户端私钥路径（PKCS#8 PEM），用于双向TLS
+    /// Client private key path (PKCS#8 PEM), for mutual TLS
+    #[serde(default)]
+    pub client_key: Option<String>,
+
+    /// 跳过证书校验，仅用于调试，切勿在生产环境使用
+    /// Skip certificate verification, for debugging only, never use in production
+    #[serde(default)]
+    pub insecure_skip_verify: bool,
+}
+
+/// 按TLS配置与传输层调优构建HTTP客户端
+/// Build an HTTP client from a TLS configuration and transport tuning
+///
+/// # 参数 (Parameters)
+/// * `tls` - TLS配置，为 None 时使用默认设置 / TLS configuration, defaults are used if None
+/// * `transport` - 传输层调优，TLS配置为 None 且调优全为默认值时返回全局共享客户端
+///   - Transport tuning, the global shared client is returned if it is all defaults and `tls` is None
+pub fn build_client(tls: Option<&TlsConfig>, transport: &TransportConfig) -> Result<Client, ConfigError> {
+    if tls.is_none() && transport.is_default() {
+        return Ok(shared_client());
+    }
+
+    let mut builder = transport.apply(client_builder());
+    if let Some(tls) = tls {
+        builder = apply_tls(builder, tls)?;
+    }
+
+    builder.build()
+        .change_context(ConfigError::TlsError("failed to build HTTP client".to_string()))
+}
+
+/// 向客户端构建器加入TLS配置
+/// Add a TLS configuration to a client builder
+fn apply_tls(mut builder: ClientBuilder, tls: &TlsConfig) -> Result<ClientBuilder, ConfigError> {
+    if let Some(path) = &tls.ca_cert {
+        let pem = read_file(path)?;
+        let certs = Certificate::from_pem_bundle(&pem)
+            .change_context_lazy(|| ConfigError::TlsError(format!("invalid CA certificate: {}", path)))?;
+        for cert in certs {
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+
+    match (&tls.client_cert, &tls.client_key) {
+        (Some(cert_path), Some(key_path)) => {
+            let identity = Identity::from_pkcs8_pem(&read_file(cert_path)?, &read_file(key_path)?)
+                .change_context_lazy(|| ConfigError::TlsError(format!("invalid client identity: {}", cert_path)))?;
+            builder = builder.identity(identity);
+        }
+        (None, None) => {}
+        _ => {
+            return Err(Report::new(ConfigError::TlsError(
+                "client_cert and client_key must be set together".to_string(),
+            )));
+        }
+    }
+
+    if tls.insecure_skip_verify {
+        builder = builder.danger_accept_invalid_certs(true);
+    }
+
+    Ok(builder)
+}
+
+fn read_file(path: &str) -> Result<Vec<u8>, ConfigError> {
+    fs::read(path).change_context_lazy(|| ConfigError::TlsError(format!("cannot read file: {}", path)))
+}
@@ -0,0 +1,79 @@
+// 标准库
+use std::time::Duration;
+
+// HTTP客户端
+use reqwest::ClientBuilder;
+
+// 序列化
+use serde::Deserialize;
+
+/// API来源的传输层调优，供高吞吐的智能体集群针对不同的提供商网关调整连接行为
+/// Transport tuning of an API source, for high-throughput agent fleets adjusting connection behavior to different
+/// provider gateways
+///
+/// 未设置的项沿用共享客户端的默认值（经ALPN协商HTTP/2、60秒TCP keep-alive、不限连接超时），全部未设置时
+/// 直接使用共享客户端。
+/// Unset fields keep the defaults of the shared client (HTTP/2 negotiated through ALPN, 60 second TCP keep-alive,
+/// no connect timeout), and the shared client itself is used when nothing is set.
+///
+/// ```toml
+/// [[api_source]]
+/// name = "gateway"
+/// base_url = "http://llm-gateway.internal/v1"
+/// parallelism = 64
+/// transport = { http2 = true, max_in_flight = 32, tcp_keepalive_secs = 30, connect_timeout_secs = 5 }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TransportConfig {
+    /// HTTP/2 开关：为 None 时经ALPN协商，为 true 时直接以HTTP/2连接（适用于明文 h2c 网关），为 false 时只用HTTP/1.1
+    /// HTTP/2 switch: negotiated through ALPN if None, spoken directly if true (for plaintext h2c gateways), and
+    /// HTTP/1.1 only if false
+    pub http2: Option<bool>,
+
+    /// 整个来源同时进行中的请求上限，与并行度取较小者；不是HTTP/2连接的 SETTINGS_MAX_CONCURRENT_STREAMS
+    /// Cap on the requests in flight to the whole source, whichever of it and the parallelism is smaller; this is
+    /// not the SETTINGS_MAX_CONCURRENT_STREAMS of an HTTP/2 connection
+    pub max_in_flight: Option<usize>,
+
+    /// TCP keep-alive 间隔（秒），为 0 时关闭
+    /// TCP keep-alive interval in seconds, 0 turns it off
+    pub tcp_keepalive_secs: Option<u64>,
+
+    /// 建立连接的超时（秒）
+    /// Timeout of establishing a connection, in seconds
+    pub connect_timeout_secs: Option<u64>,
+}
+
+impl TransportConfig {
+    /// 是否全部沿用默认值
+    /// Whether every default is kept
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// 按设置调整客户端构建器
+    /// Adjust a client builder as configured
+    pub fn apply(&self, builder: ClientBuilder) -> ClientBuilder {
+        let builder = match self.http2 {
+            Some(true) => builder.http2_prior_knowledge(),
+            Some(false) => builder.http1_only(),
+            None => builder,
+        };
+        let builder = match self.tcp_keepalive_secs {
+            Some(0) => builder.tcp_keepalive(None),
+            Some(secs) => builder.tcp_keepalive(Duration::from_secs(secs)),
+            None => builder,
+        };
+        match self.connect_timeout_secs {
+            Some(secs) => builder.connect_timeout(Duration::from_secs(secs)),
+            None => builder,
+        }
+    }
+
+    /// 来源实际可用的并发额度
+    /// Concurrency permits the source actually gets
+    pub fn permits(&self, parallelism: usize) -> usize {
+        self.max_in_flight.map_or(parallelism, |cap| cap.min(parallelism))
+    }
+}
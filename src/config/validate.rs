@@ -0,0 +1,307 @@
+// 标准库
+use std::collections::HashMap;
+use std::fmt::Display;
+
+// HTTP客户端
+use reqwest::Url;
+
+// 项目内部模块
+use crate::config::balance::is_healthy;
+use crate::config::{ApiInfo, CurrentConfig, ModelCapability};
+use crate::telemetry::anonymize;
+
+/// 问题严重程度
+/// Issue severity
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Severity {
+    /// 错误，相关请求会失败
+    /// Error, related requests will fail
+    Error,
+
+    /// 警告，配置可用但可能不符合预期
+    /// Warning, configuration works but may not behave as expected
+    Warning,
+}
+
+/// 问题类型
+/// Issue kind
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum IssueKind {
+    /// API密钥为空
+    /// API key is empty
+    MissingApiKey,
+
+    /// 值中仍含有未展开的环境变量引用
+    /// Value still contains an unexpanded environment variable reference
+    UnexpandedEnvVar,
+
+    /// 基础URL无效
+    /// Base URL is invalid
+    InvalidUrl,
+
+    /// 端点在最近一次探测中不可达
+    /// Endpoint was unreachable in the last probe
+    Unreachable,
+
+    /// 同名API的定义互相冲突
+    /// Definitions of an API with the same name conflict
+    DuplicateName,
+
+    /// 别名指向未定义的API
+    /// Alias points to an undefined API
+    UnknownAliasTarget,
+
+    /// 别名与API同名，API将无法直接访问
+    /// Alias has the same name as an API, which becomes unreachable by name
+    AliasShadowsApi,
+
+    /// 并行度为0，请求将永远等待
+    /// Parallelism is 0, requests would wait forever
+    ZeroParallelism,
+
+    /// 基础URL没有对应的并发信号量
+    /// Base URL has no concurrency semaphore
+    MissingSemaphore,
+
+    /// 能力未绑定任何API
+    /// Capability is not bound to any API
+    UnboundCapability,
+
+    /// 能力下所有API权重均为0
+    /// Every API of a capability has weight 0
+    ZeroWeight,
+
+    /// 模型的对话模板无法编译
+    /// Chat template of a model does not compile
+    InvalidChatTemplate,
+
+    /// 遥测匿名化哈希身份标识但盐为空
+    /// Telemetry anonymization hashes identifiers with an empty salt
+    MissingSalt,
+}
+
+/// 配置问题
+/// Configuration issue
+#[derive(Clone, Debug)]
+pub struct ConfigIssue {
+    /// 严重程度
+    /// Severity
+    pub severity: Severity,
+
+    /// 问题类型
+    /// Issue kind
+    pub kind: IssueKind,
+
+    /// 出问题的配置项，如 `api 'gpt-4o'`
+    /// The offending config item, such as `api 'gpt-4o'`
+    pub subject: String,
+
+    /// 问题描述
+    /// Problem description
+    pub message: String,
+
+    /// 修复建议
+    /// How to fix it
+    pub hint: String,
+}
+
+impl ConfigIssue {
+    fn new(severity: Severity, kind: IssueKind, subject: String, message: &str, hint: &str) -> Self {
+        Self {
+            severity,
+            kind,
+            subject,
+            message: message.to_string(),
+            hint: hint.to_string(),
+        }
+    }
+}
+
+impl Display for ConfigIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        write!(f, "[{}] {}: {} (hint: {})", severity, self.subject, self.message, self.hint)
+    }
+}
+
+/// 校验配置
+/// Validate a configuration
+pub fn validate(config: &CurrentConfig) -> Vec<ConfigIssue> {
+    let mut issues = Vec::new();
+    let mut apis: HashMap<String, ApiInfo> = HashMap::new();
+
+    for entry in config.api_info.iter() {
+        let info = entry.value();
+        let subject = format!("api '{}'", info.name);
+
+        if let Some(existing) = apis.get(&info.name) {
+            if existing.model != info.model || existing.base_url != info.base_url || existing.api_key != info.api_key {
+                issues.push(ConfigIssue::new(
+                    Severity::Error,
+                    IssueKind::DuplicateName,
+                    subject,
+                    "defined more than once with different model, base_url or api_key",
+                    "give each distinct endpoint its own API name",
+                ));
+            }
+            continue;
+        }
+        apis.insert(info.name.clone(), info.clone());
+        check_api(config, info, subject, &mut issues);
+    }
+
+    for entry in config.api_source.iter() {
+        if entry.value().parallelism == 0 {
+            issues.push(ConfigIssue::new(
+                Severity::Error,
+                IssueKind::ZeroParallelism,
+                format!("api_source '{}'", entry.key()),
+                "parallelism is 0, requests would wait forever",
+                "set parallelism to at least 1",
+            ));
+        }
+        if entry.value().transport.max_in_flight == Some(0) {
+            issues.push(ConfigIssue::new(
+                Severity::Error,
+                IssueKind::ZeroParallelism,
+                format!("api_source '{}'", entry.key()),
+                "transport.max_in_flight is 0, requests would wait forever",
+                "set max_in_flight to at least 1 or remove it",
+            ));
+        }
+    }
+
+    for entry in config.aliases.iter() {
+        let subject = format!("alias '{}'", entry.key());
+        if !apis.contains_key(entry.value()) {
+            issues.push(ConfigIssue::new(
+                Severity::Error,
+                IssueKind::UnknownAliasTarget,
+                subject,
+                &format!("points to undefined API '{}'", entry.value()),
+                "define the API or fix the alias target",
+            ));
+        } else if apis.contains_key(entry.key()) {
+            issues.push(ConfigIssue::new(
+                Severity::Warning,
+                IssueKind::AliasShadowsApi,
+                subject,
+                "has the same name as an API, which can no longer be addressed directly",
+                "rename the alias or the API",
+            ));
+        }
+    }
+
+    for entry in config.model_metadata.iter() {
+        let Some(template) = &entry.value().chat_template else {
+            continue;
+        };
+        if let Err(report) = template.check() {
+            let reason = report.frames().find_map(|frame| frame.downcast_ref::<String>()).cloned().unwrap_or_default();
+            issues.push(ConfigIssue::new(
+                Severity::Error,
+                IssueKind::InvalidChatTemplate,
+                format!("model '{}'", entry.key()),
+                &format!("chat template does not compile: {}", reason),
+                "fix the Jinja syntax of model_metadata.chat_template.template",
+            ));
+        }
+    }
+
+    for capability in [ModelCapability::Think, ModelCapability::ToolUse, ModelCapability::LongContext] {
+        let weights: Vec<u32> = config.api_info
+            .iter()
+            .filter(|entry| entry.key().1 == capability)
+            .map(|entry| entry.value().weight)
+            .collect();
+        let subject = format!("capability {:?}", capability);
+
+        if weights.is_empty() {
+            let hint = match capability {
+                ModelCapability::ToolUse => "bind an API to it, structured output and tool calls depend on it",
+                _ => "bind an API to it if chats use this capability",
+            };
+            issues.push(ConfigIssue::new(Severity::Warning, IssueKind::UnboundCapability, subject, "has no API bound", hint));
+        } else if weights.iter().all(|weight| *weight == 0) {
+            issues.push(ConfigIssue::new(
+                Severity::Warning,
+                IssueKind::ZeroWeight,
+                subject,
+                "every bound API has weight 0",
+                "give at least one API a positive weight",
+            ));
+        }
+    }
+
+    if anonymize::global().is_some_and(|anonymizer| anonymizer.config().missing_salt()) {
+        issues.push(ConfigIssue::new(
+            Severity::Error,
+            IssueKind::MissingSalt,
+            "anonymize".to_string(),
+            "identifiers are hashed with an empty salt, so their hashes can be reversed by guessing",
+            "set anonymize.salt to a long random value kept private",
+        ));
+    }
+
+    issues
+}
+
+/// 校验单个API
+/// Validate a single API
+fn check_api(config: &CurrentConfig, info: &ApiInfo, subject: String, issues: &mut Vec<ConfigIssue>) {
+    if info.api_key.trim().is_empty() {
+        issues.push(ConfigIssue::new(
+            Severity::Error,
+            IssueKind::MissingApiKey,
+            subject.clone(),
+            "api_key is empty",
+            "set api_key, e.g. api_key = \"env:OPENAI_API_KEY\"",
+        ));
+    }
+
+    for (field, value) in [("api_key", &info.api_key), ("base_url", &info.base_url)] {
+        if value.contains("${") || value.starts_with("env:") {
+            issues.push(ConfigIssue::new(
+                Severity::Error,
+                IssueKind::UnexpandedEnvVar,
+                subject.clone(),
+                &format!("{} contains an unexpanded environment variable reference", field),
+                "load the config with Config::load, which expands ${VAR} and env:VAR",
+            ));
+        }
+    }
+
+    match Url::parse(&info.base_url) {
+        Ok(url) if url.scheme() == "http" || url.scheme() == "https" => {}
+        _ => issues.push(ConfigIssue::new(
+            Severity::Error,
+            IssueKind::InvalidUrl,
+            subject.clone(),
+            &format!("base_url '{}' is not a valid http(s) URL", info.base_url),
+            "use the full endpoint URL, e.g. https://api.openai.com/v1/chat/completions",
+        )),
+    }
+
+    if !config.source_pool().contains_key(&info.base_url) {
+        issues.push(ConfigIssue::new(
+            Severity::Error,
+            IssueKind::MissingSemaphore,
+            subject.clone(),
+            "base_url has no concurrency semaphore",
+            "register the endpoint through Config::add_api_source or Config::set_global",
+        ));
+    }
+
+    if !is_healthy(&info.name) {
+        issues.push(ConfigIssue::new(
+            Severity::Warning,
+            IssueKind::Unreachable,
+            subject,
+            "endpoint was unreachable in the last probe",
+            "check the URL, network access and key, then run Config::probe again",
+        ));
+    }
+}
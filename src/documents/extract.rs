@@ -0,0 +1,80 @@
+// 标准库
+use std::io::{Cursor, Read};
+
+// 错误处理
+use error_stack::{Report, Result};
+
+// 文本处理
+use regex::Regex;
+
+// 项目内部模块
+use crate::chat::chat_base::ChatError;
+
+fn document_error(message: impl Into<String>) -> Report<ChatError> {
+    Report::new(ChatError::DocumentError(message.into()))
+}
+
+/// PDF 每页的文本
+/// Text of each PDF page
+pub(super) fn pdf_pages(bytes: &[u8]) -> Result<Vec<String>, ChatError> {
+    let document = lopdf::Document::load_mem(bytes).map_err(|e| document_error(format!("invalid PDF: {}", e)))?;
+    document
+        .get_pages()
+        .keys()
+        .map(|&page| {
+            document
+                .extract_text(&[page])
+                .map_err(|e| document_error(format!("failed to extract PDF page {}: {}", page, e)))
+        })
+        .collect()
+}
+
+/// DOCX 正文每页的文本，段落之间以换行分隔
+/// Text of each page of the DOCX body, paragraphs separated by line breaks
+pub(super) fn docx_pages(bytes: &[u8]) -> Result<Vec<String>, ChatError> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(bytes)).map_err(|e| document_error(format!("invalid DOCX: {}", e)))?;
+    let mut xml = String::new();
+    archive
+        .by_name("word/document.xml")
+        .map_err(|e| document_error(format!("DOCX has no document body: {}", e)))?
+        .read_to_string(&mut xml)
+        .map_err(|e| document_error(format!("failed to read DOCX body: {}", e)))?;
+    Ok(docx_text_pages(&xml))
+}
+
+/// 从 `word/document.xml` 提取文本，按分页符分页
+/// Extract the text of `word/document.xml`, split into pages at page breaks
+pub(super) fn docx_text_pages(xml: &str) -> Vec<String> {
+    let token = Regex::new(
+        r#"(?s)<w:t(?:\s[^>]*)?>(.*?)</w:t>|<w:tab/>|<w:br\s+w:type="page"\s*/>|<w:lastRenderedPageBreak/>|</w:p>"#,
+    )
+    .unwrap();
+
+    let mut pages = vec![String::new()];
+    for capture in token.captures_iter(xml) {
+        let page = pages.last_mut().unwrap();
+        match (capture.get(1), &capture[0]) {
+            (Some(text), _) => page.push_str(&unescape(text.as_str())),
+            (None, "<w:tab/>") => page.push('\t'),
+            (None, "</w:p>") => page.push('\n'),
+            // 显式分页后 Word 通常还会写入渲染分页标记，空白页上的分页不再计数
+            // Word usually writes a rendered page break after an explicit one, breaks on a blank page are not counted
+            (None, _) if page.trim().is_empty() => {}
+            (None, _) => pages.push(String::new()),
+        }
+    }
+
+    let pages: Vec<String> = pages.into_iter().map(|page| page.trim().to_string()).collect();
+    match pages.iter().rposition(|page| !page.is_empty()) {
+        Some(last) => pages[..=last].to_vec(),
+        None => Vec::new(),
+    }
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
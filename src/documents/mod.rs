@@ -0,0 +1,312 @@
+//! 文档接入：提取文本（PDF 与 DOCX 需要 `documents` 特性）、按页切分为片段，直接作为上下文消息加入对话，
+//! 或交给检索增强的接入流程；每个片段保留来源与页码，供引用使用
+//! Document ingestion: extract text (PDF and DOCX require the `documents` feature), split it into chunks page by
+//! page, and add them to a chat as context messages directly or hand them to the retrieval ingestion pipeline; each
+//! chunk keeps its source and page number for citations
+
+#[cfg(feature = "documents")]
+mod extract;
+
+// 标准库
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+// 错误处理
+use error_stack::{Report, Result};
+
+// 异步
+use futures::future::BoxFuture;
+
+// 数据序列化
+use serde::{Deserialize, Serialize};
+
+// 项目内部模块
+use crate::chat::chat_base::ChatError;
+use crate::chat::retriever::{Retriever, Source};
+
+/// 按页保存文本的文档，第 `i` 项为第 `i + 1` 页
+/// Document with its text kept per page, item `i` is page `i + 1`
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Document {
+    /// 来源，通常为文件名，出现在引用中
+    /// Source, usually the file name, shown in citations
+    pub source: String,
+
+    pub pages: Vec<String>,
+}
+
+impl Document {
+    /// 没有分页的纯文本，视为一页
+    /// Plain text without pages, treated as one page
+    pub fn from_text(source: &str, text: &str) -> Self {
+        Self::from_pages(source, vec![text.to_string()])
+    }
+
+    pub fn from_pages(source: &str, pages: Vec<String>) -> Self {
+        Self {
+            source: source.to_string(),
+            pages,
+        }
+    }
+
+    /// 从 PDF 内容逐页提取文本
+    /// Extract the text of PDF content page by page
+    #[cfg(feature = "documents")]
+    pub fn from_pdf(source: &str, bytes: &[u8]) -> Result<Self, ChatError> {
+        Ok(Self::from_pages(source, extract::pdf_pages(bytes)?))
+    }
+
+    /// 从 DOCX 内容提取文本，按显式分页符与渲染分页标记分页
+    /// Extract the text of DOCX content, split into pages at explicit and rendered page breaks
+    #[cfg(feature = "documents")]
+    pub fn from_docx(source: &str, bytes: &[u8]) -> Result<Self, ChatError> {
+        Ok(Self::from_pages(source, extract::docx_pages(bytes)?))
+    }
+
+    /// 按扩展名读取文件：`pdf` 与 `docx` 需要 `documents` 特性，其他扩展名按 UTF-8 文本读取
+    /// Read a file by its extension: `pdf` and `docx` require the `documents` feature, other extensions are read as
+    /// UTF-8 text
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ChatError> {
+        let path = path.as_ref();
+        let source = path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+        let bytes = std::fs::read(path)
+            .map_err(|e| Report::new(ChatError::DocumentError(format!("{}: {}", path.display(), e))))?;
+        let extension = path.extension().map(|extension| extension.to_string_lossy().to_lowercase());
+
+        match extension.as_deref() {
+            #[cfg(feature = "documents")]
+            Some("pdf") => Self::from_pdf(&source, &bytes),
+            #[cfg(feature = "documents")]
+            Some("docx") => Self::from_docx(&source, &bytes),
+            #[cfg(not(feature = "documents"))]
+            Some(extension @ ("pdf" | "docx")) => Err(Report::new(ChatError::DocumentError(format!(
+                "reading .{} files requires the `documents` feature",
+                extension
+            )))),
+            _ => String::from_utf8(bytes)
+                .map(|text| Self::from_text(&source, &text))
+                .map_err(|_| Report::new(ChatError::DocumentError(format!("{} is not UTF-8 text", path.display())))),
+        }
+    }
+}
+
+/// 文档片段，带有来源与页码
+/// Document chunk, with its source and page number
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DocumentChunk {
+    pub source: String,
+
+    /// 从 1 开始的页码
+    /// Page number starting at 1
+    pub page: usize,
+
+    /// 片段在文档中的序号
+    /// Index of the chunk within the document
+    pub index: usize,
+
+    pub text: String,
+}
+
+impl DocumentChunk {
+    /// 引用标记，如 `report.pdf, p. 3`
+    /// Citation label, such as `report.pdf, p. 3`
+    pub fn citation(&self) -> String {
+        format!("{}, p. {}", self.source, self.page)
+    }
+
+    /// 带有引用标记的片段文本，作为上下文发送
+    /// Chunk text with its citation label, sent as context
+    pub fn to_context(&self) -> String {
+        format!("[{}]\n{}", self.citation(), self.text)
+    }
+
+    /// 作为检索来源，ID由文件与片段序号组成
+    /// As a retrieval source, its ID made of the file and the chunk index
+    pub fn into_source(self) -> Source {
+        Source {
+            id: format!("{}#{}", self.source, self.index),
+            origin: Some(self.citation()),
+            text: self.text,
+        }
+    }
+}
+
+/// 按字符数切分文档，优先在段落、句子处断开，片段不跨页以保证页码准确
+/// Split documents by character count, preferring paragraph and sentence boundaries; chunks never span pages so
+/// their page numbers stay exact
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Chunker {
+    pub max_chars: usize,
+
+    /// 相邻片段重叠的字符数
+    /// Characters shared by adjacent chunks
+    pub overlap: usize,
+}
+
+impl Default for Chunker {
+    fn default() -> Self {
+        Self {
+            max_chars: 2000,
+            overlap: 200,
+        }
+    }
+}
+
+impl Chunker {
+    pub fn new(max_chars: usize) -> Self {
+        Self {
+            max_chars: max_chars.max(1),
+            overlap: 0,
+        }
+    }
+
+    pub fn overlap(mut self, overlap: usize) -> Self {
+        self.overlap = overlap.min(self.max_chars / 2);
+        self
+    }
+
+    pub fn chunk(&self, document: &Document) -> Vec<DocumentChunk> {
+        let mut chunks = Vec::new();
+        for (page, text) in document.pages.iter().enumerate() {
+            for text in self.split(text) {
+                chunks.push(DocumentChunk {
+                    source: document.source.clone(),
+                    page: page + 1,
+                    index: chunks.len(),
+                    text,
+                });
+            }
+        }
+        chunks
+    }
+
+    fn split(&self, text: &str) -> Vec<String> {
+        let chars: Vec<char> = text.trim().chars().collect();
+        let mut pieces = Vec::new();
+        let mut start = 0;
+        while start < chars.len() {
+            let mut end = (start + self.max_chars).min(chars.len());
+            if end < chars.len() {
+                // 在后半段中寻找最后一个段落或句子边界
+                // Look for the last paragraph or sentence boundary in the second half
+                let window = &chars[start + self.max_chars / 2..end];
+                let boundary = window
+                    .windows(2)
+                    .rposition(|pair| pair == ['\n', '\n'])
+                    .map(|position| position + 2)
+                    .or_else(|| window.iter().rposition(|c| matches!(c, '。' | '！' | '？' | '.' | '!' | '?' | '\n')).map(|position| position + 1));
+                if let Some(boundary) = boundary {
+                    end = start + self.max_chars / 2 + boundary;
+                }
+            }
+            let piece: String = chars[start..end].iter().collect::<String>().trim().to_string();
+            if !piece.is_empty() {
+                pieces.push(piece);
+            }
+            if end == chars.len() {
+                break;
+            }
+            start = end.saturating_sub(self.overlap).max(start + 1);
+        }
+        pieces
+    }
+}
+
+/// 检索增强的接入端，接收切分好的片段（如写入向量库）
+/// Ingestion end of retrieval augmentation, receiving the chunks (for example writing them to a vector store)
+pub trait Ingestor: Send + Sync {
+    fn ingest<'a>(&'a self, chunks: Vec<DocumentChunk>) -> BoxFuture<'a, Result<(), ChatError>>;
+}
+
+/// 切分文档并交给接入端，返回片段数
+/// Chunk a document and hand it to an ingestor, returning the number of chunks
+pub async fn ingest(document: &Document, chunker: &Chunker, ingestor: &dyn Ingestor) -> Result<usize, ChatError> {
+    let chunks = chunker.chunk(document);
+    let count = chunks.len();
+    ingestor.ingest(chunks).await?;
+    Ok(count)
+}
+
+/// 进程内的片段索引，按词重合度检索，适合小规模文档与测试；检索结果带有引用标记
+/// In-process chunk index retrieving by term overlap, suited to small document sets and tests; the results carry
+/// citation labels
+#[derive(Clone, Debug, Default)]
+pub struct ChunkIndex {
+    chunks: Arc<RwLock<Vec<DocumentChunk>>>,
+    top_k: usize,
+}
+
+impl ChunkIndex {
+    /// 每次检索返回最多 `top_k` 个片段
+    /// Each retrieval returns at most `top_k` chunks
+    pub fn new(top_k: usize) -> Self {
+        Self {
+            chunks: Arc::default(),
+            top_k,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.chunks.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 与查询词重合度最高的片段
+    /// Chunks overlapping the query terms the most
+    pub fn search(&self, query: &str) -> Vec<DocumentChunk> {
+        let terms = terms(query);
+        let chunks = self.chunks.read().unwrap();
+        let mut scored: Vec<(usize, &DocumentChunk)> = chunks
+            .iter()
+            .map(|chunk| {
+                let text = chunk.text.to_lowercase();
+                (terms.iter().filter(|term| text.contains(term.as_str())).count(), chunk)
+            })
+            .filter(|(score, _)| *score > 0)
+            .collect();
+        scored.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.index.cmp(&b.1.index)));
+        scored.into_iter().take(self.top_k).map(|(_, chunk)| chunk.clone()).collect()
+    }
+}
+
+/// 查询中的检索词：小写的拉丁词与单个中日韩字符
+/// Search terms of a query: lowercase Latin words and single CJK characters
+fn terms(query: &str) -> Vec<String> {
+    let mut terms = Vec::new();
+    for word in query.to_lowercase().split(|c: char| !c.is_alphanumeric()) {
+        if word.is_ascii() {
+            if word.len() > 2 {
+                terms.push(word.to_string());
+            }
+        } else {
+            terms.extend(word.chars().filter(|c| !c.is_ascii()).map(String::from));
+        }
+    }
+    terms.sort();
+    terms.dedup();
+    terms
+}
+
+impl Ingestor for ChunkIndex {
+    fn ingest<'a>(&'a self, chunks: Vec<DocumentChunk>) -> BoxFuture<'a, Result<(), ChatError>> {
+        self.chunks.write().unwrap().extend(chunks);
+        Box::pin(async { Ok(()) })
+    }
+}
+
+impl Retriever for ChunkIndex {
+    fn retrieve<'a>(&'a self, query: &'a str) -> BoxFuture<'a, Vec<String>> {
+        let documents = self.search(query).iter().map(DocumentChunk::to_context).collect();
+        Box::pin(async move { documents })
+    }
+
+    /// 片段ID形如 `handbook.pdf#2`，出处为引用标记
+    /// Chunk IDs look like `handbook.pdf#2`, the origin is the citation label
+    fn retrieve_sources<'a>(&'a self, query: &'a str) -> BoxFuture<'a, Vec<Source>> {
+        let sources = self.search(query).into_iter().map(DocumentChunk::into_source).collect();
+        Box::pin(async move { sources })
+    }
+}
@@ -0,0 +1,516 @@
+//! 统一的错误分类
+//! Unified error taxonomy
+//!
+//! 各模块仍定义自己的错误枚举，但都实现 `RhineError`，提供稳定的错误码、可重试判断和HTTP状态码；
+//! `ReportExt` 在整个错误报告链上查询这些信息，并读取请求失败时附加的提供商信息，
+//! 调用方因此可以按错误类型分支处理，而不必匹配错误文本。
+//! Each module still defines its own error enum, but every one implements `RhineError`, exposing a stable error
+//! code, retryability and HTTP status; `ReportExt` answers the same questions over the whole report chain and reads
+//! the provider attached when a request fails, so callers can branch on errors programmatically instead of
+//! matching error text.
+
+// 标准库
+use std::fmt::{Display, Formatter};
+
+// 错误处理
+use error_stack::{Context, Report};
+
+// 项目内部模块
+use crate::chat::analytics::AnalyticsError;
+use crate::chat::chat_base::ChatError;
+use crate::chat::chat_single::ToolCallError;
+#[cfg(feature = "encryption")]
+use crate::chat::encryption::EncryptionError;
+use crate::chat::message::MessageError;
+use crate::chat::store::StoreError;
+use crate::config::{ConfigError, ModelCapability};
+use crate::config::secrets::SecretError;
+use crate::eval::embedding_cache::EmbeddingCacheError;
+use crate::prompt::assembler::OutputDescriptionError;
+use crate::prompt::loader::PromptLoadError;
+use crate::prompt::model::PromptModelError;
+use crate::scheduler::SchedulerError;
+use crate::schema::tool_schema::ChatToolSchemaError;
+#[cfg(feature = "server")]
+use crate::server::resume::ResumeError;
+use crate::telemetry::audit::AuditError;
+use crate::telemetry::exporter::ExportError;
+use crate::telemetry::ledger::LedgerError;
+use crate::utils::common::expand_env::ExpandEnvError;
+use crate::utils::common::load_toml::LoadTomlError;
+use crate::utils::common::redact::redact;
+
+/// 所有错误枚举共同实现的分类接口
+/// Classification implemented by every error enum
+pub trait RhineError: Context {
+    /// 稳定的错误码，形如 `chat.http_error`，不随错误文本变化
+    /// Stable error code such as `chat.http_error`, independent of the error text
+    fn code(&self) -> &'static str;
+
+    /// 重试同一请求是否可能成功
+    /// Whether retrying the same request may succeed
+    fn is_retryable(&self) -> bool {
+        false
+    }
+
+    /// 提供商返回的HTTP状态码
+    /// HTTP status code returned by the provider
+    fn status(&self) -> Option<u16> {
+        None
+    }
+}
+
+/// 请求失败时附加在错误报告上的来源信息
+/// Provenance attached to a report when a request fails
+///
+/// 负载均衡和故障转移下同一能力可能落到不同的API，来源信息指明具体是哪个配置条目、模型、地址和第几次尝试。
+/// With load balancing and failover one capability may land on different APIs, the provenance names the exact
+/// config entry, model, endpoint and attempt.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProviderInfo {
+    /// 提供商，如 `openai`、`anthropic`
+    /// Provider, such as `openai` or `anthropic`
+    pub provider: String,
+
+    /// 配置中的API名称
+    /// API name in the configuration
+    pub api_name: String,
+
+    pub model: String,
+
+    pub capability: Option<ModelCapability>,
+
+    /// 去除凭据和查询参数后的接口地址
+    /// Endpoint URL with credentials and query parameters removed
+    pub base_url: String,
+
+    /// 尝试序号，从 1 开始
+    /// Attempt number, starting at 1
+    pub attempt: u32,
+}
+
+impl Display for ProviderInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Provider: {} (api: {}, model: {}, endpoint: {}, attempt: {})",
+            self.provider, self.api_name, self.model, self.base_url, self.attempt
+        )?;
+        if let Some(capability) = &self.capability {
+            write!(f, ", capability: {:?}", capability)?;
+        }
+        Ok(())
+    }
+}
+
+/// 请求失败时提供商返回的错误内容，已清除敏感信息
+/// Error body returned by the provider when a request fails, with sensitive content redacted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ErrorBody(pub String);
+
+impl Display for ErrorBody {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Response body: {}", self.0)
+    }
+}
+
+/// 去除URL中的用户信息、查询参数和片段，无法解析时整体清除敏感内容
+/// Strip the user info, query and fragment from a URL, redacting the whole text if it cannot be parsed
+pub fn sanitize_url(url: &str) -> String {
+    match reqwest::Url::parse(url) {
+        Ok(mut url) => {
+            let _ = url.set_username("");
+            let _ = url.set_password(None);
+            url.set_query(None);
+            url.set_fragment(None);
+            url.to_string()
+        }
+        Err(_) => redact(url),
+    }
+}
+
+/// 在整个错误报告链上查询分类信息
+/// Query classification over the whole report chain
+pub trait ReportExt {
+    /// 最外层错误的错误码
+    /// Error code of the outermost error
+    fn code(&self) -> &'static str;
+
+    /// 报告链中任一错误可重试时为 true
+    /// True if any error in the chain is retryable
+    fn is_retryable(&self) -> bool;
+
+    /// 报告链中最近的HTTP状态码
+    /// The nearest HTTP status code in the chain
+    fn status(&self) -> Option<u16>;
+
+    /// 请求失败时附加的提供商
+    /// Provider attached when the request failed
+    fn provider(&self) -> Option<&str>;
+
+    /// 请求失败时附加的完整来源信息
+    /// Full provenance attached when the request failed
+    fn provider_info(&self) -> Option<&ProviderInfo>;
+
+    /// 提供商返回的错误内容
+    /// Error body returned by the provider
+    fn error_body(&self) -> Option<&str>;
+
+    /// 提供商是否以参数错误拒绝了请求中的某个参数，如不支持 `response_format` 或 `tools`
+    /// Whether the provider rejected a request parameter as invalid, such as an unsupported `response_format` or
+    /// `tools`
+    fn rejects_parameter(&self, parameter: &str) -> bool;
+
+    /// 提供商是否因凭据无效或额度用尽拒绝了请求（401、402 或错误体带有凭据与额度的错误码），换用其他密钥可能成功；
+    /// 403 多为内容或地区限制，不算在内
+    /// Whether the provider rejected the request because the credential is invalid or out of quota (401, 402 or an
+    /// error body carrying a credential or quota error code), another key may succeed; 403 usually means a content
+    /// or region restriction and does not count
+    fn rejects_credentials(&self) -> bool;
+}
+
+impl<C: RhineError> ReportExt for Report<C> {
+    fn code(&self) -> &'static str {
+        self.current_context().code()
+    }
+
+    fn is_retryable(&self) -> bool {
+        self.current_context().is_retryable()
+            || self.downcast_ref::<ChatError>().is_some_and(RhineError::is_retryable)
+            || self.downcast_ref::<ExportError>().is_some_and(RhineError::is_retryable)
+    }
+
+    fn status(&self) -> Option<u16> {
+        self.current_context()
+            .status()
+            .or_else(|| self.downcast_ref::<ChatError>().and_then(RhineError::status))
+            .or_else(|| self.downcast_ref::<ExportError>().and_then(RhineError::status))
+    }
+
+    fn provider(&self) -> Option<&str> {
+        self.provider_info().map(|info| info.provider.as_str())
+    }
+
+    fn provider_info(&self) -> Option<&ProviderInfo> {
+        self.downcast_ref::<ProviderInfo>()
+    }
+
+    fn error_body(&self) -> Option<&str> {
+        self.downcast_ref::<ErrorBody>().map(|body| body.0.as_str())
+    }
+
+    fn rejects_parameter(&self, parameter: &str) -> bool {
+        matches!(self.status(), Some(400 | 422))
+            && self.error_body().is_some_and(|body| body.to_lowercase().contains(&parameter.to_lowercase()))
+    }
+
+    fn rejects_credentials(&self) -> bool {
+        matches!(self.status(), Some(401 | 402))
+            || (self.status().is_some()
+                && self
+                    .error_body()
+                    .is_some_and(|body| CREDENTIAL_ERROR_CODES.iter().any(|code| body.contains(code))))
+    }
+}
+
+/// 错误体中表示凭据无效或额度用尽的错误码
+/// Error codes in error bodies meaning the credential is invalid or out of quota
+const CREDENTIAL_ERROR_CODES: [&str; 3] = ["insufficient_quota", "invalid_api_key", "billing_hard_limit_reached"];
+
+/// 按HTTP状态码判断是否可重试：超时、限流和服务端错误可重试，0 表示连接中断
+/// Retryability by HTTP status: timeouts, rate limits and server errors are retryable, 0 means the connection broke
+pub fn is_retryable_status(status: u16) -> bool {
+    matches!(status, 0 | 408 | 409 | 425 | 429 | 500..=599)
+}
+
+impl RhineError for ChatError {
+    fn code(&self) -> &'static str {
+        match self {
+            Self::AssembleOutputDescriptionError => "chat.assemble_output_description",
+            Self::HttpError(_) => "chat.http_error",
+            Self::TimeoutError => "chat.timeout",
+            Self::ParseResponseError => "chat.parse_response",
+            Self::MissingUsageData => "chat.missing_usage",
+            Self::GetJsonError => "chat.get_json",
+            Self::GetFunctionError => "chat.get_function",
+            Self::SessionError => "chat.session",
+            Self::NoCharacterPrompts => "chat.no_character_prompts",
+            Self::UndefinedCharacter(_) => "chat.undefined_character",
+            Self::NoCharacterSelected => "chat.no_character_selected",
+            Self::PermitError => "chat.permit",
+            Self::InvalidConfig => "chat.invalid_config",
+            Self::Cancelled => "chat.cancelled",
+            Self::Moderated(_) => "chat.moderated",
+            Self::GuardrailBlocked(_) => "chat.guardrail_blocked",
+            Self::DocumentError(_) => "chat.document",
+            Self::ImageError(_) => "chat.image",
+            Self::UnknownCheckpoint(_) => "chat.unknown_checkpoint",
+            Self::AttachmentError(_) => "chat.attachment",
+            Self::StreamFailed(_) => "chat.stream_failed",
+            Self::SubscriberLagged => "chat.subscriber_lagged",
+            Self::TenantBudgetExceeded(_) => "chat.tenant_budget_exceeded",
+            Self::TenantRateLimited(_) => "chat.tenant_rate_limited",
+            Self::TenantForbidden(_) => "chat.tenant_forbidden",
+            Self::ToolSourceError(_) => "chat.tool_source",
+            Self::PersonaError(_) => "chat.persona",
+            Self::AgentSpecError(_) => "chat.agent_spec",
+            Self::ShuttingDown => "chat.shutting_down",
+            Self::PostProcessError(_) => "chat.post_process",
+            Self::ReloadError(_) => "chat.reload",
+            Self::ContextError(_) => "chat.context",
+            Self::UnknownError => "chat.network",
+        }
+    }
+
+    fn is_retryable(&self) -> bool {
+        match self {
+            Self::HttpError(status) => is_retryable_status(*status),
+            Self::TimeoutError | Self::UnknownError => true,
+            _ => false,
+        }
+    }
+
+    fn status(&self) -> Option<u16> {
+        match self {
+            Self::HttpError(status) if *status != 0 => Some(*status),
+            _ => None,
+        }
+    }
+}
+
+impl RhineError for ToolCallError {
+    fn code(&self) -> &'static str {
+        match self {
+            Self::ParseFunctionCall => "tool.parse_function_call",
+            Self::FunctionNotFound(_) => "tool.function_not_found",
+            Self::FunctionExecution(_) => "tool.function_execution",
+            Self::SerializeResult => "tool.serialize_result",
+            Self::DeserializeArguments(_) => "tool.deserialize_arguments",
+            Self::GetJson(_) => "tool.get_json",
+            Self::ExtractFunctionCall(_) => "tool.extract_function_call",
+            Self::MissingField(_) => "tool.missing_field",
+            Self::RecordToolCalls => "tool.record_tool_calls",
+            Self::TransactionAborted(_) => "tool.transaction_aborted",
+        }
+    }
+}
+
+impl RhineError for ChatToolSchemaError {
+    fn code(&self) -> &'static str {
+        match self {
+            Self::AssembleToolPrompt => "tool_schema.assemble_tool_prompt",
+            Self::MissingFunctionField => "tool_schema.missing_function",
+            Self::MissingFunctionName => "tool_schema.missing_function_name",
+            Self::MissingFunctionDescription => "tool_schema.missing_function_description",
+            Self::MissingFunctionParameters => "tool_schema.missing_function_parameters",
+            Self::MissingFunctionProperties => "tool_schema.missing_function_properties",
+            Self::ParamsParseError(..) => "tool_schema.params_parse",
+            Self::ResultParseError(_) => "tool_schema.result_parse",
+            Self::FunctionCallError => "tool_schema.function_call",
+            Self::UnknownTool(_) => "tool_schema.unknown_tool",
+        }
+    }
+}
+
+impl RhineError for MessageError {
+    fn code(&self) -> &'static str {
+        match self {
+            Self::InvalidPath => "message.invalid_path",
+            Self::InvalidIndex(..) => "message.invalid_index",
+            Self::UnsupportedOperation(_) => "message.unsupported_operation",
+            Self::UnknownId(_) => "message.unknown_id",
+            Self::InvalidApiMessage(..) => "message.invalid_api_message",
+        }
+    }
+}
+
+impl RhineError for ConfigError {
+    fn code(&self) -> &'static str {
+        match self {
+            Self::ConfigLockFailure => "config.lock_failure",
+            Self::ConfigNotInitialized => "config.not_initialized",
+            Self::ApiInfoNotFound => "config.api_info_not_found",
+            Self::NoEnabledApi => "config.no_enabled_api",
+            Self::LoadError(_) => "config.load",
+            Self::EnvExpansionError(_) => "config.env_expansion",
+            Self::SecretError(_) => "config.secret",
+            Self::UnknownApi(_) => "config.unknown_api",
+            Self::UnknownSource(_) => "config.unknown_source",
+            Self::TlsError(_) => "config.tls",
+            Self::DuplicateName(_) => "config.duplicate_name",
+            Self::UnknownProfile(_) => "config.unknown_profile",
+            Self::ProfileCycle(_) => "config.profile_cycle",
+            Self::MissingSalt(_) => "config.missing_salt",
+        }
+    }
+}
+
+impl RhineError for SecretError {
+    fn code(&self) -> &'static str {
+        match self {
+            Self::ProviderNotFound(_) => "secret.provider_not_found",
+            Self::SecretNotFound(_) => "secret.not_found",
+            Self::MalformedReference(_) => "secret.malformed_reference",
+            Self::BackendError(_) => "secret.backend",
+        }
+    }
+
+    fn is_retryable(&self) -> bool {
+        matches!(self, Self::BackendError(_))
+    }
+}
+
+impl RhineError for ExportError {
+    fn code(&self) -> &'static str {
+        match self {
+            Self::NetworkError(_) => "export.network",
+            Self::HttpError(_) => "export.http_error",
+        }
+    }
+
+    fn is_retryable(&self) -> bool {
+        match self {
+            Self::NetworkError(_) => true,
+            Self::HttpError(status) => is_retryable_status(*status),
+        }
+    }
+
+    fn status(&self) -> Option<u16> {
+        match self {
+            Self::HttpError(status) => Some(*status),
+            Self::NetworkError(_) => None,
+        }
+    }
+}
+
+impl RhineError for AuditError {
+    fn code(&self) -> &'static str {
+        match self {
+            Self::IoError(_) => "audit.io",
+        }
+    }
+}
+
+#[cfg(feature = "encryption")]
+impl RhineError for EncryptionError {
+    fn code(&self) -> &'static str {
+        match self {
+            Self::KeyUnavailable(_) => "encryption.key_unavailable",
+            Self::EncryptFailed => "encryption.encrypt_failed",
+            Self::DecryptFailed(_) => "encryption.decrypt_failed",
+            Self::IoError(_) => "encryption.io",
+        }
+    }
+}
+
+impl RhineError for StoreError {
+    fn code(&self) -> &'static str {
+        match self {
+            Self::Conflict { .. } => "store.conflict",
+            Self::Locked(_) => "store.locked",
+            Self::IoError(_) => "store.io",
+            Self::Unsupported(_) => "store.unsupported",
+            Self::CodecError(_) => "store.codec",
+        }
+    }
+}
+
+#[cfg(feature = "server")]
+impl RhineError for ResumeError {
+    fn code(&self) -> &'static str {
+        match self {
+            Self::InvalidToken => "server.invalid_resume_token",
+            Self::Expired => "server.resume_token_expired",
+            Self::UnknownConversation(_) => "server.unknown_conversation",
+            Self::Disabled => "server.resumption_disabled",
+            Self::Store => "server.store",
+        }
+    }
+}
+
+impl RhineError for EmbeddingCacheError {
+    fn code(&self) -> &'static str {
+        match self {
+            Self::IoError(_) => "embedding_cache.io",
+        }
+    }
+}
+
+impl RhineError for LedgerError {
+    fn code(&self) -> &'static str {
+        match self {
+            Self::IoError(_) => "ledger.io",
+        }
+    }
+}
+
+impl RhineError for AnalyticsError {
+    fn code(&self) -> &'static str {
+        match self {
+            Self::Store => "analytics.store",
+            Self::Ledger => "analytics.ledger",
+            Self::NoLedger => "analytics.no_ledger",
+        }
+    }
+}
+
+impl RhineError for SchedulerError {
+    fn code(&self) -> &'static str {
+        match self {
+            Self::InvalidSchedule(_) => "scheduler.invalid_schedule",
+            Self::DuplicateTask(_) => "scheduler.duplicate_task",
+            Self::UnknownTask(_) => "scheduler.unknown_task",
+        }
+    }
+}
+
+impl RhineError for PromptLoadError {
+    fn code(&self) -> &'static str {
+        match self {
+            Self::ConfigLoadError => "prompt.config_load",
+            Self::TemplateLoadError => "prompt.template_load",
+            Self::ContentLoadError(_) => "prompt.content_load",
+        }
+    }
+}
+
+impl RhineError for PromptModelError {
+    fn code(&self) -> &'static str {
+        match self {
+            Self::LoadError => "prompt.load",
+            Self::InitError => "prompt.init",
+            Self::CharacterPromptNotFound(_) => "prompt.character_not_found",
+            Self::StagePromptNotFound(_) => "prompt.stage_not_found",
+        }
+    }
+}
+
+impl RhineError for OutputDescriptionError {
+    fn code(&self) -> &'static str {
+        match self {
+            Self::MissingJsonSchemaField => "output_description.missing_json_schema",
+            Self::MissingNameField => "output_description.missing_name",
+            Self::MissingDescriptionField => "output_description.missing_description",
+            Self::MissingSchemaField => "output_description.missing_schema",
+            Self::MissingPropertiesField => "output_description.missing_properties",
+        }
+    }
+}
+
+impl RhineError for LoadTomlError {
+    fn code(&self) -> &'static str {
+        match self {
+            Self::ReadError => "toml.read",
+            Self::ParseError => "toml.parse",
+        }
+    }
+}
+
+impl RhineError for ExpandEnvError {
+    fn code(&self) -> &'static str {
+        match self {
+            Self::VarNotFound(_) => "env.var_not_found",
+            Self::Unterminated(_) => "env.unterminated",
+        }
+    }
+}
@@ -0,0 +1,199 @@
+// 标准库
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// 错误处理
+use error_stack::{Report, Result};
+use thiserror::Error;
+
+// 异步
+use futures::future::BoxFuture;
+
+// 并发和同步原语
+use dashmap::DashMap;
+
+// 内容哈希
+use sha2::{Digest, Sha256};
+
+// 观测诊断
+use metrics::counter;
+use tracing::warn;
+
+// 项目内部模块
+use crate::chat::chat_base::ChatError;
+use crate::eval::scorer::Embedder;
+use crate::utils::common::file_name::encode_file_name;
+use crate::telemetry::meter::{EMBEDDING_CACHE_HITS_TOTAL, EMBEDDING_CACHE_MISSES_TOTAL};
+
+/// 向量缓存错误枚举
+/// Embedding cache error enum
+#[derive(Debug, Error)]
+pub enum EmbeddingCacheError {
+    /// 缓存读写失败
+    /// Reading or writing the cache failed
+    #[error("Embedding cache IO error: {0}")]
+    IoError(String),
+}
+
+/// 文本内容的哈希（SHA-256 十六进制），作为缓存键
+/// Hash of a text (SHA-256 in hex), used as the cache key
+pub fn content_hash(text: &str) -> String {
+    Sha256::digest(text.as_bytes()).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// 向量缓存的存储后端，按模型与内容哈希存取向量
+/// Storage backend of the embedding cache, vectors are stored by model and content hash
+pub trait EmbeddingCache: Send + Sync {
+    /// 读取缓存的向量，不存在时返回 None
+    /// Read a cached vector, None if it does not exist
+    fn get(&self, model: &str, hash: &str) -> Result<Option<Vec<f32>>, EmbeddingCacheError>;
+
+    fn put(&self, model: &str, hash: &str, embedding: &[f32]) -> Result<(), EmbeddingCacheError>;
+}
+
+/// 进程内缓存，进程退出后内容丢失
+/// In-process cache, its content is lost when the process exits
+#[derive(Debug, Default)]
+pub struct MemoryEmbeddingCache {
+    items: DashMap<(String, String), Vec<f32>>,
+}
+
+impl EmbeddingCache for MemoryEmbeddingCache {
+    fn get(&self, model: &str, hash: &str) -> Result<Option<Vec<f32>>, EmbeddingCacheError> {
+        Ok(self.items.get(&(model.to_string(), hash.to_string())).map(|item| item.value().clone()))
+    }
+
+    fn put(&self, model: &str, hash: &str, embedding: &[f32]) -> Result<(), EmbeddingCacheError> {
+        self.items.insert((model.to_string(), hash.to_string()), embedding.to_vec());
+        Ok(())
+    }
+}
+
+/// 目录缓存，每个模型一个子目录，每个向量一个以内容哈希命名的文件（小端 f32），进程重启后仍可命中
+/// Directory cache, one subdirectory per model and one file named by content hash per vector (little-endian f32),
+/// so hits survive process restarts
+#[derive(Clone, Debug)]
+pub struct DirectoryEmbeddingCache {
+    directory: PathBuf,
+}
+
+impl DirectoryEmbeddingCache {
+    pub fn new(directory: impl Into<PathBuf>) -> Result<Self, EmbeddingCacheError> {
+        let directory = directory.into();
+        std::fs::create_dir_all(&directory)
+            .map_err(|e| Report::new(EmbeddingCacheError::IoError(format!("{}: {}", directory.display(), e))))?;
+        Ok(Self { directory })
+    }
+
+    /// 向量文件路径，不同的模型名总是对应不同的目录，且不会写出目录之外
+    /// Path of a vector file, distinct model names always map to distinct directories and nothing is written outside
+    /// the directory
+    fn path(&self, model: &str, hash: &str) -> PathBuf {
+        self.directory.join(encode_file_name(model)).join(hash)
+    }
+}
+
+impl EmbeddingCache for DirectoryEmbeddingCache {
+    fn get(&self, model: &str, hash: &str) -> Result<Option<Vec<f32>>, EmbeddingCacheError> {
+        let path = self.path(model, hash);
+        match std::fs::read(&path) {
+            Ok(bytes) => Ok(Some(
+                bytes.chunks_exact(4).map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]])).collect(),
+            )),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(Report::new(EmbeddingCacheError::IoError(format!("{}: {}", path.display(), e)))),
+        }
+    }
+
+    fn put(&self, model: &str, hash: &str, embedding: &[f32]) -> Result<(), EmbeddingCacheError> {
+        let path = self.path(model, hash);
+        let bytes: Vec<u8> = embedding.iter().flat_map(|value| value.to_le_bytes()).collect();
+        path.parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|_| std::fs::write(&path, bytes))
+            .map_err(|e| Report::new(EmbeddingCacheError::IoError(format!("{}: {}", path.display(), e))))
+    }
+}
+
+/// 缓存命中统计
+/// Cache hit statistics
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct EmbeddingCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl EmbeddingCacheStats {
+    /// 命中率，没有请求时为 0
+    /// Hit rate, 0 without any lookup
+    pub fn hit_rate(&self) -> f64 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            total => self.hits as f64 / total as f64,
+        }
+    }
+}
+
+/// 带缓存的向量化：相同内容只向模型请求一次，缓存读写失败时直接请求模型
+/// Embedder with a cache: the same content is only embedded once per model, cache failures fall back to the model
+pub struct CachedEmbedder<E> {
+    inner: E,
+    cache: Arc<dyn EmbeddingCache>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl<E: Embedder> CachedEmbedder<E> {
+    pub fn new(inner: E, cache: impl EmbeddingCache + 'static) -> Self {
+        Self::with_shared_cache(inner, Arc::new(cache))
+    }
+
+    /// 与其他向量化共用一个缓存
+    /// Share one cache with other embedders
+    pub fn with_shared_cache(inner: E, cache: Arc<dyn EmbeddingCache>) -> Self {
+        Self {
+            inner,
+            cache,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub fn stats(&self) -> EmbeddingCacheStats {
+        EmbeddingCacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl<E: Embedder> Embedder for CachedEmbedder<E> {
+    fn embed<'a>(&'a self, text: &'a str) -> BoxFuture<'a, Result<Vec<f32>, ChatError>> {
+        Box::pin(async move {
+            let model = self.inner.model();
+            let hash = content_hash(text);
+            match self.cache.get(model, &hash) {
+                Ok(Some(embedding)) => {
+                    self.hits.fetch_add(1, Ordering::Relaxed);
+                    counter!(EMBEDDING_CACHE_HITS_TOTAL, "model" => model.to_string()).increment(1);
+                    return Ok(embedding);
+                }
+                Ok(None) => {}
+                Err(report) => warn!("Embedding cache lookup failed: {:?}", report),
+            }
+
+            self.misses.fetch_add(1, Ordering::Relaxed);
+            counter!(EMBEDDING_CACHE_MISSES_TOTAL, "model" => model.to_string()).increment(1);
+            let embedding = self.inner.embed(text).await?;
+            if let Err(report) = self.cache.put(model, &hash, &embedding) {
+                warn!("Failed to cache embedding: {:?}", report);
+            }
+            Ok(embedding)
+        })
+    }
+
+    fn model(&self) -> &str {
+        self.inner.model()
+    }
+}
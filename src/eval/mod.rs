@@ -0,0 +1,262 @@
+//! 评测：以带有期望属性的提示词数据集，对一个或多个对话配置运行并打分，汇总为评测报告；或以新的模型回放保存的对话
+//! Evaluation: run datasets of prompts with expected properties against one or more chat configurations, score the
+//! outputs and aggregate them into a report; or replay stored conversations against a new model
+
+pub mod embedding_cache;
+pub mod replay;
+pub mod runner;
+pub mod scorer;
+
+// 标准库
+use std::collections::BTreeMap;
+use std::path::Path;
+
+// 错误处理
+use error_stack::{Result, ResultExt};
+
+// 数据序列化
+use serde::{Deserialize, Serialize};
+
+// 项目内部模块
+use crate::chat::chat_base::ChatError;
+
+pub use embedding_cache::{CachedEmbedder, DirectoryEmbeddingCache, EmbeddingCache, MemoryEmbeddingCache};
+pub use replay::{ReplayReport, ReplayRunner, ReplayTurn};
+pub use runner::EvalRunner;
+pub use scorer::{
+    Embedder, EmbeddingSimilarity, ExactMatch, FnEmbedder, JsonSchemaValid, JudgeRubric, OpenAiEmbedder, Score, Scorer,
+};
+
+/// 一条评测用例：输入与期望属性，未设置的期望由对应的评分器跳过
+/// One evaluation case: the input and its expected properties, scorers skip expectations that are not set
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct EvalCase {
+    pub id: String,
+
+    pub input: String,
+
+    /// 期望的回答，用于精确匹配与语义相似度
+    /// Expected answer, for exact matching and semantic similarity
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expected: Option<String>,
+
+    /// 回答需满足的JSON Schema
+    /// JSON Schema the answer must satisfy
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema: Option<serde_json::Value>,
+
+    /// 评判模型使用的评分标准
+    /// Rubric used by the judge model
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rubric: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+}
+
+impl EvalCase {
+    pub fn new(id: &str, input: &str) -> Self {
+        Self {
+            id: id.to_string(),
+            input: input.to_string(),
+            ..Self::default()
+        }
+    }
+
+    pub fn expected(mut self, expected: &str) -> Self {
+        self.expected = Some(expected.to_string());
+        self
+    }
+
+    pub fn schema(mut self, schema: serde_json::Value) -> Self {
+        self.schema = Some(schema);
+        self
+    }
+
+    pub fn rubric(mut self, rubric: &str) -> Self {
+        self.rubric = Some(rubric.to_string());
+        self
+    }
+
+    pub fn tag(mut self, tag: &str) -> Self {
+        self.tags.push(tag.to_string());
+        self
+    }
+}
+
+/// 评测数据集
+/// Evaluation dataset
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct EvalDataset {
+    pub name: String,
+    pub cases: Vec<EvalCase>,
+}
+
+impl EvalDataset {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            cases: Vec::new(),
+        }
+    }
+
+    pub fn case(mut self, case: EvalCase) -> Self {
+        self.cases.push(case);
+        self
+    }
+
+    /// 从每行一个用例的JSONL文件读取，数据集以文件名命名
+    /// Read from a JSONL file with one case per line, the dataset is named after the file
+    pub fn from_jsonl(path: impl AsRef<Path>) -> Result<Self, ChatError> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .change_context(ChatError::InvalidConfig)
+            .attach_printable_lazy(|| format!("Failed to read dataset {}", path.display()))?;
+        let cases = content
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(index, line)| {
+                serde_json::from_str(line)
+                    .change_context(ChatError::InvalidConfig)
+                    .attach_printable_lazy(|| format!("Invalid case at {}:{}", path.display(), index + 1))
+            })
+            .collect::<Result<Vec<EvalCase>, ChatError>>()?;
+        Ok(Self {
+            name: path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default(),
+            cases,
+        })
+    }
+
+    /// 只保留带有指定标签的用例
+    /// Keep only the cases with the given tag
+    pub fn filter_tag(mut self, tag: &str) -> Self {
+        self.cases.retain(|case| case.tags.iter().any(|case_tag| case_tag == tag));
+        self
+    }
+}
+
+/// 一条用例在一个对话配置下的结果
+/// Result of one case under one chat configuration
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CaseResult {
+    pub case_id: String,
+
+    /// 回答，对话失败时为 None
+    /// The answer, None if the chat failed
+    pub output: Option<String>,
+
+    pub error: Option<String>,
+
+    /// 各评分器的得分，不适用于本用例的评分器不出现
+    /// Score of each scorer, scorers not applicable to the case are absent
+    pub scores: BTreeMap<String, Score>,
+}
+
+impl CaseResult {
+    /// 对话成功且所有得分均通过
+    /// The chat succeeded and all scores passed
+    pub fn passed(&self) -> bool {
+        self.error.is_none() && self.scores.values().all(|score| score.passed)
+    }
+}
+
+/// 一个评分器在一个对话配置下的汇总
+/// Summary of one scorer under one chat configuration
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ScorerSummary {
+    pub scored: usize,
+    pub passed: usize,
+    pub mean: f64,
+}
+
+/// 一个对话配置的评测结果
+/// Evaluation results of one chat configuration
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TargetReport {
+    pub target: String,
+    pub results: Vec<CaseResult>,
+}
+
+impl TargetReport {
+    /// 所有得分均通过的用例比例
+    /// Fraction of cases whose scores all passed
+    pub fn pass_rate(&self) -> f64 {
+        if self.results.is_empty() {
+            return 0.0;
+        }
+        self.results.iter().filter(|result| result.passed()).count() as f64 / self.results.len() as f64
+    }
+
+    pub fn errors(&self) -> usize {
+        self.results.iter().filter(|result| result.error.is_some()).count()
+    }
+
+    /// 按评分器汇总得分
+    /// Scores summarized per scorer
+    pub fn summary(&self) -> BTreeMap<String, ScorerSummary> {
+        let mut summary: BTreeMap<String, ScorerSummary> = BTreeMap::new();
+        for (name, score) in self.results.iter().flat_map(|result| &result.scores) {
+            let entry = summary.entry(name.clone()).or_default();
+            entry.mean += score.value;
+            entry.scored += 1;
+            entry.passed += usize::from(score.passed);
+        }
+        for entry in summary.values_mut() {
+            entry.mean /= entry.scored as f64;
+        }
+        summary
+    }
+}
+
+/// 评测报告：数据集在各对话配置下的结果
+/// Evaluation report: the results of a dataset under each chat configuration
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EvalReport {
+    pub dataset: String,
+    pub targets: Vec<TargetReport>,
+}
+
+impl EvalReport {
+    pub fn target(&self, name: &str) -> Option<&TargetReport> {
+        self.targets.iter().find(|target| target.target == name)
+    }
+
+    /// 通过率最高的对话配置
+    /// Chat configuration with the highest pass rate
+    pub fn best(&self) -> Option<&TargetReport> {
+        self.targets.iter().max_by(|a, b| a.pass_rate().total_cmp(&b.pass_rate()))
+    }
+
+    /// 以 Markdown 表格列出各配置的通过率、错误数与各评分器均分
+    /// Markdown table of the pass rate, error count and mean of each scorer per configuration
+    pub fn to_markdown(&self) -> String {
+        let scorers: Vec<String> = self
+            .targets
+            .iter()
+            .flat_map(|target| target.summary().into_keys())
+            .collect::<std::collections::BTreeSet<_>>()
+            .into_iter()
+            .collect();
+
+        let mut lines = vec![
+            format!("| target | pass rate | errors | {} |", scorers.join(" | ")),
+            format!("|---|---|---|{}", "---|".repeat(scorers.len())),
+        ];
+        for target in &self.targets {
+            let summary = target.summary();
+            let means: Vec<String> = scorers
+                .iter()
+                .map(|scorer| summary.get(scorer).map_or("-".to_string(), |entry| format!("{:.2}", entry.mean)))
+                .collect();
+            lines.push(format!(
+                "| {} | {:.0}% | {} | {} |",
+                target.target,
+                target.pass_rate() * 100.0,
+                target.errors(),
+                means.join(" | ")
+            ));
+        }
+        lines.join("\n")
+    }
+}
@@ -0,0 +1,236 @@
+// 标准库
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Instant;
+
+// 数据序列化
+use serde::{Deserialize, Serialize};
+
+// 观测诊断
+use tracing::warn;
+
+// 项目内部模块
+use crate::chat::chat_base::ChatError;
+use crate::chat::chat_single::SingleChat;
+use crate::chat::message::{Messages, Role, Session};
+use crate::eval::EvalCase;
+use crate::eval::scorer::{Score, Scorer};
+
+type ChatFactory = Arc<dyn Fn() -> error_stack::Result<SingleChat, ChatError> + Send + Sync>;
+
+/// 回放运行器：把保存的对话中的每个用户轮次，以新的模型或提示词版本重新提问，与原回答逐轮对照，用于迁移提供商前的比较
+/// Replay runner: every user turn of a stored conversation is asked again with a new model or prompt version and
+/// compared turn by turn with the original answer, to inform migrations between providers
+///
+/// 每个轮次使用新建的对话，历史为原对话在该轮之前的消息（含原回答），各轮互不影响。原对话的系统消息默认不带上，
+/// 由新对话自身的系统提示词代替，见 `keep_system`。评分器以原回答作为期望的回答。
+/// Every turn gets a freshly built chat whose history is the original conversation before that turn (with the
+/// original answers), so turns do not affect each other. The system messages of the original conversation are
+/// left out by default in favor of the new chat's own system prompt, see `keep_system`. Scorers take the original
+/// answer as the expected answer.
+pub struct ReplayRunner {
+    target: String,
+    build: ChatFactory,
+    scorers: Vec<Arc<dyn Scorer>>,
+    keep_system: bool,
+}
+
+impl ReplayRunner {
+    /// `build` 为每个轮次创建新的对话，`target` 为报告中的名称
+    /// `build` creates a new chat for every turn, `target` names it in the report
+    pub fn new(
+        target: &str,
+        build: impl Fn() -> error_stack::Result<SingleChat, ChatError> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            target: target.to_string(),
+            build: Arc::new(build),
+            scorers: Vec::new(),
+            keep_system: false,
+        }
+    }
+
+    pub fn scorer(mut self, scorer: impl Scorer + 'static) -> Self {
+        self.scorers.push(Arc::new(scorer));
+        self
+    }
+
+    /// 是否带上原对话的系统消息，只比较模型而不更换提示词时使用
+    /// Whether to keep the system messages of the original conversation, for comparing models without changing
+    /// the prompt
+    pub fn keep_system(mut self, keep: bool) -> Self {
+        self.keep_system = keep;
+        self
+    }
+
+    /// 沿默认路径回放会话中有回答的用户轮次
+    /// Replay the answered user turns of a session along its default path
+    pub async fn replay(&self, session: &Session) -> ReplayReport {
+        let path = session.default_path_messages();
+        let mut turns = Vec::new();
+        for (position, pair) in path.windows(2).enumerate() {
+            let (question, answer) = (pair[0], pair[1]);
+            if question.role != Role::User || answer.role != Role::Assistant {
+                continue;
+            }
+            turns.push(self.replay_turn(turns.len(), &path[..position], question, answer).await);
+        }
+        ReplayReport {
+            target: self.target.clone(),
+            turns,
+        }
+    }
+
+    async fn replay_turn(
+        &self,
+        index: usize,
+        history: &[&Messages],
+        question: &Messages,
+        answer: &Messages,
+    ) -> ReplayTurn {
+        let input = question.content.to_text().into_owned();
+        let original = answer.content.to_text().into_owned();
+        let mut turn = ReplayTurn {
+            index,
+            input: input.clone(),
+            original: original.clone(),
+            original_model: answer.metadata.as_ref().map(|metadata| metadata.model.clone()),
+            replayed: None,
+            replayed_model: None,
+            error: None,
+            latency_ms: 0,
+            scores: BTreeMap::new(),
+        };
+
+        let started = Instant::now();
+        let replayed = async {
+            let mut chat = (self.build)()?;
+            for message in history.iter().filter(|message| self.keep_system || message.role != Role::System) {
+                chat.base.add_content(message.role.clone(), message.content.clone())?;
+            }
+            let replayed = chat.get_answer(&input).await?;
+            Ok::<_, error_stack::Report<ChatError>>((replayed, chat.base.model.clone()))
+        }
+        .await;
+        turn.latency_ms = started.elapsed().as_millis() as u64;
+        let (replayed, model) = match replayed {
+            Ok(replayed) => replayed,
+            Err(report) => {
+                warn!("Replay of turn {} failed: {:?}", index, report);
+                turn.error = Some(report.current_context().to_string());
+                return turn;
+            }
+        };
+
+        let case = EvalCase::new(&index.to_string(), &input).expected(&original);
+        for scorer in &self.scorers {
+            // 评分器失败记为不通过，不影响其他评分器
+            // A failing scorer counts as not passed, without affecting the other scorers
+            let score = match scorer.score(&case, &replayed).await {
+                Ok(Some(score)) => score,
+                Ok(None) => continue,
+                Err(report) => {
+                    warn!("Scorer {} failed on replayed turn {}: {:?}", scorer.name(), index, report);
+                    Score::fail(format!("scorer failed: {}", report.current_context()))
+                }
+            };
+            turn.scores.insert(scorer.name().to_string(), score);
+        }
+        turn.replayed = Some(replayed);
+        turn.replayed_model = Some(model);
+        turn
+    }
+}
+
+/// 一个用户轮次的回放结果
+/// Replay result of one user turn
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ReplayTurn {
+    /// 轮次序号，从 0 开始
+    /// Turn number, starting at 0
+    pub index: usize,
+
+    pub input: String,
+
+    pub original: String,
+
+    /// 原回答的模型，旧会话没有记录时为 None
+    /// Model of the original answer, None if older sessions did not record it
+    pub original_model: Option<String>,
+
+    /// 新的回答，回放失败时为 None
+    /// The new answer, None if the replay failed
+    pub replayed: Option<String>,
+
+    pub replayed_model: Option<String>,
+
+    pub error: Option<String>,
+
+    pub latency_ms: u64,
+
+    /// 各评分器以原回答为期望的得分
+    /// Score of each scorer, taking the original answer as expected
+    pub scores: BTreeMap<String, Score>,
+}
+
+impl ReplayTurn {
+    /// 回放成功且回答与原回答不同（忽略首尾空白）
+    /// The replay succeeded with an answer different from the original (ignoring surrounding whitespace)
+    pub fn changed(&self) -> bool {
+        self.replayed.as_deref().is_some_and(|replayed| replayed.trim() != self.original.trim())
+    }
+}
+
+/// 回放报告
+/// Replay report
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ReplayReport {
+    pub target: String,
+    pub turns: Vec<ReplayTurn>,
+}
+
+impl ReplayReport {
+    pub fn errors(&self) -> usize {
+        self.turns.iter().filter(|turn| turn.error.is_some()).count()
+    }
+
+    /// 回答发生变化的轮次数
+    /// Number of turns whose answer changed
+    pub fn changed(&self) -> usize {
+        self.turns.iter().filter(|turn| turn.changed()).count()
+    }
+
+    /// 以 Markdown 表格并排列出每个轮次的输入、原回答、新回答与得分
+    /// Markdown table of every turn side by side: the input, the original and new answers and the scores
+    pub fn to_markdown(&self) -> String {
+        let cell = |text: &str| text.replace('|', "\\|").replace('\n', "<br>");
+        let mut lines = vec![
+            format!("| # | input | original | {} | scores |", self.target),
+            "|---|---|---|---|---|".to_string(),
+        ];
+        for turn in &self.turns {
+            let original = match &turn.original_model {
+                Some(model) => format!("{} ({})", cell(&turn.original), model),
+                None => cell(&turn.original),
+            };
+            let replayed = match (&turn.replayed, &turn.error) {
+                (Some(replayed), _) => {
+                    format!("{} ({})", cell(replayed), turn.replayed_model.as_deref().unwrap_or("-"))
+                }
+                (None, Some(error)) => format!("error: {}", cell(error)),
+                (None, None) => "-".to_string(),
+            };
+            let scores: Vec<String> =
+                turn.scores.iter().map(|(name, score)| format!("{} {:.2}", name, score.value)).collect();
+            lines.push(format!(
+                "| {} | {} | {} | {} | {} |",
+                turn.index,
+                cell(&turn.input),
+                original,
+                replayed,
+                scores.join(", ")
+            ));
+        }
+        lines.join("\n")
+    }
+}
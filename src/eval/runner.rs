@@ -0,0 +1,120 @@
+// 标准库
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+// 异步
+use futures::StreamExt;
+
+// 观测诊断
+use tracing::warn;
+
+// 项目内部模块
+use crate::chat::chat_base::ChatError;
+use crate::chat::chat_single::SingleChat;
+use crate::eval::scorer::{Score, Scorer};
+use crate::eval::{CaseResult, EvalCase, EvalDataset, EvalReport, TargetReport};
+
+type ChatFactory = Arc<dyn Fn() -> error_stack::Result<SingleChat, ChatError> + Send + Sync>;
+
+/// 评测运行器：每条用例使用新建的对话，在各对话配置下运行并以所有评分器打分
+/// Evaluation runner: each case gets a freshly built chat, runs under every chat configuration and is scored by
+/// every scorer
+pub struct EvalRunner {
+    dataset: EvalDataset,
+    targets: Vec<(String, ChatFactory)>,
+    scorers: Vec<Arc<dyn Scorer>>,
+    concurrency: usize,
+}
+
+impl EvalRunner {
+    pub fn new(dataset: EvalDataset) -> Self {
+        Self {
+            dataset,
+            targets: Vec::new(),
+            scorers: Vec::new(),
+            concurrency: 4,
+        }
+    }
+
+    /// 添加一个对话配置，`build` 为每条用例创建新的对话
+    /// Add a chat configuration, `build` creates a new chat for each case
+    pub fn target(
+        mut self,
+        name: &str,
+        build: impl Fn() -> error_stack::Result<SingleChat, ChatError> + Send + Sync + 'static,
+    ) -> Self {
+        self.targets.push((name.to_string(), Arc::new(build)));
+        self
+    }
+
+    pub fn scorer(mut self, scorer: impl Scorer + 'static) -> Self {
+        self.scorers.push(Arc::new(scorer));
+        self
+    }
+
+    /// 同一配置下同时运行的用例数，默认为4
+    /// Number of cases run at once under one configuration, 4 by default
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    pub async fn run(&self) -> EvalReport {
+        let mut targets = Vec::with_capacity(self.targets.len());
+        for (name, build) in &self.targets {
+            let results = futures::stream::iter(&self.dataset.cases)
+                .map(|case| self.run_case(build, case))
+                .buffered(self.concurrency)
+                .collect()
+                .await;
+            targets.push(TargetReport {
+                target: name.clone(),
+                results,
+            });
+        }
+        EvalReport {
+            dataset: self.dataset.name.clone(),
+            targets,
+        }
+    }
+
+    async fn run_case(&self, build: &ChatFactory, case: &EvalCase) -> CaseResult {
+        let answer = match build() {
+            Ok(mut chat) => chat.get_answer(&case.input).await,
+            Err(report) => Err(report),
+        };
+        let output = match answer {
+            Ok(output) => output,
+            Err(report) => {
+                warn!("Evaluation case {} failed: {:?}", case.id, report);
+                return CaseResult {
+                    case_id: case.id.clone(),
+                    output: None,
+                    error: Some(report.current_context().to_string()),
+                    scores: BTreeMap::new(),
+                };
+            }
+        };
+
+        let mut scores = BTreeMap::new();
+        for scorer in &self.scorers {
+            // 评分器失败记为不通过，不影响其他评分器
+            // A failing scorer counts as not passed, without affecting the other scorers
+            let score = match scorer.score(case, &output).await {
+                Ok(Some(score)) => score,
+                Ok(None) => continue,
+                Err(report) => {
+                    warn!("Scorer {} failed on case {}: {:?}", scorer.name(), case.id, report);
+                    Score::fail(format!("scorer failed: {}", report.current_context()))
+                }
+            };
+            scores.insert(scorer.name().to_string(), score);
+        }
+        CaseResult {
+            case_id: case.id.clone(),
+            output: Some(output),
+            error: None,
+            scores,
+        }
+    }
+}
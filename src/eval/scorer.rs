@@ -0,0 +1,460 @@
+// 标准库
+use std::fmt::Debug;
+use std::sync::Arc;
+
+// 错误处理
+use error_stack::{Report, Result, ResultExt};
+
+// 异步
+use futures::future::BoxFuture;
+
+// 网络通信
+use reqwest::Client;
+
+// 数据序列化
+use serde::{Deserialize, Serialize};
+
+// 项目内部模块
+use crate::chat::chat_base::ChatError;
+use crate::chat::chat_single::SingleChat;
+use crate::chat::chat_tool::strip_code_fence;
+use crate::config::Config;
+use crate::error::ErrorBody;
+use crate::eval::EvalCase;
+use crate::utils::common::redact::redact;
+
+/// 一次评分：0 到 1 之间的得分与是否通过
+/// One score: a value between 0 and 1 and whether it passed
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Score {
+    pub value: f64,
+    pub passed: bool,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+impl Score {
+    pub fn pass() -> Self {
+        Self {
+            value: 1.0,
+            passed: true,
+            detail: None,
+        }
+    }
+
+    pub fn fail(detail: impl Into<String>) -> Self {
+        Self {
+            value: 0.0,
+            passed: false,
+            detail: Some(detail.into()),
+        }
+    }
+
+    /// 得分不低于阈值时通过
+    /// Passes if the value reaches the threshold
+    pub fn threshold(value: f64, threshold: f64) -> Self {
+        Self {
+            value,
+            passed: value >= threshold,
+            detail: None,
+        }
+    }
+
+    pub fn detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+}
+
+/// 评分器，对一条用例的回答打分；用例缺少所需的期望时返回 None 表示不适用
+/// Scorer, scores the answer to one case; returns None if the case lacks the expectation it needs
+pub trait Scorer: Send + Sync {
+    /// 评分器名称，作为报告中的键
+    /// Scorer name, the key in the report
+    fn name(&self) -> &str;
+
+    fn score<'a>(&'a self, case: &'a EvalCase, output: &'a str) -> BoxFuture<'a, Result<Option<Score>, ChatError>>;
+}
+
+/// 与期望回答精确匹配，默认忽略首尾空白
+/// Exact match against the expected answer, ignoring surrounding whitespace by default
+#[derive(Clone, Debug)]
+pub struct ExactMatch {
+    pub trim: bool,
+    pub case_sensitive: bool,
+}
+
+impl Default for ExactMatch {
+    fn default() -> Self {
+        Self {
+            trim: true,
+            case_sensitive: true,
+        }
+    }
+}
+
+impl ExactMatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn case_insensitive(mut self) -> Self {
+        self.case_sensitive = false;
+        self
+    }
+}
+
+impl Scorer for ExactMatch {
+    fn name(&self) -> &str {
+        "exact_match"
+    }
+
+    fn score<'a>(&'a self, case: &'a EvalCase, output: &'a str) -> BoxFuture<'a, Result<Option<Score>, ChatError>> {
+        let normalize = |text: &str| {
+            let text = if self.trim { text.trim() } else { text };
+            if self.case_sensitive { text.to_string() } else { text.to_lowercase() }
+        };
+        let score = case.expected.as_ref().map(|expected| {
+            if normalize(expected) == normalize(output) { Score::pass() } else { Score::fail(format!("expected {:?}", expected)) }
+        });
+        Box::pin(async move { Ok(score) })
+    }
+}
+
+/// 回答是符合用例 JSON Schema 的JSON，允许 Markdown 代码块包裹
+/// The answer is JSON satisfying the JSON Schema of the case, a Markdown code fence around it is allowed
+///
+/// 支持本crate生成的 schema 所用的关键字：`type`、`properties`、`required`、`additionalProperties`、`items`、
+/// `enum`，以及包裹在 `schema` 字段中的结构化输出格式。
+/// Supports the keywords used by the schemas this crate generates: `type`, `properties`, `required`,
+/// `additionalProperties`, `items`, `enum`, and the structured output format wrapped in a `schema` field.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct JsonSchemaValid;
+
+impl Scorer for JsonSchemaValid {
+    fn name(&self) -> &str {
+        "json_schema"
+    }
+
+    fn score<'a>(&'a self, case: &'a EvalCase, output: &'a str) -> BoxFuture<'a, Result<Option<Score>, ChatError>> {
+        let score = case.schema.as_ref().map(|schema| {
+            let schema = schema.get("schema").unwrap_or(schema);
+            match serde_json::from_str::<serde_json::Value>(strip_code_fence(output)) {
+                Err(e) => Score::fail(format!("invalid JSON: {}", e)),
+                Ok(value) => match violation(&value, schema, "$") {
+                    Some(violation) => Score::fail(violation),
+                    None => Score::pass(),
+                },
+            }
+        });
+        Box::pin(async move { Ok(score) })
+    }
+}
+
+/// 值违反 schema 的第一处位置与原因
+/// First place where a value violates a schema, and why
+fn violation(value: &serde_json::Value, schema: &serde_json::Value, path: &str) -> Option<String> {
+    if let Some(options) = schema["enum"].as_array()
+        && !options.contains(value)
+    {
+        return Some(format!("{} is not one of {}", path, schema["enum"]));
+    }
+
+    let types: Vec<&str> = match &schema["type"] {
+        serde_json::Value::String(kind) => vec![kind.as_str()],
+        serde_json::Value::Array(kinds) => kinds.iter().filter_map(|kind| kind.as_str()).collect(),
+        _ => Vec::new(),
+    };
+    let matches_type = |kind: &str| match kind {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "number" => value.is_number(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        _ => true,
+    };
+    if !types.is_empty() && !types.iter().any(|kind| matches_type(kind)) {
+        return Some(format!("{} is not of type {}", path, types.join(" or ")));
+    }
+
+    if let Some(object) = value.as_object() {
+        for required in schema["required"].as_array().into_iter().flatten().filter_map(|key| key.as_str()) {
+            if !object.contains_key(required) {
+                return Some(format!("{} is missing required property {:?}", path, required));
+            }
+        }
+        for (key, item) in object {
+            match schema["properties"].get(key) {
+                Some(property) => {
+                    if let Some(violation) = violation(item, property, &format!("{}.{}", path, key)) {
+                        return Some(violation);
+                    }
+                }
+                None if schema["additionalProperties"] == false => {
+                    return Some(format!("{} has unexpected property {:?}", path, key));
+                }
+                None => {}
+            }
+        }
+    }
+
+    if let (Some(items), Some(item_schema)) = (value.as_array(), schema.get("items")) {
+        for (index, item) in items.iter().enumerate() {
+            if let Some(violation) = violation(item, item_schema, &format!("{}[{}]", path, index)) {
+                return Some(violation);
+            }
+        }
+    }
+    None
+}
+
+/// 文本向量化，用于语义相似度评分
+/// Text embedding, for semantic similarity scoring
+pub trait Embedder: Send + Sync {
+    fn embed<'a>(&'a self, text: &'a str) -> BoxFuture<'a, Result<Vec<f32>, ChatError>>;
+
+    /// 向量模型名称，缓存按模型区分向量，不同的向量化方式必须使用不同的名称
+    /// Name of the embedding model, caches keep the vectors of each model apart, so different ways of embedding
+    /// must use different names
+    fn model(&self) -> &str;
+}
+
+/// 以函数向量化，如本地模型或测试中的闭包；名称用于在缓存中区分向量
+/// Embedding through a function, such as a local model or a closure in tests; the name keeps its vectors apart in
+/// caches
+#[derive(Clone)]
+pub struct FnEmbedder<F> {
+    model: String,
+    embed: F,
+}
+
+impl<F> FnEmbedder<F>
+where
+    F: Fn(&str) -> Vec<f32> + Send + Sync,
+{
+    pub fn new(model: &str, embed: F) -> Self {
+        Self {
+            model: model.to_string(),
+            embed,
+        }
+    }
+}
+
+impl<F> Embedder for FnEmbedder<F>
+where
+    F: Fn(&str) -> Vec<f32> + Send + Sync,
+{
+    fn embed<'a>(&'a self, text: &'a str) -> BoxFuture<'a, Result<Vec<f32>, ChatError>> {
+        let embedding = (self.embed)(text);
+        Box::pin(async move { Ok(embedding) })
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+}
+
+/// 调用 OpenAI 兼容的 `/embeddings` 接口
+/// Calls an OpenAI-compatible `/embeddings` endpoint
+#[derive(Clone)]
+pub struct OpenAiEmbedder {
+    url: String,
+    api_key: String,
+    client: Client,
+    model: String,
+}
+
+impl Debug for OpenAiEmbedder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OpenAiEmbedder")
+            .field("url", &self.url)
+            .field("model", &self.model)
+            .finish_non_exhaustive()
+    }
+}
+
+impl OpenAiEmbedder {
+    /// 使用配置中API的地址与密钥，接口地址由对话补全地址推出
+    /// Use the address and key of a configured API, the endpoint is derived from the chat completion address
+    pub fn from_api(api_name: &str) -> Result<Self, ChatError> {
+        let api_info = Config::get_api_info_with_name(api_name.to_string())
+            .change_context(ChatError::InvalidConfig)
+            .attach_printable_lazy(|| format!("Unknown API: {}", api_name))?;
+        let url = match api_info.base_url.strip_suffix("/chat/completions") {
+            Some(prefix) => format!("{}/embeddings", prefix),
+            None => format!("{}/embeddings", api_info.base_url.trim_end_matches('/')),
+        };
+        Ok(Self {
+            url,
+            api_key: api_info.api_key,
+            client: api_info.client,
+            model: "text-embedding-3-small".to_string(),
+        })
+    }
+
+    /// 向量模型，默认为 `text-embedding-3-small`
+    /// Embedding model, `text-embedding-3-small` by default
+    pub fn model(mut self, model: &str) -> Self {
+        self.model = model.to_string();
+        self
+    }
+}
+
+impl Embedder for OpenAiEmbedder {
+    fn embed<'a>(&'a self, text: &'a str) -> BoxFuture<'a, Result<Vec<f32>, ChatError>> {
+        Box::pin(async move {
+            let response = self
+                .client
+                .post(&self.url)
+                .bearer_auth(&self.api_key)
+                .json(&serde_json::json!({"model": self.model, "input": text}))
+                .send()
+                .await
+                .change_context(ChatError::UnknownError)
+                .attach_printable("Embedding request failed")?;
+
+            let status = response.status();
+            if !status.is_success() {
+                let body = response.text().await.unwrap_or_default();
+                return Err(Report::new(ChatError::HttpError(status.as_u16()))
+                    .attach(ErrorBody(redact(&body)))
+                    .attach_printable("Embedding endpoint returned an error"));
+            }
+
+            let parsed: serde_json::Value = response
+                .json()
+                .await
+                .change_context(ChatError::ParseResponseError)
+                .attach_printable("Failed to parse embedding response")?;
+            parsed["data"][0]["embedding"]
+                .as_array()
+                .map(|values| values.iter().filter_map(|value| value.as_f64()).map(|value| value as f32).collect())
+                .ok_or_else(|| Report::new(ChatError::ParseResponseError).attach_printable("Embedding response has no vector"))
+        })
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+}
+
+/// 回答与期望回答的向量余弦相似度，不低于阈值时通过
+/// Cosine similarity between the embeddings of the answer and the expected answer, passing at the threshold
+#[derive(Clone)]
+pub struct EmbeddingSimilarity {
+    embedder: Arc<dyn Embedder>,
+    pub threshold: f64,
+}
+
+impl Debug for EmbeddingSimilarity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EmbeddingSimilarity").field("threshold", &self.threshold).finish_non_exhaustive()
+    }
+}
+
+impl EmbeddingSimilarity {
+    /// 默认阈值为 0.8
+    /// The threshold is 0.8 by default
+    pub fn new(embedder: impl Embedder + 'static) -> Self {
+        Self {
+            embedder: Arc::new(embedder),
+            threshold: 0.8,
+        }
+    }
+
+    pub fn threshold(mut self, threshold: f64) -> Self {
+        self.threshold = threshold;
+        self
+    }
+}
+
+impl Scorer for EmbeddingSimilarity {
+    fn name(&self) -> &str {
+        "embedding_similarity"
+    }
+
+    fn score<'a>(&'a self, case: &'a EvalCase, output: &'a str) -> BoxFuture<'a, Result<Option<Score>, ChatError>> {
+        Box::pin(async move {
+            let Some(expected) = &case.expected else {
+                return Ok(None);
+            };
+            let expected = self.embedder.embed(expected).await?;
+            let actual = self.embedder.embed(output).await?;
+            Ok(Some(Score::threshold(cosine(&expected, &actual), self.threshold)))
+        })
+    }
+}
+
+/// 余弦相似度，任一向量为零向量或长度不同时为 0
+/// Cosine similarity, 0 if either vector is zero or their lengths differ
+pub fn cosine(a: &[f32], b: &[f32]) -> f64 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    let dot: f64 = a.iter().zip(b).map(|(x, y)| f64::from(*x) * f64::from(*y)).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| f64::from(*x).powi(2)).sum::<f64>().sqrt();
+    let norms = norm(a) * norm(b);
+    if norms == 0.0 { 0.0 } else { dot / norms }
+}
+
+/// 由评判模型按用例的评分标准打 0 到 10 分，换算为 0 到 1，不低于阈值时通过
+/// A judge model grades the answer 0 to 10 against the rubric of the case, scaled to 0 to 1, passing at the
+/// threshold
+#[derive(Clone, Debug)]
+pub struct JudgeRubric {
+    api_name: String,
+    pub threshold: f64,
+}
+
+impl JudgeRubric {
+    /// 默认阈值为 0.7
+    /// The threshold is 0.7 by default
+    pub fn new(api_name: &str) -> Self {
+        Self {
+            api_name: api_name.to_string(),
+            threshold: 0.7,
+        }
+    }
+
+    pub fn threshold(mut self, threshold: f64) -> Self {
+        self.threshold = threshold;
+        self
+    }
+}
+
+impl Scorer for JudgeRubric {
+    fn name(&self) -> &str {
+        "judge"
+    }
+
+    fn score<'a>(&'a self, case: &'a EvalCase, output: &'a str) -> BoxFuture<'a, Result<Option<Score>, ChatError>> {
+        Box::pin(async move {
+            let Some(rubric) = &case.rubric else {
+                return Ok(None);
+            };
+            let mut judge = SingleChat::builder()
+                .api(&self.api_name)
+                .system(
+                    "你是严格的评审，按评分标准为回答打0到10的整数分。只输出JSON：{\"score\": 分数, \"reason\": 简短理由}",
+                )
+                .build()?;
+            let answer = judge
+                .get_answer(&format!("评分标准：\n{}\n\n问题：\n{}\n\n回答：\n{}", rubric, case.input, output))
+                .await?;
+
+            #[derive(Deserialize)]
+            struct Verdict {
+                score: f64,
+                #[serde(default)]
+                reason: String,
+            }
+            let verdict: Verdict = serde_json::from_str(strip_code_fence(&answer))
+                .change_context(ChatError::GetJsonError)
+                .attach_printable_lazy(|| format!("Failed to parse the judge verdict: {}", answer))?;
+            Ok(Some(Score::threshold((verdict.score / 10.0).clamp(0.0, 1.0), self.threshold).detail(verdict.reason)))
+        })
+    }
+}
@@ -3,5 +3,21 @@ pub mod prompt;
 pub mod schema;
 pub mod utils;
 pub mod config;
+pub mod documents;
+pub mod error;
+pub mod eval;
+pub mod blocking;
+pub mod prelude;
+pub mod scheduler;
+pub mod shutdown;
+pub mod telemetry;
+pub mod warmup;
+#[cfg(feature = "server")]
+pub mod server;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+#[cfg(test)]
 mod tests;
-mod tool_use;
\ No newline at end of file
+mod tool_use;
+
+pub use crate::warmup::warmup;
@@ -0,0 +1,98 @@
+//! 常用类型的统一导出，`use rhine::prelude::*;` 即可使用对话、配置、结构化输出与错误处理
+//! Common re-exports, `use rhine::prelude::*;` brings in chats, configuration, structured output and error handling
+
+// 对话
+// Chats
+pub use crate::chat::agent::{AgentRun, AgentStop, Deadline, RunTrace, StepDecision, TraceLlmCall, TraceStep, TraceToolCall};
+pub use crate::chat::agent_spec::{Agent, AgentSpec, BudgetSpec, GuardrailSpec, MemorySpec};
+pub use crate::chat::attachment::{Attachment, AttachmentLimits, AttachmentStore};
+pub use crate::chat::broadcast::{Backpressure, StreamBroadcast};
+pub use crate::chat::builder::SingleChatBuilder;
+pub use crate::chat::chat_multi::MultiChat;
+pub use crate::chat::chat_session::ChatSession;
+pub use crate::chat::chat_single::SingleChat;
+pub use crate::chat::event::{BusEvent, ChatEvent, EventBus, EventSource};
+pub use crate::chat::handle::ChatHandle;
+pub use crate::chat::history::{HistoryPolicy, ImportanceWeighted, KeepAll, SlidingWindow, SummarizeOld};
+pub use crate::chat::moderation::{Moderation, ModerationAction, ModerationResult, Moderator, OpenAiModerator};
+pub use crate::chat::guardrail::{DenyList, Guardrail, GuardrailAction, GuardrailVerdict, Guardrails, MaxLength, RegexRule, UrlAllowlist};
+pub use crate::chat::injection::{InjectionAction, InjectionClassifier, InjectionScreen};
+pub use crate::chat::interceptor::{OutgoingRequest, RequestInterceptor};
+pub use crate::chat::language::{Lang, LanguagePolicy, LanguageTarget};
+pub use crate::chat::output_cap::{OutputCap, Truncated};
+pub use crate::chat::pii::{EntityRecognizer, PiiEntity, PiiScrubber};
+pub use crate::chat::message::{Messages, Role, Session};
+pub use crate::chat::params::ChatParams;
+pub use crate::chat::persona::{ExampleDialogue, Persona};
+pub use crate::chat::postprocess::{
+    JsonOnly, NormalizeMarkdown, PlainText, PostProcessor, PostProcessors, StripReasoning, TrimWhitespace,
+};
+pub use crate::chat::preview::{Estimate, RequestPreview};
+pub use crate::chat::artifacts::{CodeBlock, FileAction, FileDirective, FilePatch, ParsedAnswer};
+pub use crate::chat::citation::CitedAnswer;
+pub use crate::chat::compat::ProviderCompat;
+pub use crate::chat::dialogue_state::DialogueState;
+pub use crate::chat::completion::ChatTemplate;
+pub use crate::chat::export::{DatasetExporter, ExportFormat};
+pub use crate::chat::extraction::DocumentExtraction;
+pub use crate::chat::confidence::{ConfidenceReport, ConfidenceScorer, ScoredAnswer};
+pub use crate::chat::context_provider::{ContextProvider, TypedContext};
+pub use crate::chat::faithfulness::{ClaimSupport, FaithfulnessChecker, FaithfulnessReport, VerifiedAnswer};
+pub use crate::chat::hot_reload::{DEFAULT_RELOAD_INTERVAL, HotReload};
+pub use crate::chat::refusal::{AnswerOutcome, RefusalReason};
+pub use crate::chat::retriever::{Retriever, Source};
+pub use crate::chat::shadow::{JsonlShadowLog, MemoryShadowLog, Shadow, ShadowCandidate, ShadowLog, ShadowRecord};
+pub use crate::chat::analytics::{ConversationAnalytics, DailyMessages, ErrorCount, TagCost, ToolUsage, TurnStats};
+pub use crate::chat::retention::{Archiver, DirectoryArchiver, Retention, RetentionPolicy, RetentionReport};
+pub use crate::chat::scratchpad::Scratchpad;
+pub use crate::chat::summary::HandoverBrief;
+pub use crate::chat::store::{DirectorySessionStore, MemorySessionStore, SessionEntry, SessionStore, StoredSession};
+pub use crate::chat::tenant::{RateLimit, Tenant};
+pub use crate::chat::tool_source::{LocalTools, ToolSource};
+pub use crate::chat::mcp::McpTools;
+pub use crate::chat::middleware::{AnswerCache, AnswerMiddleware, Next, RewriteInput};
+pub use crate::chat::ocr::{BlockKind, OcrBlock, OcrText, OcrTools};
+pub use crate::chat::openapi::OpenApiTools;
+pub use crate::chat::transaction::{Compensation, IntentStatus, ToolIntent, TransactionScope};
+pub use crate::chat::transcript::{Perspective, TranscriptEntry};
+pub use crate::chat::trigger::ToolTrigger;
+pub use crate::chat::vision::{VisionAnswer, VisionTools};
+
+// 配置
+// Configuration
+pub use crate::config::{Config, ModelCapability};
+pub use crate::utils::common::tokens::{Heuristic, Tokenizer};
+
+// 结构化输出与工具
+// Structured output and tools
+pub use crate::chat::chat_tool::ToolChoice;
+pub use crate::schema::json_schema::JsonSchema;
+pub use crate::schema::tool_docs::{ToolDoc, ToolExample};
+pub use crate::schema::tool_error::{ToolError, ToolErrorType};
+pub use crate::schema::tool_schema::ToolRegistry;
+pub use rhine_schema_derive::{JsonSchema, tool_schema_derive};
+
+// 评测
+// Evaluation
+pub use crate::eval::{EvalCase, EvalDataset, EvalReport, EvalRunner, ReplayReport, ReplayRunner, Scorer};
+
+// 定时任务
+// Scheduled tasks
+pub use crate::scheduler::{RetryPolicy, Schedule, ScheduledTask, Scheduler};
+
+// 优雅关闭
+// Graceful shutdown
+pub use crate::shutdown::{Shutdown, ShutdownReport};
+
+// 冷启动预热
+// Cold-start warmup
+pub use crate::warmup::{EndpointWarmup, Warmup, WarmupReport, warmup};
+
+// 错误处理
+// Error handling
+pub use crate::chat::chat_base::{ChatError, ModelOverride};
+pub use crate::chat::chat_single::ToolCallError;
+pub use crate::chat::store::StoreError;
+pub use crate::config::ConfigError;
+pub use crate::error::{ReportExt, RhineError};
+pub use crate::scheduler::SchedulerError;
@@ -10,6 +10,8 @@ use indoc::indoc;
 
 // 项目内部模块
 use crate::prompt::model::{Content, Info, Prompt, Template};
+use crate::schema::tool_docs::tool_examples;
+use crate::schema::tool_error::TOOL_ERROR_PROMPT;
 use crate::schema::tool_schema::ChatToolSchemaError;
 
 /// 输出描述错误枚举
@@ -296,10 +298,12 @@ pub fn assemble_tools_prompt(json_schema_vec: Vec<serde_json::Value>) -> error_s
                   - 参数：提供工具所需的所有参数，并确保格式正确（如类型、命名等）。
                 3. 你可以在同一回答中使用多个<ToolUse></ToolUse>标签，每个标签对应任意你想要的工具调用。
                 4. 我会根据你提供的调用信息执行相应的操作，并将结果返回给你。
-                5. 不要在回答中仅包含<ToolUse></ToolUse>标签, 带有一些其他的文字, 可以是你的想法或是其他想表述的内容。\n
+                5. 不要在回答中仅包含<ToolUse></ToolUse>标签, 带有一些其他的文字, 可以是你的想法或是其他想表述的内容。
+                6. {}\n
                 你可以使用以下工具：\n\n{}\n
             </ToolUse>
         "},
+        TOOL_ERROR_PROMPT,
         indented_tools // 统一缩进后的工具描述
                       // Tool descriptions with unified indentation
     );
@@ -355,6 +359,16 @@ fn assemble_tool_prompt(json_schema: serde_json::Value) -> error_stack::Result<S
     // Extract and format property information
     result.push_str(&extract_properties(properties, 1));
 
+    // 附上登记的调用示例，与工具文档同源
+    // Append the registered example calls, from the same source as the tool documentation
+    let examples = tool_examples(function_name);
+    if !examples.is_empty() {
+        result.push_str("调用示例:\n");
+        for example in examples {
+            result.push_str(&format!("  {}: {}\n", example.description, example.arguments));
+        }
+    }
+
     Ok(result)
 }
 
@@ -5,4 +5,5 @@ pub mod model;
 pub mod assembler;
 pub mod loader;
 
+#[allow(deprecated)]
 pub static PROMPTS: Lazy<Prompts> = Lazy::new(Prompts::init_unchecked);
\ No newline at end of file
@@ -348,6 +348,7 @@ impl Prompt {
     /// 如果角色提示不存在，此函数将会panic
     /// This function will panic if the character prompt does not exist
     #[deprecated(since = "next_version", note = "请使用返回Result的default函数代替")]
+    #[allow(deprecated)]
     pub fn default_unchecked(&self) -> String {
         self.character_unchecked("assistant")
     }
@@ -0,0 +1,158 @@
+// 标准库
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+// 错误处理
+use error_stack::{Report, Result};
+
+// 时间处理
+use chrono::{DateTime, Datelike, Duration, NaiveTime, Timelike, Utc};
+
+// 项目内部模块
+use crate::scheduler::SchedulerError;
+
+/// 查找下一次触发时间时最多向后检查的天数，覆盖闰日等稀有日期
+/// Days checked ahead at most when looking for the next fire time, enough for rare dates such as leap days
+const SEARCH_DAYS: i64 = 366 * 8;
+
+/// 五段式 cron 表达式：分 时 日 月 星期，按 UTC 计算
+/// Five-field cron expression: minute hour day-of-month month day-of-week, evaluated in UTC
+///
+/// 每段支持 `*`、数值、范围 `a-b`、步长 `*/n` 与 `a-b/n`，以及逗号分隔的列表；星期 0 与 7 均表示周日。
+/// 与常见 cron 一致，日与星期都受限时满足其一即可触发。
+/// Every field accepts `*`, numbers, ranges `a-b`, steps `*/n` and `a-b/n`, and comma separated lists; both 0 and 7
+/// mean Sunday for the day of week. As in common cron implementations, when both the day of month and the day of
+/// week are restricted, matching either one fires.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CronSchedule {
+    expression: String,
+    minutes: Vec<bool>,
+    hours: Vec<bool>,
+    days: Vec<bool>,
+    months: Vec<bool>,
+    weekdays: Vec<bool>,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl CronSchedule {
+    pub fn parse(expression: &str) -> Result<Self, SchedulerError> {
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(invalid(expression, "expected 5 fields: minute hour day month weekday"));
+        };
+
+        let mut weekdays = parse_field(expression, weekday, 0, 7)?;
+        if weekdays[7] {
+            weekdays[0] = true;
+        }
+        weekdays.truncate(7);
+        Ok(Self {
+            expression: fields.join(" "),
+            minutes: parse_field(expression, minute, 0, 59)?,
+            hours: parse_field(expression, hour, 0, 23)?,
+            days: parse_field(expression, day, 1, 31)?,
+            months: parse_field(expression, month, 1, 12)?,
+            weekdays,
+            any_day: day == "*",
+            any_weekday: weekday == "*",
+        })
+    }
+
+    /// 严格晚于 `after` 的下一次触发时间，表达式永不触发（如 2 月 30 日）时返回 None
+    /// The next fire time strictly after `after`, None if the expression never fires (such as February 30)
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let start = after.with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+        let mut date = start.date_naive();
+        for _ in 0..SEARCH_DAYS {
+            if self.matches_date(date.day(), date.month(), date.weekday().num_days_from_sunday()) {
+                let (from_hour, from_minute) = if date == start.date_naive() { (start.hour(), start.minute()) } else { (0, 0) };
+                for hour in from_hour..24 {
+                    if !self.hours[hour as usize] {
+                        continue;
+                    }
+                    let first_minute = if hour == from_hour { from_minute } else { 0 };
+                    if let Some(minute) = (first_minute..60).find(|&minute| self.minutes[minute as usize]) {
+                        return Some(date.and_time(NaiveTime::from_hms_opt(hour, minute, 0)?).and_utc());
+                    }
+                }
+            }
+            date = date.succ_opt()?;
+        }
+        None
+    }
+
+    fn matches_date(&self, day: u32, month: u32, weekday: u32) -> bool {
+        if !self.months[month as usize - 1] {
+            return false;
+        }
+        let day_matches = self.days[day as usize - 1];
+        let weekday_matches = self.weekdays[weekday as usize];
+        match (self.any_day, self.any_weekday) {
+            (true, true) => true,
+            (true, false) => weekday_matches,
+            (false, true) => day_matches,
+            (false, false) => day_matches || weekday_matches,
+        }
+    }
+}
+
+impl FromStr for CronSchedule {
+    type Err = Report<SchedulerError>;
+
+    fn from_str(expression: &str) -> std::result::Result<Self, Self::Err> {
+        Self::parse(expression)
+    }
+}
+
+impl Display for CronSchedule {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.expression)
+    }
+}
+
+fn invalid(expression: &str, reason: &str) -> Report<SchedulerError> {
+    Report::new(SchedulerError::InvalidSchedule(format!("'{}': {}", expression, reason)))
+}
+
+/// 解析一段，返回从 `min` 开始的取值表
+/// Parse one field into a table of allowed values starting at `min`
+fn parse_field(expression: &str, field: &str, min: u32, max: u32) -> Result<Vec<bool>, SchedulerError> {
+    let number = |text: &str| {
+        text.parse::<u32>()
+            .ok()
+            .filter(|value| (min..=max).contains(value))
+            .ok_or_else(|| invalid(expression, &format!("'{}' is not a value between {} and {}", text, min, max)))
+    };
+
+    let mut allowed = vec![false; (max - min + 1) as usize];
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => match step.parse::<u32>() {
+                Ok(step) if step > 0 => (range, step),
+                _ => return Err(invalid(expression, &format!("'{}' has an invalid step", part))),
+            },
+            None => (part, 1),
+        };
+        let (start, end) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((start, end)) => (number(start)?, number(end)?),
+                // 带步长的单个数值表示从该值到最大值
+                // A single value with a step runs from that value up to the maximum
+                None if part.contains('/') => (number(range)?, max),
+                None => {
+                    let value = number(range)?;
+                    (value, value)
+                }
+            },
+        };
+        if start > end {
+            return Err(invalid(expression, &format!("'{}' is an empty range", part)));
+        }
+        for value in (start..=end).step_by(step as usize) {
+            allowed[(value - min) as usize] = true;
+        }
+    }
+    Ok(allowed)
+}
@@ -0,0 +1,486 @@
+//! 定时任务：按 cron 表达式、固定间隔或延迟运行已注册的智能体任务，用于无人值守的监控与报告
+//! Scheduled tasks: run registered agent tasks on cron expressions, fixed intervals or after a delay, for unattended
+//! monitoring and reporting agents
+//!
+//! 同一任务的上一次运行尚未结束时，新的触发被跳过；失败的运行按任务的重试策略重试。
+//! 运行的开始、结果与跳过都以 `ChatEvent` 发给调度器上注册的事件处理函数，并发布到 `EventBus`。
+//! A trigger is skipped while the previous run of the same task is still going; failed runs are retried according to
+//! the task's retry policy. Starts, results and skips are delivered as `ChatEvent`s to the event handlers registered
+//! on the scheduler and published to the `EventBus`.
+
+pub mod cron;
+
+// 标准库
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+// 错误处理
+use error_stack::{Report, Result};
+use thiserror::Error;
+
+// 异步
+use futures::FutureExt;
+use futures::future::BoxFuture;
+use tokio::task::AbortHandle;
+use tokio::time::Instant;
+
+// 时间处理
+use chrono::Utc;
+
+// 观测诊断
+use tracing::warn;
+
+// 唯一标识
+use uuid::Uuid;
+
+// 项目内部模块
+use crate::chat::builder::SingleChatBuilder;
+use crate::chat::chat_base::ChatError;
+use crate::chat::event::{ChatEvent, EventHandlers, EventSource};
+use crate::error::ReportExt;
+
+pub use cron::CronSchedule;
+
+/// 调度器错误枚举
+/// Scheduler error enum
+#[derive(Debug, Error)]
+pub enum SchedulerError {
+    #[error("Invalid schedule: {0}")]
+    InvalidSchedule(String),
+
+    #[error("Task '{0}' is already registered")]
+    DuplicateTask(String),
+
+    #[error("Task '{0}' not found")]
+    UnknownTask(String),
+}
+
+/// 任务的触发时间
+/// When a task fires
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Schedule {
+    /// 延迟后运行一次
+    /// Run once after the delay
+    After(Duration),
+
+    /// 按固定间隔运行，首次在一个间隔之后；间隔从计划时间算起，不受运行时长影响
+    /// Run at a fixed interval, the first time one interval from now; intervals count from the planned times, so run
+    /// durations do not make the schedule drift
+    Every(Duration),
+
+    /// 按 cron 表达式运行
+    /// Run on a cron expression
+    Cron(CronSchedule),
+}
+
+impl Schedule {
+    pub fn cron(expression: &str) -> Result<Self, SchedulerError> {
+        Ok(Self::Cron(CronSchedule::parse(expression)?))
+    }
+}
+
+/// 失败运行的重试策略：等待时间从 `initial_delay` 开始按 `multiplier` 增长，不超过 `max_delay`
+/// Retry policy of failed runs: the wait starts at `initial_delay` and grows by `multiplier`, capped at `max_delay`
+#[derive(Clone, Debug, PartialEq)]
+pub struct RetryPolicy {
+    /// 首次运行之外的最大重试次数
+    /// Maximum retries beyond the first run
+    pub max_retries: u32,
+
+    pub initial_delay: Duration,
+
+    pub multiplier: f64,
+
+    pub max_delay: Duration,
+
+    /// 为 true 时只重试可重试的错误（见 `ReportExt::is_retryable`），如限流与超时
+    /// When true only retryable errors are retried (see `ReportExt::is_retryable`), such as rate limits and timeouts
+    pub retryable_only: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 2,
+            initial_delay: Duration::from_secs(1),
+            multiplier: 2.0,
+            max_delay: Duration::from_secs(60),
+            retryable_only: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn new(max_retries: u32, initial_delay: Duration) -> Self {
+        Self {
+            max_retries,
+            initial_delay,
+            ..Self::default()
+        }
+    }
+
+    /// 不重试
+    /// Never retry
+    pub fn none() -> Self {
+        Self::new(0, Duration::ZERO)
+    }
+
+    pub fn multiplier(mut self, multiplier: f64) -> Self {
+        self.multiplier = multiplier;
+        self
+    }
+
+    pub fn max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+
+    /// 设置是否也重试不可重试的错误，如配置错误与被拦截的回答
+    /// Set whether non-retryable errors, such as configuration errors and blocked answers, are retried too
+    pub fn retry_all(mut self, retry_all: bool) -> Self {
+        self.retryable_only = !retry_all;
+        self
+    }
+
+    /// 第 `attempt` 次运行失败后的等待时间，不再重试时返回 None
+    /// Wait after the failed run number `attempt`, None if no retry follows
+    pub fn delay(&self, attempt: u32, error: &Report<ChatError>) -> Option<Duration> {
+        if attempt > self.max_retries || (self.retryable_only && !error.is_retryable()) {
+            return None;
+        }
+        let factor = self.multiplier.max(1.0).powi(attempt as i32 - 1);
+        Some(self.initial_delay.mul_f64(factor).min(self.max_delay))
+    }
+}
+
+type TaskFn = Arc<dyn Fn() -> BoxFuture<'static, Result<String, ChatError>> + Send + Sync>;
+
+/// 一个定时任务：名称、触发时间、重试策略与每次运行执行的异步函数
+/// A scheduled task: its name, schedule, retry policy and the async function executed on every run
+#[derive(Clone)]
+pub struct ScheduledTask {
+    pub name: String,
+
+    pub schedule: Schedule,
+
+    pub retry: RetryPolicy,
+
+    run: TaskFn,
+}
+
+impl ScheduledTask {
+    /// 以异步函数创建任务，函数返回的文本作为运行结果
+    /// Create a task from an async function, the text it returns is the result of the run
+    pub fn new<F, Fut>(name: &str, schedule: Schedule, run: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<String, ChatError>> + Send + 'static,
+    {
+        Self {
+            name: name.to_string(),
+            schedule,
+            retry: RetryPolicy::default(),
+            run: Arc::new(move || run().boxed()),
+        }
+    }
+
+    /// 智能体任务：每次运行以构建器创建一个新对话并发送提示词，运行之间不共享对话历史
+    /// Agent task: every run creates a fresh chat from the builder and sends the prompt, runs share no history
+    pub fn agent(name: &str, schedule: Schedule, builder: SingleChatBuilder, prompt: &str) -> Self {
+        let prompt = prompt.to_string();
+        Self::new(name, schedule, move || {
+            let builder = builder.clone();
+            let prompt = prompt.clone();
+            async move { builder.build()?.get_answer(&prompt).await }
+        })
+    }
+
+    pub fn retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+}
+
+impl Debug for ScheduledTask {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ScheduledTask")
+            .field("name", &self.name)
+            .field("schedule", &self.schedule)
+            .field("retry", &self.retry)
+            .finish()
+    }
+}
+
+/// 运行结束时清除运行标记，运行中途 panic 时也不会让任务永远处于运行状态
+/// Clears the running flag when a run ends, so a run panicking midway does not leave the task running forever
+struct RunningGuard<'a>(&'a AtomicBool);
+
+impl Drop for RunningGuard<'_> {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::Release);
+    }
+}
+
+/// 已注册任务的运行状态
+/// Run state of a registered task
+struct TaskState {
+    task: ScheduledTask,
+    running: AtomicBool,
+}
+
+impl TaskState {
+    /// 在后台开始一次运行；上一次运行尚未结束时跳过并返回 false
+    /// Start a run in the background; skipped with false if the previous run has not finished
+    fn trigger(self: &Arc<Self>, events: &EventHandlers) -> bool {
+        if self.running.swap(true, Ordering::AcqRel) {
+            warn!("Scheduled task '{}' is still running, trigger skipped", self.task.name);
+            events.emit(|| ChatEvent::TaskSkipped {
+                task: self.task.name.clone(),
+            });
+            return false;
+        }
+        let state = self.clone();
+        let events = events.clone();
+        tokio::spawn(async move {
+            let _running = RunningGuard(&state.running);
+            state.execute(&events).await;
+        });
+        true
+    }
+
+    async fn execute(&self, events: &EventHandlers) {
+        let task = &self.task.name;
+        let run_id = Uuid::new_v4().to_string();
+        let started = Instant::now();
+        events.emit(|| ChatEvent::TaskStarted {
+            task: task.clone(),
+            run_id: run_id.clone(),
+        });
+
+        let mut attempt = 1;
+        loop {
+            match (self.task.run)().await {
+                Ok(output) => {
+                    events.emit(|| ChatEvent::TaskFinished {
+                        task: task.clone(),
+                        run_id,
+                        output,
+                        attempts: attempt,
+                        duration: started.elapsed(),
+                    });
+                    return;
+                }
+                Err(report) => match self.task.retry.delay(attempt, &report) {
+                    Some(delay) => {
+                        warn!("Scheduled task '{}' failed on attempt {}, retrying in {:?}: {}", task, attempt, delay, report);
+                        tokio::time::sleep(delay).await;
+                        attempt += 1;
+                    }
+                    None => {
+                        warn!("Scheduled task '{}' failed after {} attempts: {:?}", task, attempt, report);
+                        events.emit(|| ChatEvent::TaskFailed {
+                            task: task.clone(),
+                            run_id,
+                            error: report.to_string(),
+                            attempts: attempt,
+                            duration: started.elapsed(),
+                        });
+                        return;
+                    }
+                },
+            }
+        }
+    }
+
+    /// 按触发时间循环触发任务，一次性任务触发后结束
+    /// Trigger the task on its schedule in a loop, one-off tasks end after firing
+    async fn drive(self: Arc<Self>, events: EventHandlers) {
+        let mut next = Instant::now();
+        let mut last_fire = Utc::now();
+        loop {
+            match &self.task.schedule {
+                Schedule::After(delay) => {
+                    tokio::time::sleep(*delay).await;
+                    self.trigger(&events);
+                    return;
+                }
+                Schedule::Every(interval) => {
+                    next += *interval;
+                    tokio::time::sleep_until(next).await;
+                }
+                Schedule::Cron(cron) => {
+                    // 从上一次触发时间起算，时钟稍有偏差时也不会在同一分钟内重复触发
+                    // Counted from the last fire time, so a slightly early clock does not fire twice in one minute
+                    let Some(at) = cron.next_after(Utc::now().max(last_fire)) else {
+                        warn!("Cron schedule '{}' of task '{}' never fires", cron, self.task.name);
+                        return;
+                    };
+                    tokio::time::sleep((at - Utc::now()).to_std().unwrap_or_default()).await;
+                    last_fire = at;
+                }
+            }
+            self.trigger(&events);
+        }
+    }
+}
+
+/// 定时任务调度器，需要在 tokio 运行时中使用
+/// Scheduler of timed tasks, used inside a tokio runtime
+///
+/// 调度器被丢弃或调用 `stop` 后不再触发任务，正在进行的运行仍会完成并发出结果。
+/// Once the scheduler is dropped or `stop` is called no more tasks fire, runs in progress still complete and
+/// deliver their results.
+///
+/// ```ignore
+/// let mut scheduler = Scheduler::new();
+/// scheduler.on_event(|event| {
+///     if let ChatEvent::TaskFinished { task, output, .. } = event {
+///         println!("{}: {}", task, output);
+///     }
+/// });
+/// scheduler.add(ScheduledTask::agent(
+///     "daily-report",
+///     Schedule::cron("0 9 * * 1-5")?,
+///     SingleChat::builder().capability(ModelCapability::ToolUse).tools(tools),
+///     "汇总昨天的告警并给出处理建议",
+/// ))?;
+/// scheduler.start();
+/// ```
+pub struct Scheduler {
+    tasks: Vec<Arc<TaskState>>,
+    loops: Vec<(String, AbortHandle)>,
+    events: EventHandlers,
+    started: bool,
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self {
+            tasks: Vec::new(),
+            loops: Vec::new(),
+            events: EventHandlers::new(EventSource::Scheduler),
+            started: false,
+        }
+    }
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 注册事件处理函数，接收任务的开始、结果与跳过；需在 `start` 之前注册，事件同时发布到 `EventBus`
+    /// Register an event handler receiving task starts, results and skips; register it before `start`, the events
+    /// are also published to the `EventBus`
+    pub fn on_event(&mut self, handler: impl Fn(&ChatEvent) + Send + Sync + 'static) -> &mut Self {
+        self.events.add(Arc::new(handler));
+        self
+    }
+
+    /// 注册任务，调度器已启动时任务立即开始计时；名称重复或间隔为零时报错
+    /// Register a task, it starts counting right away if the scheduler is running; fails on a duplicate name or a
+    /// zero interval
+    pub fn add(&mut self, task: ScheduledTask) -> Result<&mut Self, SchedulerError> {
+        if self.tasks.iter().any(|state| state.task.name == task.name) {
+            return Err(Report::new(SchedulerError::DuplicateTask(task.name)));
+        }
+        if task.schedule == Schedule::Every(Duration::ZERO) {
+            return Err(Report::new(SchedulerError::InvalidSchedule(format!(
+                "task '{}' has a zero interval",
+                task.name
+            ))));
+        }
+        let state = Arc::new(TaskState {
+            task,
+            running: AtomicBool::new(false),
+        });
+        if self.started {
+            self.spawn(&state);
+        }
+        self.tasks.push(state);
+        Ok(self)
+    }
+
+    /// 移除任务，不再触发；正在进行的运行仍会完成
+    /// Remove a task so it no longer fires; a run in progress still completes
+    pub fn remove(&mut self, name: &str) -> Result<(), SchedulerError> {
+        let index = self.position(name)?;
+        self.tasks.remove(index);
+        self.loops.retain(|(task, handle)| {
+            if task == name {
+                handle.abort();
+            }
+            task != name
+        });
+        Ok(())
+    }
+
+    /// 开始按触发时间运行所有已注册的任务
+    /// Start running every registered task on its schedule
+    pub fn start(&mut self) {
+        if self.started {
+            return;
+        }
+        self.started = true;
+        for state in self.tasks.clone() {
+            self.spawn(&state);
+        }
+    }
+
+    /// 停止触发任务，之后可再次 `start`
+    /// Stop triggering tasks, `start` may be called again afterwards
+    pub fn stop(&mut self) {
+        self.started = false;
+        for (_, handle) in self.loops.drain(..) {
+            handle.abort();
+        }
+    }
+
+    /// 立即在后台运行一次任务，不影响其计划；上一次运行尚未结束时跳过并返回 false
+    /// Run a task once in the background right away, leaving its schedule as is; skipped with false if the previous
+    /// run has not finished
+    pub fn run_now(&self, name: &str) -> Result<bool, SchedulerError> {
+        Ok(self.tasks[self.position(name)?].trigger(&self.events))
+    }
+
+    /// 任务是否正在运行
+    /// Whether the task is running
+    pub fn is_running(&self, name: &str) -> Result<bool, SchedulerError> {
+        Ok(self.tasks[self.position(name)?].running.load(Ordering::Acquire))
+    }
+
+    /// 已注册任务的名称
+    /// Names of the registered tasks
+    pub fn tasks(&self) -> Vec<&str> {
+        self.tasks.iter().map(|state| state.task.name.as_str()).collect()
+    }
+
+    fn position(&self, name: &str) -> Result<usize, SchedulerError> {
+        self.tasks
+            .iter()
+            .position(|state| state.task.name == name)
+            .ok_or_else(|| Report::new(SchedulerError::UnknownTask(name.to_string())))
+    }
+
+    fn spawn(&mut self, state: &Arc<TaskState>) {
+        let handle = tokio::spawn(state.clone().drive(self.events.clone()));
+        self.loops.push((state.task.name.clone(), handle.abort_handle()));
+    }
+}
+
+impl Drop for Scheduler {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+impl Debug for Scheduler {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Scheduler")
+            .field("tasks", &self.tasks())
+            .field("started", &self.started)
+            .finish()
+    }
+}
@@ -0,0 +1,239 @@
+// 标准库
+use std::collections::HashSet;
+
+// 错误处理
+use error_stack::{Report, Result};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum GbnfError {
+    #[error("Unsupported schema at {0}")]
+    UnsupportedSchema(String),
+
+    #[error("Unresolved reference: {0}")]
+    UnresolvedRef(String),
+}
+
+/// 基础规则，按需加入语法
+/// Primitive rules, added to the grammar when used
+const PRIMITIVES: [(&str, &str); 7] = [
+    ("space", r#"[ \t\n]*"#),
+    ("string", r#""\"" ( [^"\\\x7F\x00-\x1F] | "\\" ( ["\\/bfnrt] | "u" [0-9a-fA-F] [0-9a-fA-F] [0-9a-fA-F] [0-9a-fA-F] ) )* "\"" space"#),
+    ("number", r#""-"? ( [0-9] | [1-9] [0-9]* ) ( "." [0-9]+ )? ( [eE] [-+]? [0-9]+ )? space"#),
+    ("integer", r#""-"? ( [0-9] | [1-9] [0-9]* ) space"#),
+    ("boolean", r#"( "true" | "false" ) space"#),
+    ("null", r#""null" space"#),
+    ("value", r#"( object | array | string | number | boolean | null )"#),
+];
+
+/// 任意 JSON 对象与数组，用于未限定结构的值
+/// Any JSON object and array, for values whose structure is not constrained
+const ANY_CONTAINERS: [(&str, &str); 2] = [
+    ("object", r#""{" space ( string ":" space value ( "," space string ":" space value )* )? "}" space"#),
+    ("array", r#""[" space ( value ( "," space value )* )? "]" space"#),
+];
+
+/// 将 JSON Schema 转换为 GBNF 语法，供 llama.cpp 等本地后端在解码时约束输出
+/// Convert a JSON Schema into a GBNF grammar, letting local backends such as llama.cpp constrain the output while
+/// decoding
+///
+/// 接受 `#[derive(JsonSchema)]` 生成的 `response_format` 外层，支持 `object`、`array`、基础类型、类型数组（如
+/// `["string", "null"]`）、`enum`、`const`、`anyOf`/`oneOf` 与文档内的 `$ref`。对象只允许声明的属性，必需属性在前。
+/// Accepts the `response_format` wrapper generated by `#[derive(JsonSchema)]`, and supports `object`, `array`,
+/// primitive types, type arrays (such as `["string", "null"]`), `enum`, `const`, `anyOf`/`oneOf` and `$ref`s within
+/// the document. Objects only allow their declared properties, required ones first.
+pub fn json_schema_to_gbnf(schema: &serde_json::Value) -> Result<String, GbnfError> {
+    let root = match schema["type"].as_str() {
+        Some("json_schema") => &schema["json_schema"]["schema"],
+        _ => schema,
+    };
+    let mut converter = Converter {
+        root,
+        rules: Vec::new(),
+        names: HashSet::new(),
+    };
+    converter.names.insert("root".to_string());
+    let body = converter.visit(root, "root")?;
+    converter.rules.insert(0, ("root".to_string(), body));
+
+    let used = converter.rules.iter().map(|(_, body)| body.as_str()).collect::<Vec<_>>().join(" ");
+    let mut grammar: Vec<String> = converter.rules.iter().map(|(name, body)| format!("{} ::= {}", name, body)).collect();
+    let value_used = mentions(&used, "value");
+    for (name, body) in PRIMITIVES.iter().chain(ANY_CONTAINERS.iter()) {
+        let needed = match *name {
+            "space" => true,
+            "value" => value_used,
+            "integer" => mentions(&used, name),
+            _ => value_used || mentions(&used, name),
+        };
+        if needed {
+            grammar.push(format!("{} ::= {}", name, body));
+        }
+    }
+    Ok(grammar.join("\n") + "\n")
+}
+
+struct Converter<'a> {
+    root: &'a serde_json::Value,
+    /// 按定义顺序排列的规则
+    /// Rules in definition order
+    rules: Vec<(String, String)>,
+    names: HashSet<String>,
+}
+
+impl Converter<'_> {
+    /// 返回匹配该模式的规则体，复杂的子模式定义为独立规则
+    /// Return the rule body matching the schema, complex sub-schemas are defined as rules of their own
+    fn visit(&mut self, schema: &serde_json::Value, path: &str) -> Result<String, GbnfError> {
+        if let Some(reference) = schema["$ref"].as_str() {
+            let target = resolve_ref(self.root, reference)
+                .ok_or_else(|| Report::new(GbnfError::UnresolvedRef(reference.to_string())))?;
+            return self.visit(target, path);
+        }
+        if let Some(value) = schema.get("const") {
+            return Ok(format!("{} space", literal(&value.to_string())));
+        }
+        if let Some(values) = schema["enum"].as_array() {
+            let mut alternatives: Vec<String> = values.iter().map(|value| literal(&value.to_string())).collect();
+            // 可为空的类型即使枚举中没有 null 也接受 null
+            // A nullable type accepts null even if the enum does not list it
+            let nullable = schema["type"].as_array().is_some_and(|types| types.iter().any(|kind| kind == "null"));
+            if nullable && !values.iter().any(|value| value.is_null()) {
+                alternatives.push(literal("null"));
+            }
+            return Ok(format!("( {} ) space", alternatives.join(" | ")));
+        }
+        if let Some(options) = schema["anyOf"].as_array().or(schema["oneOf"].as_array()) {
+            return self.alternatives(options.iter().collect(), path);
+        }
+
+        match &schema["type"] {
+            serde_json::Value::Array(types) => {
+                let options: Vec<serde_json::Value> = types
+                    .iter()
+                    .map(|kind| {
+                        let mut option = schema.clone();
+                        option["type"] = kind.clone();
+                        option
+                    })
+                    .collect();
+                self.alternatives(options.iter().collect(), path)
+            }
+            serde_json::Value::String(kind) => match kind.as_str() {
+                "object" => self.object(schema, path),
+                "array" => {
+                    let item = match schema.get("items") {
+                        Some(items) => self.visit(items, &format!("{}-item", path))?,
+                        None => "value".to_string(),
+                    };
+                    let name = self.define(&format!("{}-item", path), item);
+                    Ok(format!(r#""[" space ( {name} ( "," space {name} )* )? "]" space"#))
+                }
+                "string" | "number" | "integer" | "boolean" | "null" => Ok(kind.clone()),
+                _ => Err(Report::new(GbnfError::UnsupportedSchema(path.to_string()))
+                    .attach_printable(format!("Unknown type: {}", kind))),
+            },
+            serde_json::Value::Null => Ok("value".to_string()),
+            _ => Err(Report::new(GbnfError::UnsupportedSchema(path.to_string()))),
+        }
+    }
+
+    fn alternatives(&mut self, options: Vec<&serde_json::Value>, path: &str) -> Result<String, GbnfError> {
+        let alternatives = options
+            .into_iter()
+            .enumerate()
+            .map(|(index, option)| self.visit(option, &format!("{}-{}", path, index)))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(format!("( {} )", alternatives.join(" | ")))
+    }
+
+    fn object(&mut self, schema: &serde_json::Value, path: &str) -> Result<String, GbnfError> {
+        let Some(properties) = schema["properties"].as_object() else {
+            return Ok("object".to_string());
+        };
+        let required: HashSet<&str> = schema["required"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|name| name.as_str())
+            .collect();
+
+        let mut mandatory = Vec::new();
+        let mut optional = Vec::new();
+        for (name, property) in properties {
+            let value = self.visit(property, &format!("{}-{}", path, name))?;
+            let value = self.define(&format!("{}-{}", path, name), value);
+            let pair = format!(r#"{} space ":" space {}"#, literal(&serde_json::json!(name).to_string()), value);
+            match required.contains(name.as_str()) {
+                true => mandatory.push(pair),
+                false => optional.push(pair),
+            }
+        }
+
+        // 可选属性按声明顺序出现，逗号只出现在两个属性之间
+        // Optional properties appear in declaration order, commas only go between two properties
+        let mut body = mandatory.join(r#" "," space "#);
+        if mandatory.is_empty() {
+            if !optional.is_empty() {
+                let chains: Vec<String> = (0..optional.len())
+                    .map(|start| {
+                        let rest: Vec<String> =
+                            optional[start + 1..].iter().map(|pair| format!(r#"( "," space {} )?"#, pair)).collect();
+                        [optional[start].clone()].into_iter().chain(rest).collect::<Vec<_>>().join(" ")
+                    })
+                    .collect();
+                body = format!("( {} )?", chains.join(" | "));
+            }
+        } else {
+            for pair in &optional {
+                body.push_str(&format!(r#" ( "," space {} )?"#, pair));
+            }
+        }
+        Ok(format!(r#""{{" space {} "}}" space"#, body))
+    }
+
+    /// 定义一条规则并返回其名称，简单的规则体直接返回
+    /// Define a rule and return its name, simple rule bodies are returned as is
+    fn define(&mut self, path: &str, body: String) -> String {
+        if PRIMITIVES.iter().chain(ANY_CONTAINERS.iter()).any(|(name, _)| *name == body) {
+            return body;
+        }
+        let base: String = path
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '-' })
+            .collect();
+        let mut name = base.clone();
+        let mut counter = 1;
+        while self.names.contains(&name) || PRIMITIVES.iter().chain(ANY_CONTAINERS.iter()).any(|(n, _)| *n == name) {
+            counter += 1;
+            name = format!("{}{}", base, counter);
+        }
+        self.names.insert(name.clone());
+        self.rules.push((name.clone(), body));
+        name
+    }
+}
+
+/// 解析文档内的 `$ref`，如 `#/$defs/Address`
+/// Resolve a `$ref` within the document, such as `#/$defs/Address`
+fn resolve_ref<'a>(root: &'a serde_json::Value, reference: &str) -> Option<&'a serde_json::Value> {
+    let pointer = reference.strip_prefix('#')?;
+    root.pointer(pointer).or_else(|| {
+        // 只给出名称时在 `$defs` 与 `definitions` 中查找
+        // A bare name is looked up in `$defs` and `definitions`
+        ["$defs", "definitions"].iter().find_map(|defs| root.get(*defs)?.get(pointer.trim_start_matches('/')))
+    })
+}
+
+/// GBNF 字符串字面量
+/// GBNF string literal
+fn literal(text: &str) -> String {
+    let escaped = text.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n").replace('\r', "\\r");
+    format!("\"{}\"", escaped)
+}
+
+/// 规则体是否引用了某条规则
+/// Whether a rule body refers to a rule
+fn mentions(body: &str, name: &str) -> bool {
+    body.split(|c: char| !(c.is_ascii_alphanumeric() || c == '-')).any(|word| word == name)
+}
@@ -1,3 +1,20 @@
 pub trait JsonSchema {
     fn json_schema() -> serde_json::Value;
 }
+
+/// 将 JSON Schema 包装为 `response_format` 形式，名称与描述取自 `title` 与 `description`；已是该形式时原样返回，
+/// 供各语言绑定把宿主语言生成的 Schema 交给 `get_json_answer_with_schema`
+/// Wrap a JSON Schema into the `response_format` shape, taking the name and description from `title` and
+/// `description`; returned as is if already in that shape, letting the language bindings hand schemas generated in
+/// the host language to `get_json_answer_with_schema`
+pub fn response_format(schema: serde_json::Value) -> serde_json::Value {
+    if schema.get("json_schema").is_some() {
+        return schema;
+    }
+    let name = schema["title"].as_str().unwrap_or("output").to_string();
+    let description = schema["description"].as_str().unwrap_or(&name).to_string();
+    serde_json::json!({
+        "type": "json_schema",
+        "json_schema": {"name": name, "description": description, "schema": schema},
+    })
+}
@@ -1,2 +1,5 @@
+pub mod gbnf;
 pub mod json_schema;
+pub mod tool_docs;
+pub mod tool_error;
 pub mod tool_schema;
\ No newline at end of file
@@ -0,0 +1,250 @@
+// 标准库
+use std::fmt::Write;
+
+// 并发和同步原语
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+
+// 错误处理
+use error_stack::{Report, Result};
+
+// 序列化
+use serde::Serialize;
+use serde_json::{Value, json};
+
+// 项目内部模块
+use crate::schema::tool_schema::{ChatToolSchemaError, create_tool, get_tool_registry};
+
+/// 工具的调用示例
+/// Example call of a tool
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ToolExample {
+    /// 示例的场景说明
+    /// What the example is about
+    pub description: String,
+
+    pub arguments: Value,
+}
+
+/// 一个工具的文档：名称、描述、参数结构与示例
+/// Documentation of one tool: name, description, parameter schema and examples
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ToolDoc {
+    pub name: String,
+
+    pub description: String,
+
+    /// 参数的 JSON Schema
+    /// JSON Schema of the parameters
+    pub parameters: Value,
+
+    pub examples: Vec<ToolExample>,
+
+    /// 本地注册表中是否有同名的函数
+    /// Whether the local registry has a function of the same name
+    pub registered: bool,
+}
+
+/// 工具定义表，工具名称到文档，与函数注册表一起构成工具的唯一来源；由 `register_tool` 与函数一同登记，
+/// 交给对话的工具（`SingleChat::set_tools` 等）也自动登记，不需要另外维护
+/// Tool definition table, from tool name to documentation, the single source of tools together with the function
+/// registry; filled by `register_tool` together with the function, and tools handed to a chat (`SingleChat::set_tools`
+/// and the like) are registered automatically, so it needs no separate upkeep
+static DEFINITIONS: Lazy<DashMap<String, ToolDoc>> = Lazy::new(DashMap::new);
+
+impl ToolDoc {
+    /// 从 OpenAI 格式的工具定义创建文档，缺少的字段与组装工具提示时一样报错
+    /// Create the documentation from a tool definition in OpenAI format, missing fields fail the same way as when
+    /// assembling the tools prompt
+    pub fn from_definition(definition: &Value) -> Result<Self, ChatToolSchemaError> {
+        let function = definition.get("function").ok_or(Report::new(ChatToolSchemaError::MissingFunctionField))?;
+        let name = function["name"].as_str().ok_or(Report::new(ChatToolSchemaError::MissingFunctionName))?;
+        let description = function["description"]
+            .as_str()
+            .ok_or(Report::new(ChatToolSchemaError::MissingFunctionDescription))?;
+        let parameters = function
+            .get("parameters")
+            .ok_or(Report::new(ChatToolSchemaError::MissingFunctionParameters))?;
+        if parameters.get("properties").is_none() {
+            return Err(Report::new(ChatToolSchemaError::MissingFunctionProperties));
+        }
+        Ok(Self {
+            name: name.to_string(),
+            description: description.to_string(),
+            parameters: parameters.clone(),
+            examples: Vec::new(),
+            registered: get_tool_registry().contains_key(name),
+        })
+    }
+
+    /// OpenAI 格式的工具定义，可直接交给对话的 `tools`
+    /// Tool definition in OpenAI format, ready for the `tools` of a chat
+    pub fn to_definition(&self) -> Value {
+        json!({
+            "type": "function",
+            "function": {"name": self.name, "description": self.description, "parameters": self.parameters},
+        })
+    }
+
+    /// 渲染为 Markdown 小节：描述、参数表与示例
+    /// Render as a Markdown section: description, parameter table and examples
+    pub fn to_markdown(&self) -> String {
+        let mut markdown = format!("## `{}`\n\n{}\n", self.name, self.description);
+        if !self.registered {
+            markdown.push_str("\n> No function is registered under this name.\n");
+        }
+
+        let mut rows = Vec::new();
+        parameter_rows(&self.parameters, "", &mut rows);
+        if rows.is_empty() {
+            markdown.push_str("\nNo parameters.\n");
+        } else {
+            markdown.push_str("\n| Parameter | Type | Required | Description |\n|---|---|---|---|\n");
+            for row in rows {
+                markdown.push_str(&row);
+            }
+        }
+
+        if !self.examples.is_empty() {
+            markdown.push_str("\n**Examples**\n");
+            for example in &self.examples {
+                let _ = write!(
+                    markdown,
+                    "\n{}\n\n```json\n{}\n```\n",
+                    example.description,
+                    serde_json::to_string_pretty(&example.arguments).unwrap_or_default(),
+                );
+            }
+        }
+        markdown
+    }
+}
+
+/// 把参数结构展开为表格行，嵌套对象的属性以 `父.子` 命名
+/// Flatten the parameter schema into table rows, properties of nested objects are named `parent.child`
+fn parameter_rows(schema: &Value, prefix: &str, rows: &mut Vec<String>) {
+    let Some(properties) = schema["properties"].as_object() else {
+        return;
+    };
+    let required: Vec<&str> = schema["required"].as_array().into_iter().flatten().filter_map(Value::as_str).collect();
+    for (name, property) in properties {
+        // 与工具提示一致，跳过思维链字段
+        // Skip the chain-of-thought field, as the tools prompt does
+        if name == "cot" {
+            continue;
+        }
+        let path = format!("{}{}", prefix, name);
+        let kind = match &property["type"] {
+            Value::String(kind) => kind.clone(),
+            Value::Array(kinds) => kinds.iter().filter_map(Value::as_str).collect::<Vec<_>>().join(" \\| "),
+            _ => String::new(),
+        };
+        let mut description = property["description"].as_str().unwrap_or_default().replace('|', "\\|");
+        if let Some(values) = property["enum"].as_array() {
+            let values: Vec<String> = values.iter().map(|value| format!("`{}`", value)).collect();
+            let _ = write!(description, " One of: {}.", values.join(", "));
+        }
+        let required = if required.contains(&name.as_str()) { "yes" } else { "no" };
+        rows.push(format!("| `{}` | {} | {} | {} |\n", path, kind, required, description.trim()));
+        if property["type"] == "object" {
+            parameter_rows(property, &format!("{}.", path), rows);
+        }
+    }
+}
+
+/// 登记工具定义，同名的定义被替换，已有的示例保留
+/// Register a tool definition, replacing one of the same name while keeping its examples
+///
+/// # 参数 (Parameters)
+/// * `definition` - OpenAI 格式的工具定义，如 `#[tool_schema_derive]` 生成的 `*_schema()` 的返回值
+///   - Tool definition in OpenAI format, such as what the `*_schema()` generated by
+///     `#[tool_schema_derive]` returns
+pub fn register_tool_definition(definition: &Value) -> Result<(), ChatToolSchemaError> {
+    let mut doc = ToolDoc::from_definition(definition)?;
+    if let Some(existing) = DEFINITIONS.get(&doc.name) {
+        doc.examples = existing.examples.clone();
+    }
+    DEFINITIONS.insert(doc.name.clone(), doc);
+    Ok(())
+}
+
+/// 同时登记工具的定义与实现函数，函数以定义中的名称加入函数注册表
+/// Register the definition of a tool together with the function implementing it, the function joins the function
+/// registry under the name of the definition
+///
+/// # 参数 (Parameters)
+/// * `definition` - OpenAI 格式的工具定义，如 `#[tool_schema_derive]` 生成的 `*_schema()` 的返回值
+///   Tool definition in OpenAI format, such as what the `*_schema()` generated by `#[tool_schema_derive]` returns
+/// * `function` - 以参数调用工具的函数
+///   Function calling the tool with its arguments
+pub fn register_tool(
+    definition: &Value,
+    function: impl Fn(Value) -> Result<Value, ChatToolSchemaError> + Send + Sync + 'static,
+) -> Result<(), ChatToolSchemaError> {
+    let doc = ToolDoc::from_definition(definition)?;
+    let (name, function) = create_tool(&doc.name, function);
+    get_tool_registry().insert(name, function);
+    register_tool_definition(definition)
+}
+
+/// 为已登记的工具添加调用示例，示例同时出现在文档与工具提示中
+/// Add an example call to a registered tool, the example shows up in both the documentation and the tools prompt
+pub fn add_tool_example(name: &str, description: &str, arguments: Value) -> Result<(), ChatToolSchemaError> {
+    let mut doc = DEFINITIONS
+        .get_mut(name)
+        .ok_or_else(|| Report::new(ChatToolSchemaError::UnknownTool(name.to_string())))?;
+    doc.examples.push(ToolExample {
+        description: description.to_string(),
+        arguments,
+    });
+    Ok(())
+}
+
+/// 移除工具定义
+/// Remove a tool definition
+pub fn remove_tool_definition(name: &str) {
+    DEFINITIONS.remove(name);
+}
+
+/// 已登记工具的示例，组装工具提示时使用
+/// Examples of a registered tool, used when assembling the tools prompt
+pub(crate) fn tool_examples(name: &str) -> Vec<ToolExample> {
+    DEFINITIONS.get(name).map(|doc| doc.examples.clone()).unwrap_or_default()
+}
+
+/// 当前登记的全部工具文档，按名称排序
+/// Documentation of every registered tool, sorted by name
+pub fn tool_docs() -> Vec<ToolDoc> {
+    let mut docs: Vec<ToolDoc> = DEFINITIONS
+        .iter()
+        .map(|entry| ToolDoc {
+            registered: get_tool_registry().contains_key(entry.key()),
+            ..entry.value().clone()
+        })
+        .collect();
+    docs.sort_by(|a, b| a.name.cmp(&b.name));
+    docs
+}
+
+/// 当前登记的全部工具定义（OpenAI 格式），可直接交给对话
+/// Every registered tool definition in OpenAI format, ready to hand to a chat
+pub fn tool_definitions() -> Vec<Value> {
+    tool_docs().iter().map(ToolDoc::to_definition).collect()
+}
+
+/// 将工具文档渲染为 Markdown，供应用文档引用
+/// Render tool documentation as Markdown, for inclusion in app docs
+pub fn render_tools_markdown(docs: &[ToolDoc]) -> String {
+    let mut markdown = String::from("# Tools\n");
+    for doc in docs {
+        markdown.push('\n');
+        markdown.push_str(&doc.to_markdown());
+    }
+    markdown
+}
+
+/// 将工具文档渲染为 JSON 数组
+/// Render tool documentation as a JSON array
+pub fn render_tools_json(docs: &[ToolDoc]) -> Value {
+    json!(docs)
+}
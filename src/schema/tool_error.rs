@@ -0,0 +1,99 @@
+// 数据序列化
+use serde::{Deserialize, Serialize};
+
+// 错误处理
+use error_stack::Report;
+
+// 项目内部模块
+use crate::schema::tool_schema::ChatToolSchemaError;
+
+/// 工具失败时交给模型的结果格式说明，随工具提示一起展示给模型
+/// Description of the result the model gets when a tool fails, shown to the model together with the tools prompt
+pub const TOOL_ERROR_PROMPT: &str = "工具调用失败时，结果为JSON：{\"error\": {\"error_type\": 错误类型, \"message\": 错误说明, \
+     \"retryable\": 是否值得重试, \"suggestion\": 修正建议（可能没有）}}。错误类型为 invalid_call（调用格式无法解析）、\
+     invalid_arguments（参数不符合定义）、not_found（没有该工具）、execution_failed（工具执行出错）之一。\
+     retryable 为 true 时按建议修正后重新调用，为 false 时不要以相同的方式重试。";
+
+/// 工具错误的类型
+/// Kind of a tool error
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ToolErrorType {
+    /// 调用格式无法解析
+    /// The call could not be parsed
+    InvalidCall,
+
+    /// 参数不符合工具的定义
+    /// The arguments do not match the tool's definition
+    InvalidArguments,
+
+    /// 没有该名称的工具
+    /// No tool has that name
+    NotFound,
+
+    /// 工具执行出错
+    /// The tool failed while running
+    ExecutionFailed,
+}
+
+/// 交给模型的结构化工具错误，格式见 `TOOL_ERROR_PROMPT`，帮助模型在智能体循环中自行纠正
+/// Structured tool error handed to the model, in the format of `TOOL_ERROR_PROMPT`, helping the model recover by
+/// itself in agent loops
+///
+/// 工具可以把它附加到返回的错误上以给出自己的说明与建议，如
+/// `Report::new(ChatToolSchemaError::FunctionCallError).attach(ToolError::new(...))`；
+/// 没有附加时按错误类型生成。
+/// Tools can attach one to the error they return to give their own message and suggestion, such as
+/// `Report::new(ChatToolSchemaError::FunctionCallError).attach(ToolError::new(...))`; without one it is derived
+/// from the error.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ToolError {
+    pub error_type: ToolErrorType,
+
+    pub message: String,
+
+    /// 修正后重试是否可能成功
+    /// Whether retrying after a correction may succeed
+    pub retryable: bool,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub suggestion: Option<String>,
+}
+
+impl ToolError {
+    pub fn new(error_type: ToolErrorType, message: &str, retryable: bool) -> Self {
+        Self {
+            error_type,
+            message: message.to_string(),
+            retryable,
+            suggestion: None,
+        }
+    }
+
+    pub fn suggestion(mut self, suggestion: &str) -> Self {
+        self.suggestion = Some(suggestion.to_string());
+        self
+    }
+
+    /// 工具返回的错误：优先使用工具附加的 `ToolError`，否则参数解析失败视为可重试的参数错误，其余为执行错误
+    /// Error returned by a tool: the `ToolError` attached by the tool if any; otherwise failing to parse the
+    /// parameters is a retryable argument error and everything else an execution error
+    pub fn from_report(report: &Report<ChatToolSchemaError>) -> Self {
+        if let Some(error) = report.downcast_ref::<ToolError>() {
+            return error.clone();
+        }
+        match report.current_context() {
+            error @ ChatToolSchemaError::ParamsParseError(..) => {
+                Self::new(ToolErrorType::InvalidArguments, &error.to_string(), true)
+                    .suggestion("按工具的参数定义修正参数后重新调用")
+            }
+            error => Self::new(ToolErrorType::ExecutionFailed, &error.to_string(), false),
+        }
+    }
+
+    /// 交给模型的工具结果
+    /// The tool result handed to the model
+    pub fn to_output(&self) -> String {
+        serde_json::to_string_pretty(&serde_json::json!({ "error": self })).unwrap_or_default()
+    }
+}
@@ -4,7 +4,9 @@ use once_cell::sync::OnceCell;
 use regex::Regex;
 use std::sync::Arc;
 use thiserror::Error;
+use crate::chat::chat_single::SingleChat;
 use crate::chat::chat_tool::ChatTool;
+use crate::chat::subagent;
 // 引入 thiserror
 
 // 定义错误类型
@@ -28,10 +30,12 @@ pub enum ChatToolSchemaError {
     ResultParseError(String),
     #[error("Failed to call function")]
     FunctionCallError,
+    #[error("Unknown tool: {0}")]
+    UnknownTool(String),
 }
 
 // 修改 ToolFunction 类型定义，使用 error_stack::Result
-type ToolFunction = Arc<dyn Fn(serde_json::Value) -> Result<serde_json::Value, ChatToolSchemaError> + Send + Sync>;
+pub type ToolFunction = Arc<dyn Fn(serde_json::Value) -> Result<serde_json::Value, ChatToolSchemaError> + Send + Sync>;
 
 static REGISTRY: OnceCell<DashMap<String, ToolFunction>> = OnceCell::new();
 
@@ -51,6 +55,41 @@ pub fn get_tool_function(name: &str) -> Option<ToolFunction> {
     get_tool_registry().get(name).map(|entry| entry.value().clone())
 }
 
+/// 本地工具注册表：除了 `get_tool_registry` 中的函数，还可以把对话注册为子智能体，由已有的工具调用机制递归地组合智能体
+/// The local tool registry: besides the functions of `get_tool_registry`, chats can be registered as sub-agents,
+/// composing agents recursively through the existing tool-call mechanism
+///
+/// ```ignore
+/// let researcher = SingleChat::builder().api("gpt-4o").system("你负责查找资料").build()?;
+/// let tool = ToolRegistry::register_agent("researcher", researcher);
+/// chat.set_tools(vec![tool])?;
+/// ```
+pub struct ToolRegistry;
+
+impl ToolRegistry {
+    /// 以 `name` 为工具名称注册子智能体，返回交给 `set_tools` 的工具定义；工具的参数是任务描述，结果是子智能体的最终回答，
+    /// 描述取自对话的系统提示词。子智能体在调用方的截止时间内运行，并加入调用方的事务，见 `SingleChat::run_agent`
+    /// Register a sub-agent under the tool name `name`, returning the tool definition to pass to `set_tools`; the
+    /// tool's argument is a task description and its result is the sub-agent's final answer, described by the chat's
+    /// system prompt. The sub-agent runs within the caller's deadline and joins the caller's transaction, see
+    /// `SingleChat::run_agent`
+    pub fn register_agent(name: &str, chat: SingleChat) -> serde_json::Value {
+        subagent::register_agent(name, None, chat)
+    }
+
+    /// 同 `register_agent`，但指定工具描述
+    /// Like `register_agent`, but with the given tool description
+    pub fn register_agent_with_description(name: &str, description: &str, chat: SingleChat) -> serde_json::Value {
+        subagent::register_agent(name, Some(description), chat)
+    }
+
+    /// 移除注册的子智能体，返回是否存在
+    /// Remove a registered sub-agent, returning whether it existed
+    pub fn remove_agent(name: &str) -> bool {
+        subagent::remove_agent(name)
+    }
+}
+
 pub async fn tool_use(text_answer: &str, tools_schema: serde_json::Value) -> Result<(), ChatToolSchemaError> {
     let functions_calling = extract_tool_uses(text_answer);
     for function_calling in functions_calling {
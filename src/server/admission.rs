@@ -0,0 +1,273 @@
+//! 准入控制：限制连接数、每个客户端排队的消息数与全服务待处理的消息数，负载过高时按可预期的方式降级
+//! Admission control: bounds connections, the messages queued per client and the messages pending across the
+//! service, so the server degrades predictably under load
+//!
+//! - 连接数已满或服务饱和时，新的连接请求以 429 与 `Retry-After` 拒绝；
+//! - 客户端排队已满或服务饱和时，新消息以 `overloaded` 帧拒绝，连接保持；
+//! - 已打开的连接不会因负载被关闭，服务恢复后可以照常发送消息；
+//! - 后台会话的消息只能占用全服务待处理额度的一部分，其余留给前台会话。
+//!
+//! - New connections are refused with 429 and `Retry-After` when the connections are used up or the service is
+//!   saturated;
+//! - New messages are refused with an `overloaded` frame when the client's queue is full or the service is
+//!   saturated, keeping the connection;
+//! - Open connections are never closed because of load, they can send messages as usual once the service recovers;
+//! - Messages of background sessions can only take part of the service's pending capacity, the rest is kept for
+//!   foreground sessions.
+
+// 标准库
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+// 并发和同步原语
+use dashmap::DashMap;
+use dashmap::mapref::entry::Entry;
+
+// 网络服务
+use axum::http::{StatusCode, header};
+use axum::response::{IntoResponse, Response};
+
+// 项目内部模块
+use crate::chat::queue::QueuePriority;
+
+/// 准入限制
+/// Admission limits
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AdmissionLimits {
+    /// 同时打开的连接数上限
+    /// Most connections open at once
+    pub max_connections: usize,
+
+    /// 每个客户端排队或正在回答的消息数上限，客户端按对端IP区分
+    /// Most messages queued or being answered per client, clients being told apart by peer IP
+    pub max_queued_per_client: usize,
+
+    /// 全服务排队或正在回答的消息数上限，达到时服务饱和
+    /// Most messages queued or being answered across the service, the service is saturated when reached
+    pub max_pending: usize,
+
+    /// 全服务排队或正在回答的后台消息数上限（见 `QueuePriority`），应小于 `max_pending` 以便为前台消息保留余量
+    /// Most background messages (see `QueuePriority`) queued or being answered across the service, should be below
+    /// `max_pending` to keep headroom for foreground messages
+    pub max_background_pending: usize,
+
+    /// 每个连接上同时打开的会话数上限
+    /// Most sessions open at once per connection
+    pub max_sessions_per_connection: usize,
+
+    /// 拒绝时建议客户端等待的时间
+    /// Time clients are advised to wait when refused
+    pub retry_after: Duration,
+
+    /// 每个连接上等待发往客户端的帧数上限，缓冲已满时回答暂停，直到客户端读取
+    /// Most frames waiting to be sent to the client per connection, answers pause while the buffer is full until the
+    /// client reads
+    pub outbound_frames: usize,
+}
+
+impl Default for AdmissionLimits {
+    fn default() -> Self {
+        Self {
+            max_connections: 1024,
+            max_queued_per_client: 16,
+            max_pending: 256,
+            max_background_pending: 128,
+            max_sessions_per_connection: 16,
+            retry_after: Duration::from_secs(5),
+            outbound_frames: 256,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Inner {
+    limits: AdmissionLimits,
+    connections: AtomicUsize,
+    pending: AtomicUsize,
+    background: AtomicUsize,
+    clients: DashMap<String, usize>,
+}
+
+/// 准入控制器，克隆共享同一份计数
+/// Admission controller, clones share the same counters
+#[derive(Clone, Debug)]
+pub struct Admission {
+    inner: Arc<Inner>,
+}
+
+impl Default for Admission {
+    fn default() -> Self {
+        Self::new(AdmissionLimits::default())
+    }
+}
+
+impl Admission {
+    pub fn new(limits: AdmissionLimits) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                limits,
+                connections: AtomicUsize::new(0),
+                pending: AtomicUsize::new(0),
+                background: AtomicUsize::new(0),
+                clients: DashMap::new(),
+            }),
+        }
+    }
+
+    pub fn limits(&self) -> &AdmissionLimits {
+        &self.inner.limits
+    }
+
+    pub fn connections(&self) -> usize {
+        self.inner.connections.load(Ordering::SeqCst)
+    }
+
+    /// 全服务排队或正在回答的消息数
+    /// Messages queued or being answered across the service
+    pub fn pending(&self) -> usize {
+        self.inner.pending.load(Ordering::SeqCst)
+    }
+
+    /// 全服务排队或正在回答的后台消息数
+    /// Background messages queued or being answered across the service
+    pub fn background_pending(&self) -> usize {
+        self.inner.background.load(Ordering::SeqCst)
+    }
+
+    pub fn is_saturated(&self) -> bool {
+        self.pending() >= self.inner.limits.max_pending
+    }
+
+    /// 接受客户端的新连接，连接数已满或服务饱和时返回 None
+    /// Accept a new connection of the client, None if the connections are used up or the service is saturated
+    pub fn connect(&self, client: &str) -> Option<ConnectionPermit> {
+        if self.is_saturated() {
+            return None;
+        }
+        let max = self.inner.limits.max_connections;
+        self.inner
+            .connections
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| (count < max).then_some(count + 1))
+            .ok()?;
+        Some(ConnectionPermit {
+            admission: self.clone(),
+            client: client.to_string(),
+            pending: Arc::new(AtomicUsize::new(0)),
+        })
+    }
+
+    /// 拒绝连接请求的响应：429 与 `Retry-After`
+    /// Response refusing a connection request: 429 with `Retry-After`
+    pub fn too_many_requests(&self) -> Response {
+        let seconds = self.inner.limits.retry_after.as_secs_f64().ceil() as u64;
+        (
+            StatusCode::TOO_MANY_REQUESTS,
+            [(header::RETRY_AFTER, seconds.to_string())],
+            "Server is overloaded, retry later",
+        )
+            .into_response()
+    }
+}
+
+/// 已接受的连接，释放时连接数减一
+/// An accepted connection, the connection count goes down by one when it is dropped
+#[derive(Debug)]
+pub struct ConnectionPermit {
+    admission: Admission,
+    client: String,
+    pending: Arc<AtomicUsize>,
+}
+
+impl ConnectionPermit {
+    pub fn client(&self) -> &str {
+        &self.client
+    }
+
+    /// 连接上没有排队或正在回答的消息
+    /// The connection has no messages queued or being answered
+    pub fn is_idle(&self) -> bool {
+        self.pending.load(Ordering::SeqCst) == 0
+    }
+
+    /// 接受连接上的一条消息，客户端排队已满或服务饱和时返回 None；返回的许可在消息回答完后释放
+    /// Accept a message on the connection, None if the client's queue is full or the service is saturated; the
+    /// returned permit is released once the message is answered
+    pub fn admit(&self) -> Option<RequestPermit> {
+        self.admit_with_priority(QueuePriority::default())
+    }
+
+    /// 按给定优先级接受连接上的一条消息，后台消息已达上限时也返回 None
+    /// Accept a message on the connection at the given priority, also None once the background messages are at
+    /// their limit
+    pub fn admit_with_priority(&self, priority: QueuePriority) -> Option<RequestPermit> {
+        let inner = &self.admission.inner;
+        let limits = &inner.limits;
+        let background = priority.is_background();
+        match inner.clients.entry(self.client.clone()) {
+            Entry::Occupied(entry) if *entry.get() >= limits.max_queued_per_client => return None,
+            entry => {
+                if background {
+                    inner
+                        .background
+                        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| {
+                            (count < limits.max_background_pending).then_some(count + 1)
+                        })
+                        .ok()?;
+                }
+                inner
+                    .pending
+                    .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| {
+                        (count < limits.max_pending).then_some(count + 1)
+                    })
+                    .inspect_err(|_| {
+                        if background {
+                            inner.background.fetch_sub(1, Ordering::SeqCst);
+                        }
+                    })
+                    .ok()?;
+                *entry.or_insert(0) += 1;
+            }
+        }
+        self.pending.fetch_add(1, Ordering::SeqCst);
+        Some(RequestPermit {
+            admission: self.admission.clone(),
+            client: self.client.clone(),
+            connection: self.pending.clone(),
+            background,
+        })
+    }
+}
+
+impl Drop for ConnectionPermit {
+    fn drop(&mut self) {
+        self.admission.inner.connections.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// 已接受的一条消息，释放时各项计数减一
+/// An accepted message, every count goes down by one when it is dropped
+#[derive(Debug)]
+pub struct RequestPermit {
+    admission: Admission,
+    client: String,
+    connection: Arc<AtomicUsize>,
+    background: bool,
+}
+
+impl Drop for RequestPermit {
+    fn drop(&mut self) {
+        let inner = &self.admission.inner;
+        if let Entry::Occupied(mut entry) = inner.clients.entry(self.client.clone()) {
+            *entry.get_mut() -= 1;
+            if *entry.get() == 0 {
+                entry.remove();
+            }
+        }
+        self.connection.fetch_sub(1, Ordering::SeqCst);
+        if self.background {
+            inner.background.fetch_sub(1, Ordering::SeqCst);
+        }
+        inner.pending.fetch_sub(1, Ordering::SeqCst);
+    }
+}
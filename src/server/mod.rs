@@ -0,0 +1,32 @@
+//! 以网络服务的形式托管对话，需要启用 `server` 特性
+//! Hosting chats as a network service, requires the `server` feature
+//!
+//! ```no_run
+//! # async fn run() -> std::io::Result<()> {
+//! rhine::server::serve("127.0.0.1:8080".parse().unwrap()).await
+//! # }
+//! ```
+
+pub mod admission;
+pub mod resume;
+pub mod websocket;
+
+// 标准库
+use std::net::SocketAddr;
+
+// 网络服务
+use axum::Router;
+
+/// 所有端点组成的路由，可并入应用自己的 axum 服务
+/// Router with every endpoint, can be merged into the application's own axum service
+pub fn router() -> Router {
+    websocket::router()
+}
+
+/// 在指定地址上运行服务，直到出错；连接信息随请求提供，准入控制按对端IP区分客户端
+/// Run the service on the address until it fails; connection info comes with requests so admission control tells
+/// clients apart by peer IP
+pub async fn serve(addr: SocketAddr) -> std::io::Result<()> {
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    axum::serve(listener, router().into_make_service_with_connect_info::<SocketAddr>()).await
+}
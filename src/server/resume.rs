@@ -0,0 +1,193 @@
+//! 会话恢复：打开会话时发给客户端一个不透明的恢复令牌，对话在每次回答后保存到存储中，客户端断开后可凭令牌重新连接并继续同一对话
+//! Session resumption: clients get an opaque resumption token when opening a session, the conversation is saved to
+//! the store after every answer, and a client that dropped can reconnect with the token to continue the same chat
+//!
+//! 令牌以共享密钥签名，只包含对话ID与过期时间，服务端不保存令牌；共用同一存储后端与密钥的多个副本都能接受彼此签发的令牌，
+//! 前端因此可以无状态地水平扩展。
+//! Tokens are signed with a shared secret and only carry the conversation id and expiry, the server keeps no token
+//! state; replicas sharing the same storage backend and secret accept each other's tokens, so frontends can scale
+//! horizontally without state.
+//!
+//! 同一对话同时只应在一个连接上打开，并发的写入以 `store.conflict` 错误帧拒绝，不会互相覆盖；写入被拒绝的会话随即关闭，
+//! 客户端可凭令牌重新打开，从存储中的最新版本继续。
+//! A conversation should be open on one connection at a time, concurrent writes are refused with a `store.conflict`
+//! error frame instead of clobbering each other; the session whose write was refused is then closed, and the client
+//! can reopen it with the token to continue from the latest version in the store.
+
+// 标准库
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// 错误处理
+use error_stack::{Report, Result};
+use thiserror::Error;
+
+// 编码与哈希
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use uuid::Uuid;
+
+// 项目内部模块
+use crate::chat::message::Session;
+use crate::chat::store::{SessionStore, StoreError, StoredSession};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// 令牌默认的有效期
+/// Default lifetime of tokens
+pub const DEFAULT_TOKEN_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// 会话恢复错误枚举
+/// Session resumption error enum
+#[derive(Debug, Error)]
+pub enum ResumeError {
+    /// 令牌格式错误或签名不符
+    /// The token is malformed or its signature does not match
+    #[error("Resumption token is invalid")]
+    InvalidToken,
+
+    /// 令牌已过期
+    /// The token has expired
+    #[error("Resumption token has expired")]
+    Expired,
+
+    /// 存储中没有令牌对应的对话，可能已被保留策略清理
+    /// The store has no conversation for the token, it may have been cleaned up by a retention policy
+    #[error("Conversation {0} is not in the store")]
+    UnknownConversation(String),
+
+    /// 服务未开启会话恢复
+    /// Session resumption is not enabled on the server
+    #[error("Session resumption is not enabled")]
+    Disabled,
+
+    /// 读写存储失败
+    /// Reading or writing the store failed
+    #[error("Conversation store failed")]
+    Store,
+}
+
+/// 会话恢复的设置：保存对话的存储与签名令牌的密钥，克隆共享同一存储
+/// Session resumption settings: the store conversations are saved to and the secret tokens are signed with, clones
+/// share the same store
+#[derive(Clone)]
+pub struct Resumption {
+    store: Arc<dyn SessionStore>,
+    secret: Arc<[u8]>,
+    ttl: Duration,
+}
+
+impl std::fmt::Debug for Resumption {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Resumption").field("ttl", &self.ttl).finish_non_exhaustive()
+    }
+}
+
+/// 连接上一个可恢复会话对应的对话及其在存储中的版本号
+/// The conversation behind a resumable session on a connection, with its revision in the store
+#[derive(Clone, Debug)]
+pub(crate) struct Conversation {
+    pub id: String,
+    pub revision: u64,
+}
+
+impl Resumption {
+    /// 创建会话恢复设置
+    /// Create session resumption settings
+    ///
+    /// # 参数 (Parameters)
+    /// * `store` - 所有副本共用的对话存储
+    ///   Conversation store shared by every replica
+    /// * `secret` - 签名令牌的密钥，所有副本必须相同，应足够长且保密
+    ///   Secret signing the tokens, must be the same on every replica, long and kept private
+    pub fn new(store: Arc<dyn SessionStore>, secret: impl AsRef<[u8]>) -> Self {
+        Self {
+            store,
+            secret: Arc::from(secret.as_ref()),
+            ttl: DEFAULT_TOKEN_TTL,
+        }
+    }
+
+    /// 设置令牌的有效期，每次打开或恢复会话都会签发新的令牌
+    /// Set the lifetime of tokens, a new token is issued every time a session is opened or resumed
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    pub fn store(&self) -> &Arc<dyn SessionStore> {
+        &self.store
+    }
+
+    /// 为对话签发恢复令牌
+    /// Issue a resumption token for a conversation
+    pub fn issue(&self, conversation: &str) -> String {
+        let expires_at = (SystemTime::now() + self.ttl).duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        let payload = format!("{}.{}", conversation, expires_at);
+        format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(&payload),
+            URL_SAFE_NO_PAD.encode(self.mac(payload.as_bytes()).finalize().into_bytes())
+        )
+    }
+
+    /// 校验令牌，返回对应的对话ID
+    /// Verify a token, returning its conversation id
+    pub fn verify(&self, token: &str) -> Result<String, ResumeError> {
+        let invalid = || Report::new(ResumeError::InvalidToken);
+        let (payload, signature) = token.split_once('.').ok_or_else(invalid)?;
+        let payload = URL_SAFE_NO_PAD.decode(payload).map_err(|_| invalid())?;
+        let signature = URL_SAFE_NO_PAD.decode(signature).map_err(|_| invalid())?;
+        // 常数时间比较签名
+        // Compare the signature in constant time
+        self.mac(&payload).verify_slice(&signature).map_err(|_| invalid())?;
+
+        let payload = String::from_utf8(payload).map_err(|_| invalid())?;
+        let (conversation, expires_at) = payload.rsplit_once('.').ok_or_else(invalid)?;
+        let expires_at: u64 = expires_at.parse().map_err(|_| invalid())?;
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        if now >= expires_at {
+            return Err(Report::new(ResumeError::Expired));
+        }
+        Ok(conversation.to_string())
+    }
+
+    /// 以密钥计算 `payload` 的 HMAC-SHA256
+    /// HMAC-SHA256 of `payload` under the secret
+    fn mac(&self, payload: &[u8]) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.secret).expect("HMAC accepts keys of any length");
+        mac.update(payload);
+        mac
+    }
+
+    /// 开始一个新对话并立即保存，令牌在第一条消息前就可使用
+    /// Start a new conversation and save it right away, so the token is usable before the first message
+    pub(crate) async fn start(&self, session: &Session) -> Result<Conversation, ResumeError> {
+        let id = Uuid::new_v4().to_string();
+        let revision = self.store.save(&id, session, 0).await.map_err(store_error)?;
+        Ok(Conversation { id, revision })
+    }
+
+    /// 按令牌读取存储中的对话
+    /// Read the conversation of a token from the store
+    pub(crate) async fn resume(&self, token: &str) -> Result<(Conversation, Session), ResumeError> {
+        let id = self.verify(token)?;
+        let Some(StoredSession { revision, session }) = self.store.load(&id).await.map_err(store_error)? else {
+            return Err(Report::new(ResumeError::UnknownConversation(id)));
+        };
+        Ok((Conversation { id, revision }, session))
+    }
+
+    /// 在存储中的版本号未变时保存对话，成功后更新版本号
+    /// Save the conversation if its revision in the store is unchanged, updating the revision on success
+    pub(crate) async fn save(&self, conversation: &mut Conversation, session: &Session) -> Result<(), StoreError> {
+        conversation.revision = self.store.save(&conversation.id, session, conversation.revision).await?;
+        Ok(())
+    }
+}
+
+fn store_error(report: Report<StoreError>) -> Report<ResumeError> {
+    report.change_context(ResumeError::Store)
+}
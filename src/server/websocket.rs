@@ -0,0 +1,572 @@
+//! WebSocket 端点（`GET /ws`）：一个连接上可打开多个命名会话，以 JSON 帧收发消息、token 与工具事件
+//! WebSocket endpoint (`GET /ws`): one connection can open several named sessions, exchanging messages, tokens and
+//! tool events as JSON frames
+//!
+//! 客户端帧 / Client frames:
+//! ```json
+//! {"type": "open", "session": "main", "capability": "long_context", "system": "be brief"}
+//! {"type": "open", "session": "main", "resume": "<resume_token>"}
+//! {"type": "open", "session": "eval", "priority": "background"}
+//! {"type": "message", "session": "main", "content": "hello"}
+//! {"type": "close", "session": "main"}
+//! ```
+//!
+//! 服务端帧 / Server frames:
+//! ```json
+//! {"type": "opened", "session": "main", "resume_token": "..."}
+//! {"type": "token", "session": "main", "delta": "hel"}
+//! {"type": "tool_call", "session": "main", "call_id": "...", "name": "search", "arguments": {}}
+//! {"type": "tool_result", "session": "main", "call_id": "...", "name": "search", "output": "...", "duration_ms": 12}
+//! {"type": "answer", "session": "main", "content": "hello", "message_id": "...", "usage": 42}
+//! {"type": "error", "session": "main", "code": "chat.http", "message": "..."}
+//! {"type": "closed", "session": "main"}
+//! {"type": "overloaded", "session": "main", "retry_after_ms": 5000}
+//! ```
+//!
+//! 同一会话的消息按收到的顺序依次回答，不同会话互不阻塞；连接断开时未完成的回答被取消。每个连接上打开的会话数有上限，
+//! 见 `AdmissionLimits::max_sessions_per_connection`。
+//! Messages of one session are answered in the order they arrive, sessions do not block each other; unfinished
+//! answers are cancelled when the connection drops. The sessions open per connection are bounded, see
+//! `AdmissionLimits::max_sessions_per_connection`.
+//!
+//! 长时间生成时代理可能以空闲为由断开连接，`router_with_ping` 会定时发送 Ping 帧保持连接。
+//! Proxies may drop the connection as idle during long generations, `router_with_ping` sends Ping frames
+//! periodically to keep it alive.
+//!
+//! 开启会话恢复后（见 `router_with_resumption`），`opened` 帧带有恢复令牌，对话在每次回答后保存；重新连接时在 `open`
+//! 帧中给出令牌即可继续同一对话，见 `resume`。
+//! With session resumption enabled (see `router_with_resumption`), the `opened` frame carries a resumption token and
+//! the conversation is saved after every answer; giving the token in the `open` frame of a new connection continues
+//! the same chat, see `resume`.
+//!
+//! 连接与消息经过准入控制，负载过高时被拒绝，见 `admission`。发往客户端的帧经过有界缓冲，客户端读取跟不上时回答暂停
+//! 等待，工具事件被丢弃。
+//! Connections and messages go through admission control and are refused under load, see `admission`. Frames to the
+//! client go through a bounded buffer: when the client does not keep up, answers pause and tool events are dropped.
+
+// 标准库
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+// 错误处理
+use error_stack::Report;
+
+// 异步
+use futures::{SinkExt, StreamExt};
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::mpsc::{Sender, channel};
+use tokio::task::JoinHandle;
+use tokio::time::{Instant, interval_at};
+
+// 网络服务
+use axum::Router;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{ConnectInfo, Extension};
+use axum::routing::{MethodRouter, get};
+
+// 数据序列化
+use serde::{Deserialize, Serialize};
+
+// 观测诊断
+use tracing::warn;
+
+// 工具库
+use uuid::Uuid;
+
+// 项目内部模块
+use crate::chat::chat_single::SingleChat;
+use crate::chat::event::ChatEvent;
+use crate::chat::queue::QueuePriority;
+use crate::chat::store::StoreError;
+use crate::config::ModelCapability;
+use crate::error::{ReportExt, RhineError};
+use crate::server::admission::{Admission, ConnectionPermit, RequestPermit};
+use crate::server::resume::{Conversation, ResumeError, Resumption};
+
+/// 客户端发送的帧
+/// Frame sent by the client
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientFrame {
+    /// 打开会话，未给出API与能力时按 long_context 选择
+    /// Open a session, selected by long_context if neither an API nor a capability is given
+    Open {
+        session: String,
+        #[serde(default)]
+        api: Option<String>,
+        #[serde(default)]
+        capability: Option<ModelCapability>,
+        #[serde(default)]
+        system: Option<String>,
+        #[serde(default)]
+        tools: Vec<serde_json::Value>,
+        /// 是否逐 token 推送回答，默认推送
+        /// Whether to push the answer token by token, on by default
+        #[serde(default = "default_stream")]
+        stream: bool,
+        /// 之前收到的恢复令牌，给出时继续该对话而不是开始新对话，`system` 被忽略
+        /// A resumption token received earlier, continuing its conversation instead of starting a new one; `system`
+        /// is ignored when given
+        #[serde(default)]
+        resume: Option<String>,
+        /// 会话的优先级，默认为 interactive；批量任务应使用 background，以免拖慢用户等待中的会话
+        /// Priority of the session, interactive by default; bulk jobs should use background so they do not delay
+        /// sessions a user is waiting on
+        #[serde(default = "default_priority")]
+        priority: QueuePriority,
+    },
+
+    /// 向会话发送用户消息
+    /// Send a user message to a session
+    Message { session: String, content: String },
+
+    /// 关闭会话，已收到的消息仍会回答
+    /// Close a session, messages already received are still answered
+    Close { session: String },
+}
+
+fn default_stream() -> bool {
+    true
+}
+
+fn default_priority() -> QueuePriority {
+    QueuePriority::Interactive
+}
+
+/// 服务端发送的帧
+/// Frame sent by the server
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerFrame {
+    Opened {
+        session: String,
+        /// 开启会话恢复时的恢复令牌
+        /// Resumption token, when session resumption is enabled
+        #[serde(skip_serializing_if = "Option::is_none")]
+        resume_token: Option<String>,
+    },
+    Token {
+        session: String,
+        delta: String,
+    },
+    ToolCall {
+        session: String,
+        call_id: String,
+        name: String,
+        arguments: serde_json::Value,
+    },
+    ToolResult {
+        session: String,
+        call_id: String,
+        name: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        output: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+        duration_ms: u64,
+    },
+    Answer {
+        session: String,
+        content: String,
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        tool_results: Vec<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        message_id: Option<String>,
+        usage: i32,
+    },
+    Error {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        session: Option<String>,
+        code: String,
+        message: String,
+    },
+    Closed {
+        session: String,
+    },
+    /// 客户端排队已满或服务饱和，消息未被接受，可在 `retry_after_ms` 后重发
+    /// The client's queue is full or the service is saturated, the message was not accepted and can be sent again
+    /// after `retry_after_ms`
+    Overloaded {
+        session: String,
+        retry_after_ms: u64,
+    },
+}
+
+impl ServerFrame {
+    fn error(session: Option<&str>, code: &str, message: impl Into<String>) -> Self {
+        Self::Error {
+            session: session.map(str::to_string),
+            code: code.to_string(),
+            message: message.into(),
+        }
+    }
+
+    /// 只发送最外层错误的描述，报告链与回溯留在服务端日志
+    /// Only the outermost error is sent, the report chain and backtrace stay in the server log
+    fn report<C: RhineError>(session: &str, report: Report<C>) -> Self {
+        warn!("WebSocket session {} failed: {:?}", session, report);
+        Self::error(Some(session), report.code(), report.current_context().to_string())
+    }
+}
+
+/// 连接上打开的一个会话：按顺序接收用户消息的工作任务
+/// A session opened on a connection: the worker task receiving user messages in order
+struct OpenSession {
+    inputs: Sender<(String, RequestPermit)>,
+    worker: JoinHandle<()>,
+    priority: QueuePriority,
+}
+
+/// WebSocket 端点的路由，使用默认的准入限制
+/// Router of the WebSocket endpoint, with the default admission limits
+pub fn router() -> Router {
+    Router::new().route("/ws", upgrade(Admission::default(), None, None))
+}
+
+/// 同 `router`，每个连接上每隔 `interval` 发送一次 Ping 帧
+/// Like `router`, sending a Ping frame every `interval` on each connection
+pub fn router_with_ping(interval: Duration) -> Router {
+    Router::new().route("/ws", upgrade(Admission::default(), Some(interval), None))
+}
+
+/// 同 `router`，使用给定的准入控制器，可与应用共享以观察负载
+/// Like `router`, using the given admission controller, which can be shared with the application to watch the load
+pub fn router_with_admission(admission: Admission, ping_interval: Option<Duration>) -> Router {
+    Router::new().route("/ws", upgrade(admission, ping_interval, None))
+}
+
+/// 同 `router_with_admission`，并开启会话恢复
+/// Like `router_with_admission`, with session resumption enabled
+pub fn router_with_resumption(
+    admission: Admission,
+    ping_interval: Option<Duration>,
+    resumption: Resumption,
+) -> Router {
+    Router::new().route("/ws", upgrade(admission, ping_interval, Some(resumption)))
+}
+
+/// 升级为 WebSocket 前先取得连接许可；客户端按对端IP区分，服务未提供连接信息时（未使用
+/// `into_make_service_with_connect_info`）每个连接各自算作一个客户端，而不是全部算作同一个
+/// Take a connection permit before upgrading to WebSocket; clients are told apart by peer IP, and each connection
+/// counts as its own client if the service does not provide connection info (without
+/// `into_make_service_with_connect_info`), rather than all of them counting as one
+fn upgrade(admission: Admission, ping_interval: Option<Duration>, resumption: Option<Resumption>) -> MethodRouter {
+    get(
+        move |ws: WebSocketUpgrade, peer: Option<Extension<ConnectInfo<SocketAddr>>>| async move {
+            let client = match peer {
+                Some(Extension(ConnectInfo(addr))) => addr.ip().to_string(),
+                None => format!("connection-{}", Uuid::new_v4()),
+            };
+            let Some(permit) = admission.connect(&client) else {
+                warn!("Refused WebSocket connection of {}: server is overloaded", client);
+                return admission.too_many_requests();
+            };
+            ws.on_upgrade(move |socket| handle_socket(socket, ping_interval, admission, permit, resumption))
+        },
+    )
+}
+
+async fn handle_socket(
+    socket: WebSocket,
+    ping_interval: Option<Duration>,
+    admission: Admission,
+    permit: ConnectionPermit,
+    resumption: Option<Resumption>,
+) {
+    let (mut sink, mut stream) = socket.split();
+    let (out, mut outgoing) = channel::<ServerFrame>(admission.limits().outbound_frames);
+    let writer = tokio::spawn(async move {
+        let mut ping = ping_interval.map(|period| interval_at(Instant::now() + period, period));
+        loop {
+            let frame = match &mut ping {
+                Some(ping) => tokio::select! {
+                    frame = outgoing.recv() => frame,
+                    _ = ping.tick() => {
+                        if sink.send(Message::Ping(Default::default())).await.is_err() {
+                            break;
+                        }
+                        continue;
+                    }
+                },
+                None => outgoing.recv().await,
+            };
+            let Some(frame) = frame else {
+                break;
+            };
+            let Ok(text) = serde_json::to_string(&frame) else {
+                continue;
+            };
+            if sink.send(Message::Text(text.into())).await.is_err() {
+                break;
+            }
+        }
+        sink
+    });
+
+    let limits = admission.limits().clone();
+    let retry_after_ms = limits.retry_after.as_millis() as u64;
+    let mut sessions: HashMap<String, OpenSession> = HashMap::new();
+    loop {
+        let Some(Ok(message)) = stream.next().await else {
+            break;
+        };
+        let text = match message {
+            Message::Text(text) => text,
+            Message::Close(_) => break,
+            _ => continue,
+        };
+        let frame = match serde_json::from_str::<ClientFrame>(&text) {
+            Ok(frame) => frame,
+            Err(e) => {
+                let _ = out.send(ServerFrame::error(None, "server.invalid_frame", e.to_string())).await;
+                continue;
+            }
+        };
+
+        match frame {
+            ClientFrame::Open { session, .. } if sessions.contains_key(&session) => {
+                let frame = ServerFrame::error(Some(&session), "server.session_exists", "Session is already open");
+                let _ = out.send(frame).await;
+            }
+            ClientFrame::Open { session, .. } if sessions.len() >= limits.max_sessions_per_connection => {
+                let message = format!("At most {} sessions can be open per connection", sessions.len());
+                let _ = out.send(ServerFrame::error(Some(&session), "server.too_many_sessions", message)).await;
+            }
+            ClientFrame::Open {
+                session,
+                api,
+                capability,
+                system,
+                tools,
+                stream,
+                resume,
+                priority,
+            } => {
+                let mut builder = SingleChat::builder().stream(stream).queue_priority(priority);
+                if let Some(api) = &api {
+                    builder = builder.api(api);
+                }
+                if let Some(capability) = capability.or(api.is_none().then_some(ModelCapability::LongContext)) {
+                    builder = builder.capability(capability);
+                }
+                if let Some(system) = &system {
+                    builder = builder.system(system);
+                }
+                if !tools.is_empty() {
+                    builder = builder.tools(tools);
+                }
+                let mut chat = match builder.build() {
+                    Ok(chat) => chat,
+                    Err(report) => {
+                        let _ = out.send(ServerFrame::report(&session, report)).await;
+                        continue;
+                    }
+                };
+                let persist = match (&resumption, resume) {
+                    (None, None) => None,
+                    (None, Some(_)) => {
+                        let _ = out.send(ServerFrame::report(&session, Report::new(ResumeError::Disabled))).await;
+                        continue;
+                    }
+                    (Some(resumption), Some(token)) => match resumption.resume(&token).await {
+                        Ok((conversation, stored)) => {
+                            chat.base.session = stored;
+                            Some((resumption.clone(), conversation))
+                        }
+                        Err(report) => {
+                            let _ = out.send(ServerFrame::report(&session, report)).await;
+                            continue;
+                        }
+                    },
+                    (Some(resumption), None) => match resumption.start(&chat.base.session).await {
+                        Ok(conversation) => Some((resumption.clone(), conversation)),
+                        Err(report) => {
+                            let _ = out.send(ServerFrame::report(&session, report)).await;
+                            continue;
+                        }
+                    },
+                };
+                let resume_token = persist
+                    .as_ref()
+                    .map(|(resumption, conversation)| resumption.issue(&conversation.id));
+                let queued = limits.max_queued_per_client;
+                let open = open_session(session.clone(), chat, stream, persist, queued, out.clone());
+                sessions.insert(session.clone(), open);
+                let _ = out.send(ServerFrame::Opened { session, resume_token }).await;
+            }
+            ClientFrame::Message { session, content } => match sessions.get(&session) {
+                Some(open) => match permit.admit_with_priority(open.priority) {
+                    // 工作任务已因保存冲突结束时会话视为已关闭
+                    // The session counts as closed once its worker ended on a save conflict
+                    Some(request) => match open.inputs.try_send((content, request)) {
+                        Ok(()) => {}
+                        Err(TrySendError::Full(_)) => {
+                            let _ = out.send(ServerFrame::Overloaded { session, retry_after_ms }).await;
+                        }
+                        Err(TrySendError::Closed(_)) => {
+                            sessions.remove(&session);
+                            let closed = "Session is not open";
+                            let frame = ServerFrame::error(Some(&session), "server.unknown_session", closed);
+                            let _ = out.send(frame).await;
+                        }
+                    },
+                    None => {
+                        let _ = out.send(ServerFrame::Overloaded { session, retry_after_ms }).await;
+                    }
+                },
+                None => {
+                    let frame = ServerFrame::error(Some(&session), "server.unknown_session", "Session is not open");
+                    let _ = out.send(frame).await;
+                }
+            },
+            ClientFrame::Close { session } => match sessions.remove(&session) {
+                // 丢弃发送端后工作任务回答完已收到的消息再发出 closed
+                // With the sender dropped, the worker answers the messages already received and then sends closed
+                Some(open) => drop(open.inputs),
+                None => {
+                    let frame = ServerFrame::error(Some(&session), "server.unknown_session", "Session is not open");
+                    let _ = out.send(frame).await;
+                }
+            },
+        }
+    }
+
+    for open in sessions.into_values() {
+        open.worker.abort();
+    }
+    drop(out);
+    let _ = writer.await;
+}
+
+/// 启动会话的工作任务，工具事件经对话的事件处理函数转发
+/// Start the worker of a session, tool events are forwarded through the chat's event handler
+///
+/// 开启会话恢复时，每次回答后把对话保存到存储中。最多 `queued` 条消息排队等待回答
+/// With session resumption enabled, the conversation is saved to the store after every answer. At most `queued`
+/// messages wait in line to be answered
+fn open_session(
+    session: String,
+    mut chat: SingleChat,
+    stream: bool,
+    mut persist: Option<(Resumption, Conversation)>,
+    queued: usize,
+    out: Sender<ServerFrame>,
+) -> OpenSession {
+    let priority = chat.base.queue_priority;
+    let events = out.clone();
+    let name = session.clone();
+    chat.on_event(move |event| {
+        let frame = match event {
+            ChatEvent::ToolCallStarted { call_id, name: tool, arguments } => ServerFrame::ToolCall {
+                session: name.clone(),
+                call_id: call_id.clone(),
+                name: tool.clone(),
+                arguments: arguments.clone(),
+            },
+            ChatEvent::ToolCallFinished {
+                call_id,
+                name: tool,
+                output,
+                error,
+                duration,
+            } => ServerFrame::ToolResult {
+                session: name.clone(),
+                call_id: call_id.clone(),
+                name: tool.clone(),
+                output: output.clone(),
+                error: error.clone(),
+                duration_ms: duration.as_millis() as u64,
+            },
+            _ => return,
+        };
+        // 事件处理函数是同步的，客户端读取跟不上时丢弃工具事件而不是阻塞回答
+        // Event handlers are synchronous, tool events are dropped rather than blocking the answer when the client
+        // does not keep up
+        if events.try_send(frame).is_err() {
+            warn!("Dropped a tool event of WebSocket session {}: the client is not reading", name);
+        }
+    });
+
+    // 消息的准入许可在回答完后释放
+    // The admission permit of a message is released once it is answered
+    let (inputs, mut received) = channel::<(String, RequestPermit)>(queued.max(1));
+    let worker = tokio::spawn(async move {
+        while let Some((input, _request)) = received.recv().await {
+            let Some(frame) = answer(&session, &mut chat, &input, stream, &out).await else {
+                continue;
+            };
+            let _ = out.send(frame).await;
+            if let Some((resumption, conversation)) = &mut persist
+                && let Err(report) = resumption.save(conversation, &chat.base.session).await
+            {
+                // 存储中的对话已被其他连接修改，之后的保存都会冲突，关闭会话由客户端凭令牌重新打开
+                // Another connection changed the stored conversation and every later save would conflict, so close
+                // the session and let the client reopen it with the token
+                let conflict = matches!(report.current_context(), StoreError::Conflict { .. });
+                let _ = out.send(ServerFrame::report(&session, report)).await;
+                if conflict {
+                    break;
+                }
+            }
+        }
+        let _ = out.send(ServerFrame::Closed { session }).await;
+    });
+    OpenSession {
+        inputs,
+        worker,
+        priority,
+    }
+}
+
+/// 回答一条消息，流式输出时逐段发送 token 帧，返回最后要发送的 answer 或 error 帧
+/// Answer one message, sending token frames piece by piece when streaming, returning the final answer or error frame
+async fn answer(
+    session: &str,
+    chat: &mut SingleChat,
+    input: &str,
+    stream: bool,
+    out: &Sender<ServerFrame>,
+) -> Option<ServerFrame> {
+    let (content, tool_results) = if !chat.tools().is_empty() {
+        match chat.get_tool_answer(input).await {
+            Ok(answer) => answer,
+            Err(report) => return Some(ServerFrame::report(session, report)),
+        }
+    } else if stream {
+        let deltas = match chat.stream_answer(input).await {
+            Ok(deltas) => deltas,
+            Err(report) => return Some(ServerFrame::report(session, report)),
+        };
+        let mut deltas = std::pin::pin!(deltas);
+        let mut content = String::new();
+        while let Some(delta) = deltas.next().await {
+            match delta {
+                Ok(delta) => {
+                    content.push_str(&delta);
+                    out.send(ServerFrame::Token {
+                        session: session.to_string(),
+                        delta,
+                    })
+                    .await
+                    .ok()?;
+                }
+                Err(report) => return Some(ServerFrame::report(session, report)),
+            }
+        }
+        (content, Vec::new())
+    } else {
+        match chat.get_answer(input).await {
+            Ok(content) => (content, Vec::new()),
+            Err(report) => return Some(ServerFrame::report(session, report)),
+        }
+    };
+
+    Some(ServerFrame::Answer {
+        session: session.to_string(),
+        content,
+        tool_results,
+        message_id: chat.last_message_id().map(str::to_string),
+        usage: chat.base.usage,
+    })
+}
@@ -0,0 +1,317 @@
+//! 优雅关闭：停止接受新的提问，在截止时间内等待进行中的生成与工具调用结束，保存打开的对话并写完审计与用量记录，
+//! 用于智能体服务的平滑部署
+//! Graceful shutdown: stop accepting new questions, wait up to a deadline for the generations and tool calls in
+//! flight, persist the open conversations and finish writing the audit and usage records, for clean deploys of agent
+//! services
+//!
+//! ```ignore
+//! let shutdown = Shutdown::new();
+//! shutdown.persist("user-42", handle.clone(), store.clone());
+//! tokio::signal::ctrl_c().await?;
+//! let report = shutdown.drain(Duration::from_secs(30)).await;
+//! ```
+
+// 标准库
+use std::future::Future;
+use std::pin::pin;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+// 并发和同步原语
+use tokio::sync::Notify;
+use tokio::time::Instant;
+
+// 错误处理
+use error_stack::{Report, Result};
+
+// 观测诊断
+use tracing::{info, warn};
+
+// 项目内部模块
+use crate::chat::chat_base::ChatError;
+use crate::chat::handle::ChatHandle;
+use crate::chat::store::{SessionStore, update_session};
+use crate::config::CFG;
+use crate::telemetry::audit::AuditLog;
+use crate::telemetry::ledger::flush_usage_ledger;
+
+/// 关闭状态与进行中的工作计数，全局配置与每个配置作用域各有一份，见 `Config::with_scoped`
+/// Shutdown state and the counts of work in flight, one for the global configuration and one for every
+/// configuration scope, see `Config::with_scoped`
+#[derive(Debug, Default)]
+pub(crate) struct ShutdownState {
+    closing: AtomicBool,
+    generations: AtomicUsize,
+    tools: AtomicUsize,
+    idle: Notify,
+}
+
+impl ShutdownState {
+    fn in_flight(&self) -> (usize, usize) {
+        (self.generations.load(Ordering::SeqCst), self.tools.load(Ordering::SeqCst))
+    }
+}
+
+/// 进行中的工作种类
+/// Kind of work in flight
+#[derive(Clone, Copy, Debug)]
+enum Work {
+    Generation,
+    Tool,
+}
+
+/// 进行中的一项工作，释放时计数减一
+/// One piece of work in flight, its count goes down by one when it is dropped
+#[derive(Debug)]
+pub(crate) struct InFlight {
+    state: Arc<ShutdownState>,
+    work: Work,
+}
+
+impl InFlight {
+    fn new(work: Work) -> Self {
+        let state = CFG.current().shutdown_state();
+        let flight = Self { state, work };
+        flight.count().fetch_add(1, Ordering::SeqCst);
+        flight
+    }
+
+    fn count(&self) -> &AtomicUsize {
+        match self.work {
+            Work::Generation => &self.state.generations,
+            Work::Tool => &self.state.tools,
+        }
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.count().fetch_sub(1, Ordering::SeqCst);
+        self.state.idle.notify_waiters();
+    }
+}
+
+/// 记录一次进行中的模型请求，持有到请求结束
+/// Track one model request in flight, held until the request ends
+pub(crate) fn track_generation() -> InFlight {
+    InFlight::new(Work::Generation)
+}
+
+/// 记录一次进行中的工具调用，持有到调用结束；在阻塞线程上运行的工具应把它移入线程，线程结束前一直计数
+/// Track one tool call in flight, held until the call ends; tools run on a blocking thread should move it into the
+/// thread, so the call counts until the thread finishes
+pub(crate) fn track_tool() -> InFlight {
+    InFlight::new(Work::Tool)
+}
+
+tokio::task_local! {
+    /// 标记已开始的回答中的工作，如工具调用与其中的子智能体，关闭开始后仍可进行
+    /// Marks work within an answer already started, such as tool calls and the sub-agents they run, which may still
+    /// go ahead once shutdown began
+    static ANSWER_STARTED: ();
+}
+
+/// 开始关闭后拒绝新的提问，已开始的回答中的工作（见 `within_started_answer`）除外
+/// Refuse new questions once shutdown began, except for work within an answer already started (see
+/// `within_started_answer`)
+pub(crate) fn admit_question() -> Result<(), ChatError> {
+    match Shutdown::is_closing() && ANSWER_STARTED.try_with(|_| ()).is_err() {
+        true => Err(Report::new(ChatError::ShuttingDown)),
+        false => Ok(()),
+    }
+}
+
+/// 让 `future` 作为已开始的回答中的工作运行，其中的提问在关闭开始后不被拒绝
+/// Run `future` as work within an answer already started, so questions it asks are not refused once shutdown began
+pub(crate) async fn within_started_answer<F: Future>(future: F) -> F::Output {
+    ANSWER_STARTED.scope((), future).await
+}
+
+/// 打开的对话，关闭时保存到存储中
+/// An open conversation, saved to its store on shutdown
+struct OpenConversation {
+    id: String,
+    handle: ChatHandle,
+    store: Arc<dyn SessionStore>,
+}
+
+/// 关闭的结果
+/// Outcome of a shutdown
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ShutdownReport {
+    /// 截止前所有进行中的工作都已结束
+    /// Every piece of work in flight ended before the deadline
+    pub drained: bool,
+
+    /// 截止时仍在进行的模型请求数
+    /// Model requests still in flight at the deadline
+    pub pending_generations: usize,
+
+    /// 截止时仍在进行的工具调用数
+    /// Tool calls still in flight at the deadline
+    pub pending_tools: usize,
+
+    /// 已保存的对话
+    /// Conversations saved
+    pub persisted: Vec<String>,
+
+    /// 保存失败的对话
+    /// Conversations that failed to save
+    pub failed: Vec<String>,
+}
+
+/// 进程级的关闭句柄，可克隆，克隆共享登记的对话
+/// Process-level shutdown handle, cloneable, clones share the registered conversations
+///
+/// 开始关闭后，`SingleChat` 与 `MultiChat` 的新提问（包括流式提问）以 `ChatError::ShuttingDown` 拒绝，
+/// 已开始的回答（包括其中的工具调用、由工具运行的子智能体与后续请求）照常完成。
+/// 截止时仍未结束的对话被取消，取消的提问可能只写入了用户消息。
+/// Once shutdown begins, new questions to `SingleChat` and `MultiChat` (streamed ones included) are refused with
+/// `ChatError::ShuttingDown`, while answers already started (including their tool calls, the sub-agents run by
+/// tools and follow-up requests) complete as usual. Conversations still busy at the deadline are cancelled, and a
+/// cancelled question may have written only the user message.
+///
+/// 关闭状态与进行中的工作按配置区分：在 `Config::with_scoped` 的作用域内，关闭只影响该作用域中的对话。
+/// The shutdown state and the work in flight are kept per configuration: within a `Config::with_scoped` scope,
+/// shutting down only affects the conversations of that scope.
+#[derive(Clone, Default)]
+pub struct Shutdown {
+    conversations: Arc<Mutex<Vec<OpenConversation>>>,
+}
+
+impl std::fmt::Debug for Shutdown {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let conversations = self.conversations.lock().unwrap().len();
+        f.debug_struct("Shutdown").field("conversations", &conversations).finish()
+    }
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 登记打开的对话，关闭时以 `id` 保存到 `store`；同一 `id` 再次登记时替换原来的登记
+    /// Register an open conversation, saved to `store` under `id` on shutdown; registering the same `id` again
+    /// replaces the earlier registration
+    pub fn persist(&self, id: &str, handle: ChatHandle, store: Arc<dyn SessionStore>) -> &Self {
+        let mut conversations = self.conversations.lock().unwrap();
+        conversations.retain(|conversation| conversation.id != id);
+        conversations.push(OpenConversation {
+            id: id.to_string(),
+            handle,
+            store,
+        });
+        self
+    }
+
+    /// 取消登记，如对话已正常结束并保存
+    /// Unregister a conversation, such as when it ended and was saved normally
+    pub fn forget(&self, id: &str) {
+        self.conversations.lock().unwrap().retain(|conversation| conversation.id != id);
+    }
+
+    /// 开始关闭：此后新的提问被拒绝
+    /// Begin the shutdown: new questions are refused from now on
+    pub fn begin(&self) {
+        CFG.current().shutdown_state().closing.store(true, Ordering::SeqCst);
+    }
+
+    /// 重新接受提问，如部署中止后继续提供服务
+    /// Accept questions again, such as when a deploy is aborted and the service carries on
+    pub fn resume(&self) {
+        CFG.current().shutdown_state().closing.store(false, Ordering::SeqCst);
+    }
+
+    /// 是否已开始关闭
+    /// Whether the shutdown has begun
+    pub fn is_closing() -> bool {
+        CFG.current().shutdown_state().closing.load(Ordering::SeqCst)
+    }
+
+    /// 进行中的模型请求数与工具调用数，在阻塞线程上运行的工具在截止时间后仍计数，直到其线程结束
+    /// Model requests and tool calls in flight, tools run on a blocking thread still count after their deadline
+    /// until their thread finishes
+    pub fn in_flight() -> (usize, usize) {
+        CFG.current().shutdown_state().in_flight()
+    }
+
+    /// 开始关闭并在 `timeout` 内等待进行中的工作结束，然后取消仍在进行的对话、保存登记的对话并写完审计与用量记录
+    /// Begin the shutdown and wait up to `timeout` for the work in flight, then cancel the conversations still busy,
+    /// save the registered conversations and finish writing the audit and usage records
+    pub async fn drain(&self, timeout: Duration) -> ShutdownReport {
+        self.begin();
+        let state = CFG.current().shutdown_state();
+        let drained = tokio::time::timeout_at(Instant::now() + timeout, wait_idle(&state)).await.is_ok();
+        let (pending_generations, pending_tools) = state.in_flight();
+        let conversations = std::mem::take(&mut *self.conversations.lock().unwrap());
+        if !drained {
+            warn!(
+                "Shutdown deadline passed with {} model requests and {} tool calls in flight",
+                pending_generations, pending_tools
+            );
+            for conversation in &conversations {
+                conversation.handle.cancel();
+            }
+        }
+
+        let mut report = ShutdownReport {
+            drained,
+            pending_generations,
+            pending_tools,
+            ..ShutdownReport::default()
+        };
+        for conversation in conversations {
+            match save(&conversation).await {
+                true => report.persisted.push(conversation.id),
+                false => report.failed.push(conversation.id),
+            }
+        }
+
+        if let Err(report) = AuditLog::flush() {
+            warn!("Failed to flush the audit log: {:?}", report);
+        }
+        if let Err(e) = tokio::task::spawn_blocking(flush_usage_ledger).await {
+            warn!("Failed to flush the usage ledger: {}", e);
+        }
+        info!(
+            "Shutdown finished, {} conversations saved, {} failed",
+            report.persisted.len(),
+            report.failed.len()
+        );
+        report
+    }
+}
+
+/// 等待进行中的模型请求与工具调用全部结束
+/// Wait until every model request and tool call in flight has ended
+async fn wait_idle(state: &ShutdownState) {
+    loop {
+        let mut notified = pin!(state.idle.notified());
+        notified.as_mut().enable();
+        if state.in_flight() == (0, 0) {
+            return;
+        }
+        notified.await;
+    }
+}
+
+/// 读取对话的当前会话并保存，返回是否成功
+/// Read the current session of the conversation and save it, returning whether it succeeded
+async fn save(conversation: &OpenConversation) -> bool {
+    let session = match conversation.handle.with(|chat| chat.base.session.clone()).await {
+        Ok(session) => session,
+        Err(report) => {
+            warn!("Failed to read conversation {} on shutdown: {:?}", conversation.id, report);
+            return false;
+        }
+    };
+    match update_session(conversation.store.as_ref(), &conversation.id, |current| *current = session.clone()).await {
+        Ok(_) => true,
+        Err(report) => {
+            warn!("Failed to save conversation {} on shutdown: {:?}", conversation.id, report);
+            false
+        }
+    }
+}
@@ -0,0 +1,363 @@
+// 标准库
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt::{self, Display};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+// 并发和同步原语
+use once_cell::sync::Lazy;
+
+// 随机数与哈希
+use hmac::{Hmac, Mac};
+use rand::Rng;
+use sha2::Sha256;
+
+// 序列化
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+// 项目内部模块
+use crate::chat::pii::detect_pii;
+use crate::telemetry::exporter::ToolCallRecord;
+use crate::telemetry::{LlmCall, TokenUsage};
+use crate::utils::common::redact::redact;
+
+/// k-匿名最多跟踪的标签数，超出时最早跟踪的标签被遗忘并重新计数
+/// Most tags tracked for k-anonymity, beyond it the earliest tracked tag is forgotten and counted anew
+const MAX_TRACKED_TAGS: usize = 10_000;
+
+/// 文本的敏感级别，从低到高排列
+/// Sensitivity level of a text, ordered from low to high
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Sensitivity {
+    /// 未发现敏感信息
+    /// No sensitive data found
+    Public,
+
+    /// 含邮箱或电话号码
+    /// Contains an email address or phone number
+    Personal,
+
+    /// 含通过 Luhn 校验的银行卡号
+    /// Contains a card number passing the Luhn check
+    Financial,
+
+    /// 含API密钥、令牌、密码等凭据
+    /// Contains credentials such as API keys, tokens or passwords
+    Credential,
+}
+
+impl Sensitivity {
+    /// 判断文本的敏感级别，取其中最高的一类
+    /// Classify a text, taking the highest kind found in it
+    pub fn classify(text: &str) -> Self {
+        if redact(text) != text {
+            return Self::Credential;
+        }
+        let labels = detect_pii(text);
+        if labels.contains(&"CARD") {
+            Self::Financial
+        } else if !labels.is_empty() {
+            Self::Personal
+        } else {
+            Self::Public
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Public => "public",
+            Self::Personal => "personal",
+            Self::Financial => "financial",
+            Self::Credential => "credential",
+        }
+    }
+}
+
+impl Display for Sensitivity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// 遥测匿名化配置，作用于审计日志与追踪导出器，使受监管的环境也能开启观测
+/// Telemetry anonymization configuration, applied to the audit log and trace exporters so observability can be
+/// enabled in regulated environments
+///
+/// 指标、用量统计与用量账本不受影响，计费与配额仍按真实用量计算。
+/// Metrics, usage aggregation and the usage ledger are unaffected, so billing and quotas still use real usage.
+///
+/// ```toml
+/// [anonymize]
+/// salt = "${TELEMETRY_SALT}"
+/// hash_identifiers = true
+/// drop_bodies_at = "personal"
+/// k_anonymity = 5
+/// noise_scale = 10.0
+/// ```
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AnonymizeConfig {
+    /// 哈希的密钥（盐），不同部署应使用不同的随机值以免哈希被跨库关联或被猜测还原；哈希身份标识时不能为空
+    /// Key (salt) of the hashes, deployments should use different random values so hashes can neither be joined
+    /// across them nor reversed by guessing; must not be empty while identifiers are hashed
+    pub salt: String,
+
+    /// 是否以加盐哈希替换会话ID、身份标签的值与请求的 `user` 字段
+    /// Whether session ids, the values of identifier tags and the `user` field of requests are replaced with salted
+    /// hashes
+    pub hash_identifiers: bool,
+
+    /// 视为用户身份的标签键，如 `user:alice` 中的 `user`
+    /// Tag keys treated as user identities, such as `user` in `user:alice`
+    pub identifier_tags: Vec<String>,
+
+    /// 消息正文、工具调用参数与工具定义达到该敏感级别时整体删除，只保留 `[dropped: <级别>]`；为 None 时不删除
+    /// Message bodies, tool call arguments and tool definitions at or above this sensitivity are dropped whole,
+    /// leaving only `[dropped: <level>]`; nothing is dropped if None
+    pub drop_bodies_at: Option<Sensitivity>,
+
+    /// k-匿名：出现在少于 k 个会话中的其余标签泛化为 `<键>:*`；为 None 时不处理
+    /// k-anonymity: other tags seen in fewer than k sessions are generalized to `<key>:*`; left as is if None
+    pub k_anonymity: Option<usize>,
+
+    /// 加在令牌数与延迟（毫秒）上的拉普拉斯噪声尺度；为 None 时不加噪声
+    /// Scale of the Laplace noise added to token counts and latency in milliseconds; no noise if None
+    pub noise_scale: Option<f64>,
+}
+
+impl Default for AnonymizeConfig {
+    fn default() -> Self {
+        Self {
+            salt: String::new(),
+            hash_identifiers: true,
+            identifier_tags: vec!["user".to_string(), "tenant".to_string()],
+            drop_bodies_at: None,
+            k_anonymity: None,
+            noise_scale: None,
+        }
+    }
+}
+
+impl AnonymizeConfig {
+    /// 是否哈希身份标识却没有设置盐
+    /// Whether identifiers are hashed without a salt
+    pub fn missing_salt(&self) -> bool {
+        self.hash_identifiers && self.salt.is_empty()
+    }
+}
+
+/// 遥测匿名化处理器
+/// Telemetry anonymizer
+///
+/// k-匿名按处理器见过的会话计数，因此每个标签在前 k-1 个会话中总是被泛化；最多跟踪 `MAX_TRACKED_TAGS` 个标签。
+/// k-anonymity counts the sessions the anonymizer has seen, so every tag is generalized in its first k-1 sessions;
+/// at most `MAX_TRACKED_TAGS` tags are tracked.
+#[derive(Debug)]
+pub struct Anonymizer {
+    config: AnonymizeConfig,
+    sessions: Mutex<TagSessions>,
+}
+
+/// 每个标签出现过的会话，最多记录 k 个，按开始跟踪的顺序淘汰
+/// Sessions each tag was seen in, at most k are kept, evicted in the order tracking started
+#[derive(Debug, Default)]
+struct TagSessions {
+    sessions: HashMap<String, HashSet<String>>,
+    order: VecDeque<String>,
+}
+
+impl TagSessions {
+    /// 记录标签出现在会话中，返回标签出现过的会话数
+    /// Record a tag seen in a session, returning the number of sessions the tag was seen in
+    fn record(&mut self, tag: &str, session_id: &str, k: usize) -> usize {
+        if !self.sessions.contains_key(tag) {
+            if self.order.len() >= MAX_TRACKED_TAGS
+                && let Some(oldest) = self.order.pop_front()
+            {
+                self.sessions.remove(&oldest);
+            }
+            self.order.push_back(tag.to_string());
+        }
+        let seen = self.sessions.entry(tag.to_string()).or_default();
+        if seen.len() < k {
+            seen.insert(session_id.to_string());
+        }
+        seen.len()
+    }
+}
+
+/// 全局匿名化处理器，为 None 时遥测按原样记录
+/// Global anonymizer, telemetry is recorded as is if None
+static ANONYMIZER: Lazy<RwLock<Option<Arc<Anonymizer>>>> = Lazy::new(|| RwLock::new(None));
+
+impl Anonymizer {
+    pub fn new(config: AnonymizeConfig) -> Self {
+        Self {
+            config,
+            sessions: Mutex::default(),
+        }
+    }
+
+    /// 开启全局匿名化，替换已开启的配置
+    /// Enable global anonymization, replacing any enabled configuration
+    pub fn enable(config: AnonymizeConfig) {
+        *ANONYMIZER.write().unwrap() = Some(Arc::new(Self::new(config)));
+    }
+
+    /// 关闭全局匿名化
+    /// Disable global anonymization
+    pub fn disable() {
+        *ANONYMIZER.write().unwrap() = None;
+    }
+
+    pub fn is_enabled() -> bool {
+        ANONYMIZER.read().unwrap().is_some()
+    }
+
+    pub fn config(&self) -> &AnonymizeConfig {
+        &self.config
+    }
+
+    /// 以盐为密钥的 HMAC-SHA256，形如 `anon-` 加16位十六进制
+    /// HMAC-SHA256 keyed with the salt, `anon-` followed by 16 hex digits
+    pub fn hash(&self, value: &str) -> String {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(self.config.salt.as_bytes()).expect("HMAC accepts keys of any length");
+        mac.update(value.as_bytes());
+        let hex: String = mac.finalize().into_bytes().iter().take(8).map(|byte| format!("{:02x}", byte)).collect();
+        format!("anon-{}", hex)
+    }
+
+    /// 匿名化一次LLM调用
+    /// Anonymize one LLM call
+    pub fn anonymize_call(&self, call: &LlmCall) -> LlmCall {
+        let mut call = call.clone();
+        call.tags = self.anonymize_tags(&call.session_id, &call.tags);
+        call.session_id = self.identifier(&call.session_id);
+
+        let mut body = (*call.request_body).clone();
+        if let Some(Value::String(user)) = body.get_mut("user") {
+            *user = self.identifier(user);
+        }
+        if let Some(level) = self.config.drop_bodies_at {
+            if let Some(messages) = body.get_mut("messages").and_then(Value::as_array_mut) {
+                for message in messages {
+                    if let Some(content) = message.get_mut("content") {
+                        drop_value(content, level);
+                    }
+                    let calls = message.get_mut("tool_calls").and_then(Value::as_array_mut).into_iter().flatten();
+                    for arguments in calls.filter_map(|tool_call| tool_call.pointer_mut("/function/arguments")) {
+                        drop_value(arguments, level);
+                    }
+                }
+            }
+            for tool in body.get_mut("tools").and_then(Value::as_array_mut).into_iter().flatten() {
+                drop_value(tool, level);
+            }
+        }
+        call.request_body = Arc::new(body);
+
+        if let Some(level) = self.config.drop_bodies_at {
+            call.output = call.output.map(|output| drop_text(output, level));
+            call.error = call.error.map(|error| drop_text(error, level));
+            if let Some(response) = call.response.as_mut() {
+                drop_value(response, level);
+            }
+        }
+
+        if let Some(scale) = self.config.noise_scale {
+            call.usage = call.usage.map(|usage| {
+                let prompt_tokens = add_noise(usage.prompt_tokens, scale);
+                let completion_tokens = add_noise(usage.completion_tokens, scale);
+                TokenUsage {
+                    prompt_tokens,
+                    completion_tokens,
+                    total_tokens: prompt_tokens + completion_tokens,
+                }
+            });
+            call.latency = Duration::from_millis(add_noise(call.latency.as_millis() as u64, scale));
+        }
+        call
+    }
+
+    /// 匿名化一次工具调用
+    /// Anonymize one tool call
+    pub fn anonymize_tool(&self, tool: &ToolCallRecord) -> ToolCallRecord {
+        let mut tool = tool.clone();
+        tool.session_id = self.identifier(&tool.session_id);
+        if let Some(level) = self.config.drop_bodies_at {
+            drop_value(&mut tool.arguments, level);
+            tool.output = tool.output.map(|output| drop_text(output, level));
+            tool.error = tool.error.map(|error| drop_text(error, level));
+        }
+        tool
+    }
+
+    fn identifier(&self, value: &str) -> String {
+        match self.config.hash_identifiers && !value.is_empty() {
+            true => self.hash(value),
+            false => value.to_string(),
+        }
+    }
+
+    /// 哈希身份标签的值，并按 k-匿名泛化其余标签
+    /// Hash the values of identifier tags and generalize the other tags by k-anonymity
+    fn anonymize_tags(&self, session_id: &str, tags: &[String]) -> Vec<String> {
+        let mut sessions = self.sessions.lock().unwrap();
+        tags.iter()
+            .map(|tag| {
+                let (key, value) = tag.split_once(':').unwrap_or(("", tag));
+                if self.config.identifier_tags.iter().any(|identifier| identifier == key) {
+                    return format!("{}:{}", key, self.identifier(value));
+                }
+                let Some(k) = self.config.k_anonymity else {
+                    return tag.clone();
+                };
+                match (sessions.record(tag, session_id, k) >= k, key.is_empty()) {
+                    (true, _) => tag.clone(),
+                    (false, true) => "*".to_string(),
+                    (false, false) => format!("{}:*", key),
+                }
+            })
+            .collect()
+    }
+}
+
+/// 全局匿名化处理器，未开启时返回 None
+/// The global anonymizer, None if disabled
+pub(crate) fn global() -> Option<Arc<Anonymizer>> {
+    ANONYMIZER.read().unwrap().clone()
+}
+
+/// 敏感级别达到 `level` 的文本替换为删除标记
+/// Replace a text at or above `level` with the drop marker
+fn drop_text(text: String, level: Sensitivity) -> String {
+    match Sensitivity::classify(&text) {
+        sensitivity if sensitivity >= level => format!("[dropped: {}]", sensitivity),
+        _ => text,
+    }
+}
+
+/// 序列化后敏感级别达到 `level` 的值整体替换为删除标记
+/// Replace a value whose serialized form is at or above `level` with the drop marker as a whole
+fn drop_value(value: &mut Value, level: Sensitivity) {
+    let text = match &*value {
+        Value::String(text) => text.clone(),
+        Value::Null => return,
+        other => other.to_string(),
+    };
+    let sensitivity = Sensitivity::classify(&text);
+    if sensitivity >= level {
+        *value = json!(format!("[dropped: {}]", sensitivity));
+    }
+}
+
+/// 加上拉普拉斯噪声，结果不小于0
+/// Add Laplace noise, the result is never below 0
+fn add_noise(value: u64, scale: f64) -> u64 {
+    let uniform: f64 = rand::rng().random_range(-0.5..0.5);
+    let noise = -scale * uniform.signum() * (1.0 - 2.0 * uniform.abs()).ln();
+    (value as f64 + noise).round().max(0.0) as u64
+}
@@ -0,0 +1,227 @@
+// 标准库
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+// 并发和同步原语
+use once_cell::sync::Lazy;
+
+// 序列化
+use serde::Deserialize;
+use serde_json::json;
+
+// 错误处理
+use error_stack::{Result, ResultExt};
+use thiserror::Error;
+
+// 观测诊断
+use tracing::warn;
+
+// 项目内部模块
+use crate::telemetry::LlmCall;
+use crate::telemetry::exporter::{ToolCallRecord, rfc3339};
+use crate::utils::common::redact::redact;
+
+/// 审计日志错误枚举
+/// Audit log error enum
+#[derive(Debug, Error)]
+pub enum AuditError {
+    /// 日志文件读写失败
+    /// Reading or writing the log file failed
+    #[error("Audit log IO error: {0}")]
+    IoError(String),
+}
+
+/// 审计日志配置
+/// Audit log configuration
+#[derive(Clone, Debug, Deserialize)]
+pub struct AuditLogConfig {
+    /// 当前日志文件路径，轮转后的文件依次追加 `.1`、`.2` 等后缀
+    /// Path of the current log file, rotated files get the `.1`, `.2`, ... suffixes
+    pub path: String,
+
+    /// 单个文件的最大字节数，超过后轮转
+    /// Maximum bytes of a single file before it is rotated
+    #[serde(default = "default_max_bytes")]
+    pub max_bytes: u64,
+
+    /// 保留的轮转文件数
+    /// Number of rotated files to keep
+    #[serde(default = "default_max_files")]
+    pub max_files: usize,
+}
+
+impl AuditLogConfig {
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: path.to_string(),
+            max_bytes: default_max_bytes(),
+            max_files: default_max_files(),
+        }
+    }
+}
+
+fn default_max_bytes() -> u64 {
+    100 * 1024 * 1024
+}
+
+fn default_max_files() -> usize {
+    10
+}
+
+/// 轮转写入的 JSONL 审计日志
+/// Rotating JSONL audit log
+///
+/// 记录发送给模型提供商的每个请求体、响应、工具调用和错误，写入前清除API密钥。
+/// Records every request body, response, tool call and error sent to model providers, with API keys redacted
+/// before writing.
+pub struct AuditLog {
+    config: AuditLogConfig,
+
+    file: File,
+
+    size: u64,
+}
+
+/// 全局审计日志，为 None 时不记录
+/// Global audit log, nothing is recorded if None
+static AUDIT_LOG: Lazy<Mutex<Option<AuditLog>>> = Lazy::new(|| Mutex::new(None));
+
+impl AuditLog {
+    /// 开启全局审计日志，替换已开启的日志
+    /// Enable the global audit log, replacing any enabled one
+    pub fn enable(config: AuditLogConfig) -> Result<(), AuditError> {
+        let log = Self::open(config)?;
+        *AUDIT_LOG.lock().unwrap() = Some(log);
+        Ok(())
+    }
+
+    /// 关闭全局审计日志
+    /// Disable the global audit log
+    pub fn disable() {
+        *AUDIT_LOG.lock().unwrap() = None;
+    }
+
+    /// 全局审计日志是否开启
+    /// Whether the global audit log is enabled
+    pub fn is_enabled() -> bool {
+        AUDIT_LOG.lock().unwrap().is_some()
+    }
+
+    /// 把已写入的记录同步到磁盘，审计日志未开启时忽略
+    /// Sync the records written so far to disk, ignored when the audit log is disabled
+    pub fn flush() -> Result<(), AuditError> {
+        match AUDIT_LOG.lock().unwrap().as_ref() {
+            Some(log) => log.file.sync_all().change_context_lazy(|| AuditError::IoError(log.config.path.clone())),
+            None => Ok(()),
+        }
+    }
+
+    fn open(config: AuditLogConfig) -> Result<Self, AuditError> {
+        let path = Path::new(&config.path);
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            fs::create_dir_all(parent).change_context_lazy(|| AuditError::IoError(config.path.clone()))?;
+        }
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .change_context_lazy(|| AuditError::IoError(config.path.clone()))?;
+        let size = file
+            .metadata()
+            .change_context_lazy(|| AuditError::IoError(config.path.clone()))?
+            .len();
+
+        Ok(Self { config, file, size })
+    }
+
+    /// 写入一条记录，必要时先轮转
+    /// Write one record, rotating first if needed
+    fn write(&mut self, record: &serde_json::Value) -> Result<(), AuditError> {
+        let mut line = redact(&record.to_string());
+        line.push('\n');
+
+        if self.size > 0 && self.size + line.len() as u64 > self.config.max_bytes {
+            self.rotate()?;
+        }
+
+        self.file
+            .write_all(line.as_bytes())
+            .change_context_lazy(|| AuditError::IoError(self.config.path.clone()))?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+    /// 轮转：`path.{n-1}` → `path.{n}`，…，`path` → `path.1`，超出保留数的文件被删除
+    /// Rotate: `path.{n-1}` → `path.{n}`, ..., `path` → `path.1`, files beyond the kept count are deleted
+    fn rotate(&mut self) -> Result<(), AuditError> {
+        let rotated = |index: usize| PathBuf::from(format!("{}.{}", self.config.path, index));
+
+        if self.config.max_files == 0 {
+            fs::remove_file(&self.config.path).change_context_lazy(|| AuditError::IoError(self.config.path.clone()))?;
+        } else {
+            let _ = fs::remove_file(rotated(self.config.max_files));
+            for index in (1..self.config.max_files).rev() {
+                if rotated(index).exists() {
+                    fs::rename(rotated(index), rotated(index + 1))
+                        .change_context_lazy(|| AuditError::IoError(self.config.path.clone()))?;
+                }
+            }
+            fs::rename(&self.config.path, rotated(1))
+                .change_context_lazy(|| AuditError::IoError(self.config.path.clone()))?;
+        }
+
+        *self = Self::open(self.config.clone())?;
+        Ok(())
+    }
+}
+
+/// 写入一条审计记录，审计日志未开启时忽略
+/// Write one audit record, ignored when the audit log is disabled
+fn append(record: serde_json::Value) {
+    let mut guard = AUDIT_LOG.lock().unwrap();
+    if let Some(log) = guard.as_mut()
+        && let Err(report) = log.write(&record)
+    {
+        warn!("Failed to write audit log: {:?}", report);
+    }
+}
+
+/// 记录一次LLM调用
+/// Record one LLM call
+pub(crate) fn record_llm_call(call: &LlmCall) {
+    append(json!({
+        "timestamp": rfc3339(SystemTime::now()),
+        "type": "llm_call",
+        "request_id": call.request_id,
+        "session_id": call.session_id,
+        "model": call.model,
+        "base_url": call.base_url,
+        "started_at": rfc3339(call.started_at),
+        "latency_ms": call.latency.as_millis() as u64,
+        "request": call.request_body,
+        "response": call.response,
+        "error": call.error,
+        "http_status": call.http_status,
+    }));
+}
+
+/// 记录一次工具调用
+/// Record one tool call
+pub(crate) fn record_tool_call(tool: &ToolCallRecord) {
+    append(json!({
+        "timestamp": rfc3339(SystemTime::now()),
+        "type": "tool_call",
+        "id": tool.id,
+        "session_id": tool.session_id,
+        "name": tool.name,
+        "started_at": rfc3339(tool.started_at),
+        "duration_ms": tool.duration.as_millis() as u64,
+        "arguments": tool.arguments,
+        "output": tool.output,
+        "error": tool.error,
+    }));
+}
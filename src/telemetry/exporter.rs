@@ -0,0 +1,258 @@
+// 标准库
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+// 并发和同步原语
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+
+// 异步
+use futures::future::BoxFuture;
+use tokio::runtime::Handle;
+
+// 序列化
+use serde::Deserialize;
+
+// 错误处理
+use error_stack::Result;
+use thiserror::Error;
+
+// 观测诊断
+use tracing::warn;
+
+// 项目内部模块
+use crate::telemetry::LlmCall;
+use crate::telemetry::langfuse::LangfuseExporter;
+use crate::telemetry::langsmith::LangSmithExporter;
+
+/// 追踪导出错误枚举
+/// Trace export error enum
+#[derive(Debug, Error)]
+pub enum ExportError {
+    /// 请求发送失败
+    /// Request could not be sent
+    #[error("Network error: {0}")]
+    NetworkError(String),
+
+    /// 接收端返回非2xx状态
+    /// Ingestion endpoint returned a non-2xx status
+    #[error("HTTP error with status code: {0}")]
+    HttpError(u16),
+}
+
+/// 工具调用记录
+/// Tool call record
+#[derive(Clone, Debug)]
+pub struct ToolCallRecord {
+    pub id: String,
+
+    /// 所属对话的会话ID
+    /// Session id of the owning chat
+    pub session_id: String,
+
+    pub name: String,
+
+    pub arguments: serde_json::Value,
+
+    pub output: Option<String>,
+
+    pub error: Option<String>,
+
+    pub started_at: SystemTime,
+
+    pub duration: Duration,
+}
+
+/// 评分，用于在LLM运维平台中标注回答质量
+/// Score, used to annotate answer quality in LLM-ops tools
+#[derive(Clone, Debug)]
+pub struct Score {
+    /// 被评分对话的会话ID
+    /// Session id of the scored chat
+    pub session_id: String,
+
+    /// 被评分的单次请求ID，为 None 时评分整个会话
+    /// Request id of the scored call, the whole session is scored if None
+    pub request_id: Option<String>,
+
+    pub name: String,
+
+    pub value: f64,
+
+    pub comment: Option<String>,
+}
+
+/// 追踪事件
+/// Trace event
+#[derive(Clone, Debug)]
+pub enum TraceEvent {
+    /// 一次LLM生成
+    /// One LLM generation
+    Generation(LlmCall),
+
+    /// 一次工具调用
+    /// One tool call
+    ToolCall(ToolCallRecord),
+
+    /// 一条评分
+    /// One score
+    Score(Score),
+}
+
+/// 追踪导出器，将对话追踪发送到外部LLM运维平台
+/// Trace exporter, ships conversation traces to an external LLM-ops tool
+pub trait TraceExporter: Send + Sync {
+    /// 导出一个事件
+    /// Export one event
+    fn export<'a>(&'a self, event: &'a TraceEvent) -> BoxFuture<'a, Result<(), ExportError>>;
+}
+
+/// 全局追踪导出器表 - 存储导出器名称到导出器的映射
+/// Global trace exporter table - stores mappings from exporter name to exporter
+pub static TRACE_EXPORTERS: Lazy<DashMap<String, Arc<dyn TraceExporter>>> = Lazy::new(DashMap::new);
+
+/// 注册追踪导出器，同名导出器会被替换
+/// Register a trace exporter, replacing any exporter with the same name
+pub fn register_trace_exporter(name: &str, exporter: Arc<dyn TraceExporter>) {
+    TRACE_EXPORTERS.insert(name.to_string(), exporter);
+}
+
+/// 移除追踪导出器
+/// Remove a trace exporter
+pub fn remove_trace_exporter(name: &str) {
+    TRACE_EXPORTERS.remove(name);
+}
+
+/// 提交一条评分
+/// Submit a score
+pub fn submit_score(score: Score) {
+    dispatch(TraceEvent::Score(score));
+}
+
+/// 在后台将事件发送给所有已注册的导出器，失败只记录警告，不影响对话
+/// Send an event to every registered exporter in the background, failures only log a warning and never affect the chat
+///
+/// 不在 Tokio 运行时内时事件被丢弃。
+/// Events are dropped outside a Tokio runtime.
+pub(crate) fn dispatch(event: TraceEvent) {
+    if TRACE_EXPORTERS.is_empty() {
+        return;
+    }
+    let Ok(handle) = Handle::try_current() else {
+        return;
+    };
+
+    let exporters: Vec<(String, Arc<dyn TraceExporter>)> = TRACE_EXPORTERS
+        .iter()
+        .map(|entry| (entry.key().clone(), entry.value().clone()))
+        .collect();
+
+    handle.spawn(async move {
+        for (name, exporter) in exporters {
+            if let Err(report) = exporter.export(&event).await {
+                warn!("Trace exporter '{}' failed: {:?}", name, report);
+            }
+        }
+    });
+}
+
+/// 配置文件中的追踪导出器条目
+/// Trace exporter entry in the config file
+#[derive(Clone, Debug, Deserialize)]
+pub struct TraceExporterEntry {
+    pub name: String,
+
+    #[serde(flatten)]
+    pub kind: TraceExporterKind,
+}
+
+/// 追踪导出器类型及其连接参数
+/// Trace exporter kind and its connection parameters
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TraceExporterKind {
+    /// Langfuse（`POST {host}/api/public/ingestion`）
+    Langfuse {
+        #[serde(default = "default_langfuse_host")]
+        host: String,
+        public_key: String,
+        secret_key: String,
+    },
+
+    /// LangSmith（`POST {host}/runs/batch`）
+    #[serde(rename = "langsmith")]
+    LangSmith {
+        #[serde(default = "default_langsmith_host")]
+        host: String,
+        api_key: String,
+        #[serde(default)]
+        project: Option<String>,
+    },
+}
+
+impl TraceExporterKind {
+    /// 按连接参数创建导出器
+    /// Create the exporter from the connection parameters
+    pub fn build(self) -> Arc<dyn TraceExporter> {
+        match self {
+            TraceExporterKind::Langfuse { host, public_key, secret_key } => {
+                Arc::new(LangfuseExporter::new(&host, &public_key, &secret_key))
+            }
+            TraceExporterKind::LangSmith { host, api_key, project } => {
+                Arc::new(LangSmithExporter::new(&host, &api_key, project.as_deref()))
+            }
+        }
+    }
+
+    /// 对所有字符串参数应用变换，用于展开环境变量和解析密钥引用
+    /// Apply a transformation to every string parameter, used to expand environment variables and resolve secret references
+    pub(crate) fn try_map_values<E>(
+        self,
+        mut f: impl FnMut(&str, String) -> core::result::Result<String, E>,
+    ) -> core::result::Result<Self, E> {
+        Ok(match self {
+            TraceExporterKind::Langfuse { host, public_key, secret_key } => TraceExporterKind::Langfuse {
+                host: f("host", host)?,
+                public_key: f("public_key", public_key)?,
+                secret_key: f("secret_key", secret_key)?,
+            },
+            TraceExporterKind::LangSmith { host, api_key, project } => TraceExporterKind::LangSmith {
+                host: f("host", host)?,
+                api_key: f("api_key", api_key)?,
+                project,
+            },
+        })
+    }
+}
+
+fn default_langfuse_host() -> String {
+    "https://cloud.langfuse.com".to_string()
+}
+
+fn default_langsmith_host() -> String {
+    "https://api.smith.langchain.com".to_string()
+}
+
+/// 将时间格式化为 RFC 3339（微秒精度，UTC）
+/// Format a time as RFC 3339 (microsecond precision, UTC)
+pub(crate) fn rfc3339(time: SystemTime) -> String {
+    chrono::DateTime::<chrono::Utc>::from(time).to_rfc3339_opts(chrono::SecondsFormat::Micros, true)
+}
+
+/// 发送 JSON 请求，非2xx状态视为失败
+/// Send a JSON request, a non-2xx status counts as failure
+pub(crate) async fn send_json(request: reqwest::RequestBuilder, body: &serde_json::Value) -> Result<(), ExportError> {
+    let response = request
+        .json(body)
+        .send()
+        .await
+        .map_err(|e| error_stack::Report::new(ExportError::NetworkError(e.to_string())))?;
+
+    if !response.status().is_success() {
+        let status = response.status().as_u16();
+        let text = response.text().await.unwrap_or_default();
+        return Err(error_stack::Report::new(ExportError::HttpError(status)).attach_printable(text));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,155 @@
+// 标准库
+use std::time::SystemTime;
+
+// 异步
+use futures::FutureExt;
+use futures::future::BoxFuture;
+
+// HTTP客户端
+use reqwest::Client;
+
+// 序列化
+use serde_json::json;
+
+// 错误处理
+use error_stack::Result;
+
+// 唯一标识
+use uuid::Uuid;
+
+// 项目内部模块
+use crate::config::http::shared_client;
+use crate::telemetry::exporter::{ExportError, TraceEvent, TraceExporter, rfc3339, send_json};
+
+/// Langfuse 导出器
+/// Langfuse exporter
+///
+/// 每个对话会话对应一条 Langfuse trace，LLM调用记为 generation，工具调用记为 span。
+/// Every chat session maps to one Langfuse trace, LLM calls become generations and tool calls become spans.
+pub struct LangfuseExporter {
+    client: Client,
+
+    endpoint: String,
+
+    public_key: String,
+
+    secret_key: String,
+}
+
+impl LangfuseExporter {
+    /// 创建 Langfuse 导出器
+    /// Create a Langfuse exporter
+    ///
+    /// # 参数 (Parameters)
+    /// * `host` - Langfuse 地址，如 `https://cloud.langfuse.com` / Langfuse host, such as `https://cloud.langfuse.com`
+    /// * `public_key` - 项目公钥 / Project public key
+    /// * `secret_key` - 项目私钥 / Project secret key
+    pub fn new(host: &str, public_key: &str, secret_key: &str) -> Self {
+        Self {
+            client: shared_client(),
+            endpoint: format!("{}/api/public/ingestion", host.trim_end_matches('/')),
+            public_key: public_key.to_string(),
+            secret_key: secret_key.to_string(),
+        }
+    }
+
+    /// 将事件转换为 Langfuse 摄取批次
+    /// Convert an event into a Langfuse ingestion batch
+    pub fn to_batch(event: &TraceEvent) -> serde_json::Value {
+        let now = rfc3339(SystemTime::now());
+        let envelope = |kind: &str, body: serde_json::Value| {
+            json!({
+                "id": Uuid::new_v4().to_string(),
+                "timestamp": now,
+                "type": kind,
+                "body": body,
+            })
+        };
+
+        let batch = match event {
+            TraceEvent::Generation(call) => {
+                let mut generation = json!({
+                    "id": call.request_id,
+                    "traceId": call.session_id,
+                    "name": "llm_call",
+                    "startTime": rfc3339(call.started_at),
+                    "endTime": rfc3339(call.started_at + call.latency),
+                    "model": call.model,
+                    "input": call.request_body.get("messages"),
+                    "output": call.output,
+                    "metadata": {
+                        "base_url": call.base_url,
+                        "capability": call.capability.as_ref().map(|capability| format!("{:?}", capability)),
+                        "stream": call.stream,
+                        "finish_reason": call.finish_reason,
+                    },
+                });
+                if let Some(ttft) = call.time_to_first_token {
+                    generation["completionStartTime"] = json!(rfc3339(call.started_at + ttft));
+                }
+                if let Some(usage) = &call.usage {
+                    generation["usage"] = json!({
+                        "input": usage.prompt_tokens,
+                        "output": usage.completion_tokens,
+                        "total": usage.total_tokens,
+                        "unit": "TOKENS",
+                    });
+                }
+                if let Some(error) = &call.error {
+                    generation["level"] = json!("ERROR");
+                    generation["statusMessage"] = json!(error);
+                }
+
+                vec![
+                    envelope("trace-create", json!({"id": call.session_id, "name": "chat"})),
+                    envelope("generation-create", generation),
+                ]
+            }
+            TraceEvent::ToolCall(tool) => {
+                let mut span = json!({
+                    "id": tool.id,
+                    "traceId": tool.session_id,
+                    "name": tool.name,
+                    "startTime": rfc3339(tool.started_at),
+                    "endTime": rfc3339(tool.started_at + tool.duration),
+                    "input": tool.arguments,
+                    "output": tool.output,
+                });
+                if let Some(error) = &tool.error {
+                    span["level"] = json!("ERROR");
+                    span["statusMessage"] = json!(error);
+                }
+
+                vec![
+                    envelope("trace-create", json!({"id": tool.session_id, "name": "chat"})),
+                    envelope("span-create", span),
+                ]
+            }
+            TraceEvent::Score(score) => vec![envelope(
+                "score-create",
+                json!({
+                    "id": Uuid::new_v4().to_string(),
+                    "traceId": score.session_id,
+                    "observationId": score.request_id,
+                    "name": score.name,
+                    "value": score.value,
+                    "comment": score.comment,
+                }),
+            )],
+        };
+
+        json!({ "batch": batch })
+    }
+}
+
+impl TraceExporter for LangfuseExporter {
+    fn export<'a>(&'a self, event: &'a TraceEvent) -> BoxFuture<'a, Result<(), ExportError>> {
+        async move {
+            let request = self.client
+                .post(&self.endpoint)
+                .basic_auth(&self.public_key, Some(&self.secret_key));
+            send_json(request, &Self::to_batch(event)).await
+        }
+        .boxed()
+    }
+}
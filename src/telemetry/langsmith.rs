@@ -0,0 +1,192 @@
+// 标准库
+use std::time::SystemTime;
+
+// 并发
+use dashmap::DashMap;
+
+// 异步
+use futures::FutureExt;
+use futures::future::BoxFuture;
+
+// HTTP客户端
+use reqwest::Client;
+
+// 序列化
+use serde_json::json;
+
+// 错误处理
+use error_stack::Result;
+
+// 项目内部模块
+use crate::config::http::shared_client;
+use crate::telemetry::exporter::{ExportError, TraceEvent, TraceExporter, rfc3339, send_json};
+
+/// LangSmith 导出器
+/// LangSmith exporter
+///
+/// 每个对话会话对应一条以会话ID为根运行的 LangSmith trace，LLM调用记为 `llm` 子运行，工具调用记为 `tool` 子运行；
+/// 会话ID同时写入 `session_id` 元数据，因此同一对话的运行在 LangSmith 中归入同一线程。
+/// Every chat session maps to one LangSmith trace whose root run has the session id, LLM calls become `llm` child
+/// runs and tool calls become `tool` child runs; the session id also goes into the `session_id` metadata, so runs of
+/// the same chat are grouped into one LangSmith thread.
+pub struct LangSmithExporter {
+    client: Client,
+
+    host: String,
+
+    api_key: String,
+
+    project: Option<String>,
+
+    /// 已创建的根运行的排序键，按会话ID索引
+    /// Ordering keys of the root runs already created, keyed by session id
+    roots: DashMap<String, String>,
+}
+
+impl LangSmithExporter {
+    /// 创建 LangSmith 导出器
+    /// Create a LangSmith exporter
+    ///
+    /// # 参数 (Parameters)
+    /// * `host` - LangSmith API地址，如 `https://api.smith.langchain.com`
+    ///   - LangSmith API host, such as `https://api.smith.langchain.com`
+    /// * `api_key` - API密钥 / API key
+    /// * `project` - 项目名称，为 None 时使用 LangSmith 默认项目 / Project name, the LangSmith default project is used if None
+    pub fn new(host: &str, api_key: &str, project: Option<&str>) -> Self {
+        Self {
+            client: shared_client(),
+            host: host.trim_end_matches('/').to_string(),
+            api_key: api_key.to_string(),
+            project: project.map(str::to_string),
+            roots: DashMap::new(),
+        }
+    }
+
+    /// 会话根运行的排序键；会话首次出现时同时返回需要创建的根运行
+    /// Ordering key of the root run of a session; the root run to create is returned as well the first time the
+    /// session is seen
+    fn trace_root(&self, session_id: &str, start: SystemTime) -> (String, Option<serde_json::Value>) {
+        if let Some(order) = self.roots.get(session_id) {
+            return (order.clone(), None);
+        }
+        let order = dotted_order(start, session_id);
+        self.roots.insert(session_id.to_string(), order.clone());
+        let root = json!({
+            "id": session_id,
+            "trace_id": session_id,
+            "dotted_order": order,
+            "name": "chat",
+            "run_type": "chain",
+            "start_time": rfc3339(start),
+            "inputs": {},
+            "extra": {"metadata": {"session_id": session_id}},
+        });
+        (order, Some(root))
+    }
+
+    /// 将生成或工具调用事件转换为待创建的运行，会话首次出现时根运行排在最前；评分事件返回空列表
+    /// Convert a generation or tool call event into the runs to create, led by the root run the first time the
+    /// session is seen; an empty list for score events
+    pub fn to_runs(&self, event: &TraceEvent) -> Vec<serde_json::Value> {
+        let (session_id, started_at) = match event {
+            TraceEvent::Generation(call) => (&call.session_id, call.started_at),
+            TraceEvent::ToolCall(tool) => (&tool.session_id, tool.started_at),
+            TraceEvent::Score(_) => return Vec::new(),
+        };
+        let (root_order, root) = self.trace_root(session_id, started_at);
+
+        let run = match event {
+            TraceEvent::Generation(call) => {
+                let mut run = json!({
+                    "id": call.request_id,
+                    "trace_id": call.session_id,
+                    "parent_run_id": call.session_id,
+                    "dotted_order": format!("{}.{}", root_order, dotted_order(call.started_at, &call.request_id)),
+                    "name": "llm_call",
+                    "run_type": "llm",
+                    "start_time": rfc3339(call.started_at),
+                    "end_time": rfc3339(call.started_at + call.latency),
+                    "inputs": {"messages": call.request_body.get("messages")},
+                    "outputs": {"content": call.output, "finish_reason": call.finish_reason},
+                    "extra": {
+                        "metadata": {
+                            "session_id": call.session_id,
+                            "ls_model_name": call.model,
+                            "ls_provider": call.system(),
+                            "capability": call.capability.as_ref().map(|capability| format!("{:?}", capability)),
+                        },
+                    },
+                    "error": call.error,
+                });
+                if let Some(usage) = &call.usage {
+                    run["outputs"]["usage_metadata"] = json!({
+                        "input_tokens": usage.prompt_tokens,
+                        "output_tokens": usage.completion_tokens,
+                        "total_tokens": usage.total_tokens,
+                    });
+                }
+                run
+            }
+            TraceEvent::ToolCall(tool) => json!({
+                "id": tool.id,
+                "trace_id": tool.session_id,
+                "parent_run_id": tool.session_id,
+                "dotted_order": format!("{}.{}", root_order, dotted_order(tool.started_at, &tool.id)),
+                "name": tool.name,
+                "run_type": "tool",
+                "start_time": rfc3339(tool.started_at),
+                "end_time": rfc3339(tool.started_at + tool.duration),
+                "inputs": tool.arguments,
+                "outputs": {"output": tool.output},
+                "extra": {"metadata": {"session_id": tool.session_id}},
+                "error": tool.error,
+            }),
+            TraceEvent::Score(_) => return Vec::new(),
+        };
+
+        let mut runs: Vec<_> = root.into_iter().chain([run]).collect();
+        if let Some(project) = &self.project {
+            for run in &mut runs {
+                run["session_name"] = json!(project);
+            }
+        }
+        runs
+    }
+}
+
+impl TraceExporter for LangSmithExporter {
+    fn export<'a>(&'a self, event: &'a TraceEvent) -> BoxFuture<'a, Result<(), ExportError>> {
+        async move {
+            let (url, body) = match event {
+                // LangSmith 的反馈挂在单次运行上，没有请求ID的会话级评分无法导出
+                // LangSmith feedback attaches to a single run, session-level scores without a request id cannot be exported
+                TraceEvent::Score(score) => match &score.request_id {
+                    Some(request_id) => (
+                        format!("{}/feedback", self.host),
+                        json!({
+                            "run_id": request_id,
+                            "key": score.name,
+                            "score": score.value,
+                            "comment": score.comment,
+                        }),
+                    ),
+                    None => return Ok(()),
+                },
+                _ => match self.to_runs(event) {
+                    runs if runs.is_empty() => return Ok(()),
+                    runs => (format!("{}/runs/batch", self.host), json!({"post": runs})),
+                },
+            };
+
+            let request = self.client.post(url).header("x-api-key", &self.api_key);
+            send_json(request, &body).await
+        }
+        .boxed()
+    }
+}
+
+/// 生成 LangSmith 运行的排序键：`<UTC时间戳><运行ID>`
+/// Build the ordering key of a LangSmith run: `<UTC timestamp><run id>`
+fn dotted_order(start: SystemTime, id: &str) -> String {
+    format!("{}{}", chrono::DateTime::<chrono::Utc>::from(start).format("%Y%m%dT%H%M%S%6fZ"), id)
+}
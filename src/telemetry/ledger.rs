@@ -0,0 +1,373 @@
+// 标准库
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{Sender, channel};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// 并发和同步原语
+use once_cell::sync::Lazy;
+
+// 序列化
+use serde::{Deserialize, Serialize};
+
+// 错误处理
+use error_stack::{Result, ResultExt};
+use thiserror::Error;
+
+// 观测诊断
+use tracing::warn;
+
+// 项目内部模块
+use crate::config::Config;
+use crate::telemetry::LlmCall;
+use crate::telemetry::usage::UsageTotals;
+
+/// 用量账本错误枚举
+/// Usage ledger error enum
+#[derive(Debug, Error)]
+pub enum LedgerError {
+    /// 账本读写失败
+    /// Reading or writing the ledger failed
+    #[error("Usage ledger IO error: {0}")]
+    IoError(String),
+}
+
+/// 账本中的一条用量记录，对应一次LLM调用
+/// One usage record of the ledger, for one LLM call
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct UsageRecord {
+    pub request_id: String,
+
+    /// 所属对话的会话ID
+    /// Session id of the owning chat
+    pub session_id: String,
+
+    pub model: String,
+
+    #[serde(default)]
+    pub capability: Option<String>,
+
+    #[serde(default)]
+    pub tags: Vec<String>,
+
+    /// 调用开始时间（Unix 毫秒）
+    /// Start time of the call (Unix milliseconds)
+    pub timestamp_ms: u64,
+
+    pub latency_ms: u64,
+
+    pub prompt_tokens: u64,
+
+    pub completion_tokens: u64,
+
+    pub total_tokens: u64,
+
+    /// 记录时按模型价格计算的费用（美元），未配置价格时为 None
+    /// Cost in USD computed from the model prices when recorded, None if no price is configured
+    #[serde(default)]
+    pub cost: Option<f64>,
+
+    #[serde(default)]
+    pub error: bool,
+}
+
+impl UsageRecord {
+    pub fn from_call(call: &LlmCall) -> Self {
+        let usage = call.usage.unwrap_or_default();
+        Self {
+            request_id: call.request_id.clone(),
+            session_id: call.session_id.clone(),
+            model: call.model.clone(),
+            capability: call.capability.as_ref().map(|capability| format!("{:?}", capability)),
+            tags: call.tags.clone(),
+            timestamp_ms: unix_millis(call.started_at),
+            latency_ms: call.latency.as_millis() as u64,
+            prompt_tokens: usage.prompt_tokens,
+            completion_tokens: usage.completion_tokens,
+            total_tokens: usage.total_tokens,
+            cost: call
+                .usage
+                .and_then(|usage| Config::get_model_metadata(&call.model)?.cost(&usage)),
+            error: call.error.is_some(),
+        }
+    }
+
+    pub fn timestamp(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(self.timestamp_ms)
+    }
+}
+
+impl From<&UsageRecord> for UsageTotals {
+    fn from(record: &UsageRecord) -> Self {
+        Self {
+            requests: 1,
+            errors: record.error as u64,
+            prompt_tokens: record.prompt_tokens,
+            completion_tokens: record.completion_tokens,
+            total_tokens: record.total_tokens,
+            cost: record.cost.unwrap_or(0.0),
+        }
+    }
+}
+
+/// 汇总的分组维度
+/// Grouping dimension of an aggregation
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UsageGroup {
+    Model,
+
+    /// 未按能力创建的对话归入 `none`
+    /// Chats not created by capability fall under `none`
+    Capability,
+
+    /// 一条记录计入其每个标签
+    /// A record counts towards each of its tags
+    Tag,
+
+    Session,
+}
+
+/// 用量账本的存储后端，记录在后台写入，查询按调用开始时间筛选
+/// Storage backend of the usage ledger, records are written in the background and queries filter by the start time
+/// of the call
+pub trait UsageLedger: Send + Sync {
+    fn append(&self, records: &[UsageRecord]) -> Result<(), LedgerError>;
+
+    /// 取出 `from` 起（含）至 `to` 止（不含）的记录
+    /// Fetch the records from `from` (inclusive) to `to` (exclusive)
+    fn query(&self, from: SystemTime, to: SystemTime) -> Result<Vec<UsageRecord>, LedgerError>;
+
+    /// 时间范围内的用量合计
+    /// Usage totals of a time range
+    fn totals(&self, from: SystemTime, to: SystemTime) -> Result<UsageTotals, LedgerError> {
+        let mut totals = UsageTotals::default();
+        for record in self.query(from, to)? {
+            totals += &UsageTotals::from(&record);
+        }
+        Ok(totals)
+    }
+
+    /// 时间范围内按维度分组的用量合计
+    /// Usage totals of a time range grouped by a dimension
+    fn totals_by(
+        &self,
+        from: SystemTime,
+        to: SystemTime,
+        group: UsageGroup,
+    ) -> Result<BTreeMap<String, UsageTotals>, LedgerError> {
+        let mut grouped: BTreeMap<String, UsageTotals> = BTreeMap::new();
+        for record in self.query(from, to)? {
+            let totals = UsageTotals::from(&record);
+            let keys = match group {
+                UsageGroup::Model => vec![record.model],
+                UsageGroup::Capability => vec![record.capability.unwrap_or_else(|| "none".to_string())],
+                UsageGroup::Tag => record.tags,
+                UsageGroup::Session => vec![record.session_id],
+            };
+            for key in keys {
+                *grouped.entry(key).or_default() += &totals;
+            }
+        }
+        Ok(grouped)
+    }
+
+    /// 时间范围内按固定周期（如一天）切分的用量合计，键为周期的开始时间，周期从 `from` 起算
+    /// Usage totals of a time range split into fixed periods (such as a day), keyed by the start of each period,
+    /// counted from `from`
+    fn series(
+        &self,
+        from: SystemTime,
+        to: SystemTime,
+        period: Duration,
+    ) -> Result<BTreeMap<SystemTime, UsageTotals>, LedgerError> {
+        let period_ms = (period.as_millis() as u64).max(1);
+        let from_ms = unix_millis(from);
+        let mut series: BTreeMap<SystemTime, UsageTotals> = BTreeMap::new();
+        for record in self.query(from, to)? {
+            let offset = (record.timestamp_ms - from_ms) / period_ms * period_ms;
+            *series.entry(from + Duration::from_millis(offset)).or_default() += &UsageTotals::from(&record);
+        }
+        Ok(series)
+    }
+}
+
+/// 进程内账本，进程退出后记录丢失，用于测试或短期统计
+/// In-process ledger, its records are lost when the process exits; for tests or short-lived statistics
+#[derive(Debug, Default)]
+pub struct MemoryUsageLedger {
+    records: Mutex<Vec<UsageRecord>>,
+}
+
+impl UsageLedger for MemoryUsageLedger {
+    fn append(&self, records: &[UsageRecord]) -> Result<(), LedgerError> {
+        self.records.lock().unwrap().extend_from_slice(records);
+        Ok(())
+    }
+
+    fn query(&self, from: SystemTime, to: SystemTime) -> Result<Vec<UsageRecord>, LedgerError> {
+        let (from, to) = (unix_millis(from), unix_millis(to));
+        Ok(self
+            .records
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|record| (from..to).contains(&record.timestamp_ms))
+            .cloned()
+            .collect())
+    }
+}
+
+/// JSONL 文件账本，每条记录一行，只追加写入
+/// JSONL file ledger, one record per line, append-only
+#[derive(Clone, Debug)]
+pub struct JsonlUsageLedger {
+    path: PathBuf,
+}
+
+impl JsonlUsageLedger {
+    pub fn new(path: impl Into<PathBuf>) -> Result<Self, LedgerError> {
+        let path = path.into();
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            fs::create_dir_all(parent).change_context_lazy(|| LedgerError::IoError(path.display().to_string()))?;
+        }
+        Ok(Self { path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn io_error(&self) -> LedgerError {
+        LedgerError::IoError(self.path.display().to_string())
+    }
+}
+
+impl UsageLedger for JsonlUsageLedger {
+    fn append(&self, records: &[UsageRecord]) -> Result<(), LedgerError> {
+        let mut lines = String::new();
+        for record in records {
+            lines.push_str(&serde_json::to_string(record).change_context_lazy(|| self.io_error())?);
+            lines.push('\n');
+        }
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| file.write_all(lines.as_bytes()))
+            .change_context_lazy(|| self.io_error())
+    }
+
+    fn query(&self, from: SystemTime, to: SystemTime) -> Result<Vec<UsageRecord>, LedgerError> {
+        let content = match fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e).change_context_lazy(|| self.io_error()),
+        };
+
+        let (from, to) = (unix_millis(from), unix_millis(to));
+        Ok(content
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .filter_map(|line| {
+                serde_json::from_str::<UsageRecord>(line)
+                    .inspect_err(|e| warn!("Skipping malformed usage record in {}: {}", self.path.display(), e))
+                    .ok()
+            })
+            .filter(|record| (from..to).contains(&record.timestamp_ms))
+            .collect())
+    }
+}
+
+/// 后台写入线程的指令
+/// Command of the background writer thread
+enum Command {
+    Record(UsageRecord),
+
+    /// 写完此前的记录后回复
+    /// Reply once the records before it are written
+    Flush(Sender<()>),
+}
+
+struct LedgerWriter {
+    ledger: Arc<dyn UsageLedger>,
+
+    commands: Sender<Command>,
+}
+
+/// 全局用量账本，为 None 时不记录
+/// Global usage ledger, nothing is recorded if None
+static LEDGER: Lazy<Mutex<Option<LedgerWriter>>> = Lazy::new(|| Mutex::new(None));
+
+/// 开启全局用量账本，替换已开启的账本；记录由后台线程批量写入，不阻塞请求
+/// Enable the global usage ledger, replacing any enabled one; records are written in batches by a background
+/// thread without blocking requests
+pub fn set_usage_ledger(ledger: Arc<dyn UsageLedger>) {
+    let (commands, received) = channel::<Command>();
+    let writer = ledger.clone();
+    let spawned = std::thread::Builder::new().name("rhine-usage-ledger".to_string()).spawn(move || {
+        while let Ok(command) = received.recv() {
+            let mut records = Vec::new();
+            let mut flushes = Vec::new();
+            for command in std::iter::once(command).chain(received.try_iter()) {
+                match command {
+                    Command::Record(record) => records.push(record),
+                    Command::Flush(reply) => flushes.push(reply),
+                }
+            }
+            if !records.is_empty()
+                && let Err(report) = writer.append(&records)
+            {
+                warn!("Failed to write {} usage records: {:?}", records.len(), report);
+            }
+            for reply in flushes {
+                let _ = reply.send(());
+            }
+        }
+    });
+    if let Err(e) = spawned {
+        warn!("Failed to start the usage ledger writer: {}", e);
+        return;
+    }
+    *LEDGER.lock().unwrap() = Some(LedgerWriter { ledger, commands });
+}
+
+/// 关闭全局用量账本，已提交的记录仍会写完
+/// Disable the global usage ledger, records already submitted are still written
+pub fn disable_usage_ledger() {
+    *LEDGER.lock().unwrap() = None;
+}
+
+/// 当前的全局用量账本，用于查询
+/// The current global usage ledger, for queries
+pub fn usage_ledger() -> Option<Arc<dyn UsageLedger>> {
+    LEDGER.lock().unwrap().as_ref().map(|writer| writer.ledger.clone())
+}
+
+/// 等待此前提交的记录全部写入，如在查询或退出前调用
+/// Wait until every record submitted so far is written, such as before querying or exiting
+pub fn flush_usage_ledger() {
+    let (reply, replied) = channel();
+    let sent = LEDGER
+        .lock()
+        .unwrap()
+        .as_ref()
+        .is_some_and(|writer| writer.commands.send(Command::Flush(reply)).is_ok());
+    if sent {
+        let _ = replied.recv();
+    }
+}
+
+/// 记录一次LLM调用，用量账本未开启时忽略
+/// Record one LLM call, ignored when the usage ledger is disabled
+pub(crate) fn record_llm_call(call: &LlmCall) {
+    if let Some(writer) = LEDGER.lock().unwrap().as_ref() {
+        let _ = writer.commands.send(Command::Record(UsageRecord::from_call(call)));
+    }
+}
+
+fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
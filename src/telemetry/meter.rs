@@ -0,0 +1,177 @@
+// 标准库
+use std::time::Duration;
+
+// 指标门面
+use metrics::{Unit, counter, describe_counter, describe_gauge, describe_histogram, gauge, histogram};
+
+// 项目内部模块
+use crate::config::{Config, ModelCapability};
+use crate::telemetry::LlmCall;
+
+/// LLM请求总数，标签：model、capability、status
+/// Total LLM requests, labels: model, capability, status
+pub const LLM_REQUESTS_TOTAL: &str = "rhine_llm_requests_total";
+
+/// LLM请求错误总数，标签：model、status
+/// Total failed LLM requests, labels: model, status
+pub const LLM_ERRORS_TOTAL: &str = "rhine_llm_errors_total";
+
+/// 令牌总数，标签：model、type（input/output）
+/// Total tokens, labels: model, type (input/output)
+pub const LLM_TOKENS_TOTAL: &str = "rhine_llm_tokens_total";
+
+/// 累计费用（美元），仅统计配置了价格的模型，标签：model
+/// Accumulated cost in USD, only models with configured prices are counted, labels: model
+pub const LLM_COST_USD: &str = "rhine_llm_cost_usd";
+
+/// LLM请求耗时，标签：model
+/// LLM request duration, labels: model
+pub const LLM_REQUEST_DURATION_SECONDS: &str = "rhine_llm_request_duration_seconds";
+
+/// 流式请求的首token延迟，标签：model
+/// Time to first token of streaming requests, labels: model
+pub const LLM_TTFT_SECONDS: &str = "rhine_llm_time_to_first_token_seconds";
+
+/// 输出速度（token/秒），标签：model
+/// Output speed in tokens per second, labels: model
+pub const LLM_OUTPUT_TOKENS_PER_SECOND: &str = "rhine_llm_output_tokens_per_second";
+
+/// 等待并发额度的时间，标签：model、capability
+/// Time spent waiting for concurrency permits, labels: model, capability
+pub const LLM_PERMIT_WAIT_SECONDS: &str = "rhine_llm_permit_wait_seconds";
+
+/// 工具调用总数，标签：tool、status
+/// Total tool calls, labels: tool, status
+pub const TOOL_CALLS_TOTAL: &str = "rhine_tool_calls_total";
+
+/// 工具调用耗时，标签：tool
+/// Tool call duration, labels: tool
+pub const TOOL_DURATION_SECONDS: &str = "rhine_tool_duration_seconds";
+
+/// 新建的HTTP连接总数，与请求总数对比可验证连接复用
+/// Total HTTP connections opened, compare with the request total to verify connection reuse
+pub const HTTP_CONNECTIONS_OPENED_TOTAL: &str = "rhine_http_connections_opened_total";
+
+/// 实际发出的DNS查询总数
+/// Total DNS lookups actually performed
+pub const HTTP_DNS_LOOKUPS_TOTAL: &str = "rhine_http_dns_lookups_total";
+
+/// 命中DNS缓存的解析总数
+/// Total resolutions answered from the DNS cache
+pub const HTTP_DNS_CACHE_HITS_TOTAL: &str = "rhine_http_dns_cache_hits_total";
+
+/// 向量缓存命中总数，标签：model
+/// Total embedding cache hits, labels: model
+pub const EMBEDDING_CACHE_HITS_TOTAL: &str = "rhine_embedding_cache_hits_total";
+
+/// 向量缓存未命中总数，标签：model
+/// Total embedding cache misses, labels: model
+pub const EMBEDDING_CACHE_MISSES_TOTAL: &str = "rhine_embedding_cache_misses_total";
+
+/// 向已安装的指标记录器登记所有指标的单位与说明
+/// Register unit and description of every metric with the installed recorder
+pub fn describe_metrics() {
+    describe_counter!(LLM_REQUESTS_TOTAL, Unit::Count, "Total LLM requests");
+    describe_counter!(LLM_ERRORS_TOTAL, Unit::Count, "Total failed LLM requests");
+    describe_counter!(LLM_TOKENS_TOTAL, Unit::Count, "Total prompt and completion tokens");
+    describe_gauge!(LLM_COST_USD, "Accumulated cost of LLM requests in USD");
+    describe_histogram!(LLM_REQUEST_DURATION_SECONDS, Unit::Seconds, "LLM request duration");
+    describe_histogram!(LLM_TTFT_SECONDS, Unit::Seconds, "Time to first token of streaming LLM requests");
+    describe_histogram!(LLM_OUTPUT_TOKENS_PER_SECOND, "Output speed of LLM requests in tokens per second");
+    describe_histogram!(LLM_PERMIT_WAIT_SECONDS, Unit::Seconds, "Time LLM requests waited for concurrency permits");
+    describe_counter!(TOOL_CALLS_TOTAL, Unit::Count, "Total tool calls");
+    describe_histogram!(TOOL_DURATION_SECONDS, Unit::Seconds, "Tool call duration");
+    describe_counter!(HTTP_CONNECTIONS_OPENED_TOTAL, Unit::Count, "Total HTTP connections opened");
+    describe_counter!(HTTP_DNS_LOOKUPS_TOTAL, Unit::Count, "Total DNS lookups performed");
+    describe_counter!(HTTP_DNS_CACHE_HITS_TOTAL, Unit::Count, "Total DNS resolutions answered from cache");
+    describe_counter!(EMBEDDING_CACHE_HITS_TOTAL, Unit::Count, "Total embeddings answered from cache");
+    describe_counter!(EMBEDDING_CACHE_MISSES_TOTAL, Unit::Count, "Total embeddings requested from the model");
+}
+
+/// 记录一次LLM调用的指标
+/// Record the metrics of one LLM call
+pub(crate) fn record_llm_call(call: &LlmCall) {
+    let model = call.model.clone();
+    let capability = call
+        .capability
+        .as_ref()
+        .map_or_else(|| "none".to_string(), |capability| format!("{:?}", capability));
+    let status = call.status();
+
+    counter!(LLM_REQUESTS_TOTAL, "model" => model.clone(), "capability" => capability, "status" => status.clone())
+        .increment(1);
+    if call.error.is_some() {
+        counter!(LLM_ERRORS_TOTAL, "model" => model.clone(), "status" => status).increment(1);
+    }
+
+    histogram!(LLM_REQUEST_DURATION_SECONDS, "model" => model.clone()).record(call.latency.as_secs_f64());
+    if let Some(ttft) = call.time_to_first_token {
+        histogram!(LLM_TTFT_SECONDS, "model" => model.clone()).record(ttft.as_secs_f64());
+    }
+    if let Some(tokens_per_second) = call.tokens_per_second() {
+        histogram!(LLM_OUTPUT_TOKENS_PER_SECOND, "model" => model.clone()).record(tokens_per_second);
+    }
+
+    if let Some(usage) = &call.usage {
+        counter!(LLM_TOKENS_TOTAL, "model" => model.clone(), "type" => "input").increment(usage.prompt_tokens);
+        counter!(LLM_TOKENS_TOTAL, "model" => model.clone(), "type" => "output").increment(usage.completion_tokens);
+
+        if let Some(cost) = Config::get_model_metadata(&call.model).and_then(|metadata| metadata.cost(usage)) {
+            // 计数器只接受整数，费用用只增不减的 gauge 累加
+            // Counters only take integers, so the cost accumulates in a gauge that only ever increases
+            gauge!(LLM_COST_USD, "model" => model).increment(cost);
+        }
+    }
+}
+
+/// 记录一次请求等待并发额度的时间
+/// Record how long one request waited for its concurrency permits
+pub(crate) fn record_permit_wait(model: &str, capability: Option<&ModelCapability>, wait: Duration) {
+    let capability = capability.map_or_else(|| "none".to_string(), |capability| format!("{:?}", capability));
+    histogram!(LLM_PERMIT_WAIT_SECONDS, "model" => model.to_string(), "capability" => capability)
+        .record(wait.as_secs_f64());
+}
+
+/// 记录一次工具调用的指标
+/// Record the metrics of one tool call
+pub(crate) fn record_tool_call(tool: &str, duration: Duration, succeeded: bool) {
+    let status = if succeeded { "ok" } else { "error" };
+    counter!(TOOL_CALLS_TOTAL, "tool" => tool.to_string(), "status" => status).increment(1);
+    histogram!(TOOL_DURATION_SECONDS, "tool" => tool.to_string()).record(duration.as_secs_f64());
+}
+
+/// 安装 Prometheus 记录器并在指定地址开启抓取端点（`GET /metrics`）
+/// Install the Prometheus recorder and serve the scrape endpoint (`GET /metrics`) on the given address
+///
+/// 需要在 Tokio 运行时内调用。
+/// Must be called inside a Tokio runtime.
+#[cfg(feature = "prometheus")]
+pub fn install_prometheus_exporter(
+    addr: std::net::SocketAddr,
+) -> core::result::Result<(), metrics_exporter_prometheus::BuildError> {
+    prometheus_builder().with_http_listener(addr).install()?;
+    describe_metrics();
+    Ok(())
+}
+
+/// 仅安装 Prometheus 记录器，由应用在自己的HTTP服务中调用 `render()` 暴露指标
+/// Install only the Prometheus recorder, the application exposes metrics by calling `render()` in its own HTTP server
+#[cfg(feature = "prometheus")]
+pub fn install_prometheus_recorder()
+-> core::result::Result<metrics_exporter_prometheus::PrometheusHandle, metrics_exporter_prometheus::BuildError> {
+    let handle = prometheus_builder().install_recorder()?;
+    describe_metrics();
+    Ok(handle)
+}
+
+#[cfg(feature = "prometheus")]
+fn prometheus_builder() -> metrics_exporter_prometheus::PrometheusBuilder {
+    use metrics_exporter_prometheus::{Matcher, PrometheusBuilder};
+
+    PrometheusBuilder::new()
+        .set_buckets_for_metric(
+            Matcher::Suffix("_seconds".to_string()),
+            &[0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0],
+        )
+        .expect("bucket list is not empty")
+}
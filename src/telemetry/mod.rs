@@ -0,0 +1,189 @@
+pub mod anonymize;
+pub mod audit;
+pub mod exporter;
+pub mod langfuse;
+pub mod langsmith;
+pub mod ledger;
+pub mod meter;
+#[cfg(feature = "otel")]
+pub mod otel;
+pub mod usage;
+
+// 标准库
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+// 项目内部模块
+use crate::config::ModelCapability;
+use crate::telemetry::exporter::ToolCallRecord;
+
+/// 一次LLM调用的令牌用量
+/// Token usage of one LLM call
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TokenUsage {
+    pub prompt_tokens: u64,
+
+    pub completion_tokens: u64,
+
+    pub total_tokens: u64,
+}
+
+impl TokenUsage {
+    /// 从响应中的 `usage` 对象解析，缺少 `total_tokens` 时返回 None
+    /// Parse from the `usage` object of a response, None if `total_tokens` is missing
+    pub fn from_json(usage: &serde_json::Value) -> Option<Self> {
+        let total_tokens = usage["total_tokens"].as_u64()?;
+        Some(Self {
+            prompt_tokens: usage["prompt_tokens"].as_u64().unwrap_or(0),
+            completion_tokens: usage["completion_tokens"].as_u64().unwrap_or(0),
+            total_tokens,
+        })
+    }
+}
+
+/// 一次已完成的LLM调用记录，交给各遥测后端导出
+/// Record of one finished LLM call, handed to every telemetry backend for export
+#[derive(Clone, Debug)]
+pub struct LlmCall {
+    /// 请求ID，与追踪 span 中的 `request_id` 一致
+    /// Request id, the same as `request_id` of the tracing span
+    pub request_id: String,
+
+    /// 所属对话的会话ID
+    /// Session id of the owning chat
+    pub session_id: String,
+
+    pub model: String,
+
+    pub base_url: String,
+
+    pub capability: Option<ModelCapability>,
+
+    pub message_count: usize,
+
+    pub stream: bool,
+
+    /// 对话上的用户标签，如租户或功能名称
+    /// User tags on the chat, such as a tenant or feature name
+    pub tags: Vec<String>,
+
+    /// 发送的请求体，与发送请求共享同一份数据
+    /// Request body that was sent, sharing the data used to send the request
+    pub request_body: Arc<serde_json::Value>,
+
+    /// 回答内容
+    /// Answer content
+    pub output: Option<String>,
+
+    /// 原始响应；流式请求为汇总后的内容、用量与结束原因
+    /// Raw response; for streaming requests the collected content, usage and finish reason
+    pub response: Option<serde_json::Value>,
+
+    /// 调用开始的墙上时间
+    /// Wall-clock time the call started
+    pub started_at: SystemTime,
+
+    pub latency: Duration,
+
+    /// 流式请求的首token延迟
+    /// Time to first token of a streaming request
+    pub time_to_first_token: Option<Duration>,
+
+    pub usage: Option<TokenUsage>,
+
+    pub finish_reason: Option<String>,
+
+    /// 失败时的错误描述
+    /// Error description on failure
+    pub error: Option<String>,
+
+    /// 失败时的HTTP状态码
+    /// HTTP status code on failure
+    pub http_status: Option<u16>,
+}
+
+impl LlmCall {
+    /// 输出速度（token/秒），流式请求从首token开始计时
+    /// Output speed in tokens per second, timed from the first token for streaming requests
+    pub fn tokens_per_second(&self) -> Option<f64> {
+        let generation = self.latency.saturating_sub(self.time_to_first_token.unwrap_or_default());
+        match &self.usage {
+            Some(usage) if !generation.is_zero() => Some(usage.completion_tokens as f64 / generation.as_secs_f64()),
+            _ => None,
+        }
+    }
+
+    /// 调用结果的状态标签：成功为 `ok`，HTTP错误为状态码，其余错误为 `error`
+    /// Status label of the call: `ok` on success, the status code on HTTP errors, `error` otherwise
+    pub fn status(&self) -> String {
+        match (&self.error, self.http_status) {
+            (None, _) => "ok".to_string(),
+            (Some(_), Some(status)) => status.to_string(),
+            (Some(_), None) => "error".to_string(),
+        }
+    }
+
+    /// 按基础URL推断提供商名称，用作 `gen_ai.system`
+    /// Infer the provider name from the base URL, used as `gen_ai.system`
+    ///
+    /// 未知主机按 OpenAI 兼容接口处理。
+    /// Unknown hosts are treated as OpenAI-compatible.
+    pub fn system(&self) -> &'static str {
+        provider_system(&self.base_url)
+    }
+}
+
+/// 根据接口地址的主机名推断提供商，未知主机按 OpenAI 兼容接口处理
+/// Infer the provider from the host of an endpoint URL, unknown hosts are treated as OpenAI-compatible
+pub fn provider_system(base_url: &str) -> &'static str {
+    let host = reqwest::Url::parse(base_url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+        .unwrap_or_default();
+
+    [
+        ("anthropic", "anthropic"),
+        ("deepseek", "deepseek"),
+        ("googleapis", "gcp.gemini"),
+        ("mistral", "mistral_ai"),
+        ("groq", "groq"),
+        ("x.ai", "xai"),
+        ("azure", "azure.ai.openai"),
+    ]
+    .into_iter()
+    .find(|(pattern, _)| host.contains(pattern))
+    .map_or("openai", |(_, system)| system)
+}
+
+/// 将一次LLM调用导出到所有已启用的遥测后端，审计日志与追踪导出器收到的是匿名化后的记录
+/// Export one LLM call to every enabled telemetry backend, the audit log and trace exporters get the anonymized
+/// record
+pub(crate) fn export_llm_call(call: LlmCall) {
+    meter::record_llm_call(&call);
+    usage::UsageAggregator::global().record(&call);
+    ledger::record_llm_call(&call);
+
+    let call = match anonymize::global() {
+        Some(anonymizer) => anonymizer.anonymize_call(&call),
+        None => call,
+    };
+    audit::record_llm_call(&call);
+
+    #[cfg(feature = "otel")]
+    otel::export_llm_call(&call);
+
+    exporter::dispatch(exporter::TraceEvent::Generation(call));
+}
+
+/// 将一次工具调用导出到所有已启用的遥测后端，审计日志与追踪导出器收到的是匿名化后的记录
+/// Export one tool call to every enabled telemetry backend, the audit log and trace exporters get the anonymized
+/// record
+pub(crate) fn export_tool_call(tool: ToolCallRecord) {
+    meter::record_tool_call(&tool.name, tool.duration, tool.error.is_none());
+    let tool = match anonymize::global() {
+        Some(anonymizer) => anonymizer.anonymize_tool(&tool),
+        None => tool,
+    };
+    audit::record_tool_call(&tool);
+    exporter::dispatch(exporter::TraceEvent::ToolCall(tool));
+}
@@ -0,0 +1,92 @@
+// 并发和同步原语
+use once_cell::sync::Lazy;
+
+// OpenTelemetry
+use opentelemetry::metrics::Histogram;
+use opentelemetry::trace::{Span, SpanKind, Status, Tracer};
+use opentelemetry::{KeyValue, StringValue, Value, global};
+
+// 项目内部模块
+use crate::telemetry::LlmCall;
+
+/// 注册到 OpenTelemetry 的仪表名称
+/// Instrumentation scope name registered with OpenTelemetry
+pub const INSTRUMENTATION_NAME: &str = "rhine";
+
+/// 令牌用量直方图（`gen_ai.client.token.usage`）
+/// Token usage histogram (`gen_ai.client.token.usage`)
+static TOKEN_USAGE: Lazy<Histogram<u64>> = Lazy::new(|| {
+    global::meter(INSTRUMENTATION_NAME)
+        .u64_histogram("gen_ai.client.token.usage")
+        .with_unit("{token}")
+        .with_description("Measures number of input and output tokens used")
+        .build()
+});
+
+/// 调用耗时直方图（`gen_ai.client.operation.duration`）
+/// Operation duration histogram (`gen_ai.client.operation.duration`)
+static OPERATION_DURATION: Lazy<Histogram<f64>> = Lazy::new(|| {
+    global::meter(INSTRUMENTATION_NAME)
+        .f64_histogram("gen_ai.client.operation.duration")
+        .with_unit("s")
+        .with_description("GenAI operation duration")
+        .build()
+});
+
+/// 按 GenAI 语义约定导出一次LLM调用的 span 与指标
+/// Export the span and metrics of one LLM call following the GenAI semantic conventions
+///
+/// 数据写入全局 tracer 与 meter provider，导出器（OTLP、Jaeger 等）由应用自行安装。
+/// Data goes to the global tracer and meter providers; the exporter (OTLP, Jaeger, ...) is installed by the application.
+pub fn export_llm_call(call: &LlmCall) {
+    let mut common = vec![
+        KeyValue::new("gen_ai.operation.name", "chat"),
+        KeyValue::new("gen_ai.system", call.system()),
+        KeyValue::new("gen_ai.request.model", call.model.clone()),
+    ];
+    if let Some(host) = reqwest::Url::parse(&call.base_url).ok().and_then(|url| url.host_str().map(str::to_string)) {
+        common.push(KeyValue::new("server.address", host));
+    }
+    if call.error.is_some() {
+        common.push(KeyValue::new("error.type", "rhine.chat_error"));
+    }
+
+    let mut attributes = common.clone();
+    attributes.push(KeyValue::new("rhine.request_id", call.request_id.clone()));
+    attributes.push(KeyValue::new("rhine.message_count", call.message_count as i64));
+    attributes.push(KeyValue::new("rhine.stream", call.stream));
+    if let Some(capability) = &call.capability {
+        attributes.push(KeyValue::new("rhine.capability", format!("{:?}", capability)));
+    }
+    if let Some(usage) = &call.usage {
+        attributes.push(KeyValue::new("gen_ai.usage.input_tokens", usage.prompt_tokens as i64));
+        attributes.push(KeyValue::new("gen_ai.usage.output_tokens", usage.completion_tokens as i64));
+    }
+    if let Some(finish_reason) = &call.finish_reason {
+        attributes.push(KeyValue::new(
+            "gen_ai.response.finish_reasons",
+            Value::Array(vec![StringValue::from(finish_reason.clone())].into()),
+        ));
+    }
+
+    let tracer = global::tracer(INSTRUMENTATION_NAME);
+    let mut span = tracer
+        .span_builder(format!("chat {}", call.model))
+        .with_kind(SpanKind::Client)
+        .with_start_time(call.started_at)
+        .with_attributes(attributes)
+        .start(&tracer);
+    if let Some(error) = &call.error {
+        span.set_status(Status::error(error.clone()));
+    }
+    span.end_with_timestamp(call.started_at + call.latency);
+
+    OPERATION_DURATION.record(call.latency.as_secs_f64(), &common);
+    if let Some(usage) = &call.usage {
+        for (token_type, tokens) in [("input", usage.prompt_tokens), ("output", usage.completion_tokens)] {
+            let mut attributes = common.clone();
+            attributes.push(KeyValue::new("gen_ai.token.type", token_type));
+            TOKEN_USAGE.record(tokens, &attributes);
+        }
+    }
+}
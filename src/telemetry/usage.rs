@@ -0,0 +1,200 @@
+// 标准库
+use std::collections::BTreeMap;
+use std::ops::AddAssign;
+use std::sync::Mutex;
+
+// 并发和同步原语
+use once_cell::sync::Lazy;
+
+// 序列化
+use serde::Serialize;
+
+// 项目内部模块
+use crate::config::Config;
+use crate::telemetry::LlmCall;
+
+/// 用量合计
+/// Usage totals
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct UsageTotals {
+    pub requests: u64,
+
+    pub errors: u64,
+
+    pub prompt_tokens: u64,
+
+    pub completion_tokens: u64,
+
+    pub total_tokens: u64,
+
+    /// 费用（美元），仅统计配置了价格的模型
+    /// Cost in USD, only models with configured prices are counted
+    pub cost: f64,
+}
+
+impl AddAssign<&UsageTotals> for UsageTotals {
+    fn add_assign(&mut self, other: &UsageTotals) {
+        self.requests += other.requests;
+        self.errors += other.errors;
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+        self.total_tokens += other.total_tokens;
+        self.cost += other.cost;
+    }
+}
+
+impl UsageTotals {
+    fn from_call(call: &LlmCall) -> Self {
+        let mut totals = Self {
+            requests: 1,
+            errors: call.error.is_some() as u64,
+            ..Default::default()
+        };
+        if let Some(usage) = &call.usage {
+            totals.prompt_tokens = usage.prompt_tokens;
+            totals.completion_tokens = usage.completion_tokens;
+            totals.total_tokens = usage.total_tokens;
+            totals.cost = Config::get_model_metadata(&call.model)
+                .and_then(|metadata| metadata.cost(usage))
+                .unwrap_or(0.0);
+        }
+        totals
+    }
+}
+
+/// 用量快照
+/// Usage snapshot
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct UsageSnapshot {
+    pub total: UsageTotals,
+
+    pub by_model: BTreeMap<String, UsageTotals>,
+
+    pub by_capability: BTreeMap<String, UsageTotals>,
+
+    pub by_tag: BTreeMap<String, UsageTotals>,
+}
+
+impl UsageSnapshot {
+    fn new(tables: UsageTables) -> Self {
+        let mut total = UsageTotals::default();
+        for totals in tables.by_model.values() {
+            total += totals;
+        }
+
+        Self {
+            total,
+            by_model: tables.by_model,
+            by_capability: tables.by_capability,
+            by_tag: tables.by_tag,
+        }
+    }
+
+    /// 导出为 JSON
+    /// Export as JSON
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::to_value(self).unwrap_or_default()
+    }
+
+    /// 导出为 CSV，每行一个维度取值：`dimension,key,requests,errors,prompt_tokens,completion_tokens,total_tokens,cost`
+    /// Export as CSV, one row per dimension value: `dimension,key,requests,errors,prompt_tokens,completion_tokens,total_tokens,cost`
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("dimension,key,requests,errors,prompt_tokens,completion_tokens,total_tokens,cost\n");
+        let rows = std::iter::once(("total", "total", &self.total))
+            .chain(self.by_model.iter().map(|(key, totals)| ("model", key.as_str(), totals)))
+            .chain(self.by_capability.iter().map(|(key, totals)| ("capability", key.as_str(), totals)))
+            .chain(self.by_tag.iter().map(|(key, totals)| ("tag", key.as_str(), totals)));
+
+        for (dimension, key, totals) in rows {
+            csv.push_str(&format!(
+                "{},{},{},{},{},{},{},{}\n",
+                dimension,
+                escape_csv(key),
+                totals.requests,
+                totals.errors,
+                totals.prompt_tokens,
+                totals.completion_tokens,
+                totals.total_tokens,
+                totals.cost,
+            ));
+        }
+        csv
+    }
+}
+
+fn escape_csv(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// 各维度的用量表
+/// Usage tables of every dimension
+#[derive(Clone, Debug, Default)]
+struct UsageTables {
+    by_model: BTreeMap<String, UsageTotals>,
+
+    by_capability: BTreeMap<String, UsageTotals>,
+
+    by_tag: BTreeMap<String, UsageTotals>,
+}
+
+/// 进程级用量聚合器，按模型、能力和用户标签汇总所有对话的消耗
+/// Process-wide usage aggregator, totals the consumption of every chat by model, capability and user tag
+///
+/// 各维度的用量表由同一把锁保护，一次调用总是同时计入或同时不计入某个快照的所有维度。
+/// The tables of every dimension share one lock, so a call is always counted in all dimensions of a snapshot or
+/// in none of them.
+#[derive(Debug, Default)]
+pub struct UsageAggregator {
+    tables: Mutex<UsageTables>,
+}
+
+/// 全局用量聚合器
+/// Global usage aggregator
+static USAGE: Lazy<UsageAggregator> = Lazy::new(UsageAggregator::default);
+
+impl UsageAggregator {
+    /// 获取全局用量聚合器
+    /// Get the global usage aggregator
+    pub fn global() -> &'static UsageAggregator {
+        &USAGE
+    }
+
+    /// 记录一次LLM调用
+    /// Record one LLM call
+    pub fn record(&self, call: &LlmCall) {
+        let totals = UsageTotals::from_call(call);
+        let capability = call
+            .capability
+            .as_ref()
+            .map_or_else(|| "none".to_string(), |capability| format!("{:?}", capability));
+
+        let mut tables = self.tables.lock().unwrap();
+        *tables.by_model.entry(call.model.clone()).or_default() += &totals;
+        *tables.by_capability.entry(capability).or_default() += &totals;
+        for tag in &call.tags {
+            *tables.by_tag.entry(tag.clone()).or_default() += &totals;
+        }
+    }
+
+    /// 获取当前用量快照
+    /// Get a snapshot of the current usage
+    pub fn snapshot(&self) -> UsageSnapshot {
+        UsageSnapshot::new(self.tables.lock().unwrap().clone())
+    }
+
+    /// 获取快照并清零，用于按周期（如每天）上报，期间记录的用量不会丢失
+    /// Take a snapshot and reset, for periodic (e.g. daily) reporting; usage recorded meanwhile is not lost
+    pub fn take(&self) -> UsageSnapshot {
+        UsageSnapshot::new(std::mem::take(&mut *self.tables.lock().unwrap()))
+    }
+
+    /// 清零所有用量
+    /// Reset all usage
+    pub fn reset(&self) {
+        *self.tables.lock().unwrap() = UsageTables::default();
+    }
+}
@@ -0,0 +1,191 @@
+// 标准库
+use std::sync::{Arc, Mutex};
+
+// 文本处理
+use regex::Regex;
+
+// 项目内部模块
+use crate::chat::chat_single::SingleChat;
+use crate::chat::event::ChatEvent;
+pub use crate::utils::common::tokens::approx_tokens;
+
+/// 对一个请求体的断言，失败时 panic 并给出请求内容
+/// Assertions on one request body, panicking with the request content on failure
+#[derive(Clone, Debug)]
+pub struct PromptAssert {
+    body: serde_json::Value,
+}
+
+impl PromptAssert {
+    pub fn new(body: serde_json::Value) -> Self {
+        Self { body }
+    }
+
+    pub fn body(&self) -> &serde_json::Value {
+        &self.body
+    }
+
+    /// 所有消息的文本，每条一行，以 `role: content` 表示
+    /// Text of all messages, one per line as `role: content`
+    pub fn prompt(&self) -> String {
+        self.body["messages"]
+            .as_array()
+            .map(|messages| {
+                messages
+                    .iter()
+                    .map(|message| {
+                        let content = match &message["content"] {
+                            serde_json::Value::String(text) => text.clone(),
+                            serde_json::Value::Null => String::new(),
+                            content => content.to_string(),
+                        };
+                        format!("{}: {}", message["role"].as_str().unwrap_or(""), content)
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn contains(&self, text: &str) -> &Self {
+        let prompt = self.prompt();
+        assert!(prompt.contains(text), "Prompt does not contain {:?}:\n{}", text, prompt);
+        self
+    }
+
+    pub fn not_contains(&self, text: &str) -> &Self {
+        let prompt = self.prompt();
+        assert!(!prompt.contains(text), "Prompt unexpectedly contains {:?}:\n{}", text, prompt);
+        self
+    }
+
+    pub fn matches(&self, pattern: &str) -> &Self {
+        let regex = Regex::new(pattern).unwrap_or_else(|e| panic!("Invalid pattern {:?}: {}", pattern, e));
+        let prompt = self.prompt();
+        assert!(regex.is_match(&prompt), "Prompt does not match {:?}:\n{}", pattern, prompt);
+        self
+    }
+
+    /// 提示词的估算 token 数不超过 `max`，见 [`approx_tokens`]
+    /// The estimated tokens of the prompt are at most `max`, see [`approx_tokens`]
+    pub fn max_tokens(&self, max: usize) -> &Self {
+        let prompt = self.prompt();
+        let tokens = approx_tokens(&prompt);
+        assert!(tokens <= max, "Prompt has about {} tokens, more than {}:\n{}", tokens, max, prompt);
+        self
+    }
+
+    pub fn message_count(&self, count: usize) -> &Self {
+        let actual = self.body["messages"].as_array().map_or(0, Vec::len);
+        assert_eq!(actual, count, "Unexpected message count:\n{}", self.prompt());
+        self
+    }
+
+    /// 请求中声明了名为 `name` 的原生工具
+    /// The request declares a native tool named `name`
+    pub fn has_tool(&self, name: &str) -> &Self {
+        let declared = self.body["tools"]
+            .as_array()
+            .is_some_and(|tools| tools.iter().any(|tool| tool["function"]["name"] == name || tool["name"] == name));
+        assert!(declared, "Request does not declare tool {:?}: {}", name, self.body["tools"]);
+        self
+    }
+}
+
+/// 一次工具调用的记录
+/// Record of one tool call
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecordedToolCall {
+    pub name: String,
+    pub arguments: serde_json::Value,
+    pub output: Option<String>,
+    pub error: Option<String>,
+}
+
+/// 通过事件记录对话中的工具调用
+/// Record the tool calls of a chat through its events
+#[derive(Clone, Debug, Default)]
+pub struct ToolCallLog {
+    calls: Arc<Mutex<Vec<RecordedToolCall>>>,
+}
+
+impl ToolCallLog {
+    /// 订阅对话的工具调用事件
+    /// Subscribe to the tool call events of a chat
+    pub fn attach(chat: &mut SingleChat) -> Self {
+        let log = Self::default();
+        let calls = log.calls.clone();
+        chat.on_event(move |event| match event {
+            ChatEvent::ToolCallStarted { name, arguments, .. } => calls.lock().unwrap().push(RecordedToolCall {
+                name: name.clone(),
+                arguments: arguments.clone(),
+                output: None,
+                error: None,
+            }),
+            ChatEvent::ToolCallFinished { name, output, error, .. } => {
+                let mut calls = calls.lock().unwrap();
+                if let Some(call) = calls.iter_mut().rev().find(|call| call.name == *name && call.output.is_none() && call.error.is_none()) {
+                    call.output = output.clone();
+                    call.error = error.clone();
+                }
+            }
+            _ => {}
+        });
+        log
+    }
+
+    pub fn calls(&self) -> Vec<RecordedToolCall> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    /// 名为 `name` 的工具至少被调用过一次，返回第一次调用
+    /// The tool named `name` was called at least once, returning the first call
+    pub fn assert_called(&self, name: &str) -> RecordedToolCall {
+        let calls = self.calls();
+        calls
+            .iter()
+            .find(|call| call.name == name)
+            .cloned()
+            .unwrap_or_else(|| panic!("Tool {:?} was not called, calls: {:?}", name, calls))
+    }
+
+    /// 名为 `name` 的工具以包含 `arguments` 的参数被调用过
+    /// The tool named `name` was called with arguments including `arguments`
+    pub fn assert_called_with(&self, name: &str, arguments: serde_json::Value) -> RecordedToolCall {
+        let calls = self.calls();
+        calls
+            .iter()
+            .find(|call| call.name == name && json_includes(&call.arguments, &arguments))
+            .cloned()
+            .unwrap_or_else(|| panic!("Tool {:?} was not called with {}, calls: {:?}", name, arguments, calls))
+    }
+
+    pub fn assert_not_called(&self, name: &str) {
+        let calls = self.calls();
+        assert!(calls.iter().all(|call| call.name != name), "Tool {:?} was called: {:?}", name, calls);
+    }
+}
+
+/// 结构化输出包含期望的字段与值，对象只比较期望中出现的键，数组逐项比较
+/// A structured output includes the expected fields and values, objects only compare the expected keys, arrays are
+/// compared item by item
+pub fn assert_json_includes(actual: &serde_json::Value, expected: &serde_json::Value) {
+    assert!(
+        json_includes(actual, expected),
+        "JSON does not include the expected value\nactual:   {}\nexpected: {}",
+        actual,
+        expected
+    );
+}
+
+fn json_includes(actual: &serde_json::Value, expected: &serde_json::Value) -> bool {
+    match (actual, expected) {
+        (serde_json::Value::Object(actual), serde_json::Value::Object(expected)) => expected
+            .iter()
+            .all(|(key, value)| actual.get(key).is_some_and(|actual| json_includes(actual, value))),
+        (serde_json::Value::Array(actual), serde_json::Value::Array(expected)) => {
+            actual.len() == expected.len() && actual.iter().zip(expected).all(|(actual, expected)| json_includes(actual, expected))
+        }
+        (actual, expected) => actual == expected,
+    }
+}
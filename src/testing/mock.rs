@@ -0,0 +1,483 @@
+// 标准库
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+use std::sync::{Arc, Mutex};
+
+// 异步
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+// 数据序列化
+use serde_json::json;
+
+// 项目内部模块
+use crate::chat::agent::RunTrace;
+use crate::chat::chat_single::UNPARSED_TOOL_CALL;
+use crate::config::compression::{CompressionConfig, gzip};
+
+// 压缩
+use flate2::Compression;
+use flate2::write::ZlibEncoder;
+use crate::config::{Config, ModelCapability};
+use crate::testing::assert::PromptAssert;
+
+/// 脚本中的一次回复
+/// One reply of the script
+#[derive(Clone, Debug)]
+enum Reply {
+    Text(String),
+    Truncated(String),
+    Filtered(String),
+    Refusal(String),
+    Logprobs { text: String, logprobs: Vec<f64> },
+    ToolCall { name: String, arguments: serde_json::Value },
+    Error { status: u16, body: String },
+}
+
+/// 模拟提供商压缩回复所用的编码
+/// Encoding the mock provider compresses replies with
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReplyEncoding {
+    Gzip,
+    Deflate,
+    Brotli,
+    /// 校验和错误的 gzip，用于检验客户端拒绝损坏的回复
+    /// gzip with a wrong checksum, to check that clients reject corrupted replies
+    CorruptGzip,
+}
+
+impl ReplyEncoding {
+    /// `Content-Encoding` 中的名称
+    /// Name in `Content-Encoding`
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Gzip | Self::CorruptGzip => "gzip",
+            Self::Deflate => "deflate",
+            Self::Brotli => "br",
+        }
+    }
+
+    fn encode(&self, data: &[u8]) -> Vec<u8> {
+        match self {
+            Self::Gzip => gzip(data).expect("Failed to gzip mock reply"),
+            Self::CorruptGzip => {
+                let mut compressed = gzip(data).expect("Failed to gzip mock reply");
+                // gzip 尾部是 CRC32 与长度各四字节
+                // The gzip trailer is the CRC32 and the length, four bytes each
+                let crc = compressed.len() - 8;
+                compressed[crc] ^= 0xff;
+                compressed
+            }
+            Self::Deflate => {
+                let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(data).expect("Failed to deflate mock reply");
+                encoder.finish().expect("Failed to deflate mock reply")
+            }
+            Self::Brotli => {
+                let mut encoder = brotli::CompressorWriter::new(Vec::new(), 4096, 5, 22);
+                encoder.write_all(data).expect("Failed to compress mock reply with brotli");
+                encoder.into_inner()
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct MockState {
+    replies: VecDeque<Reply>,
+    requests: Vec<serde_json::Value>,
+    headers: Vec<Vec<(String, String)>>,
+    reply_encoding: Option<ReplyEncoding>,
+}
+
+/// 本地运行的模拟提供商，按脚本依次回复 OpenAI 兼容的对话补全请求，并记录收到的请求体
+/// Mock provider running locally, answering OpenAI-compatible chat completion requests with the scripted replies in
+/// order and recording the request bodies it receives
+///
+/// 流式请求的文本回复按词切分为多个增量；脚本用尽时以 500 回复，使测试明确失败。
+/// HEAD 请求（如预热时建立的连接）以空的 200 回复，不消耗脚本也不记录。
+/// Text replies to streaming requests are split into deltas word by word; once the script runs out the provider
+/// answers 500, so the test fails clearly. HEAD requests (such as warmup connections) get an empty 200 without using
+/// the script or being recorded.
+///
+/// ```no_run
+/// # async fn run() {
+/// use rhine::chat::chat_single::SingleChat;
+/// use rhine::testing::MockProvider;
+///
+/// let mock = MockProvider::start("mock-api").await;
+/// mock.reply("hello");
+/// let mut chat = SingleChat::builder().api("mock-api").build().unwrap();
+/// assert_eq!(chat.get_answer("hi").await.unwrap(), "hello");
+/// mock.last_request().contains("hi");
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct MockProvider {
+    api_name: String,
+    url: String,
+    state: Arc<Mutex<MockState>>,
+}
+
+impl MockProvider {
+    /// 启动模拟提供商，并以长上下文能力注册为名为 `api_name` 的API
+    /// Start the mock provider and register it as the API named `api_name` with the long context capability
+    pub async fn start(api_name: &str) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("Failed to bind mock provider");
+        let url = format!("http://{}/v1/chat/completions", listener.local_addr().unwrap());
+        let state = Arc::new(Mutex::new(MockState::default()));
+
+        let served = state.clone();
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                tokio::spawn(serve(socket, served.clone()));
+            }
+        });
+
+        let mock = Self {
+            api_name: api_name.to_string(),
+            url,
+            state,
+        };
+        Config::add_api_source(&mock.source_name(), &mock.url, 4);
+        mock.register(api_name, ModelCapability::LongContext);
+        mock
+    }
+
+    /// 以另一能力再注册一个API，名为 `{api_name}-{capability}`，供按能力选择API的内部调用（如工具参数解析）使用
+    /// Register one more API with another capability, named `{api_name}-{capability}`, for internal calls that
+    /// select an API by capability (such as tool argument parsing)
+    pub fn with_capability(self, capability: ModelCapability) -> Self {
+        let suffix = match capability {
+            ModelCapability::Think => "think",
+            ModelCapability::ToolUse => "tool_use",
+            ModelCapability::LongContext => "long_context",
+            ModelCapability::Cheap => "cheap",
+        };
+        let name = format!("{}-{}", self.api_name, suffix);
+        self.register(&name, capability);
+        self
+    }
+
+    /// 设置发往模拟提供商的请求的HTTP压缩；声明接受 gzip 的请求会收到 gzip 压缩的回复
+    /// Set the HTTP compression of requests to the mock provider; requests accepting gzip get gzipped replies
+    pub fn with_compression(self, compression: CompressionConfig) -> Self {
+        Config::set_source_compression(&self.source_name(), compression).expect("Failed to set mock compression");
+        self.with_reply_encoding(ReplyEncoding::Gzip)
+    }
+
+    /// 以指定编码压缩之后的回复，只用于声明接受该编码的请求
+    /// Compress later replies with the given encoding, only for requests accepting it
+    pub fn with_reply_encoding(self, encoding: ReplyEncoding) -> Self {
+        self.state.lock().unwrap().reply_encoding = Some(encoding);
+        self
+    }
+
+    fn register(&self, name: &str, capability: ModelCapability) {
+        Config::add_api_info(name, "mock-model", capability, &self.source_name(), "sk-mock")
+            .expect("Failed to register mock provider");
+    }
+
+    pub(crate) fn source_name(&self) -> String {
+        format!("{}-source", self.api_name)
+    }
+
+    pub fn api_name(&self) -> &str {
+        &self.api_name
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// 追加一条文本回复
+    /// Append a text reply
+    pub fn reply(&self, text: &str) -> &Self {
+        self.push(Reply::Text(text.to_string()))
+    }
+
+    /// 追加一条因长度限制中断的文本回复，`finish_reason` 为 `length`
+    /// Append a text reply cut off by the length limit, with `finish_reason` set to `length`
+    pub fn reply_truncated(&self, text: &str) -> &Self {
+        self.push(Reply::Truncated(text.to_string()))
+    }
+
+    /// 追加一条被内容过滤器中止的文本回复，`finish_reason` 为 `content_filter`
+    /// Append a text reply stopped by the content filter, with `finish_reason` set to `content_filter`
+    pub fn reply_filtered(&self, text: &str) -> &Self {
+        self.push(Reply::Filtered(text.to_string()))
+    }
+
+    /// 追加一条模型拒绝回答的回复，说明在 `refusal` 字段中，内容为空
+    /// Append a reply in which the model refuses, the explanation in the `refusal` field and no content
+    pub fn reply_refusal(&self, refusal: &str) -> &Self {
+        self.push(Reply::Refusal(refusal.to_string()))
+    }
+
+    /// 追加一条带 logprobs 的文本回复，回复按词切分为 token，依次对应 `logprobs` 中的值，多出的词沿用最后一个值；
+    /// 流式请求时不带 logprobs
+    /// Append a text reply with logprobs, split into tokens word by word that take the values of `logprobs` in order,
+    /// extra words reusing the last value; streamed replies carry no logprobs
+    pub fn reply_with_logprobs(&self, text: &str, logprobs: &[f64]) -> &Self {
+        self.push(Reply::Logprobs {
+            text: text.to_string(),
+            logprobs: logprobs.to_vec(),
+        })
+    }
+
+    /// 追加一条内容为JSON的回复，用于结构化输出
+    /// Append a reply whose content is JSON, for structured outputs
+    pub fn reply_json(&self, value: serde_json::Value) -> &Self {
+        self.push(Reply::Text(value.to_string()))
+    }
+
+    /// 追加一条原生工具调用回复
+    /// Append a native tool call reply
+    pub fn reply_tool_call(&self, name: &str, arguments: serde_json::Value) -> &Self {
+        self.push(Reply::ToolCall {
+            name: name.to_string(),
+            arguments,
+        })
+    }
+
+    /// 按智能体运行记录追加回复：每步模型的原始回答，以及解析各个工具调用时的原生工具调用回复，用于在本地重放问题报告中的运行
+    /// Append the replies of an agent run trace: every step's raw model answer and the native tool call replies used
+    /// when parsing each tool call, to replay the run of a bug report locally
+    ///
+    /// 工具在重放时重新执行。一步中有多个工具调用时，解析请求并发发出，各调用的回复顺序可能与原运行不同。
+    /// Tools run again on replay. With several tool calls in one step the parsing requests go out concurrently, so
+    /// their replies may pair up in a different order than in the original run.
+    pub fn replay(&self, trace: &RunTrace) -> &Self {
+        for step in &trace.steps {
+            let Some(call) = &step.llm_call else {
+                continue;
+            };
+            self.reply(&call.output);
+            for tool_call in step.tool_calls.iter().filter(|tool_call| tool_call.name != UNPARSED_TOOL_CALL) {
+                self.reply_tool_call(&tool_call.name, serde_json::from_str(&tool_call.arguments).unwrap_or_default());
+            }
+        }
+        self
+    }
+
+    /// 追加一条错误回复
+    /// Append an error reply
+    pub fn reply_error(&self, status: u16, body: &str) -> &Self {
+        self.push(Reply::Error {
+            status,
+            body: body.to_string(),
+        })
+    }
+
+    fn push(&self, reply: Reply) -> &Self {
+        self.state.lock().unwrap().replies.push_back(reply);
+        self
+    }
+
+    /// 尚未使用的脚本回复数
+    /// Number of scripted replies not used yet
+    pub fn pending(&self) -> usize {
+        self.state.lock().unwrap().replies.len()
+    }
+
+    /// 按收到顺序排列的请求体
+    /// Request bodies in the order they were received
+    pub fn requests(&self) -> Vec<serde_json::Value> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    /// 第 `index` 个请求中名为 `name` 的请求头，名称不区分大小写
+    /// Header named `name` of the request at `index`, the name is case-insensitive
+    pub fn request_header(&self, index: usize, name: &str) -> Option<String> {
+        let state = self.state.lock().unwrap();
+        header(state.headers.get(index)?, name).map(str::to_string)
+    }
+
+    /// 对第 `index` 个请求进行断言
+    /// Assertions on the request at `index`
+    pub fn request(&self, index: usize) -> PromptAssert {
+        let requests = self.requests();
+        match requests.get(index) {
+            Some(body) => PromptAssert::new(body.clone()),
+            None => panic!("Mock provider received {} requests, there is no request #{}", requests.len(), index),
+        }
+    }
+
+    /// 对最后一个请求进行断言
+    /// Assertions on the latest request
+    pub fn last_request(&self) -> PromptAssert {
+        let count = self.requests().len();
+        assert!(count > 0, "Mock provider has not received any request");
+        self.request(count - 1)
+    }
+}
+
+async fn serve(mut socket: TcpStream, state: Arc<Mutex<MockState>>) {
+    let Some((method, headers, body)) = read_request(&mut socket).await else {
+        return;
+    };
+    if method == "HEAD" {
+        let _ = socket.write_all(b"HTTP/1.1 200 Mock\r\ncontent-length: 0\r\nconnection: close\r\n\r\n").await;
+        return;
+    }
+    let stream = body["stream"] == true;
+    let accepted = header(&headers, "accept-encoding").unwrap_or_default().to_string();
+    let (reply, encoding) = {
+        let mut state = state.lock().unwrap();
+        state.requests.push(body);
+        state.headers.push(headers);
+        let encoding = state
+            .reply_encoding
+            .filter(|encoding| accepted.split(',').any(|accepted| accepted.trim() == encoding.name()));
+        (state.replies.pop_front(), encoding)
+    };
+
+    let (status, content_type, response) = match reply {
+        None => (500, "application/json", json!({"error": {"message": "Mock provider script exhausted"}}).to_string()),
+        Some(Reply::Error { status, body }) => (status, "application/json", body),
+        Some(Reply::Text(text)) => text_response(&text, "stop", stream),
+        Some(Reply::Truncated(text)) => text_response(&text, "length", stream),
+        Some(Reply::Filtered(text)) => text_response(&text, "content_filter", stream),
+        Some(Reply::Refusal(refusal)) => refusal_response(&refusal, stream),
+        Some(Reply::Logprobs { text, .. }) if stream => text_response(&text, "stop", stream),
+        Some(Reply::Logprobs { text, logprobs }) => {
+            let tokens: Vec<_> = text
+                .split_inclusive(' ')
+                .enumerate()
+                .map(|(index, token)| {
+                    let logprob = logprobs.get(index).or(logprobs.last()).copied().unwrap_or(0.0);
+                    json!({"token": token, "logprob": logprob})
+                })
+                .collect();
+            (200, "application/json", json!({
+                "choices": [{
+                    "message": {"role": "assistant", "content": text},
+                    "logprobs": {"content": tokens},
+                    "finish_reason": "stop",
+                }],
+                "usage": usage(),
+            }).to_string())
+        }
+        Some(Reply::ToolCall { name, arguments }) => (200, "application/json", json!({
+            "choices": [{
+                "message": {
+                    "role": "assistant",
+                    "content": null,
+                    "tool_calls": [{
+                        "id": "call_mock",
+                        "type": "function",
+                        "function": {"name": name, "arguments": arguments.to_string()},
+                    }],
+                },
+                "finish_reason": "tool_calls",
+            }],
+            "usage": usage(),
+        }).to_string()),
+    };
+
+    let (encoding, response) = match encoding {
+        Some(encoding) => (format!("content-encoding: {}\r\n", encoding.name()), encoding.encode(response.as_bytes())),
+        None => (String::new(), response.into_bytes()),
+    };
+    let head = format!(
+        "HTTP/1.1 {} Mock\r\ncontent-type: {}\r\n{}content-length: {}\r\nconnection: close\r\n\r\n",
+        status,
+        content_type,
+        encoding,
+        response.len()
+    );
+    let _ = socket.write_all(head.as_bytes()).await;
+    let _ = socket.write_all(&response).await;
+}
+
+/// 文本回复的响应：状态码、内容类型与响应体
+/// Response to a text reply: status code, content type and body
+fn text_response(text: &str, finish_reason: &str, stream: bool) -> (u16, &'static str, String) {
+    match stream {
+        true => (200, "text/event-stream", text_events(text, finish_reason)),
+        false => (200, "application/json", json!({
+            "choices": [{"message": {"role": "assistant", "content": text}, "finish_reason": finish_reason}],
+            "usage": usage(),
+        }).to_string()),
+    }
+}
+
+/// 拒绝回复的响应，流式时说明按词分为 `refusal` 增量
+/// Response to a refusal reply, the explanation split into `refusal` deltas per word when streamed
+fn refusal_response(refusal: &str, stream: bool) -> (u16, &'static str, String) {
+    if !stream {
+        return (200, "application/json", json!({
+            "choices": [{
+                "message": {"role": "assistant", "content": null, "refusal": refusal},
+                "finish_reason": "stop",
+            }],
+            "usage": usage(),
+        }).to_string());
+    }
+    let mut events: String = refusal
+        .split_inclusive(' ')
+        .map(|word| format!("data: {}\n\n", json!({"choices": [{"delta": {"content": null, "refusal": word}}]})))
+        .collect();
+    events.push_str(&format!("data: {}\n\n", json!({"choices": [{"delta": {}, "finish_reason": "stop"}]})));
+    events.push_str("data: [DONE]\n\n");
+    (200, "text/event-stream", events)
+}
+
+/// 读取一个HTTP请求的方法、请求头与JSON请求体
+/// Read the method, the headers and the JSON body of one HTTP request
+async fn read_request(socket: &mut TcpStream) -> Option<(String, Vec<(String, String)>, serde_json::Value)> {
+    let mut request = Vec::new();
+    let mut buffer = [0u8; 4096];
+    loop {
+        let read = socket.read(&mut buffer).await.ok()?;
+        if read == 0 {
+            return None;
+        }
+        request.extend_from_slice(&buffer[..read]);
+        let text = String::from_utf8_lossy(&request);
+        let Some(header_end) = text.find("\r\n\r\n") else {
+            continue;
+        };
+        let headers: Vec<(String, String)> = text[..header_end]
+            .lines()
+            .skip(1)
+            .filter_map(|line| line.split_once(':'))
+            .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+            .collect();
+        let length = header(&headers, "content-length").and_then(|value| value.parse::<usize>().ok()).unwrap_or(0);
+        if request.len() >= header_end + 4 + length {
+            let mut body = request[header_end + 4..header_end + 4 + length].to_vec();
+            if header(&headers, "content-encoding") == Some("gzip") {
+                let mut decoded = Vec::new();
+                flate2::read::GzDecoder::new(body.as_slice()).read_to_end(&mut decoded).ok()?;
+                body = decoded;
+            }
+            let method = text.split(' ').next().unwrap_or_default().to_string();
+            let body = serde_json::from_slice(&body).unwrap_or_default();
+            return Some((method, headers, body));
+        }
+    }
+}
+
+/// 按名称查找请求头，忽略大小写
+/// Find a request header by name, ignoring case
+fn header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers.iter().find(|(key, _)| key.eq_ignore_ascii_case(name)).map(|(_, value)| value.as_str())
+}
+
+/// 流式回复的事件：每个词一个增量，最后是用量与结束标记
+/// Events of a streamed reply: one delta per word, then the usage and the end marker
+fn text_events(text: &str, finish_reason: &str) -> String {
+    let mut events: String = text
+        .split_inclusive(' ')
+        .map(|word| format!("data: {}\n\n", json!({"choices": [{"delta": {"content": word}}]})))
+        .collect();
+    events.push_str(&format!("data: {}\n\n", json!({"choices": [{"delta": {}, "finish_reason": finish_reason}]})));
+    events.push_str(&format!("data: {}\n\n", json!({"choices": [], "usage": usage()})));
+    events.push_str("data: [DONE]\n\n");
+    events
+}
+
+fn usage() -> serde_json::Value {
+    json!({"prompt_tokens": 1, "completion_tokens": 1, "total_tokens": 2})
+}
@@ -0,0 +1,16 @@
+//! 对话测试工具：以脚本驱动的模拟提供商运行对话，并对发送的提示词、工具调用与结构化输出进行断言，
+//! 以及将组装的请求体与已提交的快照比较、由模拟用户驱动端到端对话，需要启用 `testing` 特性（本crate自身的测试中始终可用）
+//! Conversation test harness: run chats against a scripted mock provider and assert on the prompts sent, the tool
+//! calls made and the structured outputs, compare assembled request bodies with committed snapshots, and drive
+//! end-to-end conversations with a simulated user, requires the `testing` feature (always available in this crate's
+//! own tests)
+
+pub mod assert;
+pub mod mock;
+pub mod simulator;
+pub mod snapshot;
+
+pub use assert::{PromptAssert, RecordedToolCall, ToolCallLog, approx_tokens, assert_json_includes};
+pub use mock::{MockProvider, ReplyEncoding};
+pub use simulator::{GoalOutcome, SimulatedTurn, SimulationReport, UserSimulator};
+pub use snapshot::{SnapshotStore, assert_request_snapshot, capture_request};
@@ -0,0 +1,208 @@
+// 错误处理
+use error_stack::{Result, ResultExt};
+
+// 数据序列化
+use serde::{Deserialize, Serialize};
+
+// 项目内部模块
+use crate::chat::chat_base::ChatError;
+use crate::chat::chat_single::SingleChat;
+use crate::chat::chat_tool::strip_code_fence;
+
+/// 模拟用户认为对话可以结束时输出的标记
+/// Marker the simulated user outputs once the conversation can end
+pub const DONE_MARKER: &str = "[DONE]";
+
+/// 模拟用户：由LLM扮演带有目标的角色，与被测智能体进行多轮对话，结束后评判各目标是否达成
+/// Simulated user: an LLM playing a persona with goals, conversing with the agent under test for several turns and
+/// judging afterwards whether each goal was achieved
+///
+/// 模拟用户与评判使用同一API，与被测智能体相互独立；可配合 [`MockProvider`](super::MockProvider) 固定双方的回复。
+/// The simulated user and the judge use the same API, independent of the agent under test; combine with
+/// [`MockProvider`](super::MockProvider) to pin the replies of both sides.
+#[derive(Clone, Debug)]
+pub struct UserSimulator {
+    api_name: String,
+    persona: String,
+    goals: Vec<String>,
+    max_turns: usize,
+    opening: Option<String>,
+}
+
+impl UserSimulator {
+    pub fn new(api_name: &str, persona: &str) -> Self {
+        Self {
+            api_name: api_name.to_string(),
+            persona: persona.to_string(),
+            goals: Vec::new(),
+            max_turns: 6,
+            opening: None,
+        }
+    }
+
+    pub fn goal(mut self, goal: &str) -> Self {
+        self.goals.push(goal.to_string());
+        self
+    }
+
+    /// 最多进行的轮数，每轮为一条用户消息与一条智能体回答，默认为6
+    /// Maximum number of turns, each a user message and an agent answer, 6 by default
+    pub fn max_turns(mut self, max_turns: usize) -> Self {
+        self.max_turns = max_turns.max(1);
+        self
+    }
+
+    /// 固定第一条用户消息，而不由模拟用户生成
+    /// Fix the first user message instead of generating it with the simulated user
+    pub fn opening(mut self, message: &str) -> Self {
+        self.opening = Some(message.to_string());
+        self
+    }
+
+    fn persona_prompt(&self) -> String {
+        format!(
+            "你在测试中扮演用户，不是助手。角色设定：{}\n你的目标：\n{}\n每次只输出你作为用户要发送的下一条消息，不要解释。\
+             当所有目标都已达成或明显无法达成时，只输出 {}。",
+            self.persona,
+            numbered(&self.goals),
+            DONE_MARKER
+        )
+    }
+
+    /// 与智能体对话并评判目标，智能体的会话保留整段对话
+    /// Converse with the agent and judge the goals, the session of the agent keeps the whole conversation
+    pub async fn run(&self, agent: &mut SingleChat) -> Result<SimulationReport, ChatError> {
+        let mut user = SingleChat::builder().api(&self.api_name).system(&self.persona_prompt()).build()?;
+        let mut transcript = Vec::with_capacity(self.max_turns);
+        let mut finished = false;
+
+        let mut message = match &self.opening {
+            Some(opening) => opening.clone(),
+            None => user.get_answer("（对话开始，请发送你的第一条消息）").await?,
+        };
+        for turn in 0..self.max_turns {
+            if message.trim() == DONE_MARKER {
+                finished = true;
+                break;
+            }
+            let answer = agent.get_answer(&message).await?;
+            transcript.push(SimulatedTurn {
+                user: message,
+                agent: answer.clone(),
+            });
+            if turn + 1 == self.max_turns {
+                break;
+            }
+            message = user.get_answer(&answer).await?;
+        }
+
+        let goals = self.judge(&transcript).await?;
+        Ok(SimulationReport {
+            transcript,
+            goals,
+            finished,
+        })
+    }
+
+    /// 由评判者逐个判断目标是否在对话中达成
+    /// Let a judge decide goal by goal whether it was achieved in the conversation
+    async fn judge(&self, transcript: &[SimulatedTurn]) -> Result<Vec<GoalOutcome>, ChatError> {
+        if self.goals.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut judge = SingleChat::builder()
+            .api(&self.api_name)
+            .system(
+                "你是对话评审，根据对话记录判断用户的每个目标是否达成。只输出JSON：\
+                 {\"goals\": [{\"goal\": 目标原文, \"achieved\": true或false, \"reason\": 简短理由}]}",
+            )
+            .build()?;
+        let conversation = transcript
+            .iter()
+            .map(|turn| format!("用户：{}\n助手：{}", turn.user, turn.agent))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let answer = judge
+            .get_answer(&format!("目标：\n{}\n\n对话记录：\n{}", numbered(&self.goals), conversation))
+            .await?;
+
+        #[derive(Deserialize)]
+        struct Verdicts {
+            goals: Vec<GoalOutcome>,
+        }
+        let verdicts: Verdicts = serde_json::from_str(strip_code_fence(&answer))
+            .change_context(ChatError::GetJsonError)
+            .attach_printable_lazy(|| format!("Failed to parse the goal verdicts: {}", answer))?;
+
+        // 以目标原文为准，评判者遗漏的目标视为未达成
+        // The goals as written are authoritative, goals the judge left out count as not achieved
+        Ok(self
+            .goals
+            .iter()
+            .enumerate()
+            .map(|(index, goal)| {
+                verdicts
+                    .goals
+                    .iter()
+                    .find(|outcome| outcome.goal.trim() == goal.trim())
+                    .or_else(|| verdicts.goals.get(index))
+                    .map(|outcome| GoalOutcome {
+                        goal: goal.clone(),
+                        ..outcome.clone()
+                    })
+                    .unwrap_or_else(|| GoalOutcome {
+                        goal: goal.clone(),
+                        achieved: false,
+                        reason: "Not judged".to_string(),
+                    })
+            })
+            .collect())
+    }
+}
+
+fn numbered(goals: &[String]) -> String {
+    goals.iter().enumerate().map(|(index, goal)| format!("{}. {}", index + 1, goal)).collect::<Vec<_>>().join("\n")
+}
+
+/// 模拟对话中的一轮
+/// One turn of the simulated conversation
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SimulatedTurn {
+    pub user: String,
+    pub agent: String,
+}
+
+/// 一个目标的评判结果
+/// Verdict on one goal
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GoalOutcome {
+    pub goal: String,
+    pub achieved: bool,
+    #[serde(default)]
+    pub reason: String,
+}
+
+/// 模拟对话的结果
+/// Result of a simulated conversation
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SimulationReport {
+    pub transcript: Vec<SimulatedTurn>,
+    pub goals: Vec<GoalOutcome>,
+
+    /// 模拟用户是否主动结束了对话，否则在达到轮数上限时停止
+    /// Whether the simulated user ended the conversation, otherwise it stopped at the turn limit
+    pub finished: bool,
+}
+
+impl SimulationReport {
+    pub fn all_achieved(&self) -> bool {
+        self.goals.iter().all(|outcome| outcome.achieved)
+    }
+
+    /// 所有目标均已达成，否则 panic 并给出未达成的目标与对话记录
+    /// All goals were achieved, otherwise panic with the missed goals and the transcript
+    pub fn assert_achieved(&self) {
+        let missed: Vec<_> = self.goals.iter().filter(|outcome| !outcome.achieved).collect();
+        assert!(missed.is_empty(), "Goals not achieved: {:#?}\ntranscript: {:#?}", missed, self.transcript);
+    }
+}
@@ -0,0 +1,135 @@
+// 标准库
+use std::path::{Path, PathBuf};
+
+// 错误处理
+use error_stack::Result;
+
+// 项目内部模块
+use crate::chat::chat_single::SingleChat;
+use crate::chat::chat_base::ChatError;
+
+/// 设置为 `1` 时以实际请求体覆盖快照，而不是比较
+/// When set to `1`, snapshots are overwritten with the actual request bodies instead of compared
+pub const UPDATE_ENV: &str = "RHINE_UPDATE_SNAPSHOTS";
+
+/// 以当前对话状态组装一次提问的完整请求体，不发送请求
+/// Assemble the full request body of one question in the current chat state, without sending it
+///
+/// 与真实请求经过相同的脱敏、审核、检索与历史策略；问题会加入会话，与真实调用一致。
+/// Goes through the same scrubbing, moderation, retrieval and history policy as a real request; the question is
+/// added to the session, as with a real call.
+pub async fn capture_request(chat: &mut SingleChat, user_input: &str) -> Result<serde_json::Value, ChatError> {
+    chat.get_req_body(user_input).await
+}
+
+/// 已提交的请求体快照目录，键按字母序输出为格式化的JSON
+/// Directory of committed request body snapshots, written as pretty JSON with sorted keys
+///
+/// 快照不存在时写入并通过，但在 CI（设置了 `CI` 环境变量）中失败；内容不一致时在旁边写入 `.json.new`
+/// 并失败，确认后以 [`UPDATE_ENV`] 覆盖。
+/// A missing snapshot is written and the assertion passes, except on CI (the `CI` variable is set) where it fails;
+/// on a mismatch a `.json.new` file is written next to it and the assertion fails, accept it by rerunning with
+/// [`UPDATE_ENV`].
+#[derive(Clone, Debug)]
+pub struct SnapshotStore {
+    dir: PathBuf,
+}
+
+impl Default for SnapshotStore {
+    /// 被测crate下的 `snapshots` 目录
+    /// The `snapshots` directory of the crate under test
+    fn default() -> Self {
+        let root = std::env::var("CARGO_MANIFEST_DIR").unwrap_or_else(|_| ".".to_string());
+        Self::new(Path::new(&root).join("snapshots"))
+    }
+}
+
+impl SnapshotStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", name))
+    }
+
+    /// 比较请求体与名为 `name` 的快照，不一致时 panic 并给出差异
+    /// Compare a request body with the snapshot named `name`, panicking with the difference on a mismatch
+    pub fn assert(&self, name: &str, body: &serde_json::Value) {
+        let actual = format!("{}\n", serde_json::to_string_pretty(&sorted(body)).unwrap());
+        let path = self.path(name);
+        let pending = path.with_extension("json.new");
+        let update = std::env::var(UPDATE_ENV).is_ok_and(|value| value == "1");
+
+        let expected = match std::fs::read_to_string(&path) {
+            Ok(expected) if !update => expected,
+            Err(_) if !update && std::env::var_os("CI").is_some() => {
+                write(&pending, &actual);
+                panic!("Missing request snapshot {}, the actual body was written to {}", path.display(), pending.display());
+            }
+            _ => {
+                write(&path, &actual);
+                let _ = std::fs::remove_file(&pending);
+                return;
+            }
+        };
+
+        if expected.replace("\r\n", "\n") != actual {
+            write(&pending, &actual);
+            panic!(
+                "Request snapshot {} changed, rerun with {}=1 to accept {}\n{}",
+                name,
+                UPDATE_ENV,
+                pending.display(),
+                diff(&expected, &actual)
+            );
+        }
+        let _ = std::fs::remove_file(&pending);
+    }
+}
+
+/// 在默认目录中比较请求体快照，见 [`SnapshotStore`]
+/// Compare a request body snapshot in the default directory, see [`SnapshotStore`]
+pub fn assert_request_snapshot(name: &str, body: &serde_json::Value) {
+    SnapshotStore::default().assert(name, body);
+}
+
+fn write(path: &Path, content: &str) {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).unwrap_or_else(|e| panic!("Failed to create {}: {}", dir.display(), e));
+    }
+    std::fs::write(path, content).unwrap_or_else(|e| panic!("Failed to write {}: {}", path.display(), e));
+}
+
+/// 递归按键排序，使快照与JSON对象的插入顺序无关
+/// Sort keys recursively, so snapshots do not depend on the insertion order of JSON objects
+fn sorted(value: &serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::Object(map) => {
+            let mut entries: Vec<_> = map.iter().collect();
+            entries.sort_by_key(|(key, _)| *key);
+            serde_json::Value::Object(entries.into_iter().map(|(key, value)| (key.clone(), sorted(value))).collect())
+        }
+        serde_json::Value::Array(items) => serde_json::Value::Array(items.iter().map(sorted).collect()),
+        value => value.clone(),
+    }
+}
+
+/// 逐行差异，跳过首尾相同的行
+/// Line by line difference, skipping the lines equal at both ends
+fn diff(expected: &str, actual: &str) -> String {
+    let expected: Vec<&str> = expected.lines().collect();
+    let actual: Vec<&str> = actual.lines().collect();
+    let prefix = expected.iter().zip(&actual).take_while(|(a, b)| a == b).count();
+    let suffix = expected[prefix..]
+        .iter()
+        .rev()
+        .zip(actual[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut lines = vec![format!("@@ line {} @@", prefix + 1)];
+    lines.extend(expected[prefix..expected.len() - suffix].iter().map(|line| format!("-{}", line)));
+    lines.extend(actual[prefix..actual.len() - suffix].iter().map(|line| format!("+{}", line)));
+    lines.join("\n")
+}
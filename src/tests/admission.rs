@@ -0,0 +1,130 @@
+use crate::tests::format_test_block;
+
+pub async fn test_admission() {
+    #[cfg(feature = "server")]
+    test_admission_limits();
+    #[cfg(all(test, feature = "server"))]
+    test_admission_over_websocket().await;
+    format_test_block("Admission", || "connection and message admission".to_string());
+}
+
+#[cfg(feature = "server")]
+fn test_admission_limits() {
+    use crate::chat::queue::QueuePriority;
+    use crate::server::admission::{Admission, AdmissionLimits};
+
+    let admission = Admission::new(AdmissionLimits {
+        max_connections: 2,
+        max_queued_per_client: 2,
+        max_pending: 3,
+        max_background_pending: 1,
+        ..Default::default()
+    });
+    let first = admission.connect("10.0.0.1").unwrap();
+    let second = admission.connect("10.0.0.2").unwrap();
+    assert!(admission.connect("10.0.0.3").is_none());
+    assert_eq!(admission.connections(), 2);
+
+    // 后台消息只能占用一部分待处理额度，每个客户端的排队数有上限
+    // Background messages only take part of the pending capacity, and every client's queue is bounded
+    let background = first.admit_with_priority(QueuePriority::Background).unwrap();
+    assert!(first.admit_with_priority(QueuePriority::Background).is_none());
+    assert_eq!(admission.background_pending(), 1);
+    let interactive = first.admit_with_priority(QueuePriority::Interactive).unwrap();
+    assert!(first.admit_with_priority(QueuePriority::Interactive).is_none());
+    assert!(!first.is_idle());
+
+    // 全服务待处理的消息达到上限时服务饱和，之后的消息被拒绝，释放后恢复
+    // The service saturates once the pending messages reach the limit, later messages are refused until some are
+    // released
+    let other = second.admit_with_priority(QueuePriority::Interactive).unwrap();
+    assert!(admission.is_saturated());
+    assert!(second.admit_with_priority(QueuePriority::Interactive).is_none());
+    drop(background);
+    assert!(!admission.is_saturated());
+    assert_eq!((admission.pending(), admission.background_pending()), (2, 0));
+    drop((interactive, other));
+    assert!(first.is_idle());
+    drop(first);
+    assert!(admission.connect("10.0.0.3").is_some());
+    assert_eq!(admission.connections(), 1);
+}
+
+#[cfg(all(test, feature = "server"))]
+async fn test_admission_over_websocket() {
+    use std::time::Duration;
+
+    use futures::{SinkExt, StreamExt};
+    use serde_json::{Value, json};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio_tungstenite::tungstenite::{Error, Message};
+    use tokio_tungstenite::{MaybeTlsStream, WebSocketStream, connect_async};
+
+    use crate::server::admission::{Admission, AdmissionLimits};
+    use crate::server::websocket::router_with_admission;
+    use crate::testing::MockProvider;
+
+    type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+    async fn send(socket: &mut Socket, frame: Value) {
+        socket.send(Message::text(frame.to_string())).await.unwrap();
+    }
+
+    async fn receive(socket: &mut Socket) -> Value {
+        loop {
+            if let Message::Text(text) = socket.next().await.unwrap().unwrap() {
+                return serde_json::from_str(&text).unwrap();
+            }
+        }
+    }
+
+    let mock = MockProvider::start("admission-ws-api").await;
+    let admission = Admission::new(AdmissionLimits {
+        max_connections: 3,
+        max_queued_per_client: 1,
+        max_pending: 2,
+        retry_after: Duration::from_secs(1),
+        ..Default::default()
+    });
+    // 不提供连接信息，每个连接各自算作一个客户端
+    // Without connection info, every connection counts as its own client
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}/ws", listener.local_addr().unwrap());
+    let router = router_with_admission(admission.clone(), None);
+    tokio::spawn(async move { axum::serve(listener, router).await });
+
+    // 连接数已满时以 429 与 Retry-After 拒绝
+    // Connections beyond the limit are refused with 429 and Retry-After
+    let (mut socket, _) = connect_async(&url).await.unwrap();
+    let unknown = admission.connect("unknown").unwrap();
+    let filler = admission.connect("10.0.0.9").unwrap();
+    let Err(Error::Http(refused)) = connect_async(&url).await else {
+        panic!("Connection beyond the limit was accepted");
+    };
+    assert_eq!(refused.status(), 429);
+    assert_eq!(refused.headers()["retry-after"], "1");
+
+    // 其他客户端排队已满不影响这个连接
+    // Another client's full queue does not affect this connection
+    let _busy = unknown.admit().unwrap();
+    send(&mut socket, json!({"type": "open", "session": "main", "api": "admission-ws-api", "stream": false})).await;
+    assert_eq!(receive(&mut socket).await["type"], "opened");
+    let message = json!({"type": "message", "session": "main", "content": "hello"});
+    mock.reply("Hi.");
+    send(&mut socket, message.clone()).await;
+    assert_eq!(receive(&mut socket).await["content"], "Hi.");
+
+    // 服务饱和时消息以 overloaded 拒绝，连接保持打开，恢复后照常回答
+    // A saturated service refuses messages with overloaded, keeping the connection open, and answers again once it
+    // recovers
+    let full = filler.admit().unwrap();
+    assert!(admission.is_saturated());
+    send(&mut socket, message.clone()).await;
+    let overloaded = receive(&mut socket).await;
+    assert_eq!((&overloaded["type"], &overloaded["retry_after_ms"]), (&json!("overloaded"), &json!(1000)));
+    drop(full);
+    mock.reply("Back again.");
+    send(&mut socket, message).await;
+    assert_eq!(receive(&mut socket).await["content"], "Back again.");
+    assert_eq!(mock.requests().len(), 2);
+}
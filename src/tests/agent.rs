@@ -0,0 +1,271 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use error_stack::Report;
+use futures::future::BoxFuture;
+use serde_json::{Value, json};
+
+use crate::chat::agent::{AgentStop, Deadline, RunTrace, StepDecision};
+use crate::chat::chat_base::ChatError;
+use crate::chat::chat_single::SingleChat;
+use crate::chat::content::Content;
+use crate::chat::image::ImagePart;
+use crate::chat::message::Role;
+use crate::chat::tool_source::{ToolSource, function_tool};
+use crate::config::{Config, ModelCapability};
+use crate::schema::tool_error::{ToolError, ToolErrorType};
+use crate::schema::tool_schema::{ChatToolSchemaError, create_tool, get_tool_registry};
+use crate::testing::MockProvider;
+use crate::tests::format_test_block;
+
+pub async fn test_agent() {
+    test_deadline().await;
+
+    let mock = MockProvider::start("agent-api").await.with_capability(ModelCapability::ToolUse);
+    get_tool_registry().insert(
+        "agent_add".to_string(),
+        create_tool("agent_add", |arguments| {
+            Ok(json!(arguments["a"].as_i64().unwrap_or(0) + arguments["b"].as_i64().unwrap_or(0)))
+        })
+        .1,
+    );
+    get_tool_registry().insert(
+        "agent_divide".to_string(),
+        create_tool("agent_divide", |arguments| match arguments["b"].as_f64() {
+            Some(b) if b != 0.0 => Ok(json!(arguments["a"].as_f64().unwrap_or(0.0) / b)),
+            _ => Err(Report::new(ChatToolSchemaError::FunctionCallError).attach(
+                ToolError::new(ToolErrorType::InvalidArguments, "b must not be zero", true)
+                    .suggestion("Ask the user for a non-zero divisor"),
+            )),
+        })
+        .1,
+    );
+    get_tool_registry().insert(
+        "agent_chart".to_string(),
+        create_tool("agent_chart", |_| {
+            let chart = ImagePart::from_bytes(b"\x89PNG\r\n\x1a\n".to_vec()).unwrap();
+            Ok(json!({"title": "Sales", "chart": chart.to_tool_output()}))
+        })
+        .1,
+    );
+
+    // 工具参数解析按能力选择API，暂时只留下模拟提供商
+    // Tool argument parsing selects an API by capability, leave only the mock provider for now
+    Config::set_api_weight("valid-api", 0);
+    test_completed_run(&mut chat(&mock), &mock).await;
+    test_run_trace(&mock).await;
+    test_expired_during_tools(&mut chat(&mock), &mock).await;
+    test_expired_during_model_call(&mut chat(&mock)).await;
+    test_step_limit(&mock).await;
+    test_tool_errors(&mut chat(&mock), &mock).await;
+    test_tool_images(&mut chat(&mock), &mock).await;
+    Config::set_api_weight("valid-api", 1);
+    Config::set_api_weight("agent-api-tool_use", 0);
+    assert_eq!(mock.pending(), 0);
+}
+
+fn chat(mock: &MockProvider) -> SingleChat {
+    SingleChat::builder().api(mock.api_name()).system("You are a calculator agent.").build().unwrap()
+}
+
+/// 停住的工具来源：调用时暂停时钟并且不再返回，暂停的时钟在运行时空闲时直接跳到截止时间，测试不必真的等待
+/// Stalled tool source: a call pauses the clock and never returns, and the paused clock jumps straight to the
+/// deadline once the runtime is idle, so the test does not actually wait
+struct Stalled;
+
+impl ToolSource for Stalled {
+    fn namespace(&self) -> Option<&str> {
+        None
+    }
+
+    fn list_tools(&self) -> BoxFuture<'_, error_stack::Result<Vec<Value>, ChatError>> {
+        Box::pin(async { Ok(vec![function_tool("agent_slow", Some("Slow lookup"), None)]) })
+    }
+
+    fn call_tool<'a>(
+        &'a self,
+        _name: &'a str,
+        _arguments: Value,
+    ) -> BoxFuture<'a, error_stack::Result<Value, ChatToolSchemaError>> {
+        Box::pin(async {
+            tokio::time::pause();
+            std::future::pending().await
+        })
+    }
+}
+
+async fn test_deadline() {
+    // 暂停时钟，计时不受机器负载影响
+    // Pause the clock, so timings do not depend on load
+    tokio::time::pause();
+    let deadline = Deadline::after(Duration::from_millis(50));
+    assert!(!deadline.is_expired());
+    assert!(deadline.remaining() <= Duration::from_millis(50));
+    assert_eq!(deadline.run(async { 7 }).await, Some(7));
+    assert_eq!(deadline.run(tokio::time::sleep(Duration::from_millis(200))).await, None);
+    assert!(deadline.is_expired());
+    assert_eq!(deadline.remaining(), Duration::ZERO);
+    // 已完成的结果在到期后仍然返回
+    // A result that is already complete is still returned after expiry
+    assert_eq!(deadline.run(async { "ready" }).await, Some("ready"));
+    tokio::time::resume();
+}
+
+async fn test_completed_run(chat: &mut SingleChat, mock: &MockProvider) {
+    mock.reply("Let me add them.<ToolUse>add 2 and 3</ToolUse>")
+        .reply_tool_call("agent_add", json!({"a": 2, "b": 3}))
+        .reply("The sum is 5.");
+
+    let run = chat.run_agent("What is 2 + 3?", Deadline::after(Duration::from_secs(10))).await.unwrap();
+    format_test_block("Agent Run", || format!("{:#?}", run));
+    assert!(run.is_complete());
+    assert_eq!(run.answer, "The sum is 5.");
+    assert_eq!(run.tool_results, vec!["5"]);
+    assert_eq!(run.steps, 2);
+    mock.last_request().contains("What is 2 + 3?").contains("tool: 5");
+
+    // 工具调用与结果以结构化条目记录在会话中
+    // Tool calls and results are recorded as typed entries in the session
+    let messages = chat.base.session.assemble_context(&chat.base.session.default_path, &Role::Assistant).unwrap();
+    let call = &messages[messages.len() - 3];
+    assert_eq!(call.tool_calls.as_ref().unwrap()[0]["function"]["name"], "agent_add");
+    let path = chat.base.session.default_path.clone();
+    let result = chat.base.session.get_node_by_path(&path[..path.len() - 1]).unwrap().content.clone();
+    let Content::ToolResult { name, content, duration_ms, is_error, .. } = result else {
+        panic!("expected a tool result, got {:?}", result);
+    };
+    assert_eq!((name.as_deref(), &*content, is_error), (Some("agent_add"), "5", false));
+    assert!(duration_ms.is_some());
+}
+
+/// 运行记录逐步记下模型调用、工具调用与决定，序列化后可在模拟提供商上重放出同样的运行
+/// The run trace records model calls, tool calls and decisions step by step, and once serialized replays the same
+/// run on the mock provider
+async fn test_run_trace(mock: &MockProvider) {
+    mock.reply("Let me add them.<ToolUse>add 4 and 5</ToolUse>")
+        .reply_tool_call("agent_add", json!({"a": 4, "b": 5}))
+        .reply("The sum is 9.");
+    let run = chat(mock).run_agent("What is 4 + 5?", Deadline::after(Duration::from_secs(10))).await.unwrap();
+    let trace = &run.trace;
+    assert_eq!(trace.user_input, "What is 4 + 5?");
+    assert_eq!(trace.stop, AgentStop::Answered);
+    assert_eq!(
+        trace.steps.iter().map(|step| step.decision.clone()).collect::<Vec<_>>(),
+        [StepDecision::CallTools, StepDecision::Answer]
+    );
+    let first = trace.steps[0].llm_call.as_ref().unwrap();
+    assert_eq!(first.output, "Let me add them.<ToolUse>add 4 and 5</ToolUse>");
+    assert_eq!(first.model, "mock-model");
+    assert_eq!(first.request_hash.len(), 64);
+    assert_eq!(trace.steps[0].tool_calls[0].name, "agent_add");
+    assert_eq!(trace.steps[0].tool_calls[0].output, "9");
+    assert_eq!(trace.usage(), (2, 2));
+
+    let report = serde_json::to_string_pretty(trace).unwrap();
+    format_test_block("Run Trace", || report.clone());
+    let loaded: RunTrace = serde_json::from_str(&report).unwrap();
+    mock.replay(&loaded);
+    let replayed = chat(mock).run_agent("What is 4 + 5?", Deadline::after(Duration::from_secs(10))).await.unwrap();
+    assert_eq!(replayed.answer, run.answer);
+    assert_eq!(replayed.trace.divergence(&loaded), None);
+
+    // 不同的输入从第一步起偏离
+    // A different input diverges from the first step
+    mock.replay(&loaded);
+    let other = chat(mock).run_agent("What is 5 + 4?", Deadline::after(Duration::from_secs(10))).await.unwrap();
+    assert_eq!(other.trace.divergence(&loaded), Some(1));
+}
+
+async fn test_expired_during_tools(chat: &mut SingleChat, mock: &MockProvider) {
+    mock.reply("Looking it up.<ToolUse>slow lookup</ToolUse>").reply_tool_call("agent_slow", json!({}));
+
+    // 工具运行超过剩余时间时，返回到期前的回答而不等待工具
+    // When a tool outlives the remaining time, the answer before the deadline is returned without waiting for it
+    chat.set_tool_sources(vec![Arc::new(Stalled)]).await.unwrap();
+    let run = chat.run_agent("Look it up", Deadline::after(Duration::from_secs(60))).await.unwrap();
+    tokio::time::resume();
+    assert_eq!(run.stop, AgentStop::DeadlineExpired);
+    assert_eq!(run.answer, "Looking it up.");
+    assert!(run.tool_results.is_empty());
+    assert_eq!(run.steps, 1);
+}
+
+async fn test_expired_during_model_call(chat: &mut SingleChat) {
+    let run = chat.run_agent("Too late", Deadline::after(Duration::ZERO)).await.unwrap();
+    assert_eq!(run.stop, AgentStop::DeadlineExpired);
+    assert!(run.answer.is_empty());
+    assert!(!run.is_complete());
+    // 没有回答的提问从会话中撤回
+    // The unanswered question is withdrawn from the session
+    assert_eq!(chat.base.session.default_path.len(), 1);
+}
+
+async fn test_step_limit(mock: &MockProvider) {
+    mock.reply("<ToolUse>add 1 and 1</ToolUse>").reply_tool_call("agent_add", json!({"a": 1, "b": 1}));
+    let mut chat = SingleChat::builder().api(mock.api_name()).max_agent_steps(1).build().unwrap();
+    let run = chat.run_agent("What is 1 + 1?", Deadline::after(Duration::from_secs(10))).await.unwrap();
+    assert_eq!(run.stop, AgentStop::StepLimit);
+    assert_eq!((run.steps, run.tool_results.as_slice()), (1, ["2".to_string()].as_slice()));
+}
+
+async fn test_tool_errors(chat: &mut SingleChat, mock: &MockProvider) {
+    let divide = json!({"type": "function", "function": {
+        "name": "agent_divide",
+        "description": "Divide a by b",
+        "parameters": {"type": "object", "properties": {"a": {"type": "number"}, "b": {"type": "number"}}}
+    }});
+    chat.set_tools(vec![divide]).unwrap();
+    mock.reply("<ToolUse>divide 1 by 0</ToolUse>")
+        .reply_tool_call("agent_divide", json!({"a": 1, "b": 0}))
+        .reply("<ToolUse>multiply 2 and 3</ToolUse>")
+        .reply_tool_call("agent_multiply", json!({"a": 2, "b": 3}))
+        .reply("Which divisor should I use?");
+
+    // 工具失败时模型收到附加的结构化错误，没有该工具时收到可用工具的建议；错误格式已在工具提示中说明
+    // On failure the model gets the attached structured error, for an unknown tool a suggestion of the available
+    // ones; the error format is described in the tools prompt
+    let first = mock.requests().len();
+    let run = chat.run_agent("Divide 1 by 0", Deadline::after(Duration::from_secs(10))).await.unwrap();
+    format_test_block("Tool Errors", || format!("{:#?}", run));
+    assert_eq!(run.steps, 3);
+    mock.request(first).contains("retryable");
+    let errors: Vec<ToolError> = run
+        .tool_results
+        .iter()
+        .map(|output| {
+            let mut output: serde_json::Value = serde_json::from_str(output).unwrap();
+            serde_json::from_value(output["error"].take()).unwrap()
+        })
+        .collect();
+    assert_eq!(
+        errors[0],
+        ToolError::new(ToolErrorType::InvalidArguments, "b must not be zero", true)
+            .suggestion("Ask the user for a non-zero divisor")
+    );
+    assert_eq!(errors[1].error_type, ToolErrorType::NotFound);
+    assert!(errors[1].retryable);
+    assert_eq!(errors[1].suggestion.as_deref(), Some("只调用以下工具之一：agent_divide"));
+    mock.last_request().contains("\"error_type\": \"not_found\"");
+}
+
+/// 工具返回的图片从结果文本中取出，OpenAI 兼容的提供商在工具结果之后的用户消息中收到图片
+/// Images returned by a tool are taken out of the result text, OpenAI-compatible providers get them in a user
+/// message after the tool results
+async fn test_tool_images(chat: &mut SingleChat, mock: &MockProvider) {
+    mock.reply("Let me draw it.<ToolUse>chart of sales</ToolUse>")
+        .reply_tool_call("agent_chart", json!({}))
+        .reply("Sales go up.");
+    let run = chat.run_agent("Plot the sales", Deadline::after(Duration::from_secs(10))).await.unwrap();
+    assert_eq!(run.answer, "Sales go up.");
+    assert!(run.tool_results[0].contains("\"chart\": \"[image 1]\""));
+
+    let request = mock.last_request();
+    let body = request.body();
+    let messages = body["messages"].as_array().unwrap();
+    let tool = messages.iter().position(|message| message["role"] == "tool").unwrap();
+    assert!(messages[tool]["content"].as_str().unwrap().contains("[image 1]"));
+    let images = &messages[tool + 1];
+    assert_eq!(images["role"], "user");
+    assert!(images["content"][0]["text"].as_str().unwrap().contains("(agent_chart)"));
+    assert_eq!(images["content"][1]["image_url"]["url"], "data:image/png;base64,iVBORw0KGgo=");
+}
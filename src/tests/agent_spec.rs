@@ -0,0 +1,97 @@
+use crate::chat::agent_spec::{Agent, AgentSpec};
+use crate::chat::guardrail::GuardrailVerdict;
+use crate::error::ReportExt;
+use crate::testing::MockProvider;
+use crate::tests::format_test_block;
+
+pub async fn test_agent_spec() {
+    let mock = MockProvider::start("agent-spec-api").await;
+    let dir = std::env::temp_dir().join(format!("rhine_agents_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("researcher.toml"),
+        r#"
+name = "researcher"
+version = "3"
+api = "agent-spec-api"
+system = "You research topics."
+
+[params]
+temperature = 0.2
+
+[memory]
+window = 4
+scratchpad = true
+
+[guardrails]
+action = "rewrite"
+deny = ["secret"]
+
+[budget]
+timeout_secs = 30
+max_tokens = 2
+"#,
+    )
+    .unwrap();
+
+    // 定义文件中的各项设置都应用到智能体的对话上
+    // Every setting of the definition file is applied to the agent's chat
+    let mut agent = Agent::from_file(dir.join("researcher.toml")).unwrap();
+    assert_eq!(agent.name(), "researcher");
+    assert_eq!(agent.spec().timeout().as_secs(), 30);
+    let chat = agent.chat();
+    assert!(chat.base.tags.contains(&"agent:researcher".to_string()));
+    assert!(chat.base.tags.contains(&"version:3".to_string()));
+    assert_eq!(chat.tools().len(), 2);
+    assert_eq!(chat.base.tenant.as_ref().unwrap().token_budget, Some(2));
+    let verdict = chat.base.guardrails.as_ref().unwrap().check("the secret plan");
+    assert_eq!(verdict, GuardrailVerdict::Rewrite("the ****** plan".to_string()));
+
+    mock.reply("Rhine is a river.");
+    let run = agent.run("What is the Rhine?").await.unwrap();
+    assert_eq!(run.answer, "Rhine is a river.");
+    let request = mock.last_request();
+    request.contains("You research topics.").contains("scratchpad_write");
+    assert!((request.body()["temperature"].as_f64().unwrap() - 0.2).abs() < 1e-6);
+
+    // 预算用完后不再发出请求
+    // No request is sent once the budget is used up
+    let exceeded = agent.run("And the Danube?").await.unwrap_err();
+    assert!(exceeded.to_string().contains("Budget of tenant agent:researcher exceeded"));
+    assert_eq!(mock.requests().len(), 1);
+
+    // 同一定义也可写成 JSON；自相矛盾、含未知字段或正则无效的定义加载失败
+    // The same definition may be written as JSON; contradictory definitions, unknown fields and invalid patterns
+    // fail to load
+    let json = r#"{"name": "writer", "api": "agent-spec-api", "system": "", "memory": {"summarize_after": 6}}"#;
+    std::fs::write(dir.join("writer.json"), json).unwrap();
+    let writer = AgentSpec::load(dir.join("writer.json")).unwrap();
+    assert_eq!(writer.memory.summarize_after, Some(6));
+    assert!(Agent::from_spec(writer).is_ok());
+
+    // YAML 同样支持，其他扩展名加载失败
+    // YAML is supported as well, other extensions fail to load
+    let yaml = "name: editor\napi: agent-spec-api\nsystem: Edit carefully.\nmemory:\n  window: 4\n";
+    std::fs::write(dir.join("editor.yaml"), yaml).unwrap();
+    let editor = AgentSpec::load(dir.join("editor.yaml")).unwrap();
+    assert_eq!((editor.name.as_str(), editor.memory.window), ("editor", Some(4)));
+    std::fs::write(dir.join("editor.yml"), yaml).unwrap();
+    assert_eq!(AgentSpec::load(dir.join("editor.yml")).unwrap(), editor);
+    std::fs::write(dir.join("editor.ini"), yaml).unwrap();
+    let unsupported = AgentSpec::load(dir.join("editor.ini")).unwrap_err();
+    assert_eq!(unsupported.code(), "chat.agent_spec");
+    assert!(format!("{:?}", unsupported).contains("Unsupported extension \"ini\""));
+
+    let both = r#"{"name": "x", "system": "", "memory": {"window": 2, "summarize_after": 6}}"#;
+    std::fs::write(dir.join("both.json"), both).unwrap();
+    assert_eq!(Agent::from_file(dir.join("both.json")).unwrap_err().code(), "chat.agent_spec");
+    std::fs::write(dir.join("typo.toml"), "name = \"x\"\nsystem = \"\"\nsystem_prompt = \"oops\"").unwrap();
+    assert_eq!(Agent::from_file(dir.join("typo.toml")).unwrap_err().code(), "chat.agent_spec");
+    let pattern = "name = \"x\"\nsystem = \"\"\n[guardrails]\npatterns = [\"(\"]";
+    std::fs::write(dir.join("pattern.toml"), pattern).unwrap();
+    assert_eq!(Agent::from_file(dir.join("pattern.toml")).unwrap_err().code(), "chat.agent_spec");
+
+    format_test_block("Agent Spec", || format!("{:#?}", agent.spec()));
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(mock.pending(), 0);
+}
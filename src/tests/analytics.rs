@@ -0,0 +1,136 @@
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use chrono::NaiveDate;
+
+use crate::chat::analytics::{AnalyticsError, ConversationAnalytics};
+use crate::chat::content::Content;
+use crate::chat::message::{MessageMetadata, Role, Session};
+use crate::chat::refusal::RefusalReason;
+use crate::chat::store::{MemorySessionStore, SessionStore};
+use crate::error::ReportExt;
+use crate::schema::tool_error::{ToolError, ToolErrorType};
+use crate::telemetry::ledger::{
+    MemoryUsageLedger, UsageLedger, UsageRecord, disable_usage_ledger, set_usage_ledger, usage_ledger,
+};
+use crate::tests::format_test_block;
+
+pub async fn test_analytics() {
+    let day = |day: u32| NaiveDate::from_ymd_opt(2026, 3, day).unwrap();
+    let at = |date: NaiveDate| date.and_hms_opt(12, 0, 0).unwrap().and_utc().timestamp_millis() as u64;
+
+    // 第一个对话：两轮，一次成功与一次找不到的工具调用，分在两天
+    // First conversation: two turns, one successful and one not-found tool call, over two days
+    let mut first = Session::new();
+    first.add_with_default_path(Role::User, "weather?").unwrap();
+    first.add_with_default_path(Role::Assistant, Content::tool_call("call-1", "weather", "{}")).unwrap();
+    let result = Content::tool_outcome("call-1", "weather", "sunny", Duration::from_millis(30), false);
+    first.add_with_default_path(Role::tool("call-1"), result).unwrap();
+    first.add_with_default_path(Role::User, "and the stock price?").unwrap();
+    first.add_with_default_path(Role::Assistant, Content::tool_call("call-2", "stocks", "{}")).unwrap();
+    let error = ToolError::new(ToolErrorType::NotFound, "no tool named stocks", false).to_output();
+    let result = Content::tool_outcome("call-2", "stocks", &error, Duration::from_millis(10), true);
+    first.add_with_default_path(Role::tool("call-2"), result).unwrap();
+    for (index, message) in first.message_roots.iter_mut().enumerate() {
+        let mut node = Some(message);
+        let mut depth = index;
+        while let Some(message) = node {
+            message.created_at_ms = Some(at(if depth < 3 { day(1) } else { day(2) }));
+            depth += 1;
+            node = message.child.first_mut();
+        }
+    }
+
+    // 第二个对话：一轮，回答被内容过滤拒绝；旧版消息没有创建时间
+    // Second conversation: one turn, answer refused by the content filter; legacy messages without creation time
+    let mut second = Session::new();
+    second.add_with_default_path(Role::User, "tell me").unwrap();
+    second.add_with_default_path(Role::Assistant, "").unwrap();
+    second.last_message_mut().unwrap().metadata = Some(MessageMetadata {
+        refusal: Some(RefusalReason::ContentFilter),
+        ..MessageMetadata::default()
+    });
+    let mut node = second.message_roots.first_mut();
+    while let Some(message) = node {
+        message.created_at_ms = None;
+        node = message.child.first_mut();
+    }
+
+    let store = Arc::new(MemorySessionStore::default());
+    store.save("first", &first, 0).await.unwrap();
+    store.save("second", &second, 0).await.unwrap();
+    let analytics = ConversationAnalytics::new(store.clone());
+
+    // 旧版消息按对话的保存时间计入今天
+    // Legacy messages count on today, when the conversation was saved
+    let from = UNIX_EPOCH + Duration::from_millis(at(day(1)) - 1);
+    let daily = analytics.messages_per_day(from, SystemTime::now() + Duration::from_secs(60)).await.unwrap();
+    assert_eq!(daily.len(), 3);
+    let counts = |index: usize| (daily[index].messages, daily[index].user_messages, daily[index].assistant_messages);
+    assert_eq!((daily[0].date, counts(0)), (day(1), (3, 1, 1)));
+    assert_eq!((daily[1].date, counts(1)), (day(2), (3, 1, 1)));
+    assert_eq!(counts(2), (2, 1, 1));
+    let until = UNIX_EPOCH + Duration::from_millis(at(day(2)));
+    assert_eq!(analytics.messages_per_day(from, until).await.unwrap().len(), 1);
+
+    let turns = analytics.average_turns().await.unwrap();
+    assert_eq!((turns.conversations, turns.turns, turns.average), (2, 3, 1.5));
+
+    let tools = analytics.tool_usage().await.unwrap();
+    let names: Vec<&str> = tools.iter().map(|tool| tool.name.as_str()).collect();
+    assert_eq!(names, ["stocks", "weather"]);
+    assert_eq!((tools[0].calls, tools[0].errors, tools[0].average_duration_ms), (1, 1, Some(10.0)));
+    assert_eq!((tools[1].calls, tools[1].errors, tools[1].average_duration_ms), (1, 0, Some(30.0)));
+
+    let errors = analytics.top_errors(10).await.unwrap();
+    let kinds: Vec<&str> = errors.iter().map(|error| error.kind.as_str()).collect();
+    assert_eq!(kinds, ["refusal.content_filter", "tool.not_found"]);
+    assert_eq!(analytics.top_errors(1).await.unwrap().len(), 1);
+
+    // 只统计之后保存过的对话
+    // Only conversations saved afterwards count
+    let later = ConversationAnalytics::new(store.clone()).since(SystemTime::now() + Duration::from_secs(60));
+    assert_eq!(later.average_turns().await.unwrap().conversations, 0);
+    assert_eq!(later.average_turns().await.unwrap().average, 0.0);
+
+    // 按标签统计费用，按费用从高到低排列
+    // Cost per tag, from most to least expensive
+    let record = |tags: &[&str], tokens: u64, cost: f64| UsageRecord {
+        request_id: format!("req-{}", tokens),
+        session_id: "first".to_string(),
+        model: "gpt-4o".to_string(),
+        capability: None,
+        tags: tags.iter().map(|tag| tag.to_string()).collect(),
+        timestamp_ms: at(day(1)),
+        latency_ms: 100,
+        prompt_tokens: tokens,
+        completion_tokens: 0,
+        total_tokens: tokens,
+        cost: Some(cost),
+        error: false,
+    };
+    let ledger = Arc::new(MemoryUsageLedger::default());
+    ledger.append(&[record(&["support"], 100, 0.01), record(&["support", "beta"], 300, 0.05)]).unwrap();
+    let costs = analytics.clone().ledger(ledger.clone()).cost_per_tag(from, until).unwrap();
+    let tags: Vec<(&str, u64, u64)> =
+        costs.iter().map(|cost| (cost.tag.as_str(), cost.requests, cost.total_tokens)).collect();
+    assert_eq!(tags, [("support", 2, 400), ("beta", 1, 300)]);
+
+    // 未设置账本时使用全局账本，全局账本也未开启时报错；之后恢复原来的全局账本
+    // Without a ledger the global one is used, with no global ledger either it fails; the original global ledger is
+    // restored afterwards
+    let global = usage_ledger();
+    set_usage_ledger(ledger);
+    assert_eq!(analytics.cost_per_tag(from, until).unwrap(), costs);
+    disable_usage_ledger();
+    let error = analytics.cost_per_tag(from, until).unwrap_err();
+    if let Some(global) = global {
+        set_usage_ledger(global);
+    }
+    assert!(matches!(error.current_context(), AnalyticsError::NoLedger));
+    assert_eq!(error.code(), "analytics.no_ledger");
+
+    format_test_block("Analytics", || {
+        format!("{} days, {} tools, {} error kinds", daily.len(), tools.len(), errors.len())
+    });
+}
@@ -0,0 +1,148 @@
+use crate::chat::artifacts::{DiffLine, FileAction, FilePatch, ParsedAnswer};
+use crate::chat::chat_single::SingleChat;
+use crate::testing::MockProvider;
+use crate::tests::format_test_block;
+
+const ANSWER: &str = r#"I will set up the project.
+
+Create file `src/main.rs` with:
+
+```rust
+fn main() {
+    println!("hi");
+}
+```
+
+```toml title="Cargo.toml"
+[package]
+name = "demo"
+```
+
+**src/lib.rs**
+```rust
+pub mod util;
+```
+
+```python
+# file: scripts/run.py
+print("run")
+```
+
+Then apply this patch:
+
+```diff
+diff --git a/src/util.rs b/src/util.rs
+--- a/src/util.rs
++++ b/src/util.rs
+@@ -1,2 +1,2 @@
+ pub fn add(a: i32, b: i32) -> i32 {
+-    a - b
++    a + b
+--- /dev/null
++++ b/README.md
+@@
++# Demo
+```
+
+Delete the file `old/legacy.rs`.
+
+An example shell command:
+
+```bash
+cargo run
+```
+
+Finally:
+
+````markdown
+```rust
+nested
+```
+"#;
+
+pub async fn test_artifacts() {
+    let parsed = ParsedAnswer::parse(ANSWER);
+
+    // 代码块带语言与文件名，文件名来自上一行说明、信息串、标签行或首行注释
+    // Code blocks carry their language and file name, taken from the line above, the info string, a label line or
+    // a first-line comment
+    let blocks: Vec<_> = parsed
+        .code_blocks
+        .iter()
+        .map(|block| (block.language.as_deref(), block.path.as_deref()))
+        .collect();
+    assert_eq!(blocks, [
+        (Some("rust"), Some("src/main.rs")),
+        (Some("toml"), Some("Cargo.toml")),
+        (Some("rust"), Some("src/lib.rs")),
+        (Some("python"), Some("scripts/run.py")),
+        (Some("diff"), None),
+        (Some("bash"), None),
+        (Some("markdown"), None),
+    ]);
+    assert_eq!(parsed.code_blocks[3].code, "print(\"run\")");
+    assert_eq!(parsed.blocks_in("RUST").len(), 2);
+
+    // 截断的回答中未闭合的代码块标记为不完整，内部较短的围栏属于内容
+    // The unclosed block of a cut-off answer is marked incomplete, shorter fences inside belong to its content
+    let last = parsed.code_blocks.last().unwrap();
+    assert!(!last.complete);
+    assert_eq!(last.code, "```rust\nnested\n```");
+    assert!(parsed.code_blocks[0].complete);
+
+    // 文件说明按出现顺序，差异与无文件名的代码块不算
+    // File directives in order of appearance, diffs and blocks without a file name do not count
+    let files: Vec<_> = parsed.files.iter().map(|file| (file.path.as_str(), file.action)).collect();
+    assert_eq!(files, [
+        ("src/main.rs", FileAction::Write),
+        ("Cargo.toml", FileAction::Write),
+        ("src/lib.rs", FileAction::Write),
+        ("scripts/run.py", FileAction::Write),
+        ("old/legacy.rs", FileAction::Delete),
+    ]);
+    assert_eq!(parsed.files[1].content.as_deref(), Some("[package]\nname = \"demo\""));
+    assert_eq!(parsed.files[4].content, None);
+
+    // 差异按文件拆分，省略行号的块也能解析
+    // Diffs are split by file, hunks with omitted line numbers parse too
+    assert_eq!(parsed.patches.len(), 2);
+    let util = &parsed.patches[0];
+    assert_eq!(util.path(), Some("src/util.rs"));
+    assert_eq!(util.hunks[0].old_start, Some(1));
+    assert_eq!(util.hunks[0].lines[1], DiffLine::Removed("    a - b".to_string()));
+    assert_eq!(util.stats(), (1, 1));
+    let readme = &parsed.patches[1];
+    assert_eq!((readme.old_path.as_deref(), readme.new_path.as_deref()), (None, Some("README.md")));
+    assert_eq!(readme.hunks[0].new_start, None);
+    assert_eq!(readme.hunks[0].lines, [DiffLine::Added("# Demo".to_string())]);
+    assert_eq!(FilePatch::parse("--- a.txt\n+++ a.txt\n@@ -3 +3 @@\n-x\n+y\n")[0].hunks[0].new_start, Some(3));
+
+    assert!(parsed.prose.starts_with("I will set up the project.\n\nCreate file `src/main.rs` with:"));
+    assert!(!parsed.prose.contains("println!"));
+    // 绝对路径与跳出工作目录的路径不会成为文件说明或差异
+    // Absolute paths and paths leaving the working directory never become file directives or diffs
+    let unsafe_paths = ParsedAnswer::parse(
+        "Create file `/etc/passwd` with:\n```\nroot\n```\n\
+        ```rust ../../x.rs\nfn x() {}\n```\n\
+        Delete the file `C:\\Windows\\win.ini`.\n\
+        ```diff\n--- a/../secret.txt\n+++ b/../secret.txt\n@@\n-a\n+b\n```",
+    );
+    assert!(unsafe_paths.files.is_empty());
+    assert!(unsafe_paths.code_blocks.iter().all(|block| block.path.is_none()));
+    assert!(unsafe_paths.patches.is_empty());
+
+    assert_eq!(ParsedAnswer::parse("No code here."), ParsedAnswer {
+        prose: "No code here.".to_string(),
+        ..Default::default()
+    });
+
+    let mock = MockProvider::start("artifacts-api").await;
+    let mut chat = SingleChat::builder().api("artifacts-api").build().unwrap();
+    mock.reply("File: config/app.json\n```json\n{\"debug\": true}\n```");
+    let answer = chat.get_parsed_answer("Write the config").await.unwrap();
+    assert_eq!(answer.files[0].path, "config/app.json");
+    assert_eq!(answer.files[0].language.as_deref(), Some("json"));
+
+    format_test_block("Artifacts", || serde_json::to_string_pretty(&parsed.files).unwrap());
+    assert_eq!(mock.pending(), 0);
+}
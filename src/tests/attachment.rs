@@ -0,0 +1,116 @@
+use bytes::Bytes;
+
+use crate::chat::attachment::{AttachmentLimits, AttachmentStore, DirectoryAttachmentStore, load_attachment};
+use crate::chat::chat_single::SingleChat;
+use crate::config::Config;
+use crate::config::prompts::PromptKey;
+use crate::error::ReportExt;
+use crate::testing::MockProvider;
+use crate::tests::format_test_block;
+
+pub async fn test_attachment() {
+    test_chat_attachments().await;
+    test_directory_store();
+    #[cfg(feature = "encryption")]
+    test_encryption();
+}
+
+async fn test_chat_attachments() {
+    let mock = MockProvider::start("attachment-api").await;
+    mock.reply("I will analyze the sales data.").reply("Done.");
+    let mut chat = SingleChat::builder()
+        .api("attachment-api")
+        .attachment_limits(AttachmentLimits {
+            max_bytes: 64,
+            max_per_message: 2,
+        })
+        .build()
+        .unwrap();
+
+    chat.attach("notes.txt", "text/plain", "meeting notes", None).unwrap();
+    let sales = chat.attach("sales.csv", "text/csv", "region,total\nnorth,42", Some("/uploads/sales.csv")).unwrap();
+    assert_eq!(chat.attach("more.txt", "text/plain", "x", None).unwrap_err().code(), "chat.attachment");
+    chat.get_answer("Analyze attachment #2").await.unwrap();
+
+    // 请求中只有附件清单，没有内容
+    // Requests carry the attachment list, not the content
+    mock.last_request()
+        .contains("#1 notes.txt")
+        .contains(&format!("#2 sales.csv（text/csv，21 字节，ID：{}）", sales.id))
+        .not_contains("north,42");
+    assert_eq!(chat.attachments().len(), 2);
+    assert_eq!(chat.attachment(2), Some(&sales));
+    assert_eq!(chat.attachment(3), None);
+    assert_eq!(chat.attachment(2).unwrap().load_text().unwrap(), "region,total\nnorth,42");
+    assert_eq!(load_attachment(&sales.id).unwrap(), Bytes::from("region,total\nnorth,42"));
+
+    // 会话只保存元数据，附件在后续提问中仍然列出
+    // The session only keeps the metadata, the attachments are still listed on later questions
+    let saved = serde_json::to_string(&chat.base.session).unwrap();
+    format_test_block("Session With Attachments", || saved.clone());
+    assert!(saved.contains("/uploads/sales.csv") && !saved.contains("north,42"));
+    chat.get_answer("Thanks").await.unwrap();
+    mock.last_request().contains("#2 sales.csv");
+
+    // 附件清单的措辞可以按提示词键替换
+    // The wording of the attachment list can be replaced by prompt key
+    Config::set_capability_prompt(PromptKey::AttachmentList, "Attached files, pass an ID to a tool to read one:\n{}");
+    Config::set_capability_prompt(PromptKey::AttachmentEntry, "{}. {} ({}, {} bytes, ID {})");
+    mock.reply("Sure.");
+    chat.get_answer("Again").await.unwrap();
+    mock.last_request()
+        .contains("Attached files, pass an ID to a tool to read one:\n1. notes.txt (text/plain, 13 bytes, ID ")
+        .not_contains("字节");
+    Config::remove_capability_prompt(&PromptKey::AttachmentList);
+    Config::remove_capability_prompt(&PromptKey::AttachmentEntry);
+
+    let oversized = chat.attach("big.bin", "application/octet-stream", vec![0u8; 65], None).unwrap_err();
+    assert_eq!(oversized.code(), "chat.attachment");
+    assert!(load_attachment("missing").is_err());
+}
+
+fn test_directory_store() {
+    let directory = std::env::temp_dir().join(format!("rhine_attachments_{}", std::process::id()));
+    let store = DirectoryAttachmentStore::new(&directory).unwrap();
+    store.put("../escape", Bytes::from("kept inside")).unwrap();
+    assert_eq!(store.get("../escape").unwrap(), Some(Bytes::from("kept inside")));
+    assert_eq!(std::fs::read_dir(&directory).unwrap().count(), 1);
+    store.remove("../escape").unwrap();
+    assert_eq!(store.get("../escape").unwrap(), None);
+    std::fs::remove_dir_all(&directory).unwrap();
+}
+
+#[cfg(feature = "encryption")]
+fn test_encryption() {
+    use crate::chat::encryption::{Cipher, EncryptedAttachmentStore, StaticKeyProvider, load_session, save_session};
+    use crate::chat::message::{Role, Session};
+
+    let dir = std::env::temp_dir().join("rhine_test_encryption");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let mut session = Session::new();
+    session.add_with_parent_path(&[], Role::User, "my phone is 555-0100").unwrap();
+    let old = Cipher::new(StaticKeyProvider::new("k1", [1; 32]));
+    let path = dir.join("session.bin");
+    save_session(&path, &session, &old).unwrap();
+    let stored = std::fs::read(&path).unwrap();
+    assert!(Cipher::is_encrypted(&stored));
+    assert!(!String::from_utf8_lossy(&stored).contains("555-0100"));
+
+    // 轮换后旧密钥仍可解密，明文会话照常加载
+    // After a rotation the retired key still decrypts, and plaintext sessions load as before
+    let rotated = Cipher::new(StaticKeyProvider::new("k2", [2; 32]).with_retired("k1", [1; 32]));
+    assert_eq!(load_session(&path, &rotated).unwrap(), session);
+    std::fs::write(&path, serde_json::to_vec(&session).unwrap()).unwrap();
+    assert_eq!(load_session(&path, &rotated).unwrap(), session);
+    let wrong = Cipher::new(StaticKeyProvider::new("k1", [3; 32]));
+    let sealed = old.encrypt(b"secret").unwrap();
+    assert_eq!(wrong.decrypt(&sealed).unwrap_err().current_context().to_string(), "Decryption failed: key k1");
+
+    let store = EncryptedAttachmentStore::new(DirectoryAttachmentStore::new(dir.join("attachments")).unwrap(), rotated);
+    store.put("report", Bytes::from_static(b"quarterly numbers")).unwrap();
+    assert!(!std::fs::read(dir.join("attachments").join("report")).unwrap().starts_with(b"quarterly"));
+    assert_eq!(store.get("report").unwrap().unwrap(), Bytes::from_static(b"quarterly numbers"));
+    format_test_block("Encryption", || format!("{} bytes sealed", sealed.len()));
+}
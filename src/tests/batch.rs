@@ -0,0 +1,42 @@
+use serde_json::json;
+
+use crate::chat::chat_base::ChatError;
+use crate::chat::chat_single::SingleChat;
+use crate::config::{Config, ModelCapability};
+use crate::tests::{format_test_block, spawn_mock_server};
+
+pub async fn test_batch() {
+    test_map_answers().await;
+}
+
+async fn test_map_answers() {
+    // 回显最后一条消息，内容为 "fail" 时返回 500
+    // Echo the last message, answer 500 when it is "fail"
+    let url = spawn_mock_server(|body| {
+        let messages = body["messages"].as_array().cloned().unwrap_or_default();
+        let content = messages.last().and_then(|m| m["content"].as_str()).unwrap_or("").to_string();
+        if content == "fail" {
+            return (500, "{}".to_string());
+        }
+        let answer = format!("{}:{}", content, messages.len());
+        (200, json!({
+            "choices": [{"message": {"content": answer}, "finish_reason": "stop"}],
+            "usage": {"prompt_tokens": 1, "completion_tokens": 1, "total_tokens": 2},
+        }).to_string())
+    })
+    .await;
+    Config::add_api_source("batch-source", &url, 4);
+    Config::add_api_info("batch-api", "batch-model", ModelCapability::LongContext, "batch-source", "sk-batch").unwrap();
+
+    let chat = SingleChat::new_with_api_name("batch-api", "", false);
+    let inputs = ["a", "b", "fail", "c", "d"];
+    let results = chat.map_answers(inputs, 2).await;
+    format_test_block("Map Answers", || format!("{:?}", results));
+
+    assert_eq!(results.len(), inputs.len());
+    assert_eq!(results[0].as_ref().unwrap(), "a:1");
+    assert_eq!(results[1].as_ref().unwrap(), "b:1");
+    assert!(matches!(results[2].as_ref().unwrap_err().current_context(), ChatError::HttpError(500)));
+    assert_eq!(results[4].as_ref().unwrap(), "d:1");
+    assert!(chat.base.session.message_roots.is_empty());
+}
@@ -0,0 +1,48 @@
+use serde_json::json;
+
+use crate::blocking::SingleChatBlocking;
+use crate::config::{Config, ModelCapability};
+use crate::tests::{format_test_block, spawn_mock_server};
+
+pub async fn test_blocking() {
+    test_single_chat_blocking().await;
+}
+
+async fn test_single_chat_blocking() {
+    let url = spawn_mock_server(|body| {
+        let content = body["messages"][0]["content"].as_str().unwrap_or("").to_uppercase();
+        if body["stream"] == true {
+            let chunk = json!({"choices": [{"delta": {"content": content}, "finish_reason": "stop"}]});
+            let usage = json!({"choices": [], "usage": {"prompt_tokens": 1, "completion_tokens": 1, "total_tokens": 2}});
+            (200, format!("data: {}\n\ndata: {}\n\ndata: [DONE]\n\n", chunk, usage))
+        } else {
+            (200, json!({
+                "choices": [{"message": {"content": content}, "finish_reason": "stop"}],
+                "usage": {"prompt_tokens": 1, "completion_tokens": 1, "total_tokens": 2},
+            }).to_string())
+        }
+    })
+    .await;
+    Config::add_api_source("blocking-source", &url, 2);
+    Config::add_api_info("blocking-api", "blocking-model", ModelCapability::LongContext, "blocking-source", "sk-blocking").unwrap();
+
+    // 模拟没有异步运行时的调用方
+    // Simulate a caller without an async runtime
+    let (answer, streamed, deltas, usage) = tokio::task::spawn_blocking(|| {
+        let mut chat = SingleChatBlocking::new_with_api_name("blocking-api", "", false);
+        let answer = chat.get_answer("hello").unwrap();
+
+        let mut chat = SingleChatBlocking::new_with_api_name("blocking-api", "", true);
+        let mut deltas = Vec::new();
+        let streamed = chat.stream_answer("world", |delta| deltas.push(delta.to_string())).unwrap();
+        (answer, streamed, deltas, chat.usage())
+    })
+    .await
+    .unwrap();
+
+    format_test_block("Blocking Chat", || format!("{} {} {:?}", answer, streamed, deltas));
+    assert_eq!(answer, "HELLO");
+    assert_eq!(streamed, "WORLD");
+    assert_eq!(deltas, vec!["WORLD"]);
+    assert_eq!(usage, 2);
+}
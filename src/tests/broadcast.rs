@@ -0,0 +1,47 @@
+use futures::StreamExt;
+
+use crate::chat::broadcast::{Backpressure, StreamBroadcast};
+use crate::chat::chat_base::ChatError;
+use crate::chat::chat_single::SingleChat;
+use crate::testing::MockProvider;
+use crate::tests::format_test_block;
+
+pub async fn test_broadcast() {
+    let mock = MockProvider::start("broadcast-api").await;
+    mock.reply("one two three four").reply_error(400, "bad request");
+    let mut chat = SingleChat::builder().api("broadcast-api").build().unwrap();
+
+    // 界面合并跟不上的内容，日志跟不上时被断开，安全检查逐段收到全部内容
+    // The UI coalesces what it cannot keep up with, the logger is disconnected, the guardrail gets every piece
+    let broadcast = StreamBroadcast::new();
+    let ui = broadcast.subscribe(Backpressure::Coalesce, 1);
+    let log = broadcast.subscribe(Backpressure::Disconnect, 1);
+    let guard = tokio::spawn(broadcast.subscribe(Backpressure::Wait, 1).collect::<Vec<_>>());
+    let answer = chat.broadcast_answer("hi", &broadcast).await.unwrap();
+
+    let ui: Vec<String> = ui.map(|delta| delta.unwrap()).collect().await;
+    let log: Vec<_> = log.collect().await;
+    let guard: Vec<String> = guard.await.unwrap().into_iter().map(|delta| delta.unwrap()).collect();
+    format_test_block("Broadcast", || format!("{}\n{:?}\n{:?}\n{:?}", answer, ui, log, guard));
+    assert_eq!(answer, "one two three four");
+    assert_eq!(ui, vec!["one ", "two three four"]);
+    assert_eq!(log.len(), 2);
+    assert_eq!(log[0].as_deref().unwrap(), "one ");
+    assert!(matches!(log[1].as_ref().unwrap_err().current_context(), ChatError::SubscriberLagged));
+    assert_eq!(guard, vec!["one ", "two ", "three ", "four"]);
+    assert_eq!(chat.base.session.last_message_mut().unwrap().content.as_text(), Some("one two three four"));
+
+    // 结束后订阅只收到完整的回答
+    // Subscribing after the end only yields the full answer
+    let late: Vec<String> = broadcast.subscribe(Backpressure::Wait, 4).map(|delta| delta.unwrap()).collect().await;
+    assert_eq!(late, vec!["one two three four"]);
+
+    // 生成失败时调用方收到原错误，订阅者的流以 StreamFailed 结束
+    // On failure the caller gets the original error and subscriber streams end with StreamFailed
+    let broadcast = StreamBroadcast::new();
+    let subscriber = broadcast.subscribe(Backpressure::Coalesce, 4);
+    assert!(chat.broadcast_answer("again", &broadcast).await.is_err());
+    let items: Vec<_> = subscriber.collect().await;
+    assert_eq!(items.len(), 1);
+    assert!(matches!(items[0].as_ref().unwrap_err().current_context(), ChatError::StreamFailed(_)));
+}
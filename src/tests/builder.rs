@@ -0,0 +1,80 @@
+use std::collections::HashMap;
+
+use futures::FutureExt;
+use futures::future::BoxFuture;
+use serde_json::json;
+
+use crate::chat::chat_base::ChatError;
+use crate::chat::chat_multi::MultiChat;
+use crate::chat::chat_single::SingleChat;
+use crate::chat::params::ChatParams;
+use crate::chat::retriever::Retriever;
+use crate::config::{Config, ModelCapability};
+use crate::tests::{format_test_block, spawn_mock_server};
+
+pub async fn test_builder() {
+    test_single_chat_builder().await;
+    test_fallible_constructors();
+}
+
+struct StaticRetriever;
+
+impl Retriever for StaticRetriever {
+    fn retrieve<'a>(&'a self, query: &'a str) -> BoxFuture<'a, Vec<String>> {
+        async move { vec![format!("notes about {}", query)] }.boxed()
+    }
+}
+
+async fn test_single_chat_builder() {
+    // 回显请求中的系统消息与温度
+    // Echo the system messages and temperature of the request
+    let url = spawn_mock_server(|body| {
+        let systems: Vec<String> = body["messages"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter(|message| message["role"] == "system")
+            .filter_map(|message| message["content"].as_str().map(str::to_string))
+            .collect();
+        let answer = format!("{}|{}", systems.join(";"), body["temperature"]);
+        (200, json!({
+            "choices": [{"message": {"content": answer}, "finish_reason": "stop"}],
+            "usage": {"prompt_tokens": 1, "completion_tokens": 1, "total_tokens": 2},
+        }).to_string())
+    })
+    .await;
+    Config::add_api_source("builder-source", &url, 2);
+    Config::add_api_info("builder-api", "builder-model", ModelCapability::LongContext, "builder-source", "sk-builder").unwrap();
+
+    let mut chat = SingleChat::builder()
+        .api("builder-api")
+        .capability(ModelCapability::LongContext)
+        .system("be brief")
+        .params(ChatParams::new().temperature(0.5))
+        .retriever(StaticRetriever)
+        .tag("builder")
+        .build()
+        .unwrap();
+    assert_eq!(chat.base.capability, Some(ModelCapability::LongContext));
+    assert_eq!(chat.base.tags, vec!["builder"]);
+
+    let answer = chat.get_answer("rust").await.unwrap();
+    format_test_block("SingleChat Builder", || answer.clone());
+    assert_eq!(answer, "be brief;以下是与问题相关的参考资料：\n[1] notes about rust|0.5");
+    assert_eq!(chat.base.session.message_roots.len(), 1);
+
+    let missing = SingleChat::builder().build().unwrap_err();
+    assert!(matches!(missing.current_context(), ChatError::InvalidConfig));
+    let unknown = SingleChat::builder().api("no-such-api").build().unwrap_err();
+    assert!(matches!(unknown.current_context(), ChatError::InvalidConfig));
+}
+
+fn test_fallible_constructors() {
+    let error = SingleChat::try_new_with_api_name("no-such-api", "", false).unwrap_err();
+    assert!(matches!(error.current_context(), ChatError::InvalidConfig));
+    assert!(SingleChat::try_new_with_api_name("builder-api", "", false).is_ok());
+
+    let prompts = HashMap::from([("alice".to_string(), "You are Alice".to_string())]);
+    let error = MultiChat::new_with_api_name("no-such-api", prompts, false).unwrap_err();
+    assert!(matches!(error.current_context(), ChatError::InvalidConfig));
+}
@@ -18,14 +18,16 @@ pub async fn test_chat() {
         Think,
         "pumpkin",
         "sk-cPdegaWl8YFcKZYs8a108b5f741844D9A1E0B90e724bBe23",
-    );
+    )
+    .unwrap();
     Config::add_api_info(
         "pumpkin-gpt-4o",
         "gpt-4o",
         ToolUse,
         "pumpkin",
         "sk-cPdegaWl8YFcKZYs8a108b5f741844D9A1E0B90e724bBe23",
-    );
+    )
+    .unwrap();
 
     test_single_chat().await;
     // test_single_chat_get_json().await;
@@ -81,7 +83,7 @@ async fn test_single_chat_get_json() {
 
 async fn test_single_chat_get_tool() {
     let mut chat = SingleChat::new_with_api_name("pumpkin-ds-r1", "", true);
-    chat.set_tools(vec![send_email_tool_schema()]);
+    chat.set_tools(vec![send_email_tool_schema()]).unwrap();
     let answer = chat
         .get_tool_answer("随意编造信息发送一封邮件")
         .await
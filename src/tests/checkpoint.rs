@@ -0,0 +1,80 @@
+use serde_json::json;
+
+use crate::chat::chat_base::ChatError;
+use crate::chat::chat_single::SingleChat;
+use crate::testing::MockProvider;
+use crate::tests::format_test_block;
+
+pub async fn test_checkpoint() {
+    let mock = MockProvider::start("checkpoint-api").await;
+    mock.reply("Paris.").reply("Deleting files.").reply("Berlin.");
+    let mut chat = SingleChat::builder().api("checkpoint-api").build().unwrap();
+    chat.get_answer("Capital of France?").await.unwrap();
+    let (path, usage) = (chat.base.session.default_path.clone(), chat.base.usage);
+
+    // 工具循环出错后回到检查点，从检查点继续
+    // Roll back to the checkpoint after a bad tool loop and carry on from there
+    let checkpoint = chat.checkpoint();
+    let tool = json!({
+        "type": "function",
+        "function": {"name": "delete_files", "description": "Delete files", "parameters": {"properties": {}}},
+    });
+    chat.set_tools(vec![tool]).unwrap();
+    chat.get_answer("Clean up the disk").await.unwrap();
+    let explored = chat.checkpoint();
+    assert!(chat.base.usage > usage);
+
+    chat.rollback(&checkpoint).unwrap();
+    assert_eq!(chat.base.session.default_path, path);
+    assert_eq!(chat.base.usage, usage);
+    assert!(chat.tools().is_empty());
+    chat.get_answer("Capital of Germany?").await.unwrap();
+    mock.last_request().contains("Capital of France?").not_contains("Clean up").not_contains("delete_files");
+
+    // 之后创建的检查点仍可恢复
+    // Checkpoints created later can still be restored
+    chat.rollback(&explored).unwrap();
+    assert_eq!(chat.tools().len(), 1);
+    assert_eq!(chat.checkpoints().len(), 2);
+
+    assert!(chat.release_checkpoint(&checkpoint));
+    assert!(!chat.release_checkpoint(&checkpoint));
+    let error = chat.rollback(&checkpoint).unwrap_err();
+    assert!(matches!(error.current_context(), ChatError::UnknownCheckpoint(id) if *id == checkpoint));
+
+    format_test_block("checkpoint", || format!("{:?}", chat.checkpoints()));
+
+    test_fork().await;
+}
+
+async fn test_fork() {
+    let mock = MockProvider::start("fork-api").await;
+    mock.reply("Day 1: Tokyo, day 2: Kyoto.").reply("About 2000 USD.").reply("Try the Tsukiji outer market.");
+    let mut chat = SingleChat::builder().api("fork-api").system("You are a travel planner.").build().unwrap();
+    chat.get_answer("Plan a trip to Japan").await.unwrap();
+    let plan = chat.base.session.default_path.clone();
+    chat.get_answer("Add a budget").await.unwrap();
+
+    // 子对话只带有分叉点之前的上下文，与父对话互不影响
+    // The child only carries the context up to the fork point, and parent and child do not affect each other
+    let mut child = chat.spawn_child(&plan, "Focus only on food recommendations.").unwrap();
+    assert_ne!(child.base.session_id, chat.base.session_id);
+    child.get_answer("Where should I eat in Tokyo?").await.unwrap();
+    mock.last_request()
+        .contains("Plan a trip to Japan")
+        .contains("system: Focus only on food recommendations.")
+        .not_contains("Add a budget");
+    assert_eq!(chat.base.session.default_path.len(), 5);
+
+    // 子对话的结论作为消息并回父对话
+    // The child's conclusion is merged back into the parent as a message
+    let conclusion = chat.merge_child(&child).unwrap();
+    format_test_block("Merged Conclusion", || conclusion.clone());
+    assert_eq!(conclusion, "Try the Tsukiji outer market.");
+    mock.reply("Final plan ready.");
+    chat.get_answer("Finalize the plan").await.unwrap();
+    mock.last_request().contains("About 2000 USD.").contains("子任务的结论：\nTry the Tsukiji outer market.");
+
+    assert!(chat.spawn_child(&[0, 7], "").is_err());
+    assert!(chat.merge_child(&chat.spawn_child(&plan[..2], "").unwrap()).is_err());
+}
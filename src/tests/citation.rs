@@ -0,0 +1,106 @@
+use crate::chat::chat_single::SingleChat;
+use crate::chat::citation::CitedAnswer;
+use crate::chat::faithfulness::FaithfulnessChecker;
+use crate::chat::retriever::{CITATION_INSTRUCTION, Source};
+use crate::config::Config;
+use crate::config::prompts::PromptKey;
+use crate::documents::{Chunker, ChunkIndex, Document, ingest};
+use crate::testing::MockProvider;
+use crate::tests::format_test_block;
+
+pub async fn test_citation() {
+    test_parse();
+    test_cited_answer().await;
+    test_verified_answer().await;
+}
+
+fn test_parse() {
+    let sources = vec![Source::new("a", "Alpha"), Source::new("b", "Beta").origin("beta.md")];
+
+    // 按首次引用重新编号，去重，移除不存在的编号
+    // Renumbered by first citation, deduplicated, made-up numbers removed
+    let cited = CitedAnswer::parse("Beta says so [2]. Both agree [1, 2][2]. Nobody said this [7].", &sources);
+    format_test_block("Cited Answer", || format!("{:#?}", cited));
+    assert_eq!(cited.text, "Beta says so [1]. Both agree [2][1]. Nobody said this .");
+    assert_eq!(cited.citations, vec![sources[1].clone(), sources[0].clone()]);
+
+    assert!(CitedAnswer::parse("No sources [1].", &[]).citations.is_empty());
+}
+
+async fn test_cited_answer() {
+    let document = Document::from_pages(
+        "handbook.pdf",
+        vec!["Vacation requests go to HR.".to_string(), "Expense reports are due monthly.".to_string()],
+    );
+    let index = ChunkIndex::new(2);
+    ingest(&document, &Chunker::default(), &index).await.unwrap();
+
+    let mock = MockProvider::start("citation-api").await;
+    mock.reply("Monthly [1].").reply("Expense reports are due monthly [1], vacations go to HR [2].");
+    let mut chat = SingleChat::builder().api("citation-api").retriever(index).build().unwrap();
+
+    // 普通提问不要求标注引用，但仍记录检索来源
+    // Plain questions do not ask for citations, the retrieved sources are still recorded
+    chat.get_answer("When are expense reports due?").await.unwrap();
+    mock.last_request().contains("[1] [handbook.pdf, p. 2]\nExpense reports").not_contains(CITATION_INSTRUCTION);
+    assert_eq!(chat.sources()[0].id, "handbook.pdf#1");
+
+    let cited = chat.get_cited_answer("When are expense reports and vacation requests due?").await.unwrap();
+    mock.last_request().contains(CITATION_INSTRUCTION);
+    assert_eq!(cited.text, "Expense reports are due monthly [1], vacations go to HR [2].");
+    assert_eq!(cited.citations.len(), 2);
+    assert_eq!(cited.citations[0].origin.as_deref(), Some("handbook.pdf, p. 2"));
+    assert_eq!(cited.citations[1].id, "handbook.pdf#0");
+}
+
+async fn test_verified_answer() {
+    let document = Document::from_pages("policy.md", vec!["Expense reports are due monthly.".to_string()]);
+    let index = ChunkIndex::new(1);
+    ingest(&document, &Chunker::default(), &index).await.unwrap();
+
+    let mock = MockProvider::start("faithfulness-api").await;
+    let judge = MockProvider::start("faithfulness-judge-api").await;
+    mock.reply("Expense reports are due weekly.").reply("Expense reports are due monthly.");
+    judge
+        .reply(r#"{"claims": [{"claim": "Expense reports are due weekly", "score": 0.1}]}"#)
+        .reply(r#"```json
+{"claims": [{"claim": "Expense reports are due monthly", "score": 0.9, "sources": [1]}]}
+```"#);
+    let checker = FaithfulnessChecker::new().api("faithfulness-judge-api").max_regenerations(1);
+    let mut chat =
+        SingleChat::builder().api("faithfulness-api").retriever(index).faithfulness(checker).build().unwrap();
+
+    // 没有依据的回答连同陈述交回模型重答，只有最终回答写入会话
+    // An unsupported answer goes back to the model with its claims, only the final answer enters the session
+    let verified = chat.get_verified_answer("When are expense reports due?").await.unwrap();
+    format_test_block("Verified Answer", || format!("{:#?}", verified));
+    assert_eq!(verified.text, "Expense reports are due monthly.");
+    assert!(verified.report.is_faithful());
+    assert_eq!(verified.report.regenerations, 1);
+    assert_eq!(verified.report.claims[0].sources, vec![1]);
+    mock.last_request().contains("Expense reports are due weekly").contains("- Expense reports are due weekly");
+    judge.request(0).contains("[1] [policy.md, p. 1]").contains("回答：\nExpense reports are due weekly.");
+    assert_eq!(chat.base.session.default_path.len(), 2);
+    assert_eq!(mock.pending() + judge.pending(), 0);
+
+    // 评判指令、材料与重答指令都可以按提示词键替换
+    // The judge instruction, the material and the regeneration instruction can all be replaced by prompt key
+    let prompts = [
+        (PromptKey::FaithfulnessJudge, "Check every claim against the sources."),
+        (PromptKey::FaithfulnessMaterial, "Sources:\n{}\n\nAnswer:\n{}"),
+        (PromptKey::FaithfulnessRegeneration, "Answer again from the sources, these claims are unsupported:\n{}"),
+    ];
+    for (key, prompt) in prompts {
+        Config::set_capability_prompt(key, prompt);
+    }
+    mock.reply("Weekly.").reply("Monthly.");
+    judge
+        .reply(r#"{"claims": [{"claim": "Reports are weekly", "score": 0.0}]}"#)
+        .reply(r#"{"claims": [{"claim": "Reports are monthly", "score": 1.0, "sources": [1]}]}"#);
+    chat.get_verified_answer("When are expense reports due, again?").await.unwrap();
+    judge.request(2).contains("Check every claim against the sources.").contains("\n\nAnswer:\nWeekly.");
+    mock.last_request().contains("Answer again from the sources, these claims are unsupported:\n- Reports are weekly");
+    for (key, _) in prompts {
+        Config::remove_capability_prompt(&key);
+    }
+}
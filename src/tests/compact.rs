@@ -0,0 +1,66 @@
+use crate::chat::chat_single::SingleChat;
+use crate::chat::content::Content;
+use crate::chat::message::Role;
+use crate::testing::MockProvider;
+use crate::tests::format_test_block;
+
+pub async fn test_compact() {
+    let mock = MockProvider::start("compact-api").await;
+    let mut chat = SingleChat::builder().api("compact-api").system("You are a travel agent.").build().unwrap();
+    let base = &mut chat.base;
+    base.add_message(Role::User, "Weather in Paris?").unwrap();
+    let question_path = base.session.default_path.clone();
+    base.add_content(Role::Assistant, Content::tool_call("call_1", "get_weather", r#"{"city":"Paris"}"#)).unwrap();
+    let call_id = base.session.last_message_id().unwrap().to_string();
+    base.add_tool_result("call_1", &format!("sunny {}", "detail ".repeat(100))).await.unwrap();
+    base.add_message(Role::Assistant, "It is sunny in Paris.").unwrap();
+    base.add_message(Role::User, "And tomorrow?").unwrap();
+    // 有的提供商在不同轮次中重复使用调用ID，未解决的调用的结果不能随同名的已解决调用一起移除
+    // Some providers reuse call IDs across turns, the result of an unresolved call must not go along with a resolved
+    // call of the same ID
+    base.add_content(Role::Assistant, Content::tool_call("call_1", "get_forecast", r#"{"city":"Paris"}"#)).unwrap();
+    base.add_tool_result("call_1", "rain").await.unwrap();
+
+    // 一条不在默认路径上的分支
+    // One branch off the default path
+    let end_path = base.session.default_path.clone();
+    base.session.add_with_parent_path(&question_path, Role::Assistant, "Abandoned answer").unwrap();
+    base.session.default_path = end_path;
+
+    let report = chat.compact();
+    assert_eq!(report.exchanges_collapsed, 1);
+    assert_eq!(report.branches_summarized, 1);
+    assert!(report.tokens_saved() > 100);
+
+    // 已解决的调用折叠为摘要并保留ID，最近一次调用与结果保持原样
+    // The resolved call is collapsed into a summary keeping its ID, the latest call and result are left as is
+    let session = &chat.base.session;
+    assert_eq!(session.default_path, vec![0; 7]);
+    assert_eq!(session.path_of(&call_id).unwrap(), vec![0; 3]);
+    let mut contents = Vec::new();
+    let mut node = &session.message_roots[0];
+    loop {
+        contents.push(node.content.clone());
+        match node.child.first() {
+            Some(child) => node = child,
+            None => break,
+        }
+    }
+    let summary = contents[2].as_text().unwrap();
+    assert!(summary.starts_with(r#"[已调用工具 get_weather({"city":"Paris"})，结果：sunny detail"#));
+    assert!(summary.ends_with("…]"));
+    assert_eq!(contents[3], Content::from("It is sunny in Paris."));
+    assert_eq!(contents[6], Content::tool_result("call_1", "rain"));
+
+    // 不在默认路径上的分支折叠为一条摘要消息，留在原处
+    // The branch off the default path is collapsed into one summary message, left where it was
+    let branch = &session.message_roots[0].child[0].child[1];
+    assert_eq!((&branch.role, branch.child.len()), (&Role::Assistant, 0));
+    assert_eq!(branch.content.as_text().unwrap(), "[未选用的分支]\nassistant: Abandoned answer");
+
+    mock.reply("Take an umbrella.");
+    chat.get_answer("Thanks").await.unwrap();
+    mock.last_request().contains("[已调用工具 get_weather").contains("rain").not_contains("Abandoned");
+
+    format_test_block("compact", || format!("{:?}\n{:#?}", report, contents));
+}
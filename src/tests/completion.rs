@@ -0,0 +1,84 @@
+use futures::StreamExt;
+use serde_json::json;
+
+use crate::chat::chat_single::SingleChat;
+use crate::chat::completion::ChatTemplate;
+use crate::config::metadata::ModelMetadata;
+use crate::config::validate::IssueKind;
+use crate::config::{Config, ModelCapability};
+use crate::tests::{format_test_block, spawn_mock_server};
+
+pub async fn test_completion() {
+    test_render();
+    test_completion_chat().await;
+}
+
+fn test_render() {
+    let template = ChatTemplate::chatml();
+    let body = json!({
+        "model": "m",
+        "messages": [
+            {"role": "system", "content": "Be brief."},
+            {"role": "user", "content": [{"type": "text", "text": "Hi"}]},
+            {"role": "assistant", "content": "Hel"},
+        ],
+        "stop": "###",
+        "tools": [],
+    });
+    let completion = template.completion_body(&body).unwrap();
+    format_test_block("Completion Body", || format!("{:#}", completion));
+
+    // 末尾的助手消息接在生成提示之后
+    // The trailing assistant message follows the generation prompt
+    assert_eq!(
+        completion["prompt"],
+        "<|im_start|>system\nBe brief.<|im_end|>\n<|im_start|>user\nHi<|im_end|>\n<|im_start|>assistant\nHel"
+    );
+    assert_eq!(completion["stop"], json!(["###", "<|im_end|>"]));
+    assert!(completion.get("messages").is_none() && completion.get("tools").is_none());
+
+    let llama = ChatTemplate::new("{{ bos_token }}{% for m in messages %}[{{ m.role }}] {{ m.content }}\n{% endfor %}")
+        .bos_token("<s>");
+    assert_eq!(llama.render(&[json!({"role": "user", "content": "Hi"})], true).unwrap(), "<s>[user] Hi\n");
+    assert!(ChatTemplate::new("{% for m in messages %}").check().is_err());
+}
+
+async fn test_completion_chat() {
+    let url = spawn_mock_server(|body| {
+        assert!(body["prompt"].as_str().unwrap().ends_with("<|im_start|>assistant\n"));
+        if body["stream"] == true {
+            let events: String = ["Hello", " there"]
+                .iter()
+                .map(|text| format!("data: {}\n\n", json!({"choices": [{"text": text}]})))
+                .collect();
+            (200, events + "data: [DONE]\n\n")
+        } else {
+            (200, json!({
+                "choices": [{"text": "Hello there", "finish_reason": "stop"}],
+                "usage": {"prompt_tokens": 5, "completion_tokens": 2, "total_tokens": 7},
+            }).to_string())
+        }
+    })
+    .await;
+    Config::add_api_source("completion-source", &url, 2);
+    Config::add_api_info("completion-api", "completion-model", ModelCapability::LongContext, "completion-source", "sk-completion")
+        .unwrap();
+    Config::set_model_metadata("completion-model", ModelMetadata {
+        chat_template: Some(ChatTemplate::chatml()),
+        ..Default::default()
+    });
+
+    let mut chat = SingleChat::builder().api("completion-api").build().unwrap();
+    assert_eq!(chat.get_answer("Hi").await.unwrap(), "Hello there");
+
+    let mut chat = SingleChat::builder().api("completion-api").build().unwrap();
+    let deltas: Vec<String> = chat.stream_answer("Hi").await.unwrap().map(Result::unwrap).collect().await;
+    assert_eq!(deltas.concat(), "Hello there");
+
+    Config::set_model_metadata("completion-model", ModelMetadata {
+        chat_template: Some(ChatTemplate::new("{% if %}")),
+        ..Default::default()
+    });
+    assert!(Config::validate().iter().any(|issue| issue.kind == IssueKind::InvalidChatTemplate));
+    Config::set_model_metadata("completion-model", ModelMetadata::default());
+}
@@ -0,0 +1,95 @@
+use futures::StreamExt;
+
+use crate::chat::chat_single::SingleChat;
+use crate::config::compression::CompressionConfig;
+use crate::config::{Config, ModelCapability};
+use crate::testing::{MockProvider, ReplyEncoding};
+use crate::tests::format_test_block;
+
+pub async fn test_compression() {
+    let compression = CompressionConfig {
+        request: true,
+        request_min_bytes: 1024,
+        ..Default::default()
+    };
+    let mock = MockProvider::start("compression-api").await.with_compression(compression);
+    let mut chat = SingleChat::builder().api("compression-api").build().unwrap();
+
+    // 小请求不压缩，压缩的回复被透明地解压
+    // Small requests are sent as is and compressed replies are decompressed transparently
+    mock.reply("Short answer.");
+    assert_eq!(chat.get_answer("Hi").await.unwrap(), "Short answer.");
+    let accepted = mock.request_header(0, "accept-encoding").unwrap();
+    assert!(["gzip", "deflate", "br"].iter().all(|encoding| accepted.contains(encoding)), "{}", accepted);
+    assert_eq!(mock.request_header(0, "content-encoding"), None);
+
+    // 超过阈值的请求体以 gzip 发送
+    // Request bodies over the threshold are sent gzipped
+    let context = "The warehouse ships orders within two days. ".repeat(50);
+    mock.reply("Two days.");
+    assert_eq!(chat.get_answer(&context).await.unwrap(), "Two days.");
+    assert_eq!(mock.request_header(1, "content-encoding").as_deref(), Some("gzip"));
+    mock.last_request().contains("The warehouse ships orders within two days.");
+
+    // 流式回复边接收边解压
+    // Streamed replies are decompressed as they arrive
+    mock.reply("streamed and compressed");
+    let deltas: Vec<String> = chat.stream_answer("Stream it").await.unwrap().map(Result::unwrap).collect().await;
+    assert_eq!(deltas.concat(), "streamed and compressed");
+
+    // deflate 与 brotli 的回复同样被解压，流式与非流式皆然
+    // deflate and brotli replies are decompressed too, streamed or not
+    for encoding in [ReplyEncoding::Deflate, ReplyEncoding::Brotli] {
+        let mock = mock.clone().with_reply_encoding(encoding);
+        mock.reply("Decoded answer.");
+        assert_eq!(chat.get_answer("Decode it").await.unwrap(), "Decoded answer.", "{:?}", encoding);
+        mock.reply("decoded stream");
+        let deltas: Vec<String> = chat.stream_answer("Stream it").await.unwrap().map(Result::unwrap).collect().await;
+        assert_eq!(deltas.concat(), "decoded stream", "{:?}", encoding);
+    }
+
+    // 校验和错误的 gzip 回复被拒绝，而不是当作正常内容
+    // gzip replies with a wrong checksum are rejected instead of being taken as content
+    let mock = mock.with_reply_encoding(ReplyEncoding::CorruptGzip);
+    mock.reply("Corrupted.");
+    assert!(chat.get_answer("Corrupt it").await.is_err());
+
+    // 关闭响应压缩后只声明接受未压缩的回复
+    // With response compression off, only uncompressed responses are accepted
+    let mock = mock.with_compression(CompressionConfig {
+        response: false,
+        ..Default::default()
+    });
+    mock.reply("Plain.");
+    assert_eq!(chat.get_answer("Again").await.unwrap(), "Plain.");
+    let last = mock.requests().len() - 1;
+    assert_eq!(mock.request_header(last, "accept-encoding").as_deref(), Some("identity"));
+
+    // 共用基础URL的来源各自使用自己的压缩设置
+    // Sources sharing a base URL each use their own compression settings
+    let config = Config::builder()
+        .api("compression-plain", mock.url(), "sk-mock", "mock-model")
+        .compression(CompressionConfig {
+            response: false,
+            ..Default::default()
+        })
+        .capability(ModelCapability::Cheap, "compression-plain")
+        .api("compression-twin", mock.url(), "sk-mock", "mock-model")
+        .capability(ModelCapability::Think, "compression-twin")
+        .build()
+        .unwrap();
+    let encodings = Config::with_scoped(config, async {
+        let mut encodings = Vec::new();
+        for api in ["compression-plain", "compression-twin"] {
+            let mut chat = SingleChat::builder().api(api).build().unwrap();
+            mock.reply("Shared URL.");
+            assert_eq!(chat.get_answer("Which source?").await.unwrap(), "Shared URL.");
+            encodings.push(mock.request_header(mock.requests().len() - 1, "accept-encoding"));
+        }
+        encodings
+    })
+    .await;
+    assert_eq!(encodings[0].as_deref(), Some("identity"));
+    assert!(encodings[1].as_deref().is_some_and(|encodings| encodings.contains("br")));
+    format_test_block("compression", || format!("{:?}", mock.request_header(1, "content-encoding")));
+}
@@ -0,0 +1,65 @@
+use serde_json::json;
+
+use crate::chat::chat_single::SingleChat;
+use crate::chat::confidence::{ConfidenceScorer, similarity};
+use crate::chat::middleware::RewriteInput;
+use crate::testing::MockProvider;
+use crate::tests::format_test_block;
+
+pub async fn test_confidence() {
+    assert_eq!(similarity("Paris.", "paris"), 1.0);
+    assert_eq!(similarity("巴黎", "伦敦"), 0.0);
+    assert!(similarity("The capital is Paris", "Paris is the capital") > 0.5);
+
+    let mock = MockProvider::start("confidence-api").await;
+    let scorer = ConfidenceScorer::new().judge_api("confidence-api").samples(2).threshold(0.6);
+    let mut chat = SingleChat::builder().api("confidence-api").confidence(scorer).build().unwrap();
+
+    // 回答带 logprobs，两次采样一致，评判给出高分
+    // The answer comes with logprobs, both samples agree and the judge scores it high
+    mock.reply_with_logprobs("Paris", &[-0.1]);
+    mock.reply("Paris").reply("Paris");
+    mock.reply_json(json!({"score": 0.9}));
+    let answer = chat.get_scored_answer("What is the capital of France?").await.unwrap();
+    assert_eq!(answer.text, "Paris");
+    assert_eq!(mock.pending(), 0);
+    let requests = mock.requests();
+    assert_eq!(requests[0]["logprobs"], json!(true));
+    assert!(requests[1].get("logprobs").is_none());
+    mock.request(3).contains("What is the capital of France?").contains("Paris");
+    let report = &answer.confidence;
+    assert!((report.logprob.unwrap() - (-0.1f64).exp()).abs() < 1e-9);
+    assert_eq!(report.consistency, Some(1.0));
+    assert_eq!(report.judge, Some(0.9));
+    assert!(!report.is_low());
+
+    // 采样不一致且评判给出低分时为低置信度，只有原回答写入会话
+    // Disagreeing samples and a low judge score make low confidence, only the answer itself is stored
+    mock.reply("Sydney").reply("Canberra").reply("Melbourne");
+    mock.reply_json(json!({"score": 0.2}));
+    let answer = chat.get_scored_answer("What is the capital of Australia?").await.unwrap();
+    assert_eq!(answer.confidence.logprob, None);
+    assert!(answer.confidence.consistency.unwrap() < 0.5);
+    assert!(answer.confidence.is_low());
+    assert_eq!(chat.base.session.last_message_mut().unwrap().content.to_string(), "Sydney");
+
+    format_test_block("confidence", || serde_json::to_string_pretty(&answer).unwrap());
+
+    // 回答经过中间件；评判的回答无法解析时不计入评判
+    // The answer goes through the middlewares; a verdict that does not parse leaves the judge out
+    let scorer = ConfidenceScorer::new().judge_api("confidence-api").logprobs(false);
+    let mut chat = SingleChat::builder()
+        .api("confidence-api")
+        .confidence(scorer)
+        .middleware(RewriteInput(|input: &str| input.replace("pls", "please")))
+        .build()
+        .unwrap();
+    mock.reply("Berlin").reply("probably right");
+    let answer = chat.get_scored_answer("capital of Germany pls").await.unwrap();
+    assert_eq!(answer.text, "Berlin");
+    assert_eq!(mock.pending(), 0);
+    mock.request(8).contains("capital of Germany please");
+    assert!(mock.requests()[8].get("logprobs").is_none());
+    assert_eq!(answer.confidence.judge, None);
+    assert!(answer.confidence.is_low());
+}
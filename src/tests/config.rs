@@ -0,0 +1,686 @@
+use std::fs;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use futures::StreamExt;
+use serde_json::json;
+
+use crate::chat::chat_base::BaseChat;
+use crate::chat::chat_single::SingleChat;
+use crate::chat::message::Role;
+use crate::chat::params::ChatParams;
+use crate::chat::queue::{FairQueue, QueuePriority};
+use crate::config::balance::{is_healthy, set_healthy};
+use crate::config::http::{pool_stats, shared_client};
+use crate::config::metadata::ModelMetadata;
+use crate::config::prompts::PromptKey;
+use crate::config::secrets::{SecretError, SecretProvider, register_secret_provider, resolve_secret};
+use crate::config::tls::TlsConfig;
+use crate::config::validate::IssueKind;
+use crate::config::{CFG, Config, ModelCapability};
+use crate::error::ReportExt;
+use crate::telemetry::exporter::{TRACE_EXPORTERS, remove_trace_exporter};
+use crate::tests::{format_test_block, spawn_mock_server};
+use crate::utils::common::expand_env::expand_env;
+
+pub async fn test_config() {
+    test_expand_env();
+    test_load_config_file();
+    test_config_builder();
+    test_weighted_balance();
+    test_credential_failover().await;
+    test_api_params();
+    test_alias_and_metadata();
+    test_probe().await;
+    test_validate();
+    test_secret_provider();
+    test_profiles();
+    test_capability_prompts();
+    test_tls_config();
+    test_shared_client().await;
+    test_concurrency_limits().await;
+    test_fair_queue().await;
+}
+
+fn test_expand_env() {
+    unsafe { std::env::set_var("RHINE_TEST_KEY", "sk-test") };
+
+    assert_eq!(expand_env("env:RHINE_TEST_KEY").unwrap(), "sk-test");
+    assert_eq!(expand_env("Bearer ${RHINE_TEST_KEY}!").unwrap(), "Bearer sk-test!");
+    assert_eq!(expand_env("plain").unwrap(), "plain");
+
+    let missing = expand_env("${RHINE_TEST_MISSING}").unwrap_err();
+    assert!(missing.to_string().contains("RHINE_TEST_MISSING"));
+    assert!(expand_env("${RHINE_TEST_KEY").is_err());
+
+    format_test_block("expand_env", || format!("missing: {:?}", missing));
+}
+
+fn test_load_config_file() {
+    unsafe { std::env::set_var("RHINE_TEST_HOST", "http://127.0.0.1:9") };
+
+    let path = std::env::temp_dir().join("rhine_test_config.toml");
+    fs::write(
+        &path,
+        r#"
+            [[api_source]]
+            name = "test-source"
+            base_url = "${RHINE_TEST_HOST}/v1/chat/completions"
+            parallelism = 2
+
+            [[api_info]]
+            name = "test-api"
+            model = "test-model"
+            capability = "long_context"
+            source = "test-source"
+            api_key = "env:RHINE_TEST_KEY"
+
+            [[trace_exporter]]
+            name = "test-langfuse"
+            kind = "langfuse"
+            host = "${RHINE_TEST_HOST}"
+            public_key = "pk-test"
+            secret_key = "env:RHINE_TEST_KEY"
+        "#,
+    )
+    .unwrap();
+
+    Config::load(path.to_str().unwrap()).unwrap();
+    assert!(TRACE_EXPORTERS.contains_key("test-langfuse"));
+    remove_trace_exporter("test-langfuse");
+    let api_info = Config::get_api_info_with_name("test-api".to_string()).unwrap();
+    assert_eq!(api_info.base_url, "http://127.0.0.1:9/v1/chat/completions");
+    assert_eq!(api_info.api_key, "sk-test");
+    assert!(Config::get_api_info_with_capability(ModelCapability::LongContext).is_ok());
+
+    format_test_block("load_config_file", || format!("{:?}", api_info.model));
+}
+
+fn test_config_builder() {
+    let config = Config::builder()
+        .api("builder-api", "http://127.0.0.1:9/v1/chat/completions", "sk-builder", "builder-model")
+        .parallelism(3)
+        .capability(ModelCapability::Think, "builder-api")
+        .build()
+        .unwrap();
+    assert_eq!(config.api_source.get("builder-api").unwrap().parallelism, 3);
+
+    assert!(Config::builder().capability(ModelCapability::Think, "nope").build().is_err());
+    assert!(Config::builder().api("unbound", "http://x", "k", "m").build().is_ok());
+
+    // 整份配置一次性替换，之前取得的配置保持原样，不会被清空一半
+    // The whole configuration is swapped at once, one taken before stays intact instead of being half cleared
+    let before = CFG.current();
+    assert!(before.api_info.iter().any(|entry| entry.value().name == "test-api"));
+    Config::set_global(config);
+    assert!(before.api_info.iter().any(|entry| entry.value().name == "test-api"));
+    assert!(Config::get_api_info_with_name("test-api".to_string()).is_err());
+    let api_info = Config::get_api_info_with_capability(ModelCapability::Think).unwrap();
+    assert_eq!(api_info.model, "builder-model");
+
+    format_test_block("config_builder", || format!("{:?}", api_info.base_url));
+}
+
+fn test_weighted_balance() {
+    let config = Config::builder()
+        .api("balance-a", "http://127.0.0.1:9/a", "sk-a", "model-a")
+        .weight(0)
+        .api("balance-b", "http://127.0.0.1:9/b", "sk-b", "model-b")
+        .weight(5)
+        .api("balance-c", "http://127.0.0.1:9/c", "sk-c", "model-c")
+        .capability(ModelCapability::LongContext, "balance-a")
+        .capability(ModelCapability::LongContext, "balance-b")
+        .capability(ModelCapability::LongContext, "balance-c")
+        .build()
+        .unwrap();
+    Config::set_global(config);
+
+    let picked: Vec<String> = (0..50)
+        .map(|_| Config::get_api_info_with_capability(ModelCapability::LongContext).unwrap().name)
+        .collect();
+    assert!(!picked.contains(&"balance-a".to_string()));
+
+    set_healthy("balance-b", false);
+    let picked = Config::get_api_info_with_capability(ModelCapability::LongContext).unwrap();
+    assert_eq!(picked.name, "balance-c");
+    set_healthy("balance-b", true);
+
+    // 候选全部停用时报错，而不是选中已停用的API
+    // Fails once every candidate is disabled instead of picking a disabled API
+    Config::set_api_weight("balance-b", 0);
+    Config::set_api_weight("balance-c", 0);
+    let error = Config::get_api_info_with_capability(ModelCapability::LongContext).unwrap_err();
+    assert_eq!(error.code(), "config.no_enabled_api");
+
+    format_test_block("weighted_balance", || format!("{:?}", picked.name));
+}
+
+async fn test_credential_failover() {
+    let rejected = Arc::new(AtomicUsize::new(0));
+    let hits = rejected.clone();
+    let rejecting = spawn_mock_server(move |_| {
+        hits.fetch_add(1, Ordering::SeqCst);
+        (401, json!({"error": {"code": "invalid_api_key"}}).to_string())
+    })
+    .await;
+    let exhausted = spawn_mock_server(|_| (429, json!({"error": {"type": "insufficient_quota"}}).to_string())).await;
+    let forbidden = spawn_mock_server(|_| (403, json!({"error": {"code": "unsupported_country"}}).to_string())).await;
+    let working = spawn_mock_server(|body| {
+        assert_eq!(body["model"], "model-c");
+        if body["stream"] == true {
+            (200, format!("data: {}\n\ndata: [DONE]\n\n", json!({"choices": [{"delta": {"content": "Hello"}, "finish_reason": "stop"}]})))
+        } else {
+            (200, json!({
+                "choices": [{"message": {"role": "assistant", "content": "Hello"}, "finish_reason": "stop"}],
+                "usage": {"prompt_tokens": 3, "completion_tokens": 1, "total_tokens": 4},
+            }).to_string())
+        }
+    })
+    .await;
+    let config = Config::builder()
+        .api("failover-a", &rejecting, "sk-a", "model-a")
+        .api("failover-b", &exhausted, "sk-b", "model-b")
+        .api("failover-c", &working, "sk-c", "model-c")
+        .api("failover-d", &forbidden, "sk-d", "model-d")
+        .capability(ModelCapability::Cheap, "failover-a")
+        .capability(ModelCapability::Cheap, "failover-b")
+        .capability(ModelCapability::Cheap, "failover-c")
+        .capability(ModelCapability::Think, "failover-d")
+        .build()
+        .unwrap();
+    Config::with_scoped(config, async {
+        // 没有能力时无处转移，错误原样返回
+        // Without a capability there is nowhere to fail over, the error is returned as is
+        let mut chat = SingleChat::builder().api("failover-a").build().unwrap();
+        let report = chat.get_answer("hi").await.unwrap_err();
+        assert!(report.rejects_credentials());
+        assert!(is_healthy("failover-a"));
+
+        // 403 多为内容或地区限制，不视为凭据失效
+        // 403 is usually a content or region restriction and does not count as a failed credential
+        let mut chat = SingleChat::builder().api("failover-d").build().unwrap();
+        assert!(!chat.get_answer("hi").await.unwrap_err().rejects_credentials());
+
+        // 凭据失效与额度用尽的API进入冷却，本次请求转到同一能力下的其他API，结束后对话仍绑定原API
+        // APIs with a rejected credential or no quota cool down, and the request moves to another API of the
+        // capability, the chat is still bound to the original API afterwards
+        let mut chat = SingleChat::builder().api("failover-a").capability(ModelCapability::Cheap).build().unwrap();
+        assert_eq!(chat.get_answer("hi").await.unwrap(), "Hello");
+        assert_eq!(chat.base.api_name, "failover-a");
+        assert!(!is_healthy("failover-a"));
+        let metadata = chat.base.session.last_message_mut().unwrap().metadata.clone().unwrap();
+        assert_eq!(metadata.model, "model-c");
+        let switches: Vec<(&str, &str)> =
+            metadata.provider_switches.iter().map(|switch| (switch.from.as_str(), switch.to.as_str())).collect();
+        assert_eq!(switches.first().map(|switch| switch.0), Some("failover-a"));
+        assert_eq!(switches.last().map(|switch| switch.1), Some("failover-c"));
+
+        // 冷却中的API在下一次请求中直接跳过
+        // The cooling API is skipped right away by the next request
+        let before = rejected.load(Ordering::SeqCst);
+        assert_eq!(chat.get_answer("again").await.unwrap(), "Hello");
+        assert_eq!(rejected.load(Ordering::SeqCst), before);
+        assert_eq!(chat.base.api_name, "failover-a");
+
+        let mut chat =
+            SingleChat::builder().api("failover-b").capability(ModelCapability::Cheap).stream(true).build().unwrap();
+        let deltas: Vec<String> = chat.stream_answer("hi").await.unwrap().map(Result::unwrap).collect().await;
+        assert_eq!(deltas.concat(), "Hello");
+        assert!(!is_healthy("failover-b"));
+        assert_eq!(chat.base.api_name, "failover-b");
+        assert_eq!(Config::get_api_info_with_capability(ModelCapability::Cheap).unwrap().name, "failover-c");
+
+        format_test_block("credential_failover", || format!("{:?}\n{:?}", report, metadata.provider_switches));
+    })
+    .await;
+    set_healthy("failover-a", true);
+    set_healthy("failover-b", true);
+}
+
+fn test_api_params() {
+    let config = Config::builder()
+        .api("params-api", "http://127.0.0.1:9/v1/chat/completions", "sk-params", "params-model")
+        .params(ChatParams::new().temperature(0.2).max_tokens(256))
+        .capability(ModelCapability::Think, "params-api")
+        .build()
+        .unwrap();
+    Config::set_global(config);
+
+    let mut chat = BaseChat::new_with_api_name("params-api", "", false);
+    chat.add_message(Role::User, "hello").unwrap();
+    chat.set_params(&ChatParams::new().max_tokens(64));
+    let body = chat.build_request_body(&chat.session.default_path.clone(), &Role::User).unwrap();
+    assert_eq!(body["temperature"], 0.2);
+    assert_eq!(body["max_tokens"], 64);
+    assert!(body.get("top_p").is_none());
+    assert!(body.get("logit_bias").is_none());
+
+    // 以分词器按文本禁止 token，偏置截断到 -100 到 100
+    // Tokens banned by text through a tokenizer, biases clamped to -100 to 100
+    let tokenizer = |text: &str| text.bytes().map(u32::from).collect::<Vec<u32>>();
+    chat.set_params(&ChatParams::new().ban_text(&tokenizer, "``").logit_bias(7, 300));
+    let body = chat.build_request_body(&chat.session.default_path.clone(), &Role::User).unwrap();
+    assert_eq!(body["logit_bias"], serde_json::json!({"7": 100, "96": -100}));
+
+    let params: ChatParams = toml::from_str("[logit_bias]\n1734 = -100").unwrap();
+    assert_eq!(params.logit_bias.unwrap()[&1734], -100);
+
+    format_test_block("api_params", || body.to_string());
+}
+
+fn test_alias_and_metadata() {
+    let config = Config::builder()
+        .api("alias-api", "http://127.0.0.1:9/v1/chat/completions", "sk-alias", "alias-model")
+        .capability(ModelCapability::Think, "alias-api")
+        .alias("fast", "alias-api")
+        .model_metadata("alias-model", ModelMetadata {
+            context_window: Some(8192),
+            supports_tools: true,
+            ..Default::default()
+        })
+        .build()
+        .unwrap();
+    Config::set_global(config);
+
+    let api_info = Config::get_api_info_with_name("fast".to_string()).unwrap();
+    assert_eq!(api_info.name, "alias-api");
+    let metadata = BaseChat::new_with_api_name("fast", "", false).model_metadata().unwrap();
+    assert_eq!(metadata.context_window, Some(8192));
+    assert!(Config::builder().alias("slow", "missing").build().is_err());
+
+    format_test_block("alias_and_metadata", || format!("{:?}", metadata));
+}
+
+async fn test_probe() {
+    let config = Config::builder()
+        .api("probe-api", "http://127.0.0.1:9/v1/chat/completions", "sk-probe", "probe-model")
+        .capability(ModelCapability::Think, "probe-api")
+        .build()
+        .unwrap();
+    Config::set_global(config);
+
+    let reports = Config::probe().await;
+    assert_eq!(reports.len(), 1);
+    assert!(!reports[0].healthy);
+    assert!(!is_healthy("probe-api"));
+    set_healthy("probe-api", true);
+
+    format_test_block("probe", || format!("{:?}", reports));
+}
+
+fn test_validate() {
+    let config = Config::builder()
+        .api("valid-api", "http://127.0.0.1:9/v1/chat/completions", "sk-valid", "valid-model")
+        .api("broken-api", "not a url", "", "broken-model")
+        .capability(ModelCapability::ToolUse, "valid-api")
+        .capability(ModelCapability::Think, "broken-api")
+        .alias("ghost", "valid-api")
+        .build()
+        .unwrap();
+    Config::set_global(config);
+    Config::add_alias("ghost", "missing-api");
+
+    let issues = Config::validate();
+    let kinds: Vec<IssueKind> = issues.iter().map(|issue| issue.kind.clone()).collect();
+    assert!(kinds.contains(&IssueKind::MissingApiKey));
+    assert!(kinds.contains(&IssueKind::InvalidUrl));
+    assert!(kinds.contains(&IssueKind::UnknownAliasTarget));
+    assert!(kinds.contains(&IssueKind::UnboundCapability));
+    assert!(issues.iter().all(|issue| issue.subject != "api 'valid-api'"));
+
+    assert!(Config::add_api_info("x", "m", ModelCapability::Think, "missing-source", "k").is_err());
+
+    format_test_block("validate", || {
+        issues.iter().map(|issue| issue.to_string()).collect::<Vec<_>>().join("\n")
+    });
+}
+
+struct StaticSecrets;
+
+impl SecretProvider for StaticSecrets {
+    fn get_secret(&self, key: &str) -> error_stack::Result<String, SecretError> {
+        match key {
+            "openai" => Ok("sk-from-vault".to_string()),
+            other => Err(error_stack::Report::new(SecretError::SecretNotFound(other.to_string()))),
+        }
+    }
+}
+
+fn test_secret_provider() {
+    register_secret_provider("vault", Arc::new(StaticSecrets));
+
+    assert_eq!(resolve_secret("secret:vault:openai").unwrap(), "sk-from-vault");
+    assert_eq!(resolve_secret("sk-plain").unwrap(), "sk-plain");
+    assert!(resolve_secret("secret:vault:missing").is_err());
+    assert!(resolve_secret("secret:nowhere:openai").is_err());
+    assert!(resolve_secret("secret:vault").is_err());
+
+    let path = std::env::temp_dir().join("rhine_test_secret_config.toml");
+    fs::write(
+        &path,
+        r#"
+            [[api_source]]
+            name = "secret-source"
+            base_url = "http://127.0.0.1:9/v1/chat/completions"
+            parallelism = 1
+
+            [[api_info]]
+            name = "secret-api"
+            model = "secret-model"
+            capability = "think"
+            source = "secret-source"
+            api_key = "secret:vault:openai"
+        "#,
+    )
+    .unwrap();
+    Config::load(path.to_str().unwrap()).unwrap();
+    let api_info = Config::get_api_info_with_name("secret-api".to_string()).unwrap();
+    assert_eq!(api_info.api_key, "sk-from-vault");
+
+    format_test_block("secret_provider", || api_info.name.clone());
+}
+
+fn test_profiles() {
+    let path = std::env::temp_dir().join("rhine_test_profile_config.toml");
+    fs::write(
+        &path,
+        r#"
+            [[api_source]]
+            name = "profile-source"
+            base_url = "http://127.0.0.1:9/dev"
+            parallelism = 1
+
+            [[api_info]]
+            name = "profile-api"
+            model = "small-model"
+            capability = "think"
+            source = "profile-source"
+            api_key = "sk-dev"
+
+            [profile.staging]
+            [[profile.staging.api_source]]
+            name = "profile-source"
+            base_url = "http://127.0.0.1:9/staging"
+            parallelism = 4
+
+            [profile.prod]
+            inherits = "staging"
+            [[profile.prod.api_info]]
+            name = "profile-api"
+            model = "large-model"
+            capability = "think"
+            source = "profile-source"
+            api_key = "sk-prod"
+
+            [profile.loop-a]
+            inherits = "loop-b"
+            [profile.loop-b]
+            inherits = "loop-a"
+        "#,
+    )
+    .unwrap();
+    let path = path.to_str().unwrap();
+
+    Config::load_with_profile(path, Some("prod")).unwrap();
+    let api_info = Config::get_api_info_with_name("profile-api".to_string()).unwrap();
+    assert_eq!(api_info.base_url, "http://127.0.0.1:9/staging");
+    assert_eq!(api_info.model, "large-model");
+    assert_eq!(api_info.api_key, "sk-prod");
+
+    assert!(Config::load_with_profile(path, Some("missing")).is_err());
+    assert!(Config::load_with_profile(path, Some("loop-a")).is_err());
+
+    format_test_block("profiles", || format!("{:?}", api_info.base_url));
+}
+
+fn test_capability_prompts() {
+    let path = std::env::temp_dir().join("rhine_test_capability_prompt.toml");
+    fs::write(
+        &path,
+        r#"
+            [capability_prompt]
+            tool_use_json = "Convert the input into the requested JSON."
+            tool_use_function = "Call the given function."
+
+            [profile.zh.capability_prompt]
+            tool_use_json = "将输入整理为指定的JSON。"
+        "#,
+    )
+    .unwrap();
+
+    // JSON 整理与函数调用各有自己的键，替换其一不影响另一个
+    // JSON conversion and function calling have their own keys, replacing one leaves the other alone
+    Config::load_with_profile(path.to_str().unwrap(), Some("zh")).unwrap();
+    assert_eq!(Config::capability_prompt(&PromptKey::ToolUseJson), "将输入整理为指定的JSON。");
+    assert_eq!(Config::capability_prompt(&PromptKey::ToolUseFunction), "Call the given function.");
+
+    Config::remove_capability_prompt(&PromptKey::ToolUseJson);
+    assert_eq!(Config::capability_prompt(&PromptKey::ToolUseJson), PromptKey::ToolUseJson.default_prompt());
+    assert_eq!(Config::capability_prompt(&PromptKey::ToolUseFunction), "Call the given function.");
+    Config::remove_capability_prompt(&PromptKey::ToolUseFunction);
+
+    // 占位符依次填入，多余的占位符留空
+    // Placeholders are filled in order, extra ones are left empty
+    Config::set_capability_prompt(PromptKey::ToolUseJson, "{} then {} and {}");
+    assert_eq!(Config::render_prompt(&PromptKey::ToolUseJson, &["one", "two"]), "one then two and ");
+    Config::remove_capability_prompt(&PromptKey::ToolUseJson);
+
+    // 能力名称不是提示词键
+    // Capability names are not prompt keys
+    fs::write(&path, "[capability_prompt]\ntool_use = \"Convert the input.\"\n").unwrap();
+    assert!(Config::load(path.to_str().unwrap()).is_err());
+
+    let config = Config::builder()
+        .capability_prompt(PromptKey::ToolUseFunction, "Call the given function.")
+        .build()
+        .unwrap();
+    let prompt = config.capability_prompts.get(&PromptKey::ToolUseFunction).unwrap();
+    assert_eq!(prompt.as_str(), "Call the given function.");
+    assert!(config.capability_prompts.get(&PromptKey::ToolUseJson).is_none());
+    fs::remove_file(&path).unwrap();
+}
+
+fn test_tls_config() {
+    let insecure = TlsConfig {
+        insecure_skip_verify: true,
+        ..Default::default()
+    };
+    let config = Config::builder()
+        .api("tls-api", "https://127.0.0.1:9/v1/chat/completions", "sk-tls", "tls-model")
+        .tls(insecure)
+        .capability(ModelCapability::Think, "tls-api")
+        .build()
+        .unwrap();
+    Config::set_global(config);
+
+    let missing_ca = TlsConfig {
+        ca_cert: Some("/nonexistent/ca.pem".to_string()),
+        ..Default::default()
+    };
+    let error = Config::set_source_tls("tls-api", missing_ca).unwrap_err();
+    let half_identity = TlsConfig {
+        client_cert: Some("/nonexistent/client.pem".to_string()),
+        ..Default::default()
+    };
+    assert!(Config::set_source_tls("tls-api", half_identity).is_err());
+
+    format_test_block("tls_config", || format!("{:?}", error));
+}
+
+async fn test_shared_client() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    // 支持 keep-alive 的最小HTTP服务
+    // Minimal HTTP server supporting keep-alive
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        while let Ok((mut socket, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let mut buffer = [0u8; 4096];
+                while let Ok(read) = socket.read(&mut buffer).await {
+                    if read == 0 {
+                        break;
+                    }
+                    let response = "HTTP/1.1 200 OK\r\ncontent-length: 2\r\n\r\nok";
+                    if socket.write_all(response.as_bytes()).await.is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+    });
+
+    let before = pool_stats();
+    let url = format!("http://localhost:{}/", port);
+    for _ in 0..3 {
+        let body = shared_client().get(&url).send().await.unwrap().text().await.unwrap();
+        assert_eq!(body, "ok");
+    }
+    let after = pool_stats();
+    format_test_block("Shared Client", || format!("{:?} -> {:?}", before, after));
+    assert_eq!(after.connections_opened - before.connections_opened, 1);
+    assert_eq!(after.dns_lookups + after.dns_cache_hits - before.dns_lookups - before.dns_cache_hits, 1);
+}
+
+async fn test_concurrency_limits() {
+    Config::add_api_source("limit-source", "http://127.0.0.1:9/v1/chat/completions", 2);
+    Config::add_api_info("limit-api", "limit-model", ModelCapability::Think, "limit-source", "sk-limit").unwrap();
+    assert_eq!(Config::get_source_permits("limit-source").unwrap().limit, 2);
+    Config::set_source_parallelism("limit-source", 3).unwrap();
+    assert!(Config::set_source_parallelism("missing-source", 3).is_err());
+
+    Config::set_capability_limit(ModelCapability::Think, 1);
+    let mut chat = BaseChat::new_with_api_name("limit-api", "", false);
+    chat.capability = Some(ModelCapability::Think);
+
+    let permit = chat.acquire_permit().await.unwrap();
+    let source = Config::get_source_permits("limit-source").unwrap();
+    let capability = Config::get_capability_permits(&ModelCapability::Think).unwrap();
+    format_test_block("Concurrency Limits", || format!("{:?}\n{:?}", source, capability));
+    assert_eq!((source.limit, source.in_use()), (3, 1));
+    assert_eq!((capability.limit, capability.in_use()), (1, 1));
+
+    let blocked = tokio::time::timeout(std::time::Duration::from_millis(50), chat.acquire_permit()).await;
+    assert!(blocked.is_err());
+
+    // 调整上限不会放过额外的请求：降低上限后，进行中的请求归还的额度先被收回
+    // Changing the limit lets no extra request through: after lowering it, permits returned by requests in flight
+    // are reclaimed first
+    Config::set_capability_limit(ModelCapability::Think, 2);
+    let second = chat.acquire_permit().await.unwrap();
+    Config::set_capability_limit(ModelCapability::Think, 1);
+    tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    drop(second);
+    let blocked = tokio::time::timeout(std::time::Duration::from_millis(50), chat.acquire_permit()).await;
+    assert!(blocked.is_err());
+
+    drop(permit);
+    assert_eq!(Config::get_capability_permits(&ModelCapability::Think).unwrap().available, 1);
+    Config::remove_capability_limit(&ModelCapability::Think);
+    assert!(Config::get_capability_permits(&ModelCapability::Think).is_none());
+
+    // 替换整份配置时已有的额度原地调整，进行中的请求仍计入新的上限
+    // Replacing the whole configuration resizes the existing permits, requests in flight still count against the
+    // new limits
+    Config::with_scoped(Config::builder().build().unwrap(), async {
+        let config = |limit| {
+            Config::builder()
+                .api("reload-api", "http://127.0.0.1:9/v1/chat/completions", "sk-reload", "reload-model")
+                .parallelism(limit)
+                .capability(ModelCapability::Think, "reload-api")
+                .capability_limit(ModelCapability::Think, limit)
+                .build()
+                .unwrap()
+        };
+        Config::set_global(config(2));
+        let mut chat = BaseChat::new_with_api_name("reload-api", "", false);
+        chat.capability = Some(ModelCapability::Think);
+        let first = chat.acquire_permit().await.unwrap();
+        let second = chat.acquire_permit().await.unwrap();
+
+        Config::set_global(config(1));
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        drop(second);
+        assert_eq!(Config::get_source_permits("reload-api").unwrap().available, 0);
+        assert_eq!(Config::get_capability_permits(&ModelCapability::Think).unwrap().available, 0);
+        drop(first);
+        assert_eq!(Config::get_source_permits("reload-api").unwrap().available, 1);
+    })
+    .await;
+}
+
+/// 依次排队，每个请求在下一个到来前已进入队列；释放 `held` 后返回各请求获得额度的顺序
+/// Queue the requests one after another, each already queued before the next arrives; returns the order in which
+/// they get a permit once `held` is released
+async fn queue_in_order(
+    queue: &FairQueue,
+    held: tokio::sync::OwnedSemaphorePermit,
+    requests: &[(&'static str, QueuePriority)],
+) -> Vec<&'static str> {
+    let order = std::sync::Mutex::new(Vec::new());
+    let mut waiting: Vec<_> = requests
+        .iter()
+        .map(|&(key, priority)| {
+            let order = &order;
+            Box::pin(async move {
+                let _permit = queue.acquire(key, priority).await.unwrap();
+                order.lock().unwrap().push(key);
+            })
+        })
+        .collect();
+    for request in &mut waiting {
+        assert!(futures::poll!(request.as_mut()).is_pending());
+    }
+    drop(held);
+    futures::future::join_all(waiting).await;
+    order.into_inner().unwrap()
+}
+
+async fn test_fair_queue() {
+    let semaphore = Arc::new(tokio::sync::Semaphore::new(1));
+    let queue = FairQueue::new(semaphore.clone());
+    let held = queue.acquire("warmup", QueuePriority::Normal).await.unwrap();
+
+    // 批量任务先排队，后到的交互请求不必等它们全部完成
+    // Bulk work queues first, a later interactive request does not wait for all of it
+    let order = queue_in_order(
+        &queue,
+        held,
+        &[
+            ("bulk", QueuePriority::Background),
+            ("bulk", QueuePriority::Background),
+            ("bulk", QueuePriority::Background),
+            ("interactive", QueuePriority::Interactive),
+        ],
+    )
+    .await;
+    format_test_block("Fair Queue", || format!("{:?}", order));
+    assert_eq!(order, ["bulk", "interactive", "bulk", "bulk"]);
+    assert_eq!(semaphore.available_permits(), 1);
+
+    // 无人排队时直接获取额度
+    // Permits are taken directly while nobody is queued
+    let permit = queue.acquire("bulk", QueuePriority::Background).await.unwrap();
+    assert_eq!(semaphore.available_permits(), 0);
+
+    // 后台通道只在没有前台请求排队时才轮到，即使它的进度更靠前
+    // The background lane only gets a turn while no foreground request is queued, even with a smaller pass
+    let requests = [
+        ("eval-a", QueuePriority::Background),
+        ("eval-b", QueuePriority::Weight(2)),
+        ("normal", QueuePriority::Normal),
+        ("chat", QueuePriority::Interactive),
+    ];
+    assert_eq!(queue_in_order(&queue, permit, &requests).await, ["eval-a", "chat", "normal", "eval-b"]);
+
+    // 前台请求源源不断时，后台请求在有限轮次后也能轮到
+    // With a steady stream of foreground requests the background still gets its turn within a bounded number of turns
+    let permit = queue.acquire("chat", QueuePriority::Interactive).await.unwrap();
+    let mut requests = vec![("chat", QueuePriority::Interactive), ("eval", QueuePriority::Background)];
+    requests.extend([("chat", QueuePriority::Interactive); 40]);
+    let order = queue_in_order(&queue, permit, &requests).await;
+    assert_eq!(order.iter().position(|key| *key == "eval"), Some(17));
+    assert!(QueuePriority::Weight(3).is_background());
+    assert!(!QueuePriority::Weight(4).is_background());
+}
@@ -0,0 +1,120 @@
+use serde_json::json;
+
+use crate::chat::content::Content;
+use crate::chat::message::{Role, Session};
+use crate::tests::format_test_block;
+
+pub async fn test_content() {
+    test_content_serde();
+    test_content_rendering();
+    test_tool_role();
+}
+
+fn test_content_serde() {
+    // 纯文本仍序列化为字符串，旧版会话可以直接读取
+    // Plain text still serializes to a string, so older sessions load unchanged
+    let mut session = Session::new();
+    session.add_with_default_path(Role::User, "hello").unwrap();
+    let value = serde_json::to_value(&session).unwrap();
+    assert_eq!(value["message_roots"][0]["content"], json!("hello"));
+
+    let parts = Content::Parts(vec![
+        Content::from("look at this"),
+        Content::image("https://example.com/cat.png"),
+        Content::tool_call("call_1", "search", r#"{"q":"cat"}"#),
+        Content::tool_result("call_1", "a cat"),
+        Content::audio("UklGRg==", "wav"),
+        Content::file("report.pdf", "application/pdf", "JVBERi0="),
+    ]);
+    session.add_with_default_path(Role::Assistant, parts.clone()).unwrap();
+    let restored: Session = serde_json::from_str(&serde_json::to_string(&session).unwrap()).unwrap();
+    assert_eq!(restored, session);
+    assert_eq!(restored.message_roots[0].child[0].content, parts);
+
+    format_test_block("content_serde", || format!("{:?}", restored));
+}
+
+fn test_content_rendering() {
+    let mut session = Session::new();
+    session.add_with_default_path(Role::System, "be helpful").unwrap();
+    session
+        .add_with_default_path(
+            Role::User,
+            vec![Content::from("what is this?"), Content::image("https://example.com/cat.png")],
+        )
+        .unwrap();
+    session
+        .add_with_default_path(Role::Assistant, Content::tool_call("call_1", "classify", r#"{"url":"cat.png"}"#))
+        .unwrap();
+    session.add_with_default_path(Role::User, Content::tool_result("call_1", "cat")).unwrap();
+    let end_path = session.default_path.clone();
+
+    // 支持图片的提供商收到分段内容，工具调用与结果使用原生字段
+    // Providers supporting images get content parts, tool calls and results use the native fields
+    let openai = serde_json::to_value(session.assemble_context_for(&end_path, &Role::Assistant, "openai").unwrap())
+        .unwrap();
+    assert_eq!(openai[1]["content"][1], json!({"type": "image_url", "image_url": {"url": "https://example.com/cat.png"}}));
+    assert_eq!(openai[2]["role"], "assistant");
+    assert_eq!(openai[2]["content"], json!(null));
+    assert_eq!(openai[2]["tool_calls"][0]["function"]["name"], "classify");
+    assert_eq!(openai[3], json!({"role": "tool", "content": "cat", "tool_call_id": "call_1"}));
+    assert!(openai[0].get("tool_calls").is_none());
+
+    // 不支持图片的提供商收到文本描述
+    // Providers without image support get a text description
+    let deepseek =
+        serde_json::to_value(session.assemble_context_for(&end_path, &Role::Assistant, "deepseek").unwrap()).unwrap();
+    assert_eq!(deepseek[1]["content"], json!("what is this?\n[image: https://example.com/cat.png]"));
+
+    // 其他角色的工具调用以文本呈现给当前发言者
+    // Tool calls of another character are shown as text to the current speaker
+    let mut group = Session::new();
+    group
+        .add_with_default_path(Role::Character("Alice".to_string()), Content::tool_call("call_2", "roll", "{}"))
+        .unwrap();
+    let end_path = group.default_path.clone();
+    let bob = group.assemble_context(&end_path, &Role::Character("Bob".to_string())).unwrap();
+    assert_eq!(bob[0].role, "user");
+    assert_eq!(bob[0].content.as_text(), Some("Alice said: [tool call: roll({})]"));
+    assert!(bob[0].tool_calls.is_none());
+
+    format_test_block("content_rendering", || format!("{}\n{}", openai, deepseek));
+}
+
+fn test_tool_role() {
+    // 工具角色带调用ID序列化，纯文本的工具结果同样以 tool 角色发出
+    // The tool role serializes with its call ID, plain-text tool results are sent with the tool role too
+    let role = Role::tool("call_9");
+    assert_eq!(serde_json::to_value(&role).unwrap(), json!({"tool": {"tool_call_id": "call_9"}}));
+    assert_eq!(serde_json::from_value::<Role>(json!({"tool": {"tool_call_id": "call_9"}})).unwrap(), role);
+    assert_eq!(serde_json::from_value::<Role>(json!("Alice")).unwrap(), Role::Character("Alice".to_string()));
+    assert_eq!(role.to_string(), "tool");
+    assert_eq!(Role::from(role.to_string().as_str()), Role::tool(""));
+    let mut session = Session::new();
+    session.add_with_default_path(role, "42").unwrap();
+    let end_path = session.default_path.clone();
+    let messages = serde_json::to_value(session.assemble_context(&end_path, &Role::Assistant).unwrap()).unwrap();
+    assert_eq!(messages[0], json!({"role": "tool", "content": "42", "tool_call_id": "call_9"}));
+
+    // OpenAI 格式的工具对话导入后再组装，得到相同的消息
+    // A tool conversation in OpenAI format assembles into the same messages after importing it
+    let conversation = json!([
+        {"role": "system", "content": "be helpful"},
+        {"role": "user", "content": "weather in Paris?"},
+        {"role": "assistant", "content": null, "tool_calls": [
+            {"id": "call_1", "type": "function", "function": {"name": "weather", "arguments": "{\"city\":\"Paris\"}"}}
+        ]},
+        {"role": "tool", "content": "18°C", "tool_call_id": "call_1"},
+        {"role": "assistant", "content": "It is 18°C."}
+    ]);
+    let session = Session::from_api_messages(conversation.as_array().unwrap()).unwrap();
+    let end_path = session.default_path.clone();
+    let assembled = serde_json::to_value(session.assemble_context(&end_path, &Role::Assistant).unwrap()).unwrap();
+    format_test_block("tool_role", || serde_json::to_string_pretty(&assembled).unwrap());
+    assert_eq!(assembled, conversation);
+    let restored: Session = serde_json::from_str(&serde_json::to_string(&session).unwrap()).unwrap();
+    assert_eq!(restored, session);
+
+    let missing_id = json!([{"role": "tool", "content": "18°C"}]);
+    assert!(Session::from_api_messages(missing_id.as_array().unwrap()).is_err());
+}
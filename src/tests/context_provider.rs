@@ -0,0 +1,97 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use serde::Serialize;
+use serde_json::json;
+
+use crate::chat::chat_base::ChatError;
+use crate::chat::chat_multi::MultiChat;
+use crate::chat::chat_single::SingleChat;
+use crate::chat::context_provider::TypedContext;
+use crate::chat::pii::PiiScrubber;
+use crate::testing::MockProvider;
+use crate::tests::format_test_block;
+
+#[derive(Serialize)]
+struct AppState {
+    user: String,
+    beta: bool,
+}
+
+static BETA: AtomicBool = AtomicBool::new(false);
+
+pub async fn test_context_provider() {
+    let mock = MockProvider::start("context-api").await;
+    let state = TypedContext::new(|| AppState {
+        user: "Alice".to_string(),
+        beta: BETA.load(Ordering::SeqCst),
+    });
+    let mut chat = SingleChat::builder()
+        .api("context-api")
+        .system("当前用户：{{ user }}{% if beta %}，已开启测试功能{% endif %}")
+        .context_provider(Arc::new(state))
+        .build()
+        .unwrap();
+
+    // 每次请求前重新查询状态，会话中保留模板
+    // State is queried again before every request and the session keeps the template
+    mock.reply("Hi Alice.");
+    chat.get_answer("Hello").await.unwrap();
+    mock.last_request().contains("当前用户：Alice").not_contains("已开启测试功能").not_contains("{{");
+    BETA.store(true, Ordering::SeqCst);
+    mock.reply("Beta is on.");
+    chat.get_answer("Anything new?").await.unwrap();
+    mock.last_request().contains("当前用户：Alice，已开启测试功能");
+    let system = chat.base.session.message_roots[0].content.to_string();
+    assert!(system.contains("{{ user }}"));
+
+    // 没有引用提供的变量的提示词原样发送，字面的 `{{` 与 `{%` 不会导致请求失败
+    // Prompts referring to no provided variable are sent as they are, a literal `{{` or `{%` does not fail them
+    let literal = "Fill in {{ name }} and keep {% as is";
+    let mut chat = SingleChat::builder()
+        .api("context-api")
+        .system(literal)
+        .context_provider(Arc::new(TypedContext::new(|| AppState { user: "Bob".to_string(), beta: false })))
+        .build()
+        .unwrap();
+    let body = chat.get_req_body_with_new_question(&[0], "Hi").await.unwrap();
+    assert_eq!(body["messages"][0]["content"], literal);
+
+    // 变量中的敏感信息在渲染前清洗，未发送的请求体同样渲染
+    // Sensitive data in the variables is scrubbed before rendering, bodies built without sending are rendered too
+    let mut chat = SingleChat::builder()
+        .api("context-api")
+        .system("Contact: {{ contact.email }}")
+        .pii_scrubber(PiiScrubber::new())
+        .context_provider(Arc::new(TypedContext::new(|| json!({"contact": {"email": "alice@example.com"}}))))
+        .build()
+        .unwrap();
+    let body = chat.get_req_body_with_new_question(&[0], "Hi").await.unwrap();
+    let system = body["messages"][0]["content"].as_str().unwrap();
+    assert!(system.starts_with("Contact: <") && !system.contains("alice@example.com"), "{}", system);
+    let body = chat.get_req_body_again(&[0, 0]).await.unwrap();
+    assert!(!body.to_string().contains("alice@example.com"));
+
+    // 多角色对话同样渲染
+    // Multi-character chats are rendered as well
+    let characters = [("guide".to_string(), "You are a guide.".to_string())].into();
+    let mut multi = MultiChat::new_with_api_name("context-api", characters, false).unwrap();
+    multi.add_system_message("当前用户：{{ user }}").unwrap();
+    multi.add_context_provider(Arc::new(TypedContext::new(|| AppState { user: "Carol".to_string(), beta: false })));
+    multi.set_character("guide").unwrap();
+    let body = multi.get_req_body("Hi").await.unwrap();
+    assert!(body.to_string().contains("当前用户：Carol"));
+
+    // 引用了提供的变量的模板又引用不存在的变量时请求失败
+    // A template referring to a provided variable and to a missing one fails the request
+    let mut broken = SingleChat::builder()
+        .api("context-api")
+        .system("{{ user }}，现在是 {{ time }}")
+        .context_provider(Arc::new(TypedContext::new(|| AppState { user: "Bob".to_string(), beta: false })))
+        .build()
+        .unwrap();
+    let report = broken.get_answer("Hi").await.unwrap_err();
+    assert!(matches!(report.current_context(), ChatError::ContextError(_)));
+    assert_eq!(mock.pending(), 0);
+    format_test_block("context provider", || mock.last_request().prompt());
+}
@@ -0,0 +1,61 @@
+use crate::chat::chat_base::CONTINUATION_PROMPT;
+use crate::chat::chat_single::SingleChat;
+use futures::StreamExt;
+use crate::testing::MockProvider;
+use crate::tests::format_test_block;
+
+pub async fn test_continuation() {
+    let mock = MockProvider::start("continuation-api").await;
+
+    // 默认不续写
+    // Not continued by default
+    mock.reply_truncated("Cut off in the");
+    let mut chat = SingleChat::builder().api("continuation-api").build().unwrap();
+    assert_eq!(chat.get_answer("hi").await.unwrap(), "Cut off in the");
+    let sent = mock.requests().len();
+
+    // 续写从行首重复的内容被去除，元数据合计两段的用量
+    // The continuation repeating from a line start is removed and the metadata sums up both parts
+    mock.reply_truncated("The quick brown fox\njumps over").reply("jumps over the lazy dog.");
+    let mut chat = SingleChat::builder().api("continuation-api").max_continuations(2).build().unwrap();
+    let answer = chat.get_answer("Tell me the pangram").await.unwrap();
+    assert_eq!(answer, "The quick brown fox\njumps over the lazy dog.");
+    assert_eq!(mock.requests().len(), sent + 2);
+    mock.last_request()
+        .contains("jumps over")
+        .contains(CONTINUATION_PROMPT);
+    let message = chat.base.session.last_message_mut().unwrap().clone();
+    assert_eq!(message.content.as_text(), Some(answer.as_str()));
+    let metadata = message.metadata.unwrap();
+    assert_eq!(metadata.continuations, 1);
+    assert_eq!(metadata.completion_tokens, Some(2));
+
+    // 流式请求同样续写，达到次数上限后返回已有的内容
+    // Streaming requests are continued as well, returning what arrived once the limit is reached
+    mock.reply_truncated("one two").reply_truncated(" three");
+    let mut chat = SingleChat::builder().api("continuation-api").stream(true).max_continuations(1).build().unwrap();
+    let streamed = chat.get_answer("count").await.unwrap();
+    assert_eq!(streamed, "one two three");
+    assert_eq!(mock.pending(), 0);
+
+    // 行内恰好相同的文字是正常的重复，予以保留
+    // Text that merely matches within a line is a legitimate repeat and is kept
+    mock.reply_truncated("Echo: knock knock").reply(" knock knock, who is there?");
+    let mut chat = SingleChat::builder().api("continuation-api").max_continuations(1).build().unwrap();
+    let repeated = chat.get_answer("echo").await.unwrap();
+    assert_eq!(repeated, "Echo: knock knock knock knock, who is there?");
+
+    // 逐段产出的流式回答同样续写，去除重叠后接着产出，写入会话的回答与产出的增量一致
+    // Answers streamed piece by piece are continued as well, the part goes on without its overlap and the answer
+    // written to the session matches the yielded deltas
+    mock.reply_truncated("Line one\nLine tw").reply("Line two\nLine three");
+    let mut chat = SingleChat::builder().api("continuation-api").max_continuations(1).build().unwrap();
+    let deltas: Vec<String> = chat.stream_answer("lines").await.unwrap().map(Result::unwrap).collect().await;
+    assert_eq!(deltas.concat(), "Line one\nLine two\nLine three");
+    let message = chat.base.session.last_message_mut().unwrap().clone();
+    assert_eq!(message.content.as_text(), Some(deltas.concat().as_str()));
+    assert_eq!(message.metadata.unwrap().continuations, 1);
+    mock.last_request().contains("Line tw").contains(CONTINUATION_PROMPT);
+    assert_eq!(mock.pending(), 0);
+    format_test_block("Continuation", || format!("{}\n{}\n{}\n{:?}", answer, streamed, repeated, metadata));
+}
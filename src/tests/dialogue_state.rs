@@ -0,0 +1,96 @@
+use rhine_schema_derive::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::chat::chat_single::SingleChat;
+use crate::chat::dialogue_state::DialogueState;
+use crate::config::Config;
+use crate::config::metadata::ModelMetadata;
+use crate::config::prompts::PromptKey;
+use crate::schema::json_schema::JsonSchema;
+use crate::testing::MockProvider;
+use crate::tests::format_test_block;
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[schema(name = "booking", description = "A table booking")]
+struct Booking {
+    #[schema(desc = "Guest name")]
+    name: Option<String>,
+
+    #[schema(desc = "Party size")]
+    guests: Option<u32>,
+
+    #[schema(desc = "Date as YYYY-MM-DD")]
+    date: Option<String>,
+}
+
+pub async fn test_dialogue_state() {
+    // 受语法约束的回答直接解析，不再发出转换请求
+    // Grammar-constrained answers are parsed directly, without a conversion request
+    let mock = MockProvider::start("dialogue-api").await;
+    Config::set_model_metadata("mock-model", ModelMetadata {
+        supports_grammar: true,
+        ..Default::default()
+    });
+    let chat = SingleChat::builder().api("dialogue-api").system("You take table bookings.").build().unwrap();
+    let mut booking = DialogueState::new(chat, Booking::default()).extract_with("dialogue-api");
+    assert_eq!(booking.missing(), ["date", "guests", "name"]);
+
+    // 每轮先提取状态，再带着状态与缺少的字段提问
+    // Every turn extracts the state first, then asks with the state and the missing fields attached
+    mock.reply_json(json!({"name": "Ada", "guests": 4, "date": null}));
+    mock.reply("For which date?");
+    assert_eq!(booking.turn("A table for 4, the name is Ada.").await.unwrap(), "For which date?");
+    assert_eq!(booking.missing(), ["date"]);
+    mock.request(0)
+        .contains("当前状态：{\"name\":null,\"guests\":null,\"date\":null}")
+        .contains("用户：A table for 4, the name is Ada.")
+        .not_contains("You take table bookings.");
+    mock.request(1).contains("\"guests\": 4").contains("尚未填写：date").contains("You take table bookings.");
+
+    mock.reply_json(json!({"name": "Ada", "guests": 4, "date": "2026-10-20"}));
+    mock.reply("Booked for Tuesday.");
+    booking.turn("Next Tuesday, the 20th.").await.unwrap();
+    mock.request(2).contains("助手上一句：For which date?");
+    mock.request(3).contains("\"date\": \"2026-10-20\"").not_contains("尚未填写");
+    assert!(booking.is_complete());
+    assert_eq!(booking.state().guests, Some(4));
+
+    // 状态不写入会话历史，提取失败时状态不变
+    // The state stays out of the session history, and a failed extraction leaves it unchanged
+    let end_path = booking.chat().base.session.default_path.clone();
+    assert_eq!(end_path.len(), 5);
+    mock.reply("not json");
+    assert!(booking.update("Actually make it 5.").await.is_err());
+    assert_eq!(booking.state().guests, Some(4));
+    booking.set_state(Booking::default());
+    assert!(!booking.is_complete());
+    assert_eq!(mock.requests().len(), 5);
+
+    // 提取指令、材料与状态说明都可以按提示词键替换
+    // The extraction instruction, the material and the state notice can all be replaced by prompt key
+    let prompts = [
+        (PromptKey::DialogueStateExtraction, "Keep the booking state up to date."),
+        (PromptKey::DialogueStateMaterial, "State: {}\n{}User: {}"),
+        (PromptKey::DialogueStateLastReply, "Assistant: {}\n"),
+        (PromptKey::DialogueStateNotice, "Booking so far:\n{}"),
+        (PromptKey::DialogueStateMissing, "\nStill missing: {}."),
+    ];
+    for (key, prompt) in prompts {
+        Config::set_capability_prompt(key, prompt);
+    }
+    mock.reply_json(json!({"name": "Bo", "guests": null, "date": null}));
+    mock.reply("How many guests?");
+    booking.turn("For Bo.").await.unwrap();
+    mock.request(5)
+        .contains("Keep the booking state up to date.")
+        .contains("Assistant: Booked for Tuesday.\nUser: For Bo.")
+        .not_contains("当前状态");
+    mock.request(6).contains("Booking so far:\n").contains("Still missing: date, guests.").not_contains("尚未填写");
+    for (key, _) in prompts {
+        Config::remove_capability_prompt(&key);
+    }
+    Config::set_model_metadata("mock-model", ModelMetadata::default());
+
+    format_test_block("dialogue_state", || format!("{:?}", mock.request(3).prompt()));
+}
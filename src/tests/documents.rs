@@ -0,0 +1,82 @@
+use crate::chat::chat_single::SingleChat;
+use crate::documents::{ChunkIndex, Chunker, Document, ingest};
+use crate::testing::MockProvider;
+use crate::tests::format_test_block;
+
+pub async fn test_documents() {
+    test_chunking();
+    test_document_context().await;
+    #[cfg(feature = "documents")]
+    test_docx();
+
+    let path = std::env::temp_dir().join("rhine_test_document.md");
+    std::fs::write(&path, "# Notes\n\nPlain text is one page.").unwrap();
+    let document = Document::load(&path).unwrap();
+    assert_eq!(document.source, "rhine_test_document.md");
+    assert_eq!(document.pages.len(), 1);
+}
+
+fn test_chunking() {
+    let document = Document::from_pages(
+        "manual.pdf",
+        vec![
+            "First paragraph about setup.\n\nSecond paragraph about wiring. It is long enough to split.".to_string(),
+            "Short second page.".to_string(),
+            "   ".to_string(),
+        ],
+    );
+    let chunks = Chunker::new(40).overlap(5).chunk(&document);
+    format_test_block("document_chunks", || format!("{:#?}", chunks));
+
+    assert!(chunks.iter().all(|chunk| chunk.text.chars().count() <= 40));
+    assert_eq!(chunks[0].text, "First paragraph about setup.");
+    assert_eq!(chunks.last().unwrap().page, 2);
+    assert_eq!(chunks.last().unwrap().citation(), "manual.pdf, p. 2");
+    assert!(chunks.iter().enumerate().all(|(index, chunk)| chunk.index == index));
+    assert_eq!(Chunker::default().chunk(&document).len(), 2);
+}
+
+async fn test_document_context() {
+    let document = Document::from_pages(
+        "handbook.pdf",
+        vec!["Vacation requests go to HR.".to_string(), "Expense reports are due monthly.".to_string()],
+    );
+
+    // 直接加入上下文
+    // Added to the context directly
+    let mock = MockProvider::start("documents-api").await;
+    mock.reply("Monthly.").reply("Monthly, see handbook.pdf, p. 2.");
+    let mut chat = SingleChat::builder().api("documents-api").build().unwrap();
+    assert_eq!(chat.add_document(&document, &Chunker::default()).await.unwrap(), 2);
+    chat.get_answer("When are expense reports due?").await.unwrap();
+    mock.last_request().contains("[handbook.pdf, p. 1]\nVacation requests").contains("[handbook.pdf, p. 2]");
+
+    // 经由检索接入，只发送相关片段
+    // Through retrieval ingestion, only the relevant chunk is sent
+    let index = ChunkIndex::new(1);
+    assert_eq!(ingest(&document, &Chunker::default(), &index).await.unwrap(), 2);
+    assert_eq!(index.search("expense reports")[0].page, 2);
+    let mut chat = SingleChat::builder().api("documents-api").retriever(index).build().unwrap();
+    chat.get_answer("When are expense reports due?").await.unwrap();
+    mock.last_request().contains("[handbook.pdf, p. 2]\nExpense reports").not_contains("Vacation");
+}
+
+#[cfg(feature = "documents")]
+fn test_docx() {
+    use std::io::Write;
+
+    let xml = concat!(
+        r#"<w:document><w:body><w:p><w:r><w:t>Intro &amp; scope</w:t></w:r></w:p>"#,
+        r#"<w:p><w:r><w:t xml:space="preserve">Ends here</w:t><w:br w:type="page"/></w:r></w:p>"#,
+        r#"<w:p><w:r><w:lastRenderedPageBreak/><w:t>Second</w:t><w:tab/><w:t>page</w:t></w:r></w:p></w:body></w:document>"#,
+    );
+    let mut bytes = std::io::Cursor::new(Vec::new());
+    let mut writer = zip::ZipWriter::new(&mut bytes);
+    writer.start_file("word/document.xml", zip::write::SimpleFileOptions::default()).unwrap();
+    writer.write_all(xml.as_bytes()).unwrap();
+    writer.finish().unwrap();
+
+    let document = Document::from_docx("spec.docx", bytes.get_ref()).unwrap();
+    assert_eq!(document.pages, vec!["Intro & scope\nEnds here", "Second\tpage"]);
+    assert!(Document::from_pdf("broken.pdf", b"not a pdf").is_err());
+}
@@ -0,0 +1,180 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use serde_json::json;
+
+use crate::chat::chat_single::SingleChat;
+use crate::config::{Config, ModelCapability};
+use crate::eval::embedding_cache::{CachedEmbedder, DirectoryEmbeddingCache, content_hash};
+use crate::eval::scorer::{Embedder, FnEmbedder, cosine};
+use crate::eval::{
+    EmbeddingSimilarity, EvalCase, EvalDataset, EvalRunner, ExactMatch, JsonSchemaValid, JudgeRubric, ReplayRunner,
+    Scorer,
+};
+use crate::testing::MockProvider;
+use crate::tests::format_test_block;
+
+pub async fn test_eval() {
+    test_eval_scorers().await;
+    test_eval_runner().await;
+    test_embedding_cache().await;
+    test_replay().await;
+}
+
+async fn test_eval_scorers() {
+    let schema = json!({
+        "name": "City",
+        "schema": {
+            "type": "object",
+            "properties": {"city": {"type": "string"}, "size": {"type": "string", "enum": ["small", "large"]}},
+            "required": ["city"],
+            "additionalProperties": false,
+        },
+    });
+    let case = EvalCase::new("json", "city?").schema(schema);
+    let score = |output: &'static str| {
+        let case = case.clone();
+        async move { JsonSchemaValid.score(&case, output).await.unwrap().unwrap() }
+    };
+    assert!(score("```json\n{\"city\": \"Paris\", \"size\": \"large\"}\n```").await.passed);
+    assert_eq!(score(r#"{"size": "large"}"#).await.detail.as_deref(), Some(r#"$ is missing required property "city""#));
+    assert_eq!(score(r#"{"city": "Paris", "size": "huge"}"#).await.detail.as_deref(), Some(r#"$.size is not one of ["small","large"]"#));
+    assert!(!score(r#"{"city": "Paris", "mayor": "x"}"#).await.passed);
+    assert!(!score("Paris").await.passed);
+    assert!(ExactMatch::new().score(&case, "Paris").await.unwrap().is_none());
+
+    let case = EvalCase::new("exact", "capital?").expected("Paris");
+    assert!(ExactMatch::new().score(&case, " Paris\n").await.unwrap().unwrap().passed);
+    assert!(!ExactMatch::new().score(&case, "paris").await.unwrap().unwrap().passed);
+    assert!(ExactMatch::new().case_insensitive().score(&case, "paris").await.unwrap().unwrap().passed);
+
+    assert!((cosine(&[1.0, 0.0], &[2.0, 0.0]) - 1.0).abs() < 1e-9);
+    assert_eq!(cosine(&[1.0, 0.0], &[0.0, 1.0]), 0.0);
+    assert_eq!(cosine(&[0.0, 0.0], &[1.0, 1.0]), 0.0);
+
+    let path = std::env::temp_dir().join("rhine_test_eval.jsonl");
+    std::fs::write(
+        &path,
+        "{\"id\": \"a\", \"input\": \"hi\", \"tags\": [\"smoke\"]}\n\n{\"id\": \"b\", \"input\": \"bye\", \"expected\": \"bye\"}\n",
+    )
+    .unwrap();
+    let dataset = EvalDataset::from_jsonl(&path).unwrap();
+    assert_eq!(dataset.name, "rhine_test_eval");
+    assert_eq!(dataset.cases.len(), 2);
+    assert_eq!(dataset.cases[1].expected.as_deref(), Some("bye"));
+    assert_eq!(dataset.filter_tag("smoke").cases.len(), 1);
+}
+
+async fn test_eval_runner() {
+    let target = MockProvider::start("eval-api").await;
+    let judge = MockProvider::start("eval-judge-api").await;
+    // 两个配置依次运行，每个配置内按用例顺序请求
+    // The two configurations run one after another, requesting in case order within each
+    target
+        .reply("Paris")
+        .reply(r#"{"city": "Paris"}"#)
+        .reply("Photosynthesis turns light into chemical energy.")
+        .reply("Lyon")
+        .reply(r#"{"city": 1}"#)
+        .reply("Plants eat sunlight.");
+    judge.reply(r#"{"score": 9, "reason": "accurate"}"#).reply("```json\n{\"score\": 3, \"reason\": \"vague\"}\n```");
+
+    let dataset = EvalDataset::new("geo")
+        .case(EvalCase::new("capital", "Capital of France?").expected("Paris"))
+        .case(EvalCase::new("json", "City as JSON").schema(json!({"type": "object", "required": ["city"], "properties": {"city": {"type": "string"}}})))
+        .case(EvalCase::new("explain", "Explain photosynthesis").rubric("Mentions energy conversion"));
+
+    // 以字母频率作为向量，只为演示相似度评分
+    // Letter frequencies as the vector, only to exercise similarity scoring
+    let letters = |text: &str| {
+        let mut vector = vec![0.0f32; 26];
+        for c in text.to_ascii_lowercase().bytes().filter(u8::is_ascii_lowercase) {
+            vector[(c - b'a') as usize] += 1.0;
+        }
+        vector
+    };
+
+    let report = EvalRunner::new(dataset)
+        .target("baseline", || SingleChat::builder().api("eval-api").system("Be brief.").build())
+        .target("candidate", || SingleChat::builder().api("eval-api").system("Be creative.").build())
+        .scorer(ExactMatch::new())
+        .scorer(JsonSchemaValid)
+        .scorer(EmbeddingSimilarity::new(FnEmbedder::new("letters", letters)).threshold(0.99))
+        .scorer(JudgeRubric::new("eval-judge-api"))
+        .concurrency(1)
+        .run()
+        .await;
+
+    format_test_block("eval", || report.to_markdown());
+    let baseline = report.target("baseline").unwrap();
+    assert_eq!(baseline.pass_rate(), 1.0);
+    assert_eq!(baseline.results[2].scores["judge"].value, 0.9);
+    let candidate = report.target("candidate").unwrap();
+    assert!(candidate.results.iter().all(|result| !result.passed()));
+    assert_eq!(candidate.summary()["judge"].mean, 0.3);
+    assert_eq!(candidate.summary()["exact_match"].passed, 0);
+    assert_eq!(report.best().unwrap().target, "baseline");
+    judge.request(0).contains("Mentions energy conversion").contains("Photosynthesis turns light");
+    assert_eq!(target.pending() + judge.pending(), 0);
+}
+
+async fn test_embedding_cache() {
+    let dir = std::env::temp_dir().join("rhine_test_embedding_cache");
+    let _ = std::fs::remove_dir_all(&dir);
+    let calls = Arc::new(AtomicUsize::new(0));
+    let counted = calls.clone();
+    let embed = FnEmbedder::new("length", move |text: &str| {
+        counted.fetch_add(1, Ordering::SeqCst);
+        vec![text.len() as f32, 0.5]
+    });
+
+    let embedder = CachedEmbedder::new(embed.clone(), DirectoryEmbeddingCache::new(&dir).unwrap());
+    for text in ["chunk one", "chunk two", "chunk one"] {
+        embedder.embed(text).await.unwrap();
+    }
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+    assert_eq!(embedder.stats().hits, 1);
+
+    // 缓存写入磁盘，新的实例仍可命中
+    // The cache is on disk, a new instance still hits it
+    let reopened = CachedEmbedder::new(embed, DirectoryEmbeddingCache::new(&dir).unwrap());
+    assert_eq!(reopened.embed("chunk two").await.unwrap(), vec![9.0, 0.5]);
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+    format_test_block("Embedding Cache", || format!("{:?} {}", reopened.stats(), content_hash("chunk two")));
+    assert_eq!(reopened.stats().hit_rate(), 1.0);
+    assert_eq!(content_hash("").len(), 64);
+}
+
+async fn test_replay() {
+    let mock = MockProvider::start("replay-api").await;
+    Config::add_api_info("replay-new", "new-model", ModelCapability::Think, "replay-api-source", "sk-mock").unwrap();
+    Config::set_api_weight("replay-new", 0);
+
+    mock.reply("Paris.").reply("Berlin.");
+    let mut original = SingleChat::builder().api("replay-api").system("Old prompt").build().unwrap();
+    original.get_answer("Capital of France?").await.unwrap();
+    original.get_answer("And Germany?").await.unwrap();
+    let session = serde_json::from_str(&serde_json::to_string(&original.base.session).unwrap()).unwrap();
+
+    // 每个轮次以原回答为历史重新提问，与原回答并排比较
+    // Every turn is asked again with the original answers as history and compared side by side
+    mock.reply("Paris.").reply("Berlin, Germany.");
+    let report = ReplayRunner::new("new-model", || SingleChat::builder().api("replay-new").system("New prompt").build())
+        .scorer(ExactMatch::new())
+        .replay(&session)
+        .await;
+    format_test_block("Replay Report", || report.to_markdown());
+    assert_eq!(report.turns.len(), 2);
+    assert_eq!((report.changed(), report.errors()), (1, 0));
+    assert!(report.turns[0].scores["exact_match"].passed);
+    assert!(!report.turns[1].scores["exact_match"].passed);
+    assert_eq!(report.turns[1].original_model.as_deref(), Some("mock-model"));
+    assert_eq!(report.turns[1].replayed_model.as_deref(), Some("new-model"));
+    mock.last_request()
+        .contains("system: New prompt")
+        .not_contains("Old prompt")
+        .contains("assistant: Paris.")
+        .message_count(4);
+    let row = "| 1 | And Germany? | Berlin. (mock-model) | Berlin, Germany. (new-model) |";
+    assert!(report.to_markdown().contains(row));
+}
@@ -0,0 +1,81 @@
+use std::sync::{Arc, Mutex};
+
+use bytes::Bytes;
+use futures::stream;
+use tokio::sync::Semaphore;
+
+use crate::chat::chat_base::BaseChat;
+use crate::chat::chat_single::SingleChat;
+use crate::chat::event::{ChatEvent, EventBus, EventSource};
+use crate::config::{Config, ModelCapability};
+use crate::tests::format_test_block;
+
+pub async fn test_event() {
+    test_stream_deltas().await;
+    test_chat_events().await;
+    test_event_bus().await;
+}
+
+async fn test_stream_deltas() {
+    let chunks: Vec<reqwest::Result<Bytes>> = vec![
+        Ok(Bytes::from("data: {\"choices\":[{\"delta\":{\"content\":\"a\"}}]}\n\n")),
+        Ok(Bytes::from("data: {\"choices\":[{\"delta\":{\"content\":\"b\"}}]}\n\ndata: [DONE]\n\n")),
+    ];
+    let permit = Arc::new(Semaphore::new(1)).acquire_owned().await.unwrap();
+
+    let mut deltas = Vec::new();
+    let output = BaseChat::collect_stream_with(stream::iter(chunks), permit, |delta| deltas.push(delta.to_string()))
+        .await
+        .unwrap();
+
+    assert_eq!(deltas, vec!["a", "b"]);
+    assert_eq!(output.content, "ab");
+}
+
+async fn test_chat_events() {
+    Config::add_api_source("event-source", "http://127.0.0.1:9/v1/chat/completions", 1);
+    Config::add_api_info("event-api", "event-model", ModelCapability::LongContext, "event-source", "sk-event")
+        .unwrap();
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let recorded = events.clone();
+    let mut chat = SingleChat::new_with_api_name("event-api", "", false);
+    chat.on_event(move |event| recorded.lock().unwrap().push(event.clone()));
+
+    let body = chat.get_req_body("hi").await.unwrap();
+    assert!(chat.get_content_from_req_body(body).await.is_err());
+
+    let events = events.lock().unwrap();
+    format_test_block("Chat Events", || format!("{:#?}", events));
+    assert!(matches!(&events[0], ChatEvent::RequestStarted { model, message_count: 1, .. } if model == "event-model"));
+    assert!(matches!(&events[1], ChatEvent::RequestDispatched { .. }));
+    assert!(matches!(&events[2], ChatEvent::Error { .. }));
+    assert!(matches!(&events[3], ChatEvent::GenerationFinished { error: Some(_), .. }));
+}
+
+async fn test_event_bus() {
+    Config::add_api_source("bus-source", "http://127.0.0.1:9/v1/chat/completions", 1);
+    Config::add_api_info("bus-api", "bus-model", ModelCapability::LongContext, "bus-source", "sk-bus").unwrap();
+
+    // 对话没有注册处理函数，事件仍发布到总线
+    // The chat has no handlers, its events still reach the bus
+    let mut events = EventBus::subscribe();
+    let mut chat = SingleChat::new_with_api_name("bus-api", "", false);
+    let body = chat.get_req_body("hi").await.unwrap();
+    assert!(chat.get_content_from_req_body(body).await.is_err());
+    EventBus::publish(EventSource::Custom("monitor".to_string()), ChatEvent::TaskSkipped { task: "report".to_string() });
+
+    let source = EventSource::Chat {
+        session_id: chat.base.session_id.clone(),
+    };
+    let mut received = Vec::new();
+    while let Ok(event) = events.try_recv() {
+        received.push(event);
+    }
+    format_test_block("Event Bus", || format!("{:#?}", received));
+    let from_chat: Vec<_> = received.iter().filter(|event| event.source == source).collect();
+    assert!(matches!(&from_chat[0].event, ChatEvent::RequestStarted { model, .. } if model == "bus-model"));
+    assert!(matches!(&from_chat[2].event, ChatEvent::Error { .. }));
+    assert!(received.iter().any(|event| event.source == EventSource::Custom("monitor".to_string())));
+    assert!(EventBus::subscriber_count() >= 1);
+}
@@ -0,0 +1,75 @@
+use crate::chat::content::Content;
+use crate::chat::export::DatasetExporter;
+use crate::chat::message::{Role, Session};
+use crate::tests::format_test_block;
+
+pub async fn test_export() {
+    test_openai();
+    test_sharegpt();
+    test_filter();
+}
+
+fn weather_session() -> Session {
+    let mut session = Session::new();
+    session.add_with_default_path(Role::System, "You are a weather bot.").unwrap();
+    session.add_with_default_path(Role::User, "Weather in Paris?").unwrap();
+    session
+        .add_with_default_path(Role::Assistant, Content::tool_call("call_1", "weather", r#"{"city":"Paris"}"#))
+        .unwrap();
+    session.add_with_default_path(Role::Assistant, Content::tool_result("call_1", "18°C")).unwrap();
+    session.add_with_default_path(Role::Assistant, "It is 18°C in Paris.").unwrap();
+    session
+}
+
+fn test_openai() {
+    let tools = vec![serde_json::json!({"type": "function", "function": {"name": "weather"}})];
+    let exporter = DatasetExporter::openai().map_role("system", "developer").tools(tools.clone());
+    let line = exporter.export(&weather_session()).unwrap();
+    format_test_block("OpenAI Export", || line.to_string());
+
+    let messages = line["messages"].as_array().unwrap();
+    assert_eq!(messages.len(), 5);
+    assert_eq!(messages[0]["role"], "developer");
+    assert_eq!(messages[2]["tool_calls"][0]["function"]["name"], "weather");
+    assert_eq!(messages[3]["role"], "tool");
+    assert_eq!(messages[3]["tool_call_id"], "call_1");
+    assert_eq!(line["tools"], serde_json::json!(tools));
+
+    // 空会话不导出
+    // Empty sessions are not exported
+    assert_eq!(exporter.to_jsonl([&weather_session(), &Session::new()]).lines().count(), 1);
+}
+
+fn test_sharegpt() {
+    let line = DatasetExporter::sharegpt().export(&weather_session()).unwrap();
+    format_test_block("ShareGPT Export", || line.to_string());
+
+    let turns = line["conversations"].as_array().unwrap();
+    let from: Vec<&str> = turns.iter().map(|turn| turn["from"].as_str().unwrap()).collect();
+    assert_eq!(from, ["system", "human", "function_call", "observation", "gpt"]);
+    let call: serde_json::Value = serde_json::from_str(turns[2]["value"].as_str().unwrap()).unwrap();
+    assert_eq!(call, serde_json::json!({"name": "weather", "arguments": {"city": "Paris"}}));
+    assert_eq!(turns[3]["value"], "18°C");
+
+    // 多角色会话按发言者区分 gpt 与 human
+    // Multi-character sessions split gpt and human turns by the speaker
+    let mut session = Session::new();
+    session.add_with_default_path(Role::Character("Alice".to_string()), "Hi Bob.").unwrap();
+    session.add_with_default_path(Role::Character("Bob".to_string()), "Hi Alice.").unwrap();
+    let line = DatasetExporter::sharegpt().speaker(Role::Character("Bob".to_string())).export(&session).unwrap();
+    assert_eq!(line["conversations"][0], serde_json::json!({"from": "human", "value": "Alice said: Hi Bob."}));
+    assert_eq!(line["conversations"][1], serde_json::json!({"from": "gpt", "value": "Hi Alice."}));
+}
+
+fn test_filter() {
+    let mut short = Session::new();
+    short.add_with_default_path(Role::User, "Hi").unwrap();
+    let exporter = DatasetExporter::openai()
+        .filter(|conversation| conversation.iter().any(|message| message.role == Role::Assistant));
+
+    let path = std::env::temp_dir().join("rhine_test_export.jsonl");
+    assert_eq!(exporter.write_jsonl(&path, [&weather_session(), &short]).unwrap(), 1);
+    let content = std::fs::read_to_string(&path).unwrap();
+    assert!(content.contains("It is 18°C in Paris."));
+    let _ = std::fs::remove_file(&path);
+}
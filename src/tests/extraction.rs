@@ -0,0 +1,70 @@
+use rhine_schema_derive::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::chat::chat_single::SingleChat;
+use crate::chat::extraction::DocumentExtraction;
+use crate::config::Config;
+use crate::config::prompts::PromptKey;
+use crate::documents::Chunker;
+use crate::schema::json_schema::JsonSchema;
+use crate::testing::MockProvider;
+use crate::tests::format_test_block;
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[schema(name = "invoice", description = "An invoice mentioned in the document", strict = true)]
+struct Invoice {
+    #[schema(desc = "Invoice number", required = true)]
+    number: String,
+
+    #[schema(desc = "Total amount")]
+    total: Option<f64>,
+}
+
+pub async fn test_extraction() {
+    let text = "Invoice INV-1 totals 12.5 dollars.\n\n\
+                Invoice INV-2 was issued in May.\n\n\
+                INV-2 totals 40 dollars, see INV-1.\n\n\
+                Nothing else is due this month.";
+    let mock = MockProvider::start("extraction-api").await;
+    let chat = SingleChat::builder().api("extraction-api").build().unwrap();
+
+    // 每个片段各提取一次，重复与被截断的条目在合并时去掉
+    // Every chunk is extracted once, repeated and truncated entries are dropped when merging
+    mock.reply("{\"number\": \"INV-1\", \"total\": 12.5}")
+        .reply("{\"number\": \"INV-2\", \"total\": null}")
+        .reply("{\"number\": \"INV-2\", \"total\": 40}\n{\"number\": \" inv-1\", \"total\": 12.5}")
+        .reply("");
+    let extraction = DocumentExtraction::new().chunker(Chunker::new(40)).concurrency(1);
+    let invoices: Vec<Invoice> = chat.extract_from_document_with(text, &extraction).await.unwrap();
+    assert_eq!(mock.requests().len(), 4);
+    mock.request(1).contains("Invoice INV-2 was issued in May.").not_contains("INV-1 totals");
+    assert_eq!(
+        invoices,
+        [
+            Invoice { number: "INV-1".to_string(), total: Some(12.5) },
+            Invoice { number: "INV-2".to_string(), total: Some(40.0) },
+        ]
+    );
+    assert!(chat.base.session.message_roots.is_empty());
+
+    // 任一片段失败时返回错误
+    // An error is returned if any chunk fails
+    mock.reply("{\"number\": \"INV-9\"}").reply("{\"number\": 9}");
+    let extraction = extraction.chunker(Chunker::new(80));
+    let error = chat.extract_from_document_with::<Invoice>(text, &extraction).await.unwrap_err();
+    format_test_block("extraction", || format!("{:?}\n{:?}", invoices, error));
+    assert_eq!(mock.pending(), 0);
+    mock.request(0).contains("以下是一份长文档中的一个片段。");
+
+    // 默认指令可以按提示词键替换，设置在提取上的指令优先
+    // The default instruction can be replaced by prompt key, an instruction set on the extraction wins
+    Config::set_capability_prompt(PromptKey::DocumentExtraction, "Extract every invoice of this excerpt.");
+    mock.reply("").reply("");
+    chat.extract_from_document_with::<Invoice>(text, &extraction).await.unwrap();
+    mock.last_request().contains("Extract every invoice of this excerpt.\n\n").not_contains("长文档");
+    mock.reply("").reply("");
+    let custom = extraction.instruction("List the invoices.");
+    chat.extract_from_document_with::<Invoice>(text, &custom).await.unwrap();
+    mock.last_request().contains("List the invoices.").not_contains("Extract every invoice");
+    Config::remove_capability_prompt(&PromptKey::DocumentExtraction);
+}
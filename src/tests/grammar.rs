@@ -0,0 +1,83 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use serde_json::json;
+
+use crate::chat::chat_single::SingleChat;
+use crate::config::metadata::ModelMetadata;
+use crate::config::{Config, ModelCapability};
+use crate::schema::gbnf::json_schema_to_gbnf;
+use crate::schema::json_schema::{JsonSchema, response_format};
+use crate::tests::prompt::StudentInfo;
+use crate::tests::{format_test_block, spawn_mock_server};
+
+pub async fn test_grammar() {
+    test_gbnf();
+    test_grammar_chat().await;
+}
+
+fn test_gbnf() {
+    let grammar = json_schema_to_gbnf(&StudentInfo::json_schema()).unwrap();
+    format_test_block("GBNF Grammar", || grammar.clone());
+    assert!(grammar.starts_with("root ::= \"{\" space"));
+    assert!(grammar.contains(r#"( "\"freshman\"" | "\"sophomore\"" | "\"junior\"" | "\"senior\"" | "null" ) space"#));
+    assert!(grammar.contains("integer ::= "));
+
+    let schema = json!({
+        "type": "object",
+        "properties": {
+            "tags": {"type": "array", "items": {"$ref": "#/$defs/tag"}},
+            "note": {"type": ["string", "null"]},
+        },
+        "$defs": {"tag": {"type": "string", "enum": ["a", "b"]}},
+    });
+    let grammar = json_schema_to_gbnf(&schema).unwrap();
+    // 全部属性可选时，允许空对象且逗号只出现在属性之间
+    // With every property optional, the empty object is allowed and commas only go between properties
+    assert!(grammar.lines().next().unwrap().contains(")? \"}\" space"));
+    assert!(grammar.contains(r#"( "\"a\"" | "\"b\"" ) space"#));
+    assert!(grammar.contains("( string | null )"));
+
+    assert!(json_schema_to_gbnf(&json!({"$ref": "#/$defs/missing"})).is_err());
+    assert!(json_schema_to_gbnf(&json!({"type": "tuple"})).is_err());
+
+    // 绑定传入的裸 Schema 包装为 `response_format` 后同样可以转换，已包装的原样保留
+    // Bare schemas handed in by the bindings convert the same once wrapped into `response_format`, wrapped ones are
+    // kept as is
+    let tags = json!({"title": "Tags", "type": "object", "properties": {"note": {"type": "string"}}});
+    let wrapped = response_format(tags);
+    assert_eq!(wrapped["json_schema"]["name"], "Tags");
+    assert_eq!(wrapped["json_schema"]["description"], "Tags");
+    assert_eq!(response_format(wrapped.clone()), wrapped);
+    assert!(json_schema_to_gbnf(&wrapped).unwrap().contains(r#""\"note\"""#));
+}
+
+async fn test_grammar_chat() {
+    let requests = Arc::new(AtomicUsize::new(0));
+    let counter = requests.clone();
+    let url = spawn_mock_server(move |body| {
+        counter.fetch_add(1, Ordering::SeqCst);
+        assert!(body["grammar"].as_str().unwrap().starts_with("root ::= "));
+        let answer = json!({"cot": "-", "name": "Li", "age": 20, "grade": "junior", "had_exam": true});
+        (200, json!({
+            "choices": [{"message": {"role": "assistant", "content": answer.to_string()}, "finish_reason": "stop"}],
+            "usage": {"prompt_tokens": 5, "completion_tokens": 9, "total_tokens": 14},
+        }).to_string())
+    })
+    .await;
+    Config::add_api_source("grammar-source", &url, 2);
+    Config::add_api_info("grammar-api", "grammar-model", ModelCapability::LongContext, "grammar-source", "sk-grammar")
+        .unwrap();
+    Config::set_model_metadata("grammar-model", ModelMetadata {
+        supports_grammar: true,
+        ..Default::default()
+    });
+
+    // 受语法约束的回答直接解析，不再发出转换请求
+    // The grammar-constrained answer is parsed directly, without a conversion request
+    let mut chat = SingleChat::builder().api("grammar-api").build().unwrap();
+    let student = chat.get_json_answer_with_schema("Make up a student", StudentInfo::json_schema()).await.unwrap();
+    assert_eq!(student["name"], "Li");
+    assert_eq!(requests.load(Ordering::SeqCst), 1);
+    Config::set_model_metadata("grammar-model", ModelMetadata::default());
+}
@@ -0,0 +1,131 @@
+use serde_json::json;
+
+use crate::chat::chat_base::ChatError;
+use crate::chat::chat_single::SingleChat;
+use crate::chat::guardrail::{DenyList, Guardrail, GuardrailAction, GuardrailVerdict, Guardrails, MaxLength, RegexRule, UrlAllowlist};
+use crate::config::prompts::PromptKey;
+use crate::config::{Config, ModelCapability};
+use crate::error::ReportExt;
+use crate::schema::json_schema::response_format;
+use crate::testing::MockProvider;
+use crate::tests::{format_test_block, spawn_mock_server};
+
+pub async fn test_guardrail() {
+    test_guardrail_rules();
+    test_guardrails_in_chat().await;
+    test_guardrail_prompts_and_json().await;
+}
+
+fn test_guardrail_rules() {
+    let deny = DenyList::new(["darn"], GuardrailAction::Rewrite);
+    assert_eq!(deny.check("Oh DARN it"), GuardrailVerdict::Rewrite("Oh **** it".to_string()));
+    assert_eq!(DenyList::new(Vec::<String>::new(), GuardrailAction::Block).check("darn"), GuardrailVerdict::Pass);
+
+    let ssn = RegexRule::new(r"\d{3}-\d{2}-\d{4}", GuardrailAction::Rewrite).unwrap().replacement("[SSN]");
+    assert_eq!(ssn.check("id 123-45-6789"), GuardrailVerdict::Rewrite("id [SSN]".to_string()));
+
+    let links = UrlAllowlist::new(["docs.rs"], GuardrailAction::Rewrite);
+    assert_eq!(links.check("see https://api.docs.rs/x"), GuardrailVerdict::Pass);
+    assert_eq!(
+        links.check("see https://docs.rs/a and http://evil.com/b."),
+        GuardrailVerdict::Rewrite("see https://docs.rs/a and [链接已移除].".to_string())
+    );
+    assert!(matches!(
+        UrlAllowlist::new(["docs.rs"], GuardrailAction::Block).check("http://notdocs.rs"),
+        GuardrailVerdict::Block(_)
+    ));
+
+    // 改写依次传递，第一个拒绝终止规则链
+    // Rewrites are passed along, the first rejection ends the chain
+    let chain = Guardrails::new()
+        .with(deny)
+        .with(MaxLength::new(8, GuardrailAction::Rewrite))
+        .with(|answer: &str| match answer.contains('!') {
+            true => GuardrailVerdict::Block("shouting".to_string()),
+            false => GuardrailVerdict::Pass,
+        });
+    assert_eq!(chain.check("darn, that is long"), GuardrailVerdict::Rewrite("****, th".to_string()));
+    assert_eq!(chain.check("ok"), GuardrailVerdict::Pass);
+    assert_eq!(chain.check("no!"), GuardrailVerdict::Block("shouting".to_string()));
+
+    format_test_block("guardrail_rules", || format!("{:?}", chain.check("darn, that is long")));
+}
+
+async fn test_guardrails_in_chat() {
+    // 首次回答带外部链接，收到反馈后改用允许的链接
+    // The first answer has an outside link, after the feedback the answer uses an allowed one
+    let url = spawn_mock_server(|body| {
+        let retried = body["messages"].as_array().unwrap().iter().any(|message| message["role"] == "system");
+        let content = match retried {
+            true => "see https://docs.rs/rhine",
+            false => "see http://evil.com",
+        };
+        (200, json!({
+            "choices": [{"message": {"content": content}, "finish_reason": "stop"}],
+            "usage": {"prompt_tokens": 1, "completion_tokens": 1, "total_tokens": 2},
+        }).to_string())
+    })
+    .await;
+    Config::add_api_source("guardrail-source", &url, 2);
+    Config::add_api_info("guardrail-api", "guardrail-model", ModelCapability::LongContext, "guardrail-source", "sk-guardrail")
+        .unwrap();
+
+    let guardrails = Guardrails::new().with(UrlAllowlist::new(["docs.rs"], GuardrailAction::Regenerate));
+    let mut chat = SingleChat::builder().api("guardrail-api").guardrails(guardrails.clone()).build().unwrap();
+    assert_eq!(chat.get_answer("where are the docs").await.unwrap(), "see https://docs.rs/rhine");
+    assert_eq!(chat.base.usage, 4);
+
+    // 不允许重新生成时直接拒绝，回答不写入会话
+    // Without regenerations the answer is rejected directly and not stored
+    let mut chat = SingleChat::builder()
+        .api("guardrail-api")
+        .guardrails(guardrails.max_regenerations(0))
+        .build()
+        .unwrap();
+    let report = chat.get_answer("where are the docs").await.unwrap_err();
+    assert!(matches!(report.current_context(), ChatError::GuardrailBlocked(_)));
+    assert_eq!(report.code(), "chat.guardrail_blocked");
+    assert_eq!(chat.base.session.default_path.len(), 1);
+}
+
+async fn test_guardrail_prompts_and_json() {
+    // 在独立的配置中运行，JSON 转换只会选到模拟提供商
+    // Run on a configuration of its own, so the JSON conversion only picks the mock provider
+    Config::with_scoped(Config::builder().build().unwrap(), async {
+        let mock = MockProvider::start("guardrail-mock-api").await.with_capability(ModelCapability::ToolUse);
+
+        // 重新生成的反馈与移除链接的文本可以按提示词键替换
+        // The regeneration feedback and the text replacing links can be replaced by prompt key
+        let prompts = [
+            (PromptKey::GuardrailRegeneration, "Your answer was rejected, answer again: {}"),
+            (PromptKey::GuardrailLinkRemoved, "[link removed]"),
+        ];
+        for (key, prompt) in prompts {
+            Config::set_capability_prompt(key, prompt);
+        }
+        let guardrails = Guardrails::new().with(UrlAllowlist::new(["docs.rs"], GuardrailAction::Regenerate));
+        let mut chat = SingleChat::builder().api("guardrail-mock-api").guardrails(guardrails).build().unwrap();
+        mock.reply("see http://evil.com").reply("see https://docs.rs/rhine");
+        assert_eq!(chat.get_answer("where are the docs").await.unwrap(), "see https://docs.rs/rhine");
+        mock.last_request()
+            .contains("Your answer was rejected, answer again: Answer links to a domain outside the allowlist")
+            .not_contains("上一个回答未通过检查");
+        let links = UrlAllowlist::new(["docs.rs"], GuardrailAction::Rewrite);
+        assert_eq!(links.check("see http://evil.com"), GuardrailVerdict::Rewrite("see [link removed]".to_string()));
+        for (key, _) in prompts {
+            Config::remove_capability_prompt(&key);
+        }
+
+        // 由文本回答转换出的JSON同样经过规则链
+        // JSON converted from a text answer goes through the guardrails as well
+        let guardrails = Guardrails::new().with(DenyList::new(["hunter2"], GuardrailAction::Block));
+        let mut chat = SingleChat::builder().api("guardrail-mock-api").guardrails(guardrails).build().unwrap();
+        mock.reply("The account is ready.").reply_json(json!({"password": "hunter2"}));
+        let properties = json!({"password": {"type": "string"}});
+        let schema = response_format(json!({"title": "Account", "type": "object", "properties": properties}));
+        let report = chat.get_json_answer_with_schema("Create an account", schema).await.unwrap_err();
+        assert!(matches!(report.current_context(), ChatError::GuardrailBlocked(_)));
+        assert_eq!(mock.pending(), 0);
+    })
+    .await;
+}
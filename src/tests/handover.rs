@@ -0,0 +1,84 @@
+use serde_json::json;
+
+use crate::chat::chat_single::SingleChat;
+use crate::chat::summary::HandoverBrief;
+use crate::config::Config;
+use crate::config::prompts::PromptKey;
+use crate::config::metadata::ModelMetadata;
+use crate::testing::MockProvider;
+use crate::tests::format_test_block;
+
+pub async fn test_handover() {
+    // 在独立的配置中运行，辅助请求不会落到其他测试注册的廉价模型上
+    // Run on a configuration of its own, so helper requests do not land on cheap models registered by other tests
+    Config::with_scoped(Config::builder().build().unwrap(), async {
+        let mock = MockProvider::start("handover-api").await;
+        Config::set_model_metadata("mock-model", ModelMetadata {
+            supports_grammar: true,
+            ..Default::default()
+        });
+        let mut chat = SingleChat::builder().api("handover-api").system("You are a support agent.").build().unwrap();
+        assert!(chat.handover_brief().await.is_err());
+
+        mock.reply("I have opened ticket #4521 and will refund the duplicate charge.");
+        chat.get_answer("I was charged twice for order 88. Can I get a refund?").await.unwrap();
+
+        // 简报按类型化的结构返回，辅助请求带上对话记录与结构说明，不写入对话历史
+        // The brief comes back in the typed structure, the helper request carries the transcript and the schema,
+        // and the history is untouched
+        mock.reply_json(json!({
+            "goal": "Get a refund for a duplicate charge on order 88",
+            "decisions": ["Refund the duplicate charge"],
+            "open_questions": [],
+            "key_facts": ["Order 88", "Ticket #4521"],
+            "pending_actions": ["Issue the refund"],
+        }));
+        let brief = chat.handover_brief().await.unwrap();
+        assert_eq!(brief, HandoverBrief {
+            goal: "Get a refund for a duplicate charge on order 88".to_string(),
+            decisions: vec!["Refund the duplicate charge".to_string()],
+            open_questions: Vec::new(),
+            key_facts: vec!["Order 88".to_string(), "Ticket #4521".to_string()],
+            pending_actions: vec!["Issue the refund".to_string()],
+        });
+        mock.last_request()
+            .contains("user: I was charged twice for order 88.")
+            .contains("assistant: I have opened ticket #4521")
+            .contains("pending_actions")
+            .not_contains("You are a support agent.");
+        assert_eq!(chat.base.session.default_path.len(), 3);
+
+        let markdown = brief.to_markdown();
+        assert!(markdown.starts_with("## 目标 / Goal\nGet a refund"));
+        assert!(markdown.contains("- Ticket #4521\n"));
+        assert!(!markdown.contains("Open questions"));
+        assert_eq!(mock.pending(), 0);
+
+        // 交接指令、对话记录标签与简报标题可以按提示词键替换
+        // The handover instruction, the transcript label and the brief headings can be replaced by prompt key
+        let prompts = [
+            (PromptKey::HandoverInstruction, "Write a handover brief for the next agent."),
+            (PromptKey::TranscriptMaterial, "Transcript:\n{}"),
+            (PromptKey::HandoverGoalHeading, "Goal"),
+            (PromptKey::HandoverKeyFactsHeading, "Key facts"),
+        ];
+        for (key, prompt) in &prompts {
+            Config::set_capability_prompt(*key, prompt);
+        }
+        mock.reply_json(serde_json::to_value(&brief).unwrap());
+        let english = chat.handover_brief().await.unwrap();
+        mock.last_request()
+            .contains("Write a handover brief for the next agent.")
+            .contains("Transcript:\nuser: I was charged twice")
+            .not_contains("这段对话将移交")
+            .not_contains("对话记录");
+        let english = english.to_markdown();
+        assert!(english.starts_with("## Goal\nGet a refund"));
+        assert!(english.contains("\n## Key facts\n"));
+        for (key, _) in &prompts {
+            Config::remove_capability_prompt(key);
+        }
+        format_test_block("handover", || markdown);
+    })
+    .await;
+}
@@ -0,0 +1,159 @@
+use serde_json::json;
+
+use crate::chat::chat_single::SingleChat;
+use crate::chat::content::Content;
+use crate::chat::history::{HistoryPolicy, ImportanceWeighted, KeepAll, SlidingWindow, SummarizeOld};
+use crate::chat::message::{ApiMessage, Role, Session};
+use crate::config::prompts::PromptKey;
+use crate::config::{Config, ModelCapability};
+use crate::testing::MockProvider;
+use crate::tests::{format_test_block, spawn_mock_server};
+
+pub async fn test_history() {
+    test_history_policies();
+    test_history_policy_in_request().await;
+    test_salience().await;
+}
+
+/// 系统消息加六轮问答，第二轮带工具调用
+/// A system message plus six exchanges, the second one with a tool call
+fn sample_session() -> Session {
+    let mut session = Session::new();
+    session.add_with_default_path(Role::System, "be brief").unwrap();
+    for turn in 0..6 {
+        session.add_with_default_path(Role::User, format!("question {}", turn)).unwrap();
+        if turn == 1 {
+            session.add_with_default_path(Role::Assistant, Content::tool_call("call_1", "search", "{}")).unwrap();
+            session.add_with_default_path(Role::User, Content::tool_result("call_1", "found")).unwrap();
+        }
+        session.add_with_default_path(Role::Assistant, format!("answer {}", turn)).unwrap();
+    }
+    session
+}
+
+fn texts(messages: &[ApiMessage<'_>]) -> Vec<String> {
+    messages.iter().map(|message| message.content.as_text().unwrap_or("<calls>").to_string()).collect()
+}
+
+fn test_history_policies() {
+    let session = sample_session();
+    let end_path = session.default_path.clone();
+    let messages = || session.assemble_context(&end_path, &Role::Assistant).unwrap();
+    assert_eq!(KeepAll.select(messages()).len(), 15);
+
+    // 窗口切开工具调用时，孤立的工具结果被移除
+    // An orphaned tool result is removed when the window splits a tool call
+    let window = SlidingWindow::new(10).select(messages());
+    assert_eq!(texts(&window)[..3], ["be brief", "answer 1", "question 2"]);
+    assert_eq!(window.len(), 10);
+
+    let summarized = SummarizeOld::new(4).select(messages());
+    assert_eq!(summarized.len(), 6);
+    assert_eq!(summarized[1].role, "system");
+    assert!(texts(&summarized)[1].contains("user: question 0\nassistant: answer 0"));
+    assert_eq!(texts(&summarized)[2..], ["question 4", "answer 4", "question 5", "answer 5"]);
+
+    let custom = SummarizeOld::with_summarizer(2, |older| format!("{} earlier messages", older.len())).select(messages());
+    assert!(texts(&custom)[1].ends_with("12 earlier messages"));
+
+    // 摘要消息的措辞来自提示词表，可以按键替换
+    // The wording of the summary message comes from the prompt table and can be replaced by key
+    Config::set_capability_prompt(PromptKey::OlderMessagesSummary, "Earlier conversation, summarized:\n{}");
+    let custom = SummarizeOld::with_summarizer(2, |older| format!("{} earlier messages", older.len()));
+    let custom = custom.select(messages());
+    assert_eq!(texts(&custom)[1], "Earlier conversation, summarized:\n12 earlier messages");
+    Config::remove_capability_prompt(&PromptKey::OlderMessagesSummary);
+
+    // 优先保留提到 "2" 的消息，其次是工具调用与结果，两者只能成对保留
+    // Prefer messages mentioning "2", then the tool call and result, which are only kept as a pair
+    let scorer = |message: &ApiMessage<'_>, _: usize, _: usize| match message.content.as_text() {
+        Some(text) if text.contains('2') => 1.0,
+        _ if message.tool_calls.is_some() || message.tool_call_id.is_some() => 0.5,
+        _ => 0.0,
+    };
+    let weighted = ImportanceWeighted::with_scorer(4, scorer).select(messages());
+    assert_eq!(texts(&weighted), ["be brief", "<calls>", "found", "question 2", "answer 2", "answer 5"]);
+    let split = ImportanceWeighted::with_scorer(3, scorer).select(messages());
+    assert_eq!(texts(&split), ["be brief", "question 2", "answer 2", "answer 5"]);
+
+    let default_weighted = ImportanceWeighted::new(4).select(messages());
+    assert_eq!(default_weighted.len(), 6);
+    assert_eq!(texts(&default_weighted).last().unwrap(), "answer 5");
+
+    // 用户标记的消息不占名额，总是保留
+    // Messages flagged by the user are always kept, outside the budget
+    let mut flagged = messages();
+    flagged[1].importance.pinned = true;
+    let pinned = ImportanceWeighted::with_scorer(3, scorer).select(flagged);
+    assert_eq!(texts(&pinned), ["be brief", "question 0", "question 2", "answer 2", "answer 5"]);
+
+    // 显著性高的旧消息胜过较新的消息
+    // An old message of high salience wins over newer ones
+    let mut rated = messages();
+    for (position, message) in rated.iter_mut().enumerate() {
+        message.importance.salience = Some(if position == 1 { 100 } else { 0 });
+    }
+    let salient = ImportanceWeighted::new(1).select(rated);
+    assert_eq!(texts(&salient), ["be brief", "question 0", "answer 5"]);
+
+    format_test_block("history_policies", || format!("{:?}\n{:?}", texts(&window), texts(&summarized)));
+}
+
+async fn test_history_policy_in_request() {
+    // 回答请求中的消息条数
+    // Answer with the number of messages in the request
+    let url = spawn_mock_server(|body| {
+        let count = body["messages"].as_array().map_or(0, |messages| messages.len());
+        (200, json!({
+            "choices": [{"message": {"content": count.to_string()}, "finish_reason": "stop"}],
+            "usage": {"prompt_tokens": 1, "completion_tokens": 1, "total_tokens": 2},
+        }).to_string())
+    })
+    .await;
+    Config::add_api_source("history-source", &url, 2);
+    Config::add_api_info("history-api", "history-model", ModelCapability::LongContext, "history-source", "sk-history")
+        .unwrap();
+
+    let mut chat = SingleChat::builder().api("history-api").system("be brief").history_policy(SlidingWindow::new(2)).build().unwrap();
+    assert_eq!(chat.get_answer("one").await.unwrap(), "2");
+    assert_eq!(chat.get_answer("two").await.unwrap(), "3");
+    assert_eq!(chat.get_answer("three").await.unwrap(), "3");
+
+    // 历史策略不修改会话本身
+    // The history policy leaves the session itself untouched
+    let end_path = chat.base.session.default_path.clone();
+    assert_eq!(chat.base.session.assemble_context(&end_path, &Role::Assistant).unwrap().len(), 7);
+
+    chat.set_history_policy(KeepAll);
+    assert_eq!(chat.get_answer("four").await.unwrap(), "8");
+}
+
+async fn test_salience() {
+    let mock = MockProvider::start("salience-api").await;
+    mock.reply("Noted, your flight is on Friday.");
+    let mut chat = SingleChat::builder().api("salience-api").system("be brief").build().unwrap();
+    chat.get_answer("My flight is on Friday.").await.unwrap();
+
+    // 分数个数不符时不写入任何评分
+    // Nothing is stored when the number of scores does not match
+    mock.reply("[80]");
+    assert!(chat.score_salience().await.is_err());
+    mock.reply("Scores: [90, 10]");
+    assert_eq!(chat.score_salience().await.unwrap(), 2);
+    mock.last_request()
+        .contains("[1] user: My flight is on Friday.")
+        .contains("[2] assistant: Noted, your flight is on Friday.")
+        .not_contains("be brief");
+    assert_eq!(chat.score_salience().await.unwrap(), 0);
+
+    let answer_id = chat.last_message_id().unwrap().to_string();
+    chat.pin_message(&answer_id, true).unwrap();
+    assert!(chat.pin_message("missing", true).is_err());
+
+    let end_path = chat.base.session.default_path.clone();
+    let messages = chat.base.session.assemble_context(&end_path, &Role::Assistant).unwrap();
+    let marks: Vec<_> =
+        messages.iter().map(|message| (message.importance.pinned, message.importance.salience)).collect();
+    assert_eq!(marks, [(false, None), (false, Some(90)), (true, Some(10))]);
+    assert_eq!(mock.requests().len(), 3);
+}
@@ -0,0 +1,82 @@
+use std::fs;
+use std::sync::{Arc, Mutex};
+
+use serde_json::json;
+
+use crate::chat::chat_single::SingleChat;
+use crate::chat::event::ChatEvent;
+use crate::chat::hot_reload::HotReload;
+use crate::testing::MockProvider;
+use crate::tests::format_test_block;
+
+fn spec(operation: &str) -> String {
+    json!({
+        "openapi": "3.0.0",
+        "servers": [{"url": "http://127.0.0.1:9"}],
+        "paths": {"/orders": {"get": {"operationId": operation, "summary": "List orders"}}}
+    })
+    .to_string()
+}
+
+pub async fn test_hot_reload() {
+    let dir = std::env::temp_dir().join(format!("rhine_hot_reload_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let prompt = dir.join("prompt.md");
+    let openapi = dir.join("shop.json");
+    fs::write(&prompt, "You are a shop assistant.").unwrap();
+    fs::write(&openapi, spec("list_orders")).unwrap();
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let recorded = events.clone();
+    let watcher = HotReload::new().prompt(&prompt).openapi("shop", &openapi);
+    let observed = watcher.clone();
+    let reload = watcher.on_reload(move |event| {
+        // 事件在锁外发出，处理函数可以查看监视器
+        // Events are emitted outside the lock, so handlers can inspect the watcher
+        assert!(format!("{:?}", observed).contains("generation"));
+        if let ChatEvent::Reloaded { path, error } = event {
+            recorded.lock().unwrap().push((path.clone(), error.is_some()));
+        }
+    });
+
+    let mock = MockProvider::start("reload-api").await;
+    let builder = SingleChat::builder().api("reload-api").system("Old prompt.");
+    let mut chat = builder.hot_reload(reload.clone()).build().unwrap();
+
+    // 首次检查加载全部文件，对话在下一次提问前应用
+    // The first check loads every file, and the chat applies them before its next question
+    assert_eq!(reload.check(), 2);
+    assert_eq!(reload.check(), 0);
+    mock.reply("Hello.");
+    chat.get_answer("Hi").await.unwrap();
+    mock.last_request().contains("You are a shop assistant.").contains("shop__list_orders").not_contains("Old prompt.");
+    assert_eq!(chat.tools().len(), 1);
+
+    // 改动的文件原地替换系统消息与工具提示，不会累积新的系统消息
+    // Changed files replace the system message and tools prompt in place, without piling up system messages
+    fs::write(&prompt, "You are a terse shop assistant.").unwrap();
+    fs::write(&openapi, spec("search_orders")).unwrap();
+    assert_eq!(reload.check(), 2);
+    mock.reply("Hi.");
+    chat.get_answer("Hello again").await.unwrap();
+    mock.last_request()
+        .contains("You are a terse shop assistant.")
+        .contains("shop__search_orders")
+        .not_contains("shop__list_orders")
+        .message_count(5);
+
+    // 加载失败时保留上一个版本，事件附上错误
+    // A failed load keeps the previous version and the event carries the error
+    fs::write(&openapi, "{ not json").unwrap();
+    assert_eq!(reload.check(), 0);
+    mock.reply("Still here.");
+    chat.get_answer("Are you there?").await.unwrap();
+    mock.last_request().contains("shop__search_orders");
+
+    let events = events.lock().unwrap().clone();
+    assert_eq!(events.len(), 5);
+    assert_eq!(events.iter().filter(|(_, failed)| *failed).count(), 1);
+    assert!(events[4].0.ends_with("shop.json"));
+    format_test_block("hot reload", || format!("{:#?}", reload));
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,163 @@
+use serde_json::json;
+
+use crate::chat::content::Content;
+use crate::chat::image::{ImagePart, take_tool_images};
+use crate::chat::message::{Role, Session};
+use crate::tests::format_test_block;
+
+/// 仅含文件头的 PNG，足以识别类型
+/// PNG with only its signature, enough for type detection
+const PNG_HEADER: &[u8] = &[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+pub async fn test_image() {
+    test_image_sources();
+    test_image_content();
+    test_tool_images();
+    #[cfg(feature = "images")]
+    test_image_resize();
+}
+
+fn test_image_sources() {
+    let image = ImagePart::from_bytes(PNG_HEADER).unwrap();
+    assert_eq!(image.mime_type(), Some("image/png"));
+    assert_eq!(image.to_url(), "data:image/png;base64,iVBORw0KGgo=");
+    assert_eq!(ImagePart::from_url(&image.to_url()).unwrap(), image);
+    assert!(ImagePart::from_bytes(b"plain text".to_vec()).is_err());
+
+    // 文件头优先于扩展名，无法识别时按扩展名判断
+    // The header wins over the extension, which is the fallback for unknown headers
+    let path = std::env::temp_dir().join("rhine_test_image.jpg");
+    std::fs::write(&path, PNG_HEADER).unwrap();
+    assert_eq!(ImagePart::from_path(&path).unwrap().mime_type(), Some("image/png"));
+    std::fs::write(&path, b"truncated").unwrap();
+    assert_eq!(ImagePart::from_path(&path).unwrap().mime_type(), Some("image/jpeg"));
+    assert!(ImagePart::from_path(path.with_extension("txt")).is_err());
+
+    let url = ImagePart::from_url("https://example.com/cat.png").unwrap();
+    assert_eq!(url, ImagePart::Url("https://example.com/cat.png".to_string()));
+    assert_eq!(url.mime_type(), None);
+    assert!(ImagePart::from_url("ftp://example.com/cat.png").is_err());
+    assert!(ImagePart::from_url("data:text/plain;base64,aGk=").is_err());
+    assert!(ImagePart::from_url("data:image/png,raw").is_err());
+}
+
+fn test_image_content() {
+    let image = ImagePart::from_bytes(PNG_HEADER).unwrap();
+    let mut session = Session::new();
+    session
+        .add_with_default_path(Role::User, vec![Content::from("what is this?"), image.clone().into()])
+        .unwrap();
+    let end_path = session.default_path.clone();
+    let openai = serde_json::to_value(session.assemble_context_for(&end_path, &Role::Assistant, "openai").unwrap())
+        .unwrap();
+    assert_eq!(openai[0]["content"][1], json!({"type": "image_url", "image_url": {"url": image.to_url()}}));
+
+    // 内嵌图片在日志中只显示占位
+    // Inline images show only a placeholder in logs
+    assert_eq!(Content::from(image).to_text(), "[image]");
+
+    format_test_block("image_content", || format!("{}", openai));
+}
+
+fn test_tool_images() {
+    let image = ImagePart::from_bytes(PNG_HEADER).unwrap();
+
+    // 带标记的图片从结果中取出，按出现顺序编号；形似图片但没有标记的对象保持原样
+    // Marked images are taken out of the result, numbered in order of appearance; image-like objects without the
+    // marker are left as they are
+    let mut result = json!({
+        "chart": image.to_tool_output(),
+        "content": [
+            {"type": "text", "text": "search results"},
+            ImagePart::Url("https://example.com/a.png".to_string()).to_tool_output(),
+            {"type": "image", "url": "https://example.com/found.png", "width": 640},
+            {"type": "image", "data": "iVBORw0KGgo=", "mimeType": "image/png"},
+            {"type": "image_url", "image_url": {"url": "https://example.com/b.png"}},
+        ],
+    });
+    let images = take_tool_images(&mut result);
+    assert_eq!(images.len(), 2);
+    assert_eq!(&*images[0], image.to_url());
+    assert_eq!(&*images[1], "https://example.com/a.png");
+    assert_eq!(result["chart"], "[image 1]");
+    assert_eq!(result["content"][1], "[image 2]");
+    assert_eq!(result["content"][2]["url"], "https://example.com/found.png");
+    assert_eq!(result["content"][3]["type"], "image");
+    assert_eq!(result["content"][4]["type"], "image_url");
+
+    // Anthropic 在工具消息中内嵌图片，OpenAI 在工具结果后另发一条用户消息，不支持图片的提供商只留占位
+    // Anthropic gets the images inline in the tool message, OpenAI in a user message after the tool results, and
+    // providers without image support only keep the placeholders
+    let mut session = Session::new();
+    session.add_with_default_path(Role::User, "draw it").unwrap();
+    session
+        .add_with_default_path(Role::Assistant, vec![Content::tool_call("c1", "draw", "{}"), Content::tool_call(
+            "c2", "draw", "{}",
+        )])
+        .unwrap();
+    for call_id in ["c1", "c2"] {
+        let result = Content::tool_result(call_id, "[image 1]").with_images(vec![image.to_url().into()]);
+        session.add_with_default_path(Role::tool(call_id), result).unwrap();
+    }
+    let end_path = session.default_path.clone();
+    let render = |provider| {
+        serde_json::to_value(session.assemble_context_for(&end_path, &Role::Assistant, provider).unwrap()).unwrap()
+    };
+    let openai = render("openai");
+    let roles: Vec<_> = openai.as_array().unwrap().iter().map(|message| message["role"].clone()).collect();
+    assert_eq!(roles, ["user", "assistant", "tool", "tool", "user"]);
+    assert_eq!(openai[2]["content"], "[image 1]");
+    assert_eq!(openai[4]["content"].as_array().unwrap().len(), 4);
+    let anthropic = render("anthropic");
+    assert_eq!(anthropic.as_array().unwrap().len(), 4);
+    assert_eq!(anthropic[2]["content"][1]["image_url"]["url"], image.to_url());
+    let deepseek = render("deepseek");
+    assert_eq!(deepseek.as_array().unwrap().len(), 4);
+    assert_eq!(deepseek[3]["content"], "[image 1]");
+}
+
+#[cfg(feature = "images")]
+fn test_image_resize() {
+    use std::io::Cursor;
+
+    use image::{GenericImageView, ImageFormat, RgbImage, RgbaImage};
+
+    use crate::chat::image::ImageLimits;
+
+    let encode = |image: image::DynamicImage| {
+        let mut bytes = Cursor::new(Vec::new());
+        image.write_to(&mut bytes, ImageFormat::Png).unwrap();
+        bytes.into_inner()
+    };
+    let noisy = RgbImage::from_fn(400, 200, |x, y| image::Rgb([(x * 7) as u8, (y * 13) as u8, ((x ^ y) * 3) as u8]));
+
+    // 尺寸超限时按比例缩小，不透明图片重新编码为 JPEG
+    // Oversized dimensions are scaled proportionally, opaque images are re-encoded as JPEG
+    let limits = ImageLimits {
+        max_dimension: 100,
+        ..Default::default()
+    };
+    let resized = ImagePart::from_bytes(encode(noisy.clone().into())).unwrap().resize(&limits).unwrap();
+    let ImagePart::Data { mime_type, bytes } = &resized else { panic!("expected inline image") };
+    assert_eq!(mime_type, "image/jpeg");
+    assert_eq!(image::load_from_memory(bytes).unwrap().dimensions(), (100, 50));
+
+    // 字节数超限时继续缩小，透明图片保持 PNG
+    // Oversized bytes keep shrinking the image, transparent images stay PNG
+    let transparent = RgbaImage::from_fn(400, 200, |x, y| image::Rgba([(x * 7) as u8, (y * 13) as u8, 0, (x + y) as u8]));
+    let limits = ImageLimits {
+        max_dimension: 4096,
+        max_bytes: 20 * 1024,
+    };
+    let resized = ImagePart::from_bytes(encode(transparent.into())).unwrap().resize(&limits).unwrap();
+    let ImagePart::Data { mime_type, bytes } = &resized else { panic!("expected inline image") };
+    assert_eq!(mime_type, "image/png");
+    assert!(bytes.len() <= limits.max_bytes);
+
+    // 已满足限制的图片与网址保持不变
+    // Images within the limits and URLs are left unchanged
+    let small = ImagePart::from_bytes(encode(noisy.into())).unwrap();
+    assert_eq!(small.clone().resize(&ImageLimits::default()).unwrap(), small);
+    let url = ImagePart::from_url("https://example.com/cat.png").unwrap();
+    assert_eq!(url.clone().resize(&limits).unwrap(), url);
+}
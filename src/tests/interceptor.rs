@@ -0,0 +1,87 @@
+use reqwest::header::HeaderValue;
+use serde_json::json;
+
+use crate::chat::chat_single::SingleChat;
+use crate::chat::compat::ProviderCompat;
+use crate::chat::interceptor::{OutgoingRequest, clear_request_interceptors, register_request_interceptor};
+use crate::chat::message::Role;
+use crate::chat::params::ChatParams;
+use crate::config::Config;
+use crate::testing::MockProvider;
+use crate::tests::format_test_block;
+
+pub async fn test_interceptor() {
+    let mock = MockProvider::start("interceptor-api").await;
+    mock.reply("first").reply("second");
+
+    // 全局拦截器先执行，对话自身的拦截器可以看到并覆盖它的修改
+    // Global interceptors run first, the chat's own interceptors see and can override their changes
+    register_request_interceptor(|request: &mut OutgoingRequest| {
+        request.headers.insert("x-gateway", HeaderValue::from_static("global"));
+        request.body["metadata"] = json!({"experiment": "a"});
+    });
+    let mut chat = SingleChat::builder()
+        .api("interceptor-api")
+        .request_interceptor(|request: &mut OutgoingRequest| {
+            assert_eq!(request.body["metadata"]["experiment"], "a");
+            request.body["metadata"]["experiment"] = json!("b");
+            request.body["top_k"] = json!(20);
+        })
+        .build()
+        .unwrap();
+    chat.get_answer("hello").await.unwrap();
+    let body = mock.requests()[0].clone();
+    format_test_block("Intercepted Request", || serde_json::to_string_pretty(&body).unwrap());
+    assert_eq!(mock.request_header(0, "x-gateway").as_deref(), Some("global"));
+    assert_eq!(body["metadata"]["experiment"], "b");
+    assert_eq!(body["top_k"], 20);
+
+    let preview = chat.dry_run("again").await.unwrap();
+    assert!(preview.headers.contains(&("x-gateway".to_string(), "global".to_string())));
+    assert_eq!(preview.body["top_k"], 20);
+
+    clear_request_interceptors();
+    SingleChat::builder().api("interceptor-api").build().unwrap().get_answer("hello").await.unwrap();
+    assert_eq!(mock.request_header(1, "x-gateway"), None);
+    assert!(mock.requests()[1].get("metadata").is_none());
+}
+
+pub async fn test_compat() {
+    let mock = MockProvider::start("compat-api").await;
+    mock.reply("first").reply("second").reply("third");
+    let mut chat = SingleChat::builder()
+        .api("compat-api")
+        .system("Be brief.")
+        .params(ChatParams { logit_bias: Some([(42, -100)].into()), ..Default::default() })
+        .build()
+        .unwrap();
+    chat.base.add_message(Role::System, "Answer in English.").unwrap();
+
+    // 只允许一条系统消息时合并为一条，不支持的参数被移除
+    // Only one system message allowed: they are merged into one, and unsupported parameters are removed
+    let compat = ProviderCompat::new().max_system_messages(1).unsupported_param("logit_bias");
+    Config::set_source_compat("compat-api-source", compat).unwrap();
+    chat.get_answer("hello").await.unwrap();
+    let body = mock.requests()[0].clone();
+    format_test_block("Compat Request", || serde_json::to_string_pretty(&body).unwrap());
+    assert_eq!(body["messages"][0], json!({"role": "system", "content": "Be brief.\n\nAnswer in English."}));
+    assert_eq!(body["messages"].as_array().unwrap().iter().filter(|m| m["role"] == "system").count(), 1);
+    assert!(body.get("logit_bias").is_none());
+
+    // 不支持 system 角色时并入第一条用户消息，预览同样经过改写
+    // Without the system role they go into the first user message, and previews are rewritten too
+    Config::set_source_compat("compat-api-source", ProviderCompat::new().system_role(false)).unwrap();
+    chat.get_answer("again").await.unwrap();
+    let body = mock.requests()[1].clone();
+    assert_eq!(body["messages"][0]["role"], "user");
+    assert_eq!(body["messages"][0]["content"], "Be brief.\n\nAnswer in English.\n\nhello");
+    assert!(body["messages"].as_array().unwrap().iter().all(|m| m["role"] != "system"));
+    assert_eq!(body["logit_bias"]["42"], -100);
+    let preview = chat.dry_run("preview").await.unwrap();
+    assert_eq!(preview.body["messages"][0]["role"], "user");
+
+    Config::set_source_compat("compat-api-source", ProviderCompat::default()).unwrap();
+    chat.get_answer("last").await.unwrap();
+    mock.last_request().contains("system: Be brief.");
+    assert!(Config::get_compat(mock.url()).is_none());
+}
@@ -0,0 +1,64 @@
+use rhine_schema_derive::JsonSchema;
+use serde::Deserialize;
+
+use crate::chat::chat_base::ChatError;
+use crate::chat::chat_single::SingleChat;
+use crate::chat::json_lines::{JSON_LINES_INSTRUCTION, JsonLines};
+use crate::schema::json_schema::JsonSchema;
+use crate::testing::MockProvider;
+use crate::tests::format_test_block;
+
+#[derive(Debug, PartialEq, Deserialize, JsonSchema)]
+#[schema(name = "invoice", description = "An invoice found in the documents", strict = true)]
+struct Invoice {
+    #[schema(desc = "Invoice number", required = true)]
+    number: String,
+
+    #[schema(desc = "Total amount", required = true)]
+    total: f64,
+}
+
+pub async fn test_json_items() {
+    // 行可能跨多段到达，代码块标记与行尾逗号被忽略
+    // Lines may arrive across several pieces, code fences and trailing commas are ignored
+    let mut lines = JsonLines::default();
+    assert!(lines.push("```jsonl\n{\"a\":").is_empty());
+    assert_eq!(lines.push(" 1},\n{\"a\": 2}\n```\n{\"a\""), vec!["{\"a\": 1}", "{\"a\": 2}"]);
+    assert_eq!(lines.push(": 3}").len(), 0);
+    assert_eq!(lines.finish().as_deref(), Some("{\"a\": 3}"));
+
+    let mock = MockProvider::start("json-items-api").await;
+    mock.reply(
+        "{\"number\": \"INV-1\", \"total\": 12.5}\n{\"number\": \"INV-2\", \"total\": 40}\n\
+         {\"number\": \"INV-3\", \"total\": 7.25}",
+    );
+    let mut chat = SingleChat::builder().api("json-items-api").build().unwrap();
+    let mut seen = Vec::new();
+    let invoices: Vec<Invoice> = chat
+        .get_json_items("List every invoice", |invoice: &Invoice| seen.push(invoice.number.clone()))
+        .await
+        .unwrap();
+    assert_eq!(seen, ["INV-1", "INV-2", "INV-3"]);
+    assert_eq!(invoices[1], Invoice { number: "INV-2".to_string(), total: 40.0 });
+    let request = mock.last_request();
+    request.contains(JSON_LINES_INSTRUCTION).contains("invoice");
+    assert_eq!(request.body()["stream"], true);
+    // 格式说明不写入会话，每次请求中只有一条
+    // The format instruction is not written to the session, each request holds it once
+    assert_eq!(chat.base.session.default_path.len(), 2);
+
+    // 无法解析为目标类型的行返回错误，之前的条目已交给回调
+    // A line that does not parse as the target type is an error, the items before it were already handed over
+    mock.reply("{\"number\": \"INV-4\", \"total\": 1}\n{\"number\": 5}\n");
+    let mut delivered = 0;
+    let error = chat.get_json_items("Again", |_: &Invoice| delivered += 1).await.unwrap_err();
+    assert!(matches!(error.current_context(), ChatError::GetJsonError));
+    assert_eq!(delivered, 1);
+    let request = mock.last_request();
+    let body = request.body();
+    let messages = body["messages"].as_array().unwrap();
+    assert_eq!(messages.iter().filter(|message| message["role"] == "system").count(), 1);
+
+    format_test_block("JSON Items", || format!("{:?}\n{:?}", invoices, error));
+    assert_eq!(mock.pending(), 0);
+}
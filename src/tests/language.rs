@@ -0,0 +1,67 @@
+use serde_json::json;
+
+use crate::chat::chat_single::SingleChat;
+use crate::chat::language::{Lang, LanguagePolicy, detect_language};
+use crate::testing::MockProvider;
+use crate::tests::format_test_block;
+
+const ENGLISH: &str = "The weather in Paris is mild and pleasant this week.";
+const CHINESE: &str = "本周巴黎的天气温和宜人，适合出门散步。";
+
+pub async fn test_language() {
+    test_detection();
+
+    let mock = MockProvider::start("language-api").await;
+    let mut chat = SingleChat::builder()
+        .api("language-api")
+        .language_policy(LanguagePolicy::match_user())
+        .build()
+        .unwrap();
+
+    // 回答语种与用户不符时附上该回答与语种要求重新提问，只有最终回答写入会话
+    // An answer in another language than the user's is sent back with a language instruction, only the final
+    // answer is stored
+    mock.reply(CHINESE).reply(ENGLISH);
+    let answer = chat.get_answer("Could you tell me what the weather will be like in Paris this week?").await.unwrap();
+    assert_eq!(answer, ENGLISH);
+    mock.last_request().contains(CHINESE).contains("answer again entirely in English");
+    assert_eq!(chat.base.session.default_path.len(), 2);
+
+    // 重问次数用尽后返回最后一次回答
+    // Once the retries are used up the last answer is returned
+    chat.set_language_policy(LanguagePolicy::fixed(Lang::Eng).max_retries(1));
+    mock.reply(CHINESE).reply(CHINESE);
+    assert_eq!(chat.get_answer("再说一遍巴黎的天气怎么样？").await.unwrap(), CHINESE);
+    assert_eq!(mock.requests().len(), 4);
+    assert_eq!(mock.pending(), 0);
+
+    format_test_block("language", || mock.request(1).prompt());
+}
+
+fn test_detection() {
+    assert_eq!(detect_language(ENGLISH, 10), Some(Lang::Eng));
+    assert_eq!(detect_language(CHINESE, 10), Some(Lang::Cmn));
+    assert_eq!(detect_language("Hi", 10), None);
+
+    // 代码块不计入识别
+    // Code blocks are left out of detection
+    let answer = format!("{}\n```rust\nfn main() {{ println!(\"hello world, this is code\"); }}\n```", CHINESE);
+    assert_eq!(detect_language(&answer, 10), Some(Lang::Cmn));
+    assert_eq!(detect_language("```\nlet answer = compute_the_answer();\n```", 10), None);
+
+    // 跟随用户时识别最后一条用户消息，分段内容只取文字
+    // Following the user detects the last user message, taking only the text of multi-part content
+    let policy = LanguagePolicy::match_user();
+    let body = json!({"messages": [
+        {"role": "user", "content": CHINESE},
+        {"role": "assistant", "content": CHINESE},
+        {"role": "user", "content": [{"type": "text", "text": ENGLISH}, {"type": "image_url", "image_url": {}}]},
+    ]});
+    assert_eq!(policy.expected(&body), Some(Lang::Eng));
+    assert_eq!(policy.expected(&json!({"messages": [{"role": "user", "content": "ok"}]})), None);
+    assert_eq!(LanguagePolicy::fixed(Lang::Cmn).expected(&body), Some(Lang::Cmn));
+
+    assert_eq!(policy.mismatch(CHINESE, Lang::Eng), Some(Lang::Cmn));
+    assert_eq!(policy.mismatch(ENGLISH, Lang::Eng), None);
+    assert_eq!(policy.min_chars(1000).mismatch(CHINESE, Lang::Eng), None);
+}
@@ -0,0 +1,118 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use error_stack::{Report, Result};
+use futures::future::BoxFuture;
+
+use crate::chat::chat_base::ChatError;
+use crate::chat::chat_single::SingleChat;
+use crate::chat::guardrail::{DenyList, GuardrailAction, Guardrails};
+use crate::chat::middleware::{AnswerCache, AnswerMiddleware, Next, RewriteInput};
+use crate::chat::pii::PiiScrubber;
+use crate::chat::tenant::Tenant;
+use crate::testing::MockProvider;
+use crate::tests::format_test_block;
+
+/// 记录进入与离开的顺序
+/// Records the order of entering and leaving
+struct Trace {
+    name: &'static str,
+    log: Arc<Mutex<Vec<String>>>,
+}
+
+impl AnswerMiddleware for Trace {
+    fn call<'a>(&'a self, input: String, next: Next<'a>) -> BoxFuture<'a, Result<String, ChatError>> {
+        Box::pin(async move {
+            self.log.lock().unwrap().push(format!("{} > {}", self.name, input));
+            let answer = next.run(input).await?;
+            self.log.lock().unwrap().push(format!("{} < {}", self.name, answer));
+            Ok(format!("{} [{}]", answer, self.name))
+        })
+    }
+}
+
+/// 超过提问次数后拒绝，不请求模型
+/// Refuses once the question budget is spent, without asking the model
+struct QuestionBudget {
+    remaining: AtomicUsize,
+}
+
+impl AnswerMiddleware for QuestionBudget {
+    fn call<'a>(&'a self, input: String, next: Next<'a>) -> BoxFuture<'a, Result<String, ChatError>> {
+        Box::pin(async move {
+            let spent = self.remaining.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |left| left.checked_sub(1));
+            if spent.is_err() {
+                return Err(Report::new(ChatError::GuardrailBlocked("question budget".to_string())));
+            }
+            next.run(input).await
+        })
+    }
+}
+
+pub async fn test_middleware() {
+    let mock = MockProvider::start("middleware-api").await;
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let mut chat = SingleChat::builder()
+        .api("middleware-api")
+        .middleware(Trace { name: "outer", log: log.clone() })
+        .middleware(RewriteInput(|input: &str| input.replace("pls", "please")))
+        .middleware(Trace { name: "inner", log: log.clone() })
+        .build()
+        .unwrap();
+
+    // 外层先进后出，改写后的输入发给模型
+    // The outer layer enters first and leaves last, and the rewritten input goes to the model
+    mock.reply("Sure.");
+    assert_eq!(chat.get_answer("help pls").await.unwrap(), "Sure. [inner] [outer]");
+    mock.last_request().contains("user: help please").not_contains("pls");
+    assert_eq!(
+        *log.lock().unwrap(),
+        ["outer > help pls", "inner > help please", "inner < Sure.", "outer < Sure. [inner]"]
+    );
+
+    // 预算用完时直接返回错误，不请求模型
+    // A spent budget fails right away without asking the model
+    let mut chat = SingleChat::builder().api("middleware-api").build().unwrap();
+    chat.add_middleware(QuestionBudget { remaining: AtomicUsize::new(1) });
+    mock.reply("Paris.");
+    assert_eq!(chat.get_answer("Capital of France?").await.unwrap(), "Paris.");
+    assert!(chat.get_answer("Capital of Spain?").await.is_err());
+    assert_eq!(mock.requests().len(), 2);
+
+    // 请求完全相同的另一个对话命中缓存，命中的回答仍经过护栏与 PII 还原，历史中只保存占位符
+    // Another chat sending the identical request hits the cache, the hit still goes through guardrails and PII
+    // restoring, and the history only stores placeholders
+    let cache = AnswerCache::new();
+    let cached_chat = |system: &str, tenant: Option<Tenant>| {
+        let mut builder = SingleChat::builder()
+            .api("middleware-api")
+            .system(system)
+            .answer_cache(cache.clone())
+            .pii_scrubber(PiiScrubber::new())
+            .guardrails(Guardrails::new().with(DenyList::new(["darn"], GuardrailAction::Rewrite)));
+        if let Some(tenant) = tenant {
+            builder = builder.tenant(tenant);
+        }
+        builder.build().unwrap()
+    };
+    mock.reply("darn, mailing <EMAIL_1>");
+    let question = "mail eve@example.com";
+    assert_eq!(cached_chat("be brief", None).get_answer(question).await.unwrap(), "****, mailing eve@example.com");
+    let mut hit = cached_chat("be brief", None);
+    assert_eq!(hit.get_answer(question).await.unwrap(), "****, mailing eve@example.com");
+    assert_eq!(mock.requests().len(), 3);
+    let answer_path = hit.base.session.default_path.clone();
+    let stored = hit.base.session.get_node_by_path(&answer_path).unwrap().content.to_string();
+    assert!(stored.contains("<EMAIL_1>") && !stored.contains("eve@example.com"), "{}", stored);
+
+    // 系统提示词或租户不同的请求不命中
+    // A different system prompt or tenant misses
+    mock.reply("other prompt").reply("other tenant");
+    assert_eq!(cached_chat("be verbose", None).get_answer(question).await.unwrap(), "other prompt");
+    let tenant = Some(Tenant::new("middleware-tenant"));
+    assert_eq!(cached_chat("be brief", tenant).get_answer(question).await.unwrap(), "other tenant");
+    assert_eq!(mock.requests().len(), 5);
+    assert_eq!(cache.len(), 3);
+
+    format_test_block("middleware", || log.lock().unwrap().join("\n"));
+}
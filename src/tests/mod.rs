@@ -1,10 +1,265 @@
 use tracing::log::info;
-use crate::tests::prompt::test_prompt;
 use crate::tests::chat::test_chat;
+use crate::tests::config::test_config;
+use crate::tests::stream::test_stream;
+use crate::tests::telemetry::test_telemetry;
+use crate::tests::event::test_event;
+use crate::tests::batch::test_batch;
+use crate::tests::speculation::test_speculation;
+use crate::tests::error::test_error;
+use crate::tests::builder::test_builder;
+use crate::tests::session::test_session;
+use crate::tests::blocking::test_blocking;
+use crate::tests::content::test_content;
+use crate::tests::image::test_image;
+use crate::tests::history::test_history;
+use crate::tests::moderation::test_moderation;
+use crate::tests::pii::test_pii;
+use crate::tests::guardrail::test_guardrail;
+use crate::tests::injection::test_injection;
+use crate::tests::output_cap::test_output_cap;
+#[cfg(test)]
+use crate::tests::testing::test_testing;
+#[cfg(test)]
+use crate::tests::eval::test_eval;
+#[cfg(test)]
+use crate::tests::documents::test_documents;
+#[cfg(test)]
+use crate::tests::summary::test_summary;
+#[cfg(test)]
+use crate::tests::transform::test_transform;
+#[cfg(test)]
+use crate::tests::checkpoint::test_checkpoint;
+#[cfg(test)]
+use crate::tests::compact::test_compact;
+#[cfg(test)]
+use crate::tests::scheduler::test_scheduler;
+#[cfg(test)]
+use crate::tests::citation::test_citation;
+#[cfg(test)]
+use crate::tests::attachment::test_attachment;
+#[cfg(test)]
+use crate::tests::store::test_store;
+#[cfg(test)]
+use crate::tests::json_items::test_json_items;
+#[cfg(test)]
+use crate::tests::tool_source::test_tool_sources;
+#[cfg(test)]
+use crate::tests::persona::test_personas;
+#[cfg(test)]
+use crate::tests::dialogue_state::test_dialogue_state;
+#[cfg(test)]
+use crate::tests::trigger::test_tool_triggers;
+#[cfg(test)]
+use crate::tests::middleware::test_middleware;
+#[cfg(test)]
+use crate::tests::shutdown::test_shutdown;
+#[cfg(test)]
+use crate::tests::ocr::test_ocr;
+#[cfg(test)]
+use crate::tests::postprocess::test_post_processors;
+#[cfg(test)]
+use crate::tests::shadow::test_shadow;
+#[cfg(test)]
+use crate::tests::refusal::test_refusal;
+#[cfg(test)]
+use crate::tests::retention::test_retention;
+#[cfg(test)]
+use crate::tests::subagent::test_subagent;
+#[cfg(test)]
+use crate::tests::confidence::test_confidence;
+#[cfg(test)]
+use crate::tests::hot_reload::test_hot_reload;
+#[cfg(test)]
+use crate::tests::extraction::test_extraction;
+#[cfg(test)]
+use crate::tests::compression::test_compression;
+#[cfg(test)]
+use crate::tests::transaction::test_transaction;
+#[cfg(test)]
+use crate::tests::context_provider::test_context_provider;
+#[cfg(test)]
+use crate::tests::vision::test_vision;
+#[cfg(test)]
+use crate::tests::warmup::test_warmup;
+#[cfg(test)]
+use crate::tests::typing_indicator::test_typing_indicator;
+#[cfg(test)]
+use crate::tests::scratchpad::test_scratchpad;
+#[cfg(test)]
+use crate::tests::agent_spec::test_agent_spec;
+#[cfg(test)]
+use crate::tests::scoped_config::test_scoped_config;
+#[cfg(test)]
+use crate::tests::handover::test_handover;
+#[cfg(test)]
+use crate::tests::transport::test_transport;
+#[cfg(test)]
+use crate::tests::tool_docs::test_tool_docs;
+#[cfg(test)]
+use crate::tests::artifacts::test_artifacts;
+#[cfg(test)]
+use crate::tests::resume::test_resume;
+use crate::tests::admission::test_admission;
+use crate::tests::websocket::test_websocket;
+#[cfg(test)]
+use crate::tests::language::test_language;
+#[cfg(test)]
+use crate::tests::analytics::test_analytics;
+#[cfg(test)]
+use crate::tests::request_id::test_request_id;
+#[cfg(test)]
+use crate::tests::agent::test_agent;
+#[cfg(test)]
+use crate::tests::export::test_export;
+#[cfg(test)]
+use crate::tests::completion::test_completion;
+#[cfg(test)]
+use crate::tests::grammar::test_grammar;
+#[cfg(test)]
+use crate::tests::preview::test_preview;
+#[cfg(test)]
+use crate::tests::interceptor::{test_compat, test_interceptor};
+#[cfg(test)]
+use crate::tests::model_override::test_model_override;
+#[cfg(test)]
+use crate::tests::broadcast::test_broadcast;
+#[cfg(test)]
+use crate::tests::tenant::test_tenant;
+#[cfg(test)]
+use crate::tests::continuation::test_continuation;
 
 mod prompt;
 mod message;
 mod chat;
+mod config;
+mod stream;
+mod telemetry;
+mod event;
+mod batch;
+mod speculation;
+mod error;
+mod builder;
+mod session;
+mod blocking;
+mod content;
+mod image;
+mod history;
+mod moderation;
+mod pii;
+mod guardrail;
+mod injection;
+mod output_cap;
+#[cfg(test)]
+mod testing;
+#[cfg(test)]
+mod eval;
+#[cfg(test)]
+mod documents;
+#[cfg(test)]
+mod summary;
+#[cfg(test)]
+mod transform;
+#[cfg(test)]
+mod checkpoint;
+#[cfg(test)]
+mod compact;
+#[cfg(test)]
+mod scheduler;
+#[cfg(test)]
+mod citation;
+#[cfg(test)]
+mod attachment;
+#[cfg(test)]
+mod store;
+#[cfg(test)]
+mod json_items;
+#[cfg(test)]
+mod tool_source;
+#[cfg(test)]
+mod persona;
+#[cfg(test)]
+mod dialogue_state;
+#[cfg(test)]
+mod trigger;
+#[cfg(test)]
+mod middleware;
+#[cfg(test)]
+mod shutdown;
+#[cfg(test)]
+mod ocr;
+#[cfg(test)]
+mod postprocess;
+#[cfg(test)]
+mod shadow;
+#[cfg(test)]
+mod refusal;
+#[cfg(test)]
+mod retention;
+#[cfg(test)]
+mod subagent;
+#[cfg(test)]
+mod confidence;
+#[cfg(test)]
+mod hot_reload;
+#[cfg(test)]
+mod extraction;
+#[cfg(test)]
+mod compression;
+#[cfg(test)]
+mod transaction;
+#[cfg(test)]
+mod context_provider;
+#[cfg(test)]
+mod vision;
+#[cfg(test)]
+mod warmup;
+#[cfg(test)]
+mod typing_indicator;
+#[cfg(test)]
+mod scratchpad;
+#[cfg(test)]
+mod agent_spec;
+#[cfg(test)]
+mod scoped_config;
+#[cfg(test)]
+mod handover;
+#[cfg(test)]
+mod transport;
+#[cfg(test)]
+mod tool_docs;
+#[cfg(test)]
+mod artifacts;
+#[cfg(test)]
+mod resume;
+mod admission;
+mod websocket;
+#[cfg(test)]
+mod language;
+#[cfg(test)]
+mod analytics;
+#[cfg(test)]
+mod request_id;
+#[cfg(test)]
+mod agent;
+#[cfg(test)]
+mod export;
+#[cfg(test)]
+mod completion;
+#[cfg(test)]
+mod grammar;
+#[cfg(test)]
+mod preview;
+#[cfg(test)]
+mod interceptor;
+#[cfg(test)]
+mod model_override;
+#[cfg(test)]
+mod broadcast;
+#[cfg(test)]
+mod tenant;
+#[cfg(test)]
+mod continuation;
 
 
 #[tokio::test]
@@ -17,8 +272,83 @@ pub async fn test() {
         .directory("./logs")
         .file_name("test.log")
         .init();
-    println!("log level: {}", "info");
+    println!("log level: info");
     // test_prompt().await;
+    test_config().await;
+    test_stream().await;
+    test_telemetry().await;
+    test_event().await;
+    test_batch().await;
+    test_speculation().await;
+    test_error().await;
+    test_builder().await;
+    test_session().await;
+    test_blocking().await;
+    test_content().await;
+    test_image().await;
+    test_history().await;
+    test_moderation().await;
+    test_pii().await;
+    test_guardrail().await;
+    test_injection().await;
+    test_output_cap().await;
+    test_testing().await;
+    test_eval().await;
+    test_documents().await;
+    test_summary().await;
+    test_transform().await;
+    test_checkpoint().await;
+    test_compact().await;
+    test_scheduler().await;
+    test_citation().await;
+    test_attachment().await;
+    test_request_id().await;
+    test_agent().await;
+    test_export().await;
+    test_completion().await;
+    test_grammar().await;
+    test_preview().await;
+    test_interceptor().await;
+    test_compat().await;
+    test_model_override().await;
+    test_broadcast().await;
+    test_tenant().await;
+    test_continuation().await;
+    test_store().await;
+    test_json_items().await;
+    test_tool_sources().await;
+    test_personas().await;
+    test_dialogue_state().await;
+    test_tool_triggers().await;
+    test_middleware().await;
+    test_shutdown().await;
+    test_ocr().await;
+    test_post_processors().await;
+    test_shadow().await;
+    test_refusal().await;
+    test_retention().await;
+    test_subagent().await;
+    test_confidence().await;
+    test_hot_reload().await;
+    test_extraction().await;
+    test_compression().await;
+    test_transaction().await;
+    test_context_provider().await;
+    test_vision().await;
+    test_warmup().await;
+    test_typing_indicator().await;
+    test_scratchpad().await;
+    test_agent_spec().await;
+    test_scoped_config().await;
+    test_handover().await;
+    test_transport().await;
+    test_tool_docs().await;
+    test_artifacts().await;
+    test_resume().await;
+    test_admission().await;
+    test_websocket().await;
+    test_language().await;
+    test_analytics().await;
     test_chat().await;
 }
 
@@ -34,4 +364,68 @@ where
         title,
         content_fn()
     );
-}
\ No newline at end of file
+}
+
+/// 启动本地模拟的对话补全服务，返回接口地址；`respond` 根据请求体返回状态码和响应体
+/// Start a local mock chat completion server and return its URL; `respond` maps the request body to a status
+/// code and response body
+pub async fn spawn_mock_server<F>(respond: F) -> String
+where
+    F: Fn(serde_json::Value) -> (u16, String) + Send + Sync + 'static,
+{
+    spawn_truncating_mock_server(move |body| {
+        let (status, response) = respond(body);
+        (status, response, 0)
+    })
+    .await
+}
+
+/// 同 `spawn_mock_server`，`respond` 额外返回声明长度超出响应体的字节数，用于模拟连接在响应中途中断
+/// Like `spawn_mock_server`, `respond` also returns how many bytes the declared length exceeds the body by, to
+/// simulate a connection dropping mid-response
+pub async fn spawn_truncating_mock_server<F>(respond: F) -> String
+where
+    F: Fn(serde_json::Value) -> (u16, String, usize) + Send + Sync + 'static,
+{
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let respond = std::sync::Arc::new(respond);
+    tokio::spawn(async move {
+        while let Ok((mut socket, _)) = listener.accept().await {
+            let respond = respond.clone();
+            tokio::spawn(async move {
+                let mut request = Vec::new();
+                let mut buffer = [0u8; 4096];
+                let body = loop {
+                    let Ok(read) = socket.read(&mut buffer).await else { return };
+                    if read == 0 {
+                        return;
+                    }
+                    request.extend_from_slice(&buffer[..read]);
+                    let text = String::from_utf8_lossy(&request);
+                    let Some(header_end) = text.find("\r\n\r\n") else { continue };
+                    let length = text[..header_end]
+                        .lines()
+                        .find_map(|line| line.to_ascii_lowercase().strip_prefix("content-length:").map(|v| v.trim().to_string()))
+                        .and_then(|v| v.parse::<usize>().ok())
+                        .unwrap_or(0);
+                    if request.len() >= header_end + 4 + length {
+                        break request[header_end + 4..header_end + 4 + length].to_vec();
+                    }
+                };
+
+                let (status, response, missing) = respond(serde_json::from_slice(&body).unwrap_or_default());
+                let head = format!(
+                    "HTTP/1.1 {} Mock\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+                    status,
+                    response.len() + missing
+                );
+                let _ = socket.write_all(head.as_bytes()).await;
+                let _ = socket.write_all(response.as_bytes()).await;
+            });
+        }
+    });
+    format!("http://127.0.0.1:{}/v1/chat/completions", port)
+}
@@ -0,0 +1,50 @@
+use crate::chat::chat_base::ModelOverride;
+use crate::chat::chat_single::SingleChat;
+use crate::chat::message::Role;
+use crate::config::{Config, ModelCapability};
+use crate::testing::MockProvider;
+use crate::tests::format_test_block;
+
+pub async fn test_model_override() {
+    let mock = MockProvider::start("override-api").await;
+    Config::add_api_info("override-strong", "strong-model", ModelCapability::Think, "override-api-source", "sk-mock")
+        .unwrap();
+    // 只按名称使用，不参与按能力的选择
+    // Only used by name, kept out of selection by capability
+    Config::set_api_weight("override-strong", 0);
+    mock.reply("Hello!").reply("Revenue grew 12%.").reply("Bye!");
+
+    let mut chat = SingleChat::builder().api("override-api").build().unwrap();
+    chat.get_answer("Hi").await.unwrap();
+    let answer = chat
+        .get_answer_with_model("Analyze the report", ModelOverride::Api("override-strong".to_string()))
+        .await
+        .unwrap();
+    assert_eq!(answer, "Revenue grew 12%.");
+    chat.get_answer("Thanks").await.unwrap();
+
+    // 覆盖只作用于一次调用，历史共用
+    // The override applies to one call only, and the history is shared
+    let models: Vec<_> = mock.requests().iter().map(|request| request["model"].clone()).collect();
+    assert_eq!(models, ["mock-model", "strong-model", "mock-model"]);
+    mock.request(1).contains("Hi").contains("Hello!");
+    assert_eq!(chat.base.api_name, "override-api");
+
+    // 每条回答记录实际使用的模型
+    // Every answer records the model actually used
+    let mut session = chat.base.session.clone();
+    let answer_models: Vec<_> = (1..=3)
+        .map(|turn| {
+            let message = session.get_node_by_path(&chat.base.session.default_path[..turn * 2]).unwrap();
+            assert_eq!(message.role, Role::Assistant);
+            message.metadata.as_ref().unwrap().model.clone()
+        })
+        .collect();
+    format_test_block("Answer Models", || format!("{:?}", answer_models));
+    assert_eq!(answer_models, ["mock-model", "strong-model", "mock-model"]);
+
+    // 未知的API在请求前报错，对话保持原样
+    // An unknown API fails before any request and leaves the chat as it was
+    assert!(chat.get_answer_with_model("Hi", ModelOverride::Api("missing-api".to_string())).await.is_err());
+    assert_eq!(chat.base.session.default_path.len(), 6);
+}
@@ -0,0 +1,91 @@
+use serde_json::json;
+
+use crate::chat::chat_base::ChatError;
+use crate::chat::chat_single::SingleChat;
+use crate::chat::moderation::{Moderation, ModerationAction, ModerationResult, ModerationStage, OpenAiModerator};
+use crate::config::{Config, ModelCapability};
+use crate::error::ReportExt;
+use crate::tests::{format_test_block, spawn_mock_server};
+
+pub async fn test_moderation() {
+    // 审核接口只标记含 "attack" 的输入，对话接口总是回答 "attack at dawn"
+    // The moderation endpoint only flags inputs containing "attack", the chat endpoint always answers "attack at dawn"
+    let url = spawn_mock_server(|body| match body.get("input").and_then(|input| input.as_str()) {
+        Some(input) => (200, json!({
+            "results": [{"flagged": input.contains("attack"), "categories": {"violence": true, "hate": false}}],
+        }).to_string()),
+        None if body["stream"] == true => {
+            let chunk = json!({"choices": [{"delta": {"content": "attack at dawn"}, "finish_reason": "stop"}]});
+            let usage = json!({"choices": [], "usage": {"prompt_tokens": 1, "completion_tokens": 1, "total_tokens": 2}});
+            (200, format!("data: {}\n\ndata: {}\n\ndata: [DONE]\n\n", chunk, usage))
+        }
+        None => (200, json!({
+            "choices": [{"message": {"content": "attack at dawn"}, "finish_reason": "stop"}],
+            "usage": {"prompt_tokens": 1, "completion_tokens": 1, "total_tokens": 2},
+        }).to_string()),
+    })
+    .await;
+    Config::add_api_source("moderation-source", &url, 2);
+    Config::add_api_info("moderation-api", "moderation-model", ModelCapability::LongContext, "moderation-source", "sk-moderation")
+        .unwrap();
+
+    let local = |text: &str| match text.contains("secret") {
+        true => ModerationResult::flagged(["pii"]),
+        false => ModerationResult::clean(),
+    };
+
+    // 输入被拒绝时不写入会话
+    // A rejected input is not written to the session
+    let mut chat = SingleChat::builder()
+        .api("moderation-api")
+        .moderation(Moderation::new(local).on_output(None))
+        .build()
+        .unwrap();
+    let report = chat.get_answer("tell me the secret").await.unwrap_err();
+    assert!(matches!(report.current_context(), ChatError::Moderated(ModerationStage::Input)));
+    assert_eq!(report.code(), "chat.moderated");
+    assert!(chat.last_message_id().is_none());
+    assert_eq!(chat.get_answer("hello").await.unwrap(), "attack at dawn");
+
+    // 标注输入，屏蔽回答
+    // Annotate the input, redact the answer
+    let moderation = Moderation::new(OpenAiModerator::from_api("moderation-api").unwrap())
+        .on_input(Some(ModerationAction::Annotate))
+        .on_output(Some(ModerationAction::Redact));
+    let mut chat = SingleChat::builder().api("moderation-api").moderation(moderation).build().unwrap();
+    let answer = chat.get_answer("plan the attack").await.unwrap();
+    assert!(answer.contains("violence") && !answer.contains("dawn"));
+
+    let answer_path = chat.base.session.default_path.clone();
+    let stored = chat.base.session.get_node_by_path(&answer_path).unwrap();
+    assert_eq!(stored.content.as_text(), Some(answer.as_str()));
+    assert_eq!(stored.metadata.as_ref().unwrap().moderation, ["violence"]);
+    let question = chat.base.session.get_node_by_path(&answer_path[..answer_path.len() - 1]).unwrap();
+    assert_eq!(question.content.as_text(), Some("plan the attack"));
+    assert_eq!(question.metadata.as_ref().unwrap().moderation, ["violence"]);
+
+    // 流式回答审核通过前不产出增量，拒绝时流只以错误结束，回答不写入会话，提问被撤回
+    // A streamed answer yields no deltas before it passes moderation, a rejection only ends the stream with an
+    // error, the answer is not written to the session and the question is withdrawn
+    let moderation = Moderation::new(OpenAiModerator::from_api("moderation-api").unwrap()).on_input(None);
+    let mut chat = SingleChat::builder().api("moderation-api").moderation(moderation).build().unwrap();
+    let results: Vec<_> = futures::StreamExt::collect(chat.stream_answer("hello").await.unwrap()).await;
+    assert_eq!(results.len(), 1);
+    let last = results.last().unwrap().as_ref().unwrap_err();
+    assert!(matches!(last.current_context(), ChatError::Moderated(ModerationStage::Output)));
+    assert!(chat.last_message_id().is_none());
+
+    // 屏蔽时流只产出写入会话的屏蔽说明
+    // When redacted, the stream only yields the notice written to the session
+    let moderation = Moderation::new(OpenAiModerator::from_api("moderation-api").unwrap())
+        .on_input(None)
+        .on_output(Some(ModerationAction::Redact));
+    let mut chat = SingleChat::builder().api("moderation-api").moderation(moderation).build().unwrap();
+    let deltas: Vec<_> = futures::StreamExt::collect(chat.stream_answer("hello").await.unwrap()).await;
+    let deltas: Vec<String> = deltas.into_iter().map(Result::unwrap).collect();
+    assert_eq!(deltas.len(), 1);
+    assert!(deltas[0].contains("violence") && !deltas[0].contains("dawn"));
+    assert_eq!(chat.base.session.last_message_mut().unwrap().content.as_text(), Some(deltas[0].as_str()));
+
+    format_test_block("moderation", || format!("{}\n{:?}", answer, chat.base.moderation));
+}
@@ -0,0 +1,85 @@
+use serde_json::json;
+
+use crate::chat::image::ImagePart;
+use crate::chat::ocr::{BlockKind, OcrText, OcrTools};
+use crate::chat::tool_source::ToolSource;
+use crate::config::Config;
+use crate::config::prompts::PromptKey;
+use crate::testing::MockProvider;
+use crate::tests::format_test_block;
+
+/// 只有 PNG 文件头的图片
+/// Image made of nothing but a PNG header
+const PNG: &str = "data:image/png;base64,iVBORw0KGgo=";
+
+pub async fn test_ocr() {
+    let mock = MockProvider::start("ocr-api").await;
+    let tools = OcrTools::with_api("ocr-api").namespace("vision");
+    let listed = tools.list_tools().await.unwrap();
+    assert_eq!(listed[0]["function"]["name"], "ocr_image");
+    assert_eq!(listed[0]["function"]["parameters"]["required"], json!(["image"]));
+
+    // 模型按块返回文字，未知的类型归为 other
+    // The model returns the text in blocks, unknown kinds become other
+    mock.reply(
+        "```json\n{\"blocks\": [{\"kind\": \"heading\", \"text\": \"Invoice 42\"}, \
+        {\"kind\": \"table\", \"text\": \"| item | qty |\\n|---|---|\\n| tea | 2 |\"}, \
+        {\"kind\": \"stamp\", \"text\": \"PAID\"}]}\n```",
+    );
+    let output = tools.call_tool("ocr_image", json!({"image": PNG})).await.unwrap();
+    let recognized: OcrText = serde_json::from_value(output).unwrap();
+    let kinds: Vec<BlockKind> = recognized.blocks.iter().map(|block| block.kind).collect();
+    assert_eq!(kinds, [BlockKind::Heading, BlockKind::Table, BlockKind::Other]);
+    assert!(recognized.text.starts_with("Invoice 42\n\n| item | qty |"));
+    assert_eq!(recognized.engine, "ocr-api");
+    mock.last_request().contains("你是文字识别引擎").contains(PNG).message_count(2);
+
+    // 输出不是 JSON 时整体作为一个段落
+    // Output that is not JSON becomes a single paragraph
+    mock.reply("Closed on Sundays");
+    let plain = tools.clone().language("English").recognize(ImagePart::from_url(PNG).unwrap()).await.unwrap();
+    assert_eq!(plain.blocks.len(), 1);
+    assert_eq!(plain.blocks[0].kind, BlockKind::Paragraph);
+    assert_eq!(plain.text, "Closed on Sundays");
+    mock.last_request().contains("文字的语言：English");
+
+    // 文件路径只在设置了目录时允许，且不能离开目录
+    // File paths are only allowed with a directory set, and must not leave it
+    let dir = std::env::temp_dir().join("rhine_ocr_test");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("scan.png"), b"\x89PNG\r\n\x1a\n").unwrap();
+    std::fs::write(std::env::temp_dir().join("rhine_ocr_outside.png"), b"\x89PNG\r\n\x1a\n").unwrap();
+    assert!(tools.call_tool("ocr_image", json!({"image": "scan.png"})).await.is_err());
+    let tools = OcrTools::with_api("ocr-api").files(&dir);
+    assert!(tools.call_tool("ocr_image", json!({"image": "../rhine_ocr_outside.png"})).await.is_err());
+    mock.reply("{\"blocks\": [{\"kind\": \"caption\", \"text\": \"Figure 1\"}]}");
+    let output = tools.call_tool("ocr_image", json!({"image": "scan.png"})).await.unwrap();
+    assert_eq!(output["blocks"][0]["kind"], "caption");
+    assert_eq!(mock.requests().len(), 3);
+
+    // 指令、请求与工具说明都可以按提示词键替换
+    // The instruction, the request and the tool descriptions can all be replaced by prompt key
+    let prompts = [
+        (PromptKey::OcrInstruction, "Transcribe the image as JSON blocks."),
+        (PromptKey::OcrRequestWithLanguage, "Read the text, it is in {}."),
+        (PromptKey::OcrToolDescription, "Read the text of an image"),
+        (PromptKey::OcrImageArgumentWithFiles, "Image URL or path in the files directory"),
+    ];
+    for (key, prompt) in prompts {
+        Config::set_capability_prompt(key, prompt);
+    }
+    let listed = tools.list_tools().await.unwrap();
+    assert_eq!(listed[0]["function"]["description"], "Read the text of an image");
+    assert_eq!(listed[0]["function"]["parameters"]["properties"]["image"]["description"], prompts[3].1);
+    mock.reply("Closed on Sundays");
+    tools.language("English").recognize(ImagePart::from_url(PNG).unwrap()).await.unwrap();
+    mock.last_request()
+        .contains("Transcribe the image as JSON blocks.")
+        .contains("Read the text, it is in English.")
+        .not_contains("文字识别");
+    for (key, _) in prompts {
+        Config::remove_capability_prompt(&key);
+    }
+
+    format_test_block("ocr", || mock.request(0).prompt());
+}
@@ -0,0 +1,95 @@
+use std::sync::Arc;
+
+use futures::StreamExt;
+use serde_json::json;
+
+use crate::chat::chat_single::SingleChat;
+use crate::chat::output_cap::{CapCounter, OutputCap, Truncated};
+use crate::config::{Config, ModelCapability};
+use crate::tests::{format_test_block, spawn_mock_server};
+use crate::utils::common::tokens::Heuristic;
+
+pub async fn test_output_cap() {
+    test_output_cap_counting();
+    test_output_cap_in_chat().await;
+}
+
+fn test_output_cap_counting() {
+    let mut content = "你好，世界".to_string();
+    assert_eq!(OutputCap::new().max_bytes(7).truncate(&mut content, None), Some(Truncated::Bytes(7)));
+    assert_eq!(content, "你好");
+
+    let mut content = "abcdefghij".to_string();
+    assert_eq!(OutputCap::new().max_tokens(2).truncate(&mut content, Some(4)), Some(Truncated::Tokens(2)));
+    assert_eq!(content, "abcde");
+    assert_eq!(OutputCap::new().max_tokens(8).truncate(&mut content, Some(4)), None);
+
+    let mut counter = CapCounter::new(OutputCap::new().max_tokens(2));
+    assert_eq!(counter.admit("a".to_string()), "a");
+    assert_eq!(counter.admit("b".to_string()), "b");
+    assert!(counter.truncated.is_none());
+    assert_eq!(counter.admit("c".to_string()), "");
+    assert_eq!(counter.truncated, Some(Truncated::Tokens(2)));
+
+    // 一段多个 token 的增量越过上限时按比例截断："abcdefgh" 估算为两个 token
+    // A multi-token delta crossing the cap is cut proportionally: "abcdefgh" is estimated at two tokens
+    let mut counter = CapCounter::new(OutputCap::new().max_tokens(3)).tokenizer(Arc::new(Heuristic));
+    assert_eq!(counter.admit("abcdefgh".to_string()), "abcdefgh");
+    assert_eq!(counter.admit("ijklmnop".to_string()), "ijkl");
+    assert_eq!(counter.truncated, Some(Truncated::Tokens(3)));
+
+    let mut counter = CapCounter::new(OutputCap::new().max_bytes(4));
+    assert_eq!(counter.admit("ab".to_string()), "ab");
+    assert_eq!(counter.admit("cdef".to_string()), "cd");
+    assert_eq!(counter.admit("g".to_string()), "");
+
+    format_test_block("output_cap", || format!("{:?}", counter.truncated));
+}
+
+async fn test_output_cap_in_chat() {
+    // 回答为十段 "0123456789"，每段估算为三个 token
+    // The answer is ten pieces of "0123456789", each estimated at three tokens
+    let url = spawn_mock_server(|body| {
+        if body["stream"] == true {
+            let mut events: String = (0..10)
+                .map(|_| format!("data: {}\n\n", json!({"choices": [{"delta": {"content": "0123456789"}}]})))
+                .collect();
+            events.push_str("data: [DONE]\n\n");
+            (200, events)
+        } else {
+            (200, json!({
+                "choices": [{"message": {"content": "0123456789".repeat(10)}, "finish_reason": "stop"}],
+                "usage": {"prompt_tokens": 1, "completion_tokens": 10, "total_tokens": 11},
+            }).to_string())
+        }
+    })
+    .await;
+    Config::add_api_source("cap-source", &url, 2);
+    Config::add_api_info("cap-api", "cap-model", ModelCapability::LongContext, "cap-source", "sk-cap").unwrap();
+
+    let mut chat = SingleChat::builder().api("cap-api").output_cap(OutputCap::new().max_bytes(25)).build().unwrap();
+    assert_eq!(chat.get_answer("count").await.unwrap(), "0123456789012345678901234");
+    let stored = chat.base.session.last_message_mut().unwrap();
+    assert_eq!(stored.metadata.as_ref().unwrap().truncated, Some(Truncated::Bytes(25)));
+
+    let mut chat = SingleChat::builder()
+        .api("cap-api")
+        .output_cap(OutputCap::new().max_tokens(7))
+        .build()
+        .unwrap();
+    let deltas: Vec<String> = chat.stream_answer("count").await.unwrap().map(Result::unwrap).collect().await;
+    assert_eq!(deltas.len(), 3);
+    let stored = chat.base.session.last_message_mut().unwrap();
+    assert_eq!(stored.content.as_text(), Some("01234567890123456789012"));
+    assert_eq!(stored.metadata.as_ref().unwrap().truncated, Some(Truncated::Tokens(7)));
+
+    // 非流式接口下的流式请求同样中止
+    // Streamed requests behind the non-streaming interface are aborted as well
+    let mut chat = SingleChat::builder()
+        .api("cap-api")
+        .stream(true)
+        .output_cap(OutputCap::new().max_tokens(2))
+        .build()
+        .unwrap();
+    assert_eq!(chat.get_answer("count").await.unwrap(), "012345");
+}
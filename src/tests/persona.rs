@@ -0,0 +1,84 @@
+use crate::chat::chat_base::ChatError;
+use crate::chat::chat_multi::MultiChat;
+use crate::chat::persona::load_personas;
+use crate::chat::transcript::Perspective;
+use crate::error::ReportExt;
+use crate::testing::MockProvider;
+use crate::tests::format_test_block;
+
+pub async fn test_personas() {
+    let mock = MockProvider::start("persona-api").await;
+    let dir = std::env::temp_dir().join(format!("rhine_personas_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("alice.toml"),
+        r#"
+name = "alice"
+system = "You are Alice, a tea merchant."
+voice = "Warm, fond of proverbs."
+api = "persona-api"
+
+[[examples]]
+user = "What do you sell?"
+reply = "Tea, friend."
+"#,
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("bob.json"),
+        r#"{"name": "bob", "system": "You are Bob, a barista.", "api": "persona-api"}"#,
+    )
+    .unwrap();
+    std::fs::write(dir.join("notes.txt"), "not a persona").unwrap();
+
+    // 一次调用创建整个角色阵容，人设的语气与示例对话随系统提示词发送
+    // One call creates the whole cast, the persona's voice and example dialogues go out with the system prompt
+    let mut cast = MultiChat::from_personas(&dir, false).unwrap();
+    assert_eq!(cast.characters(), ["alice", "bob"]);
+    assert_eq!(cast.persona("alice").unwrap().examples[0].reply, "Tea, friend.");
+    mock.reply("Tea, always.").reply("Coffee, obviously.").reply("Agreed to disagree.");
+    cast.set_character("alice").unwrap();
+    assert_eq!(cast.get_answer("Is tea better than coffee?").await.unwrap(), "Tea, always.");
+    mock.last_request()
+        .contains("You are Alice, a tea merchant.")
+        .contains("说话风格：Warm, fond of proverbs.")
+        .contains("alice: Tea, friend.")
+        .contains("Is tea better than coffee?");
+
+    // 角色共用一段对话，轮到的角色听到此前的全部发言，别人的话标明发言者
+    // The characters share one conversation, the one whose turn it is hears everything said before, with other
+    // characters' lines labelled
+    assert_eq!(cast.turn("bob").await.unwrap(), "Coffee, obviously.");
+    mock.last_request()
+        .contains("You are Bob, a barista.")
+        .not_contains("You are Alice")
+        .contains("Is tea better than coffee?")
+        .contains("alice said: Tea, always.");
+    assert_eq!(cast.turn("bob").await.unwrap(), "Agreed to disagree.");
+    let observer = cast.transcript(&Perspective::Observer).unwrap();
+    format_test_block("Persona Cast", || cast.observer_markdown());
+
+    mock.reply("Only the two of us.").reply("Indeed.");
+    assert_eq!(cast.round().await.unwrap(), ["Only the two of us.", "Indeed."]);
+    mock.request(3).contains("bob said: Coffee, obviously.").contains("bob said: Agreed to disagree.");
+    assert_ne!(cast.transcript(&Perspective::Observer).unwrap(), observer);
+
+    let unknown = cast.turn("carol").await.unwrap_err();
+    assert!(matches!(unknown.current_context(), ChatError::UndefinedCharacter(name) if name == "carol"));
+
+    // 人设也可以单独成为一个对话
+    // A persona can also become a chat of its own
+    let mut alice = cast.persona("alice").unwrap().chat().unwrap();
+    mock.reply("Tea, friend.");
+    alice.get_answer("Hello").await.unwrap();
+    mock.last_request().contains("You are Alice, a tea merchant.").not_contains("Bob");
+    assert_eq!(mock.pending(), 0);
+
+    // 角色名称重复或人设无效时加载失败
+    // Loading fails on repeated character names or invalid personas
+    std::fs::write(dir.join("copy.json"), r#"{"name": "bob", "system": "Another Bob"}"#).unwrap();
+    assert_eq!(load_personas(&dir).unwrap_err().code(), "chat.persona");
+    std::fs::write(dir.join("copy.json"), r#"{"system": "Nameless"}"#).unwrap();
+    assert_eq!(load_personas(&dir).unwrap_err().code(), "chat.persona");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
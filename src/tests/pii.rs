@@ -0,0 +1,92 @@
+use futures::StreamExt;
+use serde_json::json;
+
+use crate::chat::chat_single::SingleChat;
+use crate::chat::message::Role;
+use crate::chat::pii::{PiiEntity, PiiScrubber};
+use crate::config::{Config, ModelCapability};
+use crate::tests::{format_test_block, spawn_mock_server};
+
+pub async fn test_pii() {
+    test_scrub_and_restore().await;
+    test_pii_in_chat().await;
+}
+
+async fn test_scrub_and_restore() {
+    let recognizer = |text: &str| match text.find("Alice") {
+        Some(start) => vec![PiiEntity { start, end: start + 5, label: "PERSON".to_string() }],
+        None => Vec::new(),
+    };
+    let scrubber = PiiScrubber::new()
+        .with_pattern("EMPLOYEE_ID", r"EMP-\d{4}")
+        .unwrap()
+        .with_recognizer(recognizer);
+    assert!(PiiScrubber::new().with_pattern("lower", "x").is_err());
+
+    let text = "Alice (EMP-0042) mails alice@example.com or bob@example.com, calls +1 (555) 123-4567, \
+                pays with 4111 1111 1111 1111, order 1234567890123";
+    let scrubbed = scrubber.scrub(text).await.unwrap();
+    assert_eq!(
+        scrubbed,
+        "<PERSON_1> (<EMPLOYEE_ID_1>) mails <EMAIL_1> or <EMAIL_2>, calls <PHONE_1>, pays with <CARD_1>, \
+         order 1234567890123"
+    );
+    assert_eq!(scrubber.restore(&scrubbed), text);
+
+    // 同一原文再次出现时沿用占位符，未知占位符保持不变
+    // The same original reuses its placeholder, unknown placeholders are left as is
+    assert_eq!(scrubber.scrub("cc bob@example.com").await.unwrap(), "cc <EMAIL_2>");
+    assert_eq!(scrubber.restore("<EMAIL_9> <EMAIL_1>"), "<EMAIL_9> alice@example.com");
+
+    format_test_block("pii_scrub", || scrubbed.clone());
+}
+
+async fn test_pii_in_chat() {
+    // 回答复述最后一条用户消息，流式时把内容切成两个字符一段
+    // Answer by repeating the last user message, split into two-character pieces when streaming
+    let url = spawn_mock_server(|body| {
+        let request = body.to_string();
+        assert!(!request.contains("@example.com"), "PII left the process: {}", request);
+        let last = body["messages"].as_array().unwrap().last().unwrap()["content"].as_str().unwrap_or("").to_string();
+        let answer = format!("noted: {}", last);
+        if body["stream"] == true {
+            let chars: Vec<char> = answer.chars().collect();
+            let mut events: String = chars
+                .chunks(2)
+                .map(|piece| {
+                    let chunk = json!({"choices": [{"delta": {"content": piece.iter().collect::<String>()}}]});
+                    format!("data: {}\n\n", chunk)
+                })
+                .collect();
+            let usage = json!({"choices": [], "usage": {"prompt_tokens": 1, "completion_tokens": 1, "total_tokens": 2}});
+            events.push_str(&format!("data: {}\n\ndata: [DONE]\n\n", usage));
+            (200, events)
+        } else {
+            (200, json!({
+                "choices": [{"message": {"content": answer}, "finish_reason": "stop"}],
+                "usage": {"prompt_tokens": 1, "completion_tokens": 1, "total_tokens": 2},
+            }).to_string())
+        }
+    })
+    .await;
+    Config::add_api_source("pii-source", &url, 2);
+    Config::add_api_info("pii-api", "pii-model", ModelCapability::LongContext, "pii-source", "sk-pii").unwrap();
+
+    let mut chat = SingleChat::builder().api("pii-api").pii_scrubber(PiiScrubber::new()).build().unwrap();
+    let answer = chat.get_answer("write to carol@example.com").await.unwrap();
+    assert_eq!(answer, "noted: write to carol@example.com");
+
+    // 会话中只保存占位符
+    // The session only stores placeholders
+    let end_path = chat.base.session.default_path.clone();
+    let context = chat.base.session.assemble_context(&end_path, &Role::Assistant).unwrap();
+    assert_eq!(context.last().unwrap().content.as_text(), Some("noted: write to <EMAIL_1>"));
+
+    let mut deltas = Vec::new();
+    let mut stream = Box::pin(chat.stream_answer("and dave@example.com").await.unwrap());
+    while let Some(delta) = stream.next().await {
+        deltas.push(delta.unwrap());
+    }
+    assert_eq!(deltas.concat(), "noted: and dave@example.com");
+    assert!(deltas.iter().any(|delta| delta.contains("dave@example.com")));
+}
@@ -0,0 +1,71 @@
+use crate::chat::chat_base::ChatError;
+use crate::chat::chat_single::SingleChat;
+use crate::chat::postprocess::{JsonOnly, NormalizeMarkdown, PostProcessor, PostProcessors, StripReasoning};
+use crate::testing::MockProvider;
+use crate::tests::format_test_block;
+use futures::StreamExt;
+
+pub async fn test_post_processors() {
+    test_processors();
+
+    // 后处理后的回答被返回并写入会话
+    // The processed answer is returned and stored in the session
+    let mock = MockProvider::start("postprocess-api").await;
+    let mut chat = SingleChat::builder()
+        .api("postprocess-api")
+        .post_processors(PostProcessors::plain_text())
+        .build()
+        .unwrap();
+    mock.reply("<think>The user wants a greeting.</think>\n\n## Hello\n\nNice to **meet** you!  \n");
+    let answer = chat.get_answer("Hi").await.unwrap();
+    assert_eq!(answer, "Hello\n\nNice to meet you!");
+    mock.reply("Again.");
+    chat.get_answer("Once more").await.unwrap();
+    mock.last_request().contains("assistant: Hello\n\nNice to meet you!").not_contains("<think>");
+
+    // 要求只输出 JSON 而回答中没有 JSON 时返回错误
+    // JSON only fails when the answer has no JSON
+    chat.set_post_processors(PostProcessors::json_only());
+    mock.reply("Sure! Here it is:\n```json\n{\"city\": \"Paris\", \"tags\": [\"a\", \"b\"]}\n```\nAnything else?");
+    assert_eq!(chat.get_answer("City?").await.unwrap(), "{\"city\": \"Paris\", \"tags\": [\"a\", \"b\"]}");
+    mock.reply("I cannot answer that.");
+    let failed = chat.get_answer("Country?").await.unwrap_err();
+    assert!(matches!(failed.current_context(), ChatError::PostProcessError(_)));
+
+    // 流式回答先经后处理再产出，处理失败时不产出内容，并撤回没有回答的提问
+    // Streamed answers are post-processed before they are yielded; when processing fails nothing is yielded and the
+    // unanswered question is withdrawn
+    let mut chat = SingleChat::builder()
+        .api("postprocess-api")
+        .post_processors(PostProcessors::json_only())
+        .build()
+        .unwrap();
+    mock.reply("Here: {\"city\": \"Rome\"} hope it helps");
+    let deltas: Vec<String> = chat.stream_answer("City?").await.unwrap().map(Result::unwrap).collect().await;
+    assert_eq!(deltas, vec!["{\"city\": \"Rome\"}"]);
+    let answered = chat.base.session.clone();
+    mock.reply("I cannot answer that.");
+    let results: Vec<_> = chat.stream_answer("Country?").await.unwrap().collect().await;
+    assert_eq!(results.len(), 1);
+    let failed = results.into_iter().next().unwrap().unwrap_err();
+    assert!(matches!(failed.current_context(), ChatError::PostProcessError(_)));
+    assert_eq!(chat.base.session, answered);
+
+    format_test_block("post_processors", || mock.request(1).prompt());
+}
+
+fn test_processors() {
+    let stripped = StripReasoning.process("<thinking>plan</thinking>Answer <think>\nhm\n</think>done").unwrap();
+    assert_eq!(stripped, "Answer done");
+    assert_eq!(StripReasoning.process("half of the plan</think>\nAnswer").unwrap(), "\nAnswer");
+
+    let normalized = NormalizeMarkdown.process("* one  \r\n+ two\n\n\n\n```\n*  kept\n\n\n").unwrap();
+    assert_eq!(normalized, "- one\n- two\n\n```\n*  kept\n\n\n\n```");
+
+    assert_eq!(JsonOnly.process("[1, 2] and {\"a\": 1}").unwrap(), "[1, 2]");
+    assert_eq!(JsonOnly.process("{broken [3]").unwrap(), "[3]");
+    assert!(JsonOnly.process("{broken").is_err());
+
+    let custom = PostProcessors::markdown().with(|answer: &str| answer.to_uppercase());
+    assert_eq!(custom.process("  <think>x</think>\n* hi  \n\n\n* there\n").unwrap(), "- HI\n\n- THERE");
+}
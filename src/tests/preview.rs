@@ -0,0 +1,99 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::chat::chat_single::SingleChat;
+use crate::chat::moderation::{Moderation, ModerationResult};
+use crate::chat::params::ChatParams;
+use crate::chat::preview::Estimate;
+use crate::config::Config;
+use crate::config::metadata::ModelMetadata;
+use crate::testing::MockProvider;
+use crate::tests::format_test_block;
+use crate::utils::common::redact::REDACTED;
+
+/// 审核函数被调用的次数
+/// Number of calls to the moderation classifier
+static MODERATED: AtomicUsize = AtomicUsize::new(0);
+
+pub async fn test_preview() {
+    let mock = MockProvider::start("preview-api").await;
+    mock.reply("Paris.");
+    Config::set_model_metadata("mock-model", ModelMetadata {
+        input_price: Some(1.0),
+        output_price: Some(2.0),
+        ..Default::default()
+    });
+
+    let mut chat = SingleChat::builder()
+        .api("preview-api")
+        .system("Answer briefly.")
+        .params(ChatParams::default().max_tokens(100))
+        .build()
+        .unwrap();
+    let preview = chat.dry_run("What is the capital of France?").await.unwrap();
+    format_test_block("Request Preview", || serde_json::to_string_pretty(&preview).unwrap());
+
+    // 请求未发出，对话历史不变，之后的提问照常进行
+    // Nothing is sent and the history is unchanged, later questions go ahead as usual
+    assert_eq!(mock.requests().len(), 0);
+    assert_eq!(preview.url, mock.url());
+    assert_eq!(preview.model, "mock-model");
+    assert_eq!(preview.body["max_tokens"], 100);
+    assert_eq!(preview.body["messages"].as_array().unwrap().len(), 2);
+    assert!(preview.headers.contains(&("authorization".to_string(), REDACTED.to_string())));
+    assert!(!serde_json::to_string(&preview).unwrap().contains("sk-mock"));
+    assert_eq!(preview.estimated_prompt_tokens, 12);
+    assert_eq!(preview.estimated_cost, Some((12.0 + 200.0) / 1_000_000.0));
+
+    // 预估的费用范围从只计提示词到加上最长回答，可按分词器精确计数，也可按其他模型的价格比较
+    // The estimated cost range goes from the prompt alone to adding the longest answer, the prompt can be counted
+    // exactly by a tokenizer and the cost compared at another model's prices
+    let estimate = chat.estimate("What is the capital of France?").await.unwrap();
+    assert_eq!(estimate.prompt_tokens, 12);
+    assert_eq!(estimate.max_completion_tokens, Some(100));
+    assert_eq!(estimate.cost_range, Some((12.0 / 1_000_000.0, (12.0 + 200.0) / 1_000_000.0)));
+    let bytes = |text: &str| text.bytes().map(u32::from).collect::<Vec<u32>>();
+    let exact = chat.estimate_with("What is the capital of France?", &bytes).await.unwrap();
+    assert_eq!(exact.prompt_tokens, 45);
+    let cheaper = ModelMetadata {
+        context_window: Some(40),
+        input_price: Some(0.5),
+        output_price: Some(1.0),
+        ..Default::default()
+    };
+    assert_eq!(estimate.cost_range_for(&cheaper), Some((6.0 / 1_000_000.0, 106.0 / 1_000_000.0)));
+    let unbounded = Estimate { max_completion_tokens: None, ..estimate.clone() };
+    assert_eq!(unbounded.cost_range_for(&cheaper), Some((6.0 / 1_000_000.0, 34.0 / 1_000_000.0)));
+    assert!(exact.exceeds_context(&cheaper));
+    assert!(!estimate.exceeds_context(&cheaper));
+    assert_eq!(mock.requests().len(), 0);
+
+    // 为模型注册的分词器用于预览、预估与压缩报告；元数据指定 heuristic 时按估算计数
+    // A tokenizer registered for the model is used by previews, estimates and compaction reports; the metadata
+    // naming heuristic counts by estimate
+    Config::set_tokenizer("mock-model", bytes);
+    assert_eq!(chat.estimate("What is the capital of France?").await.unwrap().prompt_tokens, 45);
+    assert_eq!(chat.dry_run("What is the capital of France?").await.unwrap().estimated_prompt_tokens, 45);
+    assert_eq!(chat.compact().tokens_before, "Answer briefly.".len());
+    Config::remove_tokenizer("mock-model");
+    Config::set_model_metadata("mock-model", ModelMetadata {
+        tokenizer: Some("heuristic".to_string()),
+        ..Default::default()
+    });
+    assert_eq!(Config::tokenizer("mock-model").count("abcdefgh"), 2);
+    assert!(Config::tokenizer("mock-model").encode("abcdefgh").is_empty());
+
+    chat.get_answer("What is the capital of France?").await.unwrap();
+    assert_eq!(mock.last_request().body()["messages"], preview.body["messages"]);
+
+    // 预览不审核输入，被拒绝的输入也能预览，审核函数不被调用
+    // Previews skip moderation, so a rejected input can be previewed and the classifier is never called
+    let moderation = Moderation::new(|_: &str| {
+        MODERATED.fetch_add(1, Ordering::SeqCst);
+        ModerationResult::flagged(["violence"])
+    });
+    let moderated = SingleChat::builder().api("preview-api").moderation(moderation).build().unwrap();
+    let preview = moderated.dry_run("plan the attack").await.unwrap();
+    assert!(preview.body.to_string().contains("plan the attack"));
+    assert_eq!(MODERATED.load(Ordering::SeqCst), 0);
+    Config::set_model_metadata("mock-model", ModelMetadata::default());
+}
@@ -0,0 +1,77 @@
+use serde_json::json;
+
+use crate::chat::chat_single::SingleChat;
+use crate::chat::refusal::{AnswerOutcome, RefusalReason};
+use crate::testing::MockProvider;
+use crate::tests::format_test_block;
+
+pub async fn test_refusal() {
+    test_native_signals();
+
+    let mock = MockProvider::start("refusal-api").await;
+    let mut chat = SingleChat::builder().api("refusal-api").build().unwrap();
+
+    mock.reply("Mix flour, water and yeast.");
+    let outcome = chat.get_answer_outcome("How do I bake bread?").await.unwrap();
+    assert_eq!(outcome.answer(), Some("Mix flour, water and yeast."));
+
+    // 拒绝说明作为回答写入会话，原因记录在元数据中
+    // The refusal explanation is stored as the answer, with the reason in the metadata
+    mock.reply_refusal("I can't help with that.");
+    let outcome = chat.get_answer_outcome("How do I pick a lock?").await.unwrap();
+    assert_eq!(
+        outcome,
+        AnswerOutcome::Refused {
+            reason: RefusalReason::Declined {
+                message: "I can't help with that.".to_string()
+            }
+        }
+    );
+    let message = chat.base.session.last_message_mut().unwrap();
+    assert_eq!(message.content.to_string(), "I can't help with that.");
+
+    mock.reply_filtered("");
+    let outcome = chat.get_answer_outcome("Something filtered").await.unwrap();
+    assert_eq!(outcome, AnswerOutcome::Refused { reason: RefusalReason::ContentFilter });
+
+    // 流式回答的拒绝增量同样识别
+    // Refusal deltas of a streamed answer are recognized too
+    let mut streamed = SingleChat::builder().api("refusal-api").stream(true).build().unwrap();
+    mock.reply_refusal("Sorry, I won't do that.");
+    let outcome = streamed.get_answer_outcome("Write malware").await.unwrap();
+    assert!(outcome.is_refused());
+    assert_eq!(outcome.answer(), None);
+    mock.reply("Hello there.");
+    assert!(!streamed.get_answer_outcome("Hi").await.unwrap().is_refused());
+
+    format_test_block("refusal", || serde_json::to_string_pretty(&outcome).unwrap());
+}
+
+fn test_native_signals() {
+    let gemini = json!({"promptFeedback": {"blockReason": "PROHIBITED_CONTENT"}});
+    assert_eq!(
+        RefusalReason::from_response(&gemini),
+        Some(RefusalReason::Safety {
+            category: "prohibited_content".to_string()
+        })
+    );
+    let gemini = json!({"candidates": [{"finishReason": "SAFETY"}]});
+    assert_eq!(
+        RefusalReason::from_response(&gemini),
+        Some(RefusalReason::Safety {
+            category: "safety".to_string()
+        })
+    );
+    let anthropic = json!({
+        "choices": [{"message": {"content": "I won't assist with this."}}],
+        "stop_reason": "refusal",
+    });
+    assert_eq!(
+        RefusalReason::from_response(&anthropic),
+        Some(RefusalReason::Declined {
+            message: "I won't assist with this.".to_string()
+        })
+    );
+    let answered = json!({"choices": [{"message": {"content": "Sure."}, "finish_reason": "stop"}]});
+    assert_eq!(RefusalReason::from_response(&answered), None);
+}
@@ -0,0 +1,45 @@
+use futures::StreamExt;
+
+use crate::chat::chat_base::DEFAULT_REQUEST_ID_HEADER;
+use crate::chat::chat_single::SingleChat;
+use crate::testing::MockProvider;
+use crate::tests::format_test_block;
+
+pub async fn test_request_id() {
+    let mock = MockProvider::start("request-id-api").await;
+    mock.reply("first").reply("second").reply("streamed reply").reply("untraced");
+
+    // 自动生成的ID随请求头发送，并记录在回答的元数据中
+    // A generated ID is sent in the header and recorded in the metadata of the answer
+    let mut chat = SingleChat::builder().api("request-id-api").build().unwrap();
+    chat.get_answer("one").await.unwrap();
+    let generated = chat.base.session.last_message_mut().unwrap().metadata.clone().unwrap().request_id;
+    format_test_block("Generated Request ID", || generated.clone());
+    assert_eq!(mock.request_header(0, DEFAULT_REQUEST_ID_HEADER), Some(generated.clone()));
+    assert_eq!(mock.request_header(0, "x-request-id"), Some(generated.clone()));
+
+    // 指定的ID只用于下一次请求
+    // A given ID is only used for the next request
+    chat.set_request_id("trace-4f2a");
+    chat.get_answer("two").await.unwrap();
+    assert_eq!(mock.request_header(1, DEFAULT_REQUEST_ID_HEADER).as_deref(), Some("trace-4f2a"));
+    assert_eq!(chat.base.session.last_message_mut().unwrap().metadata.as_ref().unwrap().request_id, "trace-4f2a");
+
+    let mut stream = SingleChat::builder()
+        .api("request-id-api")
+        .stream(true)
+        .request_id_header(Some("X-Correlation-Id"))
+        .build()
+        .unwrap();
+    let _: Vec<_> = stream.stream_answer("three").await.unwrap().collect().await;
+    let streamed = mock.request_header(2, "X-Correlation-Id").unwrap();
+    assert_ne!(streamed, "trace-4f2a");
+    assert_ne!(streamed, generated);
+    assert_eq!(mock.request_header(2, DEFAULT_REQUEST_ID_HEADER), None);
+    assert_eq!(stream.base.session.last_message_mut().unwrap().metadata.as_ref().unwrap().request_id, streamed);
+
+    let mut chat = SingleChat::builder().api("request-id-api").request_id_header(None).build().unwrap();
+    chat.get_answer("four").await.unwrap();
+    assert_eq!(mock.request_header(3, DEFAULT_REQUEST_ID_HEADER), None);
+    assert_eq!(mock.pending(), 0);
+}
@@ -0,0 +1,155 @@
+use crate::tests::format_test_block;
+
+pub async fn test_resume() {
+    #[cfg(feature = "server")]
+    test_resume_tokens().await;
+    #[cfg(all(test, feature = "server"))]
+    test_resume_over_websocket().await;
+    format_test_block("Resume", || "session resumption tokens".to_string());
+}
+
+#[cfg(feature = "server")]
+async fn test_resume_tokens() {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use crate::chat::message::{Role, Session};
+    use crate::chat::store::{MemorySessionStore, StoreError};
+    use crate::error::ReportExt;
+    use crate::server::resume::{ResumeError, Resumption};
+
+    // 两个副本共用存储与密钥，任一副本签发的令牌都能在另一副本上恢复
+    // Two replicas share the store and secret, a token issued by either resumes on the other
+    let store = Arc::new(MemorySessionStore::default());
+    let first = Resumption::new(store.clone(), "shared-secret");
+    let second = Resumption::new(store.clone(), "shared-secret");
+    let mut session = Session::new();
+    session.add_with_default_path(Role::System, "be brief").unwrap();
+    let mut conversation = first.start(&session).await.unwrap();
+    let token = first.issue(&conversation.id);
+    assert_eq!(second.verify(&token).unwrap(), conversation.id);
+
+    session.add_with_default_path(Role::User, "hello").unwrap();
+    first.save(&mut conversation, &session).await.unwrap();
+    let (resumed, stored) = second.resume(&token).await.unwrap();
+    assert_eq!(resumed.id, conversation.id);
+    assert_eq!(resumed.revision, 2);
+    assert_eq!(stored.last_message_id(), session.last_message_id());
+
+    // 过期的对话写入被拒绝，不会覆盖另一副本的保存
+    // A write based on a stale revision is refused instead of clobbering the other replica's save
+    let mut stale = resumed.clone();
+    second.save(&mut stale.clone(), &session).await.unwrap();
+    let conflict = second.save(&mut stale, &session).await.unwrap_err();
+    assert!(matches!(conflict.current_context(), StoreError::Conflict { .. }));
+
+    // 篡改、换了密钥或过期的令牌都被拒绝
+    // Tampered tokens, tokens of another secret and expired tokens are refused
+    let (payload, signature) = token.split_once('.').unwrap();
+    let forged = format!("{}x.{}", payload, signature);
+    assert_eq!(first.verify(&forged).unwrap_err().code(), "server.invalid_resume_token");
+    assert!(first.verify("not a token").is_err());
+    let other = Resumption::new(store.clone(), "other-secret");
+    assert!(matches!(other.verify(&token).unwrap_err().current_context(), ResumeError::InvalidToken));
+    let expired = Resumption::new(store.clone(), "shared-secret").ttl(Duration::ZERO);
+    let token = expired.issue(&conversation.id);
+    assert_eq!(first.verify(&token).unwrap_err().code(), "server.resume_token_expired");
+
+    // 对话被清理后令牌不再可用
+    // Tokens stop working once the conversation is cleaned up
+    let token = first.issue("missing-conversation");
+    let unknown = first.resume(&token).await.unwrap_err();
+    assert!(matches!(unknown.current_context(), ResumeError::UnknownConversation(_)));
+}
+
+#[cfg(all(test, feature = "server"))]
+async fn test_resume_over_websocket() {
+    use std::sync::Arc;
+
+    use futures::{SinkExt, StreamExt};
+    use serde_json::{Value, json};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio_tungstenite::tungstenite::Message;
+    use tokio_tungstenite::{MaybeTlsStream, WebSocketStream, connect_async};
+
+    use crate::chat::store::{MemorySessionStore, SessionStore};
+    use crate::server::admission::Admission;
+    use crate::server::resume::Resumption;
+    use crate::server::websocket::router_with_resumption;
+    use crate::testing::MockProvider;
+
+    type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+    async fn send(socket: &mut Socket, frame: Value) {
+        socket.send(Message::text(frame.to_string())).await.unwrap();
+    }
+
+    async fn receive(socket: &mut Socket) -> Value {
+        loop {
+            if let Message::Text(text) = socket.next().await.unwrap().unwrap() {
+                return serde_json::from_str(&text).unwrap();
+            }
+        }
+    }
+
+    let mock = MockProvider::start("resume-ws-api").await;
+    let store = Arc::new(MemorySessionStore::default());
+    let resumption = Resumption::new(store.clone(), "ws-secret");
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}/ws", listener.local_addr().unwrap());
+    let router = router_with_resumption(Admission::default(), None, resumption.clone());
+    tokio::spawn(async move { axum::serve(listener, router).await });
+
+    let open = |resume: Option<&str>| json!({
+        "type": "open", "session": "main", "api": "resume-ws-api", "stream": false, "resume": resume,
+    });
+    let message = |content: &str| json!({"type": "message", "session": "main", "content": content});
+
+    // 打开会话即保存对话并签发令牌，每次回答后保存
+    // Opening a session saves the conversation and issues a token, and every answer is saved
+    let (mut socket, _) = connect_async(&url).await.unwrap();
+    send(&mut socket, open(None)).await;
+    let opened = receive(&mut socket).await;
+    assert_eq!(opened["type"], "opened");
+    let token = opened["resume_token"].as_str().unwrap().to_string();
+    let id = resumption.verify(&token).unwrap();
+    assert_eq!(store.load(&id).await.unwrap().unwrap().revision, 1);
+
+    mock.reply("Hi there.");
+    send(&mut socket, message("hello")).await;
+    assert_eq!(receive(&mut socket).await["content"], "Hi there.");
+    let stored = store.load(&id).await.unwrap().unwrap();
+    assert_eq!(stored.revision, 2);
+    assert!(serde_json::to_string(&stored.session).unwrap().contains("Hi there."));
+    drop(socket);
+
+    // 新连接凭令牌继续同一对话
+    // A new connection continues the same chat with the token
+    let (mut socket, _) = connect_async(&url).await.unwrap();
+    send(&mut socket, open(Some(&token))).await;
+    assert_eq!(receive(&mut socket).await["type"], "opened");
+    mock.reply("Still here.");
+    send(&mut socket, message("again")).await;
+    assert_eq!(receive(&mut socket).await["content"], "Still here.");
+    mock.last_request().contains("user: hello").contains("assistant: Hi there.").contains("user: again");
+    assert_eq!(store.load(&id).await.unwrap().unwrap().revision, 3);
+
+    // 同一对话在两个连接上打开时后保存的一方收到冲突帧，会话随即关闭
+    // With the chat open on two connections the later save gets a conflict frame, and its session is closed
+    let (mut other, _) = connect_async(&url).await.unwrap();
+    send(&mut other, open(Some(&token))).await;
+    assert_eq!(receive(&mut other).await["type"], "opened");
+    mock.reply("First.").reply("Second.");
+    send(&mut socket, message("one")).await;
+    assert_eq!(receive(&mut socket).await["content"], "First.");
+    send(&mut other, message("two")).await;
+    assert_eq!(receive(&mut other).await["content"], "Second.");
+    let conflict = receive(&mut other).await;
+    assert_eq!((&conflict["type"], &conflict["code"]), (&json!("error"), &json!("store.conflict")));
+    assert_eq!(receive(&mut other).await["type"], "closed");
+    send(&mut other, message("three")).await;
+    assert_eq!(receive(&mut other).await["code"], "server.unknown_session");
+
+    let stored = serde_json::to_string(&store.load(&id).await.unwrap().unwrap().session).unwrap();
+    assert!(stored.contains("First.") && !stored.contains("Second."));
+}
@@ -0,0 +1,98 @@
+use std::fs::File;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use error_stack::Report;
+
+use crate::chat::message::{Role, Session};
+use crate::chat::retention::{DirectoryArchiver, Retention, RetentionPolicy};
+use crate::chat::store::{DirectorySessionStore, MemorySessionStore, SessionStore, StoreError, StoredSession};
+use crate::chat::tenant::Tenant;
+use crate::tests::format_test_block;
+use crate::utils::common::file_name::encode_file_name;
+
+const DAY: Duration = Duration::from_secs(86400);
+
+fn session(text: &str) -> Session {
+    let mut session = Session::new();
+    session.add_with_default_path(Role::User, text).unwrap();
+    session
+}
+
+pub async fn test_retention() {
+    let directory = std::env::temp_dir().join(format!("rhine_retention_{}", std::process::id()));
+    let store = Arc::new(DirectorySessionStore::new(directory.join("sessions")).unwrap());
+    let now = SystemTime::now();
+    for (id, age) in [("acme/1", 3), ("acme/2", 1), ("acme/3", 0), ("globex/1", 1), ("globex/2", 0), ("loose", 1)] {
+        store.save(id, &session(id), 0).await.unwrap();
+        let path = directory.join("sessions").join(format!("{}.json", encode_file_name(id)));
+        File::options().write(true).open(path).unwrap().set_modified(now - DAY * age).unwrap();
+    }
+    let mut listed: Vec<String> = store.list().await.unwrap().into_iter().map(|entry| entry.id).collect();
+    listed.sort();
+    assert_eq!(listed, ["acme/1", "acme/2", "acme/3", "globex/1", "globex/2", "loose"]);
+
+    // 过期的对话与超出租户上限的较早对话被归档后删除
+    // Expired conversations and the older ones over a tenant's cap are archived, then deleted
+    let policy = RetentionPolicy::new().max_age(DAY * 2).per_tenant(1).tenant_quota(&Tenant::new("globex"), 2);
+    let retention =
+        Retention::new(store.clone(), policy).archiver(DirectoryArchiver::new(directory.join("archive")).unwrap());
+    let report = retention.sweep().await.unwrap();
+    assert_eq!(report.examined, 6);
+    assert_eq!(report.removed, ["acme/1", "acme/2"]);
+    assert_eq!(report.archived, 2);
+    assert!(store.load("acme/2").await.unwrap().is_none());
+    let archived: StoredSession =
+        serde_json::from_slice(&std::fs::read(directory.join("archive").join("acme%2F2.json")).unwrap()).unwrap();
+    assert_eq!(archived.session.message_roots[0].content.to_string(), "acme/2");
+
+    // 归档失败的对话保留，留待下次清理
+    // Conversations that fail to archive are kept for the next sweep
+    let failing = |id: &str, _: &StoredSession| -> error_stack::Result<(), StoreError> {
+        Err(Report::new(StoreError::IoError(id.to_string())))
+    };
+    let report = Retention::new(store.clone(), RetentionPolicy::new().max_conversations(3))
+        .archiver(failing)
+        .sweep()
+        .await
+        .unwrap();
+    assert_eq!(report.failed, ["loose"]);
+    assert!(report.removed.is_empty());
+    assert!(store.load("loose").await.unwrap().is_some());
+
+    // 归档期间又被保存的对话不会被删除
+    // A conversation saved again while it is being archived is not deleted
+    let memory = Arc::new(MemorySessionStore::default());
+    memory.save("busy", &session("busy"), 0).await.unwrap();
+    let writer = memory.clone();
+    let racing = move |id: &str, stored: &StoredSession| -> error_stack::Result<(), StoreError> {
+        futures::executor::block_on(writer.save(id, &session("new answer"), stored.revision)).map(|_| ())
+    };
+    let report = Retention::new(memory.clone(), RetentionPolicy::new().max_conversations(0))
+        .archiver(racing)
+        .sweep()
+        .await
+        .unwrap();
+    assert_eq!(report.refreshed, ["busy"]);
+    assert!(report.removed.is_empty() && report.failed.is_empty());
+    assert_eq!(memory.load("busy").await.unwrap().unwrap().revision, 2);
+
+    // 后台任务定期清理
+    // The background task sweeps periodically
+    let memory = Arc::new(MemorySessionStore::default());
+    memory.save("a", &session("a"), 0).await.unwrap();
+    memory.save("b", &session("b"), 0).await.unwrap();
+    let retention = Retention::new(memory.clone(), RetentionPolicy::new().max_conversations(1));
+    let task = retention.spawn(Duration::from_millis(10));
+    for _ in 0..100 {
+        if memory.list().await.unwrap().len() == 1 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    task.abort();
+    assert_eq!(memory.list().await.unwrap().len(), 1);
+
+    format_test_block("Retention", || format!("{:?}", report));
+    std::fs::remove_dir_all(&directory).unwrap();
+}
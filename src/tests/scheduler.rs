@@ -0,0 +1,172 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use error_stack::Report;
+
+use crate::chat::chat_base::ChatError;
+use crate::chat::chat_single::SingleChat;
+use crate::chat::event::ChatEvent;
+use crate::error::ReportExt;
+use crate::scheduler::{CronSchedule, RetryPolicy, Schedule, ScheduledTask, Scheduler};
+use crate::testing::MockProvider;
+use crate::tests::format_test_block;
+
+pub async fn test_scheduler() {
+    test_cron();
+    test_retry_policy();
+    test_overlap_and_retries().await;
+    test_agent_task().await;
+}
+
+fn at(time: &str) -> DateTime<Utc> {
+    time.parse().unwrap()
+}
+
+fn test_cron() {
+    // 工作日 9 点到 17 点每 15 分钟，周五收盘后顺延到周一
+    // Every 15 minutes from 9 to 17 on weekdays, after Friday's last slot it moves on to Monday
+    let cron = CronSchedule::parse("*/15 9-17 * * 1-5").unwrap();
+    assert_eq!(cron.next_after(at("2025-06-02T09:07:30Z")), Some(at("2025-06-02T09:15:00Z")));
+    assert_eq!(cron.next_after(at("2025-06-06T17:45:00Z")), Some(at("2025-06-09T09:00:00Z")));
+
+    // 日与星期都受限时满足其一即可，7 表示周日
+    // With both the day and the weekday restricted either one fires, 7 means Sunday
+    let cron = CronSchedule::parse("30 8 1 * 7").unwrap();
+    assert_eq!(cron.next_after(at("2025-06-02T00:00:00Z")), Some(at("2025-06-08T08:30:00Z")));
+    assert_eq!(cron.next_after(at("2025-06-29T09:00:00Z")), Some(at("2025-07-01T08:30:00Z")));
+
+    assert_eq!(CronSchedule::parse("0 0 29 2 *").unwrap().next_after(at("2025-03-01T00:00:00Z")), Some(at("2028-02-29T00:00:00Z")));
+    assert_eq!(CronSchedule::parse("0 0 30 2 *").unwrap().next_after(at("2025-03-01T00:00:00Z")), None);
+
+    for invalid in ["61 * * * *", "* * * *", "*/0 * * * *", "5-1 * * * *", "a * * * *"] {
+        let error = Schedule::cron(invalid).unwrap_err();
+        format_test_block("Invalid Cron", || format!("{:?}", error));
+        assert_eq!(error.code(), "scheduler.invalid_schedule");
+    }
+}
+
+fn test_retry_policy() {
+    let retryable = Report::new(ChatError::TimeoutError);
+    let fatal = Report::new(ChatError::InvalidConfig);
+    let policy = RetryPolicy::new(3, Duration::from_secs(1)).max_delay(Duration::from_secs(3));
+    assert_eq!(policy.delay(1, &retryable), Some(Duration::from_secs(1)));
+    assert_eq!(policy.delay(2, &retryable), Some(Duration::from_secs(2)));
+    assert_eq!(policy.delay(3, &retryable), Some(Duration::from_secs(3)));
+    assert_eq!(policy.delay(4, &retryable), None);
+    assert_eq!(policy.delay(1, &fatal), None);
+    assert_eq!(policy.retry_all(true).delay(1, &fatal), Some(Duration::from_secs(1)));
+    assert_eq!(RetryPolicy::none().delay(1, &retryable), None);
+}
+
+fn record(scheduler: &mut Scheduler) -> Arc<Mutex<Vec<ChatEvent>>> {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let recorded = events.clone();
+    scheduler.on_event(move |event| recorded.lock().unwrap().push(event.clone()));
+    events
+}
+
+async fn test_overlap_and_retries() {
+    // 暂停时钟，运行时空闲时直接推进到下一个计时器，计时不受机器负载影响
+    // Pause the clock, it jumps to the next timer whenever the runtime is idle, so timings do not depend on load
+    tokio::time::pause();
+    let mut scheduler = Scheduler::new();
+    let events = record(&mut scheduler);
+
+    // 运行中的任务再次触发时跳过
+    // A task triggered again while running is skipped
+    let slow = ScheduledTask::new("slow", Schedule::After(Duration::from_secs(3600)), || async {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        Ok("done".to_string())
+    });
+    scheduler.add(slow).unwrap();
+    assert_eq!(scheduler.add(ScheduledTask::new("slow", Schedule::After(Duration::ZERO), || async { Ok(String::new()) })).unwrap_err().code(), "scheduler.duplicate_task");
+    assert!(scheduler.run_now("slow").unwrap());
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    assert!(scheduler.is_running("slow").unwrap());
+    assert!(!scheduler.run_now("slow").unwrap());
+    tokio::time::sleep(Duration::from_millis(150)).await;
+    assert!(!scheduler.is_running("slow").unwrap());
+
+    // 运行中途 panic 的任务不会一直处于运行状态，之后仍可触发
+    // A task panicking midway does not stay running and can be triggered again
+    let panics = ScheduledTask::new("panics", Schedule::After(Duration::from_secs(3600)), || async {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        panic!("scheduled task panicking on purpose");
+    });
+    scheduler.add(panics).unwrap();
+    assert!(scheduler.run_now("panics").unwrap());
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(!scheduler.is_running("panics").unwrap());
+    assert!(scheduler.run_now("panics").unwrap());
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    scheduler.remove("panics").unwrap();
+
+    // 可重试的错误按策略重试，不可重试的错误直接失败
+    // Retryable errors are retried per the policy, non-retryable ones fail right away
+    let calls = Arc::new(AtomicU32::new(0));
+    let counter = calls.clone();
+    let flaky = ScheduledTask::new("flaky", Schedule::After(Duration::from_millis(5)), move || {
+        let call = counter.fetch_add(1, Ordering::SeqCst);
+        async move {
+            match call {
+                0 | 1 => Err(Report::new(ChatError::HttpError(429))),
+                _ => Ok("recovered".to_string()),
+            }
+        }
+    })
+    .retry(RetryPolicy::new(3, Duration::from_millis(5)));
+    let broken = ScheduledTask::new("broken", Schedule::After(Duration::from_millis(5)), || async {
+        Err(Report::new(ChatError::InvalidConfig))
+    });
+    scheduler.add(flaky).unwrap().add(broken).unwrap().start();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // 固定间隔的任务在停止后不再触发
+    // Interval tasks no longer fire once stopped
+    let ticks = Arc::new(AtomicU32::new(0));
+    let counter = ticks.clone();
+    scheduler
+        .add(ScheduledTask::new("tick", Schedule::Every(Duration::from_millis(20)), move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+            async { Ok("tick".to_string()) }
+        }))
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(110)).await;
+    scheduler.stop();
+    let fired = ticks.load(Ordering::SeqCst);
+    tokio::time::sleep(Duration::from_millis(60)).await;
+    assert_eq!(fired, 5);
+    assert_eq!(ticks.load(Ordering::SeqCst), fired);
+    assert_eq!(scheduler.add(ScheduledTask::new("zero", Schedule::Every(Duration::ZERO), || async { Ok(String::new()) })).unwrap_err().code(), "scheduler.invalid_schedule");
+    assert_eq!(scheduler.remove("missing").unwrap_err().code(), "scheduler.unknown_task");
+    scheduler.remove("tick").unwrap();
+    assert_eq!(scheduler.tasks(), vec!["slow", "flaky", "broken"]);
+
+    let events = events.lock().unwrap();
+    format_test_block("Scheduler Events", || format!("{:#?}", events));
+    assert_eq!(events.iter().filter(|event| matches!(event, ChatEvent::TaskSkipped { task } if task == "slow")).count(), 1);
+    assert!(events.iter().any(|event| matches!(event, ChatEvent::TaskFinished { task, output, attempts: 1, .. } if task == "slow" && output == "done")));
+    assert!(events.iter().any(|event| matches!(event, ChatEvent::TaskFinished { task, output, attempts: 3, .. } if task == "flaky" && output == "recovered")));
+    assert!(events.iter().any(|event| matches!(event, ChatEvent::TaskFailed { task, attempts: 1, .. } if task == "broken")));
+    assert_eq!(calls.load(Ordering::SeqCst), 3);
+    tokio::time::resume();
+}
+
+async fn test_agent_task() {
+    let mock = MockProvider::start("scheduler-api").await;
+    mock.reply("All 3 services are healthy.");
+
+    let mut scheduler = Scheduler::new();
+    let events = record(&mut scheduler);
+    let builder = SingleChat::builder().api("scheduler-api").system("You are a monitoring agent.");
+    scheduler.add(ScheduledTask::agent("health", Schedule::cron("0 * * * *").unwrap(), builder, "Check the services")).unwrap();
+    scheduler.run_now("health").unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    mock.last_request().contains("You are a monitoring agent.").contains("Check the services");
+    let events = events.lock().unwrap();
+    assert!(matches!(&events[0], ChatEvent::TaskStarted { task, .. } if task == "health"));
+    assert!(matches!(&events[1], ChatEvent::TaskFinished { output, .. } if output == "All 3 services are healthy."));
+}
@@ -0,0 +1,68 @@
+use crate::chat::chat_single::SingleChat;
+use crate::config::{CFG, Config, ModelCapability};
+use crate::testing::MockProvider;
+use crate::tests::format_test_block;
+
+pub async fn test_scoped_config() {
+    let mock_a = MockProvider::start("scoped-a").await;
+    let mock_b = MockProvider::start("scoped-b").await;
+    let scoped = |mock: &MockProvider, model: &str| {
+        Config::builder()
+            .api("scoped-writer", mock.url(), "sk-scoped", model)
+            .capability(ModelCapability::LongContext, "scoped-writer")
+            .alias("writer", "scoped-writer")
+            .build()
+            .unwrap()
+    };
+    let answer = || async {
+        let mut chat = SingleChat::builder().api("writer").build().unwrap();
+        let answer = chat.get_answer("Who are you?").await.unwrap();
+        (answer, chat.base.model.clone())
+    };
+
+    // 两个并行的作用域以同一名称解析到各自的API，互不干扰
+    // Two parallel scopes resolve the same name to their own APIs without interfering
+    mock_a.reply("I am A.");
+    mock_b.reply("I am B.");
+    let (a, b) = tokio::join!(
+        Config::with_scoped(scoped(&mock_a, "model-a"), answer()),
+        Config::with_scoped(scoped(&mock_b, "model-b"), answer()),
+    );
+    assert_eq!(a, ("I am A.".to_string(), "model-a".to_string()));
+    assert_eq!(b, ("I am B.".to_string(), "model-b".to_string()));
+    assert_eq!(mock_a.requests().len(), 1);
+    assert_eq!(mock_b.requests().len(), 1);
+
+    // 作用域内的修改只影响作用域中的配置，全局配置在作用域之外保持不变
+    // Changes within the scope only affect the scoped configuration, the global one is unchanged outside it
+    let names = Config::with_scoped(scoped(&mock_a, "model-a"), async {
+        Config::add_alias("scoped-fast", "scoped-writer");
+        let mut names: Vec<String> = CFG.current().api_info.iter().map(|entry| entry.key().0.clone()).collect();
+        names.sort();
+        names
+    })
+    .await;
+    assert_eq!(names, ["scoped-writer"]);
+    assert!(CFG.current().aliases.get("writer").is_none());
+    assert!(CFG.current().aliases.get("scoped-fast").is_none());
+    assert!(CFG.current().api_info.iter().any(|entry| entry.key().0 == "scoped-a"));
+    assert!(SingleChat::builder().api("scoped-writer").build().is_err());
+
+    // 作用域有自己的并发额度，其中对来源的修改不影响全局的额度；新任务以 `in_current_scope` 继承作用域
+    // The scope has permits of its own and changing sources within it leaves the global permits alone; new tasks
+    // inherit the scope through `in_current_scope`
+    let global_permits = Config::get_source_permits(&mock_a.source_name()).unwrap();
+    mock_a.reply("I am A again.");
+    let (permits, spawned) = Config::with_scoped(scoped(&mock_a, "model-a"), async {
+        Config::set_source_parallelism("scoped-writer", 7).unwrap();
+        let permits = Config::get_source_permits("scoped-writer").unwrap();
+        (permits, tokio::spawn(Config::in_current_scope(answer())).await.unwrap())
+    })
+    .await;
+    assert_eq!((permits.limit, permits.available), (7, 7));
+    assert_eq!(spawned, ("I am A again.".to_string(), "model-a".to_string()));
+    assert_eq!(Config::get_source_permits(&mock_a.source_name()).unwrap(), global_permits);
+
+    format_test_block("Scoped Config", || format!("{:?}\n{:?}", a, b));
+    assert_eq!(mock_a.pending() + mock_b.pending(), 0);
+}
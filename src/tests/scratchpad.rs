@@ -0,0 +1,81 @@
+use std::time::Duration;
+
+use serde_json::json;
+
+use crate::chat::agent::Deadline;
+use crate::chat::chat_single::SingleChat;
+use crate::chat::message::Session;
+use crate::config::prompts::PromptKey;
+use crate::config::{Config, ModelCapability};
+use crate::testing::MockProvider;
+use crate::tests::format_test_block;
+
+pub async fn test_scratchpad() {
+    // 在独立的配置中运行，工具参数解析只会选到模拟提供商
+    // Run on a configuration of its own, so tool argument parsing only picks the mock provider
+    Config::with_scoped(Config::builder().build().unwrap(), async {
+        let mock = MockProvider::start("scratchpad-api").await.with_capability(ModelCapability::ToolUse);
+        let mut chat =
+            SingleChat::builder().api("scratchpad-api").system("You plan trips.").scratchpad().build().unwrap();
+        let names: Vec<&str> = chat.tools().iter().filter_map(|tool| tool["function"]["name"].as_str()).collect();
+        assert_eq!(names, ["scratchpad_write", "scratchpad_read"]);
+
+        // 模型把中间结果写入草稿本，追加写入接在已有内容之后
+        // The model writes intermediate results to the scratchpad, appending goes after the existing content
+        mock.reply("<ToolUse>note the plan</ToolUse>")
+            .reply_tool_call("scratchpad_write", json!({"key": "plan", "content": "1. book flights"}))
+            .reply("<ToolUse>extend the plan</ToolUse>")
+            .reply_tool_call("scratchpad_write", json!({"key": "plan", "content": "2. book hotel", "append": true}))
+            .reply("Plan noted.");
+        let run = chat.run_agent("Plan a trip to Rome", Deadline::after(Duration::from_secs(10))).await.unwrap();
+        assert!(run.is_complete());
+        assert_eq!(chat.scratchpad().get("plan").as_deref(), Some("1. book flights\n2. book hotel"));
+
+        // 压缩与检查点保留笔记，检查点中的副本不随之后的写入改变
+        // Compaction and checkpoints keep the notes, the copy in a checkpoint does not change with later writes
+        let checkpoint = chat.checkpoint();
+        chat.compact();
+        chat.scratchpad().set("budget", "2000 EUR");
+        chat.rollback(&checkpoint).unwrap();
+        assert_eq!(chat.scratchpad().notes().len(), 1);
+
+        // 笔记不进入提示词，模型读取时作为工具结果返回；读取不存在的笔记列出已有的名称
+        // Notes stay out of the prompt and come back as the tool result when read; a missing note lists the keys
+        mock.reply("<ToolUse>check the plan</ToolUse>")
+            .reply_tool_call("scratchpad_read", json!({"key": "plan"}))
+            .reply("<ToolUse>check the budget</ToolUse>")
+            .reply_tool_call("scratchpad_read", json!({"key": "budget"}))
+            .reply("Flights, then the hotel.");
+        let run = chat.run_agent("What is next?", Deadline::after(Duration::from_secs(10))).await.unwrap();
+        let read: serde_json::Value = serde_json::from_str(&run.tool_results[0]).unwrap();
+        assert_eq!(read, json!({"key": "plan", "content": "1. book flights\n2. book hotel"}));
+        let missing: serde_json::Value = serde_json::from_str(&run.tool_results[1]).unwrap();
+        assert_eq!(missing, json!({"key": "budget", "content": null, "keys": ["plan"]}));
+
+        // 之后设置的工具保留草稿本工具，笔记随会话保存与加载
+        // Tools set later keep the scratchpad tools, and notes are saved and loaded with the session
+        let parameters = json!({"type": "object", "properties": {"query": {"type": "string"}}});
+        let search = json!({"name": "search", "description": "Search the web", "parameters": parameters});
+        chat.set_tools(vec![json!({"type": "function", "function": search})]).unwrap();
+        assert_eq!(chat.tools().len(), 3);
+        let saved = serde_json::to_string(&chat.base.session).unwrap();
+        let loaded: Session = serde_json::from_str(&saved).unwrap();
+        assert_eq!(loaded.scratchpad.get("plan"), chat.scratchpad().get("plan"));
+        assert!(!serde_json::to_string(&Session::new()).unwrap().contains("scratchpad"));
+
+        // 草稿本工具的说明可以按提示词键替换
+        // The descriptions of the scratchpad tools can be replaced by prompt key
+        Config::set_capability_prompt(PromptKey::ScratchpadWriteDescription, "Keep a note for later turns");
+        Config::set_capability_prompt(PromptKey::ScratchpadReadKeyArgument, "Note to read, all notes if omitted");
+        let english = SingleChat::builder().api("scratchpad-api").scratchpad().build().unwrap();
+        assert_eq!(english.tools()[0]["function"]["description"], "Keep a note for later turns");
+        let read = &english.tools()[1]["function"]["parameters"]["properties"]["key"];
+        assert_eq!(read["description"], "Note to read, all notes if omitted");
+        Config::remove_capability_prompt(&PromptKey::ScratchpadWriteDescription);
+        Config::remove_capability_prompt(&PromptKey::ScratchpadReadKeyArgument);
+
+        assert_eq!(mock.pending(), 0);
+        format_test_block("scratchpad", || format!("{:#?}", chat.scratchpad()));
+    })
+    .await;
+}
@@ -0,0 +1,166 @@
+use std::collections::HashMap;
+
+use futures::StreamExt;
+use serde_json::json;
+
+use crate::prelude::*;
+use crate::tests::{format_test_block, spawn_mock_server};
+
+pub async fn test_session() {
+    test_chat_session().await;
+    test_chat_handle().await;
+    test_message_ids().await;
+    test_transcripts();
+}
+
+/// 对具体对话类型泛型的应用代码
+/// Application code generic over the chat type
+async fn ask_twice<C: ChatSession>(chat: &mut C) -> (String, String, i32) {
+    let answer = chat.get_answer("first").await.unwrap();
+    let streamed: Vec<String> = chat
+        .stream_answer("second")
+        .await
+        .unwrap()
+        .map(|delta| delta.unwrap())
+        .collect()
+        .await;
+    (answer, streamed.concat(), chat.usage())
+}
+
+async fn test_chat_session() {
+    // 非流式请求返回消息数，流式请求分两段返回消息数
+    // Answer the message count, in two pieces for streaming requests
+    let url = spawn_mock_server(|body| {
+        let count = body["messages"].as_array().map_or(0, |messages| messages.len());
+        let usage = json!({"prompt_tokens": 1, "completion_tokens": 1, "total_tokens": 2});
+        if body["stream"] == true {
+            let chunk = |content: &str| json!({"choices": [{"delta": {"content": content}}]}).to_string();
+            let body = [
+                format!("data: {}", chunk("n=")),
+                format!("data: {}", chunk(&count.to_string())),
+                format!("data: {}", json!({"choices": [], "usage": usage})),
+                "data: [DONE]".to_string(),
+            ]
+            .join("\n\n");
+            (200, body)
+        } else {
+            (200, json!({
+                "choices": [{"message": {"content": format!("n={}", count)}, "finish_reason": "stop"}],
+                "usage": usage,
+            }).to_string())
+        }
+    })
+    .await;
+    Config::add_api_source("session-source", &url, 2);
+    Config::add_api_info("session-api", "session-model", ModelCapability::LongContext, "session-source", "sk-session").unwrap();
+
+    let mut single = SingleChat::new_with_api_name("session-api", "", false);
+    let single_result = ask_twice(&mut single).await;
+
+    let prompts = HashMap::from([("alice".to_string(), "You are Alice".to_string())]);
+    let mut multi = MultiChat::new_with_api_name("session-api", prompts, false).unwrap();
+    multi.set_character("alice").unwrap();
+    let multi_result = ask_twice(&mut multi).await;
+
+    format_test_block("Chat Session", || format!("{:?}\n{:?}", single_result, multi_result));
+    assert_eq!(single_result, ("n=1".to_string(), "n=3".to_string(), 4));
+    // 多角色对话的请求另带当前角色的提示词
+    // Requests of multi-character chats also carry the prompt of the current character
+    assert_eq!(multi_result, ("n=2".to_string(), "n=4".to_string(), 4));
+}
+
+async fn test_chat_handle() {
+    let handle = ChatHandle::spawn(SingleChat::new_with_api_name("session-api", "", false));
+
+    // 空闲时取消不影响之后的命令
+    // Cancelling while idle does not affect later commands
+    handle.cancel();
+
+    let first = handle.clone();
+    let second = handle.clone();
+    let (a, b) = tokio::join!(
+        tokio::spawn(async move { first.get_answer("a").await.unwrap() }),
+        tokio::spawn(async move { second.get_answer("b").await.unwrap() }),
+    );
+    let mut answers = vec![a.unwrap(), b.unwrap()];
+    answers.sort();
+
+    let streamed: Vec<String> = handle
+        .stream_answer("c")
+        .await
+        .unwrap()
+        .map(|delta| delta.unwrap())
+        .collect()
+        .await;
+    let (usage, roots) = handle.with(|chat| (chat.base.usage, chat.base.session.message_roots.len())).await.unwrap();
+
+    format_test_block("Chat Handle", || format!("{:?} {:?} {}", answers, streamed, usage));
+    assert_eq!(answers, vec!["n=1", "n=3"]);
+    assert_eq!(streamed.concat(), "n=5");
+    assert_eq!(usage, 6);
+    assert_eq!(roots, 1);
+}
+
+async fn test_message_ids() {
+    let mut chat = SingleChat::new_with_api_name("session-api", "", false);
+    chat.get_answer("first").await.unwrap();
+    let first_answer = chat.last_message_id().unwrap().to_string();
+    chat.get_answer("second").await.unwrap();
+
+    // 在第一轮回答处分支，原路径保留
+    // Branch off the first answer, the original path is kept
+    let fork = chat.fork_at_id(&first_answer).unwrap();
+    assert_eq!(fork.last_message_id(), Some(first_answer.as_str()));
+    assert_eq!(chat.get_answer_at(&first_answer, "branch").await.unwrap(), "n=3");
+    assert_eq!(chat.base.session.default_path, vec![0, 0, 1, 0]);
+
+    // 消息ID随会话序列化保存，旧版会话加载时补齐
+    // Message IDs are saved with the session, and filled in when loading older sessions
+    let saved = serde_json::to_string(&chat.base.session).unwrap();
+    let restored: Session = serde_json::from_str(&saved).unwrap();
+    assert_eq!(restored.path_of(&first_answer).unwrap(), vec![0, 0]);
+    let legacy: Session = serde_json::from_value(json!({
+        "message_roots": [{"role": "user", "content": "hi", "child": []}],
+        "default_path": [0],
+    }))
+    .unwrap();
+    assert!(legacy.last_message_id().is_some_and(|id| !id.is_empty()));
+
+    let unknown = chat.get_answer_at("no-such-id", "lost").await.unwrap_err();
+    assert!(matches!(unknown.current_context(), ChatError::SessionError));
+    format_test_block("Message IDs", || format!("{} -> {:?}", first_answer, chat.base.session.default_path));
+}
+
+fn test_transcripts() {
+    let prompts = HashMap::from([
+        ("alice".to_string(), "You are Alice".to_string()),
+        ("bob".to_string(), "You are Bob".to_string()),
+    ]);
+    let mut multi = MultiChat::new_with_api_name("session-api", prompts, false).unwrap();
+    multi.add_system_message("Debate the topic").unwrap();
+    multi.add_user_message("Is tea better than coffee?").unwrap();
+    multi.base.add_message(Role::Character("alice".to_string()), "Tea.").unwrap();
+    multi.base.add_message(Role::Character("bob".to_string()), "Coffee.").unwrap();
+
+    // 角色视角与请求中一致，旁观视角标明每条消息的发言者
+    // A character's view matches its requests, the observer view labels the speaker of every message
+    let alice = multi.transcript(&Perspective::Character("alice".to_string())).unwrap();
+    assert_eq!(alice["perspective"], "alice");
+    assert_eq!(alice["messages"][2], json!({"role": "assistant", "content": "Tea."}));
+    assert_eq!(alice["messages"][3], json!({"role": "user", "content": "bob said: Coffee."}));
+    let bob = multi.transcript(&Perspective::Character("bob".to_string())).unwrap();
+    assert_eq!(bob["messages"][2], json!({"role": "user", "content": "alice said: Tea."}));
+    assert_eq!(bob["messages"][3], json!({"role": "assistant", "content": "Coffee."}));
+
+    let observer = multi.transcript(&Perspective::Observer).unwrap();
+    assert_eq!(observer["messages"][1], json!({"speaker": "user", "content": "Is tea better than coffee?"}));
+    assert_eq!(observer["messages"][3], json!({"speaker": "bob", "content": "Coffee."}));
+    let transcripts = multi.transcripts();
+    let perspectives: Vec<_> = transcripts.iter().map(|transcript| transcript["perspective"].clone()).collect();
+    assert_eq!(perspectives, vec!["alice", "bob", "observer"]);
+
+    let unknown = multi.transcript(&Perspective::Character("carol".to_string())).unwrap_err();
+    assert!(matches!(unknown.current_context(), ChatError::UndefinedCharacter(name) if name == "carol"));
+    format_test_block("Transcripts", || multi.observer_markdown());
+    assert!(multi.observer_markdown().ends_with("**alice**: Tea.\n\n**bob**: Coffee."));
+}
@@ -0,0 +1,88 @@
+use std::time::Duration;
+
+use crate::chat::chat_single::SingleChat;
+use crate::chat::shadow::{MemoryShadowLog, Shadow, ShadowCandidate};
+use crate::chat::tenant::Tenant;
+use crate::testing::MockProvider;
+use crate::tests::format_test_block;
+
+pub async fn test_shadow() {
+    let primary = MockProvider::start("shadow-primary").await;
+    let candidate = MockProvider::start("shadow-candidate").await;
+    let log = MemoryShadowLog::new();
+    let shadow = Shadow::new(log.clone())
+        .candidate(ShadowCandidate::new("shadow-candidate", 100.0).prompt("Be terse.").label("terse"))
+        .candidate(ShadowCandidate::new("shadow-candidate", 0.0).label("never"));
+    let mut chat = SingleChat::builder()
+        .api("shadow-primary")
+        .system("You are a helpful assistant.")
+        .shadow(shadow)
+        .build()
+        .unwrap();
+
+    // 用户收到线上回答，候选在后台收到同一请求
+    // The user gets the live answer, the candidate gets the same request in the background
+    primary.reply("Paris is the capital of France.");
+    candidate.reply("Paris.");
+    assert_eq!(chat.get_answer("Capital of France?").await.unwrap(), "Paris is the capital of France.");
+    let records = wait_for_records(&log, 1).await;
+    assert_eq!(records.len(), 1);
+    let record = &records[0];
+    assert_eq!(record.candidate, "terse");
+    assert_eq!(record.input, "Capital of France?");
+    assert_eq!(record.primary_answer, "Paris is the capital of France.");
+    assert_eq!(record.candidate_answer.as_deref(), Some("Paris."));
+    assert_eq!(record.candidate_model, "mock-model");
+    assert_eq!(record.session_id, chat.base.session_id);
+    candidate
+        .last_request()
+        .contains("system: Be terse.")
+        .not_contains("helpful assistant")
+        .contains("user: Capital of France?");
+
+    // 候选失败只记录错误，不影响用户；候选的回答不写入会话
+    // A failing candidate only records the error without affecting the user; its answer stays out of the session
+    primary.reply("It is Berlin.");
+    candidate.reply_error(400, "model not available");
+    assert_eq!(chat.get_answer("And Germany?").await.unwrap(), "It is Berlin.");
+    let records = wait_for_records(&log, 2).await;
+    assert!(records[1].candidate_answer.is_none());
+    assert!(records[1].candidate_error.is_some());
+    primary.last_request().contains("assistant: Paris is the capital of France.").not_contains("Paris.\n");
+    assert_eq!(primary.requests().len(), 2);
+
+    // 影子请求计入原对话租户的用量
+    // Shadow requests are charged to the tenant of the chat
+    let tenant = Tenant::new("shadow-tenant");
+    let tenant_log = MemoryShadowLog::new();
+    let mut tenant_chat = SingleChat::builder()
+        .api("shadow-primary")
+        .tenant(tenant.clone())
+        .shadow(Shadow::new(tenant_log.clone()).candidate(ShadowCandidate::new("shadow-candidate", 100.0)))
+        .build()
+        .unwrap();
+    primary.reply("Rome.");
+    candidate.reply("Rome!");
+    assert_eq!(tenant_chat.get_answer("Capital of Italy?").await.unwrap(), "Rome.");
+    assert_eq!(wait_for_records(&tenant_log, 1).await[0].candidate_answer.as_deref(), Some("Rome!"));
+    assert_eq!(tenant.usage().requests, 2);
+
+    // 非有限的比例视为 0，抽样不会出错
+    // A non-finite percentage counts as 0, so sampling does not fail
+    let nan = Shadow::new(MemoryShadowLog::new()).candidate(ShadowCandidate::new("shadow-candidate", f64::NAN));
+    assert!(nan.sample().is_empty());
+
+    format_test_block("shadow", || candidate.request(0).prompt());
+}
+
+/// 等待后台的影子请求写入记录
+/// Wait for the background shadow requests to log their records
+async fn wait_for_records(log: &MemoryShadowLog, count: usize) -> Vec<crate::chat::shadow::ShadowRecord> {
+    for _ in 0..200 {
+        if log.records().len() >= count {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    log.records()
+}
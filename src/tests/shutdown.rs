@@ -0,0 +1,99 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde_json::json;
+
+use crate::chat::chat_base::ChatError;
+use crate::chat::chat_multi::MultiChat;
+use crate::chat::chat_single::SingleChat;
+use crate::chat::handle::ChatHandle;
+use crate::chat::store::{MemorySessionStore, SessionStore};
+use crate::chat::trigger::ToolTrigger;
+use crate::config::Config;
+use crate::error::ReportExt;
+use crate::schema::tool_schema::{create_tool, get_tool_registry};
+use crate::shutdown::{Shutdown, within_started_answer};
+use crate::testing::MockProvider;
+use crate::tests::format_test_block;
+
+pub async fn test_shutdown() {
+    // 在独立的配置中运行，关闭状态不会影响并行的其他测试
+    // Run on a configuration of its own, so the shutdown state does not affect other tests running in parallel
+    Config::with_scoped(Config::builder().build().unwrap(), async {
+        let mock = MockProvider::start("shutdown-api").await;
+        let store = Arc::new(MemorySessionStore::default());
+        let shutdown = Shutdown::new();
+
+        // 空闲时立即完成，登记的对话被保存，之后的提问被拒绝
+        // An idle process drains at once, the registered conversation is saved and later questions are refused
+        let handle = ChatHandle::spawn(SingleChat::builder().api("shutdown-api").build().unwrap());
+        shutdown.persist("idle", handle.clone(), store.clone());
+        mock.reply("Hello!");
+        handle.get_answer("Hi").await.unwrap();
+        let report = shutdown.drain(Duration::from_secs(1)).await;
+        assert!(report.drained);
+        assert_eq!(report.persisted, ["idle"]);
+        assert_eq!(store.load("idle").await.unwrap().unwrap().session.default_path.len(), 2);
+
+        let refused = handle.get_answer("Still there?").await.unwrap_err();
+        assert!(matches!(refused.current_context(), ChatError::ShuttingDown));
+        assert_eq!(refused.code(), "chat.shutting_down");
+
+        // 多角色对话与流式提问同样被拒绝，已开始的回答中的提问（如子智能体）照常进行
+        // Multi-character chats and streamed questions are refused as well, while questions within an answer already
+        // started (such as sub-agents) go ahead
+        let characters = HashMap::from([("guide".to_string(), "You are a guide.".to_string())]);
+        let mut multi = MultiChat::new_with_api_name("shutdown-api", characters, false).unwrap();
+        multi.set_character("guide").unwrap();
+        let refused = multi.get_answer("Hi").await.unwrap_err();
+        assert!(matches!(refused.current_context(), ChatError::ShuttingDown));
+        assert!(multi.stream_answer("Hi").await.is_err());
+        let mut chat = SingleChat::builder().api("shutdown-api").build().unwrap();
+        assert!(chat.stream_answer("Hi").await.is_err());
+        mock.reply("Still working.");
+        assert_eq!(within_started_answer(chat.get_answer("Hi")).await.unwrap(), "Still working.");
+        assert_eq!(mock.requests().len(), 2);
+        shutdown.resume();
+
+        // 截止时仍在调用工具的对话被取消，仍然保存；阻塞线程上的工具在线程结束前仍计为进行中
+        // A conversation still calling a tool at the deadline is cancelled and still saved; a tool on a blocking
+        // thread counts as in flight until the thread finishes
+        let (release, released) = std::sync::mpsc::channel::<()>();
+        let released = Mutex::new(released);
+        get_tool_registry().insert(
+            "shutdown_slow".to_string(),
+            create_tool("shutdown_slow", move |_| {
+                released.lock().unwrap().recv().unwrap();
+                Ok(json!({"done": true}))
+            })
+            .1,
+        );
+        let trigger = ToolTrigger::new(r"slow", "shutdown_slow").unwrap();
+        let chat = SingleChat::builder().api("shutdown-api").tool_trigger(trigger).build().unwrap();
+        let handle = ChatHandle::spawn(chat);
+        shutdown.persist("busy", handle.clone(), store.clone());
+        let asking = handle.clone();
+        let answer = async move { asking.get_answer("do something slow").await };
+        let answer = tokio::spawn(Config::in_current_scope(answer));
+        while Shutdown::in_flight().1 == 0 {
+            tokio::task::yield_now().await;
+        }
+        let report = shutdown.drain(Duration::from_millis(20)).await;
+        assert!(!report.drained);
+        assert_eq!(report.pending_tools, 1);
+        assert_eq!(report.persisted, ["busy"]);
+        assert!(answer.await.unwrap().is_err());
+        assert_eq!(Shutdown::in_flight(), (0, 1));
+        release.send(()).unwrap();
+        assert!(shutdown.drain(Duration::from_secs(1)).await.drained);
+        assert_eq!(Shutdown::in_flight(), (0, 0));
+        assert_eq!(mock.requests().len(), 2);
+        shutdown.resume();
+        get_tool_registry().remove("shutdown_slow");
+
+        format_test_block("shutdown", || format!("{:#?}", report));
+    })
+    .await;
+    assert!(!Shutdown::is_closing());
+}
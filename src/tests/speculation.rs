@@ -0,0 +1,70 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use serde_json::json;
+
+use crate::chat::chat_single::SingleChat;
+use crate::chat::message::Role;
+use crate::chat::tenant::Tenant;
+use crate::config::{Config, ModelCapability};
+use crate::tests::{format_test_block, spawn_mock_server};
+
+pub async fn test_speculation() {
+    let requests = Arc::new(AtomicUsize::new(0));
+    let counter = requests.clone();
+    let url = spawn_mock_server(move |body| {
+        counter.fetch_add(1, Ordering::SeqCst);
+        let messages = body["messages"].as_array().cloned().unwrap_or_default();
+        let content = messages.last().and_then(|m| m["content"].as_str()).unwrap_or("").to_string();
+        let response = json!({
+            "choices": [{"message": {"content": format!("answer to {}", content)}, "finish_reason": "stop"}],
+            "usage": {"prompt_tokens": 1, "completion_tokens": 1, "total_tokens": 2},
+        });
+        (200, response.to_string())
+    })
+    .await;
+    Config::add_api_source("speculation-source", &url, 2);
+    Config::add_api_info("speculation-api", "m", ModelCapability::LongContext, "speculation-source", "sk-spec").unwrap();
+
+    let mut chat = SingleChat::new_with_api_name("speculation-api", "", false);
+    chat.set_tenant(Tenant::new("speculation-tenant"));
+    assert!(chat.speculate("next"));
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+    // 输入一致：直接使用预生成的回答，不再发请求
+    // Matching input: the speculative answer is served without another request
+    let answer = chat.get_answer("next").await.unwrap();
+    assert_eq!(answer, "answer to next");
+    assert_eq!(requests.load(Ordering::SeqCst), 1);
+    assert_eq!(chat.base.usage, 2);
+    assert_eq!(chat.base.tenant.as_ref().unwrap().usage().total_tokens, 2);
+    let last = chat.base.session.last_message_mut().unwrap().clone();
+    assert_eq!(last.role, Role::Assistant);
+    assert!(last.metadata.is_some());
+
+    // 输入不一致：放弃预生成，正常回答
+    // Different input: the speculation is discarded and the question answered normally
+    // 放弃的预生成不计入对话与租户的用量
+    // Discarded speculations are not charged to the chat or the tenant
+    chat.speculate("guess");
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    assert_eq!(requests.load(Ordering::SeqCst), 2);
+    let answer = chat.get_answer("actual").await.unwrap();
+    format_test_block("Speculation", || format!("{:?}", chat.base.session));
+    assert_eq!(answer, "answer to actual");
+    assert_eq!(chat.base.usage, 4);
+    assert_eq!(chat.base.tenant.as_ref().unwrap().usage().total_tokens, 4);
+
+    // 丢弃对话时取消尚未发出的预生成
+    // Dropping the chat cancels a speculation not sent yet
+    chat.speculate("never");
+    drop(chat);
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    assert_eq!(requests.load(Ordering::SeqCst), 3);
+
+    // 不在 tokio 运行时中时不预生成，也不会 panic
+    // Outside a tokio runtime there is no speculation and no panic
+    let mut chat = SingleChat::new_with_api_name("speculation-api", "", false);
+    assert!(!std::thread::spawn(move || chat.speculate("next")).join().unwrap());
+}
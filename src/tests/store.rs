@@ -0,0 +1,172 @@
+use std::sync::Arc;
+
+use crate::chat::message::{Role, Session};
+use crate::chat::store::{
+    DirectorySessionStore, MemorySessionStore, SessionCodec, SessionStore, StoreError, update_session,
+};
+use crate::error::ReportExt;
+use crate::tests::format_test_block;
+use crate::utils::common::file_name::{decode_file_name, encode_file_name};
+
+fn session(text: &str) -> Session {
+    let mut session = Session::new();
+    session.add_with_default_path(Role::User, text).unwrap();
+    session
+}
+
+/// 按位取反并加上标记的编码，没有标记的数据原样读出
+/// Codec inverting every bit behind a marker, data without the marker reads back as is
+struct Inverted;
+
+impl SessionCodec for Inverted {
+    fn encode(&self, data: Vec<u8>) -> error_stack::Result<Vec<u8>, StoreError> {
+        Ok([b"INV".as_slice(), &data.iter().map(|byte| !byte).collect::<Vec<u8>>()].concat())
+    }
+
+    fn decode(&self, data: Vec<u8>) -> error_stack::Result<Vec<u8>, StoreError> {
+        match data.strip_prefix(b"INV") {
+            Some(encoded) => Ok(encoded.iter().map(|byte| !byte).collect()),
+            None => Ok(data),
+        }
+    }
+}
+
+/// 两个写入者读取同一版本，后保存的一方得到冲突错误而不是覆盖前者
+/// Two writers read the same revision, the later save gets a conflict error instead of overwriting the first
+async fn check_compare_and_swap(store: &dyn SessionStore) {
+    assert_eq!(store.load("chat-1").await.unwrap(), None);
+    assert_eq!(store.save("chat-1", &session("hello"), 0).await.unwrap(), 1);
+    let read = store.load("chat-1").await.unwrap().unwrap();
+    let first = session("first writer");
+    assert_eq!(store.save("chat-1", &first, read.revision).await.unwrap(), 2);
+    let conflict = store.save("chat-1", &session("second writer"), read.revision).await.unwrap_err();
+    assert!(matches!(
+        conflict.current_context(),
+        StoreError::Conflict { id, expected: 1, actual: 2 } if id == "chat-1"
+    ));
+    assert_eq!(conflict.code(), "store.conflict");
+    assert_eq!(store.load("chat-1").await.unwrap().unwrap().session, first);
+    assert!(store.save("chat-2", &session("new"), 3).await.is_err());
+    assert!(store.save("chat-2", &session("new"), 0).await.is_ok());
+    assert!(store.save("chat-2", &session("again"), 0).await.is_err());
+    let stale = store.remove_revision("chat-1", read.revision).await.unwrap_err();
+    assert!(matches!(stale.current_context(), StoreError::Conflict { expected: 1, actual: 2, .. }));
+    store.remove_revision("chat-1", 2).await.unwrap();
+    store.remove_revision("chat-1", 2).await.unwrap();
+    store.remove("chat-2").await.unwrap();
+    assert_eq!(store.load("chat-1").await.unwrap(), None);
+}
+
+/// 并发更新在冲突时退避重试，所有写入都得以保留
+/// Concurrent updates back off and retry on conflicts, and every write is kept
+async fn check_concurrent_updates(store: Arc<dyn SessionStore>) -> u64 {
+    let writers: Vec<_> = (0..4)
+        .map(|i| {
+            let store = store.clone();
+            tokio::spawn(async move {
+                update_session(store.as_ref(), "shared", |session| {
+                    session.add_with_default_path(Role::User, format!("writer {}", i)).unwrap();
+                })
+                .await
+                .unwrap()
+            })
+        })
+        .collect();
+    for writer in writers {
+        writer.await.unwrap();
+    }
+    let shared = store.load("shared").await.unwrap().unwrap();
+    assert_eq!(shared.session.default_path.len(), 4);
+    shared.revision
+}
+
+pub async fn test_store() {
+    check_compare_and_swap(&MemorySessionStore::default()).await;
+    assert_eq!(check_concurrent_updates(Arc::new(MemorySessionStore::default())).await, 4);
+
+    let directory = std::env::temp_dir().join(format!("rhine_sessions_{}", std::process::id()));
+    let store = Arc::new(DirectorySessionStore::new(&directory).unwrap());
+    check_compare_and_swap(store.as_ref()).await;
+    let revision = check_concurrent_updates(store.clone()).await;
+    assert_eq!(revision, 4);
+
+    // 锁被占用时等待超时，释放后即可保存
+    // Waiting on a held lock times out, saving works again once it is released
+    let mut impatient = DirectorySessionStore::new(&directory).unwrap();
+    impatient.lock_timeout = std::time::Duration::from_millis(30);
+    let held = store.lock("shared").await.unwrap();
+    let locked = impatient.save("shared", &session("blocked"), 4).await.unwrap_err();
+    assert_eq!(locked.code(), "store.locked");
+    drop(held);
+    assert_eq!(impatient.save("shared", &session("unblocked"), 4).await.unwrap(), 5);
+
+    // 文件名对ID是单射的，路径分隔符不会写出目录，列出时从文件名还原ID
+    // File names are injective over IDs, path separators do not escape the directory, and listing restores the IDs
+    // from the file names
+    let ids = ["acme/1", "acme.1", "acme_1", "Acme_1", "../escape", "租户/1"];
+    for id in ids {
+        store.save(id, &session(id), 0).await.unwrap();
+    }
+    for id in ids {
+        let stored = store.load(id).await.unwrap().unwrap();
+        assert_eq!(stored.session.message_roots[0].content.to_string(), id);
+    }
+    assert!(directory.join("%2E%2E%2Fescape.json").exists());
+    let mut listed: Vec<String> = store.list().await.unwrap().into_iter().map(|entry| entry.id).collect();
+    listed.sort();
+    let mut expected: Vec<&str> = ids.into_iter().chain(["shared"]).collect();
+    expected.sort();
+    assert_eq!(listed, expected);
+    for (id, name) in [("acme/1", "acme%2F1"), ("Acme_1", "%41cme_1"), ("", "")] {
+        assert_eq!(encode_file_name(id), name);
+        assert_eq!(decode_file_name(name).as_deref(), Some(id));
+    }
+    assert_eq!(decode_file_name("bad%2"), None);
+
+    #[cfg(feature = "sqlite")]
+    {
+        use crate::chat::sqlite_store::SqliteSessionStore;
+
+        let timeout = std::time::Duration::from_secs(5);
+        let sqlite = Arc::new(SqliteSessionStore::open(directory.join("sessions.db"), timeout).unwrap());
+        check_compare_and_swap(sqlite.as_ref()).await;
+        assert_eq!(check_concurrent_updates(sqlite.clone()).await, 4);
+        let listed: Vec<String> = sqlite.list().await.unwrap().into_iter().map(|entry| entry.id).collect();
+        assert_eq!(listed, ["shared"]);
+
+        let encoded = SqliteSessionStore::open(directory.join("sessions.db"), timeout).unwrap().codec(Inverted);
+        check_compare_and_swap(&encoded).await;
+        assert_eq!(encoded.load("shared").await.unwrap().unwrap().session.default_path.len(), 4);
+        encoded.save("private", &session("my phone is 555-0100"), 0).await.unwrap();
+        let private = encoded.load("private").await.unwrap().unwrap().session;
+        assert_eq!(private.message_roots[0].content.to_string(), "my phone is 555-0100");
+        assert!(sqlite.load("private").await.is_err());
+    }
+
+    // 启用编码的存储对调用方透明，文件中没有明文，启用前保存的对话仍可读出
+    // A store with a codec is transparent to callers, files hold no plaintext, and conversations saved before the
+    // codec was enabled still load
+    let encoded = DirectorySessionStore::new(&directory).unwrap().codec(Inverted);
+    check_compare_and_swap(&encoded).await;
+    encoded.save("private", &session("my phone is 555-0100"), 0).await.unwrap();
+    assert!(!String::from_utf8_lossy(&std::fs::read(directory.join("private.json")).unwrap()).contains("555-0100"));
+    assert_eq!(encoded.load("acme/1").await.unwrap().unwrap().session.message_roots[0].content.to_string(), "acme/1");
+    assert!(store.load("private").await.is_err());
+
+    #[cfg(feature = "encryption")]
+    {
+        use crate::chat::encryption::{Cipher, StaticKeyProvider};
+
+        let encrypted = DirectorySessionStore::new(&directory)
+            .unwrap()
+            .codec(Cipher::new(StaticKeyProvider::new("k1", [1; 32])));
+        encrypted.save("sealed", &session("my phone is 555-0100"), 0).await.unwrap();
+        assert!(Cipher::is_encrypted(&std::fs::read(directory.join("sealed.json")).unwrap()));
+        let sealed = encrypted.load("sealed").await.unwrap().unwrap().session;
+        assert_eq!(sealed.message_roots[0].content.to_string(), "my phone is 555-0100");
+        assert_eq!(encrypted.load("shared").await.unwrap().unwrap().revision, 5);
+    }
+
+    format_test_block("Session Store", || format!("{:?}\n{:?}", revision, locked));
+    std::fs::remove_dir_all(&directory).unwrap();
+}
@@ -0,0 +1,251 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use bytes::Bytes;
+use futures::{StreamExt, stream};
+use serde_json::json;
+use tokio::sync::Semaphore;
+
+use crate::chat::chat_base::{BaseChat, ChatError, ModelOverride};
+use crate::chat::chat_single::SingleChat;
+use crate::chat::event::ChatEvent;
+use crate::chat::message::Role;
+use crate::chat::stream::{SseDecoder, StreamStalled, stalling_chunk_stream};
+use crate::chat::tenant::Tenant;
+use crate::config::{Config, ModelCapability};
+use crate::tests::{format_test_block, spawn_mock_server, spawn_truncating_mock_server};
+
+pub async fn test_stream() {
+    test_collect_stream().await;
+    test_sse_decoder();
+    test_stall_detection().await;
+    test_stream_answer().await;
+    test_stream_resume().await;
+    test_stream_failover().await;
+}
+
+async fn test_collect_stream() {
+    let chunks: Vec<reqwest::Result<Bytes>> = vec![
+        Ok(Bytes::from("data: {\"choices\":[{\"delta\":{\"content\":\"Hel\"},\"finish_reason\":null}]}\n\n")),
+        Ok(Bytes::from("data: {\"choices\":[{\"delta\":{\"content\":\"lo\"},\"finish_reason\":\"stop\"}]}\n\n")),
+        Ok(Bytes::from(
+            "data: {\"choices\":[],\"usage\":{\"prompt_tokens\":3,\"completion_tokens\":2,\"total_tokens\":5}}\n\ndata: [DONE]\n\n",
+        )),
+    ];
+    let semaphore = Arc::new(Semaphore::new(1));
+    let permit = semaphore.clone().acquire_owned().await.unwrap();
+
+    let output = BaseChat::collect_stream(stream::iter(chunks), permit).await.unwrap();
+
+    format_test_block("Collect Stream", || format!("{:?}", output));
+    assert_eq!(output.content, "Hello");
+    assert_eq!(output.finish_reason.as_deref(), Some("stop"));
+    assert_eq!(output.usage.unwrap()["total_tokens"], 5);
+    assert_eq!(semaphore.available_permits(), 1);
+}
+
+fn test_sse_decoder() {
+    let mut decoder = SseDecoder::default();
+    assert!(decoder.push(b"data: {\"choices\":[{\"delta\":").is_empty());
+    assert_eq!(decoder.push(b"{}}]}\r\n\r\n: keep-alive\n\ndata: [DONE]\n"), vec!["{\"choices\":[{\"delta\":{}}]}"]);
+    assert!(decoder.push(b"data: {}").is_empty());
+    assert_eq!(decoder.finish().as_deref(), Some("{}"));
+
+    // 保活事件不产出负载
+    // Keep-alive events yield no payload
+    assert!(decoder.push(b"event: ping\ndata: {\"type\": \"ping\"}\n\nid: 7\nretry: 1000\n").is_empty());
+}
+
+async fn test_stall_detection() {
+    // 保活注释之后不再有事件块，停滞检测中止流
+    // No chunk follows the keep-alive comments, the stall detector aborts the stream
+    let chunks: Vec<reqwest::Result<Bytes>> = vec![
+        Ok(Bytes::from("data: {\"choices\":[{\"delta\":{\"content\":\"Hel\"},\"finish_reason\":null}]}\n\n")),
+        Ok(Bytes::from(": keep-alive\n\n")),
+        Ok(Bytes::from("event: ping\ndata: {\"type\": \"ping\"}\n\n")),
+    ];
+    let semaphore = Arc::new(Semaphore::new(1));
+    let permit = semaphore.clone().acquire_owned().await.unwrap();
+    let bytes = stream::iter(chunks).chain(stream::pending());
+
+    let timeout = Duration::from_millis(50);
+    let results: Vec<_> = stalling_chunk_stream(bytes, permit, Some(timeout)).collect().await;
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0].as_ref().unwrap().content, "Hel");
+    let report = results[1].as_ref().unwrap_err();
+    format_test_block("Stream Stall", || format!("{:?}", report));
+    assert_eq!(report.downcast_ref::<StreamStalled>(), Some(&StreamStalled(timeout)));
+    assert_eq!(semaphore.available_permits(), 1);
+}
+
+async fn test_stream_answer() {
+    let url = spawn_mock_server(|_| {
+        let body = [
+            r#"data: {"choices":[{"delta":{"content":"Hel"},"finish_reason":null}]}"#,
+            r#"data: {"choices":[{"delta":{"content":"lo"},"finish_reason":"stop"}]}"#,
+            r#"data: {"choices":[],"usage":{"prompt_tokens":3,"completion_tokens":2,"total_tokens":5}}"#,
+            "data: [DONE]",
+        ]
+        .join("\n\n");
+        (200, body)
+    })
+    .await;
+    Config::add_api_source("stream-source", &url, 1);
+    Config::add_api_info("stream-api", "stream-model", ModelCapability::LongContext, "stream-source", "sk-stream").unwrap();
+
+    let mut chat = SingleChat::new_with_api_name("stream-api", "", true);
+    let deltas: Vec<String> = chat
+        .stream_answer("hi")
+        .await
+        .unwrap()
+        .map(|delta| delta.unwrap())
+        .collect()
+        .await;
+
+    let answer = chat.base.session.last_message_mut().unwrap().clone();
+    format_test_block("Stream Answer", || format!("{:?}\n{:?}", deltas, answer));
+    assert_eq!(deltas, vec!["Hel", "lo"]);
+    assert_eq!(answer.role, Role::Assistant);
+    assert_eq!(answer.content.as_text(), Some("Hello"));
+    assert_eq!(answer.metadata.unwrap().completion_tokens, Some(2));
+
+    // 只产出增量时，内容只在续传可能用到时保留，不能续传时调用记录中没有回答内容
+    // With deltas only, the content is kept only while a resume may use it, without resumes the call records no
+    // answer content
+    for (resume_attempts, recorded) in [(0, None), (2, Some("Hello"))] {
+        let answers = Arc::new(Mutex::new(Vec::new()));
+        let received = answers.clone();
+        let mut chat = SingleChat::new_with_api_name("stream-api", "", true);
+        chat.base.stream_resume_attempts = resume_attempts;
+        chat.base.events.add(Arc::new(move |event: &ChatEvent| {
+            if let ChatEvent::AnswerReady { content, .. } = event {
+                received.lock().unwrap().push(content.clone());
+            }
+        }));
+        let body = json!({"model": "stream-model", "messages": [{"role": "user", "content": "hi"}], "stream": true});
+        let deltas: Vec<String> = chat.base.stream_content(body, None).map(Result::unwrap).collect().await;
+        assert_eq!(deltas.concat(), "Hello");
+        assert_eq!(answers.lock().unwrap().first().map(String::as_str), recorded, "{}", resume_attempts);
+        assert!(chat.base.session.last_message_mut().is_err());
+    }
+}
+
+async fn test_stream_resume() {
+    // 第一次请求在 "Hel" 之后断开，续传请求以 "Hel" 为助手前缀时补全剩余内容
+    // The first request drops after "Hel", the resume request with "Hel" as the assistant prefix completes the rest
+    let url = spawn_truncating_mock_server(|body| {
+        let last = body["messages"].as_array().and_then(|messages| messages.last()).cloned().unwrap_or_default();
+        if last["role"] == "assistant" && last["content"] == "Hel" {
+            let body = [
+                r#"data: {"choices":[{"delta":{"content":"lo"},"finish_reason":"stop"}]}"#,
+                r#"data: {"choices":[],"usage":{"prompt_tokens":4,"completion_tokens":1,"total_tokens":5}}"#,
+                "data: [DONE]",
+            ]
+            .join("\n\n");
+            (200, body, 0)
+        } else {
+            (200, r#"data: {"choices":[{"delta":{"content":"Hel"},"finish_reason":null}]}"#.to_string() + "\n\n", 64)
+        }
+    })
+    .await;
+    Config::add_api_source("resume-source", &url, 1);
+    Config::add_api_info("resume-api", "resume-model", ModelCapability::LongContext, "resume-source", "sk-resume").unwrap();
+
+    // 续传不算租户的新请求：两次各续传一次的调用只用掉频率上限中的两次
+    // Resumes are not new requests of the tenant: two calls resuming once each use only two of the rate limit
+    let mut chat = SingleChat::new_with_api_name("resume-api", "", true);
+    chat.set_tenant(Tenant::new("resume-tenant").rate_limit(3, Duration::from_secs(60)));
+    let deltas: Vec<String> = chat
+        .stream_answer("hi")
+        .await
+        .unwrap()
+        .map(|delta| delta.unwrap())
+        .collect()
+        .await;
+    format_test_block("Stream Resume", || format!("{:?}", deltas));
+    assert_eq!(deltas, vec!["Hel", "lo"]);
+    assert_eq!(chat.base.session.last_message_mut().unwrap().content.as_text(), Some("Hello"));
+
+    let answer = chat.get_answer("hi").await.unwrap();
+    assert_eq!(answer, "Hello");
+
+    chat.base.stream_resume_attempts = 0;
+    let dropped = chat.get_answer("hi").await.unwrap_err();
+    assert!(!matches!(dropped.current_context(), ChatError::TenantRateLimited(_)));
+}
+
+async fn test_stream_failover() {
+    // 主API在 "Hel" 之后断开，备用API以 "Hel" 为助手前缀补全剩余内容，未收到前缀时从头回答
+    // The primary drops after "Hel", the fallback completes the rest with "Hel" as the assistant prefix and answers
+    // from scratch without a prefix
+    let dropping = spawn_truncating_mock_server(|_| {
+        (200, r#"data: {"choices":[{"delta":{"content":"Hel"},"finish_reason":null}]}"#.to_string() + "\n\n", 64)
+    })
+    .await;
+    let unavailable = spawn_truncating_mock_server(|_| (503, r#"{"error":"overloaded"}"#.to_string(), 0)).await;
+    let backup = spawn_truncating_mock_server(|body| {
+        let last = body["messages"].as_array().and_then(|messages| messages.last()).cloned().unwrap_or_default();
+        let content = if last["role"] == "assistant" && last["content"] == "Hel" { "lo" } else { "Hello" };
+        let body = [
+            format!(r#"data: {{"choices":[{{"delta":{{"content":"{}"}},"finish_reason":"stop"}}]}}"#, content),
+            "data: [DONE]".to_string(),
+        ]
+        .join("\n\n");
+        (200, body, 0)
+    })
+    .await;
+    let config = Config::builder()
+        .api("failover-dropping", &dropping, "sk", "failover-dropping-model")
+        .api("failover-unavailable", &unavailable, "sk", "failover-unavailable-model")
+        .api("failover-backup", &backup, "sk", "failover-backup-model")
+        .capability(ModelCapability::LongContext, "failover-dropping")
+        .capability(ModelCapability::LongContext, "failover-unavailable")
+        .capability(ModelCapability::LongContext, "failover-backup")
+        .build()
+        .unwrap();
+    Config::with_scoped(config, async {
+        let fallback = ModelOverride::Api("failover-backup".to_string());
+
+        let mut chat = SingleChat::builder()
+            .api("failover-dropping")
+            .stream(true)
+            .stream_fallback(fallback.clone())
+            .build()
+            .unwrap();
+        chat.base.stream_resume_attempts = 0;
+        let deltas: Vec<String> = chat
+            .stream_answer("hi")
+            .await
+            .unwrap()
+            .map(|delta| delta.unwrap())
+            .collect()
+            .await;
+        let answer = chat.base.session.last_message_mut().unwrap().clone();
+        format_test_block("Stream Failover", || format!("{:?}\n{:?}", deltas, answer.metadata));
+        assert_eq!(deltas, vec!["Hel", "lo"]);
+        assert_eq!(answer.content.as_text(), Some("Hello"));
+        let metadata = answer.metadata.unwrap();
+        assert_eq!(metadata.model, "failover-backup-model");
+        assert_eq!(metadata.provider_switches.len(), 1);
+        let switch = &metadata.provider_switches[0];
+        assert_eq!((switch.from.as_str(), switch.to.as_str()), ("failover-dropping", "failover-backup"));
+        assert_eq!(switch.received_bytes, 3);
+        // 换用只在本次请求内有效
+        // The switch only lasts for the request
+        assert_eq!(chat.base.api_name, "failover-dropping");
+
+        // 首个 token 之前失败时备用API从头回答
+        // A failure before the first token makes the fallback answer from scratch
+        let mut chat =
+            SingleChat::builder().api("failover-unavailable").stream(true).stream_fallback(fallback).build().unwrap();
+        assert_eq!(chat.get_answer("hi").await.unwrap(), "Hello");
+        let switches = chat.base.session.last_message_mut().unwrap().metadata.clone().unwrap().provider_switches;
+        assert_eq!(switches.len(), 1);
+        assert_eq!((switches[0].error.as_str(), switches[0].received_bytes), ("chat.http_error", 0));
+        assert_eq!(chat.base.api_name, "failover-unavailable");
+
+        let mut chat = SingleChat::builder().api("failover-unavailable").stream(true).build().unwrap();
+        assert!(chat.get_answer("hi").await.is_err());
+    })
+    .await;
+}
@@ -0,0 +1,100 @@
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::time::Duration;
+
+use serde_json::json;
+
+use crate::chat::agent::{AgentScope, Deadline};
+use crate::chat::chat_single::SingleChat;
+use crate::chat::subagent::registered_agent;
+use crate::chat::transaction::{IntentStatus, TransactionScope};
+use crate::config::{Config, ModelCapability};
+use crate::schema::tool_schema::{ToolRegistry, create_tool, get_tool_registry};
+use crate::testing::MockProvider;
+use crate::tests::format_test_block;
+
+static CHARGED: AtomicI64 = AtomicI64::new(0);
+
+pub async fn test_subagent() {
+    // 在独立的配置中运行，工具参数解析只会选到模拟提供商
+    // Run on a configuration of its own, so tool argument parsing only picks the mock provider
+    Config::with_scoped(Config::builder().build().unwrap(), async {
+        let mock = MockProvider::start("subagent-api").await.with_capability(ModelCapability::ToolUse);
+        let researcher = SingleChat::builder().api("subagent-api").system("You research facts.").build().unwrap();
+        let tool = ToolRegistry::register_agent("subagent_researcher", researcher);
+        assert_eq!(tool["function"]["name"], "subagent_researcher");
+        assert!(tool["function"]["description"].as_str().unwrap().contains("You research facts."));
+
+        // 子智能体在全新的子对话中完成任务，最终回答作为工具结果交回
+        // The sub-agent carries out the task in a fresh child chat, its final answer handed back as the tool result
+        let mut chat = SingleChat::builder().api("subagent-api").tools(vec![tool]).build().unwrap();
+        mock.reply("<ToolUse>ask the researcher</ToolUse>")
+            .reply_tool_call("subagent_researcher", json!({"task": "Find the capital of France"}))
+            .reply("Paris.")
+            .reply("The researcher says the capital is Paris.");
+        let run = chat.run_agent("Which city is the capital of France?", Deadline::after(Duration::from_secs(10)));
+        let run = run.await.unwrap();
+        assert!(run.is_complete());
+        assert_eq!(run.answer, "The researcher says the capital is Paris.");
+        let result: serde_json::Value = serde_json::from_str(&run.tool_results[0]).unwrap();
+        assert_eq!(result, json!({"answer": "Paris.", "stop": "answered"}));
+        mock.request(2)
+            .contains("system: You research facts.")
+            .contains("user: Find the capital of France")
+            .not_contains("Which city");
+
+        // 每次调用互不共享历史
+        // Calls share no history
+        let agent = registered_agent("subagent_researcher").unwrap();
+        mock.reply("Berlin.");
+        let output = agent.call_tool("subagent_researcher", json!({"task": "Capital of Germany"})).await.unwrap();
+        assert_eq!(output["answer"], "Berlin.");
+        mock.last_request().not_contains("Paris").message_count(2);
+        assert!(agent.call_tool("subagent_researcher", json!({})).await.is_err());
+
+        // 子智能体沿用调用方的截止时间，调用方已到期时不再发出请求
+        // The sub-agent keeps the caller's deadline and sends no request once the caller expired
+        let caller = AgentScope {
+            deadline: Deadline::after(Duration::ZERO),
+            journal: None,
+        };
+        let output = caller.run(agent.call_tool("subagent_researcher", json!({"task": "Capital of Spain"}))).await;
+        assert_eq!(output.unwrap()["stop"], "deadline_expired");
+        assert_eq!(mock.requests().len(), 5);
+
+        // 子智能体有副作用的工具调用记录到调用方的事务中，随调用方一起提交，而不是自行提交
+        // Calls to tools with side effects by the sub-agent are recorded into the caller's transaction and committed
+        // with the caller rather than on their own
+        get_tool_registry().insert(
+            "subagent_charge".to_string(),
+            create_tool("subagent_charge", |arguments| {
+                let amount = arguments["amount"].as_i64().unwrap_or_default();
+                Ok(json!(CHARGED.fetch_add(amount, Ordering::SeqCst) + amount))
+            })
+            .1,
+        );
+        let cashier = SingleChat::builder().api("subagent-api").system("You take payments.").build().unwrap();
+        let cashier = ToolRegistry::register_agent("subagent_cashier", cashier);
+        let scope = TransactionScope::new("checkout").side_effect("subagent_charge");
+        let mut chat =
+            SingleChat::builder().api("subagent-api").tools(vec![cashier]).transaction(scope).build().unwrap();
+        mock.reply("<ToolUse>ask the cashier</ToolUse>")
+            .reply_tool_call("subagent_cashier", json!({"task": "Charge 5"}))
+            .reply("<ToolUse>charge 5</ToolUse>")
+            .reply_tool_call("subagent_charge", json!({"amount": 5}))
+            .reply("Charged.")
+            .reply("Paid.");
+        let run = chat.run_agent("Pay for it", Deadline::after(Duration::from_secs(10))).await.unwrap();
+        assert_eq!(run.answer, "Paid.");
+        assert_eq!(run.journal.len(), 1);
+        assert_eq!((run.journal[0].name.as_str(), run.journal[0].status), ("subagent_charge", IntentStatus::Committed));
+        assert_eq!(CHARGED.load(Ordering::SeqCst), 5);
+        assert_eq!(mock.pending(), 0);
+        format_test_block("subagent", || mock.request(2).prompt());
+
+        assert!(ToolRegistry::remove_agent("subagent_researcher"));
+        assert!(ToolRegistry::remove_agent("subagent_cashier"));
+        assert!(registered_agent("subagent_researcher").is_none());
+        get_tool_registry().remove("subagent_charge");
+    })
+    .await;
+}
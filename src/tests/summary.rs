@@ -0,0 +1,44 @@
+use crate::chat::chat_single::SingleChat;
+use crate::chat::summary::{ChatMetadata, clean_title};
+use crate::config::ModelCapability;
+use crate::testing::MockProvider;
+use crate::tests::format_test_block;
+
+pub async fn test_summary() {
+    test_clean_title();
+
+    let mock = MockProvider::start("summary-api").await;
+    mock.reply("You can reset it from the login page.");
+    let mut chat = SingleChat::builder().api("summary-api").system("You are a support agent.").build().unwrap();
+    assert!(chat.generate_title().await.is_err());
+    chat.get_answer("How do I reset my password?").await.unwrap();
+
+    // 没有绑定廉价模型时使用对话自身的API，辅助请求不写入对话历史
+    // Without a cheap model the chat's own API is used, and the helper request stays out of the chat history
+    mock.reply("标题：“Password reset help”");
+    assert_eq!(chat.generate_title().await.unwrap(), "Password reset help");
+    mock.last_request()
+        .contains("user: How do I reset my password?")
+        .contains("assistant: You can reset it from the login page.")
+        .not_contains("You are a support agent.")
+        .message_count(2);
+    assert_eq!(chat.base.session.default_path.len(), 3);
+
+    let mock = mock.with_capability(ModelCapability::Cheap);
+    mock.reply("  The user asked how to reset a password and was pointed to the login page.\n");
+    let summary = chat.summary().await.unwrap();
+    assert_eq!(
+        chat.base.metadata,
+        ChatMetadata {
+            title: Some("Password reset help".to_string()),
+            summary: Some("The user asked how to reset a password and was pointed to the login page.".to_string()),
+        }
+    );
+    format_test_block("chat_summary", || format!("{:?}\n{}", chat.base.metadata, summary));
+}
+
+fn test_clean_title() {
+    assert_eq!(clean_title("《旅行计划》。"), "旅行计划");
+    assert_eq!(clean_title("\n\"Trip planning.\"\nextra"), "Trip planning");
+    assert_eq!(clean_title(&"长".repeat(50)).chars().count(), 30);
+}
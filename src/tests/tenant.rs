@@ -0,0 +1,122 @@
+use std::path::Path;
+use std::time::Duration;
+
+use serde_json::json;
+
+use crate::chat::chat_base::ChatError;
+use crate::chat::chat_single::SingleChat;
+use crate::chat::queue::QueuePriority;
+use crate::chat::tenant::Tenant;
+use crate::config::Config;
+use crate::testing::MockProvider;
+use crate::tests::format_test_block;
+
+fn tool(name: &str) -> serde_json::Value {
+    json!({
+        "type": "function",
+        "function": {
+            "name": name,
+            "description": "A tenant test tool",
+            "parameters": {"type": "object", "properties": {"x": {"type": "string"}}, "required": ["x"]}
+        }
+    })
+}
+
+pub async fn test_tenant() {
+    // 在独立的配置中运行，模拟提供商不会留在全局配置中
+    // Run on a configuration of its own, so the mock provider does not stay registered globally
+    Config::with_scoped(Config::builder().build().unwrap(), async {
+        let mock = MockProvider::start("tenant-api").await;
+        let acme = Tenant::new("acme").token_budget(3).allowed_tools(&["tenant_search"]);
+        let beta = Tenant::new("beta").rate_limit(1, Duration::from_secs(60));
+
+        // 工具按租户筛选，用量标签与排队键按租户设置
+        // Tools are filtered per tenant, the usage tag and queue key are set per tenant
+        let mut chat = SingleChat::builder()
+            .api("tenant-api")
+            .tenant(acme.clone())
+            .tools(vec![tool("tenant_search"), tool("tenant_delete")])
+            .build()
+            .unwrap();
+        assert_eq!(chat.tools().len(), 1);
+        assert_eq!(chat.tools()[0]["function"]["name"], "tenant_search");
+        assert!(chat.base.tags.contains(&"tenant:acme".to_string()));
+        assert_eq!(chat.base.queue_key.as_deref(), Some("tenant:acme"));
+
+        // 每次调用 2 个令牌，第二次调用后用完 3 个令牌的预算，第三次不再发出请求
+        // Each call uses 2 tokens, so the 3 token budget is used up after the second call and the third is not sent
+        mock.reply("one").reply("two");
+        assert_eq!(chat.get_answer("first").await.unwrap(), "one");
+        let mut other = SingleChat::builder().api("tenant-api").tenant(acme.clone()).build().unwrap();
+        assert_eq!(other.get_answer("second").await.unwrap(), "two");
+        let sent = mock.requests().len();
+        let exceeded = chat.get_answer("third").await.unwrap_err();
+        assert!(matches!(exceeded.current_context(), ChatError::TenantBudgetExceeded(id) if id == "acme"));
+        assert_eq!(mock.requests().len(), sent);
+        assert_eq!(acme.usage().total_tokens, 4);
+        // 被拒绝的调用与用量账本中一样记为失败的请求
+        // The rejected call counts as a failed request, as in the usage ledger
+        assert_eq!(acme.usage().requests, 3);
+        assert_eq!(acme.usage().errors, 1);
+
+        // 标题等辅助请求同样受租户预算约束
+        // Helper requests such as titles are bound by the tenant's budget as well
+        let helper = chat.generate_title().await.unwrap_err();
+        assert!(matches!(helper.current_context(), ChatError::TenantBudgetExceeded(id) if id == "acme"));
+        assert_eq!(mock.requests().len(), sent);
+
+        // 其他租户不受影响，但有自己的频率上限
+        // Other tenants are unaffected but have their own rate limit
+        let mut limited = SingleChat::builder().api("tenant-api").tenant(beta.clone()).build().unwrap();
+        mock.reply("three");
+        assert_eq!(limited.get_answer("hello").await.unwrap(), "three");
+        let rate_limited = limited.get_answer("again").await.unwrap_err();
+        assert!(matches!(rate_limited.current_context(), ChatError::TenantRateLimited(id) if id == "beta"));
+        assert_eq!(beta.usage().total_tokens, 2);
+        assert_eq!(acme.usage().total_tokens, 4);
+
+        // 附件存入租户的命名空间，其他租户无法读取
+        // Attachments go into the tenant's namespace and other tenants cannot read them
+        let attachment = chat.attach("notes.txt", "text/plain", "secret", None).unwrap();
+        assert!(attachment.id.starts_with("acme/"));
+        assert_eq!(acme.load_attachment(&attachment.id).unwrap(), "secret");
+        let forbidden = beta.load_attachment(&attachment.id).unwrap_err();
+        assert!(matches!(forbidden.current_context(), ChatError::TenantForbidden(id) if id == "beta"));
+        assert_eq!(acme.storage_key("sessions/1"), "acme/sessions/1");
+
+        // 嵌套命名空间中的附件不属于外层命名空间
+        // Attachments of a nested namespace do not belong to the outer namespace
+        let acme_eu = Tenant::new("acme-eu").namespace("acme/eu");
+        let mut nested = SingleChat::builder().api("tenant-api").tenant(acme_eu.clone()).build().unwrap();
+        let nested_attachment = nested.attach("eu.txt", "text/plain", "eu secret", None).unwrap();
+        assert!(nested_attachment.id.starts_with("acme/eu/"));
+        assert_eq!(acme_eu.load_attachment(&nested_attachment.id).unwrap(), "eu secret");
+        let outer = acme.load_attachment(&nested_attachment.id).unwrap_err();
+        assert!(matches!(outer.current_context(), ChatError::TenantForbidden(id) if id == "acme"));
+        assert!(acme_eu.load_attachment(&attachment.id).is_err());
+        assert_eq!(Tenant::new("../x").storage_dir("/data"), Path::new("/data/%2E%2E%2Fx"));
+        assert_ne!(Tenant::new("acme.co").storage_dir("/data"), Tenant::new("acme_co").storage_dir("/data"));
+
+        // 先设置工具再设置租户时同样按租户筛选
+        // Tools set before the tenant are filtered for it all the same
+        let mut late = SingleChat::builder().api("tenant-api").build().unwrap();
+        late.set_tools(vec![tool("tenant_search"), tool("tenant_delete")]).unwrap();
+        late.set_tenant(acme.clone());
+        assert_eq!(late.tools().len(), 1);
+        assert!(!serde_json::to_string(&late.base.session).unwrap().contains("tenant_delete"));
+
+        // 后台请求不能使用为前台保留的频率余量
+        // Background requests cannot use the rate headroom kept for the foreground
+        let gamma = Tenant::new("gamma").rate_limit(3, Duration::from_secs(60)).interactive_reserve(2);
+        gamma.admit_with_priority(QueuePriority::Background).unwrap();
+        let background = gamma.admit_with_priority(QueuePriority::Background).unwrap_err();
+        assert!(matches!(background.current_context(), ChatError::TenantRateLimited(id) if id == "gamma"));
+        gamma.admit_with_priority(QueuePriority::Interactive).unwrap();
+        gamma.admit().unwrap();
+        assert!(gamma.admit_with_priority(QueuePriority::Interactive).is_err());
+
+        format_test_block("Tenant", || format!("{:?}\n{:?}\n{:?}", acme.usage(), beta.usage(), exceeded));
+        assert_eq!(mock.pending(), 0);
+    })
+    .await;
+}
@@ -0,0 +1,166 @@
+use futures::StreamExt;
+use serde_json::json;
+
+use crate::chat::chat_single::SingleChat;
+use crate::chat::chat_tool::ToolChoice;
+use crate::chat::message::Role;
+use crate::config::{Config, ModelCapability};
+use crate::schema::tool_schema::{create_tool, get_tool_registry};
+use crate::testing::{MockProvider, SnapshotStore, ToolCallLog, UserSimulator, approx_tokens, assert_json_includes, capture_request};
+use crate::tests::format_test_block;
+
+pub async fn test_testing() {
+    assert_eq!(approx_tokens("abcdefgh"), 2);
+    assert_eq!(approx_tokens("你好ab"), 3);
+
+    let mock = MockProvider::start("harness-api").await.with_capability(ModelCapability::ToolUse);
+    test_scripted_answers(&mock).await;
+    test_tool_calls(&mock).await;
+    test_tool_choice(&mock).await;
+    test_snapshots(&mock).await;
+    test_simulator(&mock).await;
+    assert_eq!(mock.pending(), 0);
+}
+
+async fn test_scripted_answers(mock: &MockProvider) {
+    mock.reply("Paris is the capital.")
+        .reply_json(json!({"city": "Paris", "tags": ["capital", "france"], "population": 2100000}))
+        .reply("streamed answer here");
+
+    let mut chat = SingleChat::builder()
+        .api(mock.api_name())
+        .system("You are a geography tutor.")
+        .build()
+        .unwrap();
+    assert_eq!(chat.get_answer("What is the capital of France?").await.unwrap(), "Paris is the capital.");
+    mock.last_request()
+        .contains("geography tutor")
+        .matches(r"user: What is the capital of \w+\?")
+        .not_contains("Berlin")
+        .message_count(2)
+        .max_tokens(32);
+
+    let answer = chat.get_answer("Describe it as JSON").await.unwrap();
+    let value: serde_json::Value = serde_json::from_str(&answer).unwrap();
+    assert_json_includes(&value, &json!({"city": "Paris", "tags": ["capital", "france"]}));
+    mock.last_request().message_count(4).contains("assistant: Paris is the capital.");
+
+    let deltas: Vec<String> = chat.stream_answer("Once more").await.unwrap().map(Result::unwrap).collect().await;
+    assert_eq!(deltas.concat(), "streamed answer here");
+    assert_eq!(mock.requests().last().unwrap()["stream"], true);
+    format_test_block("testing", || mock.last_request().prompt());
+}
+
+async fn test_tool_calls(mock: &MockProvider) {
+    get_tool_registry().insert(
+        "harness_add".to_string(),
+        create_tool("harness_add", |arguments| {
+            Ok(json!(arguments["a"].as_i64().unwrap_or(0) + arguments["b"].as_i64().unwrap_or(0)))
+        })
+        .1,
+    );
+
+    // 工具参数解析按能力选择API，暂时只留下模拟提供商
+    // Tool argument parsing selects an API by capability, leave only the mock provider for now
+    Config::set_api_weight("valid-api", 0);
+    mock.reply("<ToolUse>add 1 and 2</ToolUse>").reply_tool_call("harness_add", json!({"a": 1, "b": 2}));
+
+    let mut chat = SingleChat::builder().api(mock.api_name()).build().unwrap();
+    let log = ToolCallLog::attach(&mut chat);
+    let (_, results) = chat.get_tool_answer("add 1 and 2").await.unwrap();
+    Config::set_api_weight("valid-api", 1);
+    Config::set_api_weight(&format!("{}-tool_use", mock.api_name()), 0);
+
+    assert_eq!(results, vec!["3"]);
+    mock.request(mock.requests().len() - 2).contains("add 1 and 2");
+    let call = log.assert_called_with("harness_add", json!({"a": 1}));
+    assert_eq!(call.output.as_deref(), Some("3"));
+    log.assert_not_called("send_email");
+}
+
+async fn test_tool_choice(mock: &MockProvider) {
+    Config::set_api_weight("valid-api", 0);
+    Config::set_api_weight(&format!("{}-tool_use", mock.api_name()), 1);
+    let mut chat = SingleChat::builder()
+        .api(mock.api_name())
+        .tool_choice(ToolChoice::function("harness_add"))
+        .parallel_tool_calls(false)
+        .build()
+        .unwrap();
+
+    // 回答中没有工具调用时仍强制调用
+    // The call is forced even when the answer contains none
+    mock.reply("No tool needed.").reply_tool_call("harness_add", json!({"a": 2, "b": 3}));
+    let (_, results) = chat.get_tool_answer("add 2 and 3").await.unwrap();
+    assert_eq!(results, vec!["5"]);
+    let request = mock.requests().pop().unwrap();
+    assert_eq!(request["tool_choice"], json!({"type": "function", "function": {"name": "harness_add"}}));
+    assert_eq!(request["parallel_tool_calls"], false);
+    mock.last_request().contains("add 2 and 3");
+
+    // 不允许并行时回答中的多个调用依次执行，不会丢弃
+    // Without parallel calls the calls of an answer run one after another instead of being dropped
+    mock.reply("<ToolUse>add 1 and 1</ToolUse><ToolUse>add 2 and 2</ToolUse>")
+        .reply_tool_call("harness_add", json!({"a": 1, "b": 1}))
+        .reply_tool_call("harness_add", json!({"a": 2, "b": 2}));
+    let (_, results) = chat.get_tool_answer("add twice").await.unwrap();
+    Config::set_api_weight("valid-api", 1);
+    Config::set_api_weight(&format!("{}-tool_use", mock.api_name()), 0);
+    assert_eq!(results, vec!["2", "4"]);
+}
+
+async fn test_snapshots(mock: &MockProvider) {
+    // 已提交的快照守护提示词组装
+    // The committed snapshot guards the prompt assembly
+    let mut chat = SingleChat::builder().api(mock.api_name()).system("You are a geography tutor.").build().unwrap();
+    chat.base.add_message(Role::User, "What is the capital of France?").unwrap();
+    chat.base.add_message(Role::Assistant, "Paris.").unwrap();
+    let body = capture_request(&mut chat, "And of Italy?").await.unwrap();
+    SnapshotStore::new(concat!(env!("CARGO_MANIFEST_DIR"), "/src/tests/snapshots")).assert("geography_request", &body);
+    assert_eq!(mock.pending(), 0);
+
+    let store = SnapshotStore::new(std::env::temp_dir().join("rhine_test_snapshots"));
+    std::fs::create_dir_all(std::env::temp_dir().join("rhine_test_snapshots")).unwrap();
+    std::fs::write(store.path("changed"), "{\n  \"messages\": [],\n  \"model\": \"a\"\n}\n").unwrap();
+    store.assert("changed", &json!({"model": "a", "messages": []}));
+    store.assert("changed", &json!({"messages": [], "model": "a"}));
+    let changed = std::panic::catch_unwind(|| store.assert("changed", &json!({"model": "b", "messages": []})));
+    assert!(changed.is_err());
+    let pending = std::fs::read_to_string(store.path("changed").with_extension("json.new")).unwrap();
+    format_test_block("snapshot", || pending.clone());
+    assert!(pending.contains("\"model\": \"b\""));
+}
+
+async fn test_simulator(agent_mock: &MockProvider) {
+    let user_mock = MockProvider::start("simulator-api").await;
+    user_mock
+        .reply("I want a refund for order 42.")
+        .reply("Thanks, that is all.")
+        .reply("[DONE]")
+        .reply(&json!({"goals": [
+            {"goal": "Get a refund for order 42", "achieved": true, "reason": "The agent issued the refund"},
+        ]}).to_string());
+    agent_mock.reply("Your refund for order 42 has been issued.").reply("You are welcome!");
+
+    let mut agent = SingleChat::builder().api(agent_mock.api_name()).system("You are a support agent.").build().unwrap();
+    let report = UserSimulator::new("simulator-api", "An impatient customer")
+        .goal("Get a refund for order 42")
+        .goal("Receive a discount code")
+        .max_turns(4)
+        .run(&mut agent)
+        .await
+        .unwrap();
+
+    format_test_block("simulator", || serde_json::to_string_pretty(&report).unwrap());
+    assert!(report.finished);
+    assert_eq!(report.transcript.len(), 2);
+    assert_eq!(report.transcript[0].agent, "Your refund for order 42 has been issued.");
+    assert!(report.goals[0].achieved);
+    assert!(!report.goals[1].achieved);
+    assert!(!report.all_achieved());
+    user_mock.request(0).contains("impatient customer").contains("Receive a discount code");
+    user_mock.request(1).contains("user: Your refund for order 42 has been issued.");
+    user_mock.last_request().contains("I want a refund for order 42.");
+    assert_eq!(user_mock.pending(), 0);
+    assert_eq!(agent_mock.pending(), 0);
+}
@@ -0,0 +1,105 @@
+use serde_json::json;
+
+use crate::chat::chat_single::SingleChat;
+use crate::prompt::assembler::assemble_tools_prompt;
+use crate::error::ReportExt;
+use crate::schema::tool_docs::{
+    add_tool_example, register_tool, register_tool_definition, remove_tool_definition, render_tools_json,
+    render_tools_markdown, tool_definitions, tool_docs,
+};
+use crate::schema::tool_schema::{get_tool_function, get_tool_registry};
+use crate::testing::MockProvider;
+use crate::tests::format_test_block;
+
+pub async fn test_tool_docs() {
+    let weather = json!({
+        "type": "function",
+        "function": {
+            "name": "docs_get_weather",
+            "description": "Get the current weather of a city.",
+            "parameters": {
+                "type": "object",
+                "properties": {
+                    "city": {"type": "string", "description": "City name"},
+                    "unit": {"type": "string", "description": "Temperature unit", "enum": ["celsius", "fahrenheit"]},
+                    "location": {
+                        "type": "object",
+                        "description": "Exact position",
+                        "properties": {"lat": {"type": "number", "description": "Latitude"}},
+                    },
+                },
+                "required": ["city"],
+            },
+        },
+    });
+    let unregistered = json!({
+        "type": "function",
+        "function": {"name": "docs_unregistered", "description": "Not backed by a function.", "parameters": {
+            "type": "object", "properties": {},
+        }},
+    });
+    // 定义与函数一同登记
+    // The definition is registered together with the function
+    register_tool(&weather, |_| Ok(json!({"temperature": 21}))).unwrap();
+    assert_eq!(get_tool_function("docs_get_weather").unwrap()(json!({})).unwrap()["temperature"], 21);
+    register_tool_definition(&unregistered).unwrap();
+    assert!(register_tool_definition(&json!({"type": "function", "function": {"name": "broken"}})).is_err());
+
+    // 示例在重新登记定义后保留，未登记的工具不能添加示例
+    // Examples survive re-registering the definition, and unregistered tools cannot get examples
+    add_tool_example("docs_get_weather", "Weather in Paris", json!({"city": "Paris", "unit": "celsius"})).unwrap();
+    register_tool_definition(&weather).unwrap();
+    let unknown = add_tool_example("docs_missing", "Nothing", json!({})).unwrap_err();
+    assert_eq!(unknown.code(), "tool_schema.unknown_tool");
+
+    let docs: Vec<_> = tool_docs().into_iter().filter(|doc| doc.name.starts_with("docs_")).collect();
+    assert_eq!(docs.len(), 2);
+    assert!(docs[0].registered);
+    assert!(!docs[1].registered);
+    assert_eq!(docs[0].examples.len(), 1);
+    assert!(tool_definitions().contains(&weather));
+
+    // Markdown 文档列出参数表、嵌套属性、枚举与示例
+    // The Markdown documentation lists the parameter table, nested properties, enums and examples
+    let markdown = render_tools_markdown(&docs);
+    assert!(markdown.starts_with("# Tools\n\n## `docs_get_weather`\n\nGet the current weather of a city.\n"));
+    assert!(markdown.contains("| `city` | string | yes | City name |"));
+    assert!(markdown.contains("| `unit` | string | no | Temperature unit One of: `\"celsius\"`, `\"fahrenheit\"`. |"));
+    assert!(markdown.contains("| `location.lat` | number | no | Latitude |"));
+    assert!(markdown.contains("Weather in Paris\n\n```json\n{\n  \"city\": \"Paris\""));
+    assert!(markdown.contains("> No function is registered under this name."));
+    assert_eq!(render_tools_json(&docs)[0]["examples"][0]["arguments"]["city"], "Paris");
+
+    // 模型看到的工具提示来自同一份定义与示例
+    // The tools prompt the model sees comes from the same definitions and examples
+    let prompt = assemble_tools_prompt(vec![weather.clone()]).unwrap();
+    assert!(prompt.contains("调用示例:"));
+    assert!(prompt.contains(r#"Weather in Paris: {"city":"Paris","unit":"celsius"}"#));
+
+    let mock = MockProvider::start("tool-docs-api").await;
+    let mut chat = SingleChat::builder().api("tool-docs-api").tools(vec![weather]).build().unwrap();
+    mock.reply("It is sunny.");
+    chat.get_answer("Weather?").await.unwrap();
+    mock.last_request().contains("Weather in Paris");
+
+    // 只交给对话的工具同样出现在文档中
+    // Tools only handed to a chat show up in the documentation as well
+    let chat_only = json!({
+        "type": "function",
+        "function": {"name": "docs_chat_only", "description": "Only known to a chat.", "parameters": {
+            "type": "object", "properties": {"query": {"type": "string", "description": "Query"}},
+        }},
+    });
+    chat.set_tools(vec![chat_only.clone()]).unwrap();
+    let documented: Vec<_> = tool_docs().into_iter().filter(|doc| doc.name == "docs_chat_only").collect();
+    assert_eq!(documented.len(), 1);
+    assert_eq!(documented[0].to_definition(), chat_only);
+
+    format_test_block("Tool Docs", || markdown.clone());
+    remove_tool_definition("docs_get_weather");
+    remove_tool_definition("docs_unregistered");
+    remove_tool_definition("docs_chat_only");
+    get_tool_registry().remove("docs_get_weather");
+    assert!(tool_docs().iter().all(|doc| !doc.name.starts_with("docs_")));
+    assert_eq!(mock.pending(), 0);
+}
@@ -0,0 +1,125 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde_json::json;
+
+use crate::chat::agent::Deadline;
+use crate::chat::chat_base::ChatError;
+use crate::chat::chat_single::SingleChat;
+use crate::chat::image::take_tool_images;
+use crate::chat::mcp::McpTools;
+use crate::chat::openapi::OpenApiTools;
+use crate::chat::tool_source::{LocalTools, ToolSource, collect_tools, function_tool};
+use crate::config::{Config, ModelCapability};
+use crate::schema::tool_schema::{create_tool, get_tool_registry};
+use crate::testing::MockProvider;
+use crate::tests::{format_test_block, spawn_mock_server};
+
+/// 模拟的 MCP 服务，提供一个 `lookup` 工具
+/// Mock MCP server offering one `lookup` tool
+async fn mcp_server() -> String {
+    spawn_mock_server(|body| {
+        let result = match body["method"].as_str() {
+            Some("initialize") => json!({"protocolVersion": "2025-03-26", "capabilities": {"tools": {}}}),
+            Some("tools/list") => json!({"tools": [{
+                "name": "lookup",
+                "description": "Look up a customer",
+                "inputSchema": {"type": "object", "properties": {"q": {"type": "string"}}, "required": ["q"]}
+            }]}),
+            Some("tools/call") if body["params"]["arguments"]["q"] == "logo" => json!({"content": [
+                {"type": "text", "text": "the logo of acme"},
+                {"type": "image", "data": "iVBORw0KGgo=", "mimeType": "image/png"},
+            ]}),
+            Some("tools/call") => json!({"content": [{
+                "type": "text",
+                "text": format!("customer {} is active", body["params"]["arguments"]["q"].as_str().unwrap_or_default())
+            }]}),
+            _ => return (202, String::new()),
+        };
+        (200, json!({"jsonrpc": "2.0", "id": body["id"], "result": result}).to_string())
+    })
+    .await
+}
+
+/// 模拟的账单接口，原样返回请求体
+/// Mock billing service echoing the request body
+async fn billing_spec() -> serde_json::Value {
+    let url = spawn_mock_server(|body| (200, json!({"created": true, "note": body}).to_string())).await;
+    json!({
+        "openapi": "3.0.0",
+        "servers": [{"url": url}],
+        "paths": {"/invoices/{id}/notes": {
+            "parameters": [{"$ref": "#/components/parameters/InvoiceId"}],
+            "post": {
+                "operationId": "lookup",
+                "summary": "Add a note to an invoice",
+                "parameters": [{"name": "notify", "in": "query", "schema": {"type": "boolean"}}],
+                "requestBody": {"required": true, "content": {"application/json": {"schema": {
+                    "type": "object", "properties": {"text": {"type": "string"}}
+                }}}}
+            }
+        }},
+        "components": {"parameters": {"InvoiceId": {"name": "id", "in": "path", "schema": {"type": "string"}}}}
+    })
+}
+
+pub async fn test_tool_sources() {
+    // 在独立的配置中运行，工具参数解析只会选到模拟提供商
+    // Run on a configuration of its own, so tool argument parsing only picks the mock provider
+    Config::with_scoped(Config::builder().build().unwrap(), async {
+        get_tool_registry().insert(
+            "source_clock".to_string(),
+            create_tool("source_clock", |_| Ok(json!("12:00"))).1,
+        );
+        let clock = function_tool("source_clock", Some("Time"), None);
+        let local: Arc<dyn ToolSource> = Arc::new(LocalTools::new(vec![clock]));
+        let crm: Arc<dyn ToolSource> = Arc::new(McpTools::new("crm", &mcp_server().await));
+        let billing = OpenApiTools::from_spec("billing", &billing_spec().await).unwrap();
+        let url = billing.url_for("lookup", &json!({"id": "A/1", "notify": true})).unwrap();
+        assert!(url.ends_with("/invoices/A%2F1/notes?notify=true"));
+        let billing: Arc<dyn ToolSource> = Arc::new(billing);
+
+        // 同名的工具按来源加上命名空间，加上命名空间后仍然重名时报错
+        // Tools of the same name are namespaced by source, names colliding even then are an error
+        let (tools, _) = collect_tools(&[local.clone(), crm.clone(), billing.clone()]).await.unwrap();
+        let names: Vec<_> = tools.iter().map(|tool| tool["function"]["name"].as_str().unwrap()).collect();
+        assert_eq!(names, ["source_clock", "crm__lookup", "billing__lookup"]);
+        assert_eq!(tools[2]["function"]["parameters"]["required"], json!(["id", "body"]));
+        let duplicate = collect_tools(&[crm.clone(), crm.clone()]).await.unwrap_err();
+        assert!(matches!(duplicate.current_context(), ChatError::InvalidConfig));
+
+        // MCP 工具返回的图片内容转换为带标记的图片，由对话取出
+        // Image content returned by MCP tools is converted to marked images, for the chat to take out
+        let mut logo = crm.call_tool("lookup", json!({"q": "logo"})).await.unwrap();
+        assert_eq!(logo["output"], "the logo of acme");
+        assert_eq!(&*take_tool_images(&mut logo)[0], "data:image/png;base64,iVBORw0KGgo=");
+        assert_eq!(logo["images"], json!(["[image 1]"]));
+
+        let mock = MockProvider::start("source-api").await.with_capability(ModelCapability::ToolUse);
+        let mut chat = SingleChat::builder().api("source-api").build().unwrap();
+        chat.set_tool_sources(vec![local, crm, billing]).await.unwrap();
+        assert_eq!(chat.tools().len(), 3);
+
+        // 模型调用按名称转给对应的来源，工具提示跨来源统一组装
+        // Calls by the model are routed to their source by name, with one tools prompt across sources
+        mock.reply("<ToolUse>check acme</ToolUse>")
+            .reply_tool_call("crm__lookup", json!({"q": "acme"}))
+            .reply("<ToolUse>note the invoice</ToolUse>")
+            .reply_tool_call("billing__lookup", json!({"id": "A1", "body": {"text": "paid"}}))
+            .reply("<ToolUse>what time is it</ToolUse>")
+            .reply_tool_call("source_clock", json!({}))
+            .reply("Acme is active, the note is added and it is noon.");
+        let run = chat.run_agent("Check acme", Deadline::after(Duration::from_secs(10))).await.unwrap();
+        format_test_block("Tool Sources", || format!("{:#?}", run));
+        assert!(run.is_complete());
+        assert_eq!(run.tool_results[0], "\"customer acme is active\"");
+        let note: serde_json::Value = serde_json::from_str(&run.tool_results[1]).unwrap();
+        assert_eq!(note, json!({"created": true, "note": {"text": "paid"}}));
+        assert_eq!(run.tool_results[2], "\"12:00\"");
+        mock.last_request().contains("crm__lookup").contains("billing__lookup").contains("source_clock");
+
+        assert_eq!(mock.pending(), 0);
+        get_tool_registry().remove("source_clock");
+    })
+    .await;
+}
@@ -0,0 +1,88 @@
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::time::Duration;
+
+use error_stack::Report;
+use serde_json::json;
+
+use crate::chat::agent::Deadline;
+use crate::chat::chat_single::{SingleChat, ToolCallError};
+use crate::chat::transaction::{IntentStatus, TransactionScope};
+use crate::config::prompts::PromptKey;
+use crate::config::{Config, ModelCapability};
+use crate::schema::tool_schema::{ChatToolSchemaError, create_tool, get_tool_registry};
+use crate::testing::MockProvider;
+use crate::tests::format_test_block;
+
+static CHARGED: AtomicI64 = AtomicI64::new(0);
+static STOCK: AtomicI64 = AtomicI64::new(10);
+
+pub async fn test_transaction() {
+    let mock = MockProvider::start("txn-api").await.with_capability(ModelCapability::ToolUse);
+    get_tool_registry().insert(
+        "txn_charge".to_string(),
+        create_tool("txn_charge", |arguments| match arguments["amount"].as_i64() {
+            Some(amount) if amount > 0 => Ok(json!(CHARGED.fetch_add(amount, Ordering::SeqCst) + amount)),
+            _ => Err(Report::new(ChatToolSchemaError::FunctionCallError)),
+        })
+        .1,
+    );
+    get_tool_registry().insert(
+        "txn_reserve".to_string(),
+        create_tool("txn_reserve", |_| Ok(json!(STOCK.fetch_sub(1, Ordering::SeqCst) - 1))).1,
+    );
+    get_tool_registry().insert("txn_price".to_string(), create_tool("txn_price", |_| Ok(json!(42))).1);
+
+    let scope = TransactionScope::new("checkout").side_effect("txn_charge").compensate("txn_reserve", |_, _| {
+        STOCK.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    });
+    let mut chat = SingleChat::builder().api("txn-api").transaction(scope).build().unwrap();
+    let deadline = || Deadline::after(Duration::from_secs(10));
+
+    // 工具参数解析按能力选择API，暂时只留下模拟提供商
+    // Tool argument parsing selects an API by capability, leave only the mock provider for now
+    Config::set_api_weight("valid-api", 0);
+
+    // 没有副作用的工具立即执行，有副作用的只记录意图，运行以回答结束后才执行
+    // Tools without side effects run right away, those with side effects only record an intent run at the end
+    mock.reply("<ToolUse>price</ToolUse>")
+        .reply_tool_call("txn_price", json!({}))
+        .reply("<ToolUse>charge 42</ToolUse>")
+        .reply_tool_call("txn_charge", json!({"amount": 42}))
+        .reply("Paid 42.");
+    let run = chat.run_agent("Buy it", deadline()).await.unwrap();
+    assert_eq!(run.tool_results[0], "42");
+    mock.last_request().contains("pending").contains("该操作已记录");
+    assert_eq!(run.journal.len(), 1);
+    assert_eq!(run.journal[0].status, IntentStatus::Committed);
+    assert_eq!(run.journal[0].output.as_deref(), Some("42"));
+    assert_eq!(CHARGED.load(Ordering::SeqCst), 42);
+    format_test_block("transaction", || serde_json::to_string_pretty(&run.journal).unwrap());
+
+    // 运行失败时丢弃意图；交给模型的说明可以按提示词键替换
+    // Intents are discarded when the run fails; the note handed to the model can be replaced by prompt key
+    Config::set_capability_prompt(PromptKey::TransactionPending, "Recorded, it runs once the task succeeds");
+    mock.reply("<ToolUse>charge 7</ToolUse>")
+        .reply_tool_call("txn_charge", json!({"amount": 7}))
+        .reply_error(400, &json!({"error": {"message": "bad request"}}).to_string());
+    assert!(chat.run_agent("Buy another", deadline()).await.is_err());
+    mock.last_request().contains("Recorded, it runs once the task succeeds");
+    Config::remove_capability_prompt(&PromptKey::TransactionPending);
+    assert_eq!(CHARGED.load(Ordering::SeqCst), 42);
+
+    // 提交中途失败时补偿已执行的意图
+    // A commit failing halfway compensates the intents already executed
+    mock.reply("<ToolUse>reserve</ToolUse>")
+        .reply_tool_call("txn_reserve", json!({}))
+        .reply("<ToolUse>charge 0</ToolUse>")
+        .reply_tool_call("txn_charge", json!({"amount": 0}))
+        .reply("Reserved and paid.");
+    let report = chat.run_agent("Reserve and pay", deadline()).await.unwrap_err();
+    assert!(matches!(report.current_context(), ToolCallError::TransactionAborted(name) if name == "checkout"));
+    assert_eq!(STOCK.load(Ordering::SeqCst), 10);
+    assert_eq!(CHARGED.load(Ordering::SeqCst), 42);
+
+    Config::set_api_weight("valid-api", 1);
+    Config::set_api_weight("txn-api-tool_use", 0);
+    assert_eq!(mock.pending(), 0);
+}
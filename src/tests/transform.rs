@@ -0,0 +1,74 @@
+use futures::StreamExt;
+
+use crate::chat::chat_single::SingleChat;
+use crate::chat::transform::{MaskWords, StopSequences, StreamTransformer, StreamTransformers, StripMarkdown};
+use crate::testing::MockProvider;
+use crate::tests::format_test_block;
+
+pub async fn test_transform() {
+    test_transformers();
+    test_stream_transformers().await;
+}
+
+/// 逐段送入增量，返回每段的输出与结束时的剩余
+/// Feed the deltas one by one, returning the output of each and the rest at the end
+fn feed(transformer: &mut impl StreamTransformer, deltas: &[&str]) -> Vec<String> {
+    let mut outputs: Vec<String> = deltas.iter().map(|delta| transformer.push(delta)).collect();
+    outputs.push(transformer.finish());
+    outputs
+}
+
+fn test_transformers() {
+    // 被切断的词在补全后才遮盖，长词优先
+    // Split words are masked once complete, longer words first
+    let mut mask = MaskWords::new(["darn", "DARNED"]);
+    let outputs = feed(&mut mask, &["oh da", "rned it, Dar", "n!"]);
+    assert_eq!(outputs.concat(), "oh ****** it, ****!");
+    assert!(outputs.iter().all(|output| !output.contains("da") && !output.contains("Da")));
+
+    let mut strip = StripMarkdown::new();
+    let outputs = feed(
+        &mut strip,
+        &["## Sum", "mary\n> **Note**: see [the docs](https://", "example.com) and `cfg`\n```rust\nlet x = 1;\n```\n*done*"],
+    );
+    assert_eq!(outputs[0], "");
+    assert_eq!(outputs.concat(), "Summary\nNote: see the docs and cfg\nlet x = 1;\ndone");
+
+    // 可能是停止序列开头的文本暂存，停止后不再输出
+    // Text that may start a stop sequence is held back, nothing is sent after stopping
+    let mut stop = StopSequences::new(["<END>"]);
+    assert_eq!(stop.push("answer <E"), "answer ");
+    assert_eq!(stop.push("x> more <EN"), "<Ex> more ");
+    assert!(!stop.stopped());
+    assert_eq!(stop.push("D> ignored"), "");
+    assert!(stop.stopped());
+    assert_eq!(stop.push("after"), "");
+    assert_eq!(stop.finish(), "");
+
+    // 链中前面变换的剩余在结束时经过后面的变换
+    // At the end, the rest of an earlier transformer goes through the later ones
+    let mut chain = StreamTransformers::new().with(StripMarkdown::new()).with(MaskWords::new(["secret"])).start();
+    assert_eq!(feed(&mut chain, &["**sec", "ret** plan"]).concat(), "****** plan");
+}
+
+async fn test_stream_transformers() {
+    let mock = MockProvider::start("transform-api").await;
+    mock.reply("the darn build STOP leaked tokens");
+    let mut chat = SingleChat::builder()
+        .api("transform-api")
+        .stream_transformers(
+            StreamTransformers::new().with(MaskWords::new(["darn"])).with(StopSequences::new([" STOP"])),
+        )
+        .build()
+        .unwrap();
+    let deltas: Vec<String> = chat.stream_answer("build?").await.unwrap().map(Result::unwrap).collect().await;
+    assert_eq!(deltas.concat(), "the **** build");
+
+    // 停止序列结束流，会话保存模型的原始输出
+    // The stop sequence ends the stream, the session keeps the model's raw output
+    let stored = chat.base.session.last_message_mut().unwrap();
+    assert!(stored.content.to_text().starts_with("the darn build STOP"));
+    assert!(!stored.content.to_text().contains("tokens"));
+
+    format_test_block("stream_transformers", || format!("{:?}\n{}", deltas, stored.content));
+}
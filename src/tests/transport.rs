@@ -0,0 +1,92 @@
+use std::time::Duration;
+
+use crate::chat::chat_base::BaseChat;
+use crate::chat::chat_single::SingleChat;
+use crate::config::tls::build_client;
+use crate::config::transport::TransportConfig;
+use crate::config::validate::IssueKind;
+use crate::config::{Config, ConfigFile};
+use crate::testing::MockProvider;
+use crate::tests::format_test_block;
+
+pub async fn test_transport() {
+    let mock = MockProvider::start("transport-api").await;
+    let source = "transport-api-source";
+    assert_eq!(Config::get_source_permits(source).unwrap().limit, 4);
+
+    // 调优写入来源：只用HTTP/1.1的客户端照常请求，进行中的请求上限成为来源的并发上限
+    // Tuning is stored on the source: the HTTP/1.1 only client requests as usual, and the in-flight cap becomes the
+    // concurrency limit of the source
+    let transport = TransportConfig {
+        http2: Some(false),
+        max_in_flight: Some(2),
+        tcp_keepalive_secs: Some(0),
+        connect_timeout_secs: Some(5),
+    };
+    Config::set_source_transport(source, transport.clone()).unwrap();
+    assert_eq!(Config::get_source_permits(source).unwrap().limit, 2);
+    Config::set_source_parallelism(source, 1).unwrap();
+    assert_eq!(Config::get_source_permits(source).unwrap().limit, 1);
+    Config::set_source_parallelism(source, 4).unwrap();
+
+    // 调整进行中的请求上限不会放过额外的请求，进行中的请求仍计入新的上限
+    // Changing the in-flight cap lets no extra request through, requests in flight still count against the new cap
+    let permit = BaseChat::new_with_api_name("transport-api", "", false).acquire_permit().await.unwrap();
+    let single = TransportConfig {
+        max_in_flight: Some(1),
+        ..transport.clone()
+    };
+    Config::set_source_transport(source, single).unwrap();
+    assert_eq!(Config::get_source_permits(source).unwrap().available, 0);
+    drop(permit);
+    assert_eq!(Config::get_source_permits(source).unwrap().available, 1);
+    Config::set_source_transport(source, transport.clone()).unwrap();
+
+    let mut chat = SingleChat::builder().api("transport-api").build().unwrap();
+    mock.reply("Over HTTP/1.1.");
+    assert_eq!(chat.get_answer("Hi").await.unwrap(), "Over HTTP/1.1.");
+    assert!(Config::set_source_transport("missing-source", transport).is_err());
+
+    // 强制HTTP/2时不再协商，只会HTTP/1.1的服务端无法应答
+    // Forcing HTTP/2 skips negotiation, so a server speaking only HTTP/1.1 cannot answer
+    let http2 = TransportConfig {
+        http2: Some(true),
+        ..Default::default()
+    };
+    let client = build_client(None, &http2).unwrap();
+    let sent = tokio::time::timeout(Duration::from_secs(5), client.post(mock.url()).body("{}").send()).await;
+    assert!(!matches!(sent, Ok(Ok(_))));
+
+    // 配置文件中的写法，未知字段报错；进行中的请求上限为 0 时校验报错
+    // The config file form, unknown fields fail; validation reports a zero in-flight cap
+    let file: ConfigFile = toml::from_str(
+        r#"
+[[api_source]]
+name = "gateway"
+base_url = "http://localhost:8080/v1"
+parallelism = 64
+transport = { http2 = true, max_in_flight = 32, tcp_keepalive_secs = 30 }
+"#,
+    )
+    .unwrap();
+    let parsed = &file.api_source[0].transport;
+    assert_eq!(parsed.http2, Some(true));
+    assert_eq!(parsed.permits(64), 32);
+    assert_eq!(parsed.connect_timeout_secs, None);
+    assert!(toml::from_str::<TransportConfig>("http_2 = true").is_err());
+    assert!(TransportConfig::default().is_default());
+
+    Config::set_source_transport(source, TransportConfig {
+        max_in_flight: Some(0),
+        ..Default::default()
+    })
+    .unwrap();
+    let zero = Config::validate()
+        .into_iter()
+        .any(|issue| issue.kind == IssueKind::ZeroParallelism && issue.subject.contains(source));
+    Config::set_source_transport(source, TransportConfig::default()).unwrap();
+    assert!(zero);
+
+    assert_eq!(mock.pending(), 0);
+    format_test_block("transport", || format!("{:?}", parsed));
+}
@@ -0,0 +1,72 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use serde_json::json;
+
+use crate::chat::chat_single::SingleChat;
+use crate::chat::trigger::ToolTrigger;
+use crate::schema::tool_schema::{create_tool, get_tool_registry};
+use crate::testing::MockProvider;
+use crate::tests::format_test_block;
+
+/// 触发器工具被调用的次数
+/// Number of calls to the trigger tool
+static TICKET_CALLS: AtomicUsize = AtomicUsize::new(0);
+
+pub async fn test_tool_triggers() {
+    test_trigger_calls();
+
+    get_tool_registry().insert(
+        "trigger_ticket".to_string(),
+        create_tool("trigger_ticket", |arguments| {
+            TICKET_CALLS.fetch_add(1, Ordering::SeqCst);
+            Ok(json!({"key": arguments["key"], "status": "open"}))
+        })
+        .1,
+    );
+    let mock = MockProvider::start("trigger-api").await;
+    let trigger = ToolTrigger::new(r"(?P<key>JIRA-\d+)", "trigger_ticket").unwrap();
+    let mut chat = SingleChat::builder().api("trigger-api").tool_trigger(trigger).build().unwrap();
+
+    // 工具在请求模型之前调用，结果只进入本次请求
+    // The tool is called before the model is asked, and its result only goes into this request
+    mock.reply("JIRA-123 is still open.");
+    assert_eq!(chat.get_answer("What is the status of JIRA-123?").await.unwrap(), "JIRA-123 is still open.");
+    mock.last_request()
+        .contains("以下是根据用户输入自动调用工具得到的结果")
+        .contains("[trigger_ticket {\"key\":\"JIRA-123\"}]")
+        .contains("\"status\": \"open\"")
+        .message_count(2);
+    assert_eq!(chat.base.session.default_path.len(), 2);
+
+    mock.reply("Hello!");
+    chat.get_answer("Hi there").await.unwrap();
+    mock.last_request().not_contains("自动调用工具").message_count(3);
+
+    // 预览请求时不调用触发器的工具
+    // Previewing a request does not call the tools of the triggers
+    let preview = chat.dry_run("What about JIRA-456?").await.unwrap();
+    assert!(!preview.body.to_string().contains("自动调用工具"));
+    assert_eq!(TICKET_CALLS.load(Ordering::SeqCst), 1);
+
+    // 触发器的工具不存在时不发出请求，问题也不写入历史
+    // No request is made when the tool of a trigger does not exist, and the question stays out of the history
+    chat.add_tool_trigger(ToolTrigger::new(r"INC-\d+", "trigger_missing").unwrap());
+    assert!(chat.get_answer("Any news on INC-7?").await.is_err());
+    assert_eq!(mock.requests().len(), 2);
+    assert_eq!(chat.base.session.default_path.len(), 4);
+
+    format_test_block("tool_triggers", || mock.request(0).prompt());
+}
+
+fn test_trigger_calls() {
+    let ticket = ToolTrigger::new(r"(?P<key>JIRA-\d+)", "get_ticket").unwrap();
+    assert_eq!(ticket.calls("JIRA-1 blocks JIRA-2, see JIRA-1"), [json!({"key": "JIRA-1"}), json!({"key": "JIRA-2"})]);
+    assert!(ticket.calls("nothing here").is_empty());
+
+    let order = ToolTrigger::new(r"order #(\d+)", "get_order")
+        .unwrap()
+        .arguments(json!({"id": "$1", "expand": ["items"], "limit": 3}));
+    assert_eq!(order.calls("Where is order #42?"), [json!({"id": "42", "expand": ["items"], "limit": 3})]);
+    assert_eq!(order.tool(), "get_order");
+    assert!(ToolTrigger::new("(", "broken").is_err());
+}
@@ -0,0 +1,37 @@
+use std::sync::{Arc, Mutex};
+
+use crate::chat::chat_single::SingleChat;
+use crate::chat::event::ChatEvent;
+use crate::testing::MockProvider;
+use crate::tests::format_test_block;
+
+pub async fn test_typing_indicator() {
+    let mock = MockProvider::start("typing-api").await;
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let recorded = events.clone();
+    let mut chat = SingleChat::new_with_api_name("typing-api", "", true);
+    chat.on_event(move |event| recorded.lock().unwrap().push(event.clone()));
+
+    // 流式回答依次发出：开始、发送、第一个 token、各段内容、回答完成与生成结束
+    // A streamed answer emits in order: started, dispatched, first token, the deltas, answer ready and finished
+    mock.reply("Hello there");
+    chat.get_answer("hi").await.unwrap();
+    let events = std::mem::take(&mut *events.lock().unwrap());
+    let kinds: Vec<String> =
+        events.iter().map(|event| format!("{:?}", event).split([' ', '{']).next().unwrap().to_string()).collect();
+    let expected = ["RequestStarted", "RequestDispatched", "FirstToken", "TokenReceived", "TokenReceived"];
+    assert_eq!(kinds, [&expected[..], &["AnswerReady", "GenerationFinished"]].concat());
+    let (ChatEvent::RequestDispatched { at: dispatched, .. }, ChatEvent::FirstToken { at: first, latency, .. }) =
+        (&events[1], &events[2])
+    else {
+        unreachable!()
+    };
+    assert!(first >= dispatched);
+    let ChatEvent::GenerationFinished { at: finished, duration, finish_reason, error, .. } = &events[6] else {
+        unreachable!()
+    };
+    assert!(finished >= first && duration >= latency);
+    assert_eq!(finish_reason.as_deref(), Some("stop"));
+    assert!(error.is_none());
+    format_test_block("typing indicator", || format!("{:#?}", kinds));
+}
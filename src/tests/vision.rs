@@ -0,0 +1,96 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde_json::json;
+
+use crate::chat::agent::Deadline;
+use crate::chat::chat_single::SingleChat;
+use crate::chat::tool_source::ToolSource;
+use crate::chat::vision::{VisionAnswer, VisionTools};
+use crate::config::prompts::PromptKey;
+use crate::config::{Config, ModelCapability};
+use crate::testing::MockProvider;
+use crate::tests::format_test_block;
+
+/// 只有 PNG 文件头的图片
+/// Image made of nothing but a PNG header
+const PNG: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+pub async fn test_vision() {
+    // 在独立的配置中运行，工具参数解析只会选到模拟提供商
+    // Run on a configuration of its own, so tool argument parsing only picks the mock provider
+    Config::with_scoped(Config::builder().build().unwrap(), async {
+        let primary = MockProvider::start("vision-primary-api").await.with_capability(ModelCapability::ToolUse);
+        let vision = MockProvider::start("vision-api").await;
+        let tools = VisionTools::with_api("vision-api");
+        let listed = tools.list_tools().await.unwrap();
+        assert_eq!(listed[0]["function"]["name"], "ask_image");
+        assert_eq!(listed[0]["function"]["parameters"]["required"], json!(["image", "question"]));
+
+        // 主模型以附件ID调用工具，问题与图片交给视觉模型，回答作为工具结果交回
+        // The primary model calls the tool with the attachment ID, the question and image go to the vision model and
+        // its answer comes back as the tool result
+        let mut chat = SingleChat::builder().api("vision-primary-api").build().unwrap();
+        chat.set_tool_sources(vec![Arc::new(tools.clone())]).await.unwrap();
+        let screenshot = chat.attach("error.png", "image/png", PNG, None).unwrap();
+        primary
+            .reply("<ToolUse>look at the screenshot</ToolUse>")
+            .reply_tool_call("ask_image", json!({"image": screenshot.id, "question": "What error is shown?"}))
+            .reply("The screenshot shows a timeout.");
+        vision.reply("  A dialog saying \"Connection timed out\".\n");
+        let run = chat.run_agent("What went wrong in error.png?", Deadline::after(Duration::from_secs(10))).await;
+        let run = run.unwrap();
+        assert!(run.is_complete());
+        let result: VisionAnswer = serde_json::from_str(&run.tool_results[0]).unwrap();
+        assert_eq!(result.answer, "A dialog saying \"Connection timed out\".");
+        assert_eq!(result.engine, "vision-api");
+        vision
+            .last_request()
+            .contains("你是视觉助手")
+            .contains("What error is shown?")
+            .contains("data:image/png;base64,iVBORw0KGgo=")
+            .not_contains("What went wrong")
+            .message_count(2);
+        primary.last_request().contains("Connection timed out").not_contains("iVBORw0KGgo");
+
+        // 自定义指令替换默认指令；未知的附件与缺少的参数报错
+        // A custom instruction replaces the default one; unknown attachments and missing arguments fail
+        vision.reply("Two bars.");
+        let tools = tools.instruction("Describe charts in one sentence.");
+        let url = "data:image/png;base64,iVBORw0KGgo=";
+        let output = tools.call_tool("ask_image", json!({"image": url, "question": "How many bars?"})).await.unwrap();
+        assert_eq!(output["answer"], "Two bars.");
+        vision.last_request().contains("Describe charts in one sentence.").not_contains("你是视觉助手");
+        assert!(tools.call_tool("ask_image", json!({"image": "missing", "question": "?"})).await.is_err());
+        assert!(tools.call_tool("ask_image", json!({"image": url})).await.is_err());
+        assert_eq!(vision.requests().len(), 2);
+
+        // 默认指令与工具说明可以按提示词键替换
+        // The default instruction and the tool descriptions can be replaced by prompt key
+        let prompts = [
+            (PromptKey::VisionInstruction, "Answer from the image alone."),
+            (PromptKey::VisionToolDescription, "Ask a model that can see images"),
+            (PromptKey::VisionImageArgument, "Attachment ID or image URL"),
+            (PromptKey::VisionQuestionArgument, "What to find out from the image"),
+        ];
+        for (key, prompt) in prompts {
+            Config::set_capability_prompt(key, prompt);
+        }
+        let tools = VisionTools::with_api("vision-api");
+        let listed = tools.list_tools().await.unwrap();
+        assert_eq!(listed[0]["function"]["description"], "Ask a model that can see images");
+        let properties = &listed[0]["function"]["parameters"]["properties"];
+        assert_eq!(properties["image"]["description"], "Attachment ID or image URL");
+        assert_eq!(properties["question"]["description"], "What to find out from the image");
+        vision.reply("Three bars.");
+        tools.call_tool("ask_image", json!({"image": url, "question": "How many bars?"})).await.unwrap();
+        vision.last_request().contains("Answer from the image alone.").not_contains("你是视觉助手");
+        for (key, _) in prompts {
+            Config::remove_capability_prompt(&key);
+        }
+
+        assert_eq!(primary.pending(), 0);
+        format_test_block("vision", || vision.request(0).prompt());
+    })
+    .await;
+}
@@ -0,0 +1,62 @@
+use std::time::Duration;
+
+use crate::chat::params::ChatParams;
+use crate::config::{Config, ModelCapability};
+use crate::testing::MockProvider;
+use crate::tests::format_test_block;
+use crate::warmup::Warmup;
+
+pub async fn test_warmup() {
+    // 在独立的配置中运行，不可达的端点不会留在全局配置中
+    // Run on a configuration of its own, so the unreachable endpoint is not left in the global configuration
+    let config = Config::builder()
+        .api("warmup-dead", "http://127.0.0.1:9/v1/chat/completions", "sk-dead", "dead-model")
+        .weight(0)
+        .capability(ModelCapability::Cheap, "warmup-dead")
+        .build()
+        .unwrap();
+    Config::with_scoped(config, async {
+        let mock = MockProvider::start("warmup-api").await;
+
+        // 连接只发送 HEAD 请求，不消耗脚本；提示词缓存以系统提示词发送一次 1-token 补全
+        // Connecting only sends a HEAD request without using the script; priming sends one 1-token completion with the
+        // system prompt
+        mock.reply("o");
+        let warmup = Warmup::new().api("warmup-api").timeout(Duration::from_secs(2));
+        let warmup = warmup.prime_cache("You are a support agent.");
+        let report = warmup.run().await;
+        assert!(report.is_warm());
+        assert_eq!(report.endpoints.len(), 1);
+        let endpoint = &report.endpoints[0];
+        assert_eq!(endpoint.host.as_deref(), Some("127.0.0.1"));
+        assert!(endpoint.resolved && endpoint.connected);
+        assert_eq!(endpoint.primed, Some(true));
+        assert_eq!(report.tokenizers, ["mock-model"]);
+        assert_eq!(mock.requests().len(), 1);
+        mock.last_request().contains("system: You are a support agent.").message_count(2);
+        assert_eq!(mock.requests()[0]["max_tokens"], 1);
+
+        // 预热补全的生成参数可以放宽
+        // The generation parameters of the priming completion can be loosened
+        mock.reply("ok");
+        let loose = Warmup::new().api("warmup-api").tokenizers(false).prime_params(ChatParams::new().max_tokens(16));
+        assert!(loose.prime_cache("You are a support agent.").run().await.is_warm());
+        assert_eq!(mock.requests()[1]["max_tokens"], 16);
+
+        // 无法连接的端点不算预热完成，也不再尝试预热缓存
+        // An endpoint that cannot be reached leaves the warmup incomplete and is not primed
+        let report = warmup.api("warmup-dead").tokenizers(false).run().await;
+        assert!(!report.is_warm());
+        assert_eq!(report.endpoints.len(), 2);
+        let dead = &report.endpoints[1];
+        assert_eq!(dead.api_name, "warmup-dead");
+        assert!(dead.resolved && !dead.connected);
+        assert_eq!(dead.primed, Some(false));
+        assert!(dead.error.as_deref().unwrap().starts_with("Network error"));
+        assert!(report.tokenizers.is_empty());
+        assert_eq!(mock.pending(), 0);
+
+        format_test_block("warmup", || mock.request(0).prompt());
+    })
+    .await;
+}
@@ -0,0 +1,96 @@
+use crate::tests::format_test_block;
+
+pub async fn test_websocket() {
+    #[cfg(all(test, feature = "server"))]
+    test_websocket_sessions().await;
+    format_test_block("WebSocket", || "sessions over one connection".to_string());
+}
+
+#[cfg(all(test, feature = "server"))]
+async fn test_websocket_sessions() {
+    use futures::{SinkExt, StreamExt};
+    use serde_json::{Value, json};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio_tungstenite::tungstenite::Message;
+    use tokio_tungstenite::{MaybeTlsStream, WebSocketStream, connect_async};
+
+    use crate::server::admission::{Admission, AdmissionLimits};
+    use crate::server::websocket::router_with_admission;
+    use crate::testing::MockProvider;
+
+    type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+    async fn send(socket: &mut Socket, frame: Value) {
+        socket.send(Message::text(frame.to_string())).await.unwrap();
+    }
+
+    async fn receive(socket: &mut Socket) -> Value {
+        loop {
+            if let Message::Text(text) = socket.next().await.unwrap().unwrap() {
+                return serde_json::from_str(&text).unwrap();
+            }
+        }
+    }
+
+    let mock = MockProvider::start("websocket-api").await;
+    let admission = Admission::new(AdmissionLimits {
+        max_sessions_per_connection: 2,
+        ..Default::default()
+    });
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}/ws", listener.local_addr().unwrap());
+    let router = router_with_admission(admission, None);
+    tokio::spawn(async move { axum::serve(listener, router).await });
+    let (mut socket, _) = connect_async(&url).await.unwrap();
+
+    let open = |session: &str, stream: bool| json!({
+        "type": "open", "session": session, "api": "websocket-api", "stream": stream,
+    });
+    let message = |session: &str, content: &str| json!({"type": "message", "session": session, "content": content});
+
+    // 无法解析的帧与未打开的会话以错误帧回复，连接保持
+    // Unparsable frames and sessions that are not open get error frames, the connection stays
+    socket.send(Message::text("not json")).await.unwrap();
+    assert_eq!(receive(&mut socket).await["code"], "server.invalid_frame");
+    send(&mut socket, message("main", "hello")).await;
+    assert_eq!(receive(&mut socket).await["code"], "server.unknown_session");
+
+    // 每个连接打开的会话数有上限，同名会话不能重复打开
+    // Sessions per connection are bounded and a session name cannot be opened twice
+    send(&mut socket, open("main", true)).await;
+    assert_eq!(receive(&mut socket).await["type"], "opened");
+    send(&mut socket, open("main", true)).await;
+    assert_eq!(receive(&mut socket).await["code"], "server.session_exists");
+    send(&mut socket, open("side", false)).await;
+    assert_eq!(receive(&mut socket).await["type"], "opened");
+    send(&mut socket, open("third", false)).await;
+    let refused = receive(&mut socket).await;
+    assert_eq!((&refused["session"], &refused["code"]), (&json!("third"), &json!("server.too_many_sessions")));
+
+    // 流式会话逐 token 推送，最后是完整回答
+    // A streaming session pushes token by token, then the whole answer
+    mock.reply("Hello there friend");
+    send(&mut socket, message("main", "hi")).await;
+    let mut deltas = String::new();
+    let answer = loop {
+        let frame = receive(&mut socket).await;
+        match frame["type"].as_str() {
+            Some("token") => deltas.push_str(frame["delta"].as_str().unwrap()),
+            _ => break frame,
+        }
+    };
+    assert_eq!(deltas, "Hello there friend");
+    assert_eq!((&answer["type"], &answer["content"]), (&json!("answer"), &json!("Hello there friend")));
+    assert!(answer["message_id"].is_string());
+
+    // 关闭会话后先回答已收到的消息，再发出 closed，之后可以打开新的会话
+    // Closing a session answers the messages already received before sending closed, then a new session can open
+    mock.reply("Bye.");
+    send(&mut socket, message("side", "bye")).await;
+    send(&mut socket, json!({"type": "close", "session": "side"})).await;
+    assert_eq!(receive(&mut socket).await["content"], "Bye.");
+    assert_eq!(receive(&mut socket).await, json!({"type": "closed", "session": "side"}));
+    send(&mut socket, open("third", false)).await;
+    assert_eq!(receive(&mut socket).await["type"], "opened");
+    assert_eq!(mock.pending(), 0);
+}
@@ -0,0 +1,44 @@
+use std::env;
+use error_stack::{Report, Result};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ExpandEnvError {
+    #[error("Environment variable not found: {0}")]
+    VarNotFound(String),
+
+    #[error("Unterminated '${{' in value: {0}")]
+    Unterminated(String),
+}
+
+/// 展开值中的环境变量引用
+/// Expand environment variable references in a value
+///
+/// 支持两种写法 / Two forms are supported:
+/// * `env:NAME` - 整个值取自环境变量 NAME / the whole value is taken from variable NAME
+/// * `${NAME}` - 在字符串任意位置插值 / interpolated anywhere inside the string
+pub fn expand_env(value: &str) -> Result<String, ExpandEnvError> {
+    if let Some(name) = value.strip_prefix("env:") {
+        return read_var(name.trim());
+    }
+
+    let mut result = String::with_capacity(value.len());
+    let mut rest = value;
+
+    while let Some(start) = rest.find("${") {
+        result.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let end = after
+            .find('}')
+            .ok_or_else(|| Report::new(ExpandEnvError::Unterminated(value.to_string())))?;
+        result.push_str(&read_var(after[..end].trim())?);
+        rest = &after[end + 1..];
+    }
+    result.push_str(rest);
+
+    Ok(result)
+}
+
+fn read_var(name: &str) -> Result<String, ExpandEnvError> {
+    env::var(name).map_err(|_| Report::new(ExpandEnvError::VarNotFound(name.to_string())))
+}
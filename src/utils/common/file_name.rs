@@ -0,0 +1,34 @@
+/// 将任意ID编码为文件名：小写字母、数字、`-` 与 `_` 原样保留，其余字节（包括大写字母）编码为 `%XX`，
+/// 不同的ID总是得到不同的文件名，在不区分大小写的文件系统上也是如此，且编码结果不含路径分隔符与 `.`
+/// Encode any ID as a file name: lowercase letters, digits, `-` and `_` are kept, every other byte (uppercase
+/// letters included) becomes `%XX`, so distinct IDs always get distinct file names, on case-insensitive file
+/// systems too, and the result contains neither path separators nor `.`
+pub fn encode_file_name(id: &str) -> String {
+    let mut name = String::with_capacity(id.len());
+    for byte in id.bytes() {
+        if byte.is_ascii_lowercase() || byte.is_ascii_digit() || byte == b'-' || byte == b'_' {
+            name.push(byte as char);
+        } else {
+            name.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    name
+}
+
+/// 还原 `encode_file_name` 编码的文件名，不是合法编码时返回 None
+/// Restore a file name encoded by `encode_file_name`, None if it is not a valid encoding
+pub fn decode_file_name(name: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(name.len());
+    let mut rest = name.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
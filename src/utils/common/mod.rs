@@ -1 +1,5 @@
-pub mod load_toml;
\ No newline at end of file
+pub mod load_toml;
+pub mod expand_env;
+pub mod redact;
+pub mod tokens;
+pub mod file_name;
@@ -0,0 +1,67 @@
+use std::sync::RwLock;
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use crate::config::CFG;
+
+/// 替换敏感内容的占位符
+/// Placeholder replacing sensitive content
+pub const REDACTED: &str = "[REDACTED]";
+
+/// 默认的敏感内容模式，`prefix` 捕获组会被保留
+/// Default sensitive patterns, the `prefix` capture group is kept
+const DEFAULT_PATTERNS: &[&str] = &[
+    // Authorization / Proxy-Authorization 头
+    // Authorization / Proxy-Authorization headers
+    r#"(?i)(?P<prefix>(proxy-)?authorization"?\s*[:=]\s*"?((bearer|basic)\s+)?)[^\s",}]+"#,
+    // JSON 或 TOML 中的密钥字段
+    // Key fields in JSON or TOML
+    r#"(?i)(?P<prefix>"?(api[_-]?key|x-api-key|secret[_-]?key|access[_-]?token|password)"?\s*[:=]\s*")[^"]*"#,
+    // URL 查询参数中的密钥
+    // Keys in URL query parameters
+    r#"(?i)(?P<prefix>[?&](api[_-]?key|key|token|access_token)=)[^&\s"]+"#,
+    // 常见的明文密钥形式
+    // Common shapes of plaintext keys
+    r"(?i)(?P<prefix>bearer\s+)[A-Za-z0-9_\-.=]{16,}",
+    r"\b(sk|pk|rk)-[A-Za-z0-9_\-]{16,}",
+];
+
+/// 敏感内容模式表，可通过 `add_redaction_pattern` 扩展
+/// Sensitive pattern table, extensible through `add_redaction_pattern`
+static PATTERNS: Lazy<RwLock<Vec<Regex>>> = Lazy::new(|| {
+    RwLock::new(DEFAULT_PATTERNS.iter().map(|pattern| Regex::new(pattern).unwrap()).collect())
+});
+
+/// 添加自定义敏感内容模式，匹配到的内容会被替换为 `[REDACTED]`，名为 `prefix` 的捕获组会被保留
+/// Add a custom sensitive pattern, matched content is replaced by `[REDACTED]` except a capture group named `prefix`
+pub fn add_redaction_pattern(pattern: &str) -> Result<(), regex::Error> {
+    let regex = Regex::new(pattern)?;
+    PATTERNS.write().unwrap().push(regex);
+    Ok(())
+}
+
+/// 清除文本中的敏感内容：所有已配置的API密钥以及匹配敏感模式的内容
+/// Redact sensitive content from text: every configured API key and anything matching a sensitive pattern
+///
+/// 用于错误附件与日志输出，避免请求体、请求头中的密钥泄露。
+/// Used for error attachments and log output, so keys inside request bodies and headers never leak.
+pub fn redact(text: &str) -> String {
+    let mut redacted = text.to_string();
+    for entry in CFG.current().api_info.iter() {
+        let key = &entry.value().api_key;
+        if key.len() >= 8 {
+            redacted = redacted.replace(key.as_str(), REDACTED);
+        }
+    }
+
+    for pattern in PATTERNS.read().unwrap().iter() {
+        redacted = pattern
+            .replace_all(&redacted, |captures: &regex::Captures| {
+                let prefix = captures.name("prefix").map_or("", |prefix| prefix.as_str());
+                format!("{}{}", prefix, REDACTED)
+            })
+            .into_owned();
+    }
+    redacted
+}
@@ -0,0 +1,151 @@
+// 标准库
+use std::sync::Arc;
+
+// 并发和同步原语
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+
+// 项目内部模块
+use crate::config::Config;
+
+/// 估算文本的 token 数：约四个字符一个 token，中日韩字符各算一个
+/// Estimate the tokens of a text: about four characters per token, each CJK character counting as one
+pub fn approx_tokens(text: &str) -> usize {
+    let (cjk, other) = text.chars().fold((0usize, 0usize), |(cjk, other), c| match c as u32 {
+        0x3000..=0x9FFF | 0xAC00..=0xD7AF | 0xF900..=0xFAFF => (cjk + 1, other),
+        _ => (cjk, other + 1),
+    });
+    cjk + other.div_ceil(4)
+}
+
+/// 分词器，把文本映射为模型的 token ID，用于按文本设置 `logit_bias`，以及计算预估、压缩报告等处的 token 数
+/// Tokenizer mapping text to the token IDs of a model, used to set `logit_bias` by text and to count tokens for
+/// estimates, compaction reports and the like
+///
+/// token ID 因模型而异，需使用与目标模型一致的分词器；每个模型使用的分词器见 `tokenizer_for`。
+/// Token IDs differ between models, the tokenizer must match the target model; see `tokenizer_for` for the
+/// tokenizer each model uses.
+pub trait Tokenizer: Send + Sync {
+    fn encode(&self, text: &str) -> Vec<u32>;
+
+    /// 文本的 token 数
+    /// Number of tokens in a text
+    fn count(&self, text: &str) -> usize {
+        self.encode(text).len()
+    }
+}
+
+/// 同步函数可直接作为分词器
+/// Synchronous functions can be used directly as tokenizers
+impl<F> Tokenizer for F
+where
+    F: Fn(&str) -> Vec<u32> + Send + Sync,
+{
+    fn encode(&self, text: &str) -> Vec<u32> {
+        self(text)
+    }
+}
+
+/// 估算分词器，按 `approx_tokens` 计数，用于没有本地分词器的模型；无法给出 token ID，`encode` 返回空列表
+/// Estimating tokenizer counting by `approx_tokens`, for models without a local tokenizer; it cannot give token
+/// IDs, so `encode` returns an empty list
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Heuristic;
+
+impl Tokenizer for Heuristic {
+    fn encode(&self, _text: &str) -> Vec<u32> {
+        Vec::new()
+    }
+
+    fn count(&self, text: &str) -> usize {
+        approx_tokens(text)
+    }
+}
+
+/// 代码中为模型注册的分词器，如 HuggingFace 的 `tokenizers`
+/// Tokenizers registered for models in code, such as HuggingFace `tokenizers`
+static TOKENIZERS: Lazy<DashMap<String, Arc<dyn Tokenizer>>> = Lazy::new(DashMap::new);
+
+/// 已加载的 tiktoken 编码，加载较慢，按编码名称缓存
+/// Loaded tiktoken encodings, cached by encoding name as loading is slow
+#[cfg(feature = "tiktoken")]
+static ENCODINGS: Lazy<DashMap<String, Arc<dyn Tokenizer>>> = Lazy::new(DashMap::new);
+
+/// 为模型注册分词器，优先于配置与内置的选择；为 None 时移除
+/// Register a tokenizer for a model, taking precedence over the configured and built-in choices; removed if None
+pub(crate) fn register_tokenizer(model: &str, tokenizer: Option<Arc<dyn Tokenizer>>) {
+    match tokenizer {
+        Some(tokenizer) => TOKENIZERS.insert(model.to_string(), tokenizer),
+        None => TOKENIZERS.remove(model).map(|(_, tokenizer)| tokenizer),
+    };
+}
+
+/// 模型使用的分词器，依次为：代码中注册的分词器、模型元数据 `tokenizer` 指定的分词器、tiktoken 认识的模型对应的分词器
+/// （需启用 `tiktoken` 特性），都没有时退回 `Heuristic` 估算
+/// Tokenizer of a model, in order: the tokenizer registered in code, the one named by the `tokenizer` of the model
+/// metadata, the tiktoken tokenizer of models tiktoken knows (requires the `tiktoken` feature), falling back to the
+/// `Heuristic` estimate
+pub fn tokenizer_for(model: &str) -> Arc<dyn Tokenizer> {
+    if let Some(tokenizer) = TOKENIZERS.get(model) {
+        return tokenizer.value().clone();
+    }
+    let configured = Config::get_model_metadata(model).and_then(|metadata| metadata.tokenizer);
+    match configured.as_deref() {
+        Some("heuristic") => Arc::new(Heuristic),
+        #[cfg(feature = "tiktoken")]
+        Some(encoding) => Tiktoken::cached(encoding, || Tiktoken::for_encoding(encoding)),
+        #[cfg(feature = "tiktoken")]
+        None => Tiktoken::cached(model, || Tiktoken::for_model(model)),
+        #[cfg(not(feature = "tiktoken"))]
+        _ => Arc::new(Heuristic),
+    }
+}
+
+/// OpenAI 模型的分词器
+/// Tokenizer of OpenAI models
+#[cfg(feature = "tiktoken")]
+pub struct Tiktoken(tiktoken_rs::CoreBPE);
+
+#[cfg(feature = "tiktoken")]
+impl Tiktoken {
+    /// 模型对应的分词器，未知模型返回 None
+    /// Tokenizer of a model, None for unknown models
+    pub fn for_model(model: &str) -> Option<Self> {
+        tiktoken_rs::get_bpe_from_model(model).ok().map(Self)
+    }
+
+    /// 按编码名称（如 `o200k_base`、`cl100k_base`）取得分词器，未知编码返回 None
+    /// Tokenizer by encoding name (such as `o200k_base` or `cl100k_base`), None for unknown encodings
+    pub fn for_encoding(encoding: &str) -> Option<Self> {
+        use tiktoken_rs::tokenizer::Tokenizer as Encoding;
+        let encoding = match encoding {
+            "o200k_base" => Encoding::O200kBase,
+            "cl100k_base" => Encoding::Cl100kBase,
+            "p50k_base" => Encoding::P50kBase,
+            "p50k_edit" => Encoding::P50kEdit,
+            "r50k_base" => Encoding::R50kBase,
+            "gpt2" => Encoding::Gpt2,
+            _ => return None,
+        };
+        tiktoken_rs::get_bpe_from_tokenizer(encoding).ok().map(Self)
+    }
+
+    /// 按名称缓存的分词器，无法加载时退回 `Heuristic` 且不缓存
+    /// Tokenizer cached by name, falling back to `Heuristic` without caching if it cannot be loaded
+    fn cached(name: &str, load: impl FnOnce() -> Option<Self>) -> Arc<dyn Tokenizer> {
+        if let Some(tokenizer) = ENCODINGS.get(name) {
+            return tokenizer.value().clone();
+        }
+        match load() {
+            Some(tokenizer) => ENCODINGS.entry(name.to_string()).or_insert(Arc::new(tokenizer)).value().clone(),
+            None => Arc::new(Heuristic),
+        }
+    }
+}
+
+#[cfg(feature = "tiktoken")]
+impl Tokenizer for Tiktoken {
+    fn encode(&self, text: &str) -> Vec<u32> {
+        self.0.encode_ordinary(text)
+    }
+}
@@ -0,0 +1,305 @@
+//! 冷启动预热：预先解析DNS、与已配置的端点建立TLS连接、加载分词器，并可用固定的系统提示词预热提供商的提示词缓存，
+//! 降低无服务器部署中第一个请求的延迟
+//! Cold-start warmup: pre-resolve DNS, open TLS connections to the configured endpoints, load the tokenizers and
+//! optionally prime the provider-side prompt caches with the static system prompt, cutting the latency of the first
+//! request in serverless deployments
+//!
+//! ```ignore
+//! Config::load("rhine.toml")?;
+//! let report = Warmup::new().prime_cache(SYSTEM_PROMPT).run().await;
+//! if !report.is_warm() {
+//!     warn!("Warmup incomplete: {:?}", report);
+//! }
+//! ```
+
+// 标准库
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+// 异步
+use futures::future::join_all;
+use tokio::time::timeout;
+
+// HTTP客户端
+use reqwest::Url;
+use reqwest::dns::{Name, Resolve};
+
+// 观测诊断
+use tracing::info;
+
+// 项目内部模块
+use crate::chat::chat_base::BaseChat;
+use crate::chat::message::Role;
+use crate::chat::params::ChatParams;
+use crate::config::http::dns_resolver;
+use crate::config::{ApiInfo, CFG, Config};
+use crate::utils::common::tokens::tokenizer_for;
+
+/// 默认的单步预热超时时间
+/// Default timeout of a single warmup step
+pub const DEFAULT_WARMUP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 预热提示词缓存的补全默认生成的令牌数上限
+/// Default token limit of the completion priming a prompt cache
+pub const DEFAULT_PRIME_MAX_TOKENS: u32 = 1;
+
+/// 单个端点的预热结果
+/// Warmup result of a single endpoint
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EndpointWarmup {
+    /// API名称
+    /// API name
+    pub api_name: String,
+
+    /// 模型名称
+    /// Model name
+    pub model: String,
+
+    /// 端点主机名，无法从地址中解析时为 None
+    /// Host name of the endpoint, None if it cannot be parsed from the address
+    pub host: Option<String>,
+
+    /// DNS解析是否成功，主机为IP地址时无需解析，视为成功
+    /// Whether the DNS resolution succeeded, hosts given as IP addresses need none and count as resolved
+    pub resolved: bool,
+
+    /// 是否已建立连接；端点返回任何HTTP响应都视为成功，连接留在连接池中供后续请求复用
+    /// Whether a connection was opened; any HTTP response of the endpoint counts, and the connection stays in the
+    /// pool for later requests to reuse
+    pub connected: bool,
+
+    /// 提示词缓存是否预热成功，未要求预热时为 None
+    /// Whether the prompt cache was primed, None if priming was not asked for
+    pub primed: Option<bool>,
+
+    /// 该端点预热的总耗时
+    /// Total time spent warming the endpoint
+    pub latency: Duration,
+
+    /// 第一个失败步骤的原因
+    /// Reason of the first failed step
+    pub error: Option<String>,
+}
+
+/// 预热的结果
+/// Outcome of a warmup
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct WarmupReport {
+    pub endpoints: Vec<EndpointWarmup>,
+
+    /// 已加载分词器的模型
+    /// Models whose tokenizers were loaded
+    pub tokenizers: Vec<String>,
+
+    pub elapsed: Duration,
+}
+
+impl WarmupReport {
+    /// 所有端点都已连接，且要求预热的提示词缓存都已预热
+    /// Every endpoint is connected and every prompt cache asked for is primed
+    pub fn is_warm(&self) -> bool {
+        self.endpoints.iter().all(|endpoint| endpoint.connected && endpoint.primed != Some(false))
+    }
+}
+
+/// 预热设置，默认预热所有已配置的API并加载其模型的分词器，不预热提示词缓存
+/// Warmup settings, by default every configured API is warmed and the tokenizers of its models are loaded, without
+/// priming prompt caches
+#[derive(Clone, Debug)]
+pub struct Warmup {
+    apis: Vec<String>,
+    prime_prompt: Option<String>,
+    prime_params: ChatParams,
+    tokenizers: bool,
+    timeout: Duration,
+}
+
+impl Default for Warmup {
+    fn default() -> Self {
+        Self {
+            apis: Vec::new(),
+            prime_prompt: None,
+            prime_params: ChatParams::new().max_tokens(DEFAULT_PRIME_MAX_TOKENS),
+            tokenizers: true,
+            timeout: DEFAULT_WARMUP_TIMEOUT,
+        }
+    }
+}
+
+impl Warmup {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 只预热指定的API（可多次调用，支持别名）；未指定时预热所有已配置的API
+    /// Only warm the given API (may be called repeatedly, aliases are accepted); every configured API is warmed if
+    /// none is given
+    pub fn api(mut self, name: &str) -> Self {
+        self.apis.push(Config::resolve_alias(name));
+        self
+    }
+
+    /// 以固定的系统提示词向每个端点发送一次短补全，使提供商缓存该前缀；
+    /// 提示词应与对话实际发送的系统提示词完全一致，缓存才会命中
+    /// Send a short completion with the static system prompt to every endpoint so the provider caches the prefix;
+    /// the prompt must match the system prompt chats actually send for the cache to hit
+    pub fn prime_cache(mut self, system_prompt: &str) -> Self {
+        self.prime_prompt = Some(system_prompt.to_string());
+        self
+    }
+
+    /// 预热提示词缓存的补全所用的生成参数，覆盖API的参数；默认只限制为 `DEFAULT_PRIME_MAX_TOKENS` 个令牌，
+    /// 不接受如此小上限的模型（如推理模型）可在此放宽
+    /// Generation parameters of the completion priming the prompt cache, overriding the API's parameters; by default
+    /// only limited to `DEFAULT_PRIME_MAX_TOKENS` tokens, models rejecting such a small limit (such as reasoning
+    /// models) can be given more here
+    pub fn prime_params(mut self, params: ChatParams) -> Self {
+        self.prime_params = params;
+        self
+    }
+
+    /// 是否加载分词器
+    /// Whether to load the tokenizers
+    pub fn tokenizers(mut self, tokenizers: bool) -> Self {
+        self.tokenizers = tokenizers;
+        self
+    }
+
+    /// 每个预热步骤的超时时间
+    /// Timeout of every warmup step
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// 并发预热所有端点并加载分词器
+    /// Warm every endpoint and load the tokenizers concurrently
+    pub async fn run(&self) -> WarmupReport {
+        let started = Instant::now();
+
+        // 同名API在不同能力下共享端点，只预热一次
+        // APIs with the same name share an endpoint across capabilities, warm it once
+        let apis: HashMap<String, ApiInfo> = CFG
+            .current()
+            .api_info
+            .iter()
+            .filter(|entry| self.apis.is_empty() || self.apis.contains(&entry.value().name))
+            .map(|entry| (entry.value().name.clone(), entry.value().clone()))
+            .collect();
+        let mut models: Vec<String> = apis.values().map(|api| api.model.clone()).collect();
+        models.sort();
+        models.dedup();
+        if !self.tokenizers {
+            models.clear();
+        }
+
+        let endpoints = join_all(apis.into_values().map(|api| self.warm_endpoint(api)));
+        let tokenizers = join_all(models.into_iter().map(|model| async move {
+            tokio::task::spawn_blocking(move || {
+                tokenizer_for(&model);
+                model
+            })
+            .await
+            .ok()
+        }));
+        let (mut endpoints, tokenizers) = tokio::join!(endpoints, tokenizers);
+        endpoints.sort_by(|a, b| a.api_name.cmp(&b.api_name));
+
+        let report = WarmupReport {
+            endpoints,
+            tokenizers: tokenizers.into_iter().flatten().collect(),
+            elapsed: started.elapsed(),
+        };
+        info!(
+            "Warmed {} endpoints and {} tokenizers in {:?}",
+            report.endpoints.iter().filter(|endpoint| endpoint.connected).count(),
+            report.tokenizers.len(),
+            report.elapsed
+        );
+        report
+    }
+
+    /// 预热单个端点：解析DNS、建立连接，并按需预热提示词缓存
+    /// Warm a single endpoint: resolve DNS, open a connection and prime the prompt cache if asked
+    async fn warm_endpoint(&self, api: ApiInfo) -> EndpointWarmup {
+        let started = Instant::now();
+        let host = Url::parse(&api.base_url).ok().and_then(|url| url.host_str().map(str::to_string));
+        let mut report = EndpointWarmup {
+            api_name: api.name.clone(),
+            model: api.model.clone(),
+            host: host.clone(),
+            ..EndpointWarmup::default()
+        };
+
+        let resolved = match &host {
+            None => Err(format!("Invalid base URL: {}", api.base_url)),
+            Some(host) => self.resolve(host).await,
+        };
+        report.resolved = resolved.is_ok();
+        report.error = resolved.err();
+
+        if report.resolved {
+            let request = api.client.head(&api.base_url).send();
+            match timeout(self.timeout, request).await {
+                Ok(Ok(_)) => report.connected = true,
+                Ok(Err(e)) => report.error = Some(format!("Network error: {}", e)),
+                Err(_) => report.error = Some("Timeout error".to_string()),
+            }
+        }
+
+        if let Some(prompt) = &self.prime_prompt {
+            let primed = match report.connected {
+                true => self.prime(&api, prompt).await,
+                false => Err("Endpoint is not connected".to_string()),
+            };
+            report.primed = Some(primed.is_ok());
+            report.error = report.error.take().or(primed.err());
+        }
+
+        report.latency = started.elapsed();
+        report
+    }
+
+    /// 通过全局DNS解析器解析主机，结果进入DNS缓存；IP地址无需解析
+    /// Resolve the host through the global DNS resolver so the result lands in the DNS cache; IP addresses need none
+    async fn resolve(&self, host: &str) -> core::result::Result<(), String> {
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if host.parse::<std::net::IpAddr>().is_ok() {
+            return Ok(());
+        }
+        let name = Name::from_str(host).map_err(|_| format!("Invalid host name: {}", host))?;
+        match timeout(self.timeout, dns_resolver().resolve(name)).await {
+            Ok(Ok(_)) => Ok(()),
+            Ok(Err(e)) => Err(format!("DNS error: {}", e)),
+            Err(_) => Err("Timeout error".to_string()),
+        }
+    }
+
+    /// 通过对话以系统提示词发送一次短补全，与普通请求一样经过请求ID、压缩、拦截器与兼容设置，并计入用量与费用；
+    /// 请求失败与超时均视为失败
+    /// Send a short completion with the system prompt through a chat, going through the request ID, compression,
+    /// interceptors and compatibility settings like any request and counted in usage and cost; failed requests and
+    /// timeouts both count as failures
+    async fn prime(&self, api: &ApiInfo, prompt: &str) -> core::result::Result<(), String> {
+        let mut chat = BaseChat::try_new_with_api_name(&api.name, "", false).map_err(|e| e.to_string())?;
+        chat.set_params(&self.prime_params);
+        let body = chat
+            .add_message(Role::System, prompt)
+            .and_then(|_| chat.add_message(Role::User, "ping"))
+            .and_then(|_| chat.build_request_body(&chat.session.default_path.clone(), &Role::User))
+            .map_err(|e| e.to_string())?;
+        match timeout(self.timeout, chat.get_content(body)).await {
+            Ok(Ok(_)) => Ok(()),
+            Ok(Err(e)) => Err(format!("{:?}", e.current_context())),
+            Err(_) => Err("Timeout error".to_string()),
+        }
+    }
+}
+
+/// 以默认设置预热：所有已配置的API与其模型的分词器，不预热提示词缓存
+/// Warm up with the default settings: every configured API and the tokenizers of its models, without priming
+/// prompt caches
+pub async fn warmup() -> WarmupReport {
+    Warmup::new().run().await
+}